@@ -0,0 +1,66 @@
+//! Chain anchors on events that move funds
+//!
+//! Event timestamps come from the vault's clock, which nobody outside the
+//! vault can check. Deposits, withdrawals and emergency withdrawals also
+//! record the chain tip the node last reported when they were emitted: its
+//! height, hash and median time past. An auditor can look the block up on
+//! their own node, which bounds when the event happened by chain time, and
+//! the height is recorded as the event's `block_number`.
+//!
+//! Anchors are taken from the chain tip the RPC client cached on its last
+//! tip lookup, which the reorg watcher makes every poll, so emitting an event
+//! never waits on the node. Events are left unanchored when no tip was
+//! fetched recently or the backend has no node.
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::errors::ContractError;
+use crate::events::Event;
+
+/// Chain tip at the time an event was emitted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainAnchor {
+    /// Height of the tip
+    pub block_height: u64,
+    /// Hash of the tip
+    pub block_hash: String,
+    /// Median time past of the tip
+    pub median_time: DateTime<Utc>,
+}
+
+/// Outcome of checking an event's anchor against the node's chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnchorVerification {
+    /// The event carries no anchor
+    Unanchored,
+    /// The anchored block is on the main chain
+    MainChain,
+    /// A different block is on the main chain at the anchored height
+    Reorged {
+        /// Hash of the block on the main chain at that height
+        main_chain_hash: String,
+    },
+}
+
+impl AnchorVerification {
+    /// Whether the event is anchored to a block on the main chain
+    pub fn is_valid(&self) -> bool {
+        *self == AnchorVerification::MainChain
+    }
+}
+
+/// Check that the block an event is anchored to is still on the node's main chain
+pub fn verify_anchor(event: &Event, rpc: &BitcoinRpcClient) -> Result<AnchorVerification, ContractError> {
+    let Some(anchor) = event.chain_anchor() else {
+        return Ok(AnchorVerification::Unanchored);
+    };
+
+    let main_chain_hash = rpc.get_block_hash(anchor.block_height)?;
+    if main_chain_hash == anchor.block_hash {
+        Ok(AnchorVerification::MainChain)
+    } else {
+        Ok(AnchorVerification::Reorged { main_chain_hash })
+    }
+}
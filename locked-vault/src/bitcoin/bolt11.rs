@@ -0,0 +1,375 @@
+//! BOLT11 invoices with route hints
+//!
+//! Nodes behind private channels are not in the public channel graph, so a
+//! payer can only reach them through the route hints (`r` fields) of their
+//! invoices. Each hint is a path of private channels ending at the payee,
+//! starting from a node the payer can already reach.
+//!
+//! Only the fields the vault uses are read: amount, timestamp, payment hash,
+//! description, payee (`n`), expiry and route hints; other fields are
+//! skipped. The payee has to be given explicitly, since recovering it from
+//! the signature is not supported, and the signature is checked against it.
+//! Encoded invoices always carry the payee and a recovery ID of 0.
+
+use bitcoincore_rpc::bitcoin::bech32::{self, u5, ToBase32, Variant};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::Network;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::signature::{SignatureVerifier, Signer};
+use crate::errors::ContractError;
+
+/// Tag of the payment hash field
+const TAG_PAYMENT_HASH: u8 = 1;
+
+/// Tag of a route hint field
+const TAG_ROUTE_HINT: u8 = 3;
+
+/// Tag of the expiry field
+const TAG_EXPIRY: u8 = 6;
+
+/// Tag of the description field
+const TAG_DESCRIPTION: u8 = 13;
+
+/// Tag of the payee field
+const TAG_PAYEE: u8 = 19;
+
+/// Length of the timestamp in 5-bit groups
+const TIMESTAMP_LENGTH: usize = 7;
+
+/// Length of the signature and recovery ID in 5-bit groups
+const SIGNATURE_LENGTH: usize = 104;
+
+/// Length of one encoded route hint hop in bytes
+const HOP_LENGTH: usize = 51;
+
+/// Largest field length the two 5-bit length groups can express
+const MAX_FIELD_LENGTH: usize = 1023;
+
+/// Millisatoshis per bitcoin
+const MSAT_PER_BTC: u64 = 100_000_000_000;
+
+/// One private channel of a route hint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteHintHop {
+    /// Hex-encoded public key of the node the channel leads from
+    pub node_id: String,
+    /// Short channel ID of the channel towards the next hop or the payee
+    pub short_channel_id: u64,
+    /// Base fee of the channel in millisatoshis
+    pub fee_base_msat: u32,
+    /// Proportional fee of the channel in millionths
+    pub fee_proportional_millionths: u32,
+    /// CLTV expiry delta of the channel
+    pub cltv_expiry_delta: u16,
+}
+
+/// Path of private channels ending at the payee
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteHint {
+    /// Hops from the entry node to the payee
+    pub hops: Vec<RouteHintHop>,
+}
+
+/// Decoded BOLT11 invoice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bolt11Invoice {
+    /// Currency prefix, `tb` for testnet
+    pub currency: String,
+    /// Amount in millisatoshis, `None` for an invoice of any amount
+    pub amount_msat: Option<u64>,
+    /// Creation time in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// Hex-encoded payment hash
+    pub payment_hash: String,
+    /// Description
+    pub description: Option<String>,
+    /// Hex-encoded public key of the payee
+    pub payee: String,
+    /// Expiry in seconds, `None` for the default of one hour
+    pub expiry: Option<u64>,
+    /// Route hints to the payee
+    pub route_hints: Vec<RouteHint>,
+}
+
+impl Bolt11Invoice {
+    /// Get the currency prefix of a network
+    pub fn currency_for(network: Network) -> &'static str {
+        match network {
+            Network::Bitcoin => "bc",
+            Network::Testnet => "tb",
+            Network::Signet => "tbs",
+            _ => "bcrt",
+        }
+    }
+    
+    /// Decode an invoice and check its signature
+    pub fn decode(invoice: &str) -> Result<Self, ContractError> {
+        let (hrp, data, variant) = bech32::decode(invoice)
+            .map_err(|e| ContractError::InvalidInvoice(format!("Invalid bech32: {}", e)))?;
+        if variant != Variant::Bech32 {
+            return Err(ContractError::InvalidInvoice("Invoices use bech32, not bech32m".to_string()));
+        }
+        
+        let (currency, amount_msat) = parse_hrp(&hrp)?;
+        
+        if data.len() < TIMESTAMP_LENGTH + SIGNATURE_LENGTH {
+            return Err(ContractError::InvalidInvoice("Invoice is too short".to_string()));
+        }
+        let (signed, signature) = data.split_at(data.len() - SIGNATURE_LENGTH);
+        let timestamp = u5_to_int(&signed[..TIMESTAMP_LENGTH]);
+        
+        // Read the tagged fields, skipping the ones the vault does not use
+        let mut payment_hash = None;
+        let mut description = None;
+        let mut payee = None;
+        let mut expiry = None;
+        let mut route_hints = Vec::new();
+        
+        let mut fields = &signed[TIMESTAMP_LENGTH..];
+        while !fields.is_empty() {
+            if fields.len() < 3 {
+                return Err(ContractError::InvalidInvoice("Truncated field".to_string()));
+            }
+            
+            let tag = fields[0].to_u8();
+            let length = u5_to_int(&fields[1..3]) as usize;
+            if fields.len() < 3 + length {
+                return Err(ContractError::InvalidInvoice("Truncated field".to_string()));
+            }
+            let value = &fields[3..3 + length];
+            fields = &fields[3 + length..];
+            
+            match tag {
+                // Fields of an unexpected length are skipped as the specification requires
+                TAG_PAYMENT_HASH if length == 52 => payment_hash = Some(hex::encode(u5_to_bytes(value))),
+                TAG_PAYEE if length == 53 => payee = Some(hex::encode(u5_to_bytes(value))),
+                TAG_DESCRIPTION => {
+                    let text = String::from_utf8(u5_to_bytes(value))
+                        .map_err(|_| ContractError::InvalidInvoice("Description is not UTF-8".to_string()))?;
+                    description = Some(text);
+                },
+                TAG_EXPIRY => expiry = Some(u5_to_int(value)),
+                TAG_ROUTE_HINT => route_hints.push(decode_route_hint(&u5_to_bytes(value))?),
+                _ => {},
+            }
+        }
+        
+        let payment_hash = payment_hash.ok_or_else(|| ContractError::InvalidInvoice("Missing payment hash".to_string()))?;
+        let payee = payee.ok_or_else(|| ContractError::InvalidInvoice("Missing payee".to_string()))?;
+        
+        // The payee signs the human-readable part and every field
+        let digest = signing_digest(&hrp, signed);
+        let signature = u5_to_bytes(signature);
+        let valid = SignatureVerifier::new(Network::Testnet)
+            .verify(&digest, &signature[..64], &hex::decode(&payee).unwrap_or_default())
+            .unwrap_or(false);
+        if !valid {
+            return Err(ContractError::InvalidInvoice("Signature does not match the payee".to_string()));
+        }
+        
+        Ok(Self {
+            currency,
+            amount_msat,
+            timestamp,
+            payment_hash,
+            description,
+            payee,
+            expiry,
+            route_hints,
+        })
+    }
+    
+    /// Encode the invoice, signed by the payee's key
+    pub fn encode(&self, signer: &dyn Signer) -> Result<String, ContractError> {
+        if hex::encode(signer.public_key()) != self.payee {
+            return Err(ContractError::InvalidInvoice("Signer is not the payee".to_string()));
+        }
+        
+        let hrp = format!("ln{}{}", self.currency, encode_amount(self.amount_msat));
+        
+        let mut data = int_to_u5(self.timestamp, TIMESTAMP_LENGTH);
+        push_field(&mut data, TAG_PAYMENT_HASH, &decode_hex_field(&self.payment_hash, 32, "payment hash")?.to_base32())?;
+        if let Some(description) = &self.description {
+            push_field(&mut data, TAG_DESCRIPTION, &description.as_bytes().to_base32())?;
+        }
+        push_field(&mut data, TAG_PAYEE, &decode_hex_field(&self.payee, 33, "payee")?.to_base32())?;
+        if let Some(expiry) = self.expiry {
+            push_field(&mut data, TAG_EXPIRY, &int_to_u5(expiry, 0))?;
+        }
+        for hint in &self.route_hints {
+            push_field(&mut data, TAG_ROUTE_HINT, &encode_route_hint(hint)?.to_base32())?;
+        }
+        
+        // Compact signature followed by the recovery ID, which readers ignore given the payee
+        let mut signature = signer.sign(&signing_digest(&hrp, &data))?;
+        signature.push(0);
+        data.extend(signature.to_base32());
+        
+        bech32::encode(&hrp, data, Variant::Bech32)
+            .map_err(|e| ContractError::InvalidInvoice(format!("Invalid bech32: {}", e)))
+    }
+}
+
+/// Split a human-readable part into the currency and the amount in millisatoshis
+fn parse_hrp(hrp: &str) -> Result<(String, Option<u64>), ContractError> {
+    let rest = hrp.strip_prefix("ln")
+        .ok_or_else(|| ContractError::InvalidInvoice(format!("Not a Lightning invoice: {}", hrp)))?;
+    
+    let amount_start = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+    let (currency, amount) = rest.split_at(amount_start);
+    if currency.is_empty() {
+        return Err(ContractError::InvalidInvoice(format!("Missing currency in {}", hrp)));
+    }
+    if amount.is_empty() {
+        return Ok((currency.to_string(), None));
+    }
+    
+    let invalid = || ContractError::InvalidInvoice(format!("Invalid amount in {}", hrp));
+    let (digits, multiplier) = match amount.char_indices().last() {
+        Some((index, unit)) if unit.is_ascii_alphabetic() => (&amount[..index], Some(unit)),
+        _ => (amount, None),
+    };
+    let value: u64 = digits.parse().map_err(|_| invalid())?;
+    
+    let amount_msat = match multiplier {
+        None => value.checked_mul(MSAT_PER_BTC),
+        Some('m') => value.checked_mul(MSAT_PER_BTC / 1_000),
+        Some('u') => value.checked_mul(MSAT_PER_BTC / 1_000_000),
+        Some('n') => value.checked_mul(MSAT_PER_BTC / 1_000_000_000),
+        // A pico-bitcoin is a tenth of a millisatoshi
+        Some('p') if value.is_multiple_of(10) => Some(value / 10),
+        _ => None,
+    };
+    
+    Ok((currency.to_string(), Some(amount_msat.ok_or_else(invalid)?)))
+}
+
+/// Write an amount with the largest multiplier that keeps it whole
+fn encode_amount(amount_msat: Option<u64>) -> String {
+    let Some(amount_msat) = amount_msat else {
+        return String::new();
+    };
+    
+    for (unit, msat) in [("", MSAT_PER_BTC), ("m", MSAT_PER_BTC / 1_000), ("u", MSAT_PER_BTC / 1_000_000), ("n", MSAT_PER_BTC / 1_000_000_000)] {
+        if amount_msat.is_multiple_of(msat) {
+            return format!("{}{}", amount_msat / msat, unit);
+        }
+    }
+    
+    format!("{}p", amount_msat.saturating_mul(10))
+}
+
+/// Hash the human-readable part and the fields, as the payee signs them
+fn signing_digest(hrp: &str, data: &[u5]) -> [u8; 32] {
+    let mut preimage = hrp.as_bytes().to_vec();
+    preimage.extend(u5_to_padded_bytes(data));
+    sha256::Hash::hash(&preimage).to_byte_array()
+}
+
+/// Append a tagged field
+fn push_field(data: &mut Vec<u5>, tag: u8, value: &[u5]) -> Result<(), ContractError> {
+    if value.len() > MAX_FIELD_LENGTH {
+        return Err(ContractError::InvalidInvoice(format!("Field {} is too long", tag)));
+    }
+    
+    data.push(to_u5(tag));
+    data.extend(int_to_u5(value.len() as u64, 2));
+    data.extend_from_slice(value);
+    
+    Ok(())
+}
+
+/// Decode a hex field of a fixed length
+fn decode_hex_field(value: &str, length: usize, name: &str) -> Result<Vec<u8>, ContractError> {
+    hex::decode(value).ok()
+        .filter(|bytes| bytes.len() == length)
+        .ok_or_else(|| ContractError::InvalidInvoice(format!("Invalid {} {}", name, value)))
+}
+
+/// Decode the hops of a route hint field
+fn decode_route_hint(bytes: &[u8]) -> Result<RouteHint, ContractError> {
+    if bytes.is_empty() || !bytes.len().is_multiple_of(HOP_LENGTH) {
+        return Err(ContractError::InvalidInvoice("Invalid route hint length".to_string()));
+    }
+    
+    let hops = bytes.chunks(HOP_LENGTH)
+        .map(|hop| RouteHintHop {
+            node_id: hex::encode(&hop[..33]),
+            short_channel_id: u64::from_be_bytes(hop[33..41].try_into().unwrap_or_default()),
+            fee_base_msat: u32::from_be_bytes(hop[41..45].try_into().unwrap_or_default()),
+            fee_proportional_millionths: u32::from_be_bytes(hop[45..49].try_into().unwrap_or_default()),
+            cltv_expiry_delta: u16::from_be_bytes(hop[49..51].try_into().unwrap_or_default()),
+        })
+        .collect();
+    
+    Ok(RouteHint { hops })
+}
+
+/// Encode the hops of a route hint field
+fn encode_route_hint(hint: &RouteHint) -> Result<Vec<u8>, ContractError> {
+    if hint.hops.is_empty() {
+        return Err(ContractError::InvalidInvoice("Route hint has no hops".to_string()));
+    }
+    
+    let mut bytes = Vec::with_capacity(hint.hops.len() * HOP_LENGTH);
+    for hop in &hint.hops {
+        bytes.extend(decode_hex_field(&hop.node_id, 33, "route hint node")?);
+        bytes.extend(hop.short_channel_id.to_be_bytes());
+        bytes.extend(hop.fee_base_msat.to_be_bytes());
+        bytes.extend(hop.fee_proportional_millionths.to_be_bytes());
+        bytes.extend(hop.cltv_expiry_delta.to_be_bytes());
+    }
+    
+    Ok(bytes)
+}
+
+/// Convert a value below 32 to a 5-bit group
+fn to_u5(value: u8) -> u5 {
+    u5::try_from_u8(value & 31).unwrap_or_default()
+}
+
+/// Write an integer as big-endian 5-bit groups, at least `length` of them
+fn int_to_u5(mut value: u64, length: usize) -> Vec<u5> {
+    let mut groups = Vec::new();
+    while value > 0 || groups.len() < length {
+        groups.push(to_u5((value & 31) as u8));
+        value >>= 5;
+    }
+    groups.reverse();
+    groups
+}
+
+/// Read big-endian 5-bit groups as an integer
+fn u5_to_int(groups: &[u5]) -> u64 {
+    groups.iter().fold(0u64, |value, group| (value << 5) | group.to_u8() as u64)
+}
+
+/// Read 5-bit groups as bytes, dropping the incomplete last byte
+fn u5_to_bytes(groups: &[u5]) -> Vec<u8> {
+    let mut bytes = u5_to_padded_bytes(groups);
+    bytes.truncate(groups.len() * 5 / 8);
+    bytes
+}
+
+/// Read 5-bit groups as bytes, padding the last byte with zero bits
+fn u5_to_padded_bytes(groups: &[u5]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(groups.len() * 5 / 8 + 1);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    
+    for group in groups {
+        buffer = (buffer << 5) | group.to_u8() as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    if bits > 0 {
+        bytes.push((buffer << (8 - bits)) as u8);
+    }
+    
+    bytes
+}
@@ -0,0 +1,140 @@
+//! Bounded address balance cache
+//!
+//! Entries expire after the configured TTL and the least recently used entry
+//! is evicted once the cache is full. Hits, misses and evictions are counted
+//! in the metrics registry.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::bitcoin::testnet::CacheConfig;
+use crate::clock::{Clock, SystemClock};
+use crate::metrics::{Counter, MetricsRegistry};
+use crate::models::TokenType;
+
+/// Cached balance
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    /// Balance
+    balance: u64,
+    /// When the balance was fetched
+    fetched_at: DateTime<Utc>,
+    /// Access sequence number for LRU eviction
+    last_access: u64,
+}
+
+/// Cache of balances keyed by address and token type
+#[derive(Debug)]
+pub(crate) struct BalanceCache {
+    /// Cache settings
+    config: CacheConfig,
+    /// Cached balances
+    entries: HashMap<String, CacheEntry>,
+    /// Access sequence counter
+    access_counter: u64,
+    /// Time source for expiry
+    clock: Arc<dyn Clock>,
+    /// Cache hits
+    hits: Arc<Counter>,
+    /// Cache misses, including expired entries
+    misses: Arc<Counter>,
+    /// Entries evicted to respect the size cap
+    evictions: Arc<Counter>,
+}
+
+impl BalanceCache {
+    /// Create an empty cache reporting to the given registry
+    pub fn new(config: CacheConfig, metrics: &MetricsRegistry) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            access_counter: 0,
+            clock: Arc::new(SystemClock),
+            hits: metrics.counter("balance_cache_hits"),
+            misses: metrics.counter("balance_cache_misses"),
+            evictions: metrics.counter("balance_cache_evictions"),
+        }
+    }
+    
+    /// Replace the time source used for expiry
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+    
+    /// Build the cache key for an address and token
+    fn key(address: &str, token_type: &TokenType) -> String {
+        format!("{}:{:?}", address, token_type)
+    }
+    
+    /// Get a cached balance if present and not expired
+    pub fn get(&mut self, address: &str, token_type: &TokenType) -> Option<u64> {
+        let key = Self::key(address, token_type);
+        let ttl = Duration::from_std(self.config.ttl).unwrap_or(Duration::MAX);
+        let now = self.clock.now();
+        
+        match self.entries.get_mut(&key) {
+            Some(entry) if now - entry.fetched_at < ttl => {
+                self.access_counter += 1;
+                entry.last_access = self.access_counter;
+                self.hits.inc();
+                Some(entry.balance)
+            },
+            Some(_) => {
+                // Drop the expired entry
+                self.entries.remove(&key);
+                self.misses.inc();
+                None
+            },
+            None => {
+                self.misses.inc();
+                None
+            },
+        }
+    }
+    
+    /// Cache a balance, evicting the least recently used entry if full
+    pub fn insert(&mut self, address: &str, token_type: &TokenType, balance: u64) {
+        self.access_counter += 1;
+        
+        self.entries.insert(Self::key(address, token_type), CacheEntry {
+            balance,
+            fetched_at: self.clock.now(),
+            last_access: self.access_counter,
+        });
+        
+        while self.entries.len() > self.config.max_entries {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(key, _)| key.clone());
+            
+            match oldest {
+                Some(key) => {
+                    self.entries.remove(&key);
+                    self.evictions.inc();
+                },
+                None => break,
+            }
+        }
+    }
+    
+    /// Remove the cached balance of an address and token
+    pub fn invalidate(&mut self, address: &str, token_type: &TokenType) {
+        self.entries.remove(&Self::key(address, token_type));
+    }
+    
+    /// Remove every cached balance
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    
+    /// Number of cached balances
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    
+    /// Whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
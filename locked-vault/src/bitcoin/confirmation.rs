@@ -0,0 +1,111 @@
+//! Confirmations required before funds count as received
+//!
+//! A `ConfirmationPolicy` gives each token type a base number of
+//! confirmations and optional amount tiers that raise it for larger amounts.
+//! A tier applies from its `min_amount` inclusive up to the next tier's
+//! `min_amount` exclusive, and the base applies below the first tier. For
+//! example, Bitcoin with base 1 and tiers at 1 000 000 sats (3 confirmations)
+//! and 100 000 000 sats (6 confirmations) requires 1 confirmation below
+//! 0.01 BTC, 3 from 0.01 BTC up to but excluding 1 BTC, and 6 from 1 BTC.
+//! Token types without a rule use the policy's default.
+
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::models::TokenType;
+
+/// Confirmations required from an amount upwards
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationTier {
+    /// Smallest amount the tier applies to
+    pub min_amount: u64,
+    /// Confirmations required
+    pub confirmations: u32,
+}
+
+/// Confirmation requirements of one token type
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenConfirmationRule {
+    /// Token type the rule applies to
+    pub token_type: TokenType,
+    /// Confirmations required below the first tier
+    pub base_confirmations: u32,
+    /// Tiers in ascending order of `min_amount`
+    #[serde(default)]
+    pub tiers: Vec<ConfirmationTier>,
+}
+
+/// Confirmations required per token type and amount
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmationPolicy {
+    /// Confirmations required for token types without a rule
+    pub default_confirmations: u32,
+    /// Rules by token type
+    #[serde(default)]
+    pub rules: Vec<TokenConfirmationRule>,
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self::uniform(1)
+    }
+}
+
+impl ConfirmationPolicy {
+    /// Create a policy requiring the same confirmations for everything
+    pub fn uniform(confirmations: u32) -> Self {
+        Self {
+            default_confirmations: confirmations,
+            rules: Vec::new(),
+        }
+    }
+    
+    /// Add or replace the rule of a token type
+    pub fn with_rule(mut self, token_type: TokenType, base_confirmations: u32, tiers: Vec<ConfirmationTier>) -> Self {
+        self.rules.retain(|rule| rule.token_type != token_type);
+        self.rules.push(TokenConfirmationRule {
+            token_type,
+            base_confirmations,
+            tiers,
+        });
+        self
+    }
+    
+    /// Get the confirmations an amount of a token type requires
+    pub fn required_confirmations(&self, token_type: &TokenType, amount: u64) -> u32 {
+        let rule = match self.rules.iter().find(|rule| &rule.token_type == token_type) {
+            Some(rule) => rule,
+            None => return self.default_confirmations,
+        };
+        
+        // The last tier the amount reaches wins
+        rule.tiers.iter()
+            .take_while(|tier| amount >= tier.min_amount)
+            .last()
+            .map(|tier| tier.confirmations)
+            .unwrap_or(rule.base_confirmations)
+    }
+    
+    /// Check that nothing requires zero confirmations and tiers ascend
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if self.default_confirmations == 0 {
+            return Err(ContractError::InvalidConfirmationPolicy("default confirmations cannot be zero".to_string()));
+        }
+        
+        for (index, rule) in self.rules.iter().enumerate() {
+            if self.rules[..index].iter().any(|other| other.token_type == rule.token_type) {
+                return Err(ContractError::InvalidConfirmationPolicy(format!("{:?} has more than one rule", rule.token_type)));
+            }
+            
+            if rule.base_confirmations == 0 || rule.tiers.iter().any(|tier| tier.confirmations == 0) {
+                return Err(ContractError::InvalidConfirmationPolicy(format!("{:?} requires zero confirmations", rule.token_type)));
+            }
+            
+            if rule.tiers.windows(2).any(|pair| pair[0].min_amount >= pair[1].min_amount) {
+                return Err(ContractError::InvalidConfirmationPolicy(format!("tiers of {:?} are not in ascending order", rule.token_type)));
+            }
+        }
+        
+        Ok(())
+    }
+}
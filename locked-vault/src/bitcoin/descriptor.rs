@@ -0,0 +1,536 @@
+//! Output descriptors of script escrow deposits and watched wallets
+//!
+//! A script escrow deposit is paid to a P2WSH output that the vault and the
+//! depositor can spend together at any time, and the depositor alone from
+//! the lock height on. Its terms are the miniscript policy
+//! `wsh(or_d(multi(2,VAULT,DEPOSITOR),and_v(v:pk(DEPOSITOR),after(HEIGHT))))`,
+//! which any descriptor wallet can import to watch or spend the output.
+//! Multisig wallets pay to `wsh(multi(M,KEY,...))`, and plain addresses are
+//! watched with `addr(ADDRESS)`.
+//!
+//! A MuSig2 escrow is the taproot form of the same terms,
+//! `tr(musig(VAULT,DEPOSITOR),and_v(v:pk(DEPOSITOR),after(HEIGHT)))`: the
+//! internal key aggregates both keys so a cooperative spend is a single key
+//! path signature, and the recovery leaf lets the depositor spend alone
+//! from the lock height on.
+//!
+//! Descriptors carry the eight character checksum of Bitcoin Core's
+//! `getdescriptorinfo`. Parsing accepts a descriptor with or without one,
+//! but a checksum that is present has to match.
+
+use std::str::FromStr;
+use bitcoincore_rpc::bitcoin::{Address, Network, PublicKey, ScriptBuf, Sequence, Transaction, TxOut, Witness};
+use bitcoincore_rpc::bitcoin::absolute::LockTime;
+use bitcoincore_rpc::bitcoin::blockdata::opcodes::all::{
+    OP_CHECKMULTISIG, OP_CHECKSIGVERIFY, OP_CLTV, OP_ENDIF, OP_IFDUP, OP_NOTIF, OP_PUSHNUM_2,
+};
+use bitcoincore_rpc::bitcoin::blockdata::script::Builder;
+use bitcoincore_rpc::bitcoin::hashes::Hash;
+use bitcoincore_rpc::bitcoin::secp256k1::{schnorr, Secp256k1, XOnlyPublicKey};
+use bitcoincore_rpc::bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+use bitcoincore_rpc::bitcoin::taproot::{ControlBlock, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::signature::MusigKeyAgg;
+use crate::errors::ContractError;
+
+/// Characters a descriptor may contain, in checksum order
+const INPUT_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// Characters of the checksum itself
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// Length of a descriptor checksum
+const CHECKSUM_LENGTH: usize = 8;
+
+/// Smallest `nLockTime` value that is a timestamp rather than a block height
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Compute the checksum of a descriptor without one
+pub fn descriptor_checksum(descriptor: &str) -> Result<String, ContractError> {
+    let mut checksum: u64 = 1;
+    let mut classes: u64 = 0;
+    let mut class_count = 0;
+    
+    for character in descriptor.chars() {
+        let position = INPUT_CHARSET.find(character)
+            .ok_or_else(|| ContractError::InvalidDescriptor(format!("Invalid character {:?}", character)))? as u64;
+        
+        // The low bits of each character, and its class once per group of three
+        checksum = polymod(checksum, position & 31);
+        classes = classes * 3 + (position >> 5);
+        class_count += 1;
+        if class_count == 3 {
+            checksum = polymod(checksum, classes);
+            classes = 0;
+            class_count = 0;
+        }
+    }
+    if class_count > 0 {
+        checksum = polymod(checksum, classes);
+    }
+    
+    // Shift in room for the checksum characters
+    for _ in 0..CHECKSUM_LENGTH {
+        checksum = polymod(checksum, 0);
+    }
+    checksum ^= 1;
+    
+    Ok((0..CHECKSUM_LENGTH)
+        .map(|index| CHECKSUM_CHARSET[((checksum >> (5 * (CHECKSUM_LENGTH - 1 - index))) & 31) as usize] as char)
+        .collect())
+}
+
+/// Append the checksum to a descriptor without one
+pub fn with_checksum(descriptor: &str) -> Result<String, ContractError> {
+    Ok(format!("{}#{}", descriptor, descriptor_checksum(descriptor)?))
+}
+
+/// Strip the checksum off a descriptor, checking it if present
+pub fn strip_checksum(descriptor: &str) -> Result<&str, ContractError> {
+    let (body, checksum) = match descriptor.split_once('#') {
+        Some(parts) => parts,
+        None => return Ok(descriptor),
+    };
+    
+    if checksum.len() != CHECKSUM_LENGTH {
+        return Err(ContractError::InvalidDescriptor(format!(
+            "Checksum must be {} characters, got {}", CHECKSUM_LENGTH, checksum.len()
+        )));
+    }
+    
+    let expected = descriptor_checksum(body)?;
+    if checksum != expected {
+        return Err(ContractError::InvalidDescriptor(format!(
+            "Checksum {} does not match, expected {}", checksum, expected
+        )));
+    }
+    
+    Ok(body)
+}
+
+/// One step of the descriptor checksum's BCH code
+fn polymod(checksum: u64, value: u64) -> u64 {
+    const GENERATORS: [u64; 5] = [0xf5dee51989, 0xa9fdca3312, 0x1bab10e32d, 0x3706b1677a, 0x644d626ffd];
+    
+    let top = checksum >> 35;
+    let mut checksum = ((checksum & 0x7ffffffff) << 5) ^ value;
+    for (bit, generator) in GENERATORS.iter().enumerate() {
+        if (top >> bit) & 1 == 1 {
+            checksum ^= generator;
+        }
+    }
+    checksum
+}
+
+/// Terms of a script escrow output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeLockPolicy {
+    /// Vault key, needed for the cooperative path
+    pub vault_key: PublicKey,
+    /// Depositor key, needed for both paths
+    pub depositor_key: PublicKey,
+    /// Block height from which the depositor can spend alone
+    pub lock_height: u32,
+}
+
+impl TimeLockPolicy {
+    /// Create the terms from hex-encoded compressed public keys
+    pub fn new(vault_key: &str, depositor_key: &str, lock_height: u32) -> Result<Self, ContractError> {
+        if lock_height == 0 || lock_height >= LOCKTIME_THRESHOLD {
+            return Err(ContractError::InvalidDescriptor(format!(
+                "Lock height must be a block height between 1 and {}, got {}", LOCKTIME_THRESHOLD - 1, lock_height
+            )));
+        }
+        
+        Ok(Self {
+            vault_key: parse_key(vault_key)?,
+            depositor_key: parse_key(depositor_key)?,
+            lock_height,
+        })
+    }
+    
+    /// Parse the terms from a descriptor, with or without its checksum
+    pub fn parse(descriptor: &str) -> Result<Self, ContractError> {
+        let body = strip_checksum(descriptor)?;
+        let unexpected = || ContractError::InvalidDescriptor(format!("Not a time-locked escrow descriptor: {}", body));
+        
+        // wsh(or_d(multi(2,VAULT,DEPOSITOR),and_v(v:pk(DEPOSITOR),after(HEIGHT))))
+        let inner = body.strip_prefix("wsh(or_d(multi(2,")
+            .and_then(|rest| rest.strip_suffix("))))"))
+            .ok_or_else(unexpected)?;
+        let (keys, recovery) = inner.split_once("),and_v(v:pk(").ok_or_else(unexpected)?;
+        let (vault_key, depositor_key) = keys.split_once(',').ok_or_else(unexpected)?;
+        let (recovery_key, lock_height) = recovery.split_once("),after(").ok_or_else(unexpected)?;
+        
+        if recovery_key != depositor_key {
+            return Err(ContractError::InvalidDescriptor(
+                "The recovery path must use the depositor key of the cooperative path".to_string()
+            ));
+        }
+        
+        // Reject forms like "+5" or "007" that would not round-trip
+        let height: u32 = lock_height.parse().map_err(|_| unexpected())?;
+        if height.to_string() != lock_height {
+            return Err(unexpected());
+        }
+        
+        Self::new(vault_key, depositor_key, height)
+    }
+    
+    /// Render the terms as a descriptor with its checksum
+    pub fn descriptor(&self) -> String {
+        let body = format!(
+            "wsh(or_d(multi(2,{vault},{depositor}),and_v(v:pk({depositor}),after({height}))))",
+            vault = self.vault_key,
+            depositor = self.depositor_key,
+            height = self.lock_height,
+        );
+        
+        // Hex keys and decimal heights only use descriptor characters
+        let checksum = descriptor_checksum(&body).unwrap_or_default();
+        format!("{}#{}", body, checksum)
+    }
+    
+    /// Build the witness script the output commits to
+    pub fn witness_script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_opcode(OP_PUSHNUM_2)
+            .push_key(&self.vault_key)
+            .push_key(&self.depositor_key)
+            .push_opcode(OP_PUSHNUM_2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .push_opcode(OP_IFDUP)
+            .push_opcode(OP_NOTIF)
+            .push_key(&self.depositor_key)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_int(self.lock_height as i64)
+            .push_opcode(OP_CLTV)
+            .push_opcode(OP_ENDIF)
+            .into_script()
+    }
+    
+    /// Derive the P2WSH address of the output
+    pub fn address(&self, network: Network) -> Address {
+        Address::p2wsh(&self.witness_script(), network)
+    }
+}
+
+/// Terms of a MuSig2 taproot escrow output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusigEscrowPolicy {
+    /// Vault key, aggregated into the internal key
+    pub vault_key: PublicKey,
+    /// Depositor key, aggregated into the internal key and alone in the recovery leaf
+    pub depositor_key: PublicKey,
+    /// Block height from which the depositor can spend alone
+    pub lock_height: u32,
+}
+
+impl MusigEscrowPolicy {
+    /// Create the terms from hex-encoded compressed public keys
+    pub fn new(vault_key: &str, depositor_key: &str, lock_height: u32) -> Result<Self, ContractError> {
+        let terms = TimeLockPolicy::new(vault_key, depositor_key, lock_height)?;
+        
+        if terms.vault_key == terms.depositor_key {
+            return Err(ContractError::InvalidDescriptor(
+                "The vault and depositor keys of a MuSig2 escrow must differ".to_string()
+            ));
+        }
+        
+        Ok(Self {
+            vault_key: terms.vault_key,
+            depositor_key: terms.depositor_key,
+            lock_height,
+        })
+    }
+    
+    /// Parse the terms from a descriptor, with or without its checksum
+    pub fn parse(descriptor: &str) -> Result<Self, ContractError> {
+        let body = strip_checksum(descriptor)?;
+        let unexpected = || ContractError::InvalidDescriptor(format!("Not a MuSig2 escrow descriptor: {}", body));
+        
+        // tr(musig(VAULT,DEPOSITOR),and_v(v:pk(DEPOSITOR_XONLY),after(HEIGHT)))
+        let inner = body.strip_prefix("tr(musig(")
+            .and_then(|rest| rest.strip_suffix(")))"))
+            .ok_or_else(unexpected)?;
+        let (keys, recovery) = inner.split_once("),and_v(v:pk(").ok_or_else(unexpected)?;
+        let (vault_key, depositor_key) = keys.split_once(',').ok_or_else(unexpected)?;
+        let (recovery_key, lock_height) = recovery.split_once("),after(").ok_or_else(unexpected)?;
+        
+        let height: u32 = lock_height.parse().map_err(|_| unexpected())?;
+        if height.to_string() != lock_height {
+            return Err(unexpected());
+        }
+        
+        let policy = Self::new(vault_key, depositor_key, height)?;
+        if recovery_key != policy.depositor_x_only().to_string() {
+            return Err(ContractError::InvalidDescriptor(
+                "The recovery leaf must use the depositor key of the internal key".to_string()
+            ));
+        }
+        
+        Ok(policy)
+    }
+    
+    /// Render the terms as a descriptor with its checksum
+    pub fn descriptor(&self) -> String {
+        let body = format!(
+            "tr(musig({vault},{depositor}),and_v(v:pk({recovery}),after({height})))",
+            vault = self.vault_key,
+            depositor = self.depositor_key,
+            recovery = self.depositor_x_only(),
+            height = self.lock_height,
+        );
+        
+        // Hex keys and decimal heights only use descriptor characters
+        let checksum = descriptor_checksum(&body).unwrap_or_default();
+        format!("{}#{}", body, checksum)
+    }
+    
+    /// Aggregate the vault and depositor keys, untweaked
+    pub fn key_agg(&self) -> Result<MusigKeyAgg, ContractError> {
+        MusigKeyAgg::new(&[self.vault_key.inner, self.depositor_key.inner])
+    }
+    
+    /// Aggregated key, tweaked to sign for the output's key path
+    pub fn tweaked_key_agg(&self) -> Result<MusigKeyAgg, ContractError> {
+        self.key_agg()?.with_taproot_tweak(self.spend_info()?.merkle_root())
+    }
+    
+    /// Build the recovery leaf script
+    pub fn recovery_script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_x_only_key(&self.depositor_x_only())
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_int(self.lock_height as i64)
+            .push_opcode(OP_CLTV)
+            .into_script()
+    }
+    
+    /// Build the taproot tree of the output, the recovery leaf alone
+    pub fn spend_info(&self) -> Result<TaprootSpendInfo, ContractError> {
+        let secp = Secp256k1::verification_only();
+        let internal_key = self.key_agg()?.x_only_key();
+        
+        TaprootBuilder::new()
+            .add_leaf(0, self.recovery_script())
+            .map_err(|e| ContractError::InvalidDescriptor(format!("Invalid recovery leaf: {}", e)))?
+            .finalize(&secp, internal_key)
+            .map_err(|_| ContractError::InvalidDescriptor("Incomplete taproot tree".to_string()))
+    }
+    
+    /// Derive the P2TR address of the output
+    pub fn address(&self, network: Network) -> Result<Address, ContractError> {
+        Ok(Address::p2tr_tweaked(self.spend_info()?.output_key(), network))
+    }
+    
+    /// Control block proving the recovery leaf is in the output's tree
+    pub fn recovery_control_block(&self) -> Result<ControlBlock, ContractError> {
+        self.spend_info()?
+            .control_block(&(self.recovery_script(), LeafVersion::TapScript))
+            .ok_or_else(|| ContractError::InvalidDescriptor("Recovery leaf missing from the taproot tree".to_string()))
+    }
+    
+    /// Compute the digest the depositor signs to recover input `input_index` alone
+    /// 
+    /// The transaction has to be valid for the recovery leaf's CLTV: a block
+    /// height lock time at or past the lock height, and a non-final sequence
+    /// on the input.
+    pub fn recovery_sighash(&self, tx: &Transaction, input_index: usize, prevouts: &[TxOut]) -> Result<[u8; 32], ContractError> {
+        let input = tx.input.get(input_index)
+            .ok_or_else(|| ContractError::InvalidDescriptor(format!("No input {}", input_index)))?;
+        
+        match tx.lock_time {
+            LockTime::Blocks(height) if height.to_consensus_u32() >= self.lock_height => {},
+            _ => return Err(ContractError::InvalidDescriptor(format!(
+                "Recovery needs a lock time of block {} or later, got {}", self.lock_height, tx.lock_time
+            ))),
+        }
+        if input.sequence == Sequence::MAX {
+            return Err(ContractError::InvalidDescriptor(
+                "Recovery needs a non-final input sequence to enable the lock time".to_string()
+            ));
+        }
+        
+        let leaf_hash = TapLeafHash::from_script(&self.recovery_script(), LeafVersion::TapScript);
+        SighashCache::new(tx)
+            .taproot_script_spend_signature_hash(input_index, &Prevouts::All(prevouts), leaf_hash, TapSighashType::Default)
+            .map(|sighash| sighash.to_byte_array())
+            .map_err(|e| ContractError::InvalidDescriptor(format!("Failed to compute the recovery sighash: {}", e)))
+    }
+    
+    /// Build the witness spending through the recovery leaf
+    pub fn recovery_witness(&self, signature: &schnorr::Signature) -> Result<Witness, ContractError> {
+        let mut witness = Witness::new();
+        witness.push(signature.as_ref());
+        witness.push(self.recovery_script().as_bytes());
+        witness.push(self.recovery_control_block()?.serialize());
+        Ok(witness)
+    }
+    
+    /// Build the witness of a cooperative key path spend
+    pub fn key_path_witness(signature: &schnorr::Signature) -> Witness {
+        let mut witness = Witness::new();
+        witness.push(signature.as_ref());
+        witness
+    }
+    
+    /// X-only depositor key, as used in the recovery leaf
+    fn depositor_x_only(&self) -> XOnlyPublicKey {
+        self.depositor_key.inner.x_only_public_key().0
+    }
+}
+
+/// Most keys a `multi` witness script can hold
+pub const MAX_MULTISIG_KEYS: usize = 20;
+
+/// Terms of an M-of-N multisig output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigPolicy {
+    /// Signatures needed to spend
+    pub required_signatures: u8,
+    /// Keys that can sign, in script order
+    pub public_keys: Vec<PublicKey>,
+}
+
+impl MultisigPolicy {
+    /// Create the terms from hex-encoded compressed public keys
+    pub fn new(required_signatures: u8, public_keys: &[String]) -> Result<Self, ContractError> {
+        if public_keys.is_empty() || public_keys.len() > MAX_MULTISIG_KEYS {
+            return Err(ContractError::InvalidDescriptor(format!(
+                "A multisig needs 1 to {} keys, got {}", MAX_MULTISIG_KEYS, public_keys.len()
+            )));
+        }
+        
+        if required_signatures == 0 || required_signatures as usize > public_keys.len() {
+            return Err(ContractError::InvalidDescriptor(format!(
+                "A multisig of {} keys needs 1 to {} signatures, got {}", public_keys.len(), public_keys.len(), required_signatures
+            )));
+        }
+        
+        Ok(Self {
+            required_signatures,
+            public_keys: public_keys.iter().map(|key| parse_key(key)).collect::<Result<_, _>>()?,
+        })
+    }
+    
+    /// Parse the terms from a descriptor, with or without its checksum
+    pub fn parse(descriptor: &str) -> Result<Self, ContractError> {
+        let body = strip_checksum(descriptor)?;
+        let unexpected = || ContractError::InvalidDescriptor(format!("Not a multisig descriptor: {}", body));
+        
+        // wsh(multi(M,KEY,...))
+        let inner = body.strip_prefix("wsh(multi(")
+            .and_then(|rest| rest.strip_suffix("))"))
+            .ok_or_else(unexpected)?;
+        let (required, keys) = inner.split_once(',').ok_or_else(unexpected)?;
+        
+        let required_signatures: u8 = required.parse().map_err(|_| unexpected())?;
+        if required_signatures.to_string() != required {
+            return Err(unexpected());
+        }
+        
+        let keys: Vec<String> = keys.split(',').map(str::to_string).collect();
+        Self::new(required_signatures, &keys)
+    }
+    
+    /// Render the terms as a descriptor with its checksum
+    pub fn descriptor(&self) -> String {
+        let keys: Vec<String> = self.public_keys.iter().map(|key| key.to_string()).collect();
+        let body = format!("wsh(multi({},{}))", self.required_signatures, keys.join(","));
+        
+        // Hex keys and decimal counts only use descriptor characters
+        let checksum = descriptor_checksum(&body).unwrap_or_default();
+        format!("{}#{}", body, checksum)
+    }
+    
+    /// Build the witness script the output commits to
+    pub fn witness_script(&self) -> ScriptBuf {
+        let builder = self.public_keys.iter()
+            .fold(Builder::new().push_int(self.required_signatures as i64), |builder, key| builder.push_key(key));
+        
+        builder
+            .push_int(self.public_keys.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script()
+    }
+    
+    /// Derive the P2WSH address of the output
+    pub fn address(&self, network: Network) -> Address {
+        Address::p2wsh(&self.witness_script(), network)
+    }
+}
+
+/// Render a descriptor watching a plain address, with its checksum
+pub fn address_descriptor(address: &str) -> Result<String, ContractError> {
+    with_checksum(&format!("addr({})", address))
+}
+
+/// Derive the address a watch descriptor pays to
+/// 
+/// Understands `addr(...)` descriptors and the descriptors of multisig,
+/// time-locked escrow and MuSig2 escrow outputs.
+pub fn descriptor_address(descriptor: &str, network: Network) -> Result<Address, ContractError> {
+    let body = strip_checksum(descriptor)?;
+    
+    if let Some(address) = body.strip_prefix("addr(").and_then(|rest| rest.strip_suffix(')')) {
+        return Address::from_str(address)
+            .and_then(|address| address.require_network(network))
+            .map_err(|e| ContractError::InvalidDescriptor(format!("Invalid address {}: {}", address, e)));
+    }
+    
+    if body.starts_with("wsh(multi(") {
+        return Ok(MultisigPolicy::parse(body)?.address(network));
+    }
+    
+    if body.starts_with("tr(musig(") {
+        return MusigEscrowPolicy::parse(body)?.address(network);
+    }
+    
+    Ok(TimeLockPolicy::parse(body)?.address(network))
+}
+
+/// Entry of a Bitcoin Core `importdescriptors` request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DescriptorImport {
+    /// Descriptor with its checksum
+    pub desc: String,
+    /// Unix time to rescan the chain from
+    pub timestamp: u64,
+    /// Whether outputs are change
+    pub internal: bool,
+    /// Whether the wallet hands out new addresses from the descriptor
+    pub active: bool,
+}
+
+impl DescriptorImport {
+    /// Import a descriptor to watch only, rescanning from `timestamp`
+    pub fn watch_only(desc: String, timestamp: u64) -> Self {
+        Self {
+            desc,
+            timestamp,
+            internal: false,
+            active: false,
+        }
+    }
+}
+
+/// Parse a network name as used by `get_network_type`
+pub fn parse_network(network: &str) -> Result<Network, ContractError> {
+    Network::from_str(network)
+        .map_err(|_| ContractError::InvalidDescriptor(format!("Unknown network: {}", network)))
+}
+
+/// Parse a hex-encoded compressed public key
+pub(crate) fn parse_key(key: &str) -> Result<PublicKey, ContractError> {
+    let public_key = PublicKey::from_str(key)
+        .map_err(|e| ContractError::InvalidDescriptor(format!("Invalid public key {}: {}", key, e)))?;
+    
+    // Segwit scripts only take compressed keys
+    if !public_key.compressed {
+        return Err(ContractError::InvalidDescriptor(format!("Public key {} is not compressed", key)));
+    }
+    
+    Ok(public_key)
+}
@@ -0,0 +1,395 @@
+//! Circuit breakers for optional backends
+//!
+//! A flapping Ordinals API should not hold up every ordinal operation for a
+//! full timeout while Bitcoin keeps working. Each optional backend gets a
+//! `FeatureGate` that watches the outcome of its recent calls and health
+//! probes. Once the share of failures crosses the threshold the gate opens
+//! and operations on the backend's tokens are refused right away with a
+//! retry-after hint. After the cooldown the gate lets one call through as a
+//! probe: success closes it, failure opens it for another cooldown.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::health::BackendComponent;
+use crate::clock::{Clock, SystemClock};
+use crate::errors::ContractError;
+use crate::models::TokenType;
+use crate::logging::TRANSFER;
+
+/// State of a feature gate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GateState {
+    /// Calls go through
+    Closed,
+    /// Calls are refused until the cooldown ends
+    Open,
+    /// One probe call is in flight; others are refused until it ends
+    HalfOpen,
+}
+
+impl fmt::Display for GateState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GateState::Closed => write!(f, "closed"),
+            GateState::Open => write!(f, "open"),
+            GateState::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// When a feature gate opens and how long it stays open
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureGateConfig {
+    /// Recent outcomes the failure ratio is computed over
+    pub window: usize,
+    /// Fewest outcomes in the window before the gate may open
+    pub min_calls: usize,
+    /// Share of failed outcomes, from 0 to 1, that opens the gate
+    pub failure_ratio: f64,
+    /// How long the gate stays open before letting a probe through
+    pub cooldown: Duration,
+}
+
+impl Default for FeatureGateConfig {
+    fn default() -> Self {
+        Self {
+            window: 20,
+            min_calls: 5,
+            failure_ratio: 0.5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+impl FeatureGateConfig {
+    /// Validate the settings
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window == 0 {
+            return Err("Feature gate window must be at least 1".to_string());
+        }
+        
+        if self.min_calls == 0 || self.min_calls > self.window {
+            return Err("Feature gate minimum calls must be between 1 and the window".to_string());
+        }
+        
+        if !(self.failure_ratio > 0.0 && self.failure_ratio <= 1.0) {
+            return Err("Feature gate failure ratio must be above 0 and at most 1".to_string());
+        }
+        
+        Ok(())
+    }
+}
+
+/// State of a feature gate for diagnostics
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureGateStatus {
+    /// Backend the gate guards
+    pub component: BackendComponent,
+    /// Current state
+    pub state: GateState,
+    /// Outcomes in the window
+    pub recent_calls: usize,
+    /// Failed outcomes in the window
+    pub recent_failures: usize,
+    /// When the gate last opened, if it is not closed
+    pub opened_at: Option<DateTime<Utc>>,
+    /// Seconds until the gate lets a probe through, if it is open
+    pub retry_after_secs: Option<u64>,
+    /// Times the gate has opened
+    pub trips: u64,
+}
+
+/// Mutable state of a feature gate
+#[derive(Debug)]
+struct GateInner {
+    /// Current state
+    state: GateState,
+    /// Recent outcomes, oldest first, `true` for a failure
+    outcomes: VecDeque<bool>,
+    /// When the gate last opened
+    opened_at: Option<DateTime<Utc>>,
+    /// Times the gate has opened
+    trips: u64,
+    /// Time source for the cooldown
+    clock: Arc<dyn Clock>,
+}
+
+/// Circuit breaker of one optional backend
+#[derive(Debug)]
+pub struct FeatureGate {
+    /// Backend the gate guards
+    component: BackendComponent,
+    /// Settings
+    config: FeatureGateConfig,
+    /// Mutable state, never held across a backend call
+    inner: Mutex<GateInner>,
+}
+
+impl FeatureGate {
+    /// Create a closed gate for a backend
+    pub fn new(component: BackendComponent, config: FeatureGateConfig) -> Self {
+        Self {
+            component,
+            config,
+            inner: Mutex::new(GateInner {
+                state: GateState::Closed,
+                outcomes: VecDeque::new(),
+                opened_at: None,
+                trips: 0,
+                clock: Arc::new(SystemClock),
+            }),
+        }
+    }
+    
+    /// Backend the gate guards
+    pub fn component(&self) -> BackendComponent {
+        self.component
+    }
+    
+    /// Replace the time source used for the cooldown
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.clock = clock;
+        }
+    }
+    
+    /// Refuse new work while the gate is open, without taking the probe slot
+    ///
+    /// For queueing work the backend handles later; the call that reaches the
+    /// backend goes through `call`.
+    pub fn admit(&self) -> Result<(), ContractError> {
+        let inner = self.lock()?;
+        
+        match inner.state {
+            GateState::Open if self.remaining_cooldown(&inner).is_some() => Err(self.rejection(&inner)),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Run a backend call through the gate, recording its outcome
+    ///
+    /// Fails right away with `BackendUnavailable` while the gate is open or
+    /// a probe is in flight. Errors blaming the caller rather than the
+    /// backend, such as an unauthorized transfer, do not count as failures.
+    pub fn call<T>(&self, operation: impl FnOnce() -> Result<T, ContractError>) -> Result<T, ContractError> {
+        self.enter()?;
+        
+        let result = operation();
+        self.record(match &result {
+            Ok(_) => true,
+            Err(e) => !is_backend_failure(e),
+        });
+        
+        result
+    }
+    
+    /// Record the outcome of a health probe
+    ///
+    /// A successful probe after the cooldown closes an open gate, as a probe
+    /// call would; other probes while the gate is open are ignored.
+    pub fn record_probe(&self, healthy: bool) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        
+        if inner.state == GateState::Open {
+            if healthy && self.remaining_cooldown(&inner).is_none() {
+                self.close(&mut inner);
+            }
+            return;
+        }
+        
+        self.push_outcome(&mut inner, healthy);
+    }
+    
+    /// Current state
+    pub fn state(&self) -> GateState {
+        self.inner.lock().map(|inner| inner.state).unwrap_or(GateState::Open)
+    }
+    
+    /// State of the gate for diagnostics
+    pub fn status(&self) -> FeatureGateStatus {
+        let inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        
+        FeatureGateStatus {
+            component: self.component,
+            state: inner.state,
+            recent_calls: inner.outcomes.len(),
+            recent_failures: inner.outcomes.iter().filter(|failed| **failed).count(),
+            opened_at: inner.opened_at,
+            retry_after_secs: match inner.state {
+                GateState::Open => Some(self.remaining_cooldown(&inner).map_or(0, |remaining| remaining.as_secs())),
+                _ => None,
+            },
+            trips: inner.trips,
+        }
+    }
+    
+    /// Lock the state
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, GateInner>, ContractError> {
+        self.inner.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))
+    }
+    
+    /// Let a call through or refuse it, taking the probe slot after the cooldown
+    fn enter(&self) -> Result<(), ContractError> {
+        let mut inner = self.lock()?;
+        
+        match inner.state {
+            GateState::Closed => Ok(()),
+            GateState::HalfOpen => Err(self.rejection(&inner)),
+            GateState::Open => {
+                if self.remaining_cooldown(&inner).is_some() {
+                    return Err(self.rejection(&inner));
+                }
+                
+                info!(target: TRANSFER, "{} gate half-open, probing", self.component);
+                inner.state = GateState::HalfOpen;
+                Ok(())
+            },
+        }
+    }
+    
+    /// Record the outcome of a call let through
+    fn record(&self, success: bool) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        
+        match inner.state {
+            GateState::HalfOpen if success => self.close(&mut inner),
+            GateState::HalfOpen => self.trip(&mut inner),
+            GateState::Closed => self.push_outcome(&mut inner, success),
+            // Calls let through before the gate opened end after the fact
+            GateState::Open => {},
+        }
+    }
+    
+    /// Add an outcome to the window, opening the gate past the failure ratio
+    fn push_outcome(&self, inner: &mut GateInner, success: bool) {
+        inner.outcomes.push_back(!success);
+        while inner.outcomes.len() > self.config.window {
+            inner.outcomes.pop_front();
+        }
+        
+        let failures = inner.outcomes.iter().filter(|failed| **failed).count();
+        if inner.outcomes.len() >= self.config.min_calls
+            && failures as f64 >= self.config.failure_ratio * inner.outcomes.len() as f64
+        {
+            self.trip(inner);
+        }
+    }
+    
+    /// Open the gate for a cooldown
+    fn trip(&self, inner: &mut GateInner) {
+        warn!(target: TRANSFER, "{} gate open for {}s after repeated failures", self.component, self.config.cooldown.as_secs());
+        
+        inner.state = GateState::Open;
+        inner.opened_at = Some(inner.clock.now());
+        inner.trips += 1;
+    }
+    
+    /// Close the gate with a clean window
+    fn close(&self, inner: &mut GateInner) {
+        info!(target: TRANSFER, "{} gate closed", self.component);
+        
+        inner.state = GateState::Closed;
+        inner.opened_at = None;
+        inner.outcomes.clear();
+    }
+    
+    /// Time left of the cooldown, `None` once it has passed
+    fn remaining_cooldown(&self, inner: &GateInner) -> Option<Duration> {
+        let opened_at = inner.opened_at?;
+        let elapsed = (inner.clock.now() - opened_at).to_std().unwrap_or_default();
+        self.config.cooldown.checked_sub(elapsed).filter(|remaining| !remaining.is_zero())
+    }
+    
+    /// Error refusing a call while the gate is open
+    fn rejection(&self, inner: &GateInner) -> ContractError {
+        // Round up, so a client waiting the hint finds the cooldown over
+        let retry_after = self.remaining_cooldown(inner)
+            .map_or(0, |remaining| remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0));
+        
+        ContractError::BackendUnavailable(format!("{} failing, retry after {}s", self.component, retry_after))
+    }
+}
+
+/// Whether an error is the backend's fault rather than the caller's
+fn is_backend_failure(error: &ContractError) -> bool {
+    !matches!(
+        error,
+        ContractError::Unauthorized
+            | ContractError::InvalidAddress
+            | ContractError::InvalidAmount { .. }
+            | ContractError::UnsupportedTokenOperation
+    )
+}
+
+/// Feature gates of the optional backends of a transfer
+#[derive(Debug, Clone)]
+pub struct FeatureGates {
+    /// Lightning node
+    lightning: Arc<FeatureGate>,
+    /// Ordinals API
+    ordinals: Arc<FeatureGate>,
+    /// Runes indexer
+    runes: Arc<FeatureGate>,
+}
+
+impl FeatureGates {
+    /// Create closed gates sharing one configuration
+    pub fn new(config: &FeatureGateConfig) -> Self {
+        Self {
+            lightning: Arc::new(FeatureGate::new(BackendComponent::Lightning, config.clone())),
+            ordinals: Arc::new(FeatureGate::new(BackendComponent::Ordinals, config.clone())),
+            runes: Arc::new(FeatureGate::new(BackendComponent::RunesIndexer, config.clone())),
+        }
+    }
+    
+    /// Gate of the optional backend a token type depends on, `None` for Bitcoin
+    pub fn for_token_type(&self, token_type: &TokenType) -> Option<&Arc<FeatureGate>> {
+        match token_type {
+            TokenType::Lightning => Some(&self.lightning),
+            TokenType::Ordinal(_) => Some(&self.ordinals),
+            TokenType::Rune(_) => Some(&self.runes),
+            _ => None,
+        }
+    }
+    
+    /// Gate of a backend, `None` for backends without one
+    pub fn get(&self, component: BackendComponent) -> Option<&Arc<FeatureGate>> {
+        match component {
+            BackendComponent::Lightning => Some(&self.lightning),
+            BackendComponent::Ordinals => Some(&self.ordinals),
+            BackendComponent::RunesIndexer => Some(&self.runes),
+            BackendComponent::BitcoinRpc => None,
+        }
+    }
+    
+    /// Every gate
+    pub fn all(&self) -> [&Arc<FeatureGate>; 3] {
+        [&self.lightning, &self.ordinals, &self.runes]
+    }
+    
+    /// State of every gate for diagnostics
+    pub fn statuses(&self) -> Vec<FeatureGateStatus> {
+        self.all().iter().map(|gate| gate.status()).collect()
+    }
+    
+    /// Replace the time source used for the cooldowns
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        for gate in self.all() {
+            gate.set_clock(clock.clone());
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Shared fee rate estimates
+//!
+//! One `FeeOracle` owns the fee rate cache of a node connection. The RPC
+//! client, the transfer queue and payout batches, inscriptions and withdrawal
+//! fee quotes all price transactions through it, so they agree on the rate
+//! within the same second. Every change to the cached rates starts a new
+//! snapshot with a higher ID: rates read under the same snapshot ID are the
+//! same everywhere, and a quote can record the snapshot it was priced against.
+//!
+//! A rate is served from the cache while it is younger than the cache TTL and
+//! fetched from the node otherwise. When the node has no estimate, the rate
+//! last fetched for the target is used while younger than `max_fallback_age`,
+//! then the configured floor rate; without either the estimate fails. Rates
+//! served from a fallback or past the TTL mark the oracle as stale.
+//!
+//! Rates are asked for by `ConfirmationTarget`: the urgent, normal and
+//! economy service levels resolve to blocks through the configured
+//! `FeeTargets`, and a custom target names its blocks itself.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::thread;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::testnet::FeeTargets;
+use crate::clock::{Clock, SystemClock};
+use crate::errors::ContractError;
+use crate::metrics::{Counter, MetricsRegistry};
+use crate::models::TransferPriority;
+use crate::logging::RPC;
+
+/// Service level a fee is estimated for
+/// 
+/// Serializes as `"urgent"`, `"normal"`, `"economy"` or `{"custom": blocks}`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfirmationTarget {
+    /// Next block or so, for transfers that cannot wait
+    Urgent,
+    /// Within about an hour
+    Normal,
+    /// Within about a day, for transfers nobody waits on
+    Economy,
+    /// Within this many blocks
+    Custom(u16),
+}
+
+impl ConfirmationTarget {
+    /// Name of the service level
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfirmationTarget::Urgent => "urgent",
+            ConfirmationTarget::Normal => "normal",
+            ConfirmationTarget::Economy => "economy",
+            ConfirmationTarget::Custom(_) => "custom",
+        }
+    }
+}
+
+impl fmt::Display for ConfirmationTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfirmationTarget::Custom(blocks) => write!(f, "custom ({} blocks)", blocks),
+            named => f.write_str(named.name()),
+        }
+    }
+}
+
+impl From<u16> for ConfirmationTarget {
+    fn from(blocks: u16) -> Self {
+        ConfirmationTarget::Custom(blocks)
+    }
+}
+
+impl From<TransferPriority> for ConfirmationTarget {
+    fn from(priority: TransferPriority) -> Self {
+        match priority {
+            TransferPriority::High => ConfirmationTarget::Urgent,
+            TransferPriority::Normal => ConfirmationTarget::Normal,
+            TransferPriority::Low => ConfirmationTarget::Economy,
+        }
+    }
+}
+
+/// Fee rate in satoshis per virtual byte
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct FeeRate(f64);
+
+impl FeeRate {
+    /// Create a fee rate from satoshis per virtual byte
+    pub fn from_sat_per_vb(sat_per_vb: f64) -> Self {
+        Self(sat_per_vb)
+    }
+    
+    /// Satoshis per virtual byte
+    pub fn sat_per_vb(&self) -> f64 {
+        self.0
+    }
+    
+    /// Fee of a transaction of `vsize` virtual bytes, rounded up
+    pub fn fee_for_vsize(&self, vsize: u64) -> u64 {
+        (vsize as f64 * self.0).ceil() as u64
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3} sat/vB", self.0)
+    }
+}
+
+/// Where a cached fee rate came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeeRateOrigin {
+    /// Estimated by the node
+    Node,
+    /// Last node estimate, kept because the node had none
+    LastKnown,
+    /// Configured floor rate, used because the node had no estimate
+    Floor,
+}
+
+/// Fee rate cached for one confirmation target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    /// Confirmation target
+    pub target: ConfirmationTarget,
+    /// Blocks the target resolved to
+    pub blocks: u16,
+    /// Fee rate
+    pub fee_rate: FeeRate,
+    /// Where the rate came from
+    pub origin: FeeRateOrigin,
+    /// When the rate was fetched, or the fallback applied
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// Fee rates of the oracle at one moment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeeSnapshot {
+    /// Snapshot ID, increasing with every change to the rates
+    pub snapshot_id: u64,
+    /// When the snapshot was read
+    pub taken_at: DateTime<Utc>,
+    /// Cached rates, by confirmation target
+    pub estimates: Vec<FeeEstimate>,
+    /// Error of the last failed fetch, cleared by the next successful one
+    pub last_error: Option<String>,
+    /// Whether any rate is missing fresh node data
+    pub stale: bool,
+}
+
+impl FeeSnapshot {
+    /// Rate cached for a confirmation target
+    pub fn fee_rate(&self, target: ConfirmationTarget) -> Option<FeeRate> {
+        self.estimates.iter()
+            .find(|estimate| estimate.target == target)
+            .map(|estimate| estimate.fee_rate)
+    }
+}
+
+/// Source of fresh fee estimates, usually the node
+pub trait FeeSource: Send + Sync + fmt::Debug {
+    /// Estimate the fee rate confirming within `blocks`
+    fn estimate_fee_rate(&self, blocks: u16) -> Result<FeeRate, ContractError>;
+}
+
+/// Settings of a fee oracle
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeOracleConfig {
+    /// How long a node estimate is served without asking the node again
+    pub cache_ttl: Duration,
+    /// How long the last node estimate stands in when the node has none
+    pub max_fallback_age: Duration,
+    /// Rate used when the node has no estimate and no recent one is cached, if any
+    pub floor_rate: Option<FeeRate>,
+    /// Interval of the background refresh
+    pub refresh_interval: Duration,
+}
+
+impl Default for FeeOracleConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl: Duration::from_secs(600),
+            max_fallback_age: Duration::from_secs(3600),
+            floor_rate: None,
+            refresh_interval: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Mutable state of a fee oracle
+struct OracleState {
+    /// Cached rates by confirmation target
+    estimates: BTreeMap<ConfirmationTarget, FeeEstimate>,
+    /// Targets the background refresh keeps warm
+    tracked: BTreeSet<ConfirmationTarget>,
+    /// ID of the current snapshot
+    snapshot_id: u64,
+    /// Error of the last failed fetch
+    last_error: Option<String>,
+    /// Time source for cache expiry
+    clock: Arc<dyn Clock>,
+    /// Successful fetches
+    refreshes: Arc<Counter>,
+    /// Failed fetches
+    refresh_failures: Arc<Counter>,
+}
+
+impl OracleState {
+    /// Replace the cached rate of a target, starting a new snapshot
+    fn store(&mut self, estimate: FeeEstimate) -> (FeeRate, u64) {
+        let fee_rate = estimate.fee_rate;
+        self.estimates.insert(estimate.target, estimate);
+        self.snapshot_id += 1;
+        
+        (fee_rate, self.snapshot_id)
+    }
+}
+
+/// Fee rate cache and fallback chain shared by every component pricing transactions
+pub struct FeeOracle {
+    /// Settings
+    config: FeeOracleConfig,
+    /// Source of fresh estimates
+    source: Arc<dyn FeeSource>,
+    /// Blocks of the named confirmation targets
+    targets: FeeTargets,
+    /// Cached rates and counters
+    state: Mutex<OracleState>,
+    /// Held while fetching, so concurrent misses ask the node once
+    fetching: Mutex<()>,
+    /// Running flag of the background refresh
+    running: Arc<Mutex<bool>>,
+}
+
+impl fmt::Debug for FeeOracle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeeOracle")
+            .field("config", &self.config)
+            .field("source", &self.source)
+            .field("targets", &self.targets)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FeeOracle {
+    /// Create an oracle over a source, reporting to a private metrics registry
+    pub fn new(config: FeeOracleConfig, source: Arc<dyn FeeSource>) -> Self {
+        let metrics = MetricsRegistry::new();
+        
+        Self {
+            config,
+            source,
+            targets: FeeTargets::default(),
+            state: Mutex::new(OracleState {
+                estimates: BTreeMap::new(),
+                tracked: BTreeSet::new(),
+                snapshot_id: 0,
+                last_error: None,
+                clock: Arc::new(SystemClock),
+                refreshes: metrics.counter("fee_oracle_refreshes"),
+                refresh_failures: metrics.counter("fee_oracle_refresh_failures"),
+            }),
+            fetching: Mutex::new(()),
+            running: Arc::new(Mutex::new(false)),
+        }
+    }
+    
+    /// Resolve the named targets with these blocks instead of the defaults
+    pub fn with_targets(mut self, targets: FeeTargets) -> Self {
+        self.targets = targets;
+        self
+    }
+    
+    /// Keep the rates of these targets warm in the background refresh
+    pub fn tracking(self, targets: impl IntoIterator<Item = ConfirmationTarget>) -> Self {
+        if let Ok(mut state) = self.state.lock() {
+            state.tracked.extend(targets);
+        }
+        self
+    }
+    
+    /// Settings
+    pub fn config(&self) -> &FeeOracleConfig {
+        &self.config
+    }
+    
+    /// Get the blocks a confirmation target resolves to
+    pub fn blocks(&self, target: ConfirmationTarget) -> u16 {
+        self.targets.blocks(target)
+    }
+    
+    /// Report refreshes and refresh failures to a shared registry
+    pub fn set_metrics_registry(&self, metrics: &MetricsRegistry) {
+        if let Ok(mut state) = self.state.lock() {
+            state.refreshes = metrics.counter("fee_oracle_refreshes");
+            state.refresh_failures = metrics.counter("fee_oracle_refresh_failures");
+        }
+    }
+    
+    /// Replace the time source used for cache expiry
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.clock = clock;
+        }
+    }
+    
+    /// Get the fee rate confirming within `target`
+    pub fn fee_rate(&self, target: ConfirmationTarget) -> Result<FeeRate, ContractError> {
+        self.fee_rate_with_snapshot(target).map(|(fee_rate, _)| fee_rate)
+    }
+    
+    /// Get the fee rate confirming within `target` and the ID of the snapshot it belongs to
+    pub fn fee_rate_with_snapshot(&self, target: ConfirmationTarget) -> Result<(FeeRate, u64), ContractError> {
+        if let Some(cached) = self.fresh(target)? {
+            return Ok(cached);
+        }
+        
+        let _fetching = self.fetching.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        // Another caller may have fetched the rate while this one waited
+        if let Some(cached) = self.fresh(target)? {
+            return Ok(cached);
+        }
+        
+        self.fetch(target)
+    }
+    
+    /// Get the cached rates without asking the node
+    pub fn current_snapshot(&self) -> FeeSnapshot {
+        let Ok(state) = self.state.lock() else {
+            return FeeSnapshot {
+                snapshot_id: 0,
+                taken_at: Utc::now(),
+                estimates: Vec::new(),
+                last_error: Some("Fee oracle lock poisoned".to_string()),
+                stale: true,
+            };
+        };
+        
+        let now = state.clock.now();
+        let estimates: Vec<FeeEstimate> = state.estimates.values().cloned().collect();
+        let stale = state.tracked.iter().any(|target| !state.estimates.contains_key(target))
+            || estimates.iter().any(|estimate| !self.is_fresh(estimate, now));
+        
+        FeeSnapshot {
+            snapshot_id: state.snapshot_id,
+            taken_at: now,
+            estimates,
+            last_error: state.last_error.clone(),
+            stale,
+        }
+    }
+    
+    /// Whether any cached or tracked rate is missing fresh node data
+    pub fn is_stale(&self) -> bool {
+        self.current_snapshot().stale
+    }
+    
+    /// Fetch every tracked target from the source, returning the resulting snapshot
+    ///
+    /// Targets the source has no estimate for fall back as usual, and show
+    /// up as a stale snapshot and in the refresh failure counter.
+    pub fn refresh(&self) -> FeeSnapshot {
+        let targets: Vec<ConfirmationTarget> = match self.state.lock() {
+            Ok(state) => state.tracked.iter().chain(state.estimates.keys()).copied().collect::<BTreeSet<_>>().into_iter().collect(),
+            Err(_) => Vec::new(),
+        };
+        
+        if let Ok(_fetching) = self.fetching.lock() {
+            for target in targets {
+                if let Err(e) = self.fetch(target) {
+                    warn!(target: RPC, "No fee rate for {}: {}", target, e);
+                }
+            }
+        }
+        
+        self.current_snapshot()
+    }
+    
+    /// Refresh the rates in the background every `refresh_interval`
+    ///
+    /// The refresh stops with `stop` or once the oracle is dropped.
+    pub fn start(self: &Arc<Self>) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        if *running {
+            return Ok(());
+        }
+        
+        *running = true;
+        
+        // The thread must not keep the oracle alive
+        let oracle: Weak<Self> = Arc::downgrade(self);
+        let running = self.running.clone();
+        let interval = self.config.refresh_interval;
+        
+        thread::spawn(move || {
+            info!(target: RPC, "Fee oracle refresh started");
+            
+            while *running.lock().unwrap() {
+                let Some(oracle) = oracle.upgrade() else {
+                    break;
+                };
+                oracle.refresh();
+                drop(oracle);
+                
+                thread::sleep(interval);
+            }
+            
+            info!(target: RPC, "Fee oracle refresh stopped");
+        });
+        
+        Ok(())
+    }
+    
+    /// Stop the background refresh
+    pub fn stop(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *running = false;
+        
+        Ok(())
+    }
+    
+    /// Lock the state
+    fn lock(&self) -> Result<MutexGuard<'_, OracleState>, ContractError> {
+        self.state.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))
+    }
+    
+    /// Whether a cached rate is a node estimate younger than the cache TTL
+    fn is_fresh(&self, estimate: &FeeEstimate, now: DateTime<Utc>) -> bool {
+        estimate.origin == FeeRateOrigin::Node
+            && (now - estimate.fetched_at).to_std().is_ok_and(|age| age < self.config.cache_ttl)
+    }
+    
+    /// Get a fresh cached rate and its snapshot, tracking the target
+    fn fresh(&self, target: ConfirmationTarget) -> Result<Option<(FeeRate, u64)>, ContractError> {
+        let mut state = self.lock()?;
+        state.tracked.insert(target);
+        
+        let now = state.clock.now();
+        Ok(state.estimates.get(&target)
+            .filter(|estimate| self.is_fresh(estimate, now))
+            .map(|estimate| (estimate.fee_rate, state.snapshot_id)))
+    }
+    
+    /// Ask the source for a rate, falling back when it has none
+    fn fetch(&self, target: ConfirmationTarget) -> Result<(FeeRate, u64), ContractError> {
+        let blocks = self.blocks(target);
+        let result = self.source.estimate_fee_rate(blocks);
+        
+        let mut state = self.lock()?;
+        let now = state.clock.now();
+        
+        let error = match result {
+            Ok(fee_rate) => {
+                state.refreshes.inc();
+                state.last_error = None;
+                return Ok(state.store(FeeEstimate { target, blocks, fee_rate, origin: FeeRateOrigin::Node, fetched_at: now }));
+            },
+            Err(e) => e,
+        };
+        
+        state.refresh_failures.inc();
+        state.last_error = Some(error.to_string());
+        
+        // The last node estimate while it is recent enough
+        let last_known = state.estimates.get(&target)
+            .filter(|estimate| estimate.origin != FeeRateOrigin::Floor)
+            .filter(|estimate| (now - estimate.fetched_at).to_std().is_ok_and(|age| age < self.config.max_fallback_age))
+            .cloned();
+        if let Some(estimate) = last_known {
+            warn!(target: RPC, "No fee estimate for {}, keeping the last one of {}: {}", target, estimate.fee_rate, error);
+            if estimate.origin == FeeRateOrigin::LastKnown {
+                return Ok((estimate.fee_rate, state.snapshot_id));
+            }
+            return Ok(state.store(FeeEstimate { origin: FeeRateOrigin::LastKnown, ..estimate }));
+        }
+        
+        // Then the floor rate
+        if let Some(fee_rate) = self.config.floor_rate {
+            warn!(target: RPC, "No fee estimate for {}, using the floor rate of {}: {}", target, fee_rate, error);
+            return Ok(state.store(FeeEstimate { target, blocks, fee_rate, origin: FeeRateOrigin::Floor, fetched_at: now }));
+        }
+        
+        Err(error)
+    }
+}
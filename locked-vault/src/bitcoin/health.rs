@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::models::TokenType;
+use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::bitcoin::lightning::LightningClient;
+use crate::bitcoin::ordinals::OrdinalsClient;
+use crate::bitcoin::feature_gate::FeatureGate;
+use crate::logging::TRANSFER;
+
+/// Minimum verification progress for the node to be considered synced
+pub(crate) const MIN_VERIFICATION_PROGRESS: f64 = 0.9999;
+
+/// Backend components monitored by the health checker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BackendComponent {
+    /// Bitcoin Core RPC node
+    BitcoinRpc,
+    /// Lightning Network node
+    Lightning,
+    /// Ordinals API
+    Ordinals,
+    /// Runes indexer
+    RunesIndexer,
+}
+
+impl BackendComponent {
+    /// Get the backend component a token type depends on
+    pub fn for_token_type(token_type: &TokenType) -> Option<Self> {
+        match token_type {
+            TokenType::Bitcoin | TokenType::Rune(_) => Some(BackendComponent::BitcoinRpc),
+            TokenType::Lightning => Some(BackendComponent::Lightning),
+            TokenType::Ordinal(_) => Some(BackendComponent::Ordinals),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BackendComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendComponent::BitcoinRpc => write!(f, "bitcoin-rpc"),
+            BackendComponent::Lightning => write!(f, "lightning"),
+            BackendComponent::Ordinals => write!(f, "ordinals"),
+            BackendComponent::RunesIndexer => write!(f, "runes-indexer"),
+        }
+    }
+}
+
+/// Health snapshot for a single backend component
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// Component this status describes
+    pub component: BackendComponent,
+    /// Whether the last probe succeeded
+    pub healthy: bool,
+    /// Latency of the last probe
+    pub latency: Option<Duration>,
+    /// Error returned by the last failed probe
+    pub last_error: Option<String>,
+    /// Timestamp of the last successful probe
+    pub last_success: Option<DateTime<Utc>>,
+    /// Timestamp since which the component has been continuously unhealthy
+    pub unhealthy_since: Option<DateTime<Utc>>,
+}
+
+impl HealthStatus {
+    /// Create a status for a component that has not been probed yet
+    fn unknown(component: BackendComponent) -> Self {
+        Self {
+            component,
+            healthy: false,
+            latency: None,
+            last_error: Some("Not probed yet".to_string()),
+            last_success: None,
+            unhealthy_since: Some(Utc::now()),
+        }
+    }
+}
+
+/// A backend that can be probed for liveness
+pub trait HealthProbe: fmt::Debug + Send + Sync {
+    /// Component probed by this implementation
+    fn component(&self) -> BackendComponent;
+    
+    /// Probe the backend, returning an error if it is unusable
+    fn probe(&self) -> Result<(), ContractError>;
+}
+
+impl HealthProbe for BitcoinRpcClient {
+    fn component(&self) -> BackendComponent {
+        BackendComponent::BitcoinRpc
+    }
+    
+    fn probe(&self) -> Result<(), ContractError> {
+        let info = self.get_blockchain_info()?;
+        
+        // A node still in initial block download reports stale balances
+        if info.initial_block_download {
+            return Err(ContractError::BitcoinTestnetError("Node is in initial block download".to_string()));
+        }
+        
+        if info.verification_progress < MIN_VERIFICATION_PROGRESS {
+            return Err(ContractError::BitcoinTestnetError(
+                format!("Node is not synced (verification progress {:.4})", info.verification_progress)
+            ));
+        }
+        
+        Ok(())
+    }
+}
+
+impl HealthProbe for LightningClient {
+    fn component(&self) -> BackendComponent {
+        BackendComponent::Lightning
+    }
+    
+    fn probe(&self) -> Result<(), ContractError> {
+        self.get_node_info()?;
+        
+        // An unsynced node cannot route payments reliably
+        if !self.is_synced_to_chain()? {
+            return Err(ContractError::BitcoinTestnetError("Lightning node is not synced to chain".to_string()));
+        }
+        
+        Ok(())
+    }
+}
+
+impl HealthProbe for OrdinalsClient {
+    fn component(&self) -> BackendComponent {
+        BackendComponent::Ordinals
+    }
+    
+    fn probe(&self) -> Result<(), ContractError> {
+        self.ping()
+    }
+}
+
+/// Periodically probes backends and keeps a health snapshot for each
+#[derive(Debug)]
+pub struct HealthChecker {
+    /// Registered probes
+    probes: Vec<Arc<dyn HealthProbe>>,
+    /// Feature gates fed the probe outcomes of their backend
+    gates: Vec<Arc<FeatureGate>>,
+    /// Latest status per component
+    statuses: Arc<Mutex<HashMap<BackendComponent, HealthStatus>>>,
+    /// Running flag
+    running: Arc<Mutex<bool>>,
+    /// Probe interval
+    interval: Duration,
+}
+
+impl HealthChecker {
+    /// Create a new health checker
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            probes: Vec::new(),
+            gates: Vec::new(),
+            statuses: Arc::new(Mutex::new(HashMap::new())),
+            running: Arc::new(Mutex::new(false)),
+            interval,
+        }
+    }
+    
+    /// Register a probe
+    pub fn add_probe(&mut self, probe: Arc<dyn HealthProbe>) {
+        let component = probe.component();
+        
+        if let Ok(mut statuses) = self.statuses.lock() {
+            statuses.entry(component).or_insert_with(|| HealthStatus::unknown(component));
+        }
+        
+        self.probes.push(probe);
+    }
+    
+    /// Feed the probe outcomes of the gate's backend to a feature gate
+    pub fn add_gate(&mut self, gate: Arc<FeatureGate>) {
+        self.gates.push(gate);
+    }
+    
+    /// Run every probe once and update the snapshot
+    pub fn check_now(&self) -> Result<(), ContractError> {
+        Self::run_probes(&self.probes, &self.gates, &self.statuses)
+    }
+    
+    /// Run probes and record their outcome
+    fn run_probes(
+        probes: &[Arc<dyn HealthProbe>],
+        gates: &[Arc<FeatureGate>],
+        statuses: &Mutex<HashMap<BackendComponent, HealthStatus>>,
+    ) -> Result<(), ContractError> {
+        for probe in probes {
+            let started = Instant::now();
+            let result = probe.probe();
+            let latency = started.elapsed();
+            
+            for gate in gates.iter().filter(|gate| gate.component() == probe.component()) {
+                gate.record_probe(result.is_ok());
+            }
+            
+            let mut statuses = statuses.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            let component = probe.component();
+            let status = statuses.entry(component).or_insert_with(|| HealthStatus::unknown(component));
+            
+            let now = Utc::now();
+            status.latency = Some(latency);
+            
+            match result {
+                Ok(()) => {
+                    status.healthy = true;
+                    status.last_error = None;
+                    status.last_success = Some(now);
+                    status.unhealthy_since = None;
+                },
+                Err(e) => {
+                    warn!(target: TRANSFER, "Health probe for {} failed: {}", component, e);
+                    
+                    status.healthy = false;
+                    status.last_error = Some(e.to_string());
+                    status.unhealthy_since.get_or_insert(now);
+                },
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Start probing in the background
+    pub fn start(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        if *running {
+            return Ok(());
+        }
+        
+        *running = true;
+        
+        // Clone Arc references for the thread
+        let probes = self.probes.clone();
+        let gates = self.gates.clone();
+        let statuses = self.statuses.clone();
+        let running = self.running.clone();
+        let interval = self.interval;
+        
+        // Spawn probing thread
+        thread::spawn(move || {
+            info!(target: TRANSFER, "Health checking started");
+            
+            while *running.lock().unwrap() {
+                if let Err(e) = Self::run_probes(&probes, &gates, &statuses) {
+                    warn!(target: TRANSFER, "Health check failed: {:?}", e);
+                }
+                
+                debug!(target: TRANSFER, "Health check completed for {} backends", probes.len());
+                
+                thread::sleep(interval);
+            }
+            
+            info!(target: TRANSFER, "Health checking stopped");
+        });
+        
+        Ok(())
+    }
+    
+    /// Stop background probing
+    pub fn stop(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *running = false;
+        
+        Ok(())
+    }
+    
+    /// Get the current health snapshot of every registered component
+    pub fn health_report(&self) -> Vec<HealthStatus> {
+        let statuses = match self.statuses.lock() {
+            Ok(statuses) => statuses,
+            Err(_) => return Vec::new(),
+        };
+        
+        let mut report: Vec<HealthStatus> = statuses.values().cloned().collect();
+        report.sort_by_key(|status| status.component.to_string());
+        report
+    }
+    
+    /// Check whether every registered component is healthy
+    pub fn is_healthy(&self) -> bool {
+        self.health_report().iter().all(|status| status.healthy)
+    }
+    
+    /// Check whether a component is usable, tolerating outages shorter than the grace period
+    ///
+    /// Components without a registered probe are assumed available.
+    pub fn is_available(&self, component: BackendComponent, grace_period: Duration) -> bool {
+        let statuses = match self.statuses.lock() {
+            Ok(statuses) => statuses,
+            Err(_) => return false,
+        };
+        
+        match statuses.get(&component) {
+            Some(status) if !status.healthy => {
+                let since = status.unhealthy_since.unwrap_or_else(Utc::now);
+                (Utc::now() - since).to_std()
+                    .map(|elapsed| elapsed < grace_period)
+                    .unwrap_or(true)
+            },
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Detection of funds sent straight to the contract wallet
+//!
+//! Funds that arrive without a `deposit()` call would otherwise sit in the
+//! wallet unaccounted for. The watcher lists the wallet's outputs and reports
+//! each one that has the confirmations the confirmation policy requires for
+//! its amount, is not yet known and
+//! was not paid by the wallet itself (change of our own payouts), so the
+//! contract can record it as unattributed.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{error, info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::models::TokenType;
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::logging::MEMPOOL;
+
+/// Confirmed output to the contract wallet that no known transfer accounts for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IncomingFunds {
+    /// Transaction ID
+    pub txid: String,
+    /// Output index
+    pub vout: u32,
+    /// Amount in satoshis
+    pub amount: u64,
+    /// Addresses that funded the transaction, best effort
+    pub sender_addresses: Vec<String>,
+    /// Confirmations when detected
+    pub confirmations: u32,
+}
+
+impl IncomingFunds {
+    /// Get the output reference (`txid:vout`)
+    pub fn outpoint(&self) -> String {
+        format!("{}:{}", self.txid, self.vout)
+    }
+}
+
+/// Callback receiving detected funds
+pub type IncomingCallback = Arc<dyn Fn(&IncomingFunds) + Send + Sync>;
+
+/// Watcher for confirmed outputs to the contract wallet
+pub struct IncomingFundsWatcher {
+    /// Bitcoin RPC client
+    bitcoin_rpc: Arc<BitcoinRpcClient>,
+    /// Contract wallet address
+    wallet_address: String,
+    /// Confirmations an output needs before it is reported, by amount
+    confirmation_policy: Arc<Mutex<ConfirmationPolicy>>,
+    /// Output references already reported or belonging to known transfers
+    known: Arc<Mutex<HashSet<String>>>,
+    /// Receiver of detected funds
+    callback: Arc<Mutex<Option<IncomingCallback>>>,
+    /// Running flag
+    running: Arc<Mutex<bool>>,
+    /// Polling interval
+    interval: Duration,
+}
+
+impl std::fmt::Debug for IncomingFundsWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IncomingFundsWatcher")
+            .field("wallet_address", &self.wallet_address)
+            .field("confirmation_policy", &self.confirmation_policy)
+            .field("known", &self.known)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl IncomingFundsWatcher {
+    /// Create a new watcher for a wallet address
+    pub fn new(bitcoin_rpc: Arc<BitcoinRpcClient>, wallet_address: String, confirmation_policy: ConfirmationPolicy, interval: Duration) -> Self {
+        Self {
+            bitcoin_rpc,
+            wallet_address,
+            confirmation_policy: Arc::new(Mutex::new(confirmation_policy)),
+            known: Arc::new(Mutex::new(HashSet::new())),
+            callback: Arc::new(Mutex::new(None)),
+            running: Arc::new(Mutex::new(false)),
+            interval,
+        }
+    }
+    
+    /// Set the callback that receives detected funds, typically recording them in the contract
+    pub fn set_callback(&self, callback: IncomingCallback) -> Result<(), ContractError> {
+        let mut slot = self.callback.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *slot = Some(callback);
+        
+        Ok(())
+    }
+    
+    /// Replace the confirmation policy for the next polls
+    pub fn set_confirmation_policy(&self, confirmation_policy: ConfirmationPolicy) -> Result<(), ContractError> {
+        let mut policy = self.confirmation_policy.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *policy = confirmation_policy;
+        
+        Ok(())
+    }
+    
+    /// Mark an output (`txid:vout`) as belonging to a known transfer so it is never reported
+    pub fn mark_known(&self, outpoint: &str) -> Result<(), ContractError> {
+        let mut known = self.known.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        known.insert(outpoint.to_lowercase());
+        
+        Ok(())
+    }
+    
+    /// Report newly confirmed unknown outputs, returning and dispatching them
+    pub fn poll(&self) -> Result<Vec<IncomingFunds>, ContractError> {
+        Self::poll_once(
+            &self.bitcoin_rpc,
+            &self.wallet_address,
+            &self.confirmation_policy,
+            &self.known,
+            &self.callback,
+        )
+    }
+    
+    /// Start polling in the background
+    pub fn start(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        if *running {
+            return Ok(());
+        }
+        
+        *running = true;
+        
+        // Clone Arc references for the thread
+        let bitcoin_rpc = self.bitcoin_rpc.clone();
+        let wallet_address = self.wallet_address.clone();
+        let confirmation_policy = self.confirmation_policy.clone();
+        let known = self.known.clone();
+        let callback = self.callback.clone();
+        let running = self.running.clone();
+        let interval = self.interval;
+        
+        // Spawn polling thread
+        thread::spawn(move || {
+            info!(target: MEMPOOL, "Incoming funds watcher started");
+            
+            while *running.lock().unwrap() {
+                if let Err(e) = Self::poll_once(&bitcoin_rpc, &wallet_address, &confirmation_policy, &known, &callback) {
+                    error!(target: MEMPOOL, "Failed to check for incoming funds: {:?}", e);
+                }
+                
+                thread::sleep(interval);
+            }
+            
+            info!(target: MEMPOOL, "Incoming funds watcher stopped");
+        });
+        
+        Ok(())
+    }
+    
+    /// Stop polling
+    pub fn stop(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *running = false;
+        
+        Ok(())
+    }
+    
+    /// One polling round, shared by `poll` and the background thread
+    fn poll_once(
+        bitcoin_rpc: &BitcoinRpcClient,
+        wallet_address: &str,
+        confirmation_policy: &Mutex<ConfirmationPolicy>,
+        known: &Mutex<HashSet<String>>,
+        callback: &Mutex<Option<IncomingCallback>>,
+    ) -> Result<Vec<IncomingFunds>, ContractError> {
+        let utxos = bitcoin_rpc.get_address_utxos(wallet_address)?;
+        let confirmation_policy = confirmation_policy.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .clone();
+        
+        let mut detected = Vec::new();
+        {
+            let mut known = known.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            let mut candidates: Vec<_> = utxos.get_all().into_iter()
+                .filter(|utxo| utxo.confirmations >= confirmation_policy.required_confirmations(&TokenType::Bitcoin, utxo.amount))
+                .filter(|utxo| !known.contains(&utxo.reference().to_string()))
+                .collect();
+            candidates.sort_by_key(|utxo| utxo.reference());
+            
+            for utxo in candidates {
+                let sender_addresses = match bitcoin_rpc.get_input_addresses(&utxo.txid) {
+                    Ok(addresses) => addresses,
+                    Err(e) => {
+                        warn!(target: MEMPOOL, "Failed to look up the senders of {}: {:?}", utxo.txid, e);
+                        Vec::new()
+                    },
+                };
+                
+                known.insert(utxo.reference().to_string());
+                
+                // Change of a payout the wallet made itself
+                if sender_addresses.iter().any(|address| address == wallet_address) {
+                    continue;
+                }
+                
+                detected.push(IncomingFunds {
+                    txid: utxo.txid.clone(),
+                    vout: utxo.vout,
+                    amount: utxo.amount,
+                    sender_addresses,
+                    confirmations: utxo.confirmations,
+                });
+            }
+        }
+        
+        // Dispatch outside the lock so the callback may call back into the watcher
+        let callback = callback.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .clone();
+        
+        if let Some(callback) = callback {
+            for funds in &detected {
+                callback(funds);
+            }
+        }
+        
+        Ok(detected)
+    }
+}
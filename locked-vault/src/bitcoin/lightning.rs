@@ -1,10 +1,33 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::Network;
+use log::{debug, info, warn};
 use serde::{Serialize, Deserialize};
 
+use crate::config::Secret;
 use crate::errors::ContractError;
+use crate::bitcoin::bolt11::{Bolt11Invoice, RouteHint, RouteHintHop};
 use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::bitcoin::signature::{KeySigner, Signer};
+use crate::logging::LIGHTNING;
+
+/// Block height of the simulated funding transactions of new channels
+const SIMULATED_FUNDING_HEIGHT: u64 = 2_500_000;
+
+/// Base fee advertised in route hints for the vault's private channels, in millisatoshis
+const HINT_FEE_BASE_MSAT: u32 = 1_000;
+
+/// Proportional fee advertised in route hints for the vault's private channels, in millionths
+const HINT_FEE_PROPORTIONAL_MILLIONTHS: u32 = 1;
+
+/// CLTV expiry delta advertised in route hints for the vault's private channels
+const HINT_CLTV_EXPIRY_DELTA: u16 = 40;
+
+/// Smallest HTLC the node accepts, in satoshis
+pub const MIN_HTLC_SATS: u64 = 1;
 
 
 /// Lightning Network invoice
@@ -26,6 +49,9 @@ pub struct LightningInvoice {
     pub bolt11: String,
     /// Payment status
     pub status: InvoiceStatus,
+    /// Route hints embedded for the node's private channels
+    #[serde(default)]
+    pub route_hints: Vec<RouteHint>,
 }
 
 /// Lightning invoice status
@@ -88,6 +114,46 @@ pub struct LightningChannel {
     pub status: ChannelStatus,
     /// Remote node ID
     pub remote_node: String,
+    /// Short channel ID, as route hints refer to the channel
+    #[serde(default)]
+    pub short_channel_id: u64,
+    /// Whether the channel is kept out of the public channel graph
+    #[serde(default)]
+    pub private: bool,
+}
+
+/// Channel of the public channel graph, as announced over gossip
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicChannel {
+    /// Short channel ID
+    pub short_channel_id: u64,
+    /// Node at one end
+    pub node_a: String,
+    /// Node at the other end
+    pub node_b: String,
+}
+
+/// Invoice to pay and how to route the payment
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentParams {
+    /// BOLT11 invoice to pay
+    pub bolt11: String,
+    /// Route hints to try besides the invoice's own
+    #[serde(default)]
+    pub route_hints: Vec<RouteHint>,
+    /// Channel the payment has to leave through, to drain it
+    #[serde(default)]
+    pub first_hop_channel: Option<String>,
+}
+
+impl PaymentParams {
+    /// Pay an invoice along any route
+    pub fn new(bolt11: impl Into<String>) -> Self {
+        Self {
+            bolt11: bolt11.into(),
+            ..Self::default()
+        }
+    }
 }
 
 /// Lightning channel status
@@ -112,14 +178,20 @@ pub struct LightningClient {
     bitcoin_rpc: Arc<BitcoinRpcClient>,
     /// Lightning node URL
     node_url: String,
-    /// API key
-    api_key: String,
+    /// API key or macaroon
+    api_key: Secret<String>,
     /// Invoices cache
     invoices: Arc<Mutex<HashMap<String, LightningInvoice>>>,
     /// Payments cache
     payments: Arc<Mutex<HashMap<String, LightningPayment>>>,
     /// Channels cache
     channels: Arc<Mutex<HashMap<String, LightningChannel>>>,
+    /// Public channel graph learned from gossip, by short channel ID
+    public_graph: Arc<Mutex<HashMap<u64, PublicChannel>>>,
+    /// Key of the node, signing its invoices
+    node_key: KeySigner,
+    /// Transaction index of the next simulated funding transaction
+    next_funding_index: AtomicU64,
     /// Last API call timestamp for rate limiting
     last_api_call: Arc<Mutex<Instant>>,
 }
@@ -129,15 +201,18 @@ impl LightningClient {
     pub fn new(
         bitcoin_rpc: Arc<BitcoinRpcClient>,
         node_url: String,
-        api_key: String,
+        api_key: impl Into<Secret<String>>,
     ) -> Self {
         Self {
             bitcoin_rpc,
             node_url,
-            api_key,
+            api_key: api_key.into(),
             invoices: Arc::new(Mutex::new(HashMap::new())),
             payments: Arc::new(Mutex::new(HashMap::new())),
             channels: Arc::new(Mutex::new(HashMap::new())),
+            public_graph: Arc::new(Mutex::new(HashMap::new())),
+            node_key: KeySigner::generate(),
+            next_funding_index: AtomicU64::new(1),
             last_api_call: Arc::new(Mutex::new(Instant::now())),
         }
     }
@@ -162,7 +237,15 @@ impl LightningClient {
         Ok(())
     }
     
+    /// Get the node's public key (hex)
+    pub fn node_id(&self) -> String {
+        hex::encode(self.node_key.public_key())
+    }
+    
     /// Create a new invoice
+    /// 
+    /// The invoice carries a route hint for every open private channel of
+    /// the node, since payers cannot find those channels in the public graph.
     pub fn create_invoice(
         &self,
         amount: u64,
@@ -175,12 +258,39 @@ impl LightningClient {
         // For now, we'll simulate it
         
         let id = format!("invoice_{}", Instant::now().elapsed().as_nanos());
-        let payment_hash = format!("hash_{}", id);
+        let preimage: [u8; 32] = rand::random();
+        let payment_hash = sha256::Hash::hash(&preimage).to_string();
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
         
+        // Hint every private channel that can take the payment
+        let private_channels: Vec<String> = {
+            let channels = self.channels.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            let mut private_channels: Vec<&LightningChannel> = channels.values()
+                .filter(|channel| channel.private && channel.status == ChannelStatus::Open)
+                .collect();
+            private_channels.sort_by(|a, b| a.id.cmp(&b.id));
+            private_channels.into_iter().map(|channel| channel.id.clone()).collect()
+        };
+        let route_hints = private_channels.iter()
+            .map(|channel_id| self.route_hint_for(channel_id))
+            .collect::<Result<Vec<RouteHint>, ContractError>>()?;
+        
+        let bolt11 = Bolt11Invoice {
+            currency: Bolt11Invoice::currency_for(Network::Testnet).to_string(),
+            amount_msat: Some(amount.saturating_mul(1_000)),
+            timestamp,
+            payment_hash: payment_hash.clone(),
+            description: Some(description.to_string()),
+            payee: self.node_id(),
+            expiry: Some(expiry as u64),
+            route_hints: route_hints.clone(),
+        }.encode(&self.node_key)?;
+        
         let invoice = LightningInvoice {
             id: id.clone(),
             payment_hash,
@@ -188,8 +298,9 @@ impl LightningClient {
             description: description.to_string(),
             expiry,
             timestamp,
-            bolt11: format!("lntb{}n1p...", amount),
+            bolt11,
             status: InvoiceStatus::Pending,
+            route_hints,
         };
         
         // Cache the invoice
@@ -198,6 +309,8 @@ impl LightningClient {
         
         invoices.insert(id, invoice.clone());
         
+        debug!(target: LIGHTNING, "Created invoice {} for {} sats with {} route hint(s)", invoice.id, amount, invoice.route_hints.len());
+        
         Ok(invoice)
     }
     
@@ -216,31 +329,81 @@ impl LightningClient {
         Err(ContractError::BitcoinTestnetError(format!("Invoice not found: {}", invoice_id)))
     }
     
+    /// Find a created invoice by its payment hash
+    pub fn find_invoice(&self, payment_hash: &str) -> Result<Option<LightningInvoice>, ContractError> {
+        let invoices = self.invoices.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        Ok(invoices.values().find(|invoice| invoice.payment_hash == payment_hash).cloned())
+    }
+    
     /// Pay an invoice
-    pub fn pay_invoice(&self, _bolt11: &str) -> Result<LightningPayment, ContractError> {
+    /// 
+    /// The payee is reached through the public channel graph, or through one
+    /// of the route hints of the invoice or the params, whose first node has
+    /// to be reachable. A payment without a route is recorded as failed.
+    pub fn pay_invoice(&self, params: &PaymentParams) -> Result<LightningPayment, ContractError> {
         self.rate_limit()?;
         
         // In a real implementation, this would call the Lightning Network API
         // For now, we'll simulate it
         
+        let invoice = Bolt11Invoice::decode(&params.bolt11)?;
+        let amount = invoice.amount_msat
+            .map(|amount_msat| amount_msat.div_ceil(1_000))
+            .ok_or_else(|| ContractError::InvalidInvoice("Invoice has no amount".to_string()))?;
+        let fee = (amount as f64 * 0.01) as u64; // 1% fee
+        
+        // The payee itself, or the entry node of any hint, completes a route
+        let mut targets: HashSet<String> = HashSet::from([invoice.payee.clone()]);
+        for hint in invoice.route_hints.iter().chain(&params.route_hints) {
+            if let Some(entry) = hint.hops.first() {
+                targets.insert(entry.node_id.clone());
+            }
+        }
+        
+        let mut channels = self.channels.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        let graph = self.public_graph.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        // Leave through the requested channel, or any open one with the balance
+        let mut candidates: Vec<&LightningChannel> = match &params.first_hop_channel {
+            Some(channel_id) => vec![channels.get(channel_id)
+                .ok_or_else(|| ContractError::BitcoinTestnetError(format!("Channel not found: {}", channel_id)))?],
+            None => channels.values().collect(),
+        };
+        candidates.sort_by(|a, b| a.id.cmp(&b.id));
+        
+        let node_id = self.node_id();
+        let first_hop = candidates.into_iter()
+            .filter(|channel| channel.status == ChannelStatus::Open && channel.local_balance >= amount + fee)
+            .find(|channel| reaches_any(&graph, &channel.remote_node, &node_id, &targets))
+            .map(|channel| channel.id.clone());
+        
+        let status = match first_hop.and_then(|channel_id| channels.get_mut(&channel_id)) {
+            Some(channel) => {
+                channel.local_balance -= amount + fee;
+                channel.remote_balance += amount;
+                PaymentStatus::Succeeded
+            },
+            None => PaymentStatus::Failed,
+        };
+        
         let id = format!("payment_{}", Instant::now().elapsed().as_nanos());
-        let payment_hash = format!("hash_{}", id);
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
         
-        // Parse amount from bolt11 (in a real implementation)
-        let amount = 1000; // Placeholder
-        
         let payment = LightningPayment {
             id: id.clone(),
-            payment_hash,
+            payment_hash: invoice.payment_hash,
             amount,
-            fee: (amount as f64 * 0.01) as u64, // 1% fee
-            status: PaymentStatus::Succeeded,
+            fee: if status == PaymentStatus::Succeeded { fee } else { 0 },
+            status,
             timestamp,
-            destination: "02...".to_string(), // Placeholder
+            destination: invoice.payee,
         };
         
         // Cache the payment
@@ -249,21 +412,31 @@ impl LightningClient {
         
         payments.insert(id, payment.clone());
         
+        match payment.status {
+            PaymentStatus::Succeeded => info!(target: LIGHTNING, "Paid {} sats to {} for a fee of {}", payment.amount, payment.destination, payment.fee),
+            _ => warn!(target: LIGHTNING, "No route with {} sats of balance to {}", payment.amount, payment.destination),
+        }
+        
         Ok(payment)
     }
     
     /// Open a channel
+    /// 
+    /// A public channel is announced to the channel graph; a private one is
+    /// only reachable through the route hints of the node's invoices.
     pub fn open_channel(
         &self,
         node_id: &str,
         capacity: u64,
+        private: bool,
     ) -> Result<LightningChannel, ContractError> {
         self.rate_limit()?;
         
         // In a real implementation, this would call the Lightning Network API
         // For now, we'll simulate it
         
-        let id = format!("channel_{}", Instant::now().elapsed().as_nanos());
+        let funding_index = self.next_funding_index.fetch_add(1, Ordering::Relaxed);
+        let id = format!("channel_{}", funding_index);
         let funding_txid = format!("txid_{}", id);
         
         let channel = LightningChannel {
@@ -274,6 +447,8 @@ impl LightningClient {
             remote_balance: 0,
             status: ChannelStatus::PendingOpen,
             remote_node: node_id.to_string(),
+            short_channel_id: (SIMULATED_FUNDING_HEIGHT << 40) | (funding_index << 16),
+            private,
         };
         
         // Cache the channel
@@ -282,9 +457,87 @@ impl LightningClient {
         
         channels.insert(id, channel.clone());
         
+        if !private {
+            self.announce_channel(channel.short_channel_id, &self.node_id(), node_id)?;
+        }
+        
+        info!(target: LIGHTNING, "Opening {} channel {} of {} sats to {}", if private { "private" } else { "public" }, channel.id, capacity, node_id);
+        
         Ok(channel)
     }
     
+    /// Record that a channel's funding transaction confirmed, opening it
+    pub fn confirm_channel_funding(&self, channel_id: &str) -> Result<(), ContractError> {
+        let mut channels = self.channels.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        match channels.get_mut(channel_id) {
+            Some(channel) if channel.status == ChannelStatus::PendingOpen => {
+                channel.status = ChannelStatus::Open;
+                Ok(())
+            },
+            Some(channel) => Err(ContractError::BitcoinTestnetError(format!(
+                "Channel {} is not pending open but {:?}", channel_id, channel.status
+            ))),
+            None => Err(ContractError::BitcoinTestnetError(format!("Channel not found: {}", channel_id))),
+        }
+    }
+    
+    /// Record a public channel announced over gossip
+    pub fn announce_channel(&self, short_channel_id: u64, node_a: &str, node_b: &str) -> Result<(), ContractError> {
+        let mut graph = self.public_graph.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        graph.insert(short_channel_id, PublicChannel {
+            short_channel_id,
+            node_a: node_a.to_string(),
+            node_b: node_b.to_string(),
+        });
+        
+        Ok(())
+    }
+    
+    /// Get the public channel graph, private channels excluded
+    pub fn get_public_channels(&self) -> Result<Vec<PublicChannel>, ContractError> {
+        let graph = self.public_graph.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        let mut public_channels: Vec<PublicChannel> = graph.values().cloned().collect();
+        public_channels.sort_by_key(|channel| channel.short_channel_id);
+        
+        Ok(public_channels)
+    }
+    
+    /// Get the route hint through which payers reach the node over a private channel
+    pub fn get_route_hint(&self, channel_id: &str) -> Result<RouteHint, ContractError> {
+        self.rate_limit()?;
+        
+        self.route_hint_for(channel_id)
+    }
+    
+    /// Build the route hint of a private channel
+    fn route_hint_for(&self, channel_id: &str) -> Result<RouteHint, ContractError> {
+        let channels = self.channels.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        let channel = channels.get(channel_id)
+            .ok_or_else(|| ContractError::BitcoinTestnetError(format!("Channel not found: {}", channel_id)))?;
+        if !channel.private {
+            return Err(ContractError::BitcoinTestnetError(format!("Channel {} is public and needs no hint", channel_id)));
+        }
+        
+        // Payers enter at the peer and take the private channel to this node
+        Ok(RouteHint {
+            hops: vec![RouteHintHop {
+                node_id: channel.remote_node.clone(),
+                short_channel_id: channel.short_channel_id,
+                fee_base_msat: HINT_FEE_BASE_MSAT,
+                fee_proportional_millionths: HINT_FEE_PROPORTIONAL_MILLIONTHS,
+                cltv_expiry_delta: HINT_CLTV_EXPIRY_DELTA,
+            }],
+        })
+    }
+    
     /// Close a channel
     pub fn close_channel(&self, channel_id: &str) -> Result<(), ContractError> {
         self.rate_limit()?;
@@ -295,6 +548,7 @@ impl LightningClient {
         
         if let Some(channel) = channels.get_mut(channel_id) {
             channel.status = ChannelStatus::PendingClose;
+            info!(target: LIGHTNING, "Closing channel {}", channel_id);
             Ok(())
         } else {
             Err(ContractError::BitcoinTestnetError(format!("Channel not found: {}", channel_id)))
@@ -326,6 +580,20 @@ impl LightningClient {
         Ok(channels.values().cloned().collect())
     }
     
+    /// Get the smallest and largest payment a single HTLC can carry, in satoshis
+    /// 
+    /// The largest is the capacity of the biggest open channel, 0 without one.
+    pub fn htlc_limits(&self) -> Result<(u64, u64), ContractError> {
+        let max = self.get_channels()?
+            .iter()
+            .filter(|channel| channel.status == ChannelStatus::Open)
+            .map(|channel| channel.capacity)
+            .max()
+            .unwrap_or(0);
+        
+        Ok((MIN_HTLC_SATS, max))
+    }
+    
     /// Get node info
     pub fn get_node_info(&self) -> Result<String, ContractError> {
         self.rate_limit()?;
@@ -333,6 +601,44 @@ impl LightningClient {
         // In a real implementation, this would call the Lightning Network API
         // For now, we'll return a placeholder
         
-        Ok("02...@127.0.0.1:9735".to_string())
+        Ok(format!("{}@127.0.0.1:9735", self.node_id()))
     }
+    
+    /// Check whether the node has caught up with the chain
+    pub fn is_synced_to_chain(&self) -> Result<bool, ContractError> {
+        self.rate_limit()?;
+        
+        // In a real implementation, this would read `synced_to_chain` from the node info
+        // For now, we'll report the node as synced
+        
+        Ok(true)
+    }
+}
+
+/// Check whether a node reaches any target over the public channel graph, not passing through `own_node`
+fn reaches_any(graph: &HashMap<u64, PublicChannel>, start: &str, own_node: &str, targets: &HashSet<String>) -> bool {
+    let mut visited: HashSet<&str> = HashSet::from([start, own_node]);
+    let mut queue: VecDeque<&str> = VecDeque::from([start]);
+    
+    while let Some(node) = queue.pop_front() {
+        if targets.contains(node) {
+            return true;
+        }
+        
+        for channel in graph.values() {
+            let peer = if channel.node_a == node {
+                channel.node_b.as_str()
+            } else if channel.node_b == node {
+                channel.node_a.as_str()
+            } else {
+                continue;
+            };
+            
+            if visited.insert(peer) {
+                queue.push_back(peer);
+            }
+        }
+    }
+    
+    false
 }
@@ -6,6 +6,8 @@ use log::{debug, info, error};
 
 use crate::errors::ContractError;
 use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::introspection::Heartbeat;
+use crate::logging::MEMPOOL;
 
 /// Mempool transaction
 #[derive(Debug, Clone)]
@@ -37,6 +39,8 @@ pub struct MempoolMonitor {
     running: Arc<Mutex<bool>>,
     /// Monitoring interval
     interval: Duration,
+    /// Time of the last monitoring loop
+    heartbeat: Heartbeat,
 }
 
 impl MempoolMonitor {
@@ -48,6 +52,7 @@ impl MempoolMonitor {
             monitored_addresses: Arc::new(Mutex::new(HashSet::new())),
             running: Arc::new(Mutex::new(false)),
             interval,
+            heartbeat: Heartbeat::new(),
         }
     }
     
@@ -68,10 +73,11 @@ impl MempoolMonitor {
         let monitored_addresses = self.monitored_addresses.clone();
         let running = self.running.clone();
         let interval = self.interval;
+        let heartbeat = self.heartbeat.clone();
         
         // Spawn monitoring thread
         thread::spawn(move || {
-            info!("Mempool monitoring started");
+            info!(target: MEMPOOL, "Mempool monitoring started");
             
             while *running.lock().unwrap() {
                 // Get mempool transactions
@@ -79,7 +85,7 @@ impl MempoolMonitor {
                     Ok(txids) => {
                         // Update transactions
                         let mut txs = transactions.lock().unwrap();
-                        let _addresses = monitored_addresses.lock().unwrap();
+                        let addresses = monitored_addresses.lock().unwrap().clone();
                         
                         // Mark all as not seen in this iteration
                         for tx in txs.values_mut() {
@@ -96,7 +102,7 @@ impl MempoolMonitor {
                                 let now = Instant::now();
                                 
                                 // Check if related to monitored addresses
-                                let is_related = false; // In a real implementation, check transaction outputs
+                                let is_related = Self::pays_to_any(&bitcoin_rpc, &txid, &addresses);
                                 
                                 txs.insert(txid.clone(), MempoolTransaction {
                                     txid,
@@ -112,23 +118,47 @@ impl MempoolMonitor {
                         // Remove transactions that haven't been seen for a while
                         txs.retain(|_, tx| tx.last_seen.elapsed() < Duration::from_secs(3600));
                         
-                        debug!("Mempool: {} transactions", txs.len());
+                        debug!(target: MEMPOOL, "Mempool: {} transactions", txs.len());
                     },
                     Err(e) => {
-                        error!("Failed to get mempool transactions: {:?}", e);
+                        error!(target: MEMPOOL, "Failed to get mempool transactions: {:?}", e);
                     }
                 }
                 
+                heartbeat.beat();
+                
                 // Sleep
                 thread::sleep(interval);
             }
             
-            info!("Mempool monitoring stopped");
+            info!(target: MEMPOOL, "Mempool monitoring stopped");
         });
         
         Ok(())
     }
     
+    /// Get the heartbeat of the monitoring loop
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat.clone()
+    }
+    
+    /// Whether a transaction pays any of the given addresses
+    /// 
+    /// Transactions the node cannot decode count as unrelated.
+    pub(crate) fn pays_to_any(bitcoin_rpc: &BitcoinRpcClient, txid: &str, addresses: &HashSet<String>) -> bool {
+        if addresses.is_empty() {
+            return false;
+        }
+        
+        match bitcoin_rpc.get_output_addresses(txid) {
+            Ok(outputs) => outputs.iter().any(|address| addresses.contains(address)),
+            Err(e) => {
+                debug!(target: MEMPOOL, "Failed to decode mempool transaction {}: {:?}", txid, e);
+                false
+            },
+        }
+    }
+    
     /// Stop monitoring
     pub fn stop(&self) -> Result<(), ContractError> {
         let mut running = self.running.lock()
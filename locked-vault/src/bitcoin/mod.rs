@@ -1,8 +1,9 @@
 //! Bitcoin-related functionality for the time-locked deposit contract
 //! 
 //! This module contains all Bitcoin-specific implementations, including
-//! testnet support, RPC client, UTXO management, Lightning Network,
-//! Ordinals, multi-signature, mempool monitoring, and signature verification.
+//! testnet support, RPC client, UTXO management, Lightning Network with BOLT11 route hints,
+//! Ordinals, multi-signature, script escrow descriptors, payout batching, mempool, reorg and incoming funds monitoring, chain anchors for events, confirmation policies, signature verification,
+//! backend health checking, circuit breakers of optional backends, shared fee estimates, transaction template validation and network checks of addresses.
 
 // Re-export submodules
 pub mod testnet;
@@ -11,17 +12,42 @@ pub mod utxo;
 pub mod lightning;
 pub mod ordinals;
 pub mod multisig;
+pub mod descriptor;
+pub mod bolt11;
 pub mod mempool;
+pub mod reorg;
+pub mod anchor;
+pub mod incoming;
+pub mod confirmation;
 pub mod signature;
 pub mod transfer;
+pub mod payout;
+pub mod health;
+pub mod cache;
+pub mod fee_oracle;
+pub mod feature_gate;
+pub mod template;
+pub mod network;
 
 // Re-export commonly used types
 pub use testnet::BitcoinTestnetConfig;
-pub use rpc::BitcoinRpcClient;
-pub use utxo::{Utxo, UtxoSet};
-pub use lightning::LightningClient;
-pub use ordinals::OrdinalsClient;
+pub use rpc::{BitcoinRpcClient, DuplicateOutputs, SignedTx};
+pub use utxo::{CoinControl, DustChange, DustPolicy, OperationalUtxo, ScriptType, SelectionStrategy, SweepInputs, Utxo, UtxoRef, UtxoSet};
+pub use lightning::{LightningClient, PaymentParams, PublicChannel};
+pub use ordinals::{Inscription, OrdinalsApiFlavor, OrdinalsClient, SatInfo, SatRarity};
 pub use mempool::MempoolMonitor;
-pub use multisig::MultisigClient;
-pub use signature::SignatureVerifier;
-pub use transfer::BitcoinTestnetTransfer;
\ No newline at end of file
+pub use reorg::{ReorgNotice, ReorgWatcher};
+pub use anchor::{AnchorVerification, ChainAnchor};
+pub use incoming::{IncomingFunds, IncomingFundsWatcher};
+pub use confirmation::{ConfirmationPolicy, ConfirmationTier, TokenConfirmationRule};
+pub use multisig::{MultisigClient, MusigSpend, MusigSpendStage, WalletKind};
+pub use descriptor::{MusigEscrowPolicy, TimeLockPolicy};
+pub use bolt11::{Bolt11Invoice, RouteHint, RouteHintHop};
+pub use signature::{KeySigner, MusigAggNonce, MusigKeyAgg, MusigPublicNonce, MusigSecretNonce, SignatureVerifier, Signer};
+pub use transfer::{BatchResult, BitcoinTestnetTransfer, ProcessingProgress};
+pub use payout::{PayoutBatch, PayoutBatcher, PayoutEntry};
+pub use health::{HealthChecker, HealthStatus};
+pub use fee_oracle::{ConfirmationTarget, FeeOracle, FeeOracleConfig, FeeRate, FeeSnapshot};
+pub use feature_gate::{FeatureGate, FeatureGateConfig, FeatureGateStatus, FeatureGates, GateState};
+pub use template::{DataOutputs, DestinationPolicy};
+pub use network::{AddressNetwork, NetworkGuard};
\ No newline at end of file
@@ -1,9 +1,31 @@
 use bitcoincore_rpc::bitcoin::Network;
-use std::collections::HashMap;
+use bitcoincore_rpc::bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use serde::{Serialize, Deserialize};
+use zeroize::Zeroizing;
 
 use crate::errors::ContractError;
+use crate::bitcoin::descriptor::{MultisigPolicy, MusigEscrowPolicy};
+use crate::bitcoin::network::NetworkGuard;
 use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::bitcoin::signature::{
+    musig_aggregate, musig_partial_sign, musig_partial_verify, MusigAggNonce, MusigPublicNonce, MusigSecretNonce,
+};
+use crate::config::Secret;
+use crate::store::encrypted::{EncryptedStateStore, MUSIG_SESSIONS_BLOB};
+
+/// Kind of output a multi-signature wallet pays to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletKind {
+    /// M-of-N `multi` witness script in a P2WSH output
+    #[default]
+    P2wsh,
+    /// MuSig2 key of the vault and a depositor in a P2TR output, with a
+    /// recovery leaf for the depositor from the lock height on
+    MusigTaproot,
+}
 
 /// Multi-signature wallet
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,6 +44,12 @@ pub struct MultisigWallet {
     pub address: String,
     /// Network
     pub network: String,
+    /// Kind of output
+    #[serde(default)]
+    pub kind: WalletKind,
+    /// Block height from which the depositor of a MuSig2 wallet can spend alone
+    #[serde(default)]
+    pub lock_height: Option<u32>,
 }
 
 impl MultisigWallet {
@@ -44,16 +72,10 @@ impl MultisigWallet {
             ));
         }
         
-        // In a real implementation, this would create a proper redeem script
-        // For now, we'll use a placeholder
-        let redeem_script = "redeem_script_placeholder".to_string();
-        
-        // Generate address from redeem script
-        let address = match network {
-            Network::Testnet => "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-            Network::Bitcoin => "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-            _ => "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-        };
+        // Build the witness script and derive its P2WSH address
+        let policy = MultisigPolicy::new(required_signatures, &public_keys)?;
+        let redeem_script = hex::encode(policy.witness_script().as_bytes());
+        let address = policy.address(network).to_string();
         
         Ok(Self {
             name,
@@ -63,8 +85,174 @@ impl MultisigWallet {
             redeem_script,
             address,
             network: network.to_string(),
+            kind: WalletKind::P2wsh,
+            lock_height: None,
+        })
+    }
+    
+    /// Create a 2-of-2 MuSig2 wallet of the vault and a depositor
+    /// 
+    /// The redeem script is the depositor's recovery leaf; cooperative
+    /// spends sign for the aggregated key and reveal no script.
+    pub fn new_musig(
+        name: String,
+        vault_key: &str,
+        depositor_key: &str,
+        lock_height: u32,
+        network: Network,
+    ) -> Result<Self, ContractError> {
+        let policy = MusigEscrowPolicy::new(vault_key, depositor_key, lock_height)?;
+        let address = policy.address(network)?.to_string();
+        
+        Ok(Self {
+            name,
+            required_signatures: 2,
+            total_signers: 2,
+            public_keys: vec![policy.vault_key.to_string(), policy.depositor_key.to_string()],
+            redeem_script: hex::encode(policy.recovery_script().as_bytes()),
+            address,
+            network: network.to_string(),
+            kind: WalletKind::MusigTaproot,
+            lock_height: Some(lock_height),
         })
     }
+    
+    /// Parse the stored terms of a P2WSH wallet
+    pub fn policy(&self) -> Result<MultisigPolicy, ContractError> {
+        if self.kind != WalletKind::P2wsh {
+            return Err(ContractError::BitcoinTestnetError(format!("Wallet {} is not a P2WSH multisig", self.name)));
+        }
+        MultisigPolicy::new(self.required_signatures, &self.public_keys)
+    }
+    
+    /// Parse the stored terms of a MuSig2 wallet
+    pub fn musig_policy(&self) -> Result<MusigEscrowPolicy, ContractError> {
+        match (self.kind, self.public_keys.as_slice(), self.lock_height) {
+            (WalletKind::MusigTaproot, [vault_key, depositor_key], Some(lock_height)) => {
+                MusigEscrowPolicy::new(vault_key, depositor_key, lock_height)
+            },
+            _ => Err(ContractError::BitcoinTestnetError(format!("Wallet {} is not a MuSig2 wallet", self.name))),
+        }
+    }
+    
+    /// Render the wallet as a descriptor with its checksum
+    pub fn descriptor(&self) -> Result<String, ContractError> {
+        match self.kind {
+            WalletKind::P2wsh => Ok(self.policy()?.descriptor()),
+            WalletKind::MusigTaproot => Ok(self.musig_policy()?.descriptor()),
+        }
+    }
+}
+
+/// Stage of our side of a MuSig2 signing session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MusigSpendStage {
+    /// Our public nonce is out, waiting for the other signer's
+    AwaitingNonce,
+    /// Our partial signature is out, waiting for the other signer's
+    AwaitingPartialSignature,
+    /// Signature aggregated
+    Complete,
+}
+
+/// Our side of the MuSig2 nonce exchange for one pending spend
+/// 
+/// The secret nonce is kept, persisted encrypted, only until our partial
+/// signature is made; from then on the spend can only hand out that same
+/// signature. Its memory is wiped when it is dropped.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MusigSpend {
+    /// Spend ID, unique per signing session
+    pub spend_id: String,
+    /// Wallet the spent output belongs to
+    pub wallet: String,
+    /// Descriptor of the spent output, so the session resumes on its own
+    pub descriptor: String,
+    /// Key path sighash being signed (hex)
+    pub message: String,
+    /// Our signing key (hex)
+    pub signer_key: String,
+    /// Our public nonce (hex)
+    pub public_nonce: String,
+    /// Our secret nonce (hex), until it is used
+    secret_nonce: Option<Zeroizing<String>>,
+    /// Other signer's public nonce (hex)
+    pub peer_nonce: Option<String>,
+    /// Our partial signature (hex)
+    pub partial_signature: Option<String>,
+    /// Aggregated BIP 340 signature (hex)
+    pub signature: Option<String>,
+    /// Stage
+    pub stage: MusigSpendStage,
+}
+
+impl MusigSpend {
+    /// Decode the message
+    fn message_bytes(&self) -> Result<[u8; 32], ContractError> {
+        decode_array(&self.message, "message")
+    }
+    
+    /// Other signer's key, the wallet key that is not ours
+    fn peer_key(&self, policy: &MusigEscrowPolicy) -> PublicKey {
+        if policy.vault_key.to_string() == self.signer_key {
+            policy.depositor_key.inner
+        } else {
+            policy.vault_key.inner
+        }
+    }
+    
+    /// Both public nonces, in wallet key order
+    fn nonces(&self, policy: &MusigEscrowPolicy) -> Result<[MusigPublicNonce; 2], ContractError> {
+        let ours = MusigPublicNonce::from_slice(&decode_hex(&self.public_nonce, "public nonce")?)?;
+        let peer_nonce = self.peer_nonce.as_deref()
+            .ok_or_else(|| ContractError::BitcoinTestnetError(format!("Spend {} has no peer nonce", self.spend_id)))?;
+        let peer = MusigPublicNonce::from_slice(&decode_hex(peer_nonce, "peer nonce")?)?;
+        
+        if policy.vault_key.to_string() == self.signer_key {
+            Ok([ours, peer])
+        } else {
+            Ok([peer, ours])
+        }
+    }
+}
+
+impl fmt::Debug for MusigSpend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the secret nonce
+        f.debug_struct("MusigSpend")
+            .field("spend_id", &self.spend_id)
+            .field("wallet", &self.wallet)
+            .field("message", &self.message)
+            .field("signer_key", &self.signer_key)
+            .field("public_nonce", &self.public_nonce)
+            .field("peer_nonce", &self.peer_nonce)
+            .field("partial_signature", &self.partial_signature)
+            .field("signature", &self.signature)
+            .field("stage", &self.stage)
+            .finish()
+    }
+}
+
+/// MuSig2 signing sessions as persisted between restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MusigSessions {
+    /// Sessions, by spend ID
+    spends: HashMap<String, MusigSpend>,
+    /// Every public nonce we handed out
+    used_nonces: HashSet<String>,
+}
+
+/// Decode a hex field
+fn decode_hex(value: &str, field: &str) -> Result<Vec<u8>, ContractError> {
+    hex::decode(value).map_err(|e| ContractError::BitcoinTestnetError(format!("Invalid {} hex: {}", field, e)))
+}
+
+/// Decode a hex field of 32 bytes
+fn decode_array(value: &str, field: &str) -> Result<[u8; 32], ContractError> {
+    decode_hex(value, field)?
+        .try_into()
+        .map_err(|_| ContractError::BitcoinTestnetError(format!("The {} must be 32 bytes", field)))
 }
 
 /// Multi-signature transaction
@@ -108,6 +296,10 @@ pub struct MultisigClient {
     wallets: HashMap<String, MultisigWallet>,
     /// Transactions
     transactions: HashMap<String, MultisigTransaction>,
+    /// MuSig2 signing sessions
+    musig: MusigSessions,
+    /// Encrypted store the MuSig2 signing sessions are persisted to
+    session_store: Option<EncryptedStateStore>,
 }
 
 impl MultisigClient {
@@ -118,7 +310,29 @@ impl MultisigClient {
             network,
             wallets: HashMap::new(),
             transactions: HashMap::new(),
+            musig: MusigSessions::default(),
+            session_store: None,
+        }
+    }
+    
+    /// Persist MuSig2 signing sessions to an encrypted store, resuming those already there
+    /// 
+    /// Sessions hold secret nonces, which reveal the signing key once their
+    /// partial signature is out, so they are only ever written sealed.
+    /// Every change to a session is written before its result is handed
+    /// out, so a restart never signs again with a nonce that already
+    /// produced a partial signature. Restoring an older copy of the store
+    /// would undo that, so back it up only together with the rest of the
+    /// vault state and never roll it back.
+    pub fn with_session_store(mut self, store: EncryptedStateStore) -> Result<Self, ContractError> {
+        let stored = store.read_blob(MUSIG_SESSIONS_BLOB)
+            .map_err(|e| ContractError::StatePersistenceFailed(e.to_string()))?;
+        if let Some(raw) = stored {
+            self.musig = serde_json::from_slice(raw.expose_secret())
+                .map_err(|e| ContractError::StatePersistenceFailed(format!("Invalid MuSig2 sessions in {}: {}", store.dir().display(), e)))?;
         }
+        self.session_store = Some(store);
+        Ok(self)
     }
     
     /// Create a new multi-signature wallet
@@ -142,20 +356,43 @@ impl MultisigClient {
         Ok(wallet)
     }
     
+    /// Create a 2-of-2 MuSig2 wallet of the vault and a depositor
+    pub fn create_musig_wallet(
+        &mut self,
+        name: &str,
+        vault_key: &str,
+        depositor_key: &str,
+        lock_height: u32,
+    ) -> Result<MultisigWallet, ContractError> {
+        let wallet = MultisigWallet::new_musig(name.to_string(), vault_key, depositor_key, lock_height, self.network)?;
+        self.wallets.insert(name.to_string(), wallet.clone());
+        Ok(wallet)
+    }
+    
     /// Get a wallet by name
     pub fn get_wallet(&self, name: &str) -> Result<&MultisigWallet, ContractError> {
         self.wallets.get(name)
             .ok_or_else(|| ContractError::BitcoinTestnetError(format!("Wallet not found: {}", name)))
     }
     
+    /// Get all wallets, ordered by name
+    pub fn wallets(&self) -> Vec<&MultisigWallet> {
+        let mut wallets: Vec<&MultisigWallet> = self.wallets.values().collect();
+        wallets.sort_by(|a, b| a.name.cmp(&b.name));
+        wallets
+    }
+    
     /// Create a multi-signature transaction
     pub fn create_transaction(
         &mut self,
         wallet_name: &str,
-        _to_address: &str,
+        to_address: &str,
         _amount: u64,
         _fee_rate: f64,
     ) -> Result<MultisigTransaction, ContractError> {
+        // The recipient has to be on the wallet's network
+        NetworkGuard::new(self.network).check(to_address)?;
+        
         // Get wallet
         let wallet = self.get_wallet(wallet_name)?;
         
@@ -232,4 +469,171 @@ impl MultisigClient {
         
         Ok(tx.status)
     }
+    
+    /// Start signing a cooperative spend of a MuSig2 wallet, returning our public nonce
+    /// 
+    /// `message` is the key path sighash of the spending transaction and
+    /// `secret_key` our key in the wallet. The session is persisted before
+    /// the nonce is returned. Starting a spend ID again for the same message
+    /// resumes the session and returns the same nonce.
+    pub fn start_musig_spend(
+        &mut self,
+        wallet_name: &str,
+        spend_id: &str,
+        message: &[u8; 32],
+        secret_key: &SecretKey,
+    ) -> Result<MusigSpend, ContractError> {
+        let signer_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), secret_key).to_string();
+        
+        if let Some(spend) = self.musig.spends.get(spend_id) {
+            if spend.wallet != wallet_name || spend.message != hex::encode(message) || spend.signer_key != signer_key {
+                return Err(ContractError::BitcoinTestnetError(format!(
+                    "Spend {} is already signing another message", spend_id
+                )));
+            }
+            return Ok(spend.clone());
+        }
+        
+        let wallet = self.get_wallet(wallet_name)?;
+        let policy = wallet.musig_policy()?;
+        let (secret_nonce, public_nonce) = MusigSecretNonce::generate(secret_key, &policy.tweaked_key_agg()?, message)?;
+        let public_nonce = hex::encode(public_nonce.serialize());
+        
+        // Only a broken random source repeats a nonce, but never hand it out twice
+        if self.musig.used_nonces.contains(&public_nonce) {
+            return Err(ContractError::MusigNonceReused { spend_id: spend_id.to_string() });
+        }
+        
+        let spend = MusigSpend {
+            spend_id: spend_id.to_string(),
+            wallet: wallet_name.to_string(),
+            descriptor: policy.descriptor(),
+            message: hex::encode(message),
+            signer_key,
+            public_nonce: public_nonce.clone(),
+            secret_nonce: Some(Zeroizing::new(hex::encode(secret_nonce.serialize()))),
+            peer_nonce: None,
+            partial_signature: None,
+            signature: None,
+            stage: MusigSpendStage::AwaitingNonce,
+        };
+        
+        self.musig.spends.insert(spend_id.to_string(), spend.clone());
+        self.musig.used_nonces.insert(public_nonce.clone());
+        if let Err(e) = self.save_sessions() {
+            // The nonce was never handed out, so it can simply be forgotten
+            self.musig.spends.remove(spend_id);
+            self.musig.used_nonces.remove(&public_nonce);
+            return Err(e);
+        }
+        
+        Ok(spend)
+    }
+    
+    /// Sign a MuSig2 spend once the other signer's public nonce is in, returning our partial signature
+    /// 
+    /// Uses up our secret nonce, and persists that before the partial
+    /// signature is returned. Asking again with the same peer nonce returns
+    /// the same partial signature; any other peer nonce is refused with
+    /// `MusigNonceReused`.
+    pub fn sign_musig_spend(
+        &mut self,
+        spend_id: &str,
+        peer_nonce: &str,
+        secret_key: &SecretKey,
+    ) -> Result<String, ContractError> {
+        let spend = self.musig_spend(spend_id)?.clone();
+        
+        if let (Some(partial_signature), Some(signed_nonce)) = (&spend.partial_signature, &spend.peer_nonce) {
+            if signed_nonce == peer_nonce {
+                return Ok(partial_signature.clone());
+            }
+        }
+        let secret_nonce = spend.secret_nonce.as_deref()
+            .ok_or_else(|| ContractError::MusigNonceReused { spend_id: spend_id.to_string() })?;
+        let secret_nonce = MusigSecretNonce::from_slice(&Zeroizing::new(decode_hex(secret_nonce, "secret nonce")?))?;
+        
+        let policy = MusigEscrowPolicy::parse(&spend.descriptor)?;
+        let key_agg = policy.tweaked_key_agg()?;
+        let mut signed = MusigSpend { peer_nonce: Some(peer_nonce.to_string()), ..spend };
+        let aggregate_nonce = MusigAggNonce::new(&signed.nonces(&policy)?);
+        let partial_signature = musig_partial_sign(secret_nonce, secret_key, &key_agg, &aggregate_nonce, &signed.message_bytes()?)?;
+        
+        signed.secret_nonce = None;
+        signed.partial_signature = Some(hex::encode(partial_signature));
+        signed.stage = MusigSpendStage::AwaitingPartialSignature;
+        self.musig.spends.insert(spend_id.to_string(), signed);
+        
+        // Whatever happens to the save, the nonce is spent in memory
+        self.save_sessions()?;
+        
+        Ok(hex::encode(partial_signature))
+    }
+    
+    /// Complete a MuSig2 spend with the other signer's partial signature, returning the aggregated signature
+    /// 
+    /// The signature is a BIP 340 signature for the wallet's output key, the
+    /// whole witness of a key path spend.
+    pub fn complete_musig_spend(&mut self, spend_id: &str, peer_partial_signature: &str) -> Result<String, ContractError> {
+        let spend = self.musig_spend(spend_id)?.clone();
+        let partial_signature = spend.partial_signature.as_deref()
+            .ok_or_else(|| ContractError::BitcoinTestnetError(format!("Spend {} is not signed by us yet", spend_id)))?;
+        
+        let policy = MusigEscrowPolicy::parse(&spend.descriptor)?;
+        let key_agg = policy.tweaked_key_agg()?;
+        let nonces = spend.nonces(&policy)?;
+        let aggregate_nonce = MusigAggNonce::new(&nonces);
+        let message = spend.message_bytes()?;
+        
+        let peer_partial = decode_array(peer_partial_signature, "partial signature")?;
+        let peer_key = spend.peer_key(&policy);
+        let peer_nonce = if peer_key == policy.vault_key.inner { &nonces[0] } else { &nonces[1] };
+        if !musig_partial_verify(&peer_partial, peer_nonce, &peer_key, &key_agg, &aggregate_nonce, &message) {
+            return Err(ContractError::BitcoinTestnetError(format!("Invalid partial signature for spend {}", spend_id)));
+        }
+        
+        let partials = [decode_array(partial_signature, "partial signature")?, peer_partial];
+        let signature = musig_aggregate(&partials, &key_agg, &aggregate_nonce, &message)?;
+        
+        let message = Message::from_slice(&message)
+            .map_err(|e| ContractError::BitcoinTestnetError(format!("Invalid message: {}", e)))?;
+        Secp256k1::verification_only().verify_schnorr(&signature, &message, &key_agg.x_only_key())
+            .map_err(|e| ContractError::BitcoinTestnetError(format!("Aggregated signature does not verify: {}", e)))?;
+        
+        let signature = hex::encode(signature.as_ref());
+        self.musig.spends.insert(spend_id.to_string(), MusigSpend {
+            signature: Some(signature.clone()),
+            stage: MusigSpendStage::Complete,
+            ..spend
+        });
+        self.save_sessions()?;
+        
+        Ok(signature)
+    }
+    
+    /// Get a MuSig2 signing session by spend ID
+    pub fn musig_spend(&self, spend_id: &str) -> Result<&MusigSpend, ContractError> {
+        self.musig.spends.get(spend_id)
+            .ok_or_else(|| ContractError::BitcoinTestnetError(format!("MuSig2 spend not found: {}", spend_id)))
+    }
+    
+    /// Get all MuSig2 signing sessions, ordered by spend ID
+    pub fn musig_spends(&self) -> Vec<&MusigSpend> {
+        let mut spends: Vec<&MusigSpend> = self.musig.spends.values().collect();
+        spends.sort_by(|a, b| a.spend_id.cmp(&b.spend_id));
+        spends
+    }
+    
+    /// Seal the MuSig2 signing sessions into the session store, if any
+    fn save_sessions(&self) -> Result<(), ContractError> {
+        let store = match &self.session_store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        
+        let encoded = Secret::new(serde_json::to_vec(&self.musig)
+            .map_err(|e| ContractError::StatePersistenceFailed(format!("Failed to encode MuSig2 sessions: {}", e)))?);
+        store.write_blob(MUSIG_SESSIONS_BLOB, encoded.expose_secret())
+            .map_err(|e| ContractError::StatePersistenceFailed(e.to_string()))
+    }
 }
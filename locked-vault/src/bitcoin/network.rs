@@ -0,0 +1,133 @@
+//! Network consistency of addresses
+//!
+//! A mainnet address pasted into a testnet vault still looks like an address,
+//! and paying to it would send coins to a script nobody here can spend from.
+//! Every address entering the vault, from the owner at construction to
+//! payout destinations, fee collectors, treasury cold addresses and multisig
+//! recipients, passes through a `NetworkGuard` for the configured network.
+//!
+//! The network an address was encoded for is read from its Bech32
+//! human-readable part (`bc`, `tb` or `bcrt`) or its Base58 version byte
+//! (`0x00`/`0x05` on mainnet, `0x6f`/`0xc4` on the test networks). Testnet and
+//! signet share the `tb` part, and Base58 test addresses are valid on
+//! testnet, signet and regtest alike.
+
+use std::fmt;
+use std::str::FromStr;
+use bitcoincore_rpc::bitcoin::{base58, Address, Network};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+
+/// Network an address was encoded for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressNetwork {
+    /// `bc` HRP, or Base58 version `0x00`/`0x05`
+    Mainnet,
+    /// `tb` HRP, used by testnet and signet
+    Testnet,
+    /// `bcrt` HRP
+    Regtest,
+    /// Base58 version `0x6f`/`0xc4`, used by every test network
+    TestBase58,
+}
+
+impl AddressNetwork {
+    /// Detect the network of an address from its HRP or version byte, `None` if it is neither
+    pub fn of(address: &str) -> Option<Self> {
+        let lowercase = address.to_ascii_lowercase();
+        if let Some(separator) = lowercase.rfind('1') {
+            match &lowercase[..separator] {
+                "bc" => return Some(AddressNetwork::Mainnet),
+                "tb" => return Some(AddressNetwork::Testnet),
+                "bcrt" => return Some(AddressNetwork::Regtest),
+                _ => {},
+            }
+        }
+        
+        match base58::decode_check(address).ok()?.first()? {
+            0x00 | 0x05 => Some(AddressNetwork::Mainnet),
+            0x6f | 0xc4 => Some(AddressNetwork::TestBase58),
+            _ => None,
+        }
+    }
+    
+    /// Whether an address of this network is valid on a network
+    pub fn is_valid_for(&self, network: Network) -> bool {
+        match self {
+            AddressNetwork::Mainnet => network == Network::Bitcoin,
+            AddressNetwork::Testnet => matches!(network, Network::Testnet | Network::Signet),
+            AddressNetwork::Regtest => network == Network::Regtest,
+            AddressNetwork::TestBase58 => matches!(network, Network::Testnet | Network::Signet | Network::Regtest),
+        }
+    }
+}
+
+impl fmt::Display for AddressNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressNetwork::Mainnet => write!(f, "mainnet"),
+            AddressNetwork::Testnet | AddressNetwork::TestBase58 => write!(f, "testnet"),
+            AddressNetwork::Regtest => write!(f, "regtest"),
+        }
+    }
+}
+
+/// Name of a network in errors, `mainnet` rather than `bitcoin`
+pub fn network_name(network: Network) -> String {
+    match network {
+        Network::Bitcoin => "mainnet".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Refuses addresses of any network but the configured one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkGuard {
+    /// Network addresses must be valid on
+    network: Network,
+}
+
+impl NetworkGuard {
+    /// Create a guard for a network
+    pub fn new(network: Network) -> Self {
+        Self { network }
+    }
+    
+    /// Network the guard accepts addresses of
+    pub fn network(&self) -> Network {
+        self.network
+    }
+    
+    /// Check that an address belongs to the configured network
+    ///
+    /// Fails with `WrongNetwork` naming both networks for an address of
+    /// another network, and with `InvalidAddress` for anything that is not an
+    /// address.
+    pub fn check(&self, address: &str) -> Result<(), ContractError> {
+        self.parse(address).map(|_| ())
+    }
+    
+    /// Parse an address, checked to belong to the configured network
+    pub fn parse(&self, address: &str) -> Result<Address, ContractError> {
+        let address_network = AddressNetwork::of(address).ok_or(ContractError::InvalidAddress)?;
+        if !address_network.is_valid_for(self.network) {
+            return Err(ContractError::WrongNetwork {
+                address_network: address_network.to_string(),
+                expected_network: network_name(self.network),
+            });
+        }
+        
+        Address::from_str(address)
+            .map_err(|_| ContractError::InvalidAddress)?
+            .require_network(self.network)
+            .map_err(|_| ContractError::InvalidAddress)
+    }
+}
+
+impl Default for NetworkGuard {
+    fn default() -> Self {
+        Self::new(Network::Testnet)
+    }
+}
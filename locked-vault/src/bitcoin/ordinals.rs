@@ -1,11 +1,27 @@
+//! Ordinals inscriptions held by the vault
+//!
+//! Inscriptions are looked up through an Ordinals API. Two API flavors are
+//! understood: Hiro's (`/ordinals/v1/...`), which reports collection
+//! metadata, and ord's own JSON API, which reports the inscribed sat. Fields a
+//! flavor does not provide stay `None`; sat rarity is derived from the sat
+//! number when the API does not report it.
+
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use log::{debug, info};
 
 use crate::errors::ContractError;
+use crate::bitcoin::fee_oracle::{ConfirmationTarget, FeeOracle};
 use crate::bitcoin::rpc::BitcoinRpcClient;
-
+use crate::bitcoin::testnet::CacheConfig;
+use crate::clock::{Clock, SystemClock};
+use crate::logging::ORDINALS;
+use crate::models::FeeQuote;
 
 /// Ordinal inscription
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -28,6 +44,439 @@ pub struct Inscription {
     pub owner: String,
     /// Satoshi offset
     pub offset: u64,
+    /// Collection the inscription belongs to
+    #[serde(default)]
+    pub collection: Option<String>,
+    /// Ordinal number of the inscribed sat
+    #[serde(default)]
+    pub sat: Option<u64>,
+    /// Rarity of the inscribed sat
+    #[serde(default)]
+    pub rarity: Option<SatRarity>,
+    /// Content size in bytes
+    #[serde(default)]
+    pub content_length: Option<u64>,
+}
+
+impl Inscription {
+    /// Parse an inscription response of an Ordinals API
+    /// 
+    /// The content itself is not part of either response and is left empty.
+    pub fn from_api_response(flavor: OrdinalsApiFlavor, body: &str) -> Result<Self, ContractError> {
+        let inscription = match flavor {
+            OrdinalsApiFlavor::Hiro => {
+                let response: HiroInscription = parse_response(body)?;
+                let (txid, vout, offset) = parse_satpoint(&response.location)?;
+                let sat = response.sat_ordinal
+                    .map(|sat| sat.parse::<u64>()
+                        .map_err(|_| invalid_response(format!("invalid sat_ordinal {}", sat))))
+                    .transpose()?;
+                
+                Inscription {
+                    id: response.id,
+                    txid,
+                    vout,
+                    number: response.number,
+                    content_type: response.content_type.unwrap_or_default(),
+                    content: String::new(),
+                    // Hiro reports milliseconds
+                    timestamp: response.timestamp / 1000,
+                    owner: response.address.unwrap_or_default(),
+                    offset,
+                    collection: response.collection,
+                    sat,
+                    rarity: response.sat_rarity,
+                    content_length: response.content_length,
+                }
+            },
+            OrdinalsApiFlavor::Ord => {
+                let response: OrdInscription = parse_response(body)?;
+                let (txid, vout, offset) = parse_satpoint(&response.satpoint)?;
+                
+                Inscription {
+                    id: response.id,
+                    txid,
+                    vout,
+                    number: response.number,
+                    content_type: response.content_type.unwrap_or_default(),
+                    content: String::new(),
+                    timestamp: response.timestamp,
+                    owner: response.address.unwrap_or_default(),
+                    offset,
+                    collection: None,
+                    sat: response.sat,
+                    rarity: None,
+                    content_length: response.content_length,
+                }
+            },
+        };
+        
+        Ok(inscription.with_derived_rarity())
+    }
+    
+    /// Fill in the sat rarity from the sat number if the API did not report it
+    fn with_derived_rarity(mut self) -> Self {
+        if self.rarity.is_none() {
+            self.rarity = self.sat.and_then(SatRarity::of);
+        }
+        self
+    }
+}
+
+/// Ordinals API whose responses the client understands
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrdinalsApiFlavor {
+    /// Hiro Ordinals API, which reports collections
+    Hiro,
+    /// ord's JSON API, which reports sats
+    #[default]
+    Ord,
+}
+
+impl FromStr for OrdinalsApiFlavor {
+    type Err = String;
+    
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "hiro" => Ok(OrdinalsApiFlavor::Hiro),
+            "ord" => Ok(OrdinalsApiFlavor::Ord),
+            _ => Err(format!("Unknown Ordinals API flavor {} (expected hiro or ord)", value)),
+        }
+    }
+}
+
+/// Total number of sats that will ever exist
+const SAT_SUPPLY: u64 = 2_099_999_997_690_000;
+
+/// Sats per bitcoin
+const SATS_PER_BTC: u64 = 100_000_000;
+
+/// Blocks between subsidy halvings
+const HALVING_INTERVAL: u64 = 210_000;
+
+/// Blocks between difficulty adjustments
+const DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 2016;
+
+/// Blocks per cycle, when a halving coincides with a difficulty adjustment
+const CYCLE_INTERVAL: u64 = 6 * HALVING_INTERVAL;
+
+/// Rarity of a sat by the ordinal theory rarity index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SatRarity {
+    /// Any sat that is not the first of its block
+    Common,
+    /// First sat of a block
+    Uncommon,
+    /// First sat of a difficulty adjustment period
+    Rare,
+    /// First sat of a halving epoch
+    Epic,
+    /// First sat of a cycle
+    Legendary,
+    /// First sat of the genesis block
+    Mythic,
+}
+
+impl SatRarity {
+    /// Rarity of a sat, or `None` beyond the supply
+    pub fn of(sat: u64) -> Option<Self> {
+        let (height, offset) = sat_position(sat)?;
+        
+        let rarity = if sat == 0 {
+            SatRarity::Mythic
+        } else if offset != 0 {
+            SatRarity::Common
+        } else if height % CYCLE_INTERVAL == 0 {
+            SatRarity::Legendary
+        } else if height % HALVING_INTERVAL == 0 {
+            SatRarity::Epic
+        } else if height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0 {
+            SatRarity::Rare
+        } else {
+            SatRarity::Uncommon
+        };
+        
+        Some(rarity)
+    }
+}
+
+/// Block height that mined a sat and the sat's offset in that block's subsidy
+fn sat_position(sat: u64) -> Option<(u64, u64)> {
+    let mut epoch_start = 0u64;
+    
+    for epoch in 0..64 {
+        let subsidy = (50 * SATS_PER_BTC) >> epoch;
+        if subsidy == 0 {
+            break;
+        }
+        
+        let epoch_sats = subsidy * HALVING_INTERVAL;
+        if sat < epoch_start + epoch_sats {
+            let relative = sat - epoch_start;
+            return Some((epoch * HALVING_INTERVAL + relative / subsidy, relative % subsidy));
+        }
+        
+        epoch_start += epoch_sats;
+    }
+    
+    None
+}
+
+/// Name of a sat, counting down from "nvtdijuwxlp" for sat 0 to "a" for the last sat
+fn sat_name(sat: u64) -> Option<String> {
+    if sat >= SAT_SUPPLY {
+        return None;
+    }
+    
+    let mut remaining = SAT_SUPPLY - sat;
+    let mut name = Vec::new();
+    while remaining > 0 {
+        name.push(b'a' + ((remaining - 1) % 26) as u8);
+        remaining = (remaining - 1) / 26;
+    }
+    name.reverse();
+    
+    String::from_utf8(name).ok()
+}
+
+/// Details of a sat
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SatInfo {
+    /// Ordinal number
+    pub number: u64,
+    /// Rarity
+    pub rarity: SatRarity,
+    /// Name
+    pub name: Option<String>,
+    /// Height of the block that mined the sat
+    pub block: Option<u64>,
+}
+
+impl SatInfo {
+    /// Compute the details of a sat from its number, or `None` beyond the supply
+    pub fn from_number(number: u64) -> Option<Self> {
+        let (height, _) = sat_position(number)?;
+        
+        Some(Self {
+            number,
+            rarity: SatRarity::of(number)?,
+            name: sat_name(number),
+            block: Some(height),
+        })
+    }
+    
+    /// Parse the response of an Ordinals API to a request for sat `number`
+    /// 
+    /// Details missing from the response are computed from the sat number.
+    pub fn from_api_response(flavor: OrdinalsApiFlavor, number: u64, body: &str) -> Result<Self, ContractError> {
+        let response: SatResponse = parse_response(body)?;
+        if response.number.is_some_and(|reported| reported != number) {
+            return Err(invalid_response(format!("requested sat {} but got {:?}", number, response.number)));
+        }
+        
+        let computed = SatInfo::from_number(number)
+            .ok_or_else(|| invalid_response(format!("sat {} is beyond the supply", number)))?;
+        
+        // Hiro calls the block the coinbase height
+        let block = match flavor {
+            OrdinalsApiFlavor::Hiro => response.coinbase_height,
+            OrdinalsApiFlavor::Ord => response.block,
+        };
+        
+        Ok(Self {
+            number,
+            rarity: response.rarity.unwrap_or(computed.rarity),
+            name: response.name.or(computed.name),
+            block: block.or(computed.block),
+        })
+    }
+}
+
+/// Inscription response of the Hiro Ordinals API
+#[derive(Deserialize)]
+struct HiroInscription {
+    /// Inscription ID
+    id: String,
+    /// Inscription number
+    number: u64,
+    /// Current owner
+    #[serde(default)]
+    address: Option<String>,
+    /// Current satpoint (`txid:vout:offset`)
+    location: String,
+    /// Inscribed sat, as a decimal string
+    #[serde(default)]
+    sat_ordinal: Option<String>,
+    /// Rarity of the inscribed sat
+    #[serde(default)]
+    sat_rarity: Option<SatRarity>,
+    /// Content type
+    #[serde(default)]
+    content_type: Option<String>,
+    /// Content size in bytes
+    #[serde(default)]
+    content_length: Option<u64>,
+    /// Inscription time in milliseconds
+    #[serde(default)]
+    timestamp: u64,
+    /// Collection name
+    #[serde(default)]
+    collection: Option<String>,
+}
+
+/// Inscription response of ord's JSON API
+#[derive(Deserialize)]
+struct OrdInscription {
+    /// Inscription ID
+    id: String,
+    /// Inscription number
+    number: u64,
+    /// Current owner
+    #[serde(default)]
+    address: Option<String>,
+    /// Current satpoint (`txid:vout:offset`)
+    satpoint: String,
+    /// Inscribed sat, absent unless the index tracks sats
+    #[serde(default)]
+    sat: Option<u64>,
+    /// Content type
+    #[serde(default)]
+    content_type: Option<String>,
+    /// Content size in bytes
+    #[serde(default)]
+    content_length: Option<u64>,
+    /// Inscription time in seconds
+    #[serde(default)]
+    timestamp: u64,
+}
+
+/// Sat response of either API flavor
+#[derive(Deserialize)]
+struct SatResponse {
+    /// Ordinal number (ord only)
+    #[serde(default)]
+    number: Option<u64>,
+    /// Rarity
+    #[serde(default)]
+    rarity: Option<SatRarity>,
+    /// Name
+    #[serde(default)]
+    name: Option<String>,
+    /// Block that mined the sat (ord)
+    #[serde(default)]
+    block: Option<u64>,
+    /// Block that mined the sat (Hiro)
+    #[serde(default)]
+    coinbase_height: Option<u64>,
+}
+
+/// Error for a response the client cannot use
+fn invalid_response(reason: impl std::fmt::Display) -> ContractError {
+    ContractError::BitcoinTestnetError(format!("Invalid Ordinals API response: {}", reason))
+}
+
+/// Deserialize an API response body
+fn parse_response<R: serde::de::DeserializeOwned>(body: &str) -> Result<R, ContractError> {
+    serde_json::from_str(body).map_err(invalid_response)
+}
+
+/// Split a satpoint (`txid:vout:offset`) into its parts
+fn parse_satpoint(satpoint: &str) -> Result<(String, u32, u64), ContractError> {
+    let mut parts = satpoint.split(':');
+    
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(txid), Some(vout), Some(offset), None) => Ok((
+            txid.to_string(),
+            vout.parse().map_err(|_| invalid_response(format!("invalid satpoint {}", satpoint)))?,
+            offset.parse().map_err(|_| invalid_response(format!("invalid satpoint {}", satpoint)))?,
+        )),
+        _ => Err(invalid_response(format!("invalid satpoint {}", satpoint))),
+    }
+}
+
+/// Cached inscription
+#[derive(Debug, Clone)]
+struct CachedInscription {
+    /// Inscription as last fetched
+    inscription: Inscription,
+    /// When the inscription was fetched
+    fetched_at: DateTime<Utc>,
+    /// Access sequence number for LRU eviction
+    last_access: u64,
+}
+
+/// Bounded cache of inscriptions
+/// 
+/// Entries expire after the configured TTL and the least recently used entry
+/// is evicted once the cache is full.
+#[derive(Debug)]
+struct InscriptionCache {
+    /// Cache settings
+    config: CacheConfig,
+    /// Cached inscriptions by ID
+    entries: HashMap<String, CachedInscription>,
+    /// Access sequence counter
+    access_counter: u64,
+    /// Time source for expiry
+    clock: Arc<dyn Clock>,
+}
+
+impl InscriptionCache {
+    /// Create an empty cache
+    fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            access_counter: 0,
+            clock: Arc::new(SystemClock),
+        }
+    }
+    
+    /// Get a cached inscription if present and not expired
+    fn get(&mut self, inscription_id: &str) -> Option<Inscription> {
+        let ttl = chrono::Duration::from_std(self.config.ttl).unwrap_or(chrono::Duration::MAX);
+        let now = self.clock.now();
+        
+        match self.entries.get_mut(inscription_id) {
+            Some(entry) if now - entry.fetched_at < ttl => {
+                self.access_counter += 1;
+                entry.last_access = self.access_counter;
+                Some(entry.inscription.clone())
+            },
+            Some(_) => {
+                // Drop the expired entry
+                self.entries.remove(inscription_id);
+                None
+            },
+            None => None,
+        }
+    }
+    
+    /// Cache an inscription, evicting the least recently used entry if full
+    fn insert(&mut self, inscription: Inscription) {
+        self.access_counter += 1;
+        
+        self.entries.insert(inscription.id.clone(), CachedInscription {
+            inscription,
+            fetched_at: self.clock.now(),
+            last_access: self.access_counter,
+        });
+        
+        while self.entries.len() > self.config.max_entries {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(id, _)| id.clone());
+            
+            match oldest {
+                Some(id) => {
+                    self.entries.remove(&id);
+                },
+                None => break,
+            }
+        }
+    }
 }
 
 /// Ordinals client
@@ -37,10 +486,16 @@ pub struct OrdinalsClient {
     bitcoin_rpc: Arc<BitcoinRpcClient>,
     /// Ordinals API URL
     api_url: String,
+    /// Flavor of the Ordinals API
+    api_flavor: OrdinalsApiFlavor,
     /// Inscriptions cache
-    inscriptions: Arc<Mutex<HashMap<String, Inscription>>>,
+    inscriptions: Arc<Mutex<InscriptionCache>>,
+    /// Simulated Ordinals index, standing in for the API until it is wired up
+    index: Arc<Mutex<HashMap<String, Inscription>>>,
     /// Last API call timestamp for rate limiting
     last_api_call: Arc<Mutex<Instant>>,
+    /// Whether the simulated API fails every call
+    unreachable: Arc<AtomicBool>,
 }
 
 impl OrdinalsClient {
@@ -48,15 +503,48 @@ impl OrdinalsClient {
     pub fn new(
         bitcoin_rpc: Arc<BitcoinRpcClient>,
         api_url: String,
+    ) -> Self {
+        Self::with_cache_config(bitcoin_rpc, api_url, CacheConfig::default())
+    }
+    
+    /// Create a new Ordinals client with custom inscription cache settings
+    pub fn with_cache_config(
+        bitcoin_rpc: Arc<BitcoinRpcClient>,
+        api_url: String,
+        cache_config: CacheConfig,
     ) -> Self {
         Self {
             bitcoin_rpc,
             api_url,
-            inscriptions: Arc::new(Mutex::new(HashMap::new())),
+            api_flavor: OrdinalsApiFlavor::default(),
+            inscriptions: Arc::new(Mutex::new(InscriptionCache::new(cache_config))),
+            index: Arc::new(Mutex::new(HashMap::new())),
             last_api_call: Arc::new(Mutex::new(Instant::now())),
+            unreachable: Arc::new(AtomicBool::new(false)),
         }
     }
     
+    /// Set the flavor of the Ordinals API the client talks to
+    pub fn with_api_flavor(mut self, api_flavor: OrdinalsApiFlavor) -> Self {
+        self.api_flavor = api_flavor;
+        self
+    }
+    
+    /// Flavor of the Ordinals API the client talks to
+    pub fn api_flavor(&self) -> OrdinalsApiFlavor {
+        self.api_flavor
+    }
+    
+    /// Replace the time source used for cache expiry
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) -> Result<(), ContractError> {
+        let mut inscriptions = self.inscriptions.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        inscriptions.clock = clock;
+        
+        Ok(())
+    }
+    
     /// Make an API call with rate limiting
     fn rate_limit(&self) -> Result<(), ContractError> {
         let mut last_call = self.last_api_call.lock()
@@ -74,50 +562,152 @@ impl OrdinalsClient {
         // Update last call timestamp
         *last_call = Instant::now();
         
+        if self.unreachable.load(Ordering::Relaxed) {
+            return Err(ContractError::BitcoinTestnetError(format!("Ordinals API at {} is unreachable", self.api_url)));
+        }
+        
         Ok(())
     }
     
-    /// Get inscription by ID
-    pub fn get_inscription(&self, inscription_id: &str) -> Result<Inscription, ContractError> {
+    /// Check that the Ordinals API is reachable
+    pub fn ping(&self) -> Result<(), ContractError> {
         self.rate_limit()?;
         
+        // In a real implementation, this would request the Ordinals API root
+        // For now, we'll simulate it
+        
+        if self.api_url.is_empty() {
+            return Err(ContractError::BitcoinTestnetError("Ordinals API URL not configured".to_string()));
+        }
+        
+        Ok(())
+    }
+    
+    /// Get inscription by ID, from the cache while the entry is fresh
+    pub fn get_inscription(&self, inscription_id: &str) -> Result<Inscription, ContractError> {
         // Check cache
         {
-            let inscriptions = self.inscriptions.lock()
+            let mut inscriptions = self.inscriptions.lock()
                 .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
             
             if let Some(inscription) = inscriptions.get(inscription_id) {
-                return Ok(inscription.clone());
+                debug!(target: ORDINALS, "Inscription {} served from the cache", inscription_id);
+                return Ok(inscription);
             }
         }
         
-        // In a real implementation, this would call the Ordinals API
-        // For now, we'll simulate it
+        self.refresh_inscription(inscription_id)
+    }
+    
+    /// Get inscription by ID from the API, bypassing and then updating the cache
+    pub fn refresh_inscription(&self, inscription_id: &str) -> Result<Inscription, ContractError> {
+        self.rate_limit()?;
         
-        let inscription = Inscription {
-            id: inscription_id.to_string(),
-            txid: format!("txid_{}", inscription_id),
-            vout: 0,
-            number: 12345,
-            content_type: "image/png".to_string(),
-            content: "...".to_string(),
-            timestamp: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            owner: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-            offset: 0,
-        };
+        let inscription = self.fetch_inscription(inscription_id)?;
+        debug!(target: ORDINALS, "Fetched inscription {} owned by {}", inscription_id, inscription.owner);
         
         // Cache the inscription
         let mut inscriptions = self.inscriptions.lock()
             .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
         
-        inscriptions.insert(inscription_id.to_string(), inscription.clone());
+        inscriptions.insert(inscription.clone());
         
         Ok(inscription)
     }
     
+    /// Drop a cached inscription so the next lookup goes to the API
+    pub fn invalidate(&self, inscription_id: &str) -> Result<(), ContractError> {
+        let mut inscriptions = self.inscriptions.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        inscriptions.entries.remove(inscription_id);
+        
+        Ok(())
+    }
+    
+    /// Check whether an address currently owns an inscription
+    /// 
+    /// Always refreshes the inscription first, so a stale cache entry cannot
+    /// make an ownership check pass or fail wrongly.
+    pub fn verify_owner(&self, inscription_id: &str, expected_owner: &str) -> Result<bool, ContractError> {
+        let inscription = self.refresh_inscription(inscription_id)?;
+        Ok(inscription.owner == expected_owner)
+    }
+    
+    /// Get the collection an inscription belongs to, if the API reports one
+    pub fn get_collection(&self, inscription_id: &str) -> Result<Option<String>, ContractError> {
+        Ok(self.get_inscription(inscription_id)?.collection)
+    }
+    
+    /// Get the details of a sat
+    pub fn get_sat_info(&self, sat_number: u64) -> Result<SatInfo, ContractError> {
+        self.rate_limit()?;
+        
+        // In a real implementation, this would call the Ordinals API and parse
+        // the response with `SatInfo::from_api_response`
+        // For now, we'll compute it
+        
+        SatInfo::from_number(sat_number)
+            .ok_or_else(|| ContractError::BitcoinTestnetError(format!("Sat {} is beyond the supply", sat_number)))
+    }
+    
+    /// Number of cached inscriptions
+    pub fn cached_len(&self) -> usize {
+        self.inscriptions.lock().map(|inscriptions| inscriptions.entries.len()).unwrap_or(0)
+    }
+    
+    /// Look an inscription up in the Ordinals index
+    fn fetch_inscription(&self, inscription_id: &str) -> Result<Inscription, ContractError> {
+        // In a real implementation, this would call the Ordinals API and parse
+        // the response with `Inscription::from_api_response`
+        // For now, we'll simulate it
+        
+        let mut index = self.index.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        let inscription = index.entry(inscription_id.to_string())
+            .or_insert_with(|| Inscription {
+                id: inscription_id.to_string(),
+                txid: format!("txid_{}", inscription_id),
+                vout: 0,
+                number: 12345,
+                content_type: "image/png".to_string(),
+                content: "...".to_string(),
+                timestamp: std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+                owner: "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+                offset: 0,
+                collection: None,
+                sat: None,
+                rarity: None,
+                content_length: None,
+            });
+        
+        Ok(inscription.clone())
+    }
+    
+    /// Move an inscription in the simulated index, as a transfer made elsewhere would
+    #[cfg(test)]
+    pub(crate) fn simulate_external_transfer(&self, inscription_id: &str, new_owner: &str) -> Result<(), ContractError> {
+        let mut inscription = self.fetch_inscription(inscription_id)?;
+        inscription.owner = new_owner.to_string();
+        
+        let mut index = self.index.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        index.insert(inscription_id.to_string(), inscription);
+        
+        Ok(())
+    }
+    
+    /// Make the simulated API fail every call, or recover
+    #[cfg(test)]
+    pub(crate) fn simulate_outage(&self, unreachable: bool) {
+        self.unreachable.store(unreachable, Ordering::Relaxed);
+    }
+    
     /// Get inscriptions by address
     pub fn get_inscriptions_by_address(&self, address: &str) -> Result<Vec<Inscription>, ContractError> {
         self.rate_limit()?;
@@ -143,6 +733,10 @@ impl OrdinalsClient {
                     .as_secs(),
                 owner: address.to_string(),
                 offset: 0,
+                collection: None,
+                sat: None,
+                rarity: None,
+                content_length: None,
             };
             
             inscriptions.push(inscription);
@@ -158,13 +752,8 @@ impl OrdinalsClient {
         from_address: &str,
         to_address: &str,
     ) -> Result<String, ContractError> {
-        self.rate_limit()?;
-        
-        // Get the inscription
-        let inscription = self.get_inscription(inscription_id)?;
-        
-        // Check ownership
-        if inscription.owner != from_address {
+        // Check ownership against the current owner
+        if !self.verify_owner(inscription_id, from_address)? {
             return Err(ContractError::Unauthorized);
         }
         
@@ -173,14 +762,20 @@ impl OrdinalsClient {
         
         let txid = format!("transfer_tx_{}", Instant::now().elapsed().as_nanos());
         
-        // Update the inscription in cache
-        let mut inscriptions = self.inscriptions.lock()
-            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
-        
-        if let Some(inscription) = inscriptions.get_mut(inscription_id) {
-            inscription.owner = to_address.to_string();
+        {
+            let mut index = self.index.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            if let Some(inscription) = index.get_mut(inscription_id) {
+                inscription.owner = to_address.to_string();
+            }
         }
         
+        // The cached owner is stale now
+        self.invalidate(inscription_id)?;
+        
+        info!(target: ORDINALS, "Transferred inscription {} to {} in {}", inscription_id, to_address, txid);
+        
         Ok(txid)
     }
     
@@ -211,4 +806,24 @@ impl OrdinalsClient {
         
         Ok(fee)
     }
+    
+    /// Estimate the fee to create an inscription confirming within `target`, at the shared fee rate
+    pub fn estimate_inscription_fee(&self, content_size: usize, target: ConfirmationTarget) -> Result<FeeQuote, ContractError> {
+        let (fee_rate, snapshot_id) = self.fee_oracle().fee_rate_with_snapshot(target)?;
+        
+        // `get_inscription_fee` takes sat/kvB
+        let fee = self.get_inscription_fee(content_size, fee_rate.sat_per_vb() * 1000.0)?;
+        
+        Ok(FeeQuote {
+            fee,
+            fee_snapshot_id: Some(snapshot_id),
+            target: Some(target),
+            target_blocks: Some(self.fee_oracle().blocks(target)),
+        })
+    }
+    
+    /// Fee estimates shared with the node connection
+    pub fn fee_oracle(&self) -> &Arc<FeeOracle> {
+        self.bitcoin_rpc.fee_oracle()
+    }
 }
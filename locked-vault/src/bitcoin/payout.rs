@@ -0,0 +1,206 @@
+//! Batching of non-urgent payouts
+//!
+//! Matured withdrawals do not need to confirm quickly, so instead of paying
+//! each one in its own transaction they wait in a `PayoutBatcher` until the
+//! batching window of the oldest one elapses or the batch is flushed. A
+//! flush pays every waiting payout from one transaction with one output per
+//! destination, splitting into several transactions where the per-batch
+//! output count or total would be exceeded.
+
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::testnet::PayoutBatchConfig;
+use crate::clock::{Clock, SystemClock};
+use crate::errors::ContractError;
+
+/// Smallest payout accepted into a batch, in satoshis
+///
+/// Outputs below this are dust to most nodes' relay policy.
+pub const DUST_THRESHOLD: u64 = 546;
+
+/// Payout waiting for the next batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayoutEntry {
+    /// Destination address
+    pub destination: String,
+    /// Amount in satoshis
+    pub amount: u64,
+    /// Caller's reference, reported back with the transaction ID
+    pub reference: u64,
+}
+
+/// Payouts taken from the batcher to be paid by one transaction
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PayoutBatch {
+    /// Payouts in the batch, in queue order
+    pub entries: Vec<PayoutEntry>,
+}
+
+impl PayoutBatch {
+    /// Outputs of the batch transaction: one per destination, in order of first appearance
+    pub fn outputs(&self) -> Vec<(String, u64)> {
+        let mut outputs: Vec<(String, u64)> = Vec::new();
+        
+        for entry in &self.entries {
+            match outputs.iter_mut().find(|(destination, _)| *destination == entry.destination) {
+                Some((_, amount)) => *amount = amount.saturating_add(entry.amount),
+                None => outputs.push((entry.destination.clone(), entry.amount)),
+            }
+        }
+        
+        outputs
+    }
+    
+    /// Total paid out by the batch
+    pub fn total(&self) -> u64 {
+        self.entries.iter().fold(0u64, |total, entry| total.saturating_add(entry.amount))
+    }
+    
+    /// References of the payouts in the batch
+    pub fn references(&self) -> Vec<u64> {
+        self.entries.iter().map(|entry| entry.reference).collect()
+    }
+}
+
+/// Accumulates payouts until their batching window elapses
+#[derive(Debug)]
+pub struct PayoutBatcher {
+    /// Batching settings
+    config: PayoutBatchConfig,
+    /// Waiting payouts, in queue order
+    entries: Vec<PayoutEntry>,
+    /// When the oldest waiting payout was queued
+    opened_at: Option<DateTime<Utc>>,
+    /// Time source for the batching window
+    clock: Arc<dyn Clock>,
+}
+
+impl PayoutBatcher {
+    /// Create an empty batcher
+    pub fn new(config: PayoutBatchConfig) -> Self {
+        Self {
+            config,
+            entries: Vec::new(),
+            opened_at: None,
+            clock: Arc::new(SystemClock),
+        }
+    }
+    
+    /// Replace the time source used for the batching window
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+    
+    /// Whether a payout of this size can be batched at all
+    ///
+    /// Payouts above the per-batch total never fit and must be sent on their own.
+    pub fn accepts(&self, amount: u64) -> bool {
+        amount <= self.config.max_total
+    }
+    
+    /// Queue a payout for the next batch
+    pub fn add(&mut self, destination: &str, amount: u64, reference: u64) -> Result<(), ContractError> {
+        if amount < DUST_THRESHOLD {
+            return Err(ContractError::DustOutput { amount, threshold: DUST_THRESHOLD });
+        }
+        
+        if !self.accepts(amount) {
+            return Err(ContractError::InvalidAmount { reason: format!("payout above the batch total of {}", self.config.max_total) });
+        }
+        
+        if self.entries.is_empty() {
+            self.opened_at = Some(self.clock.now());
+        }
+        
+        self.entries.push(PayoutEntry {
+            destination: destination.to_string(),
+            amount,
+            reference,
+        });
+        
+        Ok(())
+    }
+    
+    /// Whether the batching window of the oldest waiting payout has elapsed
+    pub fn is_due(&self) -> bool {
+        let window = chrono::Duration::from_std(self.config.window).unwrap_or(chrono::Duration::MAX);
+        
+        self.opened_at
+            .and_then(|opened_at| opened_at.checked_add_signed(window))
+            .is_some_and(|due_at| self.clock.now() >= due_at)
+    }
+    
+    /// Waiting payouts, in queue order
+    pub fn entries(&self) -> &[PayoutEntry] {
+        &self.entries
+    }
+    
+    /// Number of waiting payouts
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    
+    /// Whether no payout is waiting
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    
+    /// Take every waiting payout, split into batches within the per-batch limits
+    ///
+    /// Payouts keep their queue order; a batch closes when the next payout
+    /// would add an output above `max_outputs` or push the total above `max_total`.
+    pub fn drain_batches(&mut self) -> Vec<PayoutBatch> {
+        let mut batches = Vec::new();
+        let mut current = PayoutBatch::default();
+        
+        for entry in self.entries.drain(..) {
+            let new_output = !current.entries.iter().any(|queued| queued.destination == entry.destination);
+            let outputs = current.outputs().len() + usize::from(new_output);
+            let total = current.total().checked_add(entry.amount);
+            
+            // Close the current batch if this payout does not fit
+            let fits = outputs <= self.config.max_outputs && total.is_some_and(|total| total <= self.config.max_total);
+            if !fits && !current.entries.is_empty() {
+                batches.push(std::mem::take(&mut current));
+            }
+            
+            current.entries.push(entry);
+        }
+        
+        if !current.entries.is_empty() {
+            batches.push(current);
+        }
+        
+        self.opened_at = None;
+        batches
+    }
+    
+    /// Drop the waiting payouts with a reference, returning whether any was waiting
+    pub fn remove(&mut self, reference: u64) -> bool {
+        let waiting = self.entries.len();
+        self.entries.retain(|entry| entry.reference != reference);
+        
+        if self.entries.is_empty() {
+            self.opened_at = None;
+        }
+        
+        self.entries.len() < waiting
+    }
+    
+    /// Put the payouts of failed batches back at the front of the queue
+    ///
+    /// Their batching window restarts, so a failing batch is retried at the
+    /// next due flush rather than right away.
+    pub fn requeue(&mut self, mut entries: Vec<PayoutEntry>) {
+        if entries.is_empty() {
+            return;
+        }
+        
+        entries.append(&mut self.entries);
+        self.entries = entries;
+        
+        self.opened_at = Some(self.clock.now());
+    }
+}
@@ -0,0 +1,304 @@
+//! Chain reorganization detection for deposit funding transactions
+//!
+//! The watcher remembers the block that confirmed each tracked funding
+//! transaction, and the confirmations the confirmation policy required for
+//! it when tracking started, so a later policy change does not suspend
+//! deposits that were already confirmed. When the chain tip moves it re-checks them: a transaction that
+//! drops below the confirmation threshold or ends up in a different block is
+//! reported so the contract can suspend the deposit, and reported again once
+//! it re-confirms so the deposit can be reinstated.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use log::{error, info, warn};
+
+use crate::errors::ContractError;
+use crate::models::TokenType;
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::rpc::{BitcoinRpcClient, ChainTip};
+use crate::logging::MEMPOOL;
+
+/// Change in the confirmation state of a tracked funding transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReorgNotice {
+    /// A previously confirmed funding transaction was reorganized out
+    Suspended {
+        /// Deposit funded by the transaction
+        deposit_id: u64,
+        /// Funding transaction ID
+        txid: String,
+        /// What changed
+        reason: String,
+    },
+    /// A reorganized funding transaction confirmed again
+    Reinstated {
+        /// Deposit funded by the transaction
+        deposit_id: u64,
+        /// Funding transaction ID
+        txid: String,
+    },
+}
+
+/// Callback receiving reorg notices
+pub type ReorgCallback = Arc<dyn Fn(&ReorgNotice) + Send + Sync>;
+
+/// Watch state of one funding transaction
+#[derive(Debug, Clone, Default)]
+struct TrackedTransaction {
+    /// Deposit funded by the transaction
+    deposit_id: u64,
+    /// Confirmations the transaction needs to count as confirmed
+    required_confirmations: u32,
+    /// Block that confirmed the transaction, once it reached the threshold
+    confirmed_block_hash: Option<String>,
+    /// Whether the transaction is currently reorganized out
+    suspended: bool,
+}
+
+/// Watcher for reorganizations affecting deposit funding transactions
+pub struct ReorgWatcher {
+    /// Bitcoin RPC client
+    bitcoin_rpc: Arc<BitcoinRpcClient>,
+    /// Confirmations a funding transaction needs, by token type and amount
+    confirmation_policy: Mutex<ConfirmationPolicy>,
+    /// Tracked transactions by transaction ID
+    tracked: Arc<Mutex<HashMap<String, TrackedTransaction>>>,
+    /// Chain tip seen by the last poll
+    last_tip: Arc<Mutex<Option<ChainTip>>>,
+    /// Receiver of notices
+    callback: Arc<Mutex<Option<ReorgCallback>>>,
+    /// Running flag
+    running: Arc<Mutex<bool>>,
+    /// Polling interval
+    interval: Duration,
+}
+
+impl std::fmt::Debug for ReorgWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReorgWatcher")
+            .field("confirmation_policy", &self.confirmation_policy)
+            .field("tracked", &self.tracked)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl ReorgWatcher {
+    /// Create a new reorg watcher
+    pub fn new(bitcoin_rpc: Arc<BitcoinRpcClient>, confirmation_policy: ConfirmationPolicy, interval: Duration) -> Self {
+        Self {
+            bitcoin_rpc,
+            confirmation_policy: Mutex::new(confirmation_policy),
+            tracked: Arc::new(Mutex::new(HashMap::new())),
+            last_tip: Arc::new(Mutex::new(None)),
+            callback: Arc::new(Mutex::new(None)),
+            running: Arc::new(Mutex::new(false)),
+            interval,
+        }
+    }
+    
+    /// Set the callback that receives notices, typically applying them to the contract
+    pub fn set_callback(&self, callback: ReorgCallback) -> Result<(), ContractError> {
+        let mut slot = self.callback.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *slot = Some(callback);
+        
+        Ok(())
+    }
+    
+    /// Replace the confirmation policy for transactions tracked from now on
+    pub fn set_confirmation_policy(&self, confirmation_policy: ConfirmationPolicy) -> Result<(), ContractError> {
+        let mut policy = self.confirmation_policy.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *policy = confirmation_policy;
+        
+        Ok(())
+    }
+    
+    /// Track the funding transaction of a deposit of `amount` in `token_type`
+    pub fn track(&self, deposit_id: u64, txid: &str, token_type: &TokenType, amount: u64) -> Result<(), ContractError> {
+        let required_confirmations = self.confirmation_policy.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .required_confirmations(token_type, amount);
+        
+        let mut tracked = self.tracked.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        tracked.insert(txid.to_string(), TrackedTransaction {
+            deposit_id,
+            required_confirmations,
+            ..TrackedTransaction::default()
+        });
+        
+        // Re-check on the next poll even if the tip has not moved
+        if let Ok(mut last_tip) = self.last_tip.lock() {
+            *last_tip = None;
+        }
+        
+        Ok(())
+    }
+    
+    /// Stop tracking a transaction, e.g. once its deposit is withdrawn
+    pub fn untrack(&self, txid: &str) -> Result<(), ContractError> {
+        let mut tracked = self.tracked.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        tracked.remove(txid);
+        
+        Ok(())
+    }
+    
+    /// Re-check tracked transactions if the chain tip moved, returning and dispatching notices
+    pub fn poll(&self) -> Result<Vec<ReorgNotice>, ContractError> {
+        Self::poll_once(
+            &self.bitcoin_rpc,
+            &self.tracked,
+            &self.last_tip,
+            &self.callback,
+        )
+    }
+    
+    /// Start polling in the background
+    pub fn start(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        if *running {
+            return Ok(());
+        }
+        
+        *running = true;
+        
+        // Clone Arc references for the thread
+        let bitcoin_rpc = self.bitcoin_rpc.clone();
+        let tracked = self.tracked.clone();
+        let last_tip = self.last_tip.clone();
+        let callback = self.callback.clone();
+        let running = self.running.clone();
+        let interval = self.interval;
+        
+        // Spawn polling thread
+        thread::spawn(move || {
+            info!(target: MEMPOOL, "Reorg watcher started");
+            
+            while *running.lock().unwrap() {
+                if let Err(e) = Self::poll_once(&bitcoin_rpc, &tracked, &last_tip, &callback) {
+                    error!(target: MEMPOOL, "Failed to check for reorgs: {:?}", e);
+                }
+                
+                thread::sleep(interval);
+            }
+            
+            info!(target: MEMPOOL, "Reorg watcher stopped");
+        });
+        
+        Ok(())
+    }
+    
+    /// Stop polling
+    pub fn stop(&self) -> Result<(), ContractError> {
+        let mut running = self.running.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        *running = false;
+        
+        Ok(())
+    }
+    
+    /// One polling round, shared by `poll` and the background thread
+    fn poll_once(
+        bitcoin_rpc: &BitcoinRpcClient,
+        tracked: &Mutex<HashMap<String, TrackedTransaction>>,
+        last_tip: &Mutex<Option<ChainTip>>,
+        callback: &Mutex<Option<ReorgCallback>>,
+    ) -> Result<Vec<ReorgNotice>, ContractError> {
+        // Confirmations cannot change while the tip stays put
+        let tip = bitcoin_rpc.get_chain_tip()?;
+        {
+            let mut last_tip = last_tip.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            if last_tip.as_ref() == Some(&tip) {
+                return Ok(Vec::new());
+            }
+            
+            *last_tip = Some(tip);
+        }
+        
+        let mut notices = Vec::new();
+        {
+            let mut tracked = tracked.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            for (txid, entry) in tracked.iter_mut() {
+                let status = match bitcoin_rpc.get_transaction_status(txid) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        warn!(target: MEMPOOL, "Failed to check funding transaction {}: {:?}", txid, e);
+                        continue;
+                    },
+                };
+                
+                let confirmed = status.confirmations >= entry.required_confirmations;
+                
+                if entry.suspended {
+                    // Reinstate once the transaction is buried deep enough again
+                    if confirmed {
+                        entry.suspended = false;
+                        entry.confirmed_block_hash = status.block_hash;
+                        notices.push(ReorgNotice::Reinstated {
+                            deposit_id: entry.deposit_id,
+                            txid: txid.clone(),
+                        });
+                    }
+                    
+                    continue;
+                }
+                
+                match &entry.confirmed_block_hash {
+                    // Not confirmed yet, remember the block once it is
+                    None => {
+                        if confirmed {
+                            entry.confirmed_block_hash = status.block_hash;
+                        }
+                    },
+                    Some(block_hash) => {
+                        let reason = if !confirmed {
+                            Some(format!("{} confirmations, {} required", status.confirmations, entry.required_confirmations))
+                        } else if status.block_hash.as_ref() != Some(block_hash) {
+                            Some(format!("confirming block changed from {}", block_hash))
+                        } else {
+                            None
+                        };
+                        
+                        if let Some(reason) = reason {
+                            entry.suspended = true;
+                            notices.push(ReorgNotice::Suspended {
+                                deposit_id: entry.deposit_id,
+                                txid: txid.clone(),
+                                reason,
+                            });
+                        }
+                    },
+                }
+            }
+        }
+        
+        // Dispatch outside the tracking lock so the callback may call back into the watcher
+        let callback = callback.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .clone();
+        
+        if let Some(callback) = callback {
+            for notice in &notices {
+                callback(notice);
+            }
+        }
+        
+        Ok(notices)
+    }
+}
@@ -1,14 +1,290 @@
 use bitcoincore_rpc::{Auth, Client, RpcApi};
-use bitcoincore_rpc::bitcoin::{Address, Amount, Transaction, Txid};
+use bitcoincore_rpc::json::{GetBlockchainInfoResult, ListUnspentQueryOptions, ListUnspentResultEntry};
+use bitcoincore_rpc::bitcoin::{Address, Amount, Network, SignedAmount, Transaction, Txid};
 use std::str::FromStr;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::ops::ControlFlow;
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
-use log::info;
+use chrono::DateTime;
+use log::{info, warn};
 
+use crate::bitcoin::anchor::ChainAnchor;
+use crate::bitcoin::fee_oracle::{ConfirmationTarget, FeeOracle, FeeRate, FeeSource};
+use crate::bitcoin::template::{self, DataOutputs};
 use crate::bitcoin::testnet::BitcoinTestnetConfig;
-use crate::bitcoin::utxo::{Utxo, UtxoSet};
-use crate::errors::ContractError;
+use crate::bitcoin::utxo::{CoinControl, OperationalUtxo, ScriptType, SelectionStrategy, Utxo, UtxoRef, UtxoSet};
+use crate::errors::{ContractError, TransferStage};
+use crate::introspection::RateLimiterSaturation;
+use crate::models::UnspentOutput;
+use crate::logging::RPC;
+
+/// Most confirmations `listunspent` filters on
+const MAX_CONFIRMATIONS: u32 = 9_999_999;
+
+/// Multiple of the fee of spending every gathered UTXO that must be left over
+/// before a withdrawal stops fetching more
+const FEE_BUFFER_FACTOR: u64 = 2;
+
+/// Prefix of the wallet labels of vault-created transactions
+pub const VAULT_LABEL_PREFIX: &str = "vault:";
+
+/// Wallet transactions fetched per `listtransactions` call when listing vault transactions
+const LIST_TRANSACTIONS_PAGE: usize = 100;
+
+/// Most broadcast transactions tracked until they confirm
+const MAX_TRACKED_BROADCASTS: usize = 1_000;
+
+/// Age after which the cached chain tip no longer anchors events, three reorg watcher polls
+const CHAIN_TIP_MAX_AGE: Duration = Duration::from_secs(180);
+
+/// Counters of the rate limiter, read by diagnostics without locking
+#[derive(Debug, Default)]
+struct RateLimitCounters {
+    /// Calls made through the limiter
+    calls: AtomicU64,
+    /// Calls that had to wait for their turn
+    throttled: AtomicU64,
+    /// Callers in the limiter right now
+    waiting: AtomicU64,
+}
+
+/// Spacing of node calls to the configured calls per minute, shared by every user of a connection
+#[derive(Debug, Clone)]
+struct RateLimiter {
+    /// Calls allowed per minute
+    limit_per_minute: u32,
+    /// Last API call timestamp
+    last_call: Arc<Mutex<Instant>>,
+    /// Counters of the limiter
+    counters: Arc<RateLimitCounters>,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing `limit_per_minute` calls per minute
+    fn new(limit_per_minute: u32) -> Self {
+        Self {
+            limit_per_minute,
+            last_call: Arc::new(Mutex::new(Instant::now())),
+            counters: Arc::new(RateLimitCounters::default()),
+        }
+    }
+    
+    /// Wait for the turn of the next call
+    fn acquire(&self) -> Result<(), ContractError> {
+        let counters = &self.counters;
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        counters.waiting.fetch_add(1, Ordering::Relaxed);
+        let result = self.wait_for_turn();
+        counters.waiting.fetch_sub(1, Ordering::Relaxed);
+        
+        result
+    }
+    
+    /// Wait until the rate limit allows the next call
+    fn wait_for_turn(&self) -> Result<(), ContractError> {
+        let mut last_call = self.last_call.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        
+        // Check rate limit
+        let elapsed = last_call.elapsed();
+        let min_interval = Duration::from_secs(60) / self.limit_per_minute;
+        
+        if elapsed < min_interval {
+            // Sleep to respect rate limit
+            self.counters.throttled.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(min_interval - elapsed);
+        }
+        
+        // Update last call timestamp
+        *last_call = Instant::now();
+        
+        Ok(())
+    }
+    
+    /// Get how hard the limiter is pushing back, without locking
+    fn saturation(&self) -> RateLimiterSaturation {
+        let counters = &self.counters;
+        let calls = counters.calls.load(Ordering::Relaxed);
+        let throttled_calls = counters.throttled.load(Ordering::Relaxed);
+        
+        RateLimiterSaturation {
+            limit_per_minute: self.limit_per_minute,
+            calls,
+            throttled_calls,
+            waiting_callers: counters.waiting.load(Ordering::Relaxed),
+            throttled_ratio: if calls == 0 { 0.0 } else { throttled_calls as f64 / calls as f64 },
+        }
+    }
+}
+
+/// Fee estimates of the node behind a connection, fetched through its rate limiter
+#[derive(Debug)]
+struct NodeFeeSource {
+    /// Inner RPC client
+    client: Arc<Client>,
+    /// Rate limiter of the connection
+    rate_limiter: RateLimiter,
+}
+
+impl FeeSource for NodeFeeSource {
+    fn estimate_fee_rate(&self, blocks: u16) -> Result<FeeRate, ContractError> {
+        self.rate_limiter.acquire()?;
+        
+        // Get fee estimate from node
+        let fee = self.client.estimate_smart_fee(blocks, None)
+            .map_err(|e| ContractError::RpcError { operation: "estimatesmartfee", source: e })?;
+        
+        let fee_rate = fee.fee_rate
+            .ok_or_else(|| ContractError::TransferFailed {
+                stage: TransferStage::FeeEstimation,
+                reason: "No fee estimate available".to_string(),
+            })?;
+        
+        // Convert to sat/vB - in newer versions we need to use to_sat() and divide
+        Ok(FeeRate::from_sat_per_vb(fee_rate.to_sat() as f64 / 1000.0))
+    }
+}
+
+/// Best block of the node's active chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTip {
+    /// Block height
+    pub height: u64,
+    /// Block hash
+    pub hash: String,
+}
+
+/// Confirmation state of a wallet transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionStatus {
+    /// Confirmations, zero if unconfirmed or conflicted
+    pub confirmations: u32,
+    /// Hash of the block containing the transaction, if confirmed
+    pub block_hash: Option<String>,
+}
+
+/// Wallet loaded on the node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletStatus {
+    /// Wallet name
+    pub name: String,
+    /// Whether the wallet holds private keys, i.e. is not watch-only
+    pub private_keys_enabled: bool,
+}
+
+/// What the node's wallet knows about an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressOwnership {
+    /// The wallet can sign for the address
+    pub is_mine: bool,
+    /// The wallet watches the address without its keys
+    pub is_watch_only: bool,
+    /// The wallet knows how to spend from the address, given the keys
+    pub solvable: bool,
+}
+
+/// Signed transaction waiting to be broadcast
+/// 
+/// Its inputs stay reserved until `release_utxos` is called, so other
+/// transactions built meanwhile do not spend them too.
+#[derive(Debug, Clone)]
+pub struct SignedTx {
+    /// Transaction ID
+    pub txid: String,
+    /// Signed raw transaction
+    pub hex: Vec<u8>,
+    /// References (txid:vout) of the spent UTXOs
+    pub inputs: Vec<String>,
+    /// Mining fee in satoshis
+    pub fee: u64,
+    /// Addresses paid, without the change
+    pub recipients: Vec<String>,
+    /// Wallet label given to the recipients once broadcast
+    pub label: Option<String>,
+    /// Change output paid back to the contract wallet, with its amount
+    pub change: Option<(UtxoRef, u64)>,
+}
+
+impl SignedTx {
+    /// Set the wallet label given to the recipients once broadcast
+    pub fn with_label(mut self, label: Option<String>) -> Self {
+        self.label = label;
+        self
+    }
+}
+
+/// Wallet transaction entry of a vault-created transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultTransaction {
+    /// Transaction ID
+    pub txid: String,
+    /// Wallet label of the entry's address
+    pub label: String,
+    /// Address the entry pays or receives on
+    pub address: Option<String>,
+    /// Wallet category (`send`, `receive`, ...)
+    pub category: String,
+    /// Amount in satoshis, negative for sends
+    pub amount: i64,
+    /// Mining fee in satoshis, negative, for sends
+    pub fee: Option<i64>,
+    /// Confirmations, negative if conflicted
+    pub confirmations: i64,
+    /// Unix time the wallet saw the transaction
+    pub time: u64,
+}
+
+/// What to do with outputs paying the same address more than once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateOutputs {
+    /// Combine them into one output paying the sum
+    #[default]
+    Merge,
+    /// Fail with `DuplicateOutput`
+    Reject,
+}
+
+/// Confirmations and amounts (in satoshis) a `listunspent` call is restricted to, bounds inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct UnspentRange {
+    /// Fewest confirmations
+    min_conf: u32,
+    /// Most confirmations
+    max_conf: u32,
+    /// Smallest amount
+    min_amount: u64,
+    /// Largest amount
+    max_amount: u64,
+}
+
+impl UnspentRange {
+    /// Range holding every unspent output
+    fn all() -> Self {
+        Self {
+            min_conf: 0,
+            max_conf: MAX_CONFIRMATIONS,
+            min_amount: 0,
+            max_amount: Amount::MAX_MONEY.to_sat(),
+        }
+    }
+    
+    /// Split into a lower and an upper half, by confirmations while it spans several and by amount after
+    fn split(&self) -> Option<(Self, Self)> {
+        if self.min_conf < self.max_conf {
+            let mid = self.min_conf + (self.max_conf - self.min_conf) / 2;
+            return Some((Self { max_conf: mid, ..*self }, Self { min_conf: mid + 1, ..*self }));
+        }
+        
+        if self.min_amount < self.max_amount {
+            let mid = self.min_amount + (self.max_amount - self.min_amount) / 2;
+            return Some((Self { max_amount: mid, ..*self }, Self { min_amount: mid + 1, ..*self }));
+        }
+        
+        None
+    }
+}
 
 /// Bitcoin RPC client wrapper
 #[derive(Debug, Clone)]
@@ -17,10 +293,18 @@ pub struct BitcoinRpcClient {
     client: Arc<Client>,
     /// Configuration
     config: BitcoinTestnetConfig,
-    /// Last API call timestamp for rate limiting
-    last_api_call: Arc<Mutex<Instant>>,
-    /// Fee estimates cache
-    fee_estimates: Arc<Mutex<HashMap<u16, (f64, Instant)>>>,
+    /// Rate limiter of the connection
+    rate_limiter: RateLimiter,
+    /// Broadcast transactions not known to be confirmed, oldest first, with their broadcast time
+    unconfirmed_broadcasts: Arc<Mutex<VecDeque<(String, Instant)>>>,
+    /// Fee estimates, shared with every component pricing transactions
+    fee_oracle: Arc<FeeOracle>,
+    /// UTXOs spent by built transactions that are not broadcast yet
+    reserved_utxos: Arc<Mutex<HashSet<String>>>,
+    /// Change of broadcast transactions held by the contract wallet, by reference
+    operational_utxos: Arc<Mutex<BTreeMap<String, OperationalUtxo>>>,
+    /// Chain tip of the last tip lookup, with when it was fetched
+    chain_tip: Arc<Mutex<Option<(ChainAnchor, Instant)>>>,
 }
 
 impl BitcoinRpcClient {
@@ -29,16 +313,16 @@ impl BitcoinRpcClient {
         // Create auth from config
         let auth = Auth::UserPass(
             config.rpc_username.clone(),
-            config.rpc_password.clone(),
+            config.rpc_password.expose_secret().clone(),
         );
         
         // Create RPC client
         let client = Client::new(&config.rpc_url, auth)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to create RPC client: {}", e)))?;
+            .map_err(|e| ContractError::InitializationError(format!("Failed to create RPC client: {}", e)))?;
         
         // Test connection
         let blockchain_info = client.get_blockchain_info()
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to connect to Bitcoin node: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "getblockchaininfo", source: e })?;
         
         // Verify we're on testnet
         if blockchain_info.chain != "test" {
@@ -47,34 +331,78 @@ impl BitcoinRpcClient {
             ));
         }
         
-        info!("Connected to Bitcoin testnet node");
+        info!(target: RPC, "Connected to Bitcoin testnet node");
+        
+        Ok(Self::from_client(client, config))
+    }
+    
+    /// Wrap an already connected client without checking the network
+    pub(crate) fn from_client(client: Client, config: &BitcoinTestnetConfig) -> Self {
+        let client = Arc::new(client);
+        let rate_limiter = RateLimiter::new(config.rate_limit);
+        let fee_source = NodeFeeSource { client: client.clone(), rate_limiter: rate_limiter.clone() };
+        let fee_oracle = FeeOracle::new(config.fee_oracle.clone(), Arc::new(fee_source))
+            .with_targets(config.fee_targets)
+            .tracking([ConfirmationTarget::Urgent, ConfirmationTarget::Normal, ConfirmationTarget::Economy]);
         
-        Ok(Self {
-            client: Arc::new(client),
+        Self {
+            client,
             config: config.clone(),
-            last_api_call: Arc::new(Mutex::new(Instant::now())),
-            fee_estimates: Arc::new(Mutex::new(HashMap::new())),
-        })
+            rate_limiter,
+            unconfirmed_broadcasts: Arc::new(Mutex::new(VecDeque::new())),
+            fee_oracle: Arc::new(fee_oracle),
+            reserved_utxos: Arc::new(Mutex::new(HashSet::new())),
+            operational_utxos: Arc::new(Mutex::new(BTreeMap::new())),
+            chain_tip: Arc::new(Mutex::new(None)),
+        }
     }
     
     /// Make an API call with rate limiting
     fn rate_limit(&self) -> Result<(), ContractError> {
-        let mut last_call = self.last_api_call.lock()
-            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
-        
-        // Check rate limit
-        let elapsed = last_call.elapsed();
-        let min_interval = Duration::from_secs(60) / self.config.rate_limit as u32;
+        self.rate_limiter.acquire()
+    }
+    
+    /// Get how hard the rate limiter is pushing back, without locking
+    pub fn rate_limiter_saturation(&self) -> RateLimiterSaturation {
+        self.rate_limiter.saturation()
+    }
+    
+    /// Fee estimates of this connection
+    pub fn fee_oracle(&self) -> &Arc<FeeOracle> {
+        &self.fee_oracle
+    }
+    
+    /// Broadcast transactions not known to be confirmed, oldest first, with their broadcast time
+    pub(crate) fn unconfirmed_broadcasts(&self) -> &Mutex<VecDeque<(String, Instant)>> {
+        &self.unconfirmed_broadcasts
+    }
+    
+    /// Stop tracking the oldest broadcast transactions that have confirmed
+    /// 
+    /// Checks from the oldest and stops at the first one still unconfirmed,
+    /// so a healthy node costs one call. Returns how many were dropped.
+    pub fn prune_confirmed_broadcasts(&self) -> Result<usize, ContractError> {
+        let mut pruned = 0;
         
-        if elapsed < min_interval {
-            // Sleep to respect rate limit
-            std::thread::sleep(min_interval - elapsed);
+        loop {
+            let oldest = self.unconfirmed_broadcasts.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+                .front()
+                .map(|(txid, _)| txid.clone());
+            
+            match oldest {
+                Some(txid) if self.get_transaction_confirmations(&txid)? > 0 => pruned += 1,
+                _ => return Ok(pruned),
+            }
         }
+    }
+    
+    /// Get blockchain info from the node
+    pub fn get_blockchain_info(&self) -> Result<GetBlockchainInfoResult, ContractError> {
+        self.rate_limit()?;
         
-        // Update last call timestamp
-        *last_call = Instant::now();
-        
-        Ok(())
+        self.client.get_blockchain_info()
+            .map_err(|e| ContractError::RpcError { operation: "getblockchaininfo", source: e })
     }
     
     /// Get the balance of an address
@@ -94,7 +422,7 @@ impl BitcoinRpcClient {
             .map_err(|_| ContractError::InvalidAddress)?;
         
         let utxos = self.client.list_unspent(None, None, Some(&[&checked_addr]), None, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get UTXOs: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "listunspent", source: e })?;
         
         // Sum the values
         let balance: u64 = utxos.iter()
@@ -106,153 +434,660 @@ impl BitcoinRpcClient {
     
     /// Get UTXOs for an address
     pub fn get_address_utxos(&self, address: &str) -> Result<UtxoSet, ContractError> {
-        self.rate_limit()?;
+        let mut utxo_set = UtxoSet::new();
         
+        self.for_each_utxo_page(address, |page| {
+            for utxo in page.get_all() {
+                utxo_set.add(utxo.clone());
+            }
+            ControlFlow::Continue(())
+        })?;
+        
+        Ok(utxo_set)
+    }
+    
+    /// Fetch the UTXOs of an address page by page, in ascending order of confirmations
+    /// 
+    /// Each `listunspent` call returns at most `utxo_page_size` outputs and
+    /// waits for the rate limiter. A range of confirmations holding more is
+    /// split in halves, down to single confirmation counts and then by amount,
+    /// until every page fits. `on_page` receives each page's outputs not
+    /// seen in an earlier page, since an output confirming meanwhile shows up
+    /// again in a later range, and stops the listing by returning `Break`.
+    pub fn for_each_utxo_page<F>(&self, address: &str, mut on_page: F) -> Result<(), ContractError>
+    where
+        F: FnMut(UtxoSet) -> ControlFlow<()>,
+    {
         // Convert address string to Address object
         let addr = Address::from_str(address)
             .map_err(|_| ContractError::InvalidAddress)?;
         
         // Convert to checked address for the API
         let script = addr.payload.script_pubkey();
-        let checked_addr = Address::from_script(&script, addr.network.clone())
+        let checked_addr = Address::from_script(&script, addr.network)
             .map_err(|_| ContractError::InvalidAddress)?;
         
-        // Get unspent outputs for address
-        let utxos = self.client.list_unspent(None, None, Some(&[&checked_addr]), None, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get UTXOs: {}", e)))?;
+        let page_size = self.config.utxo_page_size;
+        let mut seen: HashSet<String> = HashSet::new();
         
-        // Convert to our UTXO format
-        let mut utxo_set = UtxoSet::new();
+        // Ranges still to list, the next one last
+        let mut pending = vec![UnspentRange::all()];
         
-        for utxo in utxos {
-            let utxo_entry = Utxo {
-                txid: utxo.txid.to_string(),
-                vout: utxo.vout,
-                amount: utxo.amount.to_sat(),
-                confirmations: utxo.confirmations,
-                script_pubkey: utxo.script_pub_key.to_string(),
-                address: address.to_string(),
-                spendable: true,
-            };
+        while let Some(range) = pending.pop() {
+            // Ask for one output more than a page, to tell a full range from a truncated one
+            let mut entries = self.list_unspent_range(&checked_addr, &range, Some(page_size + 1))?;
             
-            utxo_set.add(utxo_entry);
-        }
-        
-        Ok(utxo_set)
-    }
-    
-    /// Get estimated fee rate
-    pub fn get_fee_estimate(&self, target_blocks: u16) -> Result<f64, ContractError> {
-        // Check cache first
-        {
-            let fee_estimates = self.fee_estimates.lock()
-                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            if entries.len() > page_size {
+                match range.split() {
+                    Some((lower, upper)) => {
+                        pending.push(upper);
+                        pending.push(lower);
+                        continue;
+                    },
+                    // Outputs of one amount at one depth cannot be told apart any further
+                    None => entries = self.list_unspent_range(&checked_addr, &range, None)?,
+                }
+            }
             
-            if let Some((fee, timestamp)) = fee_estimates.get(&target_blocks) {
-                // Cache is valid for 10 minutes
-                if timestamp.elapsed() < Duration::from_secs(600) {
-                    return Ok(*fee);
+            // Convert to our UTXO format, skipping outputs already listed
+            let mut page = UtxoSet::new();
+            
+            for utxo in entries {
+                let utxo_entry = Utxo {
+                    txid: utxo.txid.to_string(),
+                    vout: utxo.vout,
+                    amount: utxo.amount.to_sat(),
+                    confirmations: utxo.confirmations,
+                    script_pubkey: utxo.script_pub_key.to_hex_string(),
+                    address: address.to_string(),
+                    spendable: true,
+                };
+                
+                if seen.insert(utxo_entry.reference().to_string()) {
+                    page.add(utxo_entry);
                 }
             }
+            
+            if page.is_empty() {
+                continue;
+            }
+            
+            if on_page(page).is_break() {
+                break;
+            }
         }
         
+        Ok(())
+    }
+    
+    /// List the unspent outputs of an address within a range, at most `maximum_count` of them
+    fn list_unspent_range(
+        &self,
+        address: &Address,
+        range: &UnspentRange,
+        maximum_count: Option<usize>,
+    ) -> Result<Vec<ListUnspentResultEntry>, ContractError> {
         self.rate_limit()?;
         
-        // Get fee estimate from node
-        let fee = self.client.estimate_smart_fee(target_blocks as u16, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to estimate fee: {}", e)))?;
+        let options = ListUnspentQueryOptions {
+            minimum_amount: Some(Amount::from_sat(range.min_amount)),
+            maximum_amount: Some(Amount::from_sat(range.max_amount)),
+            maximum_count,
+            minimum_sum_amount: None,
+        };
         
-        let fee_rate = fee.fee_rate
-            .ok_or_else(|| ContractError::BitcoinTestnetError("No fee estimate available".to_string()))?;
+        self.client.list_unspent(
+            Some(range.min_conf as usize),
+            Some(range.max_conf as usize),
+            Some(&[address]),
+            None,
+            Some(options),
+        )
+            .map_err(|e| ContractError::RpcError { operation: "listunspent", source: e })
+    }
+    
+    /// Get the wallet loaded on the node
+    pub fn get_wallet_status(&self) -> Result<WalletStatus, ContractError> {
+        self.rate_limit()?;
         
-        // Convert to sat/vB - in newer versions we need to use to_sat() and divide
-        let fee_rate_sat_vb = fee_rate.to_sat() as f64 / 1000.0;
+        let info: serde_json::Value = self.client.call("getwalletinfo", &[])
+            .map_err(|e| ContractError::RpcError { operation: "getwalletinfo", source: e })?;
         
-        // Update cache
-        {
-            let mut fee_estimates = self.fee_estimates.lock()
-                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+        Ok(WalletStatus {
+            name: info["walletname"].as_str().unwrap_or_default().to_string(),
+            // Older nodes omit the flag and only have wallets with keys
+            private_keys_enabled: info["private_keys_enabled"].as_bool().unwrap_or(true),
+        })
+    }
+    
+    /// Ask the node's wallet what it knows about an address
+    pub fn get_address_ownership(&self, address: &str) -> Result<AddressOwnership, ContractError> {
+        self.rate_limit()?;
+        
+        let info: serde_json::Value = self.client.call("getaddressinfo", &[serde_json::json!(address)])
+            .map_err(|e| ContractError::RpcError { operation: "getaddressinfo", source: e })?;
+        
+        Ok(AddressOwnership {
+            is_mine: info["ismine"].as_bool().unwrap_or(false),
+            is_watch_only: info["iswatchonly"].as_bool().unwrap_or(false),
+            solvable: info["solvable"].as_bool().unwrap_or(false),
+        })
+    }
+    
+    /// Get estimated fee rate in sat/vB
+    pub fn get_fee_estimate(&self, target: ConfirmationTarget) -> Result<f64, ContractError> {
+        self.fee_oracle.fee_rate(target).map(|fee_rate| fee_rate.sat_per_vb())
+    }
+    
+    /// Get estimated fee rate in sat/vB for a target in blocks
+    #[deprecated(note = "use get_fee_estimate with a ConfirmationTarget")]
+    pub fn get_fee_estimate_blocks(&self, target_blocks: u16) -> Result<f64, ContractError> {
+        self.get_fee_estimate(ConfirmationTarget::Custom(target_blocks))
+    }
+    
+    /// Select inputs paying every recipient from an address, returning them with the change and the mining fee
+    fn select_inputs(
+        &self,
+        from_address: &str,
+        recipients: &[(String, u64)],
+        fee_rate: f64,
+        reserved: &HashSet<String>,
+        coin_control: Option<&CoinControl>,
+    ) -> Result<(Vec<Utxo>, u64, u64), ContractError> {
+        let amount = recipients.iter()
+            .try_fold(0u64, |total, (_, amount)| total.checked_add(*amount))
+            .ok_or(ContractError::ArithmeticError)?;
+        
+        // Inputs named explicitly must not be claimed by a transaction not broadcast yet
+        if let Some(CoinControl::Only(references)) = coin_control {
+            if let Some(reference) = references.iter().map(|reference| reference.to_string()).find(|reference| reserved.contains(reference)) {
+                return Err(ContractError::UtxoUnavailable {
+                    reference,
+                    reason: "reserved by a pending transaction".to_string(),
+                });
+            }
+        }
+        
+        // Size the payment and change outputs by their script types
+        let mut output_types = recipients.iter()
+            .map(|(address, _)| Self::script_type_of(address))
+            .collect::<Result<Vec<_>, _>>()?;
+        output_types.push(Self::script_type_of(from_address)?);
+        
+        let mut utxos = UtxoSet::new();
+        utxos.set_output_types(&output_types);
+        utxos.set_dust_policy(self.config.dust_policy);
+        
+        // Leave out inputs claimed by transactions not broadcast yet, and excluded ones
+        let mut unusable = reserved.clone();
+        if let Some(CoinControl::Exclude(references)) = coin_control {
+            unusable.extend(references.iter().map(|reference| reference.to_string()));
+        }
+        
+        // Fetch UTXOs of from_address until the chosen inputs are all found, or
+        // until the usable ones cover the amount with room to spare for the fee
+        self.for_each_utxo_page(from_address, |page| {
+            for utxo in page.get_all() {
+                if !unusable.contains(&utxo.reference().to_string()) {
+                    utxos.add(utxo.clone());
+                }
+            }
+            
+            let funded = match coin_control {
+                Some(CoinControl::Only(references)) => references.iter()
+                    .all(|reference| utxos.get(&reference.to_string()).is_some()),
+                _ => amount.checked_add(utxos.sweep_fee(fee_rate).saturating_mul(FEE_BUFFER_FACTOR))
+                    .is_some_and(|required| utxos.total_amount() >= required),
+            };
             
-            fee_estimates.insert(target_blocks, (fee_rate_sat_vb, Instant::now()));
+            if funded {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+        
+        // Select UTXOs for the transaction
+        let selection = match coin_control {
+            Some(CoinControl::Only(references)) => utxos.select_references(references, amount, fee_rate),
+            Some(CoinControl::Strategy(strategy)) => utxos.select_utxos(amount, fee_rate, *strategy),
+            Some(CoinControl::Exclude(_)) | None => utxos.select_utxos(amount, fee_rate, SelectionStrategy::Auto),
+        };
+        
+        let (selected_utxos, change) = selection
+            .map_err(|e| match e {
+                ContractError::InsufficientBalance { .. }
+                    | ContractError::UtxoUnavailable { .. }
+                    | ContractError::DustOutput { .. }
+                    | ContractError::TransferFailed { .. } => e,
+                other => ContractError::TransferFailed {
+                    stage: TransferStage::UtxoSelection,
+                    reason: other.to_string(),
+                },
+            })?;
+        
+        if selected_utxos.is_empty() {
+            return Err(ContractError::InsufficientBalance { available: 0, required: amount });
         }
         
-        Ok(fee_rate_sat_vb)
+        // Whatever the outputs do not claim goes to the miner
+        let input_total = selected_utxos.iter()
+            .try_fold(0u64, |total, utxo| total.checked_add(utxo.amount))
+            .ok_or(ContractError::ArithmeticError)?;
+        let fee = input_total.checked_sub(amount)
+            .and_then(|rest| rest.checked_sub(change))
+            .ok_or(ContractError::ArithmeticError)?;
+        
+        Ok((selected_utxos, change, fee))
+    }
+    
+    /// Estimate the mining fee of sending `amount` from an address at a fee rate
+    /// 
+    /// Uses the same input selection as `create_and_sign_transaction`, so the
+    /// estimate matches the fee paid as long as the UTXOs and rate are unchanged.
+    pub fn estimate_transaction_fee(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        amount: u64,
+        fee_rate: f64,
+    ) -> Result<u64, ContractError> {
+        let reserved = self.reserved_utxos.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .clone();
+        
+        let (_, _, fee) = self.select_inputs(from_address, &[(to_address.to_string(), amount)], fee_rate, &reserved, None)?;
+        Ok(fee)
     }
     
-    /// Create and sign a transaction
+    /// Get the type of script an address pays to
+    fn script_type_of(address: &str) -> Result<ScriptType, ContractError> {
+        let addr = Address::from_str(address)
+            .map_err(|_| ContractError::InvalidAddress)?;
+        
+        Ok(ScriptType::from_script(&addr.payload.script_pubkey()))
+    }
+    
+    /// Create, sign and broadcast a transaction
+    /// 
+    /// Fails with `FeeExceedsLimit` before anything is signed if the mining fee
+    /// of the selected inputs is above `max_fee`. `coin_control` restricts or
+    /// fixes the spent inputs. `label` is given to the recipient in the node
+    /// wallet once the transaction is broadcast.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_and_sign_transaction(
         &self,
         from_address: &str,
         to_address: &str,
         amount: u64,
         fee_rate: f64,
+        max_fee: Option<u64>,
+        coin_control: Option<&CoinControl>,
+        label: Option<String>,
     ) -> Result<String, ContractError> {
+        let built = self.build_transaction(from_address, to_address, amount, fee_rate, max_fee, coin_control)?
+            .with_label(label);
+        let result = self.broadcast_transaction(&built);
+        self.release_utxos(&built.inputs);
+        
+        result
+    }
+    
+    /// Create and sign a transaction without broadcasting it, reserving its inputs
+    /// 
+    /// Fails with `FeeExceedsLimit` before anything is signed if the mining fee
+    /// of the selected inputs is above `max_fee`, and with `ScriptTypeNotAllowed`
+    /// if an output pays to a script type the destination policy does not
+    /// allow. On failure nothing stays reserved.
+    pub fn build_transaction(
+        &self,
+        from_address: &str,
+        to_address: &str,
+        amount: u64,
+        fee_rate: f64,
+        max_fee: Option<u64>,
+        coin_control: Option<&CoinControl>,
+    ) -> Result<SignedTx, ContractError> {
+        let outputs = [(to_address.to_string(), amount)];
+        self.build_multi(from_address, &outputs, fee_rate, DuplicateOutputs::Reject, DataOutputs::Refuse, max_fee, coin_control)
+    }
+    
+    /// Create and sign a transaction paying several outputs, without broadcasting it
+    /// 
+    /// Outputs keep their order and the change output, if any, comes last.
+    /// Zero-value outputs fail with `InvalidAmount` and `OP_RETURN` outputs
+    /// with `ScriptTypeNotAllowed` unless `data_outputs` allows them, as a
+    /// runestone needs; outputs paying the same address twice are merged or
+    /// rejected as `duplicates` says. The inputs
+    /// are reserved like `build_transaction`, and the returned fee lets the
+    /// caller check a ceiling before calling `broadcast_transaction`, which
+    /// gives `label` to every recipient.
+    pub fn create_and_sign_transaction_multi(
+        &self,
+        from_address: &str,
+        outputs: &[(String, u64)],
+        fee_rate: f64,
+        duplicates: DuplicateOutputs,
+        data_outputs: DataOutputs,
+        label: Option<String>,
+    ) -> Result<SignedTx, ContractError> {
+        self.build_multi(from_address, outputs, fee_rate, duplicates, data_outputs, None, None)
+            .map(|built| built.with_label(label))
+    }
+    
+    /// Check and merge the outputs, then select, reserve and sign the inputs paying them
+    /// 
+    /// The signed transaction's outputs are checked against the destination
+    /// policy before it is returned.
+    #[allow(clippy::too_many_arguments)]
+    fn build_multi(
+        &self,
+        from_address: &str,
+        outputs: &[(String, u64)],
+        fee_rate: f64,
+        duplicates: DuplicateOutputs,
+        data_outputs: DataOutputs,
+        max_fee: Option<u64>,
+        coin_control: Option<&CoinControl>,
+    ) -> Result<SignedTx, ContractError> {
+        let recipients = Self::merge_outputs(outputs, duplicates, data_outputs)?;
+        
         self.rate_limit()?;
         
-        // Convert addresses
-        let to_addr = Address::from_str(to_address)
-            .map_err(|_| ContractError::InvalidAddress)?;
+        // Select inputs outside the reserved set, check the fee, then reserve them
+        let (selected_utxos, change, fee) = {
+            let mut reserved = self.reserved_utxos.lock()
+                .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
+            
+            let (selected_utxos, change, fee) = self.select_inputs(from_address, &recipients, fee_rate, &reserved, coin_control)?;
+            
+            if let Some(limit) = max_fee {
+                if fee > limit {
+                    return Err(ContractError::FeeExceedsLimit { required: fee, limit });
+                }
+            }
+            
+            reserved.extend(selected_utxos.iter().map(|utxo| utxo.reference().to_string()));
+            (selected_utxos, change, fee)
+        };
+        let inputs: Vec<String> = selected_utxos.iter().map(|utxo| utxo.reference().to_string()).collect();
         
-        // Get UTXOs for from_address
-        let utxos = self.get_address_utxos(from_address)?;
+        let result = self.sign_transaction(from_address, &recipients, &selected_utxos, change)
+            .and_then(|(hex, change_vout)| {
+                let transaction: Transaction = bitcoincore_rpc::bitcoin::consensus::deserialize(&hex)
+                    .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
+                template::validate_outputs(&transaction, &self.config.destination_policy, data_outputs)?;
+                let txid = transaction.txid().to_string();
+                
+                // Only change kept by the contract wallet is its operational money
+                let change = match change_vout {
+                    Some(vout) if from_address == self.config.contract_wallet_address => Some((UtxoRef::new(&txid, vout)?, change)),
+                    _ => None,
+                };
+                
+                Ok(SignedTx {
+                    txid,
+                    hex,
+                    inputs: inputs.clone(),
+                    fee,
+                    recipients: recipients.iter().map(|(address, _)| address.clone()).collect(),
+                    label: None,
+                    change,
+                })
+            });
         
-        // Select UTXOs for the transaction
-        let (selected_utxos, change) = utxos.select_utxos(amount, fee_rate)?;
+        if result.is_err() {
+            self.release_utxos(&inputs);
+        }
         
-        if selected_utxos.is_empty() {
-            return Err(ContractError::InsufficientBalance);
+        result
+    }
+    
+    /// Validate outputs and apply the duplicate policy, keeping first-appearance order
+    fn merge_outputs(outputs: &[(String, u64)], duplicates: DuplicateOutputs, data_outputs: DataOutputs) -> Result<Vec<(String, u64)>, ContractError> {
+        if outputs.is_empty() {
+            return Err(ContractError::InvalidAmount { reason: "transaction has no outputs".to_string() });
+        }
+        
+        let mut merged: Vec<(String, u64)> = Vec::with_capacity(outputs.len());
+        
+        for (address, amount) in outputs {
+            if *amount == 0 && data_outputs == DataOutputs::Refuse {
+                return Err(ContractError::InvalidAmount { reason: "output amount cannot be zero".to_string() });
+            }
+            
+            match (merged.iter_mut().find(|(merged_address, _)| merged_address == address), duplicates) {
+                (Some(_), DuplicateOutputs::Reject) => {
+                    return Err(ContractError::DuplicateOutput { address: address.clone() });
+                },
+                (Some((_, total)), DuplicateOutputs::Merge) => {
+                    *total = total.checked_add(*amount).ok_or(ContractError::ArithmeticError)?;
+                },
+                (None, _) => merged.push((address.clone(), *amount)),
+            }
         }
         
+        Ok(merged)
+    }
+    
+    /// Broadcast a built transaction, returning its transaction ID
+    /// 
+    /// A labelled transaction then has its recipients labelled in the node
+    /// wallet with `setlabel`, since `sendrawtransaction` takes no comment.
+    /// Labelling is best effort: the transaction is out, so a failure is only
+    /// logged.
+    /// 
+    /// The change is then tagged as operational, and operational UTXOs it
+    /// spent are dropped.
+    pub fn broadcast_transaction(&self, built: &SignedTx) -> Result<String, ContractError> {
+        self.rate_limit()?;
+        
+        let txid = self.client.send_raw_transaction(&built.hex)
+            .map_err(|e| ContractError::RpcError { operation: "sendrawtransaction", source: e })?;
+        
+        if let Ok(mut broadcasts) = self.unconfirmed_broadcasts.lock() {
+            broadcasts.push_back((txid.to_string(), Instant::now()));
+            while broadcasts.len() > MAX_TRACKED_BROADCASTS {
+                broadcasts.pop_front();
+            }
+        }
+        
+        if let Ok(mut operational) = self.operational_utxos.lock() {
+            for input in &built.inputs {
+                operational.remove(input);
+            }
+            if let Some((reference, amount)) = &built.change {
+                operational.insert(reference.to_string(), OperationalUtxo {
+                    reference: reference.clone(),
+                    amount: *amount,
+                    label: built.label.clone(),
+                });
+            }
+        }
+        
+        if let Some(label) = &built.label {
+            for address in &built.recipients {
+                if let Err(e) = self.set_label(address, label) {
+                    warn!(target: RPC, "Failed to label {} of transaction {} as {}: {}", address, txid, label, e);
+                }
+            }
+        }
+        
+        Ok(txid.to_string())
+    }
+    
+    /// Label an address in the node wallet
+    pub fn set_label(&self, address: &str, label: &str) -> Result<(), ContractError> {
+        self.rate_limit()?;
+        
+        let _: serde_json::Value = self.client.call("setlabel", &[serde_json::json!(address), serde_json::json!(label)])
+            .map_err(|e| ContractError::RpcError { operation: "setlabel", source: e })?;
+        
+        Ok(())
+    }
+    
+    /// List the wallet entries of vault-created transactions whose label starts with `label_prefix`, newest first
+    /// 
+    /// `skip` and `count` page through the matching entries. The wallet is
+    /// read with `listtransactions` in pages of `LIST_TRANSACTIONS_PAGE`
+    /// until enough entries match or the history ends. Entries without a
+    /// label never match, so a prefix outside `VAULT_LABEL_PREFIX` lists
+    /// manually labelled transactions too.
+    pub fn list_vault_transactions(&self, label_prefix: &str, count: usize, skip: usize) -> Result<Vec<VaultTransaction>, ContractError> {
+        let mut matching = Vec::new();
+        let mut skipped = 0;
+        let mut wallet_skip = 0;
+        
+        while matching.len() < count {
+            self.rate_limit()?;
+            
+            let page: Vec<serde_json::Value> = self.client.call("listtransactions", &[
+                serde_json::json!("*"),
+                serde_json::json!(LIST_TRANSACTIONS_PAGE),
+                serde_json::json!(wallet_skip),
+                serde_json::json!(true),
+            ])
+                .map_err(|e| ContractError::RpcError { operation: "listtransactions", source: e })?;
+            let exhausted = page.len() < LIST_TRANSACTIONS_PAGE;
+            wallet_skip += page.len();
+            
+            // The node lists each page oldest first
+            for entry in page.iter().rev() {
+                let transaction = match Self::parse_wallet_entry(entry) {
+                    Some(transaction) if transaction.label.starts_with(label_prefix) => transaction,
+                    _ => continue,
+                };
+                
+                if skipped < skip {
+                    skipped += 1;
+                } else if matching.len() < count {
+                    matching.push(transaction);
+                }
+            }
+            
+            if exhausted {
+                break;
+            }
+        }
+        
+        Ok(matching)
+    }
+    
+    /// Read a `listtransactions` entry that has a label
+    fn parse_wallet_entry(entry: &serde_json::Value) -> Option<VaultTransaction> {
+        let sats = |btc: &serde_json::Value| btc.as_f64()
+            .and_then(|btc| SignedAmount::from_btc(btc).ok())
+            .map(|amount| amount.to_sat());
+        
+        Some(VaultTransaction {
+            txid: entry["txid"].as_str()?.to_string(),
+            label: entry["label"].as_str().filter(|label| !label.is_empty())?.to_string(),
+            address: entry["address"].as_str().map(str::to_string),
+            category: entry["category"].as_str().unwrap_or_default().to_string(),
+            amount: sats(&entry["amount"])?,
+            fee: sats(&entry["fee"]),
+            confirmations: entry["confirmations"].as_i64().unwrap_or(0),
+            time: entry["time"].as_u64().unwrap_or(0),
+        })
+    }
+    
+    /// Get the change of broadcast transactions held by the contract wallet, in reference order
+    pub fn operational_utxos(&self) -> Vec<OperationalUtxo> {
+        self.operational_utxos.lock()
+            .map(|operational| operational.values().cloned().collect())
+            .unwrap_or_default()
+    }
+    
+    /// Get the total amount of the operational UTXOs
+    pub fn operational_balance(&self) -> u64 {
+        self.operational_utxos().iter().fold(0u64, |total, utxo| total.saturating_add(utxo.amount))
+    }
+    
+    /// Replace the operational UTXOs, e.g. with those persisted before a restart
+    pub fn restore_operational_utxos(&self, utxos: Vec<OperationalUtxo>) {
+        if let Ok(mut operational) = self.operational_utxos.lock() {
+            *operational = utxos.into_iter()
+                .map(|utxo| (utxo.reference.to_string(), utxo))
+                .collect();
+        }
+    }
+    
+    /// Release UTXOs reserved by `build_transaction`
+    pub fn release_utxos(&self, references: &[String]) {
+        if let Ok(mut reserved) = self.reserved_utxos.lock() {
+            for reference in references {
+                reserved.remove(reference);
+            }
+        }
+    }
+    
+    /// Create and sign a transaction spending the selected inputs, returning it with the output index of its change
+    /// 
+    /// Change folded into a payment to the sender has no output of its own,
+    /// so no index is returned for it.
+    fn sign_transaction(
+        &self,
+        from_address: &str,
+        recipients: &[(String, u64)],
+        selected_utxos: &[Utxo],
+        change: u64,
+    ) -> Result<(Vec<u8>, Option<u32>), ContractError> {
         // Create raw transaction inputs
         let mut inputs = Vec::new();
-        for utxo in &selected_utxos {
+        for utxo in selected_utxos {
             let txid = Txid::from_str(&utxo.txid)
                 .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
             
-            inputs.push(bitcoincore_rpc::json::CreateRawTransactionInput {
-                txid,
-                vout: utxo.vout,
-                sequence: None,
-            });
+            inputs.push(serde_json::json!({ "txid": txid.to_string(), "vout": utxo.vout }));
         }
         
-        // Create outputs
-        let mut outputs = HashMap::new();
-        
-        // Main output - use debug formatting for address
-        outputs.insert(
-            format!("{:?}", to_addr),
-            Amount::from_sat(amount),
-        );
+        // Payment outputs, keyed by the plain address string the node expects
+        let mut outputs: Vec<(String, u64)> = Vec::with_capacity(recipients.len() + 1);
+        for (to_address, amount) in recipients {
+            let to_addr = Address::from_str(to_address)
+                .map_err(|_| ContractError::InvalidAddress)?;
+            
+            outputs.push((to_addr.assume_checked().to_string(), *amount));
+        }
         
-        // Change output if needed
+        // Change output last if needed, folded into a payment to the same address
+        let mut change_vout = None;
         if change > 0 {
             let from_addr = Address::from_str(from_address)
-                .map_err(|_| ContractError::InvalidAddress)?;
+                .map_err(|_| ContractError::InvalidAddress)?
+                .assume_checked()
+                .to_string();
             
-            outputs.insert(
-                format!("{:?}", from_addr),
-                Amount::from_sat(change),
-            );
+            match outputs.iter_mut().find(|(address, _)| *address == from_addr) {
+                Some((_, amount)) => *amount = amount.checked_add(change).ok_or(ContractError::ArithmeticError)?,
+                None => {
+                    change_vout = Some(outputs.len() as u32);
+                    outputs.push((from_addr, change));
+                },
+            }
         }
         
+        // Outputs as an array of single-entry objects, which the node keeps in order
+        let outputs: Vec<serde_json::Value> = outputs.into_iter()
+            .map(|(address, amount)| serde_json::json!({ address: Amount::from_sat(amount).to_btc() }))
+            .collect();
+        
         // Create raw transaction
-        let raw_tx = self.client.create_raw_transaction(&inputs, &outputs, None, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to create raw transaction: {}", e)))?;
+        let raw_tx: String = self.client.call("createrawtransaction", &[serde_json::json!(inputs), serde_json::json!(outputs)])
+            .map_err(|e| ContractError::RpcError { operation: "createrawtransaction", source: e })?;
         
         // Sign transaction
-        let signed_tx = self.client.sign_raw_transaction_with_wallet(&raw_tx, None, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to sign transaction: {}", e)))?;
+        let signed_tx = self.client.sign_raw_transaction_with_wallet(raw_tx.as_str(), None, None)
+            .map_err(|e| ContractError::RpcError { operation: "signrawtransactionwithwallet", source: e })?;
         
         if !signed_tx.complete {
-            return Err(ContractError::BitcoinTestnetError("Transaction signing incomplete".to_string()));
+            return Err(ContractError::TransferFailed {
+                stage: TransferStage::Signing,
+                reason: "Transaction signing incomplete".to_string(),
+            });
         }
         
-        // Send transaction
-        let txid = self.client.send_raw_transaction(&signed_tx.hex)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to send transaction: {}", e)))?;
-        
-        Ok(txid.to_string())
+        Ok((signed_tx.hex, change_vout))
     }
     
     /// Get transaction details
@@ -263,20 +1098,101 @@ impl BitcoinRpcClient {
             .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
         
         let _tx = self.client.get_transaction(&tx_id, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get transaction: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "gettransaction", source: e })?;
         
         let raw_tx = self.client.get_raw_transaction(&tx_id, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get raw transaction: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "getrawtransaction", source: e })?;
         
         Ok(raw_tx)
     }
     
+    /// Get the addresses paid by the outputs of a transaction, in output order
+    /// 
+    /// Outputs whose script has no address form, such as `OP_RETURN`, are skipped.
+    pub fn get_output_addresses(&self, txid: &str) -> Result<Vec<String>, ContractError> {
+        let tx = self.get_raw_transaction(txid)?;
+        
+        Ok(tx.output.iter()
+            .filter_map(|output| Address::from_script(&output.script_pubkey, Network::Testnet).ok())
+            .map(|address| address.to_string())
+            .collect())
+    }
+    
+    /// Get the addresses that funded the inputs of a transaction, best effort
+    /// 
+    /// Looks up the output spent by each input. Inputs whose previous
+    /// transaction the node cannot serve (no `txindex`) are skipped.
+    pub fn get_input_addresses(&self, txid: &str) -> Result<Vec<String>, ContractError> {
+        let tx = self.get_raw_transaction(txid)?;
+        let mut addresses: Vec<String> = Vec::new();
+        
+        for input in &tx.input {
+            let previous = match self.get_raw_transaction(&input.previous_output.txid.to_string()) {
+                Ok(previous) => previous,
+                Err(_) => continue,
+            };
+            
+            let address = previous.output.get(input.previous_output.vout as usize)
+                .and_then(|output| Address::from_script(&output.script_pubkey, Network::Testnet).ok())
+                .map(|address| address.to_string());
+            
+            if let Some(address) = address {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+        
+        Ok(addresses)
+    }
+    
+    /// Get a raw transaction, which need not belong to the wallet
+    fn get_raw_transaction(&self, txid: &str) -> Result<Transaction, ContractError> {
+        self.rate_limit()?;
+        
+        let tx_id = Txid::from_str(txid)
+            .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
+        
+        self.client.get_raw_transaction(&tx_id, None)
+            .map_err(|e| ContractError::RpcError { operation: "getrawtransaction", source: e })
+    }
+    
+    /// Check whether an output (`txid:vout`) is still unspent
+    pub fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, ContractError> {
+        let invalid_reference = || ContractError::BitcoinTestnetError(format!("Invalid UTXO reference: {}", utxo_reference));
+        
+        let (txid, vout) = utxo_reference.split_once(':').ok_or_else(invalid_reference)?;
+        let vout = vout.parse::<u32>().map_err(|_| invalid_reference())?;
+        
+        Ok(self.get_tx_out(txid, vout)?.is_some())
+    }
+    
+    /// Get an unspent output, `None` if it is spent or does not exist
+    /// 
+    /// Outputs spent by unconfirmed transactions count as spent. Without a
+    /// transaction index the node cannot tell a spent output from one that
+    /// never existed, e.g. because its transaction was reorganized out.
+    pub fn get_tx_out(&self, txid: &str, vout: u32) -> Result<Option<UnspentOutput>, ContractError> {
+        self.rate_limit()?;
+        
+        let tx_id = Txid::from_str(txid)
+            .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
+        
+        let tx_out = self.client.get_tx_out(&tx_id, vout, Some(true))
+            .map_err(|e| ContractError::RpcError { operation: "gettxout", source: e })?;
+        
+        Ok(tx_out.map(|tx_out| UnspentOutput {
+            value: tx_out.value.to_sat(),
+            confirmations: tx_out.confirmations,
+        }))
+    }
+    
     /// Get mempool transactions
     pub fn get_mempool_transactions(&self) -> Result<Vec<String>, ContractError> {
         self.rate_limit()?;
         
         let txids = self.client.get_raw_mempool()
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get mempool: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "getrawmempool", source: e })?;
         
         Ok(txids.iter().map(|txid| txid.to_string()).collect())
     }
@@ -289,7 +1205,7 @@ impl BitcoinRpcClient {
             .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
         
         let mempool = self.client.get_raw_mempool()
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get mempool: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "getrawmempool", source: e })?;
         
         Ok(mempool.contains(&tx_id))
     }
@@ -302,9 +1218,82 @@ impl BitcoinRpcClient {
             .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
         
         let tx = self.client.get_transaction(&tx_id, None)
-            .map_err(|e| ContractError::BitcoinTestnetError(format!("Failed to get transaction: {}", e)))?;
+            .map_err(|e| ContractError::RpcError { operation: "gettransaction", source: e })?;
+        
+        // Conflicted transactions report negative confirmations
+        let confirmations = tx.info.confirmations.max(0) as u32;
+        
+        if confirmations > 0 {
+            if let Ok(mut broadcasts) = self.unconfirmed_broadcasts.lock() {
+                broadcasts.retain(|(tracked, _)| tracked != txid);
+            }
+        }
+        
+        Ok(confirmations)
+    }
+    
+    /// Get the current chain tip, caching it to anchor events
+    pub fn get_chain_tip(&self) -> Result<ChainTip, ContractError> {
+        self.rate_limit()?;
+        
+        // Called directly, since the library would also ask for the node version
+        let info: serde_json::Value = self.client.call("getblockchaininfo", &[])
+            .map_err(|e| ContractError::RpcError { operation: "getblockchaininfo", source: e })?;
+        
+        let (Some(height), Some(hash)) = (info["blocks"].as_u64(), info["bestblockhash"].as_str()) else {
+            return Err(ContractError::BitcoinTestnetError("Incomplete getblockchaininfo result".to_string()));
+        };
+        let tip = ChainTip {
+            height,
+            hash: hash.to_string(),
+        };
+        
+        let median_time = info["mediantime"].as_i64().and_then(|time| DateTime::from_timestamp(time, 0));
+        if let (Ok(mut cached), Some(median_time)) = (self.chain_tip.lock(), median_time) {
+            *cached = Some((ChainAnchor {
+                block_height: tip.height,
+                block_hash: tip.hash.clone(),
+                median_time,
+            }, Instant::now()));
+        }
+        
+        Ok(tip)
+    }
+    
+    /// Chain tip of the last tip lookup, `None` if there was none in the last few minutes
+    /// 
+    /// Never calls the node.
+    pub fn cached_chain_anchor(&self) -> Option<ChainAnchor> {
+        let cached = self.chain_tip.lock().ok()?;
+        cached.as_ref()
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < CHAIN_TIP_MAX_AGE)
+            .map(|(anchor, _)| anchor.clone())
+    }
+    
+    /// Get the hash of the main chain block at a height
+    pub fn get_block_hash(&self, height: u64) -> Result<String, ContractError> {
+        self.rate_limit()?;
+        
+        let hash = self.client.get_block_hash(height)
+            .map_err(|e| ContractError::RpcError { operation: "getblockhash", source: e })?;
+        
+        Ok(hash.to_string())
+    }
+    
+    /// Get the confirmations of a wallet transaction and the block containing it
+    pub fn get_transaction_status(&self, txid: &str) -> Result<TransactionStatus, ContractError> {
+        self.rate_limit()?;
+        
+        let tx_id = Txid::from_str(txid)
+            .map_err(|_| ContractError::InvalidBitcoinTransaction)?;
+        
+        let tx = self.client.get_transaction(&tx_id, None)
+            .map_err(|e| ContractError::RpcError { operation: "gettransaction", source: e })?;
         
-        Ok(tx.info.confirmations as u32)
+        Ok(TransactionStatus {
+            confirmations: tx.info.confirmations.max(0) as u32,
+            block_hash: tx.info.blockhash.map(|hash| hash.to_string()),
+        })
     }
     
     /// Create a multi-signature address
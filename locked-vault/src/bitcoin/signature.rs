@@ -1,7 +1,14 @@
-use bitcoincore_rpc::bitcoin::secp256k1::{Secp256k1, SecretKey, PublicKey, Message};
+use bitcoincore_rpc::bitcoin::secp256k1::{Secp256k1, SecretKey, PublicKey, Message, Parity, Scalar, XOnlyPublicKey};
+use bitcoincore_rpc::bitcoin::secp256k1::constants::CURVE_ORDER;
 use bitcoincore_rpc::bitcoin::secp256k1::ecdsa::Signature;
+use bitcoincore_rpc::bitcoin::secp256k1::schnorr;
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoincore_rpc::bitcoin::taproot::{TapNodeHash, TapTweakHash};
 use bitcoincore_rpc::bitcoin::{Address, Network};
+use rand::RngCore;
+use std::fmt;
 use std::str::FromStr;
+use zeroize::Zeroizing;
 
 use crate::errors::ContractError;
 
@@ -116,4 +123,638 @@ impl SignatureVerifier {
         
         Ok(format!("{:?}", address))
     }
-}
\ No newline at end of file
+    
+    /// Get the taproot (P2TR) address of a public key, for key-path spending
+    /// 
+    /// The key is used as the internal key with no script tree, as in BIP 86.
+    pub fn get_taproot_address_from_public_key(&self, public_key: &[u8]) -> Result<String, ContractError> {
+        // Parse public key
+        let pk = PublicKey::from_slice(public_key)
+            .map_err(|e| ContractError::BitcoinTestnetError(format!("Invalid public key: {}", e)))?;
+        
+        // Taproot commits to the x-only key
+        let (internal_key, _parity) = pk.x_only_public_key();
+        
+        // Create address
+        let address = Address::p2tr(&self.secp, internal_key, None, self.network);
+        
+        Ok(format!("{:?}", address))
+    }
+}
+
+/// Signs 32-byte message digests with a key held by the vault
+pub trait Signer: fmt::Debug + Send + Sync {
+    /// Get the compressed public key of the signing key
+    fn public_key(&self) -> Vec<u8>;
+    
+    /// Sign a 32-byte digest, returning a compact ECDSA signature
+    fn sign(&self, digest: &[u8]) -> Result<Vec<u8>, ContractError>;
+}
+
+/// Signer backed by an in-memory private key
+pub struct KeySigner {
+    /// Signature verifier used for signing
+    verifier: SignatureVerifier,
+    /// Private key bytes
+    private_key: Vec<u8>,
+    /// Compressed public key bytes
+    public_key: Vec<u8>,
+}
+
+impl KeySigner {
+    /// Create a signer from raw private key bytes
+    pub fn new(private_key: &[u8]) -> Result<Self, ContractError> {
+        let verifier = SignatureVerifier::new(Network::Testnet);
+        let public_key = verifier.derive_public_key(private_key)?;
+        
+        Ok(Self {
+            verifier,
+            private_key: private_key.to_vec(),
+            public_key,
+        })
+    }
+    
+    /// Create a signer with a fresh random key
+    pub fn generate() -> Self {
+        let verifier = SignatureVerifier::new(Network::Testnet);
+        let secret_key = SecretKey::new(&mut rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(&verifier.secp, &secret_key).serialize().to_vec();
+        
+        Self {
+            verifier,
+            private_key: secret_key.secret_bytes().to_vec(),
+            public_key,
+        }
+    }
+    
+    /// Create a signer from a hex-encoded private key
+    pub fn from_hex(private_key: &str) -> Result<Self, ContractError> {
+        let private_key = hex::decode(private_key)
+            .map_err(|e| ContractError::InitializationError(format!("Invalid private key hex: {}", e)))?;
+        
+        Self::new(&private_key)
+    }
+}
+
+impl fmt::Debug for KeySigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the private key
+        f.debug_struct("KeySigner")
+            .field("public_key", &hex::encode(&self.public_key))
+            .finish()
+    }
+}
+
+impl Signer for KeySigner {
+    fn public_key(&self) -> Vec<u8> {
+        self.public_key.clone()
+    }
+    
+    fn sign(&self, digest: &[u8]) -> Result<Vec<u8>, ContractError> {
+        self.verifier.sign(digest, &self.private_key)
+    }
+}
+
+// MuSig2 two-party signing, following BIP 327
+//
+// secp256k1 0.27 has no MuSig module, so key aggregation, nonces and partial
+// signatures are built here from its scalar and point operations. Scalars
+// modulo the curve order are held as `Option<SecretKey>`, `None` being zero,
+// because `SecretKey` is the only type with modular arithmetic.
+
+/// Scalar modulo the curve order, `None` being zero
+type OrderScalar = Option<SecretKey>;
+
+/// Add two scalars
+fn scalar_add(a: OrderScalar, b: OrderScalar) -> OrderScalar {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        // Only fails when the sum is zero
+        (Some(a), Some(b)) => a.add_tweak(&Scalar::from(b)).ok(),
+    }
+}
+
+/// Multiply two scalars
+fn scalar_mul(a: OrderScalar, b: OrderScalar) -> OrderScalar {
+    match (a, b) {
+        (Some(a), Some(b)) => a.mul_tweak(&Scalar::from(b)).ok(),
+        _ => None,
+    }
+}
+
+/// Negate a scalar when `negate` is set
+fn scalar_negate_if(a: OrderScalar, negate: bool) -> OrderScalar {
+    if negate { a.map(SecretKey::negate) } else { a }
+}
+
+/// The scalar one
+fn scalar_one() -> OrderScalar {
+    SecretKey::from_slice(&Scalar::ONE.to_be_bytes()).ok()
+}
+
+/// Reduce a 32-byte big-endian integer, such as a hash, modulo the curve order
+fn scalar_reduce(mut bytes: [u8; 32]) -> OrderScalar {
+    // Below 2^256 one subtraction of the order is always enough
+    if bytes >= CURVE_ORDER {
+        let mut borrow = 0;
+        for index in (0..32).rev() {
+            let difference = bytes[index] as i16 - CURVE_ORDER[index] as i16 - borrow;
+            bytes[index] = difference.rem_euclid(256) as u8;
+            borrow = (difference < 0) as i16;
+        }
+    }
+    SecretKey::from_slice(&bytes).ok()
+}
+
+/// Parse a 32-byte scalar that has to be below the curve order
+fn scalar_parse(bytes: &[u8; 32]) -> Option<OrderScalar> {
+    if *bytes >= CURVE_ORDER {
+        return None;
+    }
+    Some(SecretKey::from_slice(bytes).ok())
+}
+
+/// Encode a scalar as 32 big-endian bytes
+fn scalar_bytes(a: &OrderScalar) -> [u8; 32] {
+    a.map(|a| a.secret_bytes()).unwrap_or([0; 32])
+}
+
+/// Multiply a point by a scalar, `None` being the point at infinity
+fn point_mul<C: bitcoincore_rpc::bitcoin::secp256k1::Verification>(
+    secp: &Secp256k1<C>,
+    point: Option<PublicKey>,
+    scalar: OrderScalar,
+) -> Option<PublicKey> {
+    match (point, scalar) {
+        (Some(point), Some(scalar)) => point.mul_tweak(secp, &Scalar::from(scalar)).ok(),
+        _ => None,
+    }
+}
+
+/// Add two points, `None` being the point at infinity
+fn point_add(a: Option<PublicKey>, b: Option<PublicKey>) -> Option<PublicKey> {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        // Only fails when the sum is the point at infinity
+        (Some(a), Some(b)) => a.combine(&b).ok(),
+    }
+}
+
+/// Negate a point when `negate` is set
+fn point_negate_if<C: bitcoincore_rpc::bitcoin::secp256k1::Verification>(
+    secp: &Secp256k1<C>,
+    point: Option<PublicKey>,
+    negate: bool,
+) -> Option<PublicKey> {
+    if negate { point.map(|point| point.negate(secp)) } else { point }
+}
+
+/// Multiply the generator by a scalar
+fn generator_mul<C: bitcoincore_rpc::bitcoin::secp256k1::Signing>(secp: &Secp256k1<C>, scalar: OrderScalar) -> Option<PublicKey> {
+    scalar.map(|scalar| PublicKey::from_secret_key(secp, &scalar))
+}
+
+/// Whether a point's y coordinate is even
+fn has_even_y(point: &PublicKey) -> bool {
+    point.x_only_public_key().1 == Parity::Even
+}
+
+/// BIP 340 tagged hash of the concatenated parts
+fn tagged_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag_hash.as_ref());
+    engine.input(tag_hash.as_ref());
+    for part in parts {
+        engine.input(part);
+    }
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Error for a failed MuSig2 step
+fn musig_error(detail: &str) -> ContractError {
+    ContractError::BitcoinTestnetError(format!("MuSig2: {}", detail))
+}
+
+/// Aggregated public key of MuSig2 signers, with any tweaks applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusigKeyAgg {
+    /// Signer keys, in aggregation order
+    public_keys: Vec<PublicKey>,
+    /// Hash of the key list
+    list_hash: [u8; 32],
+    /// First key differing from the first one, whose coefficient is one
+    second_key: Option<PublicKey>,
+    /// Aggregated key, tweaks included
+    aggregate: PublicKey,
+    /// Whether the accumulated sign of the tweaks is negative
+    negated: bool,
+    /// Accumulated tweak
+    tweak: OrderScalar,
+}
+
+impl MusigKeyAgg {
+    /// Aggregate the keys of the signers, in the order given
+    pub fn new(public_keys: &[PublicKey]) -> Result<Self, ContractError> {
+        let first = public_keys.first().ok_or_else(|| musig_error("no keys to aggregate"))?;
+        let serialized: Vec<[u8; 33]> = public_keys.iter().map(PublicKey::serialize).collect();
+        let parts: Vec<&[u8]> = serialized.iter().map(|key| key.as_slice()).collect();
+        
+        let mut key_agg = Self {
+            public_keys: public_keys.to_vec(),
+            list_hash: tagged_hash("KeyAgg list", &parts),
+            second_key: public_keys.iter().find(|key| *key != first).copied(),
+            aggregate: *first,
+            negated: false,
+            tweak: None,
+        };
+        
+        let secp = Secp256k1::verification_only();
+        let aggregate = public_keys.iter().fold(None, |sum, key| {
+            point_add(sum, point_mul(&secp, Some(*key), key_agg.coefficient(key)))
+        });
+        key_agg.aggregate = aggregate.ok_or_else(|| musig_error("keys aggregate to infinity"))?;
+        
+        Ok(key_agg)
+    }
+    
+    /// Apply the BIP 341 taproot tweak, so the key signs for a P2TR output
+    /// 
+    /// The untweaked x-only key is the output's internal key and
+    /// `merkle_root` the root of its script tree, if any.
+    pub fn with_taproot_tweak(&self, merkle_root: Option<TapNodeHash>) -> Result<Self, ContractError> {
+        let tweak_hash = TapTweakHash::from_key_and_tweak(self.x_only_key(), merkle_root).to_byte_array();
+        self.with_tweak(&tweak_hash, true)
+    }
+    
+    /// Apply a BIP 327 tweak, x-only or plain
+    /// 
+    /// An x-only tweak applies to the even-y lift of the key, a plain one
+    /// to the key as it is.
+    pub fn with_tweak(&self, tweak: &[u8; 32], x_only: bool) -> Result<Self, ContractError> {
+        let tweak = scalar_parse(tweak).ok_or_else(|| musig_error("tweak out of range"))?;
+        
+        let secp = Secp256k1::new();
+        let flip = x_only && !has_even_y(&self.aggregate);
+        let aggregate = point_add(point_negate_if(&secp, Some(self.aggregate), flip), generator_mul(&secp, tweak))
+            .ok_or_else(|| musig_error("tweaked key is infinity"))?;
+        
+        Ok(Self {
+            aggregate,
+            negated: self.negated ^ flip,
+            tweak: scalar_add(tweak, scalar_negate_if(self.tweak, flip)),
+            ..self.clone()
+        })
+    }
+    
+    /// Signer keys, in aggregation order
+    pub fn public_keys(&self) -> &[PublicKey] {
+        &self.public_keys
+    }
+    
+    /// Aggregated key, tweaks included
+    pub fn aggregate_key(&self) -> PublicKey {
+        self.aggregate
+    }
+    
+    /// X-only aggregated key, as committed to by taproot and BIP 340
+    pub fn x_only_key(&self) -> XOnlyPublicKey {
+        self.aggregate.x_only_public_key().0
+    }
+    
+    /// Aggregation coefficient of a signer's key
+    fn coefficient(&self, public_key: &PublicKey) -> OrderScalar {
+        if Some(*public_key) == self.second_key {
+            return scalar_one();
+        }
+        scalar_reduce(tagged_hash("KeyAgg coefficient", &[&self.list_hash, &public_key.serialize()]))
+    }
+}
+
+/// Secret half of a signer's MuSig2 nonce pair
+/// 
+/// Signing takes it by value: signing two messages with one nonce reveals
+/// the secret key. It is only serialized so a signing session can resume
+/// after a restart, and whoever persists it must keep it encrypted and make
+/// sure it is used for one partial signature at most. Its memory is wiped
+/// when it is dropped.
+pub struct MusigSecretNonce {
+    /// First secret nonce
+    k1: SecretKey,
+    /// Second secret nonce
+    k2: SecretKey,
+    /// Key of the signer the nonce was generated for
+    public_key: PublicKey,
+}
+
+impl MusigSecretNonce {
+    /// Length of the serialized nonce
+    pub const LEN: usize = 97;
+    
+    /// Generate a nonce pair for signing `message` under `key_agg`
+    /// 
+    /// Fresh randomness is mixed with the secret key, the aggregated key and
+    /// the message, so a weak random source alone does not repeat a nonce.
+    pub fn generate(
+        secret_key: &SecretKey,
+        key_agg: &MusigKeyAgg,
+        message: &[u8; 32],
+    ) -> Result<(Self, MusigPublicNonce), ContractError> {
+        let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), secret_key);
+        if !key_agg.public_keys().contains(&public_key) {
+            return Err(musig_error("signing key is not one of the aggregated keys"));
+        }
+        
+        let mut random = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut random);
+        Self::derive(&random, Some(secret_key), &public_key, Some(&key_agg.x_only_key()), Some(message), &[])
+    }
+    
+    /// BIP 327 NonceGen from the randomness given
+    /// 
+    /// Every input but `random` and `public_key` is optional to the BIP;
+    /// `generate` passes them all.
+    pub(crate) fn derive(
+        random: &[u8; 32],
+        secret_key: Option<&SecretKey>,
+        public_key: &PublicKey,
+        aggregate_key: Option<&XOnlyPublicKey>,
+        message: Option<&[u8]>,
+        extra_input: &[u8],
+    ) -> Result<(Self, MusigPublicNonce), ContractError> {
+        // Without a secret key the randomness is used as it is
+        let mut seed = *random;
+        if let Some(secret_key) = secret_key {
+            seed = secret_key.secret_bytes();
+            for (byte, mask) in seed.iter_mut().zip(tagged_hash("MuSig/aux", &[random]).iter()) {
+                *byte ^= mask;
+            }
+        }
+        
+        let serialized_key = public_key.serialize();
+        let aggregate = aggregate_key.map(XOnlyPublicKey::serialize);
+        let aggregate: &[u8] = aggregate.as_ref().map_or(&[], |key| key.as_slice());
+        // A missing message differs from an empty one
+        let message_prefix = match message {
+            Some(message) => [&[1u8][..], &(message.len() as u64).to_be_bytes()].concat(),
+            None => vec![0],
+        };
+        let message = message.unwrap_or(&[]);
+        let extra_length = (extra_input.len() as u32).to_be_bytes();
+        let derive = |index: u8| {
+            scalar_reduce(tagged_hash("MuSig/nonce", &[
+                &seed,
+                &[serialized_key.len() as u8], &serialized_key,
+                &[aggregate.len() as u8], aggregate,
+                &message_prefix, message,
+                &extra_length, extra_input,
+                &[index],
+            ]))
+        };
+        
+        let secp = Secp256k1::signing_only();
+        let k1 = derive(0).ok_or_else(|| musig_error("nonce derivation produced zero"))?;
+        let k2 = derive(1).ok_or_else(|| musig_error("nonce derivation produced zero"))?;
+        let public_nonce = MusigPublicNonce {
+            r1: PublicKey::from_secret_key(&secp, &k1),
+            r2: PublicKey::from_secret_key(&secp, &k2),
+        };
+        
+        Ok((Self { k1, k2, public_key: *public_key }, public_nonce))
+    }
+    
+    /// Serialize the nonce, to persist it until it is used
+    pub fn serialize(&self) -> Zeroizing<[u8; Self::LEN]> {
+        let mut bytes = Zeroizing::new([0u8; Self::LEN]);
+        bytes[..32].copy_from_slice(&self.k1.secret_bytes());
+        bytes[32..64].copy_from_slice(&self.k2.secret_bytes());
+        bytes[64..].copy_from_slice(&self.public_key.serialize());
+        bytes
+    }
+    
+    /// Parse a serialized nonce
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ContractError> {
+        if bytes.len() != Self::LEN {
+            return Err(musig_error("secret nonce must be 97 bytes"));
+        }
+        
+        let invalid = |_| musig_error("invalid secret nonce");
+        Ok(Self {
+            k1: SecretKey::from_slice(&bytes[..32]).map_err(invalid)?,
+            k2: SecretKey::from_slice(&bytes[32..64]).map_err(invalid)?,
+            public_key: PublicKey::from_slice(&bytes[64..]).map_err(invalid)?,
+        })
+    }
+}
+
+impl Drop for MusigSecretNonce {
+    fn drop(&mut self) {
+        self.k1.non_secure_erase();
+        self.k2.non_secure_erase();
+    }
+}
+
+impl fmt::Debug for MusigSecretNonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the nonces
+        f.debug_struct("MusigSecretNonce")
+            .field("public_key", &self.public_key)
+            .finish()
+    }
+}
+
+/// Public half of a signer's MuSig2 nonce pair, sent to the other signers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusigPublicNonce {
+    /// First public nonce
+    r1: PublicKey,
+    /// Second public nonce
+    r2: PublicKey,
+}
+
+impl MusigPublicNonce {
+    /// Length of the serialized nonce
+    pub const LEN: usize = 66;
+    
+    /// Serialize the nonce as two compressed points
+    pub fn serialize(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0u8; Self::LEN];
+        bytes[..33].copy_from_slice(&self.r1.serialize());
+        bytes[33..].copy_from_slice(&self.r2.serialize());
+        bytes
+    }
+    
+    /// Parse a serialized nonce
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ContractError> {
+        if bytes.len() != Self::LEN {
+            return Err(musig_error("public nonce must be 66 bytes"));
+        }
+        
+        let invalid = |_| musig_error("invalid public nonce");
+        Ok(Self {
+            r1: PublicKey::from_slice(&bytes[..33]).map_err(invalid)?,
+            r2: PublicKey::from_slice(&bytes[33..]).map_err(invalid)?,
+        })
+    }
+}
+
+/// Sum of the public nonces of all signers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MusigAggNonce {
+    /// Sum of the first nonces, `None` being infinity
+    r1: Option<PublicKey>,
+    /// Sum of the second nonces, `None` being infinity
+    r2: Option<PublicKey>,
+}
+
+impl MusigAggNonce {
+    /// Aggregate the public nonces of all signers
+    pub fn new(nonces: &[MusigPublicNonce]) -> Self {
+        Self {
+            r1: nonces.iter().fold(None, |sum, nonce| point_add(sum, Some(nonce.r1))),
+            r2: nonces.iter().fold(None, |sum, nonce| point_add(sum, Some(nonce.r2))),
+        }
+    }
+    
+    /// Serialize the nonce, infinity as 33 zero bytes
+    pub fn serialize(&self) -> [u8; 66] {
+        let mut bytes = [0u8; 66];
+        if let Some(r1) = self.r1 {
+            bytes[..33].copy_from_slice(&r1.serialize());
+        }
+        if let Some(r2) = self.r2 {
+            bytes[33..].copy_from_slice(&r2.serialize());
+        }
+        bytes
+    }
+    
+    /// Parse a serialized nonce, 33 zero bytes being infinity
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, ContractError> {
+        if bytes.len() != 66 {
+            return Err(musig_error("aggregate nonce must be 66 bytes"));
+        }
+        
+        let parse = |point: &[u8]| {
+            if point.iter().all(|byte| *byte == 0) {
+                return Ok(None);
+            }
+            PublicKey::from_slice(point).map(Some).map_err(|_| musig_error("invalid aggregate nonce"))
+        };
+        Ok(Self {
+            r1: parse(&bytes[..33])?,
+            r2: parse(&bytes[33..])?,
+        })
+    }
+}
+
+/// Values every signer derives for one message
+struct MusigSessionValues {
+    /// Coefficient of the second nonces
+    b: OrderScalar,
+    /// Final nonce point
+    r: PublicKey,
+    /// Schnorr challenge
+    e: OrderScalar,
+}
+
+impl MusigSessionValues {
+    /// Derive the values of signing `message` with `aggregate_nonce`
+    fn new(key_agg: &MusigKeyAgg, aggregate_nonce: &MusigAggNonce, message: &[u8; 32]) -> Result<Self, ContractError> {
+        let secp = Secp256k1::new();
+        let aggregate = key_agg.x_only_key().serialize();
+        let b = scalar_reduce(tagged_hash("MuSig/noncecoef", &[&aggregate_nonce.serialize(), &aggregate, message]));
+        
+        // A final nonce at infinity is replaced by the generator
+        let r = point_add(aggregate_nonce.r1, point_mul(&secp, aggregate_nonce.r2, b))
+            .or_else(|| generator_mul(&secp, scalar_one()))
+            .ok_or_else(|| musig_error("no nonce point"))?;
+        let e = scalar_reduce(tagged_hash("BIP0340/challenge", &[&r.x_only_public_key().0.serialize(), &aggregate, message]));
+        
+        Ok(Self { b, r, e })
+    }
+}
+
+/// Create a signer's partial signature of `message`, using up its nonce
+pub fn musig_partial_sign(
+    secret_nonce: MusigSecretNonce,
+    secret_key: &SecretKey,
+    key_agg: &MusigKeyAgg,
+    aggregate_nonce: &MusigAggNonce,
+    message: &[u8; 32],
+) -> Result<[u8; 32], ContractError> {
+    let secp = Secp256k1::new();
+    let public_key = PublicKey::from_secret_key(&secp, secret_key);
+    if public_key != secret_nonce.public_key {
+        return Err(musig_error("nonce was generated for another key"));
+    }
+    if !key_agg.public_keys().contains(&public_key) {
+        return Err(musig_error("signing key is not one of the aggregated keys"));
+    }
+    
+    let values = MusigSessionValues::new(key_agg, aggregate_nonce, message)?;
+    let negate_nonce = !has_even_y(&values.r);
+    let k1 = scalar_negate_if(Some(secret_nonce.k1), negate_nonce);
+    let k2 = scalar_negate_if(Some(secret_nonce.k2), negate_nonce);
+    let d = scalar_negate_if(Some(*secret_key), !has_even_y(&key_agg.aggregate) ^ key_agg.negated);
+    
+    // s = k1 + b*k2 + e*a*d
+    let challenge = scalar_mul(scalar_mul(values.e, key_agg.coefficient(&public_key)), d);
+    let s = scalar_add(scalar_add(k1, scalar_mul(values.b, k2)), challenge);
+    
+    Ok(scalar_bytes(&s))
+}
+
+/// Check a signer's partial signature against its public nonce and key
+pub fn musig_partial_verify(
+    partial_signature: &[u8; 32],
+    public_nonce: &MusigPublicNonce,
+    public_key: &PublicKey,
+    key_agg: &MusigKeyAgg,
+    aggregate_nonce: &MusigAggNonce,
+    message: &[u8; 32],
+) -> bool {
+    let (s, values) = match (scalar_parse(partial_signature), MusigSessionValues::new(key_agg, aggregate_nonce, message)) {
+        (Some(s), Ok(values)) => (s, values),
+        _ => return false,
+    };
+    if !key_agg.public_keys().contains(public_key) {
+        return false;
+    }
+    
+    // s*G == R1 + b*R2 + e*a*P, with the signs the signer applied
+    let secp = Secp256k1::new();
+    let nonce = point_add(Some(public_nonce.r1), point_mul(&secp, Some(public_nonce.r2), values.b));
+    let nonce = point_negate_if(&secp, nonce, !has_even_y(&values.r));
+    let key = point_negate_if(&secp, Some(*public_key), !has_even_y(&key_agg.aggregate) ^ key_agg.negated);
+    let expected = point_add(nonce, point_mul(&secp, key, scalar_mul(values.e, key_agg.coefficient(public_key))));
+    
+    generator_mul(&secp, s) == expected
+}
+
+/// Combine the partial signatures of all signers into a BIP 340 signature
+/// 
+/// The signature verifies against `key_agg.x_only_key()`.
+pub fn musig_aggregate(
+    partial_signatures: &[[u8; 32]],
+    key_agg: &MusigKeyAgg,
+    aggregate_nonce: &MusigAggNonce,
+    message: &[u8; 32],
+) -> Result<schnorr::Signature, ContractError> {
+    let values = MusigSessionValues::new(key_agg, aggregate_nonce, message)?;
+    
+    let mut s = None;
+    for partial in partial_signatures {
+        s = scalar_add(s, scalar_parse(partial).ok_or_else(|| musig_error("partial signature out of range"))?);
+    }
+    
+    // Add the tweaks, which no signer's partial signature covers
+    let tweak = scalar_negate_if(key_agg.tweak, !has_even_y(&key_agg.aggregate));
+    s = scalar_add(s, scalar_mul(values.e, tweak));
+    
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(&values.r.x_only_public_key().0.serialize());
+    bytes[32..].copy_from_slice(&scalar_bytes(&s));
+    schnorr::Signature::from_slice(&bytes).map_err(|e| musig_error(&e.to_string()))
+}
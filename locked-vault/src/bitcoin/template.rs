@@ -0,0 +1,91 @@
+//! Transaction template validation
+//!
+//! A destination that parses as an address can still pay to a script the
+//! vault should not send funds to. Payout, fee withdrawal and sweep
+//! destinations are checked against the `DestinationPolicy`, an allowlist of
+//! script types (P2PKH, P2SH, P2WPKH, P2WSH and P2TR by default), before
+//! anything is queued. Every transaction `create_and_sign_transaction(_multi)`
+//! signs is checked again, output by output: an output paying to a script
+//! type outside the allowlist, an `OP_RETURN` output or a zero-value output
+//! fails the build before the transaction is returned.
+//!
+//! `DataOutputs::Allow` lets `OP_RETURN` and zero-value outputs through, as a
+//! runestone needs. Only `create_and_sign_transaction_multi` takes it from
+//! its caller; withdrawals, batches and sweeps always refuse data outputs.
+
+use bitcoincore_rpc::bitcoin::Transaction;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::testnet::utils;
+use crate::bitcoin::utxo::ScriptType;
+use crate::errors::ContractError;
+
+/// Script types funds may be paid to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DestinationPolicy {
+    /// Allowed script types; `OP_RETURN` is never allowed as a destination
+    pub allowed_script_types: Vec<ScriptType>,
+}
+
+impl Default for DestinationPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_script_types: vec![
+                ScriptType::P2pkh,
+                ScriptType::P2sh,
+                ScriptType::P2wpkh,
+                ScriptType::P2wsh,
+                ScriptType::P2tr,
+            ],
+        }
+    }
+}
+
+impl DestinationPolicy {
+    /// Check that funds may be paid to a script type
+    pub fn check(&self, script_type: ScriptType) -> Result<(), ContractError> {
+        if script_type == ScriptType::NullData || !self.allowed_script_types.contains(&script_type) {
+            return Err(ContractError::ScriptTypeNotAllowed { script_type });
+        }
+        
+        Ok(())
+    }
+    
+    /// Parse a destination address and check the type of script it pays to
+    pub fn check_address(&self, address: &str) -> Result<ScriptType, ContractError> {
+        let parsed = utils::parse_address(address)?;
+        self.check(parsed.script_type)?;
+        
+        Ok(parsed.script_type)
+    }
+}
+
+/// Whether a transaction may carry `OP_RETURN` and zero-value outputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DataOutputs {
+    /// Fail with `ScriptTypeNotAllowed` or `InvalidAmount`
+    #[default]
+    Refuse,
+    /// Let them through, for a transaction carrying a runestone
+    Allow,
+}
+
+/// Check every output of a signed transaction against the destination policy
+pub fn validate_outputs(transaction: &Transaction, policy: &DestinationPolicy, data_outputs: DataOutputs) -> Result<(), ContractError> {
+    for (vout, output) in transaction.output.iter().enumerate() {
+        let script_type = ScriptType::from_script(&output.script_pubkey);
+        
+        if data_outputs == DataOutputs::Allow && (script_type == ScriptType::NullData || output.value == 0) {
+            continue;
+        }
+        
+        policy.check(script_type)?;
+        
+        if output.value == 0 {
+            return Err(ContractError::InvalidAmount { reason: format!("output {} has zero value", vout) });
+        }
+    }
+    
+    Ok(())
+}
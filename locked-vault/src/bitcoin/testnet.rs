@@ -1,6 +1,17 @@
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::Duration;
 use bitcoincore_rpc::bitcoin::{Address, Network};
 
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::fee_oracle::{ConfirmationTarget, FeeOracleConfig};
+use crate::bitcoin::network::NetworkGuard;
+use crate::bitcoin::feature_gate::FeatureGateConfig;
+use crate::bitcoin::ordinals::OrdinalsApiFlavor;
+use crate::bitcoin::template::DestinationPolicy;
+use crate::bitcoin::utxo::{DustPolicy, SweepInputs};
+use crate::config::Secret;
+
 /// Configuration for Bitcoin testnet
 #[derive(Debug, Clone)]
 pub struct BitcoinTestnetConfig {
@@ -9,15 +20,124 @@ pub struct BitcoinTestnetConfig {
     /// RPC username
     pub rpc_username: String,
     /// RPC password
-    pub rpc_password: String,
+    pub rpc_password: Secret<String>,
     /// Contract wallet address
     pub contract_wallet_address: String,
+    /// Test network the node runs on; every address has to belong to it
+    pub network: Network,
     /// Maximum batch size for transactions
     pub max_batch_size: u32,
+    /// Most batches one call to `process_pending_transactions` sends
+    pub max_batches_per_call: u32,
+    /// Most transactions waiting in the pending queue; further transfers are refused until it drains
+    pub max_pending_transactions: usize,
+    /// Maximum number of batch transactions broadcast at the same time
+    pub broadcast_parallelism: usize,
     /// Rate limit (calls per minute)
     pub rate_limit: u32,
-    /// Minimum confirmations required
-    pub min_confirmations: u32,
+    /// Most UTXOs fetched by one `listunspent` call
+    pub utxo_page_size: usize,
+    /// Confirmations required before incoming funds count as received
+    pub confirmation_policy: ConfirmationPolicy,
+    /// Balance cache settings
+    pub balance_cache: CacheConfig,
+    /// Ordinals inscription cache settings
+    pub inscription_cache: CacheConfig,
+    /// Fee estimate confirmation targets per transfer priority
+    pub fee_targets: FeeTargets,
+    /// Caching and fallbacks of fee estimates
+    pub fee_oracle: FeeOracleConfig,
+    /// When the Lightning, Ordinals and runes backends are cut off after repeated failures
+    pub feature_gates: FeatureGateConfig,
+    /// Handling of dust change and the dust limit of withdrawals
+    pub dust_policy: DustPolicy,
+    /// UTXOs treasury sweeps may spend
+    pub sweep_inputs: SweepInputs,
+    /// Script types payouts, fee withdrawals and sweeps may pay to
+    pub destination_policy: DestinationPolicy,
+    /// Macaroon authenticating to the Lightning node
+    pub lightning_macaroon: Option<Secret<String>>,
+    /// Batching of non-urgent Bitcoin payouts, off if unset
+    pub payout_batch: Option<PayoutBatchConfig>,
+    /// Flavor of the Ordinals API
+    pub ordinals_api_flavor: OrdinalsApiFlavor,
+    /// Decimal places each rune divides into, by rune ID; runes left out divide into all of `AMOUNT_DECIMALS`
+    pub rune_divisibility: HashMap<String, u8>,
+}
+
+/// Blocks of the named confirmation targets fees are estimated for
+/// 
+/// High, normal and low priority transfers are priced for the urgent,
+/// normal and economy targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeTargets {
+    /// Blocks of the urgent target
+    pub urgent: u16,
+    /// Blocks of the normal target
+    pub normal: u16,
+    /// Blocks of the economy target
+    pub economy: u16,
+}
+
+impl FeeTargets {
+    /// Get the blocks a confirmation target resolves to
+    pub fn blocks(&self, target: ConfirmationTarget) -> u16 {
+        match target {
+            ConfirmationTarget::Urgent => self.urgent,
+            ConfirmationTarget::Normal => self.normal,
+            ConfirmationTarget::Economy => self.economy,
+            ConfirmationTarget::Custom(blocks) => blocks,
+        }
+    }
+}
+
+impl Default for FeeTargets {
+    fn default() -> Self {
+        Self {
+            urgent: 1,
+            normal: 6,
+            economy: 144,
+        }
+    }
+}
+
+/// Settings for coalescing non-urgent payouts into one transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayoutBatchConfig {
+    /// How long the first payout of a batch waits for others
+    pub window: Duration,
+    /// Maximum number of payment outputs per transaction
+    pub max_outputs: usize,
+    /// Maximum total paid out per transaction, in satoshis
+    pub max_total: u64,
+}
+
+impl Default for PayoutBatchConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(600),
+            max_outputs: 50,
+            max_total: 100_000_000,
+        }
+    }
+}
+
+/// Settings for a bounded cache, such as the address balance cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// How long a cached entry stays valid
+    pub ttl: Duration,
+    /// Maximum number of cached entries
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            max_entries: 10_000,
+        }
+    }
 }
 
 impl BitcoinTestnetConfig {
@@ -25,20 +145,42 @@ impl BitcoinTestnetConfig {
     pub fn new(
         rpc_url: String,
         rpc_username: String,
-        rpc_password: String,
+        rpc_password: impl Into<Secret<String>>,
         contract_wallet_address: String,
     ) -> Self {
         Self {
             rpc_url,
             rpc_username,
-            rpc_password,
+            rpc_password: rpc_password.into(),
             contract_wallet_address,
+            network: Network::Testnet,
             max_batch_size: 10,
+            max_batches_per_call: 1,
+            max_pending_transactions: 1000,
+            broadcast_parallelism: 4,
             rate_limit: 60,
-            min_confirmations: 1,
+            utxo_page_size: 1000,
+            confirmation_policy: ConfirmationPolicy::default(),
+            balance_cache: CacheConfig::default(),
+            inscription_cache: CacheConfig::default(),
+            fee_targets: FeeTargets::default(),
+            fee_oracle: FeeOracleConfig::default(),
+            feature_gates: FeatureGateConfig::default(),
+            dust_policy: DustPolicy::default(),
+            sweep_inputs: SweepInputs::default(),
+            destination_policy: DestinationPolicy::default(),
+            lightning_macaroon: None,
+            payout_batch: None,
+            ordinals_api_flavor: OrdinalsApiFlavor::default(),
+            rune_divisibility: HashMap::new(),
         }
     }
     
+    /// Guard refusing addresses of other networks than the configured one
+    pub fn network_guard(&self) -> NetworkGuard {
+        NetworkGuard::new(self.network)
+    }
+    
     /// Validate the configuration
     pub fn validate(&self) -> Result<(), String> {
         // Validate RPC URL
@@ -59,24 +201,84 @@ impl BitcoinTestnetConfig {
             return Err("RPC password cannot be empty".to_string());
         }
         
-        // Validate contract wallet address
-        if !utils::validate_testnet_address(&self.contract_wallet_address) {
-            return Err("Invalid testnet address for contract wallet".to_string());
+        if self.lightning_macaroon.as_ref().is_some_and(|macaroon| macaroon.is_empty()) {
+            return Err("Lightning macaroon cannot be empty".to_string());
         }
         
+        // Validate the network and the contract wallet address on it
+        if self.network == Network::Bitcoin {
+            return Err("Bitcoin testnet transfer cannot run on mainnet".to_string());
+        }
+        
+        self.network_guard().check(&self.contract_wallet_address)
+            .map_err(|e| format!("Invalid contract wallet address: {}", e))?;
+        
         // Validate batch size
         if self.max_batch_size == 0 {
             return Err("Maximum batch size cannot be zero".to_string());
         }
         
+        if self.max_batches_per_call == 0 {
+            return Err("Maximum batches per call cannot be zero".to_string());
+        }
+        
+        // Validate the queue bound; a batch has to fit in the queue
+        if self.max_pending_transactions < self.max_batch_size as usize {
+            return Err("Maximum pending transactions cannot be below the maximum batch size".to_string());
+        }
+        
+        // Validate broadcast parallelism
+        if self.broadcast_parallelism == 0 {
+            return Err("Broadcast parallelism cannot be zero".to_string());
+        }
+        
         // Validate rate limit
         if self.rate_limit == 0 {
             return Err("Rate limit cannot be zero".to_string());
         }
         
+        // Validate UTXO paging
+        if self.utxo_page_size == 0 {
+            return Err("UTXO page size cannot be zero".to_string());
+        }
+        
         // Validate confirmations
-        if self.min_confirmations == 0 {
-            return Err("Minimum confirmations cannot be zero".to_string());
+        self.confirmation_policy.validate()
+            .map_err(|e| e.to_string())?;
+        
+        // Validate fee targets
+        let targets = self.fee_targets;
+        if targets.urgent == 0 || targets.normal == 0 || targets.economy == 0 {
+            return Err("Fee confirmation targets cannot be zero".to_string());
+        }
+        
+        // Validate the dust limit
+        if self.dust_policy.dust_limit_sats == 0 {
+            return Err("Dust limit cannot be zero".to_string());
+        }
+        
+        // Validate the feature gates
+        self.feature_gates.validate()?;
+        
+        // Validate payout batching
+        if let Some(payout_batch) = &self.payout_batch {
+            if payout_batch.max_outputs == 0 {
+                return Err("Payout batch output limit cannot be zero".to_string());
+            }
+            
+            if payout_batch.max_total == 0 {
+                return Err("Payout batch total limit cannot be zero".to_string());
+            }
+        }
+        
+        // Validate balance cache
+        if self.balance_cache.max_entries == 0 {
+            return Err("Balance cache size cannot be zero".to_string());
+        }
+        
+        // Validate inscription cache
+        if self.inscription_cache.max_entries == 0 {
+            return Err("Inscription cache size cannot be zero".to_string());
         }
         
         Ok(())
@@ -86,6 +288,31 @@ impl BitcoinTestnetConfig {
 /// Utility functions for Bitcoin testnet
 pub mod utils {
     use super::*;
+    use crate::bitcoin::utxo::ScriptType;
+    use crate::errors::ContractError;
+    
+    /// A testnet address with the type of script it pays to
+    #[derive(Debug, Clone)]
+    pub struct ParsedAddress {
+        /// The address, checked to belong to a test network
+        pub address: Address,
+        /// Type of the address's output script
+        pub script_type: ScriptType,
+    }
+    
+    /// Parse a Bitcoin testnet address and detect its script type
+    pub fn parse_address(address: &str) -> Result<ParsedAddress, ContractError> {
+        if !validate_testnet_address(address) {
+            return Err(ContractError::InvalidAddress);
+        }
+        
+        let address = Address::from_str(address)
+            .map_err(|_| ContractError::InvalidAddress)?
+            .assume_checked();
+        let script_type = ScriptType::from_script(&address.script_pubkey());
+        
+        Ok(ParsedAddress { address, script_type })
+    }
     
     /// Validate a Bitcoin testnet address
     pub fn validate_testnet_address(address: &str) -> bool {
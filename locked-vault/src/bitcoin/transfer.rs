@@ -1,16 +1,42 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
+use log::{info, warn};
 
 use crate::bitcoin::testnet::{BitcoinTestnetConfig, utils};
-use crate::bitcoin::rpc::BitcoinRpcClient;
-use crate::bitcoin::lightning::LightningClient;
-use crate::bitcoin::ordinals::OrdinalsClient;
+use crate::bitcoin::fee_oracle::{ConfirmationTarget, FeeOracle};
+use crate::bitcoin::feature_gate::{FeatureGateStatus, FeatureGates};
+use crate::bitcoin::rpc::{BitcoinRpcClient, DuplicateOutputs, SignedTx, VAULT_LABEL_PREFIX};
+use crate::bitcoin::template::DataOutputs;
+use crate::bitcoin::utxo::{CoinControl, OperationalUtxo, SweepInputs};
+use crate::bitcoin::lightning::{ChannelStatus, LightningClient};
+use crate::bitcoin::ordinals::{Inscription, OrdinalsClient};
 use crate::bitcoin::mempool::MempoolMonitor;
-use crate::bitcoin::multisig::MultisigClient;
+use crate::bitcoin::reorg::ReorgWatcher;
+use crate::bitcoin::anchor::ChainAnchor;
+use crate::bitcoin::incoming::IncomingFundsWatcher;
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::multisig::{MultisigClient, MultisigWallet};
+use crate::bitcoin::descriptor::{address_descriptor, DescriptorImport};
 use crate::bitcoin::signature::SignatureVerifier;
-use crate::models::{TokenTransfer, TokenType};
-use crate::errors::ContractError;
+use crate::bitcoin::health::{HealthChecker, HealthProbe};
+use crate::bitcoin::cache::BalanceCache;
+use crate::bitcoin::payout::{PayoutBatch, PayoutBatcher};
+use crate::clock::Clock;
+use crate::introspection::{
+    DiagnosticsSource, Heartbeat, QueuedTransfer, RuntimeDiagnostics, SampleCache, UnconfirmedBroadcast, WorkerLiveness,
+    MEMPOOL_MONITOR_WORKER, PAYOUT_BATCHER_WORKER, TRANSFER_PROCESSOR_WORKER,
+};
+use crate::metrics::MetricsRegistry;
+use crate::models::{AmountConstraints, FeeQuote, ScriptEscrow, TokenTransfer, TokenType, TransferPriority, TransferQueueStatus, UnspentOutput, WithdrawOptions};
+use crate::errors::{ContractError, TransferStage, TRANSFER_QUEUE_FULL};
+use crate::store::TransferStateStore;
+use crate::logging::{LogLevels, TRANSFER};
+
+/// Node RPC error code for an unknown transaction, among others
+const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
 
 /// Implementation of TokenTransfer for Bitcoin testnet
 #[derive(Debug)]
@@ -25,19 +51,92 @@ pub struct BitcoinTestnetTransfer {
     ordinals_client: Option<Arc<OrdinalsClient>>,
     /// Mempool monitor
     mempool_monitor: Option<Arc<MempoolMonitor>>,
+    /// Reorg watcher for deposit funding transactions
+    reorg_watcher: Arc<ReorgWatcher>,
+    /// Watcher for funds sent to the contract wallet without a deposit
+    incoming_watcher: Arc<IncomingFundsWatcher>,
     /// Multisig client
     multisig_client: Option<MultisigClient>,
     /// Signature verifier
     signature_verifier: SignatureVerifier,
     /// Cache of address balances
-    balance_cache: Mutex<HashMap<String, (u64, Instant)>>,
+    balance_cache: Mutex<BalanceCache>,
+    /// Metrics registry
+    metrics: Arc<MetricsRegistry>,
     /// Pending transactions
-    pending_transactions: Mutex<Vec<PendingTransaction>>,
+    pending_transactions: Arc<Mutex<Vec<PendingTransaction>>>,
+    /// Reference assigned to the next queued transaction
+    next_reference: AtomicU64,
+    /// Non-urgent Bitcoin payouts waiting for a batch, if batching is on
+    payout_batcher: Option<Arc<Mutex<PayoutBatcher>>>,
+    /// Time the pending queue was last processed
+    processor_heartbeat: Heartbeat,
+    /// Time the payout batching window was last checked
+    batcher_heartbeat: Heartbeat,
+    /// Script escrow outputs deposits were told to pay, by address
+    watched_escrows: Mutex<BTreeMap<String, ScriptEscrow>>,
+    /// Circuit breakers of the Lightning, Ordinals and runes backends
+    feature_gates: FeatureGates,
+}
+
+/// Outcome of processing the pending transaction queue
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    /// Queue reference and transaction ID of each transaction sent
+    pub succeeded: Vec<(u64, String)>,
+    /// Queue reference and error of each transaction that failed and stays queued
+    pub failed: Vec<(u64, ContractError)>,
+}
+
+impl BatchResult {
+    /// Whether the batch had nothing to process
+    pub fn is_empty(&self) -> bool {
+        self.succeeded.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// Progress of one call working through the pending transaction queue
+#[derive(Debug, Default)]
+pub struct ProcessingProgress {
+    /// Number of queued transactions the call took up
+    pub processed: usize,
+    /// Number of transactions still queued, failed ones included
+    pub remaining: usize,
+    /// Queue reference and transaction ID of each transaction sent, due payouts included
+    pub txids: Vec<(u64, String)>,
+    /// Queue reference and error of each transaction that failed and stays queued
+    pub failed: Vec<(u64, ContractError)>,
+}
+
+impl ProcessingProgress {
+    /// Whether the call had nothing to process
+    pub fn is_empty(&self) -> bool {
+        self.txids.is_empty() && self.failed.is_empty()
+    }
+    
+    /// Whether the queue is empty after the call
+    pub fn is_complete(&self) -> bool {
+        self.remaining == 0
+    }
+    
+    /// Add the outcome of a payout batch flush
+    fn extend(&mut self, payouts: BatchResult) {
+        self.txids.extend(payouts.succeeded);
+        self.failed.extend(payouts.failed);
+    }
 }
 
 /// Represents a pending transaction
 #[derive(Debug, Clone)]
 struct PendingTransaction {
+    /// Queue reference
+    reference: u64,
+    /// What the transfer is for, part of its wallet label
+    kind: &'static str,
+    /// Deposit the transfer pays out, named in its wallet label instead of the queue reference
+    deposit_id: Option<u64>,
+    /// Failed processing attempts so far
+    attempts: u32,
     /// From address
     from_address: String,
     /// To address
@@ -46,24 +145,131 @@ struct PendingTransaction {
     amount: u64,
     /// Token type
     token_type: TokenType,
+    /// Processing priority
+    priority: TransferPriority,
+    /// Highest mining fee the sender accepts, in satoshis
+    max_onchain_fee: Option<u64>,
+    /// Inputs to spend or avoid
+    coin_control: Option<CoinControl>,
     /// Timestamp
     timestamp: Instant,
     /// Transaction ID (if sent)
     txid: Option<String>,
 }
 
+impl PendingTransaction {
+    /// Wallet label of the transaction, e.g. `vault:withdraw:ref-7` or `vault:withdraw:deposit-42`
+    fn label(&self) -> String {
+        match self.deposit_id {
+            Some(deposit_id) => format!("{}{}:deposit-{}", VAULT_LABEL_PREFIX, self.kind, deposit_id),
+            None => format!("{}{}:ref-{}", VAULT_LABEL_PREFIX, self.kind, self.reference),
+        }
+    }
+}
+
+/// Collector of the runtime diagnostics of a `BitcoinTestnetTransfer`
+/// 
+/// Locked values that are busy when collected are reported from the
+/// introspector's previous collection, marked stale.
+#[derive(Debug)]
+pub struct TransferIntrospector {
+    /// RPC client, for the rate limiter and the broadcast transactions
+    rpc_client: Arc<BitcoinRpcClient>,
+    /// Pending transaction queue
+    pending_transactions: Arc<Mutex<Vec<PendingTransaction>>>,
+    /// Payout batcher, if batching is on
+    payout_batcher: Option<Arc<Mutex<PayoutBatcher>>>,
+    /// Most transactions the pending queue holds
+    queue_capacity: usize,
+    /// Heartbeat of each background worker, by name
+    workers: Vec<(String, Heartbeat)>,
+    /// Last reading of the pending queue
+    pending_sample: SampleCache<Vec<QueuedTransfer>>,
+    /// Last reading of the payout batch
+    payout_sample: SampleCache<Vec<u64>>,
+    /// Last reading of the oldest unconfirmed broadcast
+    broadcast_sample: SampleCache<Option<UnconfirmedBroadcast>>,
+    /// Circuit breakers of the optional backends
+    feature_gates: FeatureGates,
+    /// Levels of the process logger, if adjustable
+    log_levels: Option<Arc<LogLevels>>,
+}
+
+impl TransferIntrospector {
+    /// Report the liveness of another worker, such as the daemon loop
+    pub fn with_worker(mut self, worker: &str, heartbeat: Heartbeat) -> Self {
+        self.workers.push((worker.to_string(), heartbeat));
+        self
+    }
+    
+    /// Report the levels the process logger logs each target at
+    pub fn with_log_levels(mut self, log_levels: Arc<LogLevels>) -> Self {
+        self.log_levels = Some(log_levels);
+        self
+    }
+}
+
+impl DiagnosticsSource for TransferIntrospector {
+    fn diagnostics(&self) -> RuntimeDiagnostics {
+        let collected_at = chrono::Utc::now();
+        
+        let pending_transfers = self.pending_sample.sample(&self.pending_transactions, |pending| {
+            pending.iter()
+                .map(|tx| QueuedTransfer {
+                    reference: tx.reference,
+                    kind: tx.kind.to_string(),
+                    deposit_id: tx.deposit_id,
+                    token_type: tx.token_type.clone(),
+                    amount: tx.amount,
+                    priority: tx.priority,
+                    attempts: tx.attempts,
+                    age_secs: tx.timestamp.elapsed().as_secs(),
+                })
+                .collect()
+        });
+        
+        let batched_payouts = self.payout_batcher.as_ref().map(|batcher| {
+            self.payout_sample.sample(batcher, |batcher| batcher.entries().iter().map(|entry| entry.reference).collect())
+        });
+        
+        let oldest_unconfirmed_broadcast = self.broadcast_sample.sample(self.rpc_client.unconfirmed_broadcasts(), |broadcasts| {
+            broadcasts.front().map(|(txid, broadcast_at)| UnconfirmedBroadcast {
+                txid: txid.clone(),
+                age_secs: broadcast_at.elapsed().as_secs(),
+            })
+        });
+        
+        RuntimeDiagnostics {
+            collected_at,
+            pending_transfers,
+            queue_capacity: self.queue_capacity,
+            batched_payouts,
+            workers: self.workers.iter()
+                .map(|(worker, heartbeat)| WorkerLiveness::of(worker, heartbeat, collected_at))
+                .collect(),
+            rate_limiter: self.rpc_client.rate_limiter_saturation(),
+            oldest_unconfirmed_broadcast,
+            feature_gates: self.feature_gates.statuses(),
+            log_levels: self.log_levels.as_ref().map(|levels| levels.levels()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Wallet label of a payout batch transaction, naming the deposits paid, e.g. `vault:withdraw:deposit-42`
+fn payout_batch_label(batch: &PayoutBatch) -> String {
+    let deposit_ids: Vec<String> = batch.references().iter().map(u64::to_string).collect();
+    format!("{}withdraw:deposit-{}", VAULT_LABEL_PREFIX, deposit_ids.join(","))
+}
+
 impl BitcoinTestnetTransfer {
     /// Create a new Bitcoin testnet transfer implementation
     pub fn new(config: BitcoinTestnetConfig) -> Result<Self, ContractError> {
         // Validate configuration
-        config.validate().map_err(|e| ContractError::from(e))?;
+        config.validate().map_err(ContractError::InitializationError)?;
         
         // Create RPC client
         let rpc_client = Arc::new(BitcoinRpcClient::new(&config)?);
         
-        // Create signature verifier
-        let signature_verifier = SignatureVerifier::new(bitcoincore_rpc::bitcoin::Network::Testnet);
-        
         // Create mempool monitor
         let mempool_monitor = Arc::new(MempoolMonitor::new(
             rpc_client.clone(),
@@ -74,19 +280,149 @@ impl BitcoinTestnetTransfer {
         mempool_monitor.start()?;
         
         // Create transfer implementation
-        let transfer = Self {
+        let mut transfer = Self::with_rpc_client(config, rpc_client);
+        transfer.set_mempool_monitor(mempool_monitor);
+        
+        Ok(transfer)
+    }
+    
+    /// Create a transfer implementation around an existing RPC client, without a mempool monitor
+    pub(crate) fn with_rpc_client(config: BitcoinTestnetConfig, rpc_client: Arc<BitcoinRpcClient>) -> Self {
+        // Create signature verifier
+        let signature_verifier = SignatureVerifier::new(config.network);
+        
+        // Create balance cache
+        let metrics = Arc::new(MetricsRegistry::new());
+        let balance_cache = BalanceCache::new(config.balance_cache.clone(), &metrics);
+        rpc_client.fee_oracle().set_metrics_registry(&metrics);
+        
+        // Create reorg watcher, started by the caller once it has a callback
+        let reorg_watcher = Arc::new(ReorgWatcher::new(
+            rpc_client.clone(),
+            config.confirmation_policy.clone(),
+            Duration::from_secs(60),
+        ));
+        
+        // Create incoming funds watcher, likewise started by the caller
+        let incoming_watcher = Arc::new(IncomingFundsWatcher::new(
+            rpc_client.clone(),
+            config.contract_wallet_address.clone(),
+            config.confirmation_policy.clone(),
+            Duration::from_secs(60),
+        ));
+        
+        // Create payout batcher if batching is configured
+        let payout_batcher = config.payout_batch.clone()
+            .map(|batch_config| Arc::new(Mutex::new(PayoutBatcher::new(batch_config))));
+        
+        let feature_gates = FeatureGates::new(&config.feature_gates);
+        
+        Self {
             config,
-            rpc_client: rpc_client.clone(),
+            rpc_client,
             lightning_client: None,
             ordinals_client: None,
-            mempool_monitor: Some(mempool_monitor),
+            mempool_monitor: None,
+            reorg_watcher,
+            incoming_watcher,
             multisig_client: None,
             signature_verifier,
-            balance_cache: Mutex::new(HashMap::new()),
-            pending_transactions: Mutex::new(Vec::new()),
-        };
+            balance_cache: Mutex::new(balance_cache),
+            metrics,
+            pending_transactions: Arc::new(Mutex::new(Vec::new())),
+            next_reference: AtomicU64::new(1),
+            payout_batcher,
+            processor_heartbeat: Heartbeat::new(),
+            batcher_heartbeat: Heartbeat::new(),
+            watched_escrows: Mutex::new(BTreeMap::new()),
+            feature_gates,
+        }
+    }
+    
+    /// Create a multisig wallet, watched alongside the contract wallet
+    pub fn create_multisig_wallet(
+        &mut self,
+        name: &str,
+        required_signatures: u8,
+        public_keys: Vec<String>,
+    ) -> Result<MultisigWallet, ContractError> {
+        let rpc_client = &self.rpc_client;
+        let network = self.config.network;
+        self.multisig_client
+            .get_or_insert_with(|| MultisigClient::new((**rpc_client).clone(), network))
+            .create_wallet(name, required_signatures, public_keys)
+    }
+    
+    /// Create a 2-of-2 MuSig2 wallet of the vault and a depositor, watched alongside the contract wallet
+    pub fn create_musig_wallet(
+        &mut self,
+        name: &str,
+        vault_key: &str,
+        depositor_key: &str,
+        lock_height: u32,
+    ) -> Result<MultisigWallet, ContractError> {
+        let rpc_client = &self.rpc_client;
+        self.multisig_client
+            .get_or_insert_with(|| MultisigClient::new((**rpc_client).clone(), bitcoincore_rpc::bitcoin::Network::Testnet))
+            .create_musig_wallet(name, vault_key, depositor_key, lock_height)
+    }
+    
+    /// Export output descriptors watching every address the vault receives funds at
+    /// 
+    /// Covers the contract wallet, the multisig wallets and the script escrow
+    /// outputs of deposits, each descriptor with its checksum. The
+    /// descriptors are rendered from the same terms the addresses were
+    /// derived from, so importing them watches exactly those addresses.
+    pub fn export_watch_descriptors(&self) -> Vec<String> {
+        let mut descriptors = Vec::new();
         
-        Ok(transfer)
+        match address_descriptor(&self.config.contract_wallet_address) {
+            Ok(descriptor) => descriptors.push(descriptor),
+            Err(e) => warn!(target: TRANSFER, "Contract wallet address has no descriptor: {}", e),
+        }
+        
+        for wallet in self.multisig_client.iter().flat_map(|client| client.wallets()) {
+            match wallet.descriptor() {
+                Ok(descriptor) => descriptors.push(descriptor),
+                Err(e) => warn!(target: TRANSFER, "Multisig wallet {} has no descriptor: {}", wallet.name, e),
+            }
+        }
+        
+        if let Ok(escrows) = self.watched_escrows.lock() {
+            for escrow in escrows.values() {
+                match escrow.policy() {
+                    Ok(policy) => descriptors.push(policy.descriptor()),
+                    Err(e) => warn!(target: TRANSFER, "Escrow {} has no descriptor: {}", escrow.address, e),
+                }
+            }
+        }
+        
+        descriptors
+    }
+    
+    /// Build a Bitcoin Core `importdescriptors` request watching the vault's addresses
+    /// 
+    /// The node rescans from `timestamp` for past payments to them.
+    pub fn export_import_request(&self, timestamp: u64) -> Vec<DescriptorImport> {
+        self.export_watch_descriptors()
+            .into_iter()
+            .map(|descriptor| DescriptorImport::watch_only(descriptor, timestamp))
+            .collect()
+    }
+    
+    /// Attach the Lightning client used for Lightning payouts and HTLC limits
+    pub(crate) fn set_lightning_client(&mut self, lightning_client: Arc<LightningClient>) {
+        self.lightning_client = Some(lightning_client);
+    }
+    
+    /// Attach the Ordinals client used for inscription transfers and ownership checks
+    pub(crate) fn set_ordinals_client(&mut self, ordinals_client: Arc<OrdinalsClient>) {
+        self.ordinals_client = Some(ordinals_client);
+    }
+    
+    /// Attach the mempool monitor whose loop diagnostics report on
+    pub(crate) fn set_mempool_monitor(&mut self, mempool_monitor: Arc<MempoolMonitor>) {
+        self.mempool_monitor = Some(mempool_monitor);
     }
     
     /// Create a new Bitcoin testnet transfer implementation with all clients
@@ -103,7 +439,7 @@ impl BitcoinTestnetTransfer {
             let lightning_client = Arc::new(LightningClient::new(
                 transfer.rpc_client.clone(),
                 url,
-                "api_key".to_string(), // In a real implementation, this would be provided
+                transfer.config.lightning_macaroon.clone().unwrap_or_default(),
             ));
             
             transfer.lightning_client = Some(lightning_client);
@@ -111,18 +447,19 @@ impl BitcoinTestnetTransfer {
         
         // Create Ordinals client if URL provided
         if let Some(url) = ordinals_api_url {
-            let ordinals_client = Arc::new(OrdinalsClient::new(
+            let ordinals_client = Arc::new(OrdinalsClient::with_cache_config(
                 transfer.rpc_client.clone(),
                 url,
-            ));
+                transfer.config.inscription_cache.clone(),
+            ).with_api_flavor(transfer.config.ordinals_api_flavor));
             
-            transfer.ordinals_client = Some(ordinals_client);
+            transfer.set_ordinals_client(ordinals_client);
         }
         
         // Create Multisig client
         let multisig_client = MultisigClient::new(
             (*transfer.rpc_client).clone(),
-            bitcoincore_rpc::bitcoin::Network::Testnet,
+            transfer.config.network,
         );
         
         transfer.multisig_client = Some(multisig_client);
@@ -130,154 +467,398 @@ impl BitcoinTestnetTransfer {
         Ok(transfer)
     }
     
-    /// Process pending transactions in batches
-    pub fn process_pending_transactions(&self) -> Result<Vec<String>, ContractError> {
+    /// Report metrics to a shared registry instead of the private one
+    pub fn set_metrics_registry(&mut self, metrics: Arc<MetricsRegistry>) {
+        self.balance_cache = Mutex::new(BalanceCache::new(self.config.balance_cache.clone(), &metrics));
+        self.rpc_client.fee_oracle().set_metrics_registry(&metrics);
+        self.metrics = metrics;
+    }
+    
+    /// Get the metrics registry
+    pub fn metrics(&self) -> Arc<MetricsRegistry> {
+        self.metrics.clone()
+    }
+    
+    /// Replace the time source used for balance cache expiry, the payout batching window and the feature gate cooldowns
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        if let Some(Ok(mut batcher)) = self.payout_batcher.as_ref().map(|batcher| batcher.lock()) {
+            batcher.set_clock(clock.clone());
+        }
+        
+        self.feature_gates.set_clock(clock.clone());
+        
+        if let Ok(cache) = self.balance_cache.get_mut() {
+            cache.set_clock(clock);
+        }
+    }
+    
+    /// Drop every cached balance
+    pub fn clear_balance_cache(&self) {
+        if let Ok(mut cache) = self.balance_cache.lock() {
+            cache.clear();
+        }
+    }
+    
+    /// Get the fee estimates shared by the RPC client, payouts, inscriptions and fee quotes
+    pub fn fee_oracle(&self) -> Arc<FeeOracle> {
+        self.rpc_client.fee_oracle().clone()
+    }
+    
+    /// Get the reorg watcher for deposit funding transactions
+    pub fn reorg_watcher(&self) -> Arc<ReorgWatcher> {
+        self.reorg_watcher.clone()
+    }
+    
+    /// Get the watcher for funds sent to the contract wallet without a deposit
+    pub fn incoming_watcher(&self) -> Arc<IncomingFundsWatcher> {
+        self.incoming_watcher.clone()
+    }
+    
+    /// Get the circuit breakers of the Lightning, Ordinals and runes backends
+    pub fn feature_gates(&self) -> &FeatureGates {
+        &self.feature_gates
+    }
+    
+    /// Get the change of broadcast transactions held by the contract wallet, in reference order
+    pub fn operational_utxos(&self) -> Vec<OperationalUtxo> {
+        self.rpc_client.operational_utxos()
+    }
+    
+    /// Save the operational UTXOs with the transfer state
+    pub fn save_operational_utxos(&self, store: &dyn TransferStateStore) -> Result<(), String> {
+        store.save_operational_utxos(&self.rpc_client.operational_utxos())
+    }
+    
+    /// Restore the operational UTXOs saved with the transfer state, returning how many there are
+    pub fn restore_operational_utxos(&self, store: &dyn TransferStateStore) -> Result<usize, String> {
+        let utxos = store.load_operational_utxos()?;
+        let count = utxos.len();
+        self.rpc_client.restore_operational_utxos(utxos);
+        
+        Ok(count)
+    }
+    
+    /// Create a health checker probing every backend client of this transfer
+    pub fn health_checker(&self, interval: Duration) -> HealthChecker {
+        let mut checker = HealthChecker::new(interval);
+        
+        checker.add_probe(self.rpc_client.clone());
+        
+        if let Some(lightning_client) = &self.lightning_client {
+            checker.add_probe(lightning_client.clone());
+        }
+        
+        if let Some(ordinals_client) = &self.ordinals_client {
+            checker.add_probe(ordinals_client.clone());
+        }
+        
+        for gate in self.feature_gates.all() {
+            checker.add_gate(gate.clone());
+        }
+        
+        checker
+    }
+    
+    /// Process up to `max_batches_per_call` batches of pending transactions, highest priority first
+    /// 
+    /// A batch is at most `max_batch_size` transactions. Whatever does not fit
+    /// stays queued for the next call, so one call never floods the node; the
+    /// returned progress tells how much is left. Within a batch, Bitcoin
+    /// transactions are all built first, each reserving its inputs, then
+    /// broadcast with bounded parallelism. Every item succeeds or fails on its
+    /// own: failed items stay queued with their attempt count raised, and only
+    /// the items that were sent leave the queue.
+    /// 
+    /// Transactions of equal priority keep their queue order. Bitcoin fee
+    /// estimates use the confirmation target configured for the priority and
+    /// are fetched once per target and batch.
+    /// 
+    /// A payout batch whose window has elapsed is sent as well, its payouts
+    /// reported by the references they were queued with.
+    pub fn process_pending_transactions(&self) -> Result<ProcessingProgress, ContractError> {
+        self.processor_heartbeat.beat();
+        
         let mut pending = self.pending_transactions.lock()
             .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?;
         
-        if pending.is_empty() {
-            return Ok(Vec::new());
+        let mut progress = ProcessingProgress::default();
+        
+        // Order by priority; the sort is stable so queue order is kept within a priority
+        let mut queue = pending.clone();
+        queue.sort_by_key(|tx| Reverse(tx.priority));
+        
+        // Take up no more than the batches allowed per call
+        let batch_size = self.config.max_batch_size as usize;
+        queue.truncate(batch_size.saturating_mul(self.config.max_batches_per_call as usize));
+        progress.processed = queue.len();
+        
+        for batch in queue.chunks(batch_size) {
+            let result = self.process_batch(batch);
+            progress.txids.extend(result.succeeded);
+            progress.failed.extend(result.failed);
         }
         
-        // Group transactions by token type
-        let mut grouped: HashMap<TokenType, Vec<PendingTransaction>> = HashMap::new();
+        // Sent items leave the queue; failed ones stay, counting the attempt
+        pending.retain(|tx| !progress.txids.iter().any(|(reference, _)| *reference == tx.reference));
+        for tx in pending.iter_mut() {
+            if progress.failed.iter().any(|(reference, _)| *reference == tx.reference) {
+                tx.attempts = tx.attempts.saturating_add(1);
+            }
+        }
         
-        for tx in pending.iter() {
-            grouped.entry(tx.token_type.clone())
-                .or_insert_with(Vec::new)
-                .push(tx.clone());
+        for (reference, e) in &progress.failed {
+            warn!(target: TRANSFER, "Pending transaction {} failed and stays queued: {}", reference, e);
         }
         
-        let mut processed_txids = Vec::new();
+        progress.remaining = pending.len();
         
-        // Process each group
-        for (token_type, transactions) in grouped {
-            match token_type {
+        // Send the payout batch too if its window has elapsed
+        drop(pending);
+        progress.extend(self.flush_due_payouts()?);
+        
+        Ok(progress)
+    }
+    
+    /// Send one batch of pending transactions
+    fn process_batch(&self, batch: &[PendingTransaction]) -> BatchResult {
+        let mut result = BatchResult::default();
+        
+        // One fee estimate per confirmation target for the whole batch, failures included
+        let mut fee_rates: HashMap<ConfirmationTarget, Result<f64, String>> = HashMap::new();
+        
+        // Build Bitcoin transactions up front; other tokens are sent right away
+        let mut built = Vec::new();
+        
+        for tx in batch {
+            let outcome = match tx.token_type {
                 TokenType::Bitcoin => {
-                    // Process Bitcoin transactions
-                    for batch in transactions.chunks(self.config.max_batch_size as usize) {
-                        for tx in batch {
-                            // Get fee estimate
-                            let fee_rate = self.rpc_client.get_fee_estimate(6)?;
-                            
-                            // Create and sign transaction
-                            let txid = self.rpc_client.create_and_sign_transaction(
-                                &tx.from_address,
-                                &tx.to_address,
-                                tx.amount,
-                                fee_rate,
-                            )?;
-                            
-                            processed_txids.push(txid);
-                        }
-                    }
-                },
-                TokenType::Rune(_rune_id) => {
-                    // Process Rune transactions
-                    // In a real implementation, this would use a Rune-specific API
-                    // For now, we'll simulate it
+                    let target = ConfirmationTarget::from(tx.priority);
+                    let fee_rate = fee_rates.entry(target)
+                        .or_insert_with(|| self.rpc_client.get_fee_estimate(target).map_err(|e| e.to_string()))
+                        .clone()
+                        .map_err(|reason| ContractError::TransferFailed { stage: TransferStage::FeeEstimation, reason });
                     
-                    for _tx in transactions {
-                        let txid = format!("rune_tx_{}", Instant::now().elapsed().as_nanos());
-                        processed_txids.push(txid);
-                    }
+                    fee_rate
+                        .and_then(|fee_rate| self.build_pending_transaction(tx, fee_rate))
+                        .map(|transaction| built.push((tx.reference, transaction)))
                 },
-                TokenType::Ordinal(inscription_id) => {
-                    // Process Ordinal transactions
-                    if let Some(ordinals_client) = &self.ordinals_client {
-                        for tx in transactions {
-                            let txid = ordinals_client.transfer_inscription(
-                                &inscription_id,
-                                &tx.from_address,
-                                &tx.to_address,
-                            )?;
-                            
-                            processed_txids.push(txid);
-                        }
-                    } else {
-                        return Err(ContractError::BitcoinTestnetError("Ordinals client not initialized".to_string()));
-                    }
-                },
-                TokenType::Lightning => {
-                    // Process Lightning transactions
-                    if let Some(lightning_client) = &self.lightning_client {
-                        for tx in transactions {
-                            // Create invoice
-                            let invoice = lightning_client.create_invoice(
-                                tx.amount,
-                                &format!("Payment from {} to {}", tx.from_address, tx.to_address),
-                                3600, // 1 hour expiry
-                            )?;
-                            
-                            processed_txids.push(invoice.id);
-                        }
-                    } else {
-                        return Err(ContractError::BitcoinTestnetError("Lightning client not initialized".to_string()));
-                    }
-                },
-                _ => {
-                    return Err(ContractError::UnsupportedTokenOperation);
+                _ => self.process_pending_transaction(tx)
+                    .map(|txid| result.succeeded.push((tx.reference, txid))),
+            };
+            
+            if let Err(e) = outcome {
+                result.failed.push((tx.reference, e));
+            }
+        }
+        
+        // Broadcast the built transactions, a bounded number at a time
+        for chunk in built.chunks(self.config.broadcast_parallelism) {
+            let outcomes: Vec<(u64, Result<String, ContractError>)> = std::thread::scope(|scope| {
+                let handles: Vec<_> = chunk.iter()
+                    .map(|(reference, transaction)| {
+                        (*reference, scope.spawn(move || self.rpc_client.broadcast_transaction(transaction)))
+                    })
+                    .collect();
+                
+                handles.into_iter()
+                    .map(|(reference, handle)| {
+                        let outcome = handle.join().unwrap_or_else(|_| Err(ContractError::TransferFailed {
+                            stage: TransferStage::Broadcast,
+                            reason: "Broadcast thread panicked".to_string(),
+                        }));
+                        (reference, outcome)
+                    })
+                    .collect()
+            });
+            
+            for (reference, outcome) in outcomes {
+                match outcome {
+                    Ok(txid) => result.succeeded.push((reference, txid)),
+                    Err(e) => result.failed.push((reference, e)),
                 }
             }
         }
         
-        // Clear processed transactions
-        pending.clear();
+        // The node now tracks the spent inputs of anything broadcast
+        for (_, transaction) in &built {
+            self.rpc_client.release_utxos(&transaction.inputs);
+        }
         
-        Ok(processed_txids)
+        result
     }
     
-    /// Validate a Rune token ID
-    fn validate_rune_id(&self, rune_id: &str) -> Result<(), String> {
-        if rune_id.is_empty() {
-            return Err("Rune ID cannot be empty".to_string());
-        }
+    /// Send every payout waiting for a batch, whether or not its window has elapsed
+    /// 
+    /// Each batch is one transaction with one output per destination, duplicate
+    /// destinations merged. The result lists every payout by the reference it
+    /// was queued with: the references of a sent batch share its transaction
+    /// ID, and those of a failed batch stay queued for the next flush.
+    pub fn flush_payout_batch(&self) -> Result<BatchResult, ContractError> {
+        let batcher = match &self.payout_batcher {
+            Some(batcher) => batcher,
+            None => return Ok(BatchResult::default()),
+        };
         
-        if !rune_id.starts_with("RUNE_") {
-            return Err("Rune ID must start with 'RUNE_'".to_string());
-        }
+        let batches = batcher.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .drain_batches();
         
-        if rune_id.len() < 10 {
-            return Err("Rune ID must be at least 10 characters long".to_string());
+        let mut result = BatchResult::default();
+        let mut unsent = Vec::new();
+        
+        for batch in batches {
+            match self.send_payout_batch(&batch) {
+                Ok(txid) => {
+                    result.succeeded.extend(batch.references().into_iter().map(|reference| (reference, txid.clone())));
+                },
+                Err(e) => {
+                    warn!(target: TRANSFER, "Payout batch of {} payouts failed and stays queued: {}", batch.entries.len(), e);
+                    
+                    // Every payout of the batch failed for the same reason
+                    let (stage, reason) = match e {
+                        ContractError::TransferFailed { stage, reason } => (stage, reason),
+                        other => (TransferStage::Withdrawal, other.to_string()),
+                    };
+                    result.failed.extend(batch.references().into_iter().map(|reference| {
+                        (reference, ContractError::TransferFailed { stage, reason: reason.clone() })
+                    }));
+                    unsent.extend(batch.entries);
+                },
+            }
         }
         
-        if !rune_id.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            return Err("Rune ID can only contain alphanumeric characters and underscores".to_string());
+        // Keep failed payouts queued, ahead of any queued meanwhile
+        batcher.lock()
+            .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+            .requeue(unsent);
+        
+        Ok(result)
+    }
+    
+    /// Send the waiting payouts if the batching window of the oldest one has elapsed
+    pub fn flush_due_payouts(&self) -> Result<BatchResult, ContractError> {
+        let due = match &self.payout_batcher {
+            Some(batcher) => {
+                self.batcher_heartbeat.beat();
+                batcher.lock()
+                    .map_err(|_| ContractError::BitcoinTestnetError("Failed to acquire lock".to_string()))?
+                    .is_due()
+            },
+            None => false,
+        };
+        
+        if !due {
+            return Ok(BatchResult::default());
         }
         
-        Ok(())
+        self.flush_payout_batch()
     }
     
-    /// Validate an Ordinal inscription ID
-    fn validate_ordinal_id(&self, inscription_id: &str) -> Result<(), String> {
-        if inscription_id.is_empty() {
-            return Err("Inscription ID cannot be empty".to_string());
+    /// References of the payouts waiting for a batch, in queue order
+    pub fn queued_payouts(&self) -> Vec<u64> {
+        self.payout_batcher.as_ref()
+            .and_then(|batcher| batcher.lock().ok())
+            .map(|batcher| batcher.entries().iter().map(|entry| entry.reference).collect())
+            .unwrap_or_default()
+    }
+    
+    /// Build, sign and broadcast the transaction paying one batch, returning its transaction ID
+    fn send_payout_batch(&self, batch: &PayoutBatch) -> Result<String, ContractError> {
+        // Batched payouts are regular withdrawals
+        let fee_rate = self.rpc_client.get_fee_estimate(ConfirmationTarget::Normal)?;
+        
+        let built = self.rpc_client.create_and_sign_transaction_multi(
+            &self.config.contract_wallet_address,
+            &batch.outputs(),
+            fee_rate,
+            DuplicateOutputs::Merge,
+            DataOutputs::Refuse,
+            Some(payout_batch_label(batch)),
+        )?;
+        let result = self.rpc_client.broadcast_transaction(&built);
+        self.rpc_client.release_utxos(&built.inputs);
+        
+        result
+    }
+    
+    /// Number of transactions waiting in the pending queue
+    pub fn pending_queue_depth(&self) -> usize {
+        self.pending_transactions.lock()
+            .map(|pending| pending.len())
+            .unwrap_or_default()
+    }
+    
+    /// Queue a transaction, returning the new queue depth
+    /// 
+    /// Fails with a `TRANSFER_QUEUE_FULL` error once `max_pending_transactions`
+    /// are waiting, so callers back off until the queue drains.
+    fn enqueue(&self, transaction: PendingTransaction) -> Result<usize, String> {
+        let mut pending = self.pending_transactions.lock()
+            .map_err(|_| "Failed to acquire lock".to_string())?;
+        
+        if pending.len() >= self.config.max_pending_transactions {
+            self.metrics.counter("pending_queue_rejections").inc();
+            return Err(format!(
+                "{}: {} of {} transactions waiting",
+                TRANSFER_QUEUE_FULL, pending.len(), self.config.max_pending_transactions
+            ));
         }
         
-        if !inscription_id.chars().all(|c| c.is_ascii_hexdigit()) {
-            return Err("Inscription ID must be a valid hexadecimal string".to_string());
+        pending.push(transaction);
+        self.metrics.counter("pending_queue_high_water_mark").raise_to(pending.len() as u64);
+        
+        Ok(pending.len())
+    }
+    
+    /// Queue reference and failed attempts of each queued transaction, in queue order
+    pub fn pending_references(&self) -> Vec<(u64, u32)> {
+        self.pending_transactions.lock()
+            .map(|pending| pending.iter().map(|tx| (tx.reference, tx.attempts)).collect())
+            .unwrap_or_default()
+    }
+    
+    /// Create an introspector collecting the diagnostics of this transfer
+    /// 
+    /// The introspector shares the queue, batcher, heartbeats and RPC client
+    /// with the transfer, so it keeps working after the transfer moves into
+    /// the contract, and never waits on their locks.
+    pub fn introspector(&self) -> TransferIntrospector {
+        let mut workers = vec![(TRANSFER_PROCESSOR_WORKER.to_string(), self.processor_heartbeat.clone())];
+        
+        if self.payout_batcher.is_some() {
+            workers.push((PAYOUT_BATCHER_WORKER.to_string(), self.batcher_heartbeat.clone()));
         }
         
-        if inscription_id.len() < 64 {
-            return Err("Inscription ID must be at least 64 characters long".to_string());
+        if let Some(mempool_monitor) = &self.mempool_monitor {
+            workers.push((MEMPOOL_MONITOR_WORKER.to_string(), mempool_monitor.heartbeat()));
         }
         
-        Ok(())
+        TransferIntrospector {
+            rpc_client: self.rpc_client.clone(),
+            pending_transactions: self.pending_transactions.clone(),
+            payout_batcher: self.payout_batcher.clone(),
+            queue_capacity: self.config.max_pending_transactions,
+            workers,
+            pending_sample: SampleCache::default(),
+            payout_sample: SampleCache::default(),
+            broadcast_sample: SampleCache::default(),
+            feature_gates: self.feature_gates.clone(),
+            log_levels: None,
+        }
     }
     
-    /// Get the network type
-    pub fn get_network_type(&self) -> String {
-        "testnet".to_string()
+    /// Collect the runtime diagnostics of this transfer without waiting on busy locks
+    pub fn diagnostics(&self) -> RuntimeDiagnostics {
+        self.introspector().diagnostics()
     }
     
-    /// Check if the network is testnet
-    pub fn is_testnet(&self) -> bool {
-        true
+    /// Stop tracking the oldest broadcast transactions that have confirmed, returning how many
+    pub fn prune_confirmed_broadcasts(&self) -> Result<usize, ContractError> {
+        self.rpc_client.prune_confirmed_broadcasts()
     }
-}
-
-impl TokenTransfer for BitcoinTestnetTransfer {
-    fn transfer_to_contract(&self, from_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+    
+    /// Queue a transfer into the contract, labelled with the deposit it funds if any
+    fn queue_deposit(&self, from_address: &str, token_type: &TokenType, amount: u64, deposit_id: Option<u64>) -> Result<(), String> {
         // Validate address
         self.validate_address(from_address)?;
         
@@ -308,32 +889,49 @@ impl TokenTransfer for BitcoinTestnetTransfer {
             _ => return Err("Unsupported token type for Bitcoin testnet".to_string()),
         }
         
-        // Add to pending transactions
-        let mut pending = self.pending_transactions.lock()
-            .map_err(|_| "Failed to acquire lock".to_string())?;
+        // Refuse right away while the token's backend is cut off
+        self.admit(token_type)?;
         
-        pending.push(PendingTransaction {
+        // Add to pending transactions, unless the queue is full
+        let depth = self.enqueue(PendingTransaction {
+            reference: self.next_reference.fetch_add(1, Ordering::Relaxed),
+            kind: "deposit",
+            deposit_id,
+            attempts: 0,
             from_address: from_address.to_string(),
             to_address: self.config.contract_wallet_address.clone(),
             amount,
             token_type: token_type.clone(),
+            priority: TransferPriority::Normal,
+            max_onchain_fee: None,
+            coin_control: None,
             timestamp: Instant::now(),
             txid: None,
-        });
+        })?;
         
-        // Process transactions if batch size reached
-        if pending.len() >= self.config.max_batch_size as usize {
-            drop(pending); // Release lock before processing
-            self.process_pending_transactions()
+        // Process transactions if batch size reached; the rest waits for the next round
+        if depth >= self.config.max_batch_size as usize {
+            let progress = self.process_pending_transactions()
                 .map_err(|e| format!("Failed to process transactions: {:?}", e))?;
+            if !progress.is_complete() {
+                info!(target: TRANSFER, "{} pending transactions left for the next round", progress.remaining);
+            }
         }
         
         Ok(())
     }
     
-    fn transfer_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
-        // Validate address
-        self.validate_address(to_address)?;
+    /// Queue a payout from the contract, labelled with the deposit it pays if any
+    fn queue_payout(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        deposit_id: Option<u64>,
+    ) -> Result<(), String> {
+        // Validate address and the script it pays to
+        self.validate_destination(to_address).map_err(|e| e.to_string())?;
         
         // Validate token type
         match token_type {
@@ -362,44 +960,418 @@ impl TokenTransfer for BitcoinTestnetTransfer {
             _ => return Err("Unsupported token type for Bitcoin testnet".to_string()),
         }
         
-        // Add to pending transactions
-        let mut pending = self.pending_transactions.lock()
-            .map_err(|_| "Failed to acquire lock".to_string())?;
+        // Refuse right away while the token's backend is cut off
+        self.admit(token_type)?;
         
-        pending.push(PendingTransaction {
+        // Add to pending transactions, unless the queue is full
+        let depth = self.enqueue(PendingTransaction {
+            reference: self.next_reference.fetch_add(1, Ordering::Relaxed),
+            kind: if deposit_id.is_some() { "withdraw" } else { "payout" },
+            deposit_id,
+            attempts: 0,
             from_address: self.config.contract_wallet_address.clone(),
             to_address: to_address.to_string(),
             amount,
             token_type: token_type.clone(),
+            priority,
+            max_onchain_fee: None,
+            coin_control: None,
             timestamp: Instant::now(),
             txid: None,
-        });
+        })?;
         
-        // Process transactions if batch size reached
-        if pending.len() >= self.config.max_batch_size as usize {
-            drop(pending); // Release lock before processing
-            self.process_pending_transactions()
+        // Process transactions if batch size reached; the rest waits for the next round
+        if depth >= self.config.max_batch_size as usize {
+            let progress = self.process_pending_transactions()
                 .map_err(|e| format!("Failed to process transactions: {:?}", e))?;
+            if !progress.is_complete() {
+                info!(target: TRANSFER, "{} pending transactions left for the next round", progress.remaining);
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Build and sign a pending Bitcoin transaction at `fee_rate` without broadcasting it
+    fn build_pending_transaction(&self, tx: &PendingTransaction, fee_rate: f64) -> Result<SignedTx, ContractError> {
+        self.rpc_client.build_transaction(
+            &tx.from_address,
+            &tx.to_address,
+            tx.amount,
+            fee_rate,
+            tx.max_onchain_fee,
+            tx.coin_control.as_ref(),
+        )
+            .map(|built| built.with_label(Some(tx.label())))
+    }
+    
+    /// Send one pending transaction, returning its transaction ID
+    fn process_pending_transaction(&self, tx: &PendingTransaction) -> Result<String, ContractError> {
+        match &tx.token_type {
+            TokenType::Bitcoin => {
+                // Get fee estimate for the priority's confirmation target
+                let fee_rate = self.rpc_client.get_fee_estimate(ConfirmationTarget::from(tx.priority))?;
+                
+                // Create and sign transaction
+                self.rpc_client.create_and_sign_transaction(
+                    &tx.from_address,
+                    &tx.to_address,
+                    tx.amount,
+                    fee_rate,
+                    tx.max_onchain_fee,
+                    tx.coin_control.as_ref(),
+                    Some(tx.label()),
+                )
+            },
+            TokenType::Rune(_rune_id) => self.gated(&tx.token_type, || {
+                // Process Rune transactions
+                // In a real implementation, this would use a Rune-specific API
+                // For now, we'll simulate it
+                Ok(format!("rune_tx_{}", Instant::now().elapsed().as_nanos()))
+            }),
+            TokenType::Ordinal(inscription_id) => {
+                // Process Ordinal transactions
+                let ordinals_client = self.ordinals_client.as_ref()
+                    .ok_or_else(|| ContractError::BitcoinTestnetError("Ordinals client not initialized".to_string()))?;
+                
+                self.gated(&tx.token_type, || ordinals_client.transfer_inscription(
+                    inscription_id,
+                    &tx.from_address,
+                    &tx.to_address,
+                ))
+            },
+            TokenType::Lightning => {
+                // Process Lightning transactions
+                let lightning_client = self.lightning_client.as_ref()
+                    .ok_or_else(|| ContractError::BitcoinTestnetError("Lightning client not initialized".to_string()))?;
+                
+                // Create invoice
+                let invoice = self.gated(&tx.token_type, || lightning_client.create_invoice(
+                    tx.amount,
+                    &format!("Payment from {} to {}", tx.from_address, tx.to_address),
+                    3600, // 1 hour expiry
+                ))?;
+                
+                Ok(invoice.id)
+            },
+            _ => Err(ContractError::UnsupportedTokenOperation),
+        }
+    }
+    
+    /// Run a call to a token's backend through its feature gate, if it has one
+    fn gated<T>(&self, token_type: &TokenType, operation: impl FnOnce() -> Result<T, ContractError>) -> Result<T, ContractError> {
+        match self.feature_gates.for_token_type(token_type) {
+            Some(gate) => gate.call(operation),
+            None => operation(),
+        }
+    }
+    
+    /// Refuse new work for a token whose backend's feature gate is open
+    fn admit(&self, token_type: &TokenType) -> Result<(), String> {
+        match self.feature_gates.for_token_type(token_type) {
+            Some(gate) => gate.admit().map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+    
+    /// Validate a Rune token ID
+    fn validate_rune_id(&self, rune_id: &str) -> Result<(), String> {
+        if rune_id.is_empty() {
+            return Err("Rune ID cannot be empty".to_string());
+        }
+        
+        if !rune_id.starts_with("RUNE_") {
+            return Err("Rune ID must start with 'RUNE_'".to_string());
+        }
+        
+        if rune_id.len() < 10 {
+            return Err("Rune ID must be at least 10 characters long".to_string());
+        }
+        
+        if !rune_id.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return Err("Rune ID can only contain alphanumeric characters and underscores".to_string());
         }
         
         Ok(())
     }
     
+    /// Validate an Ordinal inscription ID
+    fn validate_ordinal_id(&self, inscription_id: &str) -> Result<(), String> {
+        if inscription_id.is_empty() {
+            return Err("Inscription ID cannot be empty".to_string());
+        }
+        
+        if !inscription_id.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Inscription ID must be a valid hexadecimal string".to_string());
+        }
+        
+        if inscription_id.len() < 64 {
+            return Err("Inscription ID must be at least 64 characters long".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the network type
+    pub fn get_network_type(&self) -> String {
+        "testnet".to_string()
+    }
+    
+    /// Check if the network is testnet
+    pub fn is_testnet(&self) -> bool {
+        true
+    }
+}
+
+impl TokenTransfer for BitcoinTestnetTransfer {
+    fn transfer_to_contract(&self, from_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.queue_deposit(from_address, token_type, amount, None)
+    }
+    
+    fn transfer_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.transfer_from_contract_with_priority(to_address, token_type, amount, TransferPriority::Normal)
+    }
+    
+    fn transfer_from_contract_with_priority(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<(), String> {
+        self.queue_payout(to_address, token_type, amount, priority, None)
+    }
+    
+    fn transfer_from_contract_batched(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        reference: u64,
+    ) -> Result<(), String> {
+        // Only Bitcoin payouts wait for a batch, and only if batching is on
+        let batcher = match (token_type, &self.payout_batcher) {
+            (TokenType::Bitcoin, Some(batcher)) => batcher,
+            _ => return self.queue_payout(to_address, token_type, amount, TransferPriority::Normal, Some(reference)),
+        };
+        
+        // Validate address and the script it pays to
+        self.validate_destination(to_address).map_err(|e| e.to_string())?;
+        
+        {
+            let mut batcher = batcher.lock()
+                .map_err(|_| "Failed to acquire lock".to_string())?;
+            
+            // A payout larger than a whole batch is sent on its own
+            if !batcher.accepts(amount) {
+                drop(batcher);
+                return self.queue_payout(to_address, token_type, amount, TransferPriority::Normal, Some(reference));
+            }
+            
+            batcher.add(to_address, amount, reference).map_err(|e| e.to_string())?;
+        }
+        
+        // Send the batch if its window has elapsed; failed payouts stay queued
+        if let Err(e) = self.flush_due_payouts() {
+            warn!(target: TRANSFER, "Failed to flush payout batch: {}", e);
+        }
+        
+        Ok(())
+    }
+    
+    fn transfer_from_contract_with_fee_limit(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        max_onchain_fee: Option<u64>,
+    ) -> Result<(), ContractError> {
+        // Without a ceiling, or for tokens without a mining fee, queue as usual
+        let limit = match (token_type, max_onchain_fee) {
+            (TokenType::Bitcoin, Some(limit)) => limit,
+            _ => return self.transfer_from_contract_with_priority(to_address, token_type, amount, priority)
+                .map_err(|reason| ContractError::transfer_failed(TransferStage::Withdrawal, reason)),
+        };
+        
+        // Validate address and the script it pays to
+        self.validate_destination(to_address)?;
+        
+        // Send right away rather than batching, so the caller learns whether the ceiling held
+        self.process_pending_transaction(&PendingTransaction {
+            reference: self.next_reference.fetch_add(1, Ordering::Relaxed),
+            kind: "withdraw",
+            deposit_id: None,
+            attempts: 0,
+            from_address: self.config.contract_wallet_address.clone(),
+            to_address: to_address.to_string(),
+            amount,
+            token_type: token_type.clone(),
+            priority,
+            max_onchain_fee: Some(limit),
+            coin_control: None,
+            timestamp: Instant::now(),
+            txid: None,
+        })?;
+        
+        Ok(())
+    }
+    
+    fn transfer_from_contract_with_options(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        options: &WithdrawOptions,
+    ) -> Result<(), ContractError> {
+        // Without coin control, behave like a fee-limited transfer
+        let coin_control = match &options.coin_selection {
+            Some(coin_control) => coin_control.clone(),
+            None => return self.transfer_from_contract_with_fee_limit(to_address, token_type, amount, priority, options.max_onchain_fee),
+        };
+        
+        // Only Bitcoin payouts spend contract wallet UTXOs
+        if *token_type != TokenType::Bitcoin {
+            return Err(ContractError::UnsupportedTokenOperation);
+        }
+        
+        // Validate address and the script it pays to
+        self.validate_destination(to_address)?;
+        
+        // Send right away rather than batching, so the caller learns whether the chosen inputs work
+        self.process_pending_transaction(&PendingTransaction {
+            reference: self.next_reference.fetch_add(1, Ordering::Relaxed),
+            kind: "withdraw",
+            deposit_id: None,
+            attempts: 0,
+            from_address: self.config.contract_wallet_address.clone(),
+            to_address: to_address.to_string(),
+            amount,
+            token_type: token_type.clone(),
+            priority,
+            max_onchain_fee: options.max_onchain_fee,
+            coin_control: Some(coin_control),
+            timestamp: Instant::now(),
+            txid: None,
+        })?;
+        
+        Ok(())
+    }
+    
+    fn sweep_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<Option<String>, ContractError> {
+        // Only Bitcoin is held in the contract wallet's UTXOs
+        if *token_type != TokenType::Bitcoin {
+            return Err(ContractError::UnsupportedTokenOperation);
+        }
+        
+        // Validate address and the script it pays to
+        self.validate_destination(to_address)?;
+        
+        // Unless configured otherwise, spend only change and never deposited funds
+        let coin_control = match self.config.sweep_inputs {
+            SweepInputs::Operational => {
+                let references: Vec<_> = self.rpc_client.operational_utxos().into_iter()
+                    .map(|utxo| utxo.reference)
+                    .collect();
+                if references.is_empty() {
+                    return Err(ContractError::InsufficientBalance { available: 0, required: amount });
+                }
+                Some(CoinControl::Only(references))
+            },
+            SweepInputs::Any => None,
+        };
+        
+        // Send right away; the inputs stay reserved until the broadcast so no payout spends them
+        let txid = self.process_pending_transaction(&PendingTransaction {
+            reference: self.next_reference.fetch_add(1, Ordering::Relaxed),
+            kind: "sweep",
+            deposit_id: None,
+            attempts: 0,
+            from_address: self.config.contract_wallet_address.clone(),
+            to_address: to_address.to_string(),
+            amount,
+            token_type: token_type.clone(),
+            priority: TransferPriority::Low,
+            max_onchain_fee: None,
+            coin_control,
+            timestamp: Instant::now(),
+            txid: None,
+        })?;
+        
+        Ok(Some(txid))
+    }
+    
+    fn dust_limit(&self, token_type: &TokenType) -> Option<u64> {
+        match token_type {
+            TokenType::Bitcoin => Some(self.config.dust_policy.dust_limit_sats),
+            _ => None,
+        }
+    }
+    
+    fn estimate_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<u64, String> {
+        self.quote_withdrawal_fee(to_address, token_type, amount, priority)
+            .map(|quote| quote.fee)
+    }
+    
+    fn quote_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<FeeQuote, String> {
+        match token_type {
+            TokenType::Bitcoin => {
+                // Same fee rate and input selection as the payout itself
+                let target = ConfirmationTarget::from(priority);
+                let (fee_rate, snapshot_id) = self.rpc_client.fee_oracle().fee_rate_with_snapshot(target)
+                    .map_err(|e| e.to_string())?;
+                
+                let fee = self.rpc_client.estimate_transaction_fee(&self.config.contract_wallet_address, to_address, amount, fee_rate.sat_per_vb())
+                    .map_err(|e| e.to_string())?;
+                
+                Ok(FeeQuote {
+                    fee,
+                    fee_snapshot_id: Some(snapshot_id),
+                    target: Some(target),
+                    target_blocks: Some(self.rpc_client.fee_oracle().blocks(target)),
+                })
+            },
+            _ => Err(format!("Withdrawal fee estimate not supported for {:?}", token_type)),
+        }
+    }
+    
     fn get_balance(&self, address: &str, token_type: &TokenType) -> Result<u64, String> {
         // Validate address
         self.validate_address(address)?;
         
-        // Check cache first
-        let cache_key = format!("{}:{:?}", address, token_type);
+        // Inscription ownership is always checked fresh, never from a cache
+        if let TokenType::Ordinal(inscription_id) = token_type {
+            let ordinals_client = self.ordinals_client.as_ref()
+                .ok_or_else(|| "Ordinals client not initialized".to_string())?;
+            
+            let owned = self.gated(token_type, || ordinals_client.verify_owner(inscription_id, address))
+                .map_err(|e| match e {
+                    ContractError::BackendUnavailable(_) => e.to_string(),
+                    e => format!("Failed to get inscription: {:?}", e),
+                })?;
+            
+            return Ok(if owned { 1 } else { 0 });
+        }
         
+        // Check cache first
         let mut cache = self.balance_cache.lock()
             .map_err(|_| "Failed to acquire lock".to_string())?;
         
-        if let Some((balance, timestamp)) = cache.get(&cache_key) {
-            // Cache is valid for 1 minute
-            if timestamp.elapsed() < Duration::from_secs(60) {
-                return Ok(*balance);
-            }
+        if let Some(balance) = cache.get(address, token_type) {
+            return Ok(balance);
         }
         
         // Get balance based on token type
@@ -413,21 +1385,6 @@ impl TokenTransfer for BitcoinTestnetTransfer {
                 // For now, we'll return a dummy balance
                 1000
             },
-            TokenType::Ordinal(inscription_id) => {
-                if let Some(ordinals_client) = &self.ordinals_client {
-                    // Check if the address owns the inscription
-                    let inscription = ordinals_client.get_inscription(inscription_id)
-                        .map_err(|e| format!("Failed to get inscription: {:?}", e))?;
-                    
-                    if inscription.owner == address {
-                        1 // Owner has 1 of this inscription
-                    } else {
-                        0 // Not the owner
-                    }
-                } else {
-                    return Err("Ordinals client not initialized".to_string());
-                }
-            },
             TokenType::Lightning => {
                 // In a real implementation, this would check Lightning channel balances
                 // For now, we'll return a dummy balance
@@ -437,15 +1394,44 @@ impl TokenTransfer for BitcoinTestnetTransfer {
         };
         
         // Update cache
-        cache.insert(cache_key, (balance, Instant::now()));
+        cache.insert(address, token_type, balance);
         
         Ok(balance)
     }
     
-    fn validate_address(&self, address: &str) -> Result<(), String> {
-        if !utils::validate_testnet_address(address) {
-            return Err("Invalid Bitcoin testnet address".to_string());
+    fn invalidate_balance(&self, address: &str, token_type: &TokenType) {
+        if let Ok(mut cache) = self.balance_cache.lock() {
+            cache.invalidate(address, token_type);
         }
+    }
+    
+    fn set_confirmation_policy(&self, confirmation_policy: &ConfirmationPolicy) {
+        if let Err(e) = self.reorg_watcher.set_confirmation_policy(confirmation_policy.clone()) {
+            warn!(target: TRANSFER, "Failed to update the reorg watcher's confirmation policy: {:?}", e);
+        }
+        
+        if let Err(e) = self.incoming_watcher.set_confirmation_policy(confirmation_policy.clone()) {
+            warn!(target: TRANSFER, "Failed to update the incoming funds watcher's confirmation policy: {:?}", e);
+        }
+    }
+    
+    fn track_funding(&self, deposit_id: u64, txid: &str, token_type: &TokenType, amount: u64) {
+        if let Err(e) = self.reorg_watcher.track(deposit_id, txid, token_type, amount) {
+            warn!(target: TRANSFER, "Failed to watch funding transaction {} for reorgs: {:?}", txid, e);
+        }
+    }
+    
+    fn validate_address(&self, address: &str) -> Result<(), String> {
+        self.check_network(address).map_err(|e| e.to_string())
+    }
+    
+    fn check_network(&self, address: &str) -> Result<(), ContractError> {
+        self.config.network_guard().check(address)
+    }
+    
+    fn validate_destination(&self, address: &str) -> Result<(), ContractError> {
+        self.check_network(address)?;
+        self.config.destination_policy.check_address(address)?;
         
         Ok(())
     }
@@ -463,4 +1449,239 @@ impl TokenTransfer for BitcoinTestnetTransfer {
     fn get_network_type(&self) -> String {
         "testnet".to_string()
     }
+    
+    fn get_operational_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        match token_type {
+            TokenType::Bitcoin => Ok(self.rpc_client.operational_balance()),
+            _ => Ok(0),
+        }
+    }
+    
+    fn get_sweepable_balance(&self, token_type: &TokenType) -> Result<Option<u64>, String> {
+        match (token_type, self.config.sweep_inputs) {
+            (TokenType::Bitcoin, SweepInputs::Operational) => Ok(Some(self.rpc_client.operational_balance())),
+            _ => Ok(None),
+        }
+    }
+    
+    fn feature_gate_status(&self, token_type: &TokenType) -> Option<FeatureGateStatus> {
+        self.feature_gates.for_token_type(token_type).map(|gate| gate.status())
+    }
+    
+    fn get_contract_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        let contract_address = &self.config.contract_wallet_address;
+        
+        match token_type {
+            TokenType::Bitcoin => {
+                // Bypass the balance cache, audits need the live value
+                self.rpc_client.get_address_balance(contract_address)
+                    .map_err(|e| format!("Failed to get Bitcoin balance: {:?}", e))
+            },
+            TokenType::Rune(_) => self.get_balance(contract_address, token_type),
+            TokenType::Lightning => {
+                let lightning_client = self.lightning_client.as_ref()
+                    .ok_or_else(|| "Lightning client not initialized".to_string())?;
+                
+                // Only open channels can pay out withdrawals
+                let channels = lightning_client.get_channels()
+                    .map_err(|e| format!("Failed to get Lightning channels: {:?}", e))?;
+                
+                Ok(channels.iter()
+                    .filter(|channel| channel.status == ChannelStatus::Open)
+                    .map(|channel| channel.local_balance)
+                    .sum())
+            },
+            TokenType::Ordinal(inscription_id) => {
+                let ordinals_client = self.ordinals_client.as_ref()
+                    .ok_or_else(|| "Ordinals client not initialized".to_string())?;
+                
+                let inscriptions = ordinals_client.get_inscriptions_by_address(contract_address)
+                    .map_err(|e| format!("Failed to get inscriptions: {:?}", e))?;
+                
+                Ok(inscriptions.iter().filter(|inscription| &inscription.id == inscription_id).count() as u64)
+            },
+            _ => Err("Unsupported token type for Bitcoin testnet".to_string()),
+        }
+    }
+    
+    fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+        self.rpc_client.is_utxo_unspent(utxo_reference)
+            .map_err(|e| format!("Failed to check UTXO: {:?}", e))
+    }
+    
+    fn get_utxo(&self, utxo_reference: &str) -> Result<Option<UnspentOutput>, String> {
+        let (txid, vout) = utxo_reference.split_once(':')
+            .and_then(|(txid, vout)| vout.parse::<u32>().ok().map(|vout| (txid, vout)))
+            .ok_or_else(|| format!("Invalid UTXO reference: {}", utxo_reference))?;
+        
+        self.rpc_client.get_tx_out(txid, vout)
+            .map_err(|e| format!("Failed to look up UTXO: {:?}", e))
+    }
+    
+    fn chain_anchor(&self) -> Option<ChainAnchor> {
+        self.rpc_client.cached_chain_anchor()
+    }
+    
+    fn get_inscription_details(&self, inscription_id: &str) -> Result<Option<Inscription>, String> {
+        let Some(ordinals_client) = &self.ordinals_client else {
+            return Ok(None);
+        };
+        
+        ordinals_client.get_inscription(inscription_id)
+            .map(Some)
+            .map_err(|e| format!("Failed to get inscription: {:?}", e))
+    }
+    
+    fn lightning_invoice_id(&self, payment_hash: &str) -> Option<String> {
+        let lightning_client = self.lightning_client.as_ref()?;
+        
+        lightning_client.find_invoice(payment_hash).ok().flatten().map(|invoice| invoice.id)
+    }
+    
+    fn multisig_wallet_address(&self, wallet: &str) -> Option<String> {
+        let multisig_client = self.multisig_client.as_ref()?;
+        
+        multisig_client.get_wallet(wallet).ok().map(|wallet| wallet.address.clone())
+    }
+    
+    fn transfer_queue_status(&self) -> Option<TransferQueueStatus> {
+        Some(TransferQueueStatus {
+            depth: self.pending_queue_depth(),
+            capacity: self.config.max_pending_transactions,
+        })
+    }
+    
+    fn queued_payout_references(&self) -> Vec<u64> {
+        let mut references = self.queued_payouts();
+        if let Ok(pending) = self.pending_transactions.lock() {
+            references.extend(pending.iter().filter(|tx| tx.txid.is_none() && tx.kind != "deposit").filter_map(|tx| tx.deposit_id));
+        }
+        references
+    }
+    
+    fn find_payout_transaction(&self, reference: u64) -> Result<Option<String>, String> {
+        // Payouts are labelled in the node wallet with the deposits they pay, e.g. `vault:withdraw:deposit-42,57`
+        let prefix = format!("{}withdraw:deposit-", VAULT_LABEL_PREFIX);
+        let transactions = self.rpc_client.list_vault_transactions(&prefix, usize::MAX, 0)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(transactions.into_iter()
+            .filter(|transaction| transaction.category == "send")
+            .find(|transaction| transaction.label[prefix.len()..].split(',').any(|id| id.parse() == Ok(reference)))
+            .map(|transaction| transaction.txid))
+    }
+    
+    fn transaction_confirmations(&self, txid: &str) -> Result<Option<u32>, String> {
+        match self.rpc_client.get_transaction_confirmations(txid) {
+            Ok(confirmations) => Ok(Some(confirmations)),
+            // The wallet does not know the transaction
+            Err(ContractError::RpcError {
+                source: bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Rpc(error)),
+                ..
+            }) if error.code == RPC_INVALID_ADDRESS_OR_KEY => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+    
+    fn cancel_payout(&self, reference: u64) -> bool {
+        let batched = self.payout_batcher.as_ref()
+            .and_then(|batcher| batcher.lock().ok())
+            .is_some_and(|mut batcher| batcher.remove(reference));
+        
+        let queued = self.pending_transactions.lock()
+            .map(|mut pending| {
+                let before = pending.len();
+                pending.retain(|tx| tx.txid.is_some() || tx.kind == "deposit" || tx.deposit_id != Some(reference));
+                pending.len() < before
+            })
+            .unwrap_or(false);
+        
+        batched || queued
+    }
+    
+    fn transfer_to_contract_for_deposit(&self, from_address: &str, token_type: &TokenType, amount: u64, deposit_id: u64) -> Result<(), String> {
+        self.queue_deposit(from_address, token_type, amount, Some(deposit_id))
+    }
+    
+    fn transfer_from_contract_for_deposit(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        deposit_id: u64,
+    ) -> Result<(), String> {
+        self.queue_payout(to_address, token_type, amount, priority, Some(deposit_id))
+    }
+    
+    fn find_funding_transfer(&self, deposit_id: u64) -> Result<bool, String> {
+        {
+            let pending = self.pending_transactions.lock()
+                .map_err(|_| "Failed to acquire lock".to_string())?;
+            
+            if pending.iter().any(|tx| tx.kind == "deposit" && tx.deposit_id == Some(deposit_id)) {
+                return Ok(true);
+            }
+        }
+        
+        // Sent transfers leave the queue; the node wallet still knows them by label
+        let label = format!("{}deposit:deposit-{}", VAULT_LABEL_PREFIX, deposit_id);
+        let transactions = self.rpc_client.list_vault_transactions(&label, usize::MAX, 0)
+            .map_err(|e| e.to_string())?;
+        
+        Ok(transactions.iter().any(|transaction| transaction.label == label))
+    }
+    
+    fn cancel_pending(&self, reference: u64) -> Result<bool, String> {
+        {
+            let mut pending = self.pending_transactions.lock()
+                .map_err(|_| "Failed to acquire lock".to_string())?;
+            
+            if let Some(position) = pending.iter().position(|tx| tx.kind == "deposit" && tx.deposit_id == Some(reference)) {
+                if let Some(txid) = &pending[position].txid {
+                    return Err(format!("Funding of deposit {} was already broadcast as {}", reference, txid));
+                }
+                pending.remove(position);
+                return Ok(true);
+            }
+        }
+        
+        // Sent transfers leave the queue; the node wallet still knows them by label
+        let label = format!("{}deposit:deposit-{}", VAULT_LABEL_PREFIX, reference);
+        let transactions = self.rpc_client.list_vault_transactions(&label, usize::MAX, 0)
+            .map_err(|e| e.to_string())?;
+        
+        match transactions.into_iter().find(|transaction| transaction.label == label) {
+            Some(transaction) => Err(format!("Funding of deposit {} was already broadcast as {}", reference, transaction.txid)),
+            None => Ok(false),
+        }
+    }
+    
+    fn watch_script_escrow(&self, escrow: &ScriptEscrow) {
+        if let Ok(mut escrows) = self.watched_escrows.lock() {
+            escrows.insert(escrow.address.clone(), escrow.clone());
+        }
+    }
+    
+    fn amount_constraints(&self, token_type: &TokenType) -> Result<AmountConstraints, String> {
+        match token_type {
+            TokenType::Lightning => {
+                let lightning_client = self.lightning_client.as_ref()
+                    .ok_or_else(|| "Lightning client not initialized".to_string())?;
+                let (min, max) = self.gated(token_type, || lightning_client.htlc_limits())
+                    .map_err(|e| match e {
+                        ContractError::BackendUnavailable(_) => e.to_string(),
+                        e => format!("Failed to get Lightning HTLC limits: {:?}", e),
+                    })?;
+                
+                Ok(AmountConstraints { min, max, divisibility: None })
+            },
+            TokenType::Rune(rune_id) => Ok(AmountConstraints {
+                divisibility: self.config.rune_divisibility.get(rune_id).copied(),
+                ..AmountConstraints::default()
+            }),
+            TokenType::Ordinal(_) => Ok(AmountConstraints { min: 1, max: 1, divisibility: None }),
+            _ => Ok(AmountConstraints::default()),
+        }
+    }
 }
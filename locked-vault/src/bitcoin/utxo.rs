@@ -1,7 +1,339 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use bitcoincore_rpc::bitcoin::{Script, ScriptBuf};
 use serde::{Serialize, Deserialize};
 
-use crate::errors::ContractError;
+use crate::bitcoin::payout::DUST_THRESHOLD;
+use crate::errors::{ContractError, TransferStage, UtxoRefPart};
+
+/// Virtual size of the version, locktime, counts and segwit marker, rounded up
+const TX_OVERHEAD_VSIZE: u64 = 11;
+
+/// Size allowed for outputs and overhead when the output types are unknown
+const DEFAULT_OUTPUTS_VSIZE: u64 = 70;
+
+/// Kind of output script, as far as transaction sizing is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ScriptType {
+    /// Pay to public key hash (legacy)
+    P2pkh,
+    /// Pay to script hash
+    P2sh,
+    /// Pay to witness public key hash (native segwit v0)
+    P2wpkh,
+    /// Pay to witness script hash (native segwit v0)
+    P2wsh,
+    /// Pay to taproot (segwit v1)
+    P2tr,
+    /// `OP_RETURN` data carrier, unspendable
+    NullData,
+    /// Any other or unparseable script
+    Unknown,
+}
+
+impl ScriptType {
+    /// Detect the type of an output script
+    pub fn from_script(script: &Script) -> Self {
+        if script.is_p2pkh() {
+            ScriptType::P2pkh
+        } else if script.is_p2sh() {
+            ScriptType::P2sh
+        } else if script.is_v0_p2wpkh() {
+            ScriptType::P2wpkh
+        } else if script.is_v0_p2wsh() {
+            ScriptType::P2wsh
+        } else if script.is_v1_p2tr() {
+            ScriptType::P2tr
+        } else if script.is_op_return() {
+            ScriptType::NullData
+        } else {
+            ScriptType::Unknown
+        }
+    }
+    
+    /// Detect the type of a hex-encoded output script
+    pub fn from_script_hex(script_hex: &str) -> Self {
+        ScriptBuf::from_hex(script_hex)
+            .map(|script| Self::from_script(&script))
+            .unwrap_or(ScriptType::Unknown)
+    }
+    
+    /// Virtual size of an input spending this script type, rounded up
+    /// 
+    /// Taproot assumes a key-path spend (57.5 vbytes). Script hashes can hide
+    /// anything, so they get the same conservative estimate as unknown scripts.
+    pub fn input_vsize(&self) -> u64 {
+        match self {
+            ScriptType::P2pkh => 148,
+            ScriptType::P2wpkh => 68,
+            ScriptType::P2tr => 58,
+            ScriptType::P2sh | ScriptType::P2wsh | ScriptType::NullData | ScriptType::Unknown => 180,
+        }
+    }
+    
+    /// Virtual size of an output paying to this script type
+    pub fn output_vsize(&self) -> u64 {
+        match self {
+            ScriptType::P2pkh => 34,
+            ScriptType::P2sh => 32,
+            ScriptType::P2wpkh => 31,
+            ScriptType::P2wsh | ScriptType::P2tr | ScriptType::NullData | ScriptType::Unknown => 43,
+        }
+    }
+}
+
+impl fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptType::P2pkh => write!(f, "P2PKH"),
+            ScriptType::P2sh => write!(f, "P2SH"),
+            ScriptType::P2wpkh => write!(f, "P2WPKH"),
+            ScriptType::P2wsh => write!(f, "P2WSH"),
+            ScriptType::P2tr => write!(f, "P2TR"),
+            ScriptType::NullData => write!(f, "OP_RETURN"),
+            ScriptType::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Reference to a transaction output in canonical `txid:vout` form
+/// 
+/// The txid is exactly 64 lowercase hex characters and the output index a
+/// `u32`. Parsing trims surrounding whitespace and lowercases the txid, so
+/// two references to the same output always compare equal; anything else,
+/// e.g. `txid#0`, is refused naming the malformed part. References
+/// serialize as their canonical string and also deserialize from the older
+/// `{"txid", "vout"}` object form.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "RawUtxoRef", into = "String")]
+pub struct UtxoRef {
+    /// Transaction ID, 64 lowercase hex characters
+    txid: String,
+    /// Output index
+    vout: u32,
+}
+
+impl UtxoRef {
+    /// Length of a txid in hex characters
+    pub const TXID_LEN: usize = 64;
+    
+    /// Create a reference to an output, checking and lowercasing the txid
+    pub fn new(txid: &str, vout: u32) -> Result<Self, ContractError> {
+        let invalid = |reason: String| ContractError::InvalidUtxoReference { part: UtxoRefPart::Txid, reason };
+        
+        if txid.len() != Self::TXID_LEN {
+            return Err(invalid(format!("expected {} hex characters, got {}", Self::TXID_LEN, txid.chars().count())));
+        }
+        if let Some((position, c)) = txid.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+            return Err(invalid(format!("{:?} at position {} is not a hex digit", c, position)));
+        }
+        
+        Ok(Self {
+            txid: txid.to_ascii_lowercase(),
+            vout,
+        })
+    }
+    
+    /// Transaction ID
+    pub fn txid(&self) -> &str {
+        &self.txid
+    }
+    
+    /// Output index
+    pub fn vout(&self) -> u32 {
+        self.vout
+    }
+}
+
+impl fmt::Display for UtxoRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.txid, self.vout)
+    }
+}
+
+impl FromStr for UtxoRef {
+    type Err = ContractError;
+    
+    fn from_str(reference: &str) -> Result<Self, Self::Err> {
+        let reference = reference.trim();
+        let (txid, vout) = reference.split_once(':')
+            .ok_or_else(|| ContractError::InvalidUtxoReference {
+                part: UtxoRefPart::Separator,
+                reason: format!("expected txid:vout, got {:?}", reference),
+            })?;
+        let reference = Self::new(txid, 0)?;
+        
+        // Only plain digits, so "+1" or " 1" are not taken for output 1
+        let invalid_vout = || ContractError::InvalidUtxoReference {
+            part: UtxoRefPart::Vout,
+            reason: format!("{:?} is not an output index from 0 to {}", vout, u32::MAX),
+        };
+        if vout.is_empty() || !vout.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid_vout());
+        }
+        let vout = vout.parse::<u32>().map_err(|_| invalid_vout())?;
+        
+        Ok(Self { vout, ..reference })
+    }
+}
+
+impl From<UtxoRef> for String {
+    fn from(reference: UtxoRef) -> Self {
+        reference.to_string()
+    }
+}
+
+/// Serialized forms of a `UtxoRef`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawUtxoRef {
+    /// Canonical `txid:vout` string
+    Text(String),
+    /// Object form written before references were strings
+    Parts {
+        /// Transaction ID
+        txid: String,
+        /// Output index
+        vout: u32,
+    },
+}
+
+impl TryFrom<RawUtxoRef> for UtxoRef {
+    type Error = ContractError;
+    
+    fn try_from(raw: RawUtxoRef) -> Result<Self, Self::Error> {
+        match raw {
+            RawUtxoRef::Text(reference) => reference.parse(),
+            RawUtxoRef::Parts { txid, vout } => Self::new(&txid, vout),
+        }
+    }
+}
+
+/// Coin selection algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SelectionStrategy {
+    /// Try exact match, single input with change, branch and bound, then knapsack
+    #[default]
+    Auto,
+    /// A single input matching the amount plus fee exactly, without change
+    ExactMatch,
+    /// A single input covering the amount plus fee, with change
+    SingleWithChange,
+    /// The subset of inputs wasting the least above the amount
+    BranchAndBound,
+    /// Smallest inputs first until the amount plus fee is covered
+    Knapsack,
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionStrategy::Auto => write!(f, "auto"),
+            SelectionStrategy::ExactMatch => write!(f, "exact match"),
+            SelectionStrategy::SingleWithChange => write!(f, "single with change"),
+            SelectionStrategy::BranchAndBound => write!(f, "branch and bound"),
+            SelectionStrategy::Knapsack => write!(f, "knapsack"),
+        }
+    }
+}
+
+/// What to do with change too small to be worth its own output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DustChange {
+    /// Leave the change out and let the miner have it
+    #[default]
+    AddToFee,
+    /// Create the change output regardless of its size
+    SendAnyway,
+    /// Fail the selection with `DustOutput`
+    Error,
+}
+
+/// Handling of change outputs below the dust limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DustPolicy {
+    /// Smallest output worth creating, in satoshis
+    pub dust_limit_sats: u64,
+    /// What to do with change below `dust_limit_sats`
+    pub on_dust_change: DustChange,
+}
+
+impl Default for DustPolicy {
+    fn default() -> Self {
+        Self {
+            dust_limit_sats: DUST_THRESHOLD,
+            on_dust_change: DustChange::default(),
+        }
+    }
+}
+
+impl DustPolicy {
+    /// Change to pay back after applying the policy
+    /// 
+    /// No change and change at or above the limit are kept as they are.
+    pub fn apply(&self, change: u64) -> Result<u64, ContractError> {
+        if change == 0 || change >= self.dust_limit_sats {
+            return Ok(change);
+        }
+        
+        match self.on_dust_change {
+            DustChange::AddToFee => Ok(0),
+            DustChange::SendAnyway => Ok(change),
+            DustChange::Error => Err(ContractError::DustOutput { amount: change, threshold: self.dust_limit_sats }),
+        }
+    }
+}
+
+/// Control over which contract wallet UTXOs a payout spends
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoinControl {
+    /// Spend exactly these UTXOs, which must cover the amount and fee
+    Only(Vec<UtxoRef>),
+    /// Never spend these UTXOs
+    Exclude(Vec<UtxoRef>),
+    /// Select among all UTXOs with a specific algorithm
+    Strategy(SelectionStrategy),
+}
+
+/// Change a broadcast transaction paid back to the contract wallet
+/// 
+/// Change is the vault's operational money, no longer attributed to the
+/// deposits whose withdrawals produced it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationalUtxo {
+    /// Change output
+    pub reference: UtxoRef,
+    /// Amount in satoshis
+    pub amount: u64,
+    /// Wallet label of the transaction that created it, e.g. `vault:withdraw:deposit-42`
+    pub label: Option<String>,
+}
+
+/// Contract wallet UTXOs treasury sweeps may spend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepInputs {
+    /// Only change tagged as operational, never funds attributed to deposits
+    #[default]
+    Operational,
+    /// Any UTXO of the contract wallet
+    Any,
+}
+
+impl FromStr for SweepInputs {
+    type Err = String;
+    
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "operational" => Ok(SweepInputs::Operational),
+            "any" => Ok(SweepInputs::Any),
+            _ => Err(format!("Unknown sweep inputs {} (expected operational or any)", value)),
+        }
+    }
+}
 
 /// Represents a Bitcoin UTXO
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,7 +346,7 @@ pub struct Utxo {
     pub amount: u64,
     /// Number of confirmations
     pub confirmations: u32,
-    /// Script pubkey
+    /// Script pubkey, hex encoded
     pub script_pubkey: String,
     /// Address
     pub address: String,
@@ -23,17 +355,24 @@ pub struct Utxo {
 }
 
 impl Utxo {
-    /// Get the UTXO reference string (txid:vout)
-    pub fn reference(&self) -> String {
-        format!("{}:{}", self.txid, self.vout)
+    /// Get the reference to the UTXO's output
+    /// 
+    /// Txids come from the node and are taken as they are, only lowercased.
+    pub fn reference(&self) -> UtxoRef {
+        UtxoRef {
+            txid: self.txid.to_ascii_lowercase(),
+            vout: self.vout,
+        }
+    }
+    
+    /// Get the type of the UTXO's output script
+    pub fn script_type(&self) -> ScriptType {
+        ScriptType::from_script_hex(&self.script_pubkey)
     }
     
     /// Estimate the size of the input in a transaction
     pub fn estimate_input_size(&self) -> u64 {
-        // P2PKH input size: ~148 bytes
-        // P2SH input size: ~variable, but typically larger
-        // For simplicity, we'll use a conservative estimate
-        180
+        self.script_type().input_vsize()
     }
 }
 
@@ -44,6 +383,10 @@ pub struct UtxoSet {
     utxos: HashMap<String, Utxo>,
     /// Total amount in satoshis
     total_amount: u64,
+    /// Size of the outputs and overhead of the spending transaction, if known
+    outputs_vsize: Option<u64>,
+    /// Handling of dust change, if set
+    dust_policy: Option<DustPolicy>,
 }
 
 impl UtxoSet {
@@ -52,12 +395,42 @@ impl UtxoSet {
         Self {
             utxos: HashMap::new(),
             total_amount: 0,
+            outputs_vsize: None,
+            dust_policy: None,
         }
     }
     
+    /// Size fees for a transaction paying to outputs of these types
+    /// 
+    /// Without this, selection assumes a fixed 70 vbytes for outputs and overhead.
+    pub fn set_output_types(&mut self, output_types: &[ScriptType]) {
+        let outputs: u64 = output_types.iter().map(|script_type| script_type.output_vsize()).sum();
+        self.outputs_vsize = Some(TX_OVERHEAD_VSIZE + outputs);
+    }
+    
+    /// Apply a dust policy to the change of every selection
+    /// 
+    /// Without one, change of any size is paid back.
+    pub fn set_dust_policy(&mut self, dust_policy: DustPolicy) {
+        self.dust_policy = Some(dust_policy);
+    }
+    
+    /// Apply the dust policy, if any, to a selection's change
+    fn apply_dust_policy(&self, (selected, change): (Vec<Utxo>, u64)) -> Result<(Vec<Utxo>, u64), ContractError> {
+        match &self.dust_policy {
+            Some(dust_policy) => Ok((selected, dust_policy.apply(change)?)),
+            None => Ok((selected, change)),
+        }
+    }
+    
+    /// Size of the outputs and overhead of the spending transaction
+    fn outputs_vsize(&self) -> u64 {
+        self.outputs_vsize.unwrap_or(DEFAULT_OUTPUTS_VSIZE)
+    }
+    
     /// Add a UTXO to the set
     pub fn add(&mut self, utxo: Utxo) {
-        let reference = utxo.reference();
+        let reference = utxo.reference().to_string();
         
         // Add to total amount if not already in set
         if !self.utxos.contains_key(&reference) {
@@ -108,13 +481,76 @@ impl UtxoSet {
         self.utxos.is_empty()
     }
     
+    /// Mining fee of a transaction spending every UTXO in the set
+    pub fn sweep_fee(&self, fee_rate: f64) -> u64 {
+        let tx_size = self.utxos.values().map(|utxo| utxo.estimate_input_size()).sum::<u64>() + self.outputs_vsize();
+        (tx_size as f64 * fee_rate / 1000.0) as u64
+    }
+    
     /// Select UTXOs for a transaction
-    /// Returns (selected_utxos, change_amount)
-    pub fn select_utxos(&self, amount: u64, fee_rate: f64) -> Result<(Vec<Utxo>, u64), ContractError> {
+    /// Returns (selected_utxos, change_amount), the change after the dust policy
+    pub fn select_utxos(&self, amount: u64, fee_rate: f64, strategy: SelectionStrategy) -> Result<(Vec<Utxo>, u64), ContractError> {
         if self.total_amount < amount {
-            return Err(ContractError::InsufficientBalance);
+            return Err(ContractError::InsufficientBalance { available: self.total_amount, required: amount });
+        }
+        
+        let selection = match strategy {
+            SelectionStrategy::Auto => Some(self.select_auto(amount, fee_rate)?),
+            SelectionStrategy::ExactMatch => self.select_exact_match(amount, fee_rate),
+            SelectionStrategy::SingleWithChange => self.select_single_with_change(amount, fee_rate),
+            SelectionStrategy::BranchAndBound => self.select_branch_and_bound(amount, fee_rate),
+            SelectionStrategy::Knapsack => Some(self.select_knapsack(amount, fee_rate)?),
+        };
+        
+        let selection = selection.ok_or_else(|| ContractError::TransferFailed {
+            stage: TransferStage::UtxoSelection,
+            reason: format!("No inputs found by {} selection", strategy),
+        })?;
+        
+        self.apply_dust_policy(selection)
+    }
+    
+    /// Spend exactly the referenced UTXOs, in the given order
+    /// 
+    /// Fails with `UtxoUnavailable` if a reference is not in the set and with
+    /// `InsufficientBalance` if they do not cover the amount plus fee.
+    pub fn select_references(&self, references: &[UtxoRef], amount: u64, fee_rate: f64) -> Result<(Vec<Utxo>, u64), ContractError> {
+        let mut selected: Vec<Utxo> = Vec::with_capacity(references.len());
+        
+        for reference in references {
+            // Listing an output twice would not spend it twice
+            if selected.iter().any(|utxo| utxo.reference() == *reference) {
+                continue;
+            }
+            
+            let reference = reference.to_string();
+            let utxo = self.utxos.get(&reference).ok_or_else(|| ContractError::UtxoUnavailable {
+                reference: reference.clone(),
+                reason: "not an unspent output of the wallet".to_string(),
+            })?;
+            selected.push(utxo.clone());
         }
         
+        if selected.is_empty() {
+            return Err(ContractError::InsufficientBalance { available: 0, required: amount });
+        }
+        
+        let total_selected = selected.iter()
+            .try_fold(0u64, |total, utxo| total.checked_add(utxo.amount))
+            .ok_or(ContractError::ArithmeticError)?;
+        
+        let tx_size = selected.iter().map(|utxo| utxo.estimate_input_size()).sum::<u64>() + self.outputs_vsize();
+        let fee = (tx_size as f64 * fee_rate / 1000.0) as u64;
+        
+        let change = amount.checked_add(fee)
+            .and_then(|required| total_selected.checked_sub(required))
+            .ok_or(ContractError::InsufficientBalance { available: total_selected, required: amount.saturating_add(fee) })?;
+        
+        self.apply_dust_policy((selected, change))
+    }
+    
+    /// Try the coin selection algorithms in order of preference
+    fn select_auto(&self, amount: u64, fee_rate: f64) -> Result<(Vec<Utxo>, u64), ContractError> {
         // Try coin selection algorithms in order of preference
         
         // 1. Try exact match first (most efficient)
@@ -141,7 +577,7 @@ impl UtxoSet {
         for utxo in self.utxos.values() {
             // Estimate fee for a transaction with this single input and two outputs
             // (one for payment, one for change)
-            let tx_size = utxo.estimate_input_size() + 70; // 70 bytes for outputs and overhead
+            let tx_size = utxo.estimate_input_size() + self.outputs_vsize();
             let fee = (tx_size as f64 * fee_rate / 1000.0) as u64;
             
             // Check if this UTXO exactly matches amount + fee
@@ -157,7 +593,7 @@ impl UtxoSet {
     fn select_single_with_change(&self, amount: u64, fee_rate: f64) -> Option<(Vec<Utxo>, u64)> {
         for utxo in self.utxos.values() {
             // Estimate fee for a transaction with this single input and two outputs
-            let tx_size = utxo.estimate_input_size() + 70; // 70 bytes for outputs and overhead
+            let tx_size = utxo.estimate_input_size() + self.outputs_vsize();
             let fee = (tx_size as f64 * fee_rate / 1000.0) as u64;
             
             // Check if this UTXO can cover amount + fee
@@ -249,7 +685,7 @@ impl UtxoSet {
             let total_input = selection.iter().map(|utxo| utxo.amount).sum::<u64>();
             
             // Estimate fee
-            let tx_size = selection.iter().map(|utxo| utxo.estimate_input_size()).sum::<u64>() + 70;
+            let tx_size = selection.iter().map(|utxo| utxo.estimate_input_size()).sum::<u64>() + self.outputs_vsize();
             let fee = (tx_size as f64 * fee_rate / 1000.0) as u64;
             
             // Calculate change
@@ -277,7 +713,7 @@ impl UtxoSet {
             total_selected += utxo.amount;
             
             // Estimate fee
-            let tx_size = selected.iter().map(|u| u.estimate_input_size()).sum::<u64>() + 70;
+            let tx_size = selected.iter().map(|u| u.estimate_input_size()).sum::<u64>() + self.outputs_vsize();
             let fee = (tx_size as f64 * fee_rate / 1000.0) as u64;
             
             // Check if we have enough
@@ -287,7 +723,9 @@ impl UtxoSet {
             }
         }
         
-        // If we get here, we don't have enough funds
-        Err(ContractError::InsufficientBalance)
+        // If we get here, we don't have enough funds even spending everything
+        let tx_size = selected.iter().map(|u| u.estimate_input_size()).sum::<u64>() + self.outputs_vsize();
+        let fee = (tx_size as f64 * fee_rate / 1000.0) as u64;
+        Err(ContractError::InsufficientBalance { available: total_selected, required: amount.saturating_add(fee) })
     }
 }
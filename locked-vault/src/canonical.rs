@@ -0,0 +1,311 @@
+//! Canonical byte encoding of deposits and contract state
+//!
+//! Hashes and signatures need bytes that never change for the same value,
+//! which serde_json does not promise across versions of serde, chrono or
+//! this crate. `Deposit::canonical_bytes` and `ContractState::canonical_bytes`
+//! use an explicit encoding instead:
+//!
+//! - every encoding starts with a length-prefixed domain string and a version byte
+//! - `u64` and `u32` are little-endian, `u8` is one byte, `bool` is `0` or `1`
+//! - strings are a `u32` little-endian byte length followed by UTF-8
+//! - timestamps are strings in RFC 3339, UTC with a `Z` suffix and nine fractional digits
+//! - options are a `0` tag, or a `1` tag followed by the value
+//! - enums are a one-byte tag in declaration order followed by their fields
+//! - structs are their fields in declaration order
+//! - sequences are a `u32` little-endian count followed by the items; unordered
+//!   collections are sorted by the bytes of each encoded item
+//!
+//! Any change to what is encoded or how must bump the version of the
+//! encoding it affects. The golden-file test pins the bytes of a sample
+//! deposit.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+
+use crate::contract::state::ContractState;
+use crate::models::{Deposit, DepositFee, DepositStatus, Inheritance, MaturityReminder, PendingFunding, ScriptEscrow, TokenType};
+
+/// Version of the deposit encoding
+pub const DEPOSIT_ENCODING_VERSION: u8 = 1;
+
+/// Version of the contract state encoding
+pub const STATE_ENCODING_VERSION: u8 = 1;
+
+/// Domain of the deposit encoding
+const DEPOSIT_DOMAIN: &str = "time-locked-vault/deposit";
+
+/// Domain of the contract state encoding
+const STATE_DOMAIN: &str = "time-locked-vault/contract-state";
+
+/// Writer of the canonical encoding
+#[derive(Debug, Default)]
+pub struct CanonicalEncoder {
+    /// Bytes written so far
+    bytes: Vec<u8>,
+}
+
+impl CanonicalEncoder {
+    /// Start an encoding with its domain and version
+    pub fn new(domain: &str, version: u8) -> Self {
+        let mut encoder = Self::default();
+        encoder.string(domain);
+        encoder.u8(version);
+        encoder
+    }
+    
+    /// Take the encoded bytes
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+    
+    /// Write a byte
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+    
+    /// Write a boolean
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+    
+    /// Write a `u32`, little-endian
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    
+    /// Write a `u64`, little-endian
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    
+    /// Write bytes of a length fixed by the type, without a prefix
+    pub fn fixed(&mut self, value: &[u8]) {
+        self.bytes.extend_from_slice(value);
+    }
+    
+    /// Write a length-prefixed byte string
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value);
+    }
+    
+    /// Write a length-prefixed UTF-8 string
+    pub fn string(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+    
+    /// Write a timestamp as RFC 3339 with nanosecond precision
+    pub fn timestamp(&mut self, value: &DateTime<Utc>) {
+        self.string(&value.to_rfc3339_opts(SecondsFormat::Nanos, true));
+    }
+    
+    /// Write an optional value
+    pub fn option<V>(&mut self, value: Option<V>, mut write: impl FnMut(&mut Self, V)) {
+        match value {
+            None => self.u8(0),
+            Some(value) => {
+                self.u8(1);
+                write(self, value);
+            },
+        }
+    }
+    
+    /// Write a sequence in the given order
+    pub fn sequence<V>(&mut self, items: impl ExactSizeIterator<Item = V>, mut write: impl FnMut(&mut Self, V)) {
+        self.u32(items.len() as u32);
+        for item in items {
+            write(self, item);
+        }
+    }
+    
+    /// Write an unordered collection, sorted by the encoded bytes of each item
+    pub fn sorted<V>(&mut self, items: impl Iterator<Item = V>, mut write: impl FnMut(&mut Self, V)) {
+        let mut encoded: Vec<Vec<u8>> = items
+            .map(|item| {
+                let mut encoder = Self::default();
+                write(&mut encoder, item);
+                encoder.bytes
+            })
+            .collect();
+        encoded.sort();
+        
+        self.u32(encoded.len() as u32);
+        for item in encoded {
+            self.bytes.extend_from_slice(&item);
+        }
+    }
+    
+    /// Write a token type
+    pub fn token_type(&mut self, token_type: &TokenType) {
+        match token_type {
+            TokenType::Bitcoin => self.u8(0),
+            TokenType::Ethereum => self.u8(1),
+            TokenType::Solana => self.u8(2),
+            TokenType::Rune(id) => {
+                self.u8(3);
+                self.string(id);
+            },
+            TokenType::Ordinal(id) => {
+                self.u8(4);
+                self.string(id);
+            },
+            TokenType::Lightning => self.u8(5),
+            TokenType::Custom(id) => {
+                self.u8(6);
+                self.string(id);
+            },
+        }
+    }
+    
+    /// Write a deposit status
+    fn deposit_status(&mut self, status: &DepositStatus) {
+        self.u8(match status {
+            DepositStatus::Active => 0,
+            DepositStatus::Withdrawn => 1,
+            DepositStatus::EmergencyWithdrawn => 2,
+            DepositStatus::Suspended => 3,
+            DepositStatus::Pending => 4,
+            DepositStatus::Expired => 5,
+            DepositStatus::WithdrawalPending => 6,
+            DepositStatus::Voided => 7,
+        });
+    }
+    
+    /// Write a deposit fee
+    fn deposit_fee(&mut self, fee: &DepositFee) {
+        match fee {
+            DepositFee::Flat(amount) => {
+                self.u8(0);
+                self.u64(*amount);
+            },
+            DepositFee::Percentage(percentage) => {
+                self.u8(1);
+                self.u8(*percentage);
+            },
+        }
+    }
+    
+    /// Write a token type and an amount
+    fn token_amount(&mut self, (token_type, amount): &(TokenType, u64)) {
+        self.token_type(token_type);
+        self.u64(*amount);
+    }
+}
+
+/// SHA-256 of canonical bytes
+fn hash(bytes: &[u8]) -> [u8; 32] {
+    sha256::Hash::hash(bytes).to_byte_array()
+}
+
+impl Deposit {
+    /// Get the canonical encoding of the deposit
+    ///
+    /// Every field is encoded, in declaration order.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut encoder = CanonicalEncoder::new(DEPOSIT_DOMAIN, DEPOSIT_ENCODING_VERSION);
+        self.encode_fields(&mut encoder);
+        encoder.finish()
+    }
+    
+    /// Get the SHA-256 of the canonical encoding
+    pub fn canonical_hash(&self) -> [u8; 32] {
+        hash(&self.canonical_bytes())
+    }
+    
+    /// Write the fields of the deposit
+    fn encode_fields(&self, encoder: &mut CanonicalEncoder) {
+        encoder.u64(self.deposit_id);
+        encoder.string(&self.depositor_address);
+        encoder.token_type(&self.deposited_token_type);
+        encoder.u64(self.deposited_amount);
+        encoder.timestamp(&self.deposit_timestamp);
+        encoder.timestamp(&self.unlock_timestamp);
+        encoder.deposit_status(&self.status);
+        encoder.option(self.withdrawal_tx_hash.as_deref(), CanonicalEncoder::string);
+        encoder.timestamp(&self.last_modified);
+        encoder.option(self.utxo_reference.as_ref(), |encoder, reference| encoder.string(&reference.to_string()));
+        encoder.option(self.lightning_payment_hash.as_deref(), CanonicalEncoder::string);
+        encoder.option(self.multisig_wallet.as_deref(), CanonicalEncoder::string);
+        // The invoice ID and multisig address are looked up from the node's own backend, so they are left out
+        encoder.option(self.claim_hash.as_ref(), |encoder, claim_hash| encoder.fixed(claim_hash));
+        encoder.option(self.claimed_by.as_deref(), CanonicalEncoder::string);
+        encoder.option(self.inheritance.as_ref(), |encoder, Inheritance { beneficiary_address, inactivity_days }| {
+            encoder.string(beneficiary_address);
+            encoder.u32(*inactivity_days);
+        });
+        encoder.option(self.reminder.as_ref(), |encoder, MaturityReminder { remind_days_before, notified }| {
+            encoder.u32(*remind_days_before);
+            encoder.bool(*notified);
+        });
+        encoder.option(self.pending.as_ref(), |encoder, PendingFunding { expires_at, fee_amount }| {
+            encoder.timestamp(expires_at);
+            encoder.u64(*fee_amount);
+        });
+        encoder.option(self.escrow.as_ref(), |encoder, escrow: &ScriptEscrow| {
+            encoder.string(&escrow.vault_public_key);
+            encoder.string(&escrow.depositor_public_key);
+            encoder.u32(escrow.lock_height);
+            encoder.string(&escrow.network);
+            encoder.string(&escrow.address);
+        });
+        encoder.option(self.referrer_address.as_deref(), CanonicalEncoder::string);
+    }
+}
+
+impl ContractState {
+    /// Get the canonical encoding of the state every node of a deployment shares
+    ///
+    /// Covers the ownership, the deposits and the balances and fee settings
+    /// that decide what is owed to whom. Node-local bookkeeping is left out:
+    /// the TVL series, fee ledger, authorization log, idempotency records,
+    /// reservations, quotes, activity times, token deprecations and pauses
+    /// and the operational limits and policies. Deposits are encoded by ID and other
+    /// collections sorted, so the hash does not depend on snapshot order.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut encoder = CanonicalEncoder::new(STATE_DOMAIN, STATE_ENCODING_VERSION);
+        
+        encoder.string(&self.version);
+        encoder.string(&self.owner);
+        encoder.option(self.pending_owner.as_deref(), CanonicalEncoder::string);
+        encoder.u64(self.next_deposit_id);
+        encoder.u64(self.owner_nonce);
+        encoder.bool(self.paused);
+        
+        encoder.u8(self.emergency_withdrawal_fee_percentage);
+        encoder.u8(self.insurance_share_percentage);
+        encoder.u8(self.referral_share_percentage);
+        encoder.string(&self.fee_collector_address);
+        encoder.sorted(self.deposit_fees.iter(), |encoder, (token_type, fee)| {
+            encoder.token_type(token_type);
+            encoder.deposit_fee(fee);
+        });
+        
+        let mut deposits: Vec<&Deposit> = self.deposits.iter().collect();
+        deposits.sort_by_key(|deposit| deposit.deposit_id);
+        encoder.sequence(deposits.into_iter(), |encoder, deposit| deposit.encode_fields(encoder));
+        
+        encoder.sorted(self.total_deposits.iter(), CanonicalEncoder::token_amount);
+        encoder.sorted(self.collected_fees.iter(), CanonicalEncoder::token_amount);
+        encoder.sorted(self.insurance_pool.iter(), CanonicalEncoder::token_amount);
+        encoder.sorted(self.referral_balances.iter(), |encoder, (referrer_address, token_type, amount)| {
+            encoder.string(referrer_address);
+            encoder.token_type(token_type);
+            encoder.u64(*amount);
+        });
+        encoder.u64(self.cold_storage_balance);
+        
+        encoder.sorted(self.supported_tokens.iter(), CanonicalEncoder::token_type);
+        encoder.sorted(self.consumed_utxo_references.iter(), |encoder, (utxo_reference, deposit_id)| {
+            encoder.string(&utxo_reference.to_string());
+            encoder.u64(*deposit_id);
+        });
+        encoder.sorted(self.imported_manifests.iter(), |encoder, manifest| encoder.string(manifest));
+        
+        encoder.finish()
+    }
+    
+    /// Get the SHA-256 of the canonical encoding
+    pub fn canonical_hash(&self) -> [u8; 32] {
+        hash(&self.canonical_bytes())
+    }
+}
@@ -0,0 +1,369 @@
+//! Builder-style facade over the contract for integrators
+//!
+//! `VaultClient` wraps a `TimeLockedDeposit` and its transfer backend and
+//! exposes deposits and withdrawals as builders with named parameters, so
+//! arguments cannot be passed in the wrong order. `execute` first runs the
+//! operation's dry run (`simulate_deposit`, `simulate_withdrawal`) and the
+//! balance and UTXO checks the contract would make, so most invalid requests
+//! fail before any state changes or funds move. The results wrap the
+//! contract's event together with the references of the transfer.
+
+use chrono::{DateTime, Utc};
+
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::bitcoin::utxo::UtxoRef;
+use crate::models::{TokenType, TokenTransfer, WithdrawOptions};
+use crate::contract::authorization::OperationContext;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::simulation::{DepositSimulation, WithdrawalSimulation};
+
+/// Facade over a contract and its transfer backend
+#[derive(Debug)]
+pub struct VaultClient<T: TokenTransfer> {
+    /// Wrapped contract
+    contract: TimeLockedDeposit<T>,
+}
+
+/// Result of a deposit made through `VaultClient`
+#[derive(Debug, Clone)]
+pub struct DepositOutcome {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Token type
+    pub token_type: TokenType,
+    /// Deposit fee taken from the amount
+    pub fee_amount: u64,
+    /// Net amount locked
+    pub locked_amount: u64,
+    /// When the deposit unlocks
+    pub unlock_timestamp: DateTime<Utc>,
+    /// Normalized UTXO reference backing the deposit
+    pub utxo_reference: Option<UtxoRef>,
+    /// Transaction hash of the transfer, for backends that report one
+    pub transaction_hash: Option<String>,
+    /// Event emitted by the contract
+    pub event: Event,
+}
+
+/// Result of a withdrawal made through `VaultClient`
+#[derive(Debug, Clone)]
+pub struct WithdrawalOutcome {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Token type
+    pub token_type: TokenType,
+    /// Amount paid out
+    pub amount: u64,
+    /// Transaction hash of the payout, for backends that report one
+    pub transaction_hash: Option<String>,
+    /// Event emitted by the contract
+    pub event: Event,
+}
+
+/// Deposit being assembled, see `VaultClient::deposit`
+#[derive(Debug)]
+pub struct DepositBuilder<'a, T: TokenTransfer> {
+    /// Client the deposit is made through
+    client: &'a mut VaultClient<T>,
+    /// Depositor address
+    depositor_address: Option<String>,
+    /// Token type
+    token_type: Option<TokenType>,
+    /// Gross amount
+    amount: Option<u64>,
+    /// Lock period in days
+    lock_days: Option<u32>,
+    /// UTXO reference backing the deposit
+    utxo_reference: Option<String>,
+}
+
+/// Withdrawal being assembled, see `VaultClient::withdrawal`
+#[derive(Debug)]
+pub struct WithdrawalBuilder<'a, T: TokenTransfer> {
+    /// Client the withdrawal is made through
+    client: &'a mut VaultClient<T>,
+    /// Deposit to withdraw
+    deposit_id: u64,
+    /// Depositor address receiving the payout
+    recipient_address: Option<String>,
+    /// Highest mining fee accepted, in satoshis
+    max_fee: Option<u64>,
+    /// Context recorded in the authorization log
+    context: Option<OperationContext>,
+}
+
+impl<T: TokenTransfer> VaultClient<T> {
+    /// Wrap a contract
+    pub fn new(contract: TimeLockedDeposit<T>) -> Self {
+        Self { contract }
+    }
+    
+    /// Get the wrapped contract
+    pub fn contract(&self) -> &TimeLockedDeposit<T> {
+        &self.contract
+    }
+    
+    /// Get the wrapped contract for operations the client does not cover
+    pub fn contract_mut(&mut self) -> &mut TimeLockedDeposit<T> {
+        &mut self.contract
+    }
+    
+    /// Unwrap the contract
+    pub fn into_contract(self) -> TimeLockedDeposit<T> {
+        self.contract
+    }
+    
+    /// Start a deposit
+    ///
+    /// Depositor, token, amount and lock period are required; the UTXO
+    /// reference is optional.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use time_locked_deposit::prelude::*;
+    /// use time_locked_deposit::soak::SimulatedTransfer;
+    ///
+    /// let transfer = SimulatedTransfer::new(1_000_000, HashMap::new());
+    /// let mut client = VaultClient::new(TimeLockedDeposit::new_with_defaults("tb1qowner".to_string(), 10, transfer)?);
+    ///
+    /// // Invalid requests are caught before anything moves
+    /// let result = client.deposit().from("tb1qdepositor").token(TokenType::Bitcoin).amount_sats(100_000).execute();
+    /// assert!(matches!(result, Err(ContractError::MissingParameter(_))));
+    /// let result = client.deposit().from("tb1qdepositor").token(TokenType::Bitcoin).amount_sats(5_000_000).lock_days(30).simulate();
+    /// assert!(matches!(result, Err(ContractError::InsufficientBalance { .. })));
+    ///
+    /// let txid = "ab".repeat(32);
+    /// let deposit = client.deposit()
+    ///     .from("tb1qdepositor")
+    ///     .token(TokenType::Bitcoin)
+    ///     .amount_sats(100_000)
+    ///     .lock_days(30)
+    ///     .utxo(format!("{}:0", txid.to_uppercase()))
+    ///     .execute()?;
+    /// assert_eq!(deposit.locked_amount, 100_000);
+    /// assert_eq!(deposit.utxo_reference.map(|reference| reference.to_string()), Some(format!("{}:0", txid)));
+    ///
+    /// // The same UTXO cannot back a second deposit
+    /// let result = client.deposit().from("tb1qdepositor").token(TokenType::Bitcoin).amount_sats(100_000).lock_days(30).utxo(format!("{}:0", txid)).simulate();
+    /// assert!(matches!(result, Err(ContractError::UtxoAlreadyDeposited)));
+    /// # Ok::<(), ContractError>(())
+    /// ```
+    pub fn deposit(&mut self) -> DepositBuilder<'_, T> {
+        DepositBuilder {
+            client: self,
+            depositor_address: None,
+            token_type: None,
+            amount: None,
+            lock_days: None,
+            utxo_reference: None,
+        }
+    }
+    
+    /// Start the withdrawal of a deposit after its lock
+    ///
+    /// The depositor address the payout goes to is required; the fee limit
+    /// is optional.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::sync::Arc;
+    /// use time_locked_deposit::prelude::*;
+    /// use time_locked_deposit::soak::SimulatedTransfer;
+    ///
+    /// let transfer = SimulatedTransfer::new(1_000_000, HashMap::new());
+    /// let mut contract = TimeLockedDeposit::new_with_defaults("tb1qowner".to_string(), 10, transfer)?;
+    /// let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+    /// contract.set_clock(clock.clone());
+    /// let mut client = VaultClient::new(contract);
+    ///
+    /// let deposit = client.deposit().from("tb1qdepositor").token(TokenType::Bitcoin).amount_sats(100_000).lock_days(30).execute()?;
+    ///
+    /// // Locked deposits and other depositors are rejected up front
+    /// assert!(matches!(client.withdrawal(deposit.deposit_id).to("tb1qdepositor").simulate(), Err(ContractError::DepositLocked)));
+    /// clock.advance(chrono::Duration::days(30));
+    /// assert!(matches!(client.withdrawal(deposit.deposit_id).to("tb1qsomeone").simulate(), Err(ContractError::Unauthorized)));
+    ///
+    /// let withdrawal = client.withdrawal(deposit.deposit_id)
+    ///     .to("tb1qdepositor")
+    ///     .max_fee_sats(500)
+    ///     .execute()?;
+    /// assert_eq!(withdrawal.amount, 100_000);
+    /// assert_eq!(withdrawal.token_type, TokenType::Bitcoin);
+    /// # Ok::<(), ContractError>(())
+    /// ```
+    pub fn withdrawal(&mut self, deposit_id: u64) -> WithdrawalBuilder<'_, T> {
+        WithdrawalBuilder {
+            client: self,
+            deposit_id,
+            recipient_address: None,
+            max_fee: None,
+            context: None,
+        }
+    }
+}
+
+impl<'a, T: TokenTransfer> DepositBuilder<'a, T> {
+    /// Set the depositor address the funds come from
+    pub fn from(mut self, depositor_address: impl Into<String>) -> Self {
+        self.depositor_address = Some(depositor_address.into());
+        self
+    }
+    
+    /// Set the token type
+    pub fn token(mut self, token_type: TokenType) -> Self {
+        self.token_type = Some(token_type);
+        self
+    }
+    
+    /// Set the gross amount, in satoshis or the token's base units
+    pub fn amount_sats(mut self, amount: u64) -> Self {
+        self.amount = Some(amount);
+        self
+    }
+    
+    /// Set the lock period in days
+    pub fn lock_days(mut self, lock_days: u32) -> Self {
+        self.lock_days = Some(lock_days);
+        self
+    }
+    
+    /// Set the UTXO reference (`txid:vout`) backing the deposit
+    pub fn utxo(mut self, utxo_reference: impl Into<String>) -> Self {
+        self.utxo_reference = Some(utxo_reference.into());
+        self
+    }
+    
+    /// Check the deposit without making it
+    ///
+    /// Fails with the error `execute` would fail with for an invalid request.
+    pub fn simulate(&self) -> Result<DepositSimulation, ContractError> {
+        let (depositor_address, token_type, amount, lock_days) = self.required()?;
+        let contract = &self.client.contract;
+        
+        // Same checks as a real deposit
+        let simulation = contract.simulate_deposit(depositor_address, token_type, amount, lock_days)?;
+        
+        // Each UTXO may back only one deposit
+        if let Some(reference) = self.utxo_reference()? {
+            contract.utxo_registry.ensure_available(&reference)?;
+        }
+        
+        // Check the depositor can pay
+        let balance = contract.token_transfer.get_balance(depositor_address, token_type)
+            .map_err(|reason| ContractError::TransferFailed { stage: TransferStage::BalanceCheck, reason })?;
+        if balance < amount {
+            return Err(ContractError::InsufficientBalance { available: balance, required: amount });
+        }
+        
+        Ok(simulation)
+    }
+    
+    /// Check and make the deposit
+    pub fn execute(self) -> Result<DepositOutcome, ContractError> {
+        self.simulate()?;
+        
+        let (depositor_address, token_type, amount, lock_days) = self.required()?;
+        let event = self.client.contract.deposit(
+            depositor_address.to_string(),
+            token_type.clone(),
+            amount,
+            lock_days,
+            self.utxo_reference()?,
+        )?;
+        
+        let outcome = match &event {
+            Event::Deposited { deposit_id, token_type, fee_amount, locked_amount, unlock_timestamp, transaction_hash, .. } => DepositOutcome {
+                deposit_id: *deposit_id,
+                token_type: token_type.clone(),
+                fee_amount: *fee_amount,
+                locked_amount: *locked_amount,
+                unlock_timestamp: *unlock_timestamp,
+                utxo_reference: self.client.contract.deposit_registry.get(deposit_id)
+                    .and_then(|deposit| deposit.utxo_reference.clone()),
+                transaction_hash: transaction_hash.clone(),
+                event: event.clone(),
+            },
+            _ => return Err(ContractError::DepositNotFound),
+        };
+        
+        Ok(outcome)
+    }
+    
+    /// Parse the UTXO reference, if one was set
+    fn utxo_reference(&self) -> Result<Option<UtxoRef>, ContractError> {
+        self.utxo_reference.as_deref().map(str::parse).transpose()
+    }
+    
+    /// Get the required parameters, naming the first one missing
+    fn required(&self) -> Result<(&str, &TokenType, u64, u32), ContractError> {
+        let depositor_address = self.depositor_address.as_deref()
+            .ok_or_else(|| ContractError::MissingParameter("from".to_string()))?;
+        let token_type = self.token_type.as_ref()
+            .ok_or_else(|| ContractError::MissingParameter("token".to_string()))?;
+        let amount = self.amount
+            .ok_or_else(|| ContractError::MissingParameter("amount_sats".to_string()))?;
+        let lock_days = self.lock_days
+            .ok_or_else(|| ContractError::MissingParameter("lock_days".to_string()))?;
+        
+        Ok((depositor_address, token_type, amount, lock_days))
+    }
+}
+
+impl<'a, T: TokenTransfer> WithdrawalBuilder<'a, T> {
+    /// Set the depositor address the payout goes to
+    pub fn to(mut self, recipient_address: impl Into<String>) -> Self {
+        self.recipient_address = Some(recipient_address.into());
+        self
+    }
+    
+    /// Set the highest mining fee accepted, in satoshis
+    pub fn max_fee_sats(mut self, max_fee: u64) -> Self {
+        self.max_fee = Some(max_fee);
+        self
+    }
+    
+    /// Set the context recorded in the authorization log, `Internal` if not set
+    pub fn context(mut self, context: OperationContext) -> Self {
+        self.context = Some(context);
+        self
+    }
+    
+    /// Check the withdrawal without making it
+    ///
+    /// Fails with the error `execute` would fail with for a deposit that
+    /// cannot be withdrawn yet.
+    pub fn simulate(&self) -> Result<WithdrawalSimulation, ContractError> {
+        let recipient_address = self.required()?;
+        
+        self.client.contract.simulate_withdrawal(recipient_address, self.deposit_id, self.max_fee)
+    }
+    
+    /// Check and make the withdrawal
+    pub fn execute(self) -> Result<WithdrawalOutcome, ContractError> {
+        self.simulate()?;
+        
+        let recipient_address = self.required()?.to_string();
+        let options = WithdrawOptions { max_onchain_fee: self.max_fee, ..Default::default() };
+        let event = self.client.contract.withdraw_internal(recipient_address, self.deposit_id, options, self.context.as_ref())?;
+        
+        let outcome = match &event {
+            Event::Withdrawn { deposit_id, token_type, withdrawn_amount, transaction_hash, .. } => WithdrawalOutcome {
+                deposit_id: *deposit_id,
+                token_type: token_type.clone(),
+                amount: *withdrawn_amount,
+                transaction_hash: transaction_hash.clone(),
+                event: event.clone(),
+            },
+            _ => return Err(ContractError::DepositNotFound),
+        };
+        
+        Ok(outcome)
+    }
+    
+    /// Get the recipient address, which is required
+    fn required(&self) -> Result<&str, ContractError> {
+        self.recipient_address.as_deref()
+            .ok_or_else(|| ContractError::MissingParameter("to".to_string()))
+    }
+}
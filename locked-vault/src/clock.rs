@@ -0,0 +1,60 @@
+//! Time source for the contract
+//!
+//! Time locks, cooldowns and rate windows read the current time through a
+//! `Clock` so tests and simulations can control it.
+
+use std::fmt;
+use std::sync::Mutex;
+use chrono::{DateTime, Duration, Utc};
+
+/// Source of the current time
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Get the current time
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Clock reading the system time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Manually driven clock for tests and simulations
+#[derive(Debug)]
+pub struct MockClock {
+    /// Current time
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl MockClock {
+    /// Create a clock frozen at the given time
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+    
+    /// Move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        if let Ok(mut now) = self.now.lock() {
+            *now += duration;
+        }
+    }
+    
+    /// Set the clock to a specific time
+    pub fn set(&self, time: DateTime<Utc>) {
+        if let Ok(mut now) = self.now.lock() {
+            *now = time;
+        }
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.lock().map(|now| *now).unwrap_or_else(|_| Utc::now())
+    }
+}
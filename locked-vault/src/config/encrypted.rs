@@ -0,0 +1,117 @@
+//! Passphrase-encrypted config files (`encrypted-config` feature)
+//!
+//! Argon2id stretches the passphrase with a random 16-byte salt into a key
+//! for XChaCha20-Poly1305, the same primitives as encrypted state. The cost
+//! parameters come from the file, so they are checked against
+//! `MAX_MEMORY_KIB` and `MAX_ITERATIONS` before anything is derived.
+//!
+//! The file is a single line: `MAGIC` followed by the base64 of the Argon2id
+//! memory, iterations and parallelism (4 bytes each, big endian), salt,
+//! nonce and ciphertext. Everything before the ciphertext is authenticated
+//! along with it.
+
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rand::RngCore;
+
+use crate::config::secret::Secret;
+use crate::store::aead::{self, KEY_LEN, NONCE_LEN, TAG_LEN};
+use crate::store::argon2::{argon2id, Argon2Params};
+
+/// Marker at the start of an encrypted config file
+pub const MAGIC: &str = "vault-encrypted-config:v2:";
+
+/// Start of the marker of every format version
+const MAGIC_PREFIX: &str = "vault-encrypted-config:";
+
+/// Salt length in bytes
+const SALT_LEN: usize = 16;
+
+/// Header length in bytes, after the marker
+const HEADER_LEN: usize = 12 + SALT_LEN + NONCE_LEN;
+
+/// Whether file contents are an encrypted config, of any format version
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC_PREFIX.as_bytes())
+}
+
+/// Encrypt config file contents with a passphrase
+pub fn encrypt(plaintext: &[u8], passphrase: &Secret<String>) -> Result<String, String> {
+    encrypt_with_params(plaintext, passphrase, Argon2Params::default())
+}
+
+/// Encrypt config file contents with a passphrase and an Argon2id cost
+pub fn encrypt_with_params(plaintext: &[u8], passphrase: &Secret<String>, params: Argon2Params) -> Result<String, String> {
+    if passphrase.is_empty() {
+        return Err("Passphrase cannot be empty".to_string());
+    }
+    
+    let mut salt = [0u8; SALT_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    
+    let header = [
+        &params.memory_kib.to_be_bytes()[..],
+        &params.iterations.to_be_bytes(),
+        &params.parallelism.to_be_bytes(),
+        &salt,
+        &nonce,
+    ].concat();
+    let key = derive_key(passphrase, &salt, &params)?;
+    let ciphertext = aead::seal(&key, &nonce, &associated_data(&header), plaintext)?;
+    
+    Ok(format!("{}{}\n", MAGIC, BASE64.encode([header, ciphertext].concat())))
+}
+
+/// Decrypt an encrypted config file
+///
+/// A wrong passphrase and a tampered file fail the same way.
+pub fn decrypt(contents: &[u8], passphrase: &Secret<String>) -> Result<Secret<Vec<u8>>, String> {
+    let contents = std::str::from_utf8(contents)
+        .map(str::trim)
+        .map_err(|_| "Not an encrypted config file".to_string())?;
+    let encoded = match contents.strip_prefix(MAGIC) {
+        Some(encoded) => encoded,
+        None if is_encrypted(contents.as_bytes()) => {
+            return Err("Encrypted config file is in an unsupported format; decrypt it with the release that wrote it and encrypt it again".to_string());
+        },
+        None => return Err("Not an encrypted config file".to_string()),
+    };
+    
+    let payload = BASE64.decode(encoded)
+        .map_err(|e| format!("Malformed encrypted config file: {}", e))?;
+    
+    if payload.len() < HEADER_LEN + TAG_LEN {
+        return Err("Malformed encrypted config file: too short".to_string());
+    }
+    
+    let (header, ciphertext) = payload.split_at(HEADER_LEN);
+    let word = |at: usize| u32::from_be_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]]);
+    let params = Argon2Params { memory_kib: word(0), iterations: word(4), parallelism: word(8) };
+    let salt = &header[12..12 + SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&header[12 + SALT_LEN..]);
+    
+    // Check the cost before deriving, so a forged file cannot stall startup
+    params.validate().map_err(|reason| format!("Malformed encrypted config file: {}", reason))?;
+    
+    let key = derive_key(passphrase, salt, &params)?;
+    aead::open(&key, &nonce, &associated_data(header), ciphertext)
+        .map(Secret::new)
+        .ok_or_else(|| "Wrong passphrase or corrupted encrypted config file".to_string())
+}
+
+/// Stretch the passphrase into the encryption key
+fn derive_key(passphrase: &Secret<String>, salt: &[u8], params: &Argon2Params) -> Result<[u8; KEY_LEN], String> {
+    let derived = Secret::new(argon2id(passphrase.expose_secret().as_bytes(), salt, &[], &[], params, KEY_LEN)?);
+    
+    let mut key = [0u8; KEY_LEN];
+    key.copy_from_slice(derived.expose_secret());
+    Ok(key)
+}
+
+/// Associated data of a file: the marker and the header
+fn associated_data(header: &[u8]) -> Vec<u8> {
+    [MAGIC.as_bytes(), header].concat()
+}
@@ -0,0 +1,323 @@
+//! Vault configuration
+//!
+//! Settings come from a JSON config file or, without one, from environment
+//! variables. Credentials in either place may be given inline, as
+//! `file:<path>` or as `env:<VAR>`; they are resolved once at load time into
+//! `Secret`s, which never show up in logs or debug output. With the
+//! `encrypted-config` feature the config file itself may be encrypted with a
+//! passphrase (see `time_locked_deposit config encrypt`). A state
+//! passphrase or key file turns on encryption of persisted state, and
+//! `snapshot_codec` picks the encoding snapshot files are written in.
+
+pub mod secret;
+#[cfg(feature = "encrypted-config")]
+pub mod encrypted;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use bitcoincore_rpc::bitcoin::Network;
+use serde::Deserialize;
+
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::network::NetworkGuard;
+use crate::bitcoin::ordinals::OrdinalsApiFlavor;
+use crate::bitcoin::template::DestinationPolicy;
+use crate::bitcoin::testnet::BitcoinTestnetConfig;
+use crate::bitcoin::utxo::{DustPolicy, SweepInputs};
+use crate::store::{SnapshotCodec, StateKey};
+
+// Re-export commonly used types
+pub use secret::{Secret, SecretSource, Zeroize, REDACTED};
+
+/// Default RPC URL of a local testnet node
+const DEFAULT_RPC_URL: &str = "http://localhost:18332";
+
+/// Default testnet address for the contract wallet and owner
+const DEFAULT_ADDRESS: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+
+/// Connection settings and credentials of the vault daemon
+#[derive(Debug, Clone)]
+pub struct VaultConfig {
+    /// RPC URL for Bitcoin testnet node
+    pub rpc_url: String,
+    /// RPC username
+    pub rpc_username: String,
+    /// RPC password
+    pub rpc_password: Secret<String>,
+    /// Contract wallet address
+    pub contract_wallet_address: String,
+    /// Contract owner address
+    pub owner_address: String,
+    /// Test network the vault runs on
+    pub network: Network,
+    /// Lightning node URL
+    pub lightning_node_url: Option<String>,
+    /// Macaroon authenticating to the Lightning node
+    pub lightning_macaroon: Option<Secret<String>>,
+    /// Ordinals API URL
+    pub ordinals_api_url: Option<String>,
+    /// Flavor of the Ordinals API
+    pub ordinals_api_flavor: OrdinalsApiFlavor,
+    /// Confirmations required by token type and amount
+    pub confirmation_policy: ConfirmationPolicy,
+    /// Handling of dust change and the dust limit of withdrawals
+    pub dust_policy: DustPolicy,
+    /// UTXOs treasury sweeps may spend
+    pub sweep_inputs: SweepInputs,
+    /// Script types payouts, fee withdrawals and sweeps may pay to
+    pub destination_policy: DestinationPolicy,
+    /// Key persisted state is encrypted with, `None` to keep it in plaintext
+    pub state_key: Option<StateKey>,
+    /// Codec snapshot files are written in
+    pub snapshot_codec: SnapshotCodec,
+}
+
+/// Config file contents before credentials are resolved
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// RPC URL for Bitcoin testnet node
+    rpc_url: String,
+    /// RPC username
+    rpc_username: String,
+    /// RPC password source
+    rpc_password: SecretSource,
+    /// Contract wallet address
+    contract_wallet_address: String,
+    /// Contract owner address
+    owner_address: String,
+    /// Test network the vault runs on
+    #[serde(default = "default_network")]
+    network: Network,
+    /// Lightning node URL
+    #[serde(default)]
+    lightning_node_url: Option<String>,
+    /// Lightning macaroon source
+    #[serde(default)]
+    lightning_macaroon: Option<SecretSource>,
+    /// Ordinals API URL
+    #[serde(default)]
+    ordinals_api_url: Option<String>,
+    /// Flavor of the Ordinals API
+    #[serde(default)]
+    ordinals_api_flavor: OrdinalsApiFlavor,
+    /// Confirmations required by token type and amount
+    #[serde(default)]
+    confirmation_policy: ConfirmationPolicy,
+    /// Handling of dust change and the dust limit of withdrawals
+    #[serde(default)]
+    dust_policy: DustPolicy,
+    /// UTXOs treasury sweeps may spend
+    #[serde(default)]
+    sweep_inputs: SweepInputs,
+    /// Script types payouts, fee withdrawals and sweeps may pay to
+    #[serde(default)]
+    destination_policy: DestinationPolicy,
+    /// State encryption passphrase source
+    #[serde(default)]
+    state_passphrase: Option<SecretSource>,
+    /// File holding the state encryption key
+    #[serde(default)]
+    state_key_file: Option<PathBuf>,
+    /// Codec snapshot files are written in
+    #[serde(default)]
+    snapshot_codec: SnapshotCodec,
+}
+
+impl VaultConfig {
+    /// Parse a JSON config and resolve its credentials
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let file: ConfigFile = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid config file: {}", e))?;
+        
+        let state_passphrase = file.state_passphrase
+            .map(|source| resolve("state_passphrase", &source))
+            .transpose()?;
+        let state_key = StateKey::from_settings(state_passphrase, file.state_key_file.as_deref())
+            .map_err(|e| e.to_string())?;
+        
+        Ok(Self {
+            rpc_url: file.rpc_url,
+            rpc_username: file.rpc_username,
+            rpc_password: resolve("rpc_password", &file.rpc_password)?,
+            contract_wallet_address: file.contract_wallet_address,
+            owner_address: file.owner_address,
+            network: file.network,
+            lightning_node_url: file.lightning_node_url,
+            lightning_macaroon: file.lightning_macaroon
+                .map(|source| resolve("lightning_macaroon", &source))
+                .transpose()?,
+            ordinals_api_url: file.ordinals_api_url,
+            ordinals_api_flavor: file.ordinals_api_flavor,
+            confirmation_policy: file.confirmation_policy,
+            dust_policy: file.dust_policy,
+            sweep_inputs: file.sweep_inputs,
+            destination_policy: file.destination_policy,
+            state_key,
+            snapshot_codec: file.snapshot_codec,
+        })
+    }
+    
+    /// Load a config file, decrypting it first if it is encrypted
+    pub fn load(path: &Path, passphrase: Option<&Secret<String>>) -> Result<Self, String> {
+        let contents = Secret::new(std::fs::read(path)
+            .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?);
+        
+        #[cfg(feature = "encrypted-config")]
+        if encrypted::is_encrypted(contents.expose_secret()) {
+            let passphrase = passphrase
+                .ok_or_else(|| format!("{} is encrypted; set VAULT_CONFIG_PASSPHRASE", path.display()))?;
+            let plaintext = encrypted::decrypt(contents.expose_secret(), passphrase)?;
+            
+            return Self::from_json(&String::from_utf8_lossy(plaintext.expose_secret()));
+        }
+        
+        #[cfg(not(feature = "encrypted-config"))]
+        if contents.expose_secret().starts_with(b"vault-encrypted-config:") {
+            return Err(format!("{} is encrypted but this build lacks the encrypted-config feature", path.display()));
+        }
+        
+        let json = std::str::from_utf8(contents.expose_secret())
+            .map_err(|_| format!("Config file {} is not UTF-8", path.display()))?;
+        
+        Self::from_json(json)
+    }
+    
+    /// Read the configuration from environment variables, with local testnet defaults
+    ///
+    /// `BITCOIN_TESTNET_RPC_PASSWORD`, `LIGHTNING_MACAROON` and
+    /// `VAULT_STATE_PASSPHRASE` accept the same `file:` and `env:` sources as
+    /// the config file. `BITCOIN_NETWORK` names the test network (`testnet`
+    /// by default, `signet` or `regtest`). `CONFIRMATION_POLICY`, `DUST_POLICY` and
+    /// `DESTINATION_POLICY` hold the confirmation, dust and destination
+    /// policies as JSON, `SWEEP_INPUTS` what treasury
+    /// sweeps may spend (`operational` or `any`), `VAULT_SNAPSHOT_CODEC` the
+    /// snapshot codec (`json` or `binary`).
+    pub fn from_env() -> Result<Self, String> {
+        let rpc_password: SecretSource = env::var("BITCOIN_TESTNET_RPC_PASSWORD")
+            .unwrap_or_else(|_| "testpassword".to_string())
+            .parse()?;
+        
+        let lightning_macaroon = env::var("LIGHTNING_MACAROON")
+            .ok()
+            .map(|value| value.parse::<SecretSource>())
+            .transpose()?
+            .map(|source| resolve("LIGHTNING_MACAROON", &source))
+            .transpose()?;
+        
+        let confirmation_policy = match env::var("CONFIRMATION_POLICY") {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid CONFIRMATION_POLICY: {}", e))?,
+            Err(_) => ConfirmationPolicy::default(),
+        };
+        
+        let dust_policy = match env::var("DUST_POLICY") {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid DUST_POLICY: {}", e))?,
+            Err(_) => DustPolicy::default(),
+        };
+        
+        let destination_policy = match env::var("DESTINATION_POLICY") {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid DESTINATION_POLICY: {}", e))?,
+            Err(_) => DestinationPolicy::default(),
+        };
+        
+        Ok(Self {
+            rpc_url: env::var("BITCOIN_TESTNET_RPC_URL")
+                .unwrap_or_else(|_| DEFAULT_RPC_URL.to_string()),
+            rpc_username: env::var("BITCOIN_TESTNET_RPC_USERNAME")
+                .unwrap_or_else(|_| "testuser".to_string()),
+            rpc_password: resolve("BITCOIN_TESTNET_RPC_PASSWORD", &rpc_password)?,
+            contract_wallet_address: env::var("BITCOIN_TESTNET_CONTRACT_WALLET")
+                .unwrap_or_else(|_| DEFAULT_ADDRESS.to_string()),
+            owner_address: env::var("BITCOIN_TESTNET_OWNER_ADDRESS")
+                .unwrap_or_else(|_| DEFAULT_ADDRESS.to_string()),
+            network: match env::var("BITCOIN_NETWORK") {
+                Ok(name) => Network::from_str(&name)
+                    .map_err(|_| format!("Invalid BITCOIN_NETWORK: {}", name))?,
+                Err(_) => default_network(),
+            },
+            lightning_node_url: env::var("LIGHTNING_NODE_URL").ok(),
+            lightning_macaroon,
+            ordinals_api_url: env::var("ORDINALS_API_URL").ok(),
+            ordinals_api_flavor: env::var("ORDINALS_API_FLAVOR")
+                .map(|value| value.parse())
+                .unwrap_or(Ok(OrdinalsApiFlavor::default()))?,
+            confirmation_policy,
+            dust_policy,
+            sweep_inputs: env::var("SWEEP_INPUTS")
+                .map(|value| value.parse())
+                .unwrap_or(Ok(SweepInputs::default()))?,
+            destination_policy,
+            state_key: state_key_from_env("VAULT_STATE_PASSPHRASE", "VAULT_STATE_KEY_FILE")?,
+            snapshot_codec: env::var("VAULT_SNAPSHOT_CODEC")
+                .map(|value| value.parse())
+                .unwrap_or(Ok(SnapshotCodec::default()))?,
+        })
+    }
+    
+    /// Guard refusing addresses of other networks than the configured one
+    pub fn network_guard(&self) -> NetworkGuard {
+        NetworkGuard::new(self.network)
+    }
+    
+    /// Check that the network is a test network and the owner and contract wallet addresses belong to it
+    pub fn validate_addresses(&self) -> Result<(), String> {
+        if self.network == Network::Bitcoin {
+            return Err("The vault runs on test networks only, not on mainnet".to_string());
+        }
+        
+        let guard = self.network_guard();
+        guard.check(&self.owner_address)
+            .map_err(|e| format!("Invalid owner address {}: {}", self.owner_address, e))?;
+        guard.check(&self.contract_wallet_address)
+            .map_err(|e| format!("Invalid contract wallet address {}: {}", self.contract_wallet_address, e))?;
+        
+        Ok(())
+    }
+    
+    /// Build the Bitcoin testnet transfer configuration
+    pub fn bitcoin_config(&self) -> BitcoinTestnetConfig {
+        let mut config = BitcoinTestnetConfig::new(
+            self.rpc_url.clone(),
+            self.rpc_username.clone(),
+            self.rpc_password.clone(),
+            self.contract_wallet_address.clone(),
+        );
+        config.network = self.network;
+        config.lightning_macaroon = self.lightning_macaroon.clone();
+        config.ordinals_api_flavor = self.ordinals_api_flavor;
+        config.confirmation_policy = self.confirmation_policy.clone();
+        config.dust_policy = self.dust_policy;
+        config.sweep_inputs = self.sweep_inputs;
+        config.destination_policy = self.destination_policy.clone();
+        
+        config
+    }
+}
+
+/// Network a vault runs on unless configured otherwise
+fn default_network() -> Network {
+    Network::Testnet
+}
+
+/// Resolve the state encryption key from a passphrase and a key file variable, `None` if neither is set
+pub fn state_key_from_env(passphrase_variable: &str, key_file_variable: &str) -> Result<Option<StateKey>, String> {
+    let passphrase = env::var(passphrase_variable)
+        .ok()
+        .map(|value| value.parse::<SecretSource>())
+        .transpose()?
+        .map(|source| resolve(passphrase_variable, &source))
+        .transpose()?;
+    let key_file = env::var(key_file_variable).ok().map(PathBuf::from);
+    
+    StateKey::from_settings(passphrase, key_file.as_deref())
+        .map_err(|e| e.to_string())
+}
+
+/// Resolve a credential, naming the setting in errors
+fn resolve(setting: &str, source: &SecretSource) -> Result<Secret<String>, String> {
+    source.resolve().map_err(|e| format!("{}: {}", setting, e))
+}
@@ -0,0 +1,157 @@
+//! Credentials that must not leak
+//!
+//! `Secret` wraps a credential so formatting never shows it and dropping it
+//! overwrites its memory. `SecretSource` says where a credential comes from
+//! in a config file or environment variable: inline, `file:<path>` or
+//! `env:<VAR>`, resolved once when the configuration is loaded.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+
+pub use zeroize::Zeroize;
+
+/// Placeholder shown instead of a secret
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Prefix of a secret read from a file
+const FILE_PREFIX: &str = "file:";
+
+/// Prefix of a secret read from an environment variable
+const ENV_PREFIX: &str = "env:";
+
+/// Credential that is redacted when formatted and zeroized when dropped
+///
+/// Deliberately not `Serialize`, so a secret cannot end up in an exported
+/// config or API response by accident, and not `PartialEq`, whose early
+/// exit would leak through timing how much of a secret matched. Use
+/// `expose_secret` at the point the credential is handed to the library
+/// that needs it.
+#[derive(Clone, Default)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    /// Wrap a credential
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+    
+    /// Get the credential
+    pub fn expose_secret(&self) -> &T {
+        &self.0
+    }
+}
+
+impl Secret<String> {
+    /// Whether the credential is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<String> for Secret<String> {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Where a credential comes from
+#[derive(Clone)]
+pub enum SecretSource {
+    /// The value itself
+    Inline(Secret<String>),
+    /// Contents of a file, without the trailing newline
+    File(PathBuf),
+    /// Value of an environment variable
+    Env(String),
+}
+
+impl SecretSource {
+    /// Read the credential from its source
+    ///
+    /// Errors name the source, never the value.
+    pub fn resolve(&self) -> Result<Secret<String>, String> {
+        match self {
+            SecretSource::Inline(secret) => Ok(secret.clone()),
+            SecretSource::File(path) => {
+                let mut contents = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read secret file {}: {}", path.display(), e))?;
+                
+                // Drop the trailing newline most editors and `echo` add
+                let trimmed = contents.trim_end_matches(['\r', '\n']).len();
+                contents.truncate(trimmed);
+                
+                Ok(Secret::new(contents))
+            },
+            SecretSource::Env(variable) => std::env::var(variable)
+                .map(Secret::new)
+                .map_err(|_| format!("Secret environment variable {} is not set", variable)),
+        }
+    }
+}
+
+impl FromStr for SecretSource {
+    type Err = String;
+    
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = value.strip_prefix(FILE_PREFIX) {
+            if path.is_empty() {
+                return Err("Secret file path cannot be empty".to_string());
+            }
+            return Ok(SecretSource::File(PathBuf::from(path)));
+        }
+        
+        if let Some(variable) = value.strip_prefix(ENV_PREFIX) {
+            if variable.is_empty() {
+                return Err("Secret environment variable name cannot be empty".to_string());
+            }
+            return Ok(SecretSource::Env(variable.to_string()));
+        }
+        
+        Ok(SecretSource::Inline(Secret::from(value)))
+    }
+}
+
+impl fmt::Debug for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretSource::Inline(_) => write!(f, "Inline({})", REDACTED),
+            SecretSource::File(path) => write!(f, "File({:?})", path),
+            SecretSource::Env(variable) => write!(f, "Env({:?})", variable),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = String::deserialize(deserializer)?;
+        let source = value.parse().map_err(serde::de::Error::custom);
+        value.zeroize();
+        source
+    }
+}
@@ -0,0 +1,99 @@
+//! Assignment of active deposits to a new depositor address
+//!
+//! Lets users migrating wallets move a locked deposit to their new address
+//! without unlocking it. Withdrawal rights follow the new owner immediately.
+
+use log::info;
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::TokenTransfer;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Move an active deposit to a new owner address (current depositor only)
+    /// 
+    /// The lock, amount and unlock timestamp are unchanged. The new owner's
+    /// deposit-count limit applies as if they had made the deposit.
+    pub fn transfer_deposit_ownership(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        new_owner_address: String,
+    ) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        // Validate addresses
+        self.check_address(&caller_address)?;
+        
+        self.check_address(&new_owner_address)?;
+        if new_owner_address == caller_address {
+            return Err(ContractError::InvalidAddress);
+        }
+        
+        // Get deposit
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Check ownership
+        if deposit.depositor_address != caller_address {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Withdrawn, suspended, pending and expired deposits have no funds to act on
+        deposit.ensure_holds_funds()?;
+        
+        // Check the new owner's deposit limit
+        if let Some(max_deposits) = self.deposit_limits.max_deposits_per_user {
+            let snapshot = self.deposit_limit_snapshot(&new_owner_address, &deposit.deposited_token_type);
+            if snapshot.user_deposit_count >= max_deposits as usize {
+                return Err(ContractError::UserDepositLimitReached { limit: max_deposits, current: snapshot.user_deposit_count });
+            }
+        }
+        
+        // Reassign the deposit
+        let current_timestamp = self.clock.now();
+        if let Some(deposit) = self.deposit_registry.get_mut(&deposit_id) {
+            deposit.depositor_address = new_owner_address.clone();
+            deposit.last_modified = current_timestamp;
+            
+            // The previous owner's beneficiary has no claim on the new owner's deposit
+            deposit.inheritance = None;
+            
+            self.user_index.record_assignment(&caller_address, &new_owner_address, deposit);
+        }
+        self.record_activity(&caller_address);
+        
+        // Move the ID between user entries
+        if let Some(ids) = self.user_deposit_ids.get_mut(&caller_address) {
+            ids.retain(|id| *id != deposit_id);
+            if ids.is_empty() {
+                self.user_deposit_ids.remove(&caller_address);
+            }
+        }
+        
+        self.user_deposit_ids
+            .entry(new_owner_address.clone())
+            .or_default()
+            .push(deposit_id);
+        
+        info!(target: CONTRACT, "Deposit {} transferred from {} to {}", deposit_id, caller_address, new_owner_address);
+        
+        let event = Event::DepositOwnershipTransferred {
+            deposit_id,
+            previous_owner: caller_address,
+            new_owner: new_owner_address,
+            timestamp: current_timestamp,
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+}
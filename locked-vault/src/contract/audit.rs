@@ -0,0 +1,237 @@
+//! Reconciliation of contract state against on-chain holdings
+//!
+//! Compares what the registry says the contract wallet should hold with what
+//! the transfer layer reports it actually holds. Holdings break down into
+//! funds attributed to locked deposits, change the transfer layer tagged as
+//! operational, and the unattributed rest, which alone is held against the
+//! tolerance.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::query::DepositFilter;
+use crate::contract::circuit_breaker::PauseReason;
+use crate::logging::CONTRACT;
+
+/// Reconciliation settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconciliationConfig {
+    /// Maximum absolute difference per token that is not reported as a discrepancy
+    pub tolerance: u64,
+    /// Pause the contract when a discrepancy is found
+    pub auto_pause: bool,
+}
+
+/// Expected vs actual holdings of a single token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenReconciliation {
+    /// Token type
+    pub token_type: TokenType,
+    /// Amount attributed to locked funds (active deposits, uncollected fees and rewards, less what was swept to cold storage)
+    pub expected: u64,
+    /// Amount the contract wallet actually holds, if it could be queried
+    pub actual: Option<u64>,
+    /// Actual minus expected
+    pub delta: i64,
+    /// Part of the actual holdings that is change tagged as operational
+    #[serde(default)]
+    pub operational_change: u64,
+    /// Actual holdings attributed neither to locked funds nor to operational change, negative for a shortfall
+    #[serde(default)]
+    pub unattributed: i64,
+    /// Whether the unattributed holdings are within tolerance
+    pub within_tolerance: bool,
+    /// Error returned by the balance query
+    pub error: Option<String>,
+}
+
+/// Deposit whose recorded UTXO is no longer unspent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MissingUtxo {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Recorded UTXO reference
+    pub utxo_reference: String,
+}
+
+/// Result of reconciling the contract against the chain
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    /// When the report was generated
+    pub generated_at: DateTime<Utc>,
+    /// Tolerance applied to the per-token deltas
+    pub tolerance: u64,
+    /// Per-token comparison, sorted by token
+    pub tokens: Vec<TokenReconciliation>,
+    /// Active deposits whose UTXO reference is spent or unknown to the node
+    pub missing_utxos: Vec<MissingUtxo>,
+    /// UTXO references that could not be checked, with the reason
+    pub unchecked_utxos: Vec<(u64, String)>,
+    /// Whether the contract was paused because of this report
+    pub paused_contract: bool,
+    /// Hex-encoded canonical hash of the shared contract state, for comparing nodes
+    #[serde(default)]
+    pub state_hash: String,
+}
+
+impl ReconciliationReport {
+    /// Whether every token is within tolerance and no recorded UTXO is missing
+    pub fn is_consistent(&self) -> bool {
+        self.tokens.iter().all(|token| token.within_tolerance) && self.missing_utxos.is_empty()
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the amount of each token the contract wallet should hold
+    ///
+    /// This is the sum of active (not withdrawn) deposits, uncollected fees,
+    /// the insurance pool and unclaimed referral rewards.
+    pub fn expected_holdings(&self) -> HashMap<TokenType, u64> {
+        let mut holdings: HashMap<TokenType, u64> = HashMap::new();
+        
+        for deposit in self.deposits_filtered(DepositFilter::active()) {
+            let total = holdings.entry(deposit.deposited_token_type.clone()).or_insert(0);
+            *total = total.saturating_add(deposit.deposited_amount);
+        }
+        
+        for (token_type, fees) in self.fee_config.collected_fees.iter().chain(&self.fee_config.insurance_pool) {
+            if *fees > 0 {
+                let total = holdings.entry(token_type.clone()).or_insert(0);
+                *total = total.saturating_add(*fees);
+            }
+        }
+        
+        for ((_, token_type), rewards) in &self.referral_balances {
+            let total = holdings.entry(token_type.clone()).or_insert(0);
+            *total = total.saturating_add(*rewards);
+        }
+        
+        holdings
+    }
+    
+    /// Compare expected holdings with the balances reported by the transfer layer
+    pub fn reconcile(&self, tolerance: u64) -> ReconciliationReport {
+        // Compare balances per token; swept funds are held in cold storage instead
+        let mut tokens: Vec<TokenReconciliation> = self.expected_holdings()
+            .into_iter()
+            .map(|(token_type, expected)| {
+                let expected = match token_type {
+                    TokenType::Bitcoin => expected.saturating_sub(self.cold_storage_balance),
+                    _ => expected,
+                };
+                
+                match self.token_transfer.get_contract_balance(&token_type) {
+                    Ok(actual) => {
+                        let clamp = |amount: i128| amount.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+                        let delta = clamp(actual as i128 - expected as i128);
+                        
+                        // Change kept from past transfers is the vault's own, not a surplus
+                        let operational_change = self.token_transfer.get_operational_balance(&token_type)
+                            .unwrap_or_else(|e| {
+                                warn!(target: CONTRACT, "Failed to get the operational {:?} balance: {}", token_type, e);
+                                0
+                            });
+                        let unattributed = clamp(actual as i128 - expected as i128 - operational_change as i128);
+                        
+                        TokenReconciliation {
+                            token_type,
+                            expected,
+                            actual: Some(actual),
+                            delta,
+                            operational_change,
+                            unattributed,
+                            within_tolerance: unattributed.unsigned_abs() <= tolerance,
+                            error: None,
+                        }
+                    },
+                    Err(e) => TokenReconciliation {
+                        token_type,
+                        expected,
+                        actual: None,
+                        delta: 0,
+                        operational_change: 0,
+                        unattributed: 0,
+                        within_tolerance: false,
+                        error: Some(e),
+                    },
+                }
+            })
+            .collect();
+        
+        tokens.sort_by_key(|token| format!("{:?}", token.token_type));
+        
+        // Check recorded UTXOs of active deposits
+        let mut missing_utxos = Vec::new();
+        let mut unchecked_utxos = Vec::new();
+        
+        let mut active: Vec<_> = self.deposits_filtered(DepositFilter::active())
+            .filter_map(|deposit| deposit.utxo_reference.as_ref().map(|reference| (deposit.deposit_id, reference.to_string())))
+            .collect();
+        active.sort_by_key(|(deposit_id, _)| *deposit_id);
+        
+        for (deposit_id, utxo_reference) in active {
+            match self.token_transfer.is_utxo_unspent(&utxo_reference) {
+                Ok(true) => {},
+                Ok(false) => missing_utxos.push(MissingUtxo {
+                    deposit_id,
+                    utxo_reference,
+                }),
+                Err(e) => unchecked_utxos.push((deposit_id, e)),
+            }
+        }
+        
+        ReconciliationReport {
+            generated_at: self.clock.now(),
+            tolerance,
+            tokens,
+            missing_utxos,
+            unchecked_utxos,
+            paused_contract: false,
+            state_hash: hex::encode(self.state_hash()),
+        }
+    }
+    
+    /// Reconcile and, if configured, pause the contract on any discrepancy
+    pub fn reconcile_and_enforce(&mut self, config: &ReconciliationConfig) -> ReconciliationReport {
+        let mut report = self.reconcile(config.tolerance);
+        
+        // A deficit trips the circuit breaker whatever the reconciliation config says
+        if self.check_reconciliation_deficit(&report) {
+            report.paused_contract = true;
+        }
+        
+        if report.is_consistent() {
+            info!(target: CONTRACT, "Reconciliation found no discrepancies");
+            return report;
+        }
+        
+        warn!(
+            target: CONTRACT,
+            "Reconciliation found discrepancies: {} token(s) out of tolerance, {} missing UTXO(s)",
+            report.tokens.iter().filter(|token| !token.within_tolerance).count(),
+            report.missing_utxos.len(),
+        );
+        
+        if config.auto_pause && !self.is_contract_paused {
+            error!(target: CONTRACT, "Pausing contract after failed reconciliation");
+            
+            self.is_contract_paused = true;
+            report.paused_contract = true;
+            
+            let event = Event::ContractPaused {
+                pauser_address: "reconciliation".to_string(),
+                reason: Some(PauseReason::ReconciliationDiscrepancy),
+                timestamp: report.generated_at,
+            };
+            
+            self.emit(&event);
+        }
+        
+        report
+    }
+}
@@ -0,0 +1,192 @@
+//! Audit log of the withdrawals the contract authorized
+//!
+//! Withdrawals, emergency withdrawals and fee withdrawals may be given an
+//! `OperationContext` naming the channel the request came through: the CLI,
+//! the HTTP API with the remote address and the API key used, or the
+//! contract itself. Every successful one is recorded as an
+//! `AuthorizationRecord` carrying that context, and the context is passed to
+//! the event sinks with the event, so the hash-chained event log stores it
+//! alongside. Operations made without a context are recorded as `Internal`.
+//!
+//! The context is audit data only; it never changes whether an operation is
+//! allowed. The log keeps the latest `MAX_AUTHORIZATION_LOG_ENTRIES` records
+//! and is saved with the contract state. Snapshots from before the log start
+//! with it empty.
+
+use std::collections::VecDeque;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Most records kept in the authorization log
+pub const MAX_AUTHORIZATION_LOG_ENTRIES: usize = 10_000;
+
+/// Channel an operation was requested through
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationChannel {
+    /// Command line
+    Cli,
+    /// HTTP API
+    Http {
+        /// Address of the connecting peer
+        remote_addr: String,
+        /// Non-secret identifier of the API key presented, if any
+        #[serde(default)]
+        api_key_id: Option<String>,
+    },
+    /// The contract or the embedding program itself
+    #[default]
+    Internal,
+}
+
+/// Where an operation came from, recorded for auditing
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationContext {
+    /// Channel the operation was requested through
+    pub channel: OperationChannel,
+    /// Identifier of the request, to match the record with the caller's logs
+    #[serde(default)]
+    pub request_id: Option<String>,
+}
+
+impl OperationContext {
+    /// Create a context for an operation requested on the command line
+    pub fn cli() -> Self {
+        Self { channel: OperationChannel::Cli, request_id: None }
+    }
+    
+    /// Create a context for an operation requested over the HTTP API
+    pub fn http(remote_addr: impl Into<String>, api_key_id: Option<String>) -> Self {
+        Self {
+            channel: OperationChannel::Http { remote_addr: remote_addr.into(), api_key_id },
+            request_id: None,
+        }
+    }
+    
+    /// Set the request identifier
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+}
+
+/// Kind of operation an authorization record covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthorizedOperation {
+    /// Withdrawal of an unlocked deposit
+    Withdrawal,
+    /// Emergency withdrawal of a locked deposit
+    EmergencyWithdrawal,
+    /// Withdrawal of collected fees
+    FeeWithdrawal,
+}
+
+/// Withdrawal the contract authorized, with the context it was requested in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorizationRecord {
+    /// Position in the log, from zero
+    pub sequence: u64,
+    /// Kind of operation
+    pub operation: AuthorizedOperation,
+    /// Address that requested the operation
+    pub caller_address: String,
+    /// Deposit withdrawn, `None` for fee withdrawals
+    pub deposit_id: Option<u64>,
+    /// Token type
+    pub token_type: TokenType,
+    /// Amount paid out
+    pub amount: u64,
+    /// Context the operation was requested in
+    pub context: OperationContext,
+    /// When the operation was made
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Selects the records of `get_authorization_log`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthorizationQuery {
+    /// Records of operations requested by an address
+    Address(String),
+    /// Records of operations on a deposit
+    Deposit(u64),
+}
+
+impl AuthorizationQuery {
+    /// Check whether a record is selected
+    fn matches(&self, record: &AuthorizationRecord) -> bool {
+        match self {
+            AuthorizationQuery::Address(address) => &record.caller_address == address,
+            AuthorizationQuery::Deposit(deposit_id) => record.deposit_id == Some(*deposit_id),
+        }
+    }
+}
+
+/// Authorization records, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorizationLog {
+    /// Retained records
+    pub records: VecDeque<AuthorizationRecord>,
+    /// Sequence of the next record
+    pub next_sequence: u64,
+}
+
+impl AuthorizationLog {
+    /// Record an authorized operation, dropping the oldest record beyond the bound
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record(
+        &mut self,
+        operation: AuthorizedOperation,
+        caller_address: &str,
+        deposit_id: Option<u64>,
+        token_type: &TokenType,
+        amount: u64,
+        context: Option<&OperationContext>,
+        timestamp: DateTime<Utc>,
+    ) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        
+        self.records.push_back(AuthorizationRecord {
+            sequence,
+            operation,
+            caller_address: caller_address.to_string(),
+            deposit_id,
+            token_type: token_type.clone(),
+            amount,
+            context: context.cloned().unwrap_or_default(),
+            timestamp,
+        });
+        
+        while self.records.len() > MAX_AUTHORIZATION_LOG_ENTRIES {
+            self.records.pop_front();
+        }
+    }
+    
+    /// Get the records selected by `query` from `from` (inclusive) to `to` (exclusive), oldest first
+    pub fn query(&self, query: &AuthorizationQuery, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<AuthorizationRecord> {
+        self.records.iter()
+            .filter(|record| query.matches(record) && record.timestamp >= from && record.timestamp < to)
+            .cloned()
+            .collect()
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the authorization records selected by `query` from `from` (inclusive) to `to` (exclusive), oldest first
+    pub fn get_authorization_log(&self, query: &AuthorizationQuery, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<AuthorizationRecord> {
+        self.authorization_log.query(query, from, to)
+    }
+    
+    /// Publish an event to all registered sinks with the context of the operation that caused it
+    pub(crate) fn emit_with_context(&self, event: &Event, context: Option<&OperationContext>) {
+        for sink in &self.event_sinks {
+            sink.publish_with_context(event, context);
+        }
+    }
+}
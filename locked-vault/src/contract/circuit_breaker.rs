@@ -0,0 +1,285 @@
+//! Automatic pause on anomalous withdrawals or a reconciliation deficit
+//!
+//! With the circuit breaker enabled, every completed withdrawal is recorded
+//! and the contract pauses itself once the withdrawals within the sliding
+//! window exceed `max_withdrawal_count`, or their total in a token exceeds
+//! that token's amount limit. The withdrawal that trips the breaker still
+//! completes; only later operations see the pause. A reconciliation report
+//! showing a token's holdings short of the expected amount by more than the
+//! tolerance trips it as well.
+//!
+//! The pause is announced by a `ContractPaused` event carrying the
+//! `PauseReason`. It is lifted only by `reset_circuit_breaker`, which the
+//! owner calls explicitly and which emits its own `CircuitBreakerReset`
+//! event, so every reset shows up in the history.
+//!
+//! With `pause_token_only` set, a trip over one token's amounts pauses just
+//! that token instead, announced by a `TokenPaused` event and lifted with
+//! `unpause_token`. Trips over the withdrawal count still pause the contract.
+
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::health::BackendComponent;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::audit::ReconciliationReport;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Address recorded as the pauser when the circuit breaker trips
+pub const CIRCUIT_BREAKER_PAUSER: &str = "circuit_breaker";
+
+/// When the circuit breaker trips
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Whether withdrawals are tracked and the breaker can trip
+    pub enabled: bool,
+    /// Sliding window withdrawals are counted over
+    pub window: std::time::Duration,
+    /// Most withdrawals allowed within the window
+    pub max_withdrawal_count: Option<u32>,
+    /// Most paid out per token within the window, sorted by token
+    #[serde(default)]
+    pub max_withdrawal_amount: Vec<(TokenType, u64)>,
+    /// Whether trips over one token's amounts pause only that token
+    #[serde(default)]
+    pub pause_token_only: bool,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: std::time::Duration::from_secs(60 * 60),
+            max_withdrawal_count: None,
+            max_withdrawal_amount: Vec::new(),
+            pause_token_only: false,
+        }
+    }
+}
+
+/// Machine-readable reason for an automatic pause
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PauseReason {
+    /// More withdrawals within the window than allowed
+    WithdrawalCount {
+        /// Withdrawals within the window
+        count: u32,
+        /// Configured limit
+        limit: u32,
+    },
+    /// More of a token paid out within the window than allowed
+    WithdrawalAmount {
+        /// Token type
+        token_type: TokenType,
+        /// Amount paid out within the window
+        amount: u64,
+        /// Configured limit
+        limit: u64,
+    },
+    /// Holdings of a token short of the expected amount beyond tolerance
+    ReconciliationDeficit {
+        /// Token type
+        token_type: TokenType,
+        /// Amount the contract wallet should hold
+        expected: u64,
+        /// Amount it holds
+        actual: u64,
+    },
+    /// Reconciliation found a discrepancy and the reconciliation config pauses on it
+    ReconciliationDiscrepancy,
+    /// The feature gate of a token's backend opened
+    FeatureGateOpen {
+        /// Backend whose gate opened
+        component: BackendComponent,
+    },
+}
+
+impl PauseReason {
+    /// Get the token a pause reason is about, if it is about a single one
+    pub fn token_type(&self) -> Option<&TokenType> {
+        match self {
+            PauseReason::WithdrawalAmount { token_type, .. } | PauseReason::ReconciliationDeficit { token_type, .. } => Some(token_type),
+            PauseReason::WithdrawalCount { .. } | PauseReason::ReconciliationDiscrepancy | PauseReason::FeatureGateOpen { .. } => None,
+        }
+    }
+}
+
+/// Withdrawal counted by the circuit breaker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentWithdrawal {
+    /// When the withdrawal completed
+    pub timestamp: DateTime<Utc>,
+    /// Token type
+    pub token_type: TokenType,
+    /// Amount paid out
+    pub amount: u64,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the circuit breaker configuration
+    pub fn get_circuit_breaker_config(&self) -> &CircuitBreakerConfig {
+        &self.circuit_breaker
+    }
+    
+    /// Get why the circuit breaker paused the contract, if it is tripped
+    pub fn get_circuit_breaker_trip(&self) -> Option<&PauseReason> {
+        self.circuit_breaker_trip.as_ref()
+    }
+    
+    /// Set when the circuit breaker trips (owner only)
+    ///
+    /// Disabling the breaker forgets the tracked withdrawals but does not
+    /// lift a pause it caused; use `reset_circuit_breaker` for that.
+    pub fn set_circuit_breaker_config(&mut self, caller_address: String, mut config: CircuitBreakerConfig, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Validate the window
+        if config.enabled && config.window.is_zero() {
+            return Err(ContractError::InvalidCircuitBreakerConfig("Window must not be empty".to_string()));
+        }
+        Duration::from_std(config.window)
+            .map_err(|_| ContractError::InvalidCircuitBreakerConfig("Window is too long".to_string()))?;
+        
+        config.max_withdrawal_amount.sort_by_key(|(token_type, _)| format!("{:?}", token_type));
+        if !config.enabled {
+            self.recent_withdrawals.clear();
+        }
+        self.circuit_breaker = config;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Lift a pause caused by the circuit breaker (owner only)
+    ///
+    /// Forgets the tracked withdrawals, so the ones that tripped the breaker
+    /// do not trip it again. Fails with `CircuitBreakerNotTripped` otherwise.
+    pub fn reset_circuit_breaker(&mut self, caller_address: String, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let reason = self.circuit_breaker_trip.take().ok_or(ContractError::CircuitBreakerNotTripped)?;
+        self.recent_withdrawals.clear();
+        self.is_contract_paused = false;
+        
+        info!(target: CONTRACT, "Circuit breaker reset by {} after {:?}", caller_address, reason);
+        
+        let event = Event::CircuitBreakerReset {
+            reset_by: caller_address,
+            reason,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Count a completed withdrawal and trip the breaker if the window is now over a limit
+    pub(crate) fn record_withdrawal_for_breaker(&mut self, token_type: &TokenType, amount: u64, now: DateTime<Utc>) {
+        if !self.circuit_breaker.enabled {
+            return;
+        }
+        
+        self.recent_withdrawals.push_back(RecentWithdrawal { timestamp: now, token_type: token_type.clone(), amount });
+        self.prune_recent_withdrawals(now);
+        
+        if let Some(reason) = self.withdrawal_limit_exceeded(token_type) {
+            self.trip_circuit_breaker(reason, now);
+        }
+    }
+    
+    /// Trip the breaker on the first token short of its expected holdings beyond tolerance
+    pub(crate) fn check_reconciliation_deficit(&mut self, report: &ReconciliationReport) -> bool {
+        if !self.circuit_breaker.enabled {
+            return false;
+        }
+        
+        let deficit = report.tokens.iter().find_map(|token| match token.actual {
+            Some(actual) if !token.within_tolerance && actual < token.expected => Some(PauseReason::ReconciliationDeficit {
+                token_type: token.token_type.clone(),
+                expected: token.expected,
+                actual,
+            }),
+            _ => None,
+        });
+        
+        match deficit {
+            Some(reason) => self.trip_circuit_breaker(reason, report.generated_at),
+            None => false,
+        }
+    }
+    
+    /// Drop tracked withdrawals that left the window
+    fn prune_recent_withdrawals(&mut self, now: DateTime<Utc>) {
+        let window = Duration::from_std(self.circuit_breaker.window).unwrap_or(Duration::zero());
+        while self.recent_withdrawals.front().is_some_and(|withdrawal| withdrawal.timestamp <= now - window) {
+            self.recent_withdrawals.pop_front();
+        }
+    }
+    
+    /// Get the limit the tracked withdrawals exceed, if any
+    fn withdrawal_limit_exceeded(&self, token_type: &TokenType) -> Option<PauseReason> {
+        if let Some(limit) = self.circuit_breaker.max_withdrawal_count {
+            let count = self.recent_withdrawals.len().min(u32::MAX as usize) as u32;
+            if count > limit {
+                return Some(PauseReason::WithdrawalCount { count, limit });
+            }
+        }
+        
+        let limit = self.circuit_breaker.max_withdrawal_amount.iter()
+            .find(|(limited_token, _)| limited_token == token_type)
+            .map(|(_, limit)| *limit)?;
+        let amount = self.recent_withdrawals.iter()
+            .filter(|withdrawal| &withdrawal.token_type == token_type)
+            .fold(0u64, |total, withdrawal| total.saturating_add(withdrawal.amount));
+        
+        (amount > limit).then(|| PauseReason::WithdrawalAmount { token_type: token_type.clone(), amount, limit })
+    }
+    
+    /// Pause the contract for a reason, unless it is already paused
+    ///
+    /// Pauses only the token the reason is about under `pause_token_only`,
+    /// returning `false` since the contract itself stays open.
+    fn trip_circuit_breaker(&mut self, reason: PauseReason, now: DateTime<Utc>) -> bool {
+        if let Some(token_type) = reason.token_type().filter(|_| self.circuit_breaker.pause_token_only).cloned() {
+            self.pause_token_automatically(token_type, CIRCUIT_BREAKER_PAUSER, reason, now);
+            return false;
+        }
+        
+        if self.is_contract_paused {
+            return false;
+        }
+        
+        error!(target: CONTRACT, "Circuit breaker tripped, pausing contract: {:?}", reason);
+        
+        self.is_contract_paused = true;
+        self.circuit_breaker_trip = Some(reason.clone());
+        
+        self.emit(&Event::ContractPaused {
+            pauser_address: CIRCUIT_BREAKER_PAUSER.to_string(),
+            reason: Some(reason),
+            timestamp: now,
+        });
+        
+        true
+    }
+}
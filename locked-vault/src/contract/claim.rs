@@ -0,0 +1,149 @@
+//! Claimable deposits unlocked by a hash preimage
+//!
+//! A claimable deposit stores the SHA-256 hash of a secret. After maturity
+//! anyone presenting the secret can claim the funds to their own address,
+//! like a gift card. Before maturity the depositor keeps the usual emergency
+//! withdrawal.
+
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use log::info;
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenType, TokenTransfer, TransferPriority};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::outbox::{OutboxOperation, TransferDirection, TransferRequest};
+use crate::logging::CONTRACT;
+
+/// Compare two byte strings in time independent of where they differ
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    
+    a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Deposit tokens that whoever knows the preimage of `claim_hash` can claim after maturity
+    pub fn deposit_claimable(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        claim_hash: [u8; 32],
+    ) -> Result<Event, ContractError> {
+        self.deposit_internal(caller_address, token_type, deposit_amount, lock_period_days, None, Some(claim_hash), None, None)
+    }
+    
+    /// Claim a matured claimable deposit to the claimer's address
+    pub fn claim_deposit(&mut self, claimer_address: String, deposit_id: u64, preimage: Vec<u8>) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Reentrancy protection
+        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        // Validate address
+        self.check_address(&claimer_address)?;
+        
+        // Get deposit
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Only claimable deposits can be claimed
+        let claim_hash = deposit.claim_hash.ok_or(ContractError::Unauthorized)?;
+        
+        // Check the preimage
+        let preimage_hash = sha256::Hash::hash(&preimage).to_byte_array();
+        if !constant_time_eq(&preimage_hash, &claim_hash) {
+            return Err(ContractError::InvalidPreimage);
+        }
+        
+        // Check the deposit can be paid out now
+        self.check_payable(deposit)?;
+        
+        let deposit = self.deposit_registry.get_mut(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Mark the payout as in flight, so a state saved during the handover is recovered on restart
+        deposit.status = DepositStatus::WithdrawalPending;
+        deposit.last_modified = self.clock.now();
+        
+        let token_type = deposit.deposited_token_type.clone();
+        let amount = deposit.deposited_amount;
+        drop(_guard);
+        
+        // Commit the payout with the state change; it is not urgent and may wait for a batch
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::FromContract,
+            address: claimer_address.clone(),
+            token_type,
+            amount,
+            priority: TransferPriority::Normal,
+            deposit_id: Some(deposit_id),
+            batched: true,
+            options: None,
+        };
+        let entry_id = match self.commit_to_outbox(OutboxOperation::Claim, transfer_request, &claimer_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.reactivate_deposit(deposit_id);
+                return Err(e);
+            },
+        };
+        
+        // Transfer tokens from contract to the claimer, leaving the deposit claimable on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.reactivate_deposit(deposit_id);
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
+        }
+        
+        self.complete_outbox_entry(entry_id, None)
+    }
+    
+    /// Complete a claim once the backend accepted its payout
+    pub(crate) fn finish_claim(&mut self, claimer_address: &str, deposit_id: u64) -> Result<Event, ContractError> {
+        let current_timestamp = self.clock.now();
+        let deposit = self.deposit_registry.get_mut(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Mark as withdrawn by the claimer
+        deposit.status = DepositStatus::Withdrawn;
+        deposit.claimed_by = Some(claimer_address.to_string());
+        deposit.last_modified = current_timestamp;
+        
+        // Update totals with checked arithmetic
+        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
+            *total = total.checked_sub(deposit.deposited_amount).unwrap_or(0);
+        }
+        self.user_index.record_withdrawal(&deposit.depositor_address, deposit);
+        
+        info!(target: CONTRACT, "Deposit {} claimed by {}", deposit_id, claimer_address);
+        
+        let chain_anchor = self.token_transfer.chain_anchor();
+        let event = Event::Withdrawn {
+            deposit_id,
+            depositor_address: deposit.depositor_address.clone(),
+            token_type: deposit.deposited_token_type.clone(),
+            withdrawn_amount: deposit.deposited_amount,
+            is_emergency_withdrawal: false,
+            claimed_by: Some(claimer_address.to_string()),
+            transaction_hash: None,
+            block_number: chain_anchor.as_ref().map(|anchor| anchor.block_height),
+            chain_anchor,
+            asset_detail: deposit.asset_detail(),
+            timestamp: current_timestamp,
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+}
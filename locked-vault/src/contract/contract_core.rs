@@ -1,25 +1,91 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use chrono::{DateTime, Duration, Utc};
+use log::warn;
 
-use crate::errors::ContractError;
-use crate::events::Event;
-use crate::models::{Deposit, DepositLimits, FeeConfig, TokenType, TokenTransfer, ReentrancyGuard};
+use crate::errors::{ContractError, TransferStage};
+use crate::events::{Event, EventSink};
+use crate::models::{Deposit, DepositFee, DepositLimits, DepositStatus, PendingFunding, EmergencyPolicy, FeeConfig, FeeQuote, LockPolicy, TokenType, TokenTransfer, TransferPriority, ReentrancyGuard, WithdrawOptions};
+use crate::clock::{Clock, SystemClock};
+use crate::bitcoin::signature::Signer;
+use crate::bitcoin::utxo::UtxoRef;
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::contract::idempotency::IdempotencyRecord;
+use crate::contract::governance::Governance;
+use crate::contract::reservation::DepositReservation;
+use crate::contract::quote::EmergencyQuote;
+use crate::contract::unattributed::UnattributedFunds;
+use crate::contract::void::VoidedDeposit;
+use crate::contract::treasury::TreasuryPolicy;
+use crate::contract::deposit_id::IdScheme;
+use crate::contract::circuit_breaker::{CircuitBreakerConfig, PauseReason, RecentWithdrawal};
+use crate::contract::fee_ledger::{FeeAccrualKind, FeeLedger};
+use crate::contract::authorization::{AuthorizationLog, AuthorizedOperation, OperationContext};
+use crate::contract::fee_destination::{FeeDestinationDelayFloor, PendingFeeDestination, DEFAULT_FEE_DESTINATION_DELAY_SECS};
+use crate::contract::state::sorted_by_token;
+use crate::contract::token_support::DeprecationReason;
+use crate::contract::token_pause::TokenPausePolicy;
+use crate::contract::refund::{PendingRefund, RefundPolicy};
+use crate::contract::outbox::{OutboxEntry, OutboxOperation, TransferDirection, TransferRequest};
+use crate::contract::user_index::UserIndex;
+use crate::contract::utxo_registry::UtxoRegistry;
+use crate::bitcoin::health::{BackendComponent, HealthChecker};
+use crate::tvl::MetricsRecorder;
+use crate::store::StateStore;
+use crate::logging::CONTRACT;
 
 /// Contract version for upgrade tracking
 const CONTRACT_VERSION: &str = "1.0.0";
 
+/// Length of the rolling window for the per-user emergency withdrawal limit
+const EMERGENCY_WINDOW_DAYS: i64 = 30;
+
+/// Default retention of idempotency keys
+pub(crate) const DEFAULT_IDEMPOTENCY_RETENTION_SECS: u64 = 24 * 60 * 60;
+
+/// Default lifetime of an uncommitted deposit reservation
+pub(crate) const DEFAULT_RESERVATION_TTL_SECS: u64 = 10 * 60;
+
+/// Default validity window of an emergency withdrawal quote
+pub(crate) const DEFAULT_QUOTE_VALIDITY_SECS: u64 = 10 * 60;
+
+/// Default time an on-chain pending deposit waits for its confirmation
+pub(crate) const DEFAULT_PENDING_DEPOSIT_TIMEOUT_SECS: u64 = 24 * 60 * 60;
+
+/// Balances after an emergency withdrawal fee is credited
+struct EmergencyFeeCredit {
+    /// Share of the fee kept by the collector
+    collector_amount: u64,
+    /// Share of the fee diverted to the insurance pool
+    insurance_amount: u64,
+    /// Collected fees of the token
+    new_fees: u64,
+    /// Insurance pool of the token
+    new_pool: u64,
+    /// Referrer and its reward balance of the token, if the deposit was referred
+    new_referral_balance: Option<(String, u64)>,
+}
+
 /// Main contract storage with enhanced security features
 #[derive(Debug)]
 pub struct TimeLockedDeposit<T: TokenTransfer> {
     /// Contract owner address
     pub(crate) contract_owner_address: String,
-    /// Next deposit ID to assign
+    /// Next deposit ID to assign, or the deposit sequence number under `IdScheme::Derived`
     pub(crate) next_deposit_id: u64,
+    /// How deposit IDs are assigned
+    pub(crate) id_scheme: IdScheme,
+    /// Salt of derived deposit IDs, unique to this contract instance
+    pub(crate) deposit_id_salt: [u8; 32],
     /// Mapping of deposit IDs to deposits
     pub(crate) deposit_registry: HashMap<u64, Deposit>,
     /// Mapping of user addresses to their deposit IDs
     pub(crate) user_deposit_ids: HashMap<String, Vec<u64>>,
+    /// Per-user aggregates and archived deposit IDs
+    pub(crate) user_index: UserIndex,
+    /// UTXO references consumed by deposits
+    pub(crate) utxo_registry: UtxoRegistry,
     /// Fee configuration
     pub(crate) fee_config: FeeConfig,
     /// Contract pause state
@@ -30,6 +96,12 @@ pub struct TimeLockedDeposit<T: TokenTransfer> {
     pub(crate) pending_owner: Option<String>,
     /// Supported token types
     pub(crate) supported_tokens: Vec<TokenType>,
+    /// Supported tokens the backend currently cannot serve, closed to new deposits
+    pub(crate) deprecated_tokens: HashMap<TokenType, DeprecationReason>,
+    /// Supported tokens paused on their own, by the owner or an automatic trigger
+    pub(crate) paused_tokens: HashSet<TokenType>,
+    /// How paused tokens are treated
+    pub(crate) token_pause_policy: TokenPausePolicy,
     ///  Total deposits per token type
     pub(crate) total_deposits: HashMap<TokenType, u64>,
     /// Token transfer implementation
@@ -42,15 +114,139 @@ pub struct TimeLockedDeposit<T: TokenTransfer> {
     pub(crate) version: String,
     /// Last maintenance timestamp
     pub(crate) last_maintenance: DateTime<Utc>,
+    /// Optional backend health checker consulted before moving funds
+    pub(crate) health_checker: Option<Arc<HealthChecker>>,
+    /// How long a backend may be unhealthy before operations are rejected
+    pub(crate) backend_grace_period: std::time::Duration,
+    /// Sinks notified of every emitted event
+    pub(crate) event_sinks: Vec<Arc<dyn EventSink>>,
+    /// Recorder of the TVL series, also registered as an event sink
+    pub(crate) tvl_recorder: Option<Arc<MetricsRecorder>>,
+    /// Time source for locks, cooldowns and rate windows
+    pub(crate) clock: Arc<dyn Clock>,
+    /// Restrictions on emergency withdrawals
+    pub(crate) emergency_policy: EmergencyPolicy,
+    /// Maximum lock periods for new deposits
+    pub(crate) lock_policy: LockPolicy,
+    /// Emergency withdrawal timestamps per user within the current window
+    pub(crate) emergency_withdrawals: HashMap<String, Vec<DateTime<Utc>>>,
+    /// Vault key used to sign deposit receipts
+    pub(crate) receipt_signer: Option<Arc<dyn Signer>>,
+    /// Results of recent idempotent calls by key
+    pub(crate) idempotency_records: HashMap<String, IdempotencyRecord>,
+    /// How long idempotency keys are remembered
+    pub(crate) idempotency_retention: std::time::Duration,
+    /// Owner set and proposals when governance mode is enabled
+    pub(crate) governance: Option<Governance>,
+    /// Outstanding deposit reservations by ID
+    pub(crate) deposit_reservations: HashMap<u64, DepositReservation>,
+    /// Next reservation ID to assign
+    pub(crate) next_reservation_id: u64,
+    /// How long a reservation holds its slot if never committed
+    pub(crate) reservation_ttl: std::time::Duration,
+    /// Outstanding emergency withdrawal quotes by ID
+    pub(crate) emergency_quotes: HashMap<u64, EmergencyQuote>,
+    /// Next quote ID to assign
+    pub(crate) next_quote_id: u64,
+    /// How long an emergency withdrawal quote is honored
+    pub(crate) quote_validity: std::time::Duration,
+    /// How long an on-chain pending deposit waits for its confirmation
+    pub(crate) pending_deposit_timeout: std::time::Duration,
+    /// Vault public key (hex) of new script escrow deposits
+    pub(crate) escrow_public_key: Option<String>,
+    /// Hot wallet sweep policy, if any
+    pub(crate) treasury_policy: Option<TreasuryPolicy>,
+    /// Satoshis swept from the contract wallet to cold storage
+    pub(crate) cold_storage_balance: u64,
+    /// Public key (hex) import manifests are checked against
+    pub(crate) migration_public_key: Option<String>,
+    /// Content hashes (hex) of applied import manifests
+    pub(crate) imported_manifests: HashSet<String>,
+    /// When the circuit breaker pauses the contract
+    pub(crate) circuit_breaker: CircuitBreakerConfig,
+    /// Withdrawals within the circuit breaker window, oldest first
+    pub(crate) recent_withdrawals: VecDeque<RecentWithdrawal>,
+    /// Why the circuit breaker paused the contract, until reset
+    pub(crate) circuit_breaker_trip: Option<PauseReason>,
+    /// Activated fee withdrawal destinations, sorted
+    pub(crate) fee_destination_whitelist: Vec<String>,
+    /// Proposed fee withdrawal destinations by address
+    pub(crate) pending_fee_destinations: HashMap<String, PendingFeeDestination>,
+    /// Delay between proposing and activating a fee destination
+    pub(crate) fee_destination_delay: std::time::Duration,
+    /// Longer delay still in force after the delay was shortened
+    pub(crate) fee_destination_delay_floor: Option<FeeDestinationDelayFloor>,
+    /// Fee accruals and sweeps, bounded
+    pub(crate) fee_ledger: FeeLedger,
+    /// Withdrawals authorized and the context they were requested in, bounded
+    pub(crate) authorization_log: AuthorizationLog,
+    /// Referral rewards awaiting their claim, by referrer and token
+    pub(crate) referral_balances: HashMap<(String, TokenType), u64>,
+    /// Last authenticated interaction per user, for inheritance claims
+    pub(crate) last_activity: HashMap<String, DateTime<Utc>>,
+    /// Funds sent to the contract wallet without a deposit, by normalized output reference
+    pub(crate) unattributed_funds: HashMap<String, UnattributedFunds>,
+    /// Output references of refunded unattributed funds, never listed again
+    pub(crate) refunded_outpoints: HashSet<String>,
+    /// Pending deposits the owner voided, by deposit ID
+    pub(crate) voided_deposits: HashMap<u64, VoidedDeposit>,
+    /// Count of successful owner-only mutating calls
+    pub(crate) owner_nonce: u64,
+    /// Confirmations funding transactions need, by token type and amount
+    pub(crate) confirmation_policy: ConfirmationPolicy,
+    /// Transfers committed with their state change and not yet marked done, by entry ID
+    pub(crate) outbox: BTreeMap<u64, OutboxEntry>,
+    /// Next outbox entry ID to assign
+    pub(crate) next_outbox_entry_id: u64,
+    /// Store outbox commits are persisted to, if attached
+    pub(crate) state_store: Option<Arc<dyn StateStore>>,
+    /// Whether operations that change state or move funds are refused
+    pub(crate) read_only: bool,
+    /// Refunds not yet sent, by refund ID
+    pub(crate) pending_refunds: BTreeMap<u64, PendingRefund>,
+    /// Next refund ID to assign
+    pub(crate) next_refund_id: u64,
+    /// Deposits whose funding was queued for a refund, never refunded again
+    pub(crate) refunded_deposits: HashSet<u64>,
+    /// How refunds are queued and paid
+    pub(crate) refund_policy: RefundPolicy,
 }
 
 impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Initialize a new contract instance with the default token set
+    /// 
+    /// Bitcoin, Ethereum, Solana, the default Rune, the default Ordinal and
+    /// Lightning are offered, filtered to what the transfer backend supports.
+    pub fn new_with_defaults(contract_owner_address: String, emergency_withdrawal_fee_percentage: u8, token_transfer: T) -> Result<Self, ContractError> {
+        let default_tokens = [
+            TokenType::Bitcoin,
+            TokenType::Ethereum,
+            TokenType::Solana,
+            TokenType::Rune("RUNE_DEFAULT_TOKEN".to_string()),
+            TokenType::Ordinal("0".repeat(64)),
+            TokenType::Lightning,
+        ];
+        
+        let supported_tokens = default_tokens.into_iter()
+            .filter(|token_type| token_transfer.supports_token_type(token_type))
+            .collect();
+        
+        Self::new(contract_owner_address, emergency_withdrawal_fee_percentage, token_transfer, supported_tokens)
+    }
+    
     /// Initialize a new contract instance with enhanced security checks
     /// 
+    /// Every token in `initial_supported_tokens` must be valid and supported by
+    /// the transfer backend.
+    /// 
     /// # Gas Optimization
-    /// - Uses a fixed-size vector for supported tokens to avoid dynamic resizing
     /// - Initializes hashmaps with capacity hints where possible
-    pub fn new(contract_owner_address: String, emergency_withdrawal_fee_percentage: u8, token_transfer: T) -> Result<Self, ContractError> {
+    pub fn new(
+        contract_owner_address: String,
+        emergency_withdrawal_fee_percentage: u8,
+        token_transfer: T,
+        initial_supported_tokens: Vec<TokenType>,
+    ) -> Result<Self, ContractError> {
         // Validate inputs
         if emergency_withdrawal_fee_percentage > 100 {
             return Err(ContractError::InvalidFeePercentage);
@@ -60,55 +256,116 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
             return Err(ContractError::InvalidAddress);
         }
         
-        // Validate owner address format
+        // Refuse an owner of another network, then validate the address format
+        token_transfer.check_network(&contract_owner_address)?;
         if let Err(e) = token_transfer.validate_address(&contract_owner_address) {
             return Err(ContractError::InitializationError(e));
         }
         
-        // Initialize with default supported tokens
-        let mut supported_tokens = vec![
-            TokenType::Bitcoin,
-            TokenType::Ethereum,
-            TokenType::Solana,
-        ];
-        
-        // Add Rune token support
-        supported_tokens.push(TokenType::Rune("RUNE_DEFAULT_TOKEN".to_string()));
-        
-        // Add Ordinal support if token_transfer supports it
-        if token_transfer.supports_token_type(&TokenType::Ordinal("0".repeat(64))) {
-            supported_tokens.push(TokenType::Ordinal("0".repeat(64)));
-        }
-        
-        // Add Lightning support if token_transfer supports it
-        if token_transfer.supports_token_type(&TokenType::Lightning) {
-            supported_tokens.push(TokenType::Lightning);
+        // Validate the initial token set against the backend
+        let mut supported_tokens: Vec<TokenType> = Vec::with_capacity(initial_supported_tokens.len());
+        for token_type in initial_supported_tokens {
+            if token_type.validate().is_err() {
+                return Err(ContractError::TokenValidationFailed);
+            }
+            
+            if !token_transfer.supports_token_type(&token_type) {
+                return Err(ContractError::InitializationError(format!(
+                    "Token {} is not supported by the transfer backend", token_type.name()
+                )));
+            }
+            
+            if !supported_tokens.contains(&token_type) {
+                supported_tokens.push(token_type);
+            }
         }
         
         let fee_config = FeeConfig {
             emergency_withdrawal_fee_percentage,
             fee_collector_address: contract_owner_address.clone(),
             collected_fees: HashMap::new(),
+            deposit_fees: HashMap::new(),
+            insurance_share_percentage: 0,
+            insurance_pool: HashMap::new(),
+            referral_share_percentage: 0,
         };
         
+        // The initial fee collector needs no proposal
+        let fee_destination_whitelist = vec![fee_config.fee_collector_address.clone()];
+        
         let now = Utc::now();
         
         let contract = Self {
             contract_owner_address,
             next_deposit_id: 1,
+            id_scheme: IdScheme::Sequential,
+            deposit_id_salt: rand::random(),
             deposit_registry: HashMap::with_capacity(100), // Pre-allocate for efficiency
             user_deposit_ids: HashMap::with_capacity(50),  // Pre-allocate for efficiency
+            user_index: UserIndex::default(),
+            utxo_registry: UtxoRegistry::default(),
             fee_config,
             is_contract_paused: false,
             deposit_limits: DepositLimits::default(),
             pending_owner: None,
             supported_tokens,
+            deprecated_tokens: HashMap::new(),
+            paused_tokens: HashSet::new(),
+            token_pause_policy: TokenPausePolicy::default(),
             total_deposits: HashMap::new(),
             token_transfer,
             reentrancy_guard: ReentrancyGuard::new(),
             initialized: AtomicBool::new(false),
             version: CONTRACT_VERSION.to_string(),
             last_maintenance: now,
+            health_checker: None,
+            backend_grace_period: std::time::Duration::from_secs(0),
+            event_sinks: Vec::new(),
+            tvl_recorder: None,
+            clock: Arc::new(SystemClock),
+            emergency_policy: EmergencyPolicy::default(),
+            lock_policy: LockPolicy::default(),
+            emergency_withdrawals: HashMap::new(),
+            receipt_signer: None,
+            idempotency_records: HashMap::new(),
+            idempotency_retention: std::time::Duration::from_secs(DEFAULT_IDEMPOTENCY_RETENTION_SECS),
+            governance: None,
+            deposit_reservations: HashMap::new(),
+            next_reservation_id: 1,
+            reservation_ttl: std::time::Duration::from_secs(DEFAULT_RESERVATION_TTL_SECS),
+            last_activity: HashMap::new(),
+            emergency_quotes: HashMap::new(),
+            next_quote_id: 1,
+            quote_validity: std::time::Duration::from_secs(DEFAULT_QUOTE_VALIDITY_SECS),
+            pending_deposit_timeout: std::time::Duration::from_secs(DEFAULT_PENDING_DEPOSIT_TIMEOUT_SECS),
+            escrow_public_key: None,
+            treasury_policy: None,
+            cold_storage_balance: 0,
+            migration_public_key: None,
+            imported_manifests: HashSet::new(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            recent_withdrawals: VecDeque::new(),
+            circuit_breaker_trip: None,
+            fee_destination_whitelist,
+            pending_fee_destinations: HashMap::new(),
+            fee_destination_delay: std::time::Duration::from_secs(DEFAULT_FEE_DESTINATION_DELAY_SECS),
+            fee_destination_delay_floor: None,
+            fee_ledger: FeeLedger::default(),
+            authorization_log: AuthorizationLog::default(),
+            referral_balances: HashMap::new(),
+            unattributed_funds: HashMap::new(),
+            refunded_outpoints: HashSet::new(),
+            voided_deposits: HashMap::new(),
+            owner_nonce: 0,
+            confirmation_policy: ConfirmationPolicy::default(),
+            outbox: BTreeMap::new(),
+            next_outbox_entry_id: 1,
+            state_store: None,
+            read_only: false,
+            pending_refunds: BTreeMap::new(),
+            next_refund_id: 1,
+            refunded_deposits: HashSet::new(),
+            refund_policy: RefundPolicy::default(),
         };
         
         // Mark as initialized
@@ -131,99 +388,212 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
         token_type: TokenType,
         deposit_amount: u64,
         lock_period_days: u32,
-        utxo_reference: Option<String>,
+        utxo_reference: Option<UtxoRef>,
     ) -> Result<Event, ContractError> {
-        // Reentrancy protection
-        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        self.deposit_internal(caller_address, token_type, deposit_amount, lock_period_days, utxo_reference, None, None, None)
+    }
+    
+    /// Move the funds and record a deposit, optionally claimable by hash preimage or referred
+    /// 
+    /// `idempotency_key` is the key of the call, which derived IDs are computed from.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn deposit_internal(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        utxo_reference: Option<UtxoRef>,
+        claim_hash: Option<[u8; 32]>,
+        referrer_address: Option<String>,
+        idempotency_key: Option<&str>,
+    ) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Validate the request before moving any funds
+        self.validate_deposit_request(&caller_address, &token_type, deposit_amount, lock_period_days)?;
+        let (_, locked_amount) = Self::compute_deposit_fee(&self.fee_config, &token_type, deposit_amount)?;
+        self.deposit_limit_snapshot(&caller_address, &token_type)
+            .check(&self.deposit_limits, locked_amount)?;
+        
+        // Each UTXO may back only one deposit
+        if let Some(reference) = &utxo_reference {
+            self.utxo_registry.ensure_available(reference)?;
+        }
+        
+        {
+            // Reentrancy protection
+            let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+            
+            // Check user balance
+            match self.token_transfer.get_balance(&caller_address, &token_type) {
+                Ok(balance) => {
+                    if balance < deposit_amount {
+                        return Err(ContractError::InsufficientBalance { available: balance, required: deposit_amount });
+                    }
+                },
+                Err(e) => return Err(ContractError::transfer_failed(TransferStage::BalanceCheck, e)),
+            }
+        }
+        
+        // Reserve the deposit's ID and commit its funding transfer with the reservation
+        let sequence = self.next_deposit_id;
+        let deposit_id = self.next_deposit_id(&caller_address, &token_type, deposit_amount, utxo_reference.as_ref(), idempotency_key)?;
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::ToContract,
+            address: caller_address.clone(),
+            token_type,
+            amount: deposit_amount,
+            priority: TransferPriority::Normal,
+            deposit_id: Some(deposit_id),
+            batched: false,
+            options: None,
+        };
+        let operation = OutboxOperation::Deposit { lock_period_days, utxo_reference, claim_hash, referrer_address };
+        let entry_id = match self.commit_to_outbox(operation, transfer_request, &caller_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.next_deposit_id = sequence;
+                return Err(e);
+            },
+        };
+        
+        // Transfer tokens from user to contract, releasing the ID on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.next_deposit_id = sequence;
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
+        }
         
+        self.complete_outbox_entry(entry_id, None)
+    }
+    
+    /// Check the parts of a deposit request that do not depend on limits
+    pub(crate) fn validate_deposit_request(
+        &self,
+        caller_address: &str,
+        token_type: &TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+    ) -> Result<(), ContractError> {
         // Check contract state
         if self.is_contract_paused {
             return Err(ContractError::ContractPaused);
         }
         
         // Validate address
-        if let Err(_) = self.token_transfer.validate_address(&caller_address) {
-            return Err(ContractError::InvalidAddress);
-        }
+        self.check_address(caller_address)?;
         
         // Validate token is supported
-        if !self.supported_tokens.contains(&token_type) {
+        if !self.supported_tokens.contains(token_type) {
             return Err(ContractError::UnsupportedTokenOperation);
         }
         
+        // A token whose backend lost support takes no new deposits
+        if self.is_token_deprecated(token_type) {
+            return Err(ContractError::TokenDeprecated { token_type: token_type.clone() });
+        }
+        
+        // A paused token takes no new deposits; the contract-wide pause was checked first
+        Self::ensure_token_not_paused(&self.paused_tokens, &self.token_pause_policy, token_type, false)?;
+        
         // Validate token type parameters
         if let Err(_) = token_type.validate() {
             return Err(ContractError::TokenValidationFailed);
         }
         
-        // Validate inputs with more thorough checks
-        if deposit_amount == 0 {
-            return Err(ContractError::InvalidAmount);
-        }
-        
-        // Check for reasonable deposit amount (prevent overflow attacks)
-        if deposit_amount > u64::MAX / 2 {
-            return Err(ContractError::InvalidAmount);
-        }
+        // Check the backend for this token is alive
+        self.check_backend_available(token_type)?;
         
-        if lock_period_days == 0 {
-            return Err(ContractError::InvalidLockPeriod);
-        }
+        // Check the amount against the token and the backend's limits for it
+        token_type.validate_amount(deposit_amount, &self.token_transfer)?;
         
-        // Reasonable maximum lock period (10 years)
-        if lock_period_days > 3650 {
-            return Err(ContractError::InvalidLockPeriod);
+        // Enforce the lock policy for this token
+        let max_lock_days = self.lock_policy.max_lock_days_for(token_type);
+        if lock_period_days == 0 || lock_period_days > max_lock_days {
+            return Err(ContractError::InvalidLockPeriod { min: 1, max: max_lock_days, requested: lock_period_days });
         }
         
         // Check deposit limits
-        if let Some(max_amount) = self.deposit_limits.max_deposit_amounts.get(&token_type) {
+        if let Some(max_amount) = self.deposit_limits.max_deposit_amounts.get(token_type) {
             if deposit_amount > *max_amount {
-                return Err(ContractError::DepositLimitExceeded);
+                return Err(ContractError::DepositLimitExceeded {
+                    token_type: token_type.clone(),
+                    limit: *max_amount,
+                    attempted: deposit_amount,
+                });
             }
         }
         
-        // Check user deposit limit
-        if let Some(max_deposits) = self.deposit_limits.max_deposits_per_user {
-            // Fixed: Create a longer-lived value instead of using a temporary
-            let user_deposits = self.user_deposit_ids.get(&caller_address).cloned().unwrap_or_else(Vec::new);
-            if user_deposits.len() >= max_deposits as usize {
-                return Err(ContractError::UserDepositLimitReached);
-            }
-        }
+        // The deposit must leave something to lock after the deposit fee
+        Self::compute_deposit_fee(&self.fee_config, token_type, deposit_amount)?;
         
-        // Check total deposit limit with checked arithmetic
-        if let Some(max_total) = self.deposit_limits.max_total_deposits {
-            let current_total = self.total_deposits.get(&token_type).unwrap_or(&0);
-            
-            match current_total.checked_add(deposit_amount) {
-                Some(new_total) if new_total <= max_total => {},
-                Some(_) => return Err(ContractError::TotalDepositLimitReached),
-                None => return Err(ContractError::ArithmeticError),
-            }
-        }
+        Ok(())
+    }
+    
+    /// Check limits against a fresh snapshot and record a deposit
+    /// 
+    /// Limit checks and the state mutation happen in this single step so no
+    /// other deposit can be counted in between. A deposit with `expires_at` is
+    /// recorded as pending its funding; any other one has its funds already.
+    /// A deposit committed through the outbox passes the ID it reserved.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn commit_deposit(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        utxo_reference: Option<UtxoRef>,
+        claim_hash: Option<[u8; 32]>,
+        expires_at: Option<DateTime<Utc>>,
+        referrer_address: Option<String>,
+        reserved_deposit_id: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Split off the deposit fee; only the remainder is locked
+        let (fee_amount, locked_amount) = Self::compute_deposit_fee(&self.fee_config, &token_type, deposit_amount)?;
         
-        // Check user balance
-        match self.token_transfer.get_balance(&caller_address, &token_type) {
-            Ok(balance) => {
-                if balance < deposit_amount {
-                    return Err(ContractError::InsufficientBalance);
-                }
-            },
-            Err(e) => return Err(ContractError::from(e)),
-        }
+        // Check user and total deposit limits
+        self.deposit_limit_snapshot(&caller_address, &token_type)
+            .check(&self.deposit_limits, locked_amount)?;
         
-        // Transfer tokens from user to contract
-        match self.token_transfer.transfer_to_contract(&caller_address, &token_type, deposit_amount) {
-            Ok(_) => {},
-            Err(e) => return Err(ContractError::from(e)),
+        // Check the UTXO reference is not already consumed
+        if let Some(reference) = &utxo_reference {
+            self.utxo_registry.ensure_available(reference)?;
         }
         
+        // Compute the new totals before changing any state
+        let current_total = self.total_deposits.get(&token_type).copied().unwrap_or(0);
+        let new_total = current_total.checked_add(locked_amount).ok_or(ContractError::ArithmeticError)?;
+        
+        // A referrer is credited its share of the fee, the collector keeps the rest
+        let (collector_amount, referral_amount) = match &referrer_address {
+            Some(_) => Self::split_referral_fee(fee_amount, self.fee_config.referral_share_percentage)?,
+            None => (fee_amount, 0),
+        };
+        let new_fees = self.fee_config.collected_fees
+            .get(&token_type)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(collector_amount)
+            .ok_or(ContractError::ArithmeticError)?;
+        let new_referral_balance = match &referrer_address {
+            Some(referrer_address) => self.referral_balance_after(referrer_address, &token_type, referral_amount)?,
+            None => 0,
+        };
+        
+        // A pending deposit holds its capacity but collects its fee once funded
+        let pending = expires_at.map(|expires_at| PendingFunding { expires_at, fee_amount });
+        
         // Create deposit
-        let current_timestamp = Utc::now();
+        let current_timestamp = self.clock.now();
         let unlock_timestamp = current_timestamp + Duration::days(lock_period_days as i64);
         
-        let deposit_id = self.next_deposit_id;
-        self.next_deposit_id = self.next_deposit_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        // Assign the ID under the contract's scheme, unless the outbox reserved one
+        let deposit_id = match reserved_deposit_id {
+            Some(deposit_id) => deposit_id,
+            None => self.next_deposit_id(&caller_address, &token_type, deposit_amount, utxo_reference.as_ref(), None)?,
+        };
         
         // Determine if we need Lightning payment hash
         let lightning_payment_hash = if matches!(token_type, TokenType::Lightning) {
@@ -239,22 +609,46 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
             None
         };
         
+        // Record the invoice and wallet address the backend knows them by
+        let lightning_invoice_id = lightning_payment_hash.as_deref()
+            .and_then(|payment_hash| self.token_transfer.lightning_invoice_id(payment_hash));
+        let multisig_address = multisig_wallet.as_deref()
+            .and_then(|wallet| self.token_transfer.multisig_wallet_address(wallet));
+        
         let new_deposit = Deposit {
             deposit_id,
             depositor_address: caller_address.clone(),
             deposited_token_type: token_type.clone(),
-            deposited_amount: deposit_amount,
+            deposited_amount: locked_amount,
             deposit_timestamp: current_timestamp,
             unlock_timestamp,
-            is_withdrawn: false,
+            status: if pending.is_some() { DepositStatus::Pending } else { DepositStatus::Active },
             withdrawal_tx_hash: None,
             last_modified: current_timestamp,
             utxo_reference,
             lightning_payment_hash,
             multisig_wallet,
+            lightning_invoice_id,
+            multisig_address,
+            claim_hash,
+            claimed_by: None,
+            inheritance: None,
+            reminder: None,
+            pending,
+            escrow: None,
+            referrer_address: referrer_address.clone(),
         };
         
+        let asset_detail = new_deposit.asset_detail();
+        
+        // Register the consumed UTXO and watch its transaction for reorgs
+        if let Some(reference) = &new_deposit.utxo_reference {
+            self.utxo_registry.register(reference.clone(), deposit_id);
+            self.token_transfer.track_funding(deposit_id, reference.txid(), &token_type, deposit_amount);
+        }
+        
         // Store deposit
+        self.user_index.record_deposit(&caller_address, &new_deposit);
         self.deposit_registry.insert(deposit_id, new_deposit);
         
         // Add deposit to user's list
@@ -263,22 +657,70 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
             .or_insert_with(Vec::new)
             .push(deposit_id);
         
-        // Update total deposits with checked arithmetic
-        let current_total = self.total_deposits.get(&token_type).copied().unwrap_or(0);
-        let new_total = current_total.checked_add(deposit_amount).ok_or(ContractError::ArithmeticError)?;
+        // Update total deposits and collect the deposit fee
         self.total_deposits.insert(token_type.clone(), new_total);
+        if fee_amount > 0 && pending.is_none() {
+            self.fee_config.collected_fees.insert(token_type.clone(), new_fees);
+            self.fee_ledger.record_accrual(deposit_id, &token_type, collector_amount, FeeAccrualKind::DepositFee, current_timestamp);
+            if let Some(referrer_address) = &referrer_address {
+                self.referral_balances.insert((referrer_address.clone(), token_type.clone()), new_referral_balance);
+            }
+        }
+        self.record_activity(&caller_address);
+        
+        // Record the referral once the deposit exists
+        let referral_event = referrer_address.map(|referrer_address| Event::ReferralRecorded {
+            deposit_id,
+            depositor_address: caller_address.clone(),
+            referrer_address,
+            token_type: token_type.clone(),
+            credited_amount: if pending.is_some() { 0 } else { referral_amount },
+            timestamp: current_timestamp,
+        });
+        
+        // Announce a pending deposit until its funding arrives
+        if let Some(pending) = pending {
+            let event = Event::DepositPending {
+                deposit_id,
+                depositor_address: caller_address,
+                token_type,
+                deposit_amount,
+                expires_at: pending.expires_at,
+                asset_detail,
+                timestamp: current_timestamp,
+            };
+            
+            self.emit(&event);
+            if let Some(referral_event) = &referral_event {
+                self.emit(referral_event);
+            }
+            
+            return Ok(event);
+        }
         
         // Return deposit event with enhanced information
-        Ok(Event::Deposited {
+        let chain_anchor = self.token_transfer.chain_anchor();
+        let event = Event::Deposited {
             deposit_id,
             depositor_address: caller_address,
             token_type,
             deposit_amount,
+            fee_amount,
+            locked_amount,
             unlock_timestamp,
             transaction_hash: None, // Would be filled in a real blockchain implementation
-            block_number: None,     // Would be filled in a real blockchain implementation
+            block_number: chain_anchor.as_ref().map(|anchor| anchor.block_height),
+            chain_anchor,
+            asset_detail,
             timestamp: current_timestamp,
-        })
+        };
+        
+        self.emit(&event);
+        if let Some(referral_event) = &referral_event {
+            self.emit(referral_event);
+        }
+        
+        Ok(event)
     }
     
     /// Withdraw tokens after time lock has expired - with enhanced security
@@ -287,94 +729,180 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
     /// - Uses early returns to avoid unnecessary computation
     /// - Minimizes storage operations
     pub fn withdraw(&mut self, caller_address: String, deposit_id: u64) -> Result<Event, ContractError> {
+        self.withdraw_with_fee_limit(caller_address, deposit_id, None)
+    }
+    
+    /// Withdraw a deposit, paying at most `max_onchain_fee` satoshis in mining fees
+    /// 
+    /// If the payout would cost more, it fails with `FeeExceedsLimit` and the
+    /// deposit stays withdrawable. `None` behaves exactly like `withdraw`.
+    pub fn withdraw_with_fee_limit(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        max_onchain_fee: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        self.withdraw_with_options(caller_address, deposit_id, WithdrawOptions { max_onchain_fee, ..Default::default() })
+    }
+    
+    /// Withdraw a deposit with a fee limit and/or coin control over the contract UTXOs spent
+    /// 
+    /// Coin control fails with `UtxoUnavailable` if a chosen output is not
+    /// spendable, and the deposit stays withdrawable. Default options behave
+    /// exactly like `withdraw`.
+    pub fn withdraw_with_options(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        options: WithdrawOptions,
+    ) -> Result<Event, ContractError> {
+        self.withdraw_internal(caller_address, deposit_id, options, None)
+    }
+    
+    /// Withdraw a deposit, recording the context it was requested in
+    /// 
+    /// The context is only audited; the withdrawal is checked exactly like `withdraw`.
+    pub fn withdraw_with_context(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        context: OperationContext,
+    ) -> Result<Event, ContractError> {
+        self.withdraw_internal(caller_address, deposit_id, WithdrawOptions::default(), Some(&context))
+    }
+    
+    /// Withdraw a deposit with options, recording `context` in the authorization log
+    pub(crate) fn withdraw_internal(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        options: WithdrawOptions,
+        context: Option<&OperationContext>,
+    ) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
         // Reentrancy protection
         let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
         
-        // Check contract state
-        if self.is_contract_paused {
-            return Err(ContractError::ContractPaused);
-        }
-        
-        // Validate address
-        if let Err(_) = self.token_transfer.validate_address(&caller_address) {
-            return Err(ContractError::InvalidAddress);
-        }
+        // Check the caller can withdraw the deposit now
+        self.check_withdrawable(&caller_address, deposit_id)?;
+        self.check_minimum_withdrawal(deposit_id)?;
         
-        // Get deposit
+        let current_timestamp = self.clock.now();
         let deposit = match self.deposit_registry.get_mut(&deposit_id) {
             Some(deposit) => deposit,
             None => return Err(ContractError::DepositNotFound),
         };
         
+        // Mark the payout as in flight, so a state saved during the handover is recovered on restart
+        deposit.status = DepositStatus::WithdrawalPending;
+        deposit.last_modified = current_timestamp;
         
-        // Check ownership
-        if deposit.depositor_address != caller_address {
-            return Err(ContractError::Unauthorized);
-        }
+        let token_type = deposit.deposited_token_type.clone();
+        let amount = deposit.deposited_amount;
+        drop(_guard);
+        
+        // Commit the payout with the state change; without options it is not urgent and may wait for a batch
+        let batched = options == WithdrawOptions::default();
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::FromContract,
+            address: caller_address.clone(),
+            token_type: token_type.clone(),
+            amount,
+            priority: TransferPriority::Normal,
+            deposit_id: Some(deposit_id),
+            batched,
+            options: (!batched).then_some(options),
+        };
+        let entry_id = match self.commit_to_outbox(OutboxOperation::Withdrawal, transfer_request, &caller_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.reactivate_deposit(deposit_id);
+                return Err(e);
+            },
+        };
         
-        // Check if already withdrawn
-        if deposit.is_withdrawn {
-            return Err(ContractError::DepositAlreadyWithdrawn);
+        // Transfer tokens from contract to user, leaving the deposit active on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.reactivate_deposit(deposit_id);
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
         }
         
-        // Check time lock
-        let current_timestamp = Utc::now();
-        if current_timestamp < deposit.unlock_timestamp {
-            return Err(ContractError::DepositLocked);
-        }
+        let event = self.complete_outbox_entry(entry_id, context)?;
         
-        // Mark as withdrawn
-        deposit.is_withdrawn = true;
-        deposit.last_modified = current_timestamp;
+        // The withdrawal stands even if it trips the circuit breaker
+        self.record_withdrawal_for_breaker(&token_type, amount, current_timestamp);
         
-        let token_type = deposit.deposited_token_type.clone();
-        let amount = deposit.deposited_amount;
+        Ok(event)
+    }
+    
+    /// Complete a withdrawal once the backend accepted its payout
+    pub(crate) fn finish_withdrawal(
+        &mut self,
+        caller_address: &str,
+        deposit_id: u64,
+        context: Option<&OperationContext>,
+    ) -> Result<Event, ContractError> {
+        let current_timestamp = self.clock.now();
+        let deposit = match self.deposit_registry.get_mut(&deposit_id) {
+            Some(deposit) => deposit,
+            None => return Err(ContractError::DepositNotFound),
+        };
         
-        // Transfer tokens from contract to user
-        match self.token_transfer.transfer_from_contract(&caller_address, &token_type, amount) {
-            Ok(_) => {},
-            Err(e) => return Err(ContractError::from(e)),
-        }
+        // The backend accepted the payout
+        deposit.status = DepositStatus::Withdrawn;
         
         // Update totals with checked arithmetic
         if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
             *total = total.checked_sub(deposit.deposited_amount).unwrap_or(0);
         }
+        self.user_index.record_withdrawal(caller_address, deposit);
+        self.authorization_log.record(AuthorizedOperation::Withdrawal, caller_address, Some(deposit_id), &deposit.deposited_token_type, deposit.deposited_amount, context, current_timestamp);
         
         // Return withdrawal event with enhanced information
-        Ok(Event::Withdrawn {
+        let chain_anchor = self.token_transfer.chain_anchor();
+        let event = Event::Withdrawn {
             deposit_id,
-            depositor_address: caller_address,
+            depositor_address: caller_address.to_string(),
             token_type: deposit.deposited_token_type.clone(),
             withdrawn_amount: deposit.deposited_amount,
             is_emergency_withdrawal: false,
+            claimed_by: None,
             transaction_hash: None, // Would be filled in a real blockchain implementation
-            block_number: None,     // Would be filled in a real blockchain implementation
+            block_number: chain_anchor.as_ref().map(|anchor| anchor.block_height),
+            chain_anchor,
+            asset_detail: deposit.asset_detail(),
             timestamp: current_timestamp,
-        })
+        };
+        
+        self.record_activity(caller_address);
+        self.emit_with_context(&event, context);
+        
+        Ok(event)
     }
     
-    /// Emergency withdrawal with fee penalty - with enhanced security
+    /// Make a deposit whose payout was not handed to the backend active again
+    pub(crate) fn reactivate_deposit(&mut self, deposit_id: u64) {
+        if let Some(deposit) = self.deposit_registry.get_mut(&deposit_id) {
+            deposit.status = DepositStatus::Active;
+        }
+    }
+    
+    /// Check that the caller can withdraw a deposit right now
     /// 
-    /// # Gas Optimization
-    /// - Uses checked arithmetic to prevent overflows
-    /// - Batches storage updates
-    pub fn emergency_withdraw(&mut self, caller_address: String, deposit_id: u64) -> Result<Event, ContractError> {
-        // Reentrancy protection
-        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
-        
+    /// Shared by `withdraw_with_options` and `simulate_withdrawal`.
+    pub(crate) fn check_withdrawable(&self, caller_address: &str, deposit_id: u64) -> Result<&Deposit, ContractError> {
         // Check contract state
         if self.is_contract_paused {
             return Err(ContractError::ContractPaused);
         }
         
-        // Validate address
-        if let Err(_) = self.token_transfer.validate_address(&caller_address) {
-            return Err(ContractError::InvalidAddress);
-        }
+        // Validate address and the script it pays to
+        self.token_transfer.validate_destination(caller_address)?;
         
         // Get deposit
-        let deposit = match self.deposit_registry.get_mut(&deposit_id) {
+        let deposit = match self.deposit_registry.get(&deposit_id) {
             Some(deposit) => deposit,
             None => return Err(ContractError::DepositNotFound),
         };
@@ -384,127 +912,882 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
             return Err(ContractError::Unauthorized);
         }
         
-        // Check if already withdrawn
-        if deposit.is_withdrawn {
-            return Err(ContractError::DepositAlreadyWithdrawn);
+        // A claimable deposit matures to whoever holds the preimage, not to its depositor
+        if deposit.claim_hash.is_some() {
+            return Err(ContractError::Unauthorized);
         }
         
-        // Calculate fee with robust overflow protection
-        let fee_percentage = self.fee_config.emergency_withdrawal_fee_percentage;
-        let fee_amount = match (deposit.deposited_amount as u128)
-            .checked_mul(fee_percentage as u128)
-            .and_then(|product| product.checked_div(100)) {
-            Some(amount) if amount <= u64::MAX as u128 => amount as u64,
-            _ => return Err(ContractError::ArithmeticError),
-        };
-        
-        let net_withdrawal_amount = deposit.deposited_amount.checked_sub(fee_amount)
-            .ok_or(ContractError::ArithmeticError)?;
-        
-        // Mark as withdrawn
-        deposit.is_withdrawn = true;
-        deposit.last_modified = Utc::now();
-        
-        let token_type = deposit.deposited_token_type.clone();
-        
-        // Transfer net amount to user
-        match self.token_transfer.transfer_from_contract(&caller_address, &token_type, net_withdrawal_amount) {
-            Ok(_) => {},
-            Err(e) => return Err(ContractError::from(e)),
-        }
+        // Check the deposit can be paid out now
+        self.check_payable(deposit)?;
         
-        // Accumulate fees with checked arithmetic
-        let current_fees = self.fee_config.collected_fees
-            .entry(deposit.deposited_token_type.clone())
-            .or_insert(0);
-            
-        *current_fees = current_fees.checked_add(fee_amount)
-            .ok_or(ContractError::ArithmeticError)?;
+        Ok(deposit)
+    }
+    
+    /// Check that a deposit can be paid out right now, whoever receives it
+    /// 
+    /// Shared by withdrawals and claims, which check the recipient themselves.
+    pub(crate) fn check_payable(&self, deposit: &Deposit) -> Result<(), ContractError> {
+        // Withdrawn, suspended, pending and expired deposits have no funds to pay out
+        deposit.ensure_holds_funds()?;
         
-        // Update totals with checked arithmetic
-        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
-            *total = total.checked_sub(deposit.deposited_amount).unwrap_or(0);
+        // Check time lock
+        if self.clock.now() < deposit.unlock_timestamp {
+            return Err(ContractError::DepositLocked);
         }
         
-        // Return emergency withdrawal event with enhanced information
-        Ok(Event::EmergencyWithdrawn {
-            deposit_id,
-            depositor_address: caller_address,
-            token_type: deposit.deposited_token_type.clone(),
-            withdrawn_amount: net_withdrawal_amount,
-            fee_amount,
-            transaction_hash: None, // Would be filled in a real blockchain implementation
-            block_number: None,     // Would be filled in a real blockchain implementation
-            timestamp: Utc::now(),
-        })
+        self.check_payout_available(&deposit.deposited_token_type)
     }
     
-    /// Withdraw collected fees (owner only) - with enhanced security
-    pub fn withdraw_fees(&mut self, caller_address: String, token_type: TokenType) -> Result<Event, ContractError> {
-        // Reentrancy protection
-        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
-        
-        // Check authorization
-        if caller_address != self.contract_owner_address {
-            return Err(ContractError::Unauthorized);
+    /// Check that a token can be paid out right now
+    pub(crate) fn check_payout_available(&self, token_type: &TokenType) -> Result<(), ContractError> {
+        // A paused token pays out only if the pause policy exempts withdrawals
+        Self::ensure_token_not_paused(&self.paused_tokens, &self.token_pause_policy, token_type, true)?;
+        
+        // Check the backend for this token is alive
+        if let Some(health_checker) = &self.health_checker {
+            Self::ensure_backend_available(health_checker, self.backend_grace_period, token_type)?;
         }
         
-        // Validate token type
-        if let Err(_) = token_type.validate() {
-            return Err(ContractError::TokenValidationFailed);
-        }
+        Ok(())
+    }
+    
+    /// Check a deposit is large enough to be paid out on-chain at the current fee rate
+    /// 
+    /// Fails with `WithdrawalBelowMinimum`, naming the smallest amount that
+    /// would go through, if the deposit is below the backend's dust limit
+    /// plus the estimated mining fee of its withdrawal. Tokens without a dust
+    /// limit always pass, and so does any deposit while no fee estimate is
+    /// available, since the payout itself will then wait or fail.
+    pub(crate) fn check_minimum_withdrawal(&self, deposit_id: u64) -> Result<(), ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        let Some(dust_limit) = self.token_transfer.dust_limit(&deposit.deposited_token_type) else {
+            return Ok(());
+        };
         
-        let fee_amount = match self.fee_config.collected_fees.get_mut(&token_type) {
-            Some(amount) if *amount > 0 => {
-                let tmp = *amount;
-                *amount = 0; // Reset collected fees
-                tmp
+        let fee = match self.estimate_withdrawal_fee(deposit_id) {
+            Ok(fee) => fee,
+            Err(e) => {
+                warn!(target: CONTRACT, "No fee estimate to check the minimum withdrawal of deposit {}: {}", deposit_id, e);
+                return Ok(());
             },
-            _ => return Err(ContractError::InvalidAmount),
         };
         
-        // Validate collector address
-        if let Err(_) = self.token_transfer.validate_address(&self.fee_config.fee_collector_address) {
-            return Err(ContractError::InvalidAddress);
-        }
-        
-        // Transfer fees to collector
-        match self.token_transfer.transfer_from_contract(
-            &self.fee_config.fee_collector_address, 
-            &token_type, 
-            fee_amount
-        ) {
-            Ok(_) => {},
-            Err(e) => return Err(ContractError::from(e)),
+        let minimum = dust_limit.saturating_add(fee);
+        if deposit.deposited_amount < minimum {
+            return Err(ContractError::WithdrawalBelowMinimum { amount: deposit.deposited_amount, minimum });
         }
         
-        // Return fee collection event with enhanced information
-        Ok(Event::FeeCollected {
-            token_type,
-            fee_amount,
-            collector_address: self.fee_config.fee_collector_address.clone(),
-            transaction_hash: None, // Would be filled in a real blockchain implementation
-            timestamp: Utc::now(),
-        })
+        Ok(())
     }
     
-    /// Get the network type
-    pub fn get_network_type(&self) -> String {
-        self.token_transfer.get_network_type()
+    /// Estimate the mining fee a withdrawal of a deposit would pay right now
+    /// 
+    /// Lets a caller pick a `max_onchain_fee` for `withdraw_with_fee_limit`.
+    pub fn estimate_withdrawal_fee(&self, deposit_id: u64) -> Result<u64, ContractError> {
+        self.quote_withdrawal_fee(deposit_id).map(|quote| quote.fee)
     }
     
-    /// Check if the contract is on testnet
-    pub fn is_testnet(&self) -> bool {
-        self.token_transfer.get_network_type() == "testnet"
+    /// Estimate the mining fee a withdrawal of a deposit would pay right now, with the fee rate snapshot it used
+    /// 
+    /// Quotes carrying the same snapshot ID were priced at the same fee rate.
+    pub fn quote_withdrawal_fee(&self, deposit_id: u64) -> Result<FeeQuote, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        self.token_transfer.quote_withdrawal_fee(
+            &deposit.depositor_address,
+            &deposit.deposited_token_type,
+            deposit.deposited_amount,
+            TransferPriority::Normal,
+        )
+            .map_err(|reason| ContractError::TransferFailed { stage: TransferStage::FeeEstimation, reason })
+    }
+    
+    /// Emergency withdrawal with fee penalty - with enhanced security
+    /// 
+    /// # Gas Optimization
+    /// - Uses checked arithmetic to prevent overflows
+    /// - Batches storage updates
+    pub fn emergency_withdraw(&mut self, caller_address: String, deposit_id: u64) -> Result<Event, ContractError> {
+        self.emergency_withdraw_internal(caller_address, deposit_id, None, None)
+    }
+    
+    /// Emergency withdrawal recording the context it was requested in
+    pub fn emergency_withdraw_with_context(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        context: OperationContext,
+    ) -> Result<Event, ContractError> {
+        self.emergency_withdraw_internal(caller_address, deposit_id, None, Some(&context))
+    }
+    
+    /// Emergency withdrawal charging `quoted_fee` if set, the current fee otherwise
+    pub(crate) fn emergency_withdraw_internal(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        quoted_fee: Option<u64>,
+        context: Option<&OperationContext>,
+    ) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Reentrancy protection
+        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        // Validate address and the script it pays to
+        self.token_transfer.validate_destination(&caller_address)?;
+        
+        // Get deposit
+        let deposit = match self.deposit_registry.get_mut(&deposit_id) {
+            Some(deposit) => deposit,
+            None => return Err(ContractError::DepositNotFound),
+        };
+        
+        // Check ownership
+        if deposit.depositor_address != caller_address {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Withdrawn, suspended, pending and expired deposits have no funds to act on
+        deposit.ensure_holds_funds()?;
+        
+        // Reject emergency withdrawals of freshly made deposits
+        let current_timestamp = self.clock.now();
+        
+        // The depositor's escape hatch from a claimable deposit closes at maturity
+        if deposit.claim_hash.is_some() && current_timestamp >= deposit.unlock_timestamp {
+            return Err(ContractError::Unauthorized);
+        }
+        let min_age = Duration::from_std(self.emergency_policy.min_age_before_emergency)
+            .map_err(|_| ContractError::ArithmeticError)?;
+        
+        if current_timestamp - deposit.deposit_timestamp < min_age {
+            return Err(ContractError::EmergencyTooSoon { available_at: deposit.deposit_timestamp + min_age });
+        }
+        
+        // Enforce the per-user emergency withdrawal limit, pruning entries outside the window
+        let window_start = current_timestamp - Duration::days(EMERGENCY_WINDOW_DAYS);
+        let recent_withdrawals = self.emergency_withdrawals.entry(caller_address.clone()).or_default();
+        recent_withdrawals.retain(|timestamp| *timestamp > window_start);
+        
+        if let Some(max_withdrawals) = self.emergency_policy.max_emergency_withdrawals_per_user_per_month {
+            if recent_withdrawals.len() >= max_withdrawals as usize {
+                // The limit frees up once the oldest counted withdrawal leaves the window
+                let oldest = recent_withdrawals.iter().min().copied().unwrap_or(current_timestamp);
+                return Err(ContractError::EmergencyLimitReached {
+                    limit: max_withdrawals,
+                    retry_at: oldest + Duration::days(EMERGENCY_WINDOW_DAYS),
+                });
+            }
+        }
+        
+        // A paused token pays out only if the pause policy exempts withdrawals
+        Self::ensure_token_not_paused(&self.paused_tokens, &self.token_pause_policy, &deposit.deposited_token_type, true)?;
+        
+        // Check the backend for this token is alive
+        if let Some(health_checker) = &self.health_checker {
+            Self::ensure_backend_available(health_checker, self.backend_grace_period, &deposit.deposited_token_type)?;
+        }
+        
+        // Calculate fee with robust overflow protection, honoring a quoted fee
+        let (fee_amount, net_withdrawal_amount) = match quoted_fee {
+            Some(fee_amount) => {
+                let net_amount = deposit.deposited_amount.checked_sub(fee_amount).ok_or(ContractError::ArithmeticError)?;
+                (fee_amount, net_amount)
+            },
+            None => {
+                let fee_percentage = Self::effective_emergency_fee_percentage(&self.fee_config, deposit, current_timestamp);
+                Self::compute_emergency_fee(deposit.deposited_amount, fee_percentage)?
+            },
+        };
+        
+        // Check the fee can be credited before changing any state
+        Self::credit_emergency_fee(&self.fee_config, &self.referral_balances, deposit, fee_amount)?;
+        
+        // Mark the payout as in flight until the backend accepts it
+        deposit.status = DepositStatus::WithdrawalPending;
+        deposit.last_modified = current_timestamp;
+        
+        let token_type = deposit.deposited_token_type.clone();
+        drop(_guard);
+        
+        // Commit the net payout with the state change, ahead of routine transfers
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::FromContract,
+            address: caller_address.clone(),
+            token_type: token_type.clone(),
+            amount: net_withdrawal_amount,
+            priority: TransferPriority::High,
+            deposit_id: Some(deposit_id),
+            batched: false,
+            options: None,
+        };
+        let entry_id = match self.commit_to_outbox(OutboxOperation::EmergencyWithdrawal { fee_amount }, transfer_request, &caller_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.reactivate_deposit(deposit_id);
+                return Err(e);
+            },
+        };
+        
+        // Transfer net amount to user, leaving the deposit active on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.reactivate_deposit(deposit_id);
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
+        }
+        
+        let event = self.complete_outbox_entry(entry_id, context)?;
+        
+        // The withdrawal stands even if it trips the circuit breaker
+        self.record_withdrawal_for_breaker(&token_type, net_withdrawal_amount, current_timestamp);
+        
+        Ok(event)
+    }
+    
+    /// Complete an emergency withdrawal once the backend accepted its payout
+    pub(crate) fn finish_emergency_withdrawal(
+        &mut self,
+        caller_address: &str,
+        deposit_id: u64,
+        fee_amount: u64,
+        net_withdrawal_amount: u64,
+        context: Option<&OperationContext>,
+    ) -> Result<Event, ContractError> {
+        let current_timestamp = self.clock.now();
+        let deposit = match self.deposit_registry.get_mut(&deposit_id) {
+            Some(deposit) => deposit,
+            None => return Err(ContractError::DepositNotFound),
+        };
+        let credit = Self::credit_emergency_fee(&self.fee_config, &self.referral_balances, deposit, fee_amount)?;
+        
+        // Mark as withdrawn
+        deposit.status = DepositStatus::EmergencyWithdrawn;
+        deposit.last_modified = current_timestamp;
+        
+        let token_type = deposit.deposited_token_type.clone();
+        
+        // Record the withdrawal for the per-user limit
+        self.emergency_withdrawals
+            .entry(caller_address.to_string())
+            .or_default()
+            .push(current_timestamp);
+        
+        // Accumulate fees
+        self.fee_config.collected_fees.insert(token_type.clone(), credit.new_fees);
+        self.fee_config.insurance_pool.insert(token_type.clone(), credit.new_pool);
+        if let Some((referrer_address, balance)) = credit.new_referral_balance {
+            self.referral_balances.insert((referrer_address, token_type.clone()), balance);
+        }
+        self.fee_ledger.record_accrual(deposit_id, &token_type, credit.collector_amount, FeeAccrualKind::EmergencyFee, current_timestamp);
+        
+        // Update totals with checked arithmetic
+        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
+            *total = total.checked_sub(deposit.deposited_amount).unwrap_or(0);
+        }
+        self.user_index.record_withdrawal(caller_address, deposit);
+        self.authorization_log.record(AuthorizedOperation::EmergencyWithdrawal, caller_address, Some(deposit_id), &token_type, net_withdrawal_amount, context, current_timestamp);
+        
+        // Return emergency withdrawal event with enhanced information
+        let chain_anchor = self.token_transfer.chain_anchor();
+        let event = Event::EmergencyWithdrawn {
+            deposit_id,
+            depositor_address: caller_address.to_string(),
+            token_type,
+            withdrawn_amount: net_withdrawal_amount,
+            fee_amount,
+            insurance_amount: credit.insurance_amount,
+            transaction_hash: None, // Would be filled in a real blockchain implementation
+            block_number: chain_anchor.as_ref().map(|anchor| anchor.block_height),
+            chain_anchor,
+            asset_detail: deposit.asset_detail(),
+            timestamp: current_timestamp,
+        };
+        
+        self.record_activity(caller_address);
+        self.emit_with_context(&event, context);
+        
+        Ok(event)
+    }
+    
+    /// Work out the balances an emergency withdrawal fee leaves, without changing them
+    fn credit_emergency_fee(
+        fee_config: &FeeConfig,
+        referral_balances: &HashMap<(String, TokenType), u64>,
+        deposit: &Deposit,
+        fee_amount: u64,
+    ) -> Result<EmergencyFeeCredit, ContractError> {
+        // Divert the insurance share of the fee, rounding in favor of the pool
+        let (collector_amount, insurance_amount) = Self::split_emergency_fee(fee_amount, fee_config.insurance_share_percentage)?;
+        
+        // Credit a referrer its share of what is left for the collector
+        let (collector_amount, referral_amount) = match &deposit.referrer_address {
+            Some(_) => Self::split_referral_fee(collector_amount, fee_config.referral_share_percentage)?,
+            None => (collector_amount, 0),
+        };
+        let new_referral_balance = match &deposit.referrer_address {
+            Some(referrer_address) => Some((
+                referrer_address.clone(),
+                referral_balances
+                    .get(&(referrer_address.clone(), deposit.deposited_token_type.clone()))
+                    .copied()
+                    .unwrap_or(0)
+                    .checked_add(referral_amount)
+                    .ok_or(ContractError::ArithmeticError)?,
+            )),
+            None => None,
+        };
+        
+        // Compute the new fee and pool balances
+        let new_fees = fee_config.collected_fees
+            .get(&deposit.deposited_token_type)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(collector_amount)
+            .ok_or(ContractError::ArithmeticError)?;
+        let new_pool = fee_config.insurance_pool
+            .get(&deposit.deposited_token_type)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(insurance_amount)
+            .ok_or(ContractError::ArithmeticError)?;
+        
+        Ok(EmergencyFeeCredit { collector_amount, insurance_amount, new_fees, new_pool, new_referral_balance })
+    }
+    
+    /// Get the emergency fee percentage that applies to a deposit at a given time
+    pub(crate) fn effective_emergency_fee_percentage(fee_config: &FeeConfig, _deposit: &Deposit, _at: DateTime<Utc>) -> u8 {
+        fee_config.emergency_withdrawal_fee_percentage
+    }
+    
+    /// Split an amount into the emergency fee and the net payout
+    pub(crate) fn compute_emergency_fee(amount: u64, fee_percentage: u8) -> Result<(u64, u64), ContractError> {
+        Self::split_percentage_fee(amount, fee_percentage)
+    }
+    
+    /// Split an amount into a percentage fee, rounded down, and the remainder
+    pub(crate) fn split_percentage_fee(amount: u64, fee_percentage: u8) -> Result<(u64, u64), ContractError> {
+        let fee_amount = match (amount as u128)
+            .checked_mul(fee_percentage as u128)
+            .and_then(|product| product.checked_div(100)) {
+            Some(fee) if fee <= u64::MAX as u128 => fee as u64,
+            _ => return Err(ContractError::ArithmeticError),
+        };
+        
+        let net_amount = amount.checked_sub(fee_amount)
+            .ok_or(ContractError::ArithmeticError)?;
+        
+        Ok((fee_amount, net_amount))
+    }
+    
+    /// Split a deposit into the deposit fee and the principal to lock
+    /// 
+    /// Percentage fees round down. The amount must exceed the fee.
+    pub(crate) fn compute_deposit_fee(fee_config: &FeeConfig, token_type: &TokenType, amount: u64) -> Result<(u64, u64), ContractError> {
+        let fee_amount = match fee_config.deposit_fee(token_type) {
+            None => 0,
+            Some(DepositFee::Flat(fee)) => fee,
+            Some(DepositFee::Percentage(percentage)) => Self::split_percentage_fee(amount, percentage)?.0,
+        };
+        
+        if amount <= fee_amount {
+            return Err(ContractError::DepositNotAboveFee { fee: fee_amount, amount });
+        }
+        
+        Ok((fee_amount, amount - fee_amount))
+    }
+    
+    /// Withdraw all collected fees of a token to the fee collector (owner only)
+    pub fn withdraw_all_fees(&mut self, caller_address: String, token_type: TokenType, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        self.withdraw_fees(caller_address, token_type, None, None, expected_nonce)
+    }
+    
+    /// Withdraw collected fees (owner only) - with enhanced security
+    /// 
+    /// `amount` of `None` withdraws the whole collected balance. `destination`
+    /// defaults to the configured fee collector address.
+    pub fn withdraw_fees(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        amount: Option<u64>,
+        destination: Option<String>,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        self.withdraw_fees_internal(caller_address, token_type, amount, destination, expected_nonce, None)
+    }
+    
+    /// Withdraw collected fees (owner only), recording the context it was requested in
+    pub fn withdraw_fees_with_context(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        amount: Option<u64>,
+        destination: Option<String>,
+        expected_nonce: Option<u64>,
+        context: OperationContext,
+    ) -> Result<Event, ContractError> {
+        self.withdraw_fees_internal(caller_address, token_type, amount, destination, expected_nonce, Some(&context))
+    }
+    
+    /// Withdraw collected fees (owner only), recording `context` in the authorization log
+    fn withdraw_fees_internal(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        amount: Option<u64>,
+        destination: Option<String>,
+        expected_nonce: Option<u64>,
+        context: Option<&OperationContext>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        self.ensure_sole_owner(&caller_address)?;
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let event = self.execute_fee_withdrawal(&caller_address, token_type, amount, destination, context)?;
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Withdraw collected fees once authorization has been checked
+    pub(crate) fn execute_fee_withdrawal(
+        &mut self,
+        caller_address: &str,
+        token_type: TokenType,
+        amount: Option<u64>,
+        destination: Option<String>,
+        context: Option<&OperationContext>,
+    ) -> Result<Event, ContractError> {
+        // Reentrancy protection
+        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        
+        // Validate token type
+        if let Err(_) = token_type.validate() {
+            return Err(ContractError::TokenValidationFailed);
+        }
+        
+        // Determine the amount to withdraw
+        let available = self.fee_config.collected_fees.get(&token_type).copied().unwrap_or(0);
+        let fee_amount = amount.unwrap_or(available);
+        
+        if fee_amount == 0 || fee_amount > available {
+            return Err(ContractError::InvalidAmount { reason: format!("fee withdrawal must be between 1 and the {} collected", available) });
+        }
+        
+        // Validate destination address
+        let destination_address = destination.unwrap_or_else(|| self.fee_config.fee_collector_address.clone());
+        
+        self.token_transfer.validate_destination(&destination_address)?;
+        
+        // Only activated destinations receive fees
+        self.ensure_fee_destination_whitelisted(&destination_address)?;
+        
+        drop(_guard);
+        
+        // Decrement collected fees, committing the transfer they owe with the change
+        self.fee_config.collected_fees.insert(token_type.clone(), available - fee_amount);
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::FromContract,
+            address: destination_address,
+            token_type: token_type.clone(),
+            amount: fee_amount,
+            priority: TransferPriority::Low,
+            deposit_id: None,
+            batched: false,
+            options: None,
+        };
+        let entry_id = match self.commit_to_outbox(OutboxOperation::FeeWithdrawal, transfer_request, caller_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.fee_config.collected_fees.insert(token_type, available);
+                return Err(e);
+            },
+        };
+        
+        // Transfer fees to destination, behind user withdrawals, restoring them on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.fee_config.collected_fees.insert(token_type, available);
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
+        }
+        
+        self.complete_outbox_entry(entry_id, context)
+    }
+    
+    /// Complete a fee withdrawal once the backend accepted its transfer
+    pub(crate) fn finish_fee_withdrawal(
+        &mut self,
+        caller_address: &str,
+        token_type: TokenType,
+        fee_amount: u64,
+        destination_address: String,
+        context: Option<&OperationContext>,
+    ) -> Event {
+        let remaining_fees = self.fee_config.collected_fees.get(&token_type).copied().unwrap_or(0);
+        self.fee_ledger.record_sweep(&token_type, fee_amount, destination_address.clone(), self.clock.now());
+        self.authorization_log.record(AuthorizedOperation::FeeWithdrawal, caller_address, None, &token_type, fee_amount, context, self.clock.now());
+        
+        // Return fee collection event with enhanced information
+        let event = Event::FeeCollected {
+            token_type,
+            fee_amount,
+            collector_address: self.fee_config.fee_collector_address.clone(),
+            destination_address,
+            remaining_fees,
+            transaction_hash: None, // Would be filled in a real blockchain implementation
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit_with_context(&event, context);
+        
+        event
+    }
+    
+    /// Set the address that receives collected fees (owner only)
+    pub fn set_fee_collector(&mut self, caller_address: String, fee_collector_address: String, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        self.ensure_sole_owner(&caller_address)?;
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.execute_set_fee_collector(fee_collector_address)?;
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Set the fee collector once authorization has been checked
+    pub(crate) fn execute_set_fee_collector(&mut self, fee_collector_address: String) -> Result<(), ContractError> {
+        // Validate address and the script it pays to
+        self.token_transfer.validate_destination(&fee_collector_address)?;
+        
+        self.fee_config.fee_collector_address = fee_collector_address;
+        
+        Ok(())
+    }
+    
+    /// Start transferring ownership to a new address (owner only)
+    /// 
+    /// The new owner has to call `accept_ownership` to complete the transfer.
+    pub fn transfer_ownership(&mut self, caller_address: String, new_owner: String, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        self.ensure_sole_owner(&caller_address)?;
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Validate address
+        self.check_address(&new_owner)?;
+        
+        self.pending_owner = Some(new_owner);
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Complete an ownership transfer (pending owner only)
+    pub fn accept_ownership(&mut self, caller_address: String) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        if self.pending_owner.as_deref() != Some(caller_address.as_str()) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        self.pending_owner = None;
+        
+        Ok(self.execute_ownership_change(caller_address))
+    }
+    
+    /// Hand control to a single new owner, leaving governance mode if active
+    pub(crate) fn execute_ownership_change(&mut self, new_owner: String) -> Event {
+        let previous_owner = match self.governance.take() {
+            Some(governance) => governance.owners.join(","),
+            None => self.contract_owner_address.clone(),
+        };
+        
+        self.contract_owner_address = new_owner.clone();
+        
+        let event = Event::OwnershipTransferred {
+            previous_owner,
+            new_owner,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        
+        event
+    }
+    
+    /// Check that an address is valid and belongs to the backend's network
+    /// 
+    /// Fails with `WrongNetwork` for an address of another network and with
+    /// `InvalidAddress` for one the backend does not accept.
+    pub(crate) fn check_address(&self, address: &str) -> Result<(), ContractError> {
+        self.token_transfer.check_network(address)?;
+        self.token_transfer.validate_address(address)
+            .map_err(|_| ContractError::InvalidAddress)
+    }
+    
+    /// Check whether an address is an owner, or a member of the owner set under governance
+    pub fn is_owner(&self, address: &str) -> bool {
+        match &self.governance {
+            Some(governance) => governance.owners.iter().any(|owner| owner == address),
+            None => address == self.contract_owner_address,
+        }
+    }
+    
+    /// Check that a sensitive action may be performed directly by the caller
+    /// 
+    /// Under governance sensitive actions have to go through `propose_action`.
+    pub(crate) fn ensure_sole_owner(&self, caller_address: &str) -> Result<(), ContractError> {
+        if !self.is_owner(caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        if self.governance.is_some() {
+            return Err(ContractError::GovernanceRequired);
+        }
+        
+        Ok(())
+    }
+    
+    /// Replace the time source used for locks, cooldowns and rate windows
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+    
+    /// Set the minimum deposit age before an emergency withdrawal is allowed (owner only)
+    pub fn set_min_age_before_emergency(&mut self, caller_address: String, min_age: std::time::Duration, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.emergency_policy.min_age_before_emergency = min_age;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Set the maximum emergency withdrawals per user in a rolling 30-day window (owner only)
+    pub fn set_max_emergency_withdrawals_per_month(&mut self, caller_address: String, max_withdrawals: Option<u32>, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if max_withdrawals == Some(0) {
+            return Err(ContractError::InvalidAmount { reason: "withdrawal limit cannot be zero".to_string() });
+        }
+        
+        self.emergency_policy.max_emergency_withdrawals_per_user_per_month = max_withdrawals;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Set the contract-wide maximum lock period for new deposits (owner only)
+    /// 
+    /// Existing deposits keep their unlock timestamps.
+    pub fn set_max_lock_days(&mut self, caller_address: String, max_lock_days: u32, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Per-token maximums must stay within the global one
+        let min = self.lock_policy.per_token_max.values().copied().max().unwrap_or(0).max(1);
+        if max_lock_days < min {
+            return Err(ContractError::InvalidLockPeriod { min, max: u32::MAX, requested: max_lock_days });
+        }
+        
+        self.lock_policy.max_lock_days = max_lock_days;
+        self.emit_lock_policy_updated();
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Set or clear the maximum lock period for a token type (owner only)
+    /// 
+    /// Use an empty identifier, such as `TokenType::Ordinal(String::new())`, to
+    /// cover every token of that kind. Existing deposits keep their unlock timestamps.
+    pub fn set_token_max_lock_days(&mut self, caller_address: String, token_type: TokenType, max_lock_days: Option<u32>, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        match max_lock_days {
+            Some(days) => {
+                if days == 0 || days > self.lock_policy.max_lock_days {
+                    return Err(ContractError::InvalidLockPeriod { min: 1, max: self.lock_policy.max_lock_days, requested: days });
+                }
+                
+                self.lock_policy.per_token_max.insert(token_type, days);
+            },
+            None => {
+                self.lock_policy.per_token_max.remove(&token_type);
+            },
+        }
+        
+        self.emit_lock_policy_updated();
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Get the lock policy
+    pub fn lock_policy(&self) -> &LockPolicy {
+        &self.lock_policy
+    }
+    
+    /// Set or clear the fee taken from new deposits of a token type (owner only)
+    /// 
+    /// Existing deposits are not affected.
+    pub fn set_deposit_fee(&mut self, caller_address: String, token_type: TokenType, deposit_fee: Option<DepositFee>, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        match deposit_fee {
+            Some(DepositFee::Percentage(percentage)) if percentage >= 100 => {
+                return Err(ContractError::InvalidFeePercentage);
+            },
+            Some(fee) => {
+                self.fee_config.deposit_fees.insert(token_type.clone(), fee);
+            },
+            None => {
+                self.fee_config.deposit_fees.remove(&token_type);
+            },
+        }
+        
+        self.emit(&Event::DepositFeeUpdated {
+            token_type,
+            deposit_fee,
+            timestamp: self.clock.now(),
+        });
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Announce the current lock policy
+    fn emit_lock_policy_updated(&self) {
+        self.emit(&Event::LockPolicyUpdated {
+            max_lock_days: self.lock_policy.max_lock_days,
+            per_token_max: sorted_by_token(self.lock_policy.per_token_max.iter()),
+            timestamp: self.clock.now(),
+        });
+    }
+    
+    /// Set the vault key used to sign deposit receipts
+    pub fn set_receipt_signer(&mut self, signer: Arc<dyn Signer>) {
+        self.receipt_signer = Some(signer);
+    }
+    
+    /// Register a sink notified of every event the contract emits
+    pub fn add_event_sink(&mut self, sink: Arc<dyn EventSink>) {
+        self.event_sinks.push(sink);
+    }
+    
+    /// Publish an event to all registered sinks
+    pub(crate) fn emit(&self, event: &Event) {
+        for sink in &self.event_sinks {
+            sink.publish(event);
+        }
+    }
+    
+    /// Attach a health checker so operations on a token are rejected while its backend is down
+    /// 
+    /// Backends unhealthy for less than `grace_period` are still used, so a single failed
+    /// probe does not block deposits and withdrawals.
+    pub fn set_health_checker(&mut self, health_checker: Arc<HealthChecker>, grace_period: std::time::Duration) {
+        self.health_checker = Some(health_checker);
+        self.backend_grace_period = grace_period;
+    }
+    
+    /// Get the health report of the attached health checker, if any
+    pub fn health_report(&self) -> Option<Vec<crate::bitcoin::health::HealthStatus>> {
+        self.health_checker.as_ref().map(|checker| checker.health_report())
+    }
+    
+    /// Reject operations on a token whose backend has been unhealthy past the grace period
+    fn check_backend_available(&self, token_type: &TokenType) -> Result<(), ContractError> {
+        match &self.health_checker {
+            Some(health_checker) => Self::ensure_backend_available(health_checker, self.backend_grace_period, token_type),
+            None => Ok(()),
+        }
+    }
+    
+    /// Check a token's backend against a health checker
+    pub(crate) fn ensure_backend_available(
+        health_checker: &HealthChecker,
+        grace_period: std::time::Duration,
+        token_type: &TokenType,
+    ) -> Result<(), ContractError> {
+        if let Some(component) = BackendComponent::for_token_type(token_type) {
+            if !health_checker.is_available(component, grace_period) {
+                return Err(ContractError::BackendUnavailable(component.to_string()));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Get the network type
+    pub fn get_network_type(&self) -> String {
+        self.token_transfer.get_network_type()
+    }
+    
+    /// Check if the contract is on testnet
+    pub fn is_testnet(&self) -> bool {
+        self.token_transfer.get_network_type() == "testnet"
     }
     
     /// Add a new supported token type
-    pub fn add_supported_token(&mut self, caller_address: String, token_type: TokenType) -> Result<(), ContractError> {
+    pub fn add_supported_token(&mut self, caller_address: String, token_type: TokenType, expected_nonce: Option<u64>) -> Result<(), ContractError> {
         // Check authorization
-        if caller_address != self.contract_owner_address {
+        if !self.is_owner(&caller_address) {
             return Err(ContractError::Unauthorized);
         }
         
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
         // Validate token type
         if let Err(_) = token_type.validate() {
             return Err(ContractError::TokenValidationFailed);
@@ -523,16 +1806,21 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
         // Add to supported tokens
         self.supported_tokens.push(token_type);
         
+        self.advance_owner_nonce();
+        
         Ok(())
     }
     
     /// Remove a supported token type
-    pub fn remove_supported_token(&mut self, caller_address: String, token_type: TokenType) -> Result<(), ContractError> {
+    pub fn remove_supported_token(&mut self, caller_address: String, token_type: TokenType, expected_nonce: Option<u64>) -> Result<(), ContractError> {
         // Check authorization
-        if caller_address != self.contract_owner_address {
+        if !self.is_owner(&caller_address) {
             return Err(ContractError::Unauthorized);
         }
         
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
         // Check if token type is supported
         if !self.supported_tokens.contains(&token_type) {
             return Err(ContractError::UnsupportedTokenOperation);
@@ -547,6 +1835,9 @@ impl<T: TokenTransfer> TimeLockedDeposit<T> {
         
         // Remove from supported tokens
         self.supported_tokens.retain(|t| t != &token_type);
+        self.deprecated_tokens.remove(&token_type);
+        
+        self.advance_owner_nonce();
         
         Ok(())
     }
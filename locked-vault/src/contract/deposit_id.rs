@@ -0,0 +1,174 @@
+//! Deposit ID schemes
+//!
+//! `IdScheme::Sequential` numbers deposits 1, 2, 3 and so on. Sequential IDs
+//! reveal how many deposits the contract holds, and a client only learns its
+//! ID once the call returns. Under `IdScheme::Derived` the ID is instead the
+//! SHA-256 of the depositor, token, requested amount, a reference and the
+//! contract's salt, truncated to the range below
+//! `IMPORTED_DEPOSIT_ID_OFFSET`. The reference is the deposit's UTXO
+//! reference, else its idempotency key, else the deposit's sequence number.
+//!
+//! A derived ID that is already taken is derived again with a counter
+//! appended, up to `MAX_DEPOSIT_ID_ATTEMPTS` times. `predict_deposit_id`
+//! runs the same derivation so clients can reference a deposit before
+//! making it. The scheme can only change before the first deposit.
+
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash, HashEngine};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::ContractError;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::import::IMPORTED_DEPOSIT_ID_OFFSET;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Most derivations tried before a deposit fails with `DepositIdCollision`
+pub const MAX_DEPOSIT_ID_ATTEMPTS: u32 = 16;
+
+/// How deposit IDs are assigned
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdScheme {
+    /// Consecutive IDs from 1
+    #[default]
+    Sequential,
+    /// IDs derived from the deposit and the contract's salt
+    Derived,
+}
+
+/// Derive a deposit ID candidate, 0 standing for no valid ID
+fn derive_deposit_id(
+    salt: &[u8; 32],
+    depositor_address: &str,
+    token_type: &TokenType,
+    deposit_amount: u64,
+    reference: &str,
+    attempt: u32,
+) -> u64 {
+    // Length-prefix the variable fields so no two inputs hash the same bytes
+    let mut engine = sha256::Hash::engine();
+    for field in [depositor_address, &token_type.name(), reference] {
+        engine.input(&(field.len() as u64).to_be_bytes());
+        engine.input(field.as_bytes());
+    }
+    engine.input(&deposit_amount.to_be_bytes());
+    engine.input(salt);
+    if attempt > 0 {
+        engine.input(&attempt.to_be_bytes());
+    }
+    
+    let digest = sha256::Hash::from_engine(engine).to_byte_array();
+    let mut truncated = [0u8; 8];
+    truncated.copy_from_slice(&digest[..8]);
+    
+    u64::from_be_bytes(truncated) % IMPORTED_DEPOSIT_ID_OFFSET
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get how deposit IDs are assigned
+    pub fn get_id_scheme(&self) -> IdScheme {
+        self.id_scheme
+    }
+    
+    /// Set how deposit IDs are assigned (owner only)
+    ///
+    /// Only possible before the first deposit, so IDs never mix schemes.
+    pub fn set_id_scheme(&mut self, caller_address: String, id_scheme: IdScheme, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Deposits made so far have IDs under the current scheme
+        let has_deposits = self.next_deposit_id > 1 || !self.deposit_registry.is_empty();
+        if id_scheme != self.id_scheme && has_deposits {
+            return Err(ContractError::IdSchemeLocked);
+        }
+        
+        if id_scheme != self.id_scheme {
+            info!(target: CONTRACT, "Deposit ID scheme set to {:?}", id_scheme);
+        }
+        self.id_scheme = id_scheme;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Work out the ID the next deposit with these details would get
+    ///
+    /// `utxo_reference` and `idempotency_key` are the ones the deposit will be
+    /// made with. Under `IdScheme::Derived` without either, the prediction
+    /// only holds if no other deposit is made first.
+    pub fn predict_deposit_id(
+        &self,
+        depositor_address: &str,
+        token_type: &TokenType,
+        deposit_amount: u64,
+        utxo_reference: Option<&UtxoRef>,
+        idempotency_key: Option<&str>,
+    ) -> Result<u64, ContractError> {
+        let reference = self.deposit_id_reference(utxo_reference, idempotency_key);
+        
+        self.find_deposit_id(depositor_address, token_type, deposit_amount, &reference)
+    }
+    
+    /// Assign the ID of a deposit being recorded
+    /// 
+    /// `idempotency_key` is the key the deposit is made with, if any.
+    pub(crate) fn next_deposit_id(
+        &mut self,
+        depositor_address: &str,
+        token_type: &TokenType,
+        deposit_amount: u64,
+        utxo_reference: Option<&UtxoRef>,
+        idempotency_key: Option<&str>,
+    ) -> Result<u64, ContractError> {
+        let reference = self.deposit_id_reference(utxo_reference, idempotency_key);
+        let deposit_id = self.find_deposit_id(depositor_address, token_type, deposit_amount, &reference)?;
+        
+        // The sequence keeps counting under either scheme
+        self.next_deposit_id = self.next_deposit_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        
+        Ok(deposit_id)
+    }
+    
+    /// Get the reference a derived ID is computed from
+    fn deposit_id_reference(&self, utxo_reference: Option<&UtxoRef>, idempotency_key: Option<&str>) -> String {
+        match (utxo_reference, idempotency_key) {
+            (Some(reference), _) => format!("utxo:{}", reference),
+            (None, Some(key)) => format!("key:{}", key),
+            (None, None) => format!("seq:{}", self.next_deposit_id),
+        }
+    }
+    
+    /// Find the first free ID under the current scheme
+    fn find_deposit_id(
+        &self,
+        depositor_address: &str,
+        token_type: &TokenType,
+        deposit_amount: u64,
+        reference: &str,
+    ) -> Result<u64, ContractError> {
+        match self.id_scheme {
+            // IDs from the imported range on are taken by deposits of an older deployment
+            IdScheme::Sequential => {
+                if self.next_deposit_id >= IMPORTED_DEPOSIT_ID_OFFSET {
+                    return Err(ContractError::ArithmeticError);
+                }
+                
+                Ok(self.next_deposit_id)
+            },
+            IdScheme::Derived => (0..MAX_DEPOSIT_ID_ATTEMPTS)
+                .map(|attempt| derive_deposit_id(
+                    &self.deposit_id_salt, depositor_address, token_type, deposit_amount, reference, attempt,
+                ))
+                .find(|deposit_id| *deposit_id != 0 && !self.deposit_registry.contains_key(deposit_id))
+                .ok_or(ContractError::DepositIdCollision),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! Deposits paid to their own time-locked output
+//!
+//! A script escrow deposit is not sent to the contract wallet. The depositor
+//! pays a P2WSH output spendable by the vault and the depositor together, or
+//! by the depositor alone from the lock height on, so the funds can be
+//! recovered even if the vault disappears. The deposit is pending until its
+//! funding confirms, like any other pending deposit.
+//!
+//! The locking terms are stored on the deposit and exported as a miniscript
+//! descriptor, which auditors can import into their own wallets and check
+//! against the address the depositor was told to pay.
+
+use log::info;
+
+use crate::bitcoin::descriptor::{parse_key, parse_network, TimeLockPolicy};
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{ScriptEscrow, TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Record a Bitcoin deposit to be paid to a time-locked escrow output
+    ///
+    /// The output needs signatures of the vault escrow key and
+    /// `depositor_public_key`, or of `depositor_public_key` alone from block
+    /// `lock_height` on. The deposit is pending until its funding confirms.
+    pub fn deposit_script_escrow(
+        &mut self,
+        caller_address: String,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        depositor_public_key: String,
+        lock_height: u32,
+    ) -> Result<Event, ContractError> {
+        let vault_public_key = self.escrow_public_key.clone().ok_or(ContractError::EscrowNotConfigured)?;
+        
+        // Derive the address before recording anything
+        let network = self.token_transfer.get_network_type();
+        let policy = TimeLockPolicy::new(&vault_public_key, &depositor_public_key, lock_height)?;
+        let address = policy.address(parse_network(&network)?).to_string();
+        
+        let event = self.deposit_pending(caller_address, TokenType::Bitcoin, deposit_amount, lock_period_days, None, None)?;
+        
+        // Attach the terms the depositor pays to
+        let deposit_id = event.deposit_id().ok_or(ContractError::DepositNotFound)?;
+        let deposit = self.deposit_registry.get_mut(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        let escrow = ScriptEscrow {
+            vault_public_key,
+            depositor_public_key: policy.depositor_key.to_string(),
+            lock_height,
+            network,
+            address: address.clone(),
+        };
+        self.token_transfer.watch_script_escrow(&escrow);
+        deposit.escrow = Some(escrow);
+        
+        info!(target: CONTRACT, "Deposit {} to be paid to escrow address {}", deposit_id, address);
+        
+        Ok(event)
+    }
+    
+    /// Get the vault public key of new script escrow deposits
+    pub fn get_escrow_public_key(&self) -> Option<&str> {
+        self.escrow_public_key.as_deref()
+    }
+    
+    /// Set the vault public key of new script escrow deposits (owner only)
+    ///
+    /// Existing escrow deposits keep the key they were created with. `None`
+    /// stops new escrow deposits.
+    pub fn set_escrow_public_key(&mut self, caller_address: String, public_key: Option<String>, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Store the key in its canonical form
+        self.escrow_public_key = match public_key {
+            Some(key) => Some(parse_key(&key)?.to_string()),
+            None => None,
+        };
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+}
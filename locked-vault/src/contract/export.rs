@@ -0,0 +1,67 @@
+//! CSV export of deposits
+//!
+//! Exports write one row per deposit in ID order, after a header row.
+//! Amounts are integer units and timestamps RFC 3339 in UTC, so a script
+//! reading the file needs no locale or token metadata. Fields holding a
+//! comma, quote or line break are quoted.
+//!
+//! An export reports each row to an optional `Progress` and stops with
+//! `Cancelled` before the next row once cancelled. Rows are written whole,
+//! so a cancelled export leaves the header and complete rows only; callers
+//! writing to a file should still discard it, as the CLI does.
+
+use std::io::Write;
+
+use crate::display::{format_status, format_timestamp};
+use crate::errors::ContractError;
+use crate::models::{Deposit, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::query::DepositFilter;
+use crate::progress::Progress;
+
+/// Header row of a deposit export
+pub const CSV_HEADER: &str = "id,depositor,token,amount,status,deposited_at,unlock_at";
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Write the deposits matching a filter as CSV, returning the number of rows written
+    pub fn export_deposits_csv<W: Write>(&self, filter: DepositFilter, mut writer: W, progress: Option<&Progress>) -> Result<usize, ContractError> {
+        let mut deposits: Vec<&Deposit> = self.deposits_filtered(filter).collect();
+        deposits.sort_by_key(|deposit| deposit.deposit_id);
+        
+        let idle = Progress::new();
+        let progress = progress.unwrap_or(&idle);
+        progress.start_stage("export", deposits.len() as u64);
+        
+        let write_failed = |e: std::io::Error| ContractError::ExportFailed(e.to_string());
+        writeln!(writer, "{}", CSV_HEADER).map_err(write_failed)?;
+        
+        for deposit in &deposits {
+            progress.check_cancelled()?;
+            
+            let row = [
+                deposit.deposit_id.to_string(),
+                csv_field(&deposit.depositor_address),
+                csv_field(&deposit.deposited_token_type.name()),
+                deposit.deposited_amount.to_string(),
+                format_status(&deposit.status).to_string(),
+                format_timestamp(deposit.deposit_timestamp),
+                format_timestamp(deposit.unlock_timestamp),
+            ];
+            writeln!(writer, "{}", row.join(",")).map_err(write_failed)?;
+            progress.advance(1);
+        }
+        
+        writer.flush().map_err(write_failed)?;
+        
+        Ok(deposits.len())
+    }
+}
+
+/// Quote a field if it holds a comma, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
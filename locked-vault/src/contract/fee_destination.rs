@@ -0,0 +1,217 @@
+//! Whitelist of fee withdrawal destinations
+//!
+//! Fees can only be withdrawn to activated whitelist entries, so a stolen
+//! owner key cannot send them anywhere at once. A new destination is first
+//! proposed, and may only be activated once the fee destination delay has
+//! passed, leaving time to notice the proposal and remove it. Removal is
+//! immediate. The fee collector at construction is whitelisted from the
+//! start, as is the collector of a snapshot from before the whitelist.
+//!
+//! Shortening the delay would let a key holder skip it, so a shorter delay
+//! only takes effect once the previous delay has passed since the change.
+
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::TokenTransfer;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Default delay between proposing and activating a fee destination
+pub(crate) const DEFAULT_FEE_DESTINATION_DELAY_SECS: u64 = 48 * 60 * 60;
+
+/// Fee destination delay in snapshots that predate the whitelist
+pub(crate) fn default_fee_destination_delay() -> std::time::Duration {
+    std::time::Duration::from_secs(DEFAULT_FEE_DESTINATION_DELAY_SECS)
+}
+
+/// Fee destination waiting for its delay to pass
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingFeeDestination {
+    /// Proposed address
+    pub address: String,
+    /// When the address was proposed
+    pub proposed_at: DateTime<Utc>,
+    /// When the address may be activated
+    pub activates_at: DateTime<Utc>,
+}
+
+/// Delay that still applies after the delay was shortened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeDestinationDelayFloor {
+    /// Delay before the change
+    pub delay: std::time::Duration,
+    /// When the shorter delay takes effect
+    pub until: DateTime<Utc>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the activated fee destinations, sorted
+    pub fn get_fee_destination_whitelist(&self) -> &[String] {
+        &self.fee_destination_whitelist
+    }
+    
+    /// Get the fee destinations waiting for their delay, sorted by address
+    pub fn get_pending_fee_destinations(&self) -> Vec<PendingFeeDestination> {
+        let mut pending: Vec<PendingFeeDestination> = self.pending_fee_destinations.values().cloned().collect();
+        pending.sort_by(|a, b| a.address.cmp(&b.address));
+        pending
+    }
+    
+    /// Get the delay a fee destination proposed now has to wait
+    pub fn get_fee_destination_delay(&self) -> std::time::Duration {
+        match self.fee_destination_delay_floor {
+            Some(floor) if self.clock.now() < floor.until => floor.delay.max(self.fee_destination_delay),
+            _ => self.fee_destination_delay,
+        }
+    }
+    
+    /// Set the delay between proposing and activating a fee destination (owner only)
+    ///
+    /// A longer delay applies at once, a shorter one once the current delay
+    /// has passed.
+    pub fn set_fee_destination_delay(&mut self, caller_address: String, delay: std::time::Duration, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Keep the current delay in force for as long as it lasts
+        let current = self.get_fee_destination_delay();
+        if delay < current {
+            let until = self.clock.now() + Duration::from_std(current).unwrap_or(Duration::MAX);
+            self.fee_destination_delay_floor = Some(FeeDestinationDelayFloor { delay: current, until });
+        } else {
+            self.fee_destination_delay_floor = None;
+        }
+        self.fee_destination_delay = delay;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Propose a fee withdrawal destination, activatable after the delay (owner only)
+    ///
+    /// Proposing an address again restarts its delay.
+    pub fn propose_fee_destination(&mut self, caller_address: String, address: String, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Validate address and the script it pays to
+        self.token_transfer.validate_destination(&address)?;
+        
+        let now = self.clock.now();
+        let activates_at = now + Duration::from_std(self.get_fee_destination_delay()).unwrap_or(Duration::MAX);
+        self.pending_fee_destinations.insert(address.clone(), PendingFeeDestination {
+            address: address.clone(),
+            proposed_at: now,
+            activates_at,
+        });
+        
+        info!(target: CONTRACT, "Fee destination {} proposed by {}, activatable at {}", address, caller_address, activates_at);
+        
+        let event = Event::FeeDestinationProposed {
+            address,
+            proposed_by: caller_address,
+            activates_at,
+            timestamp: now,
+        };
+        
+        self.emit(&event);
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Activate a proposed fee destination once its delay has passed (owner only)
+    pub fn activate_fee_destination(&mut self, caller_address: String, address: String, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let now = self.clock.now();
+        let pending = self.pending_fee_destinations.get(&address).ok_or(ContractError::FeeDestinationNotProposed)?;
+        if now < pending.activates_at {
+            return Err(ContractError::FeeDestinationTooSoon { activates_at: pending.activates_at });
+        }
+        
+        self.pending_fee_destinations.remove(&address);
+        if let Err(index) = self.fee_destination_whitelist.binary_search(&address) {
+            self.fee_destination_whitelist.insert(index, address.clone());
+        }
+        
+        info!(target: CONTRACT, "Fee destination {} activated by {}", address, caller_address);
+        
+        let event = Event::FeeDestinationActivated {
+            address,
+            activated_by: caller_address,
+            timestamp: now,
+        };
+        
+        self.emit(&event);
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Remove a fee destination from the whitelist, or withdraw its proposal (owner only)
+    pub fn remove_fee_destination(&mut self, caller_address: String, address: String, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let was_pending = self.pending_fee_destinations.remove(&address).is_some();
+        let was_active = match self.fee_destination_whitelist.binary_search(&address) {
+            Ok(index) => {
+                self.fee_destination_whitelist.remove(index);
+                true
+            },
+            Err(_) => false,
+        };
+        if !was_pending && !was_active {
+            return Err(ContractError::DestinationNotWhitelisted);
+        }
+        
+        info!(target: CONTRACT, "Fee destination {} removed by {}", address, caller_address);
+        
+        let event = Event::FeeDestinationRemoved {
+            address,
+            removed_by: caller_address,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Check that fees may be withdrawn to an address
+    pub(crate) fn ensure_fee_destination_whitelisted(&self, address: &str) -> Result<(), ContractError> {
+        if self.fee_destination_whitelist.binary_search_by(|entry| entry.as_str().cmp(address)).is_err() {
+            return Err(ContractError::DestinationNotWhitelisted);
+        }
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+//! History of fee accruals and fee sweeps
+//!
+//! Every deposit fee and every emergency fee credited to the collected fees
+//! is recorded as a `FeeAccrual`, and every fee withdrawal as a `FeeSweep`.
+//! Emergency fees are recorded net of the insurance share, which goes to the
+//! insurance pool instead. Sweeps draw on accruals oldest first, and each
+//! sweep records the range of accruals it drew on.
+//!
+//! The ledger keeps the latest `MAX_FEE_LEDGER_ENTRIES` entries. Older ones
+//! are folded into a per-token opening balance, so for every token the
+//! opening balance plus the retained accruals minus the retained sweeps
+//! equals the collected fees. Snapshots from before the ledger start with
+//! their collected fees as the opening balance.
+
+use std::collections::VecDeque;
+use chrono::{DateTime, Datelike, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Most accruals and sweeps kept in the ledger
+pub const MAX_FEE_LEDGER_ENTRIES: usize = 10_000;
+
+/// Operation a fee accrued from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FeeAccrualKind {
+    /// Fee of an emergency withdrawal, net of the insurance share
+    EmergencyFee,
+    /// Fee taken from a deposit
+    DepositFee,
+}
+
+/// Fee credited to the collected fees
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeAccrual {
+    /// Position in the ledger, shared with sweeps
+    pub sequence: u64,
+    /// Deposit the fee came from
+    pub deposit_id: u64,
+    /// Token type
+    pub token_type: TokenType,
+    /// Fee amount
+    pub amount: u64,
+    /// Operation the fee accrued from
+    pub kind: FeeAccrualKind,
+    /// When the fee accrued
+    pub timestamp: DateTime<Utc>,
+    /// Fees of this token accrued before this one, counting the opening balance
+    pub accrued_before: u64,
+}
+
+/// Fee withdrawal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeSweep {
+    /// Position in the ledger, shared with accruals
+    pub sequence: u64,
+    /// Token type
+    pub token_type: TokenType,
+    /// Amount withdrawn
+    pub amount: u64,
+    /// Address the fees were sent to
+    pub destination_address: String,
+    /// When the fees were withdrawn
+    pub timestamp: DateTime<Utc>,
+    /// Sequence of the first accrual drawn on, `None` if it is no longer in the ledger
+    pub first_accrual: Option<u64>,
+    /// Sequence of the last accrual drawn on, `None` if it is no longer in the ledger
+    pub last_accrual: Option<u64>,
+}
+
+/// Fees of a token in one calendar month (UTC)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeMonthSummary {
+    /// Year
+    pub year: i32,
+    /// Month, 1 to 12
+    pub month: u32,
+    /// Deposit fees accrued
+    pub deposit_fees: u64,
+    /// Emergency fees accrued
+    pub emergency_fees: u64,
+    /// Fees withdrawn
+    pub swept: u64,
+    /// Number of accruals
+    pub accrual_count: u32,
+}
+
+/// Running totals of a token since the ledger started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeTotals {
+    /// Balance before the oldest retained entry
+    pub opening_balance: u64,
+    /// Fees accrued, counting the balance the ledger started with
+    pub accrued: u64,
+    /// Fees swept
+    pub swept: u64,
+}
+
+/// Accruals and sweeps, oldest first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeLedger {
+    /// Retained accruals
+    pub accruals: VecDeque<FeeAccrual>,
+    /// Retained sweeps
+    pub sweeps: VecDeque<FeeSweep>,
+    /// Totals per token, sorted by token
+    pub totals: Vec<(TokenType, FeeTotals)>,
+    /// Sequence of the next entry
+    pub next_sequence: u64,
+}
+
+impl FeeLedger {
+    /// Start a ledger for fees collected before it existed
+    pub(crate) fn opening<'a>(collected_fees: impl Iterator<Item = (&'a TokenType, &'a u64)>) -> Self {
+        let mut ledger = Self::default();
+        for (token_type, amount) in collected_fees {
+            let totals = ledger.totals_mut(token_type);
+            totals.opening_balance = *amount;
+            totals.accrued = *amount;
+        }
+        ledger
+    }
+    
+    /// Get the totals of a token
+    fn totals(&self, token_type: &TokenType) -> FeeTotals {
+        self.totals.iter()
+            .find(|(token, _)| token == token_type)
+            .map(|(_, totals)| *totals)
+            .unwrap_or_default()
+    }
+    
+    /// Get the totals of a token for updating
+    fn totals_mut(&mut self, token_type: &TokenType) -> &mut FeeTotals {
+        let key = format!("{:?}", token_type);
+        let index = match self.totals.binary_search_by(|(token, _)| format!("{:?}", token).cmp(&key)) {
+            Ok(index) => index,
+            Err(index) => {
+                self.totals.insert(index, (token_type.clone(), FeeTotals::default()));
+                index
+            },
+        };
+        &mut self.totals[index].1
+    }
+    
+    /// Take the next sequence number
+    fn take_sequence(&mut self) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        sequence
+    }
+    
+    /// Find the retained accrual of a token holding a position of its cumulative fees
+    fn accrual_at(&self, token_type: &TokenType, position: u64) -> Option<u64> {
+        self.accruals.iter()
+            .filter(|accrual| &accrual.token_type == token_type)
+            .find(|accrual| accrual.accrued_before <= position && position - accrual.accrued_before < accrual.amount)
+            .map(|accrual| accrual.sequence)
+    }
+    
+    /// Fold the oldest entries into the opening balances until the ledger is within its bound
+    fn prune(&mut self) {
+        while self.accruals.len() + self.sweeps.len() > MAX_FEE_LEDGER_ENTRIES {
+            let accrual_first = match (self.accruals.front(), self.sweeps.front()) {
+                (Some(accrual), Some(sweep)) => accrual.sequence < sweep.sequence,
+                (accrual, _) => accrual.is_some(),
+            };
+            
+            if accrual_first {
+                if let Some(accrual) = self.accruals.pop_front() {
+                    let totals = self.totals_mut(&accrual.token_type);
+                    totals.opening_balance = totals.opening_balance.saturating_add(accrual.amount);
+                }
+            } else if let Some(sweep) = self.sweeps.pop_front() {
+                let totals = self.totals_mut(&sweep.token_type);
+                totals.opening_balance = totals.opening_balance.saturating_sub(sweep.amount);
+            }
+        }
+    }
+    
+    /// Record a fee credited to the collected fees
+    pub(crate) fn record_accrual(&mut self, deposit_id: u64, token_type: &TokenType, amount: u64, kind: FeeAccrualKind, timestamp: DateTime<Utc>) {
+        if amount == 0 {
+            return;
+        }
+        
+        let sequence = self.take_sequence();
+        let totals = self.totals_mut(token_type);
+        let accrued_before = totals.accrued;
+        totals.accrued = totals.accrued.saturating_add(amount);
+        
+        self.accruals.push_back(FeeAccrual {
+            sequence,
+            deposit_id,
+            token_type: token_type.clone(),
+            amount,
+            kind,
+            timestamp,
+            accrued_before,
+        });
+        self.prune();
+    }
+    
+    /// Record a fee withdrawal, drawing on the oldest accruals not swept yet
+    pub(crate) fn record_sweep(&mut self, token_type: &TokenType, amount: u64, destination_address: String, timestamp: DateTime<Utc>) -> FeeSweep {
+        let sequence = self.take_sequence();
+        let swept_before = self.totals(token_type).swept;
+        let first_accrual = self.accrual_at(token_type, swept_before);
+        let last_accrual = self.accrual_at(token_type, swept_before.saturating_add(amount).saturating_sub(1));
+        
+        let totals = self.totals_mut(token_type);
+        totals.swept = totals.swept.saturating_add(amount);
+        
+        let sweep = FeeSweep {
+            sequence,
+            token_type: token_type.clone(),
+            amount,
+            destination_address,
+            timestamp,
+            first_accrual,
+            last_accrual,
+        };
+        self.sweeps.push_back(sweep.clone());
+        self.prune();
+        
+        sweep
+    }
+}
+
+/// Get the summary of the month of a timestamp, inserting it in order if missing
+fn month_summary<'a>(months: &'a mut Vec<FeeMonthSummary>, timestamp: &DateTime<Utc>) -> &'a mut FeeMonthSummary {
+    let (year, month) = (timestamp.year(), timestamp.month());
+    let index = match months.binary_search_by(|summary| (summary.year, summary.month).cmp(&(year, month))) {
+        Ok(index) => index,
+        Err(index) => {
+            months.insert(index, FeeMonthSummary { year, month, deposit_fees: 0, emergency_fees: 0, swept: 0, accrual_count: 0 });
+            index
+        },
+    };
+    &mut months[index]
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the fee accruals of a token from `from` (inclusive) to `to` (exclusive), oldest first
+    pub fn get_fee_accruals(&self, token_type: &TokenType, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<FeeAccrual> {
+        self.fee_ledger.accruals.iter()
+            .filter(|accrual| &accrual.token_type == token_type && accrual.timestamp >= from && accrual.timestamp < to)
+            .cloned()
+            .collect()
+    }
+    
+    /// Get the fee sweeps of a token, oldest first
+    pub fn get_fee_sweeps(&self, token_type: &TokenType) -> Vec<FeeSweep> {
+        self.fee_ledger.sweeps.iter()
+            .filter(|sweep| &sweep.token_type == token_type)
+            .cloned()
+            .collect()
+    }
+    
+    /// Get the fee balance of a token before its oldest retained ledger entry
+    pub fn get_fee_opening_balance(&self, token_type: &TokenType) -> u64 {
+        self.fee_ledger.totals(token_type).opening_balance
+    }
+    
+    /// Sum the retained fee accruals and sweeps of a token per calendar month, oldest first
+    pub fn get_fee_summary_by_month(&self, token_type: &TokenType) -> Vec<FeeMonthSummary> {
+        let mut months: Vec<FeeMonthSummary> = Vec::new();
+        for accrual in self.fee_ledger.accruals.iter().filter(|accrual| &accrual.token_type == token_type) {
+            let summary = month_summary(&mut months, &accrual.timestamp);
+            match accrual.kind {
+                FeeAccrualKind::DepositFee => summary.deposit_fees = summary.deposit_fees.saturating_add(accrual.amount),
+                FeeAccrualKind::EmergencyFee => summary.emergency_fees = summary.emergency_fees.saturating_add(accrual.amount),
+            }
+            summary.accrual_count += 1;
+        }
+        for sweep in self.fee_ledger.sweeps.iter().filter(|sweep| &sweep.token_type == token_type) {
+            let summary = month_summary(&mut months, &sweep.timestamp);
+            summary.swept = summary.swept.saturating_add(sweep.amount);
+        }
+        
+        months
+    }
+    
+    /// Check that the ledger explains the collected fees of every token
+    pub(crate) fn check_fee_ledger(&self) -> Result<(), String> {
+        let mut tokens: Vec<&TokenType> = self.fee_config.collected_fees.keys()
+            .chain(self.fee_ledger.totals.iter().map(|(token_type, _)| token_type))
+            .collect();
+        tokens.sort_by_key(|token_type| format!("{:?}", token_type));
+        tokens.dedup();
+        
+        for token_type in tokens {
+            let opening = self.get_fee_opening_balance(token_type) as i128;
+            let accrued: i128 = self.fee_ledger.accruals.iter()
+                .filter(|accrual| &accrual.token_type == token_type)
+                .map(|accrual| accrual.amount as i128)
+                .sum();
+            let swept: i128 = self.fee_ledger.sweeps.iter()
+                .filter(|sweep| &sweep.token_type == token_type)
+                .map(|sweep| sweep.amount as i128)
+                .sum();
+            let collected = self.fee_config.collected_fees.get(token_type).copied().unwrap_or(0) as i128;
+            
+            if opening + accrued - swept != collected {
+                return Err(format!(
+                    "{:?}: fee ledger explains {} (opening {} + accrued {} - swept {}) but collected fees are {}",
+                    token_type, opening + accrued - swept, opening, accrued, swept, collected,
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Liquidity forecasts of the hot wallet
+//!
+//! Projects, week by week, how much of each token the hot wallet has to pay
+//! out over the coming weeks. Scheduled outflows are the deposits unlocking
+//! in a week, read from the unlock index like unlock schedules; deposits
+//! already unlocked or waiting on their withdrawal count towards the current
+//! week. On top of that a share of the still locked balance is assumed to be
+//! withdrawn in an emergency every week, at `emergency_rate_bps` basis
+//! points. Deposits withdrawn in an emergency no longer unlock later, so each
+//! deposit counts towards the forecast once in total. Amounts are gross: the
+//! emergency fee the vault keeps is not deducted.
+//!
+//! The cumulative amount required by the end of each week is compared with
+//! the balances of a reconciliation report to flag projected shortfalls.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::models::{DepositStatus, TokenType, TokenTransfer};
+use crate::contract::audit::ReconciliationReport;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::schedule::ScheduleGranularity;
+
+/// Longest forecast horizon, about ten years
+pub const MAX_FORECAST_WEEKS: u32 = 520;
+
+/// Basis points in a whole
+const BPS_DENOMINATOR: u32 = 10_000;
+
+/// Projected outflows of one token within one week
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForecastBucket {
+    /// Start of the week (Monday, 00:00 UTC)
+    pub week_start: DateTime<Utc>,
+    /// Token type
+    pub token: TokenType,
+    /// Amount of the deposits unlocking in the week that were not withdrawn in an emergency before
+    pub scheduled_outflow: u64,
+    /// Amount of still locked deposits assumed to be withdrawn in an emergency during the week
+    pub modeled_emergency_outflow: u64,
+    /// Total outflow from the start of the forecast to the end of the week
+    pub cumulative_required: u64,
+}
+
+/// First week in which a token's projected outflows exceed its balance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectedShortfall {
+    /// Token type
+    pub token: TokenType,
+    /// Start of the week
+    pub week_start: DateTime<Utc>,
+    /// Cumulative outflow required by the end of the week
+    pub required: u64,
+    /// Balance the hot wallet holds now
+    pub available: u64,
+    /// Required minus available
+    pub shortfall: u64,
+}
+
+/// Liquidity forecast compared with current balances
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiquidityForecast {
+    /// When the forecast was made
+    pub generated_at: DateTime<Utc>,
+    /// Number of weeks forecast
+    pub horizon_weeks: u32,
+    /// Share of the locked balance assumed to be withdrawn in an emergency each week, in basis points
+    pub emergency_rate_bps: u32,
+    /// Outflows per week and token, by week, then by token
+    pub buckets: Vec<ForecastBucket>,
+    /// Tokens whose balance does not cover the forecast, by token
+    pub shortfalls: Vec<ProjectedShortfall>,
+    /// Tokens whose balance was unknown, with the reason
+    pub unchecked_tokens: Vec<(TokenType, String)>,
+}
+
+impl LiquidityForecast {
+    /// Whether the current balances cover every week of the forecast
+    pub fn is_covered(&self) -> bool {
+        self.shortfalls.is_empty() && self.unchecked_tokens.is_empty()
+    }
+}
+
+/// Check the parameters of a forecast
+pub fn check_forecast_params(horizon_weeks: u32, emergency_rate_bps: u32) -> Result<(), ContractError> {
+    if horizon_weeks == 0 || horizon_weeks > MAX_FORECAST_WEEKS {
+        return Err(ContractError::InvalidForecast(format!("horizon of {} weeks is not between 1 and {}", horizon_weeks, MAX_FORECAST_WEEKS)));
+    }
+    if emergency_rate_bps > BPS_DENOMINATOR {
+        return Err(ContractError::InvalidForecast(format!("emergency rate of {} bps is above {}", emergency_rate_bps, BPS_DENOMINATOR)));
+    }
+    
+    Ok(())
+}
+
+/// Find the first week each token's cumulative outflow exceeds its balance in a reconciliation report
+///
+/// Returns the shortfalls and the tokens whose balance the report does not hold.
+pub fn projected_shortfalls(buckets: &[ForecastBucket], report: &ReconciliationReport) -> (Vec<ProjectedShortfall>, Vec<(TokenType, String)>) {
+    let mut shortfalls: Vec<ProjectedShortfall> = Vec::new();
+    let mut unchecked: Vec<(TokenType, String)> = Vec::new();
+    
+    for bucket in buckets {
+        if shortfalls.iter().any(|shortfall| shortfall.token == bucket.token) || unchecked.iter().any(|(token, _)| *token == bucket.token) {
+            continue;
+        }
+        
+        let balance = report.tokens.iter().find(|token| token.token_type == bucket.token);
+        let available = match balance.map(|token| (token.actual, &token.error)) {
+            Some((Some(actual), _)) => actual,
+            Some((None, error)) => {
+                unchecked.push((bucket.token.clone(), error.clone().unwrap_or_else(|| "Balance unavailable".to_string())));
+                continue;
+            },
+            None => {
+                unchecked.push((bucket.token.clone(), "Not in the reconciliation report".to_string()));
+                continue;
+            },
+        };
+        
+        if bucket.cumulative_required > available {
+            shortfalls.push(ProjectedShortfall {
+                token: bucket.token.clone(),
+                week_start: bucket.week_start,
+                required: bucket.cumulative_required,
+                available,
+                shortfall: bucket.cumulative_required - available,
+            });
+        }
+    }
+    
+    shortfalls.sort_by_key(|shortfall| format!("{:?}", shortfall.token));
+    unchecked.sort_by_key(|(token, _)| format!("{:?}", token));
+    (shortfalls, unchecked)
+}
+
+/// Scale an amount by a factor, rounding to the nearest unit
+fn scale(amount: u64, factor: f64) -> u64 {
+    (amount as f64 * factor).round() as u64
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Project the outflows of each token per week over the next `horizon_weeks` weeks
+    ///
+    /// The first week is the current one. Active, suspended and withdrawing
+    /// deposits are counted; pending deposits hold no funds yet. Buckets are
+    /// ordered by week, then by token, with one bucket per token holding
+    /// deposits in every week.
+    pub fn liquidity_forecast(&self, horizon_weeks: u32, emergency_rate_bps: u32) -> Result<Vec<ForecastBucket>, ContractError> {
+        check_forecast_params(horizon_weeks, emergency_rate_bps)?;
+        
+        let first_week = ScheduleGranularity::Weekly.period_start(self.clock.now());
+        let weeks = horizon_weeks as usize;
+        
+        // Amounts unlocking per week and after the horizon, per token
+        let mut unlocking: Vec<(TokenType, Vec<u64>, u64)> = Vec::new();
+        for (unlock_timestamp, deposit_id) in self.user_index.unlocks.values().flatten() {
+            let Some(deposit) = self.deposit_registry.get(deposit_id) else {
+                continue;
+            };
+            if !matches!(deposit.status, DepositStatus::Active | DepositStatus::Suspended | DepositStatus::WithdrawalPending) {
+                continue;
+            }
+            
+            let index = match unlocking.iter().position(|(token, _, _)| *token == deposit.deposited_token_type) {
+                Some(index) => index,
+                None => {
+                    unlocking.push((deposit.deposited_token_type.clone(), vec![0; weeks], 0));
+                    unlocking.len() - 1
+                },
+            };
+            let (_, scheduled, beyond) = &mut unlocking[index];
+            
+            // Unlocked and withdrawing deposits can be paid out this week
+            let week = if deposit.status == DepositStatus::WithdrawalPending {
+                0
+            } else {
+                unlock_timestamp.signed_duration_since(first_week).num_weeks().max(0) as usize
+            };
+            match scheduled.get_mut(week) {
+                Some(amount) => *amount = amount.saturating_add(deposit.deposited_amount),
+                None => *beyond = beyond.saturating_add(deposit.deposited_amount),
+            }
+        }
+        unlocking.sort_by_cached_key(|(token, _, _)| format!("{:?}", token));
+        
+        let rate = emergency_rate_bps as f64 / BPS_DENOMINATOR as f64;
+        let mut buckets = Vec::with_capacity(weeks * unlocking.len());
+        for (token, scheduled, beyond) in &unlocking {
+            let mut locked = scheduled.iter().fold(*beyond, |total, amount| total.saturating_add(*amount));
+            let mut cumulative_required: u64 = 0;
+            
+            for (week, scheduled) in scheduled.iter().enumerate() {
+                // Share of the deposits not withdrawn in an emergency in earlier weeks
+                let surviving = (1.0 - rate).powi(week as i32);
+                locked = locked.saturating_sub(*scheduled);
+                
+                let scheduled_outflow = scale(*scheduled, surviving);
+                let modeled_emergency_outflow = scale(locked, surviving * rate);
+                cumulative_required = cumulative_required
+                    .saturating_add(scheduled_outflow)
+                    .saturating_add(modeled_emergency_outflow);
+                
+                buckets.push(ForecastBucket {
+                    week_start: first_week + Duration::weeks(week as i64),
+                    token: token.clone(),
+                    scheduled_outflow,
+                    modeled_emergency_outflow,
+                    cumulative_required,
+                });
+            }
+        }
+        
+        // Order by week, keeping the token order within each week
+        buckets.sort_by_key(|bucket| bucket.week_start);
+        Ok(buckets)
+    }
+    
+    /// Forecast the outflows and flag the tokens whose balance in `report` does not cover them
+    pub fn liquidity_forecast_report(
+        &self,
+        horizon_weeks: u32,
+        emergency_rate_bps: u32,
+        report: &ReconciliationReport,
+    ) -> Result<LiquidityForecast, ContractError> {
+        let buckets = self.liquidity_forecast(horizon_weeks, emergency_rate_bps)?;
+        let (shortfalls, unchecked_tokens) = projected_shortfalls(&buckets, report);
+        
+        Ok(LiquidityForecast {
+            generated_at: self.clock.now(),
+            horizon_weeks,
+            emergency_rate_bps,
+            buckets,
+            shortfalls,
+            unchecked_tokens,
+        })
+    }
+}
+
+/// Source of liquidity forecasts, shared with the HTTP API
+pub trait ForecastSource: Send + Sync + fmt::Debug {
+    /// Forecast the outflows over `horizon_weeks` weeks against the current balances
+    fn liquidity_forecast(&self, horizon_weeks: u32, emergency_rate_bps: u32) -> Result<LiquidityForecast, String>;
+}
+
+/// Forecasts of a running contract against the daemon's latest reconciliation
+pub struct LiquidityForecaster<T: TokenTransfer> {
+    /// Contract holding the deposits
+    contract: Arc<Mutex<TimeLockedDeposit<T>>>,
+    /// Latest reconciliation report, refreshed by the daemon
+    reconciliation_report: Arc<Mutex<Option<ReconciliationReport>>>,
+}
+
+impl<T: TokenTransfer> fmt::Debug for LiquidityForecaster<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LiquidityForecaster").finish_non_exhaustive()
+    }
+}
+
+impl<T: TokenTransfer> LiquidityForecaster<T> {
+    /// Create a forecaster over a contract and the slot of its latest reconciliation report
+    pub fn new(contract: Arc<Mutex<TimeLockedDeposit<T>>>, reconciliation_report: Arc<Mutex<Option<ReconciliationReport>>>) -> Self {
+        Self { contract, reconciliation_report }
+    }
+}
+
+impl<T: TokenTransfer + Send> ForecastSource for LiquidityForecaster<T> {
+    fn liquidity_forecast(&self, horizon_weeks: u32, emergency_rate_bps: u32) -> Result<LiquidityForecast, String> {
+        let latest = self.reconciliation_report.lock()
+            .map_err(|_| "Reconciliation report lock poisoned".to_string())?
+            .clone();
+        let contract = self.contract.lock()
+            .map_err(|_| "Contract lock poisoned".to_string())?;
+        
+        // Reconcile now if the daemon has not yet
+        let report = latest.unwrap_or_else(|| contract.reconcile(0));
+        contract.liquidity_forecast_report(horizon_weeks, emergency_rate_bps, &report)
+            .map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,359 @@
+//! Multi-owner governance for sensitive owner actions
+//!
+//! In governance mode the single owner is replaced by an owner set with an
+//! approval threshold. Sensitive actions are proposed by one owner, approved by
+//! others and executed automatically once the threshold is reached.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::insurance::CompensationTarget;
+use crate::logging::CONTRACT;
+
+/// Sensitive owner action that requires approval under governance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OwnerAction {
+    /// Withdraw collected fees, see `withdraw_fees`
+    WithdrawFees {
+        /// Token type
+        token_type: TokenType,
+        /// Amount to withdraw, all collected fees if `None`
+        amount: Option<u64>,
+        /// Destination address, the fee collector if `None`
+        destination: Option<String>,
+    },
+    /// Change the fee collector, see `set_fee_collector`
+    SetFeeCollector {
+        /// New fee collector address
+        fee_collector_address: String,
+    },
+    /// Replace the owner set and threshold
+    UpdateOwners {
+        /// New owner addresses
+        owners: Vec<String>,
+        /// Approvals required for execution
+        threshold: u32,
+    },
+    /// Hand control to a single owner and leave governance mode
+    TransferOwnership {
+        /// New owner address
+        new_owner: String,
+    },
+    /// Pay compensation from the insurance pool, see `compensate_from_insurance`
+    CompensateFromInsurance {
+        /// Who is compensated
+        target: CompensationTarget,
+        /// Amount to pay
+        amount: u64,
+        /// Why the compensation is paid
+        reason: String,
+    },
+}
+
+/// Lifecycle state of a proposal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    /// Waiting for approvals
+    Pending,
+    /// Threshold reached and action executed
+    Executed,
+    /// Not approved within the proposal window
+    Expired,
+    /// Executed on its proposer's approval alone, and the action failed
+    Failed,
+}
+
+/// Proposed owner action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    /// Proposal ID
+    pub proposal_id: u64,
+    /// Proposed action
+    pub action: OwnerAction,
+    /// Proposing owner
+    pub proposer: String,
+    /// Owners that approved, including the proposer
+    pub approvals: Vec<String>,
+    /// When the proposal was created
+    pub created_at: DateTime<Utc>,
+    /// When the proposal expires if not executed
+    pub expires_at: DateTime<Utc>,
+    /// Current status
+    pub status: ProposalStatus,
+}
+
+/// Owner set, threshold and proposals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Governance {
+    /// Owner addresses
+    pub owners: Vec<String>,
+    /// Approvals required to execute a proposal
+    pub threshold: u32,
+    /// How long proposals stay open
+    pub proposal_ttl: Duration,
+    /// Proposals by ID
+    pub proposals: BTreeMap<u64, Proposal>,
+    /// Next proposal ID to assign
+    pub next_proposal_id: u64,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Switch from single-owner mode to an owner set with an approval threshold (owner only)
+    pub fn enable_governance(
+        &mut self,
+        caller_address: String,
+        owners: Vec<String>,
+        threshold: u32,
+        proposal_ttl: Duration,
+        expected_nonce: Option<u64>,
+    ) -> Result<(), ContractError> {
+        // Check authorization
+        self.ensure_sole_owner(&caller_address)?;
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.validate_owner_set(&owners, threshold)?;
+        
+        if proposal_ttl.is_zero() {
+            return Err(ContractError::InvalidGovernanceConfig("proposal window cannot be zero".to_string()));
+        }
+        
+        info!(target: CONTRACT, "Enabling governance with {} owners and threshold {}", owners.len(), threshold);
+        
+        self.governance = Some(Governance {
+            owners,
+            threshold,
+            proposal_ttl,
+            proposals: BTreeMap::new(),
+            next_proposal_id: 1,
+        });
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Get the governance state, if governance mode is enabled
+    pub fn governance(&self) -> Option<&Governance> {
+        self.governance.as_ref()
+    }
+    
+    /// Propose a sensitive action (any owner), returning the proposal ID
+    ///
+    /// The proposer's approval is counted, so with a threshold of one the
+    /// action executes immediately.
+    pub fn propose_action(&mut self, caller_address: String, action: OwnerAction, expected_nonce: Option<u64>) -> Result<u64, ContractError> {
+        self.expire_proposals();
+        
+        // Check authorization
+        if !self.governance.as_ref().is_some_and(|governance| governance.owners.contains(&caller_address)) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let now = self.clock.now();
+        let governance = self.governance.as_mut().ok_or(ContractError::Unauthorized)?;
+        
+        let ttl = chrono::Duration::from_std(governance.proposal_ttl)
+            .map_err(|_| ContractError::ArithmeticError)?;
+        
+        let proposal_id = governance.next_proposal_id;
+        governance.next_proposal_id = governance.next_proposal_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        
+        let proposal = Proposal {
+            proposal_id,
+            action: action.clone(),
+            proposer: caller_address.clone(),
+            approvals: Vec::new(),
+            created_at: now,
+            expires_at: now + ttl,
+            status: ProposalStatus::Pending,
+        };
+        
+        governance.proposals.insert(proposal_id, proposal);
+        
+        self.emit(&Event::ProposalCreated {
+            proposal_id,
+            proposer: caller_address.clone(),
+            action,
+            expires_at: now + ttl,
+            timestamp: now,
+        });
+        
+        // With a threshold of one the proposal executes right away; if that
+        // fails, close it instead of leaving it pending without approvals
+        if let Err(e) = self.record_approval(proposal_id, caller_address) {
+            if let Some(proposal) = self.governance.as_mut().and_then(|governance| governance.proposals.get_mut(&proposal_id)) {
+                proposal.status = ProposalStatus::Failed;
+            }
+            
+            return Err(e);
+        }
+        self.advance_owner_nonce();
+        
+        Ok(proposal_id)
+    }
+    
+    /// Approve a pending proposal (any owner), executing it once the threshold is reached
+    pub fn approve_action(&mut self, caller_address: String, proposal_id: u64, expected_nonce: Option<u64>) -> Result<ProposalStatus, ContractError> {
+        self.expire_proposals();
+        
+        let governance = self.governance.as_ref().ok_or(ContractError::Unauthorized)?;
+        
+        // Check authorization
+        if !governance.owners.contains(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let status = self.record_approval(proposal_id, caller_address)?;
+        self.advance_owner_nonce();
+        
+        Ok(status)
+    }
+    
+    /// Mark pending proposals past their window as expired
+    pub fn expire_proposals(&mut self) {
+        let now = self.clock.now();
+        
+        let expired: Vec<u64> = match self.governance.as_mut() {
+            Some(governance) => governance.proposals.values_mut()
+                .filter(|proposal| proposal.status == ProposalStatus::Pending && now >= proposal.expires_at)
+                .map(|proposal| {
+                    proposal.status = ProposalStatus::Expired;
+                    proposal.proposal_id
+                })
+                .collect(),
+            None => return,
+        };
+        
+        for proposal_id in expired {
+            self.emit(&Event::ProposalExpired {
+                proposal_id,
+                timestamp: now,
+            });
+        }
+    }
+    
+    /// Add an owner's approval and execute the proposal if the threshold is met
+    fn record_approval(&mut self, proposal_id: u64, approver: String) -> Result<ProposalStatus, ContractError> {
+        let now = self.clock.now();
+        let governance = self.governance.as_mut().ok_or(ContractError::Unauthorized)?;
+        let threshold = governance.threshold;
+        
+        let proposal = governance.proposals.get_mut(&proposal_id).ok_or(ContractError::ProposalNotFound)?;
+        
+        if proposal.status != ProposalStatus::Pending {
+            return Err(ContractError::ProposalClosed);
+        }
+        
+        if proposal.approvals.contains(&approver) {
+            return Err(ContractError::AlreadyApproved);
+        }
+        
+        proposal.approvals.push(approver.clone());
+        let approvals = proposal.approvals.len() as u32;
+        let action = proposal.action.clone();
+        
+        self.emit(&Event::ProposalApproved {
+            proposal_id,
+            approver: approver.clone(),
+            approvals,
+            threshold,
+            timestamp: now,
+        });
+        
+        if approvals < threshold {
+            return Ok(ProposalStatus::Pending);
+        }
+        
+        // Execute, withdrawing the approval again if the action fails
+        if let Err(e) = self.execute_owner_action(&approver, action.clone()) {
+            if let Some(proposal) = self.governance.as_mut().and_then(|governance| governance.proposals.get_mut(&proposal_id)) {
+                proposal.approvals.pop();
+            }
+            
+            return Err(e);
+        }
+        
+        // The proposal may be gone if the action left governance mode
+        if let Some(proposal) = self.governance.as_mut().and_then(|governance| governance.proposals.get_mut(&proposal_id)) {
+            proposal.status = ProposalStatus::Executed;
+        }
+        
+        self.emit(&Event::ProposalExecuted {
+            proposal_id,
+            action,
+            timestamp: now,
+        });
+        
+        Ok(ProposalStatus::Executed)
+    }
+    
+    /// Perform an approved action, `approver` being the owner whose approval met the threshold
+    fn execute_owner_action(&mut self, approver: &str, action: OwnerAction) -> Result<(), ContractError> {
+        match action {
+            OwnerAction::WithdrawFees { token_type, amount, destination } => {
+                self.execute_fee_withdrawal(approver, token_type, amount, destination, None)?;
+            },
+            OwnerAction::SetFeeCollector { fee_collector_address } => {
+                self.execute_set_fee_collector(fee_collector_address)?;
+            },
+            OwnerAction::UpdateOwners { owners, threshold } => {
+                self.validate_owner_set(&owners, threshold)?;
+                
+                if let Some(governance) = self.governance.as_mut() {
+                    governance.owners = owners;
+                    governance.threshold = threshold;
+                }
+            },
+            OwnerAction::TransferOwnership { new_owner } => {
+                // Validate address
+                self.check_address(&new_owner)?;
+                
+                self.execute_ownership_change(new_owner);
+            },
+            OwnerAction::CompensateFromInsurance { target, amount, reason } => {
+                self.execute_compensation(target, amount, reason)?;
+            },
+        }
+        
+        Ok(())
+    }
+    
+    /// Check an owner set and threshold
+    fn validate_owner_set(&self, owners: &[String], threshold: u32) -> Result<(), ContractError> {
+        if owners.is_empty() {
+            return Err(ContractError::InvalidGovernanceConfig("owner set cannot be empty".to_string()));
+        }
+        
+        let unique: HashSet<&String> = owners.iter().collect();
+        if unique.len() != owners.len() {
+            return Err(ContractError::InvalidGovernanceConfig("duplicate owner".to_string()));
+        }
+        
+        if threshold == 0 || threshold as usize > owners.len() {
+            return Err(ContractError::InvalidGovernanceConfig(format!(
+                "threshold must be between 1 and {}", owners.len()
+            )));
+        }
+        
+        for owner in owners {
+            self.check_address(owner)?;
+        }
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Idempotency keys for state-changing calls
+//!
+//! A call made with an idempotency key records its resulting event. Repeating
+//! the key with the same arguments returns the recorded event without running
+//! the operation again, so clients can safely retry on timeouts. Failed calls
+//! are not recorded and may be retried with the same key.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Maximum number of idempotency keys remembered at once
+const MAX_IDEMPOTENCY_KEYS: usize = 10_000;
+
+/// Recorded result of an idempotent call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    /// Operation and arguments the key was first used with
+    pub fingerprint: String,
+    /// Event produced by the call
+    pub event: Event,
+    /// When the call was executed
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Deposit tokens, deduplicating retries by idempotency key
+    pub fn deposit_idempotent(
+        &mut self,
+        idempotency_key: Option<String>,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        utxo_reference: Option<UtxoRef>,
+    ) -> Result<Event, ContractError> {
+        let fingerprint = format!(
+            "deposit:{}:{:?}:{}:{}:{:?}",
+            caller_address, token_type, deposit_amount, lock_period_days, utxo_reference
+        );
+        
+        // Derived deposit IDs are computed from the key
+        let key = idempotency_key.clone();
+        self.run_idempotent(idempotency_key, fingerprint, |contract| {
+            contract.deposit_internal(caller_address, token_type, deposit_amount, lock_period_days, utxo_reference, None, None, key.as_deref())
+        })
+    }
+    
+    /// Withdraw an unlocked deposit, deduplicating retries by idempotency key
+    pub fn withdraw_idempotent(
+        &mut self,
+        idempotency_key: Option<String>,
+        caller_address: String,
+        deposit_id: u64,
+    ) -> Result<Event, ContractError> {
+        let fingerprint = format!("withdraw:{}:{}", caller_address, deposit_id);
+        
+        self.run_idempotent(idempotency_key, fingerprint, |contract| {
+            contract.withdraw(caller_address, deposit_id)
+        })
+    }
+    
+    /// Emergency withdraw a deposit, deduplicating retries by idempotency key
+    pub fn emergency_withdraw_idempotent(
+        &mut self,
+        idempotency_key: Option<String>,
+        caller_address: String,
+        deposit_id: u64,
+    ) -> Result<Event, ContractError> {
+        let fingerprint = format!("emergency_withdraw:{}:{}", caller_address, deposit_id);
+        
+        self.run_idempotent(idempotency_key, fingerprint, |contract| {
+            contract.emergency_withdraw(caller_address, deposit_id)
+        })
+    }
+    
+    /// Set how long idempotency keys are remembered (owner only)
+    pub fn set_idempotency_retention(&mut self, caller_address: String, retention: std::time::Duration, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.idempotency_retention = retention;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Run an operation once per idempotency key
+    fn run_idempotent<F>(&mut self, idempotency_key: Option<String>, fingerprint: String, operation: F) -> Result<Event, ContractError>
+    where
+        F: FnOnce(&mut Self) -> Result<Event, ContractError>,
+    {
+        let key = match idempotency_key {
+            Some(key) => key,
+            None => return operation(self),
+        };
+        
+        // Forget expired keys
+        let now = self.clock.now();
+        let retention = Duration::from_std(self.idempotency_retention).unwrap_or(Duration::MAX);
+        self.idempotency_records.retain(|_, record| now - record.recorded_at < retention);
+        
+        // Replay the recorded result
+        if let Some(record) = self.idempotency_records.get(&key) {
+            if record.fingerprint != fingerprint {
+                return Err(ContractError::IdempotencyConflict);
+            }
+            
+            return Ok(record.event.clone());
+        }
+        
+        let event = operation(self)?;
+        
+        // Make room by dropping the oldest key
+        if self.idempotency_records.len() >= MAX_IDEMPOTENCY_KEYS {
+            let oldest = self.idempotency_records.iter()
+                .min_by_key(|(_, record)| record.recorded_at)
+                .map(|(key, _)| key.clone());
+            
+            if let Some(oldest) = oldest {
+                self.idempotency_records.remove(&oldest);
+            }
+        }
+        
+        self.idempotency_records.insert(key, IdempotencyRecord {
+            fingerprint,
+            event: event.clone(),
+            recorded_at: now,
+        });
+        
+        Ok(event)
+    }
+}
@@ -0,0 +1,307 @@
+//! Import of deposits from an older deployment
+//!
+//! The older deployment exports its deposits as a batch of `ImportedDeposit`
+//! entries with an `ImportManifest`: the SHA-256 of the batch's canonical
+//! encoding and a signature of that hash by the migration key. Manifests
+//! hashing the batch's JSON, as earlier versions did, are still accepted. The owner configures the
+//! migration key's public key here, then imports the batch as it was signed.
+//!
+//! Imported deposits keep their original ID offset by
+//! `IMPORTED_DEPOSIT_ID_OFFSET`, a range new deposits never reach, so the
+//! two deployments' IDs cannot collide. Deposits that were already withdrawn
+//! are imported as history only: they are listed but hold no funds. The
+//! hash of every applied manifest is kept, so the same batch cannot be
+//! imported twice and let depositors withdraw twice.
+
+use std::collections::HashSet;
+use chrono::{DateTime, Utc};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::secp256k1::PublicKey;
+use bitcoincore_rpc::bitcoin::Network;
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::signature::{SignatureVerifier, Signer};
+use crate::canonical::CanonicalEncoder;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{Deposit, DepositStatus, TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// First deposit ID of the range reserved for imported deposits
+pub const IMPORTED_DEPOSIT_ID_OFFSET: u64 = 1 << 48;
+
+/// Version of the import batch encoding
+pub const IMPORT_BATCH_ENCODING_VERSION: u8 = 1;
+
+/// Domain of the import batch encoding
+const IMPORT_BATCH_DOMAIN: &str = "time-locked-vault/import-batch";
+
+/// Deposit exported by an older deployment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportedDeposit {
+    /// Deposit ID in the older deployment
+    pub original_id: u64,
+    /// Address of the depositor
+    pub depositor_address: String,
+    /// Token type
+    pub token_type: TokenType,
+    /// Locked amount
+    pub amount: u64,
+    /// When the deposit was made
+    pub deposit_timestamp: DateTime<Utc>,
+    /// When the deposit unlocks
+    pub unlock_timestamp: DateTime<Utc>,
+    /// Whether the deposit was already withdrawn
+    pub withdrawn: bool,
+}
+
+impl ImportedDeposit {
+    /// Get the ID the deposit is imported under
+    pub fn imported_id(&self) -> Option<u64> {
+        if self.original_id >= IMPORTED_DEPOSIT_ID_OFFSET {
+            return None;
+        }
+        
+        Some(IMPORTED_DEPOSIT_ID_OFFSET + self.original_id)
+    }
+}
+
+/// Proof that a batch of imported deposits comes from the older deployment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportManifest {
+    /// Hex-encoded SHA-256 of the batch's canonical encoding
+    pub content_hash: String,
+    /// Hex-encoded compact ECDSA signature of the content hash by the migration key
+    pub signature: String,
+}
+
+impl ImportManifest {
+    /// Get the SHA-256 of a batch's canonical encoding, entries in the given order
+    pub fn content_hash_of(deposits: &[ImportedDeposit]) -> [u8; 32] {
+        let mut encoder = CanonicalEncoder::new(IMPORT_BATCH_DOMAIN, IMPORT_BATCH_ENCODING_VERSION);
+        encoder.sequence(deposits.iter(), |encoder, deposit| {
+            encoder.u64(deposit.original_id);
+            encoder.string(&deposit.depositor_address);
+            encoder.token_type(&deposit.token_type);
+            encoder.u64(deposit.amount);
+            encoder.timestamp(&deposit.deposit_timestamp);
+            encoder.timestamp(&deposit.unlock_timestamp);
+            encoder.bool(deposit.withdrawn);
+        });
+        sha256::Hash::hash(&encoder.finish()).to_byte_array()
+    }
+    
+    /// Get the SHA-256 of a batch's JSON, as manifests were hashed before the canonical encoding
+    fn legacy_content_hash_of(deposits: &[ImportedDeposit]) -> [u8; 32] {
+        let bytes = serde_json::to_vec(deposits).unwrap_or_default();
+        sha256::Hash::hash(&bytes).to_byte_array()
+    }
+    
+    /// Create the manifest of a batch, signed by the migration key
+    pub fn sign(deposits: &[ImportedDeposit], signer: &dyn Signer) -> Result<Self, ContractError> {
+        let content_hash = Self::content_hash_of(deposits);
+        
+        Ok(Self {
+            content_hash: hex::encode(content_hash),
+            signature: hex::encode(signer.sign(&content_hash)?),
+        })
+    }
+    
+    /// Check the manifest against a batch and the migration public key
+    fn verify(&self, deposits: &[ImportedDeposit], migration_public_key: &str) -> Result<(), ContractError> {
+        let content_hash = [Self::content_hash_of(deposits), Self::legacy_content_hash_of(deposits)]
+            .into_iter()
+            .find(|content_hash| self.content_hash == hex::encode(content_hash))
+            .ok_or_else(|| ContractError::InvalidImport("Content hash does not match the deposits".to_string()))?;
+        
+        let signature = hex::decode(&self.signature).map_err(|_| ContractError::InvalidImportSignature)?;
+        let public_key = hex::decode(migration_public_key).map_err(|_| ContractError::InvalidImportSignature)?;
+        
+        let valid = SignatureVerifier::new(Network::Testnet)
+            .verify(&content_hash, &signature, &public_key)
+            .unwrap_or(false);
+        if !valid {
+            return Err(ContractError::InvalidImportSignature);
+        }
+        
+        Ok(())
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the public key (hex) import manifests are checked against
+    pub fn get_migration_public_key(&self) -> Option<&str> {
+        self.migration_public_key.as_deref()
+    }
+    
+    /// Set the public key (hex) import manifests are checked against (owner only)
+    ///
+    /// `None` stops imports.
+    pub fn set_migration_public_key(&mut self, caller_address: String, public_key: Option<String>, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Store the key in its canonical compressed form
+        self.migration_public_key = match public_key {
+            Some(key) => {
+                let parsed = hex::decode(&key).ok()
+                    .and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+                    .ok_or_else(|| ContractError::InvalidImport(format!("Invalid migration public key {}", key)))?;
+                Some(hex::encode(parsed.serialize()))
+            },
+            None => None,
+        };
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Check whether a manifest, by its hex-encoded content hash, was already imported
+    pub fn is_manifest_imported(&self, content_hash: &str) -> bool {
+        self.imported_manifests.contains(content_hash)
+    }
+    
+    /// Import a signed batch of deposits from an older deployment (owner only)
+    ///
+    /// The batch is checked as a whole before anything is recorded. Deposit
+    /// limits do not apply, since the deposits were accepted by the older
+    /// deployment. Returns one `DepositImported` event per deposit.
+    pub fn import_deposits(
+        &mut self,
+        caller_address: String,
+        deposits: Vec<ImportedDeposit>,
+        manifest: ImportManifest,
+        expected_nonce: Option<u64>,
+    ) -> Result<Vec<Event>, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Check the batch is the one the migration key signed, and new
+        let migration_public_key = self.migration_public_key.as_deref().ok_or(ContractError::MigrationKeyNotConfigured)?;
+        manifest.verify(&deposits, migration_public_key)?;
+        if self.imported_manifests.contains(&manifest.content_hash) {
+            return Err(ContractError::ManifestAlreadyImported);
+        }
+        
+        if deposits.is_empty() {
+            return Err(ContractError::InvalidImport("No deposits to import".to_string()));
+        }
+        
+        // Validate every entry and the totals they add up to
+        let mut deposit_ids = HashSet::new();
+        let mut new_totals = self.total_deposits.clone();
+        for imported in &deposits {
+            let deposit_id = imported.imported_id().ok_or_else(|| ContractError::InvalidImport(format!(
+                "Deposit ID {} is outside the importable range", imported.original_id
+            )))?;
+            
+            if !deposit_ids.insert(deposit_id) || self.deposit_registry.contains_key(&deposit_id) {
+                return Err(ContractError::InvalidImport(format!("Deposit {} is already imported", imported.original_id)));
+            }
+            
+            if imported.amount == 0 {
+                return Err(ContractError::InvalidImport(format!("Deposit {} has no amount", imported.original_id)));
+            }
+            
+            if imported.unlock_timestamp < imported.deposit_timestamp {
+                return Err(ContractError::InvalidImport(format!("Deposit {} unlocks before it was made", imported.original_id)));
+            }
+            
+            if !self.supported_tokens.contains(&imported.token_type) {
+                return Err(ContractError::InvalidImport(format!(
+                    "Deposit {} is in unsupported token {}", imported.original_id, imported.token_type.name()
+                )));
+            }
+            
+            if let Err(e) = self.check_address(&imported.depositor_address) {
+                return Err(ContractError::InvalidImport(format!(
+                    "Deposit {} has invalid depositor address {}: {}", imported.original_id, imported.depositor_address, e
+                )));
+            }
+            
+            // Withdrawn deposits are history only and hold nothing
+            if !imported.withdrawn {
+                let total = new_totals.entry(imported.token_type.clone()).or_insert(0);
+                *total = total.checked_add(imported.amount).ok_or(ContractError::ArithmeticError)?;
+            }
+        }
+        
+        // Record the deposits and rebuild the indexes over them
+        let now = self.clock.now();
+        let mut events = Vec::with_capacity(deposits.len());
+        for imported in deposits {
+            let deposit_id = IMPORTED_DEPOSIT_ID_OFFSET + imported.original_id;
+            
+            let deposit = Deposit {
+                deposit_id,
+                depositor_address: imported.depositor_address.clone(),
+                deposited_token_type: imported.token_type.clone(),
+                deposited_amount: imported.amount,
+                deposit_timestamp: imported.deposit_timestamp,
+                unlock_timestamp: imported.unlock_timestamp,
+                status: if imported.withdrawn { DepositStatus::Withdrawn } else { DepositStatus::Active },
+                withdrawal_tx_hash: None,
+                last_modified: now,
+                utxo_reference: None,
+                lightning_payment_hash: None,
+                multisig_wallet: None,
+                lightning_invoice_id: None,
+                multisig_address: None,
+                claim_hash: None,
+                claimed_by: None,
+                inheritance: None,
+                reminder: None,
+                pending: None,
+                escrow: None,
+                referrer_address: None,
+            };
+            
+            // Same bookkeeping as restoring a snapshot
+            self.user_index.record_deposit(&imported.depositor_address, &deposit);
+            if imported.withdrawn {
+                self.user_index.record_withdrawal(&imported.depositor_address, &deposit);
+            }
+            self.user_deposit_ids.entry(imported.depositor_address.clone()).or_default().push(deposit_id);
+            self.deposit_registry.insert(deposit_id, deposit);
+            
+            events.push(Event::DepositImported {
+                deposit_id,
+                original_deposit_id: imported.original_id,
+                depositor_address: imported.depositor_address,
+                token_type: imported.token_type,
+                amount: imported.amount,
+                deposit_timestamp: imported.deposit_timestamp,
+                unlock_timestamp: imported.unlock_timestamp,
+                withdrawn: imported.withdrawn,
+                manifest_hash: manifest.content_hash.clone(),
+                timestamp: now,
+            });
+        }
+        
+        self.total_deposits = new_totals;
+        self.imported_manifests.insert(manifest.content_hash.clone());
+        
+        info!(target: CONTRACT, "Imported {} deposits from manifest {}", events.len(), manifest.content_hash);
+        
+        for event in &events {
+            self.emit(event);
+        }
+        
+        self.advance_owner_nonce();
+        
+        Ok(events)
+    }
+}
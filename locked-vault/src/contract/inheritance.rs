@@ -0,0 +1,227 @@
+//! Inheritance of deposits through a dead-man's switch
+//!
+//! A depositor can name a beneficiary and an inactivity period for a deposit.
+//! Once the deposit has matured and the depositor has made no authenticated
+//! call for that many days, the beneficiary may claim it. Any deposit,
+//! withdrawal, assignment or explicit heartbeat by the depositor restarts the
+//! inactivity period, and the depositor can still withdraw normally.
+
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositStatus, Inheritance, TokenTransfer, TransferPriority};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::outbox::{OutboxOperation, TransferDirection, TransferRequest};
+use crate::logging::CONTRACT;
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Record an authenticated interaction by a user
+    pub(crate) fn record_activity(&mut self, user_address: &str) {
+        self.last_activity.insert(user_address.to_string(), self.clock.now());
+    }
+    
+    /// Get the last authenticated interaction by a user, if any was recorded
+    pub fn get_last_activity(&self, user_address: &str) -> Option<DateTime<Utc>> {
+        self.last_activity.get(user_address).copied()
+    }
+    
+    /// Prove the caller is still active, restarting their inheritance periods
+    pub fn heartbeat(&mut self, caller_address: String) -> Result<(), ContractError> {
+        self.ensure_writable()?;
+        
+        // Validate address
+        self.check_address(&caller_address)?;
+        
+        self.record_activity(&caller_address);
+        
+        Ok(())
+    }
+    
+    /// Name a beneficiary who may claim a deposit after the depositor goes inactive (depositor only)
+    ///
+    /// Replaces any previous beneficiary of the deposit.
+    pub fn set_inheritance(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        beneficiary_address: String,
+        inactivity_days: u32,
+    ) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        // Validate addresses
+        self.check_address(&caller_address)?;
+        
+        self.check_address(&beneficiary_address)?;
+        if beneficiary_address == caller_address {
+            return Err(ContractError::InvalidAddress);
+        }
+        
+        if inactivity_days == 0 {
+            return Err(ContractError::InvalidInactivityPeriod);
+        }
+        
+        // Get deposit
+        let current_timestamp = self.clock.now();
+        let deposit = self.deposit_registry.get_mut(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Check ownership
+        if deposit.depositor_address != caller_address {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Check if already withdrawn
+        if deposit.is_withdrawn() {
+            return Err(ContractError::DepositAlreadyWithdrawn);
+        }
+        
+        deposit.inheritance = Some(Inheritance {
+            beneficiary_address: beneficiary_address.clone(),
+            inactivity_days,
+        });
+        deposit.last_modified = current_timestamp;
+        
+        self.record_activity(&caller_address);
+        
+        info!(target: CONTRACT, "Deposit {} inheritable by {} after {} inactive days", deposit_id, beneficiary_address, inactivity_days);
+        
+        let event = Event::InheritanceSet {
+            deposit_id,
+            depositor_address: caller_address,
+            beneficiary_address,
+            inactivity_days,
+            timestamp: current_timestamp,
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+    
+    /// Claim a matured deposit whose depositor has been inactive for its inheritance period
+    pub fn claim_inherited(&mut self, beneficiary_address: String, deposit_id: u64) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Reentrancy protection
+        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        // Validate address
+        self.check_address(&beneficiary_address)?;
+        
+        // Get deposit
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Only the named beneficiary can claim
+        let inactivity_days = match &deposit.inheritance {
+            Some(inheritance) if inheritance.beneficiary_address == beneficiary_address => inheritance.inactivity_days,
+            _ => return Err(ContractError::Unauthorized),
+        };
+        
+        // Check the deposit can be paid out now
+        self.check_payable(deposit)?;
+        
+        // The inactivity period runs from maturity or the depositor's last activity, whichever is later
+        let current_timestamp = self.clock.now();
+        let last_activity = self.last_activity.get(&deposit.depositor_address)
+            .copied()
+            .unwrap_or(deposit.deposit_timestamp);
+        let inactive_since = last_activity.max(deposit.unlock_timestamp);
+        if current_timestamp < inactive_since + Duration::days(inactivity_days as i64) {
+            return Err(ContractError::InheritanceNotClaimable);
+        }
+        
+        let deposit = self.deposit_registry.get_mut(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Mark the payout as in flight, so a state saved during the handover is recovered on restart
+        deposit.status = DepositStatus::WithdrawalPending;
+        deposit.last_modified = current_timestamp;
+        
+        let token_type = deposit.deposited_token_type.clone();
+        let amount = deposit.deposited_amount;
+        drop(_guard);
+        
+        // Commit the payout with the state change
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::FromContract,
+            address: beneficiary_address.clone(),
+            token_type,
+            amount,
+            priority: TransferPriority::Normal,
+            deposit_id: Some(deposit_id),
+            batched: false,
+            options: None,
+        };
+        let operation = OutboxOperation::InheritanceClaim { last_activity };
+        let entry_id = match self.commit_to_outbox(operation, transfer_request, &beneficiary_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.reactivate_deposit(deposit_id);
+                return Err(e);
+            },
+        };
+        
+        // Transfer tokens from contract to the beneficiary, leaving the deposit inheritable on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.reactivate_deposit(deposit_id);
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
+        }
+        
+        self.complete_outbox_entry(entry_id, None)
+    }
+    
+    /// Complete an inheritance claim once the backend accepted its payout
+    pub(crate) fn finish_inheritance_claim(
+        &mut self,
+        beneficiary_address: &str,
+        deposit_id: u64,
+        last_activity: DateTime<Utc>,
+    ) -> Result<Event, ContractError> {
+        let current_timestamp = self.clock.now();
+        let deposit = self.deposit_registry.get_mut(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Mark as withdrawn by the beneficiary
+        deposit.status = DepositStatus::Withdrawn;
+        deposit.claimed_by = Some(beneficiary_address.to_string());
+        deposit.last_modified = current_timestamp;
+        
+        // Update totals with checked arithmetic
+        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
+            *total = total.checked_sub(deposit.deposited_amount).unwrap_or(0);
+        }
+        self.user_index.record_withdrawal(&deposit.depositor_address, deposit);
+        
+        info!(target: CONTRACT, "Deposit {} inherited by {}", deposit_id, beneficiary_address);
+        
+        let event = Event::InheritanceClaimed {
+            deposit_id,
+            depositor_address: deposit.depositor_address.clone(),
+            beneficiary_address: beneficiary_address.to_string(),
+            token_type: deposit.deposited_token_type.clone(),
+            claimed_amount: deposit.deposited_amount,
+            last_activity,
+            asset_detail: deposit.asset_detail(),
+            timestamp: current_timestamp,
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+}
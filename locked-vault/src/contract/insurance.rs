@@ -0,0 +1,164 @@
+//! Insurance pool funded by emergency withdrawal fees
+//!
+//! A configurable share of every emergency withdrawal fee is paid into a
+//! per-token insurance pool instead of the collected fees. When a backend
+//! failure loses user funds, the owner compensates the user from the pool of
+//! the lost token. A payment can never exceed the pool balance, and the pool
+//! is only ever reduced by such payments.
+
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer, TransferPriority};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Who an insurance payment compensates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompensationTarget {
+    /// The owner of a deposit, paid in the deposit's token
+    Deposit(u64),
+    /// An address, paid in the given token
+    Address {
+        /// Recipient address
+        address: String,
+        /// Token type
+        token_type: TokenType,
+    },
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Split an emergency fee into the collector's part and the insurance pool's part
+    ///
+    /// The pool's part is rounded up, so the split never loses a unit.
+    pub(crate) fn split_emergency_fee(fee_amount: u64, insurance_share_percentage: u8) -> Result<(u64, u64), ContractError> {
+        let insurance_amount = match (fee_amount as u128)
+            .checked_mul(insurance_share_percentage as u128)
+            .and_then(|product| product.checked_add(99))
+            .and_then(|product| product.checked_div(100)) {
+            Some(share) if share <= fee_amount as u128 => share as u64,
+            _ => return Err(ContractError::ArithmeticError),
+        };
+        
+        Ok((fee_amount - insurance_amount, insurance_amount))
+    }
+    
+    /// Set the percentage of every emergency fee paid into the insurance pool (owner only)
+    ///
+    /// Fees already collected are not moved.
+    pub fn set_insurance_share(&mut self, caller_address: String, insurance_share_percentage: u8, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if insurance_share_percentage > 100 {
+            return Err(ContractError::InvalidFeePercentage);
+        }
+        
+        self.fee_config.insurance_share_percentage = insurance_share_percentage;
+        
+        self.emit(&Event::InsuranceShareUpdated {
+            insurance_share_percentage,
+            timestamp: self.clock.now(),
+        });
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Get the insurance pool balance of a token type
+    pub fn insurance_pool_balance(&self, token_type: &TokenType) -> u64 {
+        self.fee_config.insurance_pool.get(token_type).copied().unwrap_or(0)
+    }
+    
+    /// Pay compensation for lost funds from the insurance pool (owner only)
+    ///
+    /// Fails with `InsufficientBalance` if the pool of the token holds less
+    /// than `amount`. Under governance this has to go through `propose_action`.
+    pub fn compensate_from_insurance(
+        &mut self,
+        caller_address: String,
+        target: CompensationTarget,
+        amount: u64,
+        reason: String,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        self.ensure_sole_owner(&caller_address)?;
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let event = self.execute_compensation(target, amount, reason)?;
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Pay compensation from the insurance pool once authorization has been checked
+    pub(crate) fn execute_compensation(
+        &mut self,
+        target: CompensationTarget,
+        amount: u64,
+        reason: String,
+    ) -> Result<Event, ContractError> {
+        // Reentrancy protection
+        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        
+        if amount == 0 {
+            return Err(ContractError::InvalidAmount { reason: "compensation cannot be zero".to_string() });
+        }
+        
+        // Resolve the recipient and token
+        let (deposit_id, recipient_address, token_type) = match target {
+            CompensationTarget::Deposit(deposit_id) => {
+                let deposit = self.deposit_registry.get(&deposit_id)
+                    .ok_or(ContractError::DepositNotFound)?;
+                (Some(deposit_id), deposit.depositor_address.clone(), deposit.deposited_token_type.clone())
+            },
+            CompensationTarget::Address { address, token_type } => (None, address, token_type),
+        };
+        
+        self.check_address(&recipient_address)?;
+        
+        // Never pay out more than the pool holds
+        let available = self.insurance_pool_balance(&token_type);
+        let remaining_pool = available.checked_sub(amount)
+            .ok_or(ContractError::InsufficientBalance { available, required: amount })?;
+        
+        // Pay ahead of routine transfers, the user has already waited
+        if let Err(e) = self.token_transfer.transfer_from_contract_with_priority(
+            &recipient_address,
+            &token_type,
+            amount,
+            TransferPriority::High,
+        ) {
+            return Err(ContractError::transfer_failed(TransferStage::Withdrawal, e));
+        }
+        
+        self.fee_config.insurance_pool.insert(token_type.clone(), remaining_pool);
+        
+        info!(target: CONTRACT, "Insurance compensation of {} {:?} paid to {}: {}", amount, token_type, recipient_address, reason);
+        
+        let event = Event::InsuranceCompensationPaid {
+            deposit_id,
+            recipient_address,
+            token_type,
+            amount,
+            reason,
+            remaining_pool,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+}
@@ -0,0 +1,210 @@
+//! Views of the contract's accounting for invariant checks
+//!
+//! The property tests and the soak runner check these invariants after every
+//! operation, so they recompute everything from the deposit registry rather
+//! than trusting the incrementally maintained counters.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::refund::RefundReason;
+
+/// Accounting observed before an operation, to check what the operation changed
+#[derive(Debug, Clone)]
+pub(crate) struct AccountingSnapshot {
+    /// Collected fees per token type
+    collected_fees: HashMap<TokenType, u64>,
+    /// Insurance pool balance per token type
+    insurance_pool: HashMap<TokenType, u64>,
+    /// IDs of withdrawn deposits
+    withdrawn: HashSet<u64>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Sum the amounts of active deposits per token type
+    pub(crate) fn active_amounts_by_token(&self) -> HashMap<TokenType, u128> {
+        let mut amounts: HashMap<TokenType, u128> = HashMap::new();
+        
+        for deposit in self.deposits_iter().filter(|deposit| !deposit.is_closed()) {
+            *amounts.entry(deposit.deposited_token_type.clone()).or_insert(0) += deposit.deposited_amount as u128;
+        }
+        
+        amounts
+    }
+    
+    /// Get the collected fees per token type
+    pub(crate) fn collected_fees_by_token(&self) -> HashMap<TokenType, u64> {
+        self.fee_config.collected_fees.clone()
+    }
+    
+    /// Get the IDs of withdrawn deposits
+    pub(crate) fn withdrawn_deposit_ids(&self) -> HashSet<u64> {
+        self.deposits_iter()
+            .filter(|deposit| deposit.is_withdrawn())
+            .map(|deposit| deposit.deposit_id)
+            .collect()
+    }
+    
+    /// Capture the accounting an operation must not roll back
+    pub(crate) fn accounting_snapshot(&self) -> AccountingSnapshot {
+        AccountingSnapshot {
+            collected_fees: self.collected_fees_by_token(),
+            insurance_pool: self.fee_config.insurance_pool.clone(),
+            withdrawn: self.withdrawn_deposit_ids(),
+        }
+    }
+    
+    /// Check how an operation changed the accounting since `before`
+    ///
+    /// Collected fees only decrease through fee withdrawals and withdrawn
+    /// deposits stay withdrawn.
+    pub(crate) fn check_transition_invariants(&self, before: &AccountingSnapshot, withdrew_fees: bool) -> Result<(), String> {
+        if !withdrew_fees {
+            let fees_after = self.collected_fees_by_token();
+            for (token_type, fees_before) in &before.collected_fees {
+                let after = fees_after.get(token_type).copied().unwrap_or(0);
+                if after < *fees_before {
+                    return Err(format!("fees for {:?} dropped from {} to {}", token_type, fees_before, after));
+                }
+            }
+        }
+        
+        let withdrawn_after = self.withdrawn_deposit_ids();
+        if let Some(id) = before.withdrawn.difference(&withdrawn_after).next() {
+            return Err(format!("withdrawn deposit {} became active", id));
+        }
+        
+        Ok(())
+    }
+    
+    /// Check that the insurance pool changed by no more than `paid_out` since `before`
+    ///
+    /// The pool only grows with emergency fees and only shrinks by exactly
+    /// the compensation paid, which can never exceed it.
+    pub(crate) fn check_insurance_transition(&self, before: &AccountingSnapshot, paid_out: Option<(&TokenType, u64)>) -> Result<(), String> {
+        let tokens: HashSet<&TokenType> = before.insurance_pool.keys().chain(self.fee_config.insurance_pool.keys()).collect();
+        
+        for token_type in tokens {
+            let pool_before = before.insurance_pool.get(token_type).copied().unwrap_or(0);
+            let pool_after = self.fee_config.insurance_pool.get(token_type).copied().unwrap_or(0);
+            
+            match paid_out {
+                Some((paid_token, amount)) if paid_token == token_type => {
+                    if amount > pool_before {
+                        return Err(format!("compensation of {} exceeded the {:?} pool of {}", amount, token_type, pool_before));
+                    }
+                    if pool_after != pool_before - amount {
+                        return Err(format!("{:?} pool went from {} to {} paying {}", token_type, pool_before, pool_after, amount));
+                    }
+                },
+                _ if pool_after < pool_before => {
+                    return Err(format!("{:?} pool dropped from {} to {} without compensation", token_type, pool_before, pool_after));
+                },
+                _ => {},
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Check the accounting invariants that must hold between operations
+    pub(crate) fn check_accounting_invariants(&self) -> Result<(), String> {
+        // Active deposit amounts match the running totals
+        let active_amounts = self.active_amounts_by_token();
+        let tokens: HashSet<&TokenType> = active_amounts.keys().chain(self.total_deposits.keys()).collect();
+        
+        for token_type in tokens {
+            let active = active_amounts.get(token_type).copied().unwrap_or(0);
+            let total = self.total_deposits.get(token_type).copied().unwrap_or(0) as u128;
+            
+            if active != total {
+                return Err(format!("{:?}: active deposits sum to {} but total_deposits is {}", token_type, active, total));
+            }
+        }
+        
+        // Every deposit is listed exactly once, under its current owner
+        let mut listed: HashMap<u64, &str> = HashMap::new();
+        let lists = self.user_deposit_ids.iter()
+            .chain(self.user_index.archived_deposit_ids.iter());
+        
+        for (user_address, ids) in lists {
+            for id in ids {
+                if listed.insert(*id, user_address).is_some() {
+                    return Err(format!("deposit {} is listed more than once", id));
+                }
+            }
+        }
+        
+        for deposit in self.deposits_iter() {
+            match listed.remove(&deposit.deposit_id) {
+                Some(user_address) if user_address == deposit.depositor_address => {},
+                Some(user_address) => return Err(format!(
+                    "deposit {} is listed under {} but owned by {}", deposit.deposit_id, user_address, deposit.depositor_address
+                )),
+                None => return Err(format!("deposit {} is not listed under any user", deposit.deposit_id)),
+            }
+        }
+        
+        if let Some(id) = listed.keys().next() {
+            return Err(format!("deposit {} is listed but not in the registry", id));
+        }
+        
+        // Per-user aggregates agree with the registry
+        for (user_address, aggregates) in &self.user_index.aggregates {
+            let active_count = self.deposits_iter()
+                .filter(|deposit| !deposit.is_closed() && &deposit.depositor_address == user_address)
+                .count();
+            
+            if aggregates.active_count != active_count {
+                return Err(format!(
+                    "{} has {} active deposits but the index counts {}", user_address, active_count, aggregates.active_count
+                ));
+            }
+        }
+        
+        // The unlock index holds exactly the active deposits, under their owners
+        let indexed: usize = self.user_index.unlocks.values().map(|unlocks| unlocks.len()).sum();
+        let active_count = self.deposits_iter().filter(|deposit| !deposit.is_closed()).count();
+        if indexed != active_count {
+            return Err(format!("unlock index holds {} deposits but {} are active", indexed, active_count));
+        }
+        
+        for deposit in self.deposits_iter().filter(|deposit| !deposit.is_closed()) {
+            let indexed = self.user_index.unlocks.get(&deposit.depositor_address)
+                .is_some_and(|unlocks| unlocks.contains(&(deposit.unlock_timestamp, deposit.deposit_id)));
+            if !indexed {
+                return Err(format!("deposit {} is missing from the unlock index", deposit.deposit_id));
+            }
+        }
+        
+        // Unattributed funds are settled at most once
+        for outpoint in self.unattributed_funds.keys() {
+            if self.utxo_registry.owner_of(outpoint).is_some() || self.refunded_outpoints.contains(outpoint) {
+                return Err(format!("unattributed funds at {} are listed but already settled", outpoint));
+            }
+        }
+        
+        if let Some(outpoint) = self.refunded_outpoints.iter().find(|outpoint| self.utxo_registry.owner_of(outpoint).is_some()) {
+            return Err(format!("funds at {} were both refunded and deposited", outpoint));
+        }
+        
+        // Queued refunds are recorded as refunded, so their funds are not refunded twice
+        for refund in self.pending_refunds.values() {
+            let recorded = match &refund.reason {
+                RefundReason::DepositRejected { deposit_id } | RefundReason::LateFunding { deposit_id } => {
+                    self.refunded_deposits.contains(deposit_id)
+                },
+                RefundReason::UnattributedFunds { outpoint } => self.refunded_outpoints.contains(outpoint),
+            };
+            if !recorded || refund.fee_deduction > refund.amount {
+                return Err(format!("refund {} is queued inconsistently", refund.refund_id));
+            }
+        }
+        
+        // The fee ledger explains the collected fees
+        self.check_fee_ledger()?;
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+//! Versioned state snapshots and schema migrations
+//!
+//! Snapshots carry the schema version they were written with. Loading an
+//! older snapshot runs the registered migrations in sequence on the raw JSON
+//! before it is deserialized; snapshots from newer code are refused.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use log::warn;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::MigrationError;
+use crate::models::{TokenTransfer, DEFAULT_MAX_LOCK_DAYS};
+use crate::contract::contract_core::{TimeLockedDeposit, DEFAULT_IDEMPOTENCY_RETENTION_SECS, DEFAULT_RESERVATION_TTL_SECS};
+use crate::contract::state::ContractState;
+use crate::logging::CONTRACT;
+
+/// Schema version written by this code
+pub const CURRENT_STATE_VERSION: StateVersion = StateVersion { major: 1, minor: 2, patch: 0 };
+
+/// Schema version of a state snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct StateVersion {
+    /// Major version
+    pub major: u32,
+    /// Minor version
+    pub minor: u32,
+    /// Patch version
+    pub patch: u32,
+}
+
+impl fmt::Display for StateVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for StateVersion {
+    type Err = MigrationError;
+    
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = version.split('.').collect();
+        if parts.len() != 3 {
+            return Err(MigrationError::InvalidVersion(version.to_string()));
+        }
+        
+        let parse = |part: &str| part.parse::<u32>().map_err(|_| MigrationError::InvalidVersion(version.to_string()));
+        
+        Ok(StateVersion {
+            major: parse(parts[0])?,
+            minor: parse(parts[1])?,
+            patch: parse(parts[2])?,
+        })
+    }
+}
+
+impl TryFrom<String> for StateVersion {
+    type Error = MigrationError;
+    
+    fn try_from(version: String) -> Result<Self, Self::Error> {
+        version.parse()
+    }
+}
+
+impl From<StateVersion> for String {
+    fn from(version: StateVersion) -> Self {
+        version.to_string()
+    }
+}
+
+impl Default for StateVersion {
+    fn default() -> Self {
+        CURRENT_STATE_VERSION
+    }
+}
+
+/// Transformation of a raw snapshot from one schema version to the next
+pub trait Migration {
+    /// Version of the snapshots this migration reads
+    #[allow(clippy::wrong_self_convention)]
+    fn from_version(&self) -> &str;
+    
+    /// Rewrite a snapshot into the next version's schema
+    fn migrate(&self, value: Value) -> Result<Value, MigrationError>;
+}
+
+/// 1.0.0 to 1.1.0: deposits replace `is_withdrawn` with a `status`
+/// 
+/// Old snapshots cannot tell emergency withdrawals apart, so every withdrawn
+/// deposit becomes `Withdrawn`. Fields that were added to the export while it
+/// was still unversioned are filled in with their defaults when missing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DepositStatusMigration;
+
+impl Migration for DepositStatusMigration {
+    fn from_version(&self) -> &str {
+        "1.0.0"
+    }
+    
+    fn migrate(&self, mut value: Value) -> Result<Value, MigrationError> {
+        let failed = |reason: &str| MigrationError::MigrationFailed {
+            from: self.from_version().to_string(),
+            reason: reason.to_string(),
+        };
+        
+        let deposits = value.get_mut("deposits")
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| failed("missing deposits list"))?;
+        
+        for deposit in deposits {
+            let deposit = deposit.as_object_mut().ok_or_else(|| failed("deposit is not an object"))?;
+            
+            let is_withdrawn = deposit.remove("is_withdrawn")
+                .and_then(|value| value.as_bool())
+                .ok_or_else(|| failed("deposit without is_withdrawn flag"))?;
+            
+            let status = if is_withdrawn { "Withdrawn" } else { "Active" };
+            deposit.insert("status".to_string(), Value::String(status.to_string()));
+        }
+        
+        // Fields added to 1.0.0 exports over time
+        let object = value.as_object_mut().ok_or_else(|| failed("snapshot is not an object"))?;
+        let defaults = [
+            ("max_lock_days", json!(DEFAULT_MAX_LOCK_DAYS)),
+            ("per_token_max_lock_days", json!([])),
+            ("idempotency_retention", json!(Duration::from_secs(DEFAULT_IDEMPOTENCY_RETENTION_SECS))),
+            ("idempotency_records", json!([])),
+            ("governance", Value::Null),
+            ("reservation_ttl", json!(Duration::from_secs(DEFAULT_RESERVATION_TTL_SECS))),
+            ("deposit_reservations", json!([])),
+        ];
+        
+        for (field, default) in defaults {
+            object.entry(field).or_insert(default);
+        }
+        
+        Ok(value)
+    }
+}
+
+/// 1.1.0 to 1.2.0: UTXO references become canonical `txid:vout` outpoints
+/// 
+/// References were stored as given, so the same output could be recorded
+/// with an uppercase txid or stray whitespace. Each reference on a deposit
+/// and in the consumed list is rewritten in its canonical form. References
+/// that are not outpoints at all cannot back a new deposit either, so they
+/// are dropped with a warning instead of failing the load.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UtxoReferenceMigration;
+
+impl UtxoReferenceMigration {
+    /// Canonical form of a stored reference, if it is an outpoint
+    fn normalize(reference: &Value) -> Option<Value> {
+        let reference = match reference {
+            Value::String(text) => text.chars().filter(|c| !c.is_whitespace()).collect::<String>().parse::<UtxoRef>().ok()?,
+            other => serde_json::from_value::<UtxoRef>(other.clone()).ok()?,
+        };
+        
+        Some(Value::String(reference.to_string()))
+    }
+}
+
+impl Migration for UtxoReferenceMigration {
+    fn from_version(&self) -> &str {
+        "1.1.0"
+    }
+    
+    fn migrate(&self, mut value: Value) -> Result<Value, MigrationError> {
+        let failed = |reason: &str| MigrationError::MigrationFailed {
+            from: self.from_version().to_string(),
+            reason: reason.to_string(),
+        };
+        
+        let deposits = value.get_mut("deposits")
+            .and_then(Value::as_array_mut)
+            .ok_or_else(|| failed("missing deposits list"))?;
+        
+        for deposit in deposits {
+            let deposit = deposit.as_object_mut().ok_or_else(|| failed("deposit is not an object"))?;
+            let Some(reference) = deposit.get("utxo_reference").filter(|reference| !reference.is_null()) else {
+                continue;
+            };
+            
+            let normalized = Self::normalize(reference);
+            if normalized.is_none() {
+                warn!(target: CONTRACT, "Dropping UTXO reference {} of deposit {}: not an outpoint", reference, deposit.get("deposit_id").unwrap_or(&Value::Null));
+            }
+            deposit.insert("utxo_reference".to_string(), normalized.unwrap_or(Value::Null));
+        }
+        
+        // The consumed list is keyed by reference, so the first deposit to claim an output keeps it
+        if let Some(consumed) = value.get_mut("consumed_utxo_references").and_then(Value::as_array_mut) {
+            let mut normalized: Vec<Value> = Vec::with_capacity(consumed.len());
+            for entry in consumed.drain(..) {
+                let pair = entry.as_array().filter(|pair| pair.len() == 2).ok_or_else(|| failed("consumed UTXO reference is not a pair"))?;
+                match Self::normalize(&pair[0]) {
+                    Some(reference) if normalized.iter().any(|kept| kept[0] == reference) => {},
+                    Some(reference) => normalized.push(json!([reference, pair[1]])),
+                    None => warn!(target: CONTRACT, "Dropping consumed UTXO reference {}: not an outpoint", pair[0]),
+                }
+            }
+            *consumed = normalized;
+        }
+        
+        Ok(value)
+    }
+}
+
+/// Registered migrations, oldest first
+/// 
+/// Each migration upgrades to the version the next one reads, and the last
+/// one to `CURRENT_STATE_VERSION`.
+pub fn migrations() -> Vec<Box<dyn Migration>> {
+    vec![
+        Box::new(DepositStatusMigration),
+        Box::new(UtxoReferenceMigration),
+    ]
+}
+
+/// Upgrade a raw snapshot to the current schema version
+pub fn migrate_to_current(mut value: Value) -> Result<Value, MigrationError> {
+    // Snapshots from before versioning only carry the contract version
+    let raw_version = value.get("state_version")
+        .or_else(|| value.get("version"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| MigrationError::InvalidSnapshot("missing version".to_string()))?
+        .to_string();
+    
+    let mut version: StateVersion = raw_version.parse()?;
+    
+    if version > CURRENT_STATE_VERSION {
+        return Err(MigrationError::UnsupportedVersion {
+            found: version.to_string(),
+            supported: CURRENT_STATE_VERSION.to_string(),
+        });
+    }
+    
+    let registry = migrations();
+    
+    while version < CURRENT_STATE_VERSION {
+        let position = registry.iter()
+            .position(|migration| migration.from_version().parse::<StateVersion>().ok() == Some(version))
+            .ok_or_else(|| MigrationError::MissingMigration(version.to_string()))?;
+        
+        value = registry[position].migrate(value)?;
+        
+        let next_version = match registry.get(position + 1) {
+            Some(next) => next.from_version().parse()?,
+            None => CURRENT_STATE_VERSION,
+        };
+        
+        // Guard against a misordered registry
+        if next_version <= version {
+            return Err(MigrationError::MissingMigration(version.to_string()));
+        }
+        
+        version = next_version;
+    }
+    
+    if let Some(object) = value.as_object_mut() {
+        object.insert("state_version".to_string(), Value::String(CURRENT_STATE_VERSION.to_string()));
+    }
+    
+    Ok(value)
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Restore a contract from a JSON snapshot of any supported schema version
+    pub fn from_state_versioned(raw_json: &str, token_transfer: T) -> Result<Self, MigrationError> {
+        let value: Value = serde_json::from_str(raw_json)
+            .map_err(|e| MigrationError::InvalidSnapshot(e.to_string()))?;
+        
+        let state: ContractState = serde_json::from_value(migrate_to_current(value)?)
+            .map_err(|e| MigrationError::InvalidSnapshot(e.to_string()))?;
+        
+        Ok(Self::from_state(state, token_transfer)?)
+    }
+}
@@ -4,7 +4,84 @@
 //! and other contract operations.
 
 // Re-export submodules
-pub mod contract_core;
+pub(crate) mod contract_core;
+pub mod audit;
+pub mod state;
+pub mod receipt;
+pub mod view;
+pub mod idempotency;
+pub mod governance;
+pub mod reservation;
+pub mod assignment;
+pub mod user_index;
+pub mod migration;
+pub mod claim;
+pub mod query;
+pub mod summary;
+pub mod utxo_registry;
+pub mod suspension;
+pub mod schedule;
+pub mod forecast;
+pub mod inheritance;
+pub mod reminder;
+pub mod quote;
+pub mod unattributed;
+pub mod insurance;
+pub mod nonce;
+pub mod pending;
+pub mod escrow;
+pub mod simulation;
+pub mod treasury;
+pub mod import;
+pub mod circuit_breaker;
+pub mod deposit_id;
+pub mod fee_destination;
+pub mod fee_ledger;
+pub mod recovery;
+pub mod referral;
+pub mod token_support;
+pub mod token_pause;
+pub mod outbox;
+pub mod refund;
+pub mod authorization;
+pub mod utxo_audit;
+pub mod export;
+pub mod void;
+pub mod read_only;
+pub(crate) mod introspection;
 
 // Re-export commonly used types
-pub use contract_core::TimeLockedDeposit;
\ No newline at end of file
+pub use contract_core::TimeLockedDeposit;
+pub use audit::{ReconciliationConfig, ReconciliationReport};
+pub use state::{ContractState, ContractStats};
+pub use receipt::DepositReceipt;
+pub use view::{DepositView, HeldInscription};
+pub use idempotency::IdempotencyRecord;
+pub use governance::{OwnerAction, Proposal, ProposalStatus};
+pub use reservation::DepositReservation;
+pub use quote::EmergencyQuote;
+pub use user_index::{UserAggregates, UserSummary};
+pub use migration::{Migration, StateVersion};
+pub use query::DepositFilter;
+pub use summary::{DepositCursor, DepositSummary, Page, SortKey};
+pub use schedule::{ScheduleBucket, ScheduleGranularity};
+pub use forecast::{ForecastBucket, ForecastSource, LiquidityForecast, LiquidityForecaster, ProjectedShortfall};
+pub use unattributed::UnattributedFunds;
+pub use reminder::MaturityNotice;
+pub use insurance::CompensationTarget;
+pub use treasury::{SweepProposal, TreasuryPolicy};
+pub use import::{ImportedDeposit, ImportManifest};
+pub use circuit_breaker::{CircuitBreakerConfig, PauseReason};
+pub use deposit_id::IdScheme;
+pub use fee_destination::PendingFeeDestination;
+pub use fee_ledger::{FeeAccrual, FeeAccrualKind, FeeMonthSummary, FeeSweep};
+pub use recovery::WithdrawalCorrection;
+pub use token_support::DeprecationReason;
+pub use token_pause::TokenPausePolicy;
+pub use outbox::{OutboxEntry, OutboxOperation, TransferDirection, TransferRequest};
+pub use refund::{PendingRefund, RefundPolicy, RefundReason};
+pub use authorization::{AuthorizationQuery, AuthorizationRecord, AuthorizedOperation, OperationChannel, OperationContext};
+pub use utxo_audit::{UtxoAuditCursor, UtxoAuditEntry, UtxoAuditReport, UtxoAuditSource, UtxoAuditStatus, UtxoAuditSummary, UtxoAuditor};
+pub use export::CSV_HEADER;
+pub use void::VoidedDeposit;
+pub use read_only::ReadOnlyTransfer;
\ No newline at end of file
@@ -0,0 +1,38 @@
+//! Owner operation nonces
+//!
+//! Every successful owner-only mutating call advances the owner nonce by one.
+//! Owner methods take an optional expected nonce; when given it must equal the
+//! current nonce, so an instruction replayed from a persisted command queue
+//! fails with `NonceMismatch` instead of being applied twice. Failed calls do
+//! not advance the nonce.
+
+use crate::errors::ContractError;
+use crate::models::TokenTransfer;
+use crate::contract::contract_core::TimeLockedDeposit;
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the nonce the next owner call has to present
+    pub fn get_owner_nonce(&self) -> u64 {
+        self.owner_nonce
+    }
+    
+    /// Check the nonce an owner call expects against the current one
+    /// 
+    /// Every owner call passes through here, so a read-only vault refuses them here.
+    pub(crate) fn check_owner_nonce(&self, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        self.ensure_writable()?;
+        
+        match expected_nonce {
+            Some(expected) if expected != self.owner_nonce => Err(ContractError::NonceMismatch {
+                expected,
+                actual: self.owner_nonce,
+            }),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Record a successful owner call
+    pub(crate) fn advance_owner_nonce(&mut self) {
+        self.owner_nonce = self.owner_nonce.wrapping_add(1);
+    }
+}
@@ -0,0 +1,396 @@
+//! Outbox of transfers owed by committed state changes
+//!
+//! `deposit`, `withdraw`, `emergency_withdraw`, `claim_deposit`,
+//! `claim_inherited`, `claim_referral_rewards` and `withdraw_fees` change
+//! the contract and call the transfer backend, and a restart between the two
+//! used to leave them disagreeing. Each of them now commits its state change
+//! together with an `OutboxEntry` naming the transfer it owes, in one write
+//! to the attached `StateStore`, and only then dispatches the entry to the
+//! backend. The entry is marked done, by removing it and persisting again,
+//! once the transfer call has returned. An entry left in the saved state by
+//! a restart is settled by `recover_in_flight` before anything else:
+//!
+//! - transfer queued or sent by the backend: the operation is completed
+//!   without dispatching it again
+//! - transfer unknown to the backend: the entry is dispatched, then the
+//!   operation is completed
+//! - transfer the backend cannot look up: the entry stays in the outbox for
+//!   the owner to settle with `resolve_outbox_entry`
+//!
+//! A deposit whose funding was transferred but that can no longer be
+//! recorded, say because its limits were lowered meanwhile, has its funding
+//! queued for a refund to the depositor in the same write that marks its
+//! entry done.
+//!
+//! Deposit funding and the payouts of withdrawals, emergency withdrawals,
+//! claims and inheritance claims are labelled with their deposit ID, so the
+//! backend can look them up. Fee withdrawals, referral rewards and
+//! withdrawals with options are sent without one. Without an attached store
+//! nothing is persisted and the outbox only orders the steps in memory.
+
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer, TransferPriority, WithdrawOptions};
+use crate::bitcoin::utxo::UtxoRef;
+use crate::contract::authorization::OperationContext;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::refund::RefundReason;
+use crate::store::StateStore;
+use crate::logging::CONTRACT;
+
+/// Operation an outbox entry completes once its transfer returns
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutboxOperation {
+    /// Deposit recorded under its reserved ID once its funding is transferred
+    Deposit {
+        /// Lock period requested
+        lock_period_days: u32,
+        /// UTXO backing the deposit, if any
+        utxo_reference: Option<UtxoRef>,
+        /// Hash whose preimage claims the deposit, if any
+        claim_hash: Option<[u8; 32]>,
+        /// Referrer credited with a share of the deposit fee, if any
+        referrer_address: Option<String>,
+    },
+    /// Withdrawal of the deposit in flight
+    Withdrawal,
+    /// Claim of the deposit in flight by the holder of its preimage
+    Claim,
+    /// Claim of the deposit in flight by its beneficiary
+    InheritanceClaim {
+        /// Depositor's last activity the claim was allowed on
+        last_activity: DateTime<Utc>,
+    },
+    /// Emergency withdrawal of the deposit in flight
+    EmergencyWithdrawal {
+        /// Fee charged, the transfer paying out the rest
+        fee_amount: u64,
+    },
+    /// Fee withdrawal, already taken off the collected fees
+    FeeWithdrawal,
+    /// Referral reward claim, already taken off the referrer's balance
+    ReferralReward,
+}
+
+/// Direction of the transfer an outbox entry owes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferDirection {
+    /// From a depositor to the contract
+    ToContract,
+    /// From the contract to a recipient
+    FromContract,
+}
+
+/// Transfer an outbox entry owes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferRequest {
+    /// Direction of the transfer
+    pub direction: TransferDirection,
+    /// Address paying the contract or paid by it
+    pub address: String,
+    /// Token transferred
+    pub token_type: TokenType,
+    /// Amount transferred
+    pub amount: u64,
+    /// Urgency of a payout
+    pub priority: TransferPriority,
+    /// Deposit funded or paid out, if any
+    pub deposit_id: Option<u64>,
+    /// Whether the payout may wait for a batch
+    #[serde(default)]
+    pub batched: bool,
+    /// Options of a withdrawal paid out right away
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<WithdrawOptions>,
+}
+
+/// Transfer committed with a state change and not yet marked done
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Entry ID, in commit order
+    pub entry_id: u64,
+    /// Operation completed once the transfer returns
+    pub operation: OutboxOperation,
+    /// Transfer owed
+    pub transfer_request: TransferRequest,
+    /// Address that requested the operation
+    pub requested_by: String,
+    /// When the entry was committed
+    pub created_at: DateTime<Utc>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Attach the store outbox commits are persisted to
+    pub fn set_state_store(&mut self, store: Arc<dyn StateStore>) {
+        self.state_store = Some(store);
+    }
+    
+    /// Get the outbox entries not yet marked done, oldest first
+    pub fn get_outbox(&self) -> Vec<OutboxEntry> {
+        self.outbox.values().cloned().collect()
+    }
+    
+    /// Settle an outbox entry the backend cannot look up (owner only)
+    ///
+    /// `transferred` tells whether the backend's own records show the
+    /// transfer made: if so the operation is completed, otherwise the entry
+    /// is dispatched first.
+    pub fn resolve_outbox_entry(
+        &mut self,
+        caller_address: String,
+        entry_id: u64,
+        transferred: bool,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if !self.outbox.contains_key(&entry_id) {
+            return Err(ContractError::OutboxEntryNotFound);
+        }
+        
+        info!(target: CONTRACT, "Outbox entry {} resolved by {}, transferred: {}", entry_id, caller_address, transferred);
+        
+        if !transferred {
+            self.dispatch_outbox_entry(entry_id)?;
+        }
+        let event = self.complete_outbox_entry(entry_id, None)?;
+        
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Settle the outbox entries left by a restart, returning the events of the operations completed
+    ///
+    /// Entries the backend cannot look up are left for `resolve_outbox_entry`.
+    pub(crate) fn reconcile_outbox(&mut self) -> Result<Vec<Event>, ContractError> {
+        let mut events = Vec::new();
+        let entry_ids: Vec<u64> = self.outbox.keys().copied().collect();
+        
+        for entry_id in entry_ids {
+            let Some(transferred) = self.find_outbox_transfer(&self.outbox[&entry_id].transfer_request)? else {
+                warn!(target: CONTRACT, "Transfer of outbox entry {} cannot be looked up; it waits for the owner", entry_id);
+                continue;
+            };
+            
+            if !transferred {
+                info!(target: CONTRACT, "Dispatching outbox entry {} left by a restart", entry_id);
+                self.dispatch_outbox_entry(entry_id)?;
+            }
+            
+            match self.complete_outbox_entry(entry_id, None) {
+                Ok(event) => events.push(event),
+                // A deposit that cannot be recorded any more has its funding refunded
+                Err(e) if !self.outbox.contains_key(&entry_id) => {
+                    warn!(target: CONTRACT, "Outbox entry {} settled with a refund: {}", entry_id, e);
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(events)
+    }
+    
+    /// Check whether the backend queued or sent the transfer of an entry, `None` if it cannot tell
+    fn find_outbox_transfer(&self, request: &TransferRequest) -> Result<Option<bool>, ContractError> {
+        // Payouts with options are sent without their deposit ID
+        let deposit_id = match (request.deposit_id, &request.options) {
+            (Some(deposit_id), None) => deposit_id,
+            _ => return Ok(None),
+        };
+        
+        match request.direction {
+            TransferDirection::ToContract => self.token_transfer.find_funding_transfer(deposit_id)
+                .map(Some)
+                .map_err(|e| ContractError::transfer_failed(TransferStage::Deposit, e)),
+            TransferDirection::FromContract => {
+                if self.token_transfer.queued_payout_references().contains(&deposit_id) {
+                    return Ok(Some(true));
+                }
+                
+                self.token_transfer.find_payout_transaction(deposit_id)
+                    .map(|txid| Some(txid.is_some()))
+                    .map_err(|e| ContractError::transfer_failed(TransferStage::Withdrawal, e))
+            },
+        }
+    }
+    
+    /// Commit the transfer an operation owes, persisting it with the state change already made
+    ///
+    /// Leaves no entry if the state cannot be persisted; the caller then
+    /// undoes its state change.
+    pub(crate) fn commit_to_outbox(
+        &mut self,
+        operation: OutboxOperation,
+        transfer_request: TransferRequest,
+        requested_by: &str,
+    ) -> Result<u64, ContractError> {
+        self.ensure_writable()?;
+        
+        let entry_id = self.next_outbox_entry_id;
+        self.next_outbox_entry_id = entry_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        
+        self.outbox.insert(entry_id, OutboxEntry {
+            entry_id,
+            operation,
+            transfer_request,
+            requested_by: requested_by.to_string(),
+            created_at: self.clock.now(),
+        });
+        
+        if let Err(e) = self.persist_state() {
+            self.outbox.remove(&entry_id);
+            return Err(e);
+        }
+        
+        Ok(entry_id)
+    }
+    
+    /// Hand the transfer of an outbox entry to the backend
+    pub(crate) fn dispatch_outbox_entry(&self, entry_id: u64) -> Result<(), ContractError> {
+        // Reentrancy protection
+        let _guard = self.reentrancy_guard.enter().map_err(|_| ContractError::ReentrancyDetected)?;
+        
+        let request = &self.outbox.get(&entry_id).ok_or(ContractError::OutboxEntryNotFound)?.transfer_request;
+        let (address, token_type, amount) = (request.address.as_str(), &request.token_type, request.amount);
+        
+        let result = match (request.direction, request.deposit_id, &request.options) {
+            (TransferDirection::ToContract, Some(deposit_id), _) => {
+                self.token_transfer.transfer_to_contract_for_deposit(address, token_type, amount, deposit_id)
+            },
+            (TransferDirection::ToContract, None, _) => self.token_transfer.transfer_to_contract(address, token_type, amount),
+            (TransferDirection::FromContract, _, Some(options)) => {
+                return self.token_transfer.transfer_from_contract_with_options(address, token_type, amount, request.priority, options);
+            },
+            (TransferDirection::FromContract, Some(deposit_id), None) if request.batched => {
+                self.token_transfer.transfer_from_contract_batched(address, token_type, amount, deposit_id)
+            },
+            (TransferDirection::FromContract, Some(deposit_id), None) => {
+                self.token_transfer.transfer_from_contract_for_deposit(address, token_type, amount, request.priority, deposit_id)
+            },
+            (TransferDirection::FromContract, None, None) => {
+                self.token_transfer.transfer_from_contract_with_priority(address, token_type, amount, request.priority)
+            },
+        };
+        
+        let stage = match request.direction {
+            TransferDirection::ToContract => TransferStage::Deposit,
+            TransferDirection::FromContract => TransferStage::Withdrawal,
+        };
+        result.map_err(|e| ContractError::transfer_failed(stage, e))
+    }
+    
+    /// Complete the operation of an entry whose transfer returned, and mark the entry done
+    ///
+    /// The entry stays if the operation cannot be completed. The transfer
+    /// was made either way, so a failed write is only logged; the entry then
+    /// survives in the saved state for `recover_in_flight` to find.
+    pub(crate) fn complete_outbox_entry(&mut self, entry_id: u64, context: Option<&OperationContext>) -> Result<Event, ContractError> {
+        let entry = self.outbox.get(&entry_id).cloned().ok_or(ContractError::OutboxEntryNotFound)?;
+        let request = entry.transfer_request;
+        
+        let event = match entry.operation {
+            OutboxOperation::Deposit { lock_period_days, utxo_reference, claim_hash, referrer_address } => {
+                // The depositor's cached balance is stale now
+                self.token_transfer.invalidate_balance(&request.address, &request.token_type);
+                
+                let result = self.commit_deposit(
+                    request.address,
+                    request.token_type,
+                    request.amount,
+                    lock_period_days,
+                    utxo_reference,
+                    claim_hash,
+                    None,
+                    referrer_address,
+                    request.deposit_id,
+                );
+                
+                match result {
+                    Ok(event) => event,
+                    Err(e) => {
+                        self.refund_rejected_deposit(entry_id)?;
+                        return Err(e);
+                    },
+                }
+            },
+            OutboxOperation::Withdrawal => {
+                let deposit_id = request.deposit_id.ok_or(ContractError::DepositNotFound)?;
+                self.finish_withdrawal(&entry.requested_by, deposit_id, context)?
+            },
+            OutboxOperation::Claim => {
+                let deposit_id = request.deposit_id.ok_or(ContractError::DepositNotFound)?;
+                self.finish_claim(&entry.requested_by, deposit_id)?
+            },
+            OutboxOperation::InheritanceClaim { last_activity } => {
+                let deposit_id = request.deposit_id.ok_or(ContractError::DepositNotFound)?;
+                self.finish_inheritance_claim(&entry.requested_by, deposit_id, last_activity)?
+            },
+            OutboxOperation::EmergencyWithdrawal { fee_amount } => {
+                let deposit_id = request.deposit_id.ok_or(ContractError::DepositNotFound)?;
+                self.finish_emergency_withdrawal(&entry.requested_by, deposit_id, fee_amount, request.amount, context)?
+            },
+            OutboxOperation::FeeWithdrawal => {
+                self.finish_fee_withdrawal(&entry.requested_by, request.token_type, request.amount, request.address, context)
+            },
+            OutboxOperation::ReferralReward => {
+                self.finish_referral_reward(request.address, request.token_type, request.amount)
+            },
+        };
+        
+        self.outbox.remove(&entry_id);
+        if let Err(e) = self.persist_state() {
+            warn!(target: CONTRACT, "Outbox entry {} done but not persisted: {}", entry_id, e);
+        }
+        
+        Ok(event)
+    }
+    
+    /// Queue the funding of a deposit that cannot be recorded for a refund, and mark its entry done
+    ///
+    /// The refund and the removed entry are persisted in one write.
+    fn refund_rejected_deposit(&mut self, entry_id: u64) -> Result<(), ContractError> {
+        let request = self.outbox.get(&entry_id).ok_or(ContractError::OutboxEntryNotFound)?.transfer_request.clone();
+        let deposit_id = request.deposit_id.ok_or(ContractError::DepositNotFound)?;
+        
+        warn!(target: CONTRACT, "Deposit {} rejected after its funding was transferred; refunding it", deposit_id);
+        
+        self.queue_refund(request.address, request.token_type, request.amount, RefundReason::DepositRejected { deposit_id })?;
+        
+        self.outbox.remove(&entry_id);
+        if let Err(e) = self.persist_state() {
+            warn!(target: CONTRACT, "Outbox entry {} refunded but not persisted: {}", entry_id, e);
+        }
+        
+        Ok(())
+    }
+    
+    /// Drop an entry whose transfer failed, once the caller has undone its state change
+    pub(crate) fn abandon_outbox_entry(&mut self, entry_id: u64) {
+        self.outbox.remove(&entry_id);
+        if let Err(e) = self.persist_state() {
+            warn!(target: CONTRACT, "Outbox entry {} dropped but not persisted: {}", entry_id, e);
+        }
+    }
+    
+    /// Save the state to the attached store, if any
+    pub(crate) fn persist_state(&self) -> Result<(), ContractError> {
+        match &self.state_store {
+            Some(store) => store.save_state(&self.export_state()).map_err(ContractError::StatePersistenceFailed),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Deposits waiting for their funding
+//!
+//! A pending deposit is recorded before its funds arrive: an on-chain
+//! deposit waits for its funding transaction to confirm, a Lightning deposit
+//! for its invoice to be paid. It holds its deposit limit slot and counts
+//! towards the token total, but its fee is only collected once it is funded,
+//! and it cannot be withdrawn, assigned or claimed before then.
+//!
+//! Every pending deposit expires, at the invoice expiry for Lightning or
+//! after `pending_deposit_timeout` on-chain. Expiry is inclusive: a deposit is
+//! stale from `expires_at` on. Expiring one cancels it, giving back its limit
+//! slot, the token total it counted towards and its UTXO reference. Funding
+//! that confirms after the expiry is not attributed to the deposit; the
+//! output is recorded as unattributed funds for the owner to settle, or
+//! queued for a refund to the depositor if the `RefundPolicy` sets
+//! `refund_late_funding`.
+
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+
+use crate::bitcoin::incoming::IncomingFunds;
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenType, TokenTransfer};
+use crate::contract::contract_core::{TimeLockedDeposit, DEFAULT_PENDING_DEPOSIT_TIMEOUT_SECS};
+use crate::contract::fee_ledger::FeeAccrualKind;
+use crate::contract::refund::RefundReason;
+use crate::contract::utxo_registry::normalize_utxo_reference;
+use crate::logging::CONTRACT;
+
+/// Confirmation wait of on-chain pending deposits in snapshots that predate them
+pub(crate) fn default_pending_deposit_timeout() -> std::time::Duration {
+    std::time::Duration::from_secs(DEFAULT_PENDING_DEPOSIT_TIMEOUT_SECS)
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Record a deposit whose funds have not arrived yet
+    ///
+    /// Lightning deposits expire at `invoice_expires_at`, which has to lie in
+    /// the future; on-chain deposits, and Lightning deposits without an
+    /// invoice expiry, after the pending deposit timeout. The deposit is
+    /// subject to the usual deposit checks and limits.
+    pub fn deposit_pending(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        utxo_reference: Option<UtxoRef>,
+        invoice_expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        self.validate_deposit_request(&caller_address, &token_type, deposit_amount, lock_period_days)?;
+        
+        // Pick the expiry of the funding
+        let now = self.clock.now();
+        let expires_at = match invoice_expires_at {
+            Some(expires_at) if matches!(token_type, TokenType::Lightning) => {
+                if expires_at <= now {
+                    return Err(ContractError::DepositExpired);
+                }
+                expires_at
+            },
+            _ => now + Duration::from_std(self.pending_deposit_timeout).map_err(|_| ContractError::ArithmeticError)?,
+        };
+        
+        self.commit_deposit(caller_address, token_type, deposit_amount, lock_period_days, utxo_reference, None, Some(expires_at), None, None)
+    }
+    
+    /// Record that the funding of a pending deposit confirmed, returning the emitted event
+    ///
+    /// Before its expiry the deposit becomes active, its fee is collected and
+    /// `Deposited` is emitted. Once it expired, on-chain funds are recorded as
+    /// unattributed funds instead and Lightning payments fail with
+    /// `DepositExpired`, unless the refund policy queues the funding for a
+    /// refund. Deposits that are already funded or refunded return `None`.
+    pub fn confirm_pending_deposit(&mut self, deposit_id: u64) -> Result<Option<Event>, ContractError> {
+        self.ensure_writable()?;
+        
+        let now = self.clock.now();
+        
+        // Expire a stale deposit before looking at its funding
+        self.expire_if_stale(deposit_id, now);
+        
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        let pending = match (deposit.status, deposit.pending) {
+            (DepositStatus::Pending, Some(pending)) => pending,
+            (DepositStatus::Expired, Some(pending)) => return self.record_late_funding(deposit_id, pending.fee_amount),
+            _ => return Ok(None),
+        };
+        
+        self.fund_pending_deposit(deposit_id, pending.fee_amount, now).map(Some)
+    }
+    
+    /// Cancel the pending deposits whose funding did not arrive by `now`, returning the emitted events
+    pub fn expire_stale_pending_deposits(&mut self, now: DateTime<Utc>) -> Vec<Event> {
+        if self.ensure_writable().is_err() {
+            return Vec::new();
+        }
+        
+        let mut stale: Vec<u64> = self.deposit_registry.values()
+            .filter(|deposit| deposit.status == DepositStatus::Pending)
+            .filter(|deposit| deposit.pending.is_some_and(|pending| now >= pending.expires_at))
+            .map(|deposit| deposit.deposit_id)
+            .collect();
+        stale.sort_unstable();
+        
+        stale.into_iter()
+            .filter_map(|deposit_id| self.expire_pending_deposit(deposit_id, now))
+            .collect()
+    }
+    
+    /// Get how long on-chain pending deposits wait for their confirmation
+    pub fn get_pending_deposit_timeout(&self) -> std::time::Duration {
+        self.pending_deposit_timeout
+    }
+    
+    /// Set how long on-chain pending deposits wait for their confirmation (owner only)
+    ///
+    /// Deposits already pending keep the expiry they were created with.
+    pub fn set_pending_deposit_timeout(&mut self, caller_address: String, timeout: std::time::Duration, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if timeout.is_zero() {
+            return Err(ContractError::InvalidAmount { reason: "pending deposit timeout cannot be zero".to_string() });
+        }
+        
+        self.pending_deposit_timeout = timeout;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Expire a pending deposit if its funding is overdue at `now`
+    pub(crate) fn expire_if_stale(&mut self, deposit_id: u64, now: DateTime<Utc>) {
+        let is_stale = self.deposit_registry.get(&deposit_id).is_some_and(|deposit| {
+            deposit.status == DepositStatus::Pending
+                && deposit.pending.is_some_and(|pending| now >= pending.expires_at)
+        });
+        
+        if is_stale {
+            self.expire_pending_deposit(deposit_id, now);
+        }
+    }
+    
+    /// Cancel a pending deposit and give back what it held
+    fn expire_pending_deposit(&mut self, deposit_id: u64, now: DateTime<Utc>) -> Option<Event> {
+        let deposit = self.deposit_registry.get_mut(&deposit_id)?;
+        let pending = deposit.pending?;
+        
+        deposit.status = DepositStatus::Expired;
+        deposit.last_modified = now;
+        
+        // Give back the token total and the limit slot
+        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
+            *total = total.saturating_sub(deposit.deposited_amount);
+        }
+        self.user_index.record_expiry(&deposit.depositor_address, deposit);
+        
+        let event = Event::DepositExpired {
+            deposit_id,
+            depositor_address: deposit.depositor_address.clone(),
+            token_type: deposit.deposited_token_type.clone(),
+            amount: deposit.deposited_amount,
+            expires_at: pending.expires_at,
+            timestamp: now,
+        };
+        
+        // Let the output back another deposit or the unattributed ledger
+        self.release_utxo_reference(deposit_id);
+        
+        info!(target: CONTRACT, "Pending deposit {} expired unfunded", deposit_id);
+        
+        self.emit(&event);
+        
+        Some(event)
+    }
+    
+    /// Collect the deferred fee of a deposit and activate it, returning the emitted event
+    pub(crate) fn fund_pending_deposit(&mut self, deposit_id: u64, fee_amount: u64, now: DateTime<Utc>) -> Result<Event, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        
+        // Collect the fee that was deferred
+        let token_type = deposit.deposited_token_type.clone();
+        let referrer_address = deposit.referrer_address.clone();
+        let (collector_amount, referral_amount) = match &referrer_address {
+            Some(_) => Self::split_referral_fee(fee_amount, self.fee_config.referral_share_percentage)?,
+            None => (fee_amount, 0),
+        };
+        let new_fees = self.fee_config.collected_fees
+            .get(&token_type)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(collector_amount)
+            .ok_or(ContractError::ArithmeticError)?;
+        let new_referral_balance = match referrer_address {
+            Some(referrer_address) => {
+                let balance = self.referral_balance_after(&referrer_address, &token_type, referral_amount)?;
+                Some((referrer_address, balance))
+            },
+            None => None,
+        };
+        if fee_amount > 0 {
+            self.fee_config.collected_fees.insert(token_type.clone(), new_fees);
+            self.fee_ledger.record_accrual(deposit_id, &token_type, collector_amount, FeeAccrualKind::DepositFee, now);
+            if let Some((referrer_address, balance)) = new_referral_balance {
+                self.referral_balances.insert((referrer_address, token_type.clone()), balance);
+            }
+        }
+        
+        let deposit = self.deposit_registry.get_mut(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        deposit.status = DepositStatus::Active;
+        deposit.pending = None;
+        deposit.last_modified = now;
+        
+        info!(target: CONTRACT, "Pending deposit {} funded", deposit_id);
+        
+        let chain_anchor = self.token_transfer.chain_anchor();
+        let event = Event::Deposited {
+            deposit_id,
+            depositor_address: deposit.depositor_address.clone(),
+            token_type,
+            deposit_amount: deposit.deposited_amount + fee_amount,
+            fee_amount,
+            locked_amount: deposit.deposited_amount,
+            unlock_timestamp: deposit.unlock_timestamp,
+            transaction_hash: None,
+            block_number: chain_anchor.as_ref().map(|anchor| anchor.block_height),
+            chain_anchor,
+            asset_detail: deposit.asset_detail(),
+            timestamp: now,
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+    
+    /// Record the funding of an expired deposit as unattributed funds
+    fn record_late_funding(&mut self, deposit_id: u64, fee_amount: u64) -> Result<Option<Event>, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        
+        // The depositor is known, so the policy may send the funds straight back
+        if self.refund_policy.refund_late_funding {
+            let amount = deposit.deposited_amount + fee_amount;
+            return self.refund_late_funding(deposit_id, amount);
+        }
+        
+        // Only an on-chain output can be held for the owner to settle
+        let reference = deposit.utxo_reference.as_ref().ok_or(ContractError::DepositExpired)?;
+        
+        let funds = IncomingFunds {
+            txid: reference.txid().to_string(),
+            vout: reference.vout(),
+            amount: deposit.deposited_amount + fee_amount,
+            sender_addresses: vec![deposit.depositor_address.clone()],
+            confirmations: 0,
+        };
+        
+        info!(target: CONTRACT, "Funding of expired deposit {} recorded as unattributed", deposit_id);
+        
+        Ok(self.record_incoming_funds(&funds))
+    }
+    
+    /// Queue the late funding of an expired deposit for a refund to its depositor
+    ///
+    /// Returns `None` if the funding was already refunded or listed as
+    /// unattributed funds.
+    pub(crate) fn refund_late_funding(&mut self, deposit_id: u64, amount: u64) -> Result<Option<Event>, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        let depositor_address = deposit.depositor_address.clone();
+        let token_type = deposit.deposited_token_type.clone();
+        let outpoint = deposit.utxo_reference.as_ref().map(|reference| normalize_utxo_reference(&reference.to_string()));
+        
+        if let Some(outpoint) = &outpoint {
+            if self.unattributed_funds.contains_key(outpoint) || self.refunded_outpoints.contains(outpoint) {
+                return Ok(None);
+            }
+        }
+        
+        let event = self.queue_refund(depositor_address, token_type, amount, RefundReason::LateFunding { deposit_id })?;
+        
+        // The refund settles the output, so the watcher ignores it from now on
+        if let (Some(_), Some(outpoint)) = (&event, outpoint) {
+            self.refunded_outpoints.insert(outpoint);
+        }
+        
+        info!(target: CONTRACT, "Funding of expired deposit {} queued for a refund", deposit_id);
+        
+        Ok(event)
+    }
+    
+    /// Find the expired deposit an output was meant to fund
+    pub(crate) fn expired_deposit_funded_by(&self, outpoint: &str) -> Option<u64> {
+        self.deposits_iter()
+            .filter(|deposit| deposit.status == DepositStatus::Expired)
+            .find(|deposit| deposit.utxo_reference.as_ref()
+                .is_some_and(|reference| normalize_utxo_reference(&reference.to_string()) == outpoint))
+            .map(|deposit| deposit.deposit_id)
+    }
+}
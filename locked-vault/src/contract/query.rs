@@ -0,0 +1,106 @@
+//! Borrowing iterators over the deposit registry
+//!
+//! Reports and maintenance routines walk deposits through these helpers
+//! instead of collecting clones of the whole registry.
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::models::{Deposit, DepositStatus, TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Criteria for selecting deposits; unset fields match everything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositFilter {
+    /// Only deposits of this token type
+    pub token_type: Option<TokenType>,
+    /// Only deposits currently owned by this address
+    pub address: Option<String>,
+    /// Only deposits in this status
+    pub status: Option<DepositStatus>,
+    /// Only deposits unlocking at or after this time
+    pub unlock_from: Option<DateTime<Utc>>,
+    /// Only deposits unlocking before this time
+    pub unlock_until: Option<DateTime<Utc>>,
+}
+
+impl DepositFilter {
+    /// Filter matching deposits that are neither withdrawn nor suspended
+    pub fn active() -> Self {
+        Self {
+            status: Some(DepositStatus::Active),
+            ..Self::default()
+        }
+    }
+    
+    /// Restrict to a token type
+    pub fn with_token_type(mut self, token_type: TokenType) -> Self {
+        self.token_type = Some(token_type);
+        self
+    }
+    
+    /// Restrict to an owner address
+    pub fn with_address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+    
+    /// Restrict to a status
+    pub fn with_status(mut self, status: DepositStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+    
+    /// Restrict to unlock timestamps in `[from, until)`
+    pub fn with_unlock_range(mut self, from: Option<DateTime<Utc>>, until: Option<DateTime<Utc>>) -> Self {
+        self.unlock_from = from;
+        self.unlock_until = until;
+        self
+    }
+    
+    /// Check whether a deposit matches every set criterion
+    pub fn matches(&self, deposit: &Deposit) -> bool {
+        self.token_type.as_ref().is_none_or(|token_type| &deposit.deposited_token_type == token_type)
+            && self.address.as_ref().is_none_or(|address| &deposit.depositor_address == address)
+            && self.status.is_none_or(|status| deposit.status == status)
+            && self.unlock_from.is_none_or(|from| deposit.unlock_timestamp >= from)
+            && self.unlock_until.is_none_or(|until| deposit.unlock_timestamp < until)
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Iterate over all deposits without cloning, in no particular order
+    pub fn deposits_iter(&self) -> impl Iterator<Item = &Deposit> + '_ {
+        self.deposit_registry.values()
+    }
+    
+    /// Iterate over deposits matching a filter without cloning, in no particular order
+    /// 
+    /// Address filters walk only that user's deposit IDs, including archived ones.
+    pub fn deposits_filtered(&self, filter: DepositFilter) -> Box<dyn Iterator<Item = &Deposit> + '_> {
+        match filter.address.clone() {
+            Some(address) => {
+                let active_ids = self.user_deposit_ids.get(&address).into_iter().flatten();
+                let archived_ids = self.user_index.archived_deposit_ids.get(&address).into_iter().flatten();
+                
+                Box::new(active_ids.chain(archived_ids)
+                    .filter_map(move |deposit_id| self.deposit_registry.get(deposit_id))
+                    .filter(move |deposit| filter.matches(deposit)))
+            },
+            None => Box::new(self.deposit_registry.values().filter(move |deposit| filter.matches(deposit))),
+        }
+    }
+    
+    /// Apply a change to every deposit matching a filter
+    /// 
+    /// For maintenance routines only: callers must keep totals and user
+    /// indexes consistent with whatever they change.
+    pub(crate) fn for_each_deposit_mut<F>(&mut self, filter: &DepositFilter, mut f: F)
+    where
+        F: FnMut(&mut Deposit),
+    {
+        for deposit in self.deposit_registry.values_mut().filter(|deposit| filter.matches(deposit)) {
+            f(deposit);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Emergency withdrawal quotes
+//!
+//! A quote fixes the emergency fee of a deposit for a short window, so the
+//! fee a user confirmed is the fee they pay even if fee decay or an owner
+//! change moves the current fee before the withdrawal lands. Quotes are
+//! single-use; expired ones are dropped the next time quotes are touched.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::TokenTransfer;
+use crate::contract::contract_core::{TimeLockedDeposit, DEFAULT_QUOTE_VALIDITY_SECS};
+
+/// Emergency fee of a deposit fixed until `valid_until`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencyQuote {
+    /// Quote ID
+    pub quote_id: u64,
+    /// Quoted deposit
+    pub deposit_id: u64,
+    /// Address the quote was issued to
+    pub depositor_address: String,
+    /// Fee charged if the quote is used
+    pub fee_amount: u64,
+    /// Amount paid out if the quote is used
+    pub net_amount: u64,
+    /// Last moment the quote is honored (exclusive)
+    pub valid_until: DateTime<Utc>,
+}
+
+/// Validity window of quotes in snapshots that predate quotes
+pub(crate) fn default_quote_validity() -> std::time::Duration {
+    std::time::Duration::from_secs(DEFAULT_QUOTE_VALIDITY_SECS)
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Quote the emergency withdrawal of a deposit at its current fee (depositor only)
+    ///
+    /// The quote only fixes the fee; the other emergency withdrawal checks
+    /// still apply when it is used.
+    pub fn quote_emergency_withdraw(&mut self, caller_address: String, deposit_id: u64) -> Result<EmergencyQuote, ContractError> {
+        self.ensure_writable()?;
+        
+        self.prune_expired_quotes();
+        
+        // Get deposit
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Check ownership
+        if deposit.depositor_address != caller_address {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Check if already withdrawn
+        if deposit.is_withdrawn() {
+            return Err(ContractError::DepositAlreadyWithdrawn);
+        }
+        
+        // Fix the fee that applies right now
+        let now = self.clock.now();
+        let fee_percentage = Self::effective_emergency_fee_percentage(&self.fee_config, deposit, now);
+        let (fee_amount, net_amount) = Self::compute_emergency_fee(deposit.deposited_amount, fee_percentage)?;
+        
+        let validity = Duration::from_std(self.quote_validity).map_err(|_| ContractError::ArithmeticError)?;
+        
+        let quote_id = self.next_quote_id;
+        self.next_quote_id = self.next_quote_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        
+        let quote = EmergencyQuote {
+            quote_id,
+            deposit_id,
+            depositor_address: caller_address,
+            fee_amount,
+            net_amount,
+            valid_until: now + validity,
+        };
+        self.emergency_quotes.insert(quote_id, quote.clone());
+        
+        Ok(quote)
+    }
+    
+    /// Emergency withdraw a deposit at the fee of an unexpired quote, consuming the quote
+    pub fn emergency_withdraw_with_quote(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        quote_id: u64,
+    ) -> Result<Event, ContractError> {
+        let quote = self.emergency_quotes.get(&quote_id)
+            .ok_or(ContractError::QuoteNotFound)?;
+        
+        if quote.deposit_id != deposit_id || quote.depositor_address != caller_address {
+            return Err(ContractError::QuoteMismatch);
+        }
+        
+        if self.clock.now() >= quote.valid_until {
+            self.emergency_quotes.remove(&quote_id);
+            return Err(ContractError::QuoteExpired);
+        }
+        
+        let fee_amount = quote.fee_amount;
+        self.prune_expired_quotes();
+        
+        // Keep the quote if the withdrawal fails so the caller can retry within the window
+        let event = self.emergency_withdraw_internal(caller_address, deposit_id, Some(fee_amount), None)?;
+        self.emergency_quotes.remove(&quote_id);
+        
+        Ok(event)
+    }
+    
+    /// Set how long emergency withdrawal quotes are honored (owner only)
+    pub fn set_quote_validity(&mut self, caller_address: String, validity: std::time::Duration, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if validity.is_zero() {
+            return Err(ContractError::InvalidAmount { reason: "quote validity cannot be zero".to_string() });
+        }
+        
+        self.quote_validity = validity;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Drop quotes past their validity window
+    fn prune_expired_quotes(&mut self) {
+        let now = self.clock.now();
+        self.emergency_quotes.retain(|_, quote| quote.valid_until > now);
+    }
+}
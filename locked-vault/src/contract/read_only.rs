@@ -0,0 +1,226 @@
+//! Watch-only replicas of a vault
+//!
+//! A replica mirrors a production vault's state so compliance can verify
+//! every deposit against the chain, but it must never move funds. Two
+//! guards enforce that independently:
+//!
+//! - `ReadOnlyTransfer` wraps the real backend, answering balance, UTXO and
+//!   validation queries from it and refusing every transfer with
+//!   `ReadOnlyMode`.
+//! - A contract built with `from_state_read_only` refuses deposits,
+//!   withdrawals, claims and every owner call with `ReadOnlyMode` before it
+//!   changes anything.
+//!
+//! Queries, exports, statements, UTXO audits and reconciliation work as on a
+//! writable vault. Chain-driven updates are left to the production vault:
+//! confirming pending deposits, reorg notices and suspending spent deposits
+//! fail with `ReadOnlyMode`, and the daemon's periodic jobs do nothing and
+//! report no events. A replica is not turned writable again; it is rebuilt
+//! from a newer snapshot of the production vault.
+
+use crate::bitcoin::anchor::ChainAnchor;
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::feature_gate::FeatureGateStatus;
+use crate::bitcoin::ordinals::Inscription;
+use crate::errors::{ContractError, TransferStage, READ_ONLY_MODE};
+use crate::models::{
+    AmountConstraints, FeeQuote, ScriptEscrow, TokenTransfer, TokenType, TransferPriority, TransferQueueStatus,
+    UnspentOutput, WithdrawOptions,
+};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::state::ContractState;
+
+/// Backend that answers queries from an inner backend and refuses all transfers
+pub struct ReadOnlyTransfer<T: TokenTransfer> {
+    inner: T,
+}
+
+impl<T: TokenTransfer> ReadOnlyTransfer<T> {
+    /// Wrap a backend so nothing can be sent through it
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+    
+    /// Get the wrapped backend
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: TokenTransfer> TokenTransfer for ReadOnlyTransfer<T> {
+    fn transfer_to_contract(&self, from_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        Err(format!("{}refused transfer of {} {} from {}", READ_ONLY_MODE, amount, token_type.name(), from_address))
+    }
+    
+    fn transfer_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        Err(format!("{}refused transfer of {} {} to {}", READ_ONLY_MODE, amount, token_type.name(), to_address))
+    }
+    
+    fn get_balance(&self, address: &str, token_type: &TokenType) -> Result<u64, String> {
+        self.inner.get_balance(address, token_type)
+    }
+    
+    fn validate_address(&self, address: &str) -> Result<(), String> {
+        self.inner.validate_address(address)
+    }
+    
+    fn check_network(&self, address: &str) -> Result<(), ContractError> {
+        self.inner.check_network(address)
+    }
+    
+    fn validate_destination(&self, address: &str) -> Result<(), ContractError> {
+        self.inner.validate_destination(address)
+    }
+    
+    fn supports_token_type(&self, token_type: &TokenType) -> bool {
+        self.inner.supports_token_type(token_type)
+    }
+    
+    fn get_network_type(&self) -> String {
+        self.inner.get_network_type()
+    }
+    
+    fn get_contract_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        self.inner.get_contract_balance(token_type)
+    }
+    
+    fn get_operational_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        self.inner.get_operational_balance(token_type)
+    }
+    
+    fn get_sweepable_balance(&self, token_type: &TokenType) -> Result<Option<u64>, String> {
+        self.inner.get_sweepable_balance(token_type)
+    }
+    
+    fn feature_gate_status(&self, token_type: &TokenType) -> Option<FeatureGateStatus> {
+        self.inner.feature_gate_status(token_type)
+    }
+    
+    fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+        self.inner.is_utxo_unspent(utxo_reference)
+    }
+    
+    fn get_utxo(&self, utxo_reference: &str) -> Result<Option<UnspentOutput>, String> {
+        self.inner.get_utxo(utxo_reference)
+    }
+    
+    fn chain_anchor(&self) -> Option<ChainAnchor> {
+        self.inner.chain_anchor()
+    }
+    
+    fn invalidate_balance(&self, address: &str, token_type: &TokenType) {
+        self.inner.invalidate_balance(address, token_type)
+    }
+    
+    fn set_confirmation_policy(&self, confirmation_policy: &ConfirmationPolicy) {
+        self.inner.set_confirmation_policy(confirmation_policy)
+    }
+    
+    fn track_funding(&self, deposit_id: u64, txid: &str, token_type: &TokenType, amount: u64) {
+        self.inner.track_funding(deposit_id, txid, token_type, amount)
+    }
+    
+    fn get_inscription_details(&self, inscription_id: &str) -> Result<Option<Inscription>, String> {
+        self.inner.get_inscription_details(inscription_id)
+    }
+    
+    fn lightning_invoice_id(&self, payment_hash: &str) -> Option<String> {
+        self.inner.lightning_invoice_id(payment_hash)
+    }
+    
+    fn multisig_wallet_address(&self, wallet: &str) -> Option<String> {
+        self.inner.multisig_wallet_address(wallet)
+    }
+    
+    fn transfer_queue_status(&self) -> Option<TransferQueueStatus> {
+        self.inner.transfer_queue_status()
+    }
+    
+    /// Refuses before coin control is looked at, which the default would reject first
+    fn transfer_from_contract_with_options(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        _priority: TransferPriority,
+        _options: &WithdrawOptions,
+    ) -> Result<(), ContractError> {
+        self.transfer_from_contract(to_address, token_type, amount)
+            .map_err(|reason| ContractError::transfer_failed(TransferStage::Withdrawal, reason))
+    }
+    
+    fn estimate_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<u64, String> {
+        self.inner.estimate_withdrawal_fee(to_address, token_type, amount, priority)
+    }
+    
+    fn quote_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<FeeQuote, String> {
+        self.inner.quote_withdrawal_fee(to_address, token_type, amount, priority)
+    }
+    
+    fn dust_limit(&self, token_type: &TokenType) -> Option<u64> {
+        self.inner.dust_limit(token_type)
+    }
+    
+    fn queued_payout_references(&self) -> Vec<u64> {
+        self.inner.queued_payout_references()
+    }
+    
+    fn find_payout_transaction(&self, reference: u64) -> Result<Option<String>, String> {
+        self.inner.find_payout_transaction(reference)
+    }
+    
+    fn transaction_confirmations(&self, txid: &str) -> Result<Option<u32>, String> {
+        self.inner.transaction_confirmations(txid)
+    }
+    
+    fn find_funding_transfer(&self, deposit_id: u64) -> Result<bool, String> {
+        self.inner.find_funding_transfer(deposit_id)
+    }
+    
+    fn watch_script_escrow(&self, escrow: &ScriptEscrow) {
+        self.inner.watch_script_escrow(escrow)
+    }
+    
+    fn amount_constraints(&self, token_type: &TokenType) -> Result<AmountConstraints, String> {
+        self.inner.amount_constraints(token_type)
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Restore a contract from saved state that refuses every operation changing it
+    ///
+    /// Pair it with a `ReadOnlyTransfer` backend for a replica that cannot
+    /// move funds even through a path the contract does not guard.
+    pub fn from_state_read_only(state: ContractState, token_transfer: T) -> Result<Self, ContractError> {
+        let mut contract = Self::from_state(state, token_transfer)?;
+        contract.read_only = true;
+        
+        Ok(contract)
+    }
+    
+    /// Check whether the contract refuses operations that change it
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    
+    /// Refuse an operation that changes state or moves funds on a read-only contract
+    pub(crate) fn ensure_writable(&self) -> Result<(), ContractError> {
+        if self.read_only {
+            return Err(ContractError::ReadOnlyMode);
+        }
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! Signed deposit receipts
+//!
+//! A receipt carries the canonical fields of a deposit and an ECDSA signature
+//! by the vault key, so depositors can prove a deposit to third parties.
+//!
+//! The signature covers the fields in the explicit encoding of the
+//! `canonical` module. Receipts signed over the JSON payload of earlier
+//! versions still verify.
+
+use chrono::{DateTime, Utc};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::Network;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::signature::SignatureVerifier;
+use crate::canonical::CanonicalEncoder;
+use crate::errors::ContractError;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Deposit receipt signed by the vault key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositReceipt {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Address of the depositor
+    pub depositor_address: String,
+    /// Token type
+    pub token_type: TokenType,
+    /// Deposited amount
+    pub amount: u64,
+    /// Deposit timestamp
+    pub deposit_timestamp: DateTime<Utc>,
+    /// Unlock timestamp
+    pub unlock_timestamp: DateTime<Utc>,
+    /// Contract owner address
+    pub contract_owner: String,
+    /// Contract version
+    pub contract_version: String,
+    /// Hex-encoded compact ECDSA signature over the canonical payload
+    pub signature: String,
+}
+
+/// Version of the receipt encoding
+pub const RECEIPT_ENCODING_VERSION: u8 = 1;
+
+/// Domain of the receipt encoding
+const RECEIPT_DOMAIN: &str = "time-locked-vault/receipt";
+
+/// Signed fields of a receipt as JSON, as receipts were signed before the canonical encoding
+#[derive(Serialize)]
+struct LegacyReceiptPayload<'a> {
+    deposit_id: u64,
+    depositor_address: &'a str,
+    token_type: &'a TokenType,
+    amount: u64,
+    deposit_timestamp: &'a DateTime<Utc>,
+    unlock_timestamp: &'a DateTime<Utc>,
+    contract_owner: &'a str,
+    contract_version: &'a str,
+}
+
+impl DepositReceipt {
+    /// Get the canonical bytes covered by the signature
+    ///
+    /// Every field but the signature, in declaration order.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut encoder = CanonicalEncoder::new(RECEIPT_DOMAIN, RECEIPT_ENCODING_VERSION);
+        encoder.u64(self.deposit_id);
+        encoder.string(&self.depositor_address);
+        encoder.token_type(&self.token_type);
+        encoder.u64(self.amount);
+        encoder.timestamp(&self.deposit_timestamp);
+        encoder.timestamp(&self.unlock_timestamp);
+        encoder.string(&self.contract_owner);
+        encoder.string(&self.contract_version);
+        encoder.finish()
+    }
+    
+    /// Get the JSON payload receipts were signed over before the canonical encoding
+    fn legacy_bytes(&self) -> Vec<u8> {
+        let payload = LegacyReceiptPayload {
+            deposit_id: self.deposit_id,
+            depositor_address: &self.depositor_address,
+            token_type: &self.token_type,
+            amount: self.amount,
+            deposit_timestamp: &self.deposit_timestamp,
+            unlock_timestamp: &self.unlock_timestamp,
+            contract_owner: &self.contract_owner,
+            contract_version: &self.contract_version,
+        };
+        
+        serde_json::to_vec(&payload).unwrap_or_default()
+    }
+    
+    /// Get the SHA-256 digest of the canonical payload
+    pub fn digest(&self) -> [u8; 32] {
+        sha256::Hash::hash(&self.canonical_bytes()).to_byte_array()
+    }
+    
+    /// Check the signature against the vault public key
+    ///
+    /// Receipts signed over the legacy JSON payload are accepted too.
+    pub fn verify(&self, vault_pubkey: &[u8]) -> bool {
+        let signature = match hex::decode(&self.signature) {
+            Ok(signature) => signature,
+            Err(_) => return false,
+        };
+        
+        let verifier = SignatureVerifier::new(Network::Testnet);
+        let legacy_digest = sha256::Hash::hash(&self.legacy_bytes()).to_byte_array();
+        
+        [self.digest(), legacy_digest].iter()
+            .any(|digest| verifier.verify(digest, &signature, vault_pubkey).unwrap_or(false))
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Issue a signed receipt for an active deposit
+    pub fn issue_receipt(&self, deposit_id: u64) -> Result<DepositReceipt, ContractError> {
+        let signer = self.receipt_signer.as_ref()
+            .ok_or_else(|| ContractError::InitializationError("No receipt signer configured".to_string()))?;
+        
+        // Only active deposits get receipts
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        deposit.ensure_holds_funds()?;
+        
+        let mut receipt = DepositReceipt {
+            deposit_id: deposit.deposit_id,
+            depositor_address: deposit.depositor_address.clone(),
+            token_type: deposit.deposited_token_type.clone(),
+            amount: deposit.deposited_amount,
+            deposit_timestamp: deposit.deposit_timestamp,
+            unlock_timestamp: deposit.unlock_timestamp,
+            contract_owner: self.contract_owner_address.clone(),
+            contract_version: self.version.clone(),
+            signature: String::new(),
+        };
+        
+        // Sign the canonical payload
+        let signature = signer.sign(&receipt.digest())?;
+        receipt.signature = hex::encode(signature);
+        
+        Ok(receipt)
+    }
+}
@@ -0,0 +1,207 @@
+//! Startup reconciliation of withdrawals in flight
+//!
+//! `withdraw` marks a deposit `WithdrawalPending` while its payout is handed
+//! to the transfer backend. If the process stops in between, the saved state
+//! and the backend can disagree: the state may show a payout that was never
+//! sent, or the backend may still hold a payout that was already paid by a
+//! transaction it sent before the restart. `recover_in_flight` runs at
+//! startup, before any withdrawal, and settles each in-flight deposit from
+//! what the backend and the chain report:
+//!
+//! - payout confirmed: the withdrawal is completed
+//! - payout broadcast but not confirmed: the deposit stays pending, with its
+//!   transaction recorded so later runs only wait for its confirmations
+//! - payout still queued in the backend: the withdrawal is completed, as
+//!   `withdraw` would have done
+//! - payout unknown: the deposit is reverted and can be withdrawn again
+//!
+//! A queued payout is dropped if a transaction the node knows already pays
+//! its deposit, whether the deposit is in flight or already withdrawn. Each
+//! correction emits a `WithdrawalRecovered` event; running the routine again
+//! without anything changing on chain corrects nothing.
+//!
+//! Entries left in the outbox are settled first, completing their operations
+//! as described in the `outbox` module; deposits whose entry waits for the
+//! owner are left in flight.
+
+use std::collections::HashSet;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Correction made to a deposit by `recover_in_flight`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalCorrection {
+    /// Payout confirmed; the withdrawal was completed
+    Completed,
+    /// Payout still queued in the backend; the withdrawal was completed
+    Queued,
+    /// Payout broadcast but not yet confirmed; its transaction was recorded
+    AwaitingConfirmation,
+    /// Payout never sent; the deposit can be withdrawn again
+    Reverted,
+    /// Queued payout dropped because a transaction already pays the deposit
+    DuplicatePayoutDropped,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Settle withdrawals left in flight by a restart, returning the emitted events
+    ///
+    /// Fails on the first backend or chain lookup that fails; corrections
+    /// made before it stand, and the routine can simply be run again.
+    pub fn recover_in_flight(&mut self) -> Result<Vec<Event>, ContractError> {
+        self.ensure_writable()?;
+        
+        let mut events = self.reconcile_outbox()?;
+        let queued = self.token_transfer.queued_payout_references();
+        
+        // Payouts still in the outbox are settled by the owner
+        let in_outbox: HashSet<u64> = self.outbox.values()
+            .filter_map(|entry| entry.transfer_request.deposit_id)
+            .collect();
+        
+        let mut in_flight: Vec<u64> = self.deposits_iter()
+            .filter(|deposit| deposit.status == DepositStatus::WithdrawalPending && !in_outbox.contains(&deposit.deposit_id))
+            .map(|deposit| deposit.deposit_id)
+            .collect();
+        in_flight.sort_unstable();
+        
+        for deposit_id in in_flight {
+            let is_queued = queued.contains(&deposit_id);
+            let (txid, confirmations) = self.find_payout(deposit_id)?;
+            
+            // A transaction the node knows already pays the deposit, so a queued payout would pay it twice
+            if is_queued && confirmations.is_some() {
+                self.drop_duplicate_payout(deposit_id, txid.clone(), &mut events);
+            }
+            
+            match (txid, confirmations) {
+                (Some(txid), Some(confirmations)) if confirmations >= self.required_payout_confirmations(deposit_id) => {
+                    self.complete_in_flight(deposit_id, Some(txid), WithdrawalCorrection::Completed, &mut events);
+                },
+                (Some(txid), Some(_)) => {
+                    let deposit = self.deposit_registry.get_mut(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+                    if deposit.withdrawal_tx_hash.as_deref() != Some(txid.as_str()) {
+                        deposit.withdrawal_tx_hash = Some(txid.clone());
+                        deposit.last_modified = self.clock.now();
+                        self.record_correction(deposit_id, WithdrawalCorrection::AwaitingConfirmation, Some(txid), &mut events);
+                    }
+                },
+                _ if is_queued => {
+                    self.complete_in_flight(deposit_id, None, WithdrawalCorrection::Queued, &mut events);
+                },
+                _ => self.revert_in_flight(deposit_id, &mut events),
+            }
+        }
+        
+        // Queued payouts of withdrawn deposits that a known transaction already paid
+        for deposit_id in self.token_transfer.queued_payout_references() {
+            let withdrawn = self.deposit_registry.get(&deposit_id).is_some_and(|deposit| deposit.is_withdrawn());
+            if !withdrawn {
+                continue;
+            }
+            
+            if let (Some(txid), Some(_)) = self.find_payout(deposit_id)? {
+                if let Some(deposit) = self.deposit_registry.get_mut(&deposit_id) {
+                    deposit.withdrawal_tx_hash = Some(txid.clone());
+                }
+                self.drop_duplicate_payout(deposit_id, Some(txid), &mut events);
+            }
+        }
+        
+        if !events.is_empty() {
+            info!(target: CONTRACT, "Recovered {} in-flight withdrawal corrections", events.len());
+        }
+        
+        Ok(events)
+    }
+    
+    /// Find the transaction paying a deposit and its confirmations, `None` if the node does not know it
+    fn find_payout(&self, deposit_id: u64) -> Result<(Option<String>, Option<u32>), ContractError> {
+        let recorded = self.deposit_registry.get(&deposit_id).and_then(|deposit| deposit.withdrawal_tx_hash.clone());
+        let txid = match recorded {
+            Some(txid) => Some(txid),
+            None => self.token_transfer.find_payout_transaction(deposit_id)
+                .map_err(|e| ContractError::transfer_failed(TransferStage::Withdrawal, e))?,
+        };
+        
+        let confirmations = match &txid {
+            Some(txid) => self.token_transfer.transaction_confirmations(txid)
+                .map_err(|e| ContractError::transfer_failed(TransferStage::Withdrawal, e))?,
+            None => None,
+        };
+        
+        Ok((txid, confirmations))
+    }
+    
+    /// Confirmations a payout needs before its withdrawal is completed
+    fn required_payout_confirmations(&self, deposit_id: u64) -> u32 {
+        self.deposit_registry.get(&deposit_id)
+            .map(|deposit| self.confirmation_policy.required_confirmations(&deposit.deposited_token_type, deposit.deposited_amount))
+            .unwrap_or(1)
+    }
+    
+    /// Complete a withdrawal left in flight, as `withdraw` does once the backend accepts the payout
+    fn complete_in_flight(&mut self, deposit_id: u64, txid: Option<String>, correction: WithdrawalCorrection, events: &mut Vec<Event>) {
+        let now = self.clock.now();
+        let Some(deposit) = self.deposit_registry.get_mut(&deposit_id) else {
+            return;
+        };
+        
+        deposit.status = DepositStatus::Withdrawn;
+        deposit.last_modified = now;
+        if txid.is_some() {
+            deposit.withdrawal_tx_hash = txid.clone();
+        }
+        
+        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
+            *total = total.checked_sub(deposit.deposited_amount).unwrap_or(0);
+        }
+        let depositor_address = deposit.depositor_address.clone();
+        self.user_index.record_withdrawal(&depositor_address, deposit);
+        
+        self.record_correction(deposit_id, correction, txid, events);
+    }
+    
+    /// Make a deposit whose payout was never sent withdrawable again
+    fn revert_in_flight(&mut self, deposit_id: u64, events: &mut Vec<Event>) {
+        let now = self.clock.now();
+        let Some(deposit) = self.deposit_registry.get_mut(&deposit_id) else {
+            return;
+        };
+        
+        deposit.status = DepositStatus::Active;
+        deposit.last_modified = now;
+        
+        self.record_correction(deposit_id, WithdrawalCorrection::Reverted, None, events);
+    }
+    
+    /// Drop the queued payout of a deposit a known transaction already pays
+    fn drop_duplicate_payout(&mut self, deposit_id: u64, txid: Option<String>, events: &mut Vec<Event>) {
+        if self.token_transfer.cancel_payout(deposit_id) {
+            self.record_correction(deposit_id, WithdrawalCorrection::DuplicatePayoutDropped, txid, events);
+        } else {
+            warn!(target: CONTRACT, "Queued payout of deposit {} could not be dropped", deposit_id);
+        }
+    }
+    
+    /// Emit the event of a correction
+    fn record_correction(&self, deposit_id: u64, correction: WithdrawalCorrection, transaction_hash: Option<String>, events: &mut Vec<Event>) {
+        info!(target: CONTRACT, "In-flight withdrawal of deposit {}: {:?}", deposit_id, correction);
+        
+        let event = Event::WithdrawalRecovered {
+            deposit_id,
+            correction,
+            transaction_hash,
+            timestamp: self.clock.now(),
+        };
+        self.emit(&event);
+        events.push(event);
+    }
+}
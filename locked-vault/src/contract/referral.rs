@@ -0,0 +1,174 @@
+//! Referral rewards paid from deposit and emergency fees
+//!
+//! A deposit may name a referrer. Whenever the deposit pays a fee, the
+//! referral share of the collector's part of that fee, after the insurance
+//! share, is credited to the referrer instead of the collected fees. The
+//! share is rounded down, so the collector never loses a unit to rounding.
+//! Referrers claim their balance per token; it is paid out through the
+//! transfer layer like any other contract payment.
+
+use log::info;
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer, TransferPriority};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::outbox::{OutboxOperation, TransferDirection, TransferRequest};
+use crate::logging::CONTRACT;
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Make a deposit on behalf of a referrer
+    ///
+    /// Like `deposit`, but `referrer_address` is credited the referral share
+    /// of every fee the deposit pays. A depositor cannot refer themselves.
+    pub fn deposit_with_referrer(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+        utxo_reference: Option<UtxoRef>,
+        referrer_address: String,
+    ) -> Result<Event, ContractError> {
+        self.check_referrer(&caller_address, &referrer_address)?;
+        
+        self.deposit_internal(caller_address, token_type, deposit_amount, lock_period_days, utxo_reference, None, Some(referrer_address), None)
+    }
+    
+    /// Check a referrer can be credited for a deposit of `caller_address`
+    pub(crate) fn check_referrer(&self, caller_address: &str, referrer_address: &str) -> Result<(), ContractError> {
+        if referrer_address == caller_address {
+            return Err(ContractError::SelfReferral);
+        }
+        
+        self.check_address(referrer_address)?;
+        
+        Ok(())
+    }
+    
+    /// Split the collector's part of a fee into what the collector keeps and the referrer's part
+    ///
+    /// The referrer's part is rounded down.
+    pub(crate) fn split_referral_fee(fee_amount: u64, referral_share_percentage: u8) -> Result<(u64, u64), ContractError> {
+        let referral_amount = match (fee_amount as u128)
+            .checked_mul(referral_share_percentage as u128)
+            .and_then(|product| product.checked_div(100)) {
+            Some(share) if share <= fee_amount as u128 => share as u64,
+            _ => return Err(ContractError::ArithmeticError),
+        };
+        
+        Ok((fee_amount - referral_amount, referral_amount))
+    }
+    
+    /// Compute a referrer's balance after crediting `amount`, without changing it
+    pub(crate) fn referral_balance_after(&self, referrer_address: &str, token_type: &TokenType, amount: u64) -> Result<u64, ContractError> {
+        self.referral_balance(referrer_address, token_type)
+            .checked_add(amount)
+            .ok_or(ContractError::ArithmeticError)
+    }
+    
+    /// Set the percentage of fees credited to the referrer of a deposit (owner only)
+    ///
+    /// Balances already credited are not moved.
+    pub fn set_referral_share(&mut self, caller_address: String, referral_share_percentage: u8, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if referral_share_percentage > 100 {
+            return Err(ContractError::InvalidFeePercentage);
+        }
+        
+        self.fee_config.referral_share_percentage = referral_share_percentage;
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Get the referral balance of an address in a token type
+    pub fn referral_balance(&self, referrer_address: &str, token_type: &TokenType) -> u64 {
+        self.referral_balances
+            .get(&(referrer_address.to_string(), token_type.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+    
+    /// Pay the caller's referral balance in a token type
+    ///
+    /// Fails with `InvalidAmount` if there is nothing to claim. The balance
+    /// is taken off when the payout is committed and restored if the
+    /// transfer fails.
+    pub fn claim_referral_rewards(&mut self, caller_address: String, token_type: TokenType) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        // Validate address
+        self.check_address(&caller_address)?;
+        
+        // Check the token can be paid out now
+        self.check_payout_available(&token_type)?;
+        
+        let key = (caller_address, token_type);
+        let amount = self.referral_balances.get(&key).copied().unwrap_or(0);
+        if amount == 0 {
+            return Err(ContractError::InvalidAmount { reason: "no referral rewards to claim".to_string() });
+        }
+        
+        // Take the balance off, committing the transfer it owes with the change
+        self.referral_balances.remove(&key);
+        let (referrer_address, token_type) = key;
+        let transfer_request = TransferRequest {
+            direction: TransferDirection::FromContract,
+            address: referrer_address.clone(),
+            token_type: token_type.clone(),
+            amount,
+            priority: TransferPriority::Low,
+            deposit_id: None,
+            batched: false,
+            options: None,
+        };
+        let entry_id = match self.commit_to_outbox(OutboxOperation::ReferralReward, transfer_request, &referrer_address) {
+            Ok(entry_id) => entry_id,
+            Err(e) => {
+                self.referral_balances.insert((referrer_address, token_type), amount);
+                return Err(e);
+            },
+        };
+        
+        // Referral rewards wait behind user withdrawals; the balance is restored on failure
+        if let Err(e) = self.dispatch_outbox_entry(entry_id) {
+            self.referral_balances.insert((referrer_address, token_type), amount);
+            self.abandon_outbox_entry(entry_id);
+            return Err(e);
+        }
+        
+        self.complete_outbox_entry(entry_id, None)
+    }
+    
+    /// Complete a referral reward claim once the backend accepted its transfer
+    pub(crate) fn finish_referral_reward(&mut self, referrer_address: String, token_type: TokenType, amount: u64) -> Event {
+        self.record_activity(&referrer_address);
+        
+        info!(target: CONTRACT, "Referral rewards of {} {:?} paid to {}", amount, token_type, referrer_address);
+        
+        let event = Event::ReferralRewardClaimed {
+            referrer_address,
+            token_type,
+            amount,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        
+        event
+    }
+}
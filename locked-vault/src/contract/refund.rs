@@ -0,0 +1,270 @@
+//! Refunds of funds that reached the contract without a deposit to back
+//!
+//! Funds can arrive and then find no deposit to record them: a deposit whose
+//! funding the outbox already transferred can fail its limits when it is
+//! recorded, the funding of an expired pending deposit can confirm late, and
+//! the owner can decline unattributed funds. Such funds are queued in the
+//! `pending_refunds` ledger and `process_refunds` pays them back to their
+//! originating address through the transfer backend.
+//!
+//! A refund is taken out of the ledger before its payout runs and put back
+//! only if the payout fails, so processing twice sends nothing twice. Each
+//! deposit and each output is refunded at most once. The `RefundPolicy` may
+//! deduct a flat network fee from every refund, at most the whole amount; a
+//! refund the deduction consumes entirely is settled without a payout.
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::utxo_registry::normalize_utxo_reference;
+use crate::logging::CONTRACT;
+
+/// How refunds are queued and paid
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefundPolicy {
+    /// Amount deducted from every refund to cover its network fee
+    #[serde(default)]
+    pub network_fee_deduction: u64,
+    /// Whether funding that confirms after its pending deposit expired is
+    /// refunded to the depositor instead of recorded as unattributed funds
+    #[serde(default)]
+    pub refund_late_funding: bool,
+}
+
+/// Why funds are refunded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RefundReason {
+    /// Funding transferred for a deposit that could not be recorded
+    DepositRejected {
+        /// ID reserved for the deposit
+        deposit_id: u64,
+    },
+    /// Funding that arrived after its pending deposit expired
+    LateFunding {
+        /// Expired deposit
+        deposit_id: u64,
+    },
+    /// Unattributed funds the owner declined
+    UnattributedFunds {
+        /// Output reference (`txid:vout`)
+        outpoint: String,
+    },
+}
+
+impl RefundReason {
+    /// Get the deposit whose funding is refunded, if any
+    pub fn deposit_id(&self) -> Option<u64> {
+        match self {
+            RefundReason::DepositRejected { deposit_id } | RefundReason::LateFunding { deposit_id } => Some(*deposit_id),
+            RefundReason::UnattributedFunds { .. } => None,
+        }
+    }
+}
+
+/// Refund queued and not yet sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRefund {
+    /// Refund ID, in queue order
+    pub refund_id: u64,
+    /// Address the funds came from and are returned to
+    pub destination_address: String,
+    /// Token refunded
+    pub token_type: TokenType,
+    /// Amount received
+    pub amount: u64,
+    /// Amount kept to cover the network fee, at most `amount`
+    pub fee_deduction: u64,
+    /// Why the funds are refunded
+    pub reason: RefundReason,
+    /// When the refund was queued
+    pub queued_at: DateTime<Utc>,
+}
+
+impl PendingRefund {
+    /// Get the amount paid back
+    pub fn refund_amount(&self) -> u64 {
+        self.amount - self.fee_deduction
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get how refunds are queued and paid
+    pub fn get_refund_policy(&self) -> &RefundPolicy {
+        &self.refund_policy
+    }
+    
+    /// Set how refunds are queued and paid (owner only)
+    ///
+    /// Refunds already queued keep the deduction they were queued with.
+    pub fn set_refund_policy(&mut self, caller_address: String, policy: RefundPolicy, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.refund_policy = policy;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// List the refunds not yet sent, oldest first
+    pub fn list_pending_refunds(&self) -> Vec<PendingRefund> {
+        self.pending_refunds.values().cloned().collect()
+    }
+    
+    /// Queue unattributed funds for a refund to a destination, typically their sender (owner only)
+    ///
+    /// Unlike `refund_unattributed`, nothing is sent until `process_refunds`
+    /// runs, and the refund policy's deduction applies.
+    pub fn queue_unattributed_refund(
+        &mut self,
+        caller_address: String,
+        outpoint: &str,
+        destination_address: String,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Validate address and the script it pays to
+        self.token_transfer.validate_destination(&destination_address)?;
+        
+        let outpoint = normalize_utxo_reference(outpoint);
+        let funds = self.unattributed_funds.get(&outpoint).ok_or(ContractError::UnattributedFundsNotFound)?;
+        let amount = funds.amount;
+        
+        let event = self.queue_refund(destination_address, TokenType::Bitcoin, amount, RefundReason::UnattributedFunds { outpoint: outpoint.clone() })?
+            .ok_or(ContractError::UnattributedFundsNotFound)?;
+        self.unattributed_funds.remove(&outpoint);
+        
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Send the queued refunds, oldest first, returning the emitted events
+    ///
+    /// Fails on the first payout that fails, leaving that refund and the
+    /// ones after it queued; refunds sent before it stand, and the routine
+    /// can simply be run again.
+    pub fn process_refunds(&mut self) -> Result<Vec<Event>, ContractError> {
+        self.ensure_writable()?;
+        
+        let mut events = Vec::new();
+        let refund_ids: Vec<u64> = self.pending_refunds.keys().copied().collect();
+        
+        for refund_id in refund_ids {
+            // Take the refund out first so it cannot be sent twice
+            let Some(refund) = self.pending_refunds.remove(&refund_id) else {
+                continue;
+            };
+            
+            let refund_amount = refund.refund_amount();
+            if refund_amount > 0 {
+                if let Err(e) = self.token_transfer.transfer_from_contract(&refund.destination_address, &refund.token_type, refund_amount) {
+                    self.pending_refunds.insert(refund_id, refund);
+                    return Err(ContractError::transfer_failed(TransferStage::Withdrawal, e));
+                }
+            }
+            
+            info!(target: CONTRACT, "Refund {} of {} sent to {}", refund_id, refund_amount, refund.destination_address);
+            
+            let event = Event::RefundSent {
+                refund_id,
+                destination_address: refund.destination_address,
+                token_type: refund.token_type,
+                refunded_amount: refund_amount,
+                fee_deduction: refund.fee_deduction,
+                timestamp: self.clock.now(),
+            };
+            
+            self.emit(&event);
+            events.push(event);
+            
+            if let Err(e) = self.persist_state() {
+                warn!(target: CONTRACT, "Refund {} sent but not persisted: {}", refund_id, e);
+            }
+        }
+        
+        Ok(events)
+    }
+    
+    /// Queue a refund of funds received, returning the emitted event
+    ///
+    /// Returns `None` if the deposit or output was already refunded.
+    pub(crate) fn queue_refund(
+        &mut self,
+        destination_address: String,
+        token_type: TokenType,
+        amount: u64,
+        reason: RefundReason,
+    ) -> Result<Option<Event>, ContractError> {
+        // Refund each deposit and output at most once
+        let first_refund = match &reason {
+            RefundReason::DepositRejected { deposit_id } | RefundReason::LateFunding { deposit_id } => {
+                !self.refunded_deposits.contains(deposit_id)
+            },
+            RefundReason::UnattributedFunds { outpoint } => !self.refunded_outpoints.contains(outpoint),
+        };
+        if !first_refund {
+            return Ok(None);
+        }
+        
+        let refund_id = self.next_refund_id;
+        self.next_refund_id = refund_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        
+        match &reason {
+            RefundReason::DepositRejected { deposit_id } | RefundReason::LateFunding { deposit_id } => {
+                self.refunded_deposits.insert(*deposit_id);
+            },
+            RefundReason::UnattributedFunds { outpoint } => {
+                self.refunded_outpoints.insert(outpoint.clone());
+            },
+        }
+        
+        let fee_deduction = self.refund_policy.network_fee_deduction.min(amount);
+        let current_timestamp = self.clock.now();
+        
+        info!(target: CONTRACT, "Refund {} of {} queued for {}", refund_id, amount, destination_address);
+        
+        self.pending_refunds.insert(refund_id, PendingRefund {
+            refund_id,
+            destination_address: destination_address.clone(),
+            token_type: token_type.clone(),
+            amount,
+            fee_deduction,
+            reason: reason.clone(),
+            queued_at: current_timestamp,
+        });
+        
+        let event = Event::RefundQueued {
+            refund_id,
+            destination_address,
+            token_type,
+            amount,
+            fee_deduction,
+            reason,
+            timestamp: current_timestamp,
+        };
+        
+        self.emit(&event);
+        
+        Ok(Some(event))
+    }
+}
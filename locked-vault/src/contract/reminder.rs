@@ -0,0 +1,146 @@
+//! Maturity reminders
+//!
+//! A depositor can ask to be reminded a number of days before a deposit
+//! unlocks. The daemon periodically collects the deposits whose reminder is
+//! due, delivers them (as `MaturityReminder` events to the webhook sink) and
+//! acknowledges them with `mark_notified`, so each deposit is announced once.
+//! The notified flag lives on the deposit and is exported with the state, so
+//! a restart does not repeat reminders already sent.
+
+use chrono::{DateTime, Duration, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{MaturityReminder, TokenTransfer, TokenType};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Deposit whose reminder is due
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaturityNotice {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Depositor address
+    pub depositor: String,
+    /// When the deposit unlocks
+    pub unlock_at: DateTime<Utc>,
+    /// Token type
+    pub token: TokenType,
+    /// Deposited amount
+    pub amount: u64,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Ask to be reminded `remind_days_before` days before a deposit unlocks (depositor only)
+    ///
+    /// Replaces any previous preference and re-arms the reminder, so a new
+    /// window is announced even if the old one already was.
+    pub fn set_notification_preference(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        remind_days_before: u32,
+    ) -> Result<(), ContractError> {
+        self.ensure_writable()?;
+        
+        // Validate address
+        self.check_address(&caller_address)?;
+        
+        // Get deposit
+        let current_timestamp = self.clock.now();
+        let deposit = self.deposit_registry.get_mut(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        // Check ownership
+        if deposit.depositor_address != caller_address {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Check if already withdrawn
+        if deposit.is_withdrawn() {
+            return Err(ContractError::DepositAlreadyWithdrawn);
+        }
+        
+        deposit.reminder = Some(MaturityReminder {
+            remind_days_before,
+            notified: false,
+        });
+        deposit.last_modified = current_timestamp;
+        
+        self.record_activity(&caller_address);
+        
+        info!(target: CONTRACT, "Deposit {} reminder set {} days before unlock", deposit_id, remind_days_before);
+        
+        Ok(())
+    }
+    
+    /// Deposits whose reminder window has opened at `now` and that were not announced yet
+    ///
+    /// A deposit that unlocked before its reminder could be sent is still
+    /// returned, once. Notices are ordered by unlock time, then deposit ID.
+    pub fn collect_due_notifications(&self, now: DateTime<Utc>) -> Vec<MaturityNotice> {
+        let mut notices: Vec<MaturityNotice> = self.deposit_registry.values()
+            .filter(|deposit| !deposit.is_closed())
+            .filter(|deposit| {
+                deposit.reminder.is_some_and(|reminder| {
+                    !reminder.notified
+                        && deposit.unlock_timestamp
+                            .checked_sub_signed(Duration::days(reminder.remind_days_before as i64))
+                            .is_none_or(|remind_at| now >= remind_at)
+                })
+            })
+            .map(|deposit| MaturityNotice {
+                deposit_id: deposit.deposit_id,
+                depositor: deposit.depositor_address.clone(),
+                unlock_at: deposit.unlock_timestamp,
+                token: deposit.deposited_token_type.clone(),
+                amount: deposit.deposited_amount,
+            })
+            .collect();
+        
+        notices.sort_by_key(|notice| (notice.unlock_at, notice.deposit_id));
+        notices
+    }
+    
+    /// Publish a `MaturityReminder` event for each notice to the registered sinks
+    pub fn announce_maturity(&self, notices: &[MaturityNotice]) {
+        let current_timestamp = self.clock.now();
+        
+        for notice in notices {
+            self.emit(&Event::MaturityReminder {
+                deposit_id: notice.deposit_id,
+                depositor_address: notice.depositor.clone(),
+                token_type: notice.token.clone(),
+                amount: notice.amount,
+                unlock_timestamp: notice.unlock_at,
+                timestamp: current_timestamp,
+            });
+        }
+    }
+    
+    /// Record that the reminders of these deposits were delivered, returning how many were marked
+    ///
+    /// Deposits without a pending reminder are skipped.
+    pub fn mark_notified(&mut self, deposit_ids: &[u64]) -> usize {
+        if self.ensure_writable().is_err() {
+            return 0;
+        }
+        
+        let mut marked = 0;
+        
+        for deposit_id in deposit_ids {
+            let reminder = self.deposit_registry.get_mut(deposit_id)
+                .and_then(|deposit| deposit.reminder.as_mut())
+                .filter(|reminder| !reminder.notified);
+            
+            if let Some(reminder) = reminder {
+                reminder.notified = true;
+                marked += 1;
+            }
+        }
+        
+        marked
+    }
+}
@@ -0,0 +1,191 @@
+//! Deposit slot reservations
+//!
+//! Lets a caller hold deposit limit capacity across the slow
+//! `transfer_to_contract` call without keeping the contract locked:
+//! reserve a slot, move the funds through the transfer backend, then commit
+//! the reservation (or roll it back). Reserved amounts count against the
+//! limits until they are committed, rolled back or expire.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositLimits, TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Deposit capacity held for a pending transfer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositReservation {
+    /// Reservation ID
+    pub reservation_id: u64,
+    /// Address of the depositor
+    pub depositor_address: String,
+    /// Token type
+    pub token_type: TokenType,
+    /// Reserved amount
+    pub amount: u64,
+    /// Lock period of the deposit in days
+    pub lock_period_days: u32,
+    /// When the reservation releases its capacity if not committed
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Counters relevant to the deposit limits, including live reservations
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DepositLimitSnapshot {
+    /// Deposits made or reserved by the user
+    pub(crate) user_deposit_count: usize,
+    /// Active deposits plus reserved amounts of the token
+    pub(crate) token_total: u64,
+}
+
+impl DepositLimitSnapshot {
+    /// Check that one more deposit of `amount` fits the limits
+    pub(crate) fn check(&self, limits: &DepositLimits, amount: u64) -> Result<(), ContractError> {
+        // Check user deposit limit
+        if let Some(max_deposits) = limits.max_deposits_per_user {
+            if self.user_deposit_count >= max_deposits as usize {
+                return Err(ContractError::UserDepositLimitReached { limit: max_deposits, current: self.user_deposit_count });
+            }
+        }
+        
+        // Check total deposit limit with checked arithmetic, even without a limit
+        let new_total = self.token_total.checked_add(amount).ok_or(ContractError::ArithmeticError)?;
+        if let Some(max_total) = limits.max_total_deposits {
+            if new_total > max_total {
+                return Err(ContractError::TotalDepositLimitReached { limit: max_total, current: self.token_total, attempted: amount });
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Snapshot the limit counters for a user and token, counting unexpired reservations
+    pub(crate) fn deposit_limit_snapshot(&self, caller_address: &str, token_type: &TokenType) -> DepositLimitSnapshot {
+        let now = self.clock.now();
+        let live_reservations = self.deposit_reservations.values()
+            .filter(|reservation| reservation.expires_at > now);
+        
+        let mut snapshot = DepositLimitSnapshot {
+            user_deposit_count: self.user_index.lifetime_count(caller_address),
+            token_total: self.total_deposits.get(token_type).copied().unwrap_or(0),
+        };
+        
+        for reservation in live_reservations {
+            if reservation.depositor_address == caller_address {
+                snapshot.user_deposit_count += 1;
+            }
+            
+            if &reservation.token_type == token_type {
+                snapshot.token_total = snapshot.token_total.saturating_add(reservation.amount);
+            }
+        }
+        
+        snapshot
+    }
+    
+    /// Reserve deposit capacity ahead of a transfer, returning the reservation ID
+    pub fn try_reserve_deposit_slot(
+        &mut self,
+        caller_address: String,
+        token_type: TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+    ) -> Result<u64, ContractError> {
+        self.ensure_writable()?;
+        
+        self.release_expired_reservations();
+        
+        self.validate_deposit_request(&caller_address, &token_type, deposit_amount, lock_period_days)?;
+        self.deposit_limit_snapshot(&caller_address, &token_type)
+            .check(&self.deposit_limits, deposit_amount)?;
+        
+        let ttl = Duration::from_std(self.reservation_ttl).map_err(|_| ContractError::ArithmeticError)?;
+        
+        let reservation_id = self.next_reservation_id;
+        self.next_reservation_id = self.next_reservation_id.checked_add(1).ok_or(ContractError::ArithmeticError)?;
+        
+        self.deposit_reservations.insert(reservation_id, DepositReservation {
+            reservation_id,
+            depositor_address: caller_address,
+            token_type,
+            amount: deposit_amount,
+            lock_period_days,
+            expires_at: self.clock.now() + ttl,
+        });
+        
+        Ok(reservation_id)
+    }
+    
+    /// Record the deposit for a reservation once its funds have been transferred
+    pub fn commit_deposit_reservation(&mut self, reservation_id: u64, utxo_reference: Option<UtxoRef>) -> Result<Event, ContractError> {
+        self.ensure_writable()?;
+        
+        self.release_expired_reservations();
+        
+        let reservation = self.deposit_reservations.remove(&reservation_id)
+            .ok_or(ContractError::ReservationNotFound)?;
+        
+        // The depositor's cached balance is stale now
+        self.token_transfer.invalidate_balance(&reservation.depositor_address, &reservation.token_type);
+        
+        let result = self.commit_deposit(
+            reservation.depositor_address.clone(),
+            reservation.token_type.clone(),
+            reservation.amount,
+            reservation.lock_period_days,
+            utxo_reference,
+            None,
+            None,
+            None,
+            None,
+        );
+        
+        // Keep the reservation if the limits were tightened meanwhile, so the caller can roll back
+        if result.is_err() {
+            self.deposit_reservations.insert(reservation_id, reservation);
+        }
+        
+        result
+    }
+    
+    /// Release a reservation whose transfer did not happen
+    pub fn rollback_deposit_reservation(&mut self, reservation_id: u64) -> Result<(), ContractError> {
+        self.release_expired_reservations();
+        
+        self.deposit_reservations.remove(&reservation_id)
+            .map(|_| ())
+            .ok_or(ContractError::ReservationNotFound)
+    }
+    
+    /// Set how long reservations hold their capacity (owner only)
+    pub fn set_reservation_ttl(&mut self, caller_address: String, ttl: std::time::Duration, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if ttl.is_zero() {
+            return Err(ContractError::InvalidAmount { reason: "reservation TTL cannot be zero".to_string() });
+        }
+        
+        self.reservation_ttl = ttl;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Drop reservations that were never committed in time
+    fn release_expired_reservations(&mut self) {
+        let now = self.clock.now();
+        self.deposit_reservations.retain(|_, reservation| reservation.expires_at > now);
+    }
+}
@@ -0,0 +1,121 @@
+//! Unlock schedules
+//!
+//! Groups a user's active deposits by the UTC calendar period in which they
+//! unlock, so laddered deposits can be shown as amounts per month, week or
+//! day. Schedules are built from the per-user unlock index rather than by
+//! scanning the deposit registry.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Length of the periods an unlock schedule is grouped by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ScheduleGranularity {
+    /// Calendar months, starting on the 1st
+    #[default]
+    Monthly,
+    /// ISO weeks, starting on Monday
+    Weekly,
+    /// Calendar days
+    Daily,
+}
+
+impl ScheduleGranularity {
+    /// Get the start (00:00 UTC) of the period containing a timestamp
+    pub fn period_start(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let date = timestamp.date_naive();
+        
+        let start = match self {
+            ScheduleGranularity::Monthly => NaiveDate::from_ymd_opt(date.year(), date.month(), 1),
+            ScheduleGranularity::Weekly => date.checked_sub_days(Days::new(date.weekday().num_days_from_monday() as u64)),
+            ScheduleGranularity::Daily => Some(date),
+        };
+        
+        // The first day of a month or week of a valid date is always a valid date
+        start.unwrap_or(date)
+            .and_time(NaiveTime::MIN)
+            .and_utc()
+    }
+}
+
+impl fmt::Display for ScheduleGranularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleGranularity::Monthly => write!(f, "monthly"),
+            ScheduleGranularity::Weekly => write!(f, "weekly"),
+            ScheduleGranularity::Daily => write!(f, "daily"),
+        }
+    }
+}
+
+impl FromStr for ScheduleGranularity {
+    type Err = String;
+    
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "monthly" | "month" => Ok(ScheduleGranularity::Monthly),
+            "weekly" | "week" => Ok(ScheduleGranularity::Weekly),
+            "daily" | "day" => Ok(ScheduleGranularity::Daily),
+            other => Err(format!("Unknown granularity: {} (expected monthly, weekly or daily)", other)),
+        }
+    }
+}
+
+/// Amount of one token unlocking within one period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduleBucket {
+    /// Start of the period (00:00 UTC)
+    pub period_start: DateTime<Utc>,
+    /// Token type
+    pub token_type: TokenType,
+    /// Total amount unlocking in the period
+    pub amount: u64,
+    /// Deposits unlocking in the period, in unlock order
+    pub deposit_ids: Vec<u64>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the amounts of a user's active deposits unlocking per period and token
+    ///
+    /// Buckets are ordered by period, then by token type. Suspended deposits
+    /// are included since they still unlock on schedule once reinstated.
+    pub fn get_unlock_schedule(&self, address: &str, granularity: ScheduleGranularity) -> Vec<ScheduleBucket> {
+        let mut buckets: Vec<ScheduleBucket> = Vec::new();
+        
+        // The index yields deposits in unlock order, so each period's buckets are contiguous
+        for (unlock_timestamp, deposit_id) in self.user_index.unlocks(address) {
+            let Some(deposit) = self.deposit_registry.get(deposit_id) else {
+                continue;
+            };
+            
+            let period_start = granularity.period_start(*unlock_timestamp);
+            let existing = buckets.iter_mut()
+                .rev()
+                .take_while(|bucket| bucket.period_start == period_start)
+                .find(|bucket| bucket.token_type == deposit.deposited_token_type);
+            
+            match existing {
+                Some(bucket) => {
+                    bucket.amount = bucket.amount.saturating_add(deposit.deposited_amount);
+                    bucket.deposit_ids.push(*deposit_id);
+                },
+                None => buckets.push(ScheduleBucket {
+                    period_start,
+                    token_type: deposit.deposited_token_type.clone(),
+                    amount: deposit.deposited_amount,
+                    deposit_ids: vec![*deposit_id],
+                }),
+            }
+        }
+        
+        // Order tokens within each period
+        buckets.sort_by_cached_key(|bucket| (bucket.period_start, format!("{:?}", bucket.token_type)));
+        buckets
+    }
+}
@@ -0,0 +1,121 @@
+//! Dry runs of deposits and withdrawals
+//!
+//! `simulate_deposit` runs the checks of `deposit` without moving funds or
+//! recording anything, and reports what the deposit would lock and whether
+//! the backend's transfer queue has room for it. A saturated queue is not
+//! an error here: clients use it to back off before `deposit` would fail
+//! with `BackendBusy`. `simulate_withdrawal` does the same for
+//! `withdraw_with_fee_limit`.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::fee_oracle::ConfirmationTarget;
+use crate::errors::ContractError;
+use crate::models::{TokenTransfer, TokenType, TransferQueueStatus};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Outcome of a deposit dry run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositSimulation {
+    /// Deposit fee that would be taken
+    pub fee_amount: u64,
+    /// Amount that would be locked
+    pub locked_amount: u64,
+    /// When the deposit would unlock if made now
+    pub unlock_timestamp: DateTime<Utc>,
+    /// Occupancy of the backend's transfer queue, for backends with one
+    pub transfer_queue: Option<TransferQueueStatus>,
+    /// Whether the backend would refuse the transfer until its queue drains
+    pub queue_saturated: bool,
+}
+
+/// Outcome of a withdrawal dry run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawalSimulation {
+    /// Token type that would be paid out
+    pub token_type: TokenType,
+    /// Amount that would be paid out
+    pub amount: u64,
+    /// Mining fee the payout would pay now, for backends that estimate one
+    pub estimated_fee: Option<u64>,
+    /// ID of the fee oracle snapshot the estimate was priced against, for backends with one
+    pub fee_snapshot_id: Option<u64>,
+    /// Confirmation target the estimate was priced for, for backends with one
+    pub fee_target: Option<ConfirmationTarget>,
+    /// Blocks the confirmation target resolved to
+    pub fee_target_blocks: Option<u16>,
+    /// Occupancy of the backend's transfer queue, for backends with one
+    pub transfer_queue: Option<TransferQueueStatus>,
+    /// Whether the backend would refuse the transfer until its queue drains
+    pub queue_saturated: bool,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Check a deposit without making it
+    ///
+    /// Fails with the error `deposit` would return for an invalid request or
+    /// an exceeded limit.
+    pub fn simulate_deposit(
+        &self,
+        caller_address: &str,
+        token_type: &TokenType,
+        deposit_amount: u64,
+        lock_period_days: u32,
+    ) -> Result<DepositSimulation, ContractError> {
+        // Same checks as a real deposit
+        self.validate_deposit_request(caller_address, token_type, deposit_amount, lock_period_days)?;
+        let (fee_amount, locked_amount) = Self::compute_deposit_fee(&self.fee_config, token_type, deposit_amount)?;
+        self.deposit_limit_snapshot(caller_address, token_type)
+            .check(&self.deposit_limits, locked_amount)?;
+        
+        let transfer_queue = self.token_transfer.transfer_queue_status();
+        
+        Ok(DepositSimulation {
+            fee_amount,
+            locked_amount,
+            unlock_timestamp: self.clock.now() + Duration::days(lock_period_days as i64),
+            transfer_queue,
+            queue_saturated: transfer_queue.is_some_and(|queue| queue.is_saturated()),
+        })
+    }
+    
+    /// Check a withdrawal without making it
+    ///
+    /// Fails with the error `withdraw_with_fee_limit` would return for a
+    /// deposit the caller cannot withdraw yet or is too small to pay out, or
+    /// with `FeeExceedsLimit` if the estimated mining fee is above
+    /// `max_onchain_fee`. Backends that
+    /// cannot estimate fees leave the limit to the withdrawal itself.
+    pub fn simulate_withdrawal(
+        &self,
+        caller_address: &str,
+        deposit_id: u64,
+        max_onchain_fee: Option<u64>,
+    ) -> Result<WithdrawalSimulation, ContractError> {
+        // Same checks as a real withdrawal
+        let deposit = self.check_withdrawable(caller_address, deposit_id)?;
+        self.check_minimum_withdrawal(deposit_id)?;
+        
+        let quote = self.quote_withdrawal_fee(deposit_id).ok();
+        let estimated_fee = quote.map(|quote| quote.fee);
+        if let (Some(required), Some(limit)) = (estimated_fee, max_onchain_fee) {
+            if required > limit {
+                return Err(ContractError::FeeExceedsLimit { required, limit });
+            }
+        }
+        
+        let transfer_queue = self.token_transfer.transfer_queue_status();
+        
+        Ok(WithdrawalSimulation {
+            token_type: deposit.deposited_token_type.clone(),
+            amount: deposit.deposited_amount,
+            estimated_fee,
+            fee_snapshot_id: quote.and_then(|quote| quote.fee_snapshot_id),
+            fee_target: quote.and_then(|quote| quote.target),
+            fee_target_blocks: quote.and_then(|quote| quote.target_blocks),
+            transfer_queue,
+            queue_saturated: transfer_queue.is_some_and(|queue| queue.is_saturated()),
+        })
+    }
+}
@@ -0,0 +1,630 @@
+//! Serializable snapshots of contract state and statistics
+//!
+//! Maps keyed by `TokenType` are exported as sorted lists of pairs because
+//! token types with identifiers cannot be used as JSON object keys.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::models::{Deposit, DepositFee, DepositStatus, DepositLimits, EmergencyPolicy, LockPolicy, TokenType, TokenTransfer};
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::utxo::UtxoRef;
+use crate::contract::migration::{StateVersion, CURRENT_STATE_VERSION};
+use crate::contract::deposit_id::IdScheme;
+use crate::contract::idempotency::IdempotencyRecord;
+use crate::contract::governance::Governance;
+use crate::contract::reservation::DepositReservation;
+use crate::contract::quote::{default_quote_validity, EmergencyQuote};
+use crate::contract::pending::default_pending_deposit_timeout;
+use crate::contract::unattributed::UnattributedFunds;
+use crate::contract::void::VoidedDeposit;
+use crate::contract::treasury::TreasuryPolicy;
+use crate::contract::circuit_breaker::{CircuitBreakerConfig, PauseReason, RecentWithdrawal};
+use crate::contract::fee_destination::{default_fee_destination_delay, FeeDestinationDelayFloor, PendingFeeDestination};
+use crate::contract::fee_ledger::FeeLedger;
+use crate::contract::authorization::AuthorizationLog;
+use crate::contract::token_support::DeprecationReason;
+use crate::contract::token_pause::TokenPausePolicy;
+use crate::contract::outbox::OutboxEntry;
+use crate::contract::refund::{PendingRefund, RefundPolicy};
+use crate::contract::user_index::UserIndex;
+use crate::contract::query::DepositFilter;
+use crate::contract::utxo_registry::UtxoRegistry;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::tvl::{MetricsRecorder, MetricsRecorderConfig, TvlSample, TvlSeries};
+
+/// Snapshot of the contract state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractState {
+    /// Schema version of the snapshot
+    pub state_version: StateVersion,
+    /// Contract version
+    pub version: String,
+    /// Contract owner address
+    pub owner: String,
+    /// Pending owner during ownership transfer
+    pub pending_owner: Option<String>,
+    /// Next deposit ID to assign, or the deposit sequence number under `IdScheme::Derived`
+    pub next_deposit_id: u64,
+    /// How deposit IDs are assigned
+    #[serde(default)]
+    pub id_scheme: IdScheme,
+    /// Hex-encoded salt of derived deposit IDs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deposit_id_salt: Option<String>,
+    /// All deposits, sorted by ID
+    pub deposits: Vec<Deposit>,
+    /// Whether the contract is paused
+    pub paused: bool,
+    /// Percentage fee for emergency withdrawals
+    pub emergency_withdrawal_fee_percentage: u8,
+    /// Address where fees are collected
+    pub fee_collector_address: String,
+    /// Accumulated fees per token type
+    pub collected_fees: Vec<(TokenType, u64)>,
+    /// Fees charged on new deposits, sorted by token
+    #[serde(default)]
+    pub deposit_fees: Vec<(TokenType, DepositFee)>,
+    /// Percentage of every emergency fee paid into the insurance pool
+    #[serde(default)]
+    pub insurance_share_percentage: u8,
+    /// Insurance pool balance per token type, sorted by token
+    #[serde(default)]
+    pub insurance_pool: Vec<(TokenType, u64)>,
+    /// Percentage of a referred deposit's fees credited to its referrer
+    #[serde(default)]
+    pub referral_share_percentage: u8,
+    /// Unclaimed referral rewards per referrer and token type, sorted
+    #[serde(default)]
+    pub referral_balances: Vec<(String, TokenType, u64)>,
+    /// Maximum deposit amount per token type
+    pub max_deposit_amounts: Vec<(TokenType, u64)>,
+    /// Maximum number of deposits per user
+    pub max_deposits_per_user: Option<u32>,
+    /// Maximum total deposits across all users
+    pub max_total_deposits: Option<u64>,
+    /// Supported token types
+    pub supported_tokens: Vec<TokenType>,
+    /// Supported tokens closed to new deposits, sorted by token
+    #[serde(default)]
+    pub deprecated_tokens: Vec<(TokenType, DeprecationReason)>,
+    /// Supported tokens paused on their own, sorted
+    #[serde(default)]
+    pub paused_tokens: Vec<TokenType>,
+    /// How paused tokens are treated
+    #[serde(default)]
+    pub token_pause_policy: TokenPausePolicy,
+    /// Total active deposits per token type
+    pub total_deposits: Vec<(TokenType, u64)>,
+    /// Restrictions on emergency withdrawals
+    pub emergency_policy: EmergencyPolicy,
+    /// Contract-wide maximum lock period in days
+    pub max_lock_days: u32,
+    /// Per-token maximum lock periods, sorted by token
+    pub per_token_max_lock_days: Vec<(TokenType, u32)>,
+    /// Emergency withdrawal timestamps per user within the current window, sorted by user
+    pub emergency_withdrawals: Vec<(String, Vec<DateTime<Utc>>)>,
+    /// How long idempotency keys are remembered
+    pub idempotency_retention: std::time::Duration,
+    /// Recorded idempotent calls, sorted by key
+    pub idempotency_records: Vec<(String, IdempotencyRecord)>,
+    /// Owner set and proposals when governance mode is enabled
+    pub governance: Option<Governance>,
+    /// How long reservations hold their capacity
+    pub reservation_ttl: std::time::Duration,
+    /// Outstanding deposit reservations, sorted by ID
+    pub deposit_reservations: Vec<DepositReservation>,
+    /// Consumed UTXO references and the deposits that consumed them, sorted by reference
+    #[serde(default)]
+    pub consumed_utxo_references: Vec<(UtxoRef, u64)>,
+    /// Last authenticated interaction per user, sorted by user
+    #[serde(default)]
+    pub last_activity: Vec<(String, DateTime<Utc>)>,
+    /// How long emergency withdrawal quotes are honored
+    #[serde(default = "default_quote_validity")]
+    pub quote_validity: std::time::Duration,
+    /// Outstanding emergency withdrawal quotes, sorted by ID
+    #[serde(default)]
+    pub emergency_quotes: Vec<EmergencyQuote>,
+    /// Funds awaiting attribution or refund, sorted by output reference
+    #[serde(default)]
+    pub unattributed_funds: Vec<UnattributedFunds>,
+    /// Output references of refunded unattributed funds, sorted
+    #[serde(default)]
+    pub refunded_outpoints: Vec<String>,
+    /// Pending deposits the owner voided, sorted by deposit ID
+    #[serde(default)]
+    pub voided_deposits: Vec<VoidedDeposit>,
+    /// Nonce the next owner call has to present
+    #[serde(default)]
+    pub owner_nonce: u64,
+    /// Confirmations funding transactions need
+    #[serde(default)]
+    pub confirmation_policy: ConfirmationPolicy,
+    /// How long on-chain pending deposits wait for their confirmation
+    #[serde(default = "default_pending_deposit_timeout")]
+    pub pending_deposit_timeout: std::time::Duration,
+    /// Vault public key (hex) of new script escrow deposits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escrow_public_key: Option<String>,
+    /// Hot wallet sweep policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub treasury_policy: Option<TreasuryPolicy>,
+    /// Satoshis swept from the contract wallet to cold storage
+    #[serde(default)]
+    pub cold_storage_balance: u64,
+    /// Public key (hex) import manifests are checked against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub migration_public_key: Option<String>,
+    /// Content hashes (hex) of applied import manifests, sorted
+    #[serde(default)]
+    pub imported_manifests: Vec<String>,
+    /// When the circuit breaker pauses the contract
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    /// Withdrawals within the circuit breaker window, oldest first
+    #[serde(default)]
+    pub recent_withdrawals: Vec<RecentWithdrawal>,
+    /// Why the circuit breaker paused the contract, until reset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker_trip: Option<PauseReason>,
+    /// Activated fee withdrawal destinations, sorted; `None` in snapshots that predate them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_destination_whitelist: Option<Vec<String>>,
+    /// Proposed fee withdrawal destinations, sorted by address
+    #[serde(default)]
+    pub pending_fee_destinations: Vec<PendingFeeDestination>,
+    /// Delay between proposing and activating a fee destination
+    #[serde(default = "default_fee_destination_delay")]
+    pub fee_destination_delay: std::time::Duration,
+    /// Longer delay still in force after the delay was shortened
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_destination_delay_floor: Option<FeeDestinationDelayFloor>,
+    /// Fee accruals and sweeps; `None` in snapshots that predate them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_ledger: Option<FeeLedger>,
+    /// Authorized withdrawals and their contexts; empty in snapshots that predate them
+    #[serde(default)]
+    pub authorization_log: AuthorizationLog,
+    /// Recorded TVL series, if recording is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tvl_series: Option<TvlSeries>,
+    /// Transfers committed with their state change and not yet marked done, oldest first
+    #[serde(default)]
+    pub outbox: Vec<OutboxEntry>,
+    /// Next outbox entry ID to assign; 0 in snapshots that predate the outbox
+    #[serde(default)]
+    pub next_outbox_entry_id: u64,
+    /// Refunds not yet sent, oldest first
+    #[serde(default)]
+    pub pending_refunds: Vec<PendingRefund>,
+    /// Next refund ID to assign; 0 in snapshots that predate refunds
+    #[serde(default)]
+    pub next_refund_id: u64,
+    /// Deposits whose funding was queued for a refund, sorted
+    #[serde(default)]
+    pub refunded_deposits: Vec<u64>,
+    /// How refunds are queued and paid
+    #[serde(default)]
+    pub refund_policy: RefundPolicy,
+}
+
+/// Summary statistics of the contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractStats {
+    /// Contract version
+    pub version: String,
+    /// Contract owner address
+    pub owner: String,
+    /// Whether the contract is paused
+    pub paused: bool,
+    /// Number of deposits ever made
+    pub deposit_count: usize,
+    /// Number of deposits not yet withdrawn
+    pub active_deposit_count: usize,
+    /// Total active deposits per token type
+    pub total_deposits: Vec<(TokenType, u64)>,
+    /// Accumulated fees per token type
+    pub collected_fees: Vec<(TokenType, u64)>,
+    /// Insurance pool balance per token type
+    #[serde(default)]
+    pub insurance_pool: Vec<(TokenType, u64)>,
+    /// Unclaimed referral rewards per referrer and token type, sorted
+    #[serde(default)]
+    pub referral_balances: Vec<(String, TokenType, u64)>,
+    /// Token types new deposits are accepted for
+    pub supported_tokens: Vec<TokenType>,
+    /// Supported tokens closed to new deposits until their backend recovers, sorted by token
+    #[serde(default)]
+    pub deprecated_tokens: Vec<(TokenType, DeprecationReason)>,
+    /// Supported tokens paused on their own, sorted; the contract-wide pause supersedes them
+    #[serde(default)]
+    pub paused_tokens: Vec<TokenType>,
+    /// Hex-encoded public key that signs deposit receipts
+    pub vault_public_key: Option<String>,
+    /// Contract-wide maximum lock period in days
+    pub max_lock_days: u32,
+    /// Per-token maximum lock periods, sorted by token
+    pub per_token_max_lock_days: Vec<(TokenType, u32)>,
+}
+
+/// Sort token-keyed pairs for a stable export
+pub(crate) fn sorted_by_token<'a, V: Copy + 'a>(entries: impl Iterator<Item = (&'a TokenType, &'a V)>) -> Vec<(TokenType, V)> {
+    let mut entries: Vec<(TokenType, V)> = entries
+        .map(|(token_type, value)| (token_type.clone(), *value))
+        .collect();
+    entries.sort_by_key(|(token_type, _)| format!("{:?}", token_type));
+    entries
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// List unclaimed referral rewards for a stable export
+    fn sorted_referral_balances(&self) -> Vec<(String, TokenType, u64)> {
+        let mut balances: Vec<(String, TokenType, u64)> = self.referral_balances.iter()
+            .map(|((referrer_address, token_type), amount)| (referrer_address.clone(), token_type.clone(), *amount))
+            .collect();
+        balances.sort_by_key(|(referrer_address, token_type, _)| (referrer_address.clone(), format!("{:?}", token_type)));
+        balances
+    }
+    
+    /// Get summary statistics, including the receipt verification key
+    pub fn get_stats(&self) -> ContractStats {
+        ContractStats {
+            version: self.version.clone(),
+            owner: self.contract_owner_address.clone(),
+            paused: self.is_contract_paused,
+            deposit_count: self.deposit_registry.len(),
+            active_deposit_count: self.deposits_filtered(DepositFilter::active()).count(),
+            total_deposits: sorted_by_token(self.total_deposits.iter()),
+            collected_fees: sorted_by_token(self.fee_config.collected_fees.iter()),
+            insurance_pool: sorted_by_token(self.fee_config.insurance_pool.iter()),
+            referral_balances: self.sorted_referral_balances(),
+            supported_tokens: self.active_tokens(),
+            deprecated_tokens: sorted_by_token(self.deprecated_tokens.iter()),
+            paused_tokens: self.paused_tokens(),
+            vault_public_key: self.receipt_signer.as_ref().map(|signer| hex::encode(signer.public_key())),
+            max_lock_days: self.lock_policy.max_lock_days,
+            per_token_max_lock_days: sorted_by_token(self.lock_policy.per_token_max.iter()),
+        }
+    }
+    
+    /// Start recording the TVL series, returning the recorder
+    ///
+    /// Deposits funded so far are counted from the first sample on. A
+    /// recorder already running, e.g. one restored from a snapshot, is kept
+    /// with the tiers it was recording at.
+    pub fn enable_tvl_metrics(&mut self, config: MetricsRecorderConfig) -> Result<Arc<MetricsRecorder>, ContractError> {
+        if let Some(recorder) = &self.tvl_recorder {
+            return Ok(recorder.clone());
+        }
+        
+        Ok(self.attach_tvl_recorder(MetricsRecorder::new(config)?))
+    }
+    
+    /// Get the recorder of the TVL series, if recording is enabled
+    pub fn get_tvl_recorder(&self) -> Option<Arc<MetricsRecorder>> {
+        self.tvl_recorder.clone()
+    }
+    
+    /// Get a token's TVL samples from `from` (inclusive) to `to` (exclusive) at a resolution in seconds
+    pub fn get_tvl_series(
+        &self,
+        token_type: &TokenType,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        resolution_secs: u64,
+    ) -> Result<Vec<TvlSample>, ContractError> {
+        self.tvl_recorder.as_ref()
+            .ok_or(ContractError::TvlMetricsNotEnabled)?
+            .get_tvl_series(token_type, from, to, resolution_secs)
+    }
+    
+    /// Seed a recorder with the funded deposits and register it as an event sink
+    fn attach_tvl_recorder(&mut self, recorder: MetricsRecorder) -> Arc<MetricsRecorder> {
+        recorder.seed(self.deposit_registry.values());
+        
+        let recorder = Arc::new(recorder);
+        self.add_event_sink(recorder.clone());
+        self.tvl_recorder = Some(recorder.clone());
+        recorder
+    }
+    
+    /// Get the canonical hash of the state every node of a deployment shares
+    /// 
+    /// Two nodes holding the same deposits and balances get the same hash.
+    pub fn state_hash(&self) -> [u8; 32] {
+        self.export_state().canonical_hash()
+    }
+    
+    /// Export a serializable snapshot of the contract state
+    pub fn export_state(&self) -> ContractState {
+        let mut deposits: Vec<&Deposit> = self.deposits_iter().collect();
+        deposits.sort_by_key(|deposit| deposit.deposit_id);
+        let deposits: Vec<Deposit> = deposits.into_iter().cloned().collect();
+        
+        let mut emergency_withdrawals: Vec<(String, Vec<DateTime<Utc>>)> = self.emergency_withdrawals
+            .iter()
+            .filter(|(_, timestamps)| !timestamps.is_empty())
+            .map(|(user, timestamps)| (user.clone(), timestamps.clone()))
+            .collect();
+        emergency_withdrawals.sort_by(|a, b| a.0.cmp(&b.0));
+        
+        let mut idempotency_records: Vec<(String, IdempotencyRecord)> = self.idempotency_records
+            .iter()
+            .map(|(key, record)| (key.clone(), record.clone()))
+            .collect();
+        idempotency_records.sort_by(|a, b| a.0.cmp(&b.0));
+        
+        let mut deposit_reservations: Vec<DepositReservation> = self.deposit_reservations.values().cloned().collect();
+        deposit_reservations.sort_by_key(|reservation| reservation.reservation_id);
+        
+        let mut consumed_utxo_references: Vec<(UtxoRef, u64)> = self.utxo_registry.references
+            .iter()
+            .map(|(reference, deposit_id)| (reference.clone(), *deposit_id))
+            .collect();
+        consumed_utxo_references.sort();
+        
+        let mut last_activity: Vec<(String, DateTime<Utc>)> = self.last_activity
+            .iter()
+            .map(|(user, timestamp)| (user.clone(), *timestamp))
+            .collect();
+        last_activity.sort();
+        
+        let mut emergency_quotes: Vec<EmergencyQuote> = self.emergency_quotes.values().cloned().collect();
+        emergency_quotes.sort_by_key(|quote| quote.quote_id);
+        
+        let mut unattributed_funds: Vec<UnattributedFunds> = self.unattributed_funds.values().cloned().collect();
+        unattributed_funds.sort_by(|a, b| a.outpoint.cmp(&b.outpoint));
+        
+        let mut refunded_outpoints: Vec<String> = self.refunded_outpoints.iter().cloned().collect();
+        refunded_outpoints.sort();
+        let mut refunded_deposits: Vec<u64> = self.refunded_deposits.iter().copied().collect();
+        refunded_deposits.sort_unstable();
+        
+        let mut imported_manifests: Vec<String> = self.imported_manifests.iter().cloned().collect();
+        imported_manifests.sort();
+        
+        ContractState {
+            state_version: CURRENT_STATE_VERSION,
+            version: self.version.clone(),
+            owner: self.contract_owner_address.clone(),
+            pending_owner: self.pending_owner.clone(),
+            next_deposit_id: self.next_deposit_id,
+            id_scheme: self.id_scheme,
+            deposit_id_salt: Some(hex::encode(self.deposit_id_salt)),
+            deposits,
+            paused: self.is_contract_paused,
+            emergency_withdrawal_fee_percentage: self.fee_config.emergency_withdrawal_fee_percentage,
+            fee_collector_address: self.fee_config.fee_collector_address.clone(),
+            collected_fees: sorted_by_token(self.fee_config.collected_fees.iter()),
+            deposit_fees: sorted_by_token(self.fee_config.deposit_fees.iter()),
+            insurance_share_percentage: self.fee_config.insurance_share_percentage,
+            insurance_pool: sorted_by_token(self.fee_config.insurance_pool.iter()),
+            referral_share_percentage: self.fee_config.referral_share_percentage,
+            referral_balances: self.sorted_referral_balances(),
+            max_deposit_amounts: sorted_by_token(self.deposit_limits.max_deposit_amounts.iter()),
+            max_deposits_per_user: self.deposit_limits.max_deposits_per_user,
+            max_total_deposits: self.deposit_limits.max_total_deposits,
+            supported_tokens: self.supported_tokens.clone(),
+            deprecated_tokens: sorted_by_token(self.deprecated_tokens.iter()),
+            paused_tokens: self.paused_tokens(),
+            token_pause_policy: self.token_pause_policy.clone(),
+            total_deposits: sorted_by_token(self.total_deposits.iter()),
+            emergency_policy: self.emergency_policy.clone(),
+            max_lock_days: self.lock_policy.max_lock_days,
+            per_token_max_lock_days: sorted_by_token(self.lock_policy.per_token_max.iter()),
+            emergency_withdrawals,
+            idempotency_retention: self.idempotency_retention,
+            idempotency_records,
+            governance: self.governance.clone(),
+            reservation_ttl: self.reservation_ttl,
+            deposit_reservations,
+            consumed_utxo_references,
+            last_activity,
+            quote_validity: self.quote_validity,
+            emergency_quotes,
+            unattributed_funds,
+            refunded_outpoints,
+            voided_deposits: self.list_voided_deposits(),
+            owner_nonce: self.owner_nonce,
+            confirmation_policy: self.confirmation_policy.clone(),
+            pending_deposit_timeout: self.pending_deposit_timeout,
+            escrow_public_key: self.escrow_public_key.clone(),
+            treasury_policy: self.treasury_policy.clone(),
+            cold_storage_balance: self.cold_storage_balance,
+            migration_public_key: self.migration_public_key.clone(),
+            imported_manifests,
+            circuit_breaker: self.circuit_breaker.clone(),
+            recent_withdrawals: self.recent_withdrawals.iter().cloned().collect(),
+            circuit_breaker_trip: self.circuit_breaker_trip.clone(),
+            fee_destination_whitelist: Some(self.fee_destination_whitelist.clone()),
+            pending_fee_destinations: self.get_pending_fee_destinations(),
+            fee_destination_delay: self.fee_destination_delay,
+            fee_destination_delay_floor: self.fee_destination_delay_floor,
+            fee_ledger: Some(self.fee_ledger.clone()),
+            authorization_log: self.authorization_log.clone(),
+            tvl_series: self.tvl_recorder.as_ref().map(|recorder| recorder.series()),
+            outbox: self.get_outbox(),
+            next_outbox_entry_id: self.next_outbox_entry_id,
+            pending_refunds: self.list_pending_refunds(),
+            next_refund_id: self.next_refund_id,
+            refunded_deposits,
+            refund_policy: self.refund_policy.clone(),
+        }
+    }    
+    /// Restore a contract from a snapshot in the current schema
+    /// 
+    /// Runtime wiring (clock, event sinks, receipt signer, health checker) is
+    /// not part of the snapshot and starts at its defaults. User indexes are
+    /// rebuilt from the deposits, so previously archived IDs are listed again.
+    /// Supported tokens the transfer backend no longer supports are kept,
+    /// deprecated, so their deposits can still be withdrawn.
+    pub fn from_state(state: ContractState, token_transfer: T) -> Result<Self, ContractError> {
+        let (backed_tokens, unbacked_tokens): (Vec<TokenType>, Vec<TokenType>) = state.supported_tokens.iter()
+            .cloned()
+            .partition(|token_type| token_transfer.supports_token_type(token_type));
+        let mut contract = Self::new(state.owner, state.emergency_withdrawal_fee_percentage, token_transfer, backed_tokens)?;
+        contract.supported_tokens = state.supported_tokens;
+        contract.deprecated_tokens = state.deprecated_tokens.into_iter().collect();
+        for token_type in unbacked_tokens {
+            contract.deprecated_tokens.insert(token_type, DeprecationReason::Unsupported);
+        }
+        contract.paused_tokens = state.paused_tokens.into_iter().collect();
+        contract.token_pause_policy = state.token_pause_policy;
+        
+        contract.pending_owner = state.pending_owner;
+        contract.next_deposit_id = state.next_deposit_id;
+        contract.id_scheme = state.id_scheme;
+        
+        // Derived IDs can only be predicted with the salt they were derived with
+        match state.deposit_id_salt {
+            Some(salt) => {
+                contract.deposit_id_salt = hex::decode(&salt).ok()
+                    .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                    .ok_or_else(|| ContractError::InitializationError(format!("Invalid deposit ID salt {}", salt)))?;
+            },
+            None if state.id_scheme == IdScheme::Derived => {
+                return Err(ContractError::InitializationError("Derived deposit IDs need their salt".to_string()));
+            },
+            None => {},
+        }
+        contract.is_contract_paused = state.paused;
+        contract.fee_config.fee_collector_address = state.fee_collector_address;
+        contract.fee_config.collected_fees = state.collected_fees.into_iter().collect();
+        contract.fee_config.deposit_fees = state.deposit_fees.into_iter().collect();
+        contract.fee_config.insurance_share_percentage = state.insurance_share_percentage;
+        contract.fee_config.insurance_pool = state.insurance_pool.into_iter().collect();
+        contract.fee_config.referral_share_percentage = state.referral_share_percentage;
+        contract.referral_balances = state.referral_balances.into_iter()
+            .map(|(referrer_address, token_type, amount)| ((referrer_address, token_type), amount))
+            .collect();
+        contract.deposit_limits = DepositLimits {
+            max_deposit_amounts: state.max_deposit_amounts.into_iter().collect(),
+            max_deposits_per_user: state.max_deposits_per_user,
+            max_total_deposits: state.max_total_deposits,
+        };
+        contract.total_deposits = state.total_deposits.into_iter().collect();
+        contract.emergency_policy = state.emergency_policy;
+        contract.lock_policy = LockPolicy {
+            max_lock_days: state.max_lock_days,
+            per_token_max: state.per_token_max_lock_days.into_iter().collect(),
+        };
+        contract.emergency_withdrawals = state.emergency_withdrawals.into_iter().collect();
+        contract.idempotency_retention = state.idempotency_retention;
+        contract.idempotency_records = state.idempotency_records.into_iter().collect();
+        contract.governance = state.governance;
+        contract.reservation_ttl = state.reservation_ttl;
+        contract.next_reservation_id = state.deposit_reservations.iter()
+            .map(|reservation| reservation.reservation_id + 1)
+            .max()
+            .unwrap_or(1);
+        contract.deposit_reservations = state.deposit_reservations.into_iter()
+            .map(|reservation| (reservation.reservation_id, reservation))
+            .collect();
+        contract.last_activity = state.last_activity.into_iter().collect();
+        contract.quote_validity = state.quote_validity;
+        contract.next_quote_id = state.emergency_quotes.iter()
+            .map(|quote| quote.quote_id + 1)
+            .max()
+            .unwrap_or(1);
+        contract.emergency_quotes = state.emergency_quotes.into_iter()
+            .map(|quote| (quote.quote_id, quote))
+            .collect();
+        contract.unattributed_funds = state.unattributed_funds.into_iter()
+            .map(|funds| (funds.outpoint.clone(), funds))
+            .collect();
+        contract.refunded_outpoints = state.refunded_outpoints.into_iter().collect();
+        contract.voided_deposits = state.voided_deposits.into_iter()
+            .map(|voided| (voided.deposit_id, voided))
+            .collect();
+        contract.owner_nonce = state.owner_nonce;
+        contract.pending_deposit_timeout = state.pending_deposit_timeout;
+        contract.escrow_public_key = state.escrow_public_key;
+        contract.treasury_policy = state.treasury_policy;
+        contract.cold_storage_balance = state.cold_storage_balance;
+        contract.migration_public_key = state.migration_public_key;
+        contract.imported_manifests = state.imported_manifests.into_iter().collect();
+        contract.circuit_breaker = state.circuit_breaker;
+        contract.recent_withdrawals = state.recent_withdrawals.into();
+        contract.circuit_breaker_trip = state.circuit_breaker_trip;
+        
+        // Snapshots from before the whitelist keep paying their fee collector
+        let mut fee_destination_whitelist = state.fee_destination_whitelist
+            .unwrap_or_else(|| vec![contract.fee_config.fee_collector_address.clone()]);
+        fee_destination_whitelist.sort();
+        fee_destination_whitelist.dedup();
+        contract.fee_destination_whitelist = fee_destination_whitelist;
+        contract.pending_fee_destinations = state.pending_fee_destinations.into_iter()
+            .map(|pending| (pending.address.clone(), pending))
+            .collect();
+        contract.fee_destination_delay = state.fee_destination_delay;
+        contract.fee_destination_delay_floor = state.fee_destination_delay_floor;
+        
+        // Snapshots from before the fee ledger open it with their collected fees
+        contract.fee_ledger = state.fee_ledger
+            .unwrap_or_else(|| FeeLedger::opening(contract.fee_config.collected_fees.iter()));
+        contract.authorization_log = state.authorization_log;
+        contract.apply_confirmation_policy(state.confirmation_policy)?;
+        
+        // Rebuild the per-user indexes in deposit order
+        let mut user_deposit_ids: HashMap<String, Vec<u64>> = HashMap::new();
+        let mut user_index = UserIndex::default();
+        
+        for deposit in &state.deposits {
+            user_deposit_ids.entry(deposit.depositor_address.clone()).or_default().push(deposit.deposit_id);
+            user_index.record_deposit(&deposit.depositor_address, deposit);
+            
+            if deposit.is_withdrawn() {
+                user_index.record_withdrawal(&deposit.depositor_address, deposit);
+            } else if matches!(deposit.status, DepositStatus::Expired | DepositStatus::Voided) {
+                user_index.record_expiry(&deposit.depositor_address, deposit);
+            }
+        }
+        
+        // Keep watching the escrow outputs deposits were told to pay
+        for escrow in state.deposits.iter().filter_map(|deposit| deposit.escrow.as_ref()) {
+            contract.token_transfer.watch_script_escrow(escrow);
+        }
+        
+        // Snapshots from before the UTXO registry only have the references on deposits
+        let mut utxo_registry = UtxoRegistry::default();
+        for (reference, deposit_id) in state.consumed_utxo_references {
+            utxo_registry.register(reference, deposit_id);
+        }
+        for deposit in state.deposits.iter().filter(|deposit| !matches!(deposit.status, DepositStatus::Expired | DepositStatus::Voided)) {
+            if let Some(reference) = &deposit.utxo_reference {
+                utxo_registry.references.entry(reference.clone()).or_insert(deposit.deposit_id);
+            }
+        }
+        
+        contract.utxo_registry = utxo_registry;
+        contract.user_deposit_ids = user_deposit_ids;
+        contract.user_index = user_index;
+        contract.deposit_registry = state.deposits.into_iter()
+            .map(|deposit| (deposit.deposit_id, deposit))
+            .collect();
+        
+        // Entries left by a restart wait for `recover_in_flight`
+        contract.next_outbox_entry_id = state.next_outbox_entry_id.max(1);
+        contract.outbox = state.outbox.into_iter()
+            .map(|entry| (entry.entry_id, entry))
+            .collect();
+        
+        contract.next_refund_id = state.next_refund_id.max(1);
+        contract.pending_refunds = state.pending_refunds.into_iter()
+            .map(|refund| (refund.refund_id, refund))
+            .collect();
+        contract.refunded_deposits = state.refunded_deposits.into_iter().collect();
+        contract.refund_policy = state.refund_policy;
+        
+        // Keep recording the series where the snapshot left off
+        if let Some(series) = state.tvl_series {
+            contract.attach_tvl_recorder(MetricsRecorder::from_series(series)?);
+        }
+        
+        Ok(contract)
+    }
+}
@@ -0,0 +1,237 @@
+//! Summary-first queries over the deposit registry
+//!
+//! A screen showing twenty rows of a user with thousands of deposits should
+//! not fetch thousands of full deposits. `list_deposit_summaries` returns one
+//! page of compact summaries, sorted by unlock time, amount or creation time,
+//! and `get_deposit_details` fetches the full views of the rows on screen.
+//!
+//! Ties on the sort key are broken by deposit ID, so the order is total and
+//! pages never overlap. A page carries a cursor after its last row; paging on
+//! with `list_deposit_summaries_after` neither repeats nor skips deposits
+//! that were listed before, even while new deposits are made. Unlock-time
+//! queries over one user's active deposits walk the user's unlock index
+//! instead of sorting.
+
+use std::ops::Bound;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::models::{Deposit, DepositStatus, TokenTransfer, TokenType};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::query::DepositFilter;
+use crate::contract::view::DepositView;
+
+/// Most summaries a page holds
+pub const MAX_SUMMARY_PAGE_SIZE: usize = 500;
+
+/// Order deposit summaries are listed in, ascending, ties broken by deposit ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    /// By unlock time
+    #[default]
+    UnlockTime,
+    /// By deposited amount
+    Amount,
+    /// By deposit time
+    CreatedAt,
+}
+
+impl SortKey {
+    /// Value of a deposit this key sorts by
+    fn value_of(&self, deposit: &Deposit) -> SortValue {
+        match self {
+            SortKey::UnlockTime => SortValue::Timestamp(deposit.unlock_timestamp),
+            SortKey::Amount => SortValue::Amount(deposit.deposited_amount),
+            SortKey::CreatedAt => SortValue::Timestamp(deposit.deposit_timestamp),
+        }
+    }
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = String;
+    
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "unlock_time" | "unlock" => Ok(SortKey::UnlockTime),
+            "amount" => Ok(SortKey::Amount),
+            "created_at" | "created" => Ok(SortKey::CreatedAt),
+            other => Err(format!("Unknown sort key: {} (expected unlock_time, amount or created_at)", other)),
+        }
+    }
+}
+
+/// Value a deposit is sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortValue {
+    /// Unlock or deposit time
+    Timestamp(DateTime<Utc>),
+    /// Deposited amount
+    Amount(u64),
+}
+
+/// Position after the last summary of a page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositCursor {
+    /// Order the page was listed in
+    pub sort: SortKey,
+    /// Sort value of the last summary
+    pub after: SortValue,
+    /// ID of the last summary
+    pub after_deposit_id: u64,
+}
+
+impl DepositCursor {
+    /// Cursor after a deposit in an order
+    fn after(sort: SortKey, deposit: &Deposit) -> Self {
+        Self {
+            sort,
+            after: sort.value_of(deposit),
+            after_deposit_id: deposit.deposit_id,
+        }
+    }
+}
+
+/// Compact row of a deposit list
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepositSummary {
+    /// Deposit ID
+    pub id: u64,
+    /// Token deposited
+    pub token: TokenType,
+    /// Amount deposited
+    pub amount: u64,
+    /// When the deposit unlocks
+    pub unlock_at: DateTime<Utc>,
+    /// Current status
+    pub status: DepositStatus,
+}
+
+impl From<&Deposit> for DepositSummary {
+    fn from(deposit: &Deposit) -> Self {
+        Self {
+            id: deposit.deposit_id,
+            token: deposit.deposited_token_type.clone(),
+            amount: deposit.deposited_amount,
+            unlock_at: deposit.unlock_timestamp,
+            status: deposit.status,
+        }
+    }
+}
+
+/// One page of a sorted listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page<T> {
+    /// Rows of the page, in order
+    pub items: Vec<T>,
+    /// Rows matching the query across all pages
+    pub total: usize,
+    /// Cursor of the next page, `None` on the last page
+    pub next_cursor: Option<DepositCursor>,
+}
+
+/// Where a page starts
+#[derive(Debug, Clone, Copy)]
+enum PageStart {
+    /// After skipping this many matching deposits
+    Offset(usize),
+    /// After a cursor
+    After(DepositCursor),
+}
+
+/// Sort deposits and summarize one page of them
+///
+/// `limit` is clamped to between 1 and `MAX_SUMMARY_PAGE_SIZE`.
+pub fn summary_page<'a>(deposits: impl IntoIterator<Item = &'a Deposit>, sort: SortKey, offset: usize, limit: usize) -> Page<DepositSummary> {
+    sorted_page(deposits, sort, PageStart::Offset(offset), limit)
+}
+
+/// Sort deposits and summarize the page starting at `start`
+fn sorted_page<'a>(deposits: impl IntoIterator<Item = &'a Deposit>, sort: SortKey, start: PageStart, limit: usize) -> Page<DepositSummary> {
+    let mut sorted: Vec<(SortValue, u64, &Deposit)> = deposits.into_iter()
+        .map(|deposit| (sort.value_of(deposit), deposit.deposit_id, deposit))
+        .collect();
+    sorted.sort_unstable_by(|(a_value, a_id, _), (b_value, b_id, _)| a_value.cmp(b_value).then(a_id.cmp(b_id)));
+    
+    let start = match start {
+        PageStart::Offset(offset) => offset.min(sorted.len()),
+        PageStart::After(cursor) => sorted.partition_point(|(value, deposit_id, _)| (*value, *deposit_id) <= (cursor.after, cursor.after_deposit_id)),
+    };
+    
+    page_of(sorted.len(), sorted[start..].iter().map(|(_, _, deposit)| *deposit), sort, limit)
+}
+
+/// Summarize the first `limit` deposits of an ordered run
+fn page_of<'a>(total: usize, mut ordered: impl Iterator<Item = &'a Deposit>, sort: SortKey, limit: usize) -> Page<DepositSummary> {
+    let limit = limit.clamp(1, MAX_SUMMARY_PAGE_SIZE);
+    let page: Vec<&Deposit> = ordered.by_ref().take(limit).collect();
+    let next_cursor = match (page.last(), ordered.next()) {
+        (Some(last), Some(_)) => Some(DepositCursor::after(sort, last)),
+        _ => None,
+    };
+    
+    Page {
+        items: page.into_iter().map(DepositSummary::from).collect(),
+        total,
+        next_cursor,
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// List one page of summaries of the deposits matching a filter
+    ///
+    /// `offset` matching deposits are skipped and at most `limit` returned,
+    /// between 1 and `MAX_SUMMARY_PAGE_SIZE`. Continue with the page's
+    /// cursor rather than a larger offset to page stably while deposits are
+    /// being made.
+    pub fn list_deposit_summaries(&self, filter: DepositFilter, sort: SortKey, offset: usize, limit: usize) -> Page<DepositSummary> {
+        self.summaries_from(filter, sort, PageStart::Offset(offset), limit)
+    }
+    
+    /// List the page of summaries after a cursor, in the cursor's order
+    pub fn list_deposit_summaries_after(&self, filter: DepositFilter, cursor: DepositCursor, limit: usize) -> Page<DepositSummary> {
+        self.summaries_from(filter, cursor.sort, PageStart::After(cursor), limit)
+    }
+    
+    /// Get the full views of deposits, in the order asked
+    ///
+    /// Unknown IDs are left out.
+    pub fn get_deposit_details(&self, ids: &[u64]) -> Vec<DepositView> {
+        ids.iter()
+            .filter_map(|deposit_id| self.get_deposit_view(*deposit_id).ok())
+            .collect()
+    }
+    
+    /// List a page of summaries, walking the unlock index when it holds every match in order
+    fn summaries_from(&self, filter: DepositFilter, sort: SortKey, start: PageStart, limit: usize) -> Page<DepositSummary> {
+        let unlocks = match (&filter.address, filter.status, sort) {
+            (Some(address), Some(DepositStatus::Active), SortKey::UnlockTime) => match self.user_index.unlocks.get(address) {
+                Some(unlocks) => unlocks,
+                None => return page_of(0, std::iter::empty(), sort, limit),
+            },
+            _ => return sorted_page(self.deposits_filtered(filter), sort, start, limit),
+        };
+        
+        // The index holds exactly the user's active deposits, ordered by unlock time and ID
+        let matching = |(_, deposit_id): &&(DateTime<Utc>, u64)| self.deposit_registry.get(deposit_id)
+            .is_some_and(|deposit| filter.matches(deposit));
+        let total = unlocks.iter().filter(matching).count();
+        
+        let (lower, skip) = match start {
+            PageStart::Offset(offset) => (Bound::Unbounded, offset),
+            PageStart::After(DepositCursor { after: SortValue::Timestamp(after), after_deposit_id, .. }) => {
+                (Bound::Excluded((after, after_deposit_id)), 0)
+            },
+            // A cursor of another order lies after every deposit
+            PageStart::After(_) => return page_of(total, std::iter::empty(), sort, limit),
+        };
+        
+        let ordered = unlocks.range((lower, Bound::Unbounded))
+            .filter(matching)
+            .skip(skip)
+            .filter_map(|(_, deposit_id)| self.deposit_registry.get(deposit_id));
+        
+        page_of(total, ordered, sort, limit)
+    }
+}
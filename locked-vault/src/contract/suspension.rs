@@ -0,0 +1,125 @@
+//! Deposit suspension after chain reorganizations
+//!
+//! Applies `ReorgNotice`s from the reorg watcher. A suspended deposit keeps
+//! counting towards the totals, since the depositor is still owed the funds
+//! if the transaction re-confirms, but cannot be withdrawn, claimed or
+//! transferred until it is reinstated.
+//!
+//! The contract's `ConfirmationPolicy` decides how many confirmations a
+//! funding transaction needs, by token type and deposit amount, and is handed
+//! to the backend's chain watchers. They resolve it when they start tracking a
+//! transaction, so a changed policy applies to new deposits only and never
+//! suspends a deposit that was already confirmed.
+
+use log::warn;
+
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::reorg::ReorgNotice;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the confirmation policy
+    pub fn get_confirmation_policy(&self) -> &ConfirmationPolicy {
+        &self.confirmation_policy
+    }
+    
+    /// Get the confirmations the funding transaction of a deposit needs
+    pub fn required_confirmations(&self, deposit_id: u64) -> Result<u32, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        Ok(self.confirmation_policy.required_confirmations(&deposit.deposited_token_type, deposit.deposited_amount))
+    }
+    
+    /// Replace the confirmation policy (owner only)
+    pub fn set_confirmation_policy(&mut self, caller_address: String, confirmation_policy: ConfirmationPolicy, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.apply_confirmation_policy(confirmation_policy)?;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Validate a confirmation policy, keep it and hand it to the backend
+    ///
+    /// Used by the owner setter, when restoring a snapshot and for the
+    /// configured policy at startup.
+    pub(crate) fn apply_confirmation_policy(&mut self, confirmation_policy: ConfirmationPolicy) -> Result<(), ContractError> {
+        confirmation_policy.validate()?;
+        
+        self.token_transfer.set_confirmation_policy(&confirmation_policy);
+        self.confirmation_policy = confirmation_policy;
+        
+        Ok(())
+    }
+    
+    /// Suspend or reinstate a deposit after a reorg notice, returning the emitted event
+    /// 
+    /// Notices that do not change anything, such as a repeated suspension or a
+    /// reorg of an already withdrawn deposit, return `None`.
+    pub fn apply_reorg_notice(&mut self, notice: &ReorgNotice) -> Result<Option<Event>, ContractError> {
+        self.ensure_writable()?;
+        
+        let current_timestamp = self.clock.now();
+        
+        let event = match notice {
+            ReorgNotice::Suspended { deposit_id, txid, reason } => {
+                let deposit = self.deposit_registry.get_mut(deposit_id)
+                    .ok_or(ContractError::DepositNotFound)?;
+                
+                if deposit.status != DepositStatus::Active {
+                    if deposit.is_withdrawn() {
+                        warn!(target: CONTRACT, "Funding transaction {} of withdrawn deposit {} was reorganized out", txid, deposit_id);
+                    }
+                    
+                    return Ok(None);
+                }
+                
+                deposit.status = DepositStatus::Suspended;
+                deposit.last_modified = current_timestamp;
+                
+                warn!(target: CONTRACT, "Suspending deposit {}: {}", deposit_id, reason);
+                
+                Event::DepositSuspended {
+                    deposit_id: *deposit_id,
+                    txid: txid.clone(),
+                    reason: reason.clone(),
+                    timestamp: current_timestamp,
+                }
+            },
+            ReorgNotice::Reinstated { deposit_id, txid } => {
+                let deposit = self.deposit_registry.get_mut(deposit_id)
+                    .ok_or(ContractError::DepositNotFound)?;
+                
+                if !deposit.is_suspended() {
+                    return Ok(None);
+                }
+                
+                deposit.status = DepositStatus::Active;
+                deposit.last_modified = current_timestamp;
+                
+                Event::DepositReinstated {
+                    deposit_id: *deposit_id,
+                    txid: txid.clone(),
+                    timestamp: current_timestamp,
+                }
+            },
+        };
+        
+        self.emit(&event);
+        
+        Ok(Some(event))
+    }
+}
@@ -0,0 +1,209 @@
+//! Pausing a single token type
+//!
+//! Where the contract-wide pause stops everything, `pause_token` stops only
+//! the operations on one token: its deposits fail with `TokenPaused`, and so
+//! do its withdrawals unless the `TokenPausePolicy` exempts them, while every
+//! other token keeps working. The contract-wide pause supersedes it: it is
+//! checked first, so a paused contract reports `ContractPaused` whatever the
+//! state of the token, and a token paused on its own stays paused after the
+//! contract resumes.
+//!
+//! Besides the owner, the circuit breaker pauses a token when its
+//! configuration sets `pause_token_only`, and `pause_tokens_behind_open_gates`
+//! pauses the tokens of a backend whose feature gate opened when the policy
+//! sets `pause_on_open_gate`. Automatic pauses name their `PauseReason` in
+//! the `TokenPaused` event and are lifted by the owner with `unpause_token`.
+
+use std::collections::HashSet;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::feature_gate::GateState;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::circuit_breaker::PauseReason;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Address recorded as the pauser when a token is paused behind an open feature gate
+pub const FEATURE_GATE_PAUSER: &str = "feature_gate";
+
+/// How paused tokens are treated
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenPausePolicy {
+    /// Whether deposits of a paused token can still be withdrawn
+    #[serde(default)]
+    pub withdrawals_exempt: bool,
+    /// Whether a token is paused when the feature gate of its backend opens
+    #[serde(default)]
+    pub pause_on_open_gate: bool,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get how paused tokens are treated
+    pub fn get_token_pause_policy(&self) -> &TokenPausePolicy {
+        &self.token_pause_policy
+    }
+    
+    /// Set how paused tokens are treated (owner only)
+    pub fn set_token_pause_policy(&mut self, caller_address: String, policy: TokenPausePolicy, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        self.token_pause_policy = policy;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Pause the operations on one supported token (owner only)
+    pub fn pause_token(&mut self, caller_address: String, token_type: TokenType, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if !self.supported_tokens.contains(&token_type) {
+            return Err(ContractError::UnsupportedTokenOperation);
+        }
+        
+        if self.is_token_paused(&token_type) {
+            return Err(ContractError::TokenPaused { token_type });
+        }
+        
+        info!(target: CONTRACT, "Token {} paused by {}", token_type.name(), caller_address);
+        
+        self.paused_tokens.insert(token_type.clone());
+        
+        let event = Event::TokenPaused {
+            token_type,
+            pauser_address: caller_address,
+            reason: None,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Lift the pause of one token, whoever paused it (owner only)
+    ///
+    /// Forgets the token's withdrawals tracked by the circuit breaker, so the
+    /// ones that paused it do not pause it again.
+    pub fn unpause_token(&mut self, caller_address: String, token_type: TokenType, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        if !self.paused_tokens.remove(&token_type) {
+            return Err(ContractError::TokenNotPaused);
+        }
+        
+        info!(target: CONTRACT, "Token {} unpaused by {}", token_type.name(), caller_address);
+        
+        self.recent_withdrawals.retain(|withdrawal| withdrawal.token_type != token_type);
+        
+        let event = Event::TokenUnpaused {
+            token_type,
+            unpauser_address: caller_address,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Check whether a token is paused on its own
+    pub fn is_token_paused(&self, token_type: &TokenType) -> bool {
+        self.paused_tokens.contains(token_type)
+    }
+    
+    /// Get the paused tokens, sorted
+    pub fn paused_tokens(&self) -> Vec<TokenType> {
+        let mut paused_tokens: Vec<TokenType> = self.paused_tokens.iter().cloned().collect();
+        paused_tokens.sort_by_key(|token_type| format!("{:?}", token_type));
+        paused_tokens
+    }
+    
+    /// Pause the supported tokens whose backend's feature gate is open
+    ///
+    /// Does nothing unless the policy sets `pause_on_open_gate`. Returns one
+    /// event per token paused; the pauses stay after the gate closes again,
+    /// until the owner lifts them.
+    pub fn pause_tokens_behind_open_gates(&mut self) -> Vec<Event> {
+        if !self.token_pause_policy.pause_on_open_gate || self.ensure_writable().is_err() {
+            return Vec::new();
+        }
+        
+        let now = self.clock.now();
+        let mut events = Vec::new();
+        
+        for token_type in self.supported_tokens.clone() {
+            let component = match self.token_transfer.feature_gate_status(&token_type) {
+                Some(status) if status.state == GateState::Open => status.component,
+                _ => continue,
+            };
+            
+            if let Some(event) = self.pause_token_automatically(token_type, FEATURE_GATE_PAUSER, PauseReason::FeatureGateOpen { component }, now) {
+                events.push(event);
+            }
+        }
+        
+        events
+    }
+    
+    /// Pause a token for a reason, unless it is already paused
+    pub(crate) fn pause_token_automatically(&mut self, token_type: TokenType, pauser_address: &str, reason: PauseReason, now: DateTime<Utc>) -> Option<Event> {
+        if !self.paused_tokens.insert(token_type.clone()) {
+            return None;
+        }
+        
+        warn!(target: CONTRACT, "Pausing token {}: {:?}", token_type.name(), reason);
+        
+        let event = Event::TokenPaused {
+            token_type,
+            pauser_address: pauser_address.to_string(),
+            reason: Some(reason),
+            timestamp: now,
+        };
+        
+        self.emit(&event);
+        Some(event)
+    }
+    
+    /// Reject an operation on a paused token
+    ///
+    /// Takes the fields rather than `self` so it can be called while a
+    /// deposit is borrowed. Withdrawals pass if the policy exempts them.
+    pub(crate) fn ensure_token_not_paused(
+        paused_tokens: &HashSet<TokenType>,
+        policy: &TokenPausePolicy,
+        token_type: &TokenType,
+        withdrawal: bool,
+    ) -> Result<(), ContractError> {
+        if paused_tokens.contains(token_type) && !(withdrawal && policy.withdrawals_exempt) {
+            return Err(ContractError::TokenPaused { token_type: token_type.clone() });
+        }
+        
+        Ok(())
+    }
+}
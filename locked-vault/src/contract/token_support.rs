@@ -0,0 +1,93 @@
+//! Revalidation of the supported tokens against the live backend
+//!
+//! The supported tokens are checked against the transfer backend when the
+//! contract is created, but a backend can lose a capability afterwards: a
+//! revoked Lightning macaroon, or an Ordinals API dropped from the
+//! configuration of a restored contract. `revalidate_supported_tokens`
+//! queries the backend again, and the health checker if one is set, for
+//! every supported token. A token that fails is deprecated: it stays in the
+//! supported set, so its deposits can still be withdrawn, but new deposits
+//! fail with `TokenDeprecated`. It is reinstated once it passes again.
+
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::health::BackendComponent;
+use crate::events::Event;
+use crate::models::{TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Why a supported token was deprecated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeprecationReason {
+    /// The transfer backend no longer supports the token
+    Unsupported,
+    /// The token's backend has been unhealthy for longer than the grace period
+    BackendUnavailable(BackendComponent),
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Check whether the backend can currently serve a token, and why not
+    fn token_support_failure(&self, token_type: &TokenType) -> Option<DeprecationReason> {
+        if !self.token_transfer.supports_token_type(token_type) {
+            return Some(DeprecationReason::Unsupported);
+        }
+        
+        let health_checker = self.health_checker.as_ref()?;
+        BackendComponent::for_token_type(token_type)
+            .filter(|component| !health_checker.is_available(*component, self.backend_grace_period))
+            .map(DeprecationReason::BackendUnavailable)
+    }
+    
+    /// Re-check every supported token against the backend
+    ///
+    /// Deprecates the tokens that fail and reinstates deprecated ones that
+    /// pass again, returning one event per change. Running it again without
+    /// the backend changing changes nothing.
+    pub fn revalidate_supported_tokens(&mut self) -> Vec<Event> {
+        if self.ensure_writable().is_err() {
+            return Vec::new();
+        }
+        
+        let now = self.clock.now();
+        let mut events = Vec::new();
+        
+        for token_type in self.supported_tokens.clone() {
+            let failure = self.token_support_failure(&token_type);
+            
+            let event = match (failure, self.deprecated_tokens.get(&token_type).copied()) {
+                (Some(reason), current) if current != Some(reason) => {
+                    warn!(target: CONTRACT, "Deprecating token {}: {:?}", token_type.name(), reason);
+                    self.deprecated_tokens.insert(token_type.clone(), reason);
+                    Event::TokenSupportDeprecated { token_type, reason, timestamp: now }
+                },
+                (None, Some(_)) => {
+                    info!(target: CONTRACT, "Reinstating token {}", token_type.name());
+                    self.deprecated_tokens.remove(&token_type);
+                    Event::TokenSupportReinstated { token_type, timestamp: now }
+                },
+                _ => continue,
+            };
+            
+            self.emit(&event);
+            events.push(event);
+        }
+        
+        events
+    }
+    
+    /// Check whether a supported token is deprecated
+    pub fn is_token_deprecated(&self, token_type: &TokenType) -> bool {
+        self.deprecated_tokens.contains_key(token_type)
+    }
+    
+    /// Get the supported tokens new deposits are accepted for
+    pub fn active_tokens(&self) -> Vec<TokenType> {
+        self.supported_tokens.iter()
+            .filter(|token_type| !self.is_token_deprecated(token_type))
+            .cloned()
+            .collect()
+    }
+}
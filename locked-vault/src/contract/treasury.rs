@@ -0,0 +1,199 @@
+//! Sweeps of the hot wallet into cold storage
+//!
+//! The contract wallet only needs to hold what depositors can withdraw soon.
+//! A `TreasuryPolicy` names a cold storage address and how much the hot
+//! wallet keeps: at least `hot_wallet_ceiling`, and never less than the
+//! Bitcoin deposits unlocking within the next `float_days`, found through
+//! the unlock index. Everything above that is swept, net of the mining fee,
+//! so a sweep never leaves an imminent withdrawal unfundable. Backends that
+//! tag their change may further limit sweeps to it, so funds attributed to
+//! deposits stay hot unless configured otherwise.
+//!
+//! Emergency withdrawals are not scheduled and are only covered by the
+//! ceiling. Swept amounts are tracked so reconciliation still balances the
+//! contract's liabilities against its holdings.
+
+use chrono::Duration;
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::payout::DUST_THRESHOLD;
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenType, TokenTransfer, TransferPriority};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// How much of the contract wallet stays hot, and where the rest goes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreasuryPolicy {
+    /// Balance the hot wallet keeps even when few withdrawals are due, in satoshis
+    pub hot_wallet_ceiling: u64,
+    /// Days ahead whose scheduled withdrawals stay in the hot wallet
+    pub float_days: u32,
+    /// Address the excess is swept to
+    pub cold_address: String,
+}
+
+/// Sweep the policy proposes for the current hot wallet balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SweepProposal {
+    /// Bitcoin balance of the contract wallet
+    pub hot_balance: u64,
+    /// Active deposits unlocking within the float window, already unlocked ones included
+    pub due_within_float: u64,
+    /// Balance the hot wallet keeps
+    pub retained: u64,
+    /// Balance sweeps may spend, `None` if all of the hot wallet
+    #[serde(default)]
+    pub sweepable: Option<u64>,
+    /// Amount to sweep before the mining fee
+    pub sweep_amount: u64,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the treasury policy
+    pub fn get_treasury_policy(&self) -> Option<&TreasuryPolicy> {
+        self.treasury_policy.as_ref()
+    }
+    
+    /// Get the total amount swept to cold storage
+    pub fn get_cold_storage_balance(&self) -> u64 {
+        self.cold_storage_balance
+    }
+    
+    /// Set the treasury policy (owner only)
+    ///
+    /// `None` stops sweeping; funds already in cold storage stay there.
+    pub fn set_treasury_policy(&mut self, caller_address: String, policy: Option<TreasuryPolicy>, expected_nonce: Option<u64>) -> Result<(), ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Validate address and the script it pays to
+        if let Some(policy) = &policy {
+            self.token_transfer.validate_destination(&policy.cold_address)?;
+        }
+        
+        self.treasury_policy = policy;
+        
+        self.advance_owner_nonce();
+        
+        Ok(())
+    }
+    
+    /// Work out how much of the hot wallet the treasury policy would sweep now
+    pub fn compute_sweep_amount(&self) -> Result<SweepProposal, ContractError> {
+        let policy = self.treasury_policy.as_ref().ok_or(ContractError::TreasuryPolicyNotConfigured)?;
+        
+        let hot_balance = self.token_transfer.get_contract_balance(&TokenType::Bitcoin)
+            .map_err(|reason| ContractError::TransferFailed { stage: TransferStage::BalanceCheck, reason })?;
+        
+        // The index yields each user's deposits in unlock order, so stop at the end of the window
+        let horizon = self.clock.now() + Duration::days(policy.float_days as i64);
+        let mut due_within_float: u64 = 0;
+        for unlocks in self.user_index.unlocks.values() {
+            for (_, deposit_id) in unlocks.iter().take_while(|(unlock_timestamp, _)| *unlock_timestamp <= horizon) {
+                let Some(deposit) = self.deposit_registry.get(deposit_id) else {
+                    continue;
+                };
+                
+                // Pending deposits hold nothing yet, and escrow deposits are held in their own output
+                let funded = matches!(deposit.status, DepositStatus::Active | DepositStatus::Suspended);
+                if funded && deposit.deposited_token_type == TokenType::Bitcoin && deposit.escrow.is_none() {
+                    due_within_float = due_within_float.saturating_add(deposit.deposited_amount);
+                }
+            }
+        }
+        
+        let retained = policy.hot_wallet_ceiling.max(due_within_float);
+        let sweepable = self.token_transfer.get_sweepable_balance(&TokenType::Bitcoin)
+            .map_err(|reason| ContractError::TransferFailed { stage: TransferStage::BalanceCheck, reason })?;
+        
+        Ok(SweepProposal {
+            hot_balance,
+            due_within_float,
+            retained,
+            sweepable,
+            sweep_amount: hot_balance.saturating_sub(retained).min(sweepable.unwrap_or(u64::MAX)),
+        })
+    }
+    
+    /// Sweep the hot wallet excess to cold storage (owner only)
+    ///
+    /// Returns `None` if there is nothing worth sweeping.
+    pub fn execute_treasury_sweep(&mut self, caller_address: String, expected_nonce: Option<u64>) -> Result<Option<Event>, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let event = self.sweep_to_cold_storage()?;
+        
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Sweep the hot wallet excess to cold storage if a treasury policy is set, for the daemon's schedule
+    pub(crate) fn run_scheduled_treasury_sweep(&mut self) -> Result<Option<Event>, ContractError> {
+        if self.treasury_policy.is_none() {
+            return Ok(None);
+        }
+        
+        self.sweep_to_cold_storage()
+    }
+    
+    /// Send the proposed sweep, net of its mining fee, and record it
+    fn sweep_to_cold_storage(&mut self) -> Result<Option<Event>, ContractError> {
+        // Never move funds while the contract is paused, possibly after a failed reconciliation
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        let proposal = self.compute_sweep_amount()?;
+        let cold_address = self.treasury_policy.as_ref()
+            .map(|policy| policy.cold_address.clone())
+            .ok_or(ContractError::TreasuryPolicyNotConfigured)?;
+        
+        // The mining fee leaves the hot wallet too; backends without an estimate are taken to charge none
+        let fee = self.token_transfer
+            .estimate_withdrawal_fee(&cold_address, &TokenType::Bitcoin, proposal.sweep_amount, TransferPriority::Low)
+            .unwrap_or(0);
+        let amount = proposal.sweep_amount.saturating_sub(fee);
+        
+        // Outputs below the dust threshold would not relay
+        if amount < DUST_THRESHOLD {
+            return Ok(None);
+        }
+        
+        let txid = self.token_transfer.sweep_from_contract(&cold_address, &TokenType::Bitcoin, amount)?;
+        self.cold_storage_balance = self.cold_storage_balance.saturating_add(amount);
+        
+        info!(
+            target: CONTRACT,
+            "Swept {} sats to cold storage at {}, keeping {} of which {} due within the float",
+            amount, cold_address, proposal.hot_balance.saturating_sub(amount), proposal.due_within_float
+        );
+        
+        let event = Event::TreasurySwept {
+            cold_address,
+            amount,
+            hot_balance: proposal.hot_balance,
+            due_within_float: proposal.due_within_float,
+            txid,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        
+        Ok(Some(event))
+    }
+}
@@ -0,0 +1,206 @@
+//! Funds sent to the contract wallet without a deposit
+//!
+//! The incoming funds watcher reports confirmed outputs that no deposit
+//! accounts for, and they are kept in a ledger until the owner either
+//! attributes one to a depositor, turning it into a regular deposit, or
+//! refunds it. An entry is removed before either action runs and restored
+//! only if that action fails, so an output can never back two deposits or be
+//! paid out twice.
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::incoming::IncomingFunds;
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::utxo_registry::normalize_utxo_reference;
+use crate::logging::CONTRACT;
+
+/// Confirmed output to the contract wallet awaiting attribution or refund
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnattributedFunds {
+    /// Normalized output reference (`txid:vout`)
+    pub outpoint: String,
+    /// Transaction ID
+    pub txid: String,
+    /// Output index
+    pub vout: u32,
+    /// Amount in satoshis
+    pub amount: u64,
+    /// Addresses that funded the transaction, best effort
+    pub sender_addresses: Vec<String>,
+    /// When the contract recorded the output
+    pub detected_at: DateTime<Utc>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Record funds reported by the incoming funds watcher, returning the emitted event
+    ///
+    /// Outputs that already back a deposit, are already listed or were
+    /// refunded are ignored and return `None`. An output a pending deposit
+    /// waits for funds that deposit, unless it expired meanwhile; the
+    /// refund policy may then queue it for a refund to the depositor.
+    pub fn record_incoming_funds(&mut self, funds: &IncomingFunds) -> Option<Event> {
+        self.ensure_writable().ok()?;
+        
+        let outpoint = normalize_utxo_reference(&funds.outpoint());
+        
+        // Funding of a pending deposit, which releases the output if it is overdue
+        if let Some(deposit_id) = self.utxo_registry.owner_of(&outpoint) {
+            self.expire_if_stale(deposit_id, self.clock.now());
+            
+            let is_pending = self.deposit_registry.get(&deposit_id)
+                .is_some_and(|deposit| deposit.status == DepositStatus::Pending);
+            if is_pending {
+                return self.confirm_pending_deposit(deposit_id).ok().flatten();
+            }
+        }
+        
+        if self.utxo_registry.owner_of(&outpoint).is_some()
+            || self.unattributed_funds.contains_key(&outpoint)
+            || self.refunded_outpoints.contains(&outpoint)
+        {
+            return None;
+        }
+        
+        // Late funding of an expired deposit goes back to its depositor if the refund policy says so
+        if self.refund_policy.refund_late_funding {
+            if let Some(deposit_id) = self.expired_deposit_funded_by(&outpoint) {
+                return self.refund_late_funding(deposit_id, funds.amount).ok().flatten();
+            }
+        }
+        
+        let current_timestamp = self.clock.now();
+        
+        info!(target: CONTRACT, "Unattributed funds of {} sat detected at {}", funds.amount, outpoint);
+        
+        self.unattributed_funds.insert(outpoint.clone(), UnattributedFunds {
+            outpoint: outpoint.clone(),
+            txid: funds.txid.to_lowercase(),
+            vout: funds.vout,
+            amount: funds.amount,
+            sender_addresses: funds.sender_addresses.clone(),
+            detected_at: current_timestamp,
+        });
+        
+        let event = Event::UnattributedFundsDetected {
+            outpoint,
+            amount: funds.amount,
+            sender_addresses: funds.sender_addresses.clone(),
+            timestamp: current_timestamp,
+        };
+        
+        self.emit(&event);
+        
+        Some(event)
+    }
+    
+    /// List funds awaiting attribution or refund, oldest first
+    pub fn list_unattributed_funds(&self) -> Vec<UnattributedFunds> {
+        let mut funds: Vec<UnattributedFunds> = self.unattributed_funds.values().cloned().collect();
+        funds.sort_by(|a, b| (a.detected_at, &a.outpoint).cmp(&(b.detected_at, &b.outpoint)));
+        funds
+    }
+    
+    /// Turn unattributed funds into a Bitcoin deposit of a depositor (owner only)
+    ///
+    /// The deposit is subject to the usual deposit checks, limits and fees,
+    /// and consumes the output as its UTXO reference.
+    pub fn attribute_funds(
+        &mut self,
+        caller_address: String,
+        outpoint: &str,
+        depositor_address: String,
+        lock_days: u32,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Take the entry out first so it cannot be used twice
+        let reference: UtxoRef = outpoint.parse()?;
+        let outpoint = reference.to_string();
+        let funds = self.unattributed_funds.remove(&outpoint)
+            .ok_or(ContractError::UnattributedFundsNotFound)?;
+        
+        let result = self.validate_deposit_request(&depositor_address, &TokenType::Bitcoin, funds.amount, lock_days)
+            .and_then(|_| self.commit_deposit(
+                depositor_address,
+                TokenType::Bitcoin,
+                funds.amount,
+                lock_days,
+                Some(reference),
+                None,
+                None,
+                None,
+                None,
+            ));
+        
+        // Keep the entry listed if the deposit was rejected
+        match result {
+            Ok(_) => self.advance_owner_nonce(),
+            Err(_) => {
+                self.unattributed_funds.insert(outpoint, funds);
+            },
+        }
+        
+        result
+    }
+    
+    /// Send unattributed funds to a destination, typically their sender (owner only)
+    pub fn refund_unattributed(
+        &mut self,
+        caller_address: String,
+        outpoint: &str,
+        destination_address: String,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Validate address and the script it pays to
+        self.token_transfer.validate_destination(&destination_address)?;
+        
+        // Take the entry out first so it cannot be used twice
+        let outpoint = normalize_utxo_reference(outpoint);
+        let funds = self.unattributed_funds.remove(&outpoint)
+            .ok_or(ContractError::UnattributedFundsNotFound)?;
+        
+        // Keep the entry listed if the payout fails
+        if let Err(e) = self.token_transfer.transfer_from_contract(&destination_address, &TokenType::Bitcoin, funds.amount) {
+            self.unattributed_funds.insert(outpoint, funds);
+            return Err(ContractError::transfer_failed(TransferStage::Withdrawal, e));
+        }
+        
+        self.refunded_outpoints.insert(outpoint.clone());
+        
+        info!(target: CONTRACT, "Unattributed funds at {} refunded to {}", outpoint, destination_address);
+        
+        let event = Event::UnattributedFundsRefunded {
+            outpoint,
+            destination_address,
+            amount: funds.amount,
+            timestamp: self.clock.now(),
+        };
+        
+        self.emit(&event);
+        
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+}
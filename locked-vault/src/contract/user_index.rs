@@ -0,0 +1,185 @@
+//! Per-user deposit aggregates and archival
+//!
+//! Counts and active amounts are maintained incrementally so limit checks and
+//! summaries do not scan a user's deposit history. Maintenance moves the IDs
+//! of withdrawn deposits out of `user_deposit_ids` into a compact archive.
+
+use std::collections::{BTreeSet, HashMap};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::models::{Deposit, TokenType, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::state::sorted_by_token;
+use crate::logging::CONTRACT;
+
+/// Running totals for one user
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserAggregates {
+    /// Deposits not yet withdrawn
+    pub active_count: usize,
+    /// Deposits ever made, counted against the per-user limit
+    pub lifetime_count: usize,
+    /// Amount of active deposits per token type
+    pub total_active_amount: HashMap<TokenType, u64>,
+}
+
+/// Aggregates and archived deposit IDs for all users
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UserIndex {
+    /// Aggregates by user address
+    pub(crate) aggregates: HashMap<String, UserAggregates>,
+    /// IDs of withdrawn deposits moved out of `user_deposit_ids`, by user address
+    pub(crate) archived_deposit_ids: HashMap<String, Vec<u64>>,
+    /// Unlock time and ID of each active deposit, ordered by unlock time, by user address
+    pub(crate) unlocks: HashMap<String, BTreeSet<(DateTime<Utc>, u64)>>,
+}
+
+impl UserIndex {
+    /// Get the number of deposits counted against a user's limit
+    pub(crate) fn lifetime_count(&self, user_address: &str) -> usize {
+        self.aggregates.get(user_address).map(|aggregates| aggregates.lifetime_count).unwrap_or(0)
+    }
+    
+    /// Account for a new active deposit
+    pub(crate) fn record_deposit(&mut self, user_address: &str, deposit: &Deposit) {
+        let aggregates = self.aggregates.entry(user_address.to_string()).or_default();
+        aggregates.active_count += 1;
+        aggregates.lifetime_count += 1;
+        
+        let total = aggregates.total_active_amount.entry(deposit.deposited_token_type.clone()).or_insert(0);
+        *total = total.saturating_add(deposit.deposited_amount);
+        
+        self.unlocks.entry(user_address.to_string())
+            .or_default()
+            .insert((deposit.unlock_timestamp, deposit.deposit_id));
+    }
+    
+    /// Account for a withdrawn deposit
+    pub(crate) fn record_withdrawal(&mut self, user_address: &str, deposit: &Deposit) {
+        let token_type = &deposit.deposited_token_type;
+        
+        if let Some(aggregates) = self.aggregates.get_mut(user_address) {
+            aggregates.active_count = aggregates.active_count.saturating_sub(1);
+            
+            if let Some(total) = aggregates.total_active_amount.get_mut(token_type) {
+                *total = total.saturating_sub(deposit.deposited_amount);
+                if *total == 0 {
+                    aggregates.total_active_amount.remove(token_type);
+                }
+            }
+        }
+        
+        if let Some(unlocks) = self.unlocks.get_mut(user_address) {
+            unlocks.remove(&(deposit.unlock_timestamp, deposit.deposit_id));
+            if unlocks.is_empty() {
+                self.unlocks.remove(user_address);
+            }
+        }
+    }
+    
+    /// Remove an expired pending deposit, freeing its slot of the per-user limit
+    pub(crate) fn record_expiry(&mut self, user_address: &str, deposit: &Deposit) {
+        self.record_withdrawal(user_address, deposit);
+        
+        if let Some(aggregates) = self.aggregates.get_mut(user_address) {
+            aggregates.lifetime_count = aggregates.lifetime_count.saturating_sub(1);
+        }
+    }
+    
+    /// Move an active deposit from one user to another
+    pub(crate) fn record_assignment(&mut self, from_address: &str, to_address: &str, deposit: &Deposit) {
+        self.record_withdrawal(from_address, deposit);
+        
+        if let Some(aggregates) = self.aggregates.get_mut(from_address) {
+            aggregates.lifetime_count = aggregates.lifetime_count.saturating_sub(1);
+        }
+        
+        self.record_deposit(to_address, deposit);
+    }
+    
+    /// Unlock time and ID of a user's active deposits, in unlock order
+    pub(crate) fn unlocks(&self, user_address: &str) -> impl Iterator<Item = &(DateTime<Utc>, u64)> + '_ {
+        self.unlocks.get(user_address).into_iter().flatten()
+    }
+}
+
+/// Summary of one user's deposits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserSummary {
+    /// User address
+    pub address: String,
+    /// Deposits not yet withdrawn
+    pub active_count: usize,
+    /// Deposits ever made
+    pub lifetime_count: usize,
+    /// Amount of active deposits per token type, sorted by token
+    pub total_active_amount: Vec<(TokenType, u64)>,
+    /// Number of withdrawn deposits moved to the archive
+    pub archived_count: usize,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get a user's deposit counts and active amounts
+    pub fn get_user_summary(&self, user_address: &str) -> UserSummary {
+        let aggregates = self.user_index.aggregates.get(user_address).cloned().unwrap_or_default();
+        
+        UserSummary {
+            address: user_address.to_string(),
+            active_count: aggregates.active_count,
+            lifetime_count: aggregates.lifetime_count,
+            total_active_amount: sorted_by_token(aggregates.total_active_amount.iter()),
+            archived_count: self.user_index.archived_deposit_ids.get(user_address).map(|ids| ids.len()).unwrap_or(0),
+        }
+    }
+    
+    /// Get the IDs of a user's withdrawn deposits that maintenance archived
+    pub fn get_archived_deposit_ids(&self, user_address: &str) -> &[u64] {
+        self.user_index.archived_deposit_ids.get(user_address).map(|ids| ids.as_slice()).unwrap_or(&[])
+    }
+    
+    /// Archive withdrawn deposit IDs out of the per-user lists (owner only)
+    /// 
+    /// Returns the number of IDs archived.
+    pub fn perform_maintenance(&mut self, caller_address: String, expected_nonce: Option<u64>) -> Result<usize, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        let mut archived = 0;
+        
+        for (user_address, ids) in self.user_deposit_ids.iter_mut() {
+            let (withdrawn, active): (Vec<u64>, Vec<u64>) = ids.iter().partition(|id| {
+                self.deposit_registry.get(id).map(|deposit| deposit.is_withdrawn()).unwrap_or(false)
+            });
+            
+            if withdrawn.is_empty() {
+                continue;
+            }
+            
+            archived += withdrawn.len();
+            *ids = active;
+            ids.shrink_to_fit();
+            
+            self.user_index.archived_deposit_ids
+                .entry(user_address.clone())
+                .or_default()
+                .extend(withdrawn);
+        }
+        
+        self.user_deposit_ids.retain(|_, ids| !ids.is_empty());
+        self.last_maintenance = self.clock.now();
+        
+        info!(target: CONTRACT, "Maintenance archived {} withdrawn deposit IDs", archived);
+        
+        self.advance_owner_nonce();
+        
+        Ok(archived)
+    }
+}
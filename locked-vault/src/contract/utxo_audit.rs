@@ -0,0 +1,261 @@
+//! Bulk verification of the UTXOs recorded for deposits
+//!
+//! The `utxo_reference` of a deposit goes stale when the output is spent
+//! outside the vault or its transaction is reorganized out. The audit walks
+//! active Bitcoin deposits in deposit ID order, looks each recorded output up
+//! on the node and classifies it as unspent, spent or unknown, comparing the
+//! on-chain value with the recorded amount.
+//!
+//! A full audit of tens of thousands of deposits can take hours behind the
+//! node RPC rate limiter, so it runs in batches. Each report carries the
+//! cursor to pass to the next batch, and `None` once every deposit was
+//! checked. Spent findings can be fed into deposit suspension.
+//!
+//! A batch reports each lookup to an optional `Progress` and stops with
+//! `Cancelled` before the next lookup once cancelled. The audit only reads,
+//! so a cancelled batch is simply run again from the same cursor. The HTTP
+//! API runs whole audits as background jobs through a `UtxoAuditSource`.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::reorg::ReorgNotice;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenTransfer, TokenType};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::progress::Progress;
+
+/// What the node says about a recorded output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UtxoAuditStatus {
+    /// The output is unspent
+    Unspent {
+        /// Value of the output in satoshis
+        onchain_amount: u64,
+        /// Confirmations of its transaction
+        confirmations: u32,
+    },
+    /// The output is spent, or does not exist on the node's chain
+    Spent,
+    /// The output could not be checked
+    Unknown {
+        /// Why the lookup failed
+        reason: String,
+    },
+}
+
+/// Audit finding for one deposit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoAuditEntry {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Recorded UTXO reference
+    pub utxo_reference: String,
+    /// Amount recorded for the deposit
+    pub recorded_amount: u64,
+    /// What the node says about the output
+    #[serde(flatten)]
+    pub status: UtxoAuditStatus,
+}
+
+impl UtxoAuditEntry {
+    /// On-chain value minus the recorded amount, `None` unless the output is unspent
+    ///
+    /// A positive delta is expected when a deposit fee was taken out of the
+    /// funding output.
+    pub fn amount_delta(&self) -> Option<i64> {
+        match self.status {
+            UtxoAuditStatus::Unspent { onchain_amount, .. } => Some(onchain_amount as i64 - self.recorded_amount as i64),
+            _ => None,
+        }
+    }
+}
+
+/// Position of a batched audit, after the last deposit checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoAuditCursor {
+    /// ID of the last deposit checked
+    pub after_deposit_id: u64,
+}
+
+/// Findings of one batch of the UTXO audit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoAuditReport {
+    /// When the batch was checked
+    pub generated_at: DateTime<Utc>,
+    /// Findings in deposit ID order
+    pub entries: Vec<UtxoAuditEntry>,
+    /// Cursor of the next batch, `None` when the audit is complete
+    pub next_cursor: Option<UtxoAuditCursor>,
+}
+
+impl UtxoAuditReport {
+    /// Whether this batch was the last
+    pub fn is_complete(&self) -> bool {
+        self.next_cursor.is_none()
+    }
+    
+    /// Findings whose output is spent
+    pub fn spent(&self) -> impl Iterator<Item = &UtxoAuditEntry> {
+        self.entries.iter().filter(|entry| entry.status == UtxoAuditStatus::Spent)
+    }
+    
+    /// Findings whose output could not be checked
+    pub fn unknown(&self) -> impl Iterator<Item = &UtxoAuditEntry> {
+        self.entries.iter().filter(|entry| matches!(entry.status, UtxoAuditStatus::Unknown { .. }))
+    }
+    
+    /// Findings whose unspent output holds less than the recorded amount
+    pub fn underfunded(&self) -> impl Iterator<Item = &UtxoAuditEntry> {
+        self.entries.iter().filter(|entry| entry.amount_delta().is_some_and(|delta| delta < 0))
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Check the recorded UTXOs of the first `batch_size` active Bitcoin deposits
+    pub fn verify_deposit_utxos(&self, batch_size: usize, progress: Option<&Progress>) -> Result<UtxoAuditReport, ContractError> {
+        self.verify_deposit_utxos_from(None, batch_size, progress)
+    }
+    
+    /// Check the recorded UTXOs of the next `batch_size` active Bitcoin deposits after `cursor`
+    ///
+    /// Deposits without a UTXO reference are skipped. Every lookup goes
+    /// through the backend's rate limiter.
+    pub fn verify_deposit_utxos_from(&self, cursor: Option<UtxoAuditCursor>, batch_size: usize, progress: Option<&Progress>) -> Result<UtxoAuditReport, ContractError> {
+        let after = cursor.map(|cursor| cursor.after_deposit_id);
+        
+        let mut pending: Vec<(u64, String, u64)> = self.deposit_registry.values()
+            .filter(|deposit| deposit.status == DepositStatus::Active && deposit.deposited_token_type == TokenType::Bitcoin)
+            .filter(|deposit| after.is_none_or(|after| deposit.deposit_id > after))
+            .filter_map(|deposit| deposit.utxo_reference.as_ref().map(|reference| (deposit.deposit_id, reference.to_string(), deposit.deposited_amount)))
+            .collect();
+        pending.sort_by_key(|(deposit_id, _, _)| *deposit_id);
+        
+        let batch_size = batch_size.max(1);
+        let next_cursor = (pending.len() > batch_size)
+            .then(|| UtxoAuditCursor { after_deposit_id: pending[batch_size - 1].0 });
+        pending.truncate(batch_size);
+        
+        let idle = Progress::new();
+        let progress = progress.unwrap_or(&idle);
+        progress.start_stage("utxo_audit", pending.len() as u64);
+        
+        let mut entries = Vec::with_capacity(pending.len());
+        for (deposit_id, utxo_reference, recorded_amount) in pending {
+            progress.check_cancelled()?;
+            
+            let status = match self.token_transfer.get_utxo(&utxo_reference) {
+                Ok(Some(output)) => UtxoAuditStatus::Unspent {
+                    onchain_amount: output.value,
+                    confirmations: output.confirmations,
+                },
+                Ok(None) => UtxoAuditStatus::Spent,
+                Err(reason) => UtxoAuditStatus::Unknown { reason },
+            };
+            
+            entries.push(UtxoAuditEntry {
+                deposit_id,
+                utxo_reference,
+                recorded_amount,
+                status,
+            });
+            progress.advance(1);
+        }
+        
+        Ok(UtxoAuditReport {
+            generated_at: self.clock.now(),
+            entries,
+            next_cursor,
+        })
+    }
+    
+    /// Suspend the deposits an audit found spent, returning the emitted events
+    ///
+    /// Goes through the same suspension as reorganized funding transactions,
+    /// so the deposits stay suspended until a `ReorgNotice::Reinstated` is
+    /// applied. Deposits that are no longer active are left alone.
+    pub fn suspend_spent_deposits(&mut self, report: &UtxoAuditReport) -> Result<Vec<Event>, ContractError> {
+        self.ensure_writable()?;
+        
+        let mut events = Vec::new();
+        
+        for entry in report.spent() {
+            let txid = entry.utxo_reference.split_once(':')
+                .map_or(entry.utxo_reference.as_str(), |(txid, _)| txid);
+            let notice = ReorgNotice::Suspended {
+                deposit_id: entry.deposit_id,
+                txid: txid.to_string(),
+                reason: format!("Funding output {} is spent", entry.utxo_reference),
+            };
+            
+            events.extend(self.apply_reorg_notice(&notice)?);
+        }
+        
+        Ok(events)
+    }
+}
+
+/// Outcome of an audit of every active Bitcoin deposit
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct UtxoAuditSummary {
+    /// Deposits checked
+    pub checked: usize,
+    /// Deposits whose recorded output is spent
+    pub spent: Vec<u64>,
+    /// Deposits whose output could not be looked up
+    pub unknown: Vec<u64>,
+    /// Deposits whose output holds less than recorded
+    pub underfunded: Vec<u64>,
+}
+
+/// Source of full UTXO audits, shared with the HTTP API
+pub trait UtxoAuditSource: Send + Sync + fmt::Debug {
+    /// Audit every active Bitcoin deposit in batches of `batch_size`, reporting to `progress`
+    fn audit_all(&self, batch_size: usize, progress: &Progress) -> Result<UtxoAuditSummary, ContractError>;
+}
+
+/// Full audits of a running contract, locking it one batch at a time
+pub struct UtxoAuditor<T: TokenTransfer> {
+    /// Contract holding the deposits
+    contract: Arc<Mutex<TimeLockedDeposit<T>>>,
+}
+
+impl<T: TokenTransfer> fmt::Debug for UtxoAuditor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UtxoAuditor").finish_non_exhaustive()
+    }
+}
+
+impl<T: TokenTransfer> UtxoAuditor<T> {
+    /// Create an auditor over a contract
+    pub fn new(contract: Arc<Mutex<TimeLockedDeposit<T>>>) -> Self {
+        Self { contract }
+    }
+}
+
+impl<T: TokenTransfer + Send> UtxoAuditSource for UtxoAuditor<T> {
+    fn audit_all(&self, batch_size: usize, progress: &Progress) -> Result<UtxoAuditSummary, ContractError> {
+        let mut summary = UtxoAuditSummary::default();
+        let mut cursor = None;
+        
+        loop {
+            let report = self.contract.lock()
+                .map_err(|_| ContractError::InitializationError("Contract lock poisoned".to_string()))?
+                .verify_deposit_utxos_from(cursor, batch_size, Some(progress))?;
+            
+            summary.checked += report.entries.len();
+            summary.spent.extend(report.spent().map(|entry| entry.deposit_id));
+            summary.unknown.extend(report.unknown().map(|entry| entry.deposit_id));
+            summary.underfunded.extend(report.underfunded().map(|entry| entry.deposit_id));
+            
+            cursor = report.next_cursor;
+            if cursor.is_none() {
+                return Ok(summary);
+            }
+        }
+    }
+}
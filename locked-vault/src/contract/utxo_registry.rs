@@ -0,0 +1,62 @@
+//! Registry of UTXO references consumed by deposits
+//!
+//! Each on-chain output may back at most one deposit. References stay
+//! registered after withdrawal because the output was genuinely consumed.
+
+use std::collections::HashMap;
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::ContractError;
+use crate::models::TokenTransfer;
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Normalize a `txid:vout` reference: strip whitespace and lowercase the txid
+pub fn normalize_utxo_reference(reference: &str) -> String {
+    reference.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Consumed UTXO references mapped to the deposit that consumed them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UtxoRegistry {
+    /// Deposit ID by reference
+    pub(crate) references: HashMap<UtxoRef, u64>,
+}
+
+impl UtxoRegistry {
+    /// Fail if a reference already backs a deposit
+    pub(crate) fn ensure_available(&self, reference: &UtxoRef) -> Result<(), ContractError> {
+        if self.references.contains_key(reference) {
+            return Err(ContractError::UtxoAlreadyDeposited);
+        }
+        
+        Ok(())
+    }
+    
+    /// Record a reference as consumed by a deposit
+    pub(crate) fn register(&mut self, reference: UtxoRef, deposit_id: u64) {
+        self.references.insert(reference, deposit_id);
+    }
+    
+    /// Get the deposit that consumed an outpoint given as text, if any
+    pub(crate) fn owner_of(&self, outpoint: &str) -> Option<u64> {
+        let reference = outpoint.parse::<UtxoRef>().ok()?;
+        self.references.get(&reference).copied()
+    }
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get the deposit that consumed a UTXO reference, if any
+    /// 
+    /// References that do not parse were never consumed.
+    pub fn utxo_reference_owner(&self, utxo_reference: &str) -> Option<u64> {
+        self.utxo_registry.owner_of(utxo_reference)
+    }
+    
+    /// Release the reference of a deposit that was cancelled before its funds settled
+    pub(crate) fn release_utxo_reference(&mut self, deposit_id: u64) {
+        self.utxo_registry.references.retain(|_, owner| *owner != deposit_id);
+    }
+}
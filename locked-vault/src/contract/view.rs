@@ -0,0 +1,125 @@
+//! Per-deposit views with computed fields
+//!
+//! Exposes the unlock status and emergency exit cost of a deposit using the
+//! same fee math as `emergency_withdraw`, the locking descriptor of script
+//! escrow deposits, and the Ordinals metadata of the inscriptions the vault
+//! holds.
+
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::ordinals::Inscription;
+use crate::errors::ContractError;
+use crate::models::{AssetDetail, Deposit, TokenTransfer, TokenType};
+use crate::contract::contract_core::TimeLockedDeposit;
+
+/// Deposit with computed unlock and emergency exit information
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepositView {
+    /// The deposit itself
+    #[serde(flatten)]
+    pub deposit: Deposit,
+    /// Whether the time lock has expired
+    pub is_unlocked: bool,
+    /// Time left until unlock, `None` once unlocked
+    pub time_remaining: Option<Duration>,
+    /// Fee percentage an emergency withdrawal would pay now
+    pub effective_fee_percentage: u8,
+    /// Fee an emergency withdrawal would pay now
+    pub emergency_fee_if_now: u64,
+    /// Amount an emergency withdrawal would pay out now
+    pub net_emergency_payout_if_now: u64,
+    /// Resource backing the deposit
+    pub asset_detail: Option<AssetDetail>,
+    /// Ordinals metadata of the deposited inscription, when the backend has it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inscription: Option<Inscription>,
+    /// Output descriptor of the locking terms, for script escrow deposits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locking_descriptor: Option<String>,
+    /// Whether the descriptor re-derives the escrow address, for script escrow deposits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub descriptor_matches_address: Option<bool>,
+}
+
+/// Inscription held by the vault for an ordinal deposit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeldInscription {
+    /// Deposit holding the inscription
+    pub deposit_id: u64,
+    /// Depositor address
+    pub depositor: String,
+    /// Inscription ID
+    pub inscription_id: String,
+    /// When the deposit unlocks
+    pub unlock_at: DateTime<Utc>,
+    /// Ordinals metadata, when the backend has it
+    pub details: Option<Inscription>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Get a deposit together with its unlock status and current emergency exit cost
+    pub fn get_deposit_view(&self, deposit_id: u64) -> Result<DepositView, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id)
+            .ok_or(ContractError::DepositNotFound)?;
+        
+        let now = self.clock.now();
+        let is_unlocked = now >= deposit.unlock_timestamp;
+        let time_remaining = if is_unlocked {
+            None
+        } else {
+            (deposit.unlock_timestamp - now).to_std().ok()
+        };
+        
+        // Same fee path as emergency_withdraw
+        let effective_fee_percentage = Self::effective_emergency_fee_percentage(&self.fee_config, deposit, now);
+        let (emergency_fee_if_now, net_emergency_payout_if_now) =
+            Self::compute_emergency_fee(deposit.deposited_amount, effective_fee_percentage)?;
+        
+        Ok(DepositView {
+            deposit: deposit.clone(),
+            is_unlocked,
+            time_remaining,
+            effective_fee_percentage,
+            emergency_fee_if_now,
+            net_emergency_payout_if_now,
+            asset_detail: deposit.asset_detail(),
+            inscription: self.inscription_details(deposit),
+            locking_descriptor: deposit.locking_descriptor(),
+            descriptor_matches_address: deposit.escrow.as_ref()
+                .map(|_| deposit.verify_descriptor_matches_address().unwrap_or(false)),
+        })
+    }
+    
+    /// Inscriptions of the ordinal deposits not yet withdrawn, by deposit ID
+    /// 
+    /// Metadata lookups that fail leave `details` unset rather than failing the report.
+    pub fn list_held_inscriptions(&self) -> Vec<HeldInscription> {
+        let mut held: Vec<HeldInscription> = self.deposits_iter()
+            .filter(|deposit| !deposit.is_closed())
+            .filter_map(|deposit| match &deposit.deposited_token_type {
+                TokenType::Ordinal(inscription_id) => Some(HeldInscription {
+                    deposit_id: deposit.deposit_id,
+                    depositor: deposit.depositor_address.clone(),
+                    inscription_id: inscription_id.clone(),
+                    unlock_at: deposit.unlock_timestamp,
+                    details: self.inscription_details(deposit),
+                }),
+                _ => None,
+            })
+            .collect();
+        
+        held.sort_by_key(|inscription| inscription.deposit_id);
+        held
+    }
+    
+    /// Ordinals metadata of an ordinal deposit's inscription, if the backend can provide it
+    fn inscription_details(&self, deposit: &Deposit) -> Option<Inscription> {
+        let TokenType::Ordinal(inscription_id) = &deposit.deposited_token_type else {
+            return None;
+        };
+        
+        self.token_transfer.get_inscription_details(inscription_id).ok().flatten()
+    }
+}
@@ -0,0 +1,217 @@
+//! Voiding pending deposits created by mistake
+//!
+//! The owner can void a deposit that is still pending its funding, e.g. one
+//! created with the wrong amount or for the wrong depositor. Voiding keeps
+//! the deposit and the reason on record but gives back what it held like an
+//! expiry does: its limit slot, the token total it counted towards and its
+//! UTXO reference. A funding transfer the backend still holds in its queue is
+//! cancelled; one that was already broadcast cannot be stopped, so the
+//! deposit is not voided.
+//!
+//! Funding that confirms after the deposit was voided is recorded as
+//! unattributed funds. While it is listed there, the owner can restore the
+//! deposit, which consumes the output and funds the deposit as if it had
+//! never been voided.
+
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::errors::{ContractError, TransferStage};
+use crate::events::Event;
+use crate::models::{DepositStatus, TokenTransfer};
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::logging::CONTRACT;
+
+/// Record of a pending deposit the owner voided
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoidedDeposit {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Why the owner voided the deposit
+    pub reason: String,
+    /// Whether a queued funding transfer was cancelled
+    pub transfer_cancelled: bool,
+    /// When the deposit was voided
+    pub voided_at: DateTime<Utc>,
+}
+
+impl<T: TokenTransfer> TimeLockedDeposit<T> {
+    /// Void a deposit that is still pending its funding (owner only)
+    ///
+    /// Active, withdrawn and expired deposits are refused with
+    /// `DepositNotPending`. Fails without voiding anything if the deposit's
+    /// funding transfer was already broadcast.
+    pub fn void_pending_deposit(
+        &mut self,
+        caller_address: String,
+        deposit_id: u64,
+        reason: String,
+        expected_nonce: Option<u64>,
+    ) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // An overdue deposit is expired, not voided
+        let now = self.clock.now();
+        self.expire_if_stale(deposit_id, now);
+        
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        if deposit.status != DepositStatus::Pending {
+            return Err(ContractError::DepositNotPending);
+        }
+        
+        // Stop the funding transfer before giving anything back
+        let transfer_cancelled = self.token_transfer.cancel_pending(deposit_id)
+            .map_err(|e| ContractError::transfer_failed(TransferStage::Deposit, e))?;
+        
+        let deposit = self.deposit_registry.get_mut(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        deposit.status = DepositStatus::Voided;
+        deposit.last_modified = now;
+        
+        // Give back the token total and the limit slot
+        if let Some(total) = self.total_deposits.get_mut(&deposit.deposited_token_type) {
+            *total = total.saturating_sub(deposit.deposited_amount);
+        }
+        self.user_index.record_expiry(&deposit.depositor_address, deposit);
+        
+        let event = Event::DepositVoided {
+            deposit_id,
+            depositor_address: deposit.depositor_address.clone(),
+            token_type: deposit.deposited_token_type.clone(),
+            amount: deposit.deposited_amount,
+            reason: reason.clone(),
+            transfer_cancelled,
+            timestamp: now,
+        };
+        
+        // Let the output back another deposit or the unattributed ledger
+        self.release_utxo_reference(deposit_id);
+        
+        self.voided_deposits.insert(deposit_id, VoidedDeposit {
+            deposit_id,
+            reason,
+            transfer_cancelled,
+            voided_at: now,
+        });
+        
+        info!(target: CONTRACT, "Pending deposit {} voided", deposit_id);
+        
+        self.emit(&event);
+        
+        self.advance_owner_nonce();
+        
+        Ok(event)
+    }
+    
+    /// Restore a voided deposit whose funding arrived as unattributed funds (owner only)
+    ///
+    /// The output the deposit was waiting for has to be listed as
+    /// unattributed funds and cover the deposit and its fee. The deposit takes
+    /// its limit slot back, subject to the current limits, and is funded,
+    /// emitting `Deposited` before `DepositRestored`. The entry stays listed if
+    /// the deposit cannot be restored.
+    pub fn restore_voided_deposit(&mut self, caller_address: String, deposit_id: u64, expected_nonce: Option<u64>) -> Result<Event, ContractError> {
+        // Check authorization
+        if !self.is_owner(&caller_address) {
+            return Err(ContractError::Unauthorized);
+        }
+        
+        // Reject replayed owner instructions
+        self.check_owner_nonce(expected_nonce)?;
+        
+        // Check contract state
+        if self.is_contract_paused {
+            return Err(ContractError::ContractPaused);
+        }
+        
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        if deposit.status != DepositStatus::Voided {
+            return Err(ContractError::DepositNotVoided);
+        }
+        
+        // Take the entry out first so it cannot be used twice
+        let reference = deposit.utxo_reference.clone().ok_or(ContractError::UnattributedFundsNotFound)?;
+        let outpoint = reference.to_string();
+        let funds = self.unattributed_funds.remove(&outpoint)
+            .ok_or(ContractError::UnattributedFundsNotFound)?;
+        
+        // Keep the entry listed if the deposit cannot be restored
+        let result = self.reinstate_voided_deposit(deposit_id, reference, funds.amount);
+        match result {
+            Ok(_) => self.advance_owner_nonce(),
+            Err(_) => {
+                self.unattributed_funds.insert(outpoint, funds);
+            },
+        }
+        
+        result
+    }
+    
+    /// Get the record of a voided deposit
+    pub fn get_voided_deposit(&self, deposit_id: u64) -> Option<&VoidedDeposit> {
+        self.voided_deposits.get(&deposit_id)
+    }
+    
+    /// List the records of voided deposits, by deposit ID
+    pub fn list_voided_deposits(&self) -> Vec<VoidedDeposit> {
+        let mut voided: Vec<VoidedDeposit> = self.voided_deposits.values().cloned().collect();
+        voided.sort_by_key(|voided| voided.deposit_id);
+        voided
+    }
+    
+    /// Fund a voided deposit from its output and take back what it gave up
+    fn reinstate_voided_deposit(&mut self, deposit_id: u64, reference: UtxoRef, funded_amount: u64) -> Result<Event, ContractError> {
+        let deposit = self.deposit_registry.get(&deposit_id).ok_or(ContractError::DepositNotFound)?;
+        let depositor_address = deposit.depositor_address.clone();
+        let token_type = deposit.deposited_token_type.clone();
+        let locked_amount = deposit.deposited_amount;
+        let fee_amount = deposit.pending.map_or(0, |pending| pending.fee_amount);
+        
+        // The output has to cover the deposit and its fee
+        let required = locked_amount.checked_add(fee_amount).ok_or(ContractError::ArithmeticError)?;
+        if funded_amount < required {
+            return Err(ContractError::InsufficientBalance { available: funded_amount, required });
+        }
+        
+        // The limit slot was given back, so the deposit has to fit again
+        self.deposit_limit_snapshot(&depositor_address, &token_type)
+            .check(&self.deposit_limits, locked_amount)?;
+        self.utxo_registry.ensure_available(&reference)?;
+        let new_total = self.total_deposits.get(&token_type)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(locked_amount)
+            .ok_or(ContractError::ArithmeticError)?;
+        
+        let now = self.clock.now();
+        self.fund_pending_deposit(deposit_id, fee_amount, now)?;
+        
+        // Take back the output, the token total and the limit slot
+        self.utxo_registry.register(reference.clone(), deposit_id);
+        self.total_deposits.insert(token_type, new_total);
+        if let Some(deposit) = self.deposit_registry.get(&deposit_id) {
+            self.user_index.record_deposit(&depositor_address, deposit);
+        }
+        self.voided_deposits.remove(&deposit_id);
+        
+        info!(target: CONTRACT, "Voided deposit {} restored from {}", deposit_id, reference);
+        
+        let event = Event::DepositRestored {
+            deposit_id,
+            depositor_address,
+            outpoint: reference.to_string(),
+            timestamp: now,
+        };
+        
+        self.emit(&event);
+        
+        Ok(event)
+    }
+}
@@ -0,0 +1,259 @@
+//! Fixed-format display of amounts, timestamps and tables for CLI output
+//!
+//! CLI output is read by scripts as much as by people, so nothing here
+//! depends on the locale, floating point rounding or chrono's default
+//! formats. Amounts are printed from their integer units with a fixed number
+//! of decimal places and the token's symbol, e.g. `0.00150000 BTC`.
+//! Timestamps are RFC 3339 in UTC with whole seconds, e.g.
+//! `2024-03-01T12:00:00Z`.
+//!
+//! `Table` lines up columns for people, or with `TableStyle::Plain` separates
+//! them with tabs for scripts. Both styles print the header row first.
+
+use std::fmt::Write;
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::bitcoin::health::HealthStatus;
+use crate::contract::summary::DepositSummary;
+use crate::models::{AmountConstraints, Deposit, DepositStatus, TokenType, AMOUNT_DECIMALS};
+
+/// Decimal places an amount of a token is printed with
+///
+/// Amounts of every token count `AMOUNT_DECIMALS` decimal places, except
+/// Ordinal inscriptions, which are counted whole.
+pub fn token_decimals(token_type: &TokenType) -> u8 {
+    match token_type {
+        TokenType::Ordinal(_) => 0,
+        _ => AMOUNT_DECIMALS,
+    }
+}
+
+/// Symbol printed after an amount of a token
+pub fn token_symbol(token_type: &TokenType) -> &str {
+    match token_type {
+        TokenType::Bitcoin => "BTC",
+        TokenType::Lightning => "LNBTC",
+        TokenType::Ethereum => "ETH",
+        TokenType::Solana => "SOL",
+        TokenType::Rune(id) | TokenType::Custom(id) => id,
+        TokenType::Ordinal(_) => "inscription",
+    }
+}
+
+/// Format an amount with all of its token's decimal places and its symbol
+pub fn format_amount(token_type: &TokenType, amount: u64) -> String {
+    format!("{} {}", format_units(amount, token_decimals(token_type)), token_symbol(token_type))
+}
+
+/// Format an amount with the decimal places the token divides into
+///
+/// A rune with a divisibility of 2 prints `12.50 RUNE_ID` instead of
+/// `12.50000000 RUNE_ID`. Amounts that do not fit the divisibility print
+/// every decimal place, so nothing is hidden.
+pub fn format_amount_with(token_type: &TokenType, amount: u64, constraints: &AmountConstraints) -> String {
+    let decimals = token_decimals(token_type);
+    let shown = constraints.divisibility.map_or(decimals, |divisibility| divisibility.min(decimals));
+    let step = 10u64.pow(u32::from(decimals - shown));
+    
+    let units = if amount.is_multiple_of(step) {
+        format_units(amount / step, shown)
+    } else {
+        format_units(amount, decimals)
+    };
+    
+    format!("{} {}", units, token_symbol(token_type))
+}
+
+/// Format integer units with a fixed number of decimal places
+fn format_units(amount: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+    
+    let scale = 10u64.pow(u32::from(decimals));
+    format!("{}.{:0width$}", amount / scale, amount % scale, width = usize::from(decimals))
+}
+
+/// Format a timestamp as RFC 3339 in UTC with whole seconds
+pub fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Format an optional timestamp, `-` without one
+pub fn format_optional_timestamp(timestamp: Option<DateTime<Utc>>) -> String {
+    timestamp.map_or_else(|| "-".to_string(), format_timestamp)
+}
+
+/// Stable name of a deposit status
+pub fn format_status(status: &DepositStatus) -> &'static str {
+    match status {
+        DepositStatus::Active => "active",
+        DepositStatus::Withdrawn => "withdrawn",
+        DepositStatus::EmergencyWithdrawn => "emergency_withdrawn",
+        DepositStatus::Suspended => "suspended",
+        DepositStatus::Pending => "pending",
+        DepositStatus::Expired => "expired",
+        DepositStatus::WithdrawalPending => "withdrawal_pending",
+        DepositStatus::Voided => "voided",
+    }
+}
+
+/// How a table is rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TableStyle {
+    /// Columns padded to line up, separated by two spaces
+    #[default]
+    Aligned,
+    /// Columns separated by a tab, for scripts
+    Plain,
+}
+
+impl TableStyle {
+    /// `Plain` if `--plain` was passed, `Aligned` otherwise
+    pub fn from_plain_flag(plain: bool) -> Self {
+        if plain { TableStyle::Plain } else { TableStyle::Aligned }
+    }
+}
+
+/// Rows of text under a header, rendered as aligned columns or tab-separated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    /// Column headers
+    headers: Vec<String>,
+    /// Whether each column is aligned right, as numbers are
+    right_aligned: Vec<bool>,
+    /// Rows, each with one cell per column
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Create an empty table with left-aligned columns
+    pub fn new(headers: &[&str]) -> Self {
+        Self {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            right_aligned: vec![false; headers.len()],
+            rows: Vec::new(),
+        }
+    }
+    
+    /// Align a column right
+    pub fn align_right(mut self, column: usize) -> Self {
+        if let Some(right_aligned) = self.right_aligned.get_mut(column) {
+            *right_aligned = true;
+        }
+        self
+    }
+    
+    /// Add a row, padding missing cells and dropping extra ones
+    pub fn push_row(&mut self, mut row: Vec<String>) {
+        row.resize(self.headers.len(), String::new());
+        self.rows.push(row);
+    }
+    
+    /// Number of rows, not counting the header
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+    
+    /// Whether the table has no rows
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    
+    /// Render the header and rows, one line each, ending with a newline
+    pub fn render(&self, style: TableStyle) -> String {
+        let lines = std::iter::once(&self.headers).chain(&self.rows);
+        let mut output = String::new();
+        
+        match style {
+            TableStyle::Plain => {
+                for line in lines {
+                    let cells: Vec<String> = line.iter().map(|cell| cell.replace(['\t', '\n'], " ")).collect();
+                    output.push_str(&cells.join("\t"));
+                    output.push('\n');
+                }
+            },
+            TableStyle::Aligned => {
+                let widths: Vec<usize> = (0..self.headers.len())
+                    .map(|column| {
+                        std::iter::once(&self.headers).chain(&self.rows)
+                            .map(|line| line[column].chars().count())
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect();
+                
+                for line in lines {
+                    let mut rendered = String::new();
+                    for (column, cell) in line.iter().enumerate() {
+                        if column > 0 {
+                            rendered.push_str("  ");
+                        }
+                        let padding = widths[column] - cell.chars().count();
+                        if self.right_aligned[column] {
+                            let _ = write!(rendered, "{}{}", " ".repeat(padding), cell);
+                        } else {
+                            let _ = write!(rendered, "{}{}", cell, " ".repeat(padding));
+                        }
+                    }
+                    output.push_str(rendered.trim_end());
+                    output.push('\n');
+                }
+            },
+        }
+        
+        output
+    }
+}
+
+/// Table of deposits, one row each in the order given
+pub fn deposit_table<'a>(deposits: impl IntoIterator<Item = &'a Deposit>) -> Table {
+    let mut table = Table::new(&["ID", "DEPOSITOR", "TOKEN", "AMOUNT", "STATUS", "DEPOSITED", "UNLOCKS"]).align_right(0).align_right(3);
+    
+    for deposit in deposits {
+        table.push_row(vec![
+            deposit.deposit_id.to_string(),
+            deposit.depositor_address.clone(),
+            deposit.deposited_token_type.name(),
+            format_amount(&deposit.deposited_token_type, deposit.deposited_amount),
+            format_status(&deposit.status).to_string(),
+            format_timestamp(deposit.deposit_timestamp),
+            format_timestamp(deposit.unlock_timestamp),
+        ]);
+    }
+    
+    table
+}
+
+/// Table of deposit summaries, one row each in the order given
+pub fn deposit_summary_table<'a>(summaries: impl IntoIterator<Item = &'a DepositSummary>) -> Table {
+    let mut table = Table::new(&["ID", "TOKEN", "AMOUNT", "STATUS", "UNLOCKS"]).align_right(0).align_right(2);
+    
+    for summary in summaries {
+        table.push_row(vec![
+            summary.id.to_string(),
+            summary.token.name(),
+            format_amount(&summary.token, summary.amount),
+            format_status(&summary.status).to_string(),
+            format_timestamp(summary.unlock_at),
+        ]);
+    }
+    
+    table
+}
+
+/// Table of backend health, one row per component in the order given
+pub fn health_table(statuses: &[HealthStatus]) -> Table {
+    let mut table = Table::new(&["COMPONENT", "STATE", "LATENCY", "ERROR"]).align_right(2);
+    
+    for status in statuses {
+        table.push_row(vec![
+            status.component.to_string(),
+            if status.healthy { "healthy" } else { "UNHEALTHY" }.to_string(),
+            status.latency.map_or_else(|| "-".to_string(), |latency| format!("{}ms", latency.as_millis())),
+            status.last_error.clone().filter(|_| !status.healthy).unwrap_or_default(),
+        ]);
+    }
+    
+    table
+}
@@ -0,0 +1,407 @@
+//! Startup self-test of the whole configuration
+//!
+//! `run_self_test` checks, without moving any funds, everything a withdrawal
+//! will later depend on: the node and its chain, the wallet and whether it
+//! can sign for the contract address, fee estimation, the optional Lightning
+//! and Ordinals backends, persistence paths, the local clock and the owner
+//! address. Each check passes, warns or fails with a hint on how to fix it.
+//! The daemon refuses to start while any check fails (see `time_locked_deposit
+//! doctor` and `--force`).
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use bitcoincore_rpc::{Auth, Client};
+use bitcoincore_rpc::bitcoin::Network;
+use serde::Serialize;
+
+use crate::bitcoin::fee_oracle::ConfirmationTarget;
+use crate::bitcoin::health::{HealthProbe, MIN_VERIFICATION_PROGRESS};
+use crate::bitcoin::lightning::LightningClient;
+use crate::bitcoin::ordinals::OrdinalsClient;
+use crate::bitcoin::rpc::BitcoinRpcClient;
+use crate::clock::{Clock, SystemClock};
+use crate::config::VaultConfig;
+
+/// Chain name `getblockchaininfo` reports for a network
+fn expected_chain(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "main",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+        _ => "test",
+    }
+}
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    /// Works as configured
+    Pass,
+    /// Works, but something will likely go wrong later
+    Warn,
+    /// Broken; the daemon will not start
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckStatus::Pass => write!(f, "PASS"),
+            CheckStatus::Warn => write!(f, "WARN"),
+            CheckStatus::Fail => write!(f, "FAIL"),
+        }
+    }
+}
+
+/// Result of a single check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CheckResult {
+    /// Name of the check
+    pub name: String,
+    /// Outcome
+    pub status: CheckStatus,
+    /// What was found
+    pub detail: String,
+    /// How to fix it, unless the check passed
+    pub remediation: Option<String>,
+}
+
+impl CheckResult {
+    /// Passed check
+    fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            remediation: None,
+        }
+    }
+    
+    /// Check that passed with a warning
+    fn warn(name: &str, detail: impl Into<String>, remediation: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+    
+    /// Failed check
+    fn fail(name: &str, detail: impl Into<String>, remediation: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            remediation: Some(remediation.into()),
+        }
+    }
+    
+    /// Check that could not run because the node is unreachable
+    fn node_unreachable(name: &str) -> Self {
+        Self::fail(name, "Not checked: the Bitcoin RPC node is unreachable", "Fix the bitcoin-rpc check first")
+    }
+}
+
+/// Results of every check, in the order they ran
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SelfTestReport {
+    /// Check results
+    pub checks: Vec<CheckResult>,
+}
+
+impl SelfTestReport {
+    /// Whether no check failed; warnings do not count
+    pub fn passed(&self) -> bool {
+        !self.checks.iter().any(|check| check.status == CheckStatus::Fail)
+    }
+    
+    /// Get the result of a check by name
+    pub fn check(&self, name: &str) -> Option<&CheckResult> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+    
+    /// Number of checks with a given outcome
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|check| check.status == status).count()
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "{}  {:<16} {}", check.status, check.name, check.detail)?;
+            if let Some(remediation) = &check.remediation {
+                writeln!(f, "      {:<16} hint: {}", "", remediation)?;
+            }
+        }
+        
+        write!(
+            f,
+            "{} passed, {} warnings, {} failed",
+            self.count(CheckStatus::Pass),
+            self.count(CheckStatus::Warn),
+            self.count(CheckStatus::Fail),
+        )
+    }
+}
+
+/// Thresholds and extra inputs of the self-test
+#[derive(Debug, Clone)]
+pub struct SelfTestOptions {
+    /// Largest accepted difference between the local clock and the node's median time
+    ///
+    /// The median time of the last 11 blocks normally trails the wall clock
+    /// by about an hour, so this should stay well above that.
+    pub max_clock_skew: Duration,
+    /// Fee rate (sat/vB) below which the estimate looks like a fallback
+    pub min_fee_rate: f64,
+    /// Fee rate (sat/vB) above which the estimate is not trusted
+    pub max_fee_rate: f64,
+    /// Directories the vault writes to
+    pub persistence_paths: Vec<PathBuf>,
+}
+
+impl Default for SelfTestOptions {
+    fn default() -> Self {
+        Self {
+            max_clock_skew: Duration::from_secs(2 * 60 * 60),
+            min_fee_rate: 1.0,
+            max_fee_rate: 1000.0,
+            persistence_paths: Vec::new(),
+        }
+    }
+}
+
+/// Backends the self-test talks to
+#[derive(Debug, Clone)]
+pub struct SelfTestBackends {
+    /// Bitcoin node
+    pub rpc: Arc<BitcoinRpcClient>,
+    /// Lightning backend, if configured
+    pub lightning: Option<Arc<dyn HealthProbe>>,
+    /// Ordinals API, if configured
+    pub ordinals: Option<Arc<dyn HealthProbe>>,
+    /// Local clock compared against the node
+    pub clock: Arc<dyn Clock>,
+}
+
+impl SelfTestBackends {
+    /// Create the backends of a configuration without contacting them
+    pub fn from_config(config: &VaultConfig) -> Result<Self, String> {
+        let bitcoin_config = config.bitcoin_config();
+        let auth = Auth::UserPass(bitcoin_config.rpc_username.clone(), bitcoin_config.rpc_password.expose_secret().clone());
+        let client = Client::new(&bitcoin_config.rpc_url, auth)
+            .map_err(|e| format!("Failed to create RPC client: {}", e))?;
+        let rpc = Arc::new(BitcoinRpcClient::from_client(client, &bitcoin_config));
+        
+        let lightning = config.lightning_node_url.clone().map(|url| -> Arc<dyn HealthProbe> {
+            Arc::new(LightningClient::new(rpc.clone(), url, config.lightning_macaroon.clone().unwrap_or_default()))
+        });
+        
+        let ordinals = config.ordinals_api_url.clone().map(|url| -> Arc<dyn HealthProbe> {
+            Arc::new(OrdinalsClient::with_cache_config(rpc.clone(), url, bitcoin_config.inscription_cache.clone())
+                .with_api_flavor(config.ordinals_api_flavor))
+        });
+        
+        Ok(Self {
+            rpc,
+            lightning,
+            ordinals,
+            clock: Arc::new(SystemClock),
+        })
+    }
+}
+
+/// Check a configuration end to end with default thresholds
+///
+/// Never moves funds. A configuration whose backends cannot even be created
+/// yields a report with a single failed `config` check.
+pub fn run_self_test(config: &VaultConfig) -> SelfTestReport {
+    match SelfTestBackends::from_config(config) {
+        Ok(backends) => run_self_test_with(config, &backends, &SelfTestOptions::default()),
+        Err(e) => SelfTestReport {
+            checks: vec![CheckResult::fail("config", e, "Check the RPC URL and credentials")],
+        },
+    }
+}
+
+/// Check a configuration against the given backends
+pub fn run_self_test_with(config: &VaultConfig, backends: &SelfTestBackends, options: &SelfTestOptions) -> SelfTestReport {
+    let mut checks = Vec::new();
+    let rpc = &backends.rpc;
+    let guard = config.network_guard();
+    let expected_chain = expected_chain(config.network);
+    
+    // Node connectivity and chain
+    let blockchain_info = rpc.get_blockchain_info();
+    let node_reachable = blockchain_info.is_ok();
+    checks.push(match &blockchain_info {
+        Err(e) => CheckResult::fail(
+            "bitcoin-rpc",
+            format!("Cannot reach the node: {}", e),
+            "Check BITCOIN_TESTNET_RPC_URL and the RPC credentials, and that bitcoind runs with -testnet and -server",
+        ),
+        Ok(info) if info.chain != expected_chain => CheckResult::fail(
+            "bitcoin-rpc",
+            format!("Node is on the {} chain, expected {}", info.chain, expected_chain),
+            "Point BITCOIN_TESTNET_RPC_URL at a testnet node",
+        ),
+        Ok(info) if info.initial_block_download || info.verification_progress < MIN_VERIFICATION_PROGRESS => CheckResult::warn(
+            "bitcoin-rpc",
+            format!("Node is syncing (height {}, verification progress {:.4})", info.blocks, info.verification_progress),
+            "Wait for the node to finish syncing before accepting deposits",
+        ),
+        Ok(info) => CheckResult::pass("bitcoin-rpc", format!("Connected to the {} chain at height {}", info.chain, info.blocks)),
+    });
+    
+    // Wallet loaded and able to sign
+    checks.push(if !node_reachable {
+        CheckResult::node_unreachable("wallet")
+    } else {
+        match rpc.get_wallet_status() {
+            Err(e) => CheckResult::fail(
+                "wallet",
+                format!("No wallet loaded: {}", e),
+                "Load the contract wallet with `bitcoin-cli -testnet loadwallet <name>`",
+            ),
+            Ok(wallet) if !wallet.private_keys_enabled => CheckResult::warn(
+                "wallet",
+                format!("Wallet \"{}\" is watch-only; payouts cannot be signed by the node", wallet.name),
+                "Load a wallet holding the contract keys, or sign payouts externally",
+            ),
+            Ok(wallet) => CheckResult::pass("wallet", format!("Wallet \"{}\" loaded with private keys", wallet.name)),
+        }
+    });
+    
+    // Fee estimation
+    let normal_blocks = rpc.fee_oracle().blocks(ConfirmationTarget::Normal);
+    checks.push(if !node_reachable {
+        CheckResult::node_unreachable("fee-estimation")
+    } else {
+        match rpc.get_fee_estimate(ConfirmationTarget::Normal) {
+            Err(e) => CheckResult::fail(
+                "fee-estimation",
+                format!("No fee estimate for {} blocks: {}", normal_blocks, e),
+                "Let the node see more blocks, or set -fallbackfee on the node",
+            ),
+            Ok(rate) if rate > options.max_fee_rate => CheckResult::fail(
+                "fee-estimation",
+                format!("Estimate of {:.1} sat/vB exceeds {:.1} sat/vB", rate, options.max_fee_rate),
+                "Check the node's mempool and fee estimation settings",
+            ),
+            Ok(rate) if rate < options.min_fee_rate => CheckResult::warn(
+                "fee-estimation",
+                format!("Estimate of {:.2} sat/vB is below {:.1} sat/vB and may not relay", rate, options.min_fee_rate),
+                "Let the node see more blocks so its estimates settle",
+            ),
+            Ok(rate) => CheckResult::pass("fee-estimation", format!("{:.1} sat/vB for {} blocks", rate, normal_blocks)),
+        }
+    });
+    
+    // Contract wallet address
+    let contract_address = &config.contract_wallet_address;
+    checks.push(if let Err(e) = guard.check(contract_address) {
+        CheckResult::fail(
+            "contract-wallet",
+            format!("{}: {}", contract_address, e),
+            "Set BITCOIN_TESTNET_CONTRACT_WALLET to a testnet address of the node's wallet",
+        )
+    } else if !node_reachable {
+        CheckResult::node_unreachable("contract-wallet")
+    } else {
+        match rpc.get_address_ownership(contract_address) {
+            Err(e) => CheckResult::fail(
+                "contract-wallet",
+                format!("Cannot look up {}: {}", contract_address, e),
+                "Load the contract wallet on the node",
+            ),
+            Ok(ownership) if ownership.is_mine => CheckResult::pass("contract-wallet", format!("{} is spendable by the wallet", contract_address)),
+            Ok(ownership) if ownership.is_watch_only || ownership.solvable => CheckResult::warn(
+                "contract-wallet",
+                format!("{} is watched but the wallet lacks its keys", contract_address),
+                "Import the private key of the contract address into the node's wallet",
+            ),
+            Ok(_) => CheckResult::fail(
+                "contract-wallet",
+                format!("{} does not belong to the loaded wallet", contract_address),
+                "Use an address of the loaded wallet or load the wallet that owns it",
+            ),
+        }
+    });
+    
+    // Optional backends
+    checks.push(probe_backend("lightning", backends.lightning.as_deref(), "Check LIGHTNING_NODE_URL and LIGHTNING_MACAROON, and that the node is synced"));
+    checks.push(probe_backend("ordinals", backends.ordinals.as_deref(), "Check ORDINALS_API_URL and ORDINALS_API_FLAVOR"));
+    
+    // Persistence paths
+    checks.push(check_persistence_paths(&options.persistence_paths));
+    
+    // Local clock versus the node's median time
+    checks.push(match &blockchain_info {
+        Err(_) => CheckResult::node_unreachable("clock-skew"),
+        Ok(info) => {
+            let now = backends.clock.now().timestamp();
+            let skew = now - info.median_time as i64;
+            
+            if skew.unsigned_abs() > options.max_clock_skew.as_secs() {
+                CheckResult::fail(
+                    "clock-skew",
+                    format!("Local clock is {}s {} the node's median time", skew.unsigned_abs(), if skew < 0 { "behind" } else { "ahead of" }),
+                    "Synchronize the system clock with NTP, and check that the node is not stuck on an old tip",
+                )
+            } else {
+                CheckResult::pass("clock-skew", format!("Local clock is {}s from the node's median time", skew))
+            }
+        },
+    });
+    
+    // Owner address
+    checks.push(match guard.check(&config.owner_address) {
+        Ok(()) => CheckResult::pass("owner-address", format!("{} is a valid {} address", config.owner_address, config.network)),
+        Err(e) => CheckResult::fail(
+            "owner-address",
+            format!("{}: {}", config.owner_address, e),
+            "Set BITCOIN_TESTNET_OWNER_ADDRESS to an address of the configured network",
+        ),
+    });
+    
+    SelfTestReport { checks }
+}
+
+/// Probe an optional backend
+fn probe_backend(name: &str, probe: Option<&dyn HealthProbe>, remediation: &str) -> CheckResult {
+    match probe {
+        None => CheckResult::pass(name, "Not configured"),
+        Some(probe) => match probe.probe() {
+            Ok(()) => CheckResult::pass(name, "Reachable"),
+            Err(e) => CheckResult::fail(name, format!("Unreachable: {}", e), remediation),
+        },
+    }
+}
+
+/// Check that every persistence directory accepts a new file
+fn check_persistence_paths(paths: &[PathBuf]) -> CheckResult {
+    if paths.is_empty() {
+        return CheckResult::pass("persistence", "No persistence paths configured");
+    }
+    
+    for path in paths {
+        let probe = path.join(format!(".vault-doctor-{}", std::process::id()));
+        
+        if let Err(e) = std::fs::write(&probe, b"") {
+            return CheckResult::fail(
+                "persistence",
+                format!("{} is not writable: {}", path.display(), e),
+                "Create the directory and give the vault user write access",
+            );
+        }
+        
+        let _ = std::fs::remove_file(&probe);
+    }
+    
+    CheckResult::pass("persistence", format!("{} paths writable", paths.len()))
+}
@@ -1,19 +1,114 @@
+use std::fmt;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer, Deserialize};
+use serde::ser::SerializeMap;
 use thiserror::Error;
 
+use crate::bitcoin::utxo::ScriptType;
+use crate::models::TokenType;
+
+/// Prefix of the transfer error a backend returns when its queue is full
+/// 
+/// Transfers report errors as strings; the contract recognizes this one and
+/// surfaces it as the retryable `BackendBusy` instead of `TransferFailed`.
+pub const TRANSFER_QUEUE_FULL: &str = "Transfer queue full";
+
+/// Prefix of the transfer error a backend returns when a backend is unavailable
+/// 
+/// The contract surfaces such errors as the retryable `BackendUnavailable`,
+/// keeping the detail after the prefix, such as a retry-after hint.
+pub const BACKEND_UNAVAILABLE: &str = "Backend unavailable: ";
+
+/// Prefix of the error a read-only backend returns for every transfer
+/// 
+/// The contract surfaces such errors as `ReadOnlyMode`.
+pub const READ_ONLY_MODE: &str = "Read-only mode: ";
+
+/// Stage of a token transfer at which a failure occurred
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransferStage {
+    /// Checking the depositor's balance
+    BalanceCheck,
+    /// Moving funds into the contract
+    Deposit,
+    /// Moving funds out of the contract
+    Withdrawal,
+    /// Estimating the fee rate
+    FeeEstimation,
+    /// Selecting UTXOs to fund the transaction
+    UtxoSelection,
+    /// Building the raw transaction
+    Construction,
+    /// Signing the transaction
+    Signing,
+    /// Broadcasting the transaction
+    Broadcast,
+}
+
+impl fmt::Display for TransferStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferStage::BalanceCheck => write!(f, "balance check"),
+            TransferStage::Deposit => write!(f, "deposit"),
+            TransferStage::Withdrawal => write!(f, "withdrawal"),
+            TransferStage::FeeEstimation => write!(f, "fee estimation"),
+            TransferStage::UtxoSelection => write!(f, "UTXO selection"),
+            TransferStage::Construction => write!(f, "transaction construction"),
+            TransferStage::Signing => write!(f, "signing"),
+            TransferStage::Broadcast => write!(f, "broadcast"),
+        }
+    }
+}
+
+/// Part of a `txid:vout` UTXO reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UtxoRefPart {
+    /// Transaction ID
+    Txid,
+    /// Colon between the transaction ID and the output index
+    Separator,
+    /// Output index
+    Vout,
+}
+
+impl fmt::Display for UtxoRefPart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UtxoRefPart::Txid => write!(f, "txid"),
+            UtxoRefPart::Separator => write!(f, "separator"),
+            UtxoRefPart::Vout => write!(f, "vout"),
+        }
+    }
+}
+
 /// Error types for the contract
+/// 
+/// New variants may be added; match with a wildcard arm or on `code()`.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ContractError {
     /// Invalid address
     #[error("Invalid address")]
     InvalidAddress,
     
-    /// Invalid amount
-    #[error("Invalid amount")]
-    InvalidAmount,
+    /// Amount the operation or token does not accept
+    #[error("Invalid amount: {reason}")]
+    InvalidAmount {
+        /// Why the amount is refused
+        reason: String,
+    },
     
-    /// Invalid lock period
-    #[error("Invalid lock period")]
-    InvalidLockPeriod,
+    /// Lock period outside the accepted range
+    #[error("Invalid lock period: {requested} days, expected {min} to {max}")]
+    InvalidLockPeriod {
+        /// Shortest accepted period in days
+        min: u32,
+        /// Longest accepted period in days
+        max: u32,
+        /// Requested period in days
+        requested: u32,
+    },
     
     /// Invalid fee percentage
     #[error("Invalid fee percentage")]
@@ -32,8 +127,13 @@ pub enum ContractError {
     DepositLocked,
     
     /// Insufficient balance
-    #[error("Insufficient balance")]
-    InsufficientBalance,
+    #[error("Insufficient balance: {available} available, {required} required")]
+    InsufficientBalance {
+        /// Spendable amount
+        available: u64,
+        /// Amount the operation needs
+        required: u64,
+    },
     
     /// Unauthorized access
     #[error("Unauthorized access")]
@@ -43,17 +143,36 @@ pub enum ContractError {
     #[error("Contract is paused")]
     ContractPaused,
     
-    /// Deposit limit exceeded
-    #[error("Deposit limit exceeded")]
-    DepositLimitExceeded,
+    /// Deposit above the maximum amount for its token
+    #[error("Deposit limit exceeded: {attempted} above the maximum of {limit} for {}", .token_type.name())]
+    DepositLimitExceeded {
+        /// Token type
+        token_type: TokenType,
+        /// Maximum deposit amount
+        limit: u64,
+        /// Requested deposit amount
+        attempted: u64,
+    },
     
     /// User deposit limit reached
-    #[error("User deposit limit reached")]
-    UserDepositLimitReached,
+    #[error("User deposit limit reached: {current} of {limit} deposits")]
+    UserDepositLimitReached {
+        /// Maximum deposits per user
+        limit: u32,
+        /// Deposits the user already has or reserved
+        current: usize,
+    },
     
     /// Total deposit limit reached
-    #[error("Total deposit limit reached")]
-    TotalDepositLimitReached,
+    #[error("Total deposit limit reached: {current} + {attempted} above the maximum of {limit}")]
+    TotalDepositLimitReached {
+        /// Maximum total deposits
+        limit: u64,
+        /// Active and reserved deposits of the token
+        current: u64,
+        /// Requested deposit amount
+        attempted: u64,
+    },
     
     /// Unsupported token operation
     #[error("Unsupported token operation")]
@@ -82,10 +201,1156 @@ pub enum ContractError {
     /// Invalid Bitcoin transaction
     #[error("Invalid Bitcoin transaction")]
     InvalidBitcoinTransaction,
+    
+    /// Backend unavailable
+    #[error("Backend unavailable: {0}")]
+    BackendUnavailable(String),
+    
+    /// Bitcoin Core RPC call failed
+    #[error("RPC call {operation} failed: {source}")]
+    RpcError {
+        /// RPC method that failed
+        operation: &'static str,
+        /// Underlying RPC error
+        #[source]
+        source: bitcoincore_rpc::Error,
+    },
+    
+    /// Emergency withdrawal requested before the deposit reached the minimum age
+    #[error("Emergency withdrawal not allowed yet for this deposit, available at {available_at}")]
+    EmergencyTooSoon {
+        /// When the deposit reaches the minimum age
+        available_at: DateTime<Utc>,
+    },
+    
+    /// Per-user emergency withdrawal limit reached
+    #[error("Emergency withdrawal limit of {limit} per window reached, next allowed at {retry_at}")]
+    EmergencyLimitReached {
+        /// Maximum emergency withdrawals per window
+        limit: u32,
+        /// When the oldest counted withdrawal leaves the window
+        retry_at: DateTime<Utc>,
+    },
+    
+    /// Idempotency key reused with different arguments
+    #[error("Idempotency key was already used for a different request")]
+    IdempotencyConflict,
+    
+    /// Action must be approved through a governance proposal
+    #[error("Action requires governance approval")]
+    GovernanceRequired,
+    
+    /// Invalid owner set or threshold
+    #[error("Invalid governance configuration: {0}")]
+    InvalidGovernanceConfig(String),
+    
+    /// Governance proposal not found
+    #[error("Proposal not found")]
+    ProposalNotFound,
+    
+    /// Governance proposal already executed or expired
+    #[error("Proposal is no longer pending")]
+    ProposalClosed,
+    
+    /// Owner already approved the proposal
+    #[error("Proposal already approved by this owner")]
+    AlreadyApproved,
+    
+    /// Deposit reservation unknown, expired or already settled
+    #[error("Deposit reservation not found or expired")]
+    ReservationNotFound,
+    
+    /// Claim preimage does not hash to the deposit claim hash
+    #[error("Invalid claim preimage")]
+    InvalidPreimage,
+    
+    /// UTXO reference already backs another deposit
+    #[error("UTXO already deposited")]
+    UtxoAlreadyDeposited,
+    
+    /// Deposit amount does not exceed the deposit fee
+    #[error("Deposit amount of {amount} must be greater than the deposit fee of {fee}")]
+    DepositNotAboveFee {
+        /// Deposit fee
+        fee: u64,
+        /// Requested deposit amount
+        amount: u64,
+    },
+    
+    /// Deposit is suspended after its funding transaction was reorganized out
+    #[error("Deposit is suspended pending re-confirmation of its funding transaction")]
+    DepositSuspended,
+    
+    /// Mining fee above the ceiling accepted by the user
+    #[error("Mining fee of {required} sat exceeds the limit of {limit} sat")]
+    FeeExceedsLimit {
+        /// Mining fee the transaction needs
+        required: u64,
+        /// Ceiling accepted by the user
+        limit: u64,
+    },
+    
+    /// Invalid inactivity period for an inheritance
+    #[error("Invalid inactivity period")]
+    InvalidInactivityPeriod,
+    
+    /// Depositor has not been inactive long enough for the beneficiary to claim
+    #[error("Inheritance not claimable yet")]
+    InheritanceNotClaimable,
+    
+    /// Emergency withdrawal quote not found
+    #[error("Quote not found")]
+    QuoteNotFound,
+    
+    /// Emergency withdrawal quote is past its validity window
+    #[error("Quote expired")]
+    QuoteExpired,
+    
+    /// Emergency withdrawal quote was issued for another deposit or caller
+    #[error("Quote does not match the deposit")]
+    QuoteMismatch,
+    
+    /// UTXO named by coin control that cannot be spent
+    #[error("UTXO {reference} cannot be spent: {reason}")]
+    UtxoUnavailable {
+        /// Output reference (`txid:vout`)
+        reference: String,
+        /// Why it cannot be spent
+        reason: String,
+    },
+    
+    /// No unattributed funds at the given output
+    #[error("No unattributed funds at the given output")]
+    UnattributedFundsNotFound,
+    
+    /// Payout below the smallest output the network relays
+    #[error("Payout of {amount} sat is below the dust threshold of {threshold} sat")]
+    DustOutput {
+        /// Requested payout
+        amount: u64,
+        /// Smallest accepted payout
+        threshold: u64,
+    },
+    
+    /// Transaction pays the same address twice
+    #[error("Duplicate output for address {address}")]
+    DuplicateOutput {
+        /// Address appearing in more than one output
+        address: String,
+    },
+    
+    /// Owner nonce does not match the current one
+    #[error("Owner nonce mismatch: expected {expected}, current {actual}")]
+    NonceMismatch {
+        /// Nonce the caller expected
+        expected: u64,
+        /// Current owner nonce
+        actual: u64,
+    },
+    
+    /// Confirmation policy requiring zero confirmations or with unordered tiers
+    #[error("Invalid confirmation policy: {0}")]
+    InvalidConfirmationPolicy(String),
+    
+    /// Deposit still waiting for its funding
+    #[error("Deposit is waiting for its funding")]
+    DepositPending,
+    
+    /// Pending deposit expired before it was funded
+    #[error("Deposit expired before it was funded")]
+    DepositExpired,
+    
+    /// Output descriptor or escrow terms are invalid
+    #[error("Invalid descriptor: {0}")]
+    InvalidDescriptor(String),
+    
+    /// No vault escrow key is configured
+    #[error("No vault escrow key is configured")]
+    EscrowNotConfigured,
+    
+    /// Backend queue is full; retry later
+    #[error("Backend busy: {0}")]
+    BackendBusy(String),
+    
+    /// No treasury policy configured
+    #[error("No treasury policy configured")]
+    TreasuryPolicyNotConfigured,
+    
+    /// Imported deposits or their manifest are malformed
+    #[error("Invalid import: {0}")]
+    InvalidImport(String),
+    
+    /// Import manifest is not signed by the migration key
+    #[error("Import manifest is not signed by the migration key")]
+    InvalidImportSignature,
+    
+    /// Import manifest was already applied
+    #[error("Import manifest was already imported")]
+    ManifestAlreadyImported,
+    
+    /// No migration key is configured
+    #[error("No migration key is configured")]
+    MigrationKeyNotConfigured,
+    
+    /// Requested series resolution or configuration is invalid
+    #[error("Invalid series resolution: {0}")]
+    InvalidSeriesResolution(String),
+    
+    /// TVL metrics are not recorded
+    #[error("TVL metrics are not enabled")]
+    TvlMetricsNotEnabled,
+    
+    /// Circuit breaker configuration is invalid
+    #[error("Invalid circuit breaker configuration: {0}")]
+    InvalidCircuitBreakerConfig(String),
+    
+    /// Circuit breaker has not paused the contract
+    #[error("Circuit breaker is not tripped")]
+    CircuitBreakerNotTripped,
+    
+    /// Every derived deposit ID candidate is taken
+    #[error("Deposit ID derivation collided too many times")]
+    DepositIdCollision,
+    
+    /// Deposit ID scheme cannot change once deposits exist
+    #[error("Deposit ID scheme cannot change after deposits were made")]
+    IdSchemeLocked,
+    
+    /// Lightning invoice could not be decoded or encoded
+    #[error("Invalid Lightning invoice: {0}")]
+    InvalidInvoice(String),
+    
+    /// Fee withdrawal destination is not an activated whitelist entry
+    #[error("Fee destination is not whitelisted")]
+    DestinationNotWhitelisted,
+    
+    /// Fee destination was never proposed
+    #[error("Fee destination was not proposed")]
+    FeeDestinationNotProposed,
+    
+    /// Fee destination activated before its delay passed
+    #[error("Fee destination cannot be activated before {activates_at}")]
+    FeeDestinationTooSoon {
+        /// When the delay of the proposal passes
+        activates_at: DateTime<Utc>,
+    },
+    
+    /// Builder executed without a required parameter
+    #[error("Missing parameter: {0}")]
+    MissingParameter(String),
+    
+    /// Payout of the deposit was handed to the transfer backend and awaits recovery
+    #[error("Withdrawal of the deposit is already in flight")]
+    WithdrawalInFlight,
+    
+    /// Deposit names its own depositor as referrer
+    #[error("A deposit cannot refer its own depositor")]
+    SelfReferral,
+    
+    /// Deposit of a token whose backend stopped supporting it
+    #[error("Token {} is deprecated until its backend supports it again", .token_type.name())]
+    TokenDeprecated {
+        /// Token type
+        token_type: TokenType,
+    },
+    
+    /// API key lacks the capability a call needs
+    #[error("API key lacks the {capability} capability")]
+    MissingCapability {
+        /// Capability the call needs
+        capability: String,
+    },
+    
+    /// Deposit is not waiting for its funding
+    #[error("Deposit is not pending")]
+    DepositNotPending,
+    
+    /// Deposit was voided by the owner before its funding confirmed
+    #[error("Deposit was voided")]
+    DepositVoided,
+    
+    /// Deposit was not voided
+    #[error("Deposit is not voided")]
+    DepositNotVoided,
+    
+    /// Bitcoin withdrawal too small to pay out on-chain right now
+    #[error("Withdrawal of {amount} sat is below the current minimum of {minimum} sat (dust limit plus estimated mining fee)")]
+    WithdrawalBelowMinimum {
+        /// Requested withdrawal
+        amount: u64,
+        /// Dust limit plus the current fee estimate
+        minimum: u64,
+    },
+    
+    /// Requested liquidity forecast parameters are invalid
+    #[error("Invalid liquidity forecast: {0}")]
+    InvalidForecast(String),
+    
+    /// UTXO reference is not a canonical txid:vout outpoint
+    #[error("Invalid UTXO reference {part}: {reason}")]
+    InvalidUtxoReference {
+        /// Part of the reference that is malformed
+        part: UtxoRefPart,
+        /// What is wrong with it
+        reason: String,
+    },
+    
+    /// Operation on a token the owner or an automatic trigger paused
+    #[error("Token {} is paused", .token_type.name())]
+    TokenPaused {
+        /// Token type
+        token_type: TokenType,
+    },
+    
+    /// Unpausing a token that is not paused
+    #[error("Token is not paused")]
+    TokenNotPaused,
+    
+    /// Contract state could not be persisted
+    #[error("State persistence failed: {0}")]
+    StatePersistenceFailed(String),
+    
+    /// Outbox entry not found
+    #[error("Outbox entry not found")]
+    OutboxEntryNotFound,
+    
+    /// MuSig2 nonce already used for a partial signature of this spend
+    #[error("MuSig2 nonce of spend {spend_id} was already used")]
+    MusigNonceReused {
+        /// Spend ID of the signing session
+        spend_id: String,
+    },
+    
+    /// Destination or output pays to a script type the destination policy does not allow
+    #[error("Script type {script_type} is not allowed as a destination")]
+    ScriptTypeNotAllowed {
+        /// Detected script type
+        script_type: ScriptType,
+    },
+    
+    /// Address belongs to another network than the vault runs on
+    #[error("Address is for {address_network} but the vault runs on {expected_network}")]
+    WrongNetwork {
+        /// Network the address was encoded for
+        address_network: String,
+        /// Network the vault is configured for
+        expected_network: String,
+    },
+    
+    /// Long-running operation stopped on request
+    #[error("Cancelled during {stage} after {processed} items")]
+    Cancelled {
+        /// Stage the operation was in
+        stage: String,
+        /// Items of the stage done before it stopped
+        processed: u64,
+    },
+    
+    /// Export could not be written
+    #[error("Export failed: {0}")]
+    ExportFailed(String),
+    
+    /// Operation that changes state or moves funds on a read-only vault
+    #[error("Vault is read-only")]
+    ReadOnlyMode,
+    
+    /// Token transfer failed at a specific stage
+    #[error("Transfer failed during {stage}: {reason}")]
+    TransferFailed {
+        /// Stage at which the transfer failed
+        stage: TransferStage,
+        /// Failure reason
+        reason: String,
+    },
+}
+
+/// Stable machine-readable error codes for the HTTP API and logs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// Invalid address
+    InvalidAddress,
+    /// Invalid amount
+    InvalidAmount,
+    /// Invalid lock period
+    InvalidLockPeriod,
+    /// Invalid fee percentage
+    InvalidFeePercentage,
+    /// Deposit not found
+    DepositNotFound,
+    /// Deposit already withdrawn
+    DepositAlreadyWithdrawn,
+    /// Deposit locked
+    DepositLocked,
+    /// Insufficient balance
+    InsufficientBalance,
+    /// Unauthorized
+    Unauthorized,
+    /// Contract paused
+    ContractPaused,
+    /// Deposit limit exceeded
+    DepositLimitExceeded,
+    /// User deposit limit reached
+    UserDepositLimitReached,
+    /// Total deposit limit reached
+    TotalDepositLimitReached,
+    /// Unsupported token operation
+    UnsupportedTokenOperation,
+    /// Token validation failed
+    TokenValidationFailed,
+    /// Arithmetic error
+    ArithmeticError,
+    /// Reentrancy detected
+    ReentrancyDetected,
+    /// Initialization error
+    InitializationError,
+    /// Bitcoin testnet error
+    BitcoinTestnetError,
+    /// Invalid bitcoin transaction
+    InvalidBitcoinTransaction,
+    /// Backend unavailable
+    BackendUnavailable,
+    /// RPC error
+    RpcError,
+    /// Transfer failed
+    TransferFailed,
+    /// Emergency too soon
+    EmergencyTooSoon,
+    /// Emergency limit reached
+    EmergencyLimitReached,
+    /// Idempotency conflict
+    IdempotencyConflict,
+    /// Action must be approved through a governance proposal
+    GovernanceRequired,
+    /// Invalid owner set or threshold
+    InvalidGovernanceConfig,
+    /// Governance proposal not found
+    ProposalNotFound,
+    /// Governance proposal already executed or expired
+    ProposalClosed,
+    /// Owner already approved the proposal
+    AlreadyApproved,
+    /// Deposit reservation unknown, expired or already settled
+    ReservationNotFound,
+    /// Claim preimage does not hash to the deposit claim hash
+    InvalidPreimage,
+    /// UTXO reference already backs another deposit
+    UtxoAlreadyDeposited,
+    /// Deposit amount does not exceed the deposit fee
+    DepositNotAboveFee,
+    /// Deposit is suspended after its funding transaction was reorganized out
+    DepositSuspended,
+    /// Mining fee above the ceiling accepted by the user
+    FeeExceedsLimit,
+    /// Invalid inactivity period for an inheritance
+    InvalidInactivityPeriod,
+    /// Depositor has not been inactive long enough for the beneficiary to claim
+    InheritanceNotClaimable,
+    /// Emergency withdrawal quote not found
+    QuoteNotFound,
+    /// Emergency withdrawal quote is past its validity window
+    QuoteExpired,
+    /// Emergency withdrawal quote was issued for another deposit or caller
+    QuoteMismatch,
+    /// UTXO named by coin control that cannot be spent
+    UtxoUnavailable,
+    /// No unattributed funds at the given output
+    UnattributedFundsNotFound,
+    /// Payout below the smallest output the network relays
+    DustOutput,
+    /// Transaction pays the same address twice
+    DuplicateOutput,
+    /// Owner nonce does not match the current one
+    NonceMismatch,
+    /// Confirmation policy requiring zero confirmations or with unordered tiers
+    InvalidConfirmationPolicy,
+    /// Deposit still waiting for its funding
+    DepositPending,
+    /// Pending deposit expired before it was funded
+    DepositExpired,
+    /// Output descriptor or escrow terms are invalid
+    InvalidDescriptor,
+    /// No vault escrow key is configured
+    EscrowNotConfigured,
+    /// Backend queue is full; retry later
+    BackendBusy,
+    /// No treasury policy configured
+    TreasuryPolicyNotConfigured,
+    /// Imported deposits or their manifest are malformed
+    InvalidImport,
+    /// Import manifest is not signed by the migration key
+    InvalidImportSignature,
+    /// Import manifest was already applied
+    ManifestAlreadyImported,
+    /// No migration key is configured
+    MigrationKeyNotConfigured,
+    /// Requested series resolution or configuration is invalid
+    InvalidSeriesResolution,
+    /// TVL metrics are not recorded
+    TvlMetricsNotEnabled,
+    /// Circuit breaker configuration is invalid
+    InvalidCircuitBreakerConfig,
+    /// Circuit breaker has not paused the contract
+    CircuitBreakerNotTripped,
+    /// Every derived deposit ID candidate is taken
+    DepositIdCollision,
+    /// Deposit ID scheme cannot change once deposits exist
+    IdSchemeLocked,
+    /// Lightning invoice could not be decoded or encoded
+    InvalidInvoice,
+    /// Fee withdrawal destination is not an activated whitelist entry
+    DestinationNotWhitelisted,
+    /// Fee destination was never proposed
+    FeeDestinationNotProposed,
+    /// Fee destination activated before its delay passed
+    FeeDestinationTooSoon,
+    /// Builder executed without a required parameter
+    MissingParameter,
+    /// Payout of the deposit was handed to the transfer backend and awaits recovery
+    WithdrawalInFlight,
+    /// Deposit names its own depositor as referrer
+    SelfReferral,
+    /// Deposit of a token whose backend stopped supporting it
+    TokenDeprecated,
+    /// API key lacks the capability a call needs
+    MissingCapability,
+    /// Deposit is not waiting for its funding
+    DepositNotPending,
+    /// Deposit was voided by the owner before its funding confirmed
+    DepositVoided,
+    /// Deposit was not voided
+    DepositNotVoided,
+    /// Bitcoin withdrawal too small to pay out on-chain right now
+    WithdrawalBelowMinimum,
+    /// Requested liquidity forecast parameters are invalid
+    InvalidForecast,
+    /// UTXO reference is not a canonical txid:vout outpoint
+    InvalidUtxoReference,
+    /// Operation on a token the owner or an automatic trigger paused
+    TokenPaused,
+    /// Unpausing a token that is not paused
+    TokenNotPaused,
+    /// Contract state could not be persisted
+    StatePersistenceFailed,
+    /// Outbox entry not found
+    OutboxEntryNotFound,
+    /// MuSig2 nonce already used for a partial signature of this spend
+    MusigNonceReused,
+    /// Destination or output pays to a script type the destination policy does not allow
+    ScriptTypeNotAllowed,
+    /// Address belongs to another network than the vault runs on
+    WrongNetwork,
+    /// Long-running operation stopped on request
+    Cancelled,
+    /// Export could not be written
+    ExportFailed,
+    /// Operation that changes state or moves funds on a read-only vault
+    ReadOnlyMode,
+}
+
+impl ErrorCode {
+    /// Every error code, one per `ContractError` variant
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::InvalidAddress,
+        ErrorCode::InvalidAmount,
+        ErrorCode::InvalidLockPeriod,
+        ErrorCode::InvalidFeePercentage,
+        ErrorCode::DepositNotFound,
+        ErrorCode::DepositAlreadyWithdrawn,
+        ErrorCode::DepositLocked,
+        ErrorCode::InsufficientBalance,
+        ErrorCode::Unauthorized,
+        ErrorCode::ContractPaused,
+        ErrorCode::DepositLimitExceeded,
+        ErrorCode::UserDepositLimitReached,
+        ErrorCode::TotalDepositLimitReached,
+        ErrorCode::UnsupportedTokenOperation,
+        ErrorCode::TokenValidationFailed,
+        ErrorCode::ArithmeticError,
+        ErrorCode::ReentrancyDetected,
+        ErrorCode::InitializationError,
+        ErrorCode::BitcoinTestnetError,
+        ErrorCode::InvalidBitcoinTransaction,
+        ErrorCode::BackendUnavailable,
+        ErrorCode::RpcError,
+        ErrorCode::TransferFailed,
+        ErrorCode::EmergencyTooSoon,
+        ErrorCode::EmergencyLimitReached,
+        ErrorCode::IdempotencyConflict,
+        ErrorCode::GovernanceRequired,
+        ErrorCode::InvalidGovernanceConfig,
+        ErrorCode::ProposalNotFound,
+        ErrorCode::ProposalClosed,
+        ErrorCode::AlreadyApproved,
+        ErrorCode::ReservationNotFound,
+        ErrorCode::InvalidPreimage,
+        ErrorCode::UtxoAlreadyDeposited,
+        ErrorCode::DepositNotAboveFee,
+        ErrorCode::DepositSuspended,
+        ErrorCode::FeeExceedsLimit,
+        ErrorCode::InvalidInactivityPeriod,
+        ErrorCode::InheritanceNotClaimable,
+        ErrorCode::QuoteNotFound,
+        ErrorCode::QuoteExpired,
+        ErrorCode::QuoteMismatch,
+        ErrorCode::UtxoUnavailable,
+        ErrorCode::UnattributedFundsNotFound,
+        ErrorCode::DustOutput,
+        ErrorCode::DuplicateOutput,
+        ErrorCode::NonceMismatch,
+        ErrorCode::InvalidConfirmationPolicy,
+        ErrorCode::DepositPending,
+        ErrorCode::DepositExpired,
+        ErrorCode::InvalidDescriptor,
+        ErrorCode::EscrowNotConfigured,
+        ErrorCode::BackendBusy,
+        ErrorCode::TreasuryPolicyNotConfigured,
+        ErrorCode::InvalidImport,
+        ErrorCode::InvalidImportSignature,
+        ErrorCode::ManifestAlreadyImported,
+        ErrorCode::MigrationKeyNotConfigured,
+        ErrorCode::InvalidSeriesResolution,
+        ErrorCode::TvlMetricsNotEnabled,
+        ErrorCode::InvalidCircuitBreakerConfig,
+        ErrorCode::CircuitBreakerNotTripped,
+        ErrorCode::DepositIdCollision,
+        ErrorCode::IdSchemeLocked,
+        ErrorCode::InvalidInvoice,
+        ErrorCode::DestinationNotWhitelisted,
+        ErrorCode::FeeDestinationNotProposed,
+        ErrorCode::FeeDestinationTooSoon,
+        ErrorCode::MissingParameter,
+        ErrorCode::WithdrawalInFlight,
+        ErrorCode::SelfReferral,
+        ErrorCode::TokenDeprecated,
+        ErrorCode::MissingCapability,
+        ErrorCode::DepositNotPending,
+        ErrorCode::DepositVoided,
+        ErrorCode::DepositNotVoided,
+        ErrorCode::WithdrawalBelowMinimum,
+        ErrorCode::InvalidForecast,
+        ErrorCode::InvalidUtxoReference,
+        ErrorCode::TokenPaused,
+        ErrorCode::TokenNotPaused,
+        ErrorCode::StatePersistenceFailed,
+        ErrorCode::OutboxEntryNotFound,
+        ErrorCode::MusigNonceReused,
+        ErrorCode::ScriptTypeNotAllowed,
+        ErrorCode::WrongNetwork,
+        ErrorCode::Cancelled,
+        ErrorCode::ExportFailed,
+        ErrorCode::ReadOnlyMode,
+    ];
+    
+    /// Get the code as a number, for protocols with integer error codes
+    ///
+    /// Numbers start at 1000 and follow the order of `ALL`, which only ever
+    /// grows at the end, so a code keeps its number.
+    pub fn number(&self) -> i64 {
+        let position = Self::ALL.iter()
+            .position(|code| code == self)
+            .unwrap_or(Self::ALL.len());
+        
+        1000 + position as i64
+    }
+    
+    /// Get the code as a string
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidAddress => "INVALID_ADDRESS",
+            ErrorCode::InvalidAmount => "INVALID_AMOUNT",
+            ErrorCode::InvalidLockPeriod => "INVALID_LOCK_PERIOD",
+            ErrorCode::InvalidFeePercentage => "INVALID_FEE_PERCENTAGE",
+            ErrorCode::DepositNotFound => "DEPOSIT_NOT_FOUND",
+            ErrorCode::DepositAlreadyWithdrawn => "DEPOSIT_ALREADY_WITHDRAWN",
+            ErrorCode::DepositLocked => "DEPOSIT_LOCKED",
+            ErrorCode::InsufficientBalance => "INSUFFICIENT_BALANCE",
+            ErrorCode::Unauthorized => "UNAUTHORIZED",
+            ErrorCode::ContractPaused => "CONTRACT_PAUSED",
+            ErrorCode::DepositLimitExceeded => "DEPOSIT_LIMIT_EXCEEDED",
+            ErrorCode::UserDepositLimitReached => "USER_DEPOSIT_LIMIT_REACHED",
+            ErrorCode::TotalDepositLimitReached => "TOTAL_DEPOSIT_LIMIT_REACHED",
+            ErrorCode::UnsupportedTokenOperation => "UNSUPPORTED_TOKEN_OPERATION",
+            ErrorCode::TokenValidationFailed => "TOKEN_VALIDATION_FAILED",
+            ErrorCode::ArithmeticError => "ARITHMETIC_ERROR",
+            ErrorCode::ReentrancyDetected => "REENTRANCY_DETECTED",
+            ErrorCode::InitializationError => "INITIALIZATION_ERROR",
+            ErrorCode::BitcoinTestnetError => "BITCOIN_TESTNET_ERROR",
+            ErrorCode::InvalidBitcoinTransaction => "INVALID_BITCOIN_TRANSACTION",
+            ErrorCode::BackendUnavailable => "BACKEND_UNAVAILABLE",
+            ErrorCode::RpcError => "RPC_ERROR",
+            ErrorCode::TransferFailed => "TRANSFER_FAILED",
+            ErrorCode::EmergencyTooSoon => "EMERGENCY_TOO_SOON",
+            ErrorCode::EmergencyLimitReached => "EMERGENCY_LIMIT_REACHED",
+            ErrorCode::IdempotencyConflict => "IDEMPOTENCY_CONFLICT",
+            ErrorCode::GovernanceRequired => "GOVERNANCE_REQUIRED",
+            ErrorCode::InvalidGovernanceConfig => "INVALID_GOVERNANCE_CONFIG",
+            ErrorCode::ProposalNotFound => "PROPOSAL_NOT_FOUND",
+            ErrorCode::ProposalClosed => "PROPOSAL_CLOSED",
+            ErrorCode::AlreadyApproved => "ALREADY_APPROVED",
+            ErrorCode::ReservationNotFound => "RESERVATION_NOT_FOUND",
+            ErrorCode::InvalidPreimage => "INVALID_PREIMAGE",
+            ErrorCode::UtxoAlreadyDeposited => "UTXO_ALREADY_DEPOSITED",
+            ErrorCode::DepositNotAboveFee => "DEPOSIT_NOT_ABOVE_FEE",
+            ErrorCode::DepositSuspended => "DEPOSIT_SUSPENDED",
+            ErrorCode::FeeExceedsLimit => "FEE_EXCEEDS_LIMIT",
+            ErrorCode::InvalidInactivityPeriod => "INVALID_INACTIVITY_PERIOD",
+            ErrorCode::InheritanceNotClaimable => "INHERITANCE_NOT_CLAIMABLE",
+            ErrorCode::QuoteNotFound => "QUOTE_NOT_FOUND",
+            ErrorCode::QuoteExpired => "QUOTE_EXPIRED",
+            ErrorCode::QuoteMismatch => "QUOTE_MISMATCH",
+            ErrorCode::UtxoUnavailable => "UTXO_UNAVAILABLE",
+            ErrorCode::UnattributedFundsNotFound => "UNATTRIBUTED_FUNDS_NOT_FOUND",
+            ErrorCode::DustOutput => "DUST_OUTPUT",
+            ErrorCode::DuplicateOutput => "DUPLICATE_OUTPUT",
+            ErrorCode::NonceMismatch => "NONCE_MISMATCH",
+            ErrorCode::InvalidConfirmationPolicy => "INVALID_CONFIRMATION_POLICY",
+            ErrorCode::DepositPending => "DEPOSIT_PENDING",
+            ErrorCode::DepositExpired => "DEPOSIT_EXPIRED",
+            ErrorCode::InvalidDescriptor => "INVALID_DESCRIPTOR",
+            ErrorCode::EscrowNotConfigured => "ESCROW_NOT_CONFIGURED",
+            ErrorCode::BackendBusy => "BACKEND_BUSY",
+            ErrorCode::TreasuryPolicyNotConfigured => "TREASURY_POLICY_NOT_CONFIGURED",
+            ErrorCode::InvalidImport => "INVALID_IMPORT",
+            ErrorCode::InvalidImportSignature => "INVALID_IMPORT_SIGNATURE",
+            ErrorCode::ManifestAlreadyImported => "MANIFEST_ALREADY_IMPORTED",
+            ErrorCode::MigrationKeyNotConfigured => "MIGRATION_KEY_NOT_CONFIGURED",
+            ErrorCode::InvalidSeriesResolution => "INVALID_SERIES_RESOLUTION",
+            ErrorCode::TvlMetricsNotEnabled => "TVL_METRICS_NOT_ENABLED",
+            ErrorCode::InvalidCircuitBreakerConfig => "INVALID_CIRCUIT_BREAKER_CONFIG",
+            ErrorCode::CircuitBreakerNotTripped => "CIRCUIT_BREAKER_NOT_TRIPPED",
+            ErrorCode::DepositIdCollision => "DEPOSIT_ID_COLLISION",
+            ErrorCode::IdSchemeLocked => "ID_SCHEME_LOCKED",
+            ErrorCode::InvalidInvoice => "INVALID_INVOICE",
+            ErrorCode::DestinationNotWhitelisted => "DESTINATION_NOT_WHITELISTED",
+            ErrorCode::FeeDestinationNotProposed => "FEE_DESTINATION_NOT_PROPOSED",
+            ErrorCode::FeeDestinationTooSoon => "FEE_DESTINATION_TOO_SOON",
+            ErrorCode::MissingParameter => "MISSING_PARAMETER",
+            ErrorCode::WithdrawalInFlight => "WITHDRAWAL_IN_FLIGHT",
+            ErrorCode::SelfReferral => "SELF_REFERRAL",
+            ErrorCode::TokenDeprecated => "TOKEN_DEPRECATED",
+            ErrorCode::MissingCapability => "MISSING_CAPABILITY",
+            ErrorCode::DepositNotPending => "DEPOSIT_NOT_PENDING",
+            ErrorCode::DepositVoided => "DEPOSIT_VOIDED",
+            ErrorCode::DepositNotVoided => "DEPOSIT_NOT_VOIDED",
+            ErrorCode::WithdrawalBelowMinimum => "WITHDRAWAL_BELOW_MINIMUM",
+            ErrorCode::InvalidForecast => "INVALID_FORECAST",
+            ErrorCode::InvalidUtxoReference => "INVALID_UTXO_REFERENCE",
+            ErrorCode::TokenPaused => "TOKEN_PAUSED",
+            ErrorCode::TokenNotPaused => "TOKEN_NOT_PAUSED",
+            ErrorCode::StatePersistenceFailed => "STATE_PERSISTENCE_FAILED",
+            ErrorCode::OutboxEntryNotFound => "OUTBOX_ENTRY_NOT_FOUND",
+            ErrorCode::MusigNonceReused => "MUSIG_NONCE_REUSED",
+            ErrorCode::ScriptTypeNotAllowed => "SCRIPT_TYPE_NOT_ALLOWED",
+            ErrorCode::WrongNetwork => "WRONG_NETWORK",
+            ErrorCode::Cancelled => "CANCELLED",
+            ErrorCode::ExportFailed => "EXPORT_FAILED",
+            ErrorCode::ReadOnlyMode => "READ_ONLY_MODE",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ContractError {
+    /// Error of a failed backend transfer
+    /// 
+    /// `BackendBusy` if the backend's queue was full, `BackendUnavailable` if
+    /// the backend was unavailable, `ReadOnlyMode` if the backend is read-only.
+    pub fn transfer_failed(stage: TransferStage, reason: String) -> Self {
+        if reason.starts_with(TRANSFER_QUEUE_FULL) {
+            ContractError::BackendBusy(reason)
+        } else if let Some(detail) = reason.strip_prefix(BACKEND_UNAVAILABLE) {
+            ContractError::BackendUnavailable(detail.to_string())
+        } else if reason.starts_with(READ_ONLY_MODE) {
+            ContractError::ReadOnlyMode
+        } else {
+            ContractError::TransferFailed { stage, reason }
+        }
+    }
+    
+    /// Get the stable machine-readable code of this error
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ContractError::InvalidAddress => ErrorCode::InvalidAddress,
+            ContractError::InvalidAmount { .. } => ErrorCode::InvalidAmount,
+            ContractError::InvalidLockPeriod { .. } => ErrorCode::InvalidLockPeriod,
+            ContractError::InvalidFeePercentage => ErrorCode::InvalidFeePercentage,
+            ContractError::DepositNotFound => ErrorCode::DepositNotFound,
+            ContractError::DepositAlreadyWithdrawn => ErrorCode::DepositAlreadyWithdrawn,
+            ContractError::DepositLocked => ErrorCode::DepositLocked,
+            ContractError::InsufficientBalance { .. } => ErrorCode::InsufficientBalance,
+            ContractError::Unauthorized => ErrorCode::Unauthorized,
+            ContractError::ContractPaused => ErrorCode::ContractPaused,
+            ContractError::DepositLimitExceeded { .. } => ErrorCode::DepositLimitExceeded,
+            ContractError::UserDepositLimitReached { .. } => ErrorCode::UserDepositLimitReached,
+            ContractError::TotalDepositLimitReached { .. } => ErrorCode::TotalDepositLimitReached,
+            ContractError::UnsupportedTokenOperation => ErrorCode::UnsupportedTokenOperation,
+            ContractError::TokenValidationFailed => ErrorCode::TokenValidationFailed,
+            ContractError::ArithmeticError => ErrorCode::ArithmeticError,
+            ContractError::ReentrancyDetected => ErrorCode::ReentrancyDetected,
+            ContractError::InitializationError(_) => ErrorCode::InitializationError,
+            ContractError::BitcoinTestnetError(_) => ErrorCode::BitcoinTestnetError,
+            ContractError::InvalidBitcoinTransaction => ErrorCode::InvalidBitcoinTransaction,
+            ContractError::BackendUnavailable(_) => ErrorCode::BackendUnavailable,
+            ContractError::RpcError { .. } => ErrorCode::RpcError,
+            ContractError::TransferFailed { .. } => ErrorCode::TransferFailed,
+            ContractError::EmergencyTooSoon { .. } => ErrorCode::EmergencyTooSoon,
+            ContractError::EmergencyLimitReached { .. } => ErrorCode::EmergencyLimitReached,
+            ContractError::IdempotencyConflict => ErrorCode::IdempotencyConflict,
+            ContractError::GovernanceRequired => ErrorCode::GovernanceRequired,
+            ContractError::InvalidGovernanceConfig(_) => ErrorCode::InvalidGovernanceConfig,
+            ContractError::ProposalNotFound => ErrorCode::ProposalNotFound,
+            ContractError::ProposalClosed => ErrorCode::ProposalClosed,
+            ContractError::AlreadyApproved => ErrorCode::AlreadyApproved,
+            ContractError::ReservationNotFound => ErrorCode::ReservationNotFound,
+            ContractError::InvalidPreimage => ErrorCode::InvalidPreimage,
+            ContractError::UtxoAlreadyDeposited => ErrorCode::UtxoAlreadyDeposited,
+            ContractError::DepositNotAboveFee { .. } => ErrorCode::DepositNotAboveFee,
+            ContractError::DepositSuspended => ErrorCode::DepositSuspended,
+            ContractError::FeeExceedsLimit { .. } => ErrorCode::FeeExceedsLimit,
+            ContractError::InvalidInactivityPeriod => ErrorCode::InvalidInactivityPeriod,
+            ContractError::InheritanceNotClaimable => ErrorCode::InheritanceNotClaimable,
+            ContractError::QuoteNotFound => ErrorCode::QuoteNotFound,
+            ContractError::QuoteExpired => ErrorCode::QuoteExpired,
+            ContractError::QuoteMismatch => ErrorCode::QuoteMismatch,
+            ContractError::UtxoUnavailable { .. } => ErrorCode::UtxoUnavailable,
+            ContractError::UnattributedFundsNotFound => ErrorCode::UnattributedFundsNotFound,
+            ContractError::DustOutput { .. } => ErrorCode::DustOutput,
+            ContractError::DuplicateOutput { .. } => ErrorCode::DuplicateOutput,
+            ContractError::NonceMismatch{ .. } => ErrorCode::NonceMismatch,
+            ContractError::InvalidConfirmationPolicy(_) => ErrorCode::InvalidConfirmationPolicy,
+            ContractError::DepositPending => ErrorCode::DepositPending,
+            ContractError::DepositExpired => ErrorCode::DepositExpired,
+            ContractError::InvalidDescriptor(_) => ErrorCode::InvalidDescriptor,
+            ContractError::EscrowNotConfigured => ErrorCode::EscrowNotConfigured,
+            ContractError::BackendBusy(_) => ErrorCode::BackendBusy,
+            ContractError::TreasuryPolicyNotConfigured => ErrorCode::TreasuryPolicyNotConfigured,
+            ContractError::InvalidImport(_) => ErrorCode::InvalidImport,
+            ContractError::InvalidImportSignature => ErrorCode::InvalidImportSignature,
+            ContractError::ManifestAlreadyImported => ErrorCode::ManifestAlreadyImported,
+            ContractError::MigrationKeyNotConfigured => ErrorCode::MigrationKeyNotConfigured,
+            ContractError::InvalidSeriesResolution(_) => ErrorCode::InvalidSeriesResolution,
+            ContractError::TvlMetricsNotEnabled => ErrorCode::TvlMetricsNotEnabled,
+            ContractError::InvalidCircuitBreakerConfig(_) => ErrorCode::InvalidCircuitBreakerConfig,
+            ContractError::CircuitBreakerNotTripped => ErrorCode::CircuitBreakerNotTripped,
+            ContractError::DepositIdCollision => ErrorCode::DepositIdCollision,
+            ContractError::IdSchemeLocked => ErrorCode::IdSchemeLocked,
+            ContractError::InvalidInvoice(_) => ErrorCode::InvalidInvoice,
+            ContractError::DestinationNotWhitelisted => ErrorCode::DestinationNotWhitelisted,
+            ContractError::FeeDestinationNotProposed => ErrorCode::FeeDestinationNotProposed,
+            ContractError::FeeDestinationTooSoon { .. } => ErrorCode::FeeDestinationTooSoon,
+            ContractError::MissingParameter(_) => ErrorCode::MissingParameter,
+            ContractError::WithdrawalInFlight => ErrorCode::WithdrawalInFlight,
+            ContractError::SelfReferral => ErrorCode::SelfReferral,
+            ContractError::TokenDeprecated { .. } => ErrorCode::TokenDeprecated,
+            ContractError::MissingCapability { .. } => ErrorCode::MissingCapability,
+            ContractError::DepositNotPending => ErrorCode::DepositNotPending,
+            ContractError::DepositVoided => ErrorCode::DepositVoided,
+            ContractError::DepositNotVoided => ErrorCode::DepositNotVoided,
+            ContractError::WithdrawalBelowMinimum { .. } => ErrorCode::WithdrawalBelowMinimum,
+            ContractError::InvalidForecast(_) => ErrorCode::InvalidForecast,
+            ContractError::InvalidUtxoReference { .. } => ErrorCode::InvalidUtxoReference,
+            ContractError::TokenPaused { .. } => ErrorCode::TokenPaused,
+            ContractError::TokenNotPaused => ErrorCode::TokenNotPaused,
+            ContractError::StatePersistenceFailed(_) => ErrorCode::StatePersistenceFailed,
+            ContractError::OutboxEntryNotFound => ErrorCode::OutboxEntryNotFound,
+            ContractError::MusigNonceReused { .. } => ErrorCode::MusigNonceReused,
+            ContractError::ScriptTypeNotAllowed { .. } => ErrorCode::ScriptTypeNotAllowed,
+            ContractError::WrongNetwork { .. } => ErrorCode::WrongNetwork,
+            ContractError::Cancelled { .. } => ErrorCode::Cancelled,
+            ContractError::ExportFailed(_) => ErrorCode::ExportFailed,
+            ContractError::ReadOnlyMode => ErrorCode::ReadOnlyMode,
+        }
+    }
+    
+    /// Whether retrying the same operation later may succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ContractError::RpcError { source, .. } => matches!(
+                source,
+                bitcoincore_rpc::Error::JsonRpc(bitcoincore_rpc::jsonrpc::Error::Transport(_))
+                    | bitcoincore_rpc::Error::Io(_)
+            ),
+            ContractError::TransferFailed { stage, .. } => matches!(
+                stage,
+                TransferStage::BalanceCheck | TransferStage::FeeEstimation | TransferStage::Broadcast
+            ),
+            ContractError::BackendUnavailable(_) | ContractError::BackendBusy(_) | ContractError::ReentrancyDetected => true,
+            _ => false,
+        }
+    }
+}
+
+impl Serialize for ContractError {
+    /// Serialize as the stable code, the message and the error's own fields
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("code", &self.code())?;
+        map.serialize_entry("message", &self.to_string())?;
+        
+        match self {
+            ContractError::InvalidAmount { reason } => {
+                map.serialize_entry("reason", reason)?;
+            },
+            ContractError::InvalidLockPeriod { min, max, requested } => {
+                map.serialize_entry("min", min)?;
+                map.serialize_entry("max", max)?;
+                map.serialize_entry("requested", requested)?;
+            },
+            ContractError::InsufficientBalance { available, required } => {
+                map.serialize_entry("available", available)?;
+                map.serialize_entry("required", required)?;
+            },
+            ContractError::TokenDeprecated { token_type } | ContractError::TokenPaused { token_type } => {
+                map.serialize_entry("token_type", token_type)?;
+            },
+            ContractError::MissingCapability { capability } => {
+                map.serialize_entry("capability", capability)?;
+            },
+            ContractError::DepositLimitExceeded { token_type, limit, attempted } => {
+                map.serialize_entry("token_type", token_type)?;
+                map.serialize_entry("limit", limit)?;
+                map.serialize_entry("attempted", attempted)?;
+            },
+            ContractError::UserDepositLimitReached { limit, current } => {
+                map.serialize_entry("limit", limit)?;
+                map.serialize_entry("current", current)?;
+            },
+            ContractError::TotalDepositLimitReached { limit, current, attempted } => {
+                map.serialize_entry("limit", limit)?;
+                map.serialize_entry("current", current)?;
+                map.serialize_entry("attempted", attempted)?;
+            },
+            ContractError::EmergencyTooSoon { available_at } => {
+                map.serialize_entry("available_at", available_at)?;
+            },
+            ContractError::FeeDestinationTooSoon { activates_at } => {
+                map.serialize_entry("activates_at", activates_at)?;
+            },
+            ContractError::EmergencyLimitReached { limit, retry_at } => {
+                map.serialize_entry("limit", limit)?;
+                map.serialize_entry("retry_at", retry_at)?;
+            },
+            ContractError::DepositNotAboveFee { fee, amount } => {
+                map.serialize_entry("fee", fee)?;
+                map.serialize_entry("amount", amount)?;
+            },
+            ContractError::FeeExceedsLimit { required, limit } => {
+                map.serialize_entry("required", required)?;
+                map.serialize_entry("limit", limit)?;
+            },
+            ContractError::DustOutput { amount, threshold } => {
+                map.serialize_entry("amount", amount)?;
+                map.serialize_entry("threshold", threshold)?;
+            },
+            ContractError::WithdrawalBelowMinimum { amount, minimum } => {
+                map.serialize_entry("amount", amount)?;
+                map.serialize_entry("minimum", minimum)?;
+            },
+            ContractError::DuplicateOutput { address } => {
+                map.serialize_entry("address", address)?;
+            },
+            ContractError::ScriptTypeNotAllowed { script_type } => {
+                map.serialize_entry("script_type", script_type)?;
+            },
+            ContractError::WrongNetwork { address_network, expected_network } => {
+                map.serialize_entry("address_network", address_network)?;
+                map.serialize_entry("expected_network", expected_network)?;
+            },
+            ContractError::Cancelled { stage, processed } => {
+                map.serialize_entry("stage", stage)?;
+                map.serialize_entry("processed", processed)?;
+            },
+            ContractError::InvalidUtxoReference { part, reason } => {
+                map.serialize_entry("part", part)?;
+                map.serialize_entry("reason", reason)?;
+            },
+            ContractError::UtxoUnavailable { reference, reason } => {
+                map.serialize_entry("reference", reference)?;
+                map.serialize_entry("reason", reason)?;
+            },
+            ContractError::TransferFailed { stage, reason } => {
+                map.serialize_entry("stage", stage)?;
+                map.serialize_entry("reason", reason)?;
+            },
+            ContractError::NonceMismatch { expected, actual } => {
+                map.serialize_entry("expected", expected)?;
+                map.serialize_entry("actual", actual)?;
+            },
+            ContractError::RpcError { operation, .. } => {
+                map.serialize_entry("operation", operation)?;
+            },
+            ContractError::MusigNonceReused { spend_id } => {
+                map.serialize_entry("spend_id", spend_id)?;
+            },
+            ContractError::InitializationError(detail)
+                | ContractError::BitcoinTestnetError(detail)
+                | ContractError::BackendUnavailable(detail)
+                | ContractError::InvalidGovernanceConfig(detail)
+                | ContractError::InvalidConfirmationPolicy(detail)
+                | ContractError::InvalidDescriptor(detail)
+                | ContractError::BackendBusy(detail)
+                | ContractError::InvalidImport(detail)
+                | ContractError::InvalidSeriesResolution(detail)
+                | ContractError::InvalidCircuitBreakerConfig(detail)
+                | ContractError::InvalidInvoice(detail)
+                | ContractError::MissingParameter(detail)
+                | ContractError::InvalidForecast(detail)
+                | ContractError::StatePersistenceFailed(detail)
+                | ContractError::ExportFailed(detail) => {
+                map.serialize_entry("detail", detail)?;
+            },
+            ContractError::InvalidAddress
+                | ContractError::InvalidFeePercentage
+                | ContractError::DepositNotFound
+                | ContractError::DepositAlreadyWithdrawn
+                | ContractError::DepositLocked
+                | ContractError::Unauthorized
+                | ContractError::ContractPaused
+                | ContractError::UnsupportedTokenOperation
+                | ContractError::TokenValidationFailed
+                | ContractError::ArithmeticError
+                | ContractError::ReentrancyDetected
+                | ContractError::InvalidBitcoinTransaction
+                | ContractError::IdempotencyConflict
+                | ContractError::GovernanceRequired
+                | ContractError::ProposalNotFound
+                | ContractError::ProposalClosed
+                | ContractError::AlreadyApproved
+                | ContractError::ReservationNotFound
+                | ContractError::InvalidPreimage
+                | ContractError::UtxoAlreadyDeposited
+                | ContractError::DepositSuspended
+                | ContractError::InvalidInactivityPeriod
+                | ContractError::InheritanceNotClaimable
+                | ContractError::QuoteNotFound
+                | ContractError::QuoteExpired
+                | ContractError::QuoteMismatch
+                | ContractError::UnattributedFundsNotFound
+                | ContractError::DepositPending
+                | ContractError::DepositExpired
+                | ContractError::EscrowNotConfigured
+                | ContractError::TreasuryPolicyNotConfigured
+                | ContractError::InvalidImportSignature
+                | ContractError::ManifestAlreadyImported
+                | ContractError::MigrationKeyNotConfigured
+                | ContractError::TvlMetricsNotEnabled
+                | ContractError::CircuitBreakerNotTripped
+                | ContractError::DepositIdCollision
+                | ContractError::IdSchemeLocked
+                | ContractError::DestinationNotWhitelisted
+                | ContractError::FeeDestinationNotProposed
+                | ContractError::WithdrawalInFlight
+                | ContractError::SelfReferral
+                | ContractError::DepositNotPending
+                | ContractError::DepositVoided
+                | ContractError::DepositNotVoided
+                | ContractError::TokenNotPaused
+                | ContractError::OutboxEntryNotFound
+                | ContractError::ReadOnlyMode => {},
+        }
+        
+        map.end()
+    }
 }
 
 impl From<String> for ContractError {
     fn from(error: String) -> Self {
         ContractError::BitcoinTestnetError(error)
     }
-}
\ No newline at end of file
+}
+/// Errors when loading a versioned state snapshot
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    /// Snapshot is not valid JSON or does not match the expected schema
+    #[error("Invalid state snapshot: {0}")]
+    InvalidSnapshot(String),
+    
+    /// Snapshot version could not be parsed
+    #[error("Invalid state version: {0}")]
+    InvalidVersion(String),
+    
+    /// Snapshot was written by newer code than is running
+    #[error("State version {found} is newer than supported version {supported}")]
+    UnsupportedVersion {
+        /// Version of the snapshot
+        found: String,
+        /// Newest version this code can read
+        supported: String,
+    },
+    
+    /// No migration is registered for a version
+    #[error("No migration registered from state version {0}")]
+    MissingMigration(String),
+    
+    /// A migration could not transform the snapshot
+    #[error("Migration from state version {from} failed: {reason}")]
+    MigrationFailed {
+        /// Version the migration started from
+        from: String,
+        /// Failure reason
+        reason: String,
+    },
+    
+    /// Migrated state could not be restored into a contract
+    #[error("Failed to restore contract state: {0}")]
+    Restore(#[from] ContractError),
+}
+
+/// Errors of the encrypted state store
+#[derive(Error, Debug)]
+pub enum StateEncryptionError {
+    /// Stored blob lacks the encrypted state header
+    #[error("{blob} is not encrypted state")]
+    NotEncrypted {
+        /// Blob name
+        blob: String,
+    },
+    
+    /// Stored blob was written in a format this code cannot read
+    #[error("{blob} uses encryption format version {found}, expected {supported}")]
+    UnsupportedVersion {
+        /// Blob name
+        blob: String,
+        /// Version of the blob
+        found: u8,
+        /// Version this code reads and writes
+        supported: u8,
+    },
+    
+    /// Stored blob has a broken header
+    #[error("Malformed encrypted state {blob}: {reason}")]
+    Malformed {
+        /// Blob name
+        blob: String,
+        /// What is wrong with it
+        reason: String,
+    },
+    
+    /// Authentication failed, because the key is wrong or the blob was modified
+    #[error("Cannot decrypt {blob}: wrong key or tampered ciphertext")]
+    WrongKeyOrTampered {
+        /// Blob name
+        blob: String,
+    },
+    
+    /// Key or passphrase is unusable
+    #[error("Invalid state encryption key: {0}")]
+    InvalidKey(String),
+    
+    /// Reading or writing a blob failed
+    #[error("Encrypted state I/O failed: {0}")]
+    Io(String),
+    
+    /// Key rotation stopped on request before any blob was rewritten
+    #[error("Key rotation cancelled after decrypting {decrypted} blobs; nothing was rewritten")]
+    Cancelled {
+        /// Blobs decrypted before it stopped
+        decrypted: usize,
+    },
+}
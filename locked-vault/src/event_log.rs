@@ -0,0 +1,497 @@
+//! Tamper-evident event history
+//!
+//! `EventLog` is an event sink appending every contract event to a JSON lines
+//! file. Each `StoredEvent` carries the hash of the entry before it and the
+//! SHA-256 of its own canonical serialization, so changing, removing or
+//! reordering an entry breaks the chain from that entry on. Every
+//! `checkpoint_interval` events, and when the log is dropped, a checkpoint
+//! signed by the vault `Signer` commits to the head of the chain, so a
+//! rewritten tail is caught by anyone holding the vault public key.
+//! `verify_event_log` recomputes the chain and checks the checkpoints.
+//!
+//! An event caused by a withdrawal made with an `OperationContext` is stored
+//! with that context, which the entry's hash covers. Entries without one hash
+//! exactly as before contexts were recorded, so older logs still verify.
+//!
+//! The log also keeps a ledger per depositor address, built once from the
+//! existing file when it is opened and extended with every appended event,
+//! so `get_address_ledger` never rescans the file.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use bitcoincore_rpc::bitcoin::Network;
+use log::{error, info};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::signature::{SignatureVerifier, Signer};
+use crate::clock::{Clock, SystemClock};
+use crate::contract::authorization::OperationContext;
+use crate::events::{Event, EventSink};
+use crate::ledger::{LedgerEntry, LedgerIndex};
+use crate::logging::CONTRACT;
+
+/// Previous hash of the first entry of a log
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What an entry of the log records
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogRecord {
+    /// Event emitted by the contract
+    Event(Event),
+    /// Signed commitment to the entries before it
+    Checkpoint(Checkpoint),
+}
+
+/// Commitment of the vault key to the head of the chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Number of entries before the checkpoint
+    pub entries: u64,
+    /// Hash of the last entry before the checkpoint
+    pub head_hash: String,
+    /// When the checkpoint was written
+    pub timestamp: DateTime<Utc>,
+    /// Hex-encoded compressed public key of the signer
+    pub public_key: String,
+    /// Hex-encoded compact ECDSA signature over the head hash
+    pub signature: String,
+}
+
+/// Entry of the event log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredEvent {
+    /// Position in the log, from zero
+    pub index: u64,
+    /// Hash of the previous entry, `GENESIS_HASH` for the first
+    pub prev_hash: String,
+    /// Recorded event or checkpoint
+    pub record: LogRecord,
+    /// Context of the operation that caused the event, if it was given one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<OperationContext>,
+    /// Hex SHA-256 of the entry's canonical serialization
+    pub hash: String,
+}
+
+impl StoredEvent {
+    /// Create the entry at `index`, hashing it
+    pub fn new(index: u64, prev_hash: String, record: LogRecord) -> Result<Self, String> {
+        Self::with_context(index, prev_hash, record, None)
+    }
+    
+    /// Create the entry at `index` with the context of its operation, hashing both
+    pub fn with_context(index: u64, prev_hash: String, record: LogRecord, context: Option<OperationContext>) -> Result<Self, String> {
+        let value = serde_json::to_value(&record)
+            .map_err(|e| format!("Failed to serialize log record: {}", e))?;
+        let context_value = context.as_ref()
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(|e| format!("Failed to serialize operation context: {}", e))?;
+        let hash = hex::encode(entry_hash(index, &prev_hash, &value, context_value.as_ref()));
+        
+        Ok(Self {
+            index,
+            prev_hash,
+            record,
+            context,
+            hash,
+        })
+    }
+}
+
+/// SHA-256 of an entry's canonical serialization
+///
+/// The index, previous hash and record are serialized as JSON with object
+/// keys sorted, so the hash does not depend on struct field order and is
+/// recomputed identically from the stored line. The context is only part of
+/// the serialization when the entry has one.
+fn entry_hash(index: u64, prev_hash: &str, record: &serde_json::Value, context: Option<&serde_json::Value>) -> [u8; 32] {
+    let mut canonical = serde_json::json!({
+        "index": index,
+        "prev_hash": prev_hash,
+        "record": record,
+    });
+    if let Some(context) = context {
+        canonical["context"] = context.clone();
+    }
+    
+    sha256::Hash::hash(canonical.to_string().as_bytes()).to_byte_array()
+}
+
+/// Result of checking an event log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogVerification {
+    /// Entries found intact, checkpoints included
+    pub entries_checked: u64,
+    /// Signed checkpoints among them
+    pub checkpoints_verified: u64,
+    /// Index of the first entry that does not match the chain, if any
+    pub first_broken_index: Option<u64>,
+}
+
+impl LogVerification {
+    /// Whether every entry matched the chain
+    pub fn is_intact(&self) -> bool {
+        self.first_broken_index.is_none()
+    }
+}
+
+/// Event log settings
+#[derive(Debug, Clone)]
+pub struct EventLogConfig {
+    /// Path of the JSON lines file
+    pub path: PathBuf,
+    /// Events between two signed checkpoints
+    pub checkpoint_interval: u64,
+}
+
+impl EventLogConfig {
+    /// Create an event log configuration with the default checkpoint interval
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            checkpoint_interval: 100,
+        }
+    }
+    
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<(), String> {
+        if self.path.as_os_str().is_empty() {
+            return Err("Event log path cannot be empty".to_string());
+        }
+        
+        if self.checkpoint_interval == 0 {
+            return Err("Event log checkpoint interval cannot be zero".to_string());
+        }
+        
+        Ok(())
+    }
+}
+
+/// Open end of the log file
+#[derive(Debug)]
+struct LogHead {
+    /// File opened for appending
+    file: File,
+    /// Index of the next entry
+    next_index: u64,
+    /// Hash of the last entry
+    head_hash: String,
+    /// Events appended since the last checkpoint
+    since_checkpoint: u64,
+}
+
+/// Event sink appending every event to a hash-chained log file
+#[derive(Debug)]
+pub struct EventLog {
+    /// Log settings
+    config: EventLogConfig,
+    /// Vault key signing checkpoints, none to write no checkpoints
+    signer: Option<Arc<dyn Signer>>,
+    /// Time source for checkpoint timestamps
+    clock: Arc<dyn Clock>,
+    /// Open end of the log
+    head: Mutex<LogHead>,
+    /// Ledger per depositor address, updated under the head lock
+    ledger: Mutex<LedgerIndex>,
+}
+
+impl EventLog {
+    /// Open the log, creating the file or continuing the chain already in it
+    pub fn open(config: EventLogConfig, signer: Option<Arc<dyn Signer>>) -> Result<Self, String> {
+        config.validate()?;
+        
+        // Find the head of the existing chain
+        let mut next_index = 0;
+        let mut head_hash = GENESIS_HASH.to_string();
+        let mut since_checkpoint = 0;
+        let mut ledger = LedgerIndex::default();
+        
+        if config.path.exists() {
+            let file = File::open(&config.path)
+                .map_err(|e| format!("Failed to read event log {}: {}", config.path.display(), e))?;
+            
+            for line in BufReader::new(file).lines() {
+                let line = line
+                    .map_err(|e| format!("Failed to read event log {}: {}", config.path.display(), e))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                
+                let entry: StoredEvent = serde_json::from_str(&line)
+                    .map_err(|e| format!("Event log {} has an unreadable entry {}: {}", config.path.display(), next_index, e))?;
+                
+                match &entry.record {
+                    LogRecord::Event(event) => {
+                        ledger.record(entry.index, event);
+                        since_checkpoint += 1;
+                    },
+                    LogRecord::Checkpoint(_) => since_checkpoint = 0,
+                }
+                next_index = entry.index + 1;
+                head_hash = entry.hash;
+            }
+        }
+        
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)
+            .map_err(|e| format!("Failed to open event log {}: {}", config.path.display(), e))?;
+        
+        info!(target: CONTRACT, "Event log {} opened at entry {}", config.path.display(), next_index);
+        
+        Ok(Self {
+            config,
+            signer,
+            clock: Arc::new(SystemClock),
+            head: Mutex::new(LogHead {
+                file,
+                next_index,
+                head_hash,
+                since_checkpoint,
+            }),
+            ledger: Mutex::new(ledger),
+        })
+    }
+    
+    /// History of a depositor address in log order
+    /// 
+    /// Only entries from `from` (inclusive) to `to` (exclusive) count when
+    /// given; of those, `offset` are skipped and at most `limit` returned.
+    /// Events appended later only add entries after the existing ones, so
+    /// paging through a window is stable while the contract keeps running.
+    pub fn get_address_ledger(
+        &self,
+        address: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<Vec<LedgerEntry>, String> {
+        let ledger = self.ledger.lock()
+            .map_err(|_| "Event log ledger lock poisoned".to_string())?;
+        
+        Ok(ledger.entries(address, from, to, offset, limit))
+    }
+    
+    /// Replace the time source used for checkpoint timestamps
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+    
+    /// Sign the current head of the chain, if a signer is set and events were appended since the last checkpoint
+    pub fn checkpoint(&self) -> Result<(), String> {
+        let mut head = self.head.lock()
+            .map_err(|_| "Event log lock poisoned".to_string())?;
+        
+        self.write_checkpoint(&mut head)
+    }
+    
+    /// Append a checkpoint entry under the held lock
+    fn write_checkpoint(&self, head: &mut LogHead) -> Result<(), String> {
+        let signer = match &self.signer {
+            Some(signer) if head.since_checkpoint > 0 => signer,
+            _ => return Ok(()),
+        };
+        
+        // Sign the hash of the last entry
+        let head_hash = hex::decode(&head.head_hash)
+            .map_err(|e| format!("Invalid event log head hash: {}", e))?;
+        let signature = signer.sign(&head_hash)
+            .map_err(|e| format!("Failed to sign event log checkpoint: {}", e))?;
+        
+        let checkpoint = Checkpoint {
+            entries: head.next_index,
+            head_hash: head.head_hash.clone(),
+            timestamp: self.clock.now(),
+            public_key: hex::encode(signer.public_key()),
+            signature: hex::encode(signature),
+        };
+        
+        self.append(head, LogRecord::Checkpoint(checkpoint), None)?;
+        head.since_checkpoint = 0;
+        
+        Ok(())
+    }
+    
+    /// Append an entry after the head, hashing it once
+    fn append(&self, head: &mut LogHead, record: LogRecord, context: Option<OperationContext>) -> Result<(), String> {
+        let entry = StoredEvent::with_context(head.next_index, head.head_hash.clone(), record, context)?;
+        
+        let mut line = serde_json::to_string(&entry)
+            .map_err(|e| format!("Failed to serialize log entry: {}", e))?;
+        line.push('\n');
+        
+        head.file.write_all(line.as_bytes())
+            .and_then(|_| head.file.flush())
+            .map_err(|e| format!("Failed to write event log {}: {}", self.config.path.display(), e))?;
+        
+        head.next_index += 1;
+        head.head_hash = entry.hash;
+        
+        Ok(())
+    }
+}
+
+impl EventSink for EventLog {
+    fn publish(&self, event: &Event) {
+        self.publish_with_context(event, None);
+    }
+    
+    fn publish_with_context(&self, event: &Event, context: Option<&OperationContext>) {
+        let mut head = match self.head.lock() {
+            Ok(head) => head,
+            Err(_) => {
+                error!(target: CONTRACT, "Event log lock poisoned, {} not recorded", event.name());
+                return;
+            },
+        };
+        
+        let seq = head.next_index;
+        if let Err(e) = self.append(&mut head, LogRecord::Event(event.clone()), context.cloned()) {
+            error!(target: CONTRACT, "{} not recorded: {}", event.name(), e);
+            return;
+        }
+        head.since_checkpoint += 1;
+        
+        // Extend the ledger in log order
+        match self.ledger.lock() {
+            Ok(mut ledger) => ledger.record(seq, event),
+            Err(_) => error!(target: CONTRACT, "Event log ledger lock poisoned, {} not in the ledger", event.name()),
+        }
+        
+        // Sign the chain every checkpoint_interval events
+        if head.since_checkpoint >= self.config.checkpoint_interval {
+            if let Err(e) = self.write_checkpoint(&mut head) {
+                error!(target: CONTRACT, "{}", e);
+            }
+        }
+    }
+}
+
+impl Drop for EventLog {
+    fn drop(&mut self) {
+        // Commit to the events appended since the last checkpoint
+        if let Err(e) = self.checkpoint() {
+            error!(target: CONTRACT, "{}", e);
+        }
+    }
+}
+
+/// Read the ledger of a depositor address out of an event log file, in log order
+/// 
+/// The file is only read, so this works on a copy of the log or alongside a
+/// running daemon. The chain is not verified; see `verify_event_log`.
+pub fn read_address_ledger(path: &Path, address: &str) -> Result<Vec<LedgerEntry>, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to read event log {}: {}", path.display(), e))?;
+    
+    let mut ledger = LedgerIndex::default();
+    for line in BufReader::new(file).lines() {
+        let line = line
+            .map_err(|e| format!("Failed to read event log {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let entry: StoredEvent = serde_json::from_str(&line)
+            .map_err(|e| format!("Event log {} has an unreadable entry: {}", path.display(), e))?;
+        if let LogRecord::Event(event) = &entry.record {
+            ledger.record(entry.index, event);
+        }
+    }
+    
+    Ok(ledger.entries(address, None, None, 0, usize::MAX))
+}
+
+/// Recompute the hash chain of an event log and check its checkpoints
+///
+/// Checkpoints must be signed by `vault_public_key` if given, or else by the
+/// key they name. Checking stops at the first entry that cannot be read, is
+/// out of sequence, does not link to the entry before it, does not match its
+/// hash or carries an invalid checkpoint.
+pub fn verify_event_log(path: &Path, vault_public_key: Option<&[u8]>) -> Result<LogVerification, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to read event log {}: {}", path.display(), e))?;
+    
+    let verifier = SignatureVerifier::new(Network::Testnet);
+    let mut verification = LogVerification {
+        entries_checked: 0,
+        checkpoints_verified: 0,
+        first_broken_index: None,
+    };
+    let mut prev_hash = GENESIS_HASH.to_string();
+    
+    for line in BufReader::new(file).lines() {
+        let line = line
+            .map_err(|e| format!("Failed to read event log {}: {}", path.display(), e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        
+        let index = verification.entries_checked;
+        let entry = match check_entry(&line, index, &prev_hash, &verifier, vault_public_key) {
+            Some(entry) => entry,
+            None => {
+                verification.first_broken_index = Some(index);
+                break;
+            },
+        };
+        
+        if matches!(entry.record, LogRecord::Checkpoint(_)) {
+            verification.checkpoints_verified += 1;
+        }
+        verification.entries_checked += 1;
+        prev_hash = entry.hash;
+    }
+    
+    Ok(verification)
+}
+
+/// Check one stored line against the chain, returning the entry if it is intact
+fn check_entry(
+    line: &str,
+    index: u64,
+    prev_hash: &str,
+    verifier: &SignatureVerifier,
+    vault_public_key: Option<&[u8]>,
+) -> Option<StoredEvent> {
+    // Hash the record as stored, not as this version would serialize it
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let entry: StoredEvent = serde_json::from_value(value.clone()).ok()?;
+    
+    if entry.index != index || entry.prev_hash != prev_hash {
+        return None;
+    }
+    
+    // Entries recorded without a context hash as they did before contexts existed
+    if hex::encode(entry_hash(index, prev_hash, &value["record"], value.get("context"))) != entry.hash {
+        return None;
+    }
+    
+    // A checkpoint must commit to the entries before it
+    if let LogRecord::Checkpoint(checkpoint) = &entry.record {
+        let public_key = hex::decode(&checkpoint.public_key).ok()?;
+        if vault_public_key.is_some_and(|expected| expected != public_key.as_slice()) {
+            return None;
+        }
+        
+        if checkpoint.entries != index || checkpoint.head_hash != prev_hash {
+            return None;
+        }
+        
+        let head_hash = hex::decode(&checkpoint.head_hash).ok()?;
+        let signature = hex::decode(&checkpoint.signature).ok()?;
+        if !verifier.verify(&head_hash, &signature, &public_key).unwrap_or(false) {
+            return None;
+        }
+    }
+    
+    Some(entry)
+}
@@ -1,10 +1,22 @@
+use std::fmt;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
-use crate::models::TokenType;
+use crate::bitcoin::anchor::ChainAnchor;
+use crate::models::{AssetDetail, DepositFee, TokenType};
+use crate::contract::governance::OwnerAction;
+use crate::contract::circuit_breaker::PauseReason;
+use crate::contract::recovery::WithdrawalCorrection;
+use crate::contract::refund::RefundReason;
+use crate::contract::token_support::DeprecationReason;
+use crate::contract::authorization::OperationContext;
 
 /// Events emitted by the contract
+/// 
+/// New variants and fields may be added; match with a wildcard arm and prefer
+/// the accessor methods.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum Event {
     /// Deposit event
     Deposited {
@@ -14,14 +26,25 @@ pub enum Event {
         depositor_address: String,
         /// Token type
         token_type: TokenType,
-        /// Deposit amount
+        /// Gross amount transferred to the contract
         deposit_amount: u64,
+        /// Deposit fee taken from the gross amount
+        #[serde(default)]
+        fee_amount: u64,
+        /// Net amount locked in the deposit
+        #[serde(default)]
+        locked_amount: u64,
         /// Unlock timestamp
         unlock_timestamp: DateTime<Utc>,
         /// Transaction hash
         transaction_hash: Option<String>,
         /// Block number
         block_number: Option<u64>,
+        /// Chain tip when the event was emitted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chain_anchor: Option<ChainAnchor>,
+        /// Resource backing the deposit
+        asset_detail: Option<AssetDetail>,
         /// Timestamp
         timestamp: DateTime<Utc>,
     },
@@ -38,10 +61,17 @@ pub enum Event {
         withdrawn_amount: u64,
         /// Whether this was an emergency withdrawal
         is_emergency_withdrawal: bool,
+        /// Address that claimed a claimable deposit with its preimage
+        claimed_by: Option<String>,
         /// Transaction hash
         transaction_hash: Option<String>,
         /// Block number
         block_number: Option<u64>,
+        /// Chain tip when the event was emitted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chain_anchor: Option<ChainAnchor>,
+        /// Resource backing the deposit
+        asset_detail: Option<AssetDetail>,
         /// Timestamp
         timestamp: DateTime<Utc>,
     },
@@ -58,10 +88,18 @@ pub enum Event {
         withdrawn_amount: u64,
         /// Fee amount
         fee_amount: u64,
+        /// Part of the fee paid into the insurance pool
+        #[serde(default)]
+        insurance_amount: u64,
         /// Transaction hash
         transaction_hash: Option<String>,
         /// Block number
         block_number: Option<u64>,
+        /// Chain tip when the event was emitted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        chain_anchor: Option<ChainAnchor>,
+        /// Resource backing the deposit
+        asset_detail: Option<AssetDetail>,
         /// Timestamp
         timestamp: DateTime<Utc>,
     },
@@ -74,6 +112,10 @@ pub enum Event {
         fee_amount: u64,
         /// Collector address
         collector_address: String,
+        /// Address the fees were sent to
+        destination_address: String,
+        /// Fees of this token left in the contract
+        remaining_fees: u64,
         /// Transaction hash
         transaction_hash: Option<String>,
         /// Timestamp
@@ -84,6 +126,9 @@ pub enum Event {
     ContractPaused {
         /// Pauser address
         pauser_address: String,
+        /// Why the contract paused itself, for automatic pauses
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<PauseReason>,
         /// Timestamp
         timestamp: DateTime<Utc>,
     },
@@ -106,6 +151,214 @@ pub enum Event {
         timestamp: DateTime<Utc>,
     },
     
+    /// Deposit assigned to a new depositor address event
+    DepositOwnershipTransferred {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Previous depositor address
+        previous_owner: String,
+        /// New depositor address
+        new_owner: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Governance proposal created event
+    ProposalCreated {
+        /// Proposal ID
+        proposal_id: u64,
+        /// Proposing owner
+        proposer: String,
+        /// Proposed action
+        action: OwnerAction,
+        /// When the proposal expires
+        expires_at: DateTime<Utc>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Governance proposal approved event
+    ProposalApproved {
+        /// Proposal ID
+        proposal_id: u64,
+        /// Approving owner
+        approver: String,
+        /// Number of approvals so far
+        approvals: u32,
+        /// Approvals required for execution
+        threshold: u32,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Governance proposal executed event
+    ProposalExecuted {
+        /// Proposal ID
+        proposal_id: u64,
+        /// Executed action
+        action: OwnerAction,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Governance proposal expired event
+    ProposalExpired {
+        /// Proposal ID
+        proposal_id: u64,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Lock policy updated event
+    LockPolicyUpdated {
+        /// Contract-wide maximum lock period in days
+        max_lock_days: u32,
+        /// Per-token maximum lock periods, sorted by token
+        per_token_max: Vec<(TokenType, u32)>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit suspended after its funding transaction was reorganized out
+    DepositSuspended {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Funding transaction ID
+        txid: String,
+        /// What changed on chain
+        reason: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Suspended deposit reinstated after its funding transaction re-confirmed
+    DepositReinstated {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Funding transaction ID
+        txid: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Beneficiary designated for a deposit event
+    InheritanceSet {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Beneficiary address
+        beneficiary_address: String,
+        /// Days of depositor inactivity after maturity before the beneficiary may claim
+        inactivity_days: u32,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit entering its depositor's reminder window event
+    MaturityReminder {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Deposited amount
+        amount: u64,
+        /// When the deposit unlocks
+        unlock_timestamp: DateTime<Utc>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit paid out to its beneficiary after depositor inactivity event
+    InheritanceClaimed {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Beneficiary address
+        beneficiary_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Claimed amount
+        claimed_amount: u64,
+        /// Last recorded depositor activity
+        last_activity: DateTime<Utc>,
+        /// Resource backing the deposit
+        asset_detail: Option<AssetDetail>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Funds sent to the contract wallet without a deposit detected event
+    UnattributedFundsDetected {
+        /// Output reference (`txid:vout`)
+        outpoint: String,
+        /// Amount in satoshis
+        amount: u64,
+        /// Addresses that funded the transaction, best effort
+        sender_addresses: Vec<String>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Unattributed funds returned to a sender event
+    UnattributedFundsRefunded {
+        /// Output reference (`txid:vout`)
+        outpoint: String,
+        /// Refund destination
+        destination_address: String,
+        /// Refunded amount in satoshis
+        amount: u64,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Funds queued for a refund to their origin event
+    RefundQueued {
+        /// Refund ID
+        refund_id: u64,
+        /// Refund destination
+        destination_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Amount received
+        amount: u64,
+        /// Amount kept to cover the network fee
+        fee_deduction: u64,
+        /// Why the funds are refunded
+        reason: RefundReason,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Queued refund sent event
+    RefundSent {
+        /// Refund ID
+        refund_id: u64,
+        /// Refund destination
+        destination_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Amount paid back
+        refunded_amount: u64,
+        /// Amount kept to cover the network fee
+        fee_deduction: u64,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit fee changed event
+    DepositFeeUpdated {
+        /// Token type
+        token_type: TokenType,
+        /// New deposit fee, `None` if cleared
+        deposit_fee: Option<DepositFee>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
     /// Token support added event
     TokenSupportAdded {
         /// Token type
@@ -121,6 +374,259 @@ pub enum Event {
         /// Timestamp
         timestamp: DateTime<Utc>,
     },
+    
+    /// Supported token closed to new deposits after its backend lost support
+    TokenSupportDeprecated {
+        /// Token type
+        token_type: TokenType,
+        /// Why the token was deprecated
+        reason: DeprecationReason,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deprecated token open to new deposits again event
+    TokenSupportReinstated {
+        /// Token type
+        token_type: TokenType,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Single token paused event
+    TokenPaused {
+        /// Token type
+        token_type: TokenType,
+        /// Pauser address
+        pauser_address: String,
+        /// Why the token was paused, for automatic pauses
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<PauseReason>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Single token unpaused event
+    TokenUnpaused {
+        /// Token type
+        token_type: TokenType,
+        /// Unpauser address
+        unpauser_address: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Insurance share of emergency fees changed event
+    InsuranceShareUpdated {
+        /// New percentage of every emergency fee paid into the pool
+        insurance_share_percentage: u8,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Compensation paid from the insurance pool event
+    InsuranceCompensationPaid {
+        /// Deposit whose loss is compensated, if the payment names one
+        deposit_id: Option<u64>,
+        /// Compensated address
+        recipient_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Paid amount
+        amount: u64,
+        /// Why the compensation was paid
+        reason: String,
+        /// Pool balance of the token after the payment
+        remaining_pool: u64,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit created waiting for its funding event
+    DepositPending {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Amount expected, fee included
+        deposit_amount: u64,
+        /// When the deposit expires unless funded before
+        expires_at: DateTime<Utc>,
+        /// Resource backing the deposit
+        asset_detail: Option<AssetDetail>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Pending deposit expired unfunded event
+    DepositExpired {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Amount that was locked once funded
+        amount: u64,
+        /// When the deposit was due to be funded
+        expires_at: DateTime<Utc>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Pending deposit voided by the owner event
+    DepositVoided {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Amount that was locked once funded
+        amount: u64,
+        /// Why the owner voided the deposit
+        reason: String,
+        /// Whether a queued funding transfer was cancelled
+        transfer_cancelled: bool,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Voided deposit restored from its late funding event
+    DepositRestored {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Output that funded the deposit
+        outpoint: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit carried over from an older deployment event
+    DepositImported {
+        /// Deposit ID in this contract
+        deposit_id: u64,
+        /// Deposit ID in the older deployment
+        original_deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Locked amount
+        amount: u64,
+        /// When the deposit was made in the older deployment
+        deposit_timestamp: DateTime<Utc>,
+        /// Unlock timestamp
+        unlock_timestamp: DateTime<Utc>,
+        /// Whether the deposit was already withdrawn and is imported as history only
+        withdrawn: bool,
+        /// Hex-encoded content hash of the import manifest
+        manifest_hash: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Hot wallet excess sent to cold storage event
+    TreasurySwept {
+        /// Cold storage address
+        cold_address: String,
+        /// Swept amount in satoshis
+        amount: u64,
+        /// Hot wallet balance before the sweep
+        hot_balance: u64,
+        /// Withdrawals due within the float window, kept in the hot wallet
+        due_within_float: u64,
+        /// Sweep transaction ID, if the backend reports one
+        txid: Option<String>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Circuit breaker reset and the contract unpaused by the owner
+    CircuitBreakerReset {
+        /// Owner address
+        reset_by: String,
+        /// Why the breaker had tripped
+        reason: PauseReason,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Fee withdrawal destination proposed for the whitelist
+    FeeDestinationProposed {
+        /// Proposed address
+        address: String,
+        /// Owner address
+        proposed_by: String,
+        /// When the address may be activated
+        activates_at: DateTime<Utc>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Proposed fee withdrawal destination activated after its delay
+    FeeDestinationActivated {
+        /// Activated address
+        address: String,
+        /// Owner address
+        activated_by: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Fee withdrawal destination removed from the whitelist or its proposal withdrawn
+    FeeDestinationRemoved {
+        /// Removed address
+        address: String,
+        /// Owner address
+        removed_by: String,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Withdrawal left in flight by a restart settled at startup
+    WithdrawalRecovered {
+        /// Deposit ID
+        deposit_id: u64,
+        /// What was corrected
+        correction: WithdrawalCorrection,
+        /// Transaction paying the deposit, if known
+        transaction_hash: Option<String>,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Deposit made on a referral event
+    ReferralRecorded {
+        /// Deposit ID
+        deposit_id: u64,
+        /// Depositor address
+        depositor_address: String,
+        /// Address credited a share of the deposit's fees
+        referrer_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Share of the deposit fee credited so far
+        credited_amount: u64,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
+    
+    /// Referral rewards paid out event
+    ReferralRewardClaimed {
+        /// Referrer address
+        referrer_address: String,
+        /// Token type
+        token_type: TokenType,
+        /// Paid amount
+        amount: u64,
+        /// Timestamp
+        timestamp: DateTime<Utc>,
+    },
 }
 
 impl Event {
@@ -134,8 +640,90 @@ impl Event {
             Event::ContractPaused { .. } => "ContractPaused",
             Event::ContractUnpaused { .. } => "ContractUnpaused",
             Event::OwnershipTransferred { .. } => "OwnershipTransferred",
+            Event::DepositOwnershipTransferred { .. } => "DepositOwnershipTransferred",
+            Event::ProposalCreated { .. } => "ProposalCreated",
+            Event::ProposalApproved { .. } => "ProposalApproved",
+            Event::ProposalExecuted { .. } => "ProposalExecuted",
+            Event::ProposalExpired { .. } => "ProposalExpired",
+            Event::LockPolicyUpdated { .. } => "LockPolicyUpdated",
+            Event::DepositSuspended { .. } => "DepositSuspended",
+            Event::DepositReinstated { .. } => "DepositReinstated",
+            Event::InheritanceSet { .. } => "InheritanceSet",
+            Event::MaturityReminder { .. } => "MaturityReminder",
+            Event::InheritanceClaimed { .. } => "InheritanceClaimed",
+            Event::UnattributedFundsDetected { .. } => "UnattributedFundsDetected",
+            Event::UnattributedFundsRefunded { .. } => "UnattributedFundsRefunded",
+            Event::RefundQueued { .. } => "RefundQueued",
+            Event::RefundSent { .. } => "RefundSent",
+            Event::DepositFeeUpdated { .. } => "DepositFeeUpdated",
             Event::TokenSupportAdded { .. } => "TokenSupportAdded",
             Event::TokenSupportRemoved { .. } => "TokenSupportRemoved",
+            Event::TokenSupportDeprecated { .. } => "TokenSupportDeprecated",
+            Event::TokenSupportReinstated { .. } => "TokenSupportReinstated",
+            Event::TokenPaused { .. } => "TokenPaused",
+            Event::TokenUnpaused { .. } => "TokenUnpaused",
+            Event::InsuranceShareUpdated { .. } => "InsuranceShareUpdated",
+            Event::InsuranceCompensationPaid { .. } => "InsuranceCompensationPaid",
+            Event::DepositPending { .. } => "DepositPending",
+            Event::DepositExpired { .. } => "DepositExpired",
+            Event::DepositVoided { .. } => "DepositVoided",
+            Event::DepositRestored { .. } => "DepositRestored",
+            Event::DepositImported { .. } => "DepositImported",
+            Event::TreasurySwept { .. } => "TreasurySwept",
+            Event::CircuitBreakerReset { .. } => "CircuitBreakerReset",
+            Event::FeeDestinationProposed { .. } => "FeeDestinationProposed",
+            Event::FeeDestinationActivated { .. } => "FeeDestinationActivated",
+            Event::FeeDestinationRemoved { .. } => "FeeDestinationRemoved",
+            Event::WithdrawalRecovered { .. } => "WithdrawalRecovered",
+            Event::ReferralRecorded { .. } => "ReferralRecorded",
+            Event::ReferralRewardClaimed { .. } => "ReferralRewardClaimed",
+        }
+    }
+    
+    /// Get the deposit the event concerns, if any
+    pub fn deposit_id(&self) -> Option<u64> {
+        match self {
+            Event::Deposited { deposit_id, .. }
+            | Event::Withdrawn { deposit_id, .. }
+            | Event::EmergencyWithdrawn { deposit_id, .. }
+            | Event::DepositOwnershipTransferred { deposit_id, .. }
+            | Event::DepositSuspended { deposit_id, .. }
+            | Event::DepositReinstated { deposit_id, .. }
+            | Event::InheritanceSet { deposit_id, .. }
+            | Event::MaturityReminder { deposit_id, .. }
+            | Event::InheritanceClaimed { deposit_id, .. }
+            | Event::DepositPending { deposit_id, .. }
+            | Event::DepositExpired { deposit_id, .. }
+            | Event::DepositVoided { deposit_id, .. }
+            | Event::DepositRestored { deposit_id, .. }
+            | Event::DepositImported { deposit_id, .. }
+            | Event::WithdrawalRecovered { deposit_id, .. }
+            | Event::ReferralRecorded { deposit_id, .. } => Some(*deposit_id),
+            Event::InsuranceCompensationPaid { deposit_id, .. } => *deposit_id,
+            Event::RefundQueued { reason, .. } => reason.deposit_id(),
+            _ => None,
+        }
+    }
+    
+    /// Get the resource backing the deposit the event concerns, if any
+    pub fn asset_detail(&self) -> Option<&AssetDetail> {
+        match self {
+            Event::Deposited { asset_detail, .. }
+            | Event::Withdrawn { asset_detail, .. }
+            | Event::EmergencyWithdrawn { asset_detail, .. }
+            | Event::InheritanceClaimed { asset_detail, .. }
+            | Event::DepositPending { asset_detail, .. } => asset_detail.as_ref(),
+            _ => None,
+        }
+    }
+    
+    /// Get the chain tip the event is anchored to, if any
+    pub fn chain_anchor(&self) -> Option<&ChainAnchor> {
+        match self {
+            Event::Deposited { chain_anchor, .. }
+            | Event::Withdrawn { chain_anchor, .. }
+            | Event::EmergencyWithdrawn { chain_anchor, .. } => chain_anchor.as_ref(),
+            _ => None,
         }
     }
     
@@ -149,8 +737,59 @@ impl Event {
             Event::ContractPaused { timestamp, .. } => *timestamp,
             Event::ContractUnpaused { timestamp, .. } => *timestamp,
             Event::OwnershipTransferred { timestamp, .. } => *timestamp,
+            Event::DepositOwnershipTransferred { timestamp, .. } => *timestamp,
+            Event::ProposalCreated { timestamp, .. } => *timestamp,
+            Event::ProposalApproved { timestamp, .. } => *timestamp,
+            Event::ProposalExecuted { timestamp, .. } => *timestamp,
+            Event::ProposalExpired { timestamp, .. } => *timestamp,
+            Event::LockPolicyUpdated { timestamp, .. } => *timestamp,
+            Event::DepositSuspended { timestamp, .. } => *timestamp,
+            Event::DepositReinstated { timestamp, .. } => *timestamp,
+            Event::InheritanceSet { timestamp, .. } => *timestamp,
+            Event::MaturityReminder { timestamp, .. } => *timestamp,
+            Event::InheritanceClaimed { timestamp, .. } => *timestamp,
+            Event::UnattributedFundsDetected { timestamp, .. } => *timestamp,
+            Event::UnattributedFundsRefunded { timestamp, .. } => *timestamp,
+            Event::RefundQueued { timestamp, .. } => *timestamp,
+            Event::RefundSent { timestamp, .. } => *timestamp,
+            Event::DepositFeeUpdated { timestamp, .. } => *timestamp,
             Event::TokenSupportAdded { timestamp, .. } => *timestamp,
             Event::TokenSupportRemoved { timestamp, .. } => *timestamp,
+            Event::TokenSupportDeprecated { timestamp, .. } => *timestamp,
+            Event::TokenSupportReinstated { timestamp, .. } => *timestamp,
+            Event::TokenPaused { timestamp, .. } => *timestamp,
+            Event::TokenUnpaused { timestamp, .. } => *timestamp,
+            Event::InsuranceShareUpdated { timestamp, .. } => *timestamp,
+            Event::InsuranceCompensationPaid { timestamp, .. } => *timestamp,
+            Event::DepositPending { timestamp, .. } => *timestamp,
+            Event::DepositExpired { timestamp, .. } => *timestamp,
+            Event::DepositVoided { timestamp, .. } => *timestamp,
+            Event::DepositRestored { timestamp, .. } => *timestamp,
+            Event::DepositImported { timestamp, .. } => *timestamp,
+            Event::TreasurySwept { timestamp, .. } => *timestamp,
+            Event::CircuitBreakerReset { timestamp, .. } => *timestamp,
+            Event::FeeDestinationProposed { timestamp, .. } => *timestamp,
+            Event::FeeDestinationActivated { timestamp, .. } => *timestamp,
+            Event::FeeDestinationRemoved { timestamp, .. } => *timestamp,
+            Event::WithdrawalRecovered { timestamp, .. } => *timestamp,
+            Event::ReferralRecorded { timestamp, .. } => *timestamp,
+            Event::ReferralRewardClaimed { timestamp, .. } => *timestamp,
         }
     }
+}
+
+/// Receiver of events emitted by the contract
+/// 
+/// Sinks are called synchronously from contract operations, so implementations
+/// must not block; slow deliveries belong on a background queue.
+pub trait EventSink: fmt::Debug + Send + Sync {
+    /// Publish an event
+    fn publish(&self, event: &Event);
+    
+    /// Publish an event with the context of the operation that caused it
+    /// 
+    /// Sinks that do not record contexts publish the event alone.
+    fn publish_with_context(&self, event: &Event, _context: Option<&OperationContext>) {
+        self.publish(event);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,236 @@
+//! Runtime diagnostics of the transfer machinery
+//!
+//! When withdrawals are slow the operator needs to see inside the runtime:
+//! what waits in the pending transfer queue, whether the background workers
+//! are still looping, how hard the node RPC rate limiter is pushing back and
+//! which broadcast transaction has been unconfirmed the longest, which
+//! optional backends are cut off by their feature gate, and how verbosely
+//! each log target is logged.
+//! `RuntimeDiagnostics` gathers all of it from `BitcoinTestnetTransfer`, its
+//! `MempoolMonitor` and the daemon loop, and is served by `GET /diagnostics`
+//! and printed by `vault diag`.
+//!
+//! Collecting never waits on a lock the hot paths hold. Every locked value
+//! is read with `try_lock`; when the lock is busy the last value read is
+//! returned instead, as a `Sampled` marked stale with the time it was read.
+//! Worker liveness and rate limiter counters are atomics and always current.
+
+use std::sync::{Arc, Mutex, PoisonError, TryLockError};
+use std::sync::atomic::{AtomicI64, Ordering};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::bitcoin::feature_gate::FeatureGateStatus;
+use crate::logging::TargetLevel;
+use crate::models::{TokenType, TransferPriority};
+
+/// Worker name of the mempool monitor loop
+pub const MEMPOOL_MONITOR_WORKER: &str = "mempool_monitor";
+
+/// Worker name of the payout batcher, which beats whenever its window is checked
+pub const PAYOUT_BATCHER_WORKER: &str = "payout_batcher";
+
+/// Worker name of the pending transfer processor
+pub const TRANSFER_PROCESSOR_WORKER: &str = "transfer_processor";
+
+/// Worker name of the daemon's main loop
+pub const DAEMON_WORKER: &str = "daemon";
+
+/// Time of the last loop of a background worker, updated without locking
+#[derive(Debug, Clone, Default)]
+pub struct Heartbeat {
+    /// Unix milliseconds of the last beat, zero before the first
+    last_beat_millis: Arc<AtomicI64>,
+}
+
+impl Heartbeat {
+    /// Create a heartbeat that has not beaten yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Record a loop of the worker now
+    pub fn beat(&self) {
+        self.beat_at(Utc::now());
+    }
+    
+    /// Record a loop of the worker at a given time
+    pub fn beat_at(&self, timestamp: DateTime<Utc>) {
+        self.last_beat_millis.store(timestamp.timestamp_millis(), Ordering::Relaxed);
+    }
+    
+    /// Get the time of the last loop, `None` before the first
+    pub fn last_beat(&self) -> Option<DateTime<Utc>> {
+        match self.last_beat_millis.load(Ordering::Relaxed) {
+            0 => None,
+            millis => DateTime::from_timestamp_millis(millis),
+        }
+    }
+}
+
+/// Value read from behind a lock, possibly an earlier reading
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sampled<T> {
+    /// Value as of `sampled_at`
+    pub value: T,
+    /// When the value was read, `None` if it never could be
+    pub sampled_at: Option<DateTime<Utc>>,
+    /// Whether the lock was busy and the value is an earlier reading
+    pub stale: bool,
+}
+
+/// Last reading of a locked value, for when its lock is busy
+#[derive(Debug)]
+pub(crate) struct SampleCache<T> {
+    /// Last value read and when
+    last: Mutex<Option<(T, DateTime<Utc>)>>,
+}
+
+impl<T> Default for SampleCache<T> {
+    fn default() -> Self {
+        Self { last: Mutex::new(None) }
+    }
+}
+
+impl<T: Clone + Default> SampleCache<T> {
+    /// Read a value from behind `source` without waiting for its lock
+    ///
+    /// A free lock is read with `read` and the reading remembered. A busy one
+    /// yields the last reading marked stale, or the default value if there
+    /// is none yet. A poisoned lock is read anyway; diagnostics are most
+    /// wanted when something went wrong.
+    pub(crate) fn sample<S>(&self, source: &Mutex<S>, read: impl FnOnce(&S) -> T) -> Sampled<T> {
+        let guard = match source.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        };
+        
+        let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        
+        match guard {
+            Some(guard) => {
+                let value = read(&guard);
+                let sampled_at = Utc::now();
+                *last = Some((value.clone(), sampled_at));
+                Sampled { value, sampled_at: Some(sampled_at), stale: false }
+            },
+            None => match last.as_ref() {
+                Some((value, sampled_at)) => Sampled { value: value.clone(), sampled_at: Some(*sampled_at), stale: true },
+                None => Sampled { value: T::default(), sampled_at: None, stale: true },
+            },
+        }
+    }
+}
+
+/// Transfer waiting in the pending queue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedTransfer {
+    /// Queue reference
+    pub reference: u64,
+    /// What the transfer is for, e.g. `payout`
+    pub kind: String,
+    /// Deposit the transfer pays out, if any
+    pub deposit_id: Option<u64>,
+    /// Token type
+    pub token_type: TokenType,
+    /// Amount
+    pub amount: u64,
+    /// Processing priority
+    pub priority: TransferPriority,
+    /// Failed processing attempts so far
+    pub attempts: u32,
+    /// Seconds the transfer had been queued when sampled
+    pub age_secs: u64,
+}
+
+/// Liveness of a background worker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerLiveness {
+    /// Worker name, e.g. `mempool_monitor`
+    pub worker: String,
+    /// When the worker last looped, `None` if it has not yet
+    pub last_loop_at: Option<DateTime<Utc>>,
+    /// Seconds since the last loop at collection time
+    pub secs_since_last_loop: Option<i64>,
+}
+
+impl WorkerLiveness {
+    /// Get the liveness of a worker from its heartbeat as of `now`
+    pub fn of(worker: &str, heartbeat: &Heartbeat, now: DateTime<Utc>) -> Self {
+        let last_loop_at = heartbeat.last_beat();
+        Self {
+            worker: worker.to_string(),
+            last_loop_at,
+            secs_since_last_loop: last_loop_at.map(|last| (now - last).num_seconds()),
+        }
+    }
+}
+
+/// How hard the node RPC rate limiter is pushing back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimiterSaturation {
+    /// Configured calls per minute
+    pub limit_per_minute: u32,
+    /// Calls made through the limiter
+    pub calls: u64,
+    /// Calls that had to wait for their turn
+    pub throttled_calls: u64,
+    /// Callers waiting for their turn right now
+    pub waiting_callers: u64,
+    /// Share of calls that were throttled, from 0 to 1
+    pub throttled_ratio: f64,
+}
+
+/// Broadcast transaction the node has not confirmed yet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnconfirmedBroadcast {
+    /// Transaction ID
+    pub txid: String,
+    /// Seconds since the broadcast when sampled
+    pub age_secs: u64,
+}
+
+/// Snapshot of the transfer machinery for operators
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeDiagnostics {
+    /// When the diagnostics were collected
+    pub collected_at: DateTime<Utc>,
+    /// Transfers in the pending queue, in queue order
+    pub pending_transfers: Sampled<Vec<QueuedTransfer>>,
+    /// Most transfers the pending queue holds
+    pub queue_capacity: usize,
+    /// References of the payouts waiting for a batch, `None` without batching
+    pub batched_payouts: Option<Sampled<Vec<u64>>>,
+    /// Liveness of each background worker
+    pub workers: Vec<WorkerLiveness>,
+    /// Node RPC rate limiter
+    pub rate_limiter: RateLimiterSaturation,
+    /// Broadcast transaction unconfirmed the longest, if any
+    pub oldest_unconfirmed_broadcast: Sampled<Option<UnconfirmedBroadcast>>,
+    /// State of the circuit breaker of each optional backend
+    pub feature_gates: Vec<FeatureGateStatus>,
+    /// Level each log target is logged at, empty if the levels are not adjustable
+    #[serde(default)]
+    pub log_levels: Vec<TargetLevel>,
+}
+
+impl RuntimeDiagnostics {
+    /// Whether any part of the snapshot is an earlier reading
+    pub fn is_stale(&self) -> bool {
+        self.pending_transfers.stale
+            || self.batched_payouts.as_ref().is_some_and(|payouts| payouts.stale)
+            || self.oldest_unconfirmed_broadcast.stale
+    }
+    
+    /// Get the liveness of a worker by name
+    pub fn worker(&self, worker: &str) -> Option<&WorkerLiveness> {
+        self.workers.iter().find(|liveness| liveness.worker == worker)
+    }
+}
+
+/// Source of runtime diagnostics, shared with the HTTP API
+pub trait DiagnosticsSource: Send + Sync + std::fmt::Debug {
+    /// Collect the diagnostics without waiting on busy locks
+    fn diagnostics(&self) -> RuntimeDiagnostics;
+}
@@ -0,0 +1,220 @@
+//! Per-address history of deposits and withdrawals
+//!
+//! `LedgerIndex` turns the events of the event log into `LedgerEntry` rows
+//! per depositor address, keeping a running locked balance per token. An
+//! event can produce several rows: a deposit credits the gross amount and
+//! debits its fee, an emergency withdrawal debits the payout and the fee, and
+//! an assignment debits the previous depositor and credits the new one. The
+//! rows of one address are kept in log order, so a page never changes once
+//! later events arrive.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::events::Event;
+use crate::models::TokenType;
+
+/// What a ledger entry records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerKind {
+    /// Gross amount of a funded deposit
+    Deposit,
+    /// Deposit fee taken from the gross amount
+    DepositFee,
+    /// Deposit recorded before its funds arrived, not yet locked
+    PendingDeposit,
+    /// Pending deposit cancelled because it was never funded
+    Expiry,
+    /// Pending deposit voided by the owner before it was funded
+    Void,
+    /// Deposit carried over from an older deployment; withdrawn ones move nothing
+    Import,
+    /// Deposit paid out after its lock
+    Withdrawal,
+    /// Deposit paid out early, net of the fee
+    EmergencyWithdrawal,
+    /// Fee of an early payout
+    EmergencyFee,
+    /// Deposit paid out to the depositor's beneficiary
+    InheritanceClaim,
+    /// Deposit assigned to the address
+    TransferIn,
+    /// Deposit assigned away from the address
+    TransferOut,
+}
+
+impl LedgerKind {
+    /// Stable name of the kind, as serialized
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedgerKind::Deposit => "deposit",
+            LedgerKind::DepositFee => "deposit_fee",
+            LedgerKind::PendingDeposit => "pending_deposit",
+            LedgerKind::Expiry => "expiry",
+            LedgerKind::Void => "void",
+            LedgerKind::Import => "import",
+            LedgerKind::Withdrawal => "withdrawal",
+            LedgerKind::EmergencyWithdrawal => "emergency_withdrawal",
+            LedgerKind::EmergencyFee => "emergency_fee",
+            LedgerKind::InheritanceClaim => "inheritance_claim",
+            LedgerKind::TransferIn => "transfer_in",
+            LedgerKind::TransferOut => "transfer_out",
+        }
+    }
+    
+    /// Whether the entry is a fee the address paid
+    pub fn is_fee(&self) -> bool {
+        matches!(self, LedgerKind::DepositFee | LedgerKind::EmergencyFee)
+    }
+}
+
+/// Row of an address's ledger
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Index of the event in the log
+    pub seq: u64,
+    /// When the event happened
+    pub timestamp: DateTime<Utc>,
+    /// What the entry records
+    pub kind: LedgerKind,
+    /// Deposit the entry concerns
+    pub deposit_id: u64,
+    /// Token type
+    pub token: TokenType,
+    /// Change of the address's locked balance of the token
+    pub amount_delta: i128,
+    /// Locked balance of the token after the entry
+    pub balance_after: u64,
+}
+
+/// Ledger of one address
+#[derive(Debug, Default)]
+struct AddressLedger {
+    /// Entries in log order
+    entries: Vec<LedgerEntry>,
+    /// Running locked balance per token
+    balances: HashMap<TokenType, u64>,
+}
+
+impl AddressLedger {
+    /// Append an entry, moving the running balance of its token
+    fn push(&mut self, seq: u64, timestamp: DateTime<Utc>, kind: LedgerKind, deposit_id: u64, token: &TokenType, amount_delta: i128) {
+        let balance = self.balances.entry(token.clone()).or_insert(0);
+        *balance = (*balance as i128 + amount_delta).clamp(0, u64::MAX as i128) as u64;
+        
+        self.entries.push(LedgerEntry {
+            seq,
+            timestamp,
+            kind,
+            deposit_id,
+            token: token.clone(),
+            amount_delta,
+            balance_after: *balance,
+        });
+    }
+}
+
+/// Ledgers of every address seen in the event log, updated one event at a time
+#[derive(Debug, Default)]
+pub(crate) struct LedgerIndex {
+    /// Ledger per address
+    ledgers: HashMap<String, AddressLedger>,
+    /// Token and locked amount of each funded deposit, for assignments
+    deposits: HashMap<u64, (TokenType, u64)>,
+}
+
+impl LedgerIndex {
+    /// Add the entries of the event at log position `seq`
+    pub(crate) fn record(&mut self, seq: u64, event: &Event) {
+        let timestamp = event.timestamp();
+        
+        match event {
+            Event::Deposited { deposit_id, depositor_address, token_type, deposit_amount, fee_amount, .. } => {
+                let ledger = self.ledgers.entry(depositor_address.clone()).or_default();
+                ledger.push(seq, timestamp, LedgerKind::Deposit, *deposit_id, token_type, *deposit_amount as i128);
+                if *fee_amount > 0 {
+                    ledger.push(seq, timestamp, LedgerKind::DepositFee, *deposit_id, token_type, -(*fee_amount as i128));
+                }
+                
+                self.deposits.insert(*deposit_id, (token_type.clone(), deposit_amount.saturating_sub(*fee_amount)));
+            },
+            Event::DepositPending { deposit_id, depositor_address, token_type, .. } => {
+                self.ledgers.entry(depositor_address.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::PendingDeposit, *deposit_id, token_type, 0);
+            },
+            Event::DepositExpired { deposit_id, depositor_address, token_type, .. } => {
+                self.ledgers.entry(depositor_address.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::Expiry, *deposit_id, token_type, 0);
+            },
+            Event::DepositVoided { deposit_id, depositor_address, token_type, .. } => {
+                self.ledgers.entry(depositor_address.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::Void, *deposit_id, token_type, 0);
+            },
+            Event::DepositImported { deposit_id, depositor_address, token_type, amount, withdrawn, .. } => {
+                let amount_delta = if *withdrawn { 0 } else { *amount as i128 };
+                self.ledgers.entry(depositor_address.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::Import, *deposit_id, token_type, amount_delta);
+                
+                if !*withdrawn {
+                    self.deposits.insert(*deposit_id, (token_type.clone(), *amount));
+                }
+            },
+            Event::Withdrawn { deposit_id, depositor_address, token_type, withdrawn_amount, .. } => {
+                self.ledgers.entry(depositor_address.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::Withdrawal, *deposit_id, token_type, -(*withdrawn_amount as i128));
+                self.deposits.remove(deposit_id);
+            },
+            Event::EmergencyWithdrawn { deposit_id, depositor_address, token_type, withdrawn_amount, fee_amount, .. } => {
+                let ledger = self.ledgers.entry(depositor_address.clone()).or_default();
+                ledger.push(seq, timestamp, LedgerKind::EmergencyWithdrawal, *deposit_id, token_type, -(*withdrawn_amount as i128));
+                if *fee_amount > 0 {
+                    ledger.push(seq, timestamp, LedgerKind::EmergencyFee, *deposit_id, token_type, -(*fee_amount as i128));
+                }
+                self.deposits.remove(deposit_id);
+            },
+            Event::InheritanceClaimed { deposit_id, depositor_address, token_type, claimed_amount, .. } => {
+                self.ledgers.entry(depositor_address.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::InheritanceClaim, *deposit_id, token_type, -(*claimed_amount as i128));
+                self.deposits.remove(deposit_id);
+            },
+            Event::DepositOwnershipTransferred { deposit_id, previous_owner, new_owner, .. } => {
+                // Deposits made before the log started are unknown
+                let (token_type, amount) = match self.deposits.get(deposit_id) {
+                    Some(deposit) => deposit.clone(),
+                    None => return,
+                };
+                
+                self.ledgers.entry(previous_owner.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::TransferOut, *deposit_id, &token_type, -(amount as i128));
+                self.ledgers.entry(new_owner.clone()).or_default()
+                    .push(seq, timestamp, LedgerKind::TransferIn, *deposit_id, &token_type, amount as i128);
+            },
+            _ => {},
+        }
+    }
+    
+    /// Entries of an address from `from` (inclusive) to `to` (exclusive), skipping `offset` and returning at most `limit`
+    pub(crate) fn entries(
+        &self,
+        address: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<LedgerEntry> {
+        let ledger = match self.ledgers.get(address) {
+            Some(ledger) => ledger,
+            None => return Vec::new(),
+        };
+        
+        ledger.entries.iter()
+            .filter(|entry| from.is_none_or(|from| entry.timestamp >= from))
+            .filter(|entry| to.is_none_or(|to| entry.timestamp < to))
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
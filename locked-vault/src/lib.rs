@@ -14,78 +14,210 @@
 //! - Bitcoin testnet support with real RPC integration
 //! - Rune token support
 //! - Ordinals support
-//! - Lightning Network support
-//! - Multi-signature wallet support
+//! - Lightning Network support, with route hints for private channels
+//! - Multi-signature wallet support, including 2-of-2 MuSig2 taproot escrows with a depositor recovery leaf
+//! - Script escrow deposits with exportable, checksummed miniscript descriptors
+//! - Watch-only descriptor export of every vault receiving address
 //! - Time-locked deposits
-//! - Emergency withdrawals with fee
-//! - Batch transaction processing
+//! - Sequential or derived, predictable deposit IDs
+//! - Pending deposits that expire if their funding never confirms or is never paid
+//! - Owner voiding of mistaken pending deposits, restorable from late funding
+//! - Emergency withdrawals with fee, cooldown and per-user limits
+//! - Circuit breaker pausing the contract on withdrawal spikes or a reconciliation deficit
+//! - Insurance pool funded by a share of emergency fees
+//! - Referral rewards credited from the fees of referred deposits
+//! - Deprecation of supported tokens whose backend stops supporting them
+//! - Pausing a single token type, by the owner, the circuit breaker or an open feature gate
+//! - Outbox committing each transfer with its state change, so a crash neither loses nor repeats it
+//! - Fee withdrawals restricted to a whitelist whose additions wait out a delay
+//! - Queryable history of fee accruals and sweeps per token
+//! - Authorization log of withdrawals with the channel and request they came from
+//! - Owner nonces that reject replayed owner instructions
+//! - Signed, replay-protected import of deposits from an older deployment
+//! - Batch transaction processing with a bounded queue that pushes back when full
 //! - UTXO management
 //! - Mempool monitoring
-//! - Dynamic fee estimation
+//! - Confirmation requirements per token type and amount tier
+//! - Dynamic fee estimation from one shared fee oracle, with fallbacks, background refresh and snapshot IDs on quotes
+//! - Named confirmation targets with configurable blocks, reported on every fee quote
+//! - Configurable handling of dust change and a minimum for Bitcoin withdrawals
 //! - Signature verification
+//! - Signed deposit receipts
+//! - Versioned canonical encoding of deposits and contract state for hashing
 //! - Rate limiting for API calls
 //! - Secure address validation
+//! - Redacted credentials from inline, file or environment sources, optionally in an encrypted config file
+//! - Backend health checking
+//! - Circuit breakers that fail Lightning, Ordinals and rune operations fast while their backend is failing
+//! - Runtime diagnostics of the transfer queue, workers, rate limiter and unconfirmed broadcasts
+//! - Log levels per target adjustable while the daemon runs
+//! - Signed webhook notifications for contract events
+//! - Tamper-evident, hash-chained event log with signed checkpoints
+//! - Deposit and withdrawal events anchored to the chain tip for external verification
+//! - Time-ordered deposit and withdrawal ledger per address with running balances
+//! - Monthly depositor statements as JSON or markdown, from the CLI or the HTTP API
+//! - Historical TVL and average remaining lock series, downsampled into bounded tiers
+//! - Weekly hot-wallet liquidity forecasts with modeled emergency withdrawals and shortfall flags
+//! - Contract state persistence as a JSON or compact binary snapshot, or in an embedded key-value store (`kv-store` feature)
+//! - Optional encryption at rest of persisted state with XChaCha20-Poly1305, keyed by a key file or an Argon2id passphrase
+//! - Cold storage sweeps of the hot wallet that keep a float for withdrawals due soon
+//! - Change of withdrawals tagged as operational funds, which sweeps spend instead of deposited funds
+//! - Reconciliation of contract state against on-chain balances, split into locked, operational and unattributed holdings
+//! - Batched, resumable audit of the UTXOs recorded for deposits
+//! - Progress reporting and cancellation of audits, exports and state conversions, also as background jobs
+//! - CSV export of deposits
+//! - Watch-only replicas that verify a vault's state against the chain but refuse to move funds
+//! - Locale-independent, fixed-format amounts, timestamps and tables for CLI output
+//! - HTTP API with JSON-RPC 2.0 access to contract operations and scoped, hashed API keys (`server` feature)
+//! - Several isolated, separately persisted tenant vaults in one process
+//! - Time-accelerated soak runs of scripted scenarios
+//! - Startup self-test of the configuration (`doctor`)
+//! - Builder-style client facade that checks operations before making them
+//! - Startup recovery of withdrawals interrupted while their payout was in flight
 //! 
 //! # Usage
 //! 
-//! ```no_run
-//! use time_locked_deposit::bitcoin::testnet::BitcoinTestnetConfig;
-//! use time_locked_deposit::bitcoin::transfer::BitcoinTestnetTransfer;
-//! use time_locked_deposit::contract::contract_core::TimeLockedDeposit;
-//! use time_locked_deposit::models::TokenType;
+//! `prelude` re-exports the supported public surface; import from it rather
+//! than from the modules behind it, which may move between releases.
+//! `VaultClient` is the easiest way in: it wraps a contract and checks each
+//! operation before making it. The example runs against the in-memory
+//! `SimulatedTransfer`; a real deployment passes a `BitcoinTestnetTransfer`.
 //! 
-//! // Create Bitcoin testnet configuration
-//! let config = BitcoinTestnetConfig::new(
-//!     "http://localhost:18332".to_string(),
-//!     "testuser".to_string(),
-//!     "testpassword".to_string(),
-//!     "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-//! );
+//! ```
+//! use std::collections::HashMap;
+//! use std::sync::Arc;
+//! use time_locked_deposit::prelude::*;
+//! use time_locked_deposit::soak::SimulatedTransfer;
 //! 
-//! // Create Bitcoin testnet transfer implementation
-//! let transfer = BitcoinTestnetTransfer::new_with_clients(
-//!     config,
-//!     Some("http://localhost:9735".to_string()),
-//!     Some("http://localhost:3000".to_string()),
-//! ).unwrap();
+//! // Every address starts with 1,000,000 satoshis
+//! let transfer = SimulatedTransfer::new(1_000_000, HashMap::new());
 //! 
 //! // Create contract instance
-//! let mut contract = TimeLockedDeposit::new(
+//! let mut contract = TimeLockedDeposit::new_with_defaults(
 //!     "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
 //!     10, // 10% emergency withdrawal fee
 //!     transfer,
-//! ).unwrap();
+//! )?;
+//! let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+//! contract.set_clock(clock.clone());
+//! let mut client = VaultClient::new(contract);
 //! 
 //! // Deposit Bitcoin
-//! let result = contract.deposit(
-//!     "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
-//!     TokenType::Bitcoin,
-//!     100000, // 0.001 BTC in satoshis
-//!     30, // 30 days lock period
-//!     Some("txid:0".to_string()), // UTXO reference
-//! );
+//! let deposit = client.deposit()
+//!     .from("tb1qdepositor")
+//!     .token(TokenType::Bitcoin)
+//!     .amount_sats(100_000) // 0.001 BTC
+//!     .lock_days(30)
+//!     .utxo("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b:0")
+//!     .execute()?;
+//! 
+//! // Withdraw once the lock has expired
+//! clock.advance(chrono::Duration::days(30));
+//! let withdrawal = client.withdrawal(deposit.deposit_id)
+//!     .to("tb1qdepositor")
+//!     .max_fee_sats(500)
+//!     .execute()?;
+//! assert_eq!(withdrawal.amount, 100_000);
+//! # Ok::<(), ContractError>(())
 //! ```
 
 pub mod models;
+pub mod config;
+pub mod clock;
+pub mod metrics;
 pub mod errors;
 pub mod events;
 pub mod contract;
 pub mod bitcoin;
+pub mod webhook;
+pub mod event_log;
+pub mod ledger;
+pub mod canonical;
+pub mod introspection;
+pub mod tvl;
+pub mod soak;
+pub mod doctor;
+pub mod store;
+pub mod client;
+#[doc(hidden)]
+pub mod display;
+pub mod statements;
+pub mod tenancy;
+pub mod progress;
+pub mod logging;
+pub mod prelude;
+#[cfg(feature = "server")]
+pub mod server;
 
 // Re-export commonly used types
-pub use models::{TokenType, TokenTransfer, Deposit};
-pub use errors::ContractError;
+pub use models::{AmountConstraints, AmountContext, TokenType, TokenTransfer, Deposit, DepositStatus, FeeQuote, Inheritance, MaturityReminder, ScriptEscrow, TransferQueueStatus, UnspentOutput, WithdrawOptions};
+pub use errors::{ContractError, ErrorCode, MigrationError, StateEncryptionError, TransferStage, UtxoRefPart};
 pub use events::Event;
+pub use config::{Secret, SecretSource, VaultConfig};
+pub use clock::{Clock, MockClock, SystemClock};
+pub use metrics::MetricsRegistry;
 pub use contract::contract_core::TimeLockedDeposit;
-pub use bitcoin::testnet::BitcoinTestnetConfig;
-pub use bitcoin::transfer::BitcoinTestnetTransfer;
-pub use bitcoin::rpc::BitcoinRpcClient;
-pub use bitcoin::utxo::{Utxo, UtxoSet};
-pub use bitcoin::lightning::LightningClient;
-pub use bitcoin::ordinals::OrdinalsClient;
+pub use contract::audit::{ReconciliationConfig, ReconciliationReport};
+pub use contract::state::{ContractState, ContractStats};
+pub use contract::migration::{Migration, StateVersion};
+pub use contract::receipt::DepositReceipt;
+pub use contract::view::{DepositView, HeldInscription};
+pub use contract::summary::{DepositCursor, DepositSummary, Page, SortKey};
+pub use contract::schedule::{ScheduleBucket, ScheduleGranularity};
+pub use contract::forecast::{ForecastBucket, ForecastSource, LiquidityForecast, LiquidityForecaster, ProjectedShortfall};
+pub use contract::quote::EmergencyQuote;
+pub use contract::simulation::{DepositSimulation, WithdrawalSimulation};
+pub use contract::unattributed::UnattributedFunds;
+pub use contract::void::VoidedDeposit;
+pub use contract::reminder::MaturityNotice;
+pub use contract::insurance::CompensationTarget;
+pub use contract::treasury::{SweepProposal, TreasuryPolicy};
+pub use contract::import::{ImportedDeposit, ImportManifest};
+pub use contract::circuit_breaker::{CircuitBreakerConfig, PauseReason};
+pub use contract::deposit_id::IdScheme;
+pub use contract::fee_destination::PendingFeeDestination;
+pub use contract::fee_ledger::{FeeAccrual, FeeAccrualKind, FeeMonthSummary, FeeSweep};
+pub use contract::recovery::WithdrawalCorrection;
+pub use contract::outbox::{OutboxEntry, OutboxOperation, TransferDirection, TransferRequest};
+pub use contract::token_support::DeprecationReason;
+pub use contract::token_pause::TokenPausePolicy;
+pub use contract::authorization::{AuthorizationQuery, AuthorizationRecord, AuthorizedOperation, OperationChannel, OperationContext};
+pub use bitcoin::testnet::{BitcoinTestnetConfig, FeeTargets, PayoutBatchConfig};
+pub use bitcoin::transfer::{BatchResult, BitcoinTestnetTransfer, ProcessingProgress, TransferIntrospector};
+pub use bitcoin::payout::{PayoutBatch, PayoutBatcher, PayoutEntry};
+pub use bitcoin::rpc::{BitcoinRpcClient, DuplicateOutputs, SignedTx};
+pub use bitcoin::template::{DataOutputs, DestinationPolicy};
+pub use bitcoin::network::{AddressNetwork, NetworkGuard};
+pub use bitcoin::utxo::{CoinControl, DustChange, DustPolicy, OperationalUtxo, ScriptType, SelectionStrategy, SweepInputs, Utxo, UtxoRef, UtxoSet};
+pub use bitcoin::lightning::{LightningClient, PaymentParams};
+pub use bitcoin::bolt11::{Bolt11Invoice, RouteHint, RouteHintHop};
+pub use bitcoin::ordinals::{Inscription, OrdinalsApiFlavor, OrdinalsClient, SatInfo, SatRarity};
 pub use bitcoin::mempool::MempoolMonitor;
-pub use bitcoin::multisig::MultisigClient;
-pub use bitcoin::signature::SignatureVerifier;
+pub use bitcoin::anchor::{verify_anchor, AnchorVerification, ChainAnchor};
+pub use bitcoin::incoming::{IncomingFunds, IncomingFundsWatcher};
+pub use bitcoin::confirmation::{ConfirmationPolicy, ConfirmationTier, TokenConfirmationRule};
+pub use bitcoin::multisig::{MultisigClient, MusigSpend, MusigSpendStage, WalletKind};
+pub use bitcoin::descriptor::{DescriptorImport, MultisigPolicy, MusigEscrowPolicy, TimeLockPolicy};
+pub use bitcoin::signature::{KeySigner, MusigAggNonce, MusigKeyAgg, MusigPublicNonce, MusigSecretNonce, SignatureVerifier, Signer};
+pub use bitcoin::health::{HealthChecker, HealthStatus};
+pub use bitcoin::feature_gate::{FeatureGate, FeatureGateConfig, FeatureGateStatus, FeatureGates, GateState};
+pub use bitcoin::fee_oracle::{ConfirmationTarget, FeeEstimate, FeeOracle, FeeOracleConfig, FeeRate, FeeRateOrigin, FeeSnapshot, FeeSource};
+pub use webhook::{WebhookConfig, WebhookSink};
+pub use event_log::{verify_event_log, EventLog, EventLogConfig, LogVerification, StoredEvent};
+pub use introspection::{
+    DiagnosticsSource, Heartbeat, QueuedTransfer, RateLimiterSaturation, RuntimeDiagnostics, Sampled, UnconfirmedBroadcast, WorkerLiveness,
+};
+pub use ledger::{LedgerEntry, LedgerKind};
+pub use statements::{build_statement, Statement, StatementGenerator, StatementPeriod, StatementSource, TokenStatement, UpcomingUnlock};
+pub use tenancy::{validate_vault_name, TenantMetricsSink, VaultManager, VaultNamespace, VaultSettings};
+pub use tvl::{MetricsRecorder, MetricsRecorderConfig, SeriesTier, TvlSample, TvlSeries};
+pub use soak::{Scenario, SoakReport, SoakRunner};
+pub use client::{DepositOutcome, VaultClient, WithdrawalOutcome};
+pub use doctor::{run_self_test, CheckResult, CheckStatus, SelfTestReport};
+pub use progress::{JobRegistry, JobState, JobStatus, Progress, ProgressSnapshot};
+pub use store::{Argon2Params, EncryptedStateStore, JsonSnapshotStore, SnapshotCodec, StateKey, StateStore, TransferStateStore};
+#[cfg(feature = "kv-store")]
+pub use store::KvStateStore;
 
 // Include the tests module
 #[cfg(test)]
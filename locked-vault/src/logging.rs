@@ -0,0 +1,196 @@
+//! Log verbosity adjustable while the daemon runs
+//!
+//! Chasing a production incident should not need a restart with another
+//! `RUST_LOG`, which loses what the process holds in memory. Every log line
+//! of the crate names one of a few targets, such as `rpc` or `contract`, and
+//! a `GatedLogger` in front of the formatting logger passes a record only if
+//! its level is enabled for its target. `LogLevels` holds those levels as
+//! atomics, so `PUT /log-level` and `vault log-level` change them live.
+//!
+//! Records of other targets, such as those of dependencies, are gated by the
+//! default level, which is fixed at startup.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::{Serialize, Deserialize};
+
+/// Target of the contract, its state and its event sinks
+pub const CONTRACT: &str = "contract";
+
+/// Target of node RPC calls and fee estimation
+pub const RPC: &str = "rpc";
+
+/// Target of the Lightning client
+pub const LIGHTNING: &str = "lightning";
+
+/// Target of the Ordinals client
+pub const ORDINALS: &str = "ordinals";
+
+/// Target of the mempool monitor and the chain watchers
+pub const MEMPOOL: &str = "mempool";
+
+/// Target of the transfer queue, payouts and backend health
+pub const TRANSFER: &str = "transfer";
+
+/// Target of the daemon loop, the HTTP API and the CLI
+pub const DAEMON: &str = "daemon";
+
+/// Every target whose level can be adjusted
+pub const TARGETS: [&str; 7] = [CONTRACT, RPC, LIGHTNING, ORDINALS, MEMPOOL, TRANSFER, DAEMON];
+
+/// Level a target is logged at, as reported by diagnostics
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetLevel {
+    /// Log target
+    pub target: String,
+    /// Most verbose level logged, e.g. `debug`, or `off`
+    pub level: String,
+}
+
+/// Levels of each log target, adjustable at runtime
+#[derive(Debug)]
+pub struct LogLevels {
+    /// Level of records of other targets
+    default: LevelFilter,
+    /// Level of each target, in the order of `TARGETS`
+    levels: [AtomicUsize; TARGETS.len()],
+}
+
+impl LogLevels {
+    /// Log every target at `default`
+    pub fn new(default: LevelFilter) -> Self {
+        Self {
+            default,
+            levels: std::array::from_fn(|_| AtomicUsize::new(default as usize)),
+        }
+    }
+    
+    /// Parse levels in the form `RUST_LOG` takes, e.g. `info,rpc=debug`
+    ///
+    /// A bare level sets the default; `target=level` sets a target. Directives
+    /// for other targets, such as module paths, are ignored.
+    pub fn from_spec(spec: &str) -> Result<Self, String> {
+        let mut default = LevelFilter::Info;
+        let mut directives = Vec::new();
+        
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => directives.push((target, parse_level(level)?)),
+                None => default = parse_level(directive)?,
+            }
+        }
+        
+        let levels = Self::new(default);
+        for (target, level) in directives {
+            if let Some(index) = target_index(target) {
+                levels.levels[index].store(level as usize, Ordering::Relaxed);
+            }
+        }
+        
+        Ok(levels)
+    }
+    
+    /// Get the level a target is logged at
+    pub fn level(&self, target: &str) -> LevelFilter {
+        match target_index(target) {
+            Some(index) => level_filter(self.levels[index].load(Ordering::Relaxed)),
+            None => self.default,
+        }
+    }
+    
+    /// Change the level a target is logged at, failing for an unknown target
+    pub fn set_level(&self, target: &str, level: LevelFilter) -> Result<(), String> {
+        let index = target_index(target)
+            .ok_or_else(|| format!("Unknown log target: {} (expected one of {})", target, TARGETS.join(", ")))?;
+        self.levels[index].store(level as usize, Ordering::Relaxed);
+        
+        Ok(())
+    }
+    
+    /// Get the level of every target, in the order of `TARGETS`
+    pub fn levels(&self) -> Vec<TargetLevel> {
+        TARGETS.iter()
+            .map(|target| TargetLevel {
+                target: target.to_string(),
+                level: self.level(target).as_str().to_ascii_lowercase(),
+            })
+            .collect()
+    }
+    
+    /// Check whether a record is logged at the current levels
+    pub fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+}
+
+impl fmt::Display for LogLevels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let levels: Vec<String> = self.levels().into_iter()
+            .map(|level| format!("{}={}", level.target, level.level))
+            .collect();
+        write!(f, "{}", levels.join(","))
+    }
+}
+
+/// Logger passing the records enabled by `LogLevels` on to another logger
+pub struct GatedLogger {
+    /// Logger formatting and writing the records
+    inner: Box<dyn Log>,
+    /// Levels records are gated by
+    levels: Arc<LogLevels>,
+}
+
+impl GatedLogger {
+    /// Gate the records of `inner` by `levels`
+    pub fn new(inner: Box<dyn Log>, levels: Arc<LogLevels>) -> Self {
+        Self { inner, levels }
+    }
+}
+
+impl Log for GatedLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.levels.enabled(metadata) && self.inner.enabled(metadata)
+    }
+    
+    fn log(&self, record: &Record) {
+        if self.levels.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+    
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Install a gated logger as the process logger, returning its levels
+///
+/// `inner` must pass every level, since the gate decides which records
+/// are logged.
+pub fn init(inner: Box<dyn Log>, levels: LogLevels) -> Result<Arc<LogLevels>, String> {
+    let levels = Arc::new(levels);
+    log::set_boxed_logger(Box::new(GatedLogger::new(inner, levels.clone())))
+        .map_err(|e| format!("Failed to install logger: {}", e))?;
+    log::set_max_level(LevelFilter::Trace);
+    
+    Ok(levels)
+}
+
+/// Parse a level name such as `debug` or `off`
+pub fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level.trim())
+        .map_err(|_| format!("Invalid log level: {} (expected off, error, warn, info, debug or trace)", level))
+}
+
+/// Position of a target in `TARGETS`
+fn target_index(target: &str) -> Option<usize> {
+    TARGETS.iter().position(|known| *known == target)
+}
+
+/// Level filter stored as its discriminant
+fn level_filter(value: usize) -> LevelFilter {
+    LevelFilter::iter().nth(value).unwrap_or(LevelFilter::Trace)
+}
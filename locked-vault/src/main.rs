@@ -4,55 +4,208 @@
 #![allow(unused_mut)]
 
 mod models;
+mod config;
+mod clock;
+mod metrics;
 mod errors;
 mod events;
 mod contract;
 mod bitcoin;
+mod webhook;
+mod event_log;
+mod ledger;
+mod canonical;
+mod introspection;
+mod tvl;
+mod soak;
+mod doctor;
+mod store;
+mod client;
+mod display;
+mod statements;
+mod tenancy;
+mod progress;
+mod logging;
+#[cfg(feature = "server")]
+mod server;
 
 use std::env;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
 use log::{debug, info, warn, error};
-use env_logger::Env;
 
-use bitcoin::testnet::{BitcoinTestnetConfig, utils};
+use config::{Secret, SecretSource, VaultConfig};
+use bitcoin::testnet::{BitcoinTestnetConfig, PayoutBatchConfig, utils};
 use bitcoin::transfer::BitcoinTestnetTransfer;
 use bitcoin::rpc::BitcoinRpcClient;
 use bitcoin::mempool::MempoolMonitor;
+use bitcoin::reorg::ReorgNotice;
+use bitcoin::health::HealthChecker;
+use bitcoin::signature::{KeySigner, Signer};
+use bitcoin::descriptor::{self, TimeLockPolicy};
 use contract::contract_core::TimeLockedDeposit;
-use models::TokenType;
+use contract::audit::{ReconciliationConfig, ReconciliationReport};
+use contract::schedule::ScheduleGranularity;
+use contract::forecast::LiquidityForecaster;
+use contract::circuit_breaker::CircuitBreakerConfig;
+use contract::token_pause::TokenPausePolicy;
+use contract::deposit_id::IdScheme;
+use contract::authorization::AuthorizationQuery;
+use contract::utxo_audit::{UtxoAuditCursor, UtxoAuditor};
+use contract::summary::{summary_page, SortKey};
+use contract::query::DepositFilter;
+use contract::read_only::ReadOnlyTransfer;
+use models::{DepositStatus, TokenTransfer, TokenType};
+use progress::Progress;
+use logging::DAEMON;
+use webhook::{WebhookConfig, WebhookSink};
+use event_log::{EventLog, EventLogConfig};
+use statements::StatementPeriod;
+use introspection::{DiagnosticsSource, Heartbeat, DAEMON_WORKER};
+use store::{EncryptedStateStore, JsonSnapshotStore, SnapshotCodec, StateStore};
+use tvl::{MetricsRecorder, MetricsRecorderConfig, SeriesTier};
 
 fn main() -> Result<(), String> {
-    // Initialize logger
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    // Initialize logger, gated per target so levels can change while running
+    let log_levels = logging::LogLevels::from_spec(&env::var("RUST_LOG").unwrap_or_default())?;
+    let log_levels = logging::init(Box::new(env_logger::Builder::new().filter_level(log::LevelFilter::Trace).build()), log_levels)?;
     
-    info!("Time-Locked Deposit Contract - Bitcoin Testnet Deployment");
+    info!(target: DAEMON, "Time-Locked Deposit Contract - Bitcoin Testnet Deployment");
     
-    // Get configuration from environment variables
-    let rpc_url = env::var("BITCOIN_TESTNET_RPC_URL")
-        .unwrap_or_else(|_| "http://localhost:18332".to_string());
-        
-    let rpc_username = env::var("BITCOIN_TESTNET_RPC_USERNAME")
-        .unwrap_or_else(|_| "testuser".to_string());
-        
-    let rpc_password = env::var("BITCOIN_TESTNET_RPC_PASSWORD")
-        .unwrap_or_else(|_| "testpassword".to_string());
-        
-    let contract_wallet_address = env::var("BITCOIN_TESTNET_CONTRACT_WALLET")
-        .unwrap_or_else(|_| "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string());
-        
-    let owner_address = env::var("BITCOIN_TESTNET_OWNER_ADDRESS")
-        .unwrap_or_else(|_| "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string());
+    // Get the command to run (defaults to running the daemon)
+    let command = env::args().nth(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .unwrap_or_else(|| "run".to_string());
+    let force = env::args().any(|arg| arg == "--force");
+    
+    // Soak runs are self-contained and need no node
+    if command == "soak" {
+        return run_soak(env::args().skip(2));
+    }
+    
+    // Config file helpers need no node either
+    if command == "config" {
+        return run_config_command(env::args().skip(2));
+    }
+    
+    // Neither does checking the event log
+    if command == "audit-log" {
+        return run_audit_log_command(env::args().skip(2));
+    }
+    
+    // Nor converting state between storage backends
+    if command == "store" {
+        return run_store_command(env::args().skip(2));
+    }
+    
+    // Or re-encrypting it under a new key or another codec
+    if command == "state" {
+        return run_state_command(env::args().skip(2));
+    }
     
-    let lightning_node_url = env::var("LIGHTNING_NODE_URL").ok();
-    let ordinals_api_url = env::var("ORDINALS_API_URL").ok();
+    // Or checking an escrow descriptor against an address
+    if command == "descriptor" {
+        return run_descriptor_command(env::args().skip(2));
+    }
+    
+    // Or listing the deposits in a saved snapshot
+    if command == "list" {
+        return print_deposit_list(env::args().skip(2));
+    }
+    
+    // Or reading statistics out of a saved snapshot
+    if command == "stats" {
+        return print_stats(env::args().skip(2));
+    }
+    
+    // Or the authorization log it holds
+    if command == "authorizations" {
+        return print_authorizations(env::args().skip(2));
+    }
+    
+    // Or a depositor's monthly statement out of the event log
+    if command == "statement" {
+        return print_statement(env::args().skip(2));
+    }
+    
+    // Or asking the running daemon for its diagnostics
+    if command == "diag" {
+        return print_diagnostics(env::args().skip(2));
+    }
+    
+    // Or changing how verbosely it logs
+    if command == "log-level" {
+        return set_daemon_log_level(env::args().skip(2));
+    }
+    
+    // Or managing the HTTP API keys
+    #[cfg(feature = "server")]
+    if command == "apikey" {
+        return run_apikey_command(env::args().skip(2));
+    }
+    
+    // Get configuration from the config file or environment variables
+    let vault_config = load_vault_config()?;
+    
+    // Check the configuration end to end without moving funds
+    if command == "doctor" {
+        return print_self_test(&vault_config, env::args().skip(2));
+    }
+    
+    // Refuse to start on a broken configuration unless forced
+    if command == "run" {
+        let report = doctor::run_self_test(&vault_config);
+        if !report.passed() {
+            for line in report.to_string().lines() {
+                error!(target: DAEMON, "{}", line);
+            }
+            if !force {
+                return Err("Self-test failed; fix the configuration or pass --force to start anyway".to_string());
+            }
+            warn!(target: DAEMON, "Starting despite failed self-test (--force)");
+        }
+    }
+    
+    let owner_address = vault_config.owner_address.clone();
+    let lightning_node_url = vault_config.lightning_node_url.clone();
+    let ordinals_api_url = vault_config.ordinals_api_url.clone();
+    
+    let health_check_interval = env::var("HEALTH_CHECK_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(30);
+    
+    let backend_grace_period = env::var("BACKEND_GRACE_PERIOD_SECS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(120);
+    
+    let reconciliation_config = ReconciliationConfig {
+        tolerance: env::var("RECONCILIATION_TOLERANCE")
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(0),
+        auto_pause: env::var("RECONCILIATION_AUTO_PAUSE")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+    };
+    
+    // Refuse addresses of another network than the configured one
+    vault_config.validate_addresses()?;
     
     // Create Bitcoin testnet configuration
-    let config = BitcoinTestnetConfig::new(
-        rpc_url,
-        rpc_username,
-        rpc_password,
-        contract_wallet_address,
-    );
+    let mut config = vault_config.bitcoin_config();
+    
+    // Batch non-urgent payouts if a batching window is configured
+    if let Some(window) = env::var("PAYOUT_BATCH_WINDOW_SECS").ok().and_then(|value| value.parse::<u64>().ok()) {
+        config.payout_batch = Some(PayoutBatchConfig {
+            window: Duration::from_secs(window),
+            ..PayoutBatchConfig::default()
+        });
+    }
     
     // Validate configuration
     config.validate()?;
@@ -64,41 +217,1460 @@ fn main() -> Result<(), String> {
         ordinals_api_url,
     ).map_err(|e| format!("Failed to initialize transfer: {:?}", e))?;
     
+    // Create health checker for all configured backends
+    let health_checker = Arc::new(transfer.health_checker(Duration::from_secs(health_check_interval)));
+    
+    match command.as_str() {
+        "status" => return print_status(&health_checker, env::args().skip(2)),
+        "descriptors" => return print_watch_descriptors(&transfer, env::args().skip(2)),
+        "audit-utxos" => return run_utxo_audit(transfer, vault_config.snapshot_codec, env::args().skip(2)),
+        "details" => return print_deposit_details(transfer, vault_config.snapshot_codec, env::args().skip(2)),
+        "export" => return export_deposits(transfer, vault_config.snapshot_codec, env::args().skip(2)),
+        "run" | "audit" | "schedule" | "forecast" => {},
+        other => return Err(format!("Unknown command: {} (expected run, status, audit, audit-utxos, schedule, forecast, descriptors, list, details, export, stats, diag, log-level, apikey, doctor or soak)", other)),
+    }
+    
+    health_checker.start()
+        .map_err(|e| format!("Failed to start health checker: {:?}", e))?;
+    
+    // Keep fee estimates warm for payouts and withdrawal quotes
+    transfer.fee_oracle().start()
+        .map_err(|e| format!("Failed to start fee oracle: {:?}", e))?;
+    
+    // Diagnostics read the transfer machinery without going through the contract lock
+    let daemon_heartbeat = Heartbeat::new();
+    let introspector = Arc::new(
+        transfer.introspector()
+            .with_worker(DAEMON_WORKER, daemon_heartbeat.clone())
+            .with_log_levels(log_levels.clone())
+    );
+    
     // Create contract instance
-    let mut contract = TimeLockedDeposit::new(
+    let mut contract = TimeLockedDeposit::new_with_defaults(
         owner_address,
         10, // 10% emergency withdrawal fee
         transfer,
     ).map_err(|e| format!("Failed to initialize contract: {:?}", e))?;
     
+    contract.set_health_checker(health_checker.clone(), Duration::from_secs(backend_grace_period));
+    contract.apply_confirmation_policy(vault_config.confirmation_policy.clone())
+        .map_err(|e| format!("Failed to apply confirmation policy: {}", e))?;
+    
+    // Pause automatically on withdrawal spikes if a circuit breaker window is configured
+    if let Some(window) = env::var("CIRCUIT_BREAKER_WINDOW_SECS").ok().and_then(|value| value.parse::<u64>().ok()) {
+        let config = CircuitBreakerConfig {
+            enabled: true,
+            window: Duration::from_secs(window),
+            max_withdrawal_count: env::var("CIRCUIT_BREAKER_MAX_WITHDRAWALS").ok().and_then(|value| value.parse().ok()),
+            max_withdrawal_amount: env::var("CIRCUIT_BREAKER_MAX_BTC_AMOUNT").ok()
+                .and_then(|value| value.parse().ok())
+                .map(|amount| vec![(TokenType::Bitcoin, amount)])
+                .unwrap_or_default(),
+            pause_token_only: env::var("CIRCUIT_BREAKER_PAUSE_TOKEN_ONLY")
+                .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+        };
+        contract.set_circuit_breaker_config(vault_config.owner_address.clone(), config, None)
+            .map_err(|e| format!("Failed to configure circuit breaker: {}", e))?;
+    }
+    
+    // Pause single tokens behind open feature gates, and let their withdrawals through, if requested
+    let token_pause_policy = TokenPausePolicy {
+        withdrawals_exempt: env::var("TOKEN_PAUSE_EXEMPT_WITHDRAWALS")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+        pause_on_open_gate: env::var("TOKEN_PAUSE_ON_OPEN_GATE")
+            .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+            .unwrap_or(false),
+    };
+    if token_pause_policy != TokenPausePolicy::default() {
+        contract.set_token_pause_policy(vault_config.owner_address.clone(), token_pause_policy, None)
+            .map_err(|e| format!("Failed to set token pause policy: {}", e))?;
+    }
+    
+    // Derive deposit IDs instead of numbering them if requested
+    if env::var("DEPOSIT_ID_SCHEME").is_ok_and(|value| value.eq_ignore_ascii_case("derived")) {
+        contract.set_id_scheme(vault_config.owner_address.clone(), IdScheme::Derived, None)
+            .map_err(|e| format!("Failed to set deposit ID scheme: {}", e))?;
+    }
+    
+    if command == "audit" {
+        return print_audit(&mut contract, &reconciliation_config);
+    }
+    
+    if command == "schedule" {
+        return print_schedule(&contract, env::args().skip(2));
+    }
+    
+    if command == "forecast" {
+        return print_forecast(&contract, env::args().skip(2));
+    }
+    
+    // Register webhook notifications if configured
+    if let (Ok(webhook_url), Ok(webhook_secret)) = (env::var("WEBHOOK_URL"), env::var("WEBHOOK_SECRET")) {
+        let webhook = WebhookSink::new(WebhookConfig::new(webhook_url.clone(), webhook_secret))
+            .map_err(|e| format!("Failed to create webhook sink: {:?}", e))?;
+        contract.add_event_sink(Arc::new(webhook));
+        info!(target: DAEMON, "Webhook notifications enabled for {}", webhook_url);
+    }
+    
+    // Sign deposit receipts if a vault key is configured
+    let mut vault_signer: Option<Arc<dyn Signer>> = None;
+    if let Ok(receipt_key) = env::var("RECEIPT_SIGNING_KEY") {
+        let signer = Arc::new(KeySigner::from_hex(&receipt_key)
+            .map_err(|e| format!("Failed to load receipt signing key: {:?}", e))?);
+        info!(target: DAEMON, "Deposit receipts signed by {}", hex::encode(signer.public_key()));
+        contract.set_receipt_signer(signer.clone());
+        vault_signer = Some(signer);
+    }
+    
+    // Record every event in the hash-chained log, checkpointed by the vault key
+    let mut event_log = None;
+    if let Ok(event_log_path) = env::var("EVENT_LOG_PATH") {
+        let mut event_log_config = EventLogConfig::new(&event_log_path);
+        if let Some(interval) = env::var("EVENT_LOG_CHECKPOINT_INTERVAL").ok().and_then(|value| value.parse::<u64>().ok()) {
+            event_log_config.checkpoint_interval = interval;
+        }
+        
+        if vault_signer.is_none() {
+            warn!(target: DAEMON, "RECEIPT_SIGNING_KEY is not set; the event log gets no signed checkpoints");
+        }
+        
+        let log = Arc::new(EventLog::open(event_log_config, vault_signer.clone())?);
+        contract.add_event_sink(log.clone());
+        event_log = Some(log);
+        info!(target: DAEMON, "Event log enabled at {}", event_log_path);
+    }
+    
+    // Record the TVL series, at the tiers in TVL_SERIES_TIERS if set
+    let tvl_config = match env::var("TVL_SERIES_TIERS") {
+        Ok(tiers) => parse_series_tiers(&tiers)?,
+        Err(_) => MetricsRecorderConfig::default(),
+    };
+    let tvl_recorder = contract.enable_tvl_metrics(tvl_config)
+        .map_err(|e| format!("Failed to enable TVL metrics: {}", e))?;
+    
+    // Settle withdrawals a previous run left in flight before taking new ones
+    let recovered = contract.recover_in_flight()
+        .map_err(|e| format!("Failed to recover in-flight withdrawals: {}", e))?;
+    if !recovered.is_empty() {
+        info!(target: DAEMON, "Settled {} in-flight withdrawals", recovered.len());
+    }
+    
+    // Latest reconciliation report, shared with the HTTP API
+    let reconciliation_report: Arc<Mutex<Option<ReconciliationReport>>> = Arc::new(Mutex::new(None));
+    
+    // The contract is shared with the JSON-RPC interface from here on
+    let reorg_watcher = contract.token_transfer.reorg_watcher();
+    let contract = Arc::new(Mutex::new(contract));
+    
+    // Suspend deposits whose funding transaction is reorganized out, and reinstate them once it re-confirms
+    let target = contract.clone();
+    reorg_watcher.set_callback(Arc::new(move |notice: &ReorgNotice| {
+        let Ok(mut contract) = target.lock() else {
+            error!(target: DAEMON, "Contract lock poisoned, dropping reorg notice {:?}", notice);
+            return;
+        };
+        
+        if let Err(e) = contract.apply_reorg_notice(notice) {
+            warn!(target: DAEMON, "Failed to apply reorg notice {:?}: {}", notice, e);
+        }
+    })).map_err(|e| format!("Failed to set reorg callback: {:?}", e))?;
+    reorg_watcher.start()
+        .map_err(|e| format!("Failed to start reorg watcher: {:?}", e))?;
+    
+    // Start the HTTP API if a bind address is configured
+    #[cfg(feature = "server")]
+    if let Ok(bind_addr) = env::var("VAULT_HTTP_BIND") {
+        // Scoped keys from a key store take over from the single shared key;
+        // owner JSON-RPC methods stay disabled without either
+        let auth = match env::var("VAULT_API_KEYS_FILE") {
+            Ok(path) => server::ApiAuth::KeyStore(Arc::new(server::ApiKeyStore::open(path)?)),
+            Err(_) => server::ApiAuth::shared(env::var("VAULT_API_KEY")
+                .ok()
+                .map(|value| value.parse::<SecretSource>()?.resolve())
+                .transpose()
+                .map_err(|e| format!("VAULT_API_KEY: {}", e))?),
+        };
+        
+        let mut api_server = server::ApiServer::new()
+            .with_auth(auth.clone())
+            .with_health_checker(health_checker.clone())
+            .with_reconciliation_report(reconciliation_report.clone())
+            .with_tvl_recorder(tvl_recorder.clone())
+            .with_diagnostics(introspector.clone())
+            .with_log_levels(log_levels.clone())
+            .with_forecasts(Arc::new(LiquidityForecaster::new(contract.clone(), reconciliation_report.clone())))
+            .with_jobs(Arc::new(progress::JobRegistry::new()), Arc::new(UtxoAuditor::new(contract.clone())))
+            .with_jsonrpc(Arc::new(server::JsonRpcHandler::new(contract.clone(), None).with_auth(auth)));
+        
+        // Statements need the ledger of the event log
+        if let Some(event_log) = &event_log {
+            api_server = api_server.with_statements(Arc::new(statements::StatementGenerator::new(event_log.clone(), contract.clone())));
+        }
+        
+        api_server.spawn(bind_addr);
+    }
+    
     // Print contract information
-    info!("Contract initialized successfully!");
-    info!("Network type: {}", contract.get_network_type());
-    info!("Is testnet: {}", contract.is_testnet());
+    info!(target: DAEMON, "Contract initialized successfully!");
+    if let Ok(contract) = contract.lock() {
+        info!(target: DAEMON, "Network type: {}", contract.get_network_type());
+        info!(target: DAEMON, "Is testnet: {}", contract.is_testnet());
+    }
     
     // Example: Validate a testnet address
     let example_address = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
-    info!("Validating address {}: {}", example_address, utils::validate_testnet_address(example_address));
+    info!(target: DAEMON, "Validating address {}: {}", example_address, utils::validate_testnet_address(example_address));
     
     // Example: Validate a Rune token
     let rune_token = TokenType::Rune("RUNE_TEST_TOKEN_123".to_string());
     match rune_token.validate() {
-        Ok(_) => info!("Rune token validation successful"),
-        Err(e) => warn!("Rune token validation failed: {}", e),
+        Ok(_) => info!(target: DAEMON, "Rune token validation successful"),
+        Err(e) => warn!(target: DAEMON, "Rune token validation failed: {}", e),
     }
     
     // Example: Validate an Ordinal token
     let ordinal_token = TokenType::Ordinal("0".repeat(64));
     match ordinal_token.validate() {
-        Ok(_) => info!("Ordinal token validation successful"),
-        Err(e) => warn!("Ordinal token validation failed: {}", e),
+        Ok(_) => info!(target: DAEMON, "Ordinal token validation successful"),
+        Err(e) => warn!(target: DAEMON, "Ordinal token validation failed: {}", e),
     }
     
-    info!("Contract is ready for Bitcoin testnet operations");
+    info!(target: DAEMON, "Contract is ready for Bitcoin testnet operations");
     
     // Keep the application running
     loop {
         std::thread::sleep(Duration::from_secs(60));
-        info!("Contract is running...");
+        info!(target: DAEMON, "Contract is running...");
+        daemon_heartbeat.beat();
+        
+        let mut contract = contract.lock()
+            .map_err(|_| "Contract lock poisoned".to_string())?;
+        
+        // Work through the next batch of queued transfers, and the payout batch once its window has elapsed
+        match contract.token_transfer.process_pending_transactions() {
+            Ok(progress) if !progress.is_empty() => info!(
+                target: DAEMON,
+                "Sent {} transfers, {} failed, {} still queued",
+                progress.txids.len(), progress.failed.len(), progress.remaining
+            ),
+            Ok(_) => {},
+            Err(e) => warn!(target: DAEMON, "Failed to process pending transactions: {:?}", e),
+        }
+        
+        // Forget broadcasts that have confirmed, so diagnostics show the oldest one still waiting
+        if let Err(e) = contract.token_transfer.prune_confirmed_broadcasts() {
+            warn!(target: DAEMON, "Failed to check broadcast confirmations: {}", e);
+        }
+        
+        // Cancel pending deposits whose funding never arrived
+        let expired = contract.expire_stale_pending_deposits(chrono::Utc::now());
+        if !expired.is_empty() {
+            info!(target: DAEMON, "Expired {} unfunded pending deposits", expired.len());
+        }
+        
+        // Announce deposits entering their reminder window, once each
+        let notices = contract.collect_due_notifications(chrono::Utc::now());
+        if !notices.is_empty() {
+            contract.announce_maturity(&notices);
+            let deposit_ids: Vec<u64> = notices.iter().map(|notice| notice.deposit_id).collect();
+            info!(target: DAEMON, "Sent {} maturity reminders", contract.mark_notified(&deposit_ids));
+        }
+        
+        // Close tokens whose backend lost support to new deposits, and reopen recovered ones
+        let changes = contract.revalidate_supported_tokens();
+        if !changes.is_empty() {
+            info!(target: DAEMON, "Support changed for {} tokens", changes.len());
+        }
+        
+        // Pause the tokens whose backend's feature gate opened, if the policy asks for it
+        let paused = contract.pause_tokens_behind_open_gates();
+        if !paused.is_empty() {
+            info!(target: DAEMON, "Paused {} tokens behind open feature gates", paused.len());
+        }
+        
+        // Sweep the hot wallet excess to cold storage under the treasury policy
+        if let Err(e) = contract.run_scheduled_treasury_sweep() {
+            warn!(target: DAEMON, "Failed to sweep to cold storage: {}", e);
+        }
+        
+        // Refresh the reconciliation report
+        let report = contract.reconcile_and_enforce(&reconciliation_config);
+        if let Ok(mut latest) = reconciliation_report.lock() {
+            *latest = Some(report);
+        }
+    }
+}
+
+/// Print the descriptors watching the vault's receiving addresses as JSON
+/// 
+/// Usage: `descriptors [--state <snapshot.json>] [--timestamp <unix>]`
+/// 
+/// Escrow outputs are read from the deposits of the snapshot. The `import`
+/// entry can be passed as is to Bitcoin Core's `importdescriptors`, which
+/// rescans from `--timestamp`, by default from the genesis block.
+fn print_watch_descriptors(transfer: &BitcoinTestnetTransfer, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut timestamp = 0;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--state" => {
+                let path = value("--state")?;
+                let state = JsonSnapshotStore::new(&path).load_state()?
+                    .ok_or_else(|| format!("No snapshot at {}", path))?;
+                for escrow in state.deposits.iter().filter_map(|deposit| deposit.escrow.as_ref()) {
+                    transfer.watch_script_escrow(escrow);
+                }
+            },
+            "--timestamp" => timestamp = value("--timestamp")?.parse::<u64>().map_err(|_| "Invalid --timestamp".to_string())?,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
     }
+    
+    let json = serde_json::to_string_pretty(&serde_json::json!({
+        "descriptors": transfer.export_watch_descriptors(),
+        "import": transfer.export_import_request(timestamp),
+    })).map_err(|e| format!("Failed to serialize descriptors: {}", e))?;
+    println!("{}", json);
+    
+    Ok(())
+}
+
+/// Check the recorded UTXOs of the deposits in a snapshot against the node
+/// 
+/// Usage: `audit-utxos <snapshot.json> [--batch-size <n>] [--cursor-file <path>] [--suspend]`
+/// 
+/// Prints one JSON report per batch. With `--cursor-file` the position is
+/// saved after every batch, so an interrupted audit resumes where it stopped;
+/// the file is removed once the audit completes. `--suspend` suspends the
+/// deposits whose output is spent and saves the snapshot after each batch.
+fn run_utxo_audit(transfer: BitcoinTestnetTransfer, codec: SnapshotCodec, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut path = None;
+    let mut batch_size = 500;
+    let mut cursor_file = None;
+    let mut suspend = false;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--batch-size" => batch_size = value("--batch-size")?.parse::<usize>().map_err(|_| "Invalid --batch-size".to_string())?,
+            "--cursor-file" => cursor_file = Some(value("--cursor-file")?),
+            "--suspend" => suspend = true,
+            other if other.starts_with("--") => return Err(format!("Unknown argument: {}", other)),
+            other => path = Some(other.to_string()),
+        }
+    }
+    
+    let path = path.ok_or_else(|| "Usage: audit-utxos <snapshot.json> [--batch-size <n>] [--cursor-file <path>] [--suspend]".to_string())?;
+    let store = JsonSnapshotStore::new(&path).with_codec(codec);
+    let state = store.load_state()?
+        .ok_or_else(|| format!("No snapshot at {}", path))?;
+    let mut contract = TimeLockedDeposit::from_state(state, transfer)
+        .map_err(|e| format!("Failed to restore contract: {}", e))?;
+    
+    let mut cursor: Option<UtxoAuditCursor> = match &cursor_file {
+        Some(cursor_file) if std::path::Path::new(cursor_file).exists() => {
+            let contents = std::fs::read(cursor_file)
+                .map_err(|e| format!("Failed to read {}: {}", cursor_file, e))?;
+            let cursor = serde_json::from_slice(&contents)
+                .map_err(|e| format!("Invalid cursor file {}: {}", cursor_file, e))?;
+            info!(target: DAEMON, "Resuming UTXO audit after {:?}", cursor);
+            Some(cursor)
+        },
+        _ => None,
+    };
+    
+    let (mut spent, mut unknown) = (0, 0);
+    
+    loop {
+        let report = match with_progress(|progress| contract.verify_deposit_utxos_from(cursor, batch_size, Some(progress))) {
+            Ok(report) => report,
+            Err(e @ errors::ContractError::Cancelled { .. }) => {
+                return Err(format!("UTXO audit stopped: {}; run again with the same cursor file to resume", e));
+            },
+            Err(e) => return Err(format!("UTXO audit failed: {}", e)),
+        };
+        spent += report.spent().count();
+        unknown += report.unknown().count();
+        
+        let json = serde_json::to_string(&report)
+            .map_err(|e| format!("Failed to serialize UTXO audit report: {}", e))?;
+        println!("{}", json);
+        
+        if suspend {
+            let events = contract.suspend_spent_deposits(&report)
+                .map_err(|e| format!("Failed to suspend deposits: {}", e))?;
+            if !events.is_empty() {
+                store.save_state(&contract.export_state())?;
+                warn!(target: DAEMON, "Suspended {} deposit(s) with spent funding outputs", events.len());
+            }
+        }
+        
+        cursor = report.next_cursor;
+        
+        if let Some(cursor_file) = &cursor_file {
+            match &cursor {
+                Some(cursor) => {
+                    let contents = serde_json::to_vec(cursor)
+                        .map_err(|e| format!("Failed to serialize cursor: {}", e))?;
+                    store::write_atomically(std::path::Path::new(cursor_file), &contents)?;
+                },
+                None => {
+                    let _ = std::fs::remove_file(cursor_file);
+                },
+            }
+        }
+        
+        if cursor.is_none() {
+            break;
+        }
+    }
+    
+    info!(target: DAEMON, "UTXO audit complete: {} spent, {} unknown", spent, unknown);
+    
+    if spent > 0 {
+        Err(format!("{} deposit(s) have spent funding outputs", spent))
+    } else {
+        Ok(())
+    }
+}
+
+/// Read `--plain` as the only argument of a table command
+fn table_style(args: impl Iterator<Item = String>) -> Result<display::TableStyle, String> {
+    let mut plain = false;
+    
+    for arg in args {
+        match arg.as_str() {
+            "--plain" => plain = true,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    Ok(display::TableStyle::from_plain_flag(plain))
+}
+
+/// Print the deposits of a saved snapshot as a table
+/// 
+/// Usage: `list (<snapshot.json> | --vault <name>) [--address <address>] [--status <status>] [--sort <key>] [--offset <n>] [--limit <n>] [--plain]`
+/// 
+/// Deposits are listed by ID, optionally only those owned by `--address` or
+/// in `--status`, e.g. `active`. `--plain` separates columns with tabs.
+/// `--vault` reads the snapshot of a hosted vault under `VAULT_ROOT`.
+/// With `--sort` (`unlock_time`, `amount` or `created_at`), `--offset` or
+/// `--limit`, one page of deposit summaries is listed instead, by unlock
+/// time unless sorted otherwise, 50 rows unless limited otherwise.
+fn print_deposit_list(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut path = None;
+    let mut address = None;
+    let mut status = None;
+    let mut sort = None;
+    let mut offset = None;
+    let mut limit = None;
+    let mut plain = false;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--address" => address = Some(value("--address")?),
+            "--status" => status = Some(value("--status")?),
+            "--sort" => sort = Some(value("--sort")?.parse::<SortKey>()?),
+            "--offset" => offset = Some(value("--offset")?.parse::<usize>().map_err(|_| "Invalid --offset".to_string())?),
+            "--limit" => limit = Some(value("--limit")?.parse::<usize>().map_err(|_| "Invalid --limit".to_string())?),
+            "--vault" => path = Some(vault_snapshot_path(&value("--vault")?)?),
+            "--plain" => plain = true,
+            other if other.starts_with("--") => return Err(format!("Unknown argument: {}", other)),
+            other => path = Some(other.to_string()),
+        }
+    }
+    
+    let path = path.ok_or_else(|| "Usage: list (<snapshot.json> | --vault <name>) [--address <address>] [--status <status>] [--sort <key>] [--offset <n>] [--limit <n>] [--plain]".to_string())?;
+    let state = JsonSnapshotStore::new(&path).load_state()?
+        .ok_or_else(|| format!("No snapshot at {}", path))?;
+    
+    let mut deposits: Vec<&models::Deposit> = state.deposits.iter()
+        .filter(|deposit| address.as_ref().is_none_or(|address| &deposit.depositor_address == address))
+        .filter(|deposit| status.as_ref().is_none_or(|status| display::format_status(&deposit.status) == status))
+        .collect();
+    let style = display::TableStyle::from_plain_flag(plain);
+    
+    if sort.is_some() || offset.is_some() || limit.is_some() {
+        let offset = offset.unwrap_or(0);
+        let page = summary_page(deposits, sort.unwrap_or_default(), offset, limit.unwrap_or(50));
+        print!("{}", display::deposit_summary_table(&page.items).render(style));
+        if !plain {
+            println!("{} of {} deposits from offset {}", page.items.len(), page.total, offset);
+        }
+        
+        return Ok(());
+    }
+    
+    deposits.sort_by_key(|deposit| deposit.deposit_id);
+    
+    print!("{}", display::deposit_table(deposits).render(style));
+    
+    Ok(())
+}
+
+/// Print the full views of deposits in a saved snapshot as JSON
+/// 
+/// Usage: `details (<snapshot.json> | --vault <name>) <id>...`
+/// 
+/// Views are printed in the order asked, one per line; unknown IDs are left out.
+fn print_deposit_details(transfer: BitcoinTestnetTransfer, codec: SnapshotCodec, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: details (<snapshot.json> | --vault <name>) <id>...".to_string();
+    let path = snapshot_path_arg(&mut args).ok_or_else(usage)??;
+    let ids = args
+        .map(|id| id.parse::<u64>().map_err(|_| format!("Invalid deposit ID: {}", id)))
+        .collect::<Result<Vec<u64>, String>>()?;
+    if ids.is_empty() {
+        return Err(usage());
+    }
+    
+    let state = JsonSnapshotStore::new(&path).with_codec(codec).load_state()?
+        .ok_or_else(|| format!("No snapshot at {}", path))?;
+    let contract = TimeLockedDeposit::from_state_read_only(state, ReadOnlyTransfer::new(transfer))
+        .map_err(|e| format!("Failed to restore contract: {}", e))?;
+    
+    for view in contract.get_deposit_details(&ids) {
+        let json = serde_json::to_string(&view)
+            .map_err(|e| format!("Failed to serialize deposit: {}", e))?;
+        println!("{}", json);
+    }
+    
+    Ok(())
+}
+
+/// Export the deposits in a saved snapshot as CSV
+/// 
+/// Usage: `export (<snapshot.json> | --vault <name>) --output <path> [--address <address>] [--status <status>]`
+/// 
+/// The file is written only once every row is exported; Ctrl-C stops the
+/// export and leaves no partial file behind.
+fn export_deposits(transfer: BitcoinTestnetTransfer, codec: SnapshotCodec, mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: export (<snapshot.json> | --vault <name>) --output <path> [--address <address>] [--status <status>]".to_string();
+    let path = snapshot_path_arg(&mut args).ok_or_else(usage)??;
+    let mut output = None;
+    let mut filter = DepositFilter::default();
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--output" => output = Some(value("--output")?),
+            "--address" => filter = filter.with_address(value("--address")?),
+            "--status" => filter = filter.with_status(parse_deposit_status(&value("--status")?)?),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    let output = output.ok_or_else(usage)?;
+    
+    let state = JsonSnapshotStore::new(&path).with_codec(codec).load_state()?
+        .ok_or_else(|| format!("No snapshot at {}", path))?;
+    let contract = TimeLockedDeposit::from_state_read_only(state, ReadOnlyTransfer::new(transfer))
+        .map_err(|e| format!("Failed to restore contract: {}", e))?;
+    
+    let mut csv = Vec::new();
+    let rows = with_progress(|progress| contract.export_deposits_csv(filter, &mut csv, Some(progress)))
+        .map_err(|e| format!("Export stopped, nothing written: {}", e))?;
+    store::write_atomically(std::path::Path::new(&output), &csv)?;
+    
+    println!("Exported {} deposits to {}", rows, output);
+    
+    Ok(())
+}
+
+/// Deposit status by its stable name, e.g. `active`
+fn parse_deposit_status(value: &str) -> Result<DepositStatus, String> {
+    [
+        DepositStatus::Active,
+        DepositStatus::Withdrawn,
+        DepositStatus::EmergencyWithdrawn,
+        DepositStatus::Suspended,
+        DepositStatus::Pending,
+        DepositStatus::Expired,
+        DepositStatus::WithdrawalPending,
+        DepositStatus::Voided,
+    ]
+        .into_iter()
+        .find(|status| display::format_status(status) == value)
+        .ok_or_else(|| format!("Unknown deposit status: {}", value))
+}
+
+/// Set by Ctrl-C while a cancellable operation runs
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// SIGINT handler flagging the running operation for cancellation
+#[cfg(unix)]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Run a cancellable operation, with a progress bar on a terminal and Ctrl-C cancelling it
+/// 
+/// The bar is redrawn on stderr a few times a second. Ctrl-C is caught
+/// only while the operation runs; the operation then stops at its next
+/// check with `Cancelled`.
+fn with_progress<R>(operation: impl FnOnce(&Progress) -> R) -> R {
+    let progress = Arc::new(Progress::new());
+    let done = Arc::new(AtomicBool::new(false));
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    
+    #[cfg(unix)]
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    let previous = unsafe { libc::signal(libc::SIGINT, on_interrupt as extern "C" fn(libc::c_int) as *const () as libc::sighandler_t) };
+    
+    let renderer = {
+        let (progress, done) = (progress.clone(), done.clone());
+        let draw = std::io::stderr().is_terminal();
+        thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                if INTERRUPTED.load(Ordering::SeqCst) && !progress.is_cancelled() {
+                    progress.cancel();
+                }
+                if draw {
+                    eprint!("\r{}\x1b[K", progress.snapshot());
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+            if draw {
+                eprint!("\r\x1b[K");
+            }
+        })
+    };
+    
+    let result = operation(&progress);
+    done.store(true, Ordering::SeqCst);
+    let _ = renderer.join();
+    
+    #[cfg(unix)]
+    // SAFETY: restores the disposition `signal` returned above
+    unsafe { libc::signal(libc::SIGINT, previous) };
+    
+    result
+}
+
+/// Namespace of a vault hosted under `VAULT_ROOT`, by default `vaults`
+fn vault_namespace(name: &str) -> Result<tenancy::VaultNamespace, String> {
+    let root = env::var("VAULT_ROOT").unwrap_or_else(|_| tenancy::DEFAULT_VAULT_ROOT.to_string());
+    tenancy::VaultNamespace::new(root, name)
+}
+
+/// Snapshot path of a vault hosted under `VAULT_ROOT`
+fn vault_snapshot_path(name: &str) -> Result<String, String> {
+    Ok(vault_namespace(name)?.state_path().display().to_string())
+}
+
+/// Snapshot path given as the first argument, or as `--vault <name>`
+fn snapshot_path_arg(args: &mut impl Iterator<Item = String>) -> Option<Result<String, String>> {
+    match args.next()?.as_str() {
+        "--vault" => Some(args.next()
+            .ok_or_else(|| "Missing value for --vault".to_string())
+            .and_then(|name| vault_snapshot_path(&name))),
+        path => Some(Ok(path.to_string())),
+    }
+}
+
+/// Probe every backend once and print the health report
+/// 
+/// Usage: `status [--plain]`
+fn print_status(health_checker: &HealthChecker, args: impl Iterator<Item = String>) -> Result<(), String> {
+    let style = table_style(args)?;
+    
+    health_checker.check_now()
+        .map_err(|e| format!("Failed to check backend health: {:?}", e))?;
+    
+    print!("{}", display::health_table(&health_checker.health_report()).render(style));
+    
+    if health_checker.is_healthy() {
+        Ok(())
+    } else {
+        Err("One or more backends are unhealthy".to_string())
+    }
+}
+
+/// Run the self-test and print its report
+/// 
+/// Usage: `doctor [--json]`
+fn print_self_test(vault_config: &VaultConfig, args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut json = false;
+    
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let report = doctor::run_self_test(vault_config);
+    
+    if json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| format!("Failed to serialize self-test report: {}", e))?;
+        println!("{}", json);
+    } else {
+        println!("{}", report);
+    }
+    
+    if report.passed() {
+        Ok(())
+    } else {
+        Err("Self-test failed".to_string())
+    }
+}
+
+/// Reconcile the contract against on-chain balances and print the report as JSON
+fn print_audit<T: models::TokenTransfer>(
+    contract: &mut TimeLockedDeposit<T>,
+    config: &ReconciliationConfig,
+) -> Result<(), String> {
+    let report = contract.reconcile_and_enforce(config);
+    
+    let json = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Failed to serialize reconciliation report: {}", e))?;
+    println!("{}", json);
+    
+    if report.is_consistent() {
+        Ok(())
+    } else {
+        Err("Contract state does not match on-chain balances".to_string())
+    }
+}
+
+/// Load the configuration from the file in `VAULT_CONFIG`, or from environment variables without one
+/// 
+/// An encrypted config file is decrypted with `VAULT_CONFIG_PASSPHRASE`,
+/// which accepts the same `file:` and `env:` sources as credentials.
+fn load_vault_config() -> Result<VaultConfig, String> {
+    match env::var("VAULT_CONFIG") {
+        Ok(path) => {
+            let passphrase = config_passphrase()?;
+            let vault_config = VaultConfig::load(std::path::Path::new(&path), passphrase.as_ref())?;
+            info!(target: DAEMON, "Configuration loaded from {}", path);
+            Ok(vault_config)
+        },
+        Err(_) => VaultConfig::from_env(),
+    }
+}
+
+/// Resolve the config file passphrase from `VAULT_CONFIG_PASSPHRASE`, if set
+fn config_passphrase() -> Result<Option<Secret<String>>, String> {
+    env::var("VAULT_CONFIG_PASSPHRASE")
+        .ok()
+        .map(|value| value.parse::<SecretSource>()?.resolve())
+        .transpose()
+        .map_err(|e| format!("VAULT_CONFIG_PASSPHRASE: {}", e))
+}
+
+/// Encrypt or decrypt a config file with the passphrase in `VAULT_CONFIG_PASSPHRASE`
+/// 
+/// Usage: `config encrypt|decrypt <input> <output>`
+#[cfg(feature = "encrypted-config")]
+fn run_config_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: config encrypt|decrypt <input> <output>".to_string();
+    let action = args.next().ok_or_else(usage)?;
+    let input = args.next().ok_or_else(usage)?;
+    let output = args.next().ok_or_else(usage)?;
+    
+    let passphrase = config_passphrase()?
+        .ok_or_else(|| "Set VAULT_CONFIG_PASSPHRASE to the passphrase or its source".to_string())?;
+    let contents = Secret::new(std::fs::read(&input)
+        .map_err(|e| format!("Failed to read {}: {}", input, e))?);
+    
+    let (result, description) = match action.as_str() {
+        "encrypt" => {
+            // Refuse to encrypt something that is not a valid config
+            let json = std::str::from_utf8(contents.expose_secret())
+                .map_err(|_| format!("{} is not UTF-8", input))?;
+            VaultConfig::from_json(json)?;
+            
+            let encrypted = config::encrypted::encrypt(contents.expose_secret(), &passphrase)?;
+            (Secret::new(encrypted.into_bytes()), "encrypted")
+        },
+        "decrypt" => (config::encrypted::decrypt(contents.expose_secret(), &passphrase)?, "decrypted"),
+        _ => return Err(usage()),
+    };
+    
+    write_private_file(&output, result.expose_secret())?;
+    info!(target: DAEMON, "Wrote {} config to {}", description, output);
+    
+    Ok(())
+}
+
+/// Config file helpers are unavailable without the `encrypted-config` feature
+#[cfg(not(feature = "encrypted-config"))]
+fn run_config_command(_args: impl Iterator<Item = String>) -> Result<(), String> {
+    Err("config encrypt/decrypt requires the encrypted-config feature".to_string())
+}
+
+/// Write a file readable only by its owner where the platform supports it
+#[cfg(feature = "encrypted-config")]
+fn write_private_file(path: &str, contents: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    
+    options.open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+/// Check the hash chain and checkpoints of an event log and print the result as JSON
+/// 
+/// Usage: `audit-log verify <path> [--public-key <hex>]`
+fn run_audit_log_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: audit-log verify <path> [--public-key <hex>]".to_string();
+    if args.next().as_deref() != Some("verify") {
+        return Err(usage());
+    }
+    let path = args.next().ok_or_else(usage)?;
+    
+    let mut public_key = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--public-key" => {
+                let key = args.next()
+                    .ok_or_else(|| "Missing value for --public-key".to_string())?;
+                public_key = Some(hex::decode(&key)
+                    .map_err(|e| format!("Invalid public key hex: {}", e))?);
+            },
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let verification = event_log::verify_event_log(std::path::Path::new(&path), public_key.as_deref())?;
+    
+    let json = serde_json::to_string_pretty(&verification)
+        .map_err(|e| format!("Failed to serialize event log verification: {}", e))?;
+    println!("{}", json);
+    
+    match verification.first_broken_index {
+        None => Ok(()),
+        Some(index) => Err(format!("Event log {} is broken at entry {}", path, index)),
+    }
+}
+
+/// Check that a script escrow descriptor derives the address a deposit was paid to
+/// 
+/// Usage: `descriptor verify <descriptor> <address> [--network <network>]`
+fn run_descriptor_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: descriptor verify <descriptor> <address> [--network <network>]".to_string();
+    if args.next().as_deref() != Some("verify") {
+        return Err(usage());
+    }
+    let descriptor = args.next().ok_or_else(usage)?;
+    let address = args.next().ok_or_else(usage)?;
+    
+    let mut network = "testnet".to_string();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--network" => {
+                network = args.next()
+                    .ok_or_else(|| "Missing value for --network".to_string())?;
+            },
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let policy = TimeLockPolicy::parse(&descriptor).map_err(|e| e.to_string())?;
+    let derived = policy.address(descriptor::parse_network(&network).map_err(|e| e.to_string())?).to_string();
+    let matches = derived.eq_ignore_ascii_case(&address);
+    
+    let json = serde_json::to_string_pretty(&serde_json::json!({
+        "descriptor": policy.descriptor(),
+        "lock_height": policy.lock_height,
+        "address": derived,
+        "matches": matches,
+    })).map_err(|e| format!("Failed to serialize descriptor check: {}", e))?;
+    println!("{}", json);
+    
+    if matches {
+        Ok(())
+    } else {
+        Err(format!("Descriptor derives {}, not {}", derived, address))
+    }
+}
+
+/// Convert a contract snapshot between a JSON file and the key-value store
+/// 
+/// Usage: `store to-kv <snapshot.json> <store>` or `store to-json <store> <snapshot.json>`
+#[cfg(feature = "kv-store")]
+fn run_store_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: store to-kv <snapshot.json> <store> | store to-json <store> <snapshot.json>".to_string();
+    let action = args.next().ok_or_else(usage)?;
+    let input = args.next().ok_or_else(usage)?;
+    let output = args.next().ok_or_else(usage)?;
+    
+    let deposits = match action.as_str() {
+        "to-kv" => store::migrate_json_to_kv(std::path::Path::new(&input), std::path::Path::new(&output))?,
+        "to-json" => store::migrate_kv_to_json(std::path::Path::new(&input), std::path::Path::new(&output))?,
+        _ => return Err(usage()),
+    };
+    
+    println!("Converted {} deposits from {} to {}", deposits, input, output);
+    
+    Ok(())
+}
+
+/// Store conversion is unavailable without the `kv-store` feature
+#[cfg(not(feature = "kv-store"))]
+fn run_store_command(_args: impl Iterator<Item = String>) -> Result<(), String> {
+    Err("store to-kv/to-json requires the kv-store feature".to_string())
+}
+
+/// Re-encrypt an encrypted state directory under a new key, or convert a snapshot file between codecs
+///
+/// Usage: `state rotate-key <dir>` or `state convert <snapshot> --to <json|binary> [--output <path>]`.
+/// The current key comes from the vault configuration, the new one from
+/// `VAULT_NEW_STATE_PASSPHRASE` or `VAULT_NEW_STATE_KEY_FILE`. A converted
+/// snapshot replaces the input unless `--output` is given.
+fn run_state_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: state rotate-key <dir> | state convert <snapshot> --to <json|binary> [--output <path>]".to_string();
+    match args.next().as_deref() {
+        Some("rotate-key") => {},
+        Some("convert") => return convert_snapshot(args),
+        _ => return Err(usage()),
+    }
+    let dir = args.next().ok_or_else(usage)?;
+    
+    let old = load_vault_config()?.state_key
+        .ok_or_else(|| "No state key configured; set VAULT_STATE_PASSPHRASE or VAULT_STATE_KEY_FILE".to_string())?;
+    let new = config::state_key_from_env("VAULT_NEW_STATE_PASSPHRASE", "VAULT_NEW_STATE_KEY_FILE")?
+        .ok_or_else(|| "Set VAULT_NEW_STATE_PASSPHRASE or VAULT_NEW_STATE_KEY_FILE to the new key".to_string())?;
+    
+    let mut store = EncryptedStateStore::open(&dir, old.clone()).map_err(|e| e.to_string())?;
+    with_progress(|progress| store.rotate_key(&old, new, Some(progress))).map_err(|e| e.to_string())?;
+    
+    Ok(())
+}
+
+/// Rewrite a snapshot file in another codec
+fn convert_snapshot(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: state convert <snapshot> --to <json|binary> [--output <path>]".to_string();
+    let mut input = None;
+    let mut codec = None;
+    let mut output = None;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--to" => codec = Some(value("--to")?.parse::<SnapshotCodec>()?),
+            "--output" => output = Some(value("--output")?),
+            other if other.starts_with("--") => return Err(format!("Unknown argument: {}", other)),
+            other => input = Some(other.to_string()),
+        }
+    }
+    
+    let input = input.ok_or_else(usage)?;
+    let codec = codec.ok_or_else(usage)?;
+    let output = output.unwrap_or_else(|| input.clone());
+    
+    // Nothing is written until both stages are done, so a cancel leaves the output as it was
+    let (raw, state, converted) = with_progress(|progress| {
+        progress.start_stage("decode", 1);
+        let raw = std::fs::read(&input)
+            .map_err(|e| format!("Failed to read snapshot {}: {}", input, e))?;
+        let state = store::decode_snapshot(&raw)?;
+        progress.advance(1);
+        progress.check_cancelled().map_err(|e| e.to_string())?;
+        
+        progress.start_stage("encode", 1);
+        let converted = store::encode_snapshot(&state, codec)?;
+        progress.advance(1);
+        progress.check_cancelled().map_err(|e| e.to_string())?;
+        
+        Ok::<_, String>((raw, state, converted))
+    })?;
+    store::write_atomically(std::path::Path::new(&output), &converted)?;
+    
+    println!(
+        "Converted {} deposits from {} ({} bytes) to {} ({} bytes)",
+        state.deposits.len(), SnapshotCodec::detect(&raw), raw.len(), codec, converted.len()
+    );
+    
+    Ok(())
+}
+
+/// Run soak scenarios and print their reports
+/// 
+/// Usage: `soak <scenario.json>...`
+fn run_soak(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let paths: Vec<String> = args.collect();
+    if paths.is_empty() {
+        return Err("Usage: soak <scenario.json>...".to_string());
+    }
+    
+    let mut failed = Vec::new();
+    
+    for path in paths {
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let report = soak::run_scenario(&json)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        
+        println!("{}", report);
+        
+        if !report.passed() {
+            failed.push(path);
+        }
+    }
+    
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Soak scenarios failed: {}", failed.join(", ")))
+    }
+}
+
+/// Parse series tiers given as comma-separated `resolution:retention` pairs in seconds
+fn parse_series_tiers(value: &str) -> Result<MetricsRecorderConfig, String> {
+    let tiers = value.split(',')
+        .map(|tier| {
+            let (resolution, retention) = tier.trim().split_once(':')
+                .ok_or_else(|| format!("Invalid series tier {} (expected resolution:retention)", tier))?;
+            Ok(SeriesTier {
+                resolution_secs: resolution.parse().map_err(|_| format!("Invalid resolution in series tier {}", tier))?,
+                retention_secs: retention.parse().map_err(|_| format!("Invalid retention in series tier {}", tier))?,
+            })
+        })
+        .collect::<Result<Vec<SeriesTier>, String>>()?;
+    
+    let config = MetricsRecorderConfig { tiers };
+    config.validate().map_err(|e| e.to_string())?;
+    
+    Ok(config)
+}
+
+/// Print statistics of a saved snapshot as JSON
+/// 
+/// Usage: `stats (<snapshot.json> | --vault <name>) [--series [--token <token>] [--from <unix>] [--to <unix>] [--resolution <secs>]]`
+/// 
+/// With `--series` the recorded TVL series of a token is printed, by default
+/// Bitcoin over the day before the last sample at the finest resolution.
+fn print_stats(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: stats (<snapshot.json> | --vault <name>) [--series [--token <token>] [--from <unix>] [--to <unix>] [--resolution <secs>]]".to_string();
+    let path = snapshot_path_arg(&mut args).ok_or_else(usage)??;
+    
+    let mut series = false;
+    let mut token = TokenType::Bitcoin;
+    let mut from = None;
+    let mut to = None;
+    let mut resolution = None;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--series" => series = true,
+            "--token" => token = value("--token")?.parse()?,
+            "--from" => from = Some(parse_unix_timestamp(&value("--from")?)?),
+            "--to" => to = Some(parse_unix_timestamp(&value("--to")?)?),
+            "--resolution" => resolution = Some(value("--resolution")?.parse::<u64>().map_err(|_| "Invalid --resolution".to_string())?),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let state = JsonSnapshotStore::new(&path).load_state()?
+        .ok_or_else(|| format!("No snapshot at {}", path))?;
+    
+    let json = if series {
+        let tvl_series = state.tvl_series
+            .ok_or_else(|| format!("Snapshot {} has no TVL series", path))?;
+        let recorder = MetricsRecorder::from_series(tvl_series).map_err(|e| e.to_string())?;
+        
+        // Default to the day up to the latest recorded sample
+        let latest = recorder.series().tiers.iter().flatten()
+            .filter(|sample| sample.token == token)
+            .map(|sample| sample.timestamp)
+            .max();
+        let to = to.or(latest.map(|latest| latest + chrono::Duration::seconds(1))).unwrap_or_else(chrono::Utc::now);
+        let from = from.unwrap_or(to - chrono::Duration::days(1));
+        let resolution = resolution
+            .or(recorder.config().tiers.iter().map(|tier| tier.resolution_secs).min())
+            .unwrap_or(60);
+        
+        let samples = recorder.get_tvl_series(&token, from, to, resolution).map_err(|e| e.to_string())?;
+        serde_json::to_string_pretty(&serde_json::json!({
+            "token": token,
+            "resolution_secs": resolution,
+            "samples": samples,
+        }))
+    } else {
+        let active = state.deposits.iter()
+            .filter(|deposit| deposit.status == models::DepositStatus::Active)
+            .count();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "version": state.version,
+            "paused": state.paused,
+            "deposit_count": state.deposits.len(),
+            "active_deposit_count": active,
+            "total_deposits": state.total_deposits,
+            "collected_fees": state.collected_fees,
+            "state_hash": hex::encode(state.canonical_hash()),
+        }))
+    }.map_err(|e| format!("Failed to serialize stats: {}", e))?;
+    println!("{}", json);
+    
+    Ok(())
+}
+
+/// Print the authorization records of an address or deposit in a saved snapshot as JSON
+/// 
+/// Usage: `authorizations (<snapshot.json> | --vault <name>) (--address <address> | --deposit <id>) [--from <unix>] [--to <unix>]`
+fn print_authorizations(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: authorizations (<snapshot.json> | --vault <name>) (--address <address> | --deposit <id>) [--from <unix>] [--to <unix>]".to_string();
+    let path = snapshot_path_arg(&mut args).ok_or_else(usage)??;
+    
+    let mut query = None;
+    let mut from = chrono::DateTime::<chrono::Utc>::MIN_UTC;
+    let mut to = chrono::DateTime::<chrono::Utc>::MAX_UTC;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--address" => query = Some(AuthorizationQuery::Address(value("--address")?)),
+            "--deposit" => query = Some(AuthorizationQuery::Deposit(value("--deposit")?.parse::<u64>().map_err(|_| "Invalid --deposit".to_string())?)),
+            "--from" => from = parse_unix_timestamp(&value("--from")?)?,
+            "--to" => to = parse_unix_timestamp(&value("--to")?)?,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    let query = query.ok_or_else(usage)?;
+    
+    let state = JsonSnapshotStore::new(&path).load_state()?
+        .ok_or_else(|| format!("No snapshot at {}", path))?;
+    
+    let records = state.authorization_log.query(&query, from, to);
+    let json = serde_json::to_string_pretty(&records)
+        .map_err(|e| format!("Failed to serialize authorization records: {}", e))?;
+    println!("{}", json);
+    
+    Ok(())
+}
+
+/// Print the monthly statement of a depositor address as markdown or JSON
+/// 
+/// Usage: `statement --address <address> --month <YYYY-MM> [--vault <name>] [--events <events.jsonl>] [--snapshot <snapshot.json>] [--json] [--out <path>]`
+/// 
+/// Balances and activity come from the event log at `--events`, by default
+/// `EVENT_LOG_PATH`. Upcoming unlocks are listed from `--snapshot` if given.
+/// `--vault` defaults both to the files of a hosted vault under `VAULT_ROOT`.
+/// The statement is written to `--out` instead of stdout if given.
+fn print_statement(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: statement --address <address> --month <YYYY-MM> [--vault <name>] [--events <events.jsonl>] [--snapshot <snapshot.json>] [--json] [--out <path>]".to_string();
+    let mut address = None;
+    let mut period = None;
+    let mut events = None;
+    let mut snapshot = None;
+    let mut vault = None;
+    let mut json = false;
+    let mut out = None;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--address" => address = Some(value("--address")?),
+            "--month" => period = Some(value("--month")?.parse::<StatementPeriod>()?),
+            "--events" => events = Some(value("--events")?),
+            "--snapshot" => snapshot = Some(value("--snapshot")?),
+            "--vault" => vault = Some(vault_namespace(&value("--vault")?)?),
+            "--json" => json = true,
+            "--out" => out = Some(value("--out")?),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    // Explicit files win over the vault's, which win over the environment
+    if let Some(vault) = &vault {
+        events = events.or_else(|| Some(vault.event_log_path().display().to_string()));
+        snapshot = snapshot.or_else(|| Some(vault.state_path().display().to_string()));
+    }
+    let events = events.or_else(|| env::var("EVENT_LOG_PATH").ok());
+    let (address, period, events) = match (address, period, events) {
+        (Some(address), Some(period), Some(events)) => (address, period, events),
+        _ => return Err(usage()),
+    };
+    
+    let ledger = event_log::read_address_ledger(std::path::Path::new(&events), &address)?;
+    let deposits = match &snapshot {
+        Some(path) => JsonSnapshotStore::new(path).load_state()?
+            .ok_or_else(|| format!("No snapshot at {}", path))?
+            .deposits,
+        None => Vec::new(),
+    };
+    
+    let statement = statements::build_statement(&address, period, &ledger, &deposits, chrono::Utc::now());
+    let rendered = if json {
+        serde_json::to_string_pretty(&statement)
+            .map_err(|e| format!("Failed to serialize statement: {}", e))?
+    } else {
+        statement.to_markdown()
+    };
+    
+    match out {
+        Some(path) => std::fs::write(&path, rendered)
+            .map_err(|e| format!("Failed to write statement to {}: {}", path, e))?,
+        None => println!("{}", rendered),
+    }
+    
+    Ok(())
+}
+
+/// Print the runtime diagnostics of the running daemon as JSON
+/// 
+/// Usage: `diag [--addr <host:port>]`
+/// 
+/// Asks the daemon's HTTP API at `--addr`, by default `VAULT_HTTP_BIND`,
+/// presenting the owner API key from `VAULT_API_KEY`.
+fn print_diagnostics(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut addr = env::var("VAULT_HTTP_BIND").ok();
+    
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => addr = Some(args.next().ok_or_else(|| "Missing value for --addr".to_string())?),
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let body = request_daemon(addr, "GET", "/diagnostics", None)?;
+    let diagnostics: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| format!("Failed to parse diagnostics: {}", e))?;
+    let json = serde_json::to_string_pretty(&diagnostics)
+        .map_err(|e| format!("Failed to serialize diagnostics: {}", e))?;
+    println!("{}", json);
+    
+    Ok(())
+}
+
+/// Change the level a log target of the running daemon is logged at
+/// 
+/// Usage: `log-level <target> <level> [--addr <host:port>]`
+/// 
+/// Targets are `contract`, `rpc`, `lightning`, `ordinals`, `mempool`,
+/// `transfer` and `daemon`; levels `off` to `trace`. Prints the levels of
+/// every target afterwards.
+fn set_daemon_log_level(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let usage = || "Usage: log-level <target> <level> [--addr <host:port>]".to_string();
+    let mut addr = env::var("VAULT_HTTP_BIND").ok();
+    let mut positional = Vec::new();
+    
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--addr" => addr = Some(args.next().ok_or_else(|| "Missing value for --addr".to_string())?),
+            _ => positional.push(arg),
+        }
+    }
+    let (target, level) = match positional.as_slice() {
+        [target, level] => (target, level),
+        _ => return Err(usage()),
+    };
+    
+    // Catch a typo before asking the daemon
+    logging::parse_level(level)?;
+    
+    let request = serde_json::json!({ "target": target, "level": level }).to_string();
+    let body = request_daemon(addr, "PUT", "/log-level", Some(&request))?;
+    let response: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    for level in response["levels"].as_array().into_iter().flatten() {
+        println!("{:<10} {}", level["target"].as_str().unwrap_or_default(), level["level"].as_str().unwrap_or_default());
+    }
+    
+    Ok(())
+}
+
+/// Send a request to the running daemon's HTTP API, returning the body of a 200 answer
+/// 
+/// Connects to `addr`, by default `VAULT_HTTP_BIND`, presenting the owner
+/// API key from `VAULT_API_KEY`.
+fn request_daemon(addr: Option<String>, method: &str, path: &str, body: Option<&str>) -> Result<String, String> {
+    use std::io::{Read, Write};
+    
+    let addr = addr.ok_or_else(|| "No daemon address; pass --addr or set VAULT_HTTP_BIND".to_string())?;
+    let api_key = env::var("VAULT_API_KEY")
+        .map_err(|_| "VAULT_API_KEY is not set".to_string())?
+        .parse::<SecretSource>()
+        .and_then(|source| source.resolve())
+        .map_err(|e| format!("VAULT_API_KEY: {}", e))?;
+    let body = body.unwrap_or_default();
+    
+    let mut stream = std::net::TcpStream::connect(&addr)
+        .map_err(|e| format!("Failed to connect to {}: {}", addr, e))?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(|e| format!("Failed to set read timeout: {}", e))?;
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nX-Api-Key: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        method,
+        path,
+        addr,
+        api_key.expose_secret(),
+        body.len(),
+        body,
+    ).map_err(|e| format!("Failed to send request: {}", e))?;
+    
+    let mut response = String::new();
+    stream.read_to_string(&mut response)
+        .map_err(|e| format!("Failed to read response: {}", e))?;
+    
+    let (head, body) = response.split_once("\r\n\r\n")
+        .ok_or_else(|| "Malformed response from the daemon".to_string())?;
+    let status = head.split_whitespace().nth(1).unwrap_or_default();
+    if status != "200" {
+        return Err(format!("Daemon answered {}: {}", status, body));
+    }
+    
+    Ok(body.to_string())
+}
+
+/// Manage the scoped API keys of the HTTP API
+/// 
+/// Usage:
+/// - `apikey create --caps <read,deposit,withdraw,admin> [--file <path>]`
+/// - `apikey list [--file <path>] [--plain]`
+/// - `apikey revoke <key id> [--file <path>]`
+/// 
+/// The key store is `--file`, by default `VAULT_API_KEYS_FILE`. A created key
+/// is printed once and only its salted hash is stored. Revocations are picked
+/// up by a running daemon without a restart.
+#[cfg(feature = "server")]
+fn run_apikey_command(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let subcommand = args.next()
+        .ok_or_else(|| "Missing apikey subcommand (expected create, list or revoke)".to_string())?;
+    
+    let mut path = env::var("VAULT_API_KEYS_FILE").ok();
+    let mut capabilities = None;
+    let mut key_id = None;
+    let mut plain = false;
+    
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => path = Some(args.next().ok_or_else(|| "Missing value for --file".to_string())?),
+            "--plain" => plain = true,
+            "--caps" => capabilities = Some(server::auth::parse_capabilities(
+                &args.next().ok_or_else(|| "Missing value for --caps".to_string())?,
+            )?),
+            other if other.starts_with("--") => return Err(format!("Unknown argument: {}", other)),
+            other => key_id = Some(other.to_string()),
+        }
+    }
+    
+    let path = path.ok_or_else(|| "No key store; pass --file or set VAULT_API_KEYS_FILE".to_string())?;
+    let store = server::ApiKeyStore::open(&path)?;
+    
+    match subcommand.as_str() {
+        "create" => {
+            let capabilities = capabilities.ok_or_else(|| "Missing --caps".to_string())?;
+            let (record, key) = store.create(capabilities)?;
+            let capabilities: Vec<&str> = record.capabilities.iter().map(|capability| capability.as_str()).collect();
+            println!("Key ID:       {}", record.id);
+            println!("Capabilities: {}", capabilities.join(","));
+            println!("API key:      {}", key.expose_secret());
+            println!("Store the key now; it cannot be shown again.");
+        },
+        "list" => {
+            let mut table = display::Table::new(&["ID", "CAPABILITIES", "CREATED", "REVOKED"]);
+            for record in store.list()? {
+                let capabilities: Vec<&str> = record.capabilities.iter().map(|capability| capability.as_str()).collect();
+                table.push_row(vec![
+                    record.id.clone(),
+                    capabilities.join(","),
+                    display::format_timestamp(record.created_at),
+                    display::format_optional_timestamp(record.revoked_at),
+                ]);
+            }
+            print!("{}", table.render(display::TableStyle::from_plain_flag(plain)));
+        },
+        "revoke" => {
+            let key_id = key_id.ok_or_else(|| "Missing key ID to revoke".to_string())?;
+            if !store.revoke(&key_id)? {
+                return Err(format!("No active key with ID {}", key_id));
+            }
+            println!("Revoked key {}", key_id);
+        },
+        other => return Err(format!("Unknown apikey subcommand: {} (expected create, list or revoke)", other)),
+    }
+    
+    Ok(())
+}
+
+/// Print the hot wallet's liquidity forecast against its current balances as JSON
+/// 
+/// Usage: `forecast [--weeks <n>] [--emergency-rate-bps <bps>]`
+/// 
+/// Forecasts 12 weeks without emergency withdrawals by default. Exits with
+/// an error after printing if a shortfall is projected.
+fn print_forecast<T: models::TokenTransfer>(
+    contract: &TimeLockedDeposit<T>,
+    mut args: impl Iterator<Item = String>,
+) -> Result<(), String> {
+    let mut weeks = 12;
+    let mut emergency_rate_bps = 0;
+    
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "--weeks" => weeks = value("--weeks")?.parse::<u32>().map_err(|_| "Invalid --weeks".to_string())?,
+            "--emergency-rate-bps" => emergency_rate_bps = value("--emergency-rate-bps")?.parse::<u32>().map_err(|_| "Invalid --emergency-rate-bps".to_string())?,
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let report = contract.reconcile(0);
+    let forecast = contract.liquidity_forecast_report(weeks, emergency_rate_bps, &report)
+        .map_err(|e| e.to_string())?;
+    
+    let json = serde_json::to_string_pretty(&forecast)
+        .map_err(|e| format!("Failed to serialize liquidity forecast: {}", e))?;
+    println!("{}", json);
+    
+    match forecast.shortfalls.first() {
+        Some(shortfall) => Err(format!("Projected {:?} shortfall of {} from the week of {}", shortfall.token, shortfall.shortfall, shortfall.week_start.date_naive())),
+        None => Ok(()),
+    }
+}
+
+/// Parse a Unix timestamp in seconds
+fn parse_unix_timestamp(value: &str) -> Result<chrono::DateTime<chrono::Utc>, String> {
+    value.parse::<i64>().ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .ok_or_else(|| format!("Invalid timestamp: {}", value))
+}
+
+/// Print the unlock schedule of an address as JSON
+/// 
+/// Usage: `schedule --address <address> [--granularity monthly|weekly|daily]`
+fn print_schedule<T: models::TokenTransfer>(
+    contract: &TimeLockedDeposit<T>,
+    mut args: impl Iterator<Item = String>,
+) -> Result<(), String> {
+    let mut address = None;
+    let mut granularity = ScheduleGranularity::Monthly;
+    
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--address" => address = args.next(),
+            "--granularity" => {
+                granularity = args.next()
+                    .ok_or_else(|| "Missing value for --granularity".to_string())?
+                    .parse()?;
+            },
+            other => return Err(format!("Unknown argument: {}", other)),
+        }
+    }
+    
+    let address = address.ok_or_else(|| "Missing --address".to_string())?;
+    let schedule = contract.get_unlock_schedule(&address, granularity);
+    
+    let json = serde_json::to_string_pretty(&schedule)
+        .map_err(|e| format!("Failed to serialize unlock schedule: {}", e))?;
+    println!("{}", json);
+    
+    Ok(())
 }
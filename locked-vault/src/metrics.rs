@@ -0,0 +1,91 @@
+//! In-process metrics registry
+//!
+//! Components register named counters once and increment them lock-free;
+//! the registry can produce a sorted snapshot of all values. Counters kept
+//! per vault, event or similar carry labels in their name, written like
+//! Prometheus series: `vault_events{event="Deposited",vault="alpha"}`.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Monotonic counter
+#[derive(Debug, Default)]
+pub struct Counter {
+    /// Current value
+    value: AtomicU64,
+}
+
+impl Counter {
+    /// Increment the counter by one
+    pub fn inc(&self) {
+        self.add(1);
+    }
+    
+    /// Increment the counter by an amount
+    pub fn add(&self, amount: u64) {
+        self.value.fetch_add(amount, Ordering::Relaxed);
+    }
+    
+    /// Raise the counter to a value if it is higher, for high-water marks
+    pub fn raise_to(&self, value: u64) {
+        self.value.fetch_max(value, Ordering::Relaxed);
+    }
+    
+    /// Get the current value
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Registry of named counters
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    /// Counters by name
+    counters: Mutex<BTreeMap<String, Arc<Counter>>>,
+}
+
+impl MetricsRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Get the counter with the given name, registering it if needed
+    pub fn counter(&self, name: &str) -> Arc<Counter> {
+        match self.counters.lock() {
+            Ok(mut counters) => counters.entry(name.to_string()).or_default().clone(),
+            // A poisoned registry still hands out working, unregistered counters
+            Err(_) => Arc::new(Counter::default()),
+        }
+    }
+    
+    /// Get the counter with the given name and labels, registering it if needed
+    /// 
+    /// Labels are sorted by key, so their order does not make a new counter.
+    pub fn labeled_counter(&self, name: &str, labels: &[(&str, &str)]) -> Arc<Counter> {
+        self.counter(&labeled_name(name, labels))
+    }
+    
+    /// Get the current value of every counter, sorted by name
+    pub fn snapshot(&self) -> BTreeMap<String, u64> {
+        self.counters.lock()
+            .map(|counters| counters.iter().map(|(name, counter)| (name.clone(), counter.get())).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Name of a counter with labels, `name{key="value",...}` with the labels sorted by key
+pub fn labeled_name(name: &str, labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return name.to_string();
+    }
+    
+    let mut labels = labels.to_vec();
+    labels.sort();
+    let labels: Vec<String> = labels.iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    
+    format!("{}{{{}}}", name, labels.join(","))
+}
@@ -1,10 +1,23 @@
 use std::collections::HashMap;
 use std::cell::RefCell;
+use std::sync::Arc;
 use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
+use crate::errors::{ContractError, TransferStage, BACKEND_UNAVAILABLE};
+use crate::bitcoin::anchor::ChainAnchor;
+use crate::bitcoin::feature_gate::FeatureGateStatus;
+use crate::bitcoin::fee_oracle::ConfirmationTarget;
+use crate::bitcoin::ordinals::Inscription;
+use crate::bitcoin::utxo::{CoinControl, UtxoRef};
+use crate::bitcoin::confirmation::ConfirmationPolicy;
+use crate::bitcoin::descriptor::{parse_network, TimeLockPolicy};
+
 /// Represents different types of tokens that can be deposited
+/// 
+/// New token types may be added; match with a wildcard arm.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum TokenType {
     /// Bitcoin
     Bitcoin,
@@ -76,6 +89,50 @@ impl TokenType {
         }
     }
     
+    /// Check that an amount of this token can be deposited or moved
+    /// 
+    /// Every amount must be at least 1 and at most `MAX_AMOUNT`. An Ordinal
+    /// inscription is a unique asset, so its amount must be exactly 1. The
+    /// context adds the constraints of the backend moving the token, such as
+    /// the Lightning HTLC limits or a rune's divisibility.
+    pub fn validate_amount(&self, amount: u64, context: &dyn AmountContext) -> Result<(), ContractError> {
+        if matches!(self, TokenType::Ordinal(_)) && amount != 1 {
+            return Err(ContractError::InvalidAmount {
+                reason: format!("an Ordinal inscription is a unique asset, amount must be 1, not {}", amount),
+            });
+        }
+        
+        let constraints = context.amount_constraints(self)
+            .map_err(|reason| match reason.strip_prefix(BACKEND_UNAVAILABLE) {
+                Some(detail) => ContractError::BackendUnavailable(detail.to_string()),
+                None => ContractError::BackendUnavailable(reason),
+            })?;
+        
+        let min = constraints.min.max(1);
+        if amount < min {
+            return Err(ContractError::InvalidAmount {
+                reason: format!("{} {} is below the minimum of {}", amount, self.name(), min),
+            });
+        }
+        
+        let max = constraints.max.min(MAX_AMOUNT);
+        if amount > max {
+            return Err(ContractError::InvalidAmount {
+                reason: format!("{} {} is above the maximum of {}", amount, self.name(), max),
+            });
+        }
+        
+        if let Some(divisibility) = constraints.divisibility {
+            if !amount.is_multiple_of(constraints.step()) {
+                return Err(ContractError::InvalidAmount {
+                    reason: format!("{} {} has more than the {} decimal places the token divides into", amount, self.name(), divisibility),
+                });
+            }
+        }
+        
+        Ok(())
+    }
+    
     /// Check if the token type is Bitcoin-based
     pub fn is_bitcoin_based(&self) -> bool {
         match self {
@@ -98,8 +155,138 @@ impl TokenType {
     }
 }
 
+impl std::str::FromStr for TokenType {
+    type Err = String;
+    
+    /// Parse a token type in the form `name` returns, e.g. `Bitcoin` or `Rune(ID)`
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (kind, id) = match value.strip_suffix(')').and_then(|value| value.split_once('(')) {
+            Some((kind, id)) => (kind, Some(id.to_string())),
+            None => (value, None),
+        };
+        
+        match (kind.to_ascii_lowercase().as_str(), id) {
+            ("bitcoin", None) => Ok(TokenType::Bitcoin),
+            ("ethereum", None) => Ok(TokenType::Ethereum),
+            ("solana", None) => Ok(TokenType::Solana),
+            ("lightning", None) => Ok(TokenType::Lightning),
+            ("rune", Some(id)) => Ok(TokenType::Rune(id)),
+            ("ordinal", Some(id)) => Ok(TokenType::Ordinal(id)),
+            ("custom", Some(id)) => Ok(TokenType::Custom(id)),
+            _ => Err(format!("Unknown token type: {}", value)),
+        }
+    }
+}
+
+/// Largest amount of any token an operation accepts, keeping sums clear of overflow
+pub const MAX_AMOUNT: u64 = u64::MAX / 2;
+
+/// Decimal places amounts are counted in, as Bitcoin amounts count satoshis
+pub const AMOUNT_DECIMALS: u8 = 8;
+
+/// Amounts a transfer backend can move for one token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AmountConstraints {
+    /// Smallest amount
+    pub min: u64,
+    /// Largest amount
+    pub max: u64,
+    /// Decimal places the token divides into, `None` for all of `AMOUNT_DECIMALS`
+    pub divisibility: Option<u8>,
+}
+
+impl Default for AmountConstraints {
+    fn default() -> Self {
+        Self {
+            min: 1,
+            max: MAX_AMOUNT,
+            divisibility: None,
+        }
+    }
+}
+
+impl AmountConstraints {
+    /// Smallest amount step the token divides into
+    /// 
+    /// A token with 2 decimal places moves in steps of 10^6, since amounts
+    /// count `AMOUNT_DECIMALS` decimal places.
+    pub fn step(&self) -> u64 {
+        match self.divisibility {
+            Some(divisibility) if divisibility < AMOUNT_DECIMALS => 10u64.pow(u32::from(AMOUNT_DECIMALS - divisibility)),
+            _ => 1,
+        }
+    }
+}
+
+/// Source of the per-token constraints `TokenType::validate_amount` checks
+pub trait AmountContext {
+    /// Get the amounts that can be moved for a token
+    fn amount_constraints(&self, token_type: &TokenType) -> Result<AmountConstraints, String>;
+}
+
+impl AmountContext for AmountConstraints {
+    fn amount_constraints(&self, _token_type: &TokenType) -> Result<AmountConstraints, String> {
+        Ok(*self)
+    }
+}
+
+impl<T: TokenTransfer + ?Sized> AmountContext for T {
+    fn amount_constraints(&self, token_type: &TokenType) -> Result<AmountConstraints, String> {
+        TokenTransfer::amount_constraints(self, token_type)
+    }
+}
+
+/// Ordinal inscription identifier
+pub type InscriptionId = String;
+
+/// On-chain or off-chain resource backing a deposit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssetDetail {
+    /// UTXO reference (`txid:vout`)
+    Utxo(String),
+    /// Lightning invoice paying the deposit
+    LightningInvoice {
+        /// Invoice identifier, if the backend reports one
+        id: Option<String>,
+        /// Payment hash
+        payment_hash: String,
+    },
+    /// Ordinal inscription
+    Inscription(InscriptionId),
+    /// Multisig wallet holding the deposit
+    Multisig {
+        /// Wallet name
+        wallet: String,
+        /// Address the wallet received the deposit at
+        address: String,
+    },
+}
+
+/// Lifecycle status of a deposit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DepositStatus {
+    /// Locked or unlocked but not yet withdrawn
+    #[default]
+    Active,
+    /// Withdrawn after unlocking
+    Withdrawn,
+    /// Withdrawn early with the emergency fee
+    EmergencyWithdrawn,
+    /// Funding transaction reorganized out; not withdrawable until it re-confirms
+    Suspended,
+    /// Waiting for its funding to confirm or its invoice to be paid
+    Pending,
+    /// Pending deposit that was not funded before it expired
+    Expired,
+    /// Payout handed to the transfer backend, not yet known to be paid
+    WithdrawalPending,
+    /// Pending deposit the owner voided before its funding confirmed
+    Voided,
+}
+
 /// Represents a deposit in the contract
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(not(test), derive(Clone))]
 pub struct Deposit {
     /// Unique identifier for the deposit
     pub deposit_id: u64,
@@ -113,18 +300,220 @@ pub struct Deposit {
     pub deposit_timestamp: DateTime<Utc>,
     /// Timestamp when the deposit can be withdrawn
     pub unlock_timestamp: DateTime<Utc>,
-    /// Whether the deposit has been withdrawn
-    pub is_withdrawn: bool,
+    /// Lifecycle status of the deposit
+    pub status: DepositStatus,
     /// Transaction hash of the withdrawal, if any
     pub withdrawal_tx_hash: Option<String>,
     /// Last time the deposit was modified
     pub last_modified: DateTime<Utc>,
-    /// UTXO reference for Bitcoin-based tokens
-    pub utxo_reference: Option<String>,
+    /// Output funding the deposit, for Bitcoin-based tokens
+    pub utxo_reference: Option<UtxoRef>,
     /// Lightning payment hash for Lightning deposits
     pub lightning_payment_hash: Option<String>,
     /// Multisig wallet name for multisig deposits
     pub multisig_wallet: Option<String>,
+    /// ID of the Lightning invoice paying the deposit, if the backend reports one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lightning_invoice_id: Option<String>,
+    /// Address the multisig wallet received the deposit at, if the backend holds the wallet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig_address: Option<String>,
+    /// SHA-256 hash whose preimage lets anyone claim the deposit after maturity
+    #[serde(default)]
+    pub claim_hash: Option<[u8; 32]>,
+    /// Address that claimed the deposit with the preimage
+    #[serde(default)]
+    pub claimed_by: Option<String>,
+    /// Beneficiary who may claim the deposit if the depositor goes inactive
+    #[serde(default)]
+    pub inheritance: Option<Inheritance>,
+    /// Depositor's reminder before the deposit unlocks
+    #[serde(default)]
+    pub reminder: Option<MaturityReminder>,
+    /// Funding a pending deposit waits for, kept once it expired
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending: Option<PendingFunding>,
+    /// Script escrow terms, for deposits paid to their own time-locked output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escrow: Option<ScriptEscrow>,
+    /// Address credited a share of the fees this deposit pays
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer_address: Option<String>,
+}
+
+/// Funding a pending deposit is waiting for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingFunding {
+    /// When the deposit expires unless it was funded before
+    pub expires_at: DateTime<Utc>,
+    /// Deposit fee, collected once the deposit is funded
+    pub fee_amount: u64,
+}
+
+/// Terms of a deposit paid to its own time-locked P2WSH output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptEscrow {
+    /// Vault public key (hex), needed for the cooperative path
+    pub vault_public_key: String,
+    /// Depositor public key (hex), needed for both paths
+    pub depositor_public_key: String,
+    /// Block height from which the depositor can spend alone
+    pub lock_height: u32,
+    /// Network the address was derived for
+    pub network: String,
+    /// Address the depositor was told to pay
+    pub address: String,
+}
+
+impl ScriptEscrow {
+    /// Parse the stored terms
+    pub fn policy(&self) -> Result<TimeLockPolicy, ContractError> {
+        TimeLockPolicy::new(&self.vault_public_key, &self.depositor_public_key, self.lock_height)
+    }
+}
+
+/// Dead-man's switch on a deposit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inheritance {
+    /// Address that may claim the deposit
+    pub beneficiary_address: String,
+    /// Days without depositor activity after maturity before the beneficiary may claim
+    pub inactivity_days: u32,
+}
+
+/// Reminder sent to a depositor before their deposit unlocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MaturityReminder {
+    /// Days before unlocking the reminder is due
+    pub remind_days_before: u32,
+    /// Whether the reminder was sent
+    pub notified: bool,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Number of `Deposit` clones made on this thread, for asserting clone-free read paths
+    pub(crate) static DEPOSIT_CLONES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+impl Clone for Deposit {
+    fn clone(&self) -> Self {
+        DEPOSIT_CLONES.with(|clones| clones.set(clones.get() + 1));
+        
+        Self {
+            deposit_id: self.deposit_id,
+            depositor_address: self.depositor_address.clone(),
+            deposited_token_type: self.deposited_token_type.clone(),
+            deposited_amount: self.deposited_amount,
+            deposit_timestamp: self.deposit_timestamp,
+            unlock_timestamp: self.unlock_timestamp,
+            status: self.status,
+            withdrawal_tx_hash: self.withdrawal_tx_hash.clone(),
+            last_modified: self.last_modified,
+            utxo_reference: self.utxo_reference.clone(),
+            lightning_payment_hash: self.lightning_payment_hash.clone(),
+            multisig_wallet: self.multisig_wallet.clone(),
+            lightning_invoice_id: self.lightning_invoice_id.clone(),
+            multisig_address: self.multisig_address.clone(),
+            claim_hash: self.claim_hash,
+            claimed_by: self.claimed_by.clone(),
+            inheritance: self.inheritance.clone(),
+            reminder: self.reminder,
+            pending: self.pending,
+            escrow: self.escrow.clone(),
+            referrer_address: self.referrer_address.clone(),
+        }
+    }
+}
+
+impl Deposit {
+    /// Get the resource backing the deposit, if any
+    pub fn asset_detail(&self) -> Option<AssetDetail> {
+        if let Some(payment_hash) = &self.lightning_payment_hash {
+            return Some(AssetDetail::LightningInvoice {
+                id: self.lightning_invoice_id.clone(),
+                payment_hash: payment_hash.clone(),
+            });
+        }
+        
+        if let TokenType::Ordinal(inscription_id) = &self.deposited_token_type {
+            return Some(AssetDetail::Inscription(inscription_id.clone()));
+        }
+        
+        if let (Some(wallet), Some(address)) = (&self.multisig_wallet, &self.multisig_address) {
+            return Some(AssetDetail::Multisig {
+                wallet: wallet.clone(),
+                address: address.clone(),
+            });
+        }
+        
+        self.utxo_reference.as_ref().map(|reference| AssetDetail::Utxo(reference.to_string()))
+    }
+    
+    /// Check whether the deposit has been withdrawn, normally or in an emergency
+    pub fn is_withdrawn(&self) -> bool {
+        matches!(self.status, DepositStatus::Withdrawn | DepositStatus::EmergencyWithdrawn)
+    }
+    
+    /// Check whether the deposit no longer holds funds, withdrawn, expired unfunded or voided
+    pub fn is_closed(&self) -> bool {
+        self.is_withdrawn() || matches!(self.status, DepositStatus::Expired | DepositStatus::Voided)
+    }
+    
+    /// Check whether the deposit is suspended after a chain reorganization
+    pub fn is_suspended(&self) -> bool {
+        self.status == DepositStatus::Suspended
+    }
+    
+    /// Fail unless the deposit still holds funds to act on
+    /// 
+    /// Withdrawn deposits have none left, suspended ones wait for their
+    /// funding transaction to re-confirm, and `ensure_funded` covers the rest.
+    pub fn ensure_holds_funds(&self) -> Result<(), ContractError> {
+        if self.is_withdrawn() {
+            return Err(ContractError::DepositAlreadyWithdrawn);
+        }
+        
+        if self.is_suspended() {
+            return Err(ContractError::DepositSuspended);
+        }
+        
+        self.ensure_funded()
+    }
+    
+    /// Fail unless the deposit was funded and holds its funds, i.e. is neither pending, expired, voided nor being paid out
+    pub fn ensure_funded(&self) -> Result<(), ContractError> {
+        match self.status {
+            DepositStatus::Pending => Err(ContractError::DepositPending),
+            DepositStatus::Expired => Err(ContractError::DepositExpired),
+            DepositStatus::Voided => Err(ContractError::DepositVoided),
+            DepositStatus::WithdrawalPending => Err(ContractError::WithdrawalInFlight),
+            _ => Ok(()),
+        }
+    }
+    
+    /// Get the output descriptor of a script escrow deposit's locking terms, with its checksum
+    pub fn locking_descriptor(&self) -> Option<String> {
+        self.escrow.as_ref()
+            .and_then(|escrow| escrow.policy().ok())
+            .map(|policy| policy.descriptor())
+    }
+    
+    /// Re-derive the address from the locking descriptor and compare it with the stored one
+    pub fn verify_descriptor_matches_address(&self) -> Result<bool, ContractError> {
+        let escrow = self.escrow.as_ref()
+            .ok_or_else(|| ContractError::InvalidDescriptor("Deposit is not a script escrow deposit".to_string()))?;
+        let descriptor = escrow.policy()?.descriptor();
+        
+        let address = TimeLockPolicy::parse(&descriptor)?.address(parse_network(&escrow.network)?);
+        Ok(address.to_string().eq_ignore_ascii_case(&escrow.address))
+    }
+    
+    /// Get the ID of the transaction that funded the deposit, if known
+    pub fn funding_txid(&self) -> Option<&str> {
+        self.utxo_reference.as_ref().map(UtxoRef::txid)
+    }
 }
 
 /// Configuration for fees in the contract
@@ -136,6 +525,97 @@ pub struct FeeConfig {
     pub fee_collector_address: String,
     /// Accumulated fees per token type
     pub collected_fees: HashMap<TokenType, u64>,
+    /// Fees charged on new deposits per token type
+    #[serde(default)]
+    pub deposit_fees: HashMap<TokenType, DepositFee>,
+    /// Percentage of every emergency withdrawal fee paid into the insurance pool (0-100)
+    #[serde(default)]
+    pub insurance_share_percentage: u8,
+    /// Insurance pool balance per token type
+    #[serde(default)]
+    pub insurance_pool: HashMap<TokenType, u64>,
+    /// Percentage of the collector's part of a referred deposit's fees credited to the referrer (0-100)
+    #[serde(default)]
+    pub referral_share_percentage: u8,
+}
+
+impl FeeConfig {
+    /// Get the deposit fee for a token type, if one is charged
+    pub fn deposit_fee(&self, token_type: &TokenType) -> Option<DepositFee> {
+        self.deposit_fees.get(token_type).copied()
+    }
+}
+
+/// Fee taken from the principal of a new deposit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DepositFee {
+    /// Fixed amount in the token's base unit
+    Flat(u64),
+    /// Percentage of the deposited amount (0-99), rounded down
+    Percentage(u8),
+}
+
+/// Restrictions on emergency withdrawals
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencyPolicy {
+    /// Minimum deposit age before an emergency withdrawal is allowed
+    pub min_age_before_emergency: std::time::Duration,
+    /// Maximum emergency withdrawals per user in a rolling 30-day window
+    pub max_emergency_withdrawals_per_user_per_month: Option<u32>,
+}
+
+/// Default maximum lock period in days (10 years)
+pub const DEFAULT_MAX_LOCK_DAYS: u32 = 3650;
+
+/// Maximum lock periods for new deposits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockPolicy {
+    /// Contract-wide maximum lock period in days
+    pub max_lock_days: u32,
+    /// Lower maximums per token type
+    /// 
+    /// An entry with an empty identifier, such as `TokenType::Ordinal(String::new())`,
+    /// applies to every token of that kind without a more specific entry.
+    pub per_token_max: HashMap<TokenType, u32>,
+}
+
+impl Default for LockPolicy {
+    fn default() -> Self {
+        Self {
+            max_lock_days: DEFAULT_MAX_LOCK_DAYS,
+            per_token_max: HashMap::new(),
+        }
+    }
+}
+
+impl LockPolicy {
+    /// Get the maximum lock period for a token type
+    /// 
+    /// An exact entry wins over a kind-wide entry, which wins over the global maximum.
+    pub fn max_lock_days_for(&self, token_type: &TokenType) -> u32 {
+        if let Some(max_days) = self.per_token_max.get(token_type) {
+            return *max_days;
+        }
+        
+        let kind = match token_type {
+            TokenType::Rune(_) => Some(TokenType::Rune(String::new())),
+            TokenType::Ordinal(_) => Some(TokenType::Ordinal(String::new())),
+            TokenType::Custom(_) => Some(TokenType::Custom(String::new())),
+            _ => None,
+        };
+        
+        kind.and_then(|kind| self.per_token_max.get(&kind).copied())
+            .unwrap_or(self.max_lock_days)
+    }
+}
+
+/// How a withdrawal is paid out
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawOptions {
+    /// Highest mining fee the depositor accepts, in satoshis
+    pub max_onchain_fee: Option<u64>,
+    /// Contract wallet UTXOs to spend or avoid
+    pub coin_selection: Option<CoinControl>,
 }
 
 /// Limits for deposits in the contract
@@ -205,11 +685,559 @@ pub trait TokenTransfer {
         Ok(())
     }
     
+    /// Check that an address belongs to the network the backend runs on
+    /// 
+    /// Fails with `WrongNetwork` for an address of another network. Backends
+    /// without a notion of networks accept every address.
+    fn check_network(&self, _address: &str) -> Result<(), ContractError> {
+        Ok(())
+    }
+    
+    /// Validate an address funds are paid out to
+    /// 
+    /// Beyond `validate_address`, a backend may refuse destinations of
+    /// another network or whose script type its destination policy does not
+    /// allow.
+    fn validate_destination(&self, address: &str) -> Result<(), ContractError> {
+        self.check_network(address)?;
+        self.validate_address(address).map_err(|_| ContractError::InvalidAddress)
+    }
+    
     /// Check if the implementation supports a token type
     fn supports_token_type(&self, token_type: &TokenType) -> bool;
     
     /// Get the network type (e.g., "testnet", "mainnet")
     fn get_network_type(&self) -> String;
+    
+    /// Get the amount of a token actually held by the contract wallet
+    fn get_contract_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        Err(format!("Contract balance lookup not supported for {:?}", token_type))
+    }
+    
+    /// Get the part of the contract balance that is change of past transfers rather than deposited funds
+    ///
+    /// Backends that do not tag their change hold none.
+    fn get_operational_balance(&self, _token_type: &TokenType) -> Result<u64, String> {
+        Ok(0)
+    }
+    
+    /// Get how much of the contract balance treasury sweeps may spend, `None` if all of it
+    fn get_sweepable_balance(&self, _token_type: &TokenType) -> Result<Option<u64>, String> {
+        Ok(None)
+    }
+    
+    /// Get the state of the feature gate guarding a token's backend, `None` if it has none
+    fn feature_gate_status(&self, _token_type: &TokenType) -> Option<FeatureGateStatus> {
+        None
+    }
+    
+    /// Check whether a recorded UTXO reference (`txid:vout`) is still unspent
+    fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+        Err("UTXO lookup not supported".to_string())
+    }
+    
+    /// Look up a recorded UTXO reference (`txid:vout`), `None` if it is spent or does not exist
+    fn get_utxo(&self, utxo_reference: &str) -> Result<Option<UnspentOutput>, String> {
+        Err("UTXO lookup not supported".to_string())
+    }
+    
+    /// Chain tip to anchor events to, `None` if the backend has no recent one
+    /// 
+    /// Called for every event that moves funds, so it must not call the node.
+    fn chain_anchor(&self) -> Option<ChainAnchor> {
+        None
+    }
+    
+    /// Drop any cached balance of an address so the next lookup is fresh
+    fn invalidate_balance(&self, address: &str, token_type: &TokenType) {}
+    
+    /// Apply the contract's confirmation policy to the backend's chain watchers
+    fn set_confirmation_policy(&self, confirmation_policy: &ConfirmationPolicy) {}
+    
+    /// Watch the transaction funding a deposit for chain reorganizations
+    /// 
+    /// Backends without a reorg watcher ignore it.
+    fn track_funding(&self, deposit_id: u64, txid: &str, token_type: &TokenType, amount: u64) {}
+    
+    /// Transfer tokens from the contract with an urgency hint
+    /// 
+    /// Backends that do not queue transfers ignore the priority.
+    fn transfer_from_contract_with_priority(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        _priority: TransferPriority,
+    ) -> Result<(), String> {
+        self.transfer_from_contract(to_address, token_type, amount)
+    }
+    
+    /// Look up an inscription with whatever metadata the backend's Ordinals index has
+    /// 
+    /// Backends without an Ordinals index return `None`.
+    fn get_inscription_details(&self, _inscription_id: &str) -> Result<Option<Inscription>, String> {
+        Ok(None)
+    }
+    
+    /// Look up the ID of the Lightning invoice with a payment hash
+    /// 
+    /// Backends without a Lightning node return `None`.
+    fn lightning_invoice_id(&self, _payment_hash: &str) -> Option<String> {
+        None
+    }
+    
+    /// Get the address a multisig wallet receives deposits at
+    /// 
+    /// Backends that do not hold the wallet return `None`.
+    fn multisig_wallet_address(&self, _wallet: &str) -> Option<String> {
+        None
+    }
+    
+    /// Get how full the backend's queue of outgoing transfers is
+    /// 
+    /// Backends that send right away have no queue and return `None`.
+    fn transfer_queue_status(&self) -> Option<TransferQueueStatus> {
+        None
+    }
+    
+    /// Transfer tokens from the contract as a non-urgent payout that may wait for a batch
+    /// 
+    /// `reference` identifies the payout when its batch is sent; the contract
+    /// passes the deposit ID. Backends without payout batching send right away.
+    fn transfer_from_contract_batched(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        _reference: u64,
+    ) -> Result<(), String> {
+        self.transfer_from_contract(to_address, token_type, amount)
+    }
+    
+    /// Transfer tokens from the contract, refusing to pay more than `max_onchain_fee` in mining fees
+    /// 
+    /// Returns a typed error so a fee refusal (`FeeExceedsLimit`) can be told
+    /// apart from other failures. Backends without mining fees ignore the ceiling.
+    fn transfer_from_contract_with_fee_limit(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        _max_onchain_fee: Option<u64>,
+    ) -> Result<(), ContractError> {
+        self.transfer_from_contract_with_priority(to_address, token_type, amount, priority)
+            .map_err(|reason| ContractError::transfer_failed(TransferStage::Withdrawal, reason))
+    }
+    
+    /// Transfer tokens from the contract with a fee ceiling and coin control
+    /// 
+    /// Backends without coin control reject transfers that ask for it rather
+    /// than spend arbitrary inputs.
+    fn transfer_from_contract_with_options(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        options: &WithdrawOptions,
+    ) -> Result<(), ContractError> {
+        if options.coin_selection.is_some() {
+            return Err(ContractError::TransferFailed {
+                stage: TransferStage::UtxoSelection,
+                reason: "Coin control is not supported by this backend".to_string(),
+            });
+        }
+        
+        self.transfer_from_contract_with_fee_limit(to_address, token_type, amount, priority, options.max_onchain_fee)
+    }
+    
+    /// Send tokens from the contract right away, returning the transaction ID if the backend has one
+    ///
+    /// Used for treasury sweeps, which have to be settled before the hot
+    /// wallet balance is trusted again. Backends that do not queue transfers
+    /// send as usual and return `None`.
+    fn sweep_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<Option<String>, ContractError> {
+        self.transfer_from_contract(to_address, token_type, amount)
+            .map(|_| None)
+            .map_err(|reason| ContractError::transfer_failed(TransferStage::Withdrawal, reason))
+    }
+    
+    /// Estimate the mining fee of sending `amount` of a token out of the contract to an address
+    fn estimate_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<u64, String> {
+        Err(format!("Withdrawal fee estimate not supported for {:?}", token_type))
+    }
+    
+    /// Estimate the mining fee of a withdrawal along with the fee rate snapshot it used
+    fn quote_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<FeeQuote, String> {
+        self.estimate_withdrawal_fee(to_address, token_type, amount, priority)
+            .map(|fee| FeeQuote { fee, fee_snapshot_id: None, target: None, target_blocks: None })
+    }
+    
+    /// Smallest output of a token worth creating on-chain, `None` if the backend has no dust limit
+    fn dust_limit(&self, _token_type: &TokenType) -> Option<u64> {
+        None
+    }
+    
+    /// References of the payouts the backend holds but has not sent yet, in queue order
+    /// 
+    /// Backends that send right away hold nothing and return an empty list.
+    fn queued_payout_references(&self) -> Vec<u64> {
+        Vec::new()
+    }
+    
+    /// Find the transaction that sent the payout with this reference, if the backend knows one
+    /// 
+    /// Unlike the queue, this has to survive a restart of the process, so
+    /// backends look it up where it is persisted, e.g. the node wallet.
+    fn find_payout_transaction(&self, _reference: u64) -> Result<Option<String>, String> {
+        Ok(None)
+    }
+    
+    /// Get the confirmations of a transaction, `None` if the backend does not know it
+    fn transaction_confirmations(&self, _txid: &str) -> Result<Option<u32>, String> {
+        Ok(None)
+    }
+    
+    /// Drop a queued payout so it is never sent, returning whether it was queued
+    fn cancel_payout(&self, _reference: u64) -> bool {
+        false
+    }
+    
+    /// Transfer the funding of a deposit to the contract, labelled with the deposit
+    /// 
+    /// Backends that queue transfers can cancel it with `cancel_pending`
+    /// until it is sent; others transfer as usual.
+    fn transfer_to_contract_for_deposit(&self, from_address: &str, token_type: &TokenType, amount: u64, _deposit_id: u64) -> Result<(), String> {
+        self.transfer_to_contract(from_address, token_type, amount)
+    }
+    
+    /// Drop the queued funding transfer of a deposit, returning whether one was queued
+    /// 
+    /// Fails if the transfer was already broadcast, since it can no longer
+    /// be stopped. Backends that send right away hold nothing and return
+    /// `false`.
+    fn cancel_pending(&self, _reference: u64) -> Result<bool, String> {
+        Ok(false)
+    }
+    
+    /// Transfer tokens from the contract with an urgency hint, labelled with the deposit paid
+    /// 
+    /// Unlike a batched payout it is never held for a batch; like one it is
+    /// listed by `queued_payout_references` until sent and found by
+    /// `find_payout_transaction` afterwards.
+    fn transfer_from_contract_for_deposit(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        _deposit_id: u64,
+    ) -> Result<(), String> {
+        self.transfer_from_contract_with_priority(to_address, token_type, amount, priority)
+    }
+    
+    /// Check whether the funding transfer of a deposit was queued or sent
+    /// 
+    /// Like `find_payout_transaction` this has to survive a restart of the
+    /// process. Backends that send right away keep no record and return `false`.
+    fn find_funding_transfer(&self, _deposit_id: u64) -> Result<bool, String> {
+        Ok(false)
+    }
+    
+    /// Watch the output a script escrow deposit was told to pay
+    /// 
+    /// Backends that export watch descriptors include it; others ignore it.
+    fn watch_script_escrow(&self, _escrow: &ScriptEscrow) {}
+    
+    /// Get the amounts of a token the backend can move
+    /// 
+    /// Backends without limits of their own accept any amount up to `MAX_AMOUNT`.
+    fn amount_constraints(&self, _token_type: &TokenType) -> Result<AmountConstraints, String> {
+        Ok(AmountConstraints::default())
+    }
+}
+
+/// A backend shared by several contracts, such as the vaults of a `VaultManager`
+///
+/// Every call goes to the shared backend, so overrides of the provided
+/// methods are kept.
+impl<T: TokenTransfer + ?Sized> TokenTransfer for Arc<T> {
+    fn transfer_to_contract(&self, from_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        (**self).transfer_to_contract(from_address, token_type, amount)
+    }
+    
+    fn transfer_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        (**self).transfer_from_contract(to_address, token_type, amount)
+    }
+    
+    fn get_balance(&self, address: &str, token_type: &TokenType) -> Result<u64, String> {
+        (**self).get_balance(address, token_type)
+    }
+    
+    fn validate_address(&self, address: &str) -> Result<(), String> {
+        (**self).validate_address(address)
+    }
+    
+    fn check_network(&self, address: &str) -> Result<(), ContractError> {
+        (**self).check_network(address)
+    }
+    
+    fn validate_destination(&self, address: &str) -> Result<(), ContractError> {
+        (**self).validate_destination(address)
+    }
+    
+    fn supports_token_type(&self, token_type: &TokenType) -> bool {
+        (**self).supports_token_type(token_type)
+    }
+    
+    fn get_network_type(&self) -> String {
+        (**self).get_network_type()
+    }
+    
+    fn get_contract_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        (**self).get_contract_balance(token_type)
+    }
+    
+    fn get_operational_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        (**self).get_operational_balance(token_type)
+    }
+    
+    fn get_sweepable_balance(&self, token_type: &TokenType) -> Result<Option<u64>, String> {
+        (**self).get_sweepable_balance(token_type)
+    }
+    
+    fn feature_gate_status(&self, token_type: &TokenType) -> Option<FeatureGateStatus> {
+        (**self).feature_gate_status(token_type)
+    }
+    
+    fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+        (**self).is_utxo_unspent(utxo_reference)
+    }
+    
+    fn get_utxo(&self, utxo_reference: &str) -> Result<Option<UnspentOutput>, String> {
+        (**self).get_utxo(utxo_reference)
+    }
+    
+    fn chain_anchor(&self) -> Option<ChainAnchor> {
+        (**self).chain_anchor()
+    }
+    
+    fn invalidate_balance(&self, address: &str, token_type: &TokenType) {
+        (**self).invalidate_balance(address, token_type)
+    }
+    
+    fn set_confirmation_policy(&self, confirmation_policy: &ConfirmationPolicy) {
+        (**self).set_confirmation_policy(confirmation_policy)
+    }
+    
+    fn transfer_from_contract_with_priority(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<(), String> {
+        (**self).transfer_from_contract_with_priority(to_address, token_type, amount, priority)
+    }
+    
+    fn get_inscription_details(&self, inscription_id: &str) -> Result<Option<Inscription>, String> {
+        (**self).get_inscription_details(inscription_id)
+    }
+    
+    fn lightning_invoice_id(&self, payment_hash: &str) -> Option<String> {
+        (**self).lightning_invoice_id(payment_hash)
+    }
+    
+    fn multisig_wallet_address(&self, wallet: &str) -> Option<String> {
+        (**self).multisig_wallet_address(wallet)
+    }
+    
+    fn transfer_queue_status(&self) -> Option<TransferQueueStatus> {
+        (**self).transfer_queue_status()
+    }
+    
+    fn transfer_from_contract_batched(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        reference: u64,
+    ) -> Result<(), String> {
+        (**self).transfer_from_contract_batched(to_address, token_type, amount, reference)
+    }
+    
+    fn transfer_from_contract_with_fee_limit(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        max_onchain_fee: Option<u64>,
+    ) -> Result<(), ContractError> {
+        (**self).transfer_from_contract_with_fee_limit(to_address, token_type, amount, priority, max_onchain_fee)
+    }
+    
+    fn transfer_from_contract_with_options(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        options: &WithdrawOptions,
+    ) -> Result<(), ContractError> {
+        (**self).transfer_from_contract_with_options(to_address, token_type, amount, priority, options)
+    }
+    
+    fn sweep_from_contract(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+    ) -> Result<Option<String>, ContractError> {
+        (**self).sweep_from_contract(to_address, token_type, amount)
+    }
+    
+    fn estimate_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<u64, String> {
+        (**self).estimate_withdrawal_fee(to_address, token_type, amount, priority)
+    }
+    
+    fn quote_withdrawal_fee(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+    ) -> Result<FeeQuote, String> {
+        (**self).quote_withdrawal_fee(to_address, token_type, amount, priority)
+    }
+    
+    fn dust_limit(&self, token_type: &TokenType) -> Option<u64> {
+        (**self).dust_limit(token_type)
+    }
+    
+    fn queued_payout_references(&self) -> Vec<u64> {
+        (**self).queued_payout_references()
+    }
+    
+    fn find_payout_transaction(&self, reference: u64) -> Result<Option<String>, String> {
+        (**self).find_payout_transaction(reference)
+    }
+    
+    fn transaction_confirmations(&self, txid: &str) -> Result<Option<u32>, String> {
+        (**self).transaction_confirmations(txid)
+    }
+    
+    fn cancel_payout(&self, reference: u64) -> bool {
+        (**self).cancel_payout(reference)
+    }
+    
+    fn transfer_to_contract_for_deposit(
+        &self,
+        from_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        deposit_id: u64,
+    ) -> Result<(), String> {
+        (**self).transfer_to_contract_for_deposit(from_address, token_type, amount, deposit_id)
+    }
+    
+    fn cancel_pending(&self, reference: u64) -> Result<bool, String> {
+        (**self).cancel_pending(reference)
+    }
+    
+    fn transfer_from_contract_for_deposit(
+        &self,
+        to_address: &str,
+        token_type: &TokenType,
+        amount: u64,
+        priority: TransferPriority,
+        deposit_id: u64,
+    ) -> Result<(), String> {
+        (**self).transfer_from_contract_for_deposit(to_address, token_type, amount, priority, deposit_id)
+    }
+    
+    fn find_funding_transfer(&self, deposit_id: u64) -> Result<bool, String> {
+        (**self).find_funding_transfer(deposit_id)
+    }
+    
+    fn watch_script_escrow(&self, escrow: &ScriptEscrow) {
+        (**self).watch_script_escrow(escrow)
+    }
+    
+    fn amount_constraints(&self, token_type: &TokenType) -> Result<AmountConstraints, String> {
+        (**self).amount_constraints(token_type)
+    }
+}
+
+/// Unspent transaction output as the node reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnspentOutput {
+    /// Value in satoshis
+    pub value: u64,
+    /// Confirmations of the transaction creating the output, zero if unconfirmed
+    pub confirmations: u32,
+}
+
+/// Estimated mining fee of a transfer, with the fee rate snapshot and service level it was priced for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeQuote {
+    /// Mining fee
+    pub fee: u64,
+    /// ID of the fee oracle snapshot the fee rate came from, for backends with one
+    pub fee_snapshot_id: Option<u64>,
+    /// Confirmation target the fee rate was estimated for, for backends with one
+    #[serde(default)]
+    pub target: Option<ConfirmationTarget>,
+    /// Blocks the target resolved to
+    #[serde(default)]
+    pub target_blocks: Option<u16>,
+}
+
+/// Occupancy of a backend's transfer queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferQueueStatus {
+    /// Transfers waiting
+    pub depth: usize,
+    /// Most transfers that can wait
+    pub capacity: usize,
+}
+
+impl TransferQueueStatus {
+    /// Whether new transfers are refused until the queue drains
+    pub fn is_saturated(&self) -> bool {
+        self.depth >= self.capacity
+    }
+}
+
+/// Urgency of an outgoing transfer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum TransferPriority {
+    /// Routine transfers that can wait for cheap blocks, such as fee sweeps
+    Low,
+    /// Regular withdrawals
+    #[default]
+    Normal,
+    /// Transfers that should confirm quickly, such as emergency withdrawals
+    High,
 }
 
 /// Reentrancy guard to prevent reentrancy attacks
@@ -0,0 +1,65 @@
+//! Supported public surface of the crate
+//!
+//! `use time_locked_deposit::prelude::*;` brings in what an integrator needs
+//! to run a vault: the contract, its configuration, the transfer trait with
+//! the Bitcoin backend, the deposit types, errors and events, and the
+//! `VaultClient` facade. Everything here keeps its name and path across
+//! minor releases; modules can move behind it without breaking callers.
+//!
+//! `ContractError`, `Event` and `TokenType` are `#[non_exhaustive]`, so
+//! matches on them need a wildcard arm and new variants are not breaking.
+//!
+//! Deep paths into implementation modules are not part of that promise, and
+//! some are private outright. The contract's defining module is one of them:
+//!
+//! ```compile_fail,E0603
+//! use time_locked_deposit::contract::contract_core::TimeLockedDeposit;
+//! ```
+//!
+//! So is the balance cache of the Bitcoin backend:
+//!
+//! ```compile_fail,E0603
+//! use time_locked_deposit::bitcoin::cache::BalanceCache;
+//! ```
+//!
+//! And matching a public enum exhaustively does not compile outside the crate:
+//!
+//! ```compile_fail,E0004
+//! use time_locked_deposit::prelude::*;
+//!
+//! fn name(token_type: &TokenType) -> &'static str {
+//!     match token_type {
+//!         TokenType::Bitcoin => "bitcoin",
+//!         TokenType::Ethereum => "ethereum",
+//!         TokenType::Solana => "solana",
+//!         TokenType::Rune(_) => "rune",
+//!         TokenType::Ordinal(_) => "ordinal",
+//!         TokenType::Lightning => "lightning",
+//!         TokenType::Custom(_) => "custom",
+//!     }
+//! }
+//! ```
+//!
+//! The supported paths compile:
+//!
+//! ```
+//! use time_locked_deposit::prelude::*;
+//!
+//! fn name(token_type: &TokenType) -> String {
+//!     match token_type {
+//!         TokenType::Bitcoin => "bitcoin".to_string(),
+//!         other => other.name(),
+//!     }
+//! }
+//! # let _: Option<TimeLockedDeposit<BitcoinTestnetTransfer>> = None;
+//! # assert_eq!(name(&TokenType::Lightning), TokenType::Lightning.name());
+//! ```
+
+pub use crate::client::{DepositOutcome, VaultClient, WithdrawalOutcome};
+pub use crate::clock::{Clock, MockClock, SystemClock};
+pub use crate::config::{Secret, VaultConfig};
+pub use crate::contract::{ContractStats, DepositFilter, DepositView, ReadOnlyTransfer, TimeLockedDeposit};
+pub use crate::errors::{ContractError, ErrorCode};
+pub use crate::events::Event;
+pub use crate::models::{Deposit, DepositStatus, TokenTransfer, TokenType};
+pub use crate::bitcoin::{BitcoinTestnetConfig, BitcoinTestnetTransfer};
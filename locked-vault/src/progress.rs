@@ -0,0 +1,332 @@
+//! Progress reporting and cancellation of long-running operations
+//!
+//! UTXO audits, deposit exports and state conversions can run for minutes.
+//! They take an optional `Progress` handle, shared behind an `Arc` with
+//! whoever watches them: the operation sets a stage label and advances the
+//! processed count against the total, and the watcher reads them back or
+//! cancels. Operations check the cancel flag between items and stop with
+//! `ContractError::Cancelled` at a point where their state is consistent,
+//! before anything is written or with only complete items written.
+//!
+//! The HTTP API starts operations in the background through a `JobRegistry`,
+//! which keeps the progress and outcome of each job for `/jobs`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::ContractError;
+use crate::logging::DAEMON;
+
+/// Most finished jobs a registry remembers
+pub const MAX_FINISHED_JOBS: usize = 100;
+
+/// Width of the bar `ProgressSnapshot` renders
+const BAR_WIDTH: usize = 30;
+
+/// Shared progress and cancel flag of one operation
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// What the operation is doing
+    stage: Mutex<String>,
+    /// Items of the stage done
+    processed: AtomicU64,
+    /// Items of the stage, 0 while unknown
+    total: AtomicU64,
+    /// Set once cancellation is asked for
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    /// Create a handle at no stage, not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Enter a stage of `total` items, 0 if unknown, with none processed
+    pub fn start_stage(&self, stage: &str, total: u64) {
+        if let Ok(mut current) = self.stage.lock() {
+            *current = stage.to_string();
+        }
+        self.processed.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+    
+    /// Count `items` more as processed
+    pub fn advance(&self, items: u64) {
+        self.processed.fetch_add(items, Ordering::Relaxed);
+    }
+    
+    /// Current stage label
+    pub fn stage(&self) -> String {
+        self.stage.lock().map(|stage| stage.clone()).unwrap_or_default()
+    }
+    
+    /// Items of the current stage done
+    pub fn processed(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+    
+    /// Items of the current stage, 0 while unknown
+    pub fn total(&self) -> u64 {
+        self.total.load(Ordering::Relaxed)
+    }
+    
+    /// Ask the operation to stop at its next check
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    
+    /// Whether cancellation was asked for
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+    
+    /// Fail with `Cancelled` if cancellation was asked for
+    pub fn check_cancelled(&self) -> Result<(), ContractError> {
+        if self.is_cancelled() {
+            return Err(ContractError::Cancelled {
+                stage: self.stage(),
+                processed: self.processed(),
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Copy of the current state
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            stage: self.stage(),
+            processed: self.processed(),
+            total: self.total(),
+            cancelled: self.is_cancelled(),
+        }
+    }
+}
+
+/// State of a `Progress` at one moment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProgressSnapshot {
+    /// What the operation is doing
+    pub stage: String,
+    /// Items of the stage done
+    pub processed: u64,
+    /// Items of the stage, 0 while unknown
+    pub total: u64,
+    /// Whether cancellation was asked for
+    pub cancelled: bool,
+}
+
+impl ProgressSnapshot {
+    /// Share of the stage done, `None` while the total is unknown
+    pub fn fraction(&self) -> Option<f64> {
+        (self.total > 0).then(|| (self.processed.min(self.total) as f64) / (self.total as f64))
+    }
+}
+
+impl fmt::Display for ProgressSnapshot {
+    /// Render as a one-line bar, e.g. `export [#######-------]  50% 500/1000`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fraction() {
+            Some(fraction) => {
+                let filled = (fraction * BAR_WIDTH as f64).round() as usize;
+                write!(
+                    f, "{} [{}{}] {:>3}% {}/{}",
+                    self.stage, "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), (fraction * 100.0).floor() as u32, self.processed, self.total
+                )
+            },
+            None => write!(f, "{} {}", self.stage, self.processed),
+        }
+    }
+}
+
+/// Where a background job stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Still running
+    Running,
+    /// Finished with a result
+    Completed,
+    /// Stopped on an error
+    Failed,
+    /// Stopped on request
+    Cancelled,
+}
+
+/// Status of a background job
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    /// Job ID
+    pub id: u64,
+    /// Operation the job runs, e.g. `utxo_audit`
+    pub kind: String,
+    /// Where the job stands
+    pub state: JobState,
+    /// Progress of the operation
+    pub progress: ProgressSnapshot,
+    /// When the job was started
+    pub started_at: DateTime<Utc>,
+    /// When the job finished, `None` while running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Result of a completed job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Structured error of a failed or cancelled job
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+}
+
+/// A job and the handle its operation reports to
+#[derive(Debug)]
+struct Job {
+    /// Kind of operation
+    kind: String,
+    /// Progress shared with the operation
+    progress: Arc<Progress>,
+    /// Where the job stands
+    state: JobState,
+    /// When the job was started
+    started_at: DateTime<Utc>,
+    /// When the job finished
+    finished_at: Option<DateTime<Utc>>,
+    /// Result of a completed job
+    result: Option<Value>,
+    /// Error of a failed or cancelled job
+    error: Option<Value>,
+}
+
+/// Operations started in the background, with their progress and outcome
+#[derive(Debug, Default)]
+pub struct JobRegistry {
+    /// ID of the next job
+    next_id: AtomicU64,
+    /// Jobs by ID
+    jobs: Mutex<BTreeMap<u64, Job>>,
+}
+
+impl JobRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Run an operation on its own thread and return the job's ID
+    ///
+    /// The operation reports to the `Progress` it is given, which `cancel`
+    /// flags. The oldest finished jobs are forgotten beyond
+    /// `MAX_FINISHED_JOBS`.
+    pub fn spawn<F>(self: &Arc<Self>, kind: &str, operation: F) -> u64
+    where
+        F: FnOnce(&Progress) -> Result<Value, ContractError> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let progress = Arc::new(Progress::new());
+        
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.insert(id, Job {
+                kind: kind.to_string(),
+                progress: progress.clone(),
+                state: JobState::Running,
+                started_at: Utc::now(),
+                finished_at: None,
+                result: None,
+                error: None,
+            });
+        }
+        
+        info!(target: DAEMON, "Started {} job {}", kind, id);
+        
+        let registry = self.clone();
+        thread::spawn(move || {
+            let outcome = operation(&progress);
+            registry.finish(id, outcome);
+        });
+        
+        id
+    }
+    
+    /// Record how a job ended
+    fn finish(&self, id: u64, outcome: Result<Value, ContractError>) {
+        let mut jobs = match self.jobs.lock() {
+            Ok(jobs) => jobs,
+            Err(_) => return,
+        };
+        
+        if let Some(job) = jobs.get_mut(&id) {
+            job.finished_at = Some(Utc::now());
+            match outcome {
+                Ok(result) => {
+                    job.state = JobState::Completed;
+                    job.result = Some(result);
+                },
+                Err(e) => {
+                    job.state = if matches!(e, ContractError::Cancelled { .. }) { JobState::Cancelled } else { JobState::Failed };
+                    warn!(target: DAEMON, "{} job {} stopped: {}", job.kind, id, e);
+                    job.error = serde_json::to_value(&e).ok();
+                },
+            }
+        }
+        
+        // Forget the oldest finished jobs
+        let finished: Vec<u64> = jobs.iter()
+            .filter(|(_, job)| job.state != JobState::Running)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS)) {
+            jobs.remove(id);
+        }
+    }
+    
+    /// Ask a running job to stop, `false` if no such job is running
+    pub fn cancel(&self, id: u64) -> bool {
+        let jobs = match self.jobs.lock() {
+            Ok(jobs) => jobs,
+            Err(_) => return false,
+        };
+        
+        match jobs.get(&id) {
+            Some(job) if job.state == JobState::Running => {
+                job.progress.cancel();
+                true
+            },
+            _ => false,
+        }
+    }
+    
+    /// Status of a job, `None` if unknown or forgotten
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.jobs.lock().ok()?.get(&id).map(|job| job.status(id))
+    }
+    
+    /// Status of every remembered job, by ID
+    pub fn list(&self) -> Vec<JobStatus> {
+        self.jobs.lock()
+            .map(|jobs| jobs.iter().map(|(id, job)| job.status(*id)).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Job {
+    /// Status of the job under its ID
+    fn status(&self, id: u64) -> JobStatus {
+        JobStatus {
+            id,
+            kind: self.kind.clone(),
+            state: self.state,
+            progress: self.progress.snapshot(),
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            result: self.result.clone(),
+            error: self.error.clone(),
+        }
+    }
+}
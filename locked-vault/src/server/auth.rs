@@ -0,0 +1,411 @@
+//! Scoped API keys for the HTTP API
+//!
+//! Each key carries a set of capabilities. `ReadOnly` reads deposits and
+//! statistics, `Deposit` makes deposits, `Withdraw` makes withdrawals and
+//! `Admin` covers owner operations and runtime diagnostics, and every other
+//! capability too.
+//!
+//! Keys are kept in an `ApiKeyStore` file and look like
+//! `tlv_<key id>_<secret>`. The file holds only a random salt and the
+//! SHA-256 of the salt and the key, never the key itself; the key is shown
+//! once, when it is created. Keys are 256 random bits, so a slow password
+//! hash would add nothing but latency to every request. The store reloads
+//! the file whenever it changes, so a key revoked with `apikey revoke` is
+//! refused without restarting the server.
+//!
+//! The server resolves the `X-Api-Key` header of a request through its
+//! `ApiAuth`. With a key store, every endpoint but `/health` needs a key
+//! holding the capability it requires; calls without a valid key are answered
+//! 401 and calls lacking the capability 403 with a `MISSING_CAPABILITY` error.
+//! With the single shared `VAULT_API_KEY` of earlier versions, that key holds
+//! `Admin` and only owner endpoints need it.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+
+use crate::config::Secret;
+use crate::contract::claim::constant_time_eq;
+use crate::errors::ContractError;
+use crate::store::write_atomically;
+use crate::logging::DAEMON;
+
+/// Prefix of every API key
+const KEY_PREFIX: &str = "tlv_";
+
+/// Random bytes of a key ID
+const KEY_ID_BYTES: usize = 8;
+
+/// Random bytes of a key secret
+const KEY_SECRET_BYTES: usize = 32;
+
+/// Random bytes of the salt hashed with a key
+const SALT_BYTES: usize = 16;
+
+/// What an API key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Read deposits, statistics and metrics
+    ReadOnly,
+    /// Make and simulate deposits
+    Deposit,
+    /// Withdraw deposits
+    Withdraw,
+    /// Owner operations and diagnostics, and everything else
+    Admin,
+}
+
+impl Capability {
+    /// Get the name of the capability
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::ReadOnly => "read_only",
+            Capability::Deposit => "deposit",
+            Capability::Withdraw => "withdraw",
+            Capability::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = String;
+    
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_lowercase().as_str() {
+            "read" | "read_only" | "readonly" => Ok(Capability::ReadOnly),
+            "deposit" => Ok(Capability::Deposit),
+            "withdraw" => Ok(Capability::Withdraw),
+            "admin" => Ok(Capability::Admin),
+            other => Err(format!("Unknown capability: {} (expected read, deposit, withdraw or admin)", other)),
+        }
+    }
+}
+
+/// Parse a comma-separated list of capabilities, e.g. `read,deposit`
+pub fn parse_capabilities(value: &str) -> Result<BTreeSet<Capability>, String> {
+    let capabilities = value.split(',')
+        .filter(|name| !name.trim().is_empty())
+        .map(str::parse)
+        .collect::<Result<BTreeSet<Capability>, String>>()?;
+    
+    if capabilities.is_empty() {
+        return Err("At least one capability is required".to_string());
+    }
+    
+    Ok(capabilities)
+}
+
+/// Stored API key, without the key itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    /// Key ID, also part of the key
+    pub id: String,
+    /// Hex salt hashed with the key
+    salt: String,
+    /// Hex SHA-256 of the salt and the key
+    hash: String,
+    /// Capabilities the key holds
+    pub capabilities: BTreeSet<Capability>,
+    /// When the key was created
+    pub created_at: DateTime<Utc>,
+    /// When the key was revoked, if it was
+    #[serde(default)]
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKeyRecord {
+    /// Whether the key is still accepted
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+    
+    /// Check a presented key against the stored hash
+    fn matches(&self, key: &str) -> bool {
+        hex::decode(&self.salt)
+            .map(|salt| constant_time_eq(hash_key(&salt, key).as_bytes(), self.hash.as_bytes()))
+            .unwrap_or(false)
+    }
+}
+
+/// Hex SHA-256 of a salt followed by a key
+fn hash_key(salt: &[u8], key: &str) -> String {
+    let mut salted = salt.to_vec();
+    salted.extend_from_slice(key.as_bytes());
+    hex::encode(sha256::Hash::hash(&salted).to_byte_array())
+}
+
+/// Random bytes, hex-encoded
+fn random_hex(length: usize) -> String {
+    let mut bytes = vec![0u8; length];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Key ID of a key of the `tlv_<key id>_<secret>` form
+fn key_id_of(key: &str) -> Option<&str> {
+    key.strip_prefix(KEY_PREFIX)
+        .and_then(|rest| rest.split_once('_'))
+        .map(|(key_id, _)| key_id)
+}
+
+/// Caller a presented API key resolved to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyIdentity {
+    /// ID of the key, recorded in the authorization log
+    pub key_id: String,
+    /// Capabilities the key holds
+    pub capabilities: BTreeSet<Capability>,
+}
+
+impl ApiKeyIdentity {
+    /// Whether the key may make calls needing `capability`
+    pub fn allows(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability) || self.capabilities.contains(&Capability::Admin)
+    }
+}
+
+/// Contents of the key store file
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ApiKeyFile {
+    /// Every key created, revoked ones included
+    keys: Vec<ApiKeyRecord>,
+}
+
+/// Keys loaded from the store file
+#[derive(Debug, Default)]
+struct LoadedKeys {
+    /// Records in the file
+    records: Vec<ApiKeyRecord>,
+    /// Modification time and length of the file when read, `None` if it did not exist
+    version: Option<(SystemTime, u64)>,
+}
+
+/// File-backed store of hashed, scoped API keys
+#[derive(Debug)]
+pub struct ApiKeyStore {
+    /// Path of the store file
+    path: PathBuf,
+    /// Keys as last read
+    keys: RwLock<LoadedKeys>,
+}
+
+impl ApiKeyStore {
+    /// Open the store at `path`, empty if the file does not exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let store = Self {
+            path: path.as_ref().to_path_buf(),
+            keys: RwLock::new(LoadedKeys::default()),
+        };
+        store.reload()?;
+        
+        Ok(store)
+    }
+    
+    /// Create a key holding `capabilities`, returning its record and the key
+    ///
+    /// The key is not stored and cannot be shown again.
+    pub fn create(&self, capabilities: BTreeSet<Capability>) -> Result<(ApiKeyRecord, Secret<String>), String> {
+        if capabilities.is_empty() {
+            return Err("At least one capability is required".to_string());
+        }
+        
+        let id = random_hex(KEY_ID_BYTES);
+        let key = Secret::new(format!("{}{}_{}", KEY_PREFIX, id, random_hex(KEY_SECRET_BYTES)));
+        let salt = random_hex(SALT_BYTES);
+        let hash = hash_key(&hex::decode(&salt).map_err(|e| e.to_string())?, key.expose_secret());
+        
+        let record = ApiKeyRecord {
+            id,
+            salt,
+            hash,
+            capabilities,
+            created_at: Utc::now(),
+            revoked_at: None,
+        };
+        
+        self.update(|records| {
+            records.push(record.clone());
+            Ok(())
+        })?;
+        
+        Ok((record, key))
+    }
+    
+    /// Revoke a key, returning whether an active key was revoked
+    pub fn revoke(&self, key_id: &str) -> Result<bool, String> {
+        self.update(|records| {
+            let record = records.iter_mut().find(|record| record.id == key_id && record.is_active());
+            Ok(match record {
+                Some(record) => {
+                    record.revoked_at = Some(Utc::now());
+                    true
+                },
+                None => false,
+            })
+        })
+    }
+    
+    /// Get every key record, revoked ones included, in creation order
+    pub fn list(&self) -> Result<Vec<ApiKeyRecord>, String> {
+        self.refresh()?;
+        Ok(self.read().records.clone())
+    }
+    
+    /// Resolve a presented key, `None` if it is unknown, wrong or revoked
+    ///
+    /// Picks up changes other processes made to the file first.
+    pub fn authenticate(&self, key: &str) -> Option<ApiKeyIdentity> {
+        if let Err(e) = self.refresh() {
+            log::warn!(target: DAEMON, "Failed to reload API keys from {}: {}", self.path.display(), e);
+        }
+        
+        let key_id = key_id_of(key)?;
+        let keys = self.read();
+        
+        keys.records.iter()
+            .find(|record| record.id == key_id && record.is_active() && record.matches(key))
+            .map(|record| ApiKeyIdentity {
+                key_id: record.id.clone(),
+                capabilities: record.capabilities.clone(),
+            })
+    }
+    
+    /// Lock the loaded keys for reading
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, LoadedKeys> {
+        self.keys.read().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+    
+    /// Modification time and length of the file, `None` if it does not exist
+    fn file_version(&self) -> Result<Option<(SystemTime, u64)>, String> {
+        match fs::metadata(&self.path) {
+            Ok(metadata) => Ok(Some((metadata.modified().map_err(|e| e.to_string())?, metadata.len()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        }
+    }
+    
+    /// Reload the file if it changed since it was read
+    fn refresh(&self) -> Result<(), String> {
+        if self.file_version()? != self.read().version {
+            self.reload()?;
+        }
+        Ok(())
+    }
+    
+    /// Read the file
+    fn reload(&self) -> Result<(), String> {
+        let version = self.file_version()?;
+        let records = match version {
+            Some(_) => {
+                let contents = fs::read(&self.path)
+                    .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+                serde_json::from_slice::<ApiKeyFile>(&contents)
+                    .map_err(|e| format!("Invalid API key file {}: {}", self.path.display(), e))?
+                    .keys
+            },
+            None => Vec::new(),
+        };
+        
+        let mut keys = self.keys.write().unwrap_or_else(std::sync::PoisonError::into_inner);
+        *keys = LoadedKeys { records, version };
+        
+        Ok(())
+    }
+    
+    /// Change the records and write them back, starting from the file's latest contents
+    fn update<R>(&self, change: impl FnOnce(&mut Vec<ApiKeyRecord>) -> Result<R, String>) -> Result<R, String> {
+        self.refresh()?;
+        
+        let mut keys = self.keys.write().unwrap_or_else(std::sync::PoisonError::into_inner);
+        let mut records = keys.records.clone();
+        let result = change(&mut records)?;
+        
+        let contents = serde_json::to_vec_pretty(&ApiKeyFile { keys: records.clone() })
+            .map_err(|e| format!("Failed to serialize API keys: {}", e))?;
+        write_atomically(&self.path, &contents)?;
+        
+        keys.records = records;
+        keys.version = self.file_version()?;
+        
+        Ok(result)
+    }
+}
+
+/// How the HTTP API authenticates callers
+#[derive(Debug, Clone, Default)]
+pub enum ApiAuth {
+    /// No keys; owner endpoints are disabled
+    #[default]
+    Disabled,
+    /// One shared key holding `Admin`, needed by owner endpoints only
+    SharedKey(Secret<String>),
+    /// Scoped keys, needed by every endpoint but `/health`
+    KeyStore(Arc<ApiKeyStore>),
+}
+
+impl ApiAuth {
+    /// Authenticate with the shared key, or disable owner endpoints without one
+    pub fn shared(api_key: Option<Secret<String>>) -> Self {
+        match api_key {
+            Some(api_key) => ApiAuth::SharedKey(api_key),
+            None => ApiAuth::Disabled,
+        }
+    }
+    
+    /// Whether every call needs a key, not only owner calls
+    pub fn is_scoped(&self) -> bool {
+        matches!(self, ApiAuth::KeyStore(_))
+    }
+    
+    /// Resolve a presented key, `None` without a valid one
+    pub fn authenticate(&self, api_key: Option<&str>) -> Option<ApiKeyIdentity> {
+        let api_key = api_key?;
+        
+        match self {
+            ApiAuth::Disabled => None,
+            ApiAuth::SharedKey(expected) => constant_time_eq(expected.expose_secret().as_bytes(), api_key.as_bytes())
+                .then(|| ApiKeyIdentity {
+                    key_id: shared_key_id(api_key),
+                    capabilities: BTreeSet::from([Capability::Admin]),
+                }),
+            ApiAuth::KeyStore(store) => store.authenticate(api_key),
+        }
+    }
+    
+    /// Check that a caller may make a call needing `capability`
+    ///
+    /// Fails with `Unauthorized` without a valid key and with
+    /// `MissingCapability` when the key lacks the capability. Without a key
+    /// store only `Admin` calls are checked.
+    pub fn authorize(&self, identity: Option<&ApiKeyIdentity>, capability: Capability) -> Result<(), ContractError> {
+        if !self.is_scoped() && capability != Capability::Admin {
+            return Ok(());
+        }
+        
+        match identity {
+            None => Err(ContractError::Unauthorized),
+            Some(identity) if identity.allows(capability) => Ok(()),
+            Some(_) => Err(ContractError::MissingCapability { capability: capability.to_string() }),
+        }
+    }
+}
+
+/// Non-secret identifier of a key that is not in a key store: the first 16 hex digits of its SHA-256
+pub fn shared_key_id(api_key: &str) -> String {
+    hex::encode(sha256::Hash::hash(api_key.as_bytes()).to_byte_array())[..16].to_string()
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+use serde::Serialize;
+
+use crate::errors::{ContractError, ErrorCode};
+
+/// Maximum accepted request body size
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Header carrying the owner nonce an owner request expects
+pub const OWNER_NONCE_HEADER: &str = "x-owner-nonce";
+
+/// Header carrying the caller's identifier of a request, recorded for auditing
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Minimal HTTP/1.1 request
+#[derive(Debug, Clone, Default)]
+pub struct HttpRequest {
+    /// Request method (e.g. "GET")
+    pub method: String,
+    /// Request path without the query string
+    pub path: String,
+    /// Query string parameters
+    pub query: HashMap<String, String>,
+    /// Headers with lowercased names
+    pub headers: HashMap<String, String>,
+    /// Request body
+    pub body: Vec<u8>,
+    /// Address of the connecting peer, if known
+    pub remote_addr: Option<String>,
+}
+
+impl HttpRequest {
+    /// Create a request without headers or body
+    pub fn new(method: &str, target: &str) -> Self {
+        let (path, query) = split_target(target);
+        
+        Self {
+            method: method.to_uppercase(),
+            path,
+            query,
+            headers: HashMap::new(),
+            body: Vec::new(),
+            remote_addr: None,
+        }
+    }
+    
+    /// Parse a request from a stream
+    pub fn parse<R: BufRead>(reader: &mut R) -> Result<Self, String> {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)
+            .map_err(|e| format!("Failed to read request line: {}", e))?;
+        
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().ok_or_else(|| "Missing request method".to_string())?;
+        let target = parts.next().ok_or_else(|| "Missing request target".to_string())?;
+        
+        let mut request = Self::new(method, target);
+        
+        // Read headers until the empty line
+        loop {
+            let mut line = String::new();
+            let read = reader.read_line(&mut line)
+                .map_err(|e| format!("Failed to read header: {}", e))?;
+            
+            let line = line.trim_end();
+            if read == 0 || line.is_empty() {
+                break;
+            }
+            
+            if let Some((name, value)) = line.split_once(':') {
+                request.headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+        
+        // Read body if a length was given
+        let content_length = match request.header("content-length") {
+            Some(value) => value.parse::<usize>()
+                .map_err(|_| "Invalid Content-Length header".to_string())?,
+            None => 0,
+        };
+        
+        if content_length > MAX_BODY_SIZE {
+            return Err("Request body too large".to_string());
+        }
+        
+        request.body = vec![0; content_length];
+        reader.read_exact(&mut request.body)
+            .map_err(|e| format!("Failed to read body: {}", e))?;
+        
+        Ok(request)
+    }
+    
+    /// Get a header by case-insensitive name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(|value| value.as_str())
+    }
+    
+    /// Get the owner nonce of an owner request
+    ///
+    /// Owner endpoints require it, so a replayed request fails with
+    /// `NonceMismatch` instead of being applied twice.
+    pub fn owner_nonce(&self) -> Result<u64, HttpResponse> {
+        let value = self.header(OWNER_NONCE_HEADER)
+            .ok_or_else(|| HttpResponse::error(428, "Missing X-Owner-Nonce header"))?;
+        
+        value.parse()
+            .map_err(|_| HttpResponse::error(400, "Invalid X-Owner-Nonce header"))
+    }
+}
+
+/// Split a request target into path and query parameters
+fn split_target(target: &str) -> (String, HashMap<String, String>) {
+    let (path, query_string) = match target.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (target, ""),
+    };
+    
+    let query = query_string
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect();
+    
+    (path.to_string(), query)
+}
+
+/// Minimal HTTP/1.1 response
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: u16,
+    /// Content type
+    pub content_type: String,
+    /// Response body
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Create a JSON response
+    pub fn json<S: Serialize>(status: u16, value: &S) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => Self {
+                status,
+                content_type: "application/json".to_string(),
+                body,
+            },
+            Err(e) => Self::error(500, &format!("Failed to serialize response: {}", e)),
+        }
+    }
+    
+    /// Create a text response of a content type
+    pub fn text(status: u16, content_type: &str, body: String) -> Self {
+        Self {
+            status,
+            content_type: content_type.to_string(),
+            body: body.into_bytes(),
+        }
+    }
+    
+    /// Create a JSON error response
+    pub fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            content_type: "application/json".to_string(),
+            body: serde_json::json!({ "error": message }).to_string().into_bytes(),
+        }
+    }
+    
+    /// Create a JSON error response for a contract error, including its stable code and details
+    pub fn contract_error(error: &ContractError) -> Self {
+        let status = match error.code() {
+            ErrorCode::Unauthorized | ErrorCode::GovernanceRequired | ErrorCode::InvalidPreimage
+                | ErrorCode::InvalidImportSignature | ErrorCode::DestinationNotWhitelisted | ErrorCode::MissingCapability
+                | ErrorCode::ReadOnlyMode => 403,
+            ErrorCode::DepositNotFound | ErrorCode::ProposalNotFound | ErrorCode::ReservationNotFound
+                | ErrorCode::QuoteNotFound | ErrorCode::UnattributedFundsNotFound | ErrorCode::FeeDestinationNotProposed
+                | ErrorCode::OutboxEntryNotFound => 404,
+            ErrorCode::DepositAlreadyWithdrawn | ErrorCode::DepositLocked | ErrorCode::DepositSuspended | ErrorCode::ContractPaused
+                | ErrorCode::TokenPaused | ErrorCode::TokenNotPaused
+                | ErrorCode::EmergencyTooSoon | ErrorCode::IdempotencyConflict | ErrorCode::NonceMismatch
+                | ErrorCode::ProposalClosed | ErrorCode::AlreadyApproved
+                | ErrorCode::UtxoAlreadyDeposited | ErrorCode::InheritanceNotClaimable | ErrorCode::QuoteExpired
+                | ErrorCode::UtxoUnavailable | ErrorCode::DepositPending | ErrorCode::DepositExpired | ErrorCode::WithdrawalInFlight
+                | ErrorCode::DepositNotPending | ErrorCode::DepositVoided | ErrorCode::DepositNotVoided
+                | ErrorCode::EscrowNotConfigured
+                | ErrorCode::TreasuryPolicyNotConfigured | ErrorCode::ManifestAlreadyImported
+                | ErrorCode::MigrationKeyNotConfigured | ErrorCode::TvlMetricsNotEnabled
+                | ErrorCode::CircuitBreakerNotTripped | ErrorCode::DepositIdCollision | ErrorCode::IdSchemeLocked
+                | ErrorCode::FeeDestinationTooSoon | ErrorCode::MusigNonceReused => 409,
+            ErrorCode::EmergencyLimitReached => 429,
+            ErrorCode::BackendUnavailable | ErrorCode::BackendBusy | ErrorCode::TokenDeprecated => 503,
+            ErrorCode::RpcError | ErrorCode::TransferFailed | ErrorCode::BitcoinTestnetError
+                | ErrorCode::InitializationError | ErrorCode::ReentrancyDetected
+                | ErrorCode::StatePersistenceFailed => 500,
+            _ => 400,
+        };
+        
+        // The error's own fields let clients render the message in their language
+        let mut body = serde_json::to_value(error).unwrap_or_else(|_| serde_json::json!({ "code": error.code() }));
+        body["error"] = serde_json::json!(error.to_string());
+        body["retryable"] = serde_json::json!(error.is_retryable());
+        
+        Self {
+            status,
+            content_type: "application/json".to_string(),
+            body: body.to_string().into_bytes(),
+        }
+    }
+    
+    /// Get the reason phrase for the status code
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            428 => "Precondition Required",
+            429 => "Too Many Requests",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "Unknown",
+        }
+    }
+    
+    /// Write the response to a stream
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.reason(),
+            self.content_type,
+            self.body.len(),
+        )?;
+        writer.write_all(&self.body)?;
+        writer.flush()
+    }
+}
@@ -0,0 +1,558 @@
+//! JSON-RPC 2.0 interface to contract operations
+//!
+//! `POST /jsonrpc` takes a single request or a batch array as defined by the
+//! JSON-RPC 2.0 specification. Batch entries are answered in order, entries
+//! without an `id` are notifications and get no answer, and a batch of only
+//! notifications is answered with an empty 204 response. Params are given by
+//! name or, in field order, by position.
+//!
+//! A contract error becomes an error object whose `code` is the number of its
+//! `ErrorCode` and whose `data` is the same structured error the REST API
+//! returns. Calls are authorized by the API key in the `X-Api-Key` header
+//! through the handler's `ApiAuth`: with scoped keys every method needs a key
+//! holding its capability, and with the shared key only owner methods need
+//! it. A single call denied for a missing capability is answered with HTTP
+//! 403. Owner methods also need the current owner nonce in their `nonce` param.
+//!
+//! Withdrawals and fee withdrawals are recorded in the contract's
+//! authorization log with an HTTP context: the peer address, the ID of the
+//! API key presented, if any, and the `X-Request-Id` header, or the call's
+//! `id` without one.
+//!
+//! | Method | Capability | Params | Result |
+//! |---|---|---|---|
+//! | `vault_deposit` | deposit | `depositor`, `token_type`, `amount`, `lock_days`, `utxo_reference`? | `Deposited` event |
+//! | `vault_simulateDeposit` | deposit | `depositor`, `token_type`, `amount`, `lock_days` | deposit simulation |
+//! | `vault_predictDepositId` | deposit | `depositor`, `token_type`, `amount`, `utxo_reference`?, `idempotency_key`? | deposit ID |
+//! | `vault_withdraw` | withdraw | `caller`, `deposit_id` | `Withdrawn` event |
+//! | `vault_emergencyWithdraw` | withdraw | `caller`, `deposit_id` | `EmergencyWithdrawn` event |
+//! | `vault_getDeposit` | read_only | `deposit_id` | deposit view |
+//! | `vault_listDeposits` | read_only | deposit filter fields, all optional | deposits by ID |
+//! | `vault_listDepositSummaries` | read_only | `filter`?, `sort`?, `offset`?, `limit`?, `after`? | page of deposit summaries |
+//! | `vault_getDepositDetails` | read_only | `deposit_ids` | deposit views, unknown IDs left out |
+//! | `vault_getStats` | read_only | none | contract statistics |
+//! | `vault_withdrawFees` (owner) | admin | `token_type`, `amount`?, `destination`?, `nonce` | `FeeCollected` event |
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+
+use crate::bitcoin::utxo::UtxoRef;
+use crate::config::Secret;
+use crate::contract::authorization::OperationContext;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::query::DepositFilter;
+use crate::contract::summary::{DepositCursor, SortKey};
+use crate::errors::{ContractError, ErrorCode};
+use crate::models::{Deposit, TokenTransfer, TokenType};
+use crate::server::auth::{shared_key_id, ApiAuth, ApiKeyIdentity, Capability};
+use crate::server::http::{HttpRequest, HttpResponse, REQUEST_ID_HEADER};
+
+/// Header carrying the API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Invalid JSON was received
+pub const PARSE_ERROR: i64 = -32700;
+
+/// The JSON is not a valid request object
+pub const INVALID_REQUEST: i64 = -32600;
+
+/// The method does not exist
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Invalid method params
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// Internal JSON-RPC error
+pub const INTERNAL_ERROR: i64 = -32603;
+
+/// Serves JSON-RPC requests for the HTTP API
+pub trait JsonRpcEndpoint: fmt::Debug + Send + Sync {
+    /// Answer a `POST /jsonrpc` request
+    fn handle_http(&self, request: &HttpRequest) -> HttpResponse;
+}
+
+/// JSON-RPC error object
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    /// Error code
+    pub code: i64,
+    /// Short description
+    pub message: String,
+    /// Structured details
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    /// Create an error without details
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+    
+    /// Encode as a JSON-RPC error object
+    fn to_value(&self) -> Value {
+        let mut error = json!({
+            "code": self.code,
+            "message": self.message,
+        });
+        
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        
+        error
+    }
+}
+
+impl From<ContractError> for RpcError {
+    fn from(error: ContractError) -> Self {
+        Self {
+            code: error.code().number(),
+            message: error.to_string(),
+            data: serde_json::to_value(&error).ok(),
+        }
+    }
+}
+
+/// Params of `vault_deposit`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DepositParams {
+    /// Depositor address
+    depositor: String,
+    /// Token type
+    token_type: TokenType,
+    /// Amount to deposit
+    amount: u64,
+    /// Lock period in days
+    lock_days: u32,
+    /// Funding UTXO as `txid:vout`
+    #[serde(default)]
+    utxo_reference: Option<String>,
+}
+
+/// Params of `vault_simulateDeposit`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SimulateDepositParams {
+    /// Depositor address
+    depositor: String,
+    /// Token type
+    token_type: TokenType,
+    /// Amount to deposit
+    amount: u64,
+    /// Lock period in days
+    lock_days: u32,
+}
+
+/// Params of `vault_predictDepositId`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PredictDepositIdParams {
+    /// Depositor address
+    depositor: String,
+    /// Token type
+    token_type: TokenType,
+    /// Amount to deposit
+    amount: u64,
+    /// Funding UTXO as `txid:vout`
+    #[serde(default)]
+    utxo_reference: Option<String>,
+    /// Idempotency key the deposit will be made with
+    #[serde(default)]
+    idempotency_key: Option<String>,
+}
+
+/// Params of `vault_withdraw` and `vault_emergencyWithdraw`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WithdrawParams {
+    /// Caller address
+    caller: String,
+    /// Deposit to withdraw
+    deposit_id: u64,
+}
+
+/// Params of `vault_getDeposit`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GetDepositParams {
+    /// Deposit to look up
+    deposit_id: u64,
+}
+
+/// Params of `vault_listDepositSummaries`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ListDepositSummariesParams {
+    /// Deposits to list, all if omitted
+    #[serde(default)]
+    filter: DepositFilter,
+    /// Order to list them in, by unlock time if omitted
+    #[serde(default)]
+    sort: SortKey,
+    /// Matching deposits to skip
+    #[serde(default)]
+    offset: usize,
+    /// Most summaries to return
+    #[serde(default = "default_summary_page_size")]
+    limit: usize,
+    /// Cursor of a previous page to continue after, overriding `sort` and `offset`
+    #[serde(default)]
+    after: Option<DepositCursor>,
+}
+
+/// Summaries `vault_listDepositSummaries` returns without a `limit`
+fn default_summary_page_size() -> usize {
+    50
+}
+
+/// Params of `vault_getDepositDetails`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GetDepositDetailsParams {
+    /// Deposits to look up
+    deposit_ids: Vec<u64>,
+}
+
+/// Params of `vault_withdrawFees`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WithdrawFeesParams {
+    /// Token type of the fees
+    token_type: TokenType,
+    /// Amount to withdraw, all collected fees if omitted
+    #[serde(default)]
+    amount: Option<u64>,
+    /// Destination, the fee collector if omitted
+    #[serde(default)]
+    destination: Option<String>,
+    /// Current owner nonce
+    nonce: u64,
+}
+
+/// JSON-RPC handler calling into a shared contract
+pub struct JsonRpcHandler<T: TokenTransfer> {
+    /// Contract the methods operate on
+    contract: Arc<Mutex<TimeLockedDeposit<T>>>,
+    /// Resolves API keys to the capabilities methods need
+    auth: ApiAuth,
+}
+
+impl<T: TokenTransfer> fmt::Debug for JsonRpcHandler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonRpcHandler")
+            .field("auth", &self.auth)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: TokenTransfer> JsonRpcHandler<T> {
+    /// Create a handler for a shared contract whose owner methods need `api_key`
+    pub fn new(contract: Arc<Mutex<TimeLockedDeposit<T>>>, api_key: Option<Secret<String>>) -> Self {
+        Self { contract, auth: ApiAuth::shared(api_key) }
+    }
+    
+    /// Authorize calls with `auth` instead, e.g. with scoped keys
+    pub fn with_auth(mut self, auth: ApiAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+    
+    /// Answer a request body, `None` if it held only notifications
+    pub fn handle_body(&self, body: &[u8], api_key: Option<&str>) -> Option<Value> {
+        let identity = self.auth.authenticate(api_key);
+        self.handle_body_with_context(body, api_key, http_context(None, api_key, identity.as_ref(), None))
+    }
+    
+    /// Answer a request body, recording `context` for the withdrawals it makes
+    /// 
+    /// Calls without a request ID in `context` are recorded with their own `id`.
+    pub fn handle_body_with_context(&self, body: &[u8], api_key: Option<&str>, context: OperationContext) -> Option<Value> {
+        let identity = self.auth.authenticate(api_key);
+        let identity = identity.as_ref();
+        
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return Some(error_response(Value::Null, &RpcError::new(PARSE_ERROR, format!("Parse error: {}", e)))),
+        };
+        
+        match request {
+            Value::Array(calls) if calls.is_empty() => {
+                Some(error_response(Value::Null, &RpcError::new(INVALID_REQUEST, "Invalid Request: empty batch")))
+            },
+            Value::Array(calls) => {
+                let responses: Vec<Value> = calls.into_iter()
+                    .filter_map(|call| self.handle_call(call, identity, &context))
+                    .collect();
+                
+                // A batch of notifications is answered with nothing at all
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Value::Array(responses))
+                }
+            },
+            call => self.handle_call(call, identity, &context),
+        }
+    }
+    
+    /// Answer a single call, `None` for a notification
+    fn handle_call(&self, call: Value, identity: Option<&ApiKeyIdentity>, context: &OperationContext) -> Option<Value> {
+        let mut call = match call {
+            Value::Object(call) => call,
+            _ => return Some(error_response(Value::Null, &RpcError::new(INVALID_REQUEST, "Invalid Request: not an object"))),
+        };
+        
+        // Only string, number and null IDs are valid
+        let id = match call.remove("id") {
+            None => None,
+            Some(id @ (Value::String(_) | Value::Number(_) | Value::Null)) => Some(id),
+            Some(_) => return Some(error_response(Value::Null, &RpcError::new(INVALID_REQUEST, "Invalid Request: invalid id"))),
+        };
+        let response_id = id.clone().unwrap_or(Value::Null);
+        
+        if call.get("jsonrpc") != Some(&Value::from("2.0")) {
+            return Some(error_response(response_id, &RpcError::new(INVALID_REQUEST, "Invalid Request: jsonrpc must be \"2.0\"")));
+        }
+        
+        let method = match call.remove("method") {
+            Some(Value::String(method)) => method,
+            _ => return Some(error_response(response_id, &RpcError::new(INVALID_REQUEST, "Invalid Request: missing method"))),
+        };
+        
+        let params = match call.remove("params") {
+            None => Value::Object(Map::new()),
+            Some(params @ (Value::Object(_) | Value::Array(_))) => params,
+            Some(_) => return Some(error_response(response_id, &RpcError::new(INVALID_REQUEST, "Invalid Request: params must be an object or array"))),
+        };
+        
+        let mut context = context.clone();
+        if context.request_id.is_none() {
+            context.request_id = id.as_ref().and_then(request_id_of);
+        }
+        
+        let outcome = self.dispatch(&method, params, identity, context);
+        
+        // Notifications run but are never answered, errors included
+        id.as_ref()?;
+        
+        Some(match outcome {
+            Ok(result) => json!({
+                "jsonrpc": "2.0",
+                "result": result,
+                "id": response_id,
+            }),
+            Err(error) => error_response(response_id, &error),
+        })
+    }
+    
+    /// Call a method, if the caller holds the capability it needs
+    fn dispatch(&self, method: &str, params: Value, identity: Option<&ApiKeyIdentity>, context: OperationContext) -> Result<Value, RpcError> {
+        let capability = method_capability(method)
+            .ok_or_else(|| RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method)))?;
+        self.auth.authorize(identity, capability)?;
+        
+        match method {
+            "vault_deposit" => {
+                let params: DepositParams = parse_params(params)?;
+                let utxo_reference = params.utxo_reference.as_deref().map(str::parse::<UtxoRef>).transpose()?;
+                let event = self.lock()?.deposit(
+                    params.depositor,
+                    params.token_type,
+                    params.amount,
+                    params.lock_days,
+                    utxo_reference,
+                )?;
+                to_result(&event)
+            },
+            "vault_simulateDeposit" => {
+                let params: SimulateDepositParams = parse_params(params)?;
+                let simulation = self.lock()?.simulate_deposit(
+                    &params.depositor,
+                    &params.token_type,
+                    params.amount,
+                    params.lock_days,
+                )?;
+                to_result(&simulation)
+            },
+            "vault_predictDepositId" => {
+                let params: PredictDepositIdParams = parse_params(params)?;
+                let utxo_reference = params.utxo_reference.as_deref().map(str::parse::<UtxoRef>).transpose()?;
+                let deposit_id = self.lock()?.predict_deposit_id(
+                    &params.depositor,
+                    &params.token_type,
+                    params.amount,
+                    utxo_reference.as_ref(),
+                    params.idempotency_key.as_deref(),
+                )?;
+                to_result(&deposit_id)
+            },
+            "vault_withdraw" => {
+                let params: WithdrawParams = parse_params(params)?;
+                let event = self.lock()?.withdraw_with_context(params.caller, params.deposit_id, context)?;
+                to_result(&event)
+            },
+            "vault_emergencyWithdraw" => {
+                let params: WithdrawParams = parse_params(params)?;
+                let event = self.lock()?.emergency_withdraw_with_context(params.caller, params.deposit_id, context)?;
+                to_result(&event)
+            },
+            "vault_getDeposit" => {
+                let params: GetDepositParams = parse_params(params)?;
+                let view = self.lock()?.get_deposit_view(params.deposit_id)?;
+                to_result(&view)
+            },
+            "vault_listDeposits" => {
+                let filter: DepositFilter = parse_params(params)?;
+                let contract = self.lock()?;
+                let mut deposits: Vec<&Deposit> = contract.deposits_filtered(filter).collect();
+                deposits.sort_by_key(|deposit| deposit.deposit_id);
+                to_result(&deposits)
+            },
+            "vault_listDepositSummaries" => {
+                let params: ListDepositSummariesParams = parse_params(params)?;
+                let contract = self.lock()?;
+                let page = match params.after {
+                    Some(cursor) => contract.list_deposit_summaries_after(params.filter, cursor, params.limit),
+                    None => contract.list_deposit_summaries(params.filter, params.sort, params.offset, params.limit),
+                };
+                to_result(&page)
+            },
+            "vault_getDepositDetails" => {
+                let params: GetDepositDetailsParams = parse_params(params)?;
+                to_result(&self.lock()?.get_deposit_details(&params.deposit_ids))
+            },
+            "vault_getStats" => {
+                if !is_empty_params(&params) {
+                    return Err(RpcError::new(INVALID_PARAMS, "Invalid params: vault_getStats takes no params"));
+                }
+                to_result(&self.lock()?.get_stats())
+            },
+            "vault_withdrawFees" => {
+                let params: WithdrawFeesParams = parse_params(params)?;
+                
+                // The API key stands in for the owner's signature
+                let mut contract = self.lock()?;
+                let owner = contract.contract_owner_address.clone();
+                let event = contract.withdraw_fees_with_context(
+                    owner,
+                    params.token_type,
+                    params.amount,
+                    params.destination,
+                    Some(params.nonce),
+                    context,
+                )?;
+                to_result(&event)
+            },
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+        }
+    }
+    
+    /// Lock the contract
+    fn lock(&self) -> Result<MutexGuard<'_, TimeLockedDeposit<T>>, RpcError> {
+        self.contract.lock()
+            .map_err(|_| RpcError::new(INTERNAL_ERROR, "Internal error: contract lock poisoned"))
+    }
+}
+
+impl<T: TokenTransfer + Send> JsonRpcEndpoint for JsonRpcHandler<T> {
+    fn handle_http(&self, request: &HttpRequest) -> HttpResponse {
+        let api_key = request.header(API_KEY_HEADER);
+        let identity = self.auth.authenticate(api_key);
+        let context = http_context(request.remote_addr.as_deref(), api_key, identity.as_ref(), request.header(REQUEST_ID_HEADER));
+        
+        match self.handle_body_with_context(&request.body, api_key, context) {
+            Some(response) => HttpResponse::json(http_status(&response), &response),
+            None => HttpResponse {
+                status: 204,
+                content_type: "application/json".to_string(),
+                body: Vec::new(),
+            },
+        }
+    }
+}
+
+/// HTTP status of an answer: 403 for a single call the API key lacks the capability for, else 200
+/// 
+/// Batches always answer 200, since their calls can fail for different reasons.
+fn http_status(response: &Value) -> u16 {
+    let denied = response.pointer("/error/data/code")
+        .and_then(Value::as_str)
+        .is_some_and(|code| code == ErrorCode::MissingCapability.as_str());
+    
+    if denied { 403 } else { 200 }
+}
+
+/// Capability a method needs, `None` for an unknown method
+fn method_capability(method: &str) -> Option<Capability> {
+    match method {
+        "vault_deposit" | "vault_simulateDeposit" | "vault_predictDepositId" => Some(Capability::Deposit),
+        "vault_withdraw" | "vault_emergencyWithdraw" => Some(Capability::Withdraw),
+        "vault_getDeposit" | "vault_listDeposits" | "vault_listDepositSummaries" | "vault_getDepositDetails" | "vault_getStats" => Some(Capability::ReadOnly),
+        "vault_withdrawFees" => Some(Capability::Admin),
+        _ => None,
+    }
+}
+
+/// Build the context of a request received over HTTP
+/// 
+/// The API key is recorded by the ID of the key it resolved to, or else only
+/// as the first 16 hex digits of its SHA-256.
+fn http_context(remote_addr: Option<&str>, api_key: Option<&str>, identity: Option<&ApiKeyIdentity>, request_id: Option<&str>) -> OperationContext {
+    let api_key_id = match identity {
+        Some(identity) => Some(identity.key_id.clone()),
+        None => api_key.map(shared_key_id),
+    };
+    let context = OperationContext::http(remote_addr.unwrap_or("unknown"), api_key_id);
+    
+    match request_id {
+        Some(request_id) => context.with_request_id(request_id),
+        None => context,
+    }
+}
+
+/// Get the request ID of a call's `id`, `None` for a null ID
+fn request_id_of(id: &Value) -> Option<String> {
+    match id {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// Build an error response
+fn error_response(id: Value, error: &RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "error": error.to_value(),
+        "id": id,
+    })
+}
+
+/// Decode method params, by name or by position
+fn parse_params<P: DeserializeOwned>(params: Value) -> Result<P, RpcError> {
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+/// Whether params are absent or empty
+fn is_empty_params(params: &Value) -> bool {
+    match params {
+        Value::Object(params) => params.is_empty(),
+        Value::Array(params) => params.is_empty(),
+        _ => false,
+    }
+}
+
+/// Encode a method result
+fn to_result<R: serde::Serialize>(result: &R) -> Result<Value, RpcError> {
+    serde_json::to_value(result)
+        .map_err(|e| RpcError::new(INTERNAL_ERROR, format!("Internal error: {}", e)))
+}
@@ -0,0 +1,511 @@
+//! HTTP API for the time-locked deposit contract
+//! 
+//! This module contains a small dependency-free HTTP/1.1 server exposing
+//! operational endpoints such as `/health`, `/stats/reconciliation`,
+//! `/stats/tvl` and `/stats/forecast`, monthly depositor statements at `/statements`,
+//! owner-authenticated runtime diagnostics at `/diagnostics` and log levels
+//! at `/log-level`, background jobs at `/jobs`, and contract operations over
+//! JSON-RPC 2.0 at `/jsonrpc`.
+//!
+//! Callers are authorized through an `ApiAuth`. `/stats/*`, `/statements`
+//! and reading `/jobs` need the `read_only` capability once scoped keys are
+//! configured, and `/diagnostics`, `/log-level` and starting or cancelling
+//! jobs always need `admin`.
+//!
+//! Jobs are long-running operations the server started in the background:
+//! `POST /jobs/utxo-audit` audits every deposit's UTXO, `GET /jobs/<id>`
+//! reports its progress and outcome and `POST /jobs/<id>/cancel` stops it.
+//!
+//! A process hosting several vaults mounts a server per vault with
+//! `with_vault`; `/vaults/<name>/jsonrpc` and the like go to the server of
+//! the vault `name`, with its own contract, statements and keys.
+
+pub mod auth;
+pub mod http;
+pub mod jsonrpc;
+
+use std::collections::BTreeMap;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use log::{debug, info, warn};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+
+use crate::bitcoin::health::HealthChecker;
+use crate::contract::audit::ReconciliationReport;
+use crate::contract::forecast::{check_forecast_params, ForecastSource};
+use crate::contract::utxo_audit::UtxoAuditSource;
+use crate::errors::ContractError;
+use crate::introspection::DiagnosticsSource;
+use crate::models::TokenType;
+use crate::progress::JobRegistry;
+use crate::statements::{StatementPeriod, StatementSource};
+use crate::tvl::MetricsRecorder;
+use crate::logging::{parse_level, LogLevels, DAEMON};
+
+pub use auth::{ApiAuth, ApiKeyIdentity, ApiKeyRecord, ApiKeyStore, Capability};
+pub use http::{HttpRequest, HttpResponse, OWNER_NONCE_HEADER, REQUEST_ID_HEADER};
+pub use jsonrpc::{JsonRpcEndpoint, JsonRpcHandler, API_KEY_HEADER};
+
+/// HTTP API server
+#[derive(Debug, Default)]
+pub struct ApiServer {
+    /// Backend health checker
+    health_checker: Option<Arc<HealthChecker>>,
+    /// Latest reconciliation report, refreshed by the daemon
+    reconciliation_report: Option<Arc<Mutex<Option<ReconciliationReport>>>>,
+    /// JSON-RPC interface to contract operations
+    jsonrpc: Option<Arc<dyn JsonRpcEndpoint>>,
+    /// Recorder of the contract's TVL series
+    tvl_recorder: Option<Arc<MetricsRecorder>>,
+    /// Source of the runtime diagnostics served at `/diagnostics`
+    diagnostics: Option<Arc<dyn DiagnosticsSource>>,
+    /// Levels of the process logger, adjusted at `/log-level`
+    log_levels: Option<Arc<LogLevels>>,
+    /// Source of the statements served at `/statements`
+    statements: Option<Arc<dyn StatementSource>>,
+    /// Source of the liquidity forecasts served at `/stats/forecast`
+    forecasts: Option<Arc<dyn ForecastSource>>,
+    /// Background jobs served at `/jobs`
+    jobs: Option<Arc<JobRegistry>>,
+    /// Source of the UTXO audits started at `/jobs/utxo-audit`
+    utxo_audits: Option<Arc<dyn UtxoAuditSource>>,
+    /// Resolves API keys to the capabilities endpoints need
+    auth: ApiAuth,
+    /// Servers of the vaults mounted under `/vaults/<name>`, by name
+    vaults: BTreeMap<String, ApiServer>,
+}
+
+impl ApiServer {
+    /// Create a new API server
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Attach a health checker for the `/health` endpoint
+    pub fn with_health_checker(mut self, health_checker: Arc<HealthChecker>) -> Self {
+        self.health_checker = Some(health_checker);
+        self
+    }
+    
+    /// Attach the shared slot holding the latest reconciliation report
+    pub fn with_reconciliation_report(mut self, report: Arc<Mutex<Option<ReconciliationReport>>>) -> Self {
+        self.reconciliation_report = Some(report);
+        self
+    }
+    
+    /// Attach the recorder whose series `/stats/tvl` serves
+    pub fn with_tvl_recorder(mut self, recorder: Arc<MetricsRecorder>) -> Self {
+        self.tvl_recorder = Some(recorder);
+        self
+    }
+    
+    /// Serve runtime diagnostics at `/diagnostics` to callers holding the `admin` capability
+    pub fn with_diagnostics(mut self, source: Arc<dyn DiagnosticsSource>) -> Self {
+        self.diagnostics = Some(source);
+        self
+    }
+    
+    /// Let callers holding the `admin` capability read and adjust log levels at `/log-level`
+    pub fn with_log_levels(mut self, log_levels: Arc<LogLevels>) -> Self {
+        self.log_levels = Some(log_levels);
+        self
+    }
+    
+    /// Serve monthly depositor statements at `/statements`
+    pub fn with_statements(mut self, source: Arc<dyn StatementSource>) -> Self {
+        self.statements = Some(source);
+        self
+    }
+    
+    /// Serve liquidity forecasts at `/stats/forecast`
+    pub fn with_forecasts(mut self, source: Arc<dyn ForecastSource>) -> Self {
+        self.forecasts = Some(source);
+        self
+    }
+    
+    /// Serve background jobs at `/jobs`, starting UTXO audits from `utxo_audits`
+    pub fn with_jobs(mut self, jobs: Arc<JobRegistry>, utxo_audits: Arc<dyn UtxoAuditSource>) -> Self {
+        self.jobs = Some(jobs);
+        self.utxo_audits = Some(utxo_audits);
+        self
+    }
+    
+    /// Authorize callers of the REST endpoints with `auth`
+    pub fn with_auth(mut self, auth: ApiAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+    
+    /// Serve contract operations over JSON-RPC at `/jsonrpc`
+    pub fn with_jsonrpc(mut self, endpoint: Arc<dyn JsonRpcEndpoint>) -> Self {
+        self.jsonrpc = Some(endpoint);
+        self
+    }
+    
+    /// Serve the endpoints of `server` under `/vaults/<name>`
+    pub fn with_vault(mut self, name: impl Into<String>, server: ApiServer) -> Self {
+        self.vaults.insert(name.into(), server);
+        self
+    }
+    
+    /// Route a request to its handler
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        // Requests under a vault's prefix go to that vault's server
+        if let Some(rest) = request.path.strip_prefix("/vaults/") {
+            let (name, path) = rest.split_once('/').unwrap_or((rest, ""));
+            return match self.vaults.get(name) {
+                Some(server) => {
+                    let mut request = request.clone();
+                    request.path = format!("/{}", path);
+                    server.handle(&request)
+                },
+                None => HttpResponse::error(404, &format!("Unknown vault: {}", name)),
+            };
+        }
+        
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => self.handle_health(),
+            (_, "/health") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/stats/reconciliation") => self.authorized(request, Capability::ReadOnly, || self.handle_reconciliation()),
+            (_, "/stats/reconciliation") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/stats/tvl") => self.authorized(request, Capability::ReadOnly, || self.handle_tvl_series(request)),
+            (_, "/stats/tvl") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/stats/forecast") => self.authorized(request, Capability::ReadOnly, || self.handle_forecast(request)),
+            (_, "/stats/forecast") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/statements") => self.authorized(request, Capability::ReadOnly, || self.handle_statement(request)),
+            (_, "/statements") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/diagnostics") => self.authorized(request, Capability::Admin, || self.handle_diagnostics()),
+            (_, "/diagnostics") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/log-level") => self.authorized(request, Capability::Admin, || self.handle_log_levels()),
+            ("PUT", "/log-level") => self.authorized(request, Capability::Admin, || self.handle_set_log_level(request)),
+            (_, "/log-level") => HttpResponse::error(405, "Method not allowed"),
+            ("GET", "/jobs") => self.authorized(request, Capability::ReadOnly, || self.handle_job_list()),
+            ("POST", "/jobs/utxo-audit") => self.authorized(request, Capability::Admin, || self.handle_start_utxo_audit(request)),
+            (_, "/jobs") | (_, "/jobs/utxo-audit") => HttpResponse::error(405, "Method not allowed"),
+            (method, path) if path.starts_with("/jobs/") => self.handle_job(method, request),
+            ("POST", "/jsonrpc") => match &self.jsonrpc {
+                Some(endpoint) => endpoint.handle_http(request),
+                None => HttpResponse::error(404, "Not found"),
+            },
+            (_, "/jsonrpc") => HttpResponse::error(405, "Method not allowed"),
+            _ => HttpResponse::error(404, "Not found"),
+        }
+    }
+    
+    /// Report backend health, answering 503 when any backend is down
+    fn handle_health(&self) -> HttpResponse {
+        let components = self.health_checker.as_ref()
+            .map(|checker| checker.health_report())
+            .unwrap_or_default();
+        
+        let healthy = components.iter().all(|status| status.healthy);
+        let status = if healthy { 200 } else { 503 };
+        
+        HttpResponse::json(status, &json!({
+            "healthy": healthy,
+            "components": components,
+        }))
+    }
+    
+    /// Return the latest reconciliation report
+    fn handle_reconciliation(&self) -> HttpResponse {
+        let report = self.reconciliation_report.as_ref()
+            .and_then(|report| report.lock().ok().and_then(|report| report.clone()));
+        
+        match report {
+            Some(report) => HttpResponse::json(200, &report),
+            None => HttpResponse::error(404, "No reconciliation report available"),
+        }
+    }
+    
+    /// Return a token's TVL series
+    /// 
+    /// Query parameters: `token` (default `Bitcoin`), `from` and `to` as Unix
+    /// seconds (default the last day) and `resolution` in seconds (default
+    /// the finest recorded).
+    fn handle_tvl_series(&self, request: &HttpRequest) -> HttpResponse {
+        let recorder = match &self.tvl_recorder {
+            Some(recorder) => recorder,
+            None => return HttpResponse::contract_error(&ContractError::TvlMetricsNotEnabled),
+        };
+        
+        let token: TokenType = match request.query.get("token").map(|token| token.parse()).unwrap_or(Ok(TokenType::Bitcoin)) {
+            Ok(token) => token,
+            Err(e) => return HttpResponse::error(400, &e),
+        };
+        
+        let timestamp = |name: &str| request.query.get(name)
+            .map(|value| value.parse::<i64>().ok().and_then(|secs| DateTime::from_timestamp(secs, 0)))
+            .map(|timestamp| timestamp.ok_or_else(|| HttpResponse::error(400, &format!("Invalid {} timestamp", name))))
+            .transpose();
+        
+        let (from, to) = match (timestamp("from"), timestamp("to")) {
+            (Ok(from), Ok(to)) => {
+                let to = to.unwrap_or_else(Utc::now);
+                (from.unwrap_or(to - chrono::Duration::days(1)), to)
+            },
+            (Err(response), _) | (_, Err(response)) => return response,
+        };
+        
+        let default_resolution = recorder.config().tiers.iter().map(|tier| tier.resolution_secs).min().unwrap_or(60);
+        let resolution = match request.query.get("resolution").map(|value| value.parse::<u64>()).unwrap_or(Ok(default_resolution)) {
+            Ok(resolution) => resolution,
+            Err(_) => return HttpResponse::error(400, "Invalid resolution"),
+        };
+        
+        match recorder.get_tvl_series(&token, from, to, resolution) {
+            Ok(series) => HttpResponse::json(200, &json!({
+                "token": token,
+                "resolution_secs": resolution,
+                "samples": series,
+            })),
+            Err(e) => HttpResponse::contract_error(&e),
+        }
+    }
+    
+    /// Return the liquidity forecast of the hot wallet
+    /// 
+    /// Query parameters: `weeks` (default 12) and `emergency_rate_bps`, the
+    /// share of the locked balance assumed to be withdrawn in an emergency
+    /// each week (default 0).
+    fn handle_forecast(&self, request: &HttpRequest) -> HttpResponse {
+        let source = match &self.forecasts {
+            Some(source) => source,
+            None => return HttpResponse::error(404, "No liquidity forecasts available"),
+        };
+        
+        let param = |name: &str, default: u32| request.query.get(name)
+            .map(|value| value.parse::<u32>().map_err(|_| HttpResponse::error(400, &format!("Invalid {}", name))))
+            .unwrap_or(Ok(default));
+        let (horizon_weeks, emergency_rate_bps) = match (param("weeks", 12), param("emergency_rate_bps", 0)) {
+            (Ok(weeks), Ok(rate)) => (weeks, rate),
+            (Err(response), _) | (_, Err(response)) => return response,
+        };
+        if let Err(e) = check_forecast_params(horizon_weeks, emergency_rate_bps) {
+            return HttpResponse::contract_error(&e);
+        }
+        
+        match source.liquidity_forecast(horizon_weeks, emergency_rate_bps) {
+            Ok(forecast) => HttpResponse::json(200, &forecast),
+            Err(e) => HttpResponse::error(500, &e),
+        }
+    }
+    
+    /// Return the statement of an address for a month
+    /// 
+    /// Query parameters: `address`, `month` as `YYYY-MM` and `format`,
+    /// `json` (default) or `markdown`.
+    fn handle_statement(&self, request: &HttpRequest) -> HttpResponse {
+        let source = match &self.statements {
+            Some(source) => source,
+            None => return HttpResponse::error(404, "Statements need the event log"),
+        };
+        
+        let address = match request.query.get("address") {
+            Some(address) if !address.is_empty() => address,
+            _ => return HttpResponse::error(400, "Missing address"),
+        };
+        let period = match request.query.get("month").map(|month| month.parse::<StatementPeriod>()) {
+            Some(Ok(period)) => period,
+            Some(Err(e)) => return HttpResponse::error(400, &e),
+            None => return HttpResponse::error(400, "Missing month"),
+        };
+        
+        let statement = match source.generate_statement(address, period.year, period.month) {
+            Ok(statement) => statement,
+            Err(e) => return HttpResponse::error(500, &e),
+        };
+        
+        match request.query.get("format").map(String::as_str) {
+            None | Some("json") => HttpResponse::json(200, &statement),
+            Some("markdown") => HttpResponse::text(200, "text/markdown; charset=utf-8", statement.to_markdown()),
+            Some(other) => HttpResponse::error(400, &format!("Unknown format: {}", other)),
+        }
+    }
+    
+    /// Answer with `handler` if the caller's API key holds `capability`
+    /// 
+    /// Callers without a valid key get 401 and those lacking the capability
+    /// 403, both with the structured error.
+    fn authorized(&self, request: &HttpRequest, capability: Capability, handler: impl FnOnce() -> HttpResponse) -> HttpResponse {
+        let identity = self.auth.authenticate(request.header(API_KEY_HEADER));
+        
+        match self.auth.authorize(identity.as_ref(), capability) {
+            Ok(()) => handler(),
+            Err(error) => {
+                let mut response = HttpResponse::contract_error(&error);
+                if matches!(error, ContractError::Unauthorized) {
+                    response.status = 401;
+                }
+                response
+            },
+        }
+    }
+    
+    /// Return the runtime diagnostics
+    fn handle_diagnostics(&self) -> HttpResponse {
+        match &self.diagnostics {
+            Some(source) => HttpResponse::json(200, &source.diagnostics()),
+            None => HttpResponse::error(404, "Not found"),
+        }
+    }
+    
+    /// Return the level of every log target
+    fn handle_log_levels(&self) -> HttpResponse {
+        match &self.log_levels {
+            Some(log_levels) => HttpResponse::json(200, &json!({ "levels": log_levels.levels() })),
+            None => HttpResponse::error(404, "Not found"),
+        }
+    }
+    
+    /// Change the level of a log target
+    /// 
+    /// Body: `{"target": "rpc", "level": "debug"}`. Answers with the levels
+    /// of every target, or 400 for an unknown target or level.
+    fn handle_set_log_level(&self, request: &HttpRequest) -> HttpResponse {
+        let log_levels = match &self.log_levels {
+            Some(log_levels) => log_levels,
+            None => return HttpResponse::error(404, "Not found"),
+        };
+        
+        let body: serde_json::Value = match serde_json::from_slice(&request.body) {
+            Ok(body) => body,
+            Err(e) => return HttpResponse::error(400, &format!("Invalid body: {}", e)),
+        };
+        let (target, level) = match (body["target"].as_str(), body["level"].as_str()) {
+            (Some(target), Some(level)) => (target, level),
+            _ => return HttpResponse::error(400, "Body needs a target and a level"),
+        };
+        
+        match parse_level(level).and_then(|level| log_levels.set_level(target, level)) {
+            Ok(()) => {
+                info!(target: DAEMON, "Log level of {} set to {}", target, log_levels.level(target));
+                HttpResponse::json(200, &json!({ "levels": log_levels.levels() }))
+            },
+            Err(e) => HttpResponse::error(400, &e),
+        }
+    }
+    
+    /// List every remembered job
+    fn handle_job_list(&self) -> HttpResponse {
+        match &self.jobs {
+            Some(jobs) => HttpResponse::json(200, &json!({ "jobs": jobs.list() })),
+            None => HttpResponse::error(404, "Not found"),
+        }
+    }
+    
+    /// Start a UTXO audit of every deposit in the background
+    /// 
+    /// Query parameter: `batch_size`, the deposits checked per contract lock
+    /// (default 100). Answers 202 with the job's ID.
+    fn handle_start_utxo_audit(&self, request: &HttpRequest) -> HttpResponse {
+        let (jobs, source) = match (&self.jobs, &self.utxo_audits) {
+            (Some(jobs), Some(source)) => (jobs, source.clone()),
+            _ => return HttpResponse::error(404, "Not found"),
+        };
+        
+        let batch_size = match request.query.get("batch_size").map(|value| value.parse::<usize>()).unwrap_or(Ok(100)) {
+            Ok(batch_size) if batch_size > 0 => batch_size,
+            _ => return HttpResponse::error(400, "Invalid batch_size"),
+        };
+        
+        let id = jobs.spawn("utxo_audit", move |progress| {
+            let summary = source.audit_all(batch_size, progress)?;
+            serde_json::to_value(summary).map_err(|e| ContractError::InitializationError(e.to_string()))
+        });
+        
+        HttpResponse::json(202, &json!({ "id": id }))
+    }
+    
+    /// Report a job at `GET /jobs/<id>` or cancel it at `POST /jobs/<id>/cancel`
+    fn handle_job(&self, method: &str, request: &HttpRequest) -> HttpResponse {
+        let jobs = match &self.jobs {
+            Some(jobs) => jobs,
+            None => return HttpResponse::error(404, "Not found"),
+        };
+        
+        let rest = request.path.trim_start_matches("/jobs/");
+        let (id, action) = rest.split_once('/').unwrap_or((rest, ""));
+        let id = match id.parse::<u64>() {
+            Ok(id) => id,
+            Err(_) => return HttpResponse::error(404, "Not found"),
+        };
+        
+        match (method, action) {
+            ("GET", "") => self.authorized(request, Capability::ReadOnly, || match jobs.status(id) {
+                Some(status) => HttpResponse::json(200, &status),
+                None => HttpResponse::error(404, &format!("Unknown job: {}", id)),
+            }),
+            ("POST", "cancel") => self.authorized(request, Capability::Admin, || {
+                if jobs.cancel(id) {
+                    HttpResponse::json(202, &json!({ "id": id, "cancelling": true }))
+                } else if jobs.status(id).is_some() {
+                    HttpResponse::error(409, &format!("Job {} is not running", id))
+                } else {
+                    HttpResponse::error(404, &format!("Unknown job: {}", id))
+                }
+            }),
+            (_, "") | (_, "cancel") => HttpResponse::error(405, "Method not allowed"),
+            _ => HttpResponse::error(404, "Not found"),
+        }
+    }
+    
+    /// Serve a single connection
+    fn handle_connection(&self, stream: TcpStream) {
+        let mut reader = BufReader::new(match stream.try_clone() {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!(target: DAEMON, "Failed to clone connection: {}", e);
+                return;
+            }
+        });
+        
+        let response = match HttpRequest::parse(&mut reader) {
+            Ok(mut request) => {
+                request.remote_addr = stream.peer_addr().ok().map(|addr| addr.to_string());
+                debug!(target: DAEMON, "{} {}", request.method, request.path);
+                self.handle(&request)
+            },
+            Err(e) => HttpResponse::error(400, &e),
+        };
+        
+        let mut stream = stream;
+        if let Err(e) = response.write_to(&mut stream) {
+            warn!(target: DAEMON, "Failed to write response: {}", e);
+        }
+    }
+    
+    /// Listen on an address and serve requests until the listener fails
+    pub fn serve(self: Arc<Self>, bind_addr: &str) -> Result<(), ContractError> {
+        let listener = TcpListener::bind(bind_addr)
+            .map_err(|e| ContractError::InitializationError(format!("Failed to bind {}: {}", bind_addr, e)))?;
+        
+        info!(target: DAEMON, "HTTP API listening on {}", bind_addr);
+        
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = self.clone();
+                    thread::spawn(move || server.handle_connection(stream));
+                },
+                Err(e) => warn!(target: DAEMON, "Failed to accept connection: {}", e),
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Serve requests on a background thread
+    pub fn spawn(self, bind_addr: String) -> thread::JoinHandle<()> {
+        let server = Arc::new(self);
+        
+        thread::spawn(move || {
+            if let Err(e) = server.serve(&bind_addr) {
+                warn!(target: DAEMON, "HTTP API stopped: {}", e);
+            }
+        })
+    }
+}
@@ -0,0 +1,16 @@
+//! Time-accelerated soak runs for operational rehearsal
+//!
+//! Runs scripted scenarios against a contract on an in-memory transfer
+//! backend and a mock clock, so months of deposits, withdrawals, limit
+//! changes and backend failures take milliseconds. Ops use it to check a
+//! configuration change before it meets real funds (`time_locked_deposit
+//! soak <scenario.json>`); example scenarios live in `scenarios/`.
+
+pub mod scenario;
+pub mod runner;
+pub mod transfer;
+
+// Re-export commonly used types
+pub use scenario::{Action, DepositRef, EventExpectation, Expectation, Scenario, Step};
+pub use runner::{run_scenario, ActionTiming, SoakReport, SoakRunner, StepFailure};
+pub use transfer::SimulatedTransfer;
@@ -0,0 +1,397 @@
+//! Execution of soak scenarios
+//!
+//! The runner builds a contract on the simulated transfer backend and a mock
+//! clock, runs every step in order and checks its outcome and events. After
+//! each step it checks the same accounting invariants as the property tests;
+//! the first violation stops the run since later steps would only compound
+//! it. The report closes with a reconciliation of the contract's accounting
+//! against the simulated ledger and per-action timing stats.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+
+use crate::clock::{Clock, MockClock};
+use crate::errors::ContractError;
+use crate::events::{Event, EventSink};
+use crate::contract::audit::ReconciliationReport;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::soak::scenario::{Action, DepositRef, Expectation, Scenario, Step};
+use crate::soak::transfer::SimulatedTransfer;
+
+/// Event sink collecting the events of the current step
+#[derive(Debug, Default)]
+struct EventLog {
+    /// Events emitted since the last drain
+    events: Mutex<Vec<Event>>,
+}
+
+impl EventLog {
+    /// Take the collected events
+    fn drain(&self) -> Vec<Event> {
+        self.events.lock().map(|mut events| std::mem::take(&mut *events)).unwrap_or_default()
+    }
+}
+
+impl EventSink for EventLog {
+    fn publish(&self, event: &Event) {
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event.clone());
+        }
+    }
+}
+
+/// Step whose outcome or events differed from the scenario
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepFailure {
+    /// Position of the step, e.g. `4[2].3` for nested step 3 of repetition 2 of step 4
+    pub step: String,
+    /// Action of the step
+    pub action: &'static str,
+    /// What went wrong
+    pub reason: String,
+}
+
+impl fmt::Display for StepFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {} ({}): {}", self.step, self.action, self.reason)
+    }
+}
+
+/// Timing of one kind of action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ActionTiming {
+    /// Number of runs
+    pub count: u64,
+    /// Total time spent in the contract
+    pub total: Duration,
+    /// Slowest run
+    pub max: Duration,
+}
+
+impl ActionTiming {
+    /// Average time per run
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        
+        self.total / self.count.min(u32::MAX as u64) as u32
+    }
+}
+
+/// Outcome of a soak run
+#[derive(Debug, Clone)]
+pub struct SoakReport {
+    /// Scenario name
+    pub scenario: String,
+    /// Actions run, with repetitions expanded
+    pub actions_run: u64,
+    /// Steps whose outcome or events differed from the scenario
+    pub failures: Vec<StepFailure>,
+    /// First accounting invariant violation, which stopped the run
+    pub invariant_violation: Option<StepFailure>,
+    /// Contract accounting against the simulated ledger at the end of the run
+    pub reconciliation: ReconciliationReport,
+    /// Simulated time the run started at
+    pub simulated_start: DateTime<Utc>,
+    /// Simulated time the run ended at
+    pub simulated_end: DateTime<Utc>,
+    /// Wall-clock duration of the run
+    pub wall_time: Duration,
+    /// Timing per action name
+    pub timings: BTreeMap<&'static str, ActionTiming>,
+}
+
+impl SoakReport {
+    /// Whether every step behaved as scripted and the accounting held throughout
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty() && self.invariant_violation.is_none() && self.reconciliation.is_consistent()
+    }
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Soak scenario: {}", self.scenario)?;
+        writeln!(
+            f,
+            "  actions run: {} (simulated {} to {}, wall time {:?})",
+            self.actions_run, self.simulated_start, self.simulated_end, self.wall_time,
+        )?;
+        writeln!(f, "  failed expectations: {}", self.failures.len())?;
+        for failure in &self.failures {
+            writeln!(f, "    {}", failure)?;
+        }
+        
+        match &self.invariant_violation {
+            Some(violation) => writeln!(f, "  invariants: VIOLATED at {}", violation)?,
+            None => writeln!(f, "  invariants: ok after every step")?,
+        }
+        
+        let consistency = if self.reconciliation.is_consistent() { "consistent" } else { "INCONSISTENT" };
+        writeln!(f, "  holdings: {}", consistency)?;
+        for token in &self.reconciliation.tokens {
+            let actual = token.actual.map(|actual| actual.to_string()).unwrap_or_else(|| "unknown".to_string());
+            writeln!(f, "    {}: expected {}, held {}", token.token_type.name(), token.expected, actual)?;
+        }
+        
+        writeln!(f, "  timings:")?;
+        for (action, timing) in &self.timings {
+            writeln!(f, "    {:<22} {:>6} runs  mean {:>10?}  max {:>10?}", action, timing.count, timing.mean(), timing.max)?;
+        }
+        
+        write!(f, "  result: {}", if self.passed() { "PASSED" } else { "FAILED" })
+    }
+}
+
+/// Runner of a single scenario
+pub struct SoakRunner {
+    /// Scenario being run
+    scenario: Scenario,
+    /// Contract under test
+    contract: TimeLockedDeposit<SimulatedTransfer>,
+    /// Backend shared with the contract, for failure injection
+    transfer: SimulatedTransfer,
+    /// Simulated clock
+    clock: Arc<MockClock>,
+    /// Events of the current step
+    events: Arc<EventLog>,
+    /// Deposit IDs by label, including `last`
+    labels: HashMap<String, u64>,
+    /// Payouts per deposit ID
+    payouts: HashMap<u64, u32>,
+    /// Actions run so far
+    actions_run: u64,
+    /// Failed expectations so far
+    failures: Vec<StepFailure>,
+    /// First invariant violation
+    invariant_violation: Option<StepFailure>,
+    /// Timing per action name
+    timings: BTreeMap<&'static str, ActionTiming>,
+}
+
+impl SoakRunner {
+    /// Set up the contract described by a scenario
+    pub fn new(scenario: Scenario) -> Result<Self, ContractError> {
+        let transfer = SimulatedTransfer::new(scenario.initial_balance, scenario.balances.clone());
+        let clock = Arc::new(MockClock::new(scenario.start));
+        let events = Arc::new(EventLog::default());
+        
+        let mut contract = TimeLockedDeposit::new(
+            scenario.owner.clone(),
+            scenario.emergency_fee_percentage,
+            transfer.clone(),
+            scenario.tokens.clone(),
+        )?;
+        contract.set_clock(clock.clone());
+        contract.add_event_sink(events.clone());
+        
+        Ok(Self {
+            scenario,
+            contract,
+            transfer,
+            clock,
+            events,
+            labels: HashMap::new(),
+            payouts: HashMap::new(),
+            actions_run: 0,
+            failures: Vec::new(),
+            invariant_violation: None,
+            timings: BTreeMap::new(),
+        })
+    }
+    
+    /// Run every step and report the outcome
+    pub fn run(mut self) -> SoakReport {
+        let started = Instant::now();
+        let simulated_start = self.clock.now();
+        
+        let steps = std::mem::take(&mut self.scenario.steps);
+        self.run_steps(&steps, "");
+        
+        SoakReport {
+            scenario: self.scenario.name.clone(),
+            actions_run: self.actions_run,
+            failures: self.failures,
+            invariant_violation: self.invariant_violation,
+            reconciliation: self.contract.reconcile(0),
+            simulated_start,
+            simulated_end: self.clock.now(),
+            wall_time: started.elapsed(),
+            timings: self.timings,
+        }
+    }
+    
+    /// Run a list of steps, stopping at the first invariant violation
+    fn run_steps(&mut self, steps: &[Step], prefix: &str) {
+        for (index, step) in steps.iter().enumerate() {
+            if self.invariant_violation.is_some() {
+                return;
+            }
+            
+            let position = format!("{}{}", prefix, index + 1);
+            
+            match &step.action {
+                Action::Repeat { times, steps } => {
+                    for iteration in 1..=*times {
+                        self.run_steps(steps, &format!("{}[{}].", position, iteration));
+                    }
+                },
+                action => self.run_step(step, action, position),
+            }
+        }
+    }
+    
+    /// Run a single action and check it against the scenario and the invariants
+    fn run_step(&mut self, step: &Step, action: &Action, position: String) {
+        let before = self.contract.accounting_snapshot();
+        self.events.drain();
+        
+        let started = Instant::now();
+        let result = self.execute(action);
+        let elapsed = started.elapsed();
+        
+        self.actions_run += 1;
+        let timing = self.timings.entry(action.name()).or_default();
+        timing.count += 1;
+        timing.total += elapsed;
+        timing.max = timing.max.max(elapsed);
+        
+        let events = self.events.drain();
+        
+        // Outcome and events as scripted
+        if let Some(reason) = Self::outcome_mismatch(&step.expect, &result).or_else(|| Self::events_mismatch(step, &events)) {
+            self.failures.push(StepFailure { step: position.clone(), action: action.name(), reason });
+        }
+        
+        // Accounting invariants, as in the property tests
+        let withdrew_fees = matches!(action, Action::WithdrawFees { .. });
+        let violation = self.contract.check_accounting_invariants()
+            .and_then(|_| self.contract.check_transition_invariants(&before, withdrew_fees))
+            .and_then(|_| self.record_payouts(&events))
+            .err();
+        
+        if let Some(reason) = violation {
+            self.invariant_violation = Some(StepFailure { step: position, action: action.name(), reason });
+        }
+    }
+    
+    /// Describe how a result differs from the expected outcome, if it does
+    fn outcome_mismatch(expect: &Expectation, result: &Result<(), ContractError>) -> Option<String> {
+        match (expect, result) {
+            (Expectation::Ok, Ok(())) => None,
+            (Expectation::Ok, Err(e)) => Some(format!("expected success but failed with {} ({})", e.code().as_str(), e)),
+            (Expectation::Error(code), Ok(())) => Some(format!("expected {} but succeeded", code.as_str())),
+            (Expectation::Error(code), Err(e)) if e.code() == *code => None,
+            (Expectation::Error(code), Err(e)) => Some(format!("expected {} but failed with {} ({})", code.as_str(), e.code().as_str(), e)),
+        }
+    }
+    
+    /// Describe how the emitted events differ from the scripted ones, if they do
+    fn events_mismatch(step: &Step, events: &[Event]) -> Option<String> {
+        let expected = step.events.as_ref()?;
+        
+        if expected.len() != events.len() {
+            let names: Vec<&str> = events.iter().map(|event| event.name()).collect();
+            return Some(format!("expected {} events but got {} {:?}", expected.len(), events.len(), names));
+        }
+        
+        expected.iter().zip(events).find_map(|(expectation, event)| expectation.mismatch(event))
+    }
+    
+    /// Count payouts and check no deposit paid out twice
+    fn record_payouts(&mut self, events: &[Event]) -> Result<(), String> {
+        for event in events {
+            if matches!(event, Event::Withdrawn { .. } | Event::EmergencyWithdrawn { .. } | Event::InheritanceClaimed { .. }) {
+                if let Some(deposit_id) = event.deposit_id() {
+                    let count = self.payouts.entry(deposit_id).or_insert(0);
+                    *count += 1;
+                    if *count > 1 {
+                        return Err(format!("deposit {} paid out {} times", deposit_id, count));
+                    }
+                }
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Resolve a deposit reference to an ID
+    fn resolve(&self, deposit: &DepositRef) -> Result<u64, ContractError> {
+        match deposit {
+            DepositRef::Id(id) => Ok(*id),
+            DepositRef::Label(label) => self.labels.get(label).copied().ok_or(ContractError::DepositNotFound),
+        }
+    }
+    
+    /// Run an action against the contract
+    fn execute(&mut self, action: &Action) -> Result<(), ContractError> {
+        let owner = self.scenario.owner.clone();
+        
+        match action {
+            Action::Deposit { user, token, amount, lock_days, label } => {
+                let event = self.contract.deposit(user.clone(), token.clone(), *amount, *lock_days, None)?;
+                
+                if let Some(deposit_id) = event.deposit_id() {
+                    self.labels.insert("last".to_string(), deposit_id);
+                    if let Some(label) = label {
+                        self.labels.insert(label.clone(), deposit_id);
+                    }
+                }
+                
+                Ok(())
+            },
+            Action::Withdraw { user, deposit } => {
+                let deposit_id = self.resolve(deposit)?;
+                self.contract.withdraw(user.clone(), deposit_id).map(|_| ())
+            },
+            Action::EmergencyWithdraw { user, deposit } => {
+                let deposit_id = self.resolve(deposit)?;
+                self.contract.emergency_withdraw(user.clone(), deposit_id).map(|_| ())
+            },
+            Action::WithdrawFees { token, amount } => self.contract
+                .withdraw_fees(owner, token.clone(), *amount, None, None)
+                .map(|_| ()),
+            Action::SetDepositLimits { max_deposits_per_user, max_total_deposits, max_deposit_amounts } => {
+                self.contract.deposit_limits.max_deposits_per_user = *max_deposits_per_user;
+                self.contract.deposit_limits.max_total_deposits = *max_total_deposits;
+                self.contract.deposit_limits.max_deposit_amounts = max_deposit_amounts.iter().cloned().collect();
+                Ok(())
+            },
+            Action::SetMaxLockDays { days } => self.contract.set_max_lock_days(owner, *days, None),
+            Action::SetDepositFee { token, fee } => self.contract.set_deposit_fee(owner, token.clone(), *fee, None),
+            Action::SetMinEmergencyAge { hours } => self.contract
+                .set_min_age_before_emergency(owner, Duration::from_secs(hours.saturating_mul(3600)), None),
+            Action::Pause => {
+                self.contract.is_contract_paused = true;
+                Ok(())
+            },
+            Action::Unpause => {
+                self.contract.is_contract_paused = false;
+                Ok(())
+            },
+            Action::AdvanceClock { days, hours, minutes } => {
+                self.clock.advance(chrono::Duration::days(*days) + chrono::Duration::hours(*hours) + chrono::Duration::minutes(*minutes));
+                Ok(())
+            },
+            Action::FailTransfers { count } => {
+                self.transfer.fail_next(*count);
+                Ok(())
+            },
+            Action::Maintenance => self.contract
+                .perform_maintenance(owner, None)
+                .map(|_| ()),
+            Action::Repeat { .. } => Ok(()),
+        }
+    }
+}
+
+/// Parse and run a scenario
+pub fn run_scenario(json: &str) -> Result<SoakReport, String> {
+    let scenario = Scenario::from_json(json)?;
+    let runner = SoakRunner::new(scenario).map_err(|e| format!("Failed to set up the contract: {}", e))?;
+    
+    Ok(runner.run())
+}
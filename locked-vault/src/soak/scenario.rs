@@ -0,0 +1,306 @@
+//! Soak scenario files
+//!
+//! A scenario is a JSON document describing the contract setup and an
+//! ordered list of steps. Each step names an action, the outcome it must
+//! have (`"ok"` or `{"error": "<CODE>"}`) and optionally the events it must
+//! emit, in order:
+//!
+//! ```json
+//! {
+//!   "name": "lock and withdraw",
+//!   "tokens": ["Bitcoin"],
+//!   "steps": [
+//!     { "action": "deposit", "user": "alice", "token": "Bitcoin", "amount": 50000, "lock_days": 30, "label": "first" },
+//!     { "action": "withdraw", "user": "alice", "deposit": "first", "expect": { "error": "DEPOSIT_LOCKED" } },
+//!     { "action": "advance_clock", "days": 30 },
+//!     { "action": "withdraw", "user": "alice", "deposit": "first",
+//!       "events": [{ "event": "Withdrawn", "fields": { "withdrawn_amount": 50000 } }] }
+//!   ]
+//! }
+//! ```
+
+use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ErrorCode;
+use crate::events::Event;
+use crate::models::{DepositFee, TokenType};
+
+/// Contract setup and steps of a soak run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    /// Scenario name, shown in the report
+    pub name: String,
+    /// What the scenario rehearses
+    #[serde(default)]
+    pub description: String,
+    /// Contract owner address
+    #[serde(default = "default_owner")]
+    pub owner: String,
+    /// Emergency withdrawal fee percentage
+    #[serde(default = "default_emergency_fee")]
+    pub emergency_fee_percentage: u8,
+    /// Supported token types
+    #[serde(default = "default_tokens")]
+    pub tokens: Vec<TokenType>,
+    /// Simulated time the run starts at
+    #[serde(default = "default_start")]
+    pub start: DateTime<Utc>,
+    /// Balance every address starts with, per token type
+    #[serde(default = "default_initial_balance")]
+    pub initial_balance: u64,
+    /// Starting balances of specific addresses, overriding `initial_balance`
+    #[serde(default)]
+    pub balances: HashMap<String, u64>,
+    /// Steps, run in order
+    pub steps: Vec<Step>,
+}
+
+/// Default contract owner address
+fn default_owner() -> String {
+    "owner".to_string()
+}
+
+/// Default emergency withdrawal fee percentage
+fn default_emergency_fee() -> u8 {
+    10
+}
+
+/// Default supported token types
+fn default_tokens() -> Vec<TokenType> {
+    vec![TokenType::Bitcoin, TokenType::Lightning]
+}
+
+/// Default start time, fixed so runs are reproducible
+fn default_start() -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z")
+        .map(|start| start.with_timezone(&Utc))
+        .unwrap_or_default()
+}
+
+/// Default starting balance of every address
+fn default_initial_balance() -> u64 {
+    1_000_000_000
+}
+
+impl Scenario {
+    /// Parse a scenario from JSON
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("Invalid scenario: {}", e))
+    }
+    
+    /// Number of actions the scenario runs, with repetitions expanded
+    pub fn action_count(&self) -> u64 {
+        Step::count(&self.steps)
+    }
+}
+
+/// Action with its expected outcome
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Step {
+    /// Action to run
+    #[serde(flatten)]
+    pub action: Action,
+    /// Expected outcome
+    #[serde(default)]
+    pub expect: Expectation,
+    /// Events the action must emit, in order; unchecked if absent
+    #[serde(default)]
+    pub events: Option<Vec<EventExpectation>>,
+}
+
+impl Step {
+    /// Number of actions in a list of steps, with repetitions expanded
+    fn count(steps: &[Step]) -> u64 {
+        steps.iter()
+            .map(|step| match &step.action {
+                Action::Repeat { times, steps } => (*times as u64).saturating_mul(Self::count(steps)),
+                _ => 1,
+            })
+            .fold(0u64, |total, count| total.saturating_add(count))
+    }
+}
+
+/// Scripted action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Deposit by a user
+    Deposit {
+        /// Depositor address
+        user: String,
+        /// Token type
+        token: TokenType,
+        /// Gross amount
+        amount: u64,
+        /// Lock period in days
+        lock_days: u32,
+        /// Name later steps use to refer to the deposit
+        #[serde(default)]
+        label: Option<String>,
+    },
+    /// Regular withdrawal
+    Withdraw {
+        /// Caller address
+        user: String,
+        /// Deposit to withdraw
+        deposit: DepositRef,
+    },
+    /// Emergency withdrawal
+    EmergencyWithdraw {
+        /// Caller address
+        user: String,
+        /// Deposit to withdraw
+        deposit: DepositRef,
+    },
+    /// Fee withdrawal by the owner
+    WithdrawFees {
+        /// Token type
+        token: TokenType,
+        /// Amount, or everything collected
+        #[serde(default)]
+        amount: Option<u64>,
+    },
+    /// Replace the deposit limits
+    SetDepositLimits {
+        /// Maximum number of deposits per user
+        #[serde(default)]
+        max_deposits_per_user: Option<u32>,
+        /// Maximum total deposits per token type
+        #[serde(default)]
+        max_total_deposits: Option<u64>,
+        /// Maximum amount per deposit, per token type
+        #[serde(default)]
+        max_deposit_amounts: Vec<(TokenType, u64)>,
+    },
+    /// Change the maximum lock period
+    SetMaxLockDays {
+        /// Maximum lock period in days
+        days: u32,
+    },
+    /// Change the deposit fee of a token type
+    SetDepositFee {
+        /// Token type
+        token: TokenType,
+        /// Fee, or none
+        #[serde(default)]
+        fee: Option<DepositFee>,
+    },
+    /// Change how long deposits wait before an emergency withdrawal
+    SetMinEmergencyAge {
+        /// Minimum deposit age in hours
+        hours: u64,
+    },
+    /// Pause the contract
+    Pause,
+    /// Unpause the contract
+    Unpause,
+    /// Advance the simulated clock
+    AdvanceClock {
+        /// Days to advance
+        #[serde(default)]
+        days: i64,
+        /// Hours to advance
+        #[serde(default)]
+        hours: i64,
+        /// Minutes to advance
+        #[serde(default)]
+        minutes: i64,
+    },
+    /// Make the next transfers fail
+    FailTransfers {
+        /// Number of transfers that fail
+        count: u32,
+    },
+    /// Archive withdrawn deposit IDs
+    Maintenance,
+    /// Run nested steps several times
+    Repeat {
+        /// Number of repetitions
+        times: u32,
+        /// Steps of one repetition
+        steps: Vec<Step>,
+    },
+}
+
+impl Action {
+    /// Short name for reports and timing stats
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Deposit { .. } => "deposit",
+            Action::Withdraw { .. } => "withdraw",
+            Action::EmergencyWithdraw { .. } => "emergency_withdraw",
+            Action::WithdrawFees { .. } => "withdraw_fees",
+            Action::SetDepositLimits { .. } => "set_deposit_limits",
+            Action::SetMaxLockDays { .. } => "set_max_lock_days",
+            Action::SetDepositFee { .. } => "set_deposit_fee",
+            Action::SetMinEmergencyAge { .. } => "set_min_emergency_age",
+            Action::Pause => "pause",
+            Action::Unpause => "unpause",
+            Action::AdvanceClock { .. } => "advance_clock",
+            Action::FailTransfers { .. } => "fail_transfers",
+            Action::Maintenance => "maintenance",
+            Action::Repeat { .. } => "repeat",
+        }
+    }
+}
+
+/// Deposit a step refers to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DepositRef {
+    /// Deposit ID
+    Id(u64),
+    /// Label of an earlier deposit step, or `last` for the latest deposit
+    Label(String),
+}
+
+/// Outcome a step must have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Expectation {
+    /// The action succeeds
+    #[default]
+    Ok,
+    /// The action fails with this error code
+    Error(ErrorCode),
+}
+
+/// Event a step must emit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventExpectation {
+    /// Event name, e.g. `Deposited`
+    pub event: String,
+    /// Fields the event must carry with exactly these values
+    #[serde(default)]
+    pub fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl EventExpectation {
+    /// Describe how an event differs from the expectation, if it does
+    pub fn mismatch(&self, event: &Event) -> Option<String> {
+        if event.name() != self.event {
+            return Some(format!("expected event {} but got {}", self.event, event.name()));
+        }
+        
+        // Events serialize as `{ "<Name>": { fields } }`
+        let body = serde_json::to_value(event).unwrap_or_default();
+        let fields = body.get(event.name());
+        
+        for (field, expected) in &self.fields {
+            let actual = fields.and_then(|fields| fields.get(field));
+            if actual != Some(expected) {
+                return Some(format!(
+                    "event {} has {} = {} instead of {}",
+                    self.event,
+                    field,
+                    actual.map(|value| value.to_string()).unwrap_or_else(|| "nothing".to_string()),
+                    expected,
+                ));
+            }
+        }
+        
+        None
+    }
+}
@@ -0,0 +1,120 @@
+//! In-memory transfer backend for soak runs
+//!
+//! Balances live in a ledger keyed by address and token type, so the soak
+//! report can reconcile the contract's accounting against what it actually
+//! holds. Transfers can be told to fail to rehearse backend outages.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::models::{TokenTransfer, TokenType};
+
+/// Ledger address holding the contract's funds
+pub const CONTRACT_ADDRESS: &str = "contract";
+
+/// Transfer backend with an in-memory ledger
+#[derive(Debug, Clone)]
+pub struct SimulatedTransfer {
+    /// Balances per address and token type, once touched
+    ledger: Arc<Mutex<HashMap<(String, TokenType), u64>>>,
+    /// Balance of addresses not yet in the ledger
+    initial_balance: u64,
+    /// Starting balances of specific addresses
+    balances: HashMap<String, u64>,
+    /// Number of upcoming transfers that fail
+    pending_failures: Arc<AtomicU32>,
+}
+
+impl SimulatedTransfer {
+    /// Create a backend where every address starts with `initial_balance` of every token
+    ///
+    /// The contract itself starts empty.
+    pub fn new(initial_balance: u64, balances: HashMap<String, u64>) -> Self {
+        Self {
+            ledger: Arc::new(Mutex::new(HashMap::new())),
+            initial_balance,
+            balances,
+            pending_failures: Arc::new(AtomicU32::new(0)),
+        }
+    }
+    
+    /// Make the next `count` transfers fail
+    pub fn fail_next(&self, count: u32) {
+        self.pending_failures.store(count, Ordering::SeqCst);
+    }
+    
+    /// Balance of an address before it was touched
+    fn starting_balance(&self, address: &str) -> u64 {
+        if address == CONTRACT_ADDRESS {
+            return 0;
+        }
+        
+        self.balances.get(address).copied().unwrap_or(self.initial_balance)
+    }
+    
+    /// Fail the transfer if a failure is pending
+    fn check_failure(&self) -> Result<(), String> {
+        let failed = self.pending_failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| pending.checked_sub(1))
+            .is_ok();
+        
+        if failed {
+            return Err("simulated transfer failure".to_string());
+        }
+        
+        Ok(())
+    }
+    
+    /// Move funds between two ledger addresses
+    fn move_funds(&self, from_address: &str, to_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.check_failure()?;
+        
+        let mut ledger = self.ledger.lock().map_err(|_| "ledger lock poisoned".to_string())?;
+        
+        let from_key = (from_address.to_string(), token_type.clone());
+        let from_balance = ledger.get(&from_key).copied().unwrap_or_else(|| self.starting_balance(from_address));
+        let to_key = (to_address.to_string(), token_type.clone());
+        let to_balance = ledger.get(&to_key).copied().unwrap_or_else(|| self.starting_balance(to_address));
+        
+        let from_balance = from_balance.checked_sub(amount)
+            .ok_or_else(|| format!("{} holds less than {}", from_address, amount))?;
+        let to_balance = to_balance.checked_add(amount)
+            .ok_or_else(|| format!("{} balance overflow", to_address))?;
+        
+        ledger.insert(from_key, from_balance);
+        ledger.insert(to_key, to_balance);
+        
+        Ok(())
+    }
+}
+
+impl TokenTransfer for SimulatedTransfer {
+    fn transfer_to_contract(&self, from_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.move_funds(from_address, CONTRACT_ADDRESS, token_type, amount)
+    }
+    
+    fn transfer_from_contract(&self, to_address: &str, token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.move_funds(CONTRACT_ADDRESS, to_address, token_type, amount)
+    }
+    
+    fn get_balance(&self, address: &str, token_type: &TokenType) -> Result<u64, String> {
+        let ledger = self.ledger.lock().map_err(|_| "ledger lock poisoned".to_string())?;
+        
+        Ok(ledger.get(&(address.to_string(), token_type.clone()))
+            .copied()
+            .unwrap_or_else(|| self.starting_balance(address)))
+    }
+    
+    fn supports_token_type(&self, _token_type: &TokenType) -> bool {
+        true
+    }
+    
+    fn get_network_type(&self) -> String {
+        "simulated".to_string()
+    }
+    
+    fn get_contract_balance(&self, token_type: &TokenType) -> Result<u64, String> {
+        self.get_balance(CONTRACT_ADDRESS, token_type)
+    }
+}
@@ -0,0 +1,315 @@
+//! Monthly statements of a depositor address
+//!
+//! A statement covers one calendar month in UTC and is built from the
+//! address's ledger: the locked balance of each token at the start and end
+//! of the month, every ledger entry in between, the fees paid, and the
+//! deposits still locked after the month with their unlock times. The
+//! opening balance of a token is its running balance after the last entry
+//! before the month, so the closing balance of one month is always the
+//! opening balance of the next.
+//!
+//! Statements serialize to JSON and render as a markdown document with the
+//! fixed-format amounts and timestamps of `display`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::query::DepositFilter;
+use crate::display::{format_amount, format_timestamp, Table, TableStyle};
+use crate::event_log::EventLog;
+use crate::ledger::LedgerEntry;
+use crate::models::{Deposit, TokenTransfer, TokenType};
+
+/// Calendar month in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct StatementPeriod {
+    /// Year
+    pub year: i32,
+    /// Month, 1 to 12
+    pub month: u32,
+}
+
+impl StatementPeriod {
+    /// Create a period, failing for a month outside 1 to 12
+    pub fn new(year: i32, month: u32) -> Result<Self, String> {
+        let period = Self { year, month };
+        if !(1..=12).contains(&month) || Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single().is_none() {
+            return Err(format!("Invalid statement month: {}", period));
+        }
+        
+        Ok(period)
+    }
+    
+    /// Month after this one
+    pub fn next(&self) -> Self {
+        match self.month {
+            12 => Self { year: self.year + 1, month: 1 },
+            month => Self { year: self.year, month: month + 1 },
+        }
+    }
+    
+    /// Start of the month, inclusive
+    pub fn start(&self) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(self.year, self.month, 1, 0, 0, 0).single().unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+    
+    /// Start of the next month, exclusive
+    pub fn end(&self) -> DateTime<Utc> {
+        self.next().start()
+    }
+}
+
+impl fmt::Display for StatementPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl FromStr for StatementPeriod {
+    type Err = String;
+    
+    /// Parse `YYYY-MM`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (year, month) = s.split_once('-')
+            .and_then(|(year, month)| Some((year.parse::<i32>().ok()?, month.parse::<u32>().ok()?)))
+            .ok_or_else(|| format!("Invalid statement month: {} (expected YYYY-MM)", s))?;
+        
+        Self::new(year, month)
+    }
+}
+
+/// Movements of one token over a statement period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenStatement {
+    /// Token type
+    pub token: TokenType,
+    /// Locked balance at the start of the period
+    pub opening_balance: u64,
+    /// Amounts locked during the period: deposits, imports and assignments to the address
+    pub credited: u64,
+    /// Amounts released during the period, without fees: withdrawals, claims and assignments away
+    pub debited: u64,
+    /// Deposit and emergency withdrawal fees paid during the period
+    pub fees_paid: u64,
+    /// Locked balance at the end of the period
+    pub closing_balance: u64,
+}
+
+/// Deposit still locked after a statement period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpcomingUnlock {
+    /// Deposit ID
+    pub deposit_id: u64,
+    /// Token type
+    pub token: TokenType,
+    /// Locked amount
+    pub amount: u64,
+    /// When the deposit unlocks
+    pub unlock_timestamp: DateTime<Utc>,
+}
+
+/// Statement of a depositor address for one month
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Statement {
+    /// Depositor address
+    pub address: String,
+    /// Month covered
+    pub period: StatementPeriod,
+    /// When the statement was generated
+    pub generated_at: DateTime<Utc>,
+    /// Balances and movements per token, by token name
+    pub tokens: Vec<TokenStatement>,
+    /// Ledger entries of the period in log order
+    pub entries: Vec<LedgerEntry>,
+    /// Active deposits unlocking after the period, soonest first
+    pub upcoming_unlocks: Vec<UpcomingUnlock>,
+}
+
+/// Build the statement of an address for a period
+///
+/// `ledger` holds the address's ledger entries in log order; entries after
+/// the period are ignored. `deposits` are the address's deposits, of which
+/// the active ones unlocking after the period are listed.
+pub fn build_statement<'a>(
+    address: &str,
+    period: StatementPeriod,
+    ledger: &[LedgerEntry],
+    deposits: impl IntoIterator<Item = &'a Deposit>,
+    generated_at: DateTime<Utc>,
+) -> Statement {
+    let (start, end) = (period.start(), period.end());
+    let mut tokens: HashMap<TokenType, TokenStatement> = HashMap::new();
+    let mut entries = Vec::new();
+    
+    for entry in ledger.iter().filter(|entry| entry.timestamp < end) {
+        let token = tokens.entry(entry.token.clone()).or_insert_with(|| TokenStatement {
+            token: entry.token.clone(),
+            opening_balance: 0,
+            credited: 0,
+            debited: 0,
+            fees_paid: 0,
+            closing_balance: 0,
+        });
+        token.closing_balance = entry.balance_after;
+        
+        if entry.timestamp < start {
+            token.opening_balance = entry.balance_after;
+            continue;
+        }
+        
+        let amount = entry.amount_delta.unsigned_abs().min(u64::MAX as u128) as u64;
+        if entry.kind.is_fee() {
+            token.fees_paid = token.fees_paid.saturating_add(amount);
+        } else if entry.amount_delta > 0 {
+            token.credited = token.credited.saturating_add(amount);
+        } else {
+            token.debited = token.debited.saturating_add(amount);
+        }
+        entries.push(entry.clone());
+    }
+    
+    // Tokens emptied before the period have nothing to report
+    let active_tokens: Vec<&TokenType> = entries.iter().map(|entry| &entry.token).collect();
+    let mut tokens: Vec<TokenStatement> = tokens.into_values()
+        .filter(|token| token.opening_balance > 0 || active_tokens.contains(&&token.token))
+        .collect();
+    tokens.sort_by_key(|token| token.token.name());
+    
+    let upcoming = DepositFilter::active().with_address(address).with_unlock_range(Some(end), None);
+    let mut upcoming_unlocks: Vec<UpcomingUnlock> = deposits.into_iter()
+        .filter(|deposit| upcoming.matches(deposit))
+        .map(|deposit| UpcomingUnlock {
+            deposit_id: deposit.deposit_id,
+            token: deposit.deposited_token_type.clone(),
+            amount: deposit.deposited_amount,
+            unlock_timestamp: deposit.unlock_timestamp,
+        })
+        .collect();
+    upcoming_unlocks.sort_by_key(|unlock| (unlock.unlock_timestamp, unlock.deposit_id));
+    
+    Statement {
+        address: address.to_string(),
+        period,
+        generated_at,
+        tokens,
+        entries,
+        upcoming_unlocks,
+    }
+}
+
+impl Statement {
+    /// Closing balance of a token, zero if the statement does not list it
+    pub fn closing_balance(&self, token: &TokenType) -> u64 {
+        self.tokens.iter().find(|statement| &statement.token == token).map_or(0, |statement| statement.closing_balance)
+    }
+    
+    /// Opening balance of a token, zero if the statement does not list it
+    pub fn opening_balance(&self, token: &TokenType) -> u64 {
+        self.tokens.iter().find(|statement| &statement.token == token).map_or(0, |statement| statement.opening_balance)
+    }
+    
+    /// Render the statement as a markdown document
+    pub fn to_markdown(&self) -> String {
+        let mut balances = Table::new(&["TOKEN", "OPENING", "CREDITED", "DEBITED", "FEES", "CLOSING"])
+            .align_right(1).align_right(2).align_right(3).align_right(4).align_right(5);
+        for token in &self.tokens {
+            balances.push_row(vec![
+                token.token.name(),
+                format_amount(&token.token, token.opening_balance),
+                format_amount(&token.token, token.credited),
+                format_amount(&token.token, token.debited),
+                format_amount(&token.token, token.fees_paid),
+                format_amount(&token.token, token.closing_balance),
+            ]);
+        }
+        
+        let mut activity = Table::new(&["TIME", "KIND", "DEPOSIT", "CHANGE", "BALANCE"])
+            .align_right(2).align_right(3).align_right(4);
+        for entry in &self.entries {
+            let sign = if entry.amount_delta < 0 { "-" } else { "" };
+            let amount = entry.amount_delta.unsigned_abs().min(u64::MAX as u128) as u64;
+            activity.push_row(vec![
+                format_timestamp(entry.timestamp),
+                entry.kind.name().to_string(),
+                entry.deposit_id.to_string(),
+                format!("{}{}", sign, format_amount(&entry.token, amount)),
+                format_amount(&entry.token, entry.balance_after),
+            ]);
+        }
+        
+        let mut unlocks = Table::new(&["DEPOSIT", "TOKEN", "AMOUNT", "UNLOCKS"]).align_right(0).align_right(2);
+        for unlock in &self.upcoming_unlocks {
+            unlocks.push_row(vec![
+                unlock.deposit_id.to_string(),
+                unlock.token.name(),
+                format_amount(&unlock.token, unlock.amount),
+                format_timestamp(unlock.unlock_timestamp),
+            ]);
+        }
+        
+        let section = |title: &str, table: &Table, empty: &str| if table.is_empty() {
+            format!("## {}\n\n{}\n", title, empty)
+        } else {
+            format!("## {}\n\n```\n{}```\n", title, table.render(TableStyle::Aligned))
+        };
+        
+        format!(
+            "# Statement for {}\n\nPeriod: {} ({} to {})\nGenerated: {}\n\n{}\n{}\n{}",
+            self.address,
+            self.period,
+            format_timestamp(self.period.start()),
+            format_timestamp(self.period.end()),
+            format_timestamp(self.generated_at),
+            section("Balances", &balances, "No locked balances."),
+            section("Activity", &activity, "No activity."),
+            section("Upcoming Unlocks", &unlocks, "No deposits unlocking later."),
+        )
+    }
+}
+
+/// Source of statements, shared with the HTTP API
+pub trait StatementSource: Send + Sync + fmt::Debug {
+    /// Generate the statement of an address for a month
+    fn generate_statement(&self, address: &str, year: i32, month: u32) -> Result<Statement, String>;
+}
+
+/// Statements from the event log's ledger and a running contract's deposits
+pub struct StatementGenerator<T: TokenTransfer> {
+    /// Event log holding the ledger
+    event_log: Arc<EventLog>,
+    /// Contract holding the deposits
+    contract: Arc<Mutex<TimeLockedDeposit<T>>>,
+}
+
+impl<T: TokenTransfer> fmt::Debug for StatementGenerator<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatementGenerator")
+            .field("event_log", &self.event_log)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: TokenTransfer> StatementGenerator<T> {
+    /// Create a generator over an event log and the contract it records
+    pub fn new(event_log: Arc<EventLog>, contract: Arc<Mutex<TimeLockedDeposit<T>>>) -> Self {
+        Self { event_log, contract }
+    }
+}
+
+impl<T: TokenTransfer + Send> StatementSource for StatementGenerator<T> {
+    fn generate_statement(&self, address: &str, year: i32, month: u32) -> Result<Statement, String> {
+        let period = StatementPeriod::new(year, month)?;
+        let ledger = self.event_log.get_address_ledger(address, None, Some(period.end()), 0, usize::MAX)?;
+        
+        let contract = self.contract.lock()
+            .map_err(|_| "Contract lock poisoned".to_string())?;
+        let deposits = contract.deposits_filtered(DepositFilter::active().with_address(address));
+        
+        Ok(build_statement(address, period, &ledger, deposits, contract.clock.now()))
+    }
+}
@@ -0,0 +1,37 @@
+//! XChaCha20-Poly1305 authenticated encryption
+//!
+//! The AEAD of RFC 8439 with the 24-byte nonce extension of
+//! draft-irtf-cfrg-xchacha, provided by the `chacha20poly1305` crate. The
+//! random nonce is long enough that it never needs a counter.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+/// Key length in bytes
+pub(crate) const KEY_LEN: usize = 32;
+
+/// Nonce length in bytes
+pub(crate) const NONCE_LEN: usize = 24;
+
+/// Tag length in bytes
+pub(crate) const TAG_LEN: usize = 16;
+
+/// Encrypt and authenticate, returning the ciphertext followed by the tag
+///
+/// Fails only for a plaintext longer than the cipher's 256 GiB limit.
+pub(crate) fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, String> {
+    XChaCha20Poly1305::new(key.into())
+        .encrypt(XNonce::from_slice(nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| format!("{} bytes are too many to encrypt", plaintext.len()))
+}
+
+/// Check the tag and decrypt, `None` if the key, nonce, AAD or ciphertext is wrong
+pub(crate) fn open(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    if sealed.len() < TAG_LEN {
+        return None;
+    }
+    
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad })
+        .ok()
+}
@@ -0,0 +1,93 @@
+//! Argon2id passphrase hashing
+//!
+//! Argon2id of RFC 9106 (version 0x13), provided by the `argon2` crate. Its
+//! memory is wiped before it is released. Costs are checked against
+//! `MAX_MEMORY_KIB` and `MAX_ITERATIONS` first, since they come from blob
+//! headers and encrypted config files.
+
+use argon2::{Algorithm, Argon2, AssociatedData, ParamsBuilder, Version};
+use serde::{Serialize, Deserialize};
+
+/// Largest memory cost accepted, in KiB, so a forged header cannot exhaust memory
+pub const MAX_MEMORY_KIB: u32 = 1 << 20;
+
+/// Most passes accepted, so a forged header cannot stall key derivation
+pub const MAX_ITERATIONS: u32 = 64;
+
+/// Argon2id cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Argon2Params {
+    /// Memory in KiB
+    pub memory_kib: u32,
+    /// Passes over the memory
+    pub iterations: u32,
+    /// Lanes
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    /// 19 MiB, 2 passes, 1 lane
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Check the parameters are within what RFC 9106, `MAX_MEMORY_KIB` and `MAX_ITERATIONS` allow
+    pub fn validate(&self) -> Result<(), String> {
+        if self.parallelism == 0 || self.parallelism > 0xff_ffff {
+            return Err(format!("Argon2 parallelism {} is out of range", self.parallelism));
+        }
+        if self.iterations == 0 || self.iterations > MAX_ITERATIONS {
+            return Err(format!("Argon2 iterations {} is out of range", self.iterations));
+        }
+        if self.memory_kib < 8 * self.parallelism || self.memory_kib > MAX_MEMORY_KIB {
+            return Err(format!("Argon2 memory of {} KiB is out of range", self.memory_kib));
+        }
+        
+        Ok(())
+    }
+}
+
+/// Hash a password with Argon2id into `output_len` bytes
+///
+/// `secret` and `associated_data` are the optional K and X inputs.
+pub(crate) fn argon2id(
+    password: &[u8],
+    salt: &[u8],
+    secret: &[u8],
+    associated_data: &[u8],
+    params: &Argon2Params,
+    output_len: usize,
+) -> Result<Vec<u8>, String> {
+    params.validate()?;
+    
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(params.memory_kib)
+        .t_cost(params.iterations)
+        .p_cost(params.parallelism)
+        .output_len(output_len);
+    if !associated_data.is_empty() {
+        builder.data(AssociatedData::new(associated_data).map_err(|e| format!("Invalid Argon2 associated data: {}", e))?);
+    }
+    let costs = builder.build()
+        .map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+    
+    let hasher = if secret.is_empty() {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, costs)
+    } else {
+        Argon2::new_with_secret(secret, Algorithm::Argon2id, Version::V0x13, costs)
+            .map_err(|e| format!("Invalid Argon2 secret: {}", e))?
+    };
+    
+    let mut output = vec![0u8; output_len];
+    hasher.hash_password_into(password, salt, &mut output)
+        .map_err(|e| format!("Argon2 hashing failed: {}", e))?;
+    
+    Ok(output)
+}
@@ -0,0 +1,120 @@
+//! Encodings of a contract snapshot file
+//!
+//! Snapshots are JSON by default. JSON of a large contract is slow to parse
+//! and mostly field names and whitespace, so a snapshot can be written in a
+//! binary encoding instead: a header of `MAGIC` and the codec version,
+//! followed by the snapshot as CBOR (RFC 8949).
+//!
+//! CBOR is self-describing like JSON, which the snapshot schema needs: its
+//! optional fields are left out when empty and some enums are internally
+//! tagged, neither of which formats without field names such as bincode or
+//! postcard can read back. A binary snapshot of an older schema version is
+//! decoded into the same JSON value a JSON snapshot is and goes through the
+//! same migrations.
+//!
+//! Loading detects the encoding from the header, so either kind of file
+//! loads whatever codec is configured for writing.
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::contract::migration::CURRENT_STATE_VERSION;
+use crate::contract::state::ContractState;
+use crate::store::parse_snapshot;
+
+/// Marker at the start of a binary snapshot
+pub const MAGIC: &[u8; 8] = b"TLDSNAPB";
+
+/// Binary codec version written by this code
+pub const CODEC_VERSION: u8 = 1;
+
+/// Header length in bytes
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+/// Encoding snapshots are written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotCodec {
+    /// Pretty-printed JSON
+    #[default]
+    Json,
+    /// `MAGIC`, the codec version and CBOR
+    Binary,
+}
+
+impl fmt::Display for SnapshotCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotCodec::Json => write!(f, "json"),
+            SnapshotCodec::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+impl FromStr for SnapshotCodec {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(SnapshotCodec::Json),
+            "binary" => Ok(SnapshotCodec::Binary),
+            _ => Err(format!("Unknown snapshot codec: {} (expected json or binary)", s)),
+        }
+    }
+}
+
+impl SnapshotCodec {
+    /// Codec a snapshot file was written in
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(MAGIC) {
+            SnapshotCodec::Binary
+        } else {
+            SnapshotCodec::Json
+        }
+    }
+}
+
+/// Encode a snapshot with a codec
+pub fn encode_snapshot(state: &ContractState, codec: SnapshotCodec) -> Result<Vec<u8>, String> {
+    match codec {
+        SnapshotCodec::Json => serde_json::to_vec_pretty(state)
+            .map_err(|e| format!("Failed to serialize snapshot: {}", e)),
+        SnapshotCodec::Binary => {
+            let mut bytes = Vec::with_capacity(HEADER_LEN);
+            bytes.extend_from_slice(MAGIC);
+            bytes.push(CODEC_VERSION);
+            ciborium::ser::into_writer(state, &mut bytes)
+                .map_err(|e| format!("Failed to serialize snapshot: {}", e))?;
+            Ok(bytes)
+        },
+    }
+}
+
+/// Decode a snapshot in either codec, upgrading it to the current schema
+pub fn decode_snapshot(bytes: &[u8]) -> Result<ContractState, String> {
+    if SnapshotCodec::detect(bytes) == SnapshotCodec::Json {
+        let value: Value = serde_json::from_slice(bytes)
+            .map_err(|e| format!("Invalid snapshot: {}", e))?;
+        return parse_snapshot(value);
+    }
+    
+    let version = bytes.get(MAGIC.len()).copied()
+        .ok_or_else(|| "Invalid snapshot: truncated header".to_string())?;
+    if version != CODEC_VERSION {
+        return Err(format!("Unsupported binary snapshot codec version {} (expected {})", version, CODEC_VERSION));
+    }
+    let payload = &bytes[HEADER_LEN..];
+    
+    // A snapshot of the current schema needs no migration
+    if let Ok(state) = ciborium::de::from_reader::<ContractState, _>(payload) {
+        if state.state_version == CURRENT_STATE_VERSION {
+            return Ok(state);
+        }
+    }
+    
+    let value: Value = ciborium::de::from_reader(payload)
+        .map_err(|e| format!("Invalid snapshot: {}", e))?;
+    parse_snapshot(value)
+}
@@ -0,0 +1,487 @@
+//! Encryption at rest of contract and transfer state
+//!
+//! `EncryptedStateStore` keeps each serialized payload as a blob file in a
+//! directory, sealed with XChaCha20-Poly1305. The key is either a 32-byte
+//! key file or a passphrase stretched with Argon2id. Every blob starts with
+//! a versioned header:
+//!
+//! - `MAGIC` and the format version;
+//! - the key derivation (`KDF_RAW` or `KDF_ARGON2ID`) with its cost
+//!   parameters and 16-byte salt, all zero for a raw key;
+//! - the random 24-byte nonce.
+//!
+//! The header and the blob name are authenticated along with the
+//! ciphertext, so a blob cannot be edited, downgraded or swapped for
+//! another. Decryption with a wrong key and a tampered blob both fail with
+//! `StateEncryptionError::WrongKeyOrTampered`; nothing is returned unless
+//! the tag checks out.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{error, info};
+use rand::RngCore;
+use serde_json::Value;
+
+use crate::bitcoin::payout::PayoutEntry;
+use crate::bitcoin::utxo::OperationalUtxo;
+use crate::config::Secret;
+use crate::contract::state::ContractState;
+use crate::errors::StateEncryptionError;
+use crate::progress::Progress;
+use crate::store::aead::{self, KEY_LEN, NONCE_LEN};
+use crate::store::argon2::{argon2id, Argon2Params};
+use crate::store::{parse_snapshot, write_atomically, StateStore, TransferStateStore};
+use crate::logging::CONTRACT;
+
+/// Marker at the start of an encrypted blob
+pub const MAGIC: &[u8; 8] = b"TLDSTATE";
+
+/// Format version written by this code
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Key derivation of a blob sealed with a raw key
+pub const KDF_RAW: u8 = 0;
+
+/// Key derivation of a blob sealed with an Argon2id passphrase key
+pub const KDF_ARGON2ID: u8 = 1;
+
+/// Blob holding the contract snapshot
+pub const STATE_BLOB: &str = "state";
+
+/// Blob holding the payouts waiting for a batch
+pub const PENDING_PAYOUTS_BLOB: &str = "pending_payouts";
+
+/// Blob holding the change tagged as operational
+pub const OPERATIONAL_UTXOS_BLOB: &str = "operational_utxos";
+
+/// Blob holding the MuSig2 signing sessions and their secret nonces
+pub const MUSIG_SESSIONS_BLOB: &str = "musig_sessions";
+
+/// Extension of blob files
+const BLOB_EXTENSION: &str = "enc";
+
+/// Salt length in bytes
+const SALT_LEN: usize = 16;
+
+/// Header length in bytes
+const HEADER_LEN: usize = MAGIC.len() + 2 + 12 + SALT_LEN + NONCE_LEN;
+
+/// Key state blobs are encrypted with
+#[derive(Debug, Clone)]
+pub enum StateKey {
+    /// Passphrase stretched with Argon2id and a per-store salt
+    Passphrase {
+        /// Passphrase
+        passphrase: Secret<String>,
+        /// Argon2id cost of newly written blobs
+        params: Argon2Params,
+    },
+    /// 32-byte key used as is
+    Raw(Secret<Vec<u8>>),
+}
+
+impl StateKey {
+    /// Use a passphrase with the default Argon2id cost
+    pub fn from_passphrase(passphrase: Secret<String>) -> Result<Self, StateEncryptionError> {
+        Self::from_passphrase_with_params(passphrase, Argon2Params::default())
+    }
+    
+    /// Use a passphrase with a given Argon2id cost
+    pub fn from_passphrase_with_params(passphrase: Secret<String>, params: Argon2Params) -> Result<Self, StateEncryptionError> {
+        if passphrase.is_empty() {
+            return Err(StateEncryptionError::InvalidKey("Passphrase cannot be empty".to_string()));
+        }
+        params.validate().map_err(StateEncryptionError::InvalidKey)?;
+        
+        Ok(StateKey::Passphrase { passphrase, params })
+    }
+    
+    /// Use a 32-byte key
+    pub fn from_bytes(key: Secret<Vec<u8>>) -> Result<Self, StateEncryptionError> {
+        if key.expose_secret().len() != KEY_LEN {
+            return Err(StateEncryptionError::InvalidKey(format!("Key must be {} bytes", KEY_LEN)));
+        }
+        
+        Ok(StateKey::Raw(key))
+    }
+    
+    /// Read a key file holding the 32 key bytes, raw or as hex
+    pub fn from_key_file(path: &Path) -> Result<Self, StateEncryptionError> {
+        let contents = Secret::new(fs::read(path)
+            .map_err(|e| StateEncryptionError::InvalidKey(format!("Failed to read key file {}: {}", path.display(), e)))?);
+        
+        if contents.expose_secret().len() == KEY_LEN {
+            return Self::from_bytes(contents);
+        }
+        
+        let hex_key = std::str::from_utf8(contents.expose_secret())
+            .ok()
+            .and_then(|text| hex::decode(text.trim()).ok())
+            .ok_or_else(|| StateEncryptionError::InvalidKey(format!("Key file {} holds neither {} bytes nor their hex", path.display(), KEY_LEN)))?;
+        
+        Self::from_bytes(Secret::new(hex_key))
+    }
+    
+    /// Generate a random 32-byte key
+    pub fn generate() -> Self {
+        let mut key = vec![0u8; KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        
+        StateKey::Raw(Secret::new(key))
+    }
+    
+    /// Pick the key from the passphrase or key file setting, `None` if neither is set
+    pub fn from_settings(passphrase: Option<Secret<String>>, key_file: Option<&Path>) -> Result<Option<Self>, StateEncryptionError> {
+        match (passphrase, key_file) {
+            (Some(_), Some(_)) => Err(StateEncryptionError::InvalidKey("Set a state passphrase or a key file, not both".to_string())),
+            (Some(passphrase), None) => Self::from_passphrase(passphrase).map(Some),
+            (None, Some(path)) => Self::from_key_file(path).map(Some),
+            (None, None) => Ok(None),
+        }
+    }
+    
+    /// Derive the encryption key for a fresh random salt
+    fn derive_for_writing(&self) -> Result<DerivedKey, StateEncryptionError> {
+        let kdf = match self {
+            StateKey::Passphrase { params, .. } => {
+                let mut salt = [0u8; SALT_LEN];
+                rand::thread_rng().fill_bytes(&mut salt);
+                Kdf { id: KDF_ARGON2ID, params: *params, salt }
+            },
+            StateKey::Raw(_) => Kdf::raw(),
+        };
+        
+        let key = self.derive(&kdf)?.ok_or_else(|| StateEncryptionError::InvalidKey("Key does not match its derivation".to_string()))?;
+        
+        Ok(DerivedKey { kdf, key })
+    }
+    
+    /// Derive the encryption key for a blob's header, `None` if the key is of the other kind
+    fn derive(&self, kdf: &Kdf) -> Result<Option<Secret<Vec<u8>>>, StateEncryptionError> {
+        match (self, kdf.id) {
+            (StateKey::Raw(key), KDF_RAW) => Ok(Some(key.clone())),
+            (StateKey::Passphrase { passphrase, .. }, KDF_ARGON2ID) => {
+                argon2id(passphrase.expose_secret().as_bytes(), &kdf.salt, &[], &[], &kdf.params, KEY_LEN)
+                    .map(|key| Some(Secret::new(key)))
+                    .map_err(StateEncryptionError::InvalidKey)
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Key derivation recorded in a blob header
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Kdf {
+    /// `KDF_RAW` or `KDF_ARGON2ID`
+    id: u8,
+    /// Argon2id cost, all zero for a raw key
+    params: Argon2Params,
+    /// Argon2id salt, all zero for a raw key
+    salt: [u8; SALT_LEN],
+}
+
+impl Kdf {
+    /// Derivation of a raw key
+    fn raw() -> Self {
+        Self {
+            id: KDF_RAW,
+            params: Argon2Params { memory_kib: 0, iterations: 0, parallelism: 0 },
+            salt: [0u8; SALT_LEN],
+        }
+    }
+}
+
+/// Encryption key along with how it was derived
+#[derive(Debug, Clone)]
+struct DerivedKey {
+    /// Derivation written into headers
+    kdf: Kdf,
+    /// 32-byte encryption key
+    key: Secret<Vec<u8>>,
+}
+
+/// Blob header, also used as associated data
+struct Header {
+    /// Key derivation
+    kdf: Kdf,
+    /// Random nonce
+    nonce: [u8; NONCE_LEN],
+}
+
+impl Header {
+    /// Serialize the header
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.push(self.kdf.id);
+        bytes.extend_from_slice(&self.kdf.params.memory_kib.to_be_bytes());
+        bytes.extend_from_slice(&self.kdf.params.iterations.to_be_bytes());
+        bytes.extend_from_slice(&self.kdf.params.parallelism.to_be_bytes());
+        bytes.extend_from_slice(&self.kdf.salt);
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+    
+    /// Parse the header at the start of a blob
+    fn parse(blob: &str, bytes: &[u8]) -> Result<Self, StateEncryptionError> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(StateEncryptionError::NotEncrypted { blob: blob.to_string() });
+        }
+        
+        let malformed = |reason: &str| StateEncryptionError::Malformed { blob: blob.to_string(), reason: reason.to_string() };
+        if bytes.len() < HEADER_LEN {
+            return Err(malformed("too short"));
+        }
+        
+        let version = bytes[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(StateEncryptionError::UnsupportedVersion { blob: blob.to_string(), found: version, supported: FORMAT_VERSION });
+        }
+        
+        let word = |at: usize| u32::from_be_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]]);
+        let params_at = MAGIC.len() + 2;
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&bytes[params_at + 12..params_at + 12 + SALT_LEN]);
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce.copy_from_slice(&bytes[HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+        
+        let kdf = Kdf {
+            id: bytes[MAGIC.len() + 1],
+            params: Argon2Params { memory_kib: word(params_at), iterations: word(params_at + 4), parallelism: word(params_at + 8) },
+            salt,
+        };
+        
+        // Check the cost before deriving, so a forged header cannot exhaust memory
+        match kdf.id {
+            KDF_RAW if kdf == Kdf::raw() => {},
+            KDF_ARGON2ID => kdf.params.validate().map_err(|reason| malformed(&reason))?,
+            _ => return Err(malformed("unknown key derivation")),
+        }
+        
+        Ok(Self { kdf, nonce })
+    }
+}
+
+/// Contract and transfer state sealed with XChaCha20-Poly1305 in a directory
+#[derive(Debug)]
+pub struct EncryptedStateStore {
+    /// Directory holding the blob files
+    dir: PathBuf,
+    /// Key blobs are encrypted with
+    key: StateKey,
+    /// Key and derivation used for writing
+    write_key: DerivedKey,
+}
+
+impl EncryptedStateStore {
+    /// Open a store in `dir`, creating the directory if needed
+    ///
+    /// A passphrase key is stretched once here, with a fresh salt for the
+    /// blobs this store writes.
+    pub fn open(dir: impl Into<PathBuf>, key: StateKey) -> Result<Self, StateEncryptionError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|e| StateEncryptionError::Io(format!("Failed to create {}: {}", dir.display(), e)))?;
+        let write_key = key.derive_for_writing()?;
+        
+        Ok(Self { dir, key, write_key })
+    }
+    
+    /// Directory holding the blob files
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    
+    /// Names of the stored blobs, sorted
+    pub fn blob_names(&self) -> Result<Vec<String>, StateEncryptionError> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| StateEncryptionError::Io(format!("Failed to list {}: {}", self.dir.display(), e)))?;
+        
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == BLOB_EXTENSION))
+            .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+            .collect();
+        names.sort();
+        
+        Ok(names)
+    }
+    
+    /// Read and decrypt a blob, `None` if it was never written
+    pub fn read_blob(&self, name: &str) -> Result<Option<Secret<Vec<u8>>>, StateEncryptionError> {
+        let path = self.blob_path(name)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        
+        let sealed = fs::read(&path)
+            .map_err(|e| StateEncryptionError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+        
+        unseal(name, &sealed, &self.key, Some(&self.write_key)).map(Some)
+    }
+    
+    /// Encrypt and write a blob, replacing the previous one
+    pub fn write_blob(&self, name: &str, plaintext: &[u8]) -> Result<(), StateEncryptionError> {
+        let path = self.blob_path(name)?;
+        let sealed = seal(name, plaintext, &self.write_key)?;
+        
+        write_atomically(&path, &sealed).map_err(StateEncryptionError::Io)
+    }
+    
+    /// Re-encrypt every stored blob from the old key to the new one
+    ///
+    /// Every blob is decrypted before any is rewritten, so a wrong old key
+    /// changes nothing. Blobs already under the new key are rewritten as
+    /// they are, so an interrupted rotation can be run again. Returns the
+    /// number of blobs re-encrypted.
+    ///
+    /// Cancellation through `progress` is honoured only while decrypting;
+    /// once rewriting starts the rotation runs to the end.
+    pub fn rotate_key(&mut self, old: &StateKey, new: StateKey, progress: Option<&Progress>) -> Result<usize, StateEncryptionError> {
+        let idle = Progress::new();
+        let progress = progress.unwrap_or(&idle);
+        let names = self.blob_names()?;
+        
+        // Decrypt everything first
+        progress.start_stage("decrypt", names.len() as u64);
+        let mut blobs = Vec::with_capacity(names.len());
+        for name in names {
+            if progress.is_cancelled() {
+                return Err(StateEncryptionError::Cancelled { decrypted: blobs.len() });
+            }
+            
+            let path = self.blob_path(&name)?;
+            let sealed = fs::read(&path)
+                .map_err(|e| StateEncryptionError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+            let plaintext = match unseal(&name, &sealed, old, None) {
+                Err(StateEncryptionError::WrongKeyOrTampered { .. }) => unseal(&name, &sealed, &new, None)?,
+                result => result?,
+            };
+            blobs.push((name, plaintext));
+            progress.advance(1);
+        }
+        
+        // Then rewrite it under the new key
+        let write_key = new.derive_for_writing()?;
+        progress.start_stage("rewrite", blobs.len() as u64);
+        for (name, plaintext) in &blobs {
+            write_atomically(&self.blob_path(name)?, &seal(name, plaintext.expose_secret(), &write_key)?)
+                .map_err(StateEncryptionError::Io)?;
+            progress.advance(1);
+        }
+        
+        self.key = new;
+        self.write_key = write_key;
+        
+        info!(target: CONTRACT, "Re-encrypted {} state blobs in {}", blobs.len(), self.dir.display());
+        
+        Ok(blobs.len())
+    }
+    
+    /// File of a blob, rejecting names that could leave the directory
+    fn blob_path(&self, name: &str) -> Result<PathBuf, StateEncryptionError> {
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(StateEncryptionError::Io(format!("Invalid blob name {:?}", name)));
+        }
+        
+        Ok(self.dir.join(format!("{}.{}", name, BLOB_EXTENSION)))
+    }
+}
+
+/// Seal a blob under a derived key with a random nonce
+fn seal(name: &str, plaintext: &[u8], write_key: &DerivedKey) -> Result<Vec<u8>, StateEncryptionError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    
+    let header = Header { kdf: write_key.kdf.clone(), nonce }.to_bytes();
+    let aad = [header.as_slice(), name.as_bytes()].concat();
+    let ciphertext = aead::seal(&key_array(&write_key.key), &nonce, &aad, plaintext)
+        .map_err(|e| StateEncryptionError::Io(format!("Failed to encrypt {}: {}", name, e)))?;
+    
+    Ok([header, ciphertext].concat())
+}
+
+/// Check and decrypt a blob, reusing `cached` when the blob was sealed under it
+fn unseal(name: &str, sealed: &[u8], key: &StateKey, cached: Option<&DerivedKey>) -> Result<Secret<Vec<u8>>, StateEncryptionError> {
+    let header = Header::parse(name, sealed)?;
+    let wrong_key = || {
+        error!(target: CONTRACT, "Cannot decrypt state blob {}: wrong key or tampered ciphertext", name);
+        StateEncryptionError::WrongKeyOrTampered { blob: name.to_string() }
+    };
+    
+    let derived = match cached {
+        Some(cached) if cached.kdf == header.kdf => Some(cached.key.clone()),
+        _ => key.derive(&header.kdf)?,
+    };
+    let derived = derived.ok_or_else(wrong_key)?;
+    
+    let aad = [&sealed[..HEADER_LEN], name.as_bytes()].concat();
+    aead::open(&key_array(&derived), &header.nonce, &aad, &sealed[HEADER_LEN..])
+        .map(Secret::new)
+        .ok_or_else(wrong_key)
+}
+
+/// View a 32-byte key as an array
+fn key_array(key: &Secret<Vec<u8>>) -> [u8; KEY_LEN] {
+    let mut array = [0u8; KEY_LEN];
+    array.copy_from_slice(key.expose_secret());
+    array
+}
+
+impl StateStore for EncryptedStateStore {
+    fn load_state(&self) -> Result<Option<ContractState>, String> {
+        let Some(plaintext) = self.read_blob(STATE_BLOB).map_err(|e| e.to_string())? else {
+            return Ok(None);
+        };
+        
+        let value: Value = serde_json::from_slice(plaintext.expose_secret())
+            .map_err(|e| format!("Invalid encrypted snapshot: {}", e))?;
+        
+        parse_snapshot(value).map(Some)
+    }
+    
+    fn save_state(&self, state: &ContractState) -> Result<(), String> {
+        let json = Secret::new(serde_json::to_vec(state)
+            .map_err(|e| format!("Failed to serialize snapshot: {}", e))?);
+        
+        self.write_blob(STATE_BLOB, json.expose_secret()).map_err(|e| e.to_string())
+    }
+}
+
+impl TransferStateStore for EncryptedStateStore {
+    fn load_pending_payouts(&self) -> Result<Vec<PayoutEntry>, String> {
+        let Some(plaintext) = self.read_blob(PENDING_PAYOUTS_BLOB).map_err(|e| e.to_string())? else {
+            return Ok(Vec::new());
+        };
+        
+        serde_json::from_slice(plaintext.expose_secret())
+            .map_err(|e| format!("Invalid encrypted pending payouts: {}", e))
+    }
+    
+    fn save_pending_payouts(&self, entries: &[PayoutEntry]) -> Result<(), String> {
+        let json = Secret::new(serde_json::to_vec(entries)
+            .map_err(|e| format!("Failed to serialize pending payouts: {}", e))?);
+        
+        self.write_blob(PENDING_PAYOUTS_BLOB, json.expose_secret()).map_err(|e| e.to_string())
+    }
+    
+    fn load_operational_utxos(&self) -> Result<Vec<OperationalUtxo>, String> {
+        let Some(plaintext) = self.read_blob(OPERATIONAL_UTXOS_BLOB).map_err(|e| e.to_string())? else {
+            return Ok(Vec::new());
+        };
+        
+        serde_json::from_slice(plaintext.expose_secret())
+            .map_err(|e| format!("Invalid encrypted operational UTXOs: {}", e))
+    }
+    
+    fn save_operational_utxos(&self, utxos: &[OperationalUtxo]) -> Result<(), String> {
+        let json = Secret::new(serde_json::to_vec(utxos)
+            .map_err(|e| format!("Failed to serialize operational UTXOs: {}", e))?);
+        
+        self.write_blob(OPERATIONAL_UTXOS_BLOB, json.expose_secret()).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,267 @@
+//! Embedded key-value store
+//!
+//! Entries live in named trees and are held in memory; every write is a
+//! `KvBatch` appended to a single log file as one checksummed line and
+//! synced to disk before `apply` returns. Crash consistency rests on that:
+//! a batch is applied completely or not at all, because a reopened store
+//! replays every complete line and drops a trailing line that was torn by a
+//! crash mid-write. A damaged line followed by intact ones is not a torn
+//! write and fails `open` instead. `compact` rewrites the log as one batch of
+//! the live entries through a temporary file renamed over the log, so a crash
+//! during compaction leaves either the old or the new log.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::store::write_atomically;
+use crate::logging::CONTRACT;
+
+/// Single change of a batch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KvOp {
+    /// Set a key to a value
+    Put {
+        /// Tree name
+        tree: String,
+        /// Key
+        key: String,
+        /// Value
+        value: String,
+    },
+    /// Remove a key
+    Delete {
+        /// Tree name
+        tree: String,
+        /// Key
+        key: String,
+    },
+}
+
+/// Changes written together, all or none
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KvBatch {
+    /// Changes in the order they apply
+    ops: Vec<KvOp>,
+}
+
+impl KvBatch {
+    /// Create an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Set `key` in `tree` to `value`
+    pub fn put(&mut self, tree: &str, key: impl Into<String>, value: impl Into<String>) {
+        self.ops.push(KvOp::Put {
+            tree: tree.to_string(),
+            key: key.into(),
+            value: value.into(),
+        });
+    }
+    
+    /// Remove `key` from `tree`
+    pub fn delete(&mut self, tree: &str, key: impl Into<String>) {
+        self.ops.push(KvOp::Delete {
+            tree: tree.to_string(),
+            key: key.into(),
+        });
+    }
+    
+    /// Number of changes
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+    
+    /// Whether the batch changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Log-structured key-value store with named trees
+#[derive(Debug)]
+pub struct KvStore {
+    /// Log file
+    path: PathBuf,
+    /// Log file opened for appending
+    file: File,
+    /// Live entries by tree, then key
+    trees: BTreeMap<String, BTreeMap<String, String>>,
+    /// Number of batches in the log
+    log_batches: usize,
+}
+
+impl KvStore {
+    /// Open a store, creating its log file if needed and replaying the batches in it
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref().to_path_buf();
+        let mut trees = BTreeMap::new();
+        let mut log_batches = 0;
+        
+        let mut contents = Vec::new();
+        if path.exists() {
+            File::open(&path)
+                .and_then(|mut file| file.read_to_end(&mut contents))
+                .map_err(|e| format!("Failed to read store {}: {}", path.display(), e))?;
+        }
+        
+        // Replay complete batches, remembering where the last one ended
+        let mut valid_len = 0;
+        while valid_len < contents.len() {
+            let rest = &contents[valid_len..];
+            let line = match rest.iter().position(|byte| *byte == b'\n') {
+                Some(end) => &rest[..end],
+                None => break,
+            };
+            
+            match decode_batch(line) {
+                Some(ops) => {
+                    apply_ops(&mut trees, ops);
+                    log_batches += 1;
+                    valid_len += line.len() + 1;
+                },
+                None => break,
+            }
+        }
+        
+        // Only the final line may be damaged, by a crash while it was written
+        if valid_len < contents.len() {
+            let remainder = &contents[valid_len..];
+            let complete_lines = remainder.iter().filter(|byte| **byte == b'\n').count();
+            if complete_lines > 1 {
+                return Err(format!("Store {} is corrupt at byte {}", path.display(), valid_len));
+            }
+            
+            warn!(target: CONTRACT, "Dropping a torn batch of {} bytes at the end of store {}", remainder.len(), path.display());
+        }
+        
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open store {}: {}", path.display(), e))?;
+        
+        if valid_len < contents.len() {
+            file.set_len(valid_len as u64)
+                .and_then(|_| file.sync_all())
+                .map_err(|e| format!("Failed to truncate store {}: {}", path.display(), e))?;
+        }
+        
+        info!(target: CONTRACT, "Store {} opened with {} batches", path.display(), log_batches);
+        
+        Ok(Self {
+            path,
+            file,
+            trees,
+            log_batches,
+        })
+    }
+    
+    /// Get the value of a key
+    pub fn get(&self, tree: &str, key: &str) -> Option<&str> {
+        self.trees.get(tree)
+            .and_then(|entries| entries.get(key))
+            .map(|value| value.as_str())
+    }
+    
+    /// Iterate the entries of a tree in key order
+    pub fn iter<'a>(&'a self, tree: &str) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.trees.get(tree)
+            .into_iter()
+            .flat_map(|entries| entries.iter().map(|(key, value)| (key.as_str(), value.as_str())))
+    }
+    
+    /// Number of entries in a tree
+    pub fn len(&self, tree: &str) -> usize {
+        self.trees.get(tree).map(|entries| entries.len()).unwrap_or(0)
+    }
+    
+    /// Number of batches in the log, reset by `compact`
+    pub fn log_batches(&self) -> usize {
+        self.log_batches
+    }
+    
+    /// Write a batch durably, then apply it
+    pub fn apply(&mut self, batch: KvBatch) -> Result<(), String> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        
+        let line = encode_batch(&batch.ops)?;
+        
+        self.file.write_all(&line)
+            .and_then(|_| self.file.sync_data())
+            .map_err(|e| format!("Failed to write store {}: {}", self.path.display(), e))?;
+        
+        apply_ops(&mut self.trees, batch.ops);
+        self.log_batches += 1;
+        
+        Ok(())
+    }
+    
+    /// Rewrite the log as a single batch of the live entries
+    pub fn compact(&mut self) -> Result<(), String> {
+        let ops: Vec<KvOp> = self.trees.iter()
+            .flat_map(|(tree, entries)| entries.iter().map(move |(key, value)| KvOp::Put {
+                tree: tree.clone(),
+                key: key.clone(),
+                value: value.clone(),
+            }))
+            .collect();
+        
+        let contents = if ops.is_empty() { Vec::new() } else { encode_batch(&ops)? };
+        write_atomically(&self.path, &contents)?;
+        
+        // Appends have to go to the new file
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("Failed to open store {}: {}", self.path.display(), e))?;
+        self.log_batches = usize::from(!ops.is_empty());
+        
+        Ok(())
+    }
+}
+
+/// Encode a batch as `<sha256 hex> <json>\n`
+fn encode_batch(ops: &[KvOp]) -> Result<Vec<u8>, String> {
+    let json = serde_json::to_string(ops)
+        .map_err(|e| format!("Failed to serialize batch: {}", e))?;
+    let checksum = sha256::Hash::hash(json.as_bytes());
+    
+    Ok(format!("{} {}\n", checksum, json).into_bytes())
+}
+
+/// Decode a batch line, `None` if it is damaged
+fn decode_batch(line: &[u8]) -> Option<Vec<KvOp>> {
+    let line = std::str::from_utf8(line).ok()?;
+    let (checksum, json) = line.split_once(' ')?;
+    
+    if sha256::Hash::hash(json.as_bytes()).to_string() != checksum {
+        return None;
+    }
+    
+    serde_json::from_str(json).ok()
+}
+
+/// Apply decoded changes to the in-memory trees
+fn apply_ops(trees: &mut BTreeMap<String, BTreeMap<String, String>>, ops: Vec<KvOp>) {
+    for op in ops {
+        match op {
+            KvOp::Put { tree, key, value } => {
+                trees.entry(tree).or_default().insert(key, value);
+            },
+            KvOp::Delete { tree, key } => {
+                if let Some(entries) = trees.get_mut(&tree) {
+                    entries.remove(&key);
+                }
+            },
+        }
+    }
+}
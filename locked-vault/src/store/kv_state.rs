@@ -0,0 +1,320 @@
+//! Contract state in the embedded key-value store
+//!
+//! `KvStateStore` lays a contract snapshot out over these trees:
+//!
+//! - `deposits`: each deposit's JSON, keyed by its zero-padded ID so keys sort numerically
+//! - `user_index`: the deposit IDs of each depositor address
+//! - `counters`: `next_deposit_id` and `total_deposits`
+//! - `contract`: the rest of the snapshot, under `settings`
+//! - `pending_transfers`: payouts waiting for a batch, by queue position
+//! - `operational_utxos`: change tagged as operational, by `txid:vout`
+//! - `events`: emitted events, by sequence number
+//!
+//! `save_state` writes only the entries that changed, all in one batch, so a
+//! new deposit, its user index entry and the totals it moved reach the disk
+//! together or not at all (see the `kv` module for the guarantees).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+use log::{error, info};
+use serde_json::{Map, Value};
+
+use crate::bitcoin::payout::PayoutEntry;
+use crate::bitcoin::utxo::OperationalUtxo;
+use crate::contract::state::ContractState;
+use crate::events::{Event, EventSink};
+use crate::models::Deposit;
+use crate::store::kv::{KvBatch, KvStore};
+use crate::store::{parse_snapshot, JsonSnapshotStore, StateStore, TransferStateStore};
+use crate::logging::CONTRACT;
+
+/// Deposits by ID
+const DEPOSITS_TREE: &str = "deposits";
+
+/// Deposit IDs by depositor address
+const USER_INDEX_TREE: &str = "user_index";
+
+/// Counters and totals
+const COUNTERS_TREE: &str = "counters";
+
+/// Remaining contract settings
+const CONTRACT_TREE: &str = "contract";
+
+/// Payouts waiting for a batch
+const PENDING_TRANSFERS_TREE: &str = "pending_transfers";
+
+/// Change tagged as operational
+const OPERATIONAL_UTXOS_TREE: &str = "operational_utxos";
+
+/// Emitted events
+const EVENTS_TREE: &str = "events";
+
+/// Key of the settings in the contract tree
+const SETTINGS_KEY: &str = "settings";
+
+/// Snapshot fields kept in the counters tree
+const COUNTER_FIELDS: [&str; 2] = ["next_deposit_id", "total_deposits"];
+
+/// Contract state, pending transfers and events in one key-value store
+#[derive(Debug)]
+pub struct KvStateStore {
+    /// Underlying store
+    store: Mutex<KvStore>,
+}
+
+impl KvStateStore {
+    /// Open the store at `path`, creating it if needed
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        Ok(Self {
+            store: Mutex::new(KvStore::open(path)?),
+        })
+    }
+    
+    /// Look up a single deposit without loading the snapshot
+    pub fn deposit(&self, deposit_id: u64) -> Result<Option<Deposit>, String> {
+        let store = self.lock()?;
+        
+        store.get(DEPOSITS_TREE, &sequence_key(deposit_id))
+            .map(|json| serde_json::from_str(json)
+                .map_err(|e| format!("Invalid deposit {} in store: {}", deposit_id, e)))
+            .transpose()
+    }
+    
+    /// IDs of the deposits of a depositor, in deposit order
+    pub fn deposit_ids_of(&self, user_address: &str) -> Result<Vec<u64>, String> {
+        let store = self.lock()?;
+        
+        match store.get(USER_INDEX_TREE, user_address) {
+            Some(json) => serde_json::from_str(json)
+                .map_err(|e| format!("Invalid user index of {} in store: {}", user_address, e)),
+            None => Ok(Vec::new()),
+        }
+    }
+    
+    /// Events recorded by the store as a sink, oldest first
+    pub fn events(&self) -> Result<Vec<Event>, String> {
+        let store = self.lock()?;
+        
+        store.iter(EVENTS_TREE)
+            .map(|(key, json)| serde_json::from_str(json)
+                .map_err(|e| format!("Invalid event {} in store: {}", key, e)))
+            .collect()
+    }
+    
+    /// Rewrite the log of the store as its live entries
+    pub fn compact(&self) -> Result<(), String> {
+        self.lock()?.compact()
+    }
+    
+    /// Lock the underlying store
+    fn lock(&self) -> Result<MutexGuard<'_, KvStore>, String> {
+        self.store.lock()
+            .map_err(|_| "Store lock poisoned".to_string())
+    }
+}
+
+impl StateStore for KvStateStore {
+    fn load_state(&self) -> Result<Option<ContractState>, String> {
+        let store = self.lock()?;
+        
+        let settings = match store.get(CONTRACT_TREE, SETTINGS_KEY) {
+            Some(settings) => settings,
+            None => return Ok(None),
+        };
+        
+        // Reassemble the snapshot from its trees
+        let mut snapshot: Map<String, Value> = serde_json::from_str(settings)
+            .map_err(|e| format!("Invalid settings in store: {}", e))?;
+        
+        for (field, json) in store.iter(COUNTERS_TREE) {
+            let value = serde_json::from_str(json)
+                .map_err(|e| format!("Invalid counter {} in store: {}", field, e))?;
+            snapshot.insert(field.to_string(), value);
+        }
+        
+        let deposits = store.iter(DEPOSITS_TREE)
+            .map(|(key, json)| serde_json::from_str(json)
+                .map_err(|e| format!("Invalid deposit {} in store: {}", key, e)))
+            .collect::<Result<Vec<Value>, String>>()?;
+        snapshot.insert("deposits".to_string(), Value::Array(deposits));
+        
+        parse_snapshot(Value::Object(snapshot)).map(Some)
+    }
+    
+    fn save_state(&self, state: &ContractState) -> Result<(), String> {
+        let mut snapshot = match serde_json::to_value(state) {
+            Ok(Value::Object(snapshot)) => snapshot,
+            Ok(_) => return Err("Snapshot is not a JSON object".to_string()),
+            Err(e) => return Err(format!("Failed to serialize snapshot: {}", e)),
+        };
+        
+        // Deposits and the user index, one entry each
+        snapshot.remove("deposits");
+        let mut deposits = BTreeMap::new();
+        let mut user_index: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+        
+        for deposit in &state.deposits {
+            let json = serde_json::to_string(deposit)
+                .map_err(|e| format!("Failed to serialize deposit {}: {}", deposit.deposit_id, e))?;
+            deposits.insert(sequence_key(deposit.deposit_id), json);
+            user_index.entry(deposit.depositor_address.clone()).or_default().push(deposit.deposit_id);
+        }
+        
+        let user_index = user_index.into_iter()
+            .map(|(user, ids)| (user, Value::from(ids).to_string()))
+            .collect();
+        
+        // Counters, then everything else as one settings entry
+        let counters = COUNTER_FIELDS.iter()
+            .filter_map(|field| snapshot.remove(*field).map(|value| (field.to_string(), value.to_string())))
+            .collect();
+        
+        let settings = BTreeMap::from([(SETTINGS_KEY.to_string(), Value::Object(snapshot).to_string())]);
+        
+        let mut store = self.lock()?;
+        let mut batch = KvBatch::new();
+        sync_tree(&store, &mut batch, DEPOSITS_TREE, deposits);
+        sync_tree(&store, &mut batch, USER_INDEX_TREE, user_index);
+        sync_tree(&store, &mut batch, COUNTERS_TREE, counters);
+        sync_tree(&store, &mut batch, CONTRACT_TREE, settings);
+        
+        store.apply(batch)
+    }
+}
+
+impl TransferStateStore for KvStateStore {
+    fn load_pending_payouts(&self) -> Result<Vec<PayoutEntry>, String> {
+        let store = self.lock()?;
+        
+        store.iter(PENDING_TRANSFERS_TREE)
+            .map(|(key, json)| serde_json::from_str(json)
+                .map_err(|e| format!("Invalid pending payout {} in store: {}", key, e)))
+            .collect()
+    }
+    
+    fn save_pending_payouts(&self, entries: &[PayoutEntry]) -> Result<(), String> {
+        let pending = entries.iter()
+            .enumerate()
+            .map(|(position, entry)| {
+                serde_json::to_string(entry)
+                    .map(|json| (sequence_key(position as u64), json))
+                    .map_err(|e| format!("Failed to serialize pending payout: {}", e))
+            })
+            .collect::<Result<BTreeMap<String, String>, String>>()?;
+        
+        let mut store = self.lock()?;
+        let mut batch = KvBatch::new();
+        sync_tree(&store, &mut batch, PENDING_TRANSFERS_TREE, pending);
+        
+        store.apply(batch)
+    }
+    
+    fn load_operational_utxos(&self) -> Result<Vec<OperationalUtxo>, String> {
+        let store = self.lock()?;
+        
+        store.iter(OPERATIONAL_UTXOS_TREE)
+            .map(|(key, json)| serde_json::from_str(json)
+                .map_err(|e| format!("Invalid operational UTXO {} in store: {}", key, e)))
+            .collect()
+    }
+    
+    fn save_operational_utxos(&self, utxos: &[OperationalUtxo]) -> Result<(), String> {
+        let operational = utxos.iter()
+            .map(|utxo| {
+                serde_json::to_string(utxo)
+                    .map(|json| (utxo.reference.to_string(), json))
+                    .map_err(|e| format!("Failed to serialize operational UTXO: {}", e))
+            })
+            .collect::<Result<BTreeMap<String, String>, String>>()?;
+        
+        let mut store = self.lock()?;
+        let mut batch = KvBatch::new();
+        sync_tree(&store, &mut batch, OPERATIONAL_UTXOS_TREE, operational);
+        
+        store.apply(batch)
+    }
+}
+
+impl EventSink for KvStateStore {
+    fn publish(&self, event: &Event) {
+        let json = match serde_json::to_string(event) {
+            Ok(json) => json,
+            Err(e) => {
+                error!(target: CONTRACT, "Failed to serialize {} event for the store: {}", event.name(), e);
+                return;
+            },
+        };
+        
+        let mut store = match self.lock() {
+            Ok(store) => store,
+            Err(e) => {
+                error!(target: CONTRACT, "Failed to record {} event: {}", event.name(), e);
+                return;
+            },
+        };
+        
+        let mut batch = KvBatch::new();
+        batch.put(EVENTS_TREE, sequence_key(store.len(EVENTS_TREE) as u64), json);
+        
+        if let Err(e) = store.apply(batch) {
+            error!(target: CONTRACT, "Failed to record {} event: {}", event.name(), e);
+        }
+    }
+}
+
+/// Zero-padded key, so keys sort like the numbers
+fn sequence_key(sequence: u64) -> String {
+    format!("{:020}", sequence)
+}
+
+/// Add the changes that make a tree hold exactly `entries` to a batch
+fn sync_tree(store: &KvStore, batch: &mut KvBatch, tree: &str, entries: BTreeMap<String, String>) {
+    for (key, _) in store.iter(tree) {
+        if !entries.contains_key(key) {
+            batch.delete(tree, key);
+        }
+    }
+    
+    for (key, value) in entries {
+        if store.get(tree, &key) != Some(value.as_str()) {
+            batch.put(tree, key, value);
+        }
+    }
+}
+
+/// Convert a JSON snapshot file into a new key-value store, returning the number of deposits
+pub fn migrate_json_to_kv(json_path: &Path, kv_path: &Path) -> Result<usize, String> {
+    if kv_path.exists() {
+        return Err(format!("{} already exists", kv_path.display()));
+    }
+    
+    let state = JsonSnapshotStore::new(json_path).load_state()?
+        .ok_or_else(|| format!("Snapshot {} does not exist", json_path.display()))?;
+    
+    KvStateStore::open(kv_path)?.save_state(&state)?;
+    
+    info!(target: CONTRACT, "Migrated {} deposits from {} to {}", state.deposits.len(), json_path.display(), kv_path.display());
+    
+    Ok(state.deposits.len())
+}
+
+/// Write the snapshot of a key-value store to a new JSON file, returning the number of deposits
+pub fn migrate_kv_to_json(kv_path: &Path, json_path: &Path) -> Result<usize, String> {
+    if json_path.exists() {
+        return Err(format!("{} already exists", json_path.display()));
+    }
+    
+    if !kv_path.exists() {
+        return Err(format!("Store {} does not exist", kv_path.display()));
+    }
+    
+    let state = KvStateStore::open(kv_path)?.load_state()?
+        .ok_or_else(|| format!("Store {} holds no contract state", kv_path.display()))?;
+    
+    JsonSnapshotStore::new(json_path).save_state(&state)?;
+    
+    info!(target: CONTRACT, "Migrated {} deposits from {} to {}", state.deposits.len(), kv_path.display(), json_path.display());
+    
+    Ok(state.deposits.len())
+}
@@ -0,0 +1,139 @@
+//! Persistence of contract state
+//!
+//! A `StateStore` keeps the latest contract snapshot and a
+//! `TransferStateStore` the payouts still waiting for a batch and the
+//! change tagged as operational.
+//! `JsonSnapshotStore` writes the whole snapshot as one file, JSON or with
+//! the binary snapshot codec, which is simple but rewrites every deposit on
+//! each save. With the `kv-store`
+//! feature `KvStateStore` keeps deposits, user indexes, counters, pending
+//! transfers and events in separate trees of an embedded key-value store and
+//! only writes what changed. `EncryptedStateStore` keeps the same state
+//! encrypted at rest, under a key file or a passphrase.
+
+pub(crate) mod aead;
+pub(crate) mod argon2;
+pub mod codec;
+pub mod encrypted;
+#[cfg(feature = "kv-store")]
+pub mod kv;
+#[cfg(feature = "kv-store")]
+pub mod kv_state;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+
+use crate::bitcoin::payout::PayoutEntry;
+use crate::bitcoin::utxo::OperationalUtxo;
+use crate::contract::migration::migrate_to_current;
+use crate::contract::state::ContractState;
+
+// Re-export commonly used types
+pub use argon2::Argon2Params;
+pub use codec::{decode_snapshot, encode_snapshot, SnapshotCodec};
+pub use encrypted::{EncryptedStateStore, StateKey};
+#[cfg(feature = "kv-store")]
+pub use kv::{KvBatch, KvStore};
+#[cfg(feature = "kv-store")]
+pub use kv_state::{migrate_json_to_kv, migrate_kv_to_json, KvStateStore};
+
+/// Storage of the contract snapshot
+pub trait StateStore: fmt::Debug + Send + Sync {
+    /// Load the last saved snapshot, `None` if nothing was saved yet
+    fn load_state(&self) -> Result<Option<ContractState>, String>;
+    
+    /// Save a snapshot, replacing the previous one
+    fn save_state(&self, state: &ContractState) -> Result<(), String>;
+}
+
+/// Storage of payouts waiting for a batch and of operational change
+pub trait TransferStateStore {
+    /// Load the waiting payouts, in queue order
+    fn load_pending_payouts(&self) -> Result<Vec<PayoutEntry>, String>;
+    
+    /// Save the waiting payouts, replacing the previous queue
+    fn save_pending_payouts(&self, entries: &[PayoutEntry]) -> Result<(), String>;
+    
+    /// Load the change tagged as operational, in reference order
+    fn load_operational_utxos(&self) -> Result<Vec<OperationalUtxo>, String>;
+    
+    /// Save the change tagged as operational, replacing the previous set
+    fn save_operational_utxos(&self, utxos: &[OperationalUtxo]) -> Result<(), String>;
+}
+
+/// Contract snapshot kept as a single file, JSON unless another codec is set
+/// 
+/// Loading accepts a snapshot in any codec.
+#[derive(Debug, Clone)]
+pub struct JsonSnapshotStore {
+    /// Snapshot file
+    path: PathBuf,
+    /// Codec snapshots are written in
+    codec: SnapshotCodec,
+}
+
+impl JsonSnapshotStore {
+    /// Create a store writing the snapshot to `path` as JSON
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), codec: SnapshotCodec::Json }
+    }
+    
+    /// Write snapshots with a codec instead
+    pub fn with_codec(mut self, codec: SnapshotCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+    
+    /// Snapshot file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl StateStore for JsonSnapshotStore {
+    fn load_state(&self) -> Result<Option<ContractState>, String> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        
+        let raw = fs::read(&self.path)
+            .map_err(|e| format!("Failed to read snapshot {}: {}", self.path.display(), e))?;
+        
+        decode_snapshot(&raw)
+            .map(Some)
+            .map_err(|e| format!("{} ({})", e, self.path.display()))
+    }
+    
+    fn save_state(&self, state: &ContractState) -> Result<(), String> {
+        write_atomically(&self.path, &encode_snapshot(state, self.codec)?)
+    }
+}
+
+/// Upgrade a raw snapshot to the current schema and decode it
+pub(crate) fn parse_snapshot(value: Value) -> Result<ContractState, String> {
+    let value = migrate_to_current(value)
+        .map_err(|e| e.to_string())?;
+    
+    serde_json::from_value(value)
+        .map_err(|e| format!("Invalid snapshot: {}", e))
+}
+
+/// Replace a file so a crash leaves either the old or the new contents
+///
+/// The contents go to a temporary file next to `path`, which is synced and
+/// then renamed over `path`.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let temporary = path.with_extension("tmp");
+    
+    let mut file = File::create(&temporary)
+        .map_err(|e| format!("Failed to create {}: {}", temporary.display(), e))?;
+    file.write_all(contents)
+        .and_then(|_| file.sync_all())
+        .map_err(|e| format!("Failed to write {}: {}", temporary.display(), e))?;
+    
+    fs::rename(&temporary, path)
+        .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
@@ -0,0 +1,348 @@
+//! Several vaults hosted by one process
+//!
+//! A `VaultManager` hosts named vaults, one per tenant. Each vault is a
+//! `TimeLockedDeposit` of its own, created with its own owner and settings,
+//! so deposit IDs, limits, fee pools and events are never shared between
+//! tenants. Vaults may each get their own transfer backend, or share one by
+//! being handed clones of an `Arc` around it; a shared backend moves the
+//! funds of every vault through the same wallet, while each vault still only
+//! accounts for its own deposits.
+//!
+//! Every vault persists into its own directory under the manager's root,
+//! named after the vault: the snapshot at `state.json` and, if enabled, the
+//! event log at `events.jsonl`. Vault names are restricted so that they map
+//! to exactly one directory and never to one outside the root. A vault whose
+//! directory already holds a snapshot is restored from it when created.
+//!
+//! Archiving a vault saves it one last time and moves its directory under
+//! `.archived`, which no vault name can reach. It is refused while any
+//! deposit of the vault is pending, active, suspended or being withdrawn.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use log::info;
+
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::event_log::{EventLog, EventLogConfig};
+use crate::events::{Event, EventSink};
+use crate::metrics::MetricsRegistry;
+use crate::models::{DepositStatus, TokenTransfer};
+use crate::store::{JsonSnapshotStore, SnapshotCodec, StateStore};
+use crate::logging::DAEMON;
+
+/// Directory vaults are kept under unless configured otherwise
+pub const DEFAULT_VAULT_ROOT: &str = "vaults";
+
+/// Directory under the root that archived vaults are moved to
+pub const ARCHIVE_DIR: &str = ".archived";
+
+/// Longest vault name
+const MAX_VAULT_NAME_LEN: usize = 64;
+
+/// Check a vault name can be used as its directory name
+///
+/// Names are 1 to 64 lowercase ASCII letters, digits, `-` and `_`, and start
+/// with a letter or digit.
+pub fn validate_vault_name(name: &str) -> Result<(), String> {
+    let valid_chars = name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    let valid_start = name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit());
+    
+    if name.len() > MAX_VAULT_NAME_LEN || !valid_chars || !valid_start {
+        return Err(format!(
+            "Invalid vault name: {:?} (1 to {} lowercase letters, digits, - and _, starting with a letter or digit)",
+            name, MAX_VAULT_NAME_LEN
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Files of one vault under a root directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultNamespace {
+    /// Name of the vault
+    name: String,
+    /// Directory holding the vault's files
+    dir: PathBuf,
+}
+
+impl VaultNamespace {
+    /// Namespace of the vault `name` under `root`
+    pub fn new(root: impl AsRef<Path>, name: &str) -> Result<Self, String> {
+        validate_vault_name(name)?;
+        
+        Ok(Self {
+            name: name.to_string(),
+            dir: root.as_ref().join(name),
+        })
+    }
+    
+    /// Name of the vault
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    
+    /// Directory holding the vault's files
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    
+    /// Snapshot file of the vault
+    pub fn state_path(&self) -> PathBuf {
+        self.dir.join("state.json")
+    }
+    
+    /// Event log of the vault
+    pub fn event_log_path(&self) -> PathBuf {
+        self.dir.join("events.jsonl")
+    }
+}
+
+/// Settings a vault is created with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultSettings {
+    /// Owner of the vault
+    pub owner_address: String,
+    /// Emergency withdrawal fee, in percent
+    pub emergency_withdrawal_fee_percentage: u8,
+    /// Codec the vault's snapshots are written in
+    pub snapshot_codec: SnapshotCodec,
+    /// Whether to record the vault's events in its event log
+    pub event_log: bool,
+}
+
+impl VaultSettings {
+    /// Settings for a vault owned by `owner_address`, with a 10% emergency fee, JSON snapshots and no event log
+    pub fn new(owner_address: impl Into<String>) -> Self {
+        Self {
+            owner_address: owner_address.into(),
+            emergency_withdrawal_fee_percentage: 10,
+            snapshot_codec: SnapshotCodec::default(),
+            event_log: false,
+        }
+    }
+}
+
+/// Vault hosted by a manager
+struct Tenant<T: TokenTransfer> {
+    /// The vault's contract
+    contract: Arc<Mutex<TimeLockedDeposit<T>>>,
+    /// Where the vault persists
+    namespace: VaultNamespace,
+    /// Settings the vault was created with
+    settings: VaultSettings,
+    /// The vault's event log, if enabled
+    event_log: Option<Arc<EventLog>>,
+}
+
+/// Counts the events of one vault in the metrics registry
+#[derive(Debug)]
+pub struct TenantMetricsSink {
+    /// Name of the vault
+    vault: String,
+    /// Registry holding the counters
+    metrics: Arc<MetricsRegistry>,
+}
+
+impl TenantMetricsSink {
+    /// Count the events of `vault` in `metrics`
+    pub fn new(vault: impl Into<String>, metrics: Arc<MetricsRegistry>) -> Self {
+        Self { vault: vault.into(), metrics }
+    }
+}
+
+impl EventSink for TenantMetricsSink {
+    fn publish(&self, event: &Event) {
+        self.metrics.labeled_counter("vault_events", &[("vault", &self.vault), ("event", event.name())]).inc();
+    }
+}
+
+/// Named vaults of several tenants in one process
+pub struct VaultManager<T: TokenTransfer> {
+    /// Directory holding a directory per vault
+    root: PathBuf,
+    /// Hosted vaults, by name
+    vaults: BTreeMap<String, Tenant<T>>,
+    /// Counters of every vault, labelled with the vault's name
+    metrics: Arc<MetricsRegistry>,
+}
+
+impl<T: TokenTransfer> fmt::Debug for VaultManager<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VaultManager")
+            .field("root", &self.root)
+            .field("vaults", &self.vaults.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: TokenTransfer> VaultManager<T> {
+    /// Create a manager keeping its vaults under `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            vaults: BTreeMap::new(),
+            metrics: Arc::new(MetricsRegistry::new()),
+        }
+    }
+    
+    /// Directory holding a directory per vault
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    
+    /// Registry of the per-vault counters
+    pub fn metrics(&self) -> Arc<MetricsRegistry> {
+        self.metrics.clone()
+    }
+    
+    /// Create a vault, or restore it from the snapshot in its directory
+    ///
+    /// `token_transfer` is the vault's own backend or a clone of a shared one.
+    /// A restored vault keeps the owner and fee of its snapshot; the other
+    /// settings apply either way. Fails if the name is invalid or taken.
+    pub fn create_vault(&mut self, name: &str, settings: VaultSettings, token_transfer: T) -> Result<Arc<Mutex<TimeLockedDeposit<T>>>, String> {
+        let namespace = VaultNamespace::new(&self.root, name)?;
+        if self.vaults.contains_key(name) {
+            return Err(format!("Vault {} already exists", name));
+        }
+        
+        fs::create_dir_all(namespace.dir())
+            .map_err(|e| format!("Failed to create vault directory {}: {}", namespace.dir().display(), e))?;
+        
+        let store = JsonSnapshotStore::new(namespace.state_path()).with_codec(settings.snapshot_codec);
+        let mut contract = match store.load_state()? {
+            Some(state) => {
+                info!(target: DAEMON, "Vault {} restored from {}", name, namespace.state_path().display());
+                TimeLockedDeposit::from_state(state, token_transfer)
+            },
+            None => TimeLockedDeposit::new_with_defaults(
+                settings.owner_address.clone(),
+                settings.emergency_withdrawal_fee_percentage,
+                token_transfer,
+            ),
+        }.map_err(|e| format!("Failed to initialize vault {}: {}", name, e))?;
+        
+        contract.add_event_sink(Arc::new(TenantMetricsSink::new(name, self.metrics.clone())));
+        
+        let event_log = if settings.event_log {
+            let log = Arc::new(EventLog::open(EventLogConfig::new(namespace.event_log_path()), None)?);
+            contract.add_event_sink(log.clone());
+            Some(log)
+        } else {
+            None
+        };
+        
+        // Outbox commits are written to the vault's own snapshot
+        contract.set_state_store(Arc::new(store.clone()));
+        
+        // Write the snapshot right away so the directory always holds one
+        store.save_state(&contract.export_state())?;
+        
+        let contract = Arc::new(Mutex::new(contract));
+        self.vaults.insert(name.to_string(), Tenant {
+            contract: contract.clone(),
+            namespace,
+            settings,
+            event_log,
+        });
+        
+        info!(target: DAEMON, "Vault {} created", name);
+        
+        Ok(contract)
+    }
+    
+    /// Get a vault's contract
+    pub fn vault(&self, name: &str) -> Option<Arc<Mutex<TimeLockedDeposit<T>>>> {
+        self.vaults.get(name).map(|tenant| tenant.contract.clone())
+    }
+    
+    /// Names of the hosted vaults, sorted
+    pub fn vault_names(&self) -> Vec<String> {
+        self.vaults.keys().cloned().collect()
+    }
+    
+    /// Get a vault's namespace
+    pub fn namespace(&self, name: &str) -> Option<&VaultNamespace> {
+        self.vaults.get(name).map(|tenant| &tenant.namespace)
+    }
+    
+    /// Get a vault's event log, if it records one
+    pub fn event_log(&self, name: &str) -> Option<Arc<EventLog>> {
+        self.vaults.get(name).and_then(|tenant| tenant.event_log.clone())
+    }
+    
+    /// Run an operation on a vault, counting it for the vault
+    pub fn with_vault<R>(&self, name: &str, operation: impl FnOnce(&mut TimeLockedDeposit<T>) -> R) -> Result<R, String> {
+        let tenant = self.vaults.get(name).ok_or_else(|| format!("Unknown vault: {}", name))?;
+        let mut contract = lock(&tenant.contract)?;
+        
+        self.metrics.labeled_counter("vault_operations", &[("vault", name)]).inc();
+        
+        Ok(operation(&mut contract))
+    }
+    
+    /// Save a vault's snapshot into its directory
+    pub fn save_vault(&self, name: &str) -> Result<(), String> {
+        let tenant = self.vaults.get(name).ok_or_else(|| format!("Unknown vault: {}", name))?;
+        let state = lock(&tenant.contract)?.export_state();
+        
+        JsonSnapshotStore::new(tenant.namespace.state_path())
+            .with_codec(tenant.settings.snapshot_codec)
+            .save_state(&state)
+    }
+    
+    /// Save the snapshot of every vault, stopping at the first failure
+    pub fn save_all(&self) -> Result<(), String> {
+        self.vaults.keys().try_for_each(|name| self.save_vault(name))
+    }
+    
+    /// Stop hosting a vault that holds no funds and move its directory to the archive
+    ///
+    /// Fails, leaving the vault hosted, while any of its deposits is pending,
+    /// active, suspended or being withdrawn. Returns where the vault's files
+    /// were moved.
+    pub fn archive_vault(&mut self, name: &str) -> Result<PathBuf, String> {
+        let tenant = self.vaults.get(name).ok_or_else(|| format!("Unknown vault: {}", name))?;
+        
+        let locked = lock(&tenant.contract)?.deposit_registry.values()
+            .filter(|deposit| matches!(
+                deposit.status,
+                DepositStatus::Pending | DepositStatus::Active | DepositStatus::Suspended | DepositStatus::WithdrawalPending
+            ))
+            .count();
+        if locked > 0 {
+            return Err(format!("Vault {} still holds {} deposits with locked funds", name, locked));
+        }
+        
+        self.save_vault(name)?;
+        
+        let archive = self.root.join(ARCHIVE_DIR);
+        fs::create_dir_all(&archive)
+            .map_err(|e| format!("Failed to create archive directory {}: {}", archive.display(), e))?;
+        
+        // A vault archived under the same name before keeps its files
+        let mut destination = archive.join(name);
+        let mut attempt = 1;
+        while destination.exists() {
+            attempt += 1;
+            destination = archive.join(format!("{}-{}", name, attempt));
+        }
+        
+        fs::rename(tenant.namespace.dir(), &destination)
+            .map_err(|e| format!("Failed to archive vault {}: {}", name, e))?;
+        self.vaults.remove(name);
+        
+        info!(target: DAEMON, "Vault {} archived to {}", name, destination.display());
+        
+        Ok(destination)
+    }
+}
+
+/// Lock a vault's contract
+fn lock<T: TokenTransfer>(contract: &Mutex<TimeLockedDeposit<T>>) -> Result<MutexGuard<'_, TimeLockedDeposit<T>>, String> {
+    contract.lock().map_err(|_| "Contract lock poisoned".to_string())
+}
@@ -0,0 +1,364 @@
+//! Property-based accounting invariants
+//!
+//! Random sequences of deposits, withdrawals, limit changes, pauses, backend
+//! failures, insurance payouts and clock advances run against an in-memory
+//! transfer backend. The contract's accounting is checked after every step;
+//! proptest shrinks any failure to a minimal sequence of operations.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use proptest::prelude::*;
+
+use crate::bitcoin::incoming::IncomingFunds;
+use crate::clock::MockClock;
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::contract::insurance::CompensationTarget;
+use crate::errors::ContractError;
+use crate::events::Event;
+use crate::models::{DepositFee, TokenTransfer, TokenType};
+
+/// Depositor addresses the operations pick from
+const USERS: [&str; 3] = ["alice", "bob", "carol"];
+
+/// Transfer backend with unlimited balances that can be told to fail
+#[derive(Debug, Clone, Default)]
+struct FlakyTransfer {
+    /// Whether transfers currently fail
+    failing: Arc<AtomicBool>,
+}
+
+impl FlakyTransfer {
+    /// Fail the transfer if the backend is currently down
+    fn check(&self) -> Result<(), String> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err("backend unavailable".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl TokenTransfer for FlakyTransfer {
+    fn transfer_to_contract(&self, _from_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+        self.check()
+    }
+    
+    fn transfer_from_contract(&self, _to_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+        self.check()
+    }
+    
+    fn get_balance(&self, _address: &str, _token_type: &TokenType) -> Result<u64, String> {
+        Ok(u64::MAX)
+    }
+    
+    fn supports_token_type(&self, _token_type: &TokenType) -> bool {
+        true
+    }
+    
+    fn get_network_type(&self) -> String {
+        "testnet".to_string()
+    }
+}
+
+/// Operation applied to the contract
+#[derive(Debug, Clone)]
+enum Op {
+    /// Deposit by a user
+    Deposit { user: usize, token: usize, amount: u64, lock_days: u32 },
+    /// Regular withdrawal, by the owner of the deposit or someone else
+    Withdraw { deposit: u64, by_owner: bool },
+    /// Emergency withdrawal, by the owner of the deposit or someone else
+    EmergencyWithdraw { deposit: u64, by_owner: bool },
+    /// Fee withdrawal by the contract owner
+    WithdrawFees { token: usize, amount: Option<u64> },
+    /// Change the deposit fee of a token
+    SetDepositFee { token: usize, fee: Option<DepositFee> },
+    /// Change the deposit limits
+    SetLimits { max_deposits_per_user: Option<u32>, max_total_deposits: Option<u64> },
+    /// Pause or unpause the contract
+    SetPaused(bool),
+    /// Take the transfer backend down or bring it back
+    SetBackendFailing(bool),
+    /// Advance the clock by some hours
+    AdvanceClock(u32),
+    /// Archive withdrawn deposit IDs
+    Maintenance,
+    /// Name a beneficiary for a deposit, by its owner
+    SetInheritance { deposit: u64, beneficiary: usize, inactivity_days: u32 },
+    /// Claim a deposit as a beneficiary
+    ClaimInherited { deposit: u64, beneficiary: usize },
+    /// Funds arriving at the contract wallet without a deposit
+    ReceiveFunds { vout: u32, amount: u64 },
+    /// Turn received funds into a deposit of a user
+    AttributeFunds { vout: u32, user: usize, lock_days: u32 },
+    /// Refund received funds
+    RefundFunds { vout: u32 },
+    /// Change the insurance share of emergency fees
+    SetInsuranceShare(u8),
+    /// Compensate a user from the insurance pool of a token
+    Compensate { user: usize, token: usize, amount: u64 },
+}
+
+/// Token types the operations pick from
+fn tokens() -> Vec<TokenType> {
+    vec![TokenType::Bitcoin, TokenType::Lightning, TokenType::Rune("RUNE_TOKEN".to_string())]
+}
+
+/// Amounts biased towards the edges of the accepted range
+fn amount() -> impl Strategy<Value = u64> {
+    prop_oneof![
+        1u64..10_000,
+        (u64::MAX / 2 - 1_000)..=(u64::MAX / 2 + 1),
+        (u64::MAX - 10)..=u64::MAX,
+        any::<u64>(),
+    ]
+}
+
+/// Any operation
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..USERS.len(), 0..tokens().len(), amount(), 0u32..60)
+            .prop_map(|(user, token, amount, lock_days)| Op::Deposit { user, token, amount, lock_days }),
+        3 => (1u64..20, any::<bool>()).prop_map(|(deposit, by_owner)| Op::Withdraw { deposit, by_owner }),
+        3 => (1u64..20, any::<bool>()).prop_map(|(deposit, by_owner)| Op::EmergencyWithdraw { deposit, by_owner }),
+        1 => (0..tokens().len(), proptest::option::of(amount()))
+            .prop_map(|(token, amount)| Op::WithdrawFees { token, amount }),
+        1 => (0..tokens().len(), proptest::option::of(prop_oneof![
+            amount().prop_map(DepositFee::Flat),
+            (0u8..100).prop_map(DepositFee::Percentage),
+        ])).prop_map(|(token, fee)| Op::SetDepositFee { token, fee }),
+        1 => (proptest::option::of(0u32..5), proptest::option::of(amount()))
+            .prop_map(|(max_deposits_per_user, max_total_deposits)| Op::SetLimits { max_deposits_per_user, max_total_deposits }),
+        1 => any::<bool>().prop_map(Op::SetPaused),
+        1 => any::<bool>().prop_map(Op::SetBackendFailing),
+        2 => (0u32..24 * 40).prop_map(Op::AdvanceClock),
+        1 => Just(Op::Maintenance),
+        1 => (1u64..20, 0..USERS.len(), 0u32..30)
+            .prop_map(|(deposit, beneficiary, inactivity_days)| Op::SetInheritance { deposit, beneficiary, inactivity_days }),
+        2 => (1u64..20, 0..USERS.len()).prop_map(|(deposit, beneficiary)| Op::ClaimInherited { deposit, beneficiary }),
+        1 => (0u32..4, amount()).prop_map(|(vout, amount)| Op::ReceiveFunds { vout, amount }),
+        1 => (0u32..4, 0..USERS.len(), 0u32..60).prop_map(|(vout, user, lock_days)| Op::AttributeFunds { vout, user, lock_days }),
+        1 => (0u32..4).prop_map(|vout| Op::RefundFunds { vout }),
+        1 => (0u8..=110).prop_map(Op::SetInsuranceShare),
+        1 => (0..USERS.len(), 0..tokens().len(), prop_oneof![1u64..10_000, amount()])
+            .prop_map(|(user, token, amount)| Op::Compensate { user, token, amount }),
+    ]
+}
+
+/// Operation that accrues or sweeps fees
+fn fee_op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (0..USERS.len(), 0..tokens().len(), prop_oneof![1u64..10_000, amount()], 0u32..60)
+            .prop_map(|(user, token, amount, lock_days)| Op::Deposit { user, token, amount, lock_days }),
+        4 => (1u64..30).prop_map(|deposit| Op::EmergencyWithdraw { deposit, by_owner: true }),
+        2 => (0..tokens().len(), proptest::option::of(1u64..500))
+            .prop_map(|(token, amount)| Op::WithdrawFees { token, amount }),
+        1 => (0..tokens().len(), proptest::option::of(prop_oneof![
+            (1u64..100).prop_map(DepositFee::Flat),
+            (0u8..10).prop_map(DepositFee::Percentage),
+        ])).prop_map(|(token, fee)| Op::SetDepositFee { token, fee }),
+        1 => (0u8..=100).prop_map(Op::SetInsuranceShare),
+        2 => (0u32..24 * 40).prop_map(Op::AdvanceClock),
+    ]
+}
+
+/// Contract, clock and backend switch under test
+struct Harness {
+    /// Contract under test
+    contract: TimeLockedDeposit<FlakyTransfer>,
+    /// Injected clock
+    clock: Arc<MockClock>,
+    /// Backend failure switch
+    failing: Arc<AtomicBool>,
+    /// Successful withdrawals per deposit ID
+    withdrawals: HashMap<u64, u32>,
+}
+
+impl Harness {
+    /// Build a contract with no emergency waiting period
+    fn new() -> Self {
+        let transfer = FlakyTransfer::default();
+        let failing = transfer.failing.clone();
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        
+        let mut contract = TimeLockedDeposit::new("owner_address".to_string(), 10, transfer, tokens()).unwrap();
+        contract.set_clock(clock.clone());
+        contract.set_min_age_before_emergency("owner_address".to_string(), std::time::Duration::ZERO, None).unwrap();
+        
+        Harness {
+            contract,
+            clock,
+            failing,
+            withdrawals: HashMap::new(),
+        }
+    }
+    
+    /// Caller for an operation on a deposit
+    fn caller_for(&self, deposit_id: u64, by_owner: bool) -> String {
+        match self.contract.deposit_registry.get(&deposit_id) {
+            Some(deposit) if by_owner => deposit.depositor_address.clone(),
+            _ => "mallory".to_string(),
+        }
+    }
+    
+    /// Apply an operation, returning the result of contract calls
+    fn apply(&mut self, op: &Op) -> Result<Option<Event>, ContractError> {
+        let tokens = tokens();
+        
+        match op {
+            Op::Deposit { user, token, amount, lock_days } => self.contract
+                .deposit(USERS[*user].to_string(), tokens[*token].clone(), *amount, *lock_days, None)
+                .map(Some),
+            Op::Withdraw { deposit, by_owner } => {
+                let caller = self.caller_for(*deposit, *by_owner);
+                self.contract.withdraw(caller, *deposit).map(Some)
+            },
+            Op::EmergencyWithdraw { deposit, by_owner } => {
+                let caller = self.caller_for(*deposit, *by_owner);
+                self.contract.emergency_withdraw(caller, *deposit).map(Some)
+            },
+            Op::WithdrawFees { token, amount } => self.contract
+                .withdraw_fees("owner_address".to_string(), tokens[*token].clone(), *amount, None, None)
+                .map(Some),
+            Op::SetDepositFee { token, fee } => self.contract
+                .set_deposit_fee("owner_address".to_string(), tokens[*token].clone(), *fee, None)
+                .map(|_| None),
+            Op::SetLimits { max_deposits_per_user, max_total_deposits } => {
+                self.contract.deposit_limits.max_deposits_per_user = *max_deposits_per_user;
+                self.contract.deposit_limits.max_total_deposits = *max_total_deposits;
+                Ok(None)
+            },
+            Op::SetPaused(paused) => {
+                self.contract.is_contract_paused = *paused;
+                Ok(None)
+            },
+            Op::SetBackendFailing(failing) => {
+                self.failing.store(*failing, Ordering::SeqCst);
+                Ok(None)
+            },
+            Op::AdvanceClock(hours) => {
+                self.clock.advance(chrono::Duration::hours(*hours as i64));
+                Ok(None)
+            },
+            Op::Maintenance => self.contract
+                .perform_maintenance("owner_address".to_string(), None)
+                .map(|_| None),
+            Op::SetInheritance { deposit, beneficiary, inactivity_days } => {
+                let caller = self.caller_for(*deposit, true);
+                self.contract.set_inheritance(caller, *deposit, USERS[*beneficiary].to_string(), *inactivity_days).map(Some)
+            },
+            Op::ClaimInherited { deposit, beneficiary } => self.contract
+                .claim_inherited(USERS[*beneficiary].to_string(), *deposit)
+                .map(Some),
+            Op::ReceiveFunds { vout, amount } => Ok(self.contract.record_incoming_funds(&IncomingFunds {
+                txid: "cd".repeat(32),
+                vout: *vout,
+                amount: *amount,
+                sender_addresses: Vec::new(),
+                confirmations: 6,
+            })),
+            Op::AttributeFunds { vout, user, lock_days } => self.contract
+                .attribute_funds("owner_address".to_string(), &Self::outpoint(*vout), USERS[*user].to_string(), *lock_days, None)
+                .map(Some),
+            Op::RefundFunds { vout } => self.contract
+                .refund_unattributed("owner_address".to_string(), &Self::outpoint(*vout), USERS[0].to_string(), None)
+                .map(Some),
+            Op::SetInsuranceShare(percentage) => self.contract
+                .set_insurance_share("owner_address".to_string(), *percentage, None)
+                .map(|_| None),
+            Op::Compensate { user, token, amount } => self.contract
+                .compensate_from_insurance(
+                    "owner_address".to_string(),
+                    CompensationTarget::Address { address: USERS[*user].to_string(), token_type: tokens[*token].clone() },
+                    *amount,
+                    "lost to a backend failure".to_string(),
+                    None,
+                )
+                .map(Some),
+        }
+    }
+    
+    /// Reference of a received output
+    fn outpoint(vout: u32) -> String {
+        format!("{}:{}", "cd".repeat(32), vout)
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+    
+    #[test]
+    fn prop_accounting_invariants_hold(ops in proptest::collection::vec(op(), 1..40)) {
+        let mut harness = Harness::new();
+        
+        for op in &ops {
+            let before = harness.contract.accounting_snapshot();
+            
+            // Errors are fine, panics (including overflow) and drift are not
+            let result = harness.apply(op);
+            
+            if let Err(reason) = harness.contract.check_accounting_invariants() {
+                prop_assert!(false, "after {:?} ({:?}): {}", op, result, reason);
+            }
+            
+            // Collected fees only decrease through fee withdrawals and withdrawn deposits stay withdrawn
+            let withdrew_fees = matches!(op, Op::WithdrawFees { .. });
+            if let Err(reason) = harness.contract.check_transition_invariants(&before, withdrew_fees) {
+                prop_assert!(false, "after {:?}: {}", op, reason);
+            }
+            
+            // The insurance pool only shrinks by compensation, which never exceeds it
+            let paid_out = match &result {
+                Ok(Some(Event::InsuranceCompensationPaid { token_type, amount, .. })) => Some((token_type, *amount)),
+                _ => None,
+            };
+            if let Err(reason) = harness.contract.check_insurance_transition(&before, paid_out) {
+                prop_assert!(false, "after {:?}: {}", op, reason);
+            }
+            
+            // Withdrawn deposits pay out once
+            if let Ok(Some(event)) = &result {
+                if matches!(event, Event::Withdrawn { .. } | Event::EmergencyWithdrawn { .. } | Event::InheritanceClaimed { .. }) {
+                    let deposit_id = event.deposit_id().unwrap();
+                    let count = harness.withdrawals.entry(deposit_id).or_insert(0);
+                    *count += 1;
+                    prop_assert_eq!(*count, 1, "deposit {} was withdrawn twice", deposit_id);
+                }
+            }
+        }
+    }
+    
+    #[test]
+    fn prop_fee_ledger_reconciles_with_collected_fees(ops in proptest::collection::vec(fee_op(), 1..60)) {
+        let mut harness = Harness::new();
+        
+        for op in &ops {
+            let _ = harness.apply(op);
+        }
+        
+        // Accruals minus sweeps equal the collected fees of every token
+        for token_type in tokens() {
+            let accrued: u128 = harness.contract
+                .get_fee_accruals(&token_type, chrono::DateTime::<chrono::Utc>::MIN_UTC, chrono::DateTime::<chrono::Utc>::MAX_UTC)
+                .iter()
+                .map(|accrual| accrual.amount as u128)
+                .sum();
+            let sweeps = harness.contract.get_fee_sweeps(&token_type);
+            let swept: u128 = sweeps.iter().map(|sweep| sweep.amount as u128).sum();
+            let collected = harness.contract.fee_config.collected_fees.get(&token_type).copied().unwrap_or(0) as u128;
+            prop_assert_eq!(accrued - swept, collected, "{:?}", token_type);
+            
+            // Sweeps draw on accruals in order
+            let mut previous = None;
+            for sweep in &sweeps {
+                prop_assert!(sweep.first_accrual.is_some() && sweep.first_accrual <= sweep.last_accrual, "{:?}", sweep);
+                prop_assert!(previous <= sweep.first_accrual, "{:?} after {:?}", sweep, previous);
+                previous = sweep.last_accrual;
+            }
+        }
+        
+        // The ledger survives a snapshot
+        let restored = TimeLockedDeposit::from_state(harness.contract.export_state(), FlakyTransfer::default()).unwrap();
+        prop_assert_eq!(&restored.fee_ledger, &harness.contract.fee_ledger);
+    }
+}
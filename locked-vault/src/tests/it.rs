@@ -0,0 +1,647 @@
+//! End-to-end scenarios against a Bitcoin regtest chain
+//!
+//! By default the scenarios run against an in-memory chain that mimics the
+//! handful of wallet RPCs the harness needs. With the `regtest-tests` feature
+//! they drive a real `bitcoind -regtest` instead: one is spawned when
+//! `BITCOIND_PATH` is set, or an existing node is used when `REGTEST_RPC_URL`
+//! (plus `REGTEST_RPC_USER` / `REGTEST_RPC_PASSWORD`) is set.
+//!
+//! ```text
+//! BITCOIND_PATH=/usr/local/bin/bitcoind cargo test --features regtest-tests it::
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use chrono::{Duration, Utc};
+
+use crate::contract::contract_core::TimeLockedDeposit;
+use crate::errors::ContractError;
+use crate::models::{TokenTransfer, TokenType};
+
+/// Blocks a coinbase output needs before it can be spent
+const COINBASE_MATURITY: u64 = 100;
+
+/// Block subsidy on a fresh regtest chain
+const BLOCK_SUBSIDY: u64 = 50 * 100_000_000;
+
+/// Float kept in the contract wallet to pay network fees on withdrawals
+const CONTRACT_FEE_FLOAT: u64 = 100_000;
+
+/// Wallet operations the harness needs from a regtest chain
+///
+/// Every participant owns one wallet, identified by its receive address.
+pub(crate) trait RegtestChain: Send + Sync {
+    /// Create a wallet and return its receive address
+    fn create_wallet(&self, name: &str) -> Result<String, String>;
+    
+    /// Mine blocks paying the subsidy to an address
+    fn generate_to_address(&self, blocks: u64, address: &str) -> Result<(), String>;
+    
+    /// Send an exact amount from one wallet to another, returning the txid
+    fn send(&self, from: &str, to: &str, amount: u64) -> Result<String, String>;
+    
+    /// Spendable balance of the wallet owning an address
+    fn balance(&self, address: &str) -> Result<u64, String>;
+    
+    /// Unspent outputs (`txid:vout`, amount) of the wallet owning an address
+    fn utxos(&self, address: &str) -> Result<Vec<(String, u64)>, String>;
+    
+    /// Check whether an output is still unspent
+    fn is_unspent(&self, utxo_reference: &str) -> Result<bool, String>;
+    
+    /// Network fee a single transaction may cost the sender
+    fn fee_allowance(&self) -> u64;
+}
+
+/// Output tracked by the in-memory chain
+#[derive(Debug, Clone)]
+struct MockOutput {
+    /// Owning address
+    address: String,
+    /// Amount in satoshis
+    amount: u64,
+    /// Height of the block that created a coinbase output
+    coinbase_height: Option<u64>,
+}
+
+/// State of the in-memory chain
+#[derive(Debug, Default)]
+struct MockChainState {
+    /// Current block height
+    height: u64,
+    /// Counter for deterministic txids
+    next_tx: u64,
+    /// Known wallet addresses
+    wallets: Vec<String>,
+    /// Unspent outputs keyed by `txid:vout`
+    utxos: HashMap<String, MockOutput>,
+}
+
+/// Deterministic in-memory chain used when no node is configured
+#[derive(Debug, Default)]
+pub(crate) struct MockChain {
+    /// Chain state
+    state: Mutex<MockChainState>,
+}
+
+impl MockChainState {
+    /// Allocate the next txid
+    fn next_txid(&mut self) -> String {
+        self.next_tx += 1;
+        format!("{:064x}", self.next_tx)
+    }
+    
+    /// Whether an output can be spent at the current height
+    fn is_spendable(&self, output: &MockOutput) -> bool {
+        match output.coinbase_height {
+            Some(height) => self.height - height >= COINBASE_MATURITY,
+            None => true,
+        }
+    }
+}
+
+impl RegtestChain for MockChain {
+    fn create_wallet(&self, name: &str) -> Result<String, String> {
+        let mut state = self.state.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        let address = format!("bcrt1q{}{}", name, state.wallets.len());
+        state.wallets.push(address.clone());
+        Ok(address)
+    }
+    
+    fn generate_to_address(&self, blocks: u64, address: &str) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        
+        for _ in 0..blocks {
+            state.height += 1;
+            let txid = state.next_txid();
+            let height = state.height;
+            
+            state.utxos.insert(format!("{}:0", txid), MockOutput {
+                address: address.to_string(),
+                amount: BLOCK_SUBSIDY,
+                coinbase_height: Some(height),
+            });
+        }
+        
+        Ok(())
+    }
+    
+    fn send(&self, from: &str, to: &str, amount: u64) -> Result<String, String> {
+        let mut state = self.state.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        
+        // Select spendable outputs in a stable order
+        let mut candidates: Vec<(String, u64)> = state.utxos.iter()
+            .filter(|(_, output)| output.address == from && state.is_spendable(output))
+            .map(|(reference, output)| (reference.clone(), output.amount))
+            .collect();
+        candidates.sort();
+        
+        let mut selected = Vec::new();
+        let mut total = 0u64;
+        for (reference, value) in candidates {
+            if total >= amount {
+                break;
+            }
+            total += value;
+            selected.push(reference);
+        }
+        
+        if total < amount {
+            return Err(format!("Insufficient funds: have {}, need {}", total, amount));
+        }
+        
+        for reference in &selected {
+            state.utxos.remove(reference);
+        }
+        
+        let txid = state.next_txid();
+        state.utxos.insert(format!("{}:0", txid), MockOutput {
+            address: to.to_string(),
+            amount,
+            coinbase_height: None,
+        });
+        
+        if total > amount {
+            state.utxos.insert(format!("{}:1", txid), MockOutput {
+                address: from.to_string(),
+                amount: total - amount,
+                coinbase_height: None,
+            });
+        }
+        
+        Ok(txid)
+    }
+    
+    fn balance(&self, address: &str) -> Result<u64, String> {
+        Ok(self.utxos(address)?.iter().map(|(_, amount)| amount).sum())
+    }
+    
+    fn utxos(&self, address: &str) -> Result<Vec<(String, u64)>, String> {
+        let state = self.state.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        
+        let mut utxos: Vec<(String, u64)> = state.utxos.iter()
+            .filter(|(_, output)| output.address == address && state.is_spendable(output))
+            .map(|(reference, output)| (reference.clone(), output.amount))
+            .collect();
+        utxos.sort();
+        
+        Ok(utxos)
+    }
+    
+    fn is_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+        let state = self.state.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+        Ok(state.utxos.contains_key(utxo_reference))
+    }
+    
+    fn fee_allowance(&self) -> u64 {
+        0
+    }
+}
+
+#[cfg(feature = "regtest-tests")]
+mod bitcoind {
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::process::{Child, Command, Stdio};
+    use std::str::FromStr;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, Instant};
+    use bitcoincore_rpc::{Auth, Client, RpcApi};
+    use bitcoincore_rpc::bitcoin::{Address, Amount, Txid};
+    use bitcoincore_rpc::json::AddressType;
+    
+    use super::RegtestChain;
+    
+    /// Counter making wallet names unique on a shared node
+    static WALLET_COUNTER: AtomicU64 = AtomicU64::new(0);
+    
+    /// Chain backed by a `bitcoind -regtest` node
+    pub(crate) struct BitcoindChain {
+        /// Spawned node process, if the harness started one
+        process: Option<Child>,
+        /// Data directory of a spawned node
+        _datadir: Option<tempfile::TempDir>,
+        /// RPC URL of the node
+        rpc_url: String,
+        /// RPC credentials
+        auth: (String, String),
+        /// Node-level client
+        node: Client,
+        /// Wallet clients keyed by receive address
+        wallets: Mutex<HashMap<String, Client>>,
+    }
+    
+    impl BitcoindChain {
+        /// Connect to the node configured in the environment, if any
+        pub(crate) fn from_env() -> Option<Result<Self, String>> {
+            if let Ok(path) = std::env::var("BITCOIND_PATH") {
+                return Some(Self::spawn(&path));
+            }
+            
+            let rpc_url = std::env::var("REGTEST_RPC_URL").ok()?;
+            let user = std::env::var("REGTEST_RPC_USER").unwrap_or_default();
+            let password = std::env::var("REGTEST_RPC_PASSWORD").unwrap_or_default();
+            
+            Some(Self::connect(rpc_url, (user, password), None, None))
+        }
+        
+        /// Spawn a throwaway node in a temporary data directory
+        fn spawn(path: &str) -> Result<Self, String> {
+            let datadir = tempfile::tempdir().map_err(|e| format!("Failed to create datadir: {}", e))?;
+            let rpc_port = free_port()?;
+            let p2p_port = free_port()?;
+            
+            let process = Command::new(path)
+                .arg("-regtest")
+                .arg("-server=1")
+                .arg("-listen=0")
+                .arg("-fallbackfee=0.0001")
+                .arg(format!("-datadir={}", datadir.path().display()))
+                .arg(format!("-rpcport={}", rpc_port))
+                .arg(format!("-port={}", p2p_port))
+                .arg("-rpcuser=vault")
+                .arg("-rpcpassword=vault")
+                .stdout(Stdio::null())
+                .spawn()
+                .map_err(|e| format!("Failed to spawn bitcoind: {}", e))?;
+            
+            Self::connect(
+                format!("http://127.0.0.1:{}", rpc_port),
+                ("vault".to_string(), "vault".to_string()),
+                Some(process),
+                Some(datadir),
+            )
+        }
+        
+        /// Connect to a node and wait until it answers RPC calls
+        fn connect(
+            rpc_url: String,
+            auth: (String, String),
+            process: Option<Child>,
+            datadir: Option<tempfile::TempDir>,
+        ) -> Result<Self, String> {
+            let node = Client::new(&rpc_url, Auth::UserPass(auth.0.clone(), auth.1.clone()))
+                .map_err(|e| format!("Failed to create RPC client: {}", e))?;
+            
+            let chain = Self {
+                process,
+                _datadir: datadir,
+                rpc_url,
+                auth,
+                node,
+                wallets: Mutex::new(HashMap::new()),
+            };
+            
+            // The node needs a moment to start accepting RPC calls
+            let started = Instant::now();
+            loop {
+                match chain.node.get_blockchain_info() {
+                    Ok(info) if info.chain == "regtest" => return Ok(chain),
+                    Ok(info) => return Err(format!("Expected regtest, node is on {}", info.chain)),
+                    Err(_) if started.elapsed() < Duration::from_secs(30) => std::thread::sleep(Duration::from_millis(200)),
+                    Err(e) => return Err(format!("Node did not become ready: {}", e)),
+                }
+            }
+        }
+        
+        /// Run a closure with the wallet client owning an address
+        fn with_wallet<R>(&self, address: &str, f: impl FnOnce(&Client) -> Result<R, String>) -> Result<R, String> {
+            let wallets = self.wallets.lock().map_err(|_| "Failed to acquire lock".to_string())?;
+            let wallet = wallets.get(address).ok_or_else(|| format!("Unknown wallet for {}", address))?;
+            f(wallet)
+        }
+    }
+    
+    /// Parse a regtest address
+    fn parse_address(address: &str) -> Result<Address, String> {
+        Address::from_str(address)
+            .map(|address| address.assume_checked())
+            .map_err(|e| format!("Invalid address {}: {}", address, e))
+    }
+    
+    /// Find a free local port
+    fn free_port() -> Result<u16, String> {
+        TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map(|address| address.port())
+            .map_err(|e| format!("Failed to find a free port: {}", e))
+    }
+    
+    impl RegtestChain for BitcoindChain {
+        fn create_wallet(&self, name: &str) -> Result<String, String> {
+            let wallet_name = format!("{}-{}-{}", name, std::process::id(), WALLET_COUNTER.fetch_add(1, Ordering::SeqCst));
+            
+            self.node.create_wallet(&wallet_name, None, None, None, None)
+                .map_err(|e| format!("Failed to create wallet: {}", e))?;
+            
+            let wallet = Client::new(
+                &format!("{}/wallet/{}", self.rpc_url, wallet_name),
+                Auth::UserPass(self.auth.0.clone(), self.auth.1.clone()),
+            ).map_err(|e| format!("Failed to create wallet client: {}", e))?;
+            
+            let address = wallet.get_new_address(None, Some(AddressType::Bech32))
+                .map_err(|e| format!("Failed to get address: {}", e))?
+                .assume_checked()
+                .to_string();
+            
+            self.wallets.lock()
+                .map_err(|_| "Failed to acquire lock".to_string())?
+                .insert(address.clone(), wallet);
+            
+            Ok(address)
+        }
+        
+        fn generate_to_address(&self, blocks: u64, address: &str) -> Result<(), String> {
+            self.node.generate_to_address(blocks, &parse_address(address)?)
+                .map(|_| ())
+                .map_err(|e| format!("Failed to generate blocks: {}", e))
+        }
+        
+        fn send(&self, from: &str, to: &str, amount: u64) -> Result<String, String> {
+            let to = parse_address(to)?;
+            
+            self.with_wallet(from, |wallet| {
+                wallet.send_to_address(&to, Amount::from_sat(amount), None, None, None, None, None, None)
+                    .map(|txid| txid.to_string())
+                    .map_err(|e| format!("Failed to send: {}", e))
+            })
+        }
+        
+        fn balance(&self, address: &str) -> Result<u64, String> {
+            self.with_wallet(address, |wallet| {
+                wallet.get_balance(None, None)
+                    .map(|balance| balance.to_sat())
+                    .map_err(|e| format!("Failed to get balance: {}", e))
+            })
+        }
+        
+        fn utxos(&self, address: &str) -> Result<Vec<(String, u64)>, String> {
+            self.with_wallet(address, |wallet| {
+                let mut utxos: Vec<(String, u64)> = wallet.list_unspent(None, None, None, None, None)
+                    .map_err(|e| format!("Failed to list unspent: {}", e))?
+                    .into_iter()
+                    .map(|utxo| (format!("{}:{}", utxo.txid, utxo.vout), utxo.amount.to_sat()))
+                    .collect();
+                utxos.sort();
+                Ok(utxos)
+            })
+        }
+        
+        fn is_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+            let (txid, vout) = utxo_reference.split_once(':')
+                .ok_or_else(|| format!("Invalid UTXO reference: {}", utxo_reference))?;
+            let txid = Txid::from_str(txid).map_err(|e| format!("Invalid txid: {}", e))?;
+            let vout = vout.parse::<u32>().map_err(|e| format!("Invalid vout: {}", e))?;
+            
+            self.node.get_tx_out(&txid, vout, Some(true))
+                .map(|out| out.is_some())
+                .map_err(|e| format!("Failed to get transaction output: {}", e))
+        }
+        
+        fn fee_allowance(&self) -> u64 {
+            10_000
+        }
+    }
+    
+    impl Drop for BitcoindChain {
+        fn drop(&mut self) {
+            if let Some(mut process) = self.process.take() {
+                let _ = self.node.stop();
+                let _ = process.wait();
+            }
+        }
+    }
+}
+
+/// Pick the chain backend: a real node when configured, the in-memory chain otherwise
+fn regtest_chain() -> Arc<dyn RegtestChain> {
+    #[cfg(feature = "regtest-tests")]
+    if let Some(chain) = bitcoind::BitcoindChain::from_env() {
+        return Arc::new(chain.expect("Failed to start regtest node"));
+    }
+    
+    Arc::new(MockChain::default())
+}
+
+/// Token transfer moving real (regtest) coins between participant wallets
+pub(crate) struct RegtestTransfer {
+    /// Chain backend
+    chain: Arc<dyn RegtestChain>,
+    /// Miner address, used to confirm transfers
+    miner: String,
+    /// Contract wallet address
+    contract_address: String,
+}
+
+impl std::fmt::Debug for RegtestTransfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegtestTransfer")
+            .field("contract_address", &self.contract_address)
+            .finish()
+    }
+}
+
+impl RegtestTransfer {
+    /// Send and confirm a payment
+    fn send_confirmed(&self, from: &str, to: &str, amount: u64) -> Result<(), String> {
+        self.chain.send(from, to, amount)?;
+        self.chain.generate_to_address(1, &self.miner)
+    }
+}
+
+impl TokenTransfer for RegtestTransfer {
+    fn transfer_to_contract(&self, from_address: &str, _token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.send_confirmed(from_address, &self.contract_address, amount)
+    }
+    
+    fn transfer_from_contract(&self, to_address: &str, _token_type: &TokenType, amount: u64) -> Result<(), String> {
+        self.send_confirmed(&self.contract_address, to_address, amount)
+    }
+    
+    fn get_balance(&self, address: &str, _token_type: &TokenType) -> Result<u64, String> {
+        self.chain.balance(address)
+    }
+    
+    fn supports_token_type(&self, token_type: &TokenType) -> bool {
+        matches!(token_type, TokenType::Bitcoin)
+    }
+    
+    fn get_network_type(&self) -> String {
+        "regtest".to_string()
+    }
+    
+    fn get_contract_balance(&self, _token_type: &TokenType) -> Result<u64, String> {
+        self.chain.balance(&self.contract_address)
+    }
+    
+    fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, String> {
+        self.chain.is_unspent(utxo_reference)
+    }
+}
+
+/// A contract wired to funded regtest wallets
+pub(crate) struct RegtestVault {
+    /// Chain backend
+    pub(crate) chain: Arc<dyn RegtestChain>,
+    /// Miner address
+    pub(crate) miner: String,
+    /// Depositor address
+    pub(crate) depositor: String,
+    /// Owner and fee collector address
+    pub(crate) collector: String,
+    /// Contract wallet address
+    pub(crate) contract_address: String,
+    /// Contract under test
+    pub(crate) contract: TimeLockedDeposit<RegtestTransfer>,
+}
+
+impl RegtestVault {
+    /// Create wallets, mature the miner's coinbase and deploy the contract with a 10% fee
+    pub(crate) fn new() -> Self {
+        let chain = regtest_chain();
+        
+        let miner = chain.create_wallet("miner").unwrap();
+        let depositor = chain.create_wallet("depositor").unwrap();
+        let collector = chain.create_wallet("collector").unwrap();
+        let contract_address = chain.create_wallet("contract").unwrap();
+        
+        chain.generate_to_address(COINBASE_MATURITY + 1, &miner).unwrap();
+        
+        // The contract wallet pays network fees on withdrawals
+        if chain.fee_allowance() > 0 {
+            chain.send(&miner, &contract_address, CONTRACT_FEE_FLOAT).unwrap();
+            chain.generate_to_address(1, &miner).unwrap();
+        }
+        
+        let transfer = RegtestTransfer {
+            chain: chain.clone(),
+            miner: miner.clone(),
+            contract_address: contract_address.clone(),
+        };
+        
+        let contract = TimeLockedDeposit::new_with_defaults(collector.clone(), 10, transfer).unwrap();
+        
+        Self {
+            chain,
+            miner,
+            depositor,
+            collector,
+            contract_address,
+            contract,
+        }
+    }
+    
+    /// Set the emergency withdrawal fee percentage
+    pub(crate) fn with_fee(mut self, fee_percentage: u8) -> Self {
+        assert!(fee_percentage <= 100);
+        self.contract.fee_config.emergency_withdrawal_fee_percentage = fee_percentage;
+        self
+    }
+    
+    /// Send coins from the miner to the depositor and confirm them
+    pub(crate) fn fund_depositor(self, amount: u64) -> Self {
+        self.chain.send(&self.miner, &self.depositor, amount).unwrap();
+        self.mine(1);
+        self
+    }
+    
+    /// Mine blocks to the miner
+    pub(crate) fn mine(&self, blocks: u64) {
+        self.chain.generate_to_address(blocks, &self.miner).unwrap();
+    }
+    
+    /// Let a deposit's time lock expire
+    ///
+    /// The contract locks on wall-clock time, so after mining a day's worth of
+    /// blocks the unlock timestamp is moved into the past.
+    pub(crate) fn mature(&mut self, deposit_id: u64) {
+        self.mine(144);
+        
+        let deposit = self.contract.deposit_registry.get_mut(&deposit_id).unwrap();
+        deposit.unlock_timestamp = Utc::now() - Duration::seconds(1);
+    }
+    
+    /// Spendable balance of a participant
+    pub(crate) fn balance(&self, address: &str) -> u64 {
+        self.chain.balance(address).unwrap()
+    }
+    
+    /// Assert a balance, allowing for the network fees of `transactions` sends
+    pub(crate) fn assert_balance(&self, address: &str, expected: u64, transactions: u64) {
+        let balance = self.balance(address);
+        let allowance = self.chain.fee_allowance() * transactions;
+        
+        assert!(
+            balance <= expected && balance + allowance >= expected,
+            "balance of {} is {}, expected {} (fee allowance {})", address, balance, expected, allowance,
+        );
+    }
+    
+    /// Assert a participant holds an unspent output of exactly `amount`
+    pub(crate) fn assert_has_utxo(&self, address: &str, amount: u64) {
+        let utxos = self.chain.utxos(address).unwrap();
+        
+        assert!(
+            utxos.iter().any(|(_, value)| *value == amount),
+            "no UTXO of {} for {}, found {:?}", amount, address, utxos,
+        );
+    }
+}
+
+#[test]
+fn test_regtest_withdraw_after_maturity() {
+    let mut vault = RegtestVault::new().with_fee(10).fund_depositor(50_000);
+    let depositor = vault.depositor.clone();
+    
+    vault.contract.deposit(depositor.clone(), TokenType::Bitcoin, 30_000, 1, None).unwrap();
+    vault.assert_balance(&depositor, 20_000, 1);
+    
+    // Locked until maturity
+    let result = vault.contract.withdraw(depositor.clone(), 1);
+    assert!(matches!(result, Err(ContractError::DepositLocked)));
+    
+    vault.mature(1);
+    vault.contract.withdraw(depositor.clone(), 1).unwrap();
+    
+    // The full amount comes back as its own output
+    vault.assert_balance(&depositor, 50_000, 1);
+    vault.assert_has_utxo(&depositor, 30_000);
+    assert!(vault.contract.expected_holdings().is_empty());
+}
+
+#[test]
+fn test_regtest_emergency_withdraw_pays_collector() {
+    let mut vault = RegtestVault::new().with_fee(10).fund_depositor(50_000);
+    let depositor = vault.depositor.clone();
+    let collector = vault.collector.clone();
+    
+    vault.contract.deposit(depositor.clone(), TokenType::Bitcoin, 40_000, 30, None).unwrap();
+    vault.contract.emergency_withdraw(depositor.clone(), 1).unwrap();
+    
+    // Depositor gets the deposit minus the 10% fee
+    vault.assert_has_utxo(&depositor, 36_000);
+    vault.assert_balance(&depositor, 46_000, 1);
+    assert_eq!(vault.contract.expected_holdings().get(&TokenType::Bitcoin), Some(&4_000));
+    
+    // The fee lands in the collector wallet
+    assert_eq!(vault.balance(&collector), 0);
+    vault.contract.withdraw_all_fees(collector.clone(), TokenType::Bitcoin, None).unwrap();
+    vault.assert_has_utxo(&collector, 4_000);
+    assert_eq!(vault.balance(&collector), 4_000);
+}
+
+#[test]
+fn test_regtest_insufficient_balance_deposit_rejected() {
+    let mut vault = RegtestVault::new().fund_depositor(10_000);
+    let depositor = vault.depositor.clone();
+    let contract_balance = vault.balance(&vault.contract_address);
+    
+    let result = vault.contract.deposit(depositor.clone(), TokenType::Bitcoin, 20_000, 30, None);
+    assert!(matches!(result, Err(ContractError::InsufficientBalance { .. })));
+    
+    // No coins moved and nothing was recorded
+    assert_eq!(vault.balance(&depositor), 10_000);
+    assert_eq!(vault.balance(&vault.contract_address), contract_balance);
+    assert!(vault.contract.deposit_registry.is_empty());
+}
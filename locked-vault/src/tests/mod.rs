@@ -1,25 +1,66 @@
+// End-to-end regtest scenarios
+mod it;
+
+// Property-based accounting invariants
+mod invariants;
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
     use std::time::Duration;
     use bitcoincore_rpc::bitcoin::Network;
     use bitcoincore_rpc::bitcoin::secp256k1; // Use secp256k1 from bitcoincore-rpc
-    use crate::bitcoin::testnet::{BitcoinTestnetConfig, utils};
+    use crate::bitcoin::testnet::{BitcoinTestnetConfig, CacheConfig, FeeTargets, PayoutBatchConfig, utils};
     use crate::bitcoin::transfer::BitcoinTestnetTransfer;
-    use crate::bitcoin::rpc::BitcoinRpcClient;
-    use crate::bitcoin::utxo::{Utxo, UtxoSet};
-    use crate::bitcoin::lightning::{LightningClient, InvoiceStatus, ChannelStatus};
-    use crate::bitcoin::ordinals::OrdinalsClient;
+    use crate::bitcoin::feature_gate::{FeatureGate, FeatureGateConfig, GateState};
+    use crate::bitcoin::fee_oracle::{ConfirmationTarget, FeeRate, FeeRateOrigin};
+    use crate::bitcoin::rpc::{BitcoinRpcClient, DuplicateOutputs};
+    use crate::bitcoin::template::{DataOutputs, DestinationPolicy};
+    use crate::bitcoin::network::{AddressNetwork, NetworkGuard};
+    use crate::bitcoin::utxo::{CoinControl, DustChange, DustPolicy, OperationalUtxo, ScriptType, SelectionStrategy, SweepInputs, Utxo, UtxoRef, UtxoSet};
+    use crate::bitcoin::lightning::{LightningClient, InvoiceStatus, ChannelStatus, PaymentParams, PaymentStatus};
+    use crate::bitcoin::bolt11::{Bolt11Invoice, RouteHint, RouteHintHop};
+    use crate::bitcoin::ordinals::{Inscription, OrdinalsApiFlavor, OrdinalsClient, SatInfo, SatRarity};
     use crate::bitcoin::mempool::MempoolMonitor;
-    use crate::bitcoin::multisig::{MultisigClient, MultisigTxStatus};
-    use crate::bitcoin::signature::SignatureVerifier;
+    use crate::bitcoin::reorg::ReorgNotice;
+    use crate::bitcoin::multisig::{MultisigClient, MultisigTxStatus, MultisigWallet, MusigSpendStage, WalletKind};
+    use crate::bitcoin::descriptor::{descriptor_address, MultisigPolicy, MusigEscrowPolicy};
+    use crate::bitcoin::signature::{KeySigner, MusigKeyAgg, SignatureVerifier, Signer};
+    use crate::bitcoin::health::{BackendComponent, HealthChecker, HealthProbe};
+    use crate::bitcoin::cache::BalanceCache;
+    use crate::introspection::{MEMPOOL_MONITOR_WORKER, TRANSFER_PROCESSOR_WORKER};
+    use crate::metrics::MetricsRegistry;
+    use crate::server::{ApiAuth, ApiKeyStore, ApiServer, Capability, HttpRequest};
+    use crate::webhook::{WebhookConfig, WebhookSink};
+    use crate::event_log::{verify_event_log, EventLog, EventLogConfig};
+    use crate::ledger::{LedgerEntry, LedgerKind};
+    use crate::tvl::{MetricsRecorderConfig, SeriesTier};
+    use crate::clock::{Clock, MockClock};
     use crate::contract::contract_core::TimeLockedDeposit;
-    use crate::models::{TokenType, TokenTransfer};
-    use crate::errors::ContractError;
+    use crate::contract::audit::ReconciliationConfig;
+    use crate::contract::receipt::DepositReceipt;
+    use crate::contract::governance::{OwnerAction, ProposalStatus};
+    use crate::contract::insurance::CompensationTarget;
+    use crate::contract::schedule::ScheduleGranularity;
+    use crate::contract::forecast::{ForecastBucket, LiquidityForecaster, ProjectedShortfall, MAX_FORECAST_WEEKS};
+    use crate::contract::treasury::{SweepProposal, TreasuryPolicy};
+    use crate::contract::import::{ImportedDeposit, ImportManifest, IMPORTED_DEPOSIT_ID_OFFSET};
+    use crate::contract::circuit_breaker::{CircuitBreakerConfig, PauseReason};
+    use crate::contract::token_pause::TokenPausePolicy;
+    use crate::contract::recovery::WithdrawalCorrection;
+    use crate::contract::outbox::OutboxOperation;
+    use crate::contract::refund::{RefundPolicy, RefundReason};
+    use crate::contract::state::ContractState;
+    use crate::store::StateStore;
+    use crate::contract::token_support::DeprecationReason;
+    use crate::contract::deposit_id::{IdScheme, MAX_DEPOSIT_ID_ATTEMPTS};
+    use crate::models::{AmountConstraints, AssetDetail, DepositFee, DepositStatus, TokenType, TokenTransfer, TransferPriority, WithdrawOptions, MAX_AMOUNT};
+    use crate::errors::{ContractError, ErrorCode, MigrationError, StateEncryptionError, TransferStage, UtxoRefPart, BACKEND_UNAVAILABLE};
+    use crate::events::Event;
     use mockall::predicate::*;
     use mockall::mock;
     use rand;
-
+    
     // Mock TokenTransfer for testing
     mock! {
         pub TokenTransferMock {}
@@ -30,9 +71,11 @@ mod tests {
             fn validate_address(&self, address: &str) -> Result<(), String>;
             fn supports_token_type(&self, token_type: &TokenType) -> bool;
             fn get_network_type(&self) -> String;
+            fn get_contract_balance(&self, token_type: &TokenType) -> Result<u64, String>;
+            fn is_utxo_unspent(&self, utxo_reference: &str) -> Result<bool, String>;
         }
     }
-
+    
     #[test]
     fn test_bitcoin_testnet_address_validation() {
         // Valid testnet addresses
@@ -162,18 +205,18 @@ mod tests {
         utxo_set.add(utxo3);
         
         // Select UTXOs for an amount less than a single UTXO
-        let (selected, change) = utxo_set.select_utxos(400, 1.0).unwrap();
+        let (selected, change) = utxo_set.select_utxos(400, 1.0, SelectionStrategy::Auto).unwrap();
         assert_eq!(selected.len(), 1);
         assert_eq!(selected[0].amount, 500);
         assert!(change > 0);
         
         // Select UTXOs for an amount greater than a single UTXO
-        let (selected, change) = utxo_set.select_utxos(2100, 1.0).unwrap();
+        let (selected, change) = utxo_set.select_utxos(2100, 1.0, SelectionStrategy::Auto).unwrap();
         assert_eq!(selected.len(), 2);
         assert!(change > 0);
         
         // Test insufficient funds
-        assert!(utxo_set.select_utxos(10000, 1.0).is_err());
+        assert!(utxo_set.select_utxos(10000, 1.0, SelectionStrategy::Auto).is_err());
     }
     
     #[test]
@@ -192,7 +235,7 @@ mod tests {
             .returning(|| "testnet".to_string());
         
         // Create contract
-        let contract = TimeLockedDeposit::new(
+        let contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -226,7 +269,7 @@ mod tests {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -238,7 +281,7 @@ mod tests {
             TokenType::Bitcoin,
             1000,
             30, // 30 days
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -253,8 +296,8 @@ mod tests {
         
         assert_eq!(deposit.depositor_address, "depositor_address");
         assert_eq!(deposit.deposited_amount, 1000);
-        assert_eq!(deposit.is_withdrawn, false);
-        assert_eq!(deposit.utxo_reference, Some("txid:0".to_string()));
+        assert_eq!(deposit.is_withdrawn(), false);
+        assert_eq!(deposit.utxo_reference, Some(test_utxo(0)));
     }
     
     #[test]
@@ -281,7 +324,7 @@ mod tests {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -293,7 +336,7 @@ mod tests {
             TokenType::Bitcoin,
             1000,
             0, // 0 days (immediately unlocked for testing)
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -311,7 +354,7 @@ mod tests {
         
         // Check deposit was marked as withdrawn
         let deposit = contract.deposit_registry.get(&deposit_id).unwrap();
-        assert!(deposit.is_withdrawn);
+        assert!(deposit.is_withdrawn());
     }
     
     #[test]
@@ -338,7 +381,7 @@ mod tests {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -350,7 +393,7 @@ mod tests {
             TokenType::Bitcoin,
             1000,
             30, // 30 days
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -368,7 +411,7 @@ mod tests {
         
         // Check deposit was marked as withdrawn
         let deposit = contract.deposit_registry.get(&deposit_id).unwrap();
-        assert!(deposit.is_withdrawn);
+        assert!(deposit.is_withdrawn());
         
         // Check fees were collected
         let fees = contract.fee_config.collected_fees.get(&TokenType::Bitcoin).unwrap();
@@ -399,7 +442,7 @@ mod tests {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -411,7 +454,7 @@ mod tests {
             TokenType::Bitcoin,
             1000,
             30, // 30 days
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -428,9 +471,10 @@ mod tests {
         assert!(result.is_ok());
         
         // Withdraw fees
-        let result = contract.withdraw_fees(
+        let result = contract.withdraw_all_fees(
             "owner_address".to_string(),
             TokenType::Bitcoin,
+            None,
         );
         
         assert!(result.is_ok());
@@ -461,7 +505,7 @@ mod tests {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -473,7 +517,7 @@ mod tests {
             TokenType::Bitcoin,
             1000,
             30, // 30 days
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -490,9 +534,10 @@ mod tests {
         assert!(matches!(result, Err(ContractError::Unauthorized)));
         
         // Try to withdraw fees from a non-owner address
-        let result = contract.withdraw_fees(
+        let result = contract.withdraw_all_fees(
             "different_address".to_string(),
             TokenType::Bitcoin,
+            None,
         );
         
         assert!(matches!(result, Err(ContractError::Unauthorized)));
@@ -519,7 +564,7 @@ mod tests {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -537,7 +582,7 @@ mod tests {
             TokenType::Bitcoin,
             500, // At the limit
             30,
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -548,10 +593,10 @@ mod tests {
             TokenType::Bitcoin,
             501, // Exceeds the limit
             30,
-            Some("txid:1".to_string()),
+            Some(test_utxo(1)),
         );
         
-        assert!(matches!(result, Err(ContractError::DepositLimitExceeded)));
+        assert!(matches!(result, Err(ContractError::DepositLimitExceeded { .. })));
         
         // Make another deposit within limits
         let result = contract.deposit(
@@ -559,7 +604,7 @@ mod tests {
             TokenType::Bitcoin,
             400,
             30,
-            Some("txid:2".to_string()),
+            Some(test_utxo(2)),
         );
         
         assert!(result.is_ok());
@@ -570,10 +615,10 @@ mod tests {
             TokenType::Bitcoin,
             300,
             30,
-            Some("txid:3".to_string()),
+            Some(test_utxo(3)),
         );
         
-        assert!(matches!(result, Err(ContractError::UserDepositLimitReached)));
+        assert!(matches!(result, Err(ContractError::UserDepositLimitReached { .. })));
     }
     
     #[test]
@@ -599,7 +644,7 @@ mod tests {
             .returning(|| "testnet".to_string());
         
         // Create contract
-        let contract = TimeLockedDeposit::new(
+        let contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -713,29 +758,29 @@ fn test_signature_verifier() {
         });
         
         // Test exact match selection
-        let (selected, change) = utxo_set.select_utxos(3000, 1.0).unwrap();
+        let (selected, change) = utxo_set.select_utxos(3000, 1.0, SelectionStrategy::Auto).unwrap();
         assert_eq!(selected.len(), 1);
         assert_eq!(selected[0].amount, 3000);
         assert_eq!(change, 0);
         
         // Test single with change selection
-        let (selected, change) = utxo_set.select_utxos(4500, 1.0).unwrap();
+        let (selected, change) = utxo_set.select_utxos(4500, 1.0, SelectionStrategy::Auto).unwrap();
         assert_eq!(selected.len(), 1);
         assert_eq!(selected[0].amount, 5000);
         assert_eq!(change, 5000 - 4500 - 180); // 5000 - 4500 - fee
         
         // Test branch and bound selection
-        let (selected, change) = utxo_set.select_utxos(6500, 1.0).unwrap();
+        let (selected, change) = utxo_set.select_utxos(6500, 1.0, SelectionStrategy::Auto).unwrap();
         assert!(selected.len() > 1);
         assert!(change > 0);
         
         // Test knapsack selection (fallback)
-        let (selected, change) = utxo_set.select_utxos(14500, 1.0).unwrap();
+        let (selected, change) = utxo_set.select_utxos(14500, 1.0, SelectionStrategy::Auto).unwrap();
         assert!(selected.len() >= 3);
         assert!(change > 0);
         
         // Test insufficient funds
-        assert!(utxo_set.select_utxos(20000, 1.0).is_err());
+        assert!(utxo_set.select_utxos(20000, 1.0, SelectionStrategy::Auto).is_err());
     }
     
     #[test]
@@ -762,7 +807,7 @@ fn test_signature_verifier() {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -774,7 +819,7 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             u64::MAX / 3, // Large but not overflow
             30,
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -785,10 +830,10 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             u64::MAX, // Would cause overflow
             30,
-            Some("txid:1".to_string()),
+            Some(test_utxo(1)),
         );
         
-        assert!(matches!(result, Err(ContractError::InvalidAmount)));
+        assert!(matches!(result, Err(ContractError::InvalidAmount { .. })));
         
         // Test deposit with zero amount
         let result = contract.deposit(
@@ -796,10 +841,10 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             0, // Zero amount
             30,
-            Some("txid:2".to_string()),
+            Some(test_utxo(2)),
         );
         
-        assert!(matches!(result, Err(ContractError::InvalidAmount)));
+        assert!(matches!(result, Err(ContractError::InvalidAmount { .. })));
         
         // Test deposit with zero lock period
         let result = contract.deposit(
@@ -807,10 +852,10 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             1000,
             0, // Zero lock period
-            Some("txid:3".to_string()),
+            Some(test_utxo(3)),
         );
         
-        assert!(matches!(result, Err(ContractError::InvalidLockPeriod)));
+        assert!(matches!(result, Err(ContractError::InvalidLockPeriod { .. })));
         
         // Test deposit with excessive lock period
         let result = contract.deposit(
@@ -818,10 +863,10 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             1000,
             4000, // > 10 years
-            Some("txid:4".to_string()),
+            Some(test_utxo(4)),
         );
         
-        assert!(matches!(result, Err(ContractError::InvalidLockPeriod)));
+        assert!(matches!(result, Err(ContractError::InvalidLockPeriod { .. })));
     }
     
     #[test]
@@ -919,6 +964,7 @@ fn test_signature_verifier() {
         let channel = lightning_client.open_channel(
             "02...", // Fixed: removed .to_string() to match &str parameter
             100000,
+            false,
         ).unwrap();
         
         // Check channel properties
@@ -1144,7 +1190,7 @@ fn test_signature_verifier() {
             .returning(|_, _, _| Ok(()));
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -1159,7 +1205,7 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             1000,
             30,
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(matches!(result, Err(ContractError::ContractPaused)));
@@ -1173,7 +1219,7 @@ fn test_signature_verifier() {
             TokenType::Bitcoin,
             1000,
             30,
-            Some("txid:0".to_string()),
+            Some(test_utxo(0)),
         );
         
         assert!(result.is_ok());
@@ -1194,7 +1240,7 @@ fn test_signature_verifier() {
             .returning(|| "testnet".to_string());
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "owner_address".to_string(),
             10, // 10% emergency withdrawal fee
             mock,
@@ -1253,16 +1299,16 @@ fn test_signature_verifier() {
         ).unwrap();
         
         // Create contract
-        let mut contract = TimeLockedDeposit::new(
+        let mut contract = TimeLockedDeposit::new_with_defaults(
             "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
             10, // 10% emergency withdrawal fee
             transfer,
         ).unwrap();
         
-        // Check supported tokens
+        // Check supported tokens, limited to what the Bitcoin backend handles
         assert!(contract.supported_tokens.contains(&TokenType::Bitcoin));
-        assert!(contract.supported_tokens.contains(&TokenType::Ethereum));
-        assert!(contract.supported_tokens.contains(&TokenType::Solana));
+        assert!(!contract.supported_tokens.contains(&TokenType::Ethereum));
+        assert!(!contract.supported_tokens.contains(&TokenType::Solana));
         
         // Check for Rune token support
         let has_rune = contract.supported_tokens.iter()
@@ -1278,4 +1324,10918 @@ fn test_signature_verifier() {
         let has_lightning = contract.supported_tokens.contains(&TokenType::Lightning);
         assert!(has_lightning);
     }
+    
+    // Probe with a switchable outcome for health checker tests
+    #[derive(Debug)]
+    struct FakeProbe {
+        component: BackendComponent,
+        healthy: std::sync::atomic::AtomicBool,
+    }
+    
+    impl FakeProbe {
+        fn new(component: BackendComponent, healthy: bool) -> Arc<Self> {
+            Arc::new(Self {
+                component,
+                healthy: std::sync::atomic::AtomicBool::new(healthy),
+            })
+        }
+        
+        fn set_healthy(&self, healthy: bool) {
+            self.healthy.store(healthy, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+    
+    impl HealthProbe for FakeProbe {
+        fn component(&self) -> BackendComponent {
+            self.component
+        }
+        
+        fn probe(&self) -> Result<(), ContractError> {
+            if self.healthy.load(std::sync::atomic::Ordering::SeqCst) {
+                Ok(())
+            } else {
+                Err(ContractError::BitcoinTestnetError("connection refused".to_string()))
+            }
+        }
+    }
+    
+    #[test]
+    fn test_health_checker_report() {
+        let rpc_probe = FakeProbe::new(BackendComponent::BitcoinRpc, true);
+        let ordinals_probe = FakeProbe::new(BackendComponent::Ordinals, false);
+        
+        let mut checker = HealthChecker::new(Duration::from_secs(30));
+        checker.add_probe(rpc_probe.clone());
+        checker.add_probe(ordinals_probe.clone());
+        
+        checker.check_now().unwrap();
+        
+        let report = checker.health_report();
+        assert_eq!(report.len(), 2);
+        
+        let rpc_status = report.iter().find(|s| s.component == BackendComponent::BitcoinRpc).unwrap();
+        assert!(rpc_status.healthy);
+        assert!(rpc_status.latency.is_some());
+        assert!(rpc_status.last_success.is_some());
+        assert!(rpc_status.last_error.is_none());
+        
+        let ordinals_status = report.iter().find(|s| s.component == BackendComponent::Ordinals).unwrap();
+        assert!(!ordinals_status.healthy);
+        assert!(ordinals_status.last_error.as_ref().unwrap().contains("connection refused"));
+        assert!(ordinals_status.unhealthy_since.is_some());
+        assert!(!checker.is_healthy());
+        
+        // Unhealthy components are tolerated within the grace period only
+        assert!(checker.is_available(BackendComponent::Ordinals, Duration::from_secs(3600)));
+        assert!(!checker.is_available(BackendComponent::Ordinals, Duration::from_secs(0)));
+        
+        // Components without a probe are assumed available
+        assert!(checker.is_available(BackendComponent::Lightning, Duration::from_secs(0)));
+        
+        // Recovery clears the error
+        ordinals_probe.set_healthy(true);
+        checker.check_now().unwrap();
+        assert!(checker.is_healthy());
+        assert!(checker.is_available(BackendComponent::Ordinals, Duration::from_secs(0)));
+    }
+    
+    #[test]
+    fn test_backend_unavailable_rejects_operations() {
+        let mut mock = MockTokenTransferMock::new();
+        
+        // Setup mock expectations
+        mock.expect_validate_address()
+            .returning(|_| Ok(()));
+        
+        mock.expect_supports_token_type()
+            .returning(|_| true);
+        
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(10000));
+        
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        mock.expect_transfer_from_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        // Create contract
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            mock,
+        ).unwrap();
+        
+        let rpc_probe = FakeProbe::new(BackendComponent::BitcoinRpc, true);
+        let mut checker = HealthChecker::new(Duration::from_secs(30));
+        checker.add_probe(rpc_probe.clone());
+        checker.check_now().unwrap();
+        
+        let checker = Arc::new(checker);
+        contract.set_health_checker(checker.clone(), Duration::from_secs(0));
+        
+        // Deposit works while the backend is healthy
+        let result = contract.deposit(
+            "depositor_address".to_string(),
+            TokenType::Bitcoin,
+            1000,
+            30,
+            Some(test_utxo(0)),
+        );
+        assert!(result.is_ok());
+        
+        let deposit_id = contract.user_deposit_ids.get("depositor_address").unwrap()[0];
+        
+        // Backend goes down past the grace period
+        rpc_probe.set_healthy(false);
+        checker.check_now().unwrap();
+        
+        let result = contract.deposit(
+            "depositor_address".to_string(),
+            TokenType::Bitcoin,
+            1000,
+            30,
+            Some(test_utxo(1)),
+        );
+        assert!(matches!(result, Err(ContractError::BackendUnavailable(_))));
+        
+        let result = contract.emergency_withdraw("depositor_address".to_string(), deposit_id);
+        assert!(matches!(result, Err(ContractError::BackendUnavailable(_))));
+        assert!(!contract.deposit_registry.get(&deposit_id).unwrap().is_withdrawn());
+        
+        // Tokens served by other backends are unaffected
+        let result = contract.deposit(
+            "depositor_address".to_string(),
+            TokenType::Ethereum,
+            1000,
+            30,
+            None,
+        );
+        assert!(result.is_ok());
+        
+        // A longer grace period tolerates the outage
+        contract.set_health_checker(checker.clone(), Duration::from_secs(3600));
+        let result = contract.emergency_withdraw("depositor_address".to_string(), deposit_id);
+        assert!(result.is_ok());
+        
+        assert_eq!(contract.health_report().unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_health_endpoint() {
+        let rpc_probe = FakeProbe::new(BackendComponent::BitcoinRpc, false);
+        let mut checker = HealthChecker::new(Duration::from_secs(30));
+        checker.add_probe(rpc_probe.clone());
+        checker.check_now().unwrap();
+        
+        let checker = Arc::new(checker);
+        let server = ApiServer::new().with_health_checker(checker.clone());
+        
+        // Parse a raw request as it arrives on the socket
+        let raw = "GET /health HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let request = HttpRequest::parse(&mut std::io::Cursor::new(raw.as_bytes())).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/health");
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        
+        let response = server.handle(&request);
+        assert_eq!(response.status, 503);
+        
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["healthy"], false);
+        assert_eq!(body["components"][0]["component"], "BitcoinRpc");
+        
+        rpc_probe.set_healthy(true);
+        checker.check_now().unwrap();
+        
+        let response = server.handle(&request);
+        assert_eq!(response.status, 200);
+        
+        let response = server.handle(&HttpRequest::new("POST", "/health"));
+        assert_eq!(response.status, 405);
+        
+        let response = server.handle(&HttpRequest::new("GET", "/missing"));
+        assert_eq!(response.status, 404);
+    }
+    
+    
+    // Accept webhook requests, answering with the scripted statuses in order
+    fn spawn_webhook_endpoint(statuses: Vec<u16>) -> (String, std::sync::mpsc::Receiver<(String, Vec<u8>)>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+        
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/vault", listener.local_addr().unwrap());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        
+        std::thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                
+                let mut head = String::new();
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    head.push_str(&line);
+                }
+                
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                sender.send((head, body)).unwrap();
+                
+                let mut stream = reader.into_inner();
+                write!(stream, "HTTP/1.1 {} Status\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            }
+        });
+        
+        (url, receiver)
+    }
+    
+    #[test]
+    fn test_webhook_delivers_signed_events_with_retries() {
+        let mut mock = MockTokenTransferMock::new();
+        
+        // Setup mock expectations
+        mock.expect_validate_address()
+            .returning(|_| Ok(()));
+        
+        mock.expect_supports_token_type()
+            .returning(|_| true);
+        
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(10000));
+        
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        // Endpoint fails twice before accepting the event
+        let (url, requests) = spawn_webhook_endpoint(vec![500, 503, 200]);
+        
+        let mut config = WebhookConfig::new(url, "webhook-secret".to_string());
+        config.initial_backoff = Duration::from_millis(10);
+        config.timeout = Duration::from_secs(5);
+        let webhook = Arc::new(WebhookSink::new(config).unwrap());
+        
+        // Create contract
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            mock,
+        ).unwrap();
+        contract.add_event_sink(webhook.clone());
+        
+        contract.deposit(
+            "depositor_address".to_string(),
+            TokenType::Bitcoin,
+            1000,
+            30,
+            Some(test_utxo(0)),
+        ).unwrap();
+        
+        // The same signed payload is sent on every attempt
+        let mut attempts = Vec::new();
+        for _ in 0..3 {
+            attempts.push(requests.recv_timeout(Duration::from_secs(10)).unwrap());
+        }
+        
+        let expected_signature = format!("sha256={}", WebhookSink::sign("webhook-secret", &attempts[0].1));
+        for (head, body) in &attempts {
+            assert!(head.starts_with("POST /hooks/vault HTTP/1.1"));
+            assert!(head.contains("X-Vault-Event: Deposited"));
+            assert!(head.contains(&format!("X-Vault-Signature: {}", expected_signature)));
+            assert_eq!(body, &attempts[0].1);
+        }
+        
+        let payload: serde_json::Value = serde_json::from_slice(&attempts[0].1).unwrap();
+        assert_eq!(payload["Deposited"]["depositor_address"], "depositor_address");
+        assert_eq!(payload["Deposited"]["deposit_amount"], 1000);
+        
+        // Wait for the worker to record the delivery
+        for _ in 0..100 {
+            if webhook.delivery_stats().delivered == 1 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        
+        let stats = webhook.delivery_stats();
+        assert_eq!(stats.delivered, 1);
+        assert_eq!(stats.failed, 0);
+        assert_eq!(stats.pending, 0);
+    }
+    
+    #[test]
+    fn test_webhook_gives_up_after_max_attempts() {
+        // Client errors are not retried, exhausted server errors are recorded as failures
+        let (url, requests) = spawn_webhook_endpoint(vec![400, 500, 500]);
+        
+        let mut config = WebhookConfig::new(url, "webhook-secret".to_string());
+        config.max_attempts = 2;
+        config.initial_backoff = Duration::from_millis(10);
+        let webhook = WebhookSink::new(config).unwrap();
+        
+        let event = crate::events::Event::FeeCollected {
+            token_type: TokenType::Bitcoin,
+            fee_amount: 100,
+            collector_address: "owner_address".to_string(),
+            destination_address: "owner_address".to_string(),
+            remaining_fees: 0,
+            transaction_hash: None,
+            timestamp: chrono::Utc::now(),
+        };
+        
+        crate::events::EventSink::publish(&webhook, &event);
+        crate::events::EventSink::publish(&webhook, &event);
+        
+        for _ in 0..3 {
+            requests.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+        
+        for _ in 0..100 {
+            if webhook.delivery_stats().failed == 2 {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        
+        let stats = webhook.delivery_stats();
+        assert_eq!(stats.failed, 2);
+        assert_eq!(stats.delivered, 0);
+        
+        // Unsupported schemes are rejected up front
+        let config = WebhookConfig::new("https://example.com/hook".to_string(), "secret".to_string());
+        assert!(WebhookSink::new(config).is_err());
+    }
+    
+    
+    #[test]
+    fn test_reconciliation_report() {
+        let mut mock = MockTokenTransferMock::new();
+        
+        // Setup mock expectations
+        mock.expect_validate_address()
+            .returning(|_| Ok(()));
+        
+        mock.expect_supports_token_type()
+            .returning(|_| true);
+        
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(10000));
+        
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        mock.expect_transfer_from_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        // The wallet is 50 sats short on Bitcoin
+        mock.expect_get_contract_balance()
+            .returning(|token_type| match token_type {
+                TokenType::Bitcoin => Ok(1450),
+                _ => Err("not available".to_string()),
+            });
+        
+        mock.expect_is_utxo_unspent()
+            .returning(|reference| Ok(reference != test_utxo(0).to_string()));
+        
+        // Create contract
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            mock,
+        ).unwrap();
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, Some(test_utxo(0))).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 500, 30, Some(test_utxo(1))).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 200, 30, None).unwrap();
+        
+        // Emergency withdrawal leaves the fee in the contract
+        contract.emergency_withdraw("depositor_address".to_string(), 3).unwrap();
+        
+        let expected = contract.expected_holdings();
+        assert_eq!(expected.get(&TokenType::Bitcoin), Some(&1520));
+        
+        let report = contract.reconcile(100);
+        assert_eq!(report.tokens.len(), 1);
+        assert_eq!(report.tokens[0].expected, 1520);
+        assert_eq!(report.tokens[0].actual, Some(1450));
+        assert_eq!(report.tokens[0].delta, -70);
+        assert!(report.tokens[0].within_tolerance);
+        assert_eq!(report.missing_utxos.len(), 1);
+        assert_eq!(report.missing_utxos[0].deposit_id, 1);
+        assert!(!report.is_consistent());
+        assert_eq!(report.state_hash, hex::encode(contract.export_state().canonical_hash()));
+        
+        // Report serializes for the CLI and HTTP API
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["tokens"][0]["delta"], -70);
+        
+        // Without auto-pause the contract keeps running
+        let report = contract.reconcile_and_enforce(&ReconciliationConfig { tolerance: 10, auto_pause: false });
+        assert!(!report.tokens[0].within_tolerance);
+        assert!(!report.paused_contract);
+        assert!(!contract.is_contract_paused);
+        
+        // With auto-pause a discrepancy pauses the contract
+        let report = contract.reconcile_and_enforce(&ReconciliationConfig { tolerance: 10, auto_pause: true });
+        assert!(report.paused_contract);
+        assert!(contract.is_contract_paused);
+        
+        let server = ApiServer::new()
+            .with_reconciliation_report(Arc::new(std::sync::Mutex::new(Some(report))));
+        let response = server.handle(&HttpRequest::new("GET", "/stats/reconciliation"));
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["missing_utxos"][0]["utxo_reference"], test_utxo(0).to_string());
+        
+        let response = ApiServer::new().handle(&HttpRequest::new("GET", "/stats/reconciliation"));
+        assert_eq!(response.status, 404);
+    }
+    
+    
+    /// One value of every error variant
+    fn sample_errors() -> Vec<ContractError> {
+        vec![
+            ContractError::InvalidAddress,
+            ContractError::InvalidAmount { reason: "amount must be at least 1".to_string() },
+            ContractError::InvalidLockPeriod { min: 1, max: 3650, requested: 4000 },
+            ContractError::InvalidFeePercentage,
+            ContractError::DepositNotFound,
+            ContractError::DepositAlreadyWithdrawn,
+            ContractError::DepositLocked,
+            ContractError::InsufficientBalance { available: 500, required: 1_000 },
+            ContractError::NonceMismatch { expected: 3, actual: 4 },
+            ContractError::Unauthorized,
+            ContractError::ContractPaused,
+            ContractError::DepositLimitExceeded { token_type: TokenType::Bitcoin, limit: 1_000, attempted: 2_000 },
+            ContractError::UserDepositLimitReached { limit: 10, current: 10 },
+            ContractError::TotalDepositLimitReached { limit: 1_000, current: 900, attempted: 200 },
+            ContractError::UnsupportedTokenOperation,
+            ContractError::TokenValidationFailed,
+            ContractError::ArithmeticError,
+            ContractError::ReentrancyDetected,
+            ContractError::InitializationError("bad config".to_string()),
+            ContractError::BitcoinTestnetError("node error".to_string()),
+            ContractError::InvalidBitcoinTransaction,
+            ContractError::BackendUnavailable("bitcoin-rpc".to_string()),
+            ContractError::RpcError {
+                operation: "sendrawtransaction",
+                source: bitcoincore_rpc::Error::ReturnedError("txn-mempool-conflict".to_string()),
+            },
+            ContractError::TransferFailed {
+                stage: TransferStage::Broadcast,
+                reason: "peer disconnected".to_string(),
+            },
+            ContractError::EmergencyTooSoon { available_at: chrono::Utc::now() },
+            ContractError::FeeDestinationTooSoon { activates_at: chrono::Utc::now() },
+            ContractError::EmergencyLimitReached { limit: 3, retry_at: chrono::Utc::now() },
+            ContractError::IdempotencyConflict,
+            ContractError::GovernanceRequired,
+            ContractError::InvalidGovernanceConfig("threshold".to_string()),
+            ContractError::ProposalNotFound,
+            ContractError::ProposalClosed,
+            ContractError::AlreadyApproved,
+            ContractError::ReservationNotFound,
+            ContractError::InvalidPreimage,
+            ContractError::UtxoAlreadyDeposited,
+            ContractError::DepositNotAboveFee { fee: 1_000, amount: 500 },
+            ContractError::DepositSuspended,
+            ContractError::FeeExceedsLimit { required: 0, limit: 0 },
+            ContractError::InvalidInactivityPeriod,
+            ContractError::InheritanceNotClaimable,
+            ContractError::QuoteNotFound,
+            ContractError::QuoteExpired,
+            ContractError::QuoteMismatch,
+            ContractError::UtxoUnavailable { reference: String::new(), reason: String::new() },
+            ContractError::UnattributedFundsNotFound,
+            ContractError::DustOutput { amount: 100, threshold: 546 },
+            ContractError::DuplicateOutput { address: String::new() },
+            ContractError::InvalidConfirmationPolicy("tiers".to_string()),
+            ContractError::DepositPending,
+            ContractError::DepositExpired,
+            ContractError::InvalidDescriptor("checksum".to_string()),
+            ContractError::EscrowNotConfigured,
+            ContractError::BackendBusy("queue".to_string()),
+            ContractError::TreasuryPolicyNotConfigured,
+            ContractError::InvalidImport("hash".to_string()),
+            ContractError::InvalidSeriesResolution("7s".to_string()),
+            ContractError::InvalidCircuitBreakerConfig("window".to_string()),
+            ContractError::InvalidInvoice("signature".to_string()),
+            ContractError::MissingParameter("amount".to_string()),
+            ContractError::InvalidForecast("horizon".to_string()),
+            ContractError::InvalidImportSignature,
+            ContractError::ManifestAlreadyImported,
+            ContractError::MigrationKeyNotConfigured,
+            ContractError::TvlMetricsNotEnabled,
+            ContractError::CircuitBreakerNotTripped,
+            ContractError::DepositIdCollision,
+            ContractError::IdSchemeLocked,
+            ContractError::DestinationNotWhitelisted,
+            ContractError::FeeDestinationNotProposed,
+            ContractError::WithdrawalInFlight,
+            ContractError::SelfReferral,
+            ContractError::TokenDeprecated { token_type: TokenType::Lightning },
+            ContractError::MissingCapability { capability: "withdraw".to_string() },
+            ContractError::DepositNotPending,
+            ContractError::DepositVoided,
+            ContractError::DepositNotVoided,
+            ContractError::WithdrawalBelowMinimum { amount: 1_000, minimum: 1_699 },
+            ContractError::InvalidUtxoReference { part: UtxoRefPart::Vout, reason: "not a number".to_string() },
+            ContractError::TokenPaused { token_type: TokenType::Lightning },
+            ContractError::TokenNotPaused,
+            ContractError::OutboxEntryNotFound,
+            ContractError::StatePersistenceFailed("disk full".to_string()),
+            ContractError::MusigNonceReused { spend_id: "sweep-7".to_string() },
+            ContractError::ScriptTypeNotAllowed { script_type: ScriptType::NullData },
+            ContractError::WrongNetwork { address_network: "mainnet".to_string(), expected_network: "testnet".to_string() },
+            ContractError::Cancelled { stage: "export".to_string(), processed: 3 },
+            ContractError::ExportFailed("broken pipe".to_string()),
+            ContractError::ReadOnlyMode,
+        ]
+    }
+    
+    #[test]
+    fn test_error_codes_are_unique() {
+        let errors = sample_errors();
+        
+        // Every variant has its own code and every code belongs to a variant
+        let codes: std::collections::HashSet<&str> = errors.iter().map(|e| e.code().as_str()).collect();
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        assert!(ErrorCode::ALL.iter().all(|code| codes.contains(code.as_str())));
+        
+        // Codes serialize to the same string the accessor returns
+        for code in ErrorCode::ALL {
+            assert_eq!(serde_json::to_value(code).unwrap(), code.as_str());
+        }
+    }
+    
+    #[test]
+    fn test_error_context_and_retryability() {
+        use std::error::Error;
+        
+        // RPC errors keep the underlying error as their source
+        let rpc_error = ContractError::RpcError {
+            operation: "estimatesmartfee",
+            source: bitcoincore_rpc::Error::Io(std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused")),
+        };
+        assert_eq!(rpc_error.code(), ErrorCode::RpcError);
+        assert!(rpc_error.to_string().starts_with("RPC call estimatesmartfee failed"));
+        assert!(rpc_error.source().unwrap().to_string().contains("connection refused"));
+        assert!(rpc_error.is_retryable());
+        
+        let rejected = ContractError::RpcError {
+            operation: "sendrawtransaction",
+            source: bitcoincore_rpc::Error::ReturnedError("bad-txns-inputs-missingorspent".to_string()),
+        };
+        assert!(!rejected.is_retryable());
+        
+        let signing = ContractError::TransferFailed {
+            stage: TransferStage::Signing,
+            reason: "Transaction signing incomplete".to_string(),
+        };
+        assert_eq!(signing.to_string(), "Transfer failed during signing: Transaction signing incomplete");
+        assert!(!signing.is_retryable());
+        assert!(ContractError::BackendUnavailable("lightning".to_string()).is_retryable());
+        assert!(!ContractError::DepositLocked.is_retryable());
+        
+        // Contract operations report the stage at which the transfer layer failed
+        let mut mock = MockTokenTransferMock::new();
+        
+        mock.expect_validate_address()
+            .returning(|_| Ok(()));
+        
+        mock.expect_supports_token_type()
+            .returning(|_| true);
+        
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(10000));
+        
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Err("wallet locked".to_string()));
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            mock,
+        ).unwrap();
+        
+        let result = contract.deposit(
+            "depositor_address".to_string(),
+            TokenType::Bitcoin,
+            1000,
+            30,
+            None,
+        );
+        
+        match result {
+            Err(ContractError::TransferFailed { stage, reason }) => {
+                assert_eq!(stage, TransferStage::Deposit);
+                assert_eq!(reason, "wallet locked");
+            },
+            other => panic!("Expected transfer failure, got {:?}", other),
+        }
+        
+        // The HTTP layer exposes the stable code
+        let response = crate::server::HttpResponse::contract_error(&ContractError::DepositLocked);
+        assert_eq!(response.status, 409);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["code"], "DEPOSIT_LOCKED");
+        assert_eq!(body["retryable"], false);
+    }
+    
+    /// Funding output of a test deposit
+    fn test_utxo(vout: u32) -> UtxoRef {
+        UtxoRef::new(&"ef".repeat(32), vout).unwrap()
+    }
+    
+    /// Create a contract with a permissive mock and a controllable clock
+    fn contract_with_clock(clock: Arc<MockClock>) -> TimeLockedDeposit<MockTokenTransferMock> {
+        let mut mock = MockTokenTransferMock::new();
+        
+        // Setup mock expectations
+        mock.expect_validate_address()
+            .returning(|_| Ok(()));
+        
+        mock.expect_supports_token_type()
+            .returning(|_| true);
+        
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(1_000_000));
+        
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        mock.expect_transfer_from_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            mock,
+        ).unwrap();
+        
+        contract.set_clock(clock);
+        contract
+    }
+    
+    #[test]
+    fn test_emergency_withdrawal_min_age() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Only the owner can configure the cooldown
+        assert!(matches!(
+            contract.set_min_age_before_emergency("depositor_address".to_string(), Duration::from_secs(3600), None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_min_age_before_emergency("owner_address".to_string(), Duration::from_secs(3600), None).unwrap();
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        
+        // Just below the threshold the withdrawal is rejected without state change
+        clock.advance(chrono::Duration::minutes(59));
+        assert!(matches!(
+            contract.emergency_withdraw("depositor_address".to_string(), 1),
+            Err(ContractError::EmergencyTooSoon { .. })
+        ));
+        assert!(!contract.deposit_registry[&1].is_withdrawn());
+        
+        // Crossing the threshold allows it
+        clock.advance(chrono::Duration::minutes(1));
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(contract.deposit_registry[&1].is_withdrawn());
+    }
+    
+    #[test]
+    fn test_emergency_withdrawal_monthly_limit() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Only the owner can configure the limit, and zero is rejected
+        assert!(matches!(
+            contract.set_max_emergency_withdrawals_per_month("depositor_address".to_string(), Some(2), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_max_emergency_withdrawals_per_month("owner_address".to_string(), Some(0), None),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        contract.set_max_emergency_withdrawals_per_month("owner_address".to_string(), Some(2), None).unwrap();
+        
+        for _ in 0..4 {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        }
+        contract.deposit("other_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        
+        // Two withdrawals a day apart use up the allowance
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        clock.advance(chrono::Duration::days(1));
+        contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        
+        assert!(matches!(
+            contract.emergency_withdraw("depositor_address".to_string(), 3),
+            Err(ContractError::EmergencyLimitReached { .. })
+        ));
+        assert!(!contract.deposit_registry[&3].is_withdrawn());
+        
+        // The limit is per user
+        contract.emergency_withdraw("other_address".to_string(), 5).unwrap();
+        
+        // Still inside the window of the first withdrawal
+        clock.advance(chrono::Duration::days(29) - chrono::Duration::seconds(1));
+        assert!(matches!(
+            contract.emergency_withdraw("depositor_address".to_string(), 3),
+            Err(ContractError::EmergencyLimitReached { .. })
+        ));
+        
+        // Once the first withdrawal leaves the window one slot frees up
+        clock.advance(chrono::Duration::seconds(1));
+        contract.emergency_withdraw("depositor_address".to_string(), 3).unwrap();
+        assert!(matches!(
+            contract.emergency_withdraw("depositor_address".to_string(), 4),
+            Err(ContractError::EmergencyLimitReached { .. })
+        ));
+        
+        // The exported state carries the policy and the pruned log
+        let state = contract.export_state();
+        assert_eq!(state.emergency_policy.max_emergency_withdrawals_per_user_per_month, Some(2));
+        assert_eq!(state.emergency_withdrawals.len(), 2);
+        assert_eq!(state.emergency_withdrawals[0].0, "depositor_address");
+        assert_eq!(state.emergency_withdrawals[0].1.len(), 2);
+        assert_eq!(state.deposits.len(), 5);
+        assert!(serde_json::to_string(&state).is_ok());
+    }
+    
+    #[test]
+    fn test_withdraw_fees_partial_to_destination() {
+        let mut mock = MockTokenTransferMock::new();
+        
+        // Setup mock expectations
+        mock.expect_validate_address()
+            .returning(|address| if address == "bad_address" { Err("invalid".to_string()) } else { Ok(()) });
+        
+        mock.expect_supports_token_type()
+            .returning(|_| true);
+        
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(10000));
+        
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        mock.expect_transfer_from_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        // Create contract
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            mock,
+        ).unwrap();
+        
+        // Emergency withdraw to generate 100 in fees
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // Requesting more than available leaves the balance untouched
+        let result = contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(101), None, None);
+        assert!(matches!(result, Err(ContractError::InvalidAmount { .. })));
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 100);
+        
+        // An invalid destination is rejected
+        let result = contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(10), Some("bad_address".to_string()), None);
+        assert!(matches!(result, Err(ContractError::InvalidAddress)));
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 100);
+        
+        // Cold storage is not whitelisted yet
+        let result = contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(30), Some("cold_storage".to_string()), None);
+        assert!(matches!(result, Err(ContractError::DestinationNotWhitelisted)));
+        
+        // Whitelist cold storage once the delay has passed
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        contract.set_clock(clock.clone());
+        contract.propose_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        clock.advance(chrono::Duration::hours(48));
+        contract.activate_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        
+        // A partial sweep to cold storage decrements the balance
+        let event = contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(30), Some("cold_storage".to_string()), None).unwrap();
+        match event {
+            crate::events::Event::FeeCollected { fee_amount, destination_address, remaining_fees, .. } => {
+                assert_eq!(fee_amount, 30);
+                assert_eq!(destination_address, "cold_storage");
+                assert_eq!(remaining_fees, 70);
+            },
+            _ => panic!("Unexpected event"),
+        }
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 70);
+        
+        // The rest goes to the configured collector by default
+        let event = contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, None, None, None).unwrap();
+        match event {
+            crate::events::Event::FeeCollected { fee_amount, destination_address, remaining_fees, .. } => {
+                assert_eq!(fee_amount, 70);
+                assert_eq!(destination_address, "owner_address");
+                assert_eq!(remaining_fees, 0);
+            },
+            _ => panic!("Unexpected event"),
+        }
+        
+        // Nothing left to withdraw
+        let result = contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, None, None, None);
+        assert!(matches!(result, Err(ContractError::InvalidAmount { .. })));
+    }
+    
+    #[test]
+    fn test_deposit_receipts() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Receipts need a signer
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 2000, 30, None).unwrap();
+        assert!(contract.issue_receipt(1).is_err());
+        assert!(contract.get_stats().vault_public_key.is_none());
+        
+        let signer = KeySigner::new(&[7u8; 32]).unwrap();
+        let vault_pubkey = signer.public_key();
+        contract.set_receipt_signer(Arc::new(signer));
+        
+        // The stats expose the verification key
+        assert_eq!(contract.get_stats().vault_public_key, Some(hex::encode(&vault_pubkey)));
+        
+        // A receipt verifies against the vault key, also after a JSON round trip
+        let receipt = contract.issue_receipt(1).unwrap();
+        assert!(receipt.verify(&vault_pubkey));
+        let json = serde_json::to_string(&receipt).unwrap();
+        let parsed: DepositReceipt = serde_json::from_str(&json).unwrap();
+        assert!(parsed.verify(&vault_pubkey));
+        
+        // It does not verify against another key
+        let other_key = KeySigner::new(&[8u8; 32]).unwrap().public_key();
+        assert!(!receipt.verify(&other_key));
+        
+        // Tampering with any field breaks verification
+        let tampered: Vec<fn(&mut DepositReceipt)> = vec![
+            |r| r.deposit_id = 2,
+            |r| r.depositor_address = "attacker_address".to_string(),
+            |r| r.token_type = TokenType::Lightning,
+            |r| r.amount = 1_000_000,
+            |r| r.deposit_timestamp -= chrono::Duration::days(1),
+            |r| r.unlock_timestamp -= chrono::Duration::days(1),
+            |r| r.contract_owner = "attacker_address".to_string(),
+            |r| r.contract_version = "9.9.9".to_string(),
+            |r| r.signature = "00".repeat(64),
+        ];
+        for tamper in tampered {
+            let mut copy = receipt.clone();
+            tamper(&mut copy);
+            assert!(!copy.verify(&vault_pubkey));
+        }
+        
+        // Withdrawn and unknown deposits get no receipt
+        contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        assert!(matches!(contract.issue_receipt(2), Err(ContractError::DepositAlreadyWithdrawn)));
+        assert!(matches!(contract.issue_receipt(42), Err(ContractError::DepositNotFound)));
+    }
+    
+    #[test]
+    fn test_deposit_view_matches_emergency_withdrawal() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1005, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 2000, 30, None).unwrap();
+        assert!(matches!(contract.get_deposit_view(42), Err(ContractError::DepositNotFound)));
+        
+        // Locked deposit reports the time left
+        clock.advance(chrono::Duration::days(10));
+        let view = contract.get_deposit_view(1).unwrap();
+        assert!(!view.is_unlocked);
+        assert_eq!(view.time_remaining, Some(Duration::from_secs(20 * 24 * 3600)));
+        assert_eq!(view.effective_fee_percentage, 10);
+        assert_eq!(view.deposit.deposited_amount, 1005);
+        
+        // The quoted fee is exactly what the withdrawal charges
+        match contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap() {
+            crate::events::Event::EmergencyWithdrawn { withdrawn_amount, fee_amount, .. } => {
+                assert_eq!(fee_amount, view.emergency_fee_if_now);
+                assert_eq!(withdrawn_amount, view.net_emergency_payout_if_now);
+                assert_eq!(fee_amount + withdrawn_amount, 1005);
+            },
+            _ => panic!("Unexpected event"),
+        }
+        
+        // Past the unlock time nothing remains
+        clock.advance(chrono::Duration::days(20));
+        let view = contract.get_deposit_view(2).unwrap();
+        assert!(view.is_unlocked);
+        assert_eq!(view.time_remaining, None);
+        
+        // The view serializes with the deposit fields inline
+        let json = serde_json::to_value(&view).unwrap();
+        assert_eq!(json["deposit_id"], 2);
+        assert_eq!(json["emergency_fee_if_now"], 200);
+    }
+    
+    #[test]
+    fn test_balance_cache_eviction_and_expiry() {
+        let metrics = MetricsRegistry::new();
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut cache = BalanceCache::new(CacheConfig { ttl: Duration::from_secs(60), max_entries: 2 }, &metrics);
+        cache.set_clock(clock.clone());
+        
+        // Filling past the cap evicts the least recently used entry
+        cache.insert("addr_a", &TokenType::Bitcoin, 1);
+        cache.insert("addr_b", &TokenType::Bitcoin, 2);
+        assert_eq!(cache.get("addr_a", &TokenType::Bitcoin), Some(1));
+        cache.insert("addr_c", &TokenType::Bitcoin, 3);
+        
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("addr_b", &TokenType::Bitcoin), None);
+        assert_eq!(cache.get("addr_a", &TokenType::Bitcoin), Some(1));
+        assert_eq!(cache.get("addr_c", &TokenType::Bitcoin), Some(3));
+        
+        // Entries expire after the TTL
+        clock.advance(chrono::Duration::seconds(59));
+        assert_eq!(cache.get("addr_a", &TokenType::Bitcoin), Some(1));
+        clock.advance(chrono::Duration::seconds(1));
+        assert_eq!(cache.get("addr_a", &TokenType::Bitcoin), None);
+        assert_eq!(cache.len(), 1);
+        
+        // Invalidation and clearing
+        cache.insert("addr_a", &TokenType::Lightning, 4);
+        cache.invalidate("addr_a", &TokenType::Lightning);
+        assert_eq!(cache.get("addr_a", &TokenType::Lightning), None);
+        cache.clear();
+        assert!(cache.is_empty());
+        
+        // Counters are exposed through the registry
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["balance_cache_hits"], 4);
+        assert_eq!(snapshot["balance_cache_misses"], 3);
+        assert_eq!(snapshot["balance_cache_evictions"], 1);
+    }
+    
+    /// Transfer with a cached balance view, like the testnet transfer
+    #[derive(Debug)]
+    struct CachingTransfer {
+        /// Actual balances
+        balances: std::sync::Mutex<std::collections::HashMap<String, u64>>,
+        /// Balance cache
+        cache: std::sync::Mutex<BalanceCache>,
+    }
+    
+    impl TokenTransfer for CachingTransfer {
+        fn transfer_to_contract(&self, from_address: &str, _token_type: &TokenType, amount: u64) -> Result<(), String> {
+            let mut balances = self.balances.lock().unwrap();
+            let balance = balances.get_mut(from_address).ok_or("unknown address")?;
+            *balance = balance.checked_sub(amount).ok_or("insufficient funds")?;
+            Ok(())
+        }
+        
+        fn transfer_from_contract(&self, _to_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+            Ok(())
+        }
+        
+        fn get_balance(&self, address: &str, token_type: &TokenType) -> Result<u64, String> {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(balance) = cache.get(address, token_type) {
+                return Ok(balance);
+            }
+            let balance = self.balances.lock().unwrap().get(address).copied().unwrap_or(0);
+            cache.insert(address, token_type, balance);
+            Ok(balance)
+        }
+        
+        fn validate_address(&self, _address: &str) -> Result<(), String> {
+            Ok(())
+        }
+        
+        fn supports_token_type(&self, _token_type: &TokenType) -> bool {
+            true
+        }
+        
+        fn get_network_type(&self) -> String {
+            "testnet".to_string()
+        }
+        
+        fn invalidate_balance(&self, address: &str, token_type: &TokenType) {
+            self.cache.lock().unwrap().invalidate(address, token_type);
+        }
+    }
+    
+    #[test]
+    fn test_deposit_invalidates_cached_balance() {
+        let metrics = MetricsRegistry::new();
+        let transfer = CachingTransfer {
+            balances: std::sync::Mutex::new([("depositor_address".to_string(), 1500)].into_iter().collect()),
+            cache: std::sync::Mutex::new(BalanceCache::new(CacheConfig::default(), &metrics)),
+        };
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults(
+            "owner_address".to_string(),
+            10, // 10% emergency withdrawal fee
+            transfer,
+        ).unwrap();
+        
+        // The second deposit sees the reduced balance instead of the cached 1500
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        let result = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None);
+        assert!(matches!(result, Err(ContractError::InsufficientBalance { .. })));
+        assert_eq!(contract.token_transfer.get_balance("depositor_address", &TokenType::Bitcoin), Ok(500));
+        assert_eq!(contract.next_deposit_id, 2);
+    }
+    
+    #[test]
+    fn test_idempotency_keys() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let retention = Duration::from_secs(40 * 24 * 3600);
+        
+        // Only the owner can change the retention
+        assert!(matches!(
+            contract.set_idempotency_retention("depositor_address".to_string(), retention, None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_idempotency_retention("owner_address".to_string(), retention, None).unwrap();
+        
+        // Replaying the same deposit returns the original event without a second deposit
+        let first = contract.deposit_idempotent(Some("key-1".to_string()), "depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        let replay = contract.deposit_idempotent(Some("key-1".to_string()), "depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        assert_eq!(serde_json::to_value(&first).unwrap(), serde_json::to_value(&replay).unwrap());
+        assert_eq!(contract.deposit_registry.len(), 1);
+        
+        // Reusing the key with different arguments is a conflict
+        let result = contract.deposit_idempotent(Some("key-1".to_string()), "depositor_address".to_string(), TokenType::Bitcoin, 2000, 30, None);
+        assert!(matches!(result, Err(ContractError::IdempotencyConflict)));
+        let result = contract.emergency_withdraw_idempotent(Some("key-1".to_string()), "depositor_address".to_string(), 1);
+        assert!(matches!(result, Err(ContractError::IdempotencyConflict)));
+        assert_eq!(contract.deposit_registry.len(), 1);
+        
+        // Calls without a key are never deduplicated
+        contract.deposit_idempotent(None, "depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit_idempotent(None, "depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        assert_eq!(contract.deposit_registry.len(), 3);
+        
+        // Failed calls are not recorded and can be retried with the same key
+        let result = contract.withdraw_idempotent(Some("key-2".to_string()), "depositor_address".to_string(), 1);
+        assert!(matches!(result, Err(ContractError::DepositLocked)));
+        clock.advance(chrono::Duration::days(30));
+        contract.withdraw_idempotent(Some("key-2".to_string()), "depositor_address".to_string(), 1).unwrap();
+        contract.withdraw_idempotent(Some("key-2".to_string()), "depositor_address".to_string(), 1).unwrap();
+        
+        // The records are part of the exported state
+        let state = contract.export_state();
+        assert_eq!(state.idempotency_retention, retention);
+        assert_eq!(state.idempotency_records.len(), 2);
+        assert_eq!(state.idempotency_records[0].0, "key-1");
+        
+        // Once expired, the key executes again
+        clock.advance(chrono::Duration::days(10));
+        contract.deposit_idempotent(Some("key-1".to_string()), "depositor_address".to_string(), TokenType::Bitcoin, 2000, 30, None).unwrap();
+        assert_eq!(contract.deposit_registry.len(), 4);
+        assert!(contract.idempotency_records["key-1"].fingerprint.contains(":2000:"));
+    }
+    
+    #[test]
+    fn test_single_owner_fee_collector_and_ownership() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        // Only the owner changes the fee collector
+        assert!(matches!(
+            contract.set_fee_collector("depositor_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_fee_collector("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        assert_eq!(contract.fee_config.fee_collector_address, "cold_storage");
+        
+        // Ownership moves once the new owner accepts
+        contract.transfer_ownership("owner_address".to_string(), "new_owner".to_string(), None).unwrap();
+        assert!(matches!(contract.accept_ownership("depositor_address".to_string()), Err(ContractError::Unauthorized)));
+        contract.accept_ownership("new_owner".to_string()).unwrap();
+        assert!(contract.is_owner("new_owner"));
+        assert!(!contract.is_owner("owner_address"));
+    }
+    
+    /// Event sink recording event names
+    #[derive(Debug, Default)]
+    struct RecordingSink {
+        /// Names of received events
+        names: std::sync::Mutex<Vec<&'static str>>,
+    }
+    
+    impl crate::events::EventSink for RecordingSink {
+        fn publish(&self, event: &crate::events::Event) {
+            self.names.lock().unwrap().push(event.name());
+        }
+    }
+    
+    #[test]
+    fn test_governance_approval_flow() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // Generate 100 in fees
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // Invalid owner sets are rejected
+        let owners = vec!["owner_a".to_string(), "owner_b".to_string(), "owner_c".to_string()];
+        assert!(matches!(
+            contract.enable_governance("owner_address".to_string(), owners.clone(), 4, Duration::from_secs(3600), None),
+            Err(ContractError::InvalidGovernanceConfig(_))
+        ));
+        assert!(matches!(
+            contract.enable_governance("owner_address".to_string(), vec!["owner_a".to_string(), "owner_a".to_string()], 1, Duration::from_secs(3600), None),
+            Err(ContractError::InvalidGovernanceConfig(_))
+        ));
+        contract.enable_governance("owner_address".to_string(), owners, 2, Duration::from_secs(3600), None).unwrap();
+        
+        // Sensitive actions can no longer be called directly
+        assert!(matches!(
+            contract.withdraw_fees("owner_a".to_string(), TokenType::Bitcoin, None, None, None),
+            Err(ContractError::GovernanceRequired)
+        ));
+        assert!(matches!(
+            contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, None, None, None),
+            Err(ContractError::Unauthorized)
+        ));
+        
+        // Two of three owners withdraw part of the fees
+        let action = OwnerAction::WithdrawFees { token_type: TokenType::Bitcoin, amount: Some(40), destination: None };
+        assert!(matches!(contract.propose_action("depositor_address".to_string(), action.clone(), None), Err(ContractError::Unauthorized)));
+        let proposal_id = contract.propose_action("owner_a".to_string(), action, None).unwrap();
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 100);
+        assert!(matches!(contract.approve_action("owner_a".to_string(), proposal_id, None), Err(ContractError::AlreadyApproved)));
+        assert_eq!(contract.approve_action("owner_b".to_string(), proposal_id, None).unwrap(), ProposalStatus::Executed);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 60);
+        assert!(matches!(contract.approve_action("owner_c".to_string(), proposal_id, None), Err(ContractError::ProposalClosed)));
+        assert!(matches!(contract.approve_action("owner_c".to_string(), 42, None), Err(ContractError::ProposalNotFound)));
+        
+        // Unapproved proposals expire after the window
+        let proposal_id = contract.propose_action(
+            "owner_b".to_string(),
+            OwnerAction::SetFeeCollector { fee_collector_address: "cold_storage".to_string() },
+            None,
+        ).unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        assert!(matches!(contract.approve_action("owner_c".to_string(), proposal_id, None), Err(ContractError::ProposalClosed)));
+        assert_eq!(contract.governance().unwrap().proposals[&proposal_id].status, ProposalStatus::Expired);
+        assert_eq!(contract.fee_config.fee_collector_address, "owner_address");
+        
+        // Proposals are part of the exported state
+        let state = contract.export_state();
+        assert_eq!(state.governance.as_ref().unwrap().proposals.len(), 2);
+        assert!(serde_json::to_string(&state).is_ok());
+        
+        // Handing control back to a single owner leaves governance mode
+        let proposal_id = contract.propose_action(
+            "owner_c".to_string(),
+            OwnerAction::TransferOwnership { new_owner: "new_owner".to_string() },
+            None,
+        ).unwrap();
+        contract.approve_action("owner_a".to_string(), proposal_id, None).unwrap();
+        assert!(contract.governance().is_none());
+        contract.withdraw_fees("new_owner".to_string(), TokenType::Bitcoin, None, None, None).unwrap();
+        
+        let names = sink.names.lock().unwrap();
+        for name in ["ProposalCreated", "ProposalApproved", "ProposalExecuted", "ProposalExpired", "OwnershipTransferred"] {
+            assert!(names.contains(&name), "missing {}", name);
+        }
+    }
+    
+    #[test]
+    fn test_single_owner_proposal_failing_to_execute_is_closed() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        // Generate 100 in fees
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        let owners = vec!["owner_a".to_string(), "owner_b".to_string()];
+        contract.enable_governance("owner_address".to_string(), owners, 1, Duration::from_secs(3600), None).unwrap();
+        
+        // More than the collected fees: the proposer's approval executes it, which fails
+        let action = OwnerAction::WithdrawFees { token_type: TokenType::Bitcoin, amount: Some(500), destination: None };
+        assert!(contract.propose_action("owner_a".to_string(), action, None).is_err());
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 100);
+        
+        // The proposal is closed rather than left pending without approvals
+        let proposals = &contract.governance().unwrap().proposals;
+        assert_eq!(proposals.len(), 1);
+        let (&proposal_id, proposal) = proposals.iter().next().unwrap();
+        assert_eq!(proposal.status, ProposalStatus::Failed);
+        assert!(proposal.approvals.is_empty());
+        assert!(matches!(contract.approve_action("owner_b".to_string(), proposal_id, None), Err(ContractError::ProposalClosed)));
+        
+        // A corrected proposal goes through
+        let action = OwnerAction::WithdrawFees { token_type: TokenType::Bitcoin, amount: Some(40), destination: None };
+        let proposal_id = contract.propose_action("owner_b".to_string(), action, None).unwrap();
+        assert_eq!(contract.governance().unwrap().proposals[&proposal_id].status, ProposalStatus::Executed);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 60);
+    }
+    
+    #[test]
+    fn test_deposit_reservations_respect_total_limit_under_contention() {
+        use std::sync::Mutex;
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit_limits.max_total_deposits = Some(1_000);
+        contract.set_reservation_ttl("owner_address".to_string(), Duration::from_secs(60), None).unwrap();
+        
+        let contract = Arc::new(Mutex::new(contract));
+        let max_total = 1_000u64;
+        
+        let handles: Vec<_> = (0..8).map(|worker| {
+            let contract = contract.clone();
+            std::thread::spawn(move || {
+                for round in 0..50u64 {
+                    let caller = format!("user_{}", worker);
+                    let amount = 10 + (round % 7) * 5;
+                    
+                    let reservation_id = {
+                        let mut contract = contract.lock().unwrap();
+                        match contract.try_reserve_deposit_slot(caller, TokenType::Bitcoin, amount, 30) {
+                            Ok(reservation_id) => reservation_id,
+                            Err(e) => {
+                                assert!(matches!(e, ContractError::TotalDepositLimitReached { .. }));
+                                continue;
+                            }
+                        }
+                    };
+                    
+                    // Simulate the transfer outside the lock
+                    std::thread::yield_now();
+                    
+                    let mut contract = contract.lock().unwrap();
+                    match (worker + round as usize) % 3 {
+                        0 => { contract.commit_deposit_reservation(reservation_id, None).unwrap(); },
+                        1 => contract.rollback_deposit_reservation(reservation_id).unwrap(),
+                        _ => {}, // Abandoned, released on expiry
+                    }
+                    
+                    let committed = contract.total_deposits.get(&TokenType::Bitcoin).copied().unwrap_or(0);
+                    let reserved: u64 = contract.deposit_reservations.values().map(|reservation| reservation.amount).sum();
+                    assert!(committed <= max_total);
+                    assert!(committed + reserved <= max_total);
+                }
+            })
+        }).collect();
+        
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        
+        let mut contract = contract.lock().unwrap();
+        let committed = contract.total_deposits.get(&TokenType::Bitcoin).copied().unwrap_or(0);
+        assert!(committed <= max_total);
+        
+        // Abandoned reservations release their capacity after the TTL
+        clock.advance(chrono::Duration::seconds(61));
+        let remaining = max_total - committed;
+        if remaining > 0 {
+            let reservation_id = contract.try_reserve_deposit_slot("late_user".to_string(), TokenType::Bitcoin, remaining, 30).unwrap();
+            contract.commit_deposit_reservation(reservation_id, None).unwrap();
+        }
+        assert!(contract.deposit_reservations.is_empty());
+        assert_eq!(contract.total_deposits.get(&TokenType::Bitcoin).copied(), Some(max_total));
+    }
+    
+    #[test]
+    fn test_deposit_reservation_expiry_and_user_limit() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit_limits.max_deposits_per_user = Some(1);
+        
+        let reservation_id = contract.try_reserve_deposit_slot("depositor_address".to_string(), TokenType::Bitcoin, 100, 30).unwrap();
+        
+        // The reservation counts towards the per-user limit
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 30, None),
+            Err(ContractError::UserDepositLimitReached { .. })
+        ));
+        
+        // Expired reservations can no longer be committed
+        clock.advance(chrono::Duration::minutes(11));
+        assert!(matches!(contract.commit_deposit_reservation(reservation_id, None), Err(ContractError::ReservationNotFound)));
+        assert!(matches!(contract.rollback_deposit_reservation(reservation_id), Err(ContractError::ReservationNotFound)));
+        assert!(matches!(contract.commit_deposit_reservation(99, None), Err(ContractError::ReservationNotFound)));
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 30, None).unwrap();
+        assert_eq!(event.name(), "Deposited");
+    }
+    
+    #[test]
+    fn test_lock_policy_per_token_precedence() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        let ordinal = TokenType::Ordinal("0".repeat(64));
+        let other_ordinal = TokenType::Ordinal("a".repeat(64));
+        contract.add_supported_token("owner_address".to_string(), other_ordinal.clone(), None).unwrap();
+        
+        // An existing long deposit is unaffected by later policy changes
+        contract.deposit("depositor_address".to_string(), ordinal.clone(), 1, 1000, None).unwrap();
+        let unlock_before = contract.deposit_registry[&1].unlock_timestamp;
+        
+        // Only the owner may change the policy, and per-token values stay within the global maximum
+        assert!(matches!(
+            contract.set_token_max_lock_days("depositor_address".to_string(), TokenType::Ordinal(String::new()), Some(365), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_token_max_lock_days("owner_address".to_string(), TokenType::Bitcoin, Some(4000), None),
+            Err(ContractError::InvalidLockPeriod { .. })
+        ));
+        
+        // All ordinals capped at 365 days, one inscription at 30
+        contract.set_token_max_lock_days("owner_address".to_string(), TokenType::Ordinal(String::new()), Some(365), None).unwrap();
+        contract.set_token_max_lock_days("owner_address".to_string(), ordinal.clone(), Some(30), None).unwrap();
+        
+        let policy = contract.lock_policy();
+        assert_eq!(policy.max_lock_days_for(&TokenType::Bitcoin), 3650);
+        assert_eq!(policy.max_lock_days_for(&other_ordinal), 365);
+        assert_eq!(policy.max_lock_days_for(&ordinal), 30);
+        
+        assert!(contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 3650, None).is_ok());
+        assert!(contract.deposit("depositor_address".to_string(), other_ordinal.clone(), 1, 365, None).is_ok());
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), other_ordinal, 1, 366, None),
+            Err(ContractError::InvalidLockPeriod { .. })
+        ));
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), ordinal.clone(), 1, 31, None),
+            Err(ContractError::InvalidLockPeriod { .. })
+        ));
+        
+        // The global maximum cannot drop below a per-token value
+        assert!(matches!(
+            contract.set_max_lock_days("owner_address".to_string(), 100, None),
+            Err(ContractError::InvalidLockPeriod { .. })
+        ));
+        contract.set_max_lock_days("owner_address".to_string(), 400, None).unwrap();
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 401, None),
+            Err(ContractError::InvalidLockPeriod { .. })
+        ));
+        
+        assert_eq!(contract.deposit_registry[&1].unlock_timestamp, unlock_before);
+        assert_eq!(contract.get_stats().max_lock_days, 400);
+        assert_eq!(contract.export_state().per_token_max_lock_days.len(), 2);
+        
+        let names = sink.names.lock().unwrap();
+        assert_eq!(names.iter().filter(|name| **name == "LockPolicyUpdated").count(), 3);
+    }
+    
+    #[test]
+    fn test_transfer_deposit_ownership() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        contract.deposit("old_wallet".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("old_wallet".to_string(), TokenType::Bitcoin, 500, 30, None).unwrap();
+        contract.deposit("busy_wallet".to_string(), TokenType::Bitcoin, 500, 30, None).unwrap();
+        let unlock_timestamp = contract.deposit_registry[&1].unlock_timestamp;
+        
+        // Only the depositor can assign the deposit
+        assert!(matches!(
+            contract.transfer_deposit_ownership("new_wallet".to_string(), 1, "other_wallet".to_string()),
+            Err(ContractError::Unauthorized)
+        ));
+        
+        // The new owner's deposit-count limit applies
+        contract.deposit_limits.max_deposits_per_user = Some(1);
+        assert!(matches!(
+            contract.transfer_deposit_ownership("old_wallet".to_string(), 1, "busy_wallet".to_string()),
+            Err(ContractError::UserDepositLimitReached { .. })
+        ));
+        
+        let event = contract.transfer_deposit_ownership("old_wallet".to_string(), 1, "new_wallet".to_string()).unwrap();
+        assert_eq!(event.name(), "DepositOwnershipTransferred");
+        assert!(sink.names.lock().unwrap().contains(&"DepositOwnershipTransferred"));
+        assert_eq!(contract.deposit_registry[&1].depositor_address, "new_wallet");
+        assert_eq!(contract.deposit_registry[&1].unlock_timestamp, unlock_timestamp);
+        assert_eq!(contract.user_deposit_ids["old_wallet"], vec![2]);
+        assert_eq!(contract.user_deposit_ids["new_wallet"], vec![1]);
+        
+        // The old owner loses both withdrawal rights
+        assert!(matches!(
+            contract.emergency_withdraw("old_wallet".to_string(), 1),
+            Err(ContractError::Unauthorized)
+        ));
+        clock.advance(chrono::Duration::days(31));
+        assert!(matches!(
+            contract.withdraw("old_wallet".to_string(), 1),
+            Err(ContractError::Unauthorized)
+        ));
+        
+        let event = contract.withdraw("new_wallet".to_string(), 1).unwrap();
+        assert_eq!(event.name(), "Withdrawn");
+        
+        // Withdrawn deposits cannot be assigned
+        assert!(matches!(
+            contract.transfer_deposit_ownership("new_wallet".to_string(), 1, "old_wallet".to_string()),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+    }
+    
+    #[test]
+    fn test_transfer_deposit_ownership_emergency_rights_follow() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        contract.deposit("old_wallet".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.transfer_deposit_ownership("old_wallet".to_string(), 1, "new_wallet".to_string()).unwrap();
+        
+        let event = contract.emergency_withdraw("new_wallet".to_string(), 1).unwrap();
+        assert_eq!(event.name(), "EmergencyWithdrawn");
+    }
+    
+    #[test]
+    fn test_user_summary_and_maintenance_archival() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 500, 60, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Ethereum, 200, 30, None).unwrap();
+        
+        clock.advance(chrono::Duration::days(31));
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 3).unwrap();
+        
+        let summary = contract.get_user_summary("depositor_address");
+        assert_eq!(summary.active_count, 1);
+        assert_eq!(summary.lifetime_count, 3);
+        assert_eq!(summary.total_active_amount, vec![(TokenType::Bitcoin, 500)]);
+        assert_eq!(summary.archived_count, 0);
+        
+        // Maintenance moves withdrawn IDs to the archive without changing the aggregates
+        assert!(matches!(
+            contract.perform_maintenance("depositor_address".to_string(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert_eq!(contract.perform_maintenance("owner_address".to_string(), None).unwrap(), 2);
+        assert_eq!(contract.user_deposit_ids["depositor_address"], vec![2]);
+        assert_eq!(contract.get_archived_deposit_ids("depositor_address"), &[1, 3]);
+        
+        let archived_summary = contract.get_user_summary("depositor_address");
+        assert_eq!(archived_summary.archived_count, 2);
+        assert_eq!(archived_summary.lifetime_count, summary.lifetime_count);
+        assert_eq!(archived_summary.total_active_amount, summary.total_active_amount);
+        
+        // Archived deposits still count against the per-user limit
+        contract.deposit_limits.max_deposits_per_user = Some(3);
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 30, None),
+            Err(ContractError::UserDepositLimitReached { .. })
+        ));
+    }
+    
+    #[test]
+    fn test_deposit_cost_independent_of_user_history() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        let time_deposits = |contract: &mut TimeLockedDeposit<MockTokenTransferMock>, user: &str| {
+            let start = std::time::Instant::now();
+            for _ in 0..1000 {
+                contract.deposit(user.to_string(), TokenType::Bitcoin, 1, 30, None).unwrap();
+            }
+            start.elapsed()
+        };
+        
+        // Give one user a long history
+        for _ in 0..20 {
+            time_deposits(&mut contract, "heavy_user");
+        }
+        
+        let fresh = time_deposits(&mut contract, "fresh_user");
+        let heavy = time_deposits(&mut contract, "heavy_user");
+        
+        // Generous bound: copying a 20k-entry history per deposit would be orders of magnitude slower
+        assert!(heavy < fresh * 4 + Duration::from_millis(50), "fresh {:?}, heavy {:?}", fresh, heavy);
+        assert_eq!(contract.get_user_summary("heavy_user").lifetime_count, 21_000);
+    }
+    
+    #[test]
+    fn test_migrate_early_v1_0_0_snapshot() {
+        let raw = include_str!("fixtures/state_v1_0_0_early.json");
+        let contract = TimeLockedDeposit::from_state_versioned(raw, contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer).unwrap();
+        
+        // is_withdrawn became a status
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Withdrawn);
+        assert_eq!(contract.deposit_registry[&2].status, DepositStatus::Active);
+        assert_eq!(contract.next_deposit_id, 3);
+        
+        // Its placeholder reference is not an outpoint and is dropped
+        assert_eq!(contract.deposit_registry[&1].utxo_reference, None);
+        
+        // Fields added later take their defaults
+        assert_eq!(contract.lock_policy().max_lock_days, 3650);
+        assert!(contract.governance().is_none());
+        
+        // User indexes are rebuilt
+        let summary = contract.get_user_summary("depositor_address");
+        assert_eq!(summary.active_count, 1);
+        assert_eq!(summary.lifetime_count, 2);
+        assert_eq!(summary.total_active_amount, vec![(TokenType::Bitcoin, 500)]);
+        
+        let state = contract.export_state();
+        assert_eq!(state.state_version.to_string(), "1.2.0");
+    }
+    
+    #[test]
+    fn test_migrate_late_v1_0_0_snapshot() {
+        let raw = include_str!("fixtures/state_v1_0_0_late.json");
+        let contract = TimeLockedDeposit::from_state_versioned(raw, contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer).unwrap();
+        
+        assert!(contract.is_contract_paused);
+        assert_eq!(contract.fee_config.fee_collector_address, "fee_collector");
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 40);
+        assert_eq!(contract.deposit_limits.max_deposits_per_user, Some(10));
+        assert_eq!(contract.emergency_policy.max_emergency_withdrawals_per_user_per_month, Some(2));
+        assert_eq!(contract.lock_policy().max_lock_days_for(&TokenType::Ordinal("a".repeat(64))), 365);
+        assert_eq!(contract.reservation_ttl, Duration::from_secs(300));
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Active);
+    }
+    
+    #[test]
+    fn test_versioned_state_round_trip_and_newer_version_refused() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 500, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        
+        let raw = serde_json::to_string(&contract.export_state()).unwrap();
+        let restored = TimeLockedDeposit::from_state_versioned(&raw, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.deposit_registry[&2].status, DepositStatus::EmergencyWithdrawn);
+        assert_eq!(serde_json::to_string(&restored.export_state()).unwrap(), raw);
+        
+        // Snapshots from newer code are refused
+        let newer = raw.replacen("\"state_version\":\"1.2.0\"", "\"state_version\":\"2.0.0\"", 1);
+        assert!(matches!(
+            TimeLockedDeposit::from_state_versioned(&newer, contract_with_clock(clock.clone()).token_transfer),
+            Err(MigrationError::UnsupportedVersion { .. })
+        ));
+        
+        // Versions without a registered migration are refused
+        let unknown = raw.replacen("\"state_version\":\"1.2.0\"", "\"state_version\":\"0.9.0\"", 1);
+        assert!(matches!(
+            TimeLockedDeposit::from_state_versioned(&unknown, contract_with_clock(clock).token_transfer),
+            Err(MigrationError::MissingMigration(_))
+        ));
+    }
+    
+    #[test]
+    fn test_claimable_deposit_flow() {
+        use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        let secret = b"gift card secret".to_vec();
+        let claim_hash = sha256::Hash::hash(&secret).to_byte_array();
+        contract.deposit_claimable("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, claim_hash).unwrap();
+        assert_eq!(contract.deposit_registry[&1].claim_hash, Some(claim_hash));
+        
+        // Not claimable before maturity, and never with a wrong preimage
+        assert!(matches!(
+            contract.claim_deposit("claimer_address".to_string(), 1, secret.clone()),
+            Err(ContractError::DepositLocked)
+        ));
+        clock.advance(chrono::Duration::days(31));
+        assert!(matches!(
+            contract.claim_deposit("claimer_address".to_string(), 1, b"wrong secret".to_vec()),
+            Err(ContractError::InvalidPreimage)
+        ));
+        
+        // The depositor cannot take a matured gift back, by either withdrawal path
+        assert!(matches!(contract.withdraw("depositor_address".to_string(), 1), Err(ContractError::Unauthorized)));
+        assert!(matches!(contract.emergency_withdraw("depositor_address".to_string(), 1), Err(ContractError::Unauthorized)));
+        assert!(contract.simulate_withdrawal("depositor_address", 1, None).is_err());
+        
+        let event = contract.claim_deposit("claimer_address".to_string(), 1, secret.clone()).unwrap();
+        match event {
+            crate::events::Event::Withdrawn { claimed_by, depositor_address, withdrawn_amount, .. } => {
+                assert_eq!(claimed_by.as_deref(), Some("claimer_address"));
+                assert_eq!(depositor_address, "depositor_address");
+                assert_eq!(withdrawn_amount, 1000);
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.deposit_registry[&1].claimed_by.as_deref(), Some("claimer_address"));
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 0);
+        
+        // Repeated claims fail
+        assert!(matches!(
+            contract.claim_deposit("other_claimer".to_string(), 1, secret),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+        
+        // Ordinary deposits cannot be claimed
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        assert!(matches!(
+            contract.claim_deposit("claimer_address".to_string(), 2, Vec::new()),
+            Err(ContractError::Unauthorized)
+        ));
+    }
+    
+    #[test]
+    fn test_claimable_deposit_emergency_escape_hatch() {
+        use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        let secret = b"lost secret".to_vec();
+        let claim_hash = sha256::Hash::hash(&secret).to_byte_array();
+        contract.deposit_claimable("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, claim_hash).unwrap();
+        
+        // Only the depositor can take the deposit back early
+        assert!(matches!(
+            contract.emergency_withdraw("claimer_address".to_string(), 1),
+            Err(ContractError::Unauthorized)
+        ));
+        let event = contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert_eq!(event.name(), "EmergencyWithdrawn");
+        
+        assert!(matches!(
+            contract.claim_deposit("claimer_address".to_string(), 1, secret),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+    }
+    
+    #[test]
+    fn test_deposit_iterators_do_not_clone() {
+        use crate::contract::query::DepositFilter;
+        use crate::models::DEPOSIT_CLONES;
+        
+        let start = chrono::Utc::now();
+        let mut contract = contract_with_clock(Arc::new(MockClock::new(start)));
+        
+        contract.deposit("alice".to_string(), TokenType::Bitcoin, 100, 10, None).unwrap();
+        contract.deposit("alice".to_string(), TokenType::Ethereum, 200, 20, None).unwrap();
+        contract.deposit("bob".to_string(), TokenType::Bitcoin, 300, 30, None).unwrap();
+        contract.deposit("bob".to_string(), TokenType::Bitcoin, 400, 40, None).unwrap();
+        contract.emergency_withdraw("bob".to_string(), 4).unwrap();
+        contract.perform_maintenance("owner_address".to_string(), None).unwrap();
+        
+        let clones_before = DEPOSIT_CLONES.with(|clones| clones.get());
+        
+        let ids = |filter: DepositFilter| {
+            let mut ids: Vec<u64> = contract.deposits_filtered(filter).map(|deposit| deposit.deposit_id).collect();
+            ids.sort();
+            ids
+        };
+        
+        assert_eq!(contract.deposits_iter().count(), 4);
+        assert_eq!(ids(DepositFilter::default().with_token_type(TokenType::Bitcoin)), vec![1, 3, 4]);
+        assert_eq!(ids(DepositFilter::active().with_token_type(TokenType::Bitcoin)), vec![1, 3]);
+        assert_eq!(ids(DepositFilter::default().with_address("alice")), vec![1, 2]);
+        
+        // Address filters include archived deposits
+        assert_eq!(ids(DepositFilter::default().with_address("bob")), vec![3, 4]);
+        assert_eq!(ids(DepositFilter::default().with_address("bob").with_status(DepositStatus::EmergencyWithdrawn)), vec![4]);
+        
+        // Unlock range is half-open
+        let from = start + chrono::Duration::days(20);
+        let until = start + chrono::Duration::days(40);
+        assert_eq!(ids(DepositFilter::default().with_unlock_range(Some(from), Some(until))), vec![2, 3]);
+        
+        // Reports read through the iterators
+        assert_eq!(contract.get_stats().active_deposit_count, 3);
+        assert_eq!(contract.expected_holdings()[&TokenType::Bitcoin], 400 + 40);
+        
+        assert_eq!(DEPOSIT_CLONES.with(|clones| clones.get()), clones_before);
+        
+        // Maintenance helper mutates in place
+        contract.for_each_deposit_mut(&DepositFilter::default().with_address("alice"), |deposit| {
+            deposit.withdrawal_tx_hash = Some("checked".to_string());
+        });
+        assert_eq!(contract.deposits_filtered(DepositFilter::default()).filter(|deposit| deposit.withdrawal_tx_hash.is_some()).count(), 2);
+    }
+    
+    #[test]
+    fn test_utxo_references_are_deduplicated() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let txid = "AB".repeat(32);
+        let reference = |text: String| text.parse::<UtxoRef>().unwrap();
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, Some(reference(format!("{}:0", txid)))).unwrap();
+        assert_eq!(contract.deposit_registry[&1].utxo_reference, Some(reference(format!("{}:0", txid.to_lowercase()))));
+        
+        // The same output is rejected, whatever its case or surrounding whitespace
+        assert!(matches!(
+            contract.deposit("other_address".to_string(), TokenType::Bitcoin, 1000, 30, Some(reference(format!("  {}:0\n", txid.to_lowercase())))),
+            Err(ContractError::UtxoAlreadyDeposited)
+        ));
+        assert_eq!(contract.deposit_registry.len(), 1);
+        
+        // Another output of the same transaction is fine
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 500, 30, Some(reference(format!("{}:1", txid)))).unwrap();
+        assert_eq!(contract.utxo_reference_owner(&format!("{}:1", txid)), Some(2));
+        
+        // Reservations are checked on commit
+        let reservation_id = contract.try_reserve_deposit_slot("other_address".to_string(), TokenType::Bitcoin, 100, 30).unwrap();
+        assert!(matches!(
+            contract.commit_deposit_reservation(reservation_id, Some(reference(format!("{}:1", txid)))),
+            Err(ContractError::UtxoAlreadyDeposited)
+        ));
+        
+        // References stay consumed after withdrawal
+        clock.advance(chrono::Duration::days(31));
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, Some(reference(format!("{}:0", txid)))),
+            Err(ContractError::UtxoAlreadyDeposited)
+        ));
+        
+        let state = contract.export_state();
+        assert_eq!(state.consumed_utxo_references.len(), 2);
+        
+        // Cancellation releases the reference
+        contract.release_utxo_reference(2);
+        assert_eq!(contract.utxo_reference_owner(&format!("{}:1", txid)), None);
+        contract.deposit("other_address".to_string(), TokenType::Bitcoin, 500, 30, Some(reference(format!("{}:1", txid)))).unwrap();
+    }
+    
+    #[test]
+    fn test_initial_supported_tokens_must_be_backed() {
+        let backend = || {
+            let mut mock = MockTokenTransferMock::new();
+            mock.expect_validate_address()
+                .returning(|_| Ok(()));
+            mock.expect_supports_token_type()
+                .returning(|token_type| matches!(token_type, TokenType::Bitcoin | TokenType::Lightning));
+            mock
+        };
+        
+        // An unsupported token cannot sneak into the initial set
+        let result = TimeLockedDeposit::new(
+            "owner_address".to_string(),
+            10,
+            backend(),
+            vec![TokenType::Bitcoin, TokenType::Ethereum],
+        );
+        assert!(matches!(result, Err(ContractError::InitializationError(_))));
+        
+        let contract = TimeLockedDeposit::new(
+            "owner_address".to_string(),
+            10,
+            backend(),
+            vec![TokenType::Bitcoin, TokenType::Bitcoin],
+        ).unwrap();
+        assert_eq!(contract.supported_tokens, vec![TokenType::Bitcoin]);
+        
+        // The defaults are filtered to what the backend supports
+        let contract = TimeLockedDeposit::new_with_defaults("owner_address".to_string(), 10, backend()).unwrap();
+        assert_eq!(contract.get_stats().supported_tokens, vec![TokenType::Bitcoin, TokenType::Lightning]);
+    }
+    
+    #[test]
+    fn test_event_asset_detail_fixtures_round_trip() {
+        let raw = include_str!("fixtures/events_asset_detail.json");
+        let events: Vec<Event> = serde_json::from_str(raw).unwrap();
+        
+        let details: Vec<Option<&AssetDetail>> = events.iter().map(|event| event.asset_detail()).collect();
+        assert!(matches!(details[0], Some(AssetDetail::Utxo(reference)) if reference == "abcd:0"));
+        assert!(matches!(details[1], Some(AssetDetail::LightningInvoice { id: Some(_), payment_hash }) if payment_hash == "lightning_payment_2"));
+        assert!(matches!(details[2], Some(AssetDetail::Inscription(_))));
+        assert!(matches!(details[3], Some(AssetDetail::Multisig { wallet, .. }) if wallet == "vault_4"));
+        assert_eq!(events.iter().map(|event| event.deposit_id()).collect::<Vec<_>>(), vec![Some(1), Some(2), Some(3), Some(4)]);
+        
+        // Serialization is stable
+        let reserialized: serde_json::Value = serde_json::to_value(&events).unwrap();
+        assert_eq!(reserialized, serde_json::from_str::<serde_json::Value>(raw).unwrap());
+    }
+    
+    #[test]
+    fn test_events_carry_asset_detail() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let txid = "ab".repeat(32);
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, Some(format!("{}:0", txid).parse().unwrap())).unwrap();
+        assert_eq!(event.asset_detail(), Some(&AssetDetail::Utxo(format!("{}:0", txid))));
+        assert_eq!(event.deposit_id(), Some(1));
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None).unwrap();
+        assert!(matches!(event.asset_detail(), Some(AssetDetail::LightningInvoice { id: None, payment_hash }) if payment_hash == "lightning_payment_2"));
+        
+        let inscription_id = "0".repeat(64);
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Ordinal(inscription_id.clone()), 1, 30, None).unwrap();
+        assert_eq!(event.asset_detail(), Some(&AssetDetail::Inscription(inscription_id.clone())));
+        
+        // Withdrawals and views report the same detail
+        clock.advance(chrono::Duration::days(31));
+        let event = contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        assert_eq!(event.asset_detail(), Some(&AssetDetail::Utxo(format!("{}:0", txid))));
+        
+        let event = contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        assert!(matches!(event.asset_detail(), Some(AssetDetail::LightningInvoice { .. })));
+        
+        let view = contract.get_deposit_view(3).unwrap();
+        assert_eq!(view.asset_detail, Some(AssetDetail::Inscription(inscription_id)));
+        
+        // Events without a deposit have neither
+        let event = contract.withdraw_all_fees("owner_address".to_string(), TokenType::Lightning, None).unwrap();
+        assert_eq!(event.deposit_id(), None);
+        assert_eq!(event.asset_detail(), None);
+    }
+    
+    /// Transfer whose backend holds a Lightning invoice and a multisig wallet
+    #[derive(Debug)]
+    struct AssetBackendTransfer;
+    
+    impl TokenTransfer for AssetBackendTransfer {
+        fn transfer_to_contract(&self, _from_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+            Ok(())
+        }
+        
+        fn transfer_from_contract(&self, _to_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+            Ok(())
+        }
+        
+        fn get_balance(&self, _address: &str, _token_type: &TokenType) -> Result<u64, String> {
+            Ok(1000)
+        }
+        
+        fn supports_token_type(&self, _token_type: &TokenType) -> bool {
+            true
+        }
+        
+        fn get_network_type(&self) -> String {
+            "testnet".to_string()
+        }
+        
+        fn lightning_invoice_id(&self, payment_hash: &str) -> Option<String> {
+            (payment_hash == "lightning_payment_1").then(|| "invoice_1".to_string())
+        }
+        
+        fn multisig_wallet_address(&self, wallet: &str) -> Option<String> {
+            (wallet == "multisig_wallet_2").then(|| "2N1vault".to_string())
+        }
+    }
+    
+    #[test]
+    fn test_asset_detail_records_backend_invoice_and_wallet_address() {
+        let mut contract = TimeLockedDeposit::new_with_defaults("owner_address".to_string(), 10, AssetBackendTransfer).unwrap();
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None).unwrap();
+        assert_eq!(event.asset_detail(), Some(&AssetDetail::LightningInvoice {
+            id: Some("invoice_1".to_string()),
+            payment_hash: "lightning_payment_1".to_string(),
+        }));
+        
+        // The wallet's own address, not the depositor's
+        let event = contract.deposit("2N1depositor".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        assert_eq!(event.asset_detail(), Some(&AssetDetail::Multisig {
+            wallet: "multisig_wallet_2".to_string(),
+            address: "2N1vault".to_string(),
+        }));
+        assert_eq!(contract.get_deposit_view(2).unwrap().asset_detail, event.asset_detail().cloned());
+        
+        // Both are kept with the deposit
+        let deposit = &contract.deposit_registry[&1];
+        assert_eq!(deposit.lightning_invoice_id.as_deref(), Some("invoice_1"));
+        let restored: crate::models::Deposit = serde_json::from_value(serde_json::to_value(&contract.deposit_registry[&2]).unwrap()).unwrap();
+        assert_eq!(restored.multisig_address.as_deref(), Some("2N1vault"));
+    }
+    
+    #[test]
+    fn test_deposit_fee_defaults_to_zero() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_amount: 1000, fee_amount: 0, locked_amount: 1000, .. }));
+        assert_eq!(contract.deposit_registry[&1].deposited_amount, 1000);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 1000);
+        assert!(!contract.fee_config.collected_fees.contains_key(&TokenType::Bitcoin));
+    }
+    
+    #[test]
+    fn test_deposit_fee_is_taken_from_principal() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        
+        // Owner only, and a percentage must leave something to lock
+        assert!(matches!(
+            contract.set_deposit_fee("depositor_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(10)), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Percentage(100)), None),
+            Err(ContractError::InvalidFeePercentage)
+        ));
+        
+        // 1% of 99 sats rounds down to nothing
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Percentage(1)), None).unwrap();
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 99, 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_amount: 99, fee_amount: 0, locked_amount: 99, .. }));
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 250, 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_amount: 250, fee_amount: 2, locked_amount: 248, .. }));
+        assert_eq!(contract.deposit_registry[&2].deposited_amount, 248);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 99 + 248);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 2);
+        
+        // A flat fee must be strictly below the amount
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Lightning, Some(DepositFee::Flat(100)), None).unwrap();
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Lightning, 100, 30, None),
+            Err(ContractError::DepositNotAboveFee { .. })
+        ));
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Lightning, 101, 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { fee_amount: 100, locked_amount: 1, .. }));
+        
+        // Fees are part of the exported state
+        let state = contract.export_state();
+        assert_eq!(state.deposit_fees, vec![
+            (TokenType::Bitcoin, DepositFee::Percentage(1)),
+            (TokenType::Lightning, DepositFee::Flat(100)),
+        ]);
+        
+        // Clearing the fee restores full deposits
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Lightning, None, None).unwrap();
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Lightning, 100, 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { fee_amount: 0, locked_amount: 100, .. }));
+    }
+    
+    /// Contract wallet used by the mocked RPC tests
+    const RPC_CONTRACT_WALLET: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    
+    /// Recipient used by the mocked RPC tests
+    const RPC_RECIPIENT: &str = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+    
+    /// Method and parameters of a recorded RPC request
+    type RpcRequest = (String, Vec<serde_json::Value>);
+    
+    /// Configured `gettxout` answer: value and confirmations, `None` for a spent output, or an RPC error
+    type TxOutAnswer = Result<Option<(u64, u32)>, String>;
+    
+    /// JSON-RPC transport answering the wallet calls a payout needs, recording every request
+    #[derive(Debug, Clone, Default)]
+    struct MockRpcTransport {
+        /// Method and parameters of each request, in order
+        requests: Arc<std::sync::Mutex<Vec<RpcRequest>>>,
+        /// Results overriding the built-in answers, by method
+        responses: Arc<std::sync::Mutex<std::collections::HashMap<String, serde_json::Value>>>,
+        /// Reject the broadcast of every n-th created transaction
+        broadcast_failure_every: Arc<std::sync::Mutex<Option<u32>>>,
+        /// `gettxout` answers by `txid:vout`
+        tx_outs: Arc<std::sync::Mutex<std::collections::HashMap<String, TxOutAnswer>>>,
+    }
+    
+    impl MockRpcTransport {
+        /// Answer a method with a fixed result from now on
+        fn respond(&self, method: &str, result: serde_json::Value) {
+            self.responses.lock().unwrap().insert(method.to_string(), result);
+        }
+        
+        /// Answer `gettxout` for an output from now on
+        fn respond_tx_out(&self, utxo_reference: &str, tx_out: TxOutAnswer) {
+            self.tx_outs.lock().unwrap().insert(utxo_reference.to_string(), tx_out);
+        }
+        
+        /// Reject the broadcast of every n-th created transaction, counting in creation order
+        fn fail_broadcasts_every(&self, n: u32) {
+            *self.broadcast_failure_every.lock().unwrap() = Some(n);
+        }
+        
+        /// Parameters of the requests for one method, in order
+        fn calls(&self, method: &str) -> Vec<Vec<serde_json::Value>> {
+            self.requests.lock().unwrap().iter()
+                .filter(|(name, _)| name == method)
+                .map(|(_, params)| params.clone())
+                .collect()
+        }
+    }
+    
+    impl bitcoincore_rpc::jsonrpc::Transport for MockRpcTransport {
+        fn send_request(&self, request: bitcoincore_rpc::jsonrpc::Request) -> Result<bitcoincore_rpc::jsonrpc::Response, bitcoincore_rpc::jsonrpc::Error> {
+            use bitcoincore_rpc::bitcoin::{absolute::LockTime, consensus::encode::{deserialize, serialize_hex}, ScriptBuf, Transaction, TxIn, TxOut};
+            use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+            
+            let params: Vec<serde_json::Value> = request.params.iter()
+                .map(|param| serde_json::from_str(param.get()).unwrap())
+                .collect();
+            self.requests.lock().unwrap().push((request.method.to_string(), params.clone()));
+            
+            // Each created transaction carries its creation number as locktime, so they differ
+            let created = self.calls("createrawtransaction").len() as u32;
+            let tx = Transaction {
+                version: 2,
+                lock_time: LockTime::from_height(created).unwrap(),
+                input: vec![TxIn::default()],
+                output: vec![TxOut {
+                    value: 1000,
+                    script_pubkey: ScriptBuf::from(Vec::<u8>::from_hex("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap()),
+                }],
+            };
+            
+            // Decode the transaction being broadcast
+            let broadcast = match request.method {
+                "sendrawtransaction" => params[0].as_str()
+                    .and_then(|hex| Vec::<u8>::from_hex(hex).ok())
+                    .and_then(|bytes| deserialize::<Transaction>(&bytes).ok()),
+                _ => None,
+            };
+            
+            if let (Some(tx), Some(n)) = (&broadcast, *self.broadcast_failure_every.lock().unwrap()) {
+                if tx.lock_time.to_consensus_u32() % n == 0 {
+                    return Ok(bitcoincore_rpc::jsonrpc::Response {
+                        result: None,
+                        error: Some(bitcoincore_rpc::jsonrpc::error::RpcError {
+                            code: -26,
+                            message: "mempool full".to_string(),
+                            data: None,
+                        }),
+                        id: request.id,
+                        jsonrpc: Some("2.0".to_string()),
+                    });
+                }
+            }
+            
+            // The wallet holds ten 1 BTC outputs
+            let unspent: Vec<serde_json::Value> = (0..10)
+                .map(|vout| serde_json::json!({
+                    "txid": "ab".repeat(32),
+                    "vout": vout,
+                    "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                    "amount": 1.0,
+                    "confirmations": 6,
+                    "spendable": true,
+                    "solvable": true,
+                    "safe": true,
+                }))
+                .collect();
+            
+            // Outputs are unspent, spent or fail as configured
+            let tx_out = match request.method {
+                "gettxout" => self.tx_outs.lock().unwrap()
+                    .get(&format!("{}:{}", params[0].as_str().unwrap_or_default(), params[1]))
+                    .cloned(),
+                _ => None,
+            };
+            
+            if let Some(Err(message)) = &tx_out {
+                return Ok(bitcoincore_rpc::jsonrpc::Response {
+                    result: None,
+                    error: Some(bitcoincore_rpc::jsonrpc::error::RpcError {
+                        code: -5,
+                        message: message.clone(),
+                        data: None,
+                    }),
+                    id: request.id,
+                    jsonrpc: Some("2.0".to_string()),
+                });
+            }
+            
+            let overridden = self.responses.lock().unwrap().get(request.method).cloned();
+            let result = match request.method {
+                "gettxout" if tx_out.is_some() => match tx_out.unwrap().unwrap() {
+                    Some((value, confirmations)) => serde_json::json!({
+                        "bestblock": "00".repeat(32),
+                        "confirmations": confirmations,
+                        "value": value as f64 / 100_000_000.0,
+                        "scriptPubKey": { "asm": "", "hex": "0014751e76e8199196d454941c45d1b3a323f1433bd6" },
+                        "coinbase": false,
+                    }),
+                    None => serde_json::Value::Null,
+                },
+                _ if overridden.is_some() => overridden.unwrap(),
+                "estimatesmartfee" => serde_json::json!({ "feerate": 0.0001, "blocks": 1 }),
+                "listunspent" => serde_json::json!(unspent),
+                "createrawtransaction" => serde_json::json!(serialize_hex(&tx)),
+                "signrawtransactionwithwallet" => serde_json::json!({ "hex": params[0], "complete": true }),
+                "sendrawtransaction" => serde_json::json!(broadcast.map(|tx| tx.txid().to_string())),
+                _ => serde_json::Value::Null,
+            };
+            
+            // Apply the confirmation, amount and count filters of listunspent like the node
+            let result = match request.method {
+                "listunspent" => filter_unspent(result, &params),
+                _ => result,
+            };
+            
+            Ok(bitcoincore_rpc::jsonrpc::Response {
+                result: Some(serde_json::value::RawValue::from_string(result.to_string()).unwrap()),
+                error: None,
+                id: request.id,
+                jsonrpc: Some("2.0".to_string()),
+            })
+        }
+        
+        fn send_batch(&self, _requests: &[bitcoincore_rpc::jsonrpc::Request]) -> Result<Vec<bitcoincore_rpc::jsonrpc::Response>, bitcoincore_rpc::jsonrpc::Error> {
+            Ok(Vec::new())
+        }
+        
+        fn fmt_target(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "mock")
+        }
+    }
+    
+    /// Outputs of a `listunspent` result matching the request's filters, as the node would return them
+    fn filter_unspent(unspent: serde_json::Value, params: &[serde_json::Value]) -> serde_json::Value {
+        let sats = |btc: &serde_json::Value| btc.as_f64().map(|btc| (btc * 100_000_000.0).round() as u64);
+        
+        let min_conf = params.first().and_then(|value| value.as_u64()).unwrap_or(0);
+        let max_conf = params.get(1).and_then(|value| value.as_u64()).unwrap_or(9_999_999);
+        let options = params.get(4).cloned().unwrap_or(serde_json::Value::Null);
+        let min_amount = sats(&options["minimumAmount"]).unwrap_or(0);
+        let max_amount = sats(&options["maximumAmount"]).unwrap_or(u64::MAX);
+        let max_count = options["maximumCount"].as_u64().map_or(usize::MAX, |count| count as usize);
+        
+        let outputs: Vec<serde_json::Value> = unspent.as_array().cloned().unwrap_or_default().into_iter()
+            .filter(|output| (min_conf..=max_conf).contains(&output["confirmations"].as_u64().unwrap_or(0)))
+            .filter(|output| sats(&output["amount"]).is_some_and(|amount| (min_amount..=max_amount).contains(&amount)))
+            .take(max_count)
+            .collect();
+        
+        serde_json::json!(outputs)
+    }
+    
+    /// Testnet transfer talking to a mocked RPC transport
+    fn transfer_with_mock_rpc(fee_targets: FeeTargets) -> (BitcoinTestnetTransfer, MockRpcTransport) {
+        transfer_with_mock_wallet(RPC_CONTRACT_WALLET, fee_targets)
+    }
+    
+    /// Transfer implementation for a given contract wallet, talking to a mocked node
+    fn transfer_with_mock_wallet(contract_wallet: &str, fee_targets: FeeTargets) -> (BitcoinTestnetTransfer, MockRpcTransport) {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            contract_wallet.to_string(),
+        );
+        config.max_batch_size = 100;
+        config.rate_limit = 600_000;
+        config.fee_targets = fee_targets;
+        
+        transfer_with_mock_config(config)
+    }
+    
+    /// Transfer implementation for a given configuration, talking to a mocked node
+    fn transfer_with_mock_config(config: BitcoinTestnetConfig) -> (BitcoinTestnetTransfer, MockRpcTransport) {
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        
+        (BitcoinTestnetTransfer::with_rpc_client(config, rpc_client), transport)
+    }
+    
+    /// Outputs of a `createrawtransaction` request in order, as address and satoshis
+    fn raw_outputs(params: &[serde_json::Value]) -> Vec<(String, u64)> {
+        params[1].as_array().unwrap().iter()
+            .flat_map(|output| output.as_object().unwrap().iter()
+                .map(|(address, btc)| (address.clone(), (btc.as_f64().unwrap() * 100_000_000.0).round() as u64))
+                .collect::<Vec<_>>())
+            .collect()
+    }
+    
+    /// Amount paid to the recipient by each created transaction, in satoshis
+    fn created_payment_amounts(transport: &MockRpcTransport) -> Vec<u64> {
+        transport.calls("createrawtransaction").iter()
+            .map(|params| {
+                // The smaller output is the payment, the other one the change
+                raw_outputs(params).into_iter()
+                    .map(|(_, amount)| amount)
+                    .min()
+                    .unwrap()
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_pending_transfers_are_processed_by_priority() {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 1_000, TransferPriority::Low).unwrap();
+        transfer.transfer_from_contract(RPC_RECIPIENT, &TokenType::Bitcoin, 2_000).unwrap();
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 3_000, TransferPriority::High).unwrap();
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 4_000, TransferPriority::High).unwrap();
+        transfer.transfer_from_contract(RPC_RECIPIENT, &TokenType::Bitcoin, 5_000).unwrap();
+        
+        // Nothing is sent until the queue is processed
+        assert!(transport.calls("sendrawtransaction").is_empty());
+        
+        let result = transfer.process_pending_transactions().unwrap();
+        assert_eq!(result.txids.len(), 5);
+        
+        // High first, then normal, then low, keeping queue order within a priority
+        assert_eq!(created_payment_amounts(&transport), vec![3_000, 4_000, 2_000, 5_000, 1_000]);
+        assert!(transfer.process_pending_transactions().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_fee_target_follows_transfer_priority() {
+        let fee_targets = FeeTargets { urgent: 2, normal: 12, economy: 288 };
+        let (transfer, transport) = transfer_with_mock_rpc(fee_targets);
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults(RPC_CONTRACT_WALLET.to_string(), 10, transfer).unwrap();
+        contract.set_min_age_before_emergency(RPC_CONTRACT_WALLET.to_string(), Duration::ZERO, None).unwrap();
+        
+        // Deposits are queued at normal priority, emergency withdrawals high and fee sweeps low
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.emergency_withdraw(RPC_RECIPIENT.to_string(), 1).unwrap();
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        contract.set_clock(clock.clone());
+        contract.propose_fee_destination(RPC_CONTRACT_WALLET.to_string(), "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), None).unwrap();
+        clock.advance(chrono::Duration::hours(48));
+        contract.activate_fee_destination(RPC_CONTRACT_WALLET.to_string(), "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), None).unwrap();
+        contract.withdraw_fees(RPC_CONTRACT_WALLET.to_string(), TokenType::Bitcoin, None, Some("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string()), None).unwrap();
+        
+        contract.token_transfer.process_pending_transactions().unwrap();
+        
+        let targets: Vec<serde_json::Value> = transport.calls("estimatesmartfee").iter()
+            .map(|params| params[0].clone())
+            .collect();
+        assert_eq!(targets, vec![serde_json::json!(2), serde_json::json!(12), serde_json::json!(288)]);
+        assert_eq!(created_payment_amounts(&transport), vec![9_000, 10_000, 1_000]);
+        
+        // Zero targets are rejected
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.fee_targets.economy = 0;
+        assert!(config.validate().is_err());
+    }
+    
+    /// Make the mocked node report a chain tip and the state of every wallet transaction
+    fn set_mock_chain(transport: &MockRpcTransport, height: u64, confirmations: i32, block_hash: Option<&str>) {
+        transport.respond("getblockchaininfo", serde_json::json!({
+            "chain": "test",
+            "blocks": height,
+            "headers": height,
+            "bestblockhash": format!("{:064x}", height),
+            "difficulty": 1.0,
+            "mediantime": 1_700_000_000 + height * 600,
+            "verificationprogress": 1.0,
+            "initialblockdownload": false,
+            "chainwork": "00".repeat(32),
+            "size_on_disk": 0,
+            "pruned": false,
+            "warnings": "",
+        }));
+        transport.respond("gettransaction", serde_json::json!({
+            "amount": 0.0001,
+            "confirmations": confirmations,
+            "blockhash": block_hash,
+            "txid": "ab".repeat(32),
+            "time": 0,
+            "timereceived": 0,
+            "bip125-replaceable": "no",
+            "walletconflicts": [],
+            "details": [],
+            "hex": "",
+        }));
+    }
+    
+    #[test]
+    fn test_reorg_suspends_and_reinstates_deposit() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        let watcher = contract.token_transfer.reorg_watcher();
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // Committing the deposit hands its funding transaction to the watcher
+        let txid = "ab".repeat(32);
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 1000, 30, Some(format!("{}:0", txid).parse().unwrap())).unwrap();
+        assert_eq!(contract.deposit_registry[&1].funding_txid(), Some(txid.as_str()));
+        
+        // The watcher drives the contract through its callback
+        let contract = Arc::new(std::sync::Mutex::new(contract));
+        let target = contract.clone();
+        watcher.set_callback(Arc::new(move |notice: &ReorgNotice| {
+            target.lock().unwrap().apply_reorg_notice(notice).unwrap();
+        })).unwrap();
+        
+        let block_a = "aa".repeat(32);
+        let block_b = "bb".repeat(32);
+        
+        // Confirmation is remembered, and nothing is re-checked while the tip stays put
+        set_mock_chain(&transport, 100, 3, Some(&block_a));
+        assert!(watcher.poll().unwrap().is_empty());
+        let checks = transport.calls("gettransaction").len();
+        assert!(watcher.poll().unwrap().is_empty());
+        assert_eq!(transport.calls("gettransaction").len(), checks);
+        
+        // The transaction drops out of the chain
+        set_mock_chain(&transport, 101, 0, None);
+        let notices = watcher.poll().unwrap();
+        assert!(matches!(&notices[..], [ReorgNotice::Suspended { deposit_id: 1, .. }]));
+        assert_eq!(contract.lock().unwrap().deposit_registry[&1].status, DepositStatus::Suspended);
+        
+        clock.advance(chrono::Duration::days(31));
+        assert!(matches!(
+            contract.lock().unwrap().withdraw(RPC_RECIPIENT.to_string(), 1),
+            Err(ContractError::DepositSuspended)
+        ));
+        assert!(matches!(
+            contract.lock().unwrap().emergency_withdraw(RPC_RECIPIENT.to_string(), 1),
+            Err(ContractError::DepositSuspended)
+        ));
+        
+        // It confirms again in another block
+        set_mock_chain(&transport, 102, 1, Some(&block_b));
+        let notices = watcher.poll().unwrap();
+        assert!(matches!(&notices[..], [ReorgNotice::Reinstated { deposit_id: 1, .. }]));
+        assert_eq!(contract.lock().unwrap().deposit_registry[&1].status, DepositStatus::Active);
+        
+        // A confirmed transaction moving to a different block is also a reorg
+        set_mock_chain(&transport, 103, 2, Some(&block_a));
+        let notices = watcher.poll().unwrap();
+        assert!(matches!(&notices[..], [ReorgNotice::Suspended { reason, .. }] if reason.contains("block changed")));
+        
+        set_mock_chain(&transport, 104, 3, Some(&block_a));
+        watcher.poll().unwrap();
+        contract.lock().unwrap().withdraw(RPC_RECIPIENT.to_string(), 1).unwrap();
+        
+        // Suspension kept the deposit in the totals throughout
+        assert_eq!(contract.lock().unwrap().total_deposits[&TokenType::Bitcoin], 0);
+        assert_eq!(
+            *sink.names.lock().unwrap(),
+            vec!["Deposited", "DepositSuspended", "DepositReinstated", "DepositSuspended", "DepositReinstated", "Withdrawn"]
+        );
+    }
+    
+    /// Contract paying out through a Bitcoin transfer backed by a mocked node
+    fn contract_with_mock_rpc(clock: Arc<MockClock>) -> (TimeLockedDeposit<BitcoinTestnetTransfer>, MockRpcTransport) {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        
+        // 0.01 BTC/kvB makes a P2WPKH input paying a P2WSH output with change cost 153 sat
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.01, "blocks": 6 }));
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer).unwrap();
+        contract.set_clock(clock);
+        
+        (contract, transport)
+    }
+    
+    /// Mining fee of each created transaction, given the mocked 1 BTC input
+    fn created_transaction_fees(transport: &MockRpcTransport) -> Vec<u64> {
+        transport.calls("createrawtransaction").iter()
+            .map(|params| {
+                let outputs: u64 = raw_outputs(params).into_iter()
+                    .map(|(_, amount)| amount)
+                    .sum();
+                100_000_000 - outputs
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn test_withdrawal_fee_ceiling() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        // The ceiling is checked before anything is signed
+        let result = contract.withdraw_with_fee_limit(RPC_RECIPIENT.to_string(), 1, Some(152));
+        assert!(matches!(result, Err(ContractError::FeeExceedsLimit { required: 153, limit: 152 })));
+        assert_eq!(result.unwrap_err().code(), ErrorCode::FeeExceedsLimit);
+        assert!(transport.calls("createrawtransaction").is_empty());
+        
+        // The deposit stays withdrawable
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Active);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 100_000);
+        
+        // A ceiling covering the fee sends the payout right away
+        contract.withdraw_with_fee_limit(RPC_RECIPIENT.to_string(), 1, Some(153)).unwrap();
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Withdrawn);
+        assert_eq!(created_payment_amounts(&transport), vec![100_000]);
+        assert_eq!(created_transaction_fees(&transport), vec![153]);
+        assert_eq!(transport.calls("sendrawtransaction").len(), 1);
+    }
+    
+    #[test]
+    fn test_withdrawal_without_fee_ceiling_is_queued() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        // No ceiling keeps today's behaviour: the payout joins the batch, whatever it costs
+        contract.withdraw_with_fee_limit(RPC_RECIPIENT.to_string(), 1, None).unwrap();
+        assert!(transport.calls("createrawtransaction").is_empty());
+        
+        // The batch sends the deposit's inflow first, then the payout
+        contract.token_transfer.process_pending_transactions().unwrap();
+        assert_eq!(created_payment_amounts(&transport), vec![100_000, 100_000]);
+        assert_eq!(created_transaction_fees(&transport), vec![153, 153]);
+    }
+    
+    #[test]
+    fn test_withdrawal_fee_estimate_matches_actual_fee() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        let estimate = contract.estimate_withdrawal_fee(1).unwrap();
+        assert_eq!(estimate, 153);
+        assert!(matches!(contract.estimate_withdrawal_fee(2), Err(ContractError::DepositNotFound)));
+        
+        // The node's rate moves, but the cached rate holds until the next retarget
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.05, "blocks": 6 }));
+        
+        contract.withdraw_with_fee_limit(RPC_RECIPIENT.to_string(), 1, Some(estimate)).unwrap();
+        assert_eq!(created_transaction_fees(&transport), vec![estimate]);
+        assert_eq!(transport.calls("estimatesmartfee").len(), 1);
+    }
+    
+    #[test]
+    fn test_script_type_detection() {
+        let hash20 = "751e76e8199196d454941c45d1b3a323f1433bd6";
+        let hash32 = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        
+        assert_eq!(ScriptType::from_script_hex(&format!("76a914{}88ac", hash20)), ScriptType::P2pkh);
+        assert_eq!(ScriptType::from_script_hex(&format!("a914{}87", hash20)), ScriptType::P2sh);
+        assert_eq!(ScriptType::from_script_hex(&format!("0014{}", hash20)), ScriptType::P2wpkh);
+        assert_eq!(ScriptType::from_script_hex(&format!("0020{}", hash32)), ScriptType::P2wsh);
+        assert_eq!(ScriptType::from_script_hex(&format!("5120{}", hash32)), ScriptType::P2tr);
+        assert_eq!(ScriptType::from_script_hex("not hex"), ScriptType::Unknown);
+        
+        // Key-path taproot spends are the cheapest input
+        let utxo = Utxo {
+            txid: "ab".repeat(32),
+            vout: 0,
+            amount: 10_000,
+            confirmations: 6,
+            script_pubkey: format!("5120{}", hash32),
+            address: String::new(),
+            spendable: true,
+        };
+        assert_eq!(utxo.script_type(), ScriptType::P2tr);
+        assert_eq!(utxo.estimate_input_size(), 58);
+        assert!(ScriptType::P2tr.input_vsize() < ScriptType::P2wpkh.input_vsize());
+        assert_eq!(ScriptType::P2tr.output_vsize(), 43);
+        
+        // Address introspection reports the script type
+        let parsed = utils::parse_address("tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c").unwrap();
+        assert_eq!(parsed.script_type, ScriptType::P2tr);
+        assert_eq!(parsed.script_type.to_string(), "P2TR");
+        assert_eq!(utils::parse_address(RPC_CONTRACT_WALLET).unwrap().script_type, ScriptType::P2wpkh);
+        assert_eq!(utils::parse_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn").unwrap().script_type, ScriptType::P2pkh);
+        
+        // Mainnet and malformed addresses are rejected
+        assert!(matches!(
+            utils::parse_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"),
+            Err(ContractError::InvalidAddress)
+        ));
+        assert!(matches!(utils::parse_address("tb1pnotanaddress"), Err(ContractError::InvalidAddress)));
+    }
+    
+    #[test]
+    fn test_taproot_address_from_public_key() {
+        let verifier = SignatureVerifier::new(Network::Testnet);
+        let public_key = KeySigner::new(&[7u8; 32]).unwrap().public_key();
+        
+        let taproot = verifier.get_taproot_address_from_public_key(&public_key).unwrap();
+        assert!(taproot.starts_with("tb1p"));
+        assert!(utils::validate_testnet_address(&taproot));
+        assert_eq!(utils::parse_address(&taproot).unwrap().script_type, ScriptType::P2tr);
+        
+        // The same key still yields its segwit v0 address
+        let segwit = verifier.get_address_from_public_key(&public_key).unwrap();
+        assert_eq!(utils::parse_address(&segwit).unwrap().script_type, ScriptType::P2wpkh);
+        
+        // Deterministic per key, distinct across keys
+        assert_eq!(verifier.get_taproot_address_from_public_key(&public_key).unwrap(), taproot);
+        let other_key = KeySigner::new(&[8u8; 32]).unwrap().public_key();
+        assert_ne!(verifier.get_taproot_address_from_public_key(&other_key).unwrap(), taproot);
+        assert!(verifier.get_taproot_address_from_public_key(&[1, 2, 3]).is_err());
+    }
+    
+    #[test]
+    fn test_taproot_withdrawal_with_taproot_change() {
+        let verifier = SignatureVerifier::new(Network::Testnet);
+        let wallet = verifier.get_taproot_address_from_public_key(&KeySigner::new(&[7u8; 32]).unwrap().public_key()).unwrap();
+        let recipient = verifier.get_taproot_address_from_public_key(&KeySigner::new(&[8u8; 32]).unwrap().public_key()).unwrap();
+        let wallet_script = utils::parse_address(&wallet).unwrap().address.script_pubkey().to_hex_string();
+        
+        let (transfer, transport) = transfer_with_mock_wallet(&wallet, FeeTargets::default());
+        
+        // The contract wallet holds a single taproot output
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.01, "blocks": 6 }));
+        transport.respond("listunspent", serde_json::json!([{
+            "txid": "ab".repeat(32),
+            "vout": 0,
+            "scriptPubKey": wallet_script,
+            "amount": 1.0,
+            "confirmations": 6,
+            "spendable": true,
+            "solvable": true,
+            "safe": true,
+        }]));
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer).unwrap();
+        contract.set_clock(clock.clone());
+        
+        contract.deposit(recipient.clone(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        // Taproot input, taproot payment and taproot change: 58 + 11 + 43 + 43 vbytes
+        assert_eq!(contract.estimate_withdrawal_fee(1).unwrap(), 155);
+        contract.withdraw_with_fee_limit(recipient.clone(), 1, Some(155)).unwrap();
+        
+        let created = transport.calls("createrawtransaction");
+        assert_eq!(created.len(), 1);
+        let outputs: std::collections::HashMap<String, u64> = raw_outputs(&created[0]).into_iter().collect();
+        let sats = |address: &str| outputs[address];
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(sats(&recipient), 100_000);
+        assert_eq!(sats(&wallet), 100_000_000 - 100_000 - 155);
+        assert_eq!(transport.calls("sendrawtransaction").len(), 1);
+    }
+    
+    /// Ordinals client over a mocked node, with a small inscription cache on a mock clock
+    fn ordinals_client_with_cache(clock: Arc<MockClock>, max_entries: usize) -> OrdinalsClient {
+        let config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(MockRpcTransport::default()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        
+        let cache_config = CacheConfig { ttl: Duration::from_secs(60), max_entries };
+        let ordinals_client = OrdinalsClient::with_cache_config(rpc_client, "http://localhost:3000".to_string(), cache_config);
+        ordinals_client.set_clock(clock).unwrap();
+        
+        ordinals_client
+    }
+    
+    #[test]
+    fn test_inscription_cache_consistency() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let ordinals_client = ordinals_client_with_cache(clock.clone(), 2);
+        let inscription_id = "0".repeat(64);
+        
+        // The simulated index starts with the contract wallet as owner
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_CONTRACT_WALLET);
+        
+        // Someone else moves the inscription: the cache is stale, but ownership checks are not
+        ordinals_client.simulate_external_transfer(&inscription_id, RPC_RECIPIENT).unwrap();
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_CONTRACT_WALLET);
+        assert!(!ordinals_client.verify_owner(&inscription_id, RPC_CONTRACT_WALLET).unwrap());
+        assert!(ordinals_client.verify_owner(&inscription_id, RPC_RECIPIENT).unwrap());
+        
+        // Verifying refreshed the cache
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_RECIPIENT);
+        
+        // A stale cached owner cannot move the inscription
+        ordinals_client.simulate_external_transfer(&inscription_id, RPC_CONTRACT_WALLET).unwrap();
+        assert!(matches!(
+            ordinals_client.transfer_inscription(&inscription_id, RPC_RECIPIENT, "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"),
+            Err(ContractError::Unauthorized)
+        ));
+        
+        // Our own transfers invalidate the entry
+        ordinals_client.transfer_inscription(&inscription_id, RPC_CONTRACT_WALLET, RPC_RECIPIENT).unwrap();
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_RECIPIENT);
+        
+        // Entries expire after the TTL
+        ordinals_client.simulate_external_transfer(&inscription_id, RPC_CONTRACT_WALLET).unwrap();
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_RECIPIENT);
+        clock.advance(chrono::Duration::seconds(61));
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_CONTRACT_WALLET);
+        
+        // Explicit invalidation and refresh
+        ordinals_client.simulate_external_transfer(&inscription_id, RPC_RECIPIENT).unwrap();
+        ordinals_client.invalidate(&inscription_id).unwrap();
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_RECIPIENT);
+        ordinals_client.simulate_external_transfer(&inscription_id, RPC_CONTRACT_WALLET).unwrap();
+        assert_eq!(ordinals_client.refresh_inscription(&inscription_id).unwrap().owner, RPC_CONTRACT_WALLET);
+        
+        // The cache is bounded, evicting the least recently used entry
+        ordinals_client.get_inscription(&"1".repeat(64)).unwrap();
+        ordinals_client.get_inscription(&"2".repeat(64)).unwrap();
+        assert_eq!(ordinals_client.cached_len(), 2);
+        
+        // The evicted entry is fetched again
+        ordinals_client.simulate_external_transfer(&inscription_id, RPC_RECIPIENT).unwrap();
+        assert_eq!(ordinals_client.get_inscription(&inscription_id).unwrap().owner, RPC_RECIPIENT);
+    }
+    
+    #[test]
+    fn test_ordinal_balance_ignores_stale_cache() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let ordinals_client = Arc::new(ordinals_client_with_cache(clock, 10));
+        let (mut transfer, _transport) = transfer_with_mock_rpc(FeeTargets::default());
+        transfer.set_ordinals_client(ordinals_client.clone());
+        
+        let token = TokenType::Ordinal("0".repeat(64));
+        assert_eq!(transfer.get_balance(RPC_CONTRACT_WALLET, &token).unwrap(), 1);
+        assert_eq!(transfer.get_balance(RPC_RECIPIENT, &token).unwrap(), 0);
+        
+        // An ownership change elsewhere shows up immediately
+        ordinals_client.simulate_external_transfer(&"0".repeat(64), RPC_RECIPIENT).unwrap();
+        assert_eq!(transfer.get_balance(RPC_CONTRACT_WALLET, &token).unwrap(), 0);
+        assert_eq!(transfer.get_balance(RPC_RECIPIENT, &token).unwrap(), 1);
+    }
+    
+    #[test]
+    fn test_batch_failures_are_isolated_and_retained() {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        transport.fail_broadcasts_every(3);
+        
+        // References 1 to 7 are Bitcoin payouts, 8 is a rune transfer
+        for amount in 1..=7 {
+            transfer.transfer_from_contract(RPC_RECIPIENT, &TokenType::Bitcoin, amount * 1_000).unwrap();
+        }
+        transfer.transfer_from_contract(RPC_RECIPIENT, &TokenType::Rune("RUNE_TOKEN".to_string()), 500).unwrap();
+        
+        let result = transfer.process_pending_transactions().unwrap();
+        
+        // The third and sixth broadcasts fail without stopping the others
+        let mut succeeded: Vec<u64> = result.txids.iter().map(|(reference, _)| *reference).collect();
+        succeeded.sort();
+        assert_eq!(succeeded, vec![1, 2, 4, 5, 7, 8]);
+        
+        let mut failed: Vec<u64> = result.failed.iter().map(|(reference, _)| *reference).collect();
+        failed.sort();
+        assert_eq!(failed, vec![3, 6]);
+        assert!(result.failed.iter().all(|(_, e)| matches!(e, ContractError::RpcError { operation: "sendrawtransaction", .. })));
+        
+        // Every transaction was built before broadcasting, each from its own reserved input
+        let inputs: std::collections::HashSet<serde_json::Value> = transport.calls("createrawtransaction").iter()
+            .map(|params| params[0][0]["vout"].clone())
+            .collect();
+        assert_eq!(inputs.len(), 7);
+        assert_eq!(transport.calls("sendrawtransaction").len(), 7);
+        
+        // Only the failed items stay queued, with their attempt counted
+        assert_eq!(transfer.pending_references(), vec![(3, 1), (6, 1)]);
+        
+        // Retrying sends the eighth created transaction and rejects the ninth
+        let retry = transfer.process_pending_transactions().unwrap();
+        assert_eq!(retry.txids.iter().map(|(reference, _)| *reference).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(retry.failed.iter().map(|(reference, _)| *reference).collect::<Vec<_>>(), vec![6]);
+        assert_eq!(transfer.pending_references(), vec![(6, 2)]);
+        assert_eq!(created_payment_amounts(&transport)[7..], [3_000, 6_000]);
+    }
+    
+    #[test]
+    fn test_unlock_schedule_buckets_by_calendar_month() {
+        let start = chrono::DateTime::parse_from_rfc3339("2023-12-20T23:30:00Z").unwrap().with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let mut contract = contract_with_clock(clock);
+        let at = |timestamp: &str| chrono::DateTime::parse_from_rfc3339(timestamp).unwrap().with_timezone(&chrono::Utc);
+        
+        // Deposits straddling the year rollover and the leap day
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 11, None).unwrap(); // 2023-12-31 23:30
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 200, 12, None).unwrap(); // 2024-01-01 23:30
+        contract.deposit("depositor_address".to_string(), TokenType::Ethereum, 50, 13, None).unwrap(); // 2024-01-02 23:30
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 300, 40, None).unwrap(); // 2024-01-29 23:30
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 400, 71, None).unwrap(); // 2024-02-29 23:30
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 500, 72, None).unwrap(); // 2024-03-01 23:30
+        
+        let monthly = contract.get_unlock_schedule("depositor_address", ScheduleGranularity::Monthly);
+        let summary: Vec<_> = monthly.iter()
+            .map(|bucket| (bucket.period_start, bucket.token_type.clone(), bucket.amount, bucket.deposit_ids.clone()))
+            .collect();
+        assert_eq!(summary, vec![
+            (at("2023-12-01T00:00:00Z"), TokenType::Bitcoin, 100, vec![1]),
+            (at("2024-01-01T00:00:00Z"), TokenType::Bitcoin, 500, vec![2, 4]),
+            (at("2024-01-01T00:00:00Z"), TokenType::Ethereum, 50, vec![3]),
+            (at("2024-02-01T00:00:00Z"), TokenType::Bitcoin, 400, vec![5]),
+            (at("2024-03-01T00:00:00Z"), TokenType::Bitcoin, 500, vec![6]),
+        ]);
+        
+        // Every deposit appears exactly once at every granularity
+        for granularity in [ScheduleGranularity::Monthly, ScheduleGranularity::Weekly, ScheduleGranularity::Daily] {
+            let mut ids: Vec<u64> = contract.get_unlock_schedule("depositor_address", granularity)
+                .into_iter()
+                .flat_map(|bucket| bucket.deposit_ids)
+                .collect();
+            ids.sort();
+            assert_eq!(ids, vec![1, 2, 3, 4, 5, 6], "{}", granularity);
+        }
+        
+        // 2023-12-31 is a Sunday and 2024-01-01 a Monday, so they fall in different weeks
+        let weekly = contract.get_unlock_schedule("depositor_address", ScheduleGranularity::Weekly);
+        assert_eq!(weekly[0].period_start, at("2023-12-25T00:00:00Z"));
+        assert_eq!(weekly[0].deposit_ids, vec![1]);
+        assert_eq!(weekly[1].period_start, at("2024-01-01T00:00:00Z"));
+        assert_eq!(weekly[1].deposit_ids, vec![2]);
+        
+        let daily = contract.get_unlock_schedule("depositor_address", ScheduleGranularity::Daily);
+        assert_eq!(daily.len(), 6);
+        assert_eq!(daily[4].period_start, at("2024-02-29T00:00:00Z"));
+        
+        assert!(contract.get_unlock_schedule("unknown_address", ScheduleGranularity::Monthly).is_empty());
+        assert_eq!("Weekly".parse::<ScheduleGranularity>(), Ok(ScheduleGranularity::Weekly));
+        assert!("yearly".parse::<ScheduleGranularity>().is_err());
+    }
+    
+    #[test]
+    fn test_unlock_schedule_follows_withdrawals_and_assignments() {
+        let start = chrono::DateTime::parse_from_rfc3339("2024-01-15T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let mut contract = contract_with_clock(clock);
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100, 5, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 200, 10, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 300, 30, None).unwrap();
+        
+        contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        contract.transfer_deposit_ownership("depositor_address".to_string(), 3, "new_owner_address".to_string()).unwrap();
+        
+        let schedule = contract.get_unlock_schedule("depositor_address", ScheduleGranularity::Monthly);
+        assert_eq!(schedule.len(), 1);
+        assert_eq!(schedule[0].amount, 100);
+        assert_eq!(schedule[0].deposit_ids, vec![1]);
+        
+        let assigned = contract.get_unlock_schedule("new_owner_address", ScheduleGranularity::Monthly);
+        assert_eq!(assigned.len(), 1);
+        assert_eq!(assigned[0].deposit_ids, vec![3]);
+        
+        // The schedule is serializable for the API and CLI
+        let json = serde_json::to_value(&schedule).unwrap();
+        assert_eq!(json[0]["amount"], 100);
+        assert_eq!(json[0]["period_start"], "2024-01-01T00:00:00Z");
+    }
+    
+    #[test]
+    fn test_inheritance_claim_after_depositor_inactivity() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        
+        // Only the depositor can name a beneficiary, and the period must be positive
+        assert!(matches!(
+            contract.set_inheritance("beneficiary_address".to_string(), 1, "beneficiary_address".to_string(), 90),
+            Err(ContractError::InvalidAddress)
+        ));
+        assert!(matches!(
+            contract.set_inheritance("other_address".to_string(), 1, "beneficiary_address".to_string(), 90),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_inheritance("depositor_address".to_string(), 1, "beneficiary_address".to_string(), 0),
+            Err(ContractError::InvalidInactivityPeriod)
+        ));
+        contract.set_inheritance("depositor_address".to_string(), 1, "beneficiary_address".to_string(), 90).unwrap();
+        
+        // Too early: before maturity, then before the inactivity period after maturity has elapsed
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 1),
+            Err(ContractError::DepositLocked)
+        ));
+        clock.advance(chrono::Duration::days(30 + 89));
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 1),
+            Err(ContractError::InheritanceNotClaimable)
+        ));
+        
+        // Only the named beneficiary can claim
+        clock.advance(chrono::Duration::days(1));
+        assert!(matches!(
+            contract.claim_inherited("other_address".to_string(), 1),
+            Err(ContractError::Unauthorized)
+        ));
+        
+        let event = contract.claim_inherited("beneficiary_address".to_string(), 1).unwrap();
+        match event {
+            Event::InheritanceClaimed { deposit_id, depositor_address, beneficiary_address, claimed_amount, .. } => {
+                assert_eq!(deposit_id, 1);
+                assert_eq!(depositor_address, "depositor_address");
+                assert_eq!(beneficiary_address, "beneficiary_address");
+                assert_eq!(claimed_amount, 1000);
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Withdrawn);
+        assert_eq!(contract.deposit_registry[&1].claimed_by.as_deref(), Some("beneficiary_address"));
+        assert_eq!(contract.total_deposits.get(&TokenType::Bitcoin).copied().unwrap_or(0), 0);
+        assert_eq!(*sink.names.lock().unwrap(), vec!["Deposited", "InheritanceSet", "InheritanceClaimed"]);
+        
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 1),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+        assert!(matches!(
+            contract.withdraw("depositor_address".to_string(), 1),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+    }
+    
+    #[test]
+    fn test_inheritance_window_restarts_on_depositor_activity() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 500, 30, None).unwrap();
+        contract.set_inheritance("depositor_address".to_string(), 1, "beneficiary_address".to_string(), 60).unwrap();
+        contract.set_inheritance("depositor_address".to_string(), 2, "beneficiary_address".to_string(), 60).unwrap();
+        
+        // A heartbeat shortly before the window closes restarts it
+        clock.advance(chrono::Duration::days(30 + 59));
+        contract.heartbeat("depositor_address".to_string()).unwrap();
+        assert_eq!(contract.get_last_activity("depositor_address"), Some(crate::clock::Clock::now(clock.as_ref())));
+        
+        clock.advance(chrono::Duration::days(2));
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 1),
+            Err(ContractError::InheritanceNotClaimable)
+        ));
+        
+        // The depositor can still withdraw normally, which also counts as activity
+        clock.advance(chrono::Duration::days(57));
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 1),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+        
+        clock.advance(chrono::Duration::days(59));
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 2),
+            Err(ContractError::InheritanceNotClaimable)
+        ));
+        
+        clock.advance(chrono::Duration::days(1));
+        contract.claim_inherited("beneficiary_address".to_string(), 2).unwrap();
+        
+        // Activity survives a state export
+        let state = contract.export_state();
+        assert_eq!(state.last_activity.len(), 1);
+        assert_eq!(state.last_activity[0].0, "depositor_address");
+    }
+    
+    #[test]
+    fn test_inheritance_cleared_on_deposit_assignment() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.set_inheritance("depositor_address".to_string(), 1, "beneficiary_address".to_string(), 10).unwrap();
+        contract.transfer_deposit_ownership("depositor_address".to_string(), 1, "new_owner_address".to_string()).unwrap();
+        
+        clock.advance(chrono::Duration::days(365));
+        assert!(matches!(
+            contract.claim_inherited("beneficiary_address".to_string(), 1),
+            Err(ContractError::Unauthorized)
+        ));
+    }
+    
+    #[test]
+    fn test_emergency_quote_honored_despite_fee_change() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        
+        assert!(matches!(
+            contract.quote_emergency_withdraw("other_address".to_string(), 1),
+            Err(ContractError::Unauthorized)
+        ));
+        let quote = contract.quote_emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert_eq!((quote.fee_amount, quote.net_amount), (100, 900));
+        assert_eq!(quote.valid_until, crate::clock::Clock::now(clock.as_ref()) + chrono::Duration::minutes(10));
+        
+        // Quotes are part of the exported state
+        let state = contract.export_state();
+        assert_eq!(state.emergency_quotes, vec![quote.clone()]);
+        
+        // The owner raises the fee before the quoted withdrawal lands
+        contract.fee_config.emergency_withdrawal_fee_percentage = 50;
+        clock.advance(chrono::Duration::minutes(9));
+        
+        assert!(matches!(
+            contract.emergency_withdraw_with_quote("depositor_address".to_string(), 2, quote.quote_id),
+            Err(ContractError::QuoteMismatch)
+        ));
+        
+        match contract.emergency_withdraw_with_quote("depositor_address".to_string(), 1, quote.quote_id).unwrap() {
+            Event::EmergencyWithdrawn { withdrawn_amount, fee_amount, .. } => {
+                assert_eq!(withdrawn_amount, 900);
+                assert_eq!(fee_amount, 100);
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 100);
+        
+        // Quotes are single-use, and unquoted withdrawals pay the current fee
+        assert!(matches!(
+            contract.emergency_withdraw_with_quote("depositor_address".to_string(), 1, quote.quote_id),
+            Err(ContractError::QuoteNotFound)
+        ));
+        match contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap() {
+            Event::EmergencyWithdrawn { fee_amount, .. } => assert_eq!(fee_amount, 500),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_emergency_quote_rejected_after_expiry() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        
+        assert!(matches!(
+            contract.set_quote_validity("depositor_address".to_string(), Duration::from_secs(60), None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_quote_validity("owner_address".to_string(), Duration::from_secs(60), None).unwrap();
+        
+        let quote = contract.quote_emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        clock.advance(chrono::Duration::seconds(60));
+        
+        assert!(matches!(
+            contract.emergency_withdraw_with_quote("depositor_address".to_string(), 1, quote.quote_id),
+            Err(ContractError::QuoteExpired)
+        ));
+        assert!(contract.deposit_registry[&1].status == DepositStatus::Active);
+        
+        // Expired quotes are pruned when the next quote is issued
+        let first = contract.quote_emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        clock.advance(chrono::Duration::seconds(61));
+        let second = contract.quote_emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(second.quote_id > first.quote_id);
+        assert_eq!(contract.export_state().emergency_quotes, vec![second]);
+    }
+    
+    /// Wallet UTXO returned by the mocked `listunspent`
+    fn mock_utxo(vout: u32) -> UtxoRef {
+        UtxoRef::new(&"ab".repeat(32), vout).unwrap()
+    }
+    
+    /// Inputs (as `txid:vout`) of each created transaction
+    fn created_transaction_inputs(transport: &MockRpcTransport) -> Vec<Vec<String>> {
+        transport.calls("createrawtransaction").iter()
+            .map(|params| params[0].as_array().unwrap().iter()
+                .map(|input| format!("{}:{}", input["txid"].as_str().unwrap(), input["vout"]))
+                .collect())
+            .collect()
+    }
+    
+    #[test]
+    fn test_withdrawal_spends_chosen_utxos() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        // Both chosen outputs are spent, in the order given, even though one would do
+        let options = WithdrawOptions {
+            coin_selection: Some(CoinControl::Only(vec![mock_utxo(7), mock_utxo(3)])),
+            ..Default::default()
+        };
+        contract.withdraw_with_options(RPC_RECIPIENT.to_string(), 1, options).unwrap();
+        
+        assert_eq!(created_transaction_inputs(&transport), vec![vec![mock_utxo(7).to_string(), mock_utxo(3).to_string()]]);
+        assert_eq!(created_payment_amounts(&transport), vec![100_000]);
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Withdrawn);
+    }
+    
+    #[test]
+    fn test_coin_control_rejects_unspendable_utxos() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        // An output the wallet does not hold is refused before anything is signed
+        let unknown = UtxoRef::new(&"cd".repeat(32), 0).unwrap();
+        let options = WithdrawOptions {
+            coin_selection: Some(CoinControl::Only(vec![unknown.clone()])),
+            ..Default::default()
+        };
+        let result = contract.withdraw_with_options(RPC_RECIPIENT.to_string(), 1, options);
+        assert!(matches!(result, Err(ContractError::UtxoUnavailable { ref reference, .. }) if *reference == unknown.to_string()));
+        assert!(transport.calls("createrawtransaction").is_empty());
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Active);
+        
+        // Chosen outputs must cover the payout and its fee
+        let options = WithdrawOptions {
+            coin_selection: Some(CoinControl::Only(vec![mock_utxo(0)])),
+            ..Default::default()
+        };
+        let (transfer, _transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let result = transfer.transfer_from_contract_with_options(RPC_RECIPIENT, &TokenType::Bitcoin, 100_000_000, TransferPriority::Normal, &options);
+        assert!(matches!(result, Err(ContractError::InsufficientBalance { .. })));
+        
+        // Coin control only applies to Bitcoin payouts
+        let result = transfer.transfer_from_contract_with_options(RPC_RECIPIENT, &TokenType::Ethereum, 1_000, TransferPriority::Normal, &options);
+        assert!(matches!(result, Err(ContractError::UnsupportedTokenOperation)));
+    }
+    
+    #[test]
+    fn test_coin_control_skips_reserved_and_excluded_utxos() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let rpc_client = BitcoinRpcClient::from_client(client, &config);
+        
+        // An output held by a transaction not broadcast yet cannot be chosen
+        let only = CoinControl::Only(vec![mock_utxo(4)]);
+        let built = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 50_000, 1.0, None, Some(&only)).unwrap();
+        assert_eq!(built.inputs, vec![mock_utxo(4).to_string()]);
+        
+        let result = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 50_000, 1.0, None, Some(&only));
+        assert!(matches!(result, Err(ContractError::UtxoUnavailable { ref reason, .. }) if reason.contains("reserved")));
+        
+        // Excluded outputs are left alone by automatic selection
+        let excluded: Vec<UtxoRef> = (0..9).filter(|vout| *vout != 4).map(mock_utxo).collect();
+        let exclude = CoinControl::Exclude(excluded);
+        let built = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 50_000, 1.0, None, Some(&exclude)).unwrap();
+        assert_eq!(built.inputs, vec![mock_utxo(9).to_string()]);
+        
+        // Once everything spendable is excluded, nothing is left to select
+        let result = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 50_000, 1.0, None, Some(&exclude));
+        assert!(matches!(result, Err(ContractError::InsufficientBalance { .. })));
+        
+        // A forced strategy still leaves reserved outputs alone
+        rpc_client.release_utxos(&[mock_utxo(4).to_string()]);
+        let strategy = CoinControl::Strategy(SelectionStrategy::Knapsack);
+        let built = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 50_000, 1.0, None, Some(&strategy)).unwrap();
+        assert_eq!(built.inputs.len(), 1);
+        assert_ne!(built.inputs[0], mock_utxo(9).to_string());
+    }
+    
+    /// Serialized transaction spending output 0 of itself and paying an address
+    fn raw_transaction_paying(address: &str) -> String {
+        use bitcoincore_rpc::bitcoin::{absolute::LockTime, consensus::encode::serialize_hex, Address, OutPoint, Transaction, TxIn, TxOut, Txid};
+        use std::str::FromStr;
+        
+        let script_pubkey = Address::from_str(address).unwrap().assume_checked().script_pubkey();
+        serialize_hex(&Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint { txid: Txid::from_str(&"ab".repeat(32)).unwrap(), vout: 0 },
+                ..TxIn::default()
+            }],
+            output: vec![TxOut { value: 1000, script_pubkey }],
+        })
+    }
+    
+    #[test]
+    fn test_incoming_funds_watcher_reports_unknown_outputs() {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let watcher = transfer.incoming_watcher();
+        transport.respond("getrawtransaction", serde_json::json!(raw_transaction_paying(RPC_RECIPIENT)));
+        
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = reported.clone();
+        watcher.set_callback(Arc::new(move |funds: &crate::bitcoin::incoming::IncomingFunds| {
+            seen.lock().unwrap().push(funds.outpoint());
+        })).unwrap();
+        
+        // Outputs of known transfers are never reported
+        watcher.mark_known(&mock_utxo(0).to_string()).unwrap();
+        let detected = watcher.poll().unwrap();
+        
+        assert_eq!(detected.len(), 9);
+        assert!(detected.iter().all(|funds| funds.amount == 100_000_000 && funds.sender_addresses == vec![RPC_RECIPIENT.to_string()]));
+        assert!(!detected.iter().any(|funds| funds.vout == 0));
+        assert_eq!(reported.lock().unwrap().len(), 9);
+        
+        // Each output is reported once
+        assert!(watcher.poll().unwrap().is_empty());
+        
+        // Change of the wallet's own payouts is not unattributed
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        transport.respond("getrawtransaction", serde_json::json!(raw_transaction_paying(RPC_CONTRACT_WALLET)));
+        assert!(transfer.incoming_watcher().poll().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_unattributed_funds_are_attributed_or_refunded_once() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        let funds = |vout: u32| crate::bitcoin::incoming::IncomingFunds {
+            txid: "CD".repeat(32),
+            vout,
+            amount: 50_000,
+            sender_addresses: vec!["sender_address".to_string()],
+            confirmations: 6,
+        };
+        let outpoint = |vout: u32| format!("{}:{}", "cd".repeat(32), vout);
+        
+        assert!(contract.record_incoming_funds(&funds(0)).is_some());
+        assert!(contract.record_incoming_funds(&funds(0)).is_none());
+        assert!(contract.record_incoming_funds(&funds(1)).is_some());
+        assert_eq!(
+            contract.list_unattributed_funds().iter().map(|entry| entry.outpoint.clone()).collect::<Vec<_>>(),
+            vec![outpoint(0), outpoint(1)]
+        );
+        
+        // Only the owner settles entries, and a rejected deposit keeps the entry listed
+        assert!(matches!(
+            contract.attribute_funds("depositor_address".to_string(), &outpoint(0), "depositor_address".to_string(), 30, None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.attribute_funds("owner_address".to_string(), &outpoint(0), "depositor_address".to_string(), 0, None),
+            Err(ContractError::InvalidLockPeriod { .. })
+        ));
+        assert_eq!(contract.list_unattributed_funds().len(), 2);
+        
+        // Attribution creates a deposit consuming the output
+        let event = contract.attribute_funds("owner_address".to_string(), &outpoint(0).to_uppercase(), "depositor_address".to_string(), 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_id: 1, deposit_amount: 50_000, .. }));
+        assert_eq!(contract.utxo_reference_owner(&outpoint(0)), Some(1));
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 50_000);
+        assert!(matches!(
+            contract.attribute_funds("owner_address".to_string(), &outpoint(0), "bob_address".to_string(), 30, None),
+            Err(ContractError::UnattributedFundsNotFound)
+        ));
+        
+        // Refunds settle the other entry
+        contract.refund_unattributed("owner_address".to_string(), &outpoint(1), "sender_address".to_string(), None).unwrap();
+        assert!(contract.list_unattributed_funds().is_empty());
+        assert!(matches!(
+            contract.refund_unattributed("owner_address".to_string(), &outpoint(1), "sender_address".to_string(), None),
+            Err(ContractError::UnattributedFundsNotFound)
+        ));
+        
+        // Settled outputs are not listed again, even after a restore
+        let mut restored = TimeLockedDeposit::from_state(contract.export_state(), contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer).unwrap();
+        assert!(restored.record_incoming_funds(&funds(0)).is_none());
+        assert!(restored.record_incoming_funds(&funds(1)).is_none());
+        assert_eq!(
+            *sink.names.lock().unwrap(),
+            vec!["UnattributedFundsDetected", "UnattributedFundsDetected", "Deposited", "UnattributedFundsRefunded"]
+        );
+    }
+    
+    
+    /// Fields an error must carry besides its code and message
+    ///
+    /// Deliberately exhaustive so a new or changed variant fails to compile
+    /// until its payload is listed here.
+    fn expected_error_fields(error: &ContractError) -> &'static [&'static str] {
+        match error {
+            ContractError::InvalidAmount { .. } => &["reason"],
+            ContractError::InvalidLockPeriod { .. } => &["min", "max", "requested"],
+            ContractError::InsufficientBalance { .. } => &["available", "required"],
+            ContractError::DepositLimitExceeded { .. } => &["token_type", "limit", "attempted"],
+            ContractError::TokenDeprecated { .. } | ContractError::TokenPaused { .. } => &["token_type"],
+            ContractError::MissingCapability { .. } => &["capability"],
+            ContractError::UserDepositLimitReached { .. } => &["limit", "current"],
+            ContractError::TotalDepositLimitReached { .. } => &["limit", "current", "attempted"],
+            ContractError::EmergencyTooSoon { .. } => &["available_at"],
+            ContractError::FeeDestinationTooSoon { .. } => &["activates_at"],
+            ContractError::EmergencyLimitReached { .. } => &["limit", "retry_at"],
+            ContractError::DepositNotAboveFee { .. } => &["fee", "amount"],
+            ContractError::FeeExceedsLimit { .. } => &["required", "limit"],
+            ContractError::DustOutput { .. } => &["amount", "threshold"],
+            ContractError::WithdrawalBelowMinimum { .. } => &["amount", "minimum"],
+            ContractError::InvalidUtxoReference { .. } => &["part", "reason"],
+            ContractError::DuplicateOutput { .. } => &["address"],
+            ContractError::ScriptTypeNotAllowed { .. } => &["script_type"],
+            ContractError::WrongNetwork { .. } => &["address_network", "expected_network"],
+            ContractError::Cancelled { .. } => &["stage", "processed"],
+            ContractError::UtxoUnavailable { .. } => &["reference", "reason"],
+            ContractError::TransferFailed { .. } => &["stage", "reason"],
+            ContractError::NonceMismatch { .. } => &["expected", "actual"],
+            ContractError::RpcError { .. } => &["operation"],
+            ContractError::MusigNonceReused { .. } => &["spend_id"],
+            ContractError::InitializationError(_)
+                | ContractError::BitcoinTestnetError(_)
+                | ContractError::BackendUnavailable(_)
+                | ContractError::InvalidGovernanceConfig(_)
+                | ContractError::InvalidConfirmationPolicy(_)
+                | ContractError::InvalidDescriptor(_)
+                | ContractError::BackendBusy(_)
+                | ContractError::InvalidImport(_)
+                | ContractError::InvalidSeriesResolution(_)
+                | ContractError::InvalidCircuitBreakerConfig(_)
+                | ContractError::InvalidInvoice(_)
+                | ContractError::MissingParameter(_)
+                | ContractError::InvalidForecast(_)
+                | ContractError::StatePersistenceFailed(_)
+                | ContractError::ExportFailed(_) => &["detail"],
+            ContractError::InvalidAddress
+                | ContractError::InvalidFeePercentage
+                | ContractError::DepositNotFound
+                | ContractError::DepositAlreadyWithdrawn
+                | ContractError::DepositLocked
+                | ContractError::Unauthorized
+                | ContractError::ContractPaused
+                | ContractError::UnsupportedTokenOperation
+                | ContractError::TokenValidationFailed
+                | ContractError::ArithmeticError
+                | ContractError::ReentrancyDetected
+                | ContractError::InvalidBitcoinTransaction
+                | ContractError::IdempotencyConflict
+                | ContractError::GovernanceRequired
+                | ContractError::ProposalNotFound
+                | ContractError::ProposalClosed
+                | ContractError::AlreadyApproved
+                | ContractError::ReservationNotFound
+                | ContractError::InvalidPreimage
+                | ContractError::UtxoAlreadyDeposited
+                | ContractError::DepositSuspended
+                | ContractError::InvalidInactivityPeriod
+                | ContractError::InheritanceNotClaimable
+                | ContractError::QuoteNotFound
+                | ContractError::QuoteExpired
+                | ContractError::QuoteMismatch
+                | ContractError::UnattributedFundsNotFound
+                | ContractError::DepositPending
+                | ContractError::DepositExpired
+                | ContractError::EscrowNotConfigured
+                | ContractError::TreasuryPolicyNotConfigured
+                | ContractError::InvalidImportSignature
+                | ContractError::ManifestAlreadyImported
+                | ContractError::MigrationKeyNotConfigured
+                | ContractError::TvlMetricsNotEnabled
+                | ContractError::CircuitBreakerNotTripped
+                | ContractError::DepositIdCollision
+                | ContractError::IdSchemeLocked
+                | ContractError::DestinationNotWhitelisted
+                | ContractError::FeeDestinationNotProposed
+                | ContractError::WithdrawalInFlight
+                | ContractError::SelfReferral
+                | ContractError::DepositNotPending
+                | ContractError::DepositVoided
+                | ContractError::DepositNotVoided
+                | ContractError::TokenNotPaused
+                | ContractError::OutboxEntryNotFound
+                | ContractError::ReadOnlyMode => &[],
+        }
+    }
+    
+    #[test]
+    fn test_errors_serialize_with_their_fields() {
+        for error in sample_errors() {
+            let body = serde_json::to_value(&error).unwrap();
+            let object = body.as_object().unwrap();
+            
+            // Code and message always come first, then exactly the variant's fields
+            assert_eq!(body["code"], error.code().as_str());
+            assert_eq!(body["message"], error.to_string());
+            let expected = expected_error_fields(&error);
+            assert_eq!(object.len(), expected.len() + 2, "{:?}", error);
+            for field in expected {
+                assert!(object.contains_key(*field), "{:?} lacks {}", error, field);
+            }
+        }
+        
+        // Field values are machine-readable rather than baked into the message
+        let body = serde_json::to_value(ContractError::InvalidLockPeriod { min: 1, max: 3650, requested: 4000 }).unwrap();
+        assert_eq!(body["min"], 1);
+        assert_eq!(body["max"], 3650);
+        assert_eq!(body["requested"], 4000);
+        
+        // API error bodies carry the same fields next to the message
+        let response = crate::server::HttpResponse::contract_error(&ContractError::InsufficientBalance { available: 500, required: 1_000 });
+        assert_eq!(response.status, 400);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["code"], "INSUFFICIENT_BALANCE");
+        assert_eq!(body["available"], 500);
+        assert_eq!(body["required"], 1_000);
+        assert_eq!(body["retryable"], false);
+        assert!(body["error"].as_str().unwrap().contains("Insufficient"));
+    }
+    
+    #[test]
+    fn test_validation_errors_report_limits() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        contract.set_max_lock_days("owner_address".to_string(), 365, None).unwrap();
+        
+        let result = contract.deposit("owner_address".to_string(), TokenType::Bitcoin, 50_000, 400, None);
+        match result {
+            Err(ContractError::InvalidLockPeriod { min, max, requested }) => {
+                assert_eq!((min, max, requested), (1, 365, 400));
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+        
+        let result = contract.deposit("owner_address".to_string(), TokenType::Bitcoin, 2_000_000, 30, None);
+        match result {
+            Err(ContractError::InsufficientBalance { available, required }) => {
+                assert_eq!((available, required), (1_000_000, 2_000_000));
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+    
+    
+    #[test]
+    fn test_bundled_soak_scenarios_pass() {
+        for json in [include_str!("../../scenarios/lifecycle.json"), include_str!("../../scenarios/churn.json")] {
+            let scenario = crate::soak::Scenario::from_json(json).unwrap();
+            let expected_actions = scenario.action_count();
+            
+            let report = crate::soak::SoakRunner::new(scenario).unwrap().run();
+            assert!(report.passed(), "{}", report);
+            assert_eq!(report.actions_run, expected_actions);
+            assert!(report.simulated_end > report.simulated_start);
+            assert!(report.timings.contains_key("deposit"));
+        }
+    }
+    
+    #[test]
+    fn test_soak_reports_unexpected_outcomes_and_events() {
+        let json = r#"{
+            "name": "mismatches",
+            "tokens": ["Bitcoin"],
+            "steps": [
+                { "action": "deposit", "user": "alice", "token": "Bitcoin", "amount": 1000, "lock_days": 5, "label": "first" },
+                { "action": "repeat", "times": 2, "steps": [
+                    { "action": "withdraw", "user": "alice", "deposit": "first" }
+                ] },
+                { "action": "advance_clock", "days": 5 },
+                { "action": "withdraw", "user": "alice", "deposit": "first", "expect": { "error": "DEPOSIT_LOCKED" } },
+                { "action": "deposit", "user": "bob", "token": "Bitcoin", "amount": 2000, "lock_days": 5,
+                  "events": [{ "event": "Deposited", "fields": { "deposit_amount": 3000 } }] },
+                { "action": "withdraw", "user": "bob", "deposit": "unknown", "expect": { "error": "DEPOSIT_NOT_FOUND" } }
+            ]
+        }"#;
+        
+        let report = crate::soak::run_scenario(json).unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.actions_run, 7);
+        assert!(report.invariant_violation.is_none());
+        
+        // Every mismatch is reported with its position, nested steps included
+        let positions: Vec<&str> = report.failures.iter().map(|failure| failure.step.as_str()).collect();
+        assert_eq!(positions, vec!["2[1].1", "2[2].1", "4", "5"]);
+        assert!(report.failures[0].reason.contains("DEPOSIT_LOCKED"));
+        assert!(report.failures[2].reason.contains("succeeded"));
+        assert!(report.failures[3].reason.contains("deposit_amount"));
+        assert!(report.to_string().contains("result: FAILED"));
+        
+        // Malformed scenarios are rejected before anything runs
+        assert!(crate::soak::run_scenario(r#"{ "name": "bad", "steps": [{ "action": "teleport" }] }"#).is_err());
+    }
+    
+    #[test]
+    fn test_simulated_transfer_keeps_a_ledger() {
+        let transfer = crate::soak::SimulatedTransfer::new(1_000, std::collections::HashMap::from([("poor".to_string(), 10)]));
+        
+        transfer.transfer_to_contract("alice", &TokenType::Bitcoin, 400).unwrap();
+        assert_eq!(transfer.get_balance("alice", &TokenType::Bitcoin).unwrap(), 600);
+        assert_eq!(transfer.get_contract_balance(&TokenType::Bitcoin).unwrap(), 400);
+        assert_eq!(transfer.get_contract_balance(&TokenType::Lightning).unwrap(), 0);
+        
+        // Overdrafts and injected failures leave the ledger untouched
+        assert!(transfer.transfer_to_contract("poor", &TokenType::Bitcoin, 11).is_err());
+        transfer.fail_next(1);
+        assert!(transfer.transfer_from_contract("alice", &TokenType::Bitcoin, 100).is_err());
+        transfer.transfer_from_contract("alice", &TokenType::Bitcoin, 100).unwrap();
+        assert_eq!(transfer.get_balance("alice", &TokenType::Bitcoin).unwrap(), 700);
+        assert_eq!(transfer.get_balance("poor", &TokenType::Bitcoin).unwrap(), 10);
+    }
+    
+    
+    #[test]
+    fn test_secrets_never_appear_in_formatted_output() {
+        use crate::config::{Secret, SecretSource, VaultConfig};
+        
+        const PASSWORD: &str = "rpc-pass-7f3a9c";
+        const MACAROON: &str = "0201036c6e6402f801";
+        
+        // Secrets from every kind of source
+        let secret_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(secret_file.path(), format!("{}\n", MACAROON)).unwrap();
+        std::env::set_var("TEST_SECRET_FORMATTING_PASSWORD", PASSWORD);
+        
+        let json = serde_json::json!({
+            "rpc_url": "http://localhost:18332",
+            "rpc_username": "testuser",
+            "rpc_password": "env:TEST_SECRET_FORMATTING_PASSWORD",
+            "contract_wallet_address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            "owner_address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            "lightning_node_url": "http://localhost:9735",
+            "lightning_macaroon": format!("file:{}", secret_file.path().display()),
+        }).to_string();
+        let vault_config = VaultConfig::from_json(&json).unwrap();
+        assert_eq!(vault_config.rpc_password.expose_secret(), PASSWORD);
+        assert_eq!(vault_config.lightning_macaroon.as_ref().unwrap().expose_secret(), MACAROON);
+        
+        let bitcoin_config = vault_config.bitcoin_config();
+        assert!(bitcoin_config.validate().is_ok());
+        
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(MockRpcTransport::default()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &bitcoin_config));
+        let lightning_client = LightningClient::new(rpc_client, "http://localhost:9735".to_string(), MACAROON);
+        let inline: SecretSource = PASSWORD.parse().unwrap();
+        let secret = Secret::from(PASSWORD);
+        
+        let formatted = vec![
+            format!("{:?}", vault_config),
+            format!("{:#?}", vault_config),
+            format!("{:?}", bitcoin_config),
+            format!("{:#?}", bitcoin_config),
+            format!("{:?}", lightning_client),
+            format!("{:?}", inline),
+            format!("{:?}", secret),
+            format!("{}", secret),
+        ];
+        for output in formatted {
+            assert!(!output.contains(PASSWORD), "password leaked: {}", output);
+            assert!(!output.contains(MACAROON), "macaroon leaked: {}", output);
+        }
+        assert!(format!("{:?}", bitcoin_config).contains(crate::config::REDACTED));
+        
+        // Errors name the source, not the value
+        let missing: SecretSource = "env:TEST_SECRET_FORMATTING_MISSING".parse().unwrap();
+        assert!(missing.resolve().unwrap_err().contains("TEST_SECRET_FORMATTING_MISSING"));
+        assert!(VaultConfig::from_json(&json.replace("env:TEST_SECRET_FORMATTING_PASSWORD", "file:/nonexistent/secret")).is_err());
+        
+        std::env::remove_var("TEST_SECRET_FORMATTING_PASSWORD");
+    }
+    
+    #[test]
+    fn test_zeroize_clears_secret_buffers() {
+        use crate::config::Zeroize;
+        
+        let mut value = String::with_capacity(64);
+        value.push_str("wallet passphrase");
+        value.zeroize();
+        assert!(value.is_empty());
+        assert!(value.capacity() >= 64);
+        
+        let mut bytes = vec![0xAAu8; 32];
+        bytes.zeroize();
+        assert!(bytes.is_empty());
+        
+        // The buffer is overwritten, not just truncated
+        unsafe { bytes.set_len(32) };
+        assert!(bytes.iter().all(|byte| *byte == 0));
+    }
+    
+    #[cfg(feature = "encrypted-config")]
+    #[test]
+    fn test_encrypted_config_round_trip() {
+        use crate::config::{encrypted, Secret, VaultConfig};
+        
+        let json = serde_json::json!({
+            "rpc_url": "http://localhost:18332",
+            "rpc_username": "testuser",
+            "rpc_password": "rpc-pass-7f3a9c",
+            "contract_wallet_address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            "owner_address": "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+        }).to_string();
+        let passphrase = Secret::from("correct horse battery staple");
+        
+        let params = crate::store::Argon2Params { memory_kib: 64, iterations: 1, parallelism: 1 };
+        let encrypted_config = encrypted::encrypt_with_params(json.as_bytes(), &passphrase, params).unwrap();
+        assert!(encrypted::is_encrypted(encrypted_config.as_bytes()));
+        assert!(!encrypted_config.contains("rpc-pass-7f3a9c"));
+        
+        // Loading decrypts with the passphrase
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &encrypted_config).unwrap();
+        let vault_config = VaultConfig::load(file.path(), Some(&passphrase)).unwrap();
+        assert_eq!(vault_config.rpc_password.expose_secret(), "rpc-pass-7f3a9c");
+        assert!(VaultConfig::load(file.path(), None).is_err());
+        
+        // A wrong passphrase or a flipped byte is rejected
+        assert!(encrypted::decrypt(encrypted_config.as_bytes(), &Secret::from("wrong")).is_err());
+        let mut tampered = encrypted_config.into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(encrypted::decrypt(&tampered, &passphrase).is_err());
+        
+        // A forged cost is refused before any key is derived
+        use base64::Engine as _;
+        let forged = [&64u32.to_be_bytes()[..], &u32::MAX.to_be_bytes(), &1u32.to_be_bytes(), &[0; 16 + 24 + 16]].concat();
+        let forged = format!("{}{}", encrypted::MAGIC, base64::engine::general_purpose::STANDARD.encode(forged));
+        assert!(encrypted::decrypt(forged.as_bytes(), &passphrase).unwrap_err().contains("iterations"));
+        
+        // Files of the retired HMAC format are recognised but not decrypted
+        let retired = b"vault-encrypted-config:v1:AAGGoA==\n";
+        assert!(encrypted::is_encrypted(retired));
+        assert!(encrypted::decrypt(retired, &passphrase).unwrap_err().contains("unsupported format"));
+    }
+    
+    /// Testnet transfer batching payouts, talking to a mocked node and running on a mock clock
+    fn transfer_with_payout_batching(payout_batch: PayoutBatchConfig) -> (BitcoinTestnetTransfer, MockRpcTransport, Arc<MockClock>) {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.max_batch_size = 100;
+        config.rate_limit = 600_000;
+        config.payout_batch = Some(payout_batch);
+        
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut transfer = BitcoinTestnetTransfer::with_rpc_client(config, rpc_client);
+        transfer.set_clock(clock.clone());
+        
+        (transfer, transport, clock)
+    }
+    
+    /// Outputs of each created transaction in satoshis, by address
+    fn created_outputs(transport: &MockRpcTransport) -> Vec<std::collections::HashMap<String, u64>> {
+        transport.calls("createrawtransaction").iter()
+            .map(|params| raw_outputs(params).into_iter().collect())
+            .collect()
+    }
+    
+    #[test]
+    fn test_payout_batch_merges_destinations_into_one_transaction() {
+        let other_recipient = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn";
+        let (transfer, transport, clock) = transfer_with_payout_batching(PayoutBatchConfig {
+            window: Duration::from_secs(600),
+            ..PayoutBatchConfig::default()
+        });
+        
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 1).unwrap();
+        transfer.transfer_from_contract_batched(other_recipient, &TokenType::Bitcoin, 20_000, 2).unwrap();
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 5_000, 3).unwrap();
+        
+        // Nothing is sent before the window elapses
+        assert!(transfer.flush_due_payouts().unwrap().is_empty());
+        assert_eq!(transfer.queued_payouts(), vec![1, 2, 3]);
+        assert!(transport.calls("createrawtransaction").is_empty());
+        
+        clock.advance(chrono::Duration::seconds(600));
+        let result = transfer.process_pending_transactions().unwrap();
+        
+        // One transaction, one output per destination plus change
+        let outputs = created_outputs(&transport);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].len(), 3);
+        assert_eq!(outputs[0][RPC_RECIPIENT], 15_000);
+        assert_eq!(outputs[0][other_recipient], 20_000);
+        
+        // Every payout reports the batch's transaction ID
+        assert!(result.failed.is_empty());
+        let references: Vec<u64> = result.txids.iter().map(|(reference, _)| *reference).collect();
+        assert_eq!(references, vec![1, 2, 3]);
+        assert!(result.txids.iter().all(|(_, txid)| *txid == result.txids[0].1));
+        assert!(transfer.queued_payouts().is_empty());
+        
+        // Other tokens are not batched
+        assert!(transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Rune("RUNE_TEST_TOKEN_123".to_string()), 100, 4).is_ok());
+        assert!(transfer.queued_payouts().is_empty());
+        assert_eq!(transfer.pending_references().len(), 1);
+    }
+    
+    #[test]
+    fn test_payout_batch_limits_and_dust() {
+        let third_recipient = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+        let other_recipient = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn";
+        let (transfer, transport, _clock) = transfer_with_payout_batching(PayoutBatchConfig {
+            window: Duration::from_secs(600),
+            max_outputs: 2,
+            max_total: 50_000,
+        });
+        
+        // Dust is rejected outright
+        let dust = transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 545, 1);
+        assert!(dust.unwrap_err().contains("dust threshold of 546"));
+        
+        // A third destination and a total above the limit each start a new batch
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 2).unwrap();
+        transfer.transfer_from_contract_batched(other_recipient, &TokenType::Bitcoin, 10_000, 3).unwrap();
+        transfer.transfer_from_contract_batched(third_recipient, &TokenType::Bitcoin, 10_000, 4).unwrap();
+        transfer.transfer_from_contract_batched(third_recipient, &TokenType::Bitcoin, 35_000, 5).unwrap();
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 6).unwrap();
+        
+        // A payout larger than a whole batch goes to the regular queue
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 60_000, 7).unwrap();
+        assert_eq!(transfer.queued_payouts(), vec![2, 3, 4, 5, 6]);
+        assert_eq!(transfer.pending_references().len(), 1);
+        
+        let result = transfer.flush_payout_batch().unwrap();
+        assert_eq!(result.succeeded.len(), 5);
+        
+        let outputs = created_outputs(&transport);
+        assert_eq!(outputs.len(), 3);
+        assert_eq!((outputs[0][RPC_RECIPIENT], outputs[0][other_recipient]), (10_000, 10_000));
+        assert_eq!(outputs[1][third_recipient], 45_000);
+        assert_eq!(outputs[2][RPC_RECIPIENT], 10_000);
+        
+        let txid_of = |reference: u64| result.succeeded.iter().find(|(r, _)| *r == reference).unwrap().1.clone();
+        assert_eq!(txid_of(2), txid_of(3));
+        assert_eq!(txid_of(4), txid_of(5));
+        assert_ne!(txid_of(3), txid_of(4));
+        assert_ne!(txid_of(5), txid_of(6));
+    }
+    
+    #[test]
+    fn test_failed_payout_batch_stays_queued() {
+        let (transfer, transport, _clock) = transfer_with_payout_batching(PayoutBatchConfig::default());
+        transport.fail_broadcasts_every(1);
+        
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 8).unwrap();
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 20_000, 9).unwrap();
+        
+        // Both payouts report the failure and wait for the next flush
+        let result = transfer.flush_payout_batch().unwrap();
+        assert!(result.succeeded.is_empty());
+        let failed: Vec<u64> = result.failed.iter().map(|(reference, _)| *reference).collect();
+        assert_eq!(failed, vec![8, 9]);
+        assert!(result.failed.iter().all(|(_, e)| e.code() == ErrorCode::TransferFailed));
+        assert_eq!(transfer.queued_payouts(), vec![8, 9]);
+        
+        // Inputs of the failed batch are released
+        let retry = transfer.flush_payout_batch().unwrap();
+        assert_eq!(retry.failed.len(), 2);
+        assert_eq!(created_outputs(&transport).len(), 2);
+    }
+    
+    /// RPC client for the contract wallet, talking to a mocked node
+    fn mock_rpc_client() -> (BitcoinRpcClient, MockRpcTransport) {
+        mock_rpc_client_with_page_size(1000)
+    }
+    
+    /// RPC client fetching at most `page_size` UTXOs per call from a mocked node
+    fn mock_rpc_client_with_page_size(page_size: usize) -> (BitcoinRpcClient, MockRpcTransport) {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        config.utxo_page_size = page_size;
+        
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        
+        (BitcoinRpcClient::from_client(client, &config), transport)
+    }
+    
+    #[test]
+    fn test_multi_output_transaction_order_change_and_fee() {
+        let (rpc_client, transport) = mock_rpc_client();
+        let second = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn";
+        let third = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+        let outputs = vec![
+            (third.to_string(), 30_000),
+            (RPC_RECIPIENT.to_string(), 10_000),
+            (second.to_string(), 20_000),
+        ];
+        
+        let signed = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Refuse, None).unwrap();
+        
+        // Payments keep their order and the change comes last
+        let created = raw_outputs(&transport.calls("createrawtransaction")[0]);
+        assert_eq!(created[..3], outputs[..]);
+        assert_eq!(created[3].0, RPC_CONTRACT_WALLET);
+        assert_eq!(created.len(), 4);
+        
+        // The fee is what the single 1 BTC input leaves after every output
+        assert_eq!(signed.inputs.len(), 1);
+        assert_eq!(signed.fee, 100_000_000 - 60_000 - created[3].1);
+        assert!(signed.fee > 0);
+        
+        // Nothing is broadcast until asked, and the node reports the same transaction ID
+        assert!(transport.calls("sendrawtransaction").is_empty());
+        assert_eq!(rpc_client.broadcast_transaction(&signed).unwrap(), signed.txid);
+        rpc_client.release_utxos(&signed.inputs);
+    }
+    
+    #[test]
+    fn test_multi_output_duplicates_and_zero_values() {
+        let (rpc_client, transport) = mock_rpc_client();
+        let second = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn";
+        let outputs = vec![
+            (RPC_RECIPIENT.to_string(), 10_000),
+            (second.to_string(), 20_000),
+            (RPC_RECIPIENT.to_string(), 5_000),
+        ];
+        
+        // Rejected duplicates name the address and reserve nothing
+        let result = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Refuse, None);
+        assert!(matches!(result, Err(ContractError::DuplicateOutput { ref address }) if address == RPC_RECIPIENT));
+        assert!(transport.calls("listunspent").is_empty());
+        
+        // Merged duplicates become one output at the first one's position
+        let signed = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Merge, DataOutputs::Refuse, None).unwrap();
+        rpc_client.release_utxos(&signed.inputs);
+        let created = raw_outputs(&transport.calls("createrawtransaction")[0]);
+        assert_eq!(created[..2], [(RPC_RECIPIENT.to_string(), 15_000), (second.to_string(), 20_000)]);
+        
+        // Zero-value and empty output lists are refused
+        let zero = vec![(RPC_RECIPIENT.to_string(), 10_000), (second.to_string(), 0)];
+        assert!(matches!(rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &zero, 10.0, DuplicateOutputs::Merge, DataOutputs::Refuse, None), Err(ContractError::InvalidAmount { .. })));
+        assert!(matches!(rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &[], 10.0, DuplicateOutputs::Merge, DataOutputs::Refuse, None), Err(ContractError::InvalidAmount { .. })));
+        assert_eq!(transport.calls("createrawtransaction").len(), 1);
+    }
+    
+    /// Signed transaction paying one P2WPKH output of 1000 satoshis followed by the given outputs
+    fn signed_transaction_with(outputs: &[(&str, u64)]) -> serde_json::Value {
+        use bitcoincore_rpc::bitcoin::{absolute::LockTime, consensus::encode::serialize_hex, ScriptBuf, Transaction, TxIn, TxOut};
+        use bitcoincore_rpc::bitcoin::hashes::hex::FromHex;
+        
+        let output = |script_hex: &str, value: u64| TxOut { value, script_pubkey: ScriptBuf::from(Vec::<u8>::from_hex(script_hex).unwrap()) };
+        let mut output_list = vec![output("0014751e76e8199196d454941c45d1b3a323f1433bd6", 1000)];
+        output_list.extend(outputs.iter().map(|(script_hex, value)| output(script_hex, *value)));
+        
+        let hex = serialize_hex(&Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: output_list,
+        });
+        serde_json::json!({ "hex": hex, "complete": true })
+    }
+    
+    #[test]
+    fn test_destination_policy_allows_standard_script_types() {
+        let policy = DestinationPolicy::default();
+        let addresses = [
+            ("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", ScriptType::P2pkh),
+            ("2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc", ScriptType::P2sh),
+            ("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx", ScriptType::P2wpkh),
+            ("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7", ScriptType::P2wsh),
+            ("tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c", ScriptType::P2tr),
+        ];
+        
+        // Every standard type is allowed by default
+        for (address, script_type) in addresses {
+            assert_eq!(policy.check_address(address).unwrap(), script_type);
+        }
+        
+        // Data carriers and non-standard scripts are refused, naming the detected type
+        let op_return = ScriptType::from_script_hex("6a0b68656c6c6f20776f726c64");
+        let op_true = ScriptType::from_script_hex("51");
+        assert_eq!(op_return, ScriptType::NullData);
+        assert_eq!(op_true, ScriptType::Unknown);
+        assert!(matches!(policy.check(op_return), Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::NullData })));
+        assert!(matches!(policy.check(op_true), Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::Unknown })));
+        assert_eq!(policy.check(op_return).unwrap_err().to_string(), "Script type OP_RETURN is not allowed as a destination");
+        
+        // A narrower allowlist refuses the rest, and OP_RETURN can never be allowed
+        let segwit_only = DestinationPolicy { allowed_script_types: vec![ScriptType::P2wpkh, ScriptType::P2tr, ScriptType::NullData] };
+        assert!(segwit_only.check_address("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").is_ok());
+        assert!(matches!(segwit_only.check_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"), Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::P2pkh })));
+        assert!(matches!(segwit_only.check_address("2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc"), Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::P2sh })));
+        assert!(segwit_only.check(ScriptType::NullData).is_err());
+        
+        // Unparseable addresses are still invalid
+        assert!(matches!(policy.check_address("not_an_address"), Err(ContractError::InvalidAddress)));
+        
+        // The policy is read from configuration, with the standard types as default
+        let parsed: DestinationPolicy = serde_json::from_str(r#"{"allowed_script_types":["P2wpkh"]}"#).unwrap();
+        assert_eq!(parsed.allowed_script_types, vec![ScriptType::P2wpkh]);
+        assert_eq!(serde_json::from_str::<DestinationPolicy>("{}").unwrap(), DestinationPolicy::default());
+    }
+    
+    #[test]
+    fn test_signed_transactions_refuse_data_outputs_unless_flagged() {
+        let (rpc_client, transport) = mock_rpc_client();
+        let outputs = vec![(RPC_RECIPIENT.to_string(), 10_000)];
+        let runestone = "6a5d0814c0a23314d00f";
+        
+        // An OP_RETURN output fails the build
+        transport.respond("signrawtransactionwithwallet", signed_transaction_with(&[(runestone, 0)]));
+        let result = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Refuse, None);
+        assert!(matches!(result, Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::NullData })));
+        
+        // Withdrawals have no way to allow it
+        let result = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 10_000, 10.0, None, None);
+        assert!(matches!(result, Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::NullData })));
+        
+        // Flagged, a runestone and its zero-value output go through
+        let signed = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Allow, None).unwrap();
+        rpc_client.release_utxos(&signed.inputs);
+        
+        // A zero-value payment is refused unless flagged
+        transport.respond("signrawtransactionwithwallet", signed_transaction_with(&[("0014751e76e8199196d454941c45d1b3a323f1433bd6", 0)]));
+        let result = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Refuse, None);
+        assert!(matches!(result, Err(ContractError::InvalidAmount { ref reason }) if reason == "output 1 has zero value"));
+        let signed = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Allow, None).unwrap();
+        rpc_client.release_utxos(&signed.inputs);
+        
+        // The flag does not let non-standard scripts through
+        transport.respond("signrawtransactionwithwallet", signed_transaction_with(&[("51", 1000)]));
+        let result = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Allow, None);
+        assert!(matches!(result, Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::Unknown })));
+    }
+    
+    #[test]
+    fn test_payout_destinations_follow_the_destination_policy() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        config.destination_policy = DestinationPolicy { allowed_script_types: vec![ScriptType::P2wpkh, ScriptType::P2wsh] };
+        let (transfer, _transport) = transfer_with_mock_config(config);
+        let legacy = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn";
+        
+        // The backend refuses payouts and sweeps to disallowed types
+        assert!(transfer.validate_destination(RPC_RECIPIENT).is_ok());
+        assert!(transfer.transfer_from_contract(legacy, &TokenType::Bitcoin, 10_000).unwrap_err().contains("P2PKH"));
+        assert!(matches!(
+            transfer.sweep_from_contract(legacy, &TokenType::Bitcoin, 10_000),
+            Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::P2pkh })
+        ));
+        
+        // So do the fee withdrawal destination and the treasury cold address
+        let mut contract = TimeLockedDeposit::new_with_defaults(RPC_CONTRACT_WALLET.to_string(), 10, transfer).unwrap();
+        contract.set_min_age_before_emergency(RPC_CONTRACT_WALLET.to_string(), Duration::ZERO, None).unwrap();
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.emergency_withdraw(RPC_RECIPIENT.to_string(), 1).unwrap();
+        assert!(matches!(
+            contract.propose_fee_destination(RPC_CONTRACT_WALLET.to_string(), legacy.to_string(), None),
+            Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::P2pkh })
+        ));
+        assert!(matches!(
+            contract.withdraw_fees(RPC_CONTRACT_WALLET.to_string(), TokenType::Bitcoin, None, Some(legacy.to_string()), None),
+            Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::P2pkh })
+        ));
+        let policy = TreasuryPolicy { hot_wallet_ceiling: 100_000, float_days: 7, cold_address: legacy.to_string() };
+        assert!(matches!(
+            contract.set_treasury_policy(RPC_CONTRACT_WALLET.to_string(), Some(policy), None),
+            Err(ContractError::ScriptTypeNotAllowed { script_type: ScriptType::P2pkh })
+        ));
+        
+        // Allowed destinations are unaffected
+        contract.propose_fee_destination(RPC_CONTRACT_WALLET.to_string(), RPC_RECIPIENT.to_string(), None).unwrap();
+    }
+    
+    #[test]
+    fn test_network_guard_reads_hrp_and_version_bytes() {
+        let testnet = NetworkGuard::new(Network::Testnet);
+        let signet = NetworkGuard::new(Network::Signet);
+        let regtest = NetworkGuard::new(Network::Regtest);
+        let mainnet_bech32 = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        let mainnet_p2pkh = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2";
+        let mainnet_p2sh = "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy";
+        let regtest_bech32 = "bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080";
+        
+        assert_eq!(AddressNetwork::of(mainnet_bech32), Some(AddressNetwork::Mainnet));
+        assert_eq!(AddressNetwork::of(&mainnet_bech32.to_uppercase()), Some(AddressNetwork::Mainnet));
+        assert_eq!(AddressNetwork::of(mainnet_p2pkh), Some(AddressNetwork::Mainnet));
+        assert_eq!(AddressNetwork::of(mainnet_p2sh), Some(AddressNetwork::Mainnet));
+        assert_eq!(AddressNetwork::of(RPC_CONTRACT_WALLET), Some(AddressNetwork::Testnet));
+        assert_eq!(AddressNetwork::of(regtest_bech32), Some(AddressNetwork::Regtest));
+        assert_eq!(AddressNetwork::of("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn"), Some(AddressNetwork::TestBase58));
+        assert_eq!(AddressNetwork::of("2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc"), Some(AddressNetwork::TestBase58));
+        assert_eq!(AddressNetwork::of("depositor_address"), None);
+        
+        // Testnet and signet share the tb prefix, Base58 test addresses are valid on every test network
+        assert!(testnet.check(RPC_CONTRACT_WALLET).is_ok());
+        assert!(signet.check(RPC_CONTRACT_WALLET).is_ok());
+        assert!(regtest.check(regtest_bech32).is_ok());
+        assert!(regtest.check("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn").is_ok());
+        
+        // Errors name both networks
+        let error = testnet.check(mainnet_bech32).unwrap_err();
+        assert_eq!(error.to_string(), "Address is for mainnet but the vault runs on testnet");
+        assert_eq!(error.code(), ErrorCode::WrongNetwork);
+        assert_eq!(regtest.check(RPC_CONTRACT_WALLET).unwrap_err().to_string(), "Address is for testnet but the vault runs on regtest");
+        assert_eq!(testnet.check(regtest_bech32).unwrap_err().to_string(), "Address is for regtest but the vault runs on testnet");
+        assert!(matches!(testnet.check("not_an_address"), Err(ContractError::InvalidAddress)));
+        
+        // Configurations refuse mainnet and addresses of another network
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.network = Network::Regtest;
+        assert!(config.validate().unwrap_err().contains("runs on regtest"));
+        config.network = Network::Bitcoin;
+        assert!(config.validate().is_err());
+        
+        let mut vault_config = doctor_config();
+        assert!(vault_config.validate_addresses().is_ok());
+        vault_config.owner_address = mainnet_p2pkh.to_string();
+        assert!(vault_config.validate_addresses().unwrap_err().contains("Address is for mainnet but the vault runs on testnet"));
+        vault_config.owner_address = RPC_CONTRACT_WALLET.to_string();
+        vault_config.contract_wallet_address = mainnet_bech32.to_string();
+        assert!(vault_config.validate_addresses().is_err());
+        vault_config.contract_wallet_address = RPC_CONTRACT_WALLET.to_string();
+        vault_config.network = Network::Bitcoin;
+        assert!(vault_config.validate_addresses().is_err());
+    }
+    
+    #[test]
+    fn test_address_entry_points_refuse_wrong_network() {
+        type Contract = TimeLockedDeposit<BitcoinTestnetTransfer>;
+        type EntryPoint = fn(&mut Contract, &str) -> Result<(), ContractError>;
+        
+        // A testnet vault holding a deposit and collected fees
+        let vault = || {
+            let mut config = BitcoinTestnetConfig::new(
+                "http://localhost:18332".to_string(),
+                "testuser".to_string(),
+                "testpassword".to_string(),
+                RPC_CONTRACT_WALLET.to_string(),
+            );
+            config.rate_limit = 600_000;
+            let (transfer, _transport) = transfer_with_mock_config(config);
+            let mut contract = TimeLockedDeposit::new_with_defaults(RPC_CONTRACT_WALLET.to_string(), 10, transfer).unwrap();
+            contract.set_min_age_before_emergency(RPC_CONTRACT_WALLET.to_string(), Duration::ZERO, None).unwrap();
+            contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+            contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+            contract.emergency_withdraw(RPC_RECIPIENT.to_string(), 2).unwrap();
+            contract
+        };
+        
+        let entry_points: Vec<(&str, EntryPoint)> = vec![
+            ("deposit", |contract, address| contract.deposit(address.to_string(), TokenType::Bitcoin, 10_000, 30, None).map(|_| ())),
+            ("withdraw", |contract, address| contract.withdraw(address.to_string(), 1).map(|_| ())),
+            ("emergency_withdraw", |contract, address| contract.emergency_withdraw(address.to_string(), 1).map(|_| ())),
+            ("withdraw_fees", |contract, address| contract.withdraw_fees(RPC_CONTRACT_WALLET.to_string(), TokenType::Bitcoin, None, Some(address.to_string()), None).map(|_| ())),
+            ("set_fee_collector", |contract, address| contract.set_fee_collector(RPC_CONTRACT_WALLET.to_string(), address.to_string(), None)),
+            ("transfer_ownership", |contract, address| contract.transfer_ownership(RPC_CONTRACT_WALLET.to_string(), address.to_string(), None)),
+            ("propose_fee_destination", |contract, address| contract.propose_fee_destination(RPC_CONTRACT_WALLET.to_string(), address.to_string(), None).map(|_| ())),
+            ("set_treasury_policy", |contract, address| {
+                let policy = TreasuryPolicy { hot_wallet_ceiling: 100_000, float_days: 7, cold_address: address.to_string() };
+                contract.set_treasury_policy(RPC_CONTRACT_WALLET.to_string(), Some(policy), None)
+            }),
+            ("transfer_deposit_ownership", |contract, address| contract.transfer_deposit_ownership(RPC_RECIPIENT.to_string(), 1, address.to_string()).map(|_| ())),
+            ("set_inheritance", |contract, address| contract.set_inheritance(RPC_RECIPIENT.to_string(), 1, address.to_string(), 30).map(|_| ())),
+            ("transfer_from_contract", |contract, address| contract.token_transfer.validate_destination(address)),
+        ];
+        let wrong_network = [
+            ("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", "mainnet"),
+            ("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", "mainnet"),
+            ("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", "mainnet"),
+            ("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080", "regtest"),
+        ];
+        
+        for (name, entry_point) in &entry_points {
+            for (address, network) in &wrong_network {
+                let mut contract = vault();
+                match entry_point(&mut contract, address) {
+                    Err(ContractError::WrongNetwork { address_network, expected_network }) => {
+                        assert_eq!((address_network.as_str(), expected_network.as_str()), (*network, "testnet"), "{} with {}", name, address);
+                    },
+                    other => panic!("{} accepted {}: {:?}", name, address, other),
+                }
+            }
+        }
+        
+        // Construction refuses a mainnet owner
+        for (address, _) in &wrong_network {
+            let (transfer, _transport) = transfer_with_mock_config(BitcoinTestnetConfig::new(
+                "http://localhost:18332".to_string(),
+                "testuser".to_string(),
+                "testpassword".to_string(),
+                RPC_CONTRACT_WALLET.to_string(),
+            ));
+            assert!(matches!(
+                TimeLockedDeposit::new_with_defaults(address.to_string(), 10, transfer),
+                Err(ContractError::WrongNetwork { .. })
+            ));
+        }
+        
+        // So does a multisig payment
+        let (rpc_client, _transport) = mock_rpc_client();
+        let mut multisig_client = MultisigClient::new(rpc_client, Network::Testnet);
+        assert!(matches!(
+            multisig_client.create_transaction("vault", wrong_network[0].0, 10_000, 10.0),
+            Err(ContractError::WrongNetwork { .. })
+        ));
+        
+        // Testnet addresses still go through
+        let mut contract = vault();
+        contract.set_fee_collector(RPC_CONTRACT_WALLET.to_string(), RPC_RECIPIENT.to_string(), None).unwrap();
+        contract.transfer_deposit_ownership(RPC_RECIPIENT.to_string(), 1, "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string()).unwrap();
+    }
+    
+    #[test]
+    fn test_maturity_reminder_window() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 2000, 30, None).unwrap();
+        let unlock_at = contract.deposit_registry[&1].unlock_timestamp;
+        
+        // Only the depositor can ask for a reminder
+        assert!(matches!(
+            contract.set_notification_preference("other_address".to_string(), 1, 3),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_notification_preference("depositor_address".to_string(), 1, 3).unwrap();
+        
+        // One second before the window opens nothing is due
+        let remind_at = unlock_at - chrono::Duration::days(3);
+        assert!(contract.collect_due_notifications(remind_at - chrono::Duration::seconds(1)).is_empty());
+        
+        // At the boundary only the deposit with a preference is due
+        let notices = contract.collect_due_notifications(remind_at);
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].deposit_id, 1);
+        assert_eq!(notices[0].unlock_at, unlock_at);
+        assert_eq!(notices[0].amount, 1000);
+        
+        // Once acknowledged it is not announced again
+        assert_eq!(contract.mark_notified(&[1, 2]), 1);
+        assert!(contract.collect_due_notifications(unlock_at).is_empty());
+        assert_eq!(contract.mark_notified(&[1]), 0);
+        
+        // A new preference re-arms the reminder
+        contract.set_notification_preference("depositor_address".to_string(), 1, 1).unwrap();
+        assert_eq!(contract.collect_due_notifications(unlock_at).len(), 1);
+    }
+    
+    #[test]
+    fn test_maturity_reminder_survives_restart() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 2000, 30, None).unwrap();
+        contract.set_notification_preference("depositor_address".to_string(), 1, 7).unwrap();
+        contract.set_notification_preference("depositor_address".to_string(), 2, 7).unwrap();
+        let unlock_at = contract.deposit_registry[&1].unlock_timestamp;
+        
+        let notices = contract.collect_due_notifications(unlock_at);
+        assert_eq!(notices.len(), 2);
+        contract.mark_notified(&[notices[0].deposit_id]);
+        
+        // The notified flag is part of the exported state
+        let state = contract.export_state();
+        let json = serde_json::to_string(&state).unwrap();
+        let restored_state = serde_json::from_str(&json).unwrap();
+        let restored = TimeLockedDeposit::from_state(restored_state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        
+        let pending = restored.collect_due_notifications(unlock_at);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].deposit_id, notices[1].deposit_id);
+        
+        // Withdrawn deposits no longer accept a preference
+        let mut contract = restored;
+        contract.set_clock(clock.clone());
+        clock.advance(chrono::Duration::days(31));
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(matches!(
+            contract.set_notification_preference("depositor_address".to_string(), 1, 7),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+    }
+    
+    /// Field of an Ordinals API fixture as a response body
+    fn ordinals_fixture(raw: &str, field: &str) -> String {
+        let fixture: serde_json::Value = serde_json::from_str(raw).unwrap();
+        fixture[field].to_string()
+    }
+    
+    #[test]
+    fn test_ordinals_hiro_fixtures() {
+        let raw = include_str!("fixtures/ordinals_hiro.json");
+        
+        // Hiro reports the collection and the sat with its rarity
+        let inscription = Inscription::from_api_response(OrdinalsApiFlavor::Hiro, &ordinals_fixture(raw, "inscription")).unwrap();
+        assert_eq!(inscription.number, 35);
+        assert_eq!(inscription.txid, "5dbc2b5e2a6e1a2c9e3f6c7bd06aa8c3b8e8d5d0f4a1a8c5b2ea1c0d3f4b5a69");
+        assert_eq!((inscription.vout, inscription.offset), (1, 330));
+        assert_eq!(inscription.owner, "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx");
+        assert_eq!(inscription.timestamp, 1677733170);
+        assert_eq!(inscription.collection.as_deref(), Some("Testnet Frogs"));
+        assert_eq!(inscription.sat, Some(1232735286933201));
+        assert_eq!(inscription.rarity, Some(SatRarity::Common));
+        assert_eq!(inscription.content_length, Some(793));
+        
+        // Missing fields are None rather than errors
+        let minimal = Inscription::from_api_response(OrdinalsApiFlavor::Hiro, &ordinals_fixture(raw, "inscription_minimal")).unwrap();
+        assert_eq!(minimal.number, 1204);
+        assert_eq!(minimal.owner, "");
+        assert_eq!(minimal.collection, None);
+        assert_eq!(minimal.sat, None);
+        assert_eq!(minimal.rarity, None);
+        assert_eq!(minimal.content_length, None);
+        
+        // The sat response has no number; the block is the coinbase height
+        let sat = SatInfo::from_api_response(OrdinalsApiFlavor::Hiro, 2016 * 5_000_000_000, &ordinals_fixture(raw, "sat")).unwrap();
+        assert_eq!(sat.rarity, SatRarity::Rare);
+        assert_eq!(sat.block, Some(2016));
+        assert_eq!(sat, SatInfo::from_number(2016 * 5_000_000_000).unwrap());
+        
+        // A response of the other flavor is rejected
+        assert!(Inscription::from_api_response(OrdinalsApiFlavor::Ord, &ordinals_fixture(raw, "inscription")).is_err());
+    }
+    
+    #[test]
+    fn test_ordinals_ord_fixtures() {
+        let raw = include_str!("fixtures/ordinals_ord.json");
+        
+        // ord reports the sat but not its rarity, which is derived from the sat number
+        let inscription = Inscription::from_api_response(OrdinalsApiFlavor::Ord, &ordinals_fixture(raw, "inscription")).unwrap();
+        assert_eq!(inscription.number, 7);
+        assert_eq!((inscription.vout, inscription.offset), (0, 0));
+        assert_eq!(inscription.content_type, "text/plain;charset=utf-8");
+        assert_eq!(inscription.timestamp, 1677733170);
+        assert_eq!(inscription.collection, None);
+        assert_eq!(inscription.sat, Some(1_050_000_000_000_000));
+        assert_eq!(inscription.rarity, Some(SatRarity::Epic));
+        assert_eq!(inscription.content_length, Some(1024));
+        
+        let minimal = Inscription::from_api_response(OrdinalsApiFlavor::Ord, &ordinals_fixture(raw, "inscription_minimal")).unwrap();
+        assert_eq!((minimal.vout, minimal.offset), (2, 100));
+        assert_eq!(minimal.owner, "");
+        assert_eq!(minimal.sat, None);
+        assert_eq!(minimal.rarity, None);
+        assert_eq!(minimal.content_length, None);
+        
+        // Rarity, name and block missing from the sat response are computed
+        let sat = SatInfo::from_api_response(OrdinalsApiFlavor::Ord, 5_000_000_000, &ordinals_fixture(raw, "sat")).unwrap();
+        assert_eq!(sat.rarity, SatRarity::Uncommon);
+        assert_eq!(sat.block, Some(1));
+        assert!(sat.name.is_some());
+        assert!(SatInfo::from_api_response(OrdinalsApiFlavor::Ord, 1, &ordinals_fixture(raw, "sat")).is_err());
+        
+        // The serialized inscription reads back with the enriched fields
+        let json = serde_json::to_string(&inscription).unwrap();
+        let read_back: Inscription = serde_json::from_str(&json).unwrap();
+        assert_eq!(read_back.rarity, Some(SatRarity::Epic));
+    }
+    
+    #[test]
+    fn test_sat_rarity_and_names() {
+        let block_subsidy = 5_000_000_000u64;
+        
+        assert_eq!(SatRarity::of(0), Some(SatRarity::Mythic));
+        assert_eq!(SatRarity::of(1), Some(SatRarity::Common));
+        assert_eq!(SatRarity::of(block_subsidy), Some(SatRarity::Uncommon));
+        assert_eq!(SatRarity::of(block_subsidy + 1), Some(SatRarity::Common));
+        assert_eq!(SatRarity::of(2016 * block_subsidy), Some(SatRarity::Rare));
+        assert_eq!(SatRarity::of(210_000 * block_subsidy), Some(SatRarity::Epic));
+        
+        // The first cycle ends after six halvings
+        let cycle_start: u64 = (0..6).map(|epoch| 210_000 * (block_subsidy >> epoch)).sum();
+        assert_eq!(SatRarity::of(cycle_start), Some(SatRarity::Legendary));
+        
+        // Names count down to "a" at the last sat
+        assert_eq!(SatInfo::from_number(0).unwrap().name.as_deref(), Some("nvtdijuwxlp"));
+        assert_eq!(SatInfo::from_number(2_099_999_997_689_999).unwrap().name.as_deref(), Some("a"));
+        assert_eq!(SatRarity::of(2_099_999_997_690_000), None);
+        assert!(SatInfo::from_number(2_099_999_997_690_000).is_none());
+        
+        // The client computes sat details without an index
+        let (rpc_client, _transport) = mock_rpc_client();
+        let ordinals_client = OrdinalsClient::new(Arc::new(rpc_client), "http://localhost:3000".to_string())
+            .with_api_flavor(OrdinalsApiFlavor::Hiro);
+        assert_eq!(ordinals_client.api_flavor(), OrdinalsApiFlavor::Hiro);
+        assert_eq!(ordinals_client.get_sat_info(2016 * block_subsidy).unwrap().rarity, SatRarity::Rare);
+        assert_eq!(ordinals_client.get_collection("inscription_1").unwrap(), None);
+    }
+    
+    /// Transfer backed by a fixed Ordinals index
+    #[derive(Debug)]
+    struct InscriptionIndexTransfer {
+        /// Known inscriptions by ID
+        inscriptions: std::collections::HashMap<String, Inscription>,
+    }
+    
+    impl TokenTransfer for InscriptionIndexTransfer {
+        fn transfer_to_contract(&self, _from_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+            Ok(())
+        }
+        
+        fn transfer_from_contract(&self, _to_address: &str, _token_type: &TokenType, _amount: u64) -> Result<(), String> {
+            Ok(())
+        }
+        
+        fn get_balance(&self, _address: &str, _token_type: &TokenType) -> Result<u64, String> {
+            Ok(1000)
+        }
+        
+        fn supports_token_type(&self, _token_type: &TokenType) -> bool {
+            true
+        }
+        
+        fn get_network_type(&self) -> String {
+            "testnet".to_string()
+        }
+        
+        fn get_inscription_details(&self, inscription_id: &str) -> Result<Option<Inscription>, String> {
+            Ok(self.inscriptions.get(inscription_id).cloned())
+        }
+    }
+    
+    #[test]
+    fn test_held_inscriptions_report() {
+        let raw = include_str!("fixtures/ordinals_ord.json");
+        let mut inscription = Inscription::from_api_response(OrdinalsApiFlavor::Ord, &ordinals_fixture(raw, "inscription")).unwrap();
+        let known = "0".repeat(64);
+        let unknown = "a".repeat(64);
+        inscription.id = known.clone();
+        
+        let transfer = InscriptionIndexTransfer {
+            inscriptions: [(known.clone(), inscription)].into_iter().collect(),
+        };
+        let mut contract = TimeLockedDeposit::new_with_defaults("owner_address".to_string(), 10, transfer).unwrap();
+        contract.add_supported_token("owner_address".to_string(), TokenType::Ordinal(unknown.clone()), None).unwrap();
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Ordinal(unknown.clone()), 1, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Ordinal(known.clone()), 1, 30, None).unwrap();
+        
+        // Only ordinal deposits are listed, with whatever metadata the index has
+        let held = contract.list_held_inscriptions();
+        assert_eq!(held.iter().map(|held| held.deposit_id).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(held[0].inscription_id, unknown);
+        assert!(held[0].details.is_none());
+        assert_eq!(held[1].details.as_ref().unwrap().rarity, Some(SatRarity::Epic));
+        
+        // The deposit view carries the same metadata
+        let view = contract.get_deposit_view(3).unwrap();
+        assert_eq!(view.inscription.as_ref().unwrap().content_length, Some(1024));
+        assert!(contract.get_deposit_view(1).unwrap().inscription.is_none());
+        assert!(serde_json::to_value(contract.get_deposit_view(1).unwrap()).unwrap().get("inscription").is_none());
+        
+        // Withdrawn inscriptions are no longer held
+        contract.emergency_withdraw("depositor_address".to_string(), 3).unwrap();
+        assert_eq!(contract.list_held_inscriptions().len(), 1);
+    }
+    
+    /// Healthy vault configuration for self-test tests
+    fn doctor_config() -> crate::config::VaultConfig {
+        crate::config::VaultConfig::from_json(&serde_json::json!({
+            "rpc_url": "http://localhost:18332",
+            "rpc_username": "testuser",
+            "rpc_password": "testpassword",
+            "contract_wallet_address": RPC_CONTRACT_WALLET,
+            "owner_address": "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn",
+        }).to_string()).unwrap()
+    }
+    
+    /// Self-test backends on a mocked, healthy testnet node whose median time trails the clock by an hour
+    fn doctor_backends() -> (crate::doctor::SelfTestBackends, MockRpcTransport, Arc<MockClock>) {
+        let (rpc_client, transport) = mock_rpc_client();
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        
+        transport.respond("getblockchaininfo", serde_json::json!({
+            "chain": "test",
+            "blocks": 2500000,
+            "headers": 2500000,
+            "bestblockhash": "00".repeat(32),
+            "difficulty": 1.0,
+            "mediantime": (clock.now() - chrono::Duration::hours(1)).timestamp(),
+            "verificationprogress": 1.0,
+            "initialblockdownload": false,
+            "chainwork": "00".repeat(32),
+            "size_on_disk": 0,
+            "pruned": false,
+            "warnings": "",
+        }));
+        transport.respond("getnetworkinfo", serde_json::json!({ "version": 250000 }));
+        transport.respond("getwalletinfo", serde_json::json!({ "walletname": "vault", "private_keys_enabled": true }));
+        transport.respond("getaddressinfo", serde_json::json!({ "address": RPC_CONTRACT_WALLET, "ismine": true, "iswatchonly": false, "solvable": true }));
+        
+        let backends = crate::doctor::SelfTestBackends {
+            rpc: Arc::new(rpc_client),
+            lightning: Some(FakeProbe::new(BackendComponent::Lightning, true)),
+            ordinals: None,
+            clock: clock.clone(),
+        };
+        
+        (backends, transport, clock)
+    }
+    
+    #[test]
+    fn test_self_test_healthy_report() {
+        use crate::doctor::{run_self_test_with, CheckStatus, SelfTestOptions};
+        
+        let (backends, _transport, _clock) = doctor_backends();
+        let data_dir = tempfile::tempdir().unwrap();
+        let options = SelfTestOptions {
+            persistence_paths: vec![data_dir.path().to_path_buf()],
+            ..SelfTestOptions::default()
+        };
+        
+        let report = run_self_test_with(&doctor_config(), &backends, &options);
+        assert!(report.passed());
+        assert_eq!(report.count(CheckStatus::Pass), report.checks.len());
+        assert_eq!(
+            report.checks.iter().map(|check| check.name.as_str()).collect::<Vec<_>>(),
+            vec!["bitcoin-rpc", "wallet", "fee-estimation", "contract-wallet", "lightning", "ordinals", "persistence", "clock-skew", "owner-address"]
+        );
+        assert_eq!(report.check("ordinals").unwrap().detail, "Not configured");
+        assert!(report.checks.iter().all(|check| check.remediation.is_none()));
+        
+        // The probe file is cleaned up
+        assert_eq!(std::fs::read_dir(data_dir.path()).unwrap().count(), 0);
+        
+        // The report prints one line per check plus a summary, and serializes as JSON
+        assert_eq!(report.to_string().lines().count(), report.checks.len() + 1);
+        assert!(report.to_string().ends_with("9 passed, 0 warnings, 0 failed"));
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["checks"][0]["status"], "pass");
+        assert_eq!(json["checks"][0]["remediation"], serde_json::Value::Null);
+    }
+    
+    #[test]
+    fn test_self_test_broken_backends() {
+        use crate::doctor::{run_self_test_with, CheckStatus, SelfTestBackends, SelfTestOptions};
+        
+        // Wrong chain, watch-only wallet, foreign contract address, absurd fees, skewed clock
+        let (mut backends, transport, clock) = doctor_backends();
+        let mut info: serde_json::Value = serde_json::json!({
+            "chain": "main",
+            "blocks": 800000,
+            "headers": 800000,
+            "bestblockhash": "00".repeat(32),
+            "difficulty": 1.0,
+            "mediantime": (clock.now() - chrono::Duration::hours(5)).timestamp(),
+            "verificationprogress": 1.0,
+            "initialblockdownload": false,
+            "chainwork": "00".repeat(32),
+            "size_on_disk": 0,
+            "pruned": false,
+            "warnings": "",
+        });
+        transport.respond("getblockchaininfo", info.clone());
+        transport.respond("getwalletinfo", serde_json::json!({ "walletname": "watcher", "private_keys_enabled": false }));
+        transport.respond("getaddressinfo", serde_json::json!({ "ismine": false, "iswatchonly": false, "solvable": false }));
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.05, "blocks": 6 }));
+        backends.lightning = Some(FakeProbe::new(BackendComponent::Lightning, false));
+        backends.ordinals = Some(FakeProbe::new(BackendComponent::Ordinals, true));
+        
+        let mut config = doctor_config();
+        config.owner_address = "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2".to_string();
+        let options = SelfTestOptions {
+            persistence_paths: vec![std::path::PathBuf::from("/nonexistent/vault-data")],
+            ..SelfTestOptions::default()
+        };
+        
+        let report = run_self_test_with(&config, &backends, &options);
+        assert!(!report.passed());
+        let status = |name: &str| report.check(name).unwrap().status;
+        assert_eq!(status("bitcoin-rpc"), CheckStatus::Fail);
+        assert_eq!(status("wallet"), CheckStatus::Warn);
+        assert_eq!(status("fee-estimation"), CheckStatus::Fail);
+        assert_eq!(status("contract-wallet"), CheckStatus::Fail);
+        assert_eq!(status("lightning"), CheckStatus::Fail);
+        assert_eq!(status("ordinals"), CheckStatus::Pass);
+        assert_eq!(status("persistence"), CheckStatus::Fail);
+        assert_eq!(status("clock-skew"), CheckStatus::Fail);
+        assert_eq!(status("owner-address"), CheckStatus::Fail);
+        assert!(report.checks.iter()
+            .filter(|check| check.status != CheckStatus::Pass)
+            .all(|check| check.remediation.is_some()));
+        assert!(report.to_string().contains("hint: Point BITCOIN_TESTNET_RPC_URL at a testnet node"));
+        
+        // A syncing node and a watched contract address only warn
+        info["chain"] = serde_json::json!("test");
+        info["initialblockdownload"] = serde_json::json!(true);
+        info["mediantime"] = serde_json::json!(clock.now().timestamp());
+        transport.respond("getblockchaininfo", info);
+        transport.respond("getaddressinfo", serde_json::json!({ "ismine": false, "iswatchonly": true, "solvable": true }));
+        let report = run_self_test_with(&config, &backends, &options);
+        assert_eq!(report.check("bitcoin-rpc").unwrap().status, CheckStatus::Warn);
+        assert_eq!(report.check("contract-wallet").unwrap().status, CheckStatus::Warn);
+        assert_eq!(report.check("clock-skew").unwrap().status, CheckStatus::Pass);
+        
+        // An unreachable node fails every check that needs it without querying further
+        let unreachable = SelfTestBackends {
+            rpc: Arc::new(BitcoinRpcClient::from_client(
+                bitcoincore_rpc::Client::new("http://127.0.0.1:1", bitcoincore_rpc::Auth::None).unwrap(),
+                &BitcoinTestnetConfig::new(String::new(), String::new(), String::new(), RPC_CONTRACT_WALLET.to_string()),
+            )),
+            lightning: None,
+            ordinals: None,
+            clock: clock.clone(),
+        };
+        let report = run_self_test_with(&doctor_config(), &unreachable, &SelfTestOptions::default());
+        for name in ["bitcoin-rpc", "wallet", "fee-estimation", "contract-wallet", "clock-skew"] {
+            assert_eq!(report.check(name).unwrap().status, CheckStatus::Fail, "{}", name);
+        }
+        assert_eq!(report.check("owner-address").unwrap().status, CheckStatus::Pass);
+        assert_eq!(report.count(CheckStatus::Fail), 5);
+    }
+    
+    
+    /// `listunspent` result of wallet outputs with these confirmations and amounts (BTC), `vout` being the index
+    fn wallet_outputs(outputs: &[(u64, f64)]) -> serde_json::Value {
+        serde_json::json!(outputs.iter().enumerate()
+            .map(|(vout, (confirmations, amount))| serde_json::json!({
+                "txid": "cd".repeat(32),
+                "vout": vout,
+                "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                "amount": amount,
+                "confirmations": confirmations,
+                "spendable": true,
+                "solvable": true,
+                "safe": true,
+            }))
+            .collect::<Vec<_>>())
+    }
+    
+    #[test]
+    fn test_utxo_pages_cover_every_output_once() {
+        let (rpc_client, transport) = mock_rpc_client_with_page_size(3);
+        
+        // Spread over many depths, with four identical outputs at one depth
+        let mut outputs: Vec<(u64, f64)> = (0..26).map(|i| ((i * 37) % 500, (i + 1) as f64 / 1000.0)).collect();
+        outputs.extend([(3, 0.5); 4]);
+        transport.respond("listunspent", wallet_outputs(&outputs));
+        
+        let mut pages: Vec<Vec<Utxo>> = Vec::new();
+        rpc_client.for_each_utxo_page(RPC_CONTRACT_WALLET, |page| {
+            pages.push(page.get_all().into_iter().cloned().collect());
+            std::ops::ControlFlow::Continue(())
+        }).unwrap();
+        
+        // Every output is listed exactly once
+        let mut vouts: Vec<u32> = pages.iter().flatten().map(|utxo| utxo.vout).collect();
+        vouts.sort();
+        assert_eq!(vouts, (0..30).collect::<Vec<u32>>());
+        
+        // Pages come in order of confirmations and only the identical outputs overflow one
+        for pair in pages.windows(2) {
+            let last = pair[0].iter().map(|utxo| utxo.confirmations).max().unwrap();
+            let next = pair[1].iter().map(|utxo| utxo.confirmations).min().unwrap();
+            assert!(last <= next);
+        }
+        assert!(pages.iter().all(|page| page.len() <= 3 || page.iter().all(|utxo| utxo.amount == 50_000_000)));
+        
+        // The collected set matches the pages
+        let utxos = rpc_client.get_address_utxos(RPC_CONTRACT_WALLET).unwrap();
+        assert_eq!(utxos.len(), 30);
+        
+        // Every call is bounded, except the one listing the identical outputs
+        let unbounded = transport.calls("listunspent").iter()
+            .filter(|params| params[4].get("maximumCount").is_none())
+            .count();
+        assert_eq!(unbounded, 2);
+    }
+    
+    #[test]
+    fn test_withdrawal_stops_fetching_utxos_once_funded() {
+        let (rpc_client, transport) = mock_rpc_client_with_page_size(5);
+        
+        // Forty 0.01 BTC outputs, the one at vout n having n + 1 confirmations
+        let outputs: Vec<(u64, f64)> = (1..=40).map(|confirmations| (confirmations, 0.01)).collect();
+        transport.respond("listunspent", wallet_outputs(&outputs));
+        
+        rpc_client.get_address_utxos(RPC_CONTRACT_WALLET).unwrap();
+        let full_listing = transport.calls("listunspent").len();
+        
+        let built = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 2_000_000, 1000.0, None, None).unwrap();
+        let withdrawal_listing = transport.calls("listunspent").len() - full_listing;
+        
+        // The listing stopped early, with only the least confirmed outputs fetched
+        assert!(withdrawal_listing < full_listing);
+        let last_range = transport.calls("listunspent").last().unwrap().clone();
+        assert!(last_range[1].as_u64().unwrap() < 40);
+        
+        // And they were enough to pay
+        assert!(!built.inputs.is_empty());
+        for input in &built.inputs {
+            let vout: u32 = input.rsplit(':').next().unwrap().parse().unwrap();
+            assert!(vout < last_range[1].as_u64().unwrap() as u32);
+        }
+    }
+    
+    
+    /// Contract recording its events in a log at `path`, checkpointed every three events
+    fn contract_with_event_log(path: &std::path::Path, clock: Arc<MockClock>) -> TimeLockedDeposit<MockTokenTransferMock> {
+        let mut config = EventLogConfig::new(path);
+        config.checkpoint_interval = 3;
+        let signer: Arc<dyn Signer> = Arc::new(KeySigner::new(&[7u8; 32]).unwrap());
+        
+        let mut event_log = EventLog::open(config, Some(signer)).unwrap();
+        event_log.set_clock(clock.clone());
+        
+        let mut contract = contract_with_clock(clock);
+        contract.add_event_sink(Arc::new(event_log));
+        contract
+    }
+    
+    #[test]
+    fn test_event_log_chain_and_checkpoints_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let vault_key = KeySigner::new(&[7u8; 32]).unwrap().public_key();
+        
+        // Four events: a checkpoint after the third, another when the log is dropped
+        let mut contract = contract_with_event_log(&path, clock.clone());
+        for _ in 0..4 {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        }
+        drop(contract);
+        
+        let verification = verify_event_log(&path, Some(&vault_key)).unwrap();
+        assert!(verification.is_intact());
+        assert_eq!(verification.entries_checked, 6);
+        assert_eq!(verification.checkpoints_verified, 2);
+        
+        // Reopening continues the same chain
+        let mut contract = contract_with_event_log(&path, clock);
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        drop(contract);
+        
+        let verification = verify_event_log(&path, Some(&vault_key)).unwrap();
+        assert!(verification.is_intact());
+        assert_eq!(verification.entries_checked, 8);
+        
+        // Checkpoints signed by another key are rejected at the first one
+        let other_key = KeySigner::new(&[8u8; 32]).unwrap().public_key();
+        let verification = verify_event_log(&path, Some(&other_key)).unwrap();
+        assert_eq!(verification.first_broken_index, Some(3));
+        assert_eq!(verification.entries_checked, 3);
+    }
+    
+    #[test]
+    fn test_event_log_detects_mutated_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        
+        let mut contract = contract_with_event_log(&path, clock);
+        for amount in [100_000, 200_000, 300_000, 400_000, 500_000] {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, amount, 30, None).unwrap();
+        }
+        drop(contract);
+        
+        // Entries: deposits 0-2, checkpoint 3, deposits 4-5, checkpoint 6
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 7);
+        
+        // Inflate the second deposit
+        let tampered: Vec<String> = lines.iter().enumerate()
+            .map(|(index, line)| match index {
+                1 => line.replace("\"deposit_amount\":200000", "\"deposit_amount\":900000"),
+                _ => line.to_string(),
+            })
+            .collect();
+        assert_ne!(tampered[1], lines[1]);
+        std::fs::write(&path, tampered.join("\n")).unwrap();
+        
+        let verification = verify_event_log(&path, None).unwrap();
+        assert_eq!(verification.first_broken_index, Some(1));
+        assert_eq!(verification.entries_checked, 1);
+        
+        // Rehashing the altered entry moves the break to the entry linking to it
+        let mut entry: serde_json::Value = serde_json::from_str(&tampered[1]).unwrap();
+        let record: crate::event_log::LogRecord = serde_json::from_value(entry["record"].clone()).unwrap();
+        let rehashed = crate::event_log::StoredEvent::new(1, entry["prev_hash"].as_str().unwrap().to_string(), record).unwrap();
+        entry["hash"] = serde_json::json!(rehashed.hash);
+        
+        let mut rewritten = tampered.clone();
+        rewritten[1] = entry.to_string();
+        std::fs::write(&path, rewritten.join("\n")).unwrap();
+        
+        let verification = verify_event_log(&path, None).unwrap();
+        assert_eq!(verification.first_broken_index, Some(2));
+    }
+    
+    #[test]
+    fn test_insurance_share_of_emergency_fee_rounds_up() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Only the owner sets the share, and never above 100%
+        assert!(matches!(
+            contract.set_insurance_share("depositor_address".to_string(), 50, None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_insurance_share("owner_address".to_string(), 101, None),
+            Err(ContractError::InvalidFeePercentage)
+        ));
+        contract.set_insurance_share("owner_address".to_string(), 50, None).unwrap();
+        
+        // A 10% fee on 1010 is 101, split 50/50 with the odd unit going to the pool
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1010, 30, None).unwrap();
+        match contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap() {
+            Event::EmergencyWithdrawn { fee_amount, insurance_amount, .. } => {
+                assert_eq!(fee_amount, 101);
+                assert_eq!(insurance_amount, 51);
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.insurance_pool_balance(&TokenType::Bitcoin), 51);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 50);
+        
+        // The pool survives an export and restore
+        let state = contract.export_state();
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.insurance_pool_balance(&TokenType::Bitcoin), 51);
+        assert_eq!(restored.fee_config.insurance_share_percentage, 50);
+    }
+    
+    #[test]
+    fn test_insurance_compensation_is_capped_by_pool() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_insurance_share("owner_address".to_string(), 100, None).unwrap();
+        
+        // Fund the pool with a 100 fee
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert_eq!(contract.insurance_pool_balance(&TokenType::Bitcoin), 100);
+        assert_eq!(contract.fee_config.collected_fees.get(&TokenType::Bitcoin).copied().unwrap_or(0), 0);
+        
+        // Only the owner pays, and never more than the pool
+        assert!(matches!(
+            contract.compensate_from_insurance("depositor_address".to_string(), CompensationTarget::Deposit(1), 40, "lost".to_string(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.compensate_from_insurance("owner_address".to_string(), CompensationTarget::Deposit(1), 101, "lost".to_string(), None),
+            Err(ContractError::InsufficientBalance { available: 100, required: 101 })
+        ));
+        assert!(matches!(
+            contract.compensate_from_insurance("owner_address".to_string(), CompensationTarget::Deposit(9), 40, "lost".to_string(), None),
+            Err(ContractError::DepositNotFound)
+        ));
+        assert_eq!(contract.insurance_pool_balance(&TokenType::Bitcoin), 100);
+        
+        match contract.compensate_from_insurance("owner_address".to_string(), CompensationTarget::Deposit(1), 40, "stuck payout".to_string(), None).unwrap() {
+            Event::InsuranceCompensationPaid { deposit_id, recipient_address, amount, reason, remaining_pool, .. } => {
+                assert_eq!(deposit_id, Some(1));
+                assert_eq!(recipient_address, "depositor_address");
+                assert_eq!((amount, remaining_pool), (40, 60));
+                assert_eq!(reason, "stuck payout");
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.insurance_pool_balance(&TokenType::Bitcoin), 60);
+        
+        // A pool of another token is empty
+        assert!(matches!(
+            contract.compensate_from_insurance(
+                "owner_address".to_string(),
+                CompensationTarget::Address { address: "depositor_address".to_string(), token_type: TokenType::Lightning },
+                1,
+                "lost".to_string(),
+                None,
+            ),
+            Err(ContractError::InsufficientBalance { available: 0, required: 1 })
+        ));
+        
+        // Under governance the payment needs a proposal
+        let owners = vec!["owner_a".to_string(), "owner_b".to_string()];
+        contract.enable_governance("owner_address".to_string(), owners, 2, Duration::from_secs(3600), None).unwrap();
+        assert!(matches!(
+            contract.compensate_from_insurance("owner_a".to_string(), CompensationTarget::Deposit(1), 10, "lost".to_string(), None),
+            Err(ContractError::GovernanceRequired)
+        ));
+        let action = OwnerAction::CompensateFromInsurance {
+            target: CompensationTarget::Deposit(1),
+            amount: 10,
+            reason: "lost".to_string(),
+        };
+        let proposal_id = contract.propose_action("owner_a".to_string(), action, None).unwrap();
+        assert_eq!(contract.approve_action("owner_b".to_string(), proposal_id, None).unwrap(), ProposalStatus::Executed);
+        assert_eq!(contract.insurance_pool_balance(&TokenType::Bitcoin), 50);
+    }
+    
+    #[test]
+    fn test_replayed_owner_call_fails_nonce_check() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Generate 100 in fees
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert_eq!(contract.get_owner_nonce(), 0);
+        
+        // The first withdrawal succeeds and advances the nonce, its replay is rejected
+        let nonce = contract.get_owner_nonce();
+        contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(30), None, Some(nonce)).unwrap();
+        assert_eq!(contract.get_owner_nonce(), 1);
+        assert!(matches!(
+            contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(30), None, Some(nonce)),
+            Err(ContractError::NonceMismatch { expected: 0, actual: 1 })
+        ));
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 70);
+        
+        // Setters are covered too, and neither failed nor unauthorized calls advance the nonce
+        contract.set_max_lock_days("owner_address".to_string(), 365, Some(1)).unwrap();
+        assert!(matches!(
+            contract.set_max_lock_days("owner_address".to_string(), 365, Some(1)),
+            Err(ContractError::NonceMismatch { expected: 1, actual: 2 })
+        ));
+        assert!(matches!(
+            contract.set_max_lock_days("depositor_address".to_string(), 365, Some(2)),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_max_emergency_withdrawals_per_month("owner_address".to_string(), Some(0), Some(2)),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        assert_eq!(contract.get_owner_nonce(), 2);
+        
+        // Calls without an expected nonce still advance it
+        contract.set_quote_validity("owner_address".to_string(), Duration::from_secs(60), None).unwrap();
+        assert_eq!(contract.get_owner_nonce(), 3);
+        
+        // The nonce is part of the exported state
+        let state = contract.export_state();
+        assert_eq!(state.owner_nonce, 3);
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_owner_nonce(), 3);
+    }
+    
+    #[test]
+    fn test_replayed_governance_approval_fails_nonce_check() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        let owners = vec!["owner_a".to_string(), "owner_b".to_string(), "owner_c".to_string()];
+        contract.enable_governance("owner_address".to_string(), owners, 2, Duration::from_secs(3600), Some(0)).unwrap();
+        
+        let action = OwnerAction::WithdrawFees { token_type: TokenType::Bitcoin, amount: Some(40), destination: None };
+        let proposal_id = contract.propose_action("owner_a".to_string(), action.clone(), Some(1)).unwrap();
+        assert!(matches!(
+            contract.propose_action("owner_a".to_string(), action, Some(1)),
+            Err(ContractError::NonceMismatch { expected: 1, actual: 2 })
+        ));
+        
+        // Executing the proposal counts once, a replayed approval is rejected before it is recorded
+        assert_eq!(contract.approve_action("owner_b".to_string(), proposal_id, Some(2)).unwrap(), ProposalStatus::Executed);
+        assert!(matches!(
+            contract.approve_action("owner_c".to_string(), proposal_id, Some(2)),
+            Err(ContractError::NonceMismatch { expected: 2, actual: 3 })
+        ));
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 60);
+        assert_eq!(contract.governance().unwrap().proposals[&proposal_id].approvals.len(), 2);
+    }
+    
+    #[test]
+    fn test_owner_nonce_header() {
+        let mut request = HttpRequest::new("POST", "/owner/fees");
+        assert_eq!(request.owner_nonce().unwrap_err().status, 428);
+        
+        request.headers.insert(crate::server::OWNER_NONCE_HEADER.to_string(), "seven".to_string());
+        assert_eq!(request.owner_nonce().unwrap_err().status, 400);
+        
+        request.headers.insert(crate::server::OWNER_NONCE_HEADER.to_string(), "7".to_string());
+        assert_eq!(request.owner_nonce().unwrap(), 7);
+        
+        let response = crate::server::HttpResponse::contract_error(&ContractError::NonceMismatch { expected: 7, actual: 8 });
+        assert_eq!(response.status, 409);
+    }
+    
+    /// Exported state of a contract holding `count` copies of one deposit, spread over ten users
+    fn state_with_deposits(count: u64) -> crate::contract::state::ContractState {
+        let mut contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        
+        let mut state = contract.export_state();
+        let template = state.deposits[0].clone();
+        state.deposits = (1..=count)
+            .map(|deposit_id| {
+                let mut deposit = template.clone();
+                deposit.deposit_id = deposit_id;
+                deposit.depositor_address = format!("user_{}", deposit_id % 10);
+                deposit
+            })
+            .collect();
+        state.next_deposit_id = count + 1;
+        state.total_deposits = vec![(TokenType::Bitcoin, 100_000 * count)];
+        state
+    }
+    
+    #[test]
+    fn test_json_snapshot_store_round_trip() {
+        use crate::store::{JsonSnapshotStore, StateStore};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let store = JsonSnapshotStore::new(dir.path().join("state.json"));
+        assert!(store.load_state().unwrap().is_none());
+        
+        let state = state_with_deposits(3);
+        store.save_state(&state).unwrap();
+        let loaded = store.load_state().unwrap().unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&state).unwrap());
+    }
+    
+    #[cfg(feature = "kv-store")]
+    #[test]
+    fn test_kv_store_loads_many_deposits_and_writes_only_changes() {
+        use crate::store::{KvStateStore, StateStore};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kv");
+        let mut state = state_with_deposits(50_000);
+        
+        KvStateStore::open(&path).unwrap().save_state(&state).unwrap();
+        
+        // Reopening replays the log and rebuilds the snapshot in bounded time
+        let started = std::time::Instant::now();
+        let store = KvStateStore::open(&path).unwrap();
+        let loaded = store.load_state().unwrap().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(60), "load took {:?}", started.elapsed());
+        assert_eq!(loaded.deposits.len(), 50_000);
+        assert_eq!(loaded.deposits[49_999].deposit_id, 50_000);
+        assert_eq!(store.deposit(42).unwrap().unwrap().depositor_address, "user_2");
+        assert_eq!(store.deposit_ids_of("user_3").unwrap().len(), 5_000);
+        
+        // A new deposit only writes itself, its user's index entry and the counters
+        let size_before = std::fs::metadata(&path).unwrap().len();
+        let mut deposit = state.deposits[0].clone();
+        deposit.deposit_id = 50_001;
+        deposit.depositor_address = "user_new".to_string();
+        state.deposits.push(deposit);
+        state.next_deposit_id = 50_002;
+        store.save_state(&state).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() - size_before < 4_096);
+        
+        let loaded = KvStateStore::open(&path).unwrap().load_state().unwrap().unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&state).unwrap());
+        assert_eq!(loaded.next_deposit_id, 50_002);
+    }
+    
+    #[cfg(feature = "kv-store")]
+    #[test]
+    fn test_kv_store_drops_torn_batch() {
+        use std::io::Write;
+        use crate::store::{KvBatch, KvStore};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.kv");
+        
+        let mut store = KvStore::open(&path).unwrap();
+        let mut batch = KvBatch::new();
+        batch.put("deposits", "1", "first");
+        batch.put("counters", "next_deposit_id", "2");
+        store.apply(batch).unwrap();
+        drop(store);
+        
+        // A crash in the middle of the next batch leaves half a line behind
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"0123abcd [{\"put\":{\"tree\":\"deposits\",\"key\":\"2\"").unwrap();
+        drop(file);
+        
+        let mut store = KvStore::open(&path).unwrap();
+        assert_eq!(store.get("deposits", "1"), Some("first"));
+        assert_eq!(store.get("deposits", "2"), None);
+        assert_eq!(store.get("counters", "next_deposit_id"), Some("2"));
+        
+        // Writing continues after the last complete batch
+        let mut batch = KvBatch::new();
+        batch.put("deposits", "2", "second");
+        batch.delete("deposits", "1");
+        store.apply(batch).unwrap();
+        store.compact().unwrap();
+        drop(store);
+        
+        let store = KvStore::open(&path).unwrap();
+        assert_eq!(store.get("deposits", "1"), None);
+        assert_eq!(store.get("deposits", "2"), Some("second"));
+        assert_eq!(store.log_batches(), 1);
+        drop(store);
+        
+        // Damage before the last batch is corruption, not a torn write
+        let mut contents = std::fs::read(&path).unwrap();
+        contents[70] ^= 1;
+        contents.extend_from_slice(&contents.clone());
+        std::fs::write(&path, contents).unwrap();
+        assert!(KvStore::open(&path).is_err());
+    }
+    
+    #[cfg(feature = "kv-store")]
+    #[test]
+    fn test_kv_store_migration_round_trip_and_events() {
+        use crate::store::{migrate_json_to_kv, migrate_kv_to_json, JsonSnapshotStore, KvStateStore, StateStore, TransferStateStore};
+        use crate::bitcoin::payout::PayoutEntry;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let json_path = dir.path().join("state.json");
+        let kv_path = dir.path().join("vault.kv");
+        let state = state_with_deposits(25);
+        JsonSnapshotStore::new(&json_path).save_state(&state).unwrap();
+        
+        assert_eq!(migrate_json_to_kv(&json_path, &kv_path).unwrap(), 25);
+        assert!(migrate_json_to_kv(&json_path, &kv_path).is_err());
+        
+        let back_path = dir.path().join("back.json");
+        assert_eq!(migrate_kv_to_json(&kv_path, &back_path).unwrap(), 25);
+        let back = JsonSnapshotStore::new(&back_path).load_state().unwrap().unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), serde_json::to_value(&state).unwrap());
+        
+        // Pending payouts and events live in their own trees
+        let store = Arc::new(KvStateStore::open(&kv_path).unwrap());
+        let payouts = vec![
+            PayoutEntry { destination: "tb1qfirst".to_string(), amount: 10_000, reference: 1 },
+            PayoutEntry { destination: "tb1qsecond".to_string(), amount: 20_000, reference: 2 },
+        ];
+        store.save_pending_payouts(&payouts).unwrap();
+        store.save_pending_payouts(&payouts[1..]).unwrap();
+        assert_eq!(store.load_pending_payouts().unwrap(), payouts[1..].to_vec());
+        
+        let mut contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        contract.add_event_sink(store.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        let events = store.events().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].name(), "Deposited");
+        assert_eq!(store.load_state().unwrap().unwrap().deposits.len(), 25);
+    }
+    
+    /// JSON-RPC handler over a fresh contract, with owner methods behind `secret-key`
+    fn jsonrpc_handler() -> crate::server::JsonRpcHandler<MockTokenTransferMock> {
+        let contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        crate::server::JsonRpcHandler::new(
+            Arc::new(std::sync::Mutex::new(contract)),
+            Some(crate::config::Secret::new("secret-key".to_string())),
+        )
+    }
+    
+    /// Send a JSON-RPC request body and decode the answer
+    fn jsonrpc_call(handler: &crate::server::JsonRpcHandler<MockTokenTransferMock>, request: serde_json::Value, api_key: Option<&str>) -> Option<serde_json::Value> {
+        handler.handle_body(request.to_string().as_bytes(), api_key)
+    }
+    
+    #[test]
+    fn test_jsonrpc_batch() {
+        let handler = jsonrpc_handler();
+        
+        // Answers keep the batch order, skip notifications and flag invalid entries
+        let responses = jsonrpc_call(&handler, serde_json::json!([
+            {"jsonrpc": "2.0", "method": "vault_deposit", "params": {"depositor": "depositor_address", "token_type": "Bitcoin", "amount": 1000, "lock_days": 30}, "id": 1},
+            {"jsonrpc": "2.0", "method": "vault_getStats"},
+            {"jsonrpc": "2.0", "method": "vault_getDeposit", "params": [1], "id": "second"},
+            5,
+        ]), None).unwrap();
+        
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["Deposited"]["deposit_id"], 1);
+        assert_eq!(responses[1]["id"], "second");
+        assert_eq!(responses[1]["result"]["deposit_id"], 1);
+        assert_eq!(responses[2]["id"], serde_json::Value::Null);
+        assert_eq!(responses[2]["error"]["code"], crate::server::jsonrpc::INVALID_REQUEST);
+        
+        // An empty batch is a single invalid request
+        let response = jsonrpc_call(&handler, serde_json::json!([]), None).unwrap();
+        assert_eq!(response["error"]["code"], crate::server::jsonrpc::INVALID_REQUEST);
+        
+        // A batch of notifications gets no answer, over HTTP an empty 204
+        assert!(jsonrpc_call(&handler, serde_json::json!([{"jsonrpc": "2.0", "method": "vault_getStats"}]), None).is_none());
+        
+        let server = ApiServer::new().with_jsonrpc(Arc::new(handler));
+        let mut request = HttpRequest::new("POST", "/jsonrpc");
+        request.body = br#"{"jsonrpc": "2.0", "method": "vault_getStats"}"#.to_vec();
+        let response = server.handle(&request);
+        assert_eq!(response.status, 204);
+        assert!(response.body.is_empty());
+        
+        request.body = br#"{"jsonrpc": "2.0", "method": "vault_listDeposits", "params": {"address": "depositor_address"}, "id": 7}"#.to_vec();
+        let response = server.handle(&request);
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["result"].as_array().unwrap().len(), 1);
+        
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/jsonrpc")).status, 405);
+    }
+    
+    #[test]
+    fn test_deposit_summaries_sort_tie_break_and_page_stably() {
+        use crate::contract::query::DepositFilter;
+        use crate::contract::summary::{DepositSummary, Page, SortKey};
+        
+        let user = "depositor_address".to_string();
+        
+        // Deposits made at the same moment tie on creation time, and on unlock time when locked as long
+        let seeded = || {
+            let mut contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+            for (amount, lock_days) in [(5_000, 30), (3_000, 10), (5_000, 30), (1_000, 60), (3_000, 10), (5_000, 20)] {
+                contract.deposit(user.clone(), TokenType::Bitcoin, amount, lock_days, None).unwrap();
+            }
+            contract.deposit("other_depositor".to_string(), TokenType::Bitcoin, 2_000, 10, None).unwrap();
+            contract
+        };
+        let contract = seeded();
+        
+        let ids = |page: &Page<DepositSummary>| page.items.iter().map(|summary| summary.id).collect::<Vec<u64>>();
+        let mine = DepositFilter::default().with_address(user.clone());
+        let active = DepositFilter::active().with_address(user.clone());
+        
+        // Every order is ascending with ties broken by ID, whether or not the unlock index serves it
+        assert_eq!(ids(&contract.list_deposit_summaries(mine.clone(), SortKey::UnlockTime, 0, 10)), vec![2, 5, 6, 1, 3, 4]);
+        assert_eq!(ids(&contract.list_deposit_summaries(active.clone(), SortKey::UnlockTime, 0, 10)), vec![2, 5, 6, 1, 3, 4]);
+        assert_eq!(ids(&contract.list_deposit_summaries(mine.clone(), SortKey::Amount, 0, 10)), vec![4, 2, 5, 1, 3, 6]);
+        assert_eq!(ids(&contract.list_deposit_summaries(mine.clone(), SortKey::CreatedAt, 0, 10)), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(ids(&contract.list_deposit_summaries(DepositFilter::default(), SortKey::Amount, 0, 10)), vec![4, 7, 2, 5, 1, 3, 6]);
+        
+        // Offset pages cover the order without overlap, and only the last has no cursor
+        for filter in [mine.clone(), active.clone()] {
+            let pages: Vec<_> = [0, 2, 4].iter().map(|offset| contract.list_deposit_summaries(filter.clone(), SortKey::UnlockTime, *offset, 2)).collect();
+            assert_eq!(pages.iter().flat_map(ids).collect::<Vec<_>>(), vec![2, 5, 6, 1, 3, 4]);
+            assert!(pages.iter().all(|page| page.total == 6));
+            assert!(pages[1].next_cursor.is_some());
+            assert!(pages[2].next_cursor.is_none());
+        }
+        
+        let summary = &contract.list_deposit_summaries(mine.clone(), SortKey::Amount, 0, 1).items[0];
+        assert_eq!((summary.id, summary.amount, summary.status), (4, 1_000, DepositStatus::Active));
+        
+        // Paging on by cursor neither repeats nor skips listed deposits while new ones are made
+        for (filter, sort, expected) in [
+            (active.clone(), SortKey::UnlockTime, vec![2, 5, 9, 6, 1, 3, 4]),
+            (mine.clone(), SortKey::Amount, vec![4, 2, 5, 9, 1, 3, 6]),
+        ] {
+            let mut contract = seeded();
+            let mut page = contract.list_deposit_summaries(filter.clone(), sort, 0, 2);
+            let mut listed = ids(&page);
+            
+            // One deposit sorts before the page just listed, one ties with its last row
+            contract.deposit(user.clone(), TokenType::Bitcoin, 500, 5, None).unwrap();
+            contract.deposit(user.clone(), TokenType::Bitcoin, 3_000, 10, None).unwrap();
+            
+            while let Some(cursor) = page.next_cursor {
+                page = contract.list_deposit_summaries_after(filter.clone(), cursor, 2);
+                listed.extend(ids(&page));
+            }
+            
+            assert_eq!(listed, expected);
+            assert_eq!(page.total, listed.len() + 1);
+        }
+        
+        // Details come back for the rows asked, in order, without unknown IDs
+        let details = contract.get_deposit_details(&[3, 999, 1]);
+        assert_eq!(details.iter().map(|view| view.deposit.deposit_id).collect::<Vec<_>>(), vec![3, 1]);
+        assert!(!details[0].is_unlocked);
+        
+        assert_eq!("amount".parse::<SortKey>().unwrap(), SortKey::Amount);
+        assert!("size".parse::<SortKey>().is_err());
+    }
+    
+    #[test]
+    fn test_jsonrpc_deposit_summaries_and_details() {
+        let handler = jsonrpc_handler();
+        for amount in [3_000, 1_000, 2_000] {
+            jsonrpc_call(&handler, serde_json::json!({"jsonrpc": "2.0", "method": "vault_deposit", "params": {"depositor": "depositor_address", "token_type": "Bitcoin", "amount": amount, "lock_days": 30}, "id": 1}), None).unwrap();
+        }
+        
+        let call = |method: &str, params: serde_json::Value| jsonrpc_call(&handler, serde_json::json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1}), None).unwrap();
+        
+        // The first page sorted by amount, then the rest after its cursor
+        let first = call("vault_listDepositSummaries", serde_json::json!({"filter": {"address": "depositor_address"}, "sort": "amount", "limit": 2}));
+        let result = &first["result"];
+        assert_eq!(result["total"], 3);
+        assert_eq!(result["items"].as_array().unwrap().iter().map(|item| item["id"].as_u64().unwrap()).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(result["items"][0]["amount"], 1_000);
+        
+        let rest = call("vault_listDepositSummaries", serde_json::json!({"after": result["next_cursor"]}));
+        assert_eq!(rest["result"]["items"][0]["id"], 1);
+        assert!(rest["result"]["next_cursor"].is_null());
+        
+        let details = call("vault_getDepositDetails", serde_json::json!({"deposit_ids": [2, 42]}));
+        assert_eq!(details["result"].as_array().unwrap().len(), 1);
+        assert_eq!(details["result"][0]["deposit_id"], 2);
+        
+        assert_eq!(call("vault_listDepositSummaries", serde_json::json!({"sort": "size"}))["error"]["code"], crate::server::jsonrpc::INVALID_PARAMS);
+    }
+    
+    #[test]
+    fn test_jsonrpc_errors() {
+        let handler = jsonrpc_handler();
+        let error_code = |request: serde_json::Value| jsonrpc_call(&handler, request, None).unwrap()["error"]["code"].clone();
+        
+        assert_eq!(
+            handler.handle_body(b"{not json", None).unwrap()["error"]["code"],
+            crate::server::jsonrpc::PARSE_ERROR
+        );
+        assert_eq!(
+            error_code(serde_json::json!({"jsonrpc": "1.0", "method": "vault_getStats", "id": 1})),
+            crate::server::jsonrpc::INVALID_REQUEST
+        );
+        assert_eq!(
+            error_code(serde_json::json!({"jsonrpc": "2.0", "method": "vault_transferAll", "id": 1})),
+            crate::server::jsonrpc::METHOD_NOT_FOUND
+        );
+        
+        // Missing, mistyped and unknown params are rejected before touching the contract
+        for params in [
+            serde_json::json!({"caller": "depositor_address"}),
+            serde_json::json!({"caller": "depositor_address", "deposit_id": "one"}),
+            serde_json::json!({"caller": "depositor_address", "deposit_id": 1, "force": true}),
+        ] {
+            assert_eq!(
+                error_code(serde_json::json!({"jsonrpc": "2.0", "method": "vault_withdraw", "params": params, "id": 1})),
+                crate::server::jsonrpc::INVALID_PARAMS
+            );
+        }
+        assert_eq!(
+            error_code(serde_json::json!({"jsonrpc": "2.0", "method": "vault_getStats", "params": {"verbose": true}, "id": 1})),
+            crate::server::jsonrpc::INVALID_PARAMS
+        );
+        
+        // Contract errors carry their stable code as number and as data
+        let response = jsonrpc_call(&handler, serde_json::json!({"jsonrpc": "2.0", "method": "vault_getDeposit", "params": {"deposit_id": 99}, "id": 1}), None).unwrap();
+        assert_eq!(response["error"]["code"], ErrorCode::DepositNotFound.number());
+        assert_eq!(response["error"]["data"]["code"], "DEPOSIT_NOT_FOUND");
+        assert_eq!(ErrorCode::InvalidAddress.number(), 1000);
+    }
+    
+    #[test]
+    fn test_jsonrpc_owner_method_requires_api_key() {
+        let handler = jsonrpc_handler();
+        jsonrpc_call(&handler, serde_json::json!({"jsonrpc": "2.0", "method": "vault_deposit", "params": {"depositor": "depositor_address", "token_type": "Bitcoin", "amount": 1000, "lock_days": 30}, "id": 1}), None).unwrap();
+        let response = jsonrpc_call(&handler, serde_json::json!({"jsonrpc": "2.0", "method": "vault_emergencyWithdraw", "params": {"caller": "depositor_address", "deposit_id": 1}, "id": 2}), None).unwrap();
+        assert!(response.get("result").is_some());
+        
+        let withdraw_fees = serde_json::json!({"jsonrpc": "2.0", "method": "vault_withdrawFees", "params": {"token_type": "Bitcoin", "nonce": 0}, "id": 3});
+        
+        // Without the right key the owner method is refused
+        for api_key in [None, Some("wrong-key")] {
+            let response = jsonrpc_call(&handler, withdraw_fees.clone(), api_key).unwrap();
+            assert_eq!(response["error"]["code"], ErrorCode::Unauthorized.number());
+        }
+        
+        let response = jsonrpc_call(&handler, withdraw_fees.clone(), Some("secret-key")).unwrap();
+        assert_eq!(response["result"]["FeeCollected"]["fee_amount"], 100);
+        
+        // The nonce makes a replayed call fail
+        let response = jsonrpc_call(&handler, withdraw_fees, Some("secret-key")).unwrap();
+        assert_eq!(response["error"]["code"], ErrorCode::NonceMismatch.number());
+        assert_eq!(response["error"]["data"]["actual"], 1);
+    }
+    
+    /// Bitcoin policy of the documented example: 1 confirmation below 0.01 BTC, 3 below 1 BTC, 6 from 1 BTC
+    fn tiered_confirmation_policy() -> crate::bitcoin::ConfirmationPolicy {
+        crate::bitcoin::ConfirmationPolicy::uniform(2).with_rule(TokenType::Bitcoin, 1, vec![
+            crate::bitcoin::ConfirmationTier { min_amount: 1_000_000, confirmations: 3 },
+            crate::bitcoin::ConfirmationTier { min_amount: 100_000_000, confirmations: 6 },
+        ])
+    }
+    
+    #[test]
+    fn test_confirmation_policy_tiers() {
+        let policy = tiered_confirmation_policy();
+        
+        // Tier minimums are inclusive, the next tier's minimum exclusive
+        assert_eq!(policy.required_confirmations(&TokenType::Bitcoin, 0), 1);
+        assert_eq!(policy.required_confirmations(&TokenType::Bitcoin, 999_999), 1);
+        assert_eq!(policy.required_confirmations(&TokenType::Bitcoin, 1_000_000), 3);
+        assert_eq!(policy.required_confirmations(&TokenType::Bitcoin, 99_999_999), 3);
+        assert_eq!(policy.required_confirmations(&TokenType::Bitcoin, 100_000_000), 6);
+        assert_eq!(policy.required_confirmations(&TokenType::Bitcoin, u64::MAX), 6);
+        assert_eq!(policy.required_confirmations(&TokenType::Lightning, 100_000_000), 2);
+        assert!(policy.validate().is_ok());
+        
+        // Zero confirmations, unordered tiers and duplicate rules are rejected
+        let tier = |min_amount, confirmations| crate::bitcoin::ConfirmationTier { min_amount, confirmations };
+        for invalid in [
+            crate::bitcoin::ConfirmationPolicy::uniform(0),
+            crate::bitcoin::ConfirmationPolicy::uniform(1).with_rule(TokenType::Bitcoin, 0, Vec::new()),
+            crate::bitcoin::ConfirmationPolicy::uniform(1).with_rule(TokenType::Bitcoin, 1, vec![tier(10, 3), tier(10, 6)]),
+            crate::bitcoin::ConfirmationPolicy {
+                default_confirmations: 1,
+                rules: vec![policy.rules[0].clone(), policy.rules[0].clone()],
+            },
+        ] {
+            assert!(matches!(invalid.validate(), Err(ContractError::InvalidConfirmationPolicy(_))));
+        }
+        
+        // The policy is part of the config file, defaulting to one confirmation
+        let mut config = serde_json::json!({
+            "rpc_url": "http://localhost:18332",
+            "rpc_username": "testuser",
+            "rpc_password": "testpassword",
+            "contract_wallet_address": RPC_CONTRACT_WALLET,
+            "owner_address": "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn",
+        });
+        assert_eq!(crate::config::VaultConfig::from_json(&config.to_string()).unwrap().confirmation_policy, crate::bitcoin::ConfirmationPolicy::uniform(1));
+        
+        config["confirmation_policy"] = serde_json::to_value(&policy).unwrap();
+        let vault_config = crate::config::VaultConfig::from_json(&config.to_string()).unwrap();
+        assert_eq!(vault_config.confirmation_policy, policy);
+        assert_eq!(vault_config.bitcoin_config().confirmation_policy, policy);
+    }
+    
+    #[test]
+    fn test_confirmation_policy_is_owner_setting_in_state() {
+        let mut contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000_000, 30, None).unwrap();
+        assert_eq!(contract.required_confirmations(1).unwrap(), 1);
+        
+        assert!(matches!(
+            contract.set_confirmation_policy("depositor_address".to_string(), tiered_confirmation_policy(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_confirmation_policy("owner_address".to_string(), crate::bitcoin::ConfirmationPolicy::uniform(0), Some(0)),
+            Err(ContractError::InvalidConfirmationPolicy(_))
+        ));
+        assert_eq!(contract.get_owner_nonce(), 0);
+        
+        contract.set_confirmation_policy("owner_address".to_string(), tiered_confirmation_policy(), Some(0)).unwrap();
+        assert_eq!(contract.required_confirmations(1).unwrap(), 3);
+        assert_eq!(contract.get_owner_nonce(), 1);
+        
+        // The policy survives a snapshot round trip
+        let state = contract.export_state();
+        let json = serde_json::to_string(&state).unwrap();
+        let restored = TimeLockedDeposit::from_state(serde_json::from_str(&json).unwrap(), contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer).unwrap();
+        assert_eq!(restored.get_confirmation_policy(), &tiered_confirmation_policy());
+    }
+    
+    #[test]
+    fn test_confirmation_policy_change_does_not_suspend_confirmed_deposits() {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let watcher = transfer.reorg_watcher();
+        
+        // A small deposit confirms with one confirmation
+        let confirmed_txid = "ab".repeat(32);
+        watcher.track(1, &confirmed_txid, &TokenType::Bitcoin, 10_000).unwrap();
+        set_mock_chain(&transport, 100, 1, Some(&"aa".repeat(32)));
+        assert!(watcher.poll().unwrap().is_empty());
+        
+        // A stricter policy applies to deposits tracked from now on
+        transfer.set_confirmation_policy(&crate::bitcoin::ConfirmationPolicy::uniform(6));
+        let pending_txid = "cd".repeat(32);
+        watcher.track(2, &pending_txid, &TokenType::Bitcoin, 10_000).unwrap();
+        
+        // The confirmed deposit keeps its requirement, the new one never confirmed and cannot be reorganized out
+        set_mock_chain(&transport, 101, 1, Some(&"aa".repeat(32)));
+        assert!(watcher.poll().unwrap().is_empty());
+        
+        set_mock_chain(&transport, 102, 0, None);
+        let notices = watcher.poll().unwrap();
+        assert!(matches!(&notices[..], [ReorgNotice::Suspended { deposit_id: 1, .. }]));
+    }
+    
+    #[test]
+    fn test_pending_deposit_expires_at_boundary() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        contract.deposit_limits.max_deposits_per_user = Some(1);
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(10)), None).unwrap();
+        
+        // A pending deposit holds its slot and total but not its fee
+        let outpoint = format!("{}:0", "ab".repeat(32));
+        let expires_at = clock.now() + chrono::Duration::hours(24);
+        let event = contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 50_000, 1, Some(outpoint.parse().unwrap()), None).unwrap();
+        assert!(matches!(event, Event::DepositPending { deposit_id: 1, deposit_amount: 50_000, expires_at: at, .. } if at == expires_at));
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 49_990);
+        assert!(!contract.fee_config.collected_fees.contains_key(&TokenType::Bitcoin));
+        assert_eq!(contract.utxo_reference_owner(&outpoint), Some(1));
+        assert!(matches!(
+            contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 50_000, 1, None, None),
+            Err(ContractError::UserDepositLimitReached { .. })
+        ));
+        
+        // Nothing can be done with it before it is funded
+        clock.advance(chrono::Duration::hours(24) - chrono::Duration::seconds(1));
+        assert!(matches!(contract.withdraw("depositor_address".to_string(), 1), Err(ContractError::DepositPending)));
+        assert!(contract.expire_stale_pending_deposits(clock.now()).is_empty());
+        
+        // It expires exactly at its expiry and gives everything back
+        clock.advance(chrono::Duration::seconds(1));
+        let events = contract.expire_stale_pending_deposits(clock.now());
+        assert!(matches!(&events[..], [Event::DepositExpired { deposit_id: 1, amount: 49_990, expires_at: at, .. }] if *at == expires_at));
+        assert!(contract.expire_stale_pending_deposits(clock.now()).is_empty());
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Expired);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 0);
+        assert_eq!(contract.utxo_reference_owner(&outpoint), None);
+        assert!(contract.check_accounting_invariants().is_ok());
+        assert!(matches!(contract.withdraw("depositor_address".to_string(), 1), Err(ContractError::DepositExpired)));
+        assert_eq!(sink.names.lock().unwrap().as_slice(), ["DepositFeeUpdated", "DepositPending", "DepositExpired"]);
+        
+        // The expiry survives a restart without the output being claimed again
+        let state = contract.export_state();
+        assert_eq!(state.pending_deposit_timeout, std::time::Duration::from_secs(24 * 60 * 60));
+        let mut restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.utxo_reference_owner(&outpoint), None);
+        assert!(restored.check_accounting_invariants().is_ok());
+        
+        // The freed slot takes a new deposit
+        restored.set_clock(clock.clone());
+        assert!(restored.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 50_000, 1, None, None).is_ok());
+    }
+    
+    #[test]
+    fn test_pending_deposit_funding_before_and_after_expiry() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Lightning, Some(DepositFee::Flat(10)), None).unwrap();
+        contract.set_pending_deposit_timeout("owner_address".to_string(), std::time::Duration::from_secs(3600), None).unwrap();
+        assert!(matches!(
+            contract.set_pending_deposit_timeout("depositor_address".to_string(), std::time::Duration::from_secs(60), None),
+            Err(ContractError::Unauthorized)
+        ));
+        
+        // A Lightning deposit paid before its invoice expires becomes active and pays its fee
+        let invoice_expiry = clock.now() + chrono::Duration::minutes(10);
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Lightning, 1_000, 1, None, Some(invoice_expiry)).unwrap();
+        clock.advance(chrono::Duration::minutes(10) - chrono::Duration::seconds(1));
+        let event = contract.confirm_pending_deposit(1).unwrap();
+        assert!(matches!(event, Some(Event::Deposited { deposit_id: 1, deposit_amount: 1_000, fee_amount: 10, locked_amount: 990, .. })));
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Lightning], 10);
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Active);
+        assert!(contract.confirm_pending_deposit(1).unwrap().is_none());
+        
+        // A payment at the invoice expiry is too late
+        let invoice_expiry = clock.now() + chrono::Duration::minutes(10);
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Lightning, 1_000, 1, None, Some(invoice_expiry)).unwrap();
+        clock.set(invoice_expiry);
+        assert!(matches!(contract.confirm_pending_deposit(2), Err(ContractError::DepositExpired)));
+        assert_eq!(contract.deposit_registry[&2].status, DepositStatus::Expired);
+        assert_eq!(contract.total_deposits[&TokenType::Lightning], 990);
+        
+        // On-chain funding seen by the watcher in time confirms the deposit
+        let funds = |vout: u32| crate::bitcoin::incoming::IncomingFunds {
+            txid: "ef".repeat(32),
+            vout,
+            amount: 20_000,
+            sender_addresses: vec!["depositor_address".to_string()],
+            confirmations: 1,
+        };
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 20_000, 1, Some(funds(0).outpoint().parse().unwrap()), None).unwrap();
+        let event = contract.record_incoming_funds(&funds(0));
+        assert!(matches!(event, Some(Event::Deposited { deposit_id: 3, deposit_amount: 20_000, .. })));
+        assert!(contract.list_unattributed_funds().is_empty());
+        
+        // Funding confirming after the timeout ends up in the unattributed ledger
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 20_000, 1, Some(funds(1).outpoint().parse().unwrap()), None).unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        let event = contract.record_incoming_funds(&funds(1));
+        assert!(matches!(event, Some(Event::UnattributedFundsDetected { amount: 20_000, .. })));
+        assert_eq!(contract.deposit_registry[&4].status, DepositStatus::Expired);
+        assert_eq!(contract.list_unattributed_funds()[0].outpoint, funds(1).outpoint());
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 20_000);
+        assert!(matches!(contract.emergency_withdraw("depositor_address".to_string(), 4), Err(ContractError::DepositExpired)));
+        assert!(contract.check_accounting_invariants().is_ok());
+        
+        assert_eq!(
+            sink.names.lock().unwrap().as_slice(),
+            ["DepositFeeUpdated", "DepositPending", "Deposited", "DepositPending", "DepositExpired", "DepositPending", "Deposited", "DepositPending", "DepositExpired", "UnattributedFundsDetected"]
+        );
+    }
+    
+    #[test]
+    fn test_pending_transactions_are_processed_one_batch_per_call() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.max_batch_size = 3;
+        config.rate_limit = 600_000;
+        let (transfer, transport) = transfer_with_mock_config(config);
+        
+        // Without a fee estimate nothing is sent, and each full queue takes up a single batch
+        transport.respond("estimatesmartfee", serde_json::json!({ "errors": ["Insufficient data"], "blocks": 0 }));
+        for amount in 1..=5 {
+            transfer.transfer_from_contract(RPC_RECIPIENT, &TokenType::Bitcoin, amount * 1_000).unwrap();
+        }
+        
+        // Three calls past the threshold, one estimate each, never touching the fourth and fifth transfer
+        assert_eq!(transport.calls("estimatesmartfee").len(), 3);
+        assert!(transport.calls("createrawtransaction").is_empty());
+        assert_eq!(transfer.pending_references(), vec![(1, 3), (2, 3), (3, 3), (4, 0), (5, 0)]);
+        
+        // Once fees are available a call sends one batch and reports what is left
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.0001, "blocks": 1 }));
+        let progress = transfer.process_pending_transactions().unwrap();
+        assert_eq!(progress.processed, 3);
+        assert_eq!(progress.remaining, 2);
+        assert!(!progress.is_complete());
+        assert_eq!(progress.txids.iter().map(|(reference, _)| *reference).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(transport.calls("estimatesmartfee").len(), 4);
+        assert_eq!(transport.calls("sendrawtransaction").len(), 3);
+        
+        // The next call drains the rest
+        let progress = transfer.process_pending_transactions().unwrap();
+        assert_eq!((progress.processed, progress.remaining), (2, 0));
+        assert!(progress.is_complete());
+        assert!(transfer.pending_references().is_empty());
+        assert!(transfer.process_pending_transactions().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_max_batches_per_call_is_validated() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+        );
+        assert_eq!(config.max_batches_per_call, 1);
+        
+        config.max_batches_per_call = 0;
+        assert_eq!(config.validate().unwrap_err(), "Maximum batches per call cannot be zero");
+    }
+    
+    #[test]
+    fn test_address_ledger_tracks_running_balances() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let start = clock.now();
+        
+        let mut event_log = EventLog::open(EventLogConfig::new(&path), None).unwrap();
+        event_log.set_clock(clock.clone());
+        let event_log = Arc::new(event_log);
+        let mut contract = contract_with_clock(clock.clone());
+        contract.add_event_sink(event_log.clone());
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(100)), None).unwrap();
+        contract.set_min_age_before_emergency("owner_address".to_string(), Duration::ZERO, None).unwrap();
+        
+        // Two deposits, a pending deposit that expires, an assignment and an early payout
+        contract.deposit("alice_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        contract.deposit("alice_address".to_string(), TokenType::Bitcoin, 5_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        contract.deposit_pending("alice_address".to_string(), TokenType::Bitcoin, 2_000, 30, None, None).unwrap();
+        clock.advance(chrono::Duration::hours(24));
+        assert_eq!(contract.expire_stale_pending_deposits(clock.now()).len(), 1);
+        clock.advance(chrono::Duration::hours(1));
+        contract.transfer_deposit_ownership("alice_address".to_string(), 2, "bob_address".to_string()).unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        let (payout, fee) = match contract.emergency_withdraw("alice_address".to_string(), 1).unwrap() {
+            Event::EmergencyWithdrawn { withdrawn_amount, fee_amount, .. } => (withdrawn_amount, fee_amount),
+            other => panic!("unexpected event {:?}", other),
+        };
+        assert_eq!(payout + fee, 9_900);
+        
+        let rows = |ledger: Vec<LedgerEntry>| -> Vec<(u64, LedgerKind, u64, i128, u64)> {
+            ledger.into_iter().map(|entry| (entry.seq, entry.kind, entry.deposit_id, entry.amount_delta, entry.balance_after)).collect()
+        };
+        let alice = vec![
+            (1, LedgerKind::Deposit, 1, 10_000, 10_000),
+            (1, LedgerKind::DepositFee, 1, -100, 9_900),
+            (2, LedgerKind::Deposit, 2, 5_000, 14_900),
+            (2, LedgerKind::DepositFee, 2, -100, 14_800),
+            (3, LedgerKind::PendingDeposit, 3, 0, 14_800),
+            (4, LedgerKind::Expiry, 3, 0, 14_800),
+            (5, LedgerKind::TransferOut, 2, -4_900, 9_900),
+            (6, LedgerKind::EmergencyWithdrawal, 1, -(payout as i128), 9_900 - payout),
+            (6, LedgerKind::EmergencyFee, 1, -(fee as i128), 0),
+        ];
+        assert_eq!(rows(event_log.get_address_ledger("alice_address", None, None, 0, 100).unwrap()), alice);
+        assert_eq!(rows(event_log.get_address_ledger("bob_address", None, None, 0, 100).unwrap()), [(5, LedgerKind::TransferIn, 2, 4_900, 4_900)]);
+        assert!(event_log.get_address_ledger("carol_address", None, None, 0, 100).unwrap().is_empty());
+        
+        // Pages are stable and cover the ledger without gaps
+        let first = event_log.get_address_ledger("alice_address", None, None, 0, 4).unwrap();
+        contract.deposit("alice_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).unwrap();
+        assert_eq!(event_log.get_address_ledger("alice_address", None, None, 0, 4).unwrap(), first);
+        let second = event_log.get_address_ledger("alice_address", None, None, 4, 5).unwrap();
+        assert_eq!(rows(first.into_iter().chain(second).collect()), alice);
+        assert_eq!(event_log.get_address_ledger("alice_address", None, None, 9, 5).unwrap().len(), 2);
+        
+        // The window starts inclusively and ends exclusively
+        let window = event_log.get_address_ledger(
+            "alice_address",
+            Some(start + chrono::Duration::hours(1)),
+            Some(start + chrono::Duration::hours(26)),
+            0,
+            100,
+        ).unwrap();
+        assert_eq!(rows(window), alice[2..5]);
+        
+        // Reopening the log rebuilds the same ledger
+        let full = event_log.get_address_ledger("alice_address", None, None, 0, 100).unwrap();
+        drop(contract);
+        drop(event_log);
+        let reopened = EventLog::open(EventLogConfig::new(&path), None).unwrap();
+        assert_eq!(reopened.get_address_ledger("alice_address", None, None, 0, 100).unwrap(), full);
+    }
+    
+    /// Compressed public keys of the secp256k1 points G and 2G
+    const ESCROW_VAULT_KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const ESCROW_DEPOSITOR_KEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    
+    #[test]
+    fn test_descriptor_checksums_match_bitcoin_core() {
+        use crate::bitcoin::descriptor::{descriptor_checksum, strip_checksum, with_checksum};
+        
+        // Checksums as returned by getdescriptorinfo
+        let known = [
+            ("raw(deadbeef)", "89f8spxm"),
+            ("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)", "02wpgw69"),
+            ("wpkh(02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9)", "8zl0zxma"),
+            ("pkh(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5)", "8fhd9pwu"),
+        ];
+        for (descriptor, checksum) in known {
+            assert_eq!(descriptor_checksum(descriptor).unwrap(), checksum);
+            let full = with_checksum(descriptor).unwrap();
+            assert_eq!(full, format!("{}#{}", descriptor, checksum));
+            assert_eq!(strip_checksum(&full).unwrap(), descriptor);
+        }
+        
+        // A missing checksum is accepted, a wrong, short, long or empty one is not
+        assert_eq!(strip_checksum("raw(deadbeef)").unwrap(), "raw(deadbeef)");
+        for invalid in ["raw(deadbeef)#89f8spxn", "raw(deadbeef)#89f8spx", "raw(deadbeef)#89f8spxmx", "raw(deadbeef)#", "raw(deedbeef)#89f8spxm"] {
+            assert!(matches!(strip_checksum(invalid), Err(ContractError::InvalidDescriptor(_))), "{}", invalid);
+        }
+        assert!(matches!(descriptor_checksum("raw(deadbeef)\u{e9}"), Err(ContractError::InvalidDescriptor(_))));
+    }
+    
+    #[test]
+    fn test_time_lock_policy_round_trips_and_derives_its_address() {
+        use crate::bitcoin::descriptor::{descriptor_checksum, TimeLockPolicy};
+        use bitcoincore_rpc::bitcoin::ScriptBuf;
+        
+        let policy = TimeLockPolicy::new(ESCROW_VAULT_KEY, ESCROW_DEPOSITOR_KEY, 2_500_000).unwrap();
+        let body = format!(
+            "wsh(or_d(multi(2,{vault},{depositor}),and_v(v:pk({depositor}),after(2500000))))",
+            vault = ESCROW_VAULT_KEY,
+            depositor = ESCROW_DEPOSITOR_KEY,
+        );
+        let descriptor = policy.descriptor();
+        assert_eq!(descriptor, format!("{}#{}", body, descriptor_checksum(&body).unwrap()));
+        
+        // Parsing gives back the same terms, with or without the checksum
+        assert_eq!(TimeLockPolicy::parse(&descriptor).unwrap(), policy);
+        assert_eq!(TimeLockPolicy::parse(&body).unwrap(), policy);
+        assert_eq!(TimeLockPolicy::parse(&descriptor).unwrap().descriptor(), descriptor);
+        
+        // The witness script is the compiled miniscript
+        let script = format!(
+            "5221{vault}21{depositor}52ae736421{depositor}ad03a02526b168",
+            vault = ESCROW_VAULT_KEY,
+            depositor = ESCROW_DEPOSITOR_KEY,
+        );
+        assert_eq!(hex::encode(policy.witness_script().as_bytes()), script);
+        
+        // The address pays to the hash of that script
+        let address = policy.address(Network::Testnet);
+        assert!(address.to_string().starts_with("tb1q"));
+        assert_eq!(address.script_pubkey(), ScriptBuf::new_v0_p2wsh(&policy.witness_script().wscript_hash()));
+        assert_ne!(policy.address(Network::Bitcoin).to_string(), address.to_string());
+        
+        // Other policies, keys and heights are rejected
+        let recovery_by_vault = body.replace(&format!("v:pk({})", ESCROW_DEPOSITOR_KEY), &format!("v:pk({})", ESCROW_VAULT_KEY));
+        let uncompressed = "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8";
+        for invalid in [
+            recovery_by_vault,
+            body.replace("multi(2,", "multi(1,"),
+            body.replace("after(2500000)", "after(02500000)"),
+            body.replace("after(2500000)", "after(1700000000)"),
+            body.replace(ESCROW_VAULT_KEY, uncompressed),
+            body.replace(ESCROW_VAULT_KEY, "02deadbeef"),
+            format!("sh({})", body),
+        ] {
+            assert!(matches!(TimeLockPolicy::parse(&invalid), Err(ContractError::InvalidDescriptor(_))), "{}", invalid);
+        }
+    }
+    
+    #[test]
+    fn test_script_escrow_deposit_exports_its_locking_descriptor() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.token_transfer.expect_get_network_type().returning(|| "testnet".to_string());
+        
+        // Escrow deposits need a vault key set by the owner
+        assert!(matches!(
+            contract.deposit_script_escrow("depositor_address".to_string(), 50_000, 30, ESCROW_DEPOSITOR_KEY.to_string(), 2_500_000),
+            Err(ContractError::EscrowNotConfigured)
+        ));
+        assert!(matches!(
+            contract.set_escrow_public_key("depositor_address".to_string(), Some(ESCROW_VAULT_KEY.to_string()), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_escrow_public_key("owner_address".to_string(), Some("02deadbeef".to_string()), None),
+            Err(ContractError::InvalidDescriptor(_))
+        ));
+        contract.set_escrow_public_key("owner_address".to_string(), Some(ESCROW_VAULT_KEY.to_string()), None).unwrap();
+        assert_eq!(contract.get_escrow_public_key(), Some(ESCROW_VAULT_KEY));
+        
+        // Invalid terms record nothing
+        assert!(matches!(
+            contract.deposit_script_escrow("depositor_address".to_string(), 50_000, 30, ESCROW_DEPOSITOR_KEY.to_string(), 0),
+            Err(ContractError::InvalidDescriptor(_))
+        ));
+        assert!(contract.get_deposit_view(1).is_err());
+        
+        // The deposit waits for its funding and exports its terms
+        let event = contract.deposit_script_escrow("depositor_address".to_string(), 50_000, 30, ESCROW_DEPOSITOR_KEY.to_string(), 2_500_000).unwrap();
+        assert!(matches!(event, Event::DepositPending { deposit_id: 1, .. }));
+        let view = contract.get_deposit_view(1).unwrap();
+        let escrow = view.deposit.escrow.clone().unwrap();
+        let policy = crate::bitcoin::descriptor::TimeLockPolicy::new(ESCROW_VAULT_KEY, ESCROW_DEPOSITOR_KEY, 2_500_000).unwrap();
+        assert_eq!(escrow.address, policy.address(Network::Testnet).to_string());
+        assert_eq!(view.locking_descriptor, Some(policy.descriptor()));
+        assert_eq!(view.descriptor_matches_address, Some(true));
+        assert!(view.deposit.verify_descriptor_matches_address().unwrap());
+        assert!(contract.confirm_pending_deposit(1).unwrap().is_some());
+        
+        // Plain deposits have no descriptor
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 50_000, 30, None).unwrap();
+        let view = contract.get_deposit_view(2).unwrap();
+        assert_eq!(view.locking_descriptor, None);
+        assert_eq!(view.descriptor_matches_address, None);
+        assert!(matches!(view.deposit.verify_descriptor_matches_address(), Err(ContractError::InvalidDescriptor(_))));
+        
+        // The key and terms survive a restart, and a tampered address no longer matches
+        let mut state = contract.export_state();
+        assert_eq!(state.escrow_public_key.as_deref(), Some(ESCROW_VAULT_KEY));
+        let escrow_deposit = state.deposits.iter_mut().find(|deposit| deposit.deposit_id == 1).unwrap();
+        escrow_deposit.escrow.as_mut().unwrap().address = policy.address(Network::Bitcoin).to_string();
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_escrow_public_key(), Some(ESCROW_VAULT_KEY));
+        let view = restored.get_deposit_view(1).unwrap();
+        assert_eq!(view.locking_descriptor, Some(policy.descriptor()));
+        assert_eq!(view.descriptor_matches_address, Some(false));
+    }
+    
+    #[test]
+    fn test_full_transfer_queue_rejects_deposits_until_flushed() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.max_batch_size = 2;
+        config.max_pending_transactions = 3;
+        config.rate_limit = 600_000;
+        let (transfer, transport) = transfer_with_mock_config(config);
+        let metrics = transfer.metrics();
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = TimeLockedDeposit::new_with_defaults(RPC_CONTRACT_WALLET.to_string(), 10, transfer).unwrap();
+        contract.set_clock(clock.clone());
+        
+        // The node cannot estimate fees, so nothing leaves the queue
+        transport.respond("estimatesmartfee", serde_json::json!({ "errors": ["Insufficient data"], "blocks": 0 }));
+        for _ in 0..3 {
+            contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        }
+        assert_eq!(contract.token_transfer.pending_queue_depth(), 3);
+        
+        // A dry run reports the saturated queue
+        let simulation = contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 30).unwrap();
+        assert!(simulation.queue_saturated);
+        assert_eq!(simulation.transfer_queue, Some(crate::models::TransferQueueStatus { depth: 3, capacity: 3 }));
+        assert_eq!(simulation.locked_amount, 10_000);
+        
+        // The next deposit is refused as retryable without recording anything
+        let error = contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap_err();
+        assert!(matches!(&error, ContractError::BackendBusy(detail) if detail.starts_with(crate::errors::TRANSFER_QUEUE_FULL)));
+        assert!(error.is_retryable());
+        assert_eq!(crate::server::HttpResponse::contract_error(&error).status, 503);
+        assert!(matches!(contract.get_deposit_view(4), Err(ContractError::DepositNotFound)));
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 30_000);
+        assert_eq!(contract.user_deposit_ids[RPC_RECIPIENT], vec![1, 2, 3]);
+        
+        // So is a withdrawal, which leaves the deposit active
+        clock.advance(chrono::Duration::days(30));
+        assert!(matches!(contract.withdraw(RPC_RECIPIENT.to_string(), 1), Err(ContractError::BackendBusy(_))));
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Active);
+        assert_eq!(contract.token_transfer.pending_queue_depth(), 3);
+        
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["pending_queue_high_water_mark"], 3);
+        assert_eq!(snapshot["pending_queue_rejections"], 2);
+        
+        // Once a batch goes out there is room again
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.0001, "blocks": 1 }));
+        let progress = contract.token_transfer.process_pending_transactions().unwrap();
+        assert_eq!((progress.processed, progress.remaining), (2, 1));
+        assert!(!contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 30).unwrap().queue_saturated);
+        assert!(matches!(
+            contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None),
+            Ok(Event::Deposited { deposit_id: 4, .. })
+        ));
+        assert_eq!(metrics.snapshot()["pending_queue_high_water_mark"], 3);
+    }
+    
+    #[test]
+    fn test_max_pending_transactions_is_validated() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+        );
+        assert_eq!(config.max_pending_transactions, 1000);
+        
+        config.max_pending_transactions = config.max_batch_size as usize - 1;
+        assert_eq!(config.validate().unwrap_err(), "Maximum pending transactions cannot be below the maximum batch size");
+    }
+    
+    #[test]
+    fn test_treasury_sweep_keeps_withdrawals_due_within_float() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // The hot wallet holds exactly what the contract owes, and Lightning channels their share
+        let hot_balance = Arc::new(std::sync::Mutex::new(0u64));
+        let balance = hot_balance.clone();
+        contract.token_transfer.expect_get_contract_balance()
+            .returning(move |token_type| match token_type {
+                TokenType::Bitcoin => Ok(*balance.lock().unwrap()),
+                _ => Ok(50_000),
+            });
+        
+        let policy = TreasuryPolicy {
+            hot_wallet_ceiling: 100_000,
+            float_days: 7,
+            cold_address: "cold_address".to_string(),
+        };
+        assert!(matches!(contract.compute_sweep_amount(), Err(ContractError::TreasuryPolicyNotConfigured)));
+        assert!(matches!(
+            contract.set_treasury_policy("depositor_address".to_string(), Some(policy.clone()), None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_treasury_policy("owner_address".to_string(), Some(policy.clone()), None).unwrap();
+        assert_eq!(contract.get_treasury_policy(), Some(&policy));
+        
+        // One deposit matures inside the float window and one outside; Lightning and unfunded deposits are not held here
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 300_000, 3, None).unwrap();
+        contract.deposit("other_depositor".to_string(), TokenType::Bitcoin, 200_000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 50_000, 1, None).unwrap();
+        contract.deposit_pending("other_depositor".to_string(), TokenType::Bitcoin, 70_000, 1, None, None).unwrap();
+        *hot_balance.lock().unwrap() = 500_000;
+        
+        // Only the deposit due outside the window is swept
+        let proposal = contract.compute_sweep_amount().unwrap();
+        assert_eq!(proposal, SweepProposal { hot_balance: 500_000, due_within_float: 300_000, retained: 300_000, sweepable: None, sweep_amount: 200_000 });
+        let event = contract.execute_treasury_sweep("owner_address".to_string(), None).unwrap();
+        assert!(matches!(
+            event,
+            Some(Event::TreasurySwept { amount: 200_000, hot_balance: 500_000, due_within_float: 300_000, ref cold_address, txid: None, .. })
+                if cold_address == "cold_address"
+        ));
+        assert_eq!(contract.get_cold_storage_balance(), 200_000);
+        *hot_balance.lock().unwrap() = 300_000;
+        
+        // The swept amount does not show up as a shortfall, and nothing more is swept
+        assert!(contract.reconcile(0).is_consistent());
+        assert_eq!(contract.compute_sweep_amount().unwrap().sweep_amount, 0);
+        assert!(contract.execute_treasury_sweep("owner_address".to_string(), None).unwrap().is_none());
+        
+        // The deposit maturing inside the window is still paid from the hot wallet
+        clock.advance(chrono::Duration::days(3));
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        *hot_balance.lock().unwrap() = 0;
+        
+        // Once the other deposit enters the window the float only shrinks, never sweeps
+        clock.advance(chrono::Duration::days(20));
+        let proposal = contract.compute_sweep_amount().unwrap();
+        assert_eq!((proposal.due_within_float, proposal.sweep_amount), (200_000, 0));
+        
+        // Excess below the dust threshold stays put
+        *hot_balance.lock().unwrap() = 200_500;
+        assert_eq!(contract.compute_sweep_amount().unwrap().sweep_amount, 500);
+        assert!(contract.run_scheduled_treasury_sweep().unwrap().is_none());
+        
+        // Sweeps stop while the contract is paused
+        *hot_balance.lock().unwrap() = 400_000;
+        contract.is_contract_paused = true;
+        assert!(matches!(contract.run_scheduled_treasury_sweep(), Err(ContractError::ContractPaused)));
+        contract.is_contract_paused = false;
+        
+        // The policy and the swept total survive a restart
+        let state = contract.export_state();
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_treasury_policy(), Some(&policy));
+        assert_eq!(restored.get_cold_storage_balance(), 200_000);
+        
+        // Without a policy the daemon has nothing to do
+        contract.set_treasury_policy("owner_address".to_string(), None, None).unwrap();
+        assert!(contract.run_scheduled_treasury_sweep().unwrap().is_none());
+        assert!(matches!(contract.execute_treasury_sweep("owner_address".to_string(), None), Err(ContractError::TreasuryPolicyNotConfigured)));
+        
+        assert_eq!(sink.names.lock().unwrap().iter().filter(|name| **name == "TreasurySwept").count(), 1);
+    }
+    
+    #[test]
+    fn test_treasury_sweep_builds_transaction_through_transfer_layer() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let cold_address = "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc";
+        
+        // Sweeps may spend deposited funds, not only operational change
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        config.sweep_inputs = SweepInputs::Any;
+        let (transfer, transport) = transfer_with_mock_config(config);
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.01, "blocks": 6 }));
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer).unwrap();
+        contract.set_clock(clock.clone());
+        
+        // The mocked wallet holds 10 BTC; 4 BTC unlock within the float window
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 400_000_000, 3, None).unwrap();
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 300_000_000, 60, None).unwrap();
+        contract.set_treasury_policy(
+            "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(),
+            Some(TreasuryPolicy { hot_wallet_ceiling: 100_000_000, float_days: 7, cold_address: cold_address.to_string() }),
+            None,
+        ).unwrap();
+        assert!(matches!(
+            contract.set_treasury_policy(
+                "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(),
+                Some(TreasuryPolicy { hot_wallet_ceiling: 0, float_days: 7, cold_address: "not_an_address".to_string() }),
+                None,
+            ),
+            Err(ContractError::InvalidAddress)
+        ));
+        
+        let proposal = contract.compute_sweep_amount().unwrap();
+        assert_eq!((proposal.hot_balance, proposal.due_within_float, proposal.sweep_amount), (1_000_000_000, 400_000_000, 600_000_000));
+        
+        // The sweep is signed and broadcast right away, paying the mining fee out of the swept amount
+        let event = contract.execute_treasury_sweep("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), None).unwrap().unwrap();
+        let (amount, txid) = match event {
+            Event::TreasurySwept { amount, txid, .. } => (amount, txid),
+            other => panic!("unexpected event {:?}", other),
+        };
+        assert!(txid.is_some());
+        assert_eq!(transport.calls("sendrawtransaction").len(), 1);
+        
+        let created = transport.calls("createrawtransaction");
+        assert_eq!(created.len(), 1);
+        assert_eq!(raw_outputs(&created[0])[0], (cold_address.to_string(), amount));
+        let spent = created[0][0].as_array().unwrap().len() as u64 * 100_000_000;
+        let paid: u64 = raw_outputs(&created[0]).into_iter().map(|(_, amount)| amount).sum();
+        let mining_fee = spent - paid;
+        assert!(mining_fee > 0);
+        
+        // What stays hot still covers every withdrawal due within the window
+        assert!(amount + mining_fee <= proposal.sweep_amount);
+        assert!(proposal.hot_balance - amount - mining_fee >= proposal.due_within_float);
+        
+        // The swept amount is accounted to cold storage
+        assert_eq!(contract.get_cold_storage_balance(), amount);
+    }
+    
+    #[test]
+    fn test_import_deposits_from_older_deployment() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        let migration_key = KeySigner::new(&[7u8; 32]).unwrap();
+        
+        // A deposit of this deployment already holds ID 1
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        
+        let imported = |original_id: u64, depositor: &str, amount: u64, unlock_days: i64, withdrawn: bool| ImportedDeposit {
+            original_id,
+            depositor_address: depositor.to_string(),
+            token_type: TokenType::Bitcoin,
+            amount,
+            deposit_timestamp: clock.now() - chrono::Duration::days(60),
+            unlock_timestamp: clock.now() + chrono::Duration::days(unlock_days),
+            withdrawn,
+        };
+        let batch = vec![
+            imported(1, "depositor_address", 40_000, 10, false),
+            imported(2, "depositor_address", 25_000, -20, true),
+            imported(3, "other_depositor", 5_000, -1, false),
+        ];
+        let manifest = ImportManifest::sign(&batch, &migration_key).unwrap();
+        
+        // Only the owner imports, and only against a configured migration key
+        assert!(matches!(
+            contract.import_deposits("depositor_address".to_string(), batch.clone(), manifest.clone(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.import_deposits("owner_address".to_string(), batch.clone(), manifest.clone(), None),
+            Err(ContractError::MigrationKeyNotConfigured)
+        ));
+        contract.set_migration_public_key("owner_address".to_string(), Some(hex::encode(migration_key.public_key())), None).unwrap();
+        
+        // A batch signed by another key, or changed after signing, is rejected without recording anything
+        let forged = ImportManifest::sign(&batch, &KeySigner::new(&[8u8; 32]).unwrap()).unwrap();
+        assert!(matches!(
+            contract.import_deposits("owner_address".to_string(), batch.clone(), forged, None),
+            Err(ContractError::InvalidImportSignature)
+        ));
+        let mut tampered = batch.clone();
+        tampered[0].amount = 400_000;
+        assert!(matches!(
+            contract.import_deposits("owner_address".to_string(), tampered, manifest.clone(), None),
+            Err(ContractError::InvalidImport(_))
+        ));
+        assert_eq!(contract.deposit_registry.len(), 1);
+        
+        // Imported IDs land in the reserved range, clear of this deployment's IDs
+        let events = contract.import_deposits("owner_address".to_string(), batch.clone(), manifest.clone(), None).unwrap();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(
+            &events[0],
+            Event::DepositImported { deposit_id, original_deposit_id: 1, amount: 40_000, withdrawn: false, manifest_hash, .. }
+                if *deposit_id == IMPORTED_DEPOSIT_ID_OFFSET + 1 && *manifest_hash == manifest.content_hash
+        ));
+        assert_eq!(contract.deposit_registry[&1].deposited_amount, 10_000);
+        assert_eq!(contract.deposit_registry[&(IMPORTED_DEPOSIT_ID_OFFSET + 1)].status, DepositStatus::Active);
+        assert_eq!(contract.deposit_registry[&(IMPORTED_DEPOSIT_ID_OFFSET + 2)].status, DepositStatus::Withdrawn);
+        
+        // Withdrawn deposits are history only; the totals and indexes cover the rest
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 10_000 + 40_000 + 5_000);
+        assert!(contract.check_accounting_invariants().is_ok());
+        let summary = contract.get_user_summary("depositor_address");
+        assert_eq!((summary.active_count, summary.lifetime_count), (2, 3));
+        assert_eq!(summary.total_active_amount, vec![(TokenType::Bitcoin, 50_000)]);
+        assert!(matches!(
+            contract.withdraw("depositor_address".to_string(), IMPORTED_DEPOSIT_ID_OFFSET + 2),
+            Err(ContractError::DepositAlreadyWithdrawn)
+        ));
+        assert!(contract.withdraw("other_depositor".to_string(), IMPORTED_DEPOSIT_ID_OFFSET + 3).is_ok());
+        
+        // New deposits keep counting from this deployment's IDs
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        assert_eq!(event.deposit_id(), Some(2));
+        
+        // The same manifest cannot be applied twice, nor the same deposits under a new one
+        assert!(matches!(
+            contract.import_deposits("owner_address".to_string(), batch.clone(), manifest.clone(), None),
+            Err(ContractError::ManifestAlreadyImported)
+        ));
+        let reordered: Vec<ImportedDeposit> = batch.iter().rev().cloned().collect();
+        let resigned = ImportManifest::sign(&reordered, &migration_key).unwrap();
+        assert!(matches!(
+            contract.import_deposits("owner_address".to_string(), reordered, resigned, None),
+            Err(ContractError::InvalidImport(_))
+        ));
+        
+        // Applied manifests and the migration key survive a restart
+        let state = contract.export_state();
+        assert_eq!(state.imported_manifests, vec![manifest.content_hash.clone()]);
+        let mut restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert!(restored.is_manifest_imported(&manifest.content_hash));
+        assert_eq!(restored.get_migration_public_key(), Some(hex::encode(migration_key.public_key()).as_str()));
+        assert!(matches!(
+            restored.import_deposits("owner_address".to_string(), batch, manifest, None),
+            Err(ContractError::ManifestAlreadyImported)
+        ));
+        assert!(restored.check_accounting_invariants().is_ok());
+        
+        // Deposits of this deployment never reach the reserved range
+        restored.next_deposit_id = IMPORTED_DEPOSIT_ID_OFFSET;
+        assert!(matches!(
+            restored.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None),
+            Err(ContractError::ArithmeticError)
+        ));
+        
+        assert_eq!(
+            sink.names.lock().unwrap().as_slice(),
+            ["Deposited", "DepositImported", "DepositImported", "DepositImported", "Withdrawn", "Deposited"]
+        );
+    }
+    
+    #[test]
+    fn test_tvl_series_tracks_deposits_and_withdrawals() {
+        let start = chrono::DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        let clock = Arc::new(MockClock::new(start));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Queries fail until recording is enabled
+        assert!(matches!(
+            contract.get_tvl_series(&TokenType::Bitcoin, start, start + chrono::Duration::hours(1), 60),
+            Err(ContractError::TvlMetricsNotEnabled)
+        ));
+        
+        contract.enable_tvl_metrics(MetricsRecorderConfig::default()).unwrap();
+        
+        // Two deposits within the first minute share its bucket, which keeps the later state
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::seconds(30));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 50_000, 10, None).unwrap();
+        
+        // An early withdrawal two minutes in leaves the second deposit
+        clock.advance(chrono::Duration::seconds(120));
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        let end = start + chrono::Duration::hours(1);
+        let per_minute = contract.get_tvl_series(&TokenType::Bitcoin, start, end, 60).unwrap();
+        assert_eq!(per_minute.len(), 2);
+        
+        assert_eq!(per_minute[0].timestamp, start);
+        assert_eq!(per_minute[0].tvl, 150_000);
+        assert_eq!(per_minute[0].active_deposits, 2);
+        let remaining = (chrono::Duration::days(30) - chrono::Duration::seconds(30) + chrono::Duration::days(10)).num_seconds() as u64;
+        assert_eq!(per_minute[0].avg_remaining_lock_secs, remaining / 2);
+        
+        assert_eq!(per_minute[1].timestamp, start + chrono::Duration::minutes(2));
+        assert_eq!(per_minute[1].tvl, 50_000);
+        assert_eq!(per_minute[1].active_deposits, 1);
+        assert_eq!(per_minute[1].avg_remaining_lock_secs, (chrono::Duration::days(10) - chrono::Duration::seconds(120)).num_seconds() as u64);
+        
+        // Coarser resolutions keep the last value of each group, from either tier
+        let five_minutes = contract.get_tvl_series(&TokenType::Bitcoin, start, end, 300).unwrap();
+        assert_eq!(five_minutes, vec![crate::tvl::TvlSample { timestamp: start, ..per_minute[1].clone() }]);
+        let hourly = contract.get_tvl_series(&TokenType::Bitcoin, start, end, 3600).unwrap();
+        assert_eq!(hourly, five_minutes);
+        
+        // Other tokens have no series, and resolutions must build on a recorded one
+        assert!(contract.get_tvl_series(&TokenType::Lightning, start, end, 60).unwrap().is_empty());
+        assert!(matches!(
+            contract.get_tvl_series(&TokenType::Bitcoin, start, end, 90),
+            Err(ContractError::InvalidSeriesResolution(_))
+        ));
+        
+        // The stats endpoint serves the same series
+        let server = ApiServer::new().with_tvl_recorder(contract.get_tvl_recorder().unwrap());
+        let target = format!("/stats/tvl?token=Bitcoin&from={}&to={}&resolution=60", start.timestamp(), end.timestamp());
+        let response = server.handle(&HttpRequest::new("GET", &target));
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["samples"].as_array().unwrap().len(), 2);
+        assert_eq!(body["samples"][1]["tvl"], 50_000);
+        
+        let response = server.handle(&HttpRequest::new("GET", &format!("{}&token=Rune(", target)));
+        assert_eq!(response.status, 400);
+        let response = ApiServer::new().handle(&HttpRequest::new("GET", "/stats/tvl"));
+        assert_eq!(response.status, 409);
+    }
+    
+    #[test]
+    fn test_tvl_series_is_bounded_and_persisted() {
+        let start = chrono::DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+        let clock = Arc::new(MockClock::new(start));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // A deposit made before recording starts is counted from the first sample
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).unwrap();
+        
+        let config = MetricsRecorderConfig {
+            tiers: vec![
+                SeriesTier { resolution_secs: 60, retention_secs: 600 },
+                SeriesTier { resolution_secs: 3600, retention_secs: 86_400 },
+            ],
+        };
+        assert!(matches!(
+            contract.enable_tvl_metrics(MetricsRecorderConfig { tiers: vec![SeriesTier { resolution_secs: 60, retention_secs: 30 }] }),
+            Err(ContractError::InvalidSeriesResolution(_))
+        ));
+        let recorder = contract.enable_tvl_metrics(config.clone()).unwrap();
+        
+        // A deposit every five minutes for three hours
+        for _ in 0..36 {
+            clock.advance(chrono::Duration::minutes(5));
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).unwrap();
+        }
+        let end = clock.now() + chrono::Duration::seconds(1);
+        
+        // The minute tier only reaches back ten minutes
+        let series = recorder.series();
+        assert_eq!(series.tiers[0].len(), 2);
+        assert_eq!(series.tiers[1].len(), 4);
+        let per_minute = contract.get_tvl_series(&TokenType::Bitcoin, start, end, 60).unwrap();
+        assert_eq!(per_minute.iter().map(|sample| sample.tvl).collect::<Vec<_>>(), vec![36_000, 37_000]);
+        
+        // The hour tier holds the state at the end of each hour
+        let hourly = contract.get_tvl_series(&TokenType::Bitcoin, start, end, 3600).unwrap();
+        assert_eq!(hourly.iter().map(|sample| sample.tvl).collect::<Vec<_>>(), vec![12_000, 24_000, 36_000, 37_000]);
+        assert_eq!(hourly[0].timestamp, start);
+        
+        // The series survives a snapshot and keeps its tiers
+        let json = serde_json::to_string(&contract.export_state()).unwrap();
+        let mut restored = TimeLockedDeposit::from_state(serde_json::from_str(&json).unwrap(), contract_with_clock(clock.clone()).token_transfer).unwrap();
+        restored.set_clock(clock.clone());
+        assert_eq!(restored.get_tvl_recorder().unwrap().series(), series);
+        assert_eq!(restored.enable_tvl_metrics(MetricsRecorderConfig::default()).unwrap().config(), &config);
+        
+        clock.advance(chrono::Duration::minutes(5));
+        restored.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).unwrap();
+        let hourly = restored.get_tvl_series(&TokenType::Bitcoin, start, clock.now() + chrono::Duration::seconds(1), 3600).unwrap();
+        assert_eq!(hourly.last().unwrap().tvl, 38_000);
+        assert_eq!(hourly.last().unwrap().active_deposits, 38);
+    }
+    
+    #[test]
+    fn test_circuit_breaker_trips_on_withdrawal_count() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // Only the owner configures the breaker, and an enabled breaker needs a window
+        let config = CircuitBreakerConfig {
+            enabled: true,
+            window: Duration::from_secs(3600),
+            max_withdrawal_count: Some(2),
+            max_withdrawal_amount: Vec::new(),
+            pause_token_only: false,
+        };
+        assert!(matches!(
+            contract.set_circuit_breaker_config("depositor_address".to_string(), config.clone(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_circuit_breaker_config("owner_address".to_string(), CircuitBreakerConfig { window: Duration::ZERO, ..config.clone() }, None),
+            Err(ContractError::InvalidCircuitBreakerConfig(_))
+        ));
+        contract.set_circuit_breaker_config("owner_address".to_string(), config, None).unwrap();
+        
+        for _ in 0..5 {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, None).unwrap();
+        }
+        clock.advance(chrono::Duration::days(1));
+        
+        // The first withdrawal leaves the window before the third arrives
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        clock.advance(chrono::Duration::minutes(40));
+        contract.withdraw("depositor_address".to_string(), 2).unwrap();
+        clock.advance(chrono::Duration::minutes(30));
+        contract.withdraw("depositor_address".to_string(), 3).unwrap();
+        assert!(!contract.is_contract_paused);
+        
+        // The fourth makes three within the hour: it completes, then the contract pauses
+        assert!(matches!(contract.withdraw("depositor_address".to_string(), 4), Ok(Event::Withdrawn { deposit_id: 4, .. })));
+        assert!(contract.is_contract_paused);
+        assert_eq!(contract.get_circuit_breaker_trip(), Some(&PauseReason::WithdrawalCount { count: 3, limit: 2 }));
+        assert!(matches!(contract.withdraw("depositor_address".to_string(), 5), Err(ContractError::ContractPaused)));
+        
+        // The reset is its own owner action, recorded apart from the pause
+        assert!(matches!(
+            contract.reset_circuit_breaker("depositor_address".to_string(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.reset_circuit_breaker("owner_address".to_string(), None),
+            Ok(Event::CircuitBreakerReset { ref reset_by, reason: PauseReason::WithdrawalCount { count: 3, limit: 2 }, .. }) if reset_by == "owner_address"
+        ));
+        assert!(!contract.is_contract_paused);
+        assert!(matches!(
+            contract.reset_circuit_breaker("owner_address".to_string(), None),
+            Err(ContractError::CircuitBreakerNotTripped)
+        ));
+        
+        // The withdrawals that tripped the breaker no longer count
+        contract.withdraw("depositor_address".to_string(), 5).unwrap();
+        assert!(!contract.is_contract_paused);
+        
+        let names = sink.names.lock().unwrap();
+        let tail: Vec<&str> = names.iter().skip(5).map(|name| &**name).collect();
+        assert_eq!(tail, vec!["Withdrawn", "Withdrawn", "Withdrawn", "Withdrawn", "ContractPaused", "CircuitBreakerReset", "Withdrawn"]);
+    }
+    
+    #[test]
+    fn test_circuit_breaker_trips_on_amount_and_deficit() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_circuit_breaker_config("owner_address".to_string(), CircuitBreakerConfig {
+            enabled: true,
+            window: Duration::from_secs(24 * 3600),
+            max_withdrawal_count: None,
+            max_withdrawal_amount: vec![(TokenType::Bitcoin, 15_000)],
+            pause_token_only: false,
+        }, None).unwrap();
+        
+        for _ in 0..3 {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        }
+        
+        // Emergency payouts count net of the fee: 9,000 then 18,000 against a 15,000 limit
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(!contract.is_contract_paused);
+        contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        assert!(contract.is_contract_paused);
+        let reason = PauseReason::WithdrawalAmount { token_type: TokenType::Bitcoin, amount: 18_000, limit: 15_000 };
+        assert_eq!(contract.get_circuit_breaker_trip(), Some(&reason));
+        
+        // The trip survives a snapshot, and its reason is machine-readable
+        let state = contract.export_state();
+        let json = serde_json::to_value(&state).unwrap();
+        assert_eq!(json["circuit_breaker_trip"]["kind"], "withdrawal_amount");
+        let mut restored = TimeLockedDeposit::from_state(serde_json::from_value(json).unwrap(), contract_with_clock(clock.clone()).token_transfer).unwrap();
+        restored.set_clock(clock.clone());
+        assert!(restored.is_contract_paused);
+        assert_eq!(restored.get_circuit_breaker_trip(), Some(&reason));
+        restored.reset_circuit_breaker("owner_address".to_string(), None).unwrap();
+        
+        // A deficit beyond tolerance trips the breaker even without auto-pause
+        let expected = restored.expected_holdings()[&TokenType::Bitcoin];
+        restored.token_transfer.expect_get_contract_balance()
+            .returning(move |_| Ok(expected - 500));
+        let sink = Arc::new(RecordingSink::default());
+        restored.add_event_sink(sink.clone());
+        
+        let report = restored.reconcile_and_enforce(&ReconciliationConfig { tolerance: 1_000, auto_pause: false });
+        assert!(!report.paused_contract);
+        assert!(!restored.is_contract_paused);
+        
+        let report = restored.reconcile_and_enforce(&ReconciliationConfig { tolerance: 100, auto_pause: false });
+        assert!(report.paused_contract);
+        assert_eq!(
+            restored.get_circuit_breaker_trip(),
+            Some(&PauseReason::ReconciliationDeficit { token_type: TokenType::Bitcoin, expected, actual: expected - 500 })
+        );
+        assert_eq!(*sink.names.lock().unwrap(), vec!["ContractPaused"]);
+    }
+    
+    #[test]
+    fn test_derived_deposit_ids_are_predictable() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_id_scheme("owner_address".to_string(), IdScheme::Derived, None).unwrap();
+        
+        // The prediction is the ID the deposit gets, under either reference
+        let predicted = contract.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, None, Some("order-1")).unwrap();
+        let event = contract.deposit_idempotent(
+            Some("order-1".to_string()), "depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, None,
+        ).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_id, .. } if deposit_id == predicted));
+        assert!(predicted < IMPORTED_DEPOSIT_ID_OFFSET);
+        
+        let utxo: UtxoRef = format!("{}:0", "ABCD".repeat(16)).parse().unwrap();
+        let predicted = contract.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, Some(&utxo), None).unwrap();
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, Some(utxo)).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_id, .. } if deposit_id == predicted));
+        
+        // A failed keyed deposit leaves nothing behind for the next deposit to reuse
+        assert!(contract.deposit_idempotent(
+            Some("order-3".to_string()), "depositor_address".to_string(), TokenType::Bitcoin, 10_000, 0, None,
+        ).is_err());
+        let predicted = contract.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, None, None).unwrap();
+        assert_ne!(predicted, contract.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, None, Some("order-3")).unwrap());
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, None).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_id, .. } if deposit_id == predicted));
+        
+        // Another instance with the same salt derives the same IDs, and a different salt does not
+        let mut twin = contract_with_clock(clock.clone());
+        twin.set_id_scheme("owner_address".to_string(), IdScheme::Derived, None).unwrap();
+        twin.deposit_id_salt = contract.deposit_id_salt;
+        let key_id = twin.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, None, Some("order-1")).unwrap();
+        assert!(contract.deposit_registry.contains_key(&key_id));
+        twin.deposit_id_salt = [7u8; 32];
+        assert_ne!(twin.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, None, Some("order-1")).unwrap(), key_id);
+        
+        // The scheme and salt survive a restart
+        let restored_id = {
+            let mut restored = TimeLockedDeposit::from_state(contract.export_state(), contract_with_clock(clock.clone()).token_transfer).unwrap();
+            restored.set_clock(clock.clone());
+            assert_eq!(restored.get_id_scheme(), IdScheme::Derived);
+            restored.predict_deposit_id("other_address", &TokenType::Bitcoin, 5_000, None, Some("order-2")).unwrap()
+        };
+        assert_eq!(contract.predict_deposit_id("other_address", &TokenType::Bitcoin, 5_000, None, Some("order-2")).unwrap(), restored_id);
+    }
+    
+    #[test]
+    fn test_derived_deposit_id_collisions_and_scheme_lock() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Only the owner picks the scheme
+        assert!(matches!(
+            contract.set_id_scheme("depositor_address".to_string(), IdScheme::Derived, None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.set_id_scheme("owner_address".to_string(), IdScheme::Derived, None).unwrap();
+        
+        let event = contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, Some(test_utxo(0))).unwrap();
+        let Event::Deposited { deposit_id, .. } = event else { panic!("expected a deposit") };
+        
+        // Switching schemes on a live contract is rejected, keeping the same one is not
+        assert!(matches!(
+            contract.set_id_scheme("owner_address".to_string(), IdScheme::Sequential, None),
+            Err(ContractError::IdSchemeLocked)
+        ));
+        contract.set_id_scheme("owner_address".to_string(), IdScheme::Derived, None).unwrap();
+        assert_eq!(contract.get_id_scheme(), IdScheme::Derived);
+        
+        // Each taken candidate moves the derivation on to the next counter
+        let mut taken = Vec::new();
+        for _ in 0..MAX_DEPOSIT_ID_ATTEMPTS {
+            let candidate = contract.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, Some(&test_utxo(1)), None).unwrap();
+            assert!(!taken.contains(&candidate));
+            let mut occupant = contract.deposit_registry[&deposit_id].clone();
+            occupant.deposit_id = candidate;
+            contract.deposit_registry.insert(candidate, occupant);
+            taken.push(candidate);
+        }
+        
+        // Once every candidate is taken the deposit fails without being recorded
+        assert!(matches!(
+            contract.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 10_000, Some(&test_utxo(1)), None),
+            Err(ContractError::DepositIdCollision)
+        ));
+        let count = contract.deposit_registry.len();
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, Some(test_utxo(1))),
+            Err(ContractError::DepositIdCollision)
+        ));
+        assert_eq!(contract.deposit_registry.len(), count);
+        
+        // A sequential contract that already numbered a deposit cannot switch either
+        let mut sequential = contract_with_clock(clock);
+        sequential.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, None).unwrap();
+        assert!(matches!(
+            sequential.set_id_scheme("owner_address".to_string(), IdScheme::Derived, None),
+            Err(ContractError::IdSchemeLocked)
+        ));
+        assert_eq!(sequential.predict_deposit_id("depositor_address", &TokenType::Bitcoin, 1, None, None).unwrap(), 2);
+    }
+    
+    /// Lightning client against a testnet RPC config that is never contacted
+    fn test_lightning_client_instance() -> LightningClient {
+        let config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(),
+        );
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(MockRpcTransport::default()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        
+        LightningClient::new(rpc_client, "http://localhost:9735".to_string(), "api_key".to_string())
+    }
+    
+    #[test]
+    fn test_bolt11_round_trip() {
+        let payee = KeySigner::generate();
+        let peer = hex::encode(KeySigner::generate().public_key());
+        
+        let invoice = Bolt11Invoice {
+            currency: "tb".to_string(),
+            amount_msat: Some(250_000_000),
+            timestamp: 1_700_000_000,
+            payment_hash: "11".repeat(32),
+            description: Some("withdrawal 7".to_string()),
+            payee: hex::encode(payee.public_key()),
+            expiry: Some(600),
+            route_hints: vec![RouteHint {
+                hops: vec![RouteHintHop {
+                    node_id: peer,
+                    short_channel_id: (2_500_000 << 40) | (3 << 16),
+                    fee_base_msat: 1_000,
+                    fee_proportional_millionths: 100,
+                    cltv_expiry_delta: 144,
+                }],
+            }],
+        };
+        
+        // The amount takes the largest whole multiplier and every field survives
+        let encoded = invoice.encode(&payee).unwrap();
+        assert!(encoded.starts_with("lntb2500u1"));
+        assert_eq!(Bolt11Invoice::decode(&encoded).unwrap(), invoice);
+        
+        // Only the payee can sign, and a changed field breaks the signature
+        assert!(matches!(invoice.encode(&KeySigner::generate()), Err(ContractError::InvalidInvoice(_))));
+        let (_, data, variant) = bitcoincore_rpc::bitcoin::bech32::decode(&encoded).unwrap();
+        let forged = bitcoincore_rpc::bitcoin::bech32::encode("lntb1m", data, variant).unwrap();
+        assert!(matches!(Bolt11Invoice::decode(&forged), Err(ContractError::InvalidInvoice(_))));
+        assert!(matches!(Bolt11Invoice::decode("lntb1000n1p..."), Err(ContractError::InvalidInvoice(_))));
+    }
+    
+    #[test]
+    fn test_lightning_payment_to_private_channel_needs_hint() {
+        let client = test_lightning_client_instance();
+        
+        // The user is reachable only over a private channel from a public peer
+        let user = KeySigner::generate();
+        let peer = hex::encode(KeySigner::generate().public_key());
+        let elsewhere = hex::encode(KeySigner::generate().public_key());
+        let to_peer = client.open_channel(&peer, 100_000, false).unwrap();
+        let to_elsewhere = client.open_channel(&elsewhere, 100_000, false).unwrap();
+        client.confirm_channel_funding(&to_peer.id).unwrap();
+        client.confirm_channel_funding(&to_elsewhere.id).unwrap();
+        
+        let hint = RouteHint {
+            hops: vec![RouteHintHop {
+                node_id: peer.clone(),
+                short_channel_id: 42 << 40,
+                fee_base_msat: 1_000,
+                fee_proportional_millionths: 1,
+                cltv_expiry_delta: 40,
+            }],
+        };
+        let invoice = |route_hints: Vec<RouteHint>| Bolt11Invoice {
+            currency: "tb".to_string(),
+            amount_msat: Some(10_000_000),
+            timestamp: 1_700_000_000,
+            payment_hash: "22".repeat(32),
+            description: None,
+            payee: hex::encode(user.public_key()),
+            expiry: None,
+            route_hints,
+        }.encode(&user).unwrap();
+        
+        // Without a hint there is no route, with one in the invoice or the params there is
+        let payment = client.pay_invoice(&PaymentParams::new(invoice(Vec::new()))).unwrap();
+        assert_eq!(payment.status, PaymentStatus::Failed);
+        
+        let payment = client.pay_invoice(&PaymentParams::new(invoice(vec![hint.clone()]))).unwrap();
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        assert_eq!(payment.amount, 10_000);
+        assert_eq!(payment.destination, hex::encode(user.public_key()));
+        
+        let payment = client.pay_invoice(&PaymentParams {
+            route_hints: vec![hint.clone()],
+            ..PaymentParams::new(invoice(Vec::new()))
+        }).unwrap();
+        assert_eq!(payment.status, PaymentStatus::Succeeded);
+        
+        // A first hop that leads nowhere near the hint fails, the right one drains that channel
+        let payment = client.pay_invoice(&PaymentParams {
+            first_hop_channel: Some(to_elsewhere.id.clone()),
+            ..PaymentParams::new(invoice(vec![hint.clone()]))
+        }).unwrap();
+        assert_eq!(payment.status, PaymentStatus::Failed);
+        
+        client.pay_invoice(&PaymentParams {
+            first_hop_channel: Some(to_peer.id.clone()),
+            ..PaymentParams::new(invoice(vec![hint]))
+        }).unwrap();
+        let channels = client.get_channels().unwrap();
+        let balance = |id: &str| channels.iter().find(|channel| channel.id == id).unwrap().local_balance;
+        assert_eq!(balance(&to_peer.id), 100_000 - 3 * 10_100);
+        assert_eq!(balance(&to_elsewhere.id), 100_000);
+    }
+    
+    #[test]
+    fn test_lightning_invoice_hints_private_channels() {
+        let client = test_lightning_client_instance();
+        let peer = hex::encode(KeySigner::generate().public_key());
+        
+        // A private channel stays out of the public graph
+        let private = client.open_channel(&peer, 50_000, true).unwrap();
+        let public = client.open_channel(&peer, 50_000, false).unwrap();
+        let listed: Vec<u64> = client.get_public_channels().unwrap().iter().map(|channel| channel.short_channel_id).collect();
+        assert_eq!(listed, vec![public.short_channel_id]);
+        assert!(client.get_route_hint(&public.id).is_err());
+        
+        // Only open private channels are hinted
+        assert!(client.create_invoice(1_000, "deposit", 3600).unwrap().route_hints.is_empty());
+        client.confirm_channel_funding(&private.id).unwrap();
+        
+        let invoice = client.create_invoice(1_000, "deposit", 3600).unwrap();
+        let hint = client.get_route_hint(&private.id).unwrap();
+        assert_eq!(hint.hops[0].node_id, peer);
+        assert_eq!(hint.hops[0].short_channel_id, private.short_channel_id);
+        assert_eq!(invoice.route_hints, vec![hint.clone()]);
+        
+        // The encoded invoice carries the hint and is signed by the node
+        let decoded = Bolt11Invoice::decode(&invoice.bolt11).unwrap();
+        assert_eq!(decoded.payee, client.node_id());
+        assert_eq!(decoded.amount_msat, Some(1_000_000));
+        assert_eq!(decoded.route_hints, vec![hint]);
+    }
+    
+    #[test]
+    fn test_fee_destination_waits_out_delay() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // Generate 100 in fees
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // Only proposed destinations can be activated, and only by the owner
+        assert!(matches!(
+            contract.activate_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::FeeDestinationNotProposed)
+        ));
+        assert!(matches!(
+            contract.propose_fee_destination("depositor_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        contract.propose_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        assert_eq!(contract.get_pending_fee_destinations().len(), 1);
+        
+        // A proposed destination receives nothing before the delay has passed
+        clock.advance(chrono::Duration::hours(47));
+        let activates_at = contract.get_pending_fee_destinations()[0].activates_at;
+        assert!(matches!(
+            contract.activate_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::FeeDestinationTooSoon { activates_at: at }) if at == activates_at
+        ));
+        assert!(matches!(
+            contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, None, Some("cold_storage".to_string()), None),
+            Err(ContractError::DestinationNotWhitelisted)
+        ));
+        
+        // Once activated it does
+        clock.advance(chrono::Duration::hours(1));
+        contract.activate_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        assert!(contract.get_pending_fee_destinations().is_empty());
+        assert_eq!(contract.get_fee_destination_whitelist(), ["cold_storage".to_string(), "owner_address".to_string()]);
+        contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, None, Some("cold_storage".to_string()), None).unwrap();
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 0);
+        
+        let names = sink.names.lock().unwrap();
+        for name in ["FeeDestinationProposed", "FeeDestinationActivated"] {
+            assert!(names.contains(&name), "missing {}", name);
+        }
+    }
+    
+    #[test]
+    fn test_fee_destination_removal_is_immediate() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // The collector at construction is whitelisted without a proposal
+        assert_eq!(contract.get_fee_destination_whitelist(), ["owner_address".to_string()]);
+        contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(10), None, None).unwrap();
+        
+        // Removing it stops withdrawals to it at once
+        contract.remove_fee_destination("owner_address".to_string(), "owner_address".to_string(), None).unwrap();
+        assert!(matches!(
+            contract.withdraw_all_fees("owner_address".to_string(), TokenType::Bitcoin, None),
+            Err(ContractError::DestinationNotWhitelisted)
+        ));
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 90);
+        
+        // A pending proposal can be withdrawn before it activates
+        contract.propose_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        contract.remove_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        clock.advance(chrono::Duration::hours(48));
+        assert!(matches!(
+            contract.activate_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::FeeDestinationNotProposed)
+        ));
+        
+        // Unknown destinations cannot be removed
+        assert!(matches!(
+            contract.remove_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::DestinationNotWhitelisted)
+        ));
+    }
+    
+    #[test]
+    fn test_fee_destination_delay_cannot_be_skipped() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // A shorter delay only applies once the previous one has passed
+        contract.set_fee_destination_delay("owner_address".to_string(), Duration::ZERO, None).unwrap();
+        assert_eq!(contract.get_fee_destination_delay(), Duration::from_secs(48 * 60 * 60));
+        contract.propose_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        assert!(matches!(
+            contract.activate_fee_destination("owner_address".to_string(), "cold_storage".to_string(), None),
+            Err(ContractError::FeeDestinationTooSoon { .. })
+        ));
+        
+        clock.advance(chrono::Duration::hours(48));
+        assert_eq!(contract.get_fee_destination_delay(), Duration::ZERO);
+        contract.propose_fee_destination("owner_address".to_string(), "hot_storage".to_string(), None).unwrap();
+        contract.activate_fee_destination("owner_address".to_string(), "hot_storage".to_string(), None).unwrap();
+        
+        // A longer delay applies at once
+        contract.set_fee_destination_delay("owner_address".to_string(), Duration::from_secs(3600), None).unwrap();
+        assert_eq!(contract.get_fee_destination_delay(), Duration::from_secs(3600));
+    }
+    
+    #[test]
+    fn test_fee_destination_whitelist_survives_snapshot() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_fee_collector("owner_address".to_string(), "cold_storage".to_string(), None).unwrap();
+        contract.propose_fee_destination("owner_address".to_string(), "hot_storage".to_string(), None).unwrap();
+        
+        // Whitelist, proposals and delay round-trip
+        let state = contract.export_state();
+        let json = serde_json::to_string(&state).unwrap();
+        let restored = TimeLockedDeposit::from_state(serde_json::from_str(&json).unwrap(), contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_fee_destination_whitelist(), ["owner_address".to_string()]);
+        assert_eq!(restored.get_pending_fee_destinations(), contract.get_pending_fee_destinations());
+        assert_eq!(restored.get_fee_destination_delay(), contract.get_fee_destination_delay());
+        
+        // A snapshot from before the whitelist keeps paying its fee collector
+        let mut value = serde_json::to_value(&state).unwrap();
+        let object = value.as_object_mut().unwrap();
+        for key in ["fee_destination_whitelist", "pending_fee_destinations", "fee_destination_delay", "fee_destination_delay_floor"] {
+            object.remove(key);
+        }
+        let legacy = TimeLockedDeposit::from_state(serde_json::from_value(value).unwrap(), contract_with_clock(clock).token_transfer).unwrap();
+        assert_eq!(legacy.get_fee_destination_whitelist(), ["cold_storage".to_string()]);
+        assert_eq!(legacy.get_fee_destination_delay(), Duration::from_secs(48 * 60 * 60));
+    }
+    
+    #[test]
+    fn test_client_rejects_before_touching_state() {
+        use crate::client::VaultClient;
+        use crate::soak::SimulatedTransfer;
+        
+        let transfer = SimulatedTransfer::new(1_000_000, std::collections::HashMap::new());
+        let mut client = VaultClient::new(TimeLockedDeposit::new_with_defaults("owner_address".to_string(), 10, transfer).unwrap());
+        
+        // Missing parameters are named
+        assert!(matches!(
+            client.deposit().token(TokenType::Bitcoin).amount_sats(1000).lock_days(30).execute(),
+            Err(ContractError::MissingParameter(name)) if name == "from"
+        ));
+        assert!(matches!(client.withdrawal(1).execute(), Err(ContractError::MissingParameter(name)) if name == "to"));
+        
+        // A failed check leaves the contract and the ledger as they were
+        let result = client.deposit().from("depositor_address").token(TokenType::Bitcoin).amount_sats(1000).lock_days(0).execute();
+        assert!(matches!(result, Err(ContractError::InvalidLockPeriod { .. })));
+        assert!(client.contract().deposit_registry.is_empty());
+        assert_eq!(client.contract().token_transfer.get_contract_balance(&TokenType::Bitcoin).unwrap(), 0);
+        
+        // Outcomes carry the recorded deposit
+        let deposit = client.deposit().from("depositor_address").token(TokenType::Bitcoin).amount_sats(1000).lock_days(30).utxo(test_utxo(0).to_string().to_uppercase()).execute().unwrap();
+        assert_eq!(deposit.event.deposit_id(), Some(deposit.deposit_id));
+        assert_eq!(deposit.utxo_reference, Some(test_utxo(0)));
+        assert_eq!(deposit.utxo_reference, client.contract().deposit_registry[&deposit.deposit_id].utxo_reference);
+        assert!(matches!(client.withdrawal(deposit.deposit_id).to("depositor_address").execute(), Err(ContractError::DepositLocked)));
+        assert!(!client.contract().deposit_registry[&deposit.deposit_id].is_withdrawn());
+    }
+    
+    #[test]
+    fn test_broadcast_labels_recipients_in_node_wallet() {
+        let (rpc_client, transport) = mock_rpc_client();
+        let second = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn";
+        let outputs = vec![(RPC_RECIPIENT.to_string(), 10_000), (second.to_string(), 20_000)];
+        
+        // Labels wait for the broadcast and skip the change
+        let label = "vault:withdraw:deposit-42".to_string();
+        let signed = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Refuse, Some(label.clone())).unwrap();
+        assert!(transport.calls("setlabel").is_empty());
+        rpc_client.broadcast_transaction(&signed).unwrap();
+        rpc_client.release_utxos(&signed.inputs);
+        assert_eq!(transport.calls("setlabel"), vec![
+            vec![serde_json::json!(RPC_RECIPIENT), serde_json::json!(label)],
+            vec![serde_json::json!(second), serde_json::json!(label)],
+        ]);
+        
+        // Unlabelled transactions leave the wallet alone
+        let signed = rpc_client.create_and_sign_transaction_multi(RPC_CONTRACT_WALLET, &outputs, 10.0, DuplicateOutputs::Reject, DataOutputs::Refuse, None).unwrap();
+        rpc_client.broadcast_transaction(&signed).unwrap();
+        assert_eq!(transport.calls("setlabel").len(), 2);
+        
+        // A failed broadcast labels nothing
+        transport.fail_broadcasts_every(1);
+        assert!(rpc_client.create_and_sign_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 10_000, 10.0, None, None, Some(label)).is_err());
+        assert_eq!(transport.calls("setlabel").len(), 2);
+    }
+    
+    #[test]
+    fn test_transfers_label_by_reference_and_deposit() {
+        let (transfer, transport, clock) = transfer_with_payout_batching(PayoutBatchConfig {
+            window: Duration::from_secs(600),
+            ..PayoutBatchConfig::default()
+        });
+        
+        // Immediate withdrawals are labelled with their queue reference
+        transfer.transfer_from_contract_with_fee_limit(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, TransferPriority::Normal, Some(1_000_000)).unwrap();
+        assert_eq!(transport.calls("setlabel")[0], vec![serde_json::json!(RPC_RECIPIENT), serde_json::json!("vault:withdraw:ref-1")]);
+        
+        // Batched payouts name the deposits they pay
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 42).unwrap();
+        transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 5_000, 57).unwrap();
+        clock.advance(chrono::Duration::seconds(600));
+        transfer.process_pending_transactions().unwrap();
+        assert_eq!(transport.calls("setlabel")[1], vec![serde_json::json!(RPC_RECIPIENT), serde_json::json!("vault:withdraw:deposit-42,57")]);
+        
+        // Queued payouts are labelled when processed
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, TransferPriority::Low).unwrap();
+        transfer.process_pending_transactions().unwrap();
+        assert_eq!(transport.calls("setlabel")[2], vec![serde_json::json!(RPC_RECIPIENT), serde_json::json!("vault:payout:ref-2")]);
+    }
+    
+    #[test]
+    fn test_list_vault_transactions_filters_by_label_prefix() {
+        let (rpc_client, transport) = mock_rpc_client();
+        let entry = |txid: &str, label: Option<&str>, amount: f64| {
+            let mut entry = serde_json::json!({
+                "txid": txid.repeat(32),
+                "address": RPC_RECIPIENT,
+                "category": "send",
+                "amount": amount,
+                "fee": -0.00001,
+                "confirmations": 3,
+                "time": 1_700_000_000u64,
+            });
+            if let Some(label) = label {
+                entry["label"] = serde_json::json!(label);
+            }
+            entry
+        };
+        
+        // The node lists oldest first
+        transport.respond("listtransactions", serde_json::json!([
+            entry("01", Some("vault:withdraw:deposit-1"), -0.001),
+            entry("02", Some("cold storage"), -0.5),
+            entry("03", None, -0.2),
+            entry("04", Some("vault:sweep:ref-3"), -0.3),
+            entry("05", Some("vault:withdraw:ref-4"), -0.002),
+        ]));
+        
+        // Only matching labels, newest first
+        let listed = rpc_client.list_vault_transactions("vault:withdraw:", 10, 0).unwrap();
+        let txids: Vec<String> = listed.iter().map(|transaction| transaction.txid[..2].to_string()).collect();
+        assert_eq!(txids, ["05", "01"]);
+        assert_eq!(listed[0].amount, -200_000);
+        assert_eq!(listed[0].fee, Some(-1_000));
+        assert_eq!(listed[0].label, "vault:withdraw:ref-4");
+        assert_eq!(transport.calls("listtransactions")[0], vec![
+            serde_json::json!("*"), serde_json::json!(100), serde_json::json!(0), serde_json::json!(true),
+        ]);
+        
+        // Paging applies to the matching entries
+        let listed = rpc_client.list_vault_transactions(crate::bitcoin::rpc::VAULT_LABEL_PREFIX, 1, 1).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].label, "vault:sweep:ref-3");
+        assert!(rpc_client.list_vault_transactions("vault:", 0, 0).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_xchacha20_poly1305_matches_reference_vectors() {
+        use crate::store::aead;
+        
+        // RFC 8439 AEAD plaintext under a 24-byte nonce
+        let key: [u8; 32] = std::array::from_fn(|i| 0x80 + i as u8);
+        let nonce: [u8; 24] = std::array::from_fn(|i| 0x40 + i as u8);
+        let aad = hex::decode("50515253c0c1c2c3c4c5c6c7").unwrap();
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let sealed = aead::seal(&key, &nonce, &aad, plaintext).unwrap();
+        assert_eq!(hex::encode(&sealed), "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b4522f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff921f9664c97637da9768812f615c68b13b52ec0875924c1c7987947deafd8780acf49");
+        assert_eq!(aead::open(&key, &nonce, &aad, &sealed).unwrap(), plaintext.to_vec());
+        assert_eq!(hex::encode(aead::seal(&key, &nonce, &[], &[]).unwrap()), "1dac8f73146d1e9da796cb7f7221a5df");
+        
+        // Any change to the key, AAD or ciphertext fails the tag
+        let mut other_key = key;
+        other_key[0] ^= 1;
+        assert!(aead::open(&other_key, &nonce, &aad, &sealed).is_none());
+        assert!(aead::open(&key, &nonce, b"other", &sealed).is_none());
+        let mut tampered = sealed.clone();
+        tampered[10] ^= 0x80;
+        assert!(aead::open(&key, &nonce, &aad, &tampered).is_none());
+        assert!(aead::open(&key, &nonce, &aad, &sealed[..15]).is_none());
+    }
+    
+    #[test]
+    fn test_argon2id_matches_reference_vectors() {
+        use crate::store::argon2::{argon2id, Argon2Params};
+        
+        // RFC 9106 Argon2id vector, with secret and associated data over four lanes
+        let params = |memory_kib, iterations, parallelism| Argon2Params { memory_kib, iterations, parallelism };
+        assert_eq!(
+            hex::encode(argon2id(&[1; 32], &[2; 16], &[3; 8], &[4; 12], &params(32, 3, 4), 32).unwrap()),
+            "0d640df58d78766c08c037a34a8b53c9d01ef0452d75b65eb52520e96b01e659",
+        );
+        
+        // More than one address block per segment, several passes and lanes, and a long tag
+        assert_eq!(hex::encode(argon2id(b"password", b"somesalt", &[], &[], &params(64, 1, 1), 32).unwrap()), "729c7a54441bc13559bdca71348c4e554599e719c08a952601ed5c83618c1bbd");
+        assert_eq!(hex::encode(argon2id(b"correct horse", b"0123456789abcdef", &[], &[], &params(1024, 2, 2), 32).unwrap()), "832001e75dd8b0a92e992f29cbd770c0cbf380b78514511bfa8693307c9cb205");
+        assert_eq!(
+            hex::encode(argon2id(b"pw", b"saltsalt", &[], &[], &params(16, 1, 1), 100).unwrap()),
+            "ffd8f32ab13a7b6f8d38f8a9160d5b9a03f7ae9469ae2bcf1c4ad76ac6cd410275128e47fc63740e1358162c2a7662077c09b16919012496a0d331a01cd7ec0f9ebf4e77d845044af68435b31d9c4e57c40d4a2ed2ac66c2c7622efda830984ef8546908",
+        );
+        
+        // Costs a forged header could use to exhaust memory or time are refused
+        assert!(argon2id(b"pw", b"saltsalt", &[], &[], &params(u32::MAX, 1, 1), 32).is_err());
+        assert!(argon2id(b"pw", b"saltsalt", &[], &[], &params(64, 0, 1), 32).is_err());
+        assert!(argon2id(b"pw", b"saltsalt", &[], &[], &params(64, u32::MAX, 1), 32).is_err());
+    }
+    
+    /// Passphrase key cheap enough for tests
+    fn test_state_passphrase(passphrase: &str) -> crate::store::StateKey {
+        let params = crate::store::Argon2Params { memory_kib: 64, iterations: 1, parallelism: 1 };
+        crate::store::StateKey::from_passphrase_with_params(crate::config::Secret::new(passphrase.to_string()), params).unwrap()
+    }
+    
+    #[test]
+    fn test_encrypted_store_round_trips_state_and_payouts() {
+        use crate::bitcoin::payout::PayoutEntry;
+        use crate::store::{EncryptedStateStore, StateKey, StateStore, TransferStateStore};
+        
+        let payouts = vec![
+            PayoutEntry { destination: "tb1qfirst".to_string(), amount: 10_000, reference: 1 },
+            PayoutEntry { destination: "tb1qsecond".to_string(), amount: 20_000, reference: 2 },
+        ];
+        let state = state_with_deposits(3);
+        
+        for key in [StateKey::generate(), test_state_passphrase("correct horse battery staple")] {
+            let dir = tempfile::tempdir().unwrap();
+            let store = EncryptedStateStore::open(dir.path(), key.clone()).unwrap();
+            assert!(store.load_state().unwrap().is_none());
+            assert!(store.load_pending_payouts().unwrap().is_empty());
+            
+            store.save_state(&state).unwrap();
+            store.save_pending_payouts(&payouts).unwrap();
+            assert_eq!(store.blob_names().unwrap(), ["pending_payouts", "state"]);
+            
+            // Nothing readable reaches the disk
+            for name in ["state.enc", "pending_payouts.enc"] {
+                let raw = std::fs::read(dir.path().join(name)).unwrap();
+                assert!(raw.starts_with(crate::store::encrypted::MAGIC));
+                assert!(!raw.windows(6).any(|window| window == b"user_1" || window == b"tb1qfi"));
+            }
+            
+            // A store opened later with the same key reads it back
+            let reopened = EncryptedStateStore::open(dir.path(), key).unwrap();
+            let loaded = reopened.load_state().unwrap().unwrap();
+            assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&state).unwrap());
+            assert_eq!(reopened.load_pending_payouts().unwrap(), payouts);
+        }
+        
+        // Key files hold the key raw or as hex
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("state.key");
+        std::fs::write(&key_path, format!("{}\n", "ab".repeat(32))).unwrap();
+        assert!(StateKey::from_key_file(&key_path).is_ok());
+        std::fs::write(&key_path, [7u8; 32]).unwrap();
+        assert!(StateKey::from_key_file(&key_path).is_ok());
+        std::fs::write(&key_path, "too short").unwrap();
+        assert!(matches!(StateKey::from_key_file(&key_path), Err(StateEncryptionError::InvalidKey(_))));
+        assert!(StateKey::from_settings(Some(crate::config::Secret::new("pw".to_string())), Some(&key_path)).is_err());
+        assert!(StateKey::from_settings(None, None).unwrap().is_none());
+    }
+    
+    #[test]
+    fn test_encrypted_store_rejects_wrong_key_and_tampering() {
+        use crate::store::{EncryptedStateStore, StateKey, StateStore};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let store = EncryptedStateStore::open(dir.path(), test_state_passphrase("right")).unwrap();
+        store.save_state(&state_with_deposits(2)).unwrap();
+        store.write_blob("pending_payouts", b"[]").unwrap();
+        let state_path = dir.path().join("state.enc");
+        let sealed = std::fs::read(&state_path).unwrap();
+        let is_wrong_key = |result: Result<Option<crate::config::Secret<Vec<u8>>>, StateEncryptionError>| {
+            matches!(result, Err(StateEncryptionError::WrongKeyOrTampered { blob }) if blob == "state")
+        };
+        
+        // Wrong passphrase, and a raw key where a passphrase was used
+        let wrong = EncryptedStateStore::open(dir.path(), test_state_passphrase("wrong")).unwrap();
+        assert!(is_wrong_key(wrong.read_blob("state")));
+        let error = wrong.load_state().unwrap_err();
+        assert!(error.contains("wrong key or tampered ciphertext"), "{}", error);
+        assert!(is_wrong_key(EncryptedStateStore::open(dir.path(), StateKey::generate()).unwrap().read_blob("state")));
+        
+        // Flipped bits in the ciphertext, the tag, the nonce or the header
+        for position in [sealed.len() - 40, sealed.len() - 1, 50, 30] {
+            let mut tampered = sealed.clone();
+            tampered[position] ^= 0x01;
+            std::fs::write(&state_path, &tampered).unwrap();
+            assert!(is_wrong_key(store.read_blob("state")), "byte {} not authenticated", position);
+        }
+        
+        // A blob moved to another name
+        std::fs::copy(dir.path().join("pending_payouts.enc"), &state_path).unwrap();
+        assert!(is_wrong_key(store.read_blob("state")));
+        
+        // Plaintext, a future format and a forged cost fail before decryption
+        std::fs::write(&state_path, b"{\"version\":\"1\"}").unwrap();
+        assert!(matches!(store.read_blob("state"), Err(StateEncryptionError::NotEncrypted { .. })));
+        let mut future = sealed.clone();
+        future[8] = 2;
+        std::fs::write(&state_path, &future).unwrap();
+        assert!(matches!(store.read_blob("state"), Err(StateEncryptionError::UnsupportedVersion { found: 2, supported: 1, .. })));
+        let mut forged = sealed.clone();
+        forged[10..14].copy_from_slice(&u32::MAX.to_be_bytes());
+        std::fs::write(&state_path, &forged).unwrap();
+        assert!(matches!(store.read_blob("state"), Err(StateEncryptionError::Malformed { .. })));
+        std::fs::write(&state_path, &sealed[..20]).unwrap();
+        assert!(matches!(store.read_blob("state"), Err(StateEncryptionError::Malformed { .. })));
+        
+        // The untouched blob still decrypts
+        std::fs::write(&state_path, &sealed).unwrap();
+        assert!(store.load_state().unwrap().is_some());
+        assert!(store.read_blob("../state").is_err());
+    }
+    
+    #[test]
+    fn test_encrypted_store_rotates_key() {
+        use crate::store::{EncryptedStateStore, StateKey, StateStore};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let old = test_state_passphrase("old passphrase");
+        let new = StateKey::generate();
+        let mut store = EncryptedStateStore::open(dir.path(), old.clone()).unwrap();
+        store.save_state(&state_with_deposits(2)).unwrap();
+        store.write_blob("pending_payouts", b"[]").unwrap();
+        let before = std::fs::read(dir.path().join("state.enc")).unwrap();
+        
+        // A wrong old key rewrites nothing
+        assert!(matches!(
+            store.rotate_key(&test_state_passphrase("guess"), new.clone(), None),
+            Err(StateEncryptionError::WrongKeyOrTampered { .. }),
+        ));
+        assert_eq!(std::fs::read(dir.path().join("state.enc")).unwrap(), before);
+        
+        // Neither does a rotation cancelled while decrypting
+        let cancelled = crate::progress::Progress::new();
+        cancelled.cancel();
+        assert!(matches!(
+            store.rotate_key(&old, new.clone(), Some(&cancelled)),
+            Err(StateEncryptionError::Cancelled { decrypted: 0 }),
+        ));
+        assert_eq!(std::fs::read(dir.path().join("state.enc")).unwrap(), before);
+        
+        let progress = crate::progress::Progress::new();
+        assert_eq!(store.rotate_key(&old, new.clone(), Some(&progress)).unwrap(), 2);
+        assert_eq!((progress.stage().as_str(), progress.processed(), progress.total()), ("rewrite", 2, 2));
+        assert!(store.load_state().unwrap().is_some());
+        
+        // Only the new key reads the blobs now
+        assert!(EncryptedStateStore::open(dir.path(), old.clone()).unwrap().load_state().is_err());
+        let reopened = EncryptedStateStore::open(dir.path(), new.clone()).unwrap();
+        assert_eq!(reopened.load_state().unwrap().unwrap().deposits.len(), 2);
+        assert_eq!(reopened.read_blob("pending_payouts").unwrap().unwrap().expose_secret(), b"[]");
+        
+        // Running the rotation again is harmless
+        assert_eq!(store.rotate_key(&old, new, None).unwrap(), 2);
+    }
+    
+    #[test]
+    fn test_fee_ledger_records_accruals_and_sweeps() {
+        use crate::contract::{FeeAccrualKind, FeeMonthSummary};
+        
+        let start = chrono::DateTime::parse_from_rfc3339("2026-01-20T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let clock = Arc::new(MockClock::new(start));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_min_age_before_emergency("owner_address".to_string(), Duration::ZERO, None).unwrap();
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(10)), None).unwrap();
+        contract.set_insurance_share("owner_address".to_string(), 20, None).unwrap();
+        
+        // A deposit fee in January
+        contract.deposit("user_a".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        let first_id = contract.user_deposit_ids.get("user_a").unwrap()[0];
+        
+        // A deposit fee and an emergency fee in February
+        clock.advance(chrono::Duration::days(20));
+        contract.deposit("user_b".to_string(), TokenType::Bitcoin, 2000, 30, None).unwrap();
+        let event = contract.emergency_withdraw("user_a".to_string(), first_id).unwrap();
+        let (fee_amount, insurance_amount) = match event {
+            Event::EmergencyWithdrawn { fee_amount, insurance_amount, .. } => (fee_amount, insurance_amount),
+            other => panic!("unexpected event {:?}", other),
+        };
+        let emergency_fee = fee_amount - insurance_amount;
+        assert!(insurance_amount > 0);
+        
+        // Accruals are recorded net of the insurance share, filtered by time
+        let all = contract.get_fee_accruals(&TokenType::Bitcoin, start, clock.now() + chrono::Duration::seconds(1));
+        assert_eq!(all.iter().map(|accrual| (accrual.kind, accrual.amount)).collect::<Vec<_>>(), vec![
+            (FeeAccrualKind::DepositFee, 10),
+            (FeeAccrualKind::DepositFee, 10),
+            (FeeAccrualKind::EmergencyFee, emergency_fee),
+        ]);
+        assert_eq!(all[2].deposit_id, first_id);
+        assert_eq!(contract.get_fee_accruals(&TokenType::Bitcoin, start, start + chrono::Duration::days(1)).len(), 1);
+        assert!(contract.get_fee_accruals(&TokenType::Ethereum, start, clock.now() + chrono::Duration::seconds(1)).is_empty());
+        
+        // A sweep of 15 draws on the first two accruals
+        let sweep_amount = 15;
+        contract.withdraw_fees("owner_address".to_string(), TokenType::Bitcoin, Some(sweep_amount), None, None).unwrap();
+        let sweeps = contract.get_fee_sweeps(&TokenType::Bitcoin);
+        assert_eq!(sweeps.len(), 1);
+        assert_eq!((sweeps[0].first_accrual, sweeps[0].last_accrual), (Some(all[0].sequence), Some(all[1].sequence)));
+        
+        // Monthly totals
+        assert_eq!(contract.get_fee_summary_by_month(&TokenType::Bitcoin), vec![
+            FeeMonthSummary { year: 2026, month: 1, deposit_fees: 10, emergency_fees: 0, swept: 0, accrual_count: 1 },
+            FeeMonthSummary { year: 2026, month: 2, deposit_fees: 10, emergency_fees: emergency_fee, swept: sweep_amount, accrual_count: 2 },
+        ]);
+        assert_eq!(
+            contract.fee_config.collected_fees[&TokenType::Bitcoin],
+            20 + emergency_fee - sweep_amount,
+        );
+        assert!(contract.check_fee_ledger().is_ok());
+        
+        // Snapshots keep the ledger
+        let mut state = contract.export_state();
+        let restored = TimeLockedDeposit::from_state(state.clone(), contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_fee_sweeps(&TokenType::Bitcoin), sweeps);
+        
+        // Snapshots from before the ledger open it with their collected fees
+        state.fee_ledger = None;
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_fee_opening_balance(&TokenType::Bitcoin), 20 + emergency_fee - sweep_amount);
+        assert!(restored.get_fee_summary_by_month(&TokenType::Bitcoin).is_empty());
+        assert!(restored.check_fee_ledger().is_ok());
+    }
+    
+    /// Contract restored from a state saved while the withdrawals of `in_flight` were handed to the backend
+    /// 
+    /// Deposits 1 to 3 pay 10,000 sats each to `RPC_RECIPIENT` and have matured;
+    /// `withdrawn` were withdrawn before the state was saved.
+    fn restored_mid_withdrawal<T: TokenTransfer>(transfer: T, in_flight: &[u64], withdrawn: &[u64]) -> TimeLockedDeposit<T> {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        for _ in 0..3 {
+            contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        }
+        clock.advance(chrono::Duration::days(31));
+        for deposit_id in withdrawn {
+            contract.withdraw(RPC_RECIPIENT.to_string(), *deposit_id).unwrap();
+        }
+        
+        let mut state = contract.export_state();
+        state.owner = RPC_CONTRACT_WALLET.to_string();
+        state.fee_collector_address = RPC_CONTRACT_WALLET.to_string();
+        state.supported_tokens = vec![TokenType::Bitcoin];
+        for deposit in state.deposits.iter_mut().filter(|deposit| in_flight.contains(&deposit.deposit_id)) {
+            deposit.status = DepositStatus::WithdrawalPending;
+        }
+        
+        let mut restored = TimeLockedDeposit::from_state(state, transfer).unwrap();
+        restored.set_clock(clock);
+        restored
+    }
+    
+    /// Make the node wallet list one sent payout transaction under a label
+    fn set_wallet_payout(transport: &MockRpcTransport, txid: &str, label: &str) {
+        transport.respond("listtransactions", serde_json::json!([{
+            "txid": txid,
+            "address": RPC_RECIPIENT,
+            "category": "send",
+            "amount": -0.0001,
+            "fee": -0.00001,
+            "confirmations": 0,
+            "time": 1_700_000_000u64,
+            "label": label,
+        }]));
+    }
+    
+    #[test]
+    fn test_recover_in_flight_settles_each_mismatch() {
+        let (transfer, transport, _clock) = transfer_with_payout_batching(PayoutBatchConfig {
+            window: Duration::from_secs(600),
+            ..PayoutBatchConfig::default()
+        });
+        let mut contract = restored_mid_withdrawal(transfer, &[1, 2, 3], &[]);
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // Deposit 1 was broadcast, deposit 2 is still queued and deposit 3 never reached the backend
+        let txid = "cd".repeat(32);
+        set_wallet_payout(&transport, &txid, "vault:withdraw:deposit-1");
+        set_mock_chain(&transport, 100, 0, None);
+        contract.token_transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 2).unwrap();
+        
+        // In-flight deposits cannot be withdrawn again meanwhile
+        assert!(matches!(contract.withdraw(RPC_RECIPIENT.to_string(), 3), Err(ContractError::WithdrawalInFlight)));
+        
+        let events = contract.recover_in_flight().unwrap();
+        let corrections: Vec<(u64, WithdrawalCorrection, Option<String>)> = events.iter()
+            .map(|event| match event {
+                Event::WithdrawalRecovered { deposit_id, correction, transaction_hash, .. } => (*deposit_id, *correction, transaction_hash.clone()),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(corrections, vec![
+            (1, WithdrawalCorrection::AwaitingConfirmation, Some(txid.clone())),
+            (2, WithdrawalCorrection::Queued, None),
+            (3, WithdrawalCorrection::Reverted, None),
+        ]);
+        
+        // The broadcast payout keeps its deposit pending, the queued one completes and the unsent one can be retried
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::WithdrawalPending);
+        assert_eq!(contract.deposit_registry[&1].withdrawal_tx_hash, Some(txid.clone()));
+        assert_eq!(contract.deposit_registry[&2].status, DepositStatus::Withdrawn);
+        assert_eq!(contract.deposit_registry[&3].status, DepositStatus::Active);
+        assert_eq!(contract.token_transfer.queued_payouts(), vec![2]);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 20_000);
+        contract.check_accounting_invariants().unwrap();
+        
+        // Running again changes nothing until the payout confirms
+        assert!(contract.recover_in_flight().unwrap().is_empty());
+        set_mock_chain(&transport, 101, 1, Some(&"ef".repeat(32)));
+        let events = contract.recover_in_flight().unwrap();
+        assert!(matches!(
+            &events[..],
+            [Event::WithdrawalRecovered { deposit_id: 1, correction: WithdrawalCorrection::Completed, transaction_hash: Some(hash), .. }] if *hash == txid
+        ));
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Withdrawn);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 10_000);
+        assert!(contract.recover_in_flight().unwrap().is_empty());
+        contract.check_accounting_invariants().unwrap();
+        
+        assert_eq!(sink.names.lock().unwrap().len(), 4);
+        contract.withdraw(RPC_RECIPIENT.to_string(), 3).unwrap();
+    }
+    
+    #[test]
+    fn test_recover_in_flight_drops_payouts_already_paid() {
+        let (transfer, transport, _clock) = transfer_with_payout_batching(PayoutBatchConfig {
+            window: Duration::from_secs(600),
+            ..PayoutBatchConfig::default()
+        });
+        let mut contract = restored_mid_withdrawal(transfer, &[1], &[2]);
+        
+        // One confirmed transaction paid both deposits, yet the restored queue still holds their payouts
+        let txid = "cd".repeat(32);
+        set_wallet_payout(&transport, &txid, "vault:withdraw:deposit-1,2");
+        set_mock_chain(&transport, 100, 3, Some(&"ef".repeat(32)));
+        contract.token_transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 1).unwrap();
+        contract.token_transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 2).unwrap();
+        
+        let events = contract.recover_in_flight().unwrap();
+        let corrections: Vec<(u64, WithdrawalCorrection)> = events.iter()
+            .map(|event| match event {
+                Event::WithdrawalRecovered { deposit_id, correction, .. } => (*deposit_id, *correction),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(corrections, vec![
+            (1, WithdrawalCorrection::DuplicatePayoutDropped),
+            (1, WithdrawalCorrection::Completed),
+            (2, WithdrawalCorrection::DuplicatePayoutDropped),
+        ]);
+        
+        // Nothing is left to pay twice
+        assert!(contract.token_transfer.queued_payouts().is_empty());
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Withdrawn);
+        assert_eq!(contract.deposit_registry[&2].withdrawal_tx_hash, Some(txid));
+        assert!(contract.recover_in_flight().unwrap().is_empty());
+        contract.check_accounting_invariants().unwrap();
+    }
+    
+    #[test]
+    fn test_validate_amount_per_token_type() {
+        let unrestricted = AmountConstraints::default();
+        
+        // Any token needs a positive amount within the overflow guard
+        assert!(TokenType::Bitcoin.validate_amount(1, &unrestricted).is_ok());
+        assert!(TokenType::Bitcoin.validate_amount(MAX_AMOUNT, &unrestricted).is_ok());
+        assert!(matches!(TokenType::Bitcoin.validate_amount(0, &unrestricted), Err(ContractError::InvalidAmount { .. })));
+        assert!(matches!(TokenType::Bitcoin.validate_amount(MAX_AMOUNT + 1, &unrestricted), Err(ContractError::InvalidAmount { .. })));
+        
+        // An inscription is unique, whatever the backend allows
+        let ordinal = TokenType::Ordinal("a".repeat(64));
+        assert!(ordinal.validate_amount(1, &unrestricted).is_ok());
+        for amount in [0, 2, 100] {
+            match ordinal.validate_amount(amount, &unrestricted) {
+                Err(ContractError::InvalidAmount { reason }) => assert!(reason.contains("unique asset")),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        
+        // A rune with 2 decimal places moves in steps of 10^6 base units
+        let rune = TokenType::Rune("RUNE_TEST_TOKEN".to_string());
+        let two_places = AmountConstraints { divisibility: Some(2), ..AmountConstraints::default() };
+        assert_eq!(two_places.step(), 1_000_000);
+        assert!(rune.validate_amount(3_000_000, &two_places).is_ok());
+        assert!(matches!(rune.validate_amount(3_000_001, &two_places), Err(ContractError::InvalidAmount { .. })));
+        assert!(rune.validate_amount(3_000_001, &AmountConstraints { divisibility: Some(8), ..two_places }).is_ok());
+        assert!(rune.validate_amount(3, &AmountConstraints { divisibility: Some(18), ..two_places }).is_ok());
+        
+        // Lightning amounts stay within the HTLC limits
+        let htlc = AmountConstraints { min: 1_000, max: 50_000, divisibility: None };
+        assert!(TokenType::Lightning.validate_amount(1_000, &htlc).is_ok());
+        assert!(TokenType::Lightning.validate_amount(50_000, &htlc).is_ok());
+        match TokenType::Lightning.validate_amount(999, &htlc) {
+            Err(error @ ContractError::InvalidAmount { .. }) => {
+                let body = serde_json::to_value(&error).unwrap();
+                assert_eq!(body["code"], "INVALID_AMOUNT");
+                assert_eq!(body["reason"], "999 Lightning is below the minimum of 1000");
+            },
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(TokenType::Lightning.validate_amount(50_001, &htlc), Err(ContractError::InvalidAmount { .. })));
+    }
+    
+    #[test]
+    fn test_deposit_checks_backend_amount_constraints() {
+        let rune = TokenType::Rune("RUNE_TEST_TOKEN".to_string());
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rune_divisibility.insert("RUNE_TEST_TOKEN".to_string(), 0);
+        let (mut transfer, _transport) = transfer_with_mock_config(config);
+        let lightning_client = Arc::new(test_lightning_client_instance());
+        transfer.set_lightning_client(lightning_client.clone());
+        
+        let contract = TimeLockedDeposit::new(
+            RPC_CONTRACT_WALLET.to_string(),
+            10,
+            transfer,
+            vec![TokenType::Bitcoin, TokenType::Lightning, rune.clone()],
+        ).unwrap();
+        
+        // Without an open channel no HTLC can carry the deposit
+        assert!(matches!(
+            contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Lightning, 10_000, 30),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        
+        // The biggest open channel caps Lightning deposits
+        let small = lightning_client.open_channel(&hex::encode(KeySigner::generate().public_key()), 20_000, false).unwrap();
+        let large = lightning_client.open_channel(&hex::encode(KeySigner::generate().public_key()), 80_000, false).unwrap();
+        lightning_client.confirm_channel_funding(&small.id).unwrap();
+        assert!(contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Lightning, 20_000, 30).is_ok());
+        assert!(contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Lightning, 20_001, 30).is_err());
+        lightning_client.confirm_channel_funding(&large.id).unwrap();
+        assert!(contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Lightning, 80_000, 30).is_ok());
+        match contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Lightning, 80_001, 30) {
+            Err(ContractError::InvalidAmount { reason }) => assert_eq!(reason, "80001 Lightning is above the maximum of 80000"),
+            other => panic!("unexpected result {:?}", other),
+        }
+        
+        // An indivisible rune only moves whole units
+        assert!(contract.simulate_deposit(RPC_RECIPIENT, &rune, 200_000_000, 30).is_ok());
+        assert!(matches!(
+            contract.simulate_deposit(RPC_RECIPIENT, &rune, 150_000_000, 30),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        assert!(contract.simulate_deposit(RPC_RECIPIENT, &TokenType::Bitcoin, 150_000_000, 30).is_ok());
+    }
+    
+    #[test]
+    fn test_deposit_rejects_ordinal_amount_other_than_one() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        let ordinal = TokenType::Ordinal("a".repeat(64));
+        contract.add_supported_token("owner_address".to_string(), ordinal.clone(), None).unwrap();
+        
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), ordinal.clone(), 2, 30, None),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        assert!(contract.deposit_registry.is_empty());
+        
+        contract.deposit("depositor_address".to_string(), ordinal, 1, 30, None).unwrap();
+        assert_eq!(contract.deposit_registry[&1].deposited_amount, 1);
+    }
+    
+    /// Signers of the multisig wallet in the descriptor export tests
+    const MULTISIG_KEYS: [&str; 3] = [
+        "02a1633cafcc01ebfb6d78e39f687a1f0995c62fc95f51ead10a02ee0be551b5dc",
+        "03b31347e0572cb0bd58c11dd29ac3fd8b8ba73cd7f3f5b5e2314f8f5bb5c01968",
+        "02df2089105c77f266fa11a9d33f05c735234075f2e8780824c6b709415f9fb485",
+    ];
+    
+    #[test]
+    fn test_multisig_wallet_pays_to_its_witness_script() {
+        let keys: Vec<String> = MULTISIG_KEYS.iter().map(|key| key.to_string()).collect();
+        let wallet = MultisigWallet::new("auditors".to_string(), 2, keys.clone(), Network::Testnet).unwrap();
+        
+        // OP_2 <keys> OP_3 OP_CHECKMULTISIG, paid to as P2WSH
+        assert_eq!(wallet.redeem_script, format!("5221{}21{}21{}53ae", MULTISIG_KEYS[0], MULTISIG_KEYS[1], MULTISIG_KEYS[2]));
+        assert_eq!(wallet.address, "tb1qcxxg2kcz9ldv40fgdudjkttex7kg87m44erh972lq3twv3xkwa0sm5empp");
+        assert_eq!(
+            wallet.descriptor().unwrap(),
+            format!("wsh(multi(2,{}))#57xc87sr", MULTISIG_KEYS.join(",")),
+        );
+        assert_eq!(MultisigPolicy::parse(&wallet.descriptor().unwrap()).unwrap(), wallet.policy().unwrap());
+        
+        // Keys must be real compressed keys
+        assert!(matches!(
+            MultisigWallet::new("broken".to_string(), 1, vec!["02deadbeef".to_string()], Network::Testnet),
+            Err(ContractError::InvalidDescriptor(_))
+        ));
+        assert!(MultisigPolicy::parse(&format!("wsh(multi(4,{}))", MULTISIG_KEYS.join(","))).is_err());
+        assert!(MultisigPolicy::parse(&format!("wsh(multi(02,{}))", MULTISIG_KEYS.join(","))).is_err());
+    }
+    
+    #[test]
+    fn test_export_watch_descriptors_rederive_stored_addresses() {
+        // Escrow deposits recorded before a restart
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock);
+        contract.token_transfer.expect_get_network_type().returning(|| "testnet".to_string());
+        contract.set_escrow_public_key("owner_address".to_string(), Some(ESCROW_VAULT_KEY.to_string()), None).unwrap();
+        for lock_height in [2_500_000, 2_600_000] {
+            contract.deposit_script_escrow("depositor_address".to_string(), 50_000, 30, ESCROW_DEPOSITOR_KEY.to_string(), lock_height).unwrap();
+        }
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        
+        let mut state = contract.export_state();
+        state.owner = RPC_CONTRACT_WALLET.to_string();
+        state.fee_collector_address = RPC_CONTRACT_WALLET.to_string();
+        state.supported_tokens = vec![TokenType::Bitcoin];
+        
+        let (mut transfer, _transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let keys: Vec<String> = MULTISIG_KEYS.iter().map(|key| key.to_string()).collect();
+        let wallet = transfer.create_multisig_wallet("auditors", 2, keys).unwrap();
+        let restored = TimeLockedDeposit::from_state(state, transfer).unwrap();
+        
+        // The contract wallet, the multisig wallet, then the escrow outputs by address
+        let mut escrow_addresses: Vec<String> = restored.deposit_registry.values()
+            .filter_map(|deposit| deposit.escrow.as_ref())
+            .map(|escrow| escrow.address.clone())
+            .collect();
+        escrow_addresses.sort();
+        let mut stored = vec![RPC_CONTRACT_WALLET.to_string(), wallet.address.clone()];
+        stored.extend(escrow_addresses);
+        
+        let descriptors = restored.token_transfer.export_watch_descriptors();
+        assert_eq!(descriptors[0], format!("addr({})#0wnhlaqf", RPC_CONTRACT_WALLET));
+        let derived: Vec<String> = descriptors.iter()
+            .map(|descriptor| descriptor_address(descriptor, Network::Testnet).unwrap().to_string())
+            .collect();
+        assert_eq!(derived, stored);
+        
+        // Every descriptor carries its checksum, which catches tampering
+        for descriptor in &descriptors {
+            let body = crate::bitcoin::descriptor::strip_checksum(descriptor).unwrap();
+            assert_ne!(body, descriptor);
+            assert!(descriptor_address(&descriptor.replacen('0', "2", 1), Network::Testnet).is_err());
+        }
+        
+        // The import request watches without handing out addresses
+        let request = serde_json::to_value(restored.token_transfer.export_import_request(1_700_000_000)).unwrap();
+        assert_eq!(request.as_array().unwrap().len(), 4);
+        assert_eq!(request[1], serde_json::json!({
+            "desc": descriptors[1],
+            "timestamp": 1_700_000_000u64,
+            "internal": false,
+            "active": false,
+        }));
+    }
+    
+    #[test]
+    fn test_referral_share_rounds_down_after_insurance() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_insurance_share("owner_address".to_string(), 50, None).unwrap();
+        contract.set_referral_share("owner_address".to_string(), 33, None).unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        
+        // A 101 fee puts 51 in the pool; of the other 50 the referrer gets 16
+        contract.deposit_with_referrer("depositor_address".to_string(), TokenType::Bitcoin, 1010, 30, None, "referrer_address".to_string()).unwrap();
+        assert_eq!(*sink.names.lock().unwrap(), vec!["Deposited", "ReferralRecorded"]);
+        assert_eq!(contract.deposit_registry[&1].referrer_address.as_deref(), Some("referrer_address"));
+        
+        match contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap() {
+            Event::EmergencyWithdrawn { fee_amount, insurance_amount, .. } => assert_eq!((fee_amount, insurance_amount), (101, 51)),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.referral_balance("referrer_address", &TokenType::Bitcoin), 16);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 34);
+        
+        // A deposit fee of 7 credits the referrer 2
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(7)), None).unwrap();
+        match contract.deposit_with_referrer("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None, "referrer_address".to_string()).unwrap() {
+            Event::Deposited { fee_amount, locked_amount, .. } => assert_eq!((fee_amount, locked_amount), (7, 993)),
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.referral_balance("referrer_address", &TokenType::Bitcoin), 18);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 39);
+        
+        // Unclaimed rewards are held by the contract and the ledger explains only the collector's part
+        assert_eq!(contract.expected_holdings()[&TokenType::Bitcoin], 993 + 39 + 51 + 18);
+        contract.check_accounting_invariants().unwrap();
+        
+        // Balances survive an export and restore
+        let stats = contract.get_stats();
+        assert_eq!(stats.referral_balances, vec![("referrer_address".to_string(), TokenType::Bitcoin, 18)]);
+        let state = contract.export_state();
+        assert_eq!(state.referral_balances, stats.referral_balances);
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.referral_balance("referrer_address", &TokenType::Bitcoin), 18);
+        assert_eq!(restored.fee_config.referral_share_percentage, 33);
+        assert_eq!(restored.deposit_registry[&2].referrer_address.as_deref(), Some("referrer_address"));
+    }
+    
+    #[test]
+    fn test_referral_rewards_claim_and_self_referral() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        
+        // Only the owner sets the share, and never above 100%
+        assert!(matches!(
+            contract.set_referral_share("depositor_address".to_string(), 20, None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.set_referral_share("owner_address".to_string(), 101, None),
+            Err(ContractError::InvalidFeePercentage)
+        ));
+        contract.set_referral_share("owner_address".to_string(), 20, None).unwrap();
+        
+        // A depositor cannot refer themselves
+        assert!(matches!(
+            contract.deposit_with_referrer("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None, "depositor_address".to_string()),
+            Err(ContractError::SelfReferral)
+        ));
+        assert_eq!(contract.get_stats().deposit_count, 0);
+        
+        // Nothing to claim before a referred deposit pays a fee
+        contract.deposit_with_referrer("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None, "referrer_address".to_string()).unwrap();
+        assert!(matches!(
+            contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        
+        // 20% of a 100 fee
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        match contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin).unwrap() {
+            Event::ReferralRewardClaimed { referrer_address, amount, .. } => {
+                assert_eq!(referrer_address, "referrer_address");
+                assert_eq!(amount, 20);
+            },
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert_eq!(contract.referral_balance("referrer_address", &TokenType::Bitcoin), 0);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 80);
+        assert!(contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin).is_err());
+    }
+    
+    #[test]
+    fn test_referral_rewards_claim_refused_while_paused() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_referral_share("owner_address".to_string(), 20, None).unwrap();
+        contract.deposit_with_referrer("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None, "referrer_address".to_string()).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // A paused contract pays no referral rewards, and the balance is kept
+        contract.is_contract_paused = true;
+        assert!(matches!(
+            contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin),
+            Err(ContractError::ContractPaused)
+        ));
+        contract.is_contract_paused = false;
+        
+        // Neither does a paused token
+        contract.pause_token("owner_address".to_string(), TokenType::Bitcoin, None).unwrap();
+        assert!(matches!(
+            contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin),
+            Err(ContractError::TokenPaused { .. })
+        ));
+        assert_eq!(contract.referral_balance("referrer_address", &TokenType::Bitcoin), 20);
+        
+        contract.unpause_token("owner_address".to_string(), TokenType::Bitcoin, None).unwrap();
+        assert!(matches!(
+            contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin),
+            Ok(Event::ReferralRewardClaimed { amount: 20, .. })
+        ));
+    }
+    
+    /// Build a contract whose backend supports Lightning while `lightning` is set
+    fn contract_with_switchable_lightning(lightning: Arc<std::sync::atomic::AtomicBool>) -> TimeLockedDeposit<MockTokenTransferMock> {
+        let mut mock = MockTokenTransferMock::new();
+        mock.expect_validate_address()
+            .returning(|_| Ok(()));
+        mock.expect_supports_token_type()
+            .returning(move |token_type| {
+                !matches!(token_type, TokenType::Lightning) || lightning.load(std::sync::atomic::Ordering::SeqCst)
+            });
+        mock.expect_get_balance()
+            .returning(|_, _| Ok(1_000_000));
+        mock.expect_transfer_to_contract()
+            .returning(|_, _, _| Ok(()));
+        mock.expect_transfer_from_contract()
+            .returning(|_, _, _| Ok(()));
+        
+        TimeLockedDeposit::new_with_defaults("owner_address".to_string(), 10, mock).unwrap()
+    }
+    
+    #[test]
+    fn test_revalidation_deprecates_and_reinstates_tokens() {
+        let lightning = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut contract = contract_with_switchable_lightning(lightning.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None).unwrap();
+        assert!(contract.revalidate_supported_tokens().is_empty());
+        
+        // The backend drops Lightning: deprecated once, however often it is checked
+        lightning.store(false, std::sync::atomic::Ordering::SeqCst);
+        let events = contract.revalidate_supported_tokens();
+        assert!(matches!(
+            events.as_slice(),
+            [Event::TokenSupportDeprecated { token_type: TokenType::Lightning, reason: DeprecationReason::Unsupported, .. }]
+        ));
+        assert!(contract.revalidate_supported_tokens().is_empty());
+        
+        // New deposits fail with a stable error, existing ones can still leave
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None),
+            Err(ContractError::TokenDeprecated { token_type: TokenType::Lightning })
+        ));
+        let stats = contract.get_stats();
+        assert!(!stats.supported_tokens.contains(&TokenType::Lightning));
+        assert_eq!(stats.deprecated_tokens, vec![(TokenType::Lightning, DeprecationReason::Unsupported)]);
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // Support returns
+        lightning.store(true, std::sync::atomic::Ordering::SeqCst);
+        let events = contract.revalidate_supported_tokens();
+        assert!(matches!(events.as_slice(), [Event::TokenSupportReinstated { token_type: TokenType::Lightning, .. }]));
+        assert!(contract.get_stats().deprecated_tokens.is_empty());
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None).unwrap();
+    }
+    
+    #[test]
+    fn test_revalidation_follows_backend_health_and_restored_state() {
+        let lightning = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut contract = contract_with_switchable_lightning(lightning.clone());
+        
+        // An unhealthy backend deprecates its tokens only past the grace period
+        let probe = FakeProbe::new(BackendComponent::Lightning, false);
+        let mut checker = HealthChecker::new(Duration::from_secs(30));
+        checker.add_probe(probe.clone());
+        checker.check_now().unwrap();
+        let checker = Arc::new(checker);
+        
+        contract.set_health_checker(checker.clone(), Duration::from_secs(3600));
+        assert!(contract.revalidate_supported_tokens().is_empty());
+        
+        contract.set_health_checker(checker.clone(), Duration::from_secs(0));
+        let events = contract.revalidate_supported_tokens();
+        assert!(matches!(
+            events.as_slice(),
+            [Event::TokenSupportDeprecated { token_type: TokenType::Lightning, reason: DeprecationReason::BackendUnavailable(BackendComponent::Lightning), .. }]
+        ));
+        assert!(contract.is_token_deprecated(&TokenType::Lightning));
+        assert!(!contract.is_token_deprecated(&TokenType::Bitcoin));
+        
+        probe.set_healthy(true);
+        checker.check_now().unwrap();
+        assert_eq!(contract.revalidate_supported_tokens().len(), 1);
+        
+        // A snapshot restored onto a backend without Lightning keeps the token, deprecated
+        let state = contract.export_state();
+        lightning.store(false, std::sync::atomic::Ordering::SeqCst);
+        let mut restored = TimeLockedDeposit::from_state(state, contract_with_switchable_lightning(lightning.clone()).token_transfer).unwrap();
+        assert!(restored.supported_tokens.contains(&TokenType::Lightning));
+        assert!(matches!(
+            restored.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None),
+            Err(ContractError::TokenDeprecated { .. })
+        ));
+        
+        lightning.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(restored.revalidate_supported_tokens().len(), 1);
+        restored.deposit("depositor_address".to_string(), TokenType::Lightning, 1000, 30, None).unwrap();
+    }
+    
+    #[test]
+    fn test_authorization_log_records_contexts_and_round_trips() {
+        use crate::contract::authorization::{AuthorizationQuery, AuthorizedOperation, OperationChannel, OperationContext};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let start = clock.now();
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_min_age_before_emergency("owner_address".to_string(), Duration::ZERO, None).unwrap();
+        contract.deposit("alice_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.deposit("alice_address".to_string(), TokenType::Bitcoin, 20_000, 30, None).unwrap();
+        contract.deposit("bob_address".to_string(), TokenType::Bitcoin, 30_000, 30, None).unwrap();
+        
+        // An emergency withdrawal from the CLI, fees over HTTP and a plain withdrawal
+        let cli = OperationContext::cli().with_request_id("ticket-812");
+        contract.emergency_withdraw_with_context("alice_address".to_string(), 1, cli.clone()).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        let http = OperationContext::http("203.0.113.7:52100", Some("0a1b2c3d4e5f6071".to_string()));
+        contract.withdraw_fees_with_context("owner_address".to_string(), TokenType::Bitcoin, Some(500), None, None, http.clone()).unwrap();
+        contract.withdraw("alice_address".to_string(), 2).unwrap();
+        let end = clock.now() + chrono::Duration::seconds(1);
+        
+        let alice = contract.get_authorization_log(&AuthorizationQuery::Address("alice_address".to_string()), start, end);
+        assert_eq!(alice.len(), 2);
+        assert_eq!(alice[0].operation, AuthorizedOperation::EmergencyWithdrawal);
+        assert_eq!(alice[0].deposit_id, Some(1));
+        assert_eq!(alice[0].amount, 9_000);
+        assert_eq!(alice[0].context, cli);
+        assert_eq!(alice[1].operation, AuthorizedOperation::Withdrawal);
+        assert_eq!(alice[1].context.channel, OperationChannel::Internal);
+        assert_eq!(alice[1].context.request_id, None);
+        
+        let owner = contract.get_authorization_log(&AuthorizationQuery::Address("owner_address".to_string()), start, end);
+        assert_eq!(owner.len(), 1);
+        assert_eq!(owner[0].operation, AuthorizedOperation::FeeWithdrawal);
+        assert_eq!(owner[0].deposit_id, None);
+        assert_eq!(owner[0].context, http);
+        
+        // Queries by deposit and time range
+        let deposit_one = contract.get_authorization_log(&AuthorizationQuery::Deposit(1), start, end);
+        assert_eq!(deposit_one.len(), 1);
+        assert_eq!(deposit_one[0].sequence, 0);
+        assert!(contract.get_authorization_log(&AuthorizationQuery::Deposit(3), start, end).is_empty());
+        let later = contract.get_authorization_log(&AuthorizationQuery::Address("alice_address".to_string()), start + chrono::Duration::days(1), end);
+        assert_eq!(later.len(), 1);
+        assert_eq!(later[0].deposit_id, Some(2));
+        
+        // The log survives a save and restore with its contexts
+        let json = serde_json::to_string(&contract.export_state()).unwrap();
+        let restored = TimeLockedDeposit::from_state_versioned(&json, contract.token_transfer).unwrap();
+        assert_eq!(restored.get_authorization_log(&AuthorizationQuery::Address("alice_address".to_string()), start, end), alice);
+        assert_eq!(restored.get_authorization_log(&AuthorizationQuery::Address("owner_address".to_string()), start, end), owner);
+        
+        // Snapshots from before the log restore with it empty
+        let raw = include_str!("fixtures/state_v1_0_0_early.json");
+        let old = TimeLockedDeposit::from_state_versioned(raw, contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer).unwrap();
+        assert!(old.authorization_log.records.is_empty());
+    }
+    
+    #[test]
+    fn test_jsonrpc_withdrawals_record_http_context_in_event_log() {
+        use crate::contract::authorization::{AuthorizationQuery, OperationChannel};
+        use crate::event_log::{LogRecord, StoredEvent};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let start = clock.now();
+        let event_log = Arc::new(EventLog::open(EventLogConfig::new(&path), None).unwrap());
+        let mut contract = contract_with_clock(clock.clone());
+        contract.add_event_sink(event_log.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        let contract = Arc::new(std::sync::Mutex::new(contract));
+        let handler = crate::server::JsonRpcHandler::new(contract.clone(), Some(crate::config::Secret::new("secret-key".to_string())));
+        let server = ApiServer::new().with_jsonrpc(Arc::new(handler));
+        
+        // The request ID comes from the header, or else from the call ID
+        let mut request = HttpRequest::new("POST", "/jsonrpc");
+        request.remote_addr = Some("198.51.100.4:40112".to_string());
+        request.headers.insert("x-api-key".to_string(), "secret-key".to_string());
+        request.headers.insert("x-request-id".to_string(), "req-1".to_string());
+        request.body = br#"{"jsonrpc": "2.0", "method": "vault_withdraw", "params": {"caller": "depositor_address", "deposit_id": 1}, "id": 1}"#.to_vec();
+        assert_eq!(server.handle(&request).status, 200);
+        
+        request.headers.remove("x-request-id");
+        request.body = br#"{"jsonrpc": "2.0", "method": "vault_withdraw", "params": {"caller": "depositor_address", "deposit_id": 2}, "id": "call-2"}"#.to_vec();
+        assert_eq!(server.handle(&request).status, 200);
+        
+        let records = contract.lock().unwrap().get_authorization_log(
+            &AuthorizationQuery::Address("depositor_address".to_string()),
+            start,
+            clock.now() + chrono::Duration::seconds(1),
+        );
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].context.request_id.as_deref(), Some("req-1"));
+        assert_eq!(records[1].context.request_id.as_deref(), Some("call-2"));
+        let api_key_id = match &records[0].context.channel {
+            OperationChannel::Http { remote_addr, api_key_id } => {
+                assert_eq!(remote_addr, "198.51.100.4:40112");
+                api_key_id.clone().unwrap()
+            },
+            other => panic!("unexpected channel {:?}", other),
+        };
+        
+        // Only a digest of the key is recorded
+        assert_eq!(api_key_id.len(), 16);
+        assert!(!api_key_id.contains("secret"));
+        
+        // The event log stores the context next to the event and still verifies
+        drop(server);
+        drop(contract);
+        let entries: Vec<StoredEvent> = std::fs::read_to_string(&path).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(entries[0].context.is_none());
+        let withdrawn: Vec<&StoredEvent> = entries.iter()
+            .filter(|entry| matches!(&entry.record, LogRecord::Event(Event::Withdrawn { .. })))
+            .collect();
+        assert_eq!(withdrawn.len(), 2);
+        assert_eq!(withdrawn[0].context.as_ref(), Some(&records[0].context));
+        assert_eq!(withdrawn[1].context.as_ref(), Some(&records[1].context));
+        assert!(verify_event_log(&path, None).unwrap().is_intact());
+        
+        // Editing a stored context breaks the chain
+        let lines: Vec<String> = std::fs::read_to_string(&path).unwrap().lines().map(str::to_string).collect();
+        let tampered: Vec<String> = lines.iter()
+            .map(|line| line.replace("req-1", "req-9"))
+            .collect();
+        std::fs::write(&path, tampered.join("\n") + "\n").unwrap();
+        assert_eq!(verify_event_log(&path, None).unwrap().first_broken_index, Some(withdrawn[0].index));
+    }
+    
+    /// Deposit with every optional field set, pinned by the canonical encoding golden file
+    fn canonical_sample_deposit() -> crate::models::Deposit {
+        let at = |secs: i64, nanos: u32| chrono::DateTime::from_timestamp(secs, nanos).unwrap();
+        
+        crate::models::Deposit {
+            deposit_id: 42,
+            depositor_address: "tb1qdepositor".to_string(),
+            deposited_token_type: TokenType::Rune("RUNE•ONE".to_string()),
+            deposited_amount: 1_000_000,
+            deposit_timestamp: at(1_735_689_600, 0),
+            unlock_timestamp: at(1_738_281_600, 500_000_000),
+            status: DepositStatus::WithdrawalPending,
+            withdrawal_tx_hash: Some("ab".repeat(32)),
+            last_modified: at(1_735_776_000, 123_456_789),
+            utxo_reference: Some(format!("{}:1", "cd".repeat(32)).parse().unwrap()),
+            lightning_payment_hash: None,
+            multisig_wallet: Some("vault-2of3".to_string()),
+            lightning_invoice_id: None,
+            multisig_address: Some("tb1qvault".to_string()),
+            claim_hash: Some([7; 32]),
+            claimed_by: None,
+            inheritance: Some(crate::models::Inheritance { beneficiary_address: "tb1qheir".to_string(), inactivity_days: 180 }),
+            reminder: Some(crate::models::MaturityReminder { remind_days_before: 3, notified: true }),
+            pending: Some(crate::models::PendingFunding { expires_at: at(1_735_693_200, 0), fee_amount: 250 }),
+            escrow: Some(crate::models::ScriptEscrow {
+                vault_public_key: "02".to_string() + &"11".repeat(32),
+                depositor_public_key: "03".to_string() + &"22".repeat(32),
+                lock_height: 2_500_000,
+                network: "testnet".to_string(),
+                address: "tb1qescrow".to_string(),
+            }),
+            referrer_address: Some("tb1qreferrer".to_string()),
+        }
+    }
+    
+    #[test]
+    fn test_deposit_canonical_bytes_match_golden_file() {
+        let deposit = canonical_sample_deposit();
+        
+        // Any change to the encoding must bump its version and update the golden file
+        assert_eq!(hex::encode(deposit.canonical_bytes()), include_str!("fixtures/deposit_canonical_v1.hex").trim());
+        assert_eq!(
+            hex::encode(deposit.canonical_hash()),
+            "d9af35324172994d9311e4dbfde85a36013e43dc7704995403a730f7301b4830",
+        );
+        
+        // Every field counts, including the ones serde skips when empty
+        let mut changed = canonical_sample_deposit();
+        changed.referrer_address = None;
+        assert_ne!(changed.canonical_hash(), deposit.canonical_hash());
+        let mut changed = canonical_sample_deposit();
+        changed.last_modified += chrono::Duration::nanoseconds(1);
+        assert_ne!(changed.canonical_hash(), deposit.canonical_hash());
+        let mut changed = canonical_sample_deposit();
+        changed.deposited_token_type = TokenType::Ordinal("RUNE•ONE".to_string());
+        assert_ne!(changed.canonical_hash(), deposit.canonical_hash());
+        
+        // Except what the node's own backend reported
+        let mut changed = canonical_sample_deposit();
+        changed.multisig_address = None;
+        assert_eq!(changed.canonical_hash(), deposit.canonical_hash());
+    }
+    
+    #[test]
+    fn test_state_canonical_hash_ignores_order_and_local_bookkeeping() {
+        let clock = Arc::new(MockClock::new(chrono::DateTime::from_timestamp(1_735_689_600, 0).unwrap()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(100)), None).unwrap();
+        contract.deposit("alice_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.deposit("bob_address".to_string(), TokenType::Lightning, 5_000, 30, None).unwrap();
+        let state = contract.export_state();
+        assert_eq!(contract.state_hash(), state.canonical_hash());
+        
+        // Snapshot order does not matter
+        let mut reordered = state.clone();
+        reordered.deposits.reverse();
+        reordered.total_deposits.reverse();
+        reordered.supported_tokens.reverse();
+        assert_eq!(reordered.canonical_hash(), state.canonical_hash());
+        
+        // Node-local bookkeeping does not either
+        let mut local = state.clone();
+        local.last_activity.clear();
+        local.fee_ledger = None;
+        local.deprecated_tokens.push((TokenType::Lightning, DeprecationReason::Unsupported));
+        assert_eq!(local.canonical_hash(), state.canonical_hash());
+        
+        // But balances and deposits do
+        let mut changed = state.clone();
+        changed.collected_fees[0].1 += 1;
+        assert_ne!(changed.canonical_hash(), state.canonical_hash());
+        let mut changed = state.clone();
+        changed.deposits[0].deposited_amount += 1;
+        assert_ne!(changed.canonical_hash(), state.canonical_hash());
+        
+        // A restored node reports the same hash in its reconciliation report
+        let json = serde_json::to_string(&state).unwrap();
+        let restored = TimeLockedDeposit::from_state_versioned(&json, contract.token_transfer).unwrap();
+        assert_eq!(restored.state_hash(), state.canonical_hash());
+    }
+    
+    #[test]
+    fn test_receipts_and_manifests_signed_over_legacy_json_still_verify() {
+        use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let vault_key = KeySigner::new(&[7u8; 32]).unwrap();
+        let vault_pubkey = vault_key.public_key();
+        contract.set_receipt_signer(Arc::new(KeySigner::new(&[7u8; 32]).unwrap()));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        
+        // A receipt signed over the JSON payload of earlier versions
+        let mut receipt = contract.issue_receipt(1).unwrap();
+        let legacy_payload = format!(
+            r#"{{"deposit_id":{},"depositor_address":{},"token_type":{},"amount":{},"deposit_timestamp":{},"unlock_timestamp":{},"contract_owner":{},"contract_version":{}}}"#,
+            receipt.deposit_id,
+            serde_json::to_string(&receipt.depositor_address).unwrap(),
+            serde_json::to_string(&receipt.token_type).unwrap(),
+            receipt.amount,
+            serde_json::to_string(&receipt.deposit_timestamp).unwrap(),
+            serde_json::to_string(&receipt.unlock_timestamp).unwrap(),
+            serde_json::to_string(&receipt.contract_owner).unwrap(),
+            serde_json::to_string(&receipt.contract_version).unwrap(),
+        );
+        let legacy_digest = sha256::Hash::hash(legacy_payload.as_bytes()).to_byte_array();
+        assert_ne!(legacy_digest, receipt.digest());
+        receipt.signature = hex::encode(vault_key.sign(&legacy_digest).unwrap());
+        assert!(receipt.verify(&vault_pubkey));
+        receipt.amount += 1;
+        assert!(!receipt.verify(&vault_pubkey));
+        
+        // A manifest hashing the batch's JSON
+        let migration_key = KeySigner::new(&[9u8; 32]).unwrap();
+        contract.set_migration_public_key("owner_address".to_string(), Some(hex::encode(migration_key.public_key())), None).unwrap();
+        let batch = vec![ImportedDeposit {
+            original_id: 1,
+            depositor_address: "depositor_address".to_string(),
+            token_type: TokenType::Bitcoin,
+            amount: 5_000,
+            deposit_timestamp: clock.now() - chrono::Duration::days(10),
+            unlock_timestamp: clock.now() + chrono::Duration::days(10),
+            withdrawn: false,
+        }];
+        let legacy_hash = sha256::Hash::hash(&serde_json::to_vec(&batch).unwrap()).to_byte_array();
+        assert_ne!(legacy_hash, ImportManifest::content_hash_of(&batch));
+        let manifest = ImportManifest {
+            content_hash: hex::encode(legacy_hash),
+            signature: hex::encode(migration_key.sign(&legacy_hash).unwrap()),
+        };
+        assert_eq!(contract.import_deposits("owner_address".to_string(), batch, manifest, None).unwrap().len(), 1);
+    }
+    
+    
+    #[test]
+    fn test_runtime_diagnostics_report_running_components() {
+        use crate::introspection::{DiagnosticsSource, Heartbeat, RuntimeDiagnostics, DAEMON_WORKER};
+        
+        let (mut transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        
+        // A running mempool monitor and daemon loop
+        let monitor_client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let mut monitor_config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        monitor_config.rate_limit = 600_000;
+        let mempool_monitor = Arc::new(MempoolMonitor::new(
+            Arc::new(BitcoinRpcClient::from_client(monitor_client, &monitor_config)),
+            Duration::from_millis(10),
+        ));
+        mempool_monitor.start().unwrap();
+        transfer.set_mempool_monitor(mempool_monitor.clone());
+        
+        let daemon_heartbeat = Heartbeat::new();
+        daemon_heartbeat.beat();
+        let introspector = transfer.introspector().with_worker(DAEMON_WORKER, daemon_heartbeat);
+        
+        // Queued transfers are listed in queue order
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 1_000, TransferPriority::Low).unwrap();
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 2_000, TransferPriority::High).unwrap();
+        
+        let diagnostics = introspector.diagnostics();
+        assert!(!diagnostics.is_stale());
+        let queued: Vec<(u64, TransferPriority, u32)> = diagnostics.pending_transfers.value.iter()
+            .map(|transfer| (transfer.amount, transfer.priority, transfer.attempts))
+            .collect();
+        assert_eq!(queued, vec![(1_000, TransferPriority::Low, 0), (2_000, TransferPriority::High, 0)]);
+        assert_eq!(diagnostics.pending_transfers.value[0].kind, "payout");
+        assert!(diagnostics.pending_transfers.sampled_at.is_some());
+        assert_eq!(diagnostics.queue_capacity, transfer.introspector().diagnostics().queue_capacity);
+        assert!(diagnostics.batched_payouts.is_none());
+        assert!(diagnostics.worker(TRANSFER_PROCESSOR_WORKER).unwrap().last_loop_at.is_none());
+        assert!(diagnostics.worker(DAEMON_WORKER).unwrap().last_loop_at.is_some());
+        assert!(diagnostics.oldest_unconfirmed_broadcast.value.is_none());
+        
+        // Processing empties the queue, beats the processor and tracks the broadcasts
+        let progress = transfer.process_pending_transactions().unwrap();
+        assert_eq!(progress.txids.len(), 2);
+        
+        let diagnostics = introspector.diagnostics();
+        assert!(diagnostics.pending_transfers.value.is_empty());
+        assert_eq!(diagnostics.worker(TRANSFER_PROCESSOR_WORKER).unwrap().secs_since_last_loop, Some(0));
+        let oldest = diagnostics.oldest_unconfirmed_broadcast.value.clone().unwrap();
+        assert!(progress.txids.iter().any(|(_, txid)| *txid == oldest.txid));
+        assert_eq!(diagnostics.rate_limiter.limit_per_minute, 600_000);
+        assert!(diagnostics.rate_limiter.calls > 0);
+        assert_eq!(diagnostics.rate_limiter.waiting_callers, 0);
+        
+        // Confirmed broadcasts are forgotten
+        set_mock_chain(&transport, 101, 1, Some(&"cd".repeat(32)));
+        assert_eq!(transfer.prune_confirmed_broadcasts().unwrap(), 2);
+        assert!(introspector.diagnostics().oldest_unconfirmed_broadcast.value.is_none());
+        
+        // The mempool monitor reports its loop
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while introspector.diagnostics().worker(MEMPOOL_MONITOR_WORKER).unwrap().last_loop_at.is_none() {
+            assert!(std::time::Instant::now() < deadline, "mempool monitor never looped");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        mempool_monitor.stop().unwrap();
+        
+        // The snapshot survives a JSON round trip
+        let diagnostics = introspector.diagnostics();
+        let json = serde_json::to_value(&diagnostics).unwrap();
+        assert_eq!(serde_json::from_value::<RuntimeDiagnostics>(json).unwrap(), diagnostics);
+    }
+    
+    #[test]
+    fn test_runtime_diagnostics_mark_busy_locks_stale_instead_of_waiting() {
+        use crate::introspection::DiagnosticsSource;
+        
+        // Two calls a second, so processing holds the queue lock while it waits for the limiter
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 120;
+        let (transfer, _transport) = transfer_with_mock_config(config);
+        let transfer = Arc::new(transfer);
+        let introspector = transfer.introspector();
+        
+        transfer.transfer_from_contract_with_priority(RPC_RECIPIENT, &TokenType::Bitcoin, 1_000, TransferPriority::Normal).unwrap();
+        let before = introspector.diagnostics();
+        assert!(!before.pending_transfers.stale);
+        
+        let processing = {
+            let transfer = transfer.clone();
+            std::thread::spawn(move || transfer.process_pending_transactions().unwrap())
+        };
+        
+        // Wait until the processor is inside the rate limiter, holding the queue lock
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while introspector.diagnostics().rate_limiter.waiting_callers == 0 {
+            assert!(std::time::Instant::now() < deadline, "processing never reached the rate limiter");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        
+        // The busy queue yields the earlier reading, marked stale, without waiting
+        let started = std::time::Instant::now();
+        let started_at = chrono::Utc::now();
+        let during = introspector.diagnostics();
+        assert!(started.elapsed() < Duration::from_millis(250));
+        assert!(during.is_stale());
+        assert!(during.pending_transfers.stale);
+        assert_eq!(during.pending_transfers.value, before.pending_transfers.value);
+        assert!(during.pending_transfers.sampled_at.unwrap() < started_at);
+        assert_eq!(during.rate_limiter.waiting_callers, 1);
+        
+        // Once the lock is free the reading is current again
+        let progress = processing.join().unwrap();
+        assert_eq!(progress.txids.len(), 1);
+        
+        let after = introspector.diagnostics();
+        assert!(!after.is_stale());
+        assert!(after.pending_transfers.value.is_empty());
+        assert!(after.rate_limiter.throttled_calls > 0);
+    }
+    
+    #[test]
+    fn test_diagnostics_endpoint_requires_the_owner_api_key() {
+        let (transfer, _transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let server = ApiServer::new()
+            .with_auth(ApiAuth::SharedKey(crate::config::Secret::new("secret-key".to_string())))
+            .with_diagnostics(Arc::new(transfer.introspector()));
+        
+        let mut request = HttpRequest::new("GET", "/diagnostics");
+        assert_eq!(server.handle(&request).status, 401);
+        
+        request.headers.insert("x-api-key".to_string(), "wrong-key".to_string());
+        assert_eq!(server.handle(&request).status, 401);
+        
+        request.headers.insert("x-api-key".to_string(), "secret-key".to_string());
+        let response = server.handle(&request);
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["pending_transfers"]["stale"], false);
+        assert_eq!(body["workers"][0]["worker"], TRANSFER_PROCESSOR_WORKER);
+        
+        // Without a configured key no one is let in, and without a source there is nothing to serve
+        let server = ApiServer::new().with_diagnostics(Arc::new(transfer.introspector()));
+        assert_eq!(server.handle(&request).status, 401);
+        assert_eq!(ApiServer::new().with_auth(ApiAuth::SharedKey(crate::config::Secret::new("secret-key".to_string()))).handle(&request).status, 404);
+        assert_eq!(ApiServer::new().handle(&HttpRequest::new("POST", "/diagnostics")).status, 405);
+    }
+    
+    /// Logger keeping every record it is given, as target, level and message
+    #[derive(Clone, Default)]
+    struct CapturingLogger {
+        records: Arc<std::sync::Mutex<Vec<(String, log::Level, String)>>>,
+    }
+    
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+        
+        fn log(&self, record: &log::Record) {
+            self.records.lock().unwrap().push((record.target().to_string(), record.level(), record.args().to_string()));
+        }
+        
+        fn flush(&self) {}
+    }
+    
+    /// Hand a record to a logger as the `log` macros would once the process logger is installed
+    fn send(logger: &dyn log::Log, level: log::Level, target: &str, message: &str) {
+        logger.log(&log::Record::builder().level(level).target(target).args(format_args!("{}", message)).build());
+    }
+    
+    #[test]
+    fn test_log_levels_gate_records_per_target_at_runtime() {
+        use crate::logging::{parse_level, GatedLogger, LogLevels, CONTRACT, RPC};
+        use log::LevelFilter;
+        
+        let capture = CapturingLogger::default();
+        let levels = Arc::new(LogLevels::from_spec("info,rpc=debug,time_locked_deposit=trace").unwrap());
+        let logger = GatedLogger::new(Box::new(capture.clone()), levels.clone());
+        let messages = || capture.records.lock().unwrap().drain(..).map(|(target, _, message)| format!("{}: {}", target, message)).collect::<Vec<_>>();
+        
+        // Targets start at their configured level, others at the default
+        send(&logger, log::Level::Debug, RPC, "getblockcount");
+        send(&logger, log::Level::Debug, CONTRACT, "deposit 1 checked");
+        send(&logger, log::Level::Info, CONTRACT, "deposit 1 recorded");
+        send(&logger, log::Level::Debug, "ureq", "connection reused");
+        assert_eq!(messages(), vec!["rpc: getblockcount", "contract: deposit 1 recorded"]);
+        assert_eq!(levels.level(RPC), LevelFilter::Debug);
+        assert_eq!(levels.level("time_locked_deposit"), LevelFilter::Info);
+        
+        // Changing a level takes effect on the next record
+        levels.set_level(CONTRACT, LevelFilter::Trace).unwrap();
+        levels.set_level(RPC, LevelFilter::Off).unwrap();
+        send(&logger, log::Level::Trace, CONTRACT, "deposit 2 checked");
+        send(&logger, log::Level::Error, RPC, "node unreachable");
+        assert_eq!(messages(), vec!["contract: deposit 2 checked"]);
+        assert_eq!(levels.to_string(), "contract=trace,rpc=off,lightning=info,ordinals=info,mempool=info,transfer=info,daemon=info");
+        
+        // Unknown targets and levels are refused without changing anything
+        assert!(levels.set_level("database", LevelFilter::Debug).is_err());
+        assert!(parse_level("loud").is_err());
+        assert!(LogLevels::from_spec("rpc=loud").is_err());
+        assert_eq!(levels.level(CONTRACT), LevelFilter::Trace);
+    }
+    
+    #[test]
+    fn test_log_level_endpoint_adjusts_levels_live() {
+        use crate::logging::{GatedLogger, LogLevels, MEMPOOL};
+        use log::LevelFilter;
+        
+        let capture = CapturingLogger::default();
+        let levels = Arc::new(LogLevels::new(LevelFilter::Info));
+        let logger = GatedLogger::new(Box::new(capture.clone()), levels.clone());
+        let (transfer, _transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let server = ApiServer::new()
+            .with_auth(ApiAuth::SharedKey(crate::config::Secret::new("secret-key".to_string())))
+            .with_diagnostics(Arc::new(transfer.introspector().with_log_levels(levels.clone())))
+            .with_log_levels(levels.clone());
+        let request = |method: &str, path: &str, body: &str| {
+            let mut request = HttpRequest::new(method, path);
+            request.headers.insert("x-api-key".to_string(), "secret-key".to_string());
+            request.body = body.as_bytes().to_vec();
+            request
+        };
+        
+        send(&logger, log::Level::Debug, MEMPOOL, "hidden");
+        
+        // Only the owner key can change levels
+        let mut anonymous = request("PUT", "/log-level", r#"{"target":"mempool","level":"debug"}"#);
+        anonymous.headers.clear();
+        assert_eq!(server.handle(&anonymous).status, 401);
+        assert_eq!(levels.level(MEMPOOL), LevelFilter::Info);
+        
+        let response = server.handle(&request("PUT", "/log-level", r#"{"target":"mempool","level":"debug"}"#));
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["levels"][4], serde_json::json!({ "target": "mempool", "level": "debug" }));
+        send(&logger, log::Level::Debug, MEMPOOL, "shown");
+        assert_eq!(capture.records.lock().unwrap().iter().map(|(_, _, message)| message.as_str()).collect::<Vec<_>>(), vec!["shown"]);
+        
+        // The levels in effect are listed and part of the diagnostics
+        let response = server.handle(&request("GET", "/log-level", ""));
+        assert_eq!(response.status, 200);
+        let diagnostics = server.handle(&request("GET", "/diagnostics", ""));
+        let diagnostics: serde_json::Value = serde_json::from_slice(&diagnostics.body).unwrap();
+        assert_eq!(diagnostics["log_levels"], serde_json::from_slice::<serde_json::Value>(&response.body).unwrap()["levels"]);
+        
+        // Bad requests change nothing
+        for body in [r#"{"target":"database","level":"debug"}"#, r#"{"target":"rpc","level":"loud"}"#, r#"{"target":"rpc"}"#, "not json"] {
+            assert_eq!(server.handle(&request("PUT", "/log-level", body)).status, 400, "{}", body);
+        }
+        assert_eq!(levels.levels().iter().filter(|level| level.level != "info").count(), 1);
+        assert_eq!(server.handle(&request("POST", "/log-level", "")).status, 405);
+        assert_eq!(ApiServer::new().with_auth(ApiAuth::SharedKey(crate::config::Secret::new("secret-key".to_string()))).handle(&request("GET", "/log-level", "")).status, 404);
+    }
+    
+    /// Server over a fresh contract and the key store at `path`, with one deposit past its lock
+    fn scoped_server(path: &std::path::Path) -> (ApiServer, Arc<std::sync::Mutex<crate::contract::contract_core::TimeLockedDeposit<MockTokenTransferMock>>>, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        let contract = Arc::new(std::sync::Mutex::new(contract));
+        let auth = ApiAuth::KeyStore(Arc::new(ApiKeyStore::open(path).unwrap()));
+        let handler = crate::server::JsonRpcHandler::new(contract.clone(), None).with_auth(auth.clone());
+        let server = ApiServer::new().with_auth(auth).with_jsonrpc(Arc::new(handler));
+        
+        (server, contract, clock)
+    }
+    
+    /// POST a JSON-RPC call presenting `api_key`
+    fn scoped_call(server: &ApiServer, api_key: Option<&str>, call: serde_json::Value) -> (u16, serde_json::Value) {
+        let mut request = HttpRequest::new("POST", "/jsonrpc");
+        if let Some(api_key) = api_key {
+            request.headers.insert("x-api-key".to_string(), api_key.to_string());
+        }
+        request.body = call.to_string().into_bytes();
+        let response = server.handle(&request);
+        (response.status, serde_json::from_slice(&response.body).unwrap())
+    }
+    
+    #[test]
+    fn test_scoped_api_keys_enforce_capabilities_per_endpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_keys.json");
+        let store = ApiKeyStore::open(&path).unwrap();
+        let (_, read) = store.create(crate::server::auth::parse_capabilities("read").unwrap()).unwrap();
+        let (_, deposit) = store.create(crate::server::auth::parse_capabilities("read,deposit").unwrap()).unwrap();
+        let (_, withdraw) = store.create([Capability::Withdraw].into()).unwrap();
+        let (_, admin) = store.create([Capability::Admin].into()).unwrap();
+        let (server, _contract, _clock) = scoped_server(&path);
+        
+        let get_stats = serde_json::json!({"jsonrpc": "2.0", "method": "vault_getStats", "id": 1});
+        let deposit_call = serde_json::json!({"jsonrpc": "2.0", "method": "vault_deposit", "params": {"depositor": "depositor_address", "token_type": "Bitcoin", "amount": 1000, "lock_days": 30}, "id": 2});
+        let withdraw_call = serde_json::json!({"jsonrpc": "2.0", "method": "vault_withdraw", "params": {"caller": "depositor_address", "deposit_id": 1}, "id": 3});
+        
+        // Every method needs a valid key once scoped keys are configured
+        let (status, body) = scoped_call(&server, None, get_stats.clone());
+        assert_eq!(status, 200);
+        assert_eq!(body["error"]["data"]["code"], "UNAUTHORIZED");
+        let (_, body) = scoped_call(&server, Some("tlv_0000000000000000_bogus"), get_stats.clone());
+        assert_eq!(body["error"]["data"]["code"], "UNAUTHORIZED");
+        
+        // A read key reads but is refused a deposit with 403 and the missing capability
+        let (status, body) = scoped_call(&server, Some(read.expose_secret()), get_stats.clone());
+        assert_eq!(status, 200);
+        assert!(body.get("error").is_none());
+        let (status, body) = scoped_call(&server, Some(read.expose_secret()), deposit_call.clone());
+        assert_eq!(status, 403);
+        assert_eq!(body["error"]["data"]["code"], "MISSING_CAPABILITY");
+        assert_eq!(body["error"]["data"]["capability"], "deposit");
+        
+        // Each key does what its capabilities allow and nothing more
+        let (status, body) = scoped_call(&server, Some(deposit.expose_secret()), deposit_call.clone());
+        assert_eq!(status, 200);
+        assert_eq!(body["result"]["Deposited"]["deposit_id"], 2);
+        let (status, body) = scoped_call(&server, Some(deposit.expose_secret()), withdraw_call.clone());
+        assert_eq!(status, 403);
+        assert_eq!(body["error"]["data"]["capability"], "withdraw");
+        let (status, body) = scoped_call(&server, Some(withdraw.expose_secret()), get_stats.clone());
+        assert_eq!(status, 403);
+        assert_eq!(body["error"]["data"]["capability"], "read_only");
+        let (status, body) = scoped_call(&server, Some(withdraw.expose_secret()), withdraw_call);
+        assert_eq!(status, 200);
+        assert_eq!(body["result"]["Withdrawn"]["deposit_id"], 1);
+        let (status, body) = scoped_call(&server, Some(admin.expose_secret()), deposit_call);
+        assert_eq!(status, 200);
+        assert!(body.get("result").is_some());
+        
+        // Owner methods need admin, and batches answer 200 with per-call errors
+        let fees = serde_json::json!({"jsonrpc": "2.0", "method": "vault_withdrawFees", "params": {"token_type": "Bitcoin", "nonce": 0}, "id": 4});
+        let (status, body) = scoped_call(&server, Some(deposit.expose_secret()), fees.clone());
+        assert_eq!(status, 403);
+        assert_eq!(body["error"]["data"]["capability"], "admin");
+        let (status, body) = scoped_call(&server, Some(read.expose_secret()), serde_json::json!([get_stats, fees]));
+        assert_eq!(status, 200);
+        assert!(body[0].get("result").is_some());
+        assert_eq!(body[1]["error"]["data"]["code"], "MISSING_CAPABILITY");
+        
+        // REST endpoints need read, diagnostics admin, and health nothing
+        let mut request = HttpRequest::new("GET", "/stats/reconciliation");
+        assert_eq!(server.handle(&request).status, 401);
+        request.headers.insert("x-api-key".to_string(), withdraw.expose_secret().clone());
+        let response = server.handle(&request);
+        assert_eq!(response.status, 403);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["code"], "MISSING_CAPABILITY");
+        request.headers.insert("x-api-key".to_string(), read.expose_secret().clone());
+        assert_ne!(server.handle(&request).status, 403);
+        
+        let mut request = HttpRequest::new("GET", "/diagnostics");
+        request.headers.insert("x-api-key".to_string(), read.expose_secret().clone());
+        assert_eq!(server.handle(&request).status, 403);
+        request.headers.insert("x-api-key".to_string(), admin.expose_secret().clone());
+        assert_eq!(server.handle(&request).status, 404);
+        assert_ne!(server.handle(&HttpRequest::new("GET", "/health")).status, 401);
+    }
+    
+    #[test]
+    fn test_revoked_api_key_is_refused_without_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_keys.json");
+        let (server, _contract, _clock) = scoped_server(&path);
+        let get_stats = serde_json::json!({"jsonrpc": "2.0", "method": "vault_getStats", "id": 1});
+        
+        // Keys created and revoked by another process, as `vault apikey` does, apply at once
+        let cli = ApiKeyStore::open(&path).unwrap();
+        let (record, key) = cli.create([Capability::ReadOnly].into()).unwrap();
+        let (_, other) = cli.create([Capability::ReadOnly].into()).unwrap();
+        let (_, body) = scoped_call(&server, Some(key.expose_secret()), get_stats.clone());
+        assert!(body.get("result").is_some());
+        
+        assert!(ApiKeyStore::open(&path).unwrap().revoke(&record.id).unwrap());
+        let (_, body) = scoped_call(&server, Some(key.expose_secret()), get_stats.clone());
+        assert_eq!(body["error"]["data"]["code"], "UNAUTHORIZED");
+        let (_, body) = scoped_call(&server, Some(other.expose_secret()), get_stats);
+        assert!(body.get("result").is_some());
+        
+        // The revocation is kept on record and cannot be repeated
+        let records = cli.list().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].is_active());
+        assert!(records[1].is_active());
+        assert!(!cli.revoke(&record.id).unwrap());
+        assert!(!cli.revoke("unknown").unwrap());
+    }
+    
+    #[test]
+    fn test_api_keys_are_stored_as_salted_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_keys.json");
+        let store = ApiKeyStore::open(&path).unwrap();
+        let (first, first_key) = store.create([Capability::Deposit, Capability::ReadOnly].into()).unwrap();
+        let (second, second_key) = store.create([Capability::Deposit, Capability::ReadOnly].into()).unwrap();
+        
+        // The file holds neither key nor secret, and equal capabilities still hash apart
+        let contents = std::fs::read_to_string(&path).unwrap();
+        for key in [&first_key, &second_key] {
+            let secret = key.expose_secret().rsplit('_').next().unwrap();
+            assert!(!contents.contains(key.expose_secret().as_str()));
+            assert!(!contents.contains(secret));
+        }
+        let file: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        assert_ne!(file["keys"][0]["salt"], file["keys"][1]["salt"]);
+        assert_ne!(file["keys"][0]["hash"], file["keys"][1]["hash"]);
+        assert_eq!(file["keys"][0]["capabilities"], serde_json::json!(["read_only", "deposit"]));
+        
+        // Keys resolve to their own records; a known ID with a wrong secret does not
+        let identity = store.authenticate(first_key.expose_secret()).unwrap();
+        assert_eq!(identity.key_id, first.id);
+        assert!(identity.allows(Capability::ReadOnly));
+        assert!(!identity.allows(Capability::Withdraw));
+        assert_eq!(store.authenticate(second_key.expose_secret()).unwrap().key_id, second.id);
+        let forged = format!("tlv_{}_{}", first.id, "0".repeat(64));
+        assert!(store.authenticate(&forged).is_none());
+        assert!(store.authenticate(&first_key.expose_secret()[..20]).is_none());
+        
+        // The debug form of a key does not reveal it either
+        assert!(!format!("{:?}", first_key).contains(first_key.expose_secret().as_str()));
+        assert!(crate::server::auth::parse_capabilities("read,root").is_err());
+        assert!(crate::server::auth::parse_capabilities("").is_err());
+    }
+    
+    #[test]
+    fn test_authorization_log_records_the_scoped_key_id() {
+        use crate::contract::authorization::{AuthorizationQuery, OperationChannel};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api_keys.json");
+        let (server, contract, clock) = scoped_server(&path);
+        let (record, key) = ApiKeyStore::open(&path).unwrap().create([Capability::Withdraw].into()).unwrap();
+        
+        let (status, _) = scoped_call(&server, Some(key.expose_secret()), serde_json::json!({"jsonrpc": "2.0", "method": "vault_withdraw", "params": {"caller": "depositor_address", "deposit_id": 1}, "id": 1}));
+        assert_eq!(status, 200);
+        
+        let records = contract.lock().unwrap().get_authorization_log(
+            &AuthorizationQuery::Deposit(1),
+            clock.now() - chrono::Duration::days(1),
+            clock.now() + chrono::Duration::seconds(1),
+        );
+        assert_eq!(records.len(), 1);
+        match &records[0].context.channel {
+            OperationChannel::Http { api_key_id, .. } => assert_eq!(api_key_id.as_deref(), Some(record.id.as_str())),
+            other => panic!("unexpected channel {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn test_deposit_utxo_audit_classifies_outputs_in_resumable_batches() {
+        use crate::contract::utxo_audit::{UtxoAuditCursor, UtxoAuditStatus};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        let reference = |n: u8| format!("{}:{}", format!("{:02x}", n).repeat(32), n);
+        
+        // Five referenced deposits and one without a reference, which is skipped
+        for n in 1..=5u8 {
+            contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, Some(reference(n).parse().unwrap())).unwrap();
+        }
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        transport.respond_tx_out(&reference(1), Ok(Some((100_000, 12))));
+        transport.respond_tx_out(&reference(2), Ok(None));
+        transport.respond_tx_out(&reference(3), Err("Work queue depth exceeded".to_string()));
+        transport.respond_tx_out(&reference(4), Ok(Some((90_000, 3))));
+        transport.respond_tx_out(&reference(5), Ok(Some((100_500, 1))));
+        
+        // The first batch stops after two deposits and says where to go on
+        let first = contract.verify_deposit_utxos(2, None).unwrap();
+        assert_eq!(first.entries.iter().map(|entry| entry.deposit_id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(first.entries[0].status, UtxoAuditStatus::Unspent { onchain_amount: 100_000, confirmations: 12 });
+        assert_eq!(first.entries[0].amount_delta(), Some(0));
+        assert_eq!(first.entries[1].status, UtxoAuditStatus::Spent);
+        assert_eq!(first.entries[1].amount_delta(), None);
+        assert_eq!(first.next_cursor, Some(UtxoAuditCursor { after_deposit_id: 2 }));
+        assert!(!first.is_complete());
+        assert_eq!(transport.calls("gettxout").len(), 2);
+        
+        // A saved cursor resumes after the last deposit checked, even in a new process
+        let cursor: UtxoAuditCursor = serde_json::from_str(&serde_json::to_string(&first.next_cursor.unwrap()).unwrap()).unwrap();
+        let second = contract.verify_deposit_utxos_from(Some(cursor), 2, None).unwrap();
+        assert_eq!(second.entries.iter().map(|entry| entry.deposit_id).collect::<Vec<_>>(), vec![3, 4]);
+        assert!(matches!(&second.entries[0].status, UtxoAuditStatus::Unknown { reason } if reason.contains("Work queue depth exceeded")));
+        assert_eq!(second.unknown().count(), 1);
+        assert_eq!(second.entries[1].amount_delta(), Some(-10_000));
+        assert_eq!(second.underfunded().map(|entry| entry.deposit_id).collect::<Vec<_>>(), vec![4]);
+        
+        let last = contract.verify_deposit_utxos_from(second.next_cursor, 2, None).unwrap();
+        assert_eq!(last.entries.iter().map(|entry| entry.deposit_id).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(last.entries[0].amount_delta(), Some(500));
+        assert!(last.is_complete());
+        assert_eq!(transport.calls("gettxout").len(), 5);
+        assert_eq!(transport.calls("gettxout")[0], vec![serde_json::json!("01".repeat(32)), serde_json::json!(1), serde_json::json!(true)]);
+        
+        // The report serializes flat, with the classification as `status`
+        let json = serde_json::to_value(&first).unwrap();
+        assert_eq!(json["entries"][1]["status"], "spent");
+        assert_eq!(json["entries"][0]["onchain_amount"], 100_000);
+        
+        // Spent findings suspend their deposits once, and suspended deposits leave the audit
+        let events = contract.suspend_spent_deposits(&first).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], Event::DepositSuspended { deposit_id: 2, txid, .. } if *txid == "02".repeat(32)));
+        assert_eq!(contract.deposit_registry[&2].status, DepositStatus::Suspended);
+        assert!(contract.suspend_spent_deposits(&first).unwrap().is_empty());
+        
+        let full = contract.verify_deposit_utxos(100, None).unwrap();
+        assert_eq!(full.entries.iter().map(|entry| entry.deposit_id).collect::<Vec<_>>(), vec![1, 3, 4, 5]);
+        assert!(full.is_complete());
+        
+        // A cancelled audit stops before its next lookup
+        let progress = crate::progress::Progress::new();
+        progress.cancel();
+        let lookups = transport.calls("gettxout").len();
+        assert!(matches!(
+            contract.verify_deposit_utxos(100, Some(&progress)),
+            Err(ContractError::Cancelled { ref stage, processed: 0 }) if stage == "utxo_audit",
+        ));
+        assert_eq!(transport.calls("gettxout").len(), lookups);
+    }
+    
+    /// Writer cancelling an export once it has written `lines` whole lines
+    struct CancellingWriter {
+        written: Vec<u8>,
+        progress: Arc<crate::progress::Progress>,
+        lines: usize,
+    }
+    
+    impl std::io::Write for CancellingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            if self.written.iter().filter(|byte| **byte == b'\n').count() >= self.lines {
+                self.progress.cancel();
+            }
+            Ok(buf.len())
+        }
+        
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    
+    #[test]
+    fn test_deposit_export_writes_csv_and_stops_on_cancel() {
+        use crate::contract::export::CSV_HEADER;
+        use crate::contract::query::DepositFilter;
+        use crate::progress::Progress;
+        
+        let clock = Arc::new(MockClock::new(chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap()));
+        let mut contract = contract_with_clock(clock);
+        for _ in 0..4 {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).unwrap();
+        }
+        contract.deposit("odd,\"name\"".to_string(), TokenType::Bitcoin, 2_000, 30, None).unwrap();
+        
+        // Rows follow the header in ID order, with awkward fields quoted
+        let mut csv = Vec::new();
+        let progress = Progress::new();
+        assert_eq!(contract.export_deposits_csv(DepositFilter::default(), &mut csv, Some(&progress)).unwrap(), 5);
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "1,depositor_address,Bitcoin,1000,active,2023-11-14T22:13:20Z,2023-12-14T22:13:20Z");
+        assert!(lines[5].starts_with("5,\"odd,\"\"name\"\"\",Bitcoin,2000,"), "{}", lines[5]);
+        assert_eq!((progress.processed(), progress.total()), (5, 5));
+        
+        let mut filtered = Vec::new();
+        let filter = DepositFilter::default().with_address("depositor_address");
+        assert_eq!(contract.export_deposits_csv(filter, &mut filtered, None).unwrap(), 4);
+        
+        // Cancelled after two rows, the export stops with only the header and whole rows written
+        let progress = Arc::new(Progress::new());
+        let mut writer = CancellingWriter { written: Vec::new(), progress: progress.clone(), lines: 3 };
+        match contract.export_deposits_csv(DepositFilter::default(), &mut writer, Some(&progress)) {
+            Err(ContractError::Cancelled { stage, processed }) => assert_eq!((stage.as_str(), processed), ("export", 2)),
+            other => panic!("expected Cancelled, got {:?}", other),
+        }
+        let partial = String::from_utf8(writer.written).unwrap();
+        assert!(partial.ends_with('\n'));
+        assert_eq!(partial.lines().collect::<Vec<_>>(), lines[..3].to_vec());
+        assert_eq!(progress.snapshot().to_string(), format!("export [{}{}]  40% 2/5", "#".repeat(12), "-".repeat(18)));
+        
+        // A writer failing surfaces as an export error
+        struct FailingWriter;
+        impl std::io::Write for FailingWriter {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe"))
+            }
+            
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        assert!(matches!(
+            contract.export_deposits_csv(DepositFilter::default(), FailingWriter, None),
+            Err(ContractError::ExportFailed(reason)) if reason.contains("broken pipe"),
+        ));
+    }
+    
+    /// Audit source running until cancelled
+    #[derive(Debug)]
+    struct EndlessAudit;
+    
+    impl crate::contract::utxo_audit::UtxoAuditSource for EndlessAudit {
+        fn audit_all(&self, _batch_size: usize, progress: &crate::progress::Progress) -> Result<crate::contract::utxo_audit::UtxoAuditSummary, ContractError> {
+            progress.start_stage("utxo_audit", 0);
+            loop {
+                progress.check_cancelled()?;
+                progress.advance(1);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        }
+    }
+    
+    #[test]
+    fn test_jobs_endpoint_reports_and_cancels_background_audits() {
+        use crate::contract::utxo_audit::UtxoAuditor;
+        use crate::progress::{JobRegistry, JobState};
+        
+        let wait_until_finished = |jobs: &JobRegistry, id: u64| {
+            for _ in 0..400 {
+                match jobs.status(id) {
+                    Some(status) if status.state != JobState::Running => return status,
+                    _ => std::thread::sleep(std::time::Duration::from_millis(5)),
+                }
+            }
+            panic!("job {} did not finish", id);
+        };
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock);
+        let reference = format!("{}:0", "01".repeat(32));
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, Some(reference.parse().unwrap())).unwrap();
+        transport.respond_tx_out(&reference, Ok(None));
+        
+        let auth = ApiAuth::SharedKey(crate::config::Secret::new("secret-key".to_string()));
+        let jobs = Arc::new(JobRegistry::new());
+        let server = ApiServer::new()
+            .with_auth(auth.clone())
+            .with_jobs(jobs.clone(), Arc::new(UtxoAuditor::new(Arc::new(std::sync::Mutex::new(contract)))));
+        let request = |method: &str, path: &str| {
+            let mut request = HttpRequest::new(method, path);
+            request.headers.insert("x-api-key".to_string(), "secret-key".to_string());
+            request
+        };
+        
+        // Starting a job needs the owner's key
+        assert_eq!(server.handle(&HttpRequest::new("POST", "/jobs/utxo-audit")).status, 401);
+        let response = server.handle(&request("POST", "/jobs/utxo-audit"));
+        assert_eq!(response.status, 202);
+        let id = serde_json::from_slice::<serde_json::Value>(&response.body).unwrap()["id"].as_u64().unwrap();
+        
+        // The finished job carries its summary
+        assert_eq!(wait_until_finished(&jobs, id).state, JobState::Completed);
+        let response = server.handle(&request("GET", &format!("/jobs/{}", id)));
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["state"], "completed");
+        assert_eq!(body["result"]["spent"], serde_json::json!([1]));
+        assert_eq!(body["progress"]["processed"], 1);
+        assert_eq!(server.handle(&request("POST", &format!("/jobs/{}/cancel", id))).status, 409);
+        
+        // A running job is cancelled on request and keeps the structured error
+        let server = ApiServer::new().with_auth(auth).with_jobs(jobs.clone(), Arc::new(EndlessAudit));
+        let response = server.handle(&request("POST", "/jobs/utxo-audit"));
+        let id = serde_json::from_slice::<serde_json::Value>(&response.body).unwrap()["id"].as_u64().unwrap();
+        assert_eq!(jobs.status(id).unwrap().state, JobState::Running);
+        assert_eq!(server.handle(&HttpRequest::new("POST", &format!("/jobs/{}/cancel", id))).status, 401);
+        assert_eq!(server.handle(&request("POST", &format!("/jobs/{}/cancel", id))).status, 202);
+        
+        let status = wait_until_finished(&jobs, id);
+        assert_eq!(status.state, JobState::Cancelled);
+        assert_eq!(status.error.unwrap()["code"], "CANCELLED");
+        
+        let body: serde_json::Value = serde_json::from_slice(&server.handle(&request("GET", "/jobs")).body).unwrap();
+        assert_eq!(body["jobs"].as_array().unwrap().len(), 2);
+        assert_eq!(server.handle(&request("GET", "/jobs/99")).status, 404);
+        assert_eq!(server.handle(&request("POST", "/jobs/99/cancel")).status, 404);
+        assert_eq!(server.handle(&request("DELETE", &format!("/jobs/{}", id))).status, 405);
+    }
+    
+    /// Deposits of several tokens and states, pinned by the deposit listing golden files
+    fn listing_sample_deposits() -> Vec<crate::models::Deposit> {
+        let at = |secs: i64, nanos: u32| chrono::DateTime::from_timestamp(secs, nanos).unwrap();
+        let sample = |deposit_id: u64, depositor: &str, token_type: TokenType, amount: u64, status: DepositStatus| {
+            let mut deposit = canonical_sample_deposit();
+            deposit.deposit_id = deposit_id;
+            deposit.depositor_address = depositor.to_string();
+            deposit.deposited_token_type = token_type;
+            deposit.deposited_amount = amount;
+            deposit.status = status;
+            deposit.deposit_timestamp = at(1_735_689_600 + deposit_id as i64 * 3_600, 999_999_999);
+            deposit
+        };
+        
+        vec![
+            sample(1, "tb1qalice", TokenType::Bitcoin, 150_000, DepositStatus::Active),
+            sample(2, "tb1qbob", TokenType::Bitcoin, 2_100_000_000_000_000, DepositStatus::Withdrawn),
+            sample(10, "tb1qalice", TokenType::Lightning, 1, DepositStatus::Suspended),
+            sample(11, "tb1qcarol", TokenType::Rune("RUNE_EXAMPLE".to_string()), 1_250_000_000, DepositStatus::EmergencyWithdrawn),
+            sample(123, "tb1qdave", TokenType::Ordinal("ab".repeat(32)), 1, DepositStatus::WithdrawalPending),
+        ]
+    }
+    
+    #[test]
+    fn test_deposit_listing_matches_golden_files() {
+        use crate::display::{deposit_table, TableStyle};
+        
+        let deposits = listing_sample_deposits();
+        let table = deposit_table(&deposits);
+        assert_eq!(table.len(), 5);
+        
+        // Any change to the listing format breaks scripts reading it
+        assert_eq!(table.render(TableStyle::Aligned), include_str!("fixtures/deposit_listing.txt"));
+        assert_eq!(table.render(TableStyle::Plain), include_str!("fixtures/deposit_listing_plain.txt"));
+    }
+    
+    #[test]
+    fn test_display_formats_amounts_and_timestamps_without_floats() {
+        use crate::display::{format_amount, format_amount_with, format_optional_timestamp, format_timestamp};
+        use crate::models::{AmountConstraints, MAX_AMOUNT};
+        
+        assert_eq!(format_amount(&TokenType::Bitcoin, 0), "0.00000000 BTC");
+        assert_eq!(format_amount(&TokenType::Bitcoin, 1), "0.00000001 BTC");
+        assert_eq!(format_amount(&TokenType::Bitcoin, 150_000), "0.00150000 BTC");
+        assert_eq!(format_amount(&TokenType::Bitcoin, 2_099_999_997_690_000), "20999999.97690000 BTC");
+        assert_eq!(format_amount(&TokenType::Bitcoin, MAX_AMOUNT), "92233720368.54775807 BTC");
+        assert_eq!(format_amount(&TokenType::Lightning, 100_000_000), "1.00000000 LNBTC");
+        assert_eq!(format_amount(&TokenType::Ordinal("ab".repeat(32)), 1), "1 inscription");
+        
+        // Amounts that fit a rune's divisibility print only its decimal places
+        let rune = TokenType::Rune("RUNE_EXAMPLE".to_string());
+        let two_places = AmountConstraints { divisibility: Some(2), ..AmountConstraints::default() };
+        assert_eq!(format_amount_with(&rune, 1_250_000_000, &two_places), "12.50 RUNE_EXAMPLE");
+        assert_eq!(format_amount_with(&rune, 1_250_000_001, &two_places), "12.50000001 RUNE_EXAMPLE");
+        assert_eq!(format_amount_with(&rune, 500_000_000, &AmountConstraints { divisibility: Some(0), ..AmountConstraints::default() }), "5 RUNE_EXAMPLE");
+        assert_eq!(format_amount_with(&rune, 1_250_000_000, &AmountConstraints::default()), "12.50000000 RUNE_EXAMPLE");
+        
+        // Timestamps are UTC with whole seconds, whatever their precision or offset
+        let timestamp = chrono::DateTime::from_timestamp(1_709_294_400, 987_654_321).unwrap();
+        assert_eq!(format_timestamp(timestamp), "2024-03-01T12:00:00Z");
+        let offset = timestamp.with_timezone(&chrono::FixedOffset::east_opt(5 * 3_600).unwrap());
+        assert_eq!(format_timestamp(offset.with_timezone(&chrono::Utc)), "2024-03-01T12:00:00Z");
+        assert_eq!(format_optional_timestamp(None), "-");
+    }
+    
+    #[test]
+    fn test_table_aligns_columns_and_keeps_plain_cells_on_one_line() {
+        use crate::display::{Table, TableStyle};
+        
+        let mut table = Table::new(&["NAME", "COUNT", "NOTE"]).align_right(1);
+        table.push_row(vec!["a".to_string(), "7".to_string(), "tab\there".to_string()]);
+        table.push_row(vec!["longer".to_string(), "1234".to_string()]);
+        
+        assert_eq!(table.render(TableStyle::Aligned), "NAME    COUNT  NOTE\na           7  tab\there\nlonger   1234\n");
+        assert_eq!(table.render(TableStyle::Plain), "NAME\tCOUNT\tNOTE\na\t7\ttab here\nlonger\t1234\t\n");
+        assert_eq!(Table::new(&["ID"]).render(TableStyle::Plain), "ID\n");
+        assert!(Table::new(&["ID"]).is_empty());
+    }
+    
+    #[test]
+    fn test_void_pending_deposit_and_restore_from_late_funding() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        contract.deposit_limits.max_deposits_per_user = Some(2);
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Flat(10)), None).unwrap();
+        
+        // Funded deposits cannot be voided
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 1, None).unwrap();
+        assert!(matches!(
+            contract.void_pending_deposit("owner_address".to_string(), 1, "wrong amount".to_string(), None),
+            Err(ContractError::DepositNotPending)
+        ));
+        
+        // Only the owner voids, and the deposit gives back its slot, total and output
+        let funds = crate::bitcoin::incoming::IncomingFunds {
+            txid: "cd".repeat(32),
+            vout: 1,
+            amount: 20_000,
+            sender_addresses: vec!["depositor_address".to_string()],
+            confirmations: 1,
+        };
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 20_000, 1, Some(funds.outpoint().parse().unwrap()), None).unwrap();
+        assert!(matches!(
+            contract.void_pending_deposit("depositor_address".to_string(), 2, "wrong amount".to_string(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        let event = contract.void_pending_deposit("owner_address".to_string(), 2, "wrong amount".to_string(), None).unwrap();
+        assert!(matches!(&event, Event::DepositVoided { deposit_id: 2, amount: 19_990, reason, transfer_cancelled: false, .. } if reason == "wrong amount"));
+        assert_eq!(contract.deposit_registry[&2].status, DepositStatus::Voided);
+        assert_eq!(contract.deposit_registry[&2].deposited_amount, 19_990);
+        assert_eq!(contract.get_voided_deposit(2).unwrap().reason, "wrong amount");
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 990);
+        assert_eq!(contract.utxo_reference_owner(&funds.outpoint()), None);
+        assert!(contract.check_accounting_invariants().is_ok());
+        assert!(matches!(contract.withdraw("depositor_address".to_string(), 2), Err(ContractError::DepositVoided)));
+        assert!(matches!(
+            contract.void_pending_deposit("owner_address".to_string(), 2, "again".to_string(), None),
+            Err(ContractError::DepositNotPending)
+        ));
+        
+        // The record survives a restart
+        let state = contract.export_state();
+        assert_eq!(state.voided_deposits.len(), 1);
+        let restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert_eq!(restored.get_voided_deposit(2), contract.get_voided_deposit(2));
+        assert_eq!(restored.utxo_reference_owner(&funds.outpoint()), None);
+        assert!(restored.check_accounting_invariants().is_ok());
+        
+        // Nothing can be restored until the funding arrives as unattributed funds
+        assert!(matches!(
+            contract.restore_voided_deposit("owner_address".to_string(), 2, None),
+            Err(ContractError::UnattributedFundsNotFound)
+        ));
+        assert!(matches!(contract.record_incoming_funds(&funds), Some(Event::UnattributedFundsDetected { amount: 20_000, .. })));
+        
+        // The restored deposit takes back the output and pays its fee
+        let fees_before = contract.fee_config.collected_fees[&TokenType::Bitcoin];
+        let event = contract.restore_voided_deposit("owner_address".to_string(), 2, None).unwrap();
+        assert!(matches!(&event, Event::DepositRestored { deposit_id: 2, outpoint, .. } if *outpoint == funds.outpoint()));
+        assert_eq!(contract.deposit_registry[&2].status, DepositStatus::Active);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], fees_before + 10);
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 20_980);
+        assert_eq!(contract.utxo_reference_owner(&funds.outpoint()), Some(2));
+        assert!(contract.list_unattributed_funds().is_empty());
+        assert!(contract.get_voided_deposit(2).is_none());
+        assert!(contract.check_accounting_invariants().is_ok());
+        assert!(matches!(
+            contract.restore_voided_deposit("owner_address".to_string(), 2, None),
+            Err(ContractError::DepositNotVoided)
+        ));
+        
+        assert_eq!(
+            sink.names.lock().unwrap().as_slice(),
+            ["DepositFeeUpdated", "Deposited", "DepositPending", "DepositVoided", "UnattributedFundsDetected", "Deposited", "DepositRestored"]
+        );
+    }
+    
+    #[test]
+    fn test_restore_voided_deposit_keeps_funds_listed_when_refused() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit_limits.max_deposits_per_user = Some(1);
+        
+        let funds = crate::bitcoin::incoming::IncomingFunds {
+            txid: "cd".repeat(32),
+            vout: 0,
+            amount: 20_000,
+            sender_addresses: vec!["depositor_address".to_string()],
+            confirmations: 1,
+        };
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 20_000, 1, Some(funds.outpoint().parse().unwrap()), None).unwrap();
+        contract.void_pending_deposit("owner_address".to_string(), 1, "duplicate".to_string(), None).unwrap();
+        contract.record_incoming_funds(&funds).unwrap();
+        
+        // The freed slot went to another deposit, so the voided one no longer fits
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 1, None).unwrap();
+        assert!(matches!(
+            contract.restore_voided_deposit("owner_address".to_string(), 1, None),
+            Err(ContractError::UserDepositLimitReached { .. })
+        ));
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Voided);
+        assert_eq!(contract.list_unattributed_funds()[0].outpoint, funds.outpoint());
+        assert!(contract.check_accounting_invariants().is_ok());
+    }
+    
+    #[test]
+    fn test_void_pending_deposit_cancels_queued_funding() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock);
+        let owner = "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string();
+        
+        // A queued funding transfer is dropped with the deposit
+        contract.deposit_pending(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 1, None, None).unwrap();
+        contract.token_transfer.transfer_to_contract_for_deposit(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, 1).unwrap();
+        contract.token_transfer.transfer_from_contract_batched(RPC_RECIPIENT, &TokenType::Bitcoin, 5_000, 1).unwrap();
+        assert_eq!(contract.token_transfer.pending_queue_depth(), 2);
+        let event = contract.void_pending_deposit(owner.clone(), 1, "created twice".to_string(), None).unwrap();
+        assert!(matches!(event, Event::DepositVoided { deposit_id: 1, transfer_cancelled: true, .. }));
+        
+        // Payouts that share the deposit ID are left alone
+        assert_eq!(contract.token_transfer.pending_queue_depth(), 1);
+        assert_eq!(contract.token_transfer.queued_payout_references(), vec![1]);
+        assert!(transport.calls("listtransactions").is_empty());
+        
+        // Without a queued transfer the node wallet is asked whether it was sent
+        transport.respond("listtransactions", serde_json::json!([]));
+        contract.deposit_pending(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 1, None, None).unwrap();
+        let event = contract.void_pending_deposit(owner.clone(), 2, "created twice".to_string(), None).unwrap();
+        assert!(matches!(event, Event::DepositVoided { deposit_id: 2, transfer_cancelled: false, .. }));
+        
+        // A broadcast funding transfer cannot be stopped, so the deposit stays pending
+        contract.deposit_pending(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 10_000, 1, None, None).unwrap();
+        set_wallet_payout(&transport, &"ab".repeat(32), "vault:deposit:deposit-3");
+        assert!(matches!(
+            contract.void_pending_deposit(owner, 3, "created twice".to_string(), None),
+            Err(ContractError::TransferFailed { stage: TransferStage::Deposit, .. })
+        ));
+        assert_eq!(contract.deposit_registry[&3].status, DepositStatus::Pending);
+        assert!(contract.get_voided_deposit(3).is_none());
+    }
+    
+    #[test]
+    fn test_binary_snapshot_is_smaller_and_loads_faster_than_json() {
+        use crate::store::{decode_snapshot, encode_snapshot, SnapshotCodec};
+        
+        let state = state_with_deposits(10_000);
+        let json = encode_snapshot(&state, SnapshotCodec::Json).unwrap();
+        let binary = encode_snapshot(&state, SnapshotCodec::Binary).unwrap();
+        assert_eq!(SnapshotCodec::detect(&json), SnapshotCodec::Json);
+        assert_eq!(SnapshotCodec::detect(&binary), SnapshotCodec::Binary);
+        
+        // Materially smaller, a quarter at least
+        assert!(binary.len() * 4 < json.len() * 3, "json {} bytes, binary {} bytes", json.len(), binary.len());
+        
+        // Both codecs load the same state, with the same canonical hash
+        let from_json = decode_snapshot(&json).unwrap();
+        let from_binary = decode_snapshot(&binary).unwrap();
+        assert_eq!(serde_json::to_value(&from_binary).unwrap(), serde_json::to_value(&state).unwrap());
+        assert_eq!(serde_json::to_value(&from_json).unwrap(), serde_json::to_value(&from_binary).unwrap());
+        assert_eq!(from_json.canonical_hash(), from_binary.canonical_hash());
+        assert_eq!(from_binary.canonical_hash(), state.canonical_hash());
+        
+        // Best of three, so a slow run of either does not decide
+        let fastest = |bytes: &[u8]| {
+            (0..3)
+                .map(|_| {
+                    let start = std::time::Instant::now();
+                    decode_snapshot(bytes).unwrap();
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let (json_time, binary_time) = (fastest(&json), fastest(&binary));
+        assert!(binary_time < json_time, "json {:?}, binary {:?}", json_time, binary_time);
+    }
+    
+    #[test]
+    fn test_snapshot_store_writes_configured_codec_and_loads_either() {
+        use crate::store::{codec, decode_snapshot, JsonSnapshotStore, SnapshotCodec, StateStore};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.snapshot");
+        let state = state_with_deposits(3);
+        
+        // Written with the binary codec, read back by a store configured for JSON
+        JsonSnapshotStore::new(&path).with_codec(SnapshotCodec::Binary).save_state(&state).unwrap();
+        let raw = std::fs::read(&path).unwrap();
+        assert!(raw.starts_with(codec::MAGIC));
+        assert_eq!(raw[codec::MAGIC.len()], codec::CODEC_VERSION);
+        let loaded = JsonSnapshotStore::new(&path).load_state().unwrap().unwrap();
+        assert_eq!(serde_json::to_value(&loaded).unwrap(), serde_json::to_value(&state).unwrap());
+        
+        // And converted back to JSON
+        JsonSnapshotStore::new(&path).save_state(&loaded).unwrap();
+        assert!(serde_json::from_slice::<serde_json::Value>(&std::fs::read(&path).unwrap()).is_ok());
+        
+        // A binary snapshot of an older schema goes through the migrations
+        let old: serde_json::Value = serde_json::from_str(include_str!("fixtures/state_v1_0_0_early.json")).unwrap();
+        let mut old_binary = codec::MAGIC.to_vec();
+        old_binary.push(codec::CODEC_VERSION);
+        ciborium::ser::into_writer(&old, &mut old_binary).unwrap();
+        let migrated = decode_snapshot(&old_binary).unwrap();
+        assert_eq!(migrated.state_version.to_string(), "1.2.0");
+        assert_eq!(migrated.deposits[0].status, DepositStatus::Withdrawn);
+        
+        // Unknown codec versions and truncated headers are refused
+        let mut newer = raw.clone();
+        newer[codec::MAGIC.len()] = codec::CODEC_VERSION + 1;
+        assert!(decode_snapshot(&newer).unwrap_err().contains("codec version"));
+        assert!(decode_snapshot(codec::MAGIC).is_err());
+        assert_eq!("binary".parse::<SnapshotCodec>(), Ok(SnapshotCodec::Binary));
+        assert!("bincode".parse::<SnapshotCodec>().is_err());
+    }
+    
+    #[test]
+    fn test_events_anchor_to_cached_chain_tip() {
+        use crate::bitcoin::anchor::{verify_anchor, AnchorVerification};
+        
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        let transfer = BitcoinTestnetTransfer::with_rpc_client(config, rpc_client.clone());
+        let watcher = transfer.reorg_watcher();
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer).unwrap();
+        contract.set_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        
+        // Nothing to anchor to before the tip was fetched
+        let unanchored = contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        assert!(unanchored.chain_anchor().is_none());
+        assert!(matches!(unanchored, Event::Deposited { block_number: None, .. }));
+        assert!(serde_json::to_value(&unanchored).unwrap().get("Deposited").unwrap().get("chain_anchor").is_none());
+        assert_eq!(verify_anchor(&unanchored, &rpc_client).unwrap(), AnchorVerification::Unanchored);
+        
+        // The watcher's poll caches the tip, and events reuse it without calling the node
+        set_mock_chain(&transport, 100, 3, None);
+        watcher.poll().unwrap();
+        let tip_lookups = transport.calls("getblockchaininfo").len();
+        let deposited = contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 1000, 30, None).unwrap();
+        let withdrawn = contract.emergency_withdraw(RPC_RECIPIENT.to_string(), 2).unwrap();
+        assert_eq!(transport.calls("getblockchaininfo").len(), tip_lookups);
+        
+        let anchor = deposited.chain_anchor().unwrap();
+        assert_eq!(anchor.block_height, 100);
+        assert_eq!(anchor.block_hash, format!("{:064x}", 100));
+        assert_eq!(anchor.median_time.timestamp(), 1_700_000_000 + 100 * 600);
+        assert!(matches!(deposited, Event::Deposited { block_number: Some(100), .. }));
+        assert_eq!(withdrawn.chain_anchor(), Some(anchor));
+        assert!(matches!(withdrawn, Event::EmergencyWithdrawn { block_number: Some(100), .. }));
+        
+        // The anchor survives a round trip through JSON
+        let stored: Event = serde_json::from_value(serde_json::to_value(&deposited).unwrap()).unwrap();
+        assert_eq!(stored.chain_anchor(), Some(anchor));
+        
+        // Valid while the block is on the main chain
+        transport.respond("getblockhash", serde_json::json!(format!("{:064x}", 100)));
+        assert!(verify_anchor(&deposited, &rpc_client).unwrap().is_valid());
+        assert_eq!(transport.calls("getblockhash").last().unwrap(), &vec![serde_json::json!(100)]);
+        
+        // A different block at that height means the anchor was reorganized out
+        transport.respond("getblockhash", serde_json::json!("cc".repeat(32)));
+        assert_eq!(verify_anchor(&deposited, &rpc_client).unwrap(), AnchorVerification::Reorged {
+            main_chain_hash: "cc".repeat(32),
+        });
+    }
+    
+    #[test]
+    fn test_monthly_statements_carry_balances_across_months() {
+        use crate::event_log::{EventLog, EventLogConfig};
+        use crate::statements::{Statement, StatementGenerator, StatementPeriod, StatementSource};
+        use chrono::TimeZone;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let event_log = Arc::new(EventLog::open(EventLogConfig::new(dir.path().join("events.jsonl")), None).unwrap());
+        let at = |month: u32, day: u32| chrono::Utc.with_ymd_and_hms(2024, month, day, 12, 0, 0).unwrap();
+        let clock = Arc::new(MockClock::new(at(1, 15)));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.add_event_sink(event_log.clone());
+        contract.set_deposit_fee("owner_address".to_string(), TokenType::Bitcoin, Some(DepositFee::Percentage(1)), None).unwrap();
+        
+        // January: one Bitcoin deposit, 1% of it a fee
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 60, None).unwrap();
+        // February: another, and a Lightning deposit
+        clock.set(at(2, 10));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 5_000, 30, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 300, 90, None).unwrap();
+        // March: the first unlocks and is withdrawn, the Lightning deposit is taken out early
+        clock.set(at(3, 20));
+        contract.withdraw("depositor_address".to_string(), 1).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 3).unwrap();
+        // April passes without activity; May brings a long deposit
+        clock.set(at(5, 2));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 2_000, 120, None).unwrap();
+        
+        let generator = StatementGenerator::new(event_log, Arc::new(std::sync::Mutex::new(contract)));
+        let statements: Vec<Statement> = (1..=6)
+            .map(|month| generator.generate_statement("depositor_address", 2024, month).unwrap())
+            .collect();
+        
+        // Each month opens where the one before closed
+        assert_eq!(statements[0].opening_balance(&TokenType::Bitcoin), 0);
+        for pair in statements.windows(2) {
+            for token in [TokenType::Bitcoin, TokenType::Lightning] {
+                assert_eq!(pair[0].closing_balance(&token), pair[1].opening_balance(&token), "{} {}", pair[0].period, token.name());
+            }
+        }
+        
+        let january = &statements[0].tokens[0];
+        assert_eq!((january.credited, january.fees_paid, january.closing_balance), (10_000, 100, 9_900));
+        assert_eq!(statements[1].closing_balance(&TokenType::Bitcoin), 9_900 + 4_950);
+        assert_eq!(statements[1].closing_balance(&TokenType::Lightning), 300);
+        let march = &statements[2];
+        assert_eq!(march.closing_balance(&TokenType::Bitcoin), 4_950);
+        assert_eq!(march.closing_balance(&TokenType::Lightning), 0);
+        let lightning = march.tokens.iter().find(|token| token.token == TokenType::Lightning).unwrap();
+        assert_eq!(lightning.debited + lightning.fees_paid, 300);
+        assert!(lightning.fees_paid > 0);
+        
+        // A quiet month lists the balances it carries and nothing else
+        let april = &statements[3];
+        assert!(april.entries.is_empty());
+        assert_eq!(april.tokens.len(), 1);
+        assert_eq!(april.opening_balance(&TokenType::Bitcoin), april.closing_balance(&TokenType::Bitcoin));
+        
+        // Deposits still locked after the month are listed as upcoming unlocks
+        assert_eq!(statements[4].upcoming_unlocks.iter().map(|unlock| unlock.deposit_id).collect::<Vec<_>>(), vec![4]);
+        assert!(statements[5].entries.is_empty());
+        
+        // Markdown and JSON renderings
+        let markdown = march.to_markdown();
+        assert!(markdown.starts_with("# Statement for depositor_address\n"));
+        assert!(markdown.contains("Period: 2024-03 (2024-03-01T00:00:00Z to 2024-04-01T00:00:00Z)"));
+        assert!(markdown.contains("emergency_withdrawal"));
+        assert!(markdown.contains("0.00004950 BTC"));
+        let json: Statement = serde_json::from_value(serde_json::to_value(march).unwrap()).unwrap();
+        assert_eq!(&json, march);
+        
+        // Months parse as YYYY-MM
+        assert_eq!("2024-12".parse::<StatementPeriod>().unwrap().next(), StatementPeriod { year: 2025, month: 1 });
+        assert!("2024-13".parse::<StatementPeriod>().is_err());
+        assert!("June".parse::<StatementPeriod>().is_err());
+        
+        // Served over HTTP to read-only callers
+        let server = ApiServer::new().with_statements(Arc::new(generator));
+        let response = server.handle(&HttpRequest::new("GET", "/statements?address=depositor_address&month=2024-03&format=markdown"));
+        assert_eq!(response.status, 200);
+        assert!(response.content_type.starts_with("text/markdown"));
+        assert_eq!(String::from_utf8(response.body).unwrap(), markdown);
+        let response = server.handle(&HttpRequest::new("GET", "/statements?address=depositor_address&month=2024-02"));
+        let body: Statement = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body.closing_balance(&TokenType::Bitcoin), 9_900 + 4_950);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/statements?address=depositor_address&month=2024-00")).status, 400);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/statements?month=2024-02")).status, 400);
+        assert_eq!(ApiServer::new().handle(&HttpRequest::new("GET", "/statements?address=a&month=2024-02")).status, 404);
+        
+        // The CLI reads the same ledger straight from the file
+        let ledger = crate::event_log::read_address_ledger(&dir.path().join("events.jsonl"), "depositor_address").unwrap();
+        assert_eq!(ledger.len(), statements.iter().map(|statement| statement.entries.len()).sum::<usize>());
+        let offline = crate::statements::build_statement("depositor_address", march.period, &ledger, [], march.generated_at);
+        assert_eq!(offline.tokens, march.tokens);
+    }
+    
+    #[test]
+    fn test_dust_change_policy_modes() {
+        let dust_utxo_set = |on_dust_change: DustChange| {
+            let mut utxo_set = UtxoSet::new();
+            utxo_set.add(Utxo {
+                txid: "txid1".to_string(),
+                vout: 0,
+                amount: 5000,
+                confirmations: 6,
+                script_pubkey: "script1".to_string(),
+                address: "address1".to_string(),
+                spendable: true,
+            });
+            utxo_set.set_dust_policy(DustPolicy { dust_limit_sats: 546, on_dust_change });
+            utxo_set
+        };
+        
+        // 5000 - 4500 - 250 leaves 250 sat of change, below the limit
+        let (_, change) = dust_utxo_set(DustChange::AddToFee).select_utxos(4500, 1000.0, SelectionStrategy::Auto).unwrap();
+        assert_eq!(change, 0);
+        let (_, change) = dust_utxo_set(DustChange::SendAnyway).select_utxos(4500, 1000.0, SelectionStrategy::Auto).unwrap();
+        assert_eq!(change, 250);
+        let result = dust_utxo_set(DustChange::Error).select_utxos(4500, 1000.0, SelectionStrategy::Auto);
+        assert!(matches!(result, Err(ContractError::DustOutput { amount: 250, threshold: 546 })));
+        
+        // Change at or above the limit is paid back in every mode
+        for on_dust_change in [DustChange::AddToFee, DustChange::SendAnyway, DustChange::Error] {
+            let (_, change) = dust_utxo_set(on_dust_change).select_utxos(4000, 1000.0, SelectionStrategy::Auto).unwrap();
+            assert_eq!(change, 750);
+        }
+        
+        // Signed transactions follow the policy of the configuration
+        let build = |on_dust_change: DustChange| {
+            let mut config = BitcoinTestnetConfig::new(
+                "http://localhost:18332".to_string(),
+                "testuser".to_string(),
+                "testpassword".to_string(),
+                RPC_CONTRACT_WALLET.to_string(),
+            );
+            config.rate_limit = 600_000;
+            config.dust_policy.on_dust_change = on_dust_change;
+            
+            let transport = MockRpcTransport::default();
+            let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+            let rpc_client = BitcoinRpcClient::from_client(client, &config);
+            
+            // Pay all of a 1 BTC input but the fee and 300 sat
+            let fee = rpc_client.estimate_transaction_fee(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 10_000, 1000.0).unwrap();
+            let result = rpc_client.build_transaction(RPC_CONTRACT_WALLET, RPC_RECIPIENT, 100_000_000 - fee - 300, 1000.0, None, None);
+            (result, fee, transport)
+        };
+        
+        let (built, fee, transport) = build(DustChange::AddToFee);
+        assert_eq!(built.unwrap().fee, fee + 300);
+        let outputs = raw_outputs(&transport.calls("createrawtransaction")[0]);
+        assert_eq!(outputs, vec![(RPC_RECIPIENT.to_string(), 100_000_000 - fee - 300)]);
+        
+        let (built, fee, transport) = build(DustChange::SendAnyway);
+        assert_eq!(built.unwrap().fee, fee);
+        let outputs = raw_outputs(&transport.calls("createrawtransaction")[0]);
+        assert_eq!(outputs[1], (RPC_CONTRACT_WALLET.to_string(), 300));
+        
+        let (built, _, transport) = build(DustChange::Error);
+        assert!(matches!(built, Err(ContractError::DustOutput { amount: 300, threshold: 546 })));
+        assert!(transport.calls("createrawtransaction").is_empty());
+    }
+    
+    #[test]
+    fn test_withdrawal_below_dust_limit_plus_fee_is_refused() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 650, 30, None).unwrap();
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 699, 30, None).unwrap();
+        contract.token_transfer.process_pending_transactions().unwrap();
+        let payments = transport.calls("createrawtransaction").len();
+        clock.advance(chrono::Duration::days(31));
+        
+        // 546 sat of dust limit plus the 153 sat fee estimate
+        let error = contract.withdraw(RPC_RECIPIENT.to_string(), 1).unwrap_err();
+        assert!(matches!(error, ContractError::WithdrawalBelowMinimum { amount: 650, minimum: 699 }));
+        assert_eq!(error.code(), ErrorCode::WithdrawalBelowMinimum);
+        assert_eq!(
+            error.to_string(),
+            "Withdrawal of 650 sat is below the current minimum of 699 sat (dust limit plus estimated mining fee)",
+        );
+        assert!(matches!(
+            contract.simulate_withdrawal(RPC_RECIPIENT, 1, None),
+            Err(ContractError::WithdrawalBelowMinimum { amount: 650, minimum: 699 })
+        ));
+        
+        // Nothing was paid and the deposit stays active
+        contract.token_transfer.process_pending_transactions().unwrap();
+        assert_eq!(transport.calls("createrawtransaction").len(), payments);
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Active);
+        
+        // The minimum itself goes through
+        contract.withdraw(RPC_RECIPIENT.to_string(), 2).unwrap();
+        contract.token_transfer.process_pending_transactions().unwrap();
+        assert_eq!(created_payment_amounts(&transport)[payments..], [699]);
+    }
+    
+    
+    #[test]
+    fn test_vault_manager_isolates_tenants() {
+        use crate::soak::SimulatedTransfer;
+        use crate::tenancy::{VaultManager, VaultSettings};
+        
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut manager = VaultManager::new(dir.path());
+        let backend = || SimulatedTransfer::new(1_000_000, std::collections::HashMap::new());
+        for (name, owner) in [("alpha", "alpha_owner"), ("beta", "beta_owner")] {
+            let mut settings = VaultSettings::new(owner);
+            settings.event_log = true;
+            manager.create_vault(name, settings, backend()).unwrap();
+            manager.with_vault(name, |contract| contract.set_clock(clock.clone())).unwrap();
+        }
+        
+        // Names are unique and cannot leave the root
+        assert!(manager.create_vault("alpha", VaultSettings::new("other"), backend()).is_err());
+        assert!(manager.create_vault("../x", VaultSettings::new("other"), backend()).is_err());
+        assert_eq!(manager.vault_names(), vec!["alpha".to_string(), "beta".to_string()]);
+        
+        // Each vault numbers its own deposits and sees only its own
+        manager.with_vault("alpha", |contract| contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None)).unwrap().unwrap();
+        manager.with_vault("beta", |contract| contract.deposit("other_depositor".to_string(), TokenType::Bitcoin, 20_000, 60, None)).unwrap().unwrap();
+        manager.with_vault("alpha", |contract| {
+            assert_eq!(contract.deposit_registry.len(), 1);
+            assert_eq!(contract.deposit_registry[&1].depositor_address, "depositor_address");
+            assert!(contract.is_owner("alpha_owner") && !contract.is_owner("beta_owner"));
+        }).unwrap();
+        manager.with_vault("beta", |contract| {
+            assert_eq!(contract.deposit_registry.len(), 1);
+            assert_eq!(contract.deposit_registry[&1].depositor_address, "other_depositor");
+        }).unwrap();
+        
+        // Fees collected in one vault stay there
+        let beta_fees = manager.with_vault("beta", |contract| contract.fee_config.collected_fees.clone()).unwrap();
+        manager.with_vault("alpha", |contract| contract.emergency_withdraw("depositor_address".to_string(), 1)).unwrap().unwrap();
+        assert_eq!(manager.with_vault("beta", |contract| contract.fee_config.collected_fees.clone()).unwrap(), beta_fees);
+        
+        // Events go to each vault's own log and metrics labels
+        let alpha_log = manager.namespace("alpha").unwrap().event_log_path();
+        let beta_log = manager.namespace("beta").unwrap().event_log_path();
+        assert_ne!(alpha_log, beta_log);
+        assert!(crate::event_log::read_address_ledger(&alpha_log, "other_depositor").unwrap().is_empty());
+        assert!(crate::event_log::read_address_ledger(&beta_log, "depositor_address").unwrap().is_empty());
+        assert!(!crate::event_log::read_address_ledger(&beta_log, "other_depositor").unwrap().is_empty());
+        let metrics = manager.metrics().snapshot();
+        assert_eq!(metrics.get("vault_events{event=\"Deposited\",vault=\"alpha\"}"), Some(&1));
+        assert_eq!(metrics.get("vault_events{event=\"Deposited\",vault=\"beta\"}"), Some(&1));
+        assert!(!metrics.contains_key("vault_events{event=\"EmergencyWithdrawal\",vault=\"beta\"}"));
+        
+        // Snapshots are written to separate files
+        manager.save_all().unwrap();
+        let alpha_state = manager.namespace("alpha").unwrap().state_path();
+        let beta_state = manager.namespace("beta").unwrap().state_path();
+        assert_ne!(alpha_state, beta_state);
+        assert_ne!(std::fs::read(&alpha_state).unwrap(), std::fs::read(&beta_state).unwrap());
+        
+        // A vault with locked funds cannot be archived, an emptied one can
+        assert!(manager.archive_vault("beta").unwrap_err().contains("locked funds"));
+        let archived = manager.archive_vault("alpha").unwrap();
+        assert!(archived.join("state.json").exists());
+        assert!(!alpha_state.exists());
+        assert_eq!(manager.vault_names(), vec!["beta".to_string()]);
+        assert!(beta_state.exists());
+    }
+    
+    
+    #[test]
+    fn test_api_server_routes_by_vault_prefix() {
+        let mut contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        let report = contract.reconcile_and_enforce(&ReconciliationConfig { tolerance: 0, auto_pause: false });
+        let server = ApiServer::new()
+            .with_vault("alpha", ApiServer::new().with_reconciliation_report(Arc::new(std::sync::Mutex::new(Some(report)))))
+            .with_vault("beta", ApiServer::new());
+        
+        // Each prefix reaches only its own vault's API
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/vaults/alpha/stats/reconciliation")).status, 200);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/vaults/beta/stats/reconciliation")).status, 404);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/stats/reconciliation")).status, 404);
+        
+        let response = server.handle(&HttpRequest::new("GET", "/vaults/gamma/stats/reconciliation"));
+        assert_eq!(response.status, 404);
+        assert!(String::from_utf8_lossy(&response.body).contains("Unknown vault: gamma"));
+    }
+    
+    
+    #[test]
+    fn test_liquidity_forecast_buckets_and_shortfalls() {
+        use chrono::TimeZone;
+        use crate::contract::audit::{ReconciliationReport, TokenReconciliation};
+        
+        // Monday, so week boundaries fall on whole days of lock
+        let monday = chrono::Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(MockClock::new(monday));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 4_000, 3, None).unwrap();
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 16_000, 14, None).unwrap();
+        contract.deposit("other_depositor".to_string(), TokenType::Lightning, 8_000, 60, None).unwrap();
+        let (soon, later, lightning) = (
+            contract.deposit_registry[&1].deposited_amount,
+            contract.deposit_registry[&2].deposited_amount,
+            contract.deposit_registry[&3].deposited_amount,
+        );
+        let week = |n: i64| monday + chrono::Duration::weeks(n);
+        let outflows = |buckets: &[ForecastBucket], token: TokenType| buckets.iter()
+            .filter(|bucket| bucket.token == token)
+            .map(|bucket| (bucket.week_start, bucket.scheduled_outflow, bucket.modeled_emergency_outflow, bucket.cumulative_required))
+            .collect::<Vec<_>>();
+        
+        // Without emergencies only the unlocks are paid out; Lightning unlocks after the horizon
+        let buckets = contract.liquidity_forecast(3, 0).unwrap();
+        assert_eq!(buckets.len(), 6);
+        assert_eq!(buckets.iter().map(|bucket| bucket.week_start).collect::<Vec<_>>(), vec![week(0), week(0), week(1), week(1), week(2), week(2)]);
+        assert_eq!(outflows(&buckets, TokenType::Bitcoin), vec![
+            (week(0), soon, 0, soon),
+            (week(1), 0, 0, soon),
+            (week(2), later, 0, soon + later),
+        ]);
+        assert!(outflows(&buckets, TokenType::Lightning).iter().all(|(_, scheduled, emergency, cumulative)| *scheduled == 0 && *emergency == 0 && *cumulative == 0));
+        
+        // At half the locked balance a week, deposits withdrawn early no longer unlock later
+        let buckets = contract.liquidity_forecast(3, 5_000).unwrap();
+        assert_eq!(outflows(&buckets, TokenType::Bitcoin), vec![
+            (week(0), soon, later / 2, soon + later / 2),
+            (week(1), 0, later / 4, soon + later * 3 / 4),
+            (week(2), later / 4, 0, soon + later),
+        ]);
+        assert_eq!(outflows(&buckets, TokenType::Lightning), vec![
+            (week(0), 0, lightning / 2, lightning / 2),
+            (week(1), 0, lightning / 4, lightning * 3 / 4),
+            (week(2), 0, lightning / 8, lightning * 7 / 8),
+        ]);
+        
+        // Unlocked deposits are due in the current week
+        clock.set(week(1) + chrono::Duration::days(3));
+        let buckets = contract.liquidity_forecast(1, 0).unwrap();
+        assert_eq!(outflows(&buckets, TokenType::Bitcoin), vec![(week(1), soon, 0, soon)]);
+        clock.set(monday);
+        
+        // Balances flag the first week the cumulative outflow exceeds them
+        let balance = |token_type: TokenType, actual: Option<u64>| TokenReconciliation {
+            token_type,
+            expected: 0,
+            actual,
+            delta: 0,
+            operational_change: 0,
+            unattributed: 0,
+            within_tolerance: true,
+            error: actual.is_none().then(|| "node down".to_string()),
+        };
+        let mut report = ReconciliationReport {
+            generated_at: monday,
+            tolerance: 0,
+            tokens: vec![balance(TokenType::Bitcoin, Some(soon + later / 2)), balance(TokenType::Lightning, None)],
+            missing_utxos: Vec::new(),
+            unchecked_utxos: Vec::new(),
+            paused_contract: false,
+            state_hash: String::new(),
+        };
+        let forecast = contract.liquidity_forecast_report(3, 5_000, &report).unwrap();
+        assert_eq!(forecast.shortfalls, vec![ProjectedShortfall {
+            token: TokenType::Bitcoin,
+            week_start: week(1),
+            required: soon + later * 3 / 4,
+            available: soon + later / 2,
+            shortfall: later / 4,
+        }]);
+        assert_eq!(forecast.unchecked_tokens, vec![(TokenType::Lightning, "node down".to_string())]);
+        assert!(!forecast.is_covered());
+        
+        report.tokens = vec![balance(TokenType::Bitcoin, Some(soon + later)), balance(TokenType::Lightning, Some(lightning))];
+        assert!(contract.liquidity_forecast_report(3, 5_000, &report).unwrap().is_covered());
+        
+        // Horizons and rates out of range are refused
+        assert!(matches!(contract.liquidity_forecast(0, 0), Err(ContractError::InvalidForecast(_))));
+        assert!(matches!(contract.liquidity_forecast(MAX_FORECAST_WEEKS + 1, 0), Err(ContractError::InvalidForecast(_))));
+        assert!(matches!(contract.liquidity_forecast(3, 10_001), Err(ContractError::InvalidForecast(_))));
+        
+        // The stats API forecasts against the daemon's latest report
+        let forecaster = LiquidityForecaster::new(Arc::new(std::sync::Mutex::new(contract)), Arc::new(std::sync::Mutex::new(Some(report))));
+        let server = ApiServer::new().with_forecasts(Arc::new(forecaster));
+        let mut request = HttpRequest::new("GET", "/stats/forecast");
+        request.query.insert("weeks".to_string(), "3".to_string());
+        let response = server.handle(&request);
+        assert_eq!(response.status, 200);
+        let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["buckets"].as_array().unwrap().len(), 6);
+        assert_eq!(body["emergency_rate_bps"], 0);
+        
+        request.query.insert("weeks".to_string(), "0".to_string());
+        let response = server.handle(&request);
+        assert_eq!(response.status, 400);
+        assert!(String::from_utf8_lossy(&response.body).contains("INVALID_FORECAST"));
+    }
+    
+    #[test]
+    fn test_utxo_reference_parsing_is_strict_and_canonical() {
+        let txid = "ab".repeat(32);
+        let part = |text: &str| match text.parse::<UtxoRef>() {
+            Err(ContractError::InvalidUtxoReference { part, .. }) => Some(part),
+            _ => None,
+        };
+        
+        // Each malformed reference names the part at fault
+        assert_eq!(part(&format!("{}#0", txid)), Some(UtxoRefPart::Separator));
+        assert_eq!(part(&format!("{}:0", "ab".repeat(31))), Some(UtxoRefPart::Txid));
+        assert_eq!(part(&format!("{}:0", "zz".repeat(32))), Some(UtxoRefPart::Txid));
+        assert_eq!(part(&format!("{}:+1", txid)), Some(UtxoRefPart::Vout));
+        assert_eq!(part(&format!("{}:x", txid)), Some(UtxoRefPart::Vout));
+        assert_eq!(part(&format!("{}:", txid)), Some(UtxoRefPart::Vout));
+        assert_eq!(part(&format!("{}:4294967296", txid)), Some(UtxoRefPart::Vout));
+        let message = format!("{}:x", txid).parse::<UtxoRef>().unwrap_err().to_string();
+        assert!(message.starts_with("Invalid UTXO reference vout"), "{}", message);
+        
+        // Uppercase and surrounding whitespace normalize to one canonical form
+        let reference: UtxoRef = format!(" {}:4294967295\n", txid.to_uppercase()).parse().unwrap();
+        assert_eq!(reference.to_string(), format!("{}:4294967295", txid));
+        assert_eq!((reference.txid(), reference.vout()), (txid.as_str(), u32::MAX));
+        
+        // Serialized as the canonical string, with the older object form still read
+        assert_eq!(serde_json::to_value(&reference).unwrap(), serde_json::json!(reference.to_string()));
+        assert_eq!(serde_json::from_value::<UtxoRef>(serde_json::json!(reference.to_string())).unwrap(), reference);
+        let legacy = serde_json::json!({"txid": txid.to_uppercase(), "vout": u32::MAX});
+        assert_eq!(serde_json::from_value::<UtxoRef>(legacy).unwrap(), reference);
+        assert!(serde_json::from_value::<UtxoRef>(serde_json::json!("txid:0")).is_err());
+    }
+    
+    #[test]
+    fn test_invalid_utxo_references_are_rejected_at_deposit_time() {
+        use crate::client::VaultClient;
+        
+        let contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        let mut client = VaultClient::new(contract);
+        let result = client.deposit()
+            .from("depositor_address")
+            .token(TokenType::Bitcoin)
+            .amount_sats(1000)
+            .lock_days(30)
+            .utxo(format!("{}:0", "ab".repeat(30)))
+            .execute();
+        assert!(matches!(result, Err(ContractError::InvalidUtxoReference { part: UtxoRefPart::Txid, .. })));
+        
+        // Over JSON-RPC the error data names the part
+        let handler = jsonrpc_handler();
+        let response = jsonrpc_call(&handler, serde_json::json!(
+            {"jsonrpc": "2.0", "method": "vault_deposit", "params": {"depositor": "depositor_address", "token_type": "Bitcoin", "amount": 1000, "lock_days": 30, "utxo_reference": "ab:0:1"}, "id": 1}
+        ), None).unwrap();
+        assert_eq!(response["error"]["data"]["code"], "INVALID_UTXO_REFERENCE");
+        assert_eq!(response["error"]["data"]["part"], "txid");
+        
+        let reference = format!("{}:1", "AB".repeat(32));
+        let response = jsonrpc_call(&handler, serde_json::json!(
+            {"jsonrpc": "2.0", "method": "vault_deposit", "params": {"depositor": "depositor_address", "token_type": "Bitcoin", "amount": 1000, "lock_days": 30, "utxo_reference": reference}, "id": 2}
+        ), None).unwrap();
+        assert_eq!(response["result"]["Deposited"]["deposit_id"], 1);
+        let response = jsonrpc_call(&handler, serde_json::json!({"jsonrpc": "2.0", "method": "vault_getDeposit", "params": [1], "id": 3}), None).unwrap();
+        assert_eq!(response["result"]["utxo_reference"], reference.to_lowercase());
+    }
+    
+    #[test]
+    fn test_migrate_v1_1_0_snapshot_normalizes_utxo_references() {
+        let raw = include_str!("fixtures/state_v1_1_0_messy_utxos.json");
+        let contract = TimeLockedDeposit::from_state_versioned(raw, contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer).unwrap();
+        
+        // Case and whitespace are normalized away
+        assert_eq!(contract.deposit_registry[&1].utxo_reference, Some(test_utxo(0)));
+        assert_eq!(contract.deposit_registry[&2].utxo_reference, Some(test_utxo(1)));
+        assert_eq!(contract.utxo_reference_owner(&test_utxo(0).to_string()), Some(1));
+        assert_eq!(contract.utxo_reference_owner(&test_utxo(1).to_string().to_uppercase()), Some(2));
+        
+        // A reference that is not an outpoint is dropped, the deposit kept
+        assert_eq!(contract.deposit_registry[&3].utxo_reference, None);
+        assert_eq!(contract.deposit_registry[&3].deposited_amount, 3000);
+        
+        // The consumed list holds each output once, canonically
+        let state = contract.export_state();
+        assert_eq!(state.state_version.to_string(), "1.2.0");
+        assert_eq!(state.consumed_utxo_references, vec![(test_utxo(0), 1), (test_utxo(1), 2)]);
+        let reexported: serde_json::Value = serde_json::to_value(&state).unwrap();
+        assert_eq!(reexported["deposits"][0]["utxo_reference"], test_utxo(0).to_string());
+        
+        // The normalized outputs still cannot back another deposit
+        let mut contract = contract;
+        assert!(matches!(
+            contract.deposit("other_address".to_string(), TokenType::Bitcoin, 1000, 30, Some(test_utxo(1))),
+            Err(ContractError::UtxoAlreadyDeposited)
+        ));
+    }
+    
+    #[test]
+    fn test_fee_oracle_consumers_share_one_snapshot() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        let transfer = BitcoinTestnetTransfer::with_rpc_client(config, rpc_client.clone());
+        let ordinals_client = OrdinalsClient::new(rpc_client.clone(), "http://localhost:3000".to_string());
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.01, "blocks": 6 }));
+        
+        // Concurrent misses ask the node once and all get the same snapshot
+        let target = ConfirmationTarget::Normal;
+        let oracle = transfer.fee_oracle();
+        let readings: Vec<(FeeRate, u64)> = (0..8)
+            .map(|_| {
+                let oracle = oracle.clone();
+                std::thread::spawn(move || oracle.fee_rate_with_snapshot(target).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+        assert!(readings.iter().all(|reading| *reading == readings[0]));
+        assert_eq!(transport.calls("estimatesmartfee").len(), 1);
+        let (fee_rate, snapshot_id) = readings[0];
+        
+        // The RPC client, inscriptions, the snapshot and withdrawal quotes agree
+        assert_eq!(rpc_client.get_fee_estimate(target).unwrap(), fee_rate.sat_per_vb());
+        let inscription = ordinals_client.estimate_inscription_fee(100, target).unwrap();
+        assert_eq!(inscription.fee_snapshot_id, Some(snapshot_id));
+        assert_eq!(inscription.fee, ordinals_client.get_inscription_fee(100, fee_rate.sat_per_vb() * 1000.0).unwrap());
+        
+        let snapshot = oracle.current_snapshot();
+        assert_eq!(snapshot.snapshot_id, snapshot_id);
+        assert_eq!(snapshot.fee_rate(target), Some(fee_rate));
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer).unwrap();
+        contract.set_clock(clock.clone());
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        let quote = contract.quote_withdrawal_fee(1).unwrap();
+        assert_eq!(quote.fee_snapshot_id, Some(snapshot_id));
+        let simulation = contract.simulate_withdrawal(RPC_RECIPIENT, 1, None).unwrap();
+        assert_eq!(simulation.fee_snapshot_id, Some(snapshot_id));
+        assert_eq!(simulation.estimated_fee, Some(quote.fee));
+        assert_eq!(transport.calls("estimatesmartfee").len(), 1);
+    }
+    
+    #[test]
+    fn test_fee_oracle_flags_stale_rates_and_counts_failures() {
+        let (rpc_client, transport) = mock_rpc_client();
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let metrics = MetricsRegistry::new();
+        let oracle = rpc_client.fee_oracle();
+        oracle.set_clock(clock.clone());
+        oracle.set_metrics_registry(&metrics);
+        
+        // Tracked targets never fetched leave the oracle stale
+        assert!(oracle.is_stale());
+        
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.0001, "blocks": 2 }));
+        let snapshot = oracle.refresh();
+        assert!(!snapshot.stale);
+        assert_eq!(snapshot.estimates.len(), 3);
+        assert_eq!(metrics.snapshot()["fee_oracle_refreshes"], 3);
+        let target = ConfirmationTarget::Normal;
+        let fee_rate = snapshot.fee_rate(target).unwrap();
+        
+        // Past the cache TTL the node has no estimate: the last one stands in, flagged stale
+        transport.respond("estimatesmartfee", serde_json::json!({ "errors": ["Insufficient data or no feerate found"], "blocks": 0 }));
+        clock.advance(chrono::Duration::minutes(11));
+        assert!(oracle.is_stale());
+        
+        let (fallback, snapshot_id) = oracle.fee_rate_with_snapshot(target).unwrap();
+        assert_eq!(fallback, fee_rate);
+        assert!(snapshot_id > snapshot.snapshot_id);
+        assert_eq!(metrics.snapshot()["fee_oracle_refresh_failures"], 1);
+        
+        let stale = oracle.current_snapshot();
+        assert!(stale.stale);
+        assert!(stale.last_error.is_some());
+        assert_eq!(stale.estimates.iter().find(|estimate| estimate.target == target).unwrap().origin, FeeRateOrigin::LastKnown);
+        
+        // Once the last estimate is too old, the estimate fails
+        clock.advance(chrono::Duration::hours(1));
+        assert!(matches!(
+            oracle.fee_rate(target),
+            Err(ContractError::TransferFailed { stage: TransferStage::FeeEstimation, .. })
+        ));
+        assert_eq!(metrics.snapshot()["fee_oracle_refresh_failures"], 2);
+        
+        // A fresh estimate clears the error
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.0002, "blocks": 2 }));
+        assert_eq!(oracle.fee_rate(target).unwrap().sat_per_vb(), 20.0);
+        assert!(oracle.current_snapshot().last_error.is_none());
+    }
+    
+    #[test]
+    fn test_feature_gate_fails_fast_and_recovers_through_a_probe() {
+        let mut config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        config.rate_limit = 600_000;
+        config.feature_gates = FeatureGateConfig { window: 4, min_calls: 3, failure_ratio: 0.5, cooldown: Duration::from_secs(30) };
+        let transport = MockRpcTransport::default();
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(transport.clone()));
+        let rpc_client = Arc::new(BitcoinRpcClient::from_client(client, &config));
+        let ordinals_client = Arc::new(OrdinalsClient::new(rpc_client.clone(), "http://localhost:3000".to_string()));
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut transfer = BitcoinTestnetTransfer::with_rpc_client(config, rpc_client);
+        transfer.set_ordinals_client(ordinals_client.clone());
+        transfer.set_clock(clock.clone());
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.01, "blocks": 6 }));
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer).unwrap();
+        contract.set_clock(clock.clone());
+        let inscription = TokenType::Ordinal("ab".repeat(32));
+        contract.add_supported_token("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), inscription.clone(), None).unwrap();
+        ordinals_client.simulate_external_transfer(&"ab".repeat(32), RPC_RECIPIENT).unwrap();
+        let gate_status = |contract: &TimeLockedDeposit<BitcoinTestnetTransfer>| contract.token_transfer.diagnostics().feature_gates.into_iter()
+            .find(|status| status.component == BackendComponent::Ordinals)
+            .unwrap();
+        
+        // Failing calls reach the API until the failure ratio trips the gate
+        ordinals_client.simulate_outage(true);
+        for _ in 0..3 {
+            let result = contract.deposit(RPC_RECIPIENT.to_string(), inscription.clone(), 1, 30, None);
+            assert!(matches!(result, Err(ContractError::TransferFailed { stage: TransferStage::BalanceCheck, .. })));
+        }
+        let status = gate_status(&contract);
+        assert_eq!(status.state, GateState::Open);
+        assert_eq!(status.retry_after_secs, Some(30));
+        assert_eq!(status.trips, 1);
+        
+        // An open gate refuses without touching the API, which rate limits every call
+        let started = std::time::Instant::now();
+        let result = contract.deposit(RPC_RECIPIENT.to_string(), inscription.clone(), 1, 30, None);
+        assert!(started.elapsed() < Duration::from_millis(100));
+        let error = result.unwrap_err();
+        assert!(matches!(&error, ContractError::BackendUnavailable(detail) if detail == "ordinals failing, retry after 30s"));
+        assert!(error.is_retryable());
+        
+        // Bitcoin does not depend on the Ordinals API
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        
+        // After the cooldown one probe goes through; failing, it opens the gate again
+        clock.advance(chrono::Duration::seconds(31));
+        let result = contract.deposit(RPC_RECIPIENT.to_string(), inscription.clone(), 1, 30, None);
+        assert!(matches!(result, Err(ContractError::TransferFailed { stage: TransferStage::BalanceCheck, .. })));
+        assert_eq!(gate_status(&contract).state, GateState::Open);
+        assert_eq!(gate_status(&contract).trips, 2);
+        
+        // A successful probe closes it
+        ordinals_client.simulate_outage(false);
+        clock.advance(chrono::Duration::seconds(31));
+        contract.deposit(RPC_RECIPIENT.to_string(), inscription.clone(), 1, 30, None).unwrap();
+        let status = gate_status(&contract);
+        assert_eq!(status.state, GateState::Closed);
+        assert_eq!(status.retry_after_secs, None);
+        assert_eq!(status.recent_calls, 0);
+    }
+    
+    #[test]
+    fn test_feature_gate_probe_slot_and_health_checks() {
+        let config = FeatureGateConfig { window: 4, min_calls: 2, failure_ratio: 0.5, cooldown: Duration::from_secs(10) };
+        let gate = Arc::new(FeatureGate::new(BackendComponent::Lightning, config.clone()));
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        gate.set_clock(clock.clone());
+        let failure = || Err::<(), _>(ContractError::BitcoinTestnetError("node down".to_string()));
+        
+        // Caller errors do not count against the backend
+        for _ in 0..4 {
+            assert!(gate.call(|| Err::<(), _>(ContractError::Unauthorized)).is_err());
+        }
+        assert_eq!(gate.state(), GateState::Closed);
+        
+        // Failed health probes trip the gate like failed calls
+        gate.record_probe(false);
+        gate.record_probe(false);
+        assert_eq!(gate.state(), GateState::Open);
+        assert!(matches!(gate.admit(), Err(ContractError::BackendUnavailable(_))));
+        assert!(matches!(gate.call(failure), Err(ContractError::BackendUnavailable(_))));
+        
+        // While the probe is in flight every other call is refused
+        clock.advance(chrono::Duration::seconds(10));
+        assert!(gate.admit().is_ok());
+        let concurrent = gate.call(|| {
+            assert_eq!(gate.state(), GateState::HalfOpen);
+            Ok(gate.call(|| Ok(())))
+        }).unwrap();
+        assert!(matches!(concurrent, Err(ContractError::BackendUnavailable(_))));
+        assert_eq!(gate.state(), GateState::Closed);
+        
+        // A healthy probe after the cooldown closes an open gate too
+        gate.call(failure).unwrap_err();
+        gate.call(failure).unwrap_err();
+        assert_eq!(gate.state(), GateState::Open);
+        gate.record_probe(true);
+        assert_eq!(gate.state(), GateState::Open);
+        clock.advance(chrono::Duration::seconds(10));
+        gate.record_probe(true);
+        assert_eq!(gate.state(), GateState::Closed);
+        assert_eq!(gate.status().trips, 2);
+        
+        // Health checks of a transfer feed its gates
+        let (mut transfer, _transport) = transfer_with_mock_rpc(FeeTargets::default());
+        let rpc_client = Arc::new(mock_rpc_client().0);
+        let ordinals_client = Arc::new(OrdinalsClient::new(rpc_client, "http://localhost:3000".to_string()));
+        transfer.set_ordinals_client(ordinals_client.clone());
+        ordinals_client.simulate_outage(true);
+        let checker = transfer.health_checker(Duration::from_secs(60));
+        for _ in 0..5 {
+            checker.check_now().unwrap();
+        }
+        let gates = transfer.feature_gates();
+        assert_eq!(gates.get(BackendComponent::Ordinals).unwrap().state(), GateState::Open);
+        assert_eq!(gates.get(BackendComponent::Lightning).unwrap().state(), GateState::Closed);
+        assert!(gates.get(BackendComponent::BitcoinRpc).is_none());
+        assert!(matches!(
+            transfer.transfer_to_contract(RPC_RECIPIENT, &TokenType::Ordinal("ab".repeat(32)), 1),
+            Err(reason) if reason.starts_with(BACKEND_UNAVAILABLE)
+        ));
+    }
+    
+    #[test]
+    fn test_withdrawal_change_is_tagged_operational() {
+        use crate::store::{EncryptedStateStore, StateKey, TransferStateStore};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, transport) = contract_with_mock_rpc(clock.clone());
+        contract.set_treasury_policy(
+            "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(),
+            Some(TreasuryPolicy { hot_wallet_ceiling: 0, float_days: 7, cold_address: "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc".to_string() }),
+            None,
+        ).unwrap();
+        
+        // Without change there is nothing sweeps may spend
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 300_000, 60, None).unwrap();
+        let proposal = contract.compute_sweep_amount().unwrap();
+        assert_eq!((proposal.sweepable, proposal.sweep_amount), (Some(0), 0));
+        assert!(contract.execute_treasury_sweep("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), None).unwrap().is_none());
+        
+        // A withdrawal spending a 1 BTC output pays the rest back as change, last
+        clock.advance(chrono::Duration::days(31));
+        contract.withdraw_with_fee_limit(RPC_RECIPIENT.to_string(), 1, Some(153)).unwrap();
+        let created = transport.calls("createrawtransaction");
+        let withdrawal = created.last().unwrap();
+        let change = 100_000_000 - 100_000 - 153;
+        assert_eq!(raw_outputs(withdrawal), vec![(RPC_RECIPIENT.to_string(), 100_000), (RPC_CONTRACT_WALLET.to_string(), change)]);
+        
+        let operational = contract.token_transfer.operational_utxos();
+        assert_eq!(operational.len(), 1);
+        assert_eq!((operational[0].reference.vout(), operational[0].amount), (1, change));
+        assert_ne!(operational[0].reference.txid(), "ab".repeat(32));
+        assert!(operational[0].label.as_ref().unwrap().starts_with("vault:withdraw:"));
+        
+        // The wallet now holds the untouched deposits' outputs and the change
+        let spent = withdrawal[0][0]["vout"].as_u64().unwrap();
+        let mut unspent: Vec<serde_json::Value> = (0..10u64)
+            .filter(|vout| *vout != spent)
+            .map(|vout| serde_json::json!({
+                "txid": "ab".repeat(32),
+                "vout": vout,
+                "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                "amount": 1.0,
+                "confirmations": 6,
+                "spendable": true,
+                "solvable": true,
+                "safe": true,
+            }))
+            .collect();
+        unspent.push(serde_json::json!({
+            "txid": operational[0].reference.txid(),
+            "vout": 1,
+            "scriptPubKey": "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            "amount": change as f64 / 100_000_000.0,
+            "confirmations": 1,
+            "spendable": true,
+            "solvable": true,
+            "safe": true,
+        }));
+        transport.respond("listunspent", serde_json::Value::Array(unspent));
+        
+        // Reconciliation counts the change as operational instead of a surplus
+        let report = contract.reconcile(0);
+        let bitcoin = report.tokens.iter().find(|token| token.token_type == TokenType::Bitcoin).unwrap();
+        assert_eq!(bitcoin.actual, Some(900_000_000 + change));
+        assert_eq!(bitcoin.operational_change, change);
+        assert_eq!(bitcoin.delta - bitcoin.unattributed, change as i64);
+        assert_eq!(bitcoin.unattributed, 900_000_000 - bitcoin.expected as i64);
+        
+        // Sweeps spend only the change, never the deposits' outputs
+        let proposal = contract.compute_sweep_amount().unwrap();
+        assert_eq!((proposal.sweepable, proposal.sweep_amount), (Some(change), change));
+        contract.execute_treasury_sweep("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), None).unwrap().unwrap();
+        let sweep = transport.calls("createrawtransaction").last().unwrap().clone();
+        assert_eq!(sweep[0], serde_json::json!([{ "txid": operational[0].reference.txid(), "vout": 1 }]));
+        assert!(contract.token_transfer.operational_utxos().iter().all(|utxo| utxo.reference != operational[0].reference));
+        
+        // The tags are saved and restored with the transfer state
+        let dir = tempfile::tempdir().unwrap();
+        let store = EncryptedStateStore::open(dir.path(), StateKey::generate()).unwrap();
+        let tagged = OperationalUtxo { reference: operational[0].reference.clone(), amount: change, label: None };
+        store.save_operational_utxos(std::slice::from_ref(&tagged)).unwrap();
+        let (restored, _) = transfer_with_mock_rpc(FeeTargets::default());
+        assert_eq!(restored.restore_operational_utxos(&store).unwrap(), 1);
+        assert_eq!(restored.operational_utxos(), vec![tagged]);
+        contract.token_transfer.save_operational_utxos(&store).unwrap();
+        assert_eq!(store.load_operational_utxos().unwrap(), contract.token_transfer.operational_utxos());
+    }
+    
+    #[test]
+    fn test_confirmation_targets_follow_config_and_serialize_stably() {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets { urgent: 3, normal: 10, economy: 100 });
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.01, "blocks": 3 }));
+        let requested_blocks = || transport.calls("estimatesmartfee").last().unwrap()[0].clone();
+        
+        // Priorities map to the named targets, which resolve through the configured blocks
+        let urgent = transfer.quote_withdrawal_fee(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, TransferPriority::High).unwrap();
+        assert_eq!((urgent.target, urgent.target_blocks), (Some(ConfirmationTarget::Urgent), Some(3)));
+        assert_eq!(requested_blocks(), serde_json::json!(3));
+        
+        let economy = transfer.quote_withdrawal_fee(RPC_RECIPIENT, &TokenType::Bitcoin, 10_000, TransferPriority::Low).unwrap();
+        assert_eq!((economy.target, economy.target_blocks), (Some(ConfirmationTarget::Economy), Some(100)));
+        assert_eq!(requested_blocks(), serde_json::json!(100));
+        
+        // Custom targets name their blocks, and snapshots show both
+        let oracle = transfer.fee_oracle();
+        oracle.fee_rate(ConfirmationTarget::Custom(7)).unwrap();
+        assert_eq!(requested_blocks(), serde_json::json!(7));
+        let snapshot = oracle.current_snapshot();
+        let custom = snapshot.estimates.iter().find(|estimate| estimate.target == ConfirmationTarget::Custom(7)).unwrap();
+        assert_eq!(custom.blocks, 7);
+        assert_eq!(snapshot.estimates.iter().find(|estimate| estimate.target == ConfirmationTarget::Urgent).unwrap().blocks, 3);
+        assert_eq!(ConfirmationTarget::from(TransferPriority::Normal), ConfirmationTarget::Normal);
+        assert_eq!(ConfirmationTarget::Custom(7).to_string(), "custom (7 blocks)");
+        assert_eq!(ConfirmationTarget::Economy.to_string(), "economy");
+        
+        // Quotes serialize the target by name, next to its blocks
+        assert_eq!(serde_json::to_value(urgent).unwrap(), serde_json::json!({
+            "fee": urgent.fee,
+            "fee_snapshot_id": urgent.fee_snapshot_id,
+            "target": "urgent",
+            "target_blocks": 3,
+        }));
+        assert_eq!(serde_json::to_value(ConfirmationTarget::Normal).unwrap(), serde_json::json!("normal"));
+        assert_eq!(serde_json::to_value(ConfirmationTarget::Custom(7)).unwrap(), serde_json::json!({ "custom": 7 }));
+        assert_eq!(serde_json::from_value::<ConfirmationTarget>(serde_json::json!({ "custom": 7 })).unwrap(), ConfirmationTarget::Custom(7));
+        
+        // Quotes stored before targets were reported still load
+        let older: crate::models::FeeQuote = serde_json::from_value(serde_json::json!({ "fee": 153, "fee_snapshot_id": 4 })).unwrap();
+        assert_eq!((older.target, older.target_blocks), (None, None));
+        
+        // The deprecated block count overload asks for a custom target
+        let (rpc_client, transport) = mock_rpc_client();
+        transport.respond("estimatesmartfee", serde_json::json!({ "feerate": 0.02, "blocks": 12 }));
+        #[allow(deprecated)]
+        let by_blocks = rpc_client.get_fee_estimate_blocks(12).unwrap();
+        assert_eq!(by_blocks, rpc_client.get_fee_estimate(ConfirmationTarget::Custom(12)).unwrap());
+        assert_eq!(transport.calls("estimatesmartfee")[0][0], serde_json::json!(12));
+    }
+    
+    
+    #[test]
+    fn test_paused_token_rejects_deposits_while_others_keep_working() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 1, None).unwrap();
+        
+        // Only the owner pauses, and only supported tokens
+        assert!(matches!(
+            contract.pause_token("depositor_address".to_string(), TokenType::Lightning, None),
+            Err(ContractError::Unauthorized)
+        ));
+        assert!(matches!(
+            contract.pause_token("owner_address".to_string(), TokenType::Custom("OTHER".to_string()), None),
+            Err(ContractError::UnsupportedTokenOperation)
+        ));
+        assert!(matches!(
+            contract.pause_token("owner_address".to_string(), TokenType::Lightning, None),
+            Ok(Event::TokenPaused { token_type: TokenType::Lightning, reason: None, .. })
+        ));
+        assert!(matches!(
+            contract.pause_token("owner_address".to_string(), TokenType::Lightning, None),
+            Err(ContractError::TokenPaused { token_type: TokenType::Lightning })
+        ));
+        
+        // Lightning deposits and withdrawals fail, Bitcoin keeps working
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 1, None),
+            Err(ContractError::TokenPaused { token_type: TokenType::Lightning })
+        ));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 1, None).unwrap();
+        clock.advance(chrono::Duration::days(2));
+        assert!(matches!(
+            contract.withdraw("depositor_address".to_string(), 1),
+            Err(ContractError::TokenPaused { token_type: TokenType::Lightning })
+        ));
+        contract.withdraw("depositor_address".to_string(), 2).unwrap();
+        
+        // The pause is listed in the stats and survives a snapshot
+        assert_eq!(contract.get_stats().paused_tokens, vec![TokenType::Lightning]);
+        let state = contract.export_state();
+        assert_eq!(serde_json::to_value(&state).unwrap()["paused_tokens"], serde_json::json!(["Lightning"]));
+        let mut restored = TimeLockedDeposit::from_state(state, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        restored.set_clock(clock.clone());
+        assert!(restored.is_token_paused(&TokenType::Lightning));
+        
+        // The policy can let withdrawals of a paused token through, but not deposits
+        restored.set_token_pause_policy("owner_address".to_string(), TokenPausePolicy { withdrawals_exempt: true, pause_on_open_gate: false }, None).unwrap();
+        restored.withdraw("depositor_address".to_string(), 1).unwrap();
+        assert!(matches!(
+            restored.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 1, None),
+            Err(ContractError::TokenPaused { .. })
+        ));
+        
+        assert!(matches!(
+            restored.unpause_token("owner_address".to_string(), TokenType::Lightning, None),
+            Ok(Event::TokenUnpaused { token_type: TokenType::Lightning, .. })
+        ));
+        assert!(matches!(
+            restored.unpause_token("owner_address".to_string(), TokenType::Lightning, None),
+            Err(ContractError::TokenNotPaused)
+        ));
+        restored.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 1, None).unwrap();
+        assert!(restored.get_stats().paused_tokens.is_empty());
+    }
+    
+    #[test]
+    fn test_contract_pause_supersedes_token_pause() {
+        let mut contract = contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now())));
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.pause_token("owner_address".to_string(), TokenType::Lightning, None).unwrap();
+        contract.set_token_pause_policy("owner_address".to_string(), TokenPausePolicy { withdrawals_exempt: true, pause_on_open_gate: false }, None).unwrap();
+        
+        // While the contract is paused every token reports the contract pause
+        contract.is_contract_paused = true;
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 30, None),
+            Err(ContractError::ContractPaused)
+        ));
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None),
+            Err(ContractError::ContractPaused)
+        ));
+        
+        // Exempting withdrawals from token pauses does not exempt them from the contract pause
+        assert!(matches!(
+            contract.emergency_withdraw("depositor_address".to_string(), 1),
+            Err(ContractError::ContractPaused)
+        ));
+        
+        // The token stays paused after the contract resumes
+        contract.is_contract_paused = false;
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 30, None),
+            Err(ContractError::TokenPaused { token_type: TokenType::Lightning })
+        ));
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        
+        // Lifting the token pause while the contract is paused leaves the contract pause in force
+        contract.is_contract_paused = true;
+        contract.unpause_token("owner_address".to_string(), TokenType::Lightning, None).unwrap();
+        assert!(matches!(
+            contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 30, None),
+            Err(ContractError::ContractPaused)
+        ));
+        contract.is_contract_paused = false;
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 30, None).unwrap();
+    }
+    
+    #[test]
+    fn test_circuit_breaker_and_feature_gate_pause_single_tokens() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let sink = Arc::new(RecordingSink::default());
+        contract.add_event_sink(sink.clone());
+        contract.set_circuit_breaker_config("owner_address".to_string(), CircuitBreakerConfig {
+            enabled: true,
+            window: Duration::from_secs(24 * 3600),
+            max_withdrawal_count: None,
+            max_withdrawal_amount: vec![(TokenType::Bitcoin, 15_000)],
+            pause_token_only: true,
+        }, None).unwrap();
+        
+        for _ in 0..3 {
+            contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        }
+        
+        // The amount trip pauses Bitcoin alone, naming the reason
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 2).unwrap();
+        assert!(!contract.is_contract_paused);
+        assert_eq!(contract.get_circuit_breaker_trip(), None);
+        assert!(contract.is_token_paused(&TokenType::Bitcoin));
+        assert!(matches!(
+            contract.emergency_withdraw("depositor_address".to_string(), 3),
+            Err(ContractError::TokenPaused { token_type: TokenType::Bitcoin })
+        ));
+        contract.deposit("depositor_address".to_string(), TokenType::Lightning, 10_000, 30, None).unwrap();
+        assert!(sink.names.lock().unwrap().contains(&"TokenPaused"));
+        
+        // Unpausing forgets the withdrawals that tripped it
+        contract.unpause_token("owner_address".to_string(), TokenType::Bitcoin, None).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 3).unwrap();
+        assert!(!contract.is_token_paused(&TokenType::Bitcoin));
+        
+        // An open feature gate pauses its tokens only when the policy asks for it
+        let (mut contract, _transport) = contract_with_mock_rpc(clock.clone());
+        let rpc_client = Arc::new(mock_rpc_client().0);
+        let ordinals_client = Arc::new(OrdinalsClient::new(rpc_client, "http://localhost:3000".to_string()));
+        contract.token_transfer.set_ordinals_client(ordinals_client);
+        let inscription = TokenType::Ordinal("ab".repeat(32));
+        contract.add_supported_token("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), inscription.clone(), None).unwrap();
+        let gate = contract.token_transfer.feature_gates().get(BackendComponent::Ordinals).unwrap().clone();
+        for _ in 0..5 {
+            gate.record_probe(false);
+        }
+        assert_eq!(gate.state(), GateState::Open);
+        assert!(contract.pause_tokens_behind_open_gates().is_empty());
+        
+        contract.set_token_pause_policy(
+            "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(),
+            TokenPausePolicy { withdrawals_exempt: false, pause_on_open_gate: true },
+            None,
+        ).unwrap();
+        let events = contract.pause_tokens_behind_open_gates();
+        assert!(matches!(
+            events.as_slice(),
+            [Event::TokenPaused { token_type, reason: Some(PauseReason::FeatureGateOpen { component: BackendComponent::Ordinals }), .. }] if token_type == &inscription
+        ));
+        assert!(contract.pause_tokens_behind_open_gates().is_empty());
+        assert!(!contract.is_token_paused(&TokenType::Bitcoin));
+    }
+    
+    /// State store keeping every snapshot saved, as a crash would leave them on disk
+    #[derive(Debug, Default)]
+    struct RecordingStateStore {
+        /// Saved snapshots, oldest first
+        saved: std::sync::Mutex<Vec<ContractState>>,
+    }
+    
+    impl RecordingStateStore {
+        /// Last snapshot saved with an outbox entry, the state a crash between commit and drain leaves
+        fn committed_before_drain(&self) -> ContractState {
+            self.saved.lock().unwrap().iter().rev()
+                .find(|state| !state.outbox.is_empty())
+                .cloned()
+                .expect("no snapshot with an outbox entry")
+        }
+    }
+    
+    impl StateStore for RecordingStateStore {
+        fn load_state(&self) -> Result<Option<ContractState>, String> {
+            Ok(self.saved.lock().unwrap().last().cloned())
+        }
+        
+        fn save_state(&self, state: &ContractState) -> Result<(), String> {
+            self.saved.lock().unwrap().push(state.clone());
+            Ok(())
+        }
+    }
+    
+    /// Testnet transfer batching payouts over ten minutes, shared so a restarted contract can reuse it
+    fn shared_batching_transfer() -> (Arc<BitcoinTestnetTransfer>, MockRpcTransport) {
+        let (transfer, transport, _clock) = transfer_with_payout_batching(PayoutBatchConfig {
+            window: Duration::from_secs(600),
+            ..PayoutBatchConfig::default()
+        });
+        
+        // The wallet has sent nothing yet
+        transport.respond("listtransactions", serde_json::json!([]));
+        
+        (Arc::new(transfer), transport)
+    }
+    
+    #[test]
+    fn test_outbox_withdrawal_survives_a_crash_between_commit_and_drain() {
+        let (transfer, _transport) = shared_batching_transfer();
+        let mut contract = restored_mid_withdrawal(transfer.clone(), &[], &[]);
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        
+        // The withdrawal is committed with its payout before the payout is dispatched
+        contract.withdraw(RPC_RECIPIENT.to_string(), 1).unwrap();
+        let committed = store.committed_before_drain();
+        assert_eq!(committed.outbox.len(), 1);
+        assert_eq!(committed.outbox[0].operation, OutboxOperation::Withdrawal);
+        assert_eq!(committed.deposits[0].status, DepositStatus::WithdrawalPending);
+        assert!(contract.get_outbox().is_empty());
+        assert!(store.load_state().unwrap().unwrap().outbox.is_empty());
+        assert_eq!(transfer.queued_payouts(), vec![1]);
+        
+        // Crash before the drain: the payout never reached the backend, so recovery dispatches it once
+        let (fresh, _) = shared_batching_transfer();
+        let mut restarted = TimeLockedDeposit::from_state(committed.clone(), fresh.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::Withdrawn { deposit_id: 1, .. }]));
+        assert_eq!(fresh.queued_payouts(), vec![1]);
+        assert_eq!(restarted.deposit_registry[&1].status, DepositStatus::Withdrawn);
+        assert!(restarted.get_outbox().is_empty());
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert_eq!(fresh.queued_payouts(), vec![1]);
+        restarted.check_accounting_invariants().unwrap();
+        
+        // Crash after the backend queued the payout: recovery completes the withdrawal without paying twice
+        let mut restarted = TimeLockedDeposit::from_state(committed.clone(), transfer.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::Withdrawn { deposit_id: 1, .. }]));
+        assert_eq!(transfer.queued_payouts(), vec![1]);
+        assert_eq!(restarted.total_deposits[&TokenType::Bitcoin], 20_000);
+        restarted.check_accounting_invariants().unwrap();
+        
+        // Crash after the payout was broadcast: the wallet knows it, so nothing is queued again
+        let (sent, transport) = shared_batching_transfer();
+        set_wallet_payout(&transport, &"cd".repeat(32), "vault:withdraw:deposit-1");
+        let mut restarted = TimeLockedDeposit::from_state(committed, sent.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::Withdrawn { deposit_id: 1, .. }]));
+        assert!(sent.queued_payouts().is_empty());
+        assert_eq!(sent.pending_queue_depth(), 0);
+    }
+    
+    #[test]
+    fn test_outbox_deposit_survives_a_crash_between_commit_and_drain() {
+        let (transfer, transport) = transfer_with_mock_rpc(FeeTargets::default());
+        transport.respond("listtransactions", serde_json::json!([]));
+        let transfer = Arc::new(transfer);
+        let mut contract = TimeLockedDeposit::new_with_defaults("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn".to_string(), 10, transfer.clone()).unwrap();
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        
+        // The deposit's ID is reserved with its funding transfer, and the deposit recorded once the transfer is queued
+        contract.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        let committed = store.committed_before_drain();
+        assert!(matches!(committed.outbox[0].operation, OutboxOperation::Deposit { lock_period_days: 30, .. }));
+        assert_eq!(committed.outbox[0].transfer_request.deposit_id, Some(1));
+        assert!(committed.deposits.is_empty());
+        assert_eq!(committed.next_deposit_id, 2);
+        assert_eq!(transfer.pending_queue_depth(), 1);
+        
+        // Crash before the drain: recovery pulls the funding once and records the deposit under its reserved ID
+        let (fresh, fresh_transport) = transfer_with_mock_rpc(FeeTargets::default());
+        fresh_transport.respond("listtransactions", serde_json::json!([]));
+        let fresh = Arc::new(fresh);
+        let mut restarted = TimeLockedDeposit::from_state(committed.clone(), fresh.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::Deposited { deposit_id: 1, .. }]));
+        assert_eq!(fresh.pending_queue_depth(), 1);
+        assert_eq!(restarted.deposit_registry[&1].status, DepositStatus::Active);
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert_eq!(fresh.pending_queue_depth(), 1);
+        
+        // Crash after the funding was queued: the deposit is recorded without pulling the funds again
+        let mut restarted = TimeLockedDeposit::from_state(committed, transfer.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::Deposited { deposit_id: 1, .. }]));
+        assert_eq!(transfer.pending_queue_depth(), 1);
+        assert_eq!(restarted.deposit_registry.len(), 1);
+        restarted.check_accounting_invariants().unwrap();
+        
+        // Another deposit takes the next ID
+        let event = restarted.deposit(RPC_RECIPIENT.to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        assert!(matches!(event, Event::Deposited { deposit_id: 2, .. }));
+    }
+    
+    #[test]
+    fn test_outbox_entries_without_a_reference_wait_for_the_owner() {
+        let (transfer, _transport) = shared_batching_transfer();
+        let mut contract = restored_mid_withdrawal(transfer.clone(), &[], &[]);
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        
+        // Crash before an emergency payout is drained: recovery finds nothing under the deposit and pays it once
+        contract.emergency_withdraw(RPC_RECIPIENT.to_string(), 2).unwrap();
+        let committed = store.committed_before_drain();
+        assert!(matches!(committed.outbox[0].operation, OutboxOperation::EmergencyWithdrawal { fee_amount: 1_000 }));
+        let (fresh, _) = shared_batching_transfer();
+        let mut contract = TimeLockedDeposit::from_state(committed, fresh.clone()).unwrap();
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        let events = contract.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::EmergencyWithdrawn { deposit_id: 2, withdrawn_amount: 9_000, .. }]));
+        assert_eq!(fresh.queued_payout_references(), vec![2]);
+        assert_eq!(contract.fee_config.collected_fees[&TokenType::Bitcoin], 1_000);
+        
+        // A fee withdrawal carries no deposit ID, so after a crash the backend cannot tell whether it was sent
+        contract.fee_destination_whitelist = vec![RPC_CONTRACT_WALLET.to_string()];
+        contract.withdraw_fees(RPC_CONTRACT_WALLET.to_string(), TokenType::Bitcoin, None, None, None).unwrap();
+        let committed = store.committed_before_drain();
+        assert_eq!(committed.outbox[0].operation, OutboxOperation::FeeWithdrawal);
+        let mut restarted = TimeLockedDeposit::from_state(committed, fresh.clone()).unwrap();
+        let depth = fresh.pending_queue_depth();
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert_eq!(fresh.pending_queue_depth(), depth);
+        
+        // The fees stay taken off meanwhile, so they cannot be paid out twice
+        let entry_id = restarted.get_outbox()[0].entry_id;
+        assert!(matches!(
+            restarted.withdraw_fees(RPC_CONTRACT_WALLET.to_string(), TokenType::Bitcoin, None, None, None),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        assert!(matches!(restarted.resolve_outbox_entry(RPC_RECIPIENT.to_string(), entry_id, true, None), Err(ContractError::Unauthorized)));
+        assert!(matches!(restarted.resolve_outbox_entry(RPC_CONTRACT_WALLET.to_string(), entry_id + 1, true, None), Err(ContractError::OutboxEntryNotFound)));
+        
+        // The owner found the transfer sent, so the withdrawal completes without another one
+        let event = restarted.resolve_outbox_entry(RPC_CONTRACT_WALLET.to_string(), entry_id, true, None).unwrap();
+        assert!(matches!(event, Event::FeeCollected { fee_amount: 1_000, remaining_fees: 0, .. }));
+        assert_eq!(fresh.pending_queue_depth(), depth);
+        assert!(restarted.get_outbox().is_empty());
+        assert_eq!(restarted.fee_ledger.sweeps.len(), 1);
+    }
+    
+    #[test]
+    fn test_outbox_claim_survives_a_crash_between_commit_and_drain() {
+        use bitcoincore_rpc::bitcoin::hashes::{sha256, Hash};
+        
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        let secret = b"gift card secret".to_vec();
+        contract.deposit_claimable("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, sha256::Hash::hash(&secret).to_byte_array()).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        
+        let mut state = contract.export_state();
+        state.owner = RPC_CONTRACT_WALLET.to_string();
+        state.fee_collector_address = RPC_CONTRACT_WALLET.to_string();
+        state.supported_tokens = vec![TokenType::Bitcoin];
+        
+        let (transfer, _transport) = shared_batching_transfer();
+        let mut contract = TimeLockedDeposit::from_state(state, transfer.clone()).unwrap();
+        contract.set_clock(clock);
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        
+        // The claim is committed with its payout before the payout is dispatched
+        contract.claim_deposit(RPC_RECIPIENT.to_string(), 1, secret).unwrap();
+        let committed = store.committed_before_drain();
+        assert_eq!(committed.outbox[0].operation, OutboxOperation::Claim);
+        assert_eq!(committed.deposits[0].status, DepositStatus::WithdrawalPending);
+        assert_eq!(transfer.queued_payouts(), vec![1]);
+        
+        // Crash before the drain: recovery pays the claimer once and records the claim
+        let (fresh, _) = shared_batching_transfer();
+        let mut restarted = TimeLockedDeposit::from_state(committed, fresh.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(&events[..], [Event::Withdrawn { deposit_id: 1, claimed_by: Some(claimer), .. }] if claimer == RPC_RECIPIENT));
+        assert_eq!(fresh.queued_payouts(), vec![1]);
+        assert_eq!(restarted.deposit_registry[&1].claimed_by.as_deref(), Some(RPC_RECIPIENT));
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        restarted.check_accounting_invariants().unwrap();
+    }
+    
+    #[test]
+    fn test_outbox_inheritance_claim_survives_a_crash_between_commit_and_drain() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 10_000, 30, None).unwrap();
+        contract.set_inheritance("depositor_address".to_string(), 1, RPC_RECIPIENT.to_string(), 90).unwrap();
+        let last_activity = contract.get_last_activity("depositor_address").unwrap();
+        clock.advance(chrono::Duration::days(121));
+        
+        let mut state = contract.export_state();
+        state.owner = RPC_CONTRACT_WALLET.to_string();
+        state.fee_collector_address = RPC_CONTRACT_WALLET.to_string();
+        state.supported_tokens = vec![TokenType::Bitcoin];
+        
+        let (transfer, _transport) = shared_batching_transfer();
+        let mut contract = TimeLockedDeposit::from_state(state, transfer.clone()).unwrap();
+        contract.set_clock(clock);
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        
+        // The claim is committed with its payout and the activity it was allowed on
+        contract.claim_inherited(RPC_RECIPIENT.to_string(), 1).unwrap();
+        let committed = store.committed_before_drain();
+        assert_eq!(committed.outbox[0].operation, OutboxOperation::InheritanceClaim { last_activity });
+        assert_eq!(committed.outbox[0].transfer_request.deposit_id, Some(1));
+        assert_eq!(committed.deposits[0].status, DepositStatus::WithdrawalPending);
+        
+        // Crash before the drain: recovery pays the beneficiary once and records the claim
+        let (fresh, _) = shared_batching_transfer();
+        let mut restarted = TimeLockedDeposit::from_state(committed, fresh.clone()).unwrap();
+        let events = restarted.recover_in_flight().unwrap();
+        assert!(matches!(
+            &events[..],
+            [Event::InheritanceClaimed { deposit_id: 1, last_activity: at, .. }] if *at == last_activity
+        ));
+        assert_eq!(fresh.pending_queue_depth(), 1);
+        assert_eq!(restarted.deposit_registry[&1].claimed_by.as_deref(), Some(RPC_RECIPIENT));
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert_eq!(fresh.pending_queue_depth(), 1);
+        restarted.check_accounting_invariants().unwrap();
+    }
+    
+    #[test]
+    fn test_outbox_referral_reward_waits_for_the_owner_after_a_crash() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.set_referral_share("owner_address".to_string(), 20, None).unwrap();
+        contract.deposit_with_referrer("depositor_address".to_string(), TokenType::Bitcoin, 1000, 30, None, "referrer_address".to_string()).unwrap();
+        contract.emergency_withdraw("depositor_address".to_string(), 1).unwrap();
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        
+        // The balance is taken off in the same write that commits its payout
+        contract.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin).unwrap();
+        let committed = store.committed_before_drain();
+        assert_eq!(committed.outbox[0].operation, OutboxOperation::ReferralReward);
+        assert_eq!(committed.outbox[0].transfer_request.amount, 20);
+        assert!(committed.referral_balances.is_empty());
+        
+        // The reward carries no deposit ID, so after a crash it waits for the owner and cannot be claimed twice
+        let mut restarted = TimeLockedDeposit::from_state(committed, contract_with_clock(clock.clone()).token_transfer).unwrap();
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert!(matches!(
+            restarted.claim_referral_rewards("referrer_address".to_string(), TokenType::Bitcoin),
+            Err(ContractError::InvalidAmount { .. })
+        ));
+        
+        let entry_id = restarted.get_outbox()[0].entry_id;
+        let event = restarted.resolve_outbox_entry("owner_address".to_string(), entry_id, true, None).unwrap();
+        assert!(matches!(event, Event::ReferralRewardClaimed { amount: 20, .. }));
+        assert!(restarted.get_outbox().is_empty());
+        assert_eq!(restarted.referral_balance("referrer_address", &TokenType::Bitcoin), 0);
+    }
+    
+    /// Keys of the vault and the depositor in the MuSig2 escrow tests
+    fn musig_keys() -> (secp256k1::SecretKey, secp256k1::SecretKey) {
+        (secp256k1::SecretKey::from_slice(&[0x11; 32]).unwrap(), secp256k1::SecretKey::from_slice(&[0x22; 32]).unwrap())
+    }
+    
+    /// Multisig client on a mock node, optionally persisting MuSig2 sessions
+    fn musig_client(session_store: Option<(&std::path::Path, &crate::store::StateKey)>) -> MultisigClient {
+        let config = BitcoinTestnetConfig::new(
+            "http://localhost:18332".to_string(),
+            "testuser".to_string(),
+            "testpassword".to_string(),
+            RPC_CONTRACT_WALLET.to_string(),
+        );
+        let client = bitcoincore_rpc::Client::from_jsonrpc(bitcoincore_rpc::jsonrpc::Client::with_transport(MockRpcTransport::default()));
+        let client = MultisigClient::new(BitcoinRpcClient::from_client(client, &config), Network::Testnet);
+        
+        let (vault, depositor) = musig_keys();
+        let secp = secp256k1::Secp256k1::new();
+        let mut client = match session_store {
+            Some((dir, key)) => client.with_session_store(crate::store::EncryptedStateStore::open(dir, key.clone()).unwrap()).unwrap(),
+            None => client,
+        };
+        client.create_musig_wallet(
+            "escrow",
+            &vault.public_key(&secp).to_string(),
+            &depositor.public_key(&secp).to_string(),
+            2_500_000,
+        ).unwrap();
+        client
+    }
+    
+    /// Transaction spending a 100,000 sat output of `policy` back to it
+    fn musig_spending_tx(
+        policy: &MusigEscrowPolicy,
+        lock_time: u32,
+        sequence: bitcoincore_rpc::bitcoin::Sequence,
+    ) -> (bitcoincore_rpc::bitcoin::Transaction, Vec<bitcoincore_rpc::bitcoin::TxOut>) {
+        use bitcoincore_rpc::bitcoin::{absolute::LockTime, OutPoint, ScriptBuf, Transaction, TxIn, TxOut, Witness};
+        
+        let prevout = TxOut { value: 100_000, script_pubkey: policy.address(Network::Testnet).unwrap().script_pubkey() };
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![TxIn {
+                previous_output: <OutPoint as std::str::FromStr>::from_str(&format!("{}:0", "ab".repeat(32))).unwrap(),
+                script_sig: ScriptBuf::new(),
+                sequence,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut { value: 99_000, script_pubkey: prevout.script_pubkey.clone() }],
+        };
+        (tx, vec![prevout])
+    }
+    
+    #[test]
+    fn test_musig_key_aggregation_matches_bip327_vectors() {
+        let keys: Vec<secp256k1::PublicKey> = [
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "03DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+            "023590A94E768F8E1815C2F24B4D80A8E3149316C3518CE7B7AD338368D038CA66",
+        ].iter().map(|key| <secp256k1::PublicKey as std::str::FromStr>::from_str(key).unwrap()).collect();
+        
+        let aggregate = |order: &[usize]| {
+            let ordered: Vec<_> = order.iter().map(|index| keys[*index]).collect();
+            MusigKeyAgg::new(&ordered).unwrap().x_only_key().to_string().to_uppercase()
+        };
+        assert_eq!(aggregate(&[0, 1, 2]), "90539EEDE565F5D054F32CC0C220126889ED1E5D193BAF15AEF344FE59D4610C");
+        assert_eq!(aggregate(&[2, 1, 0]), "6204DE8B083426DC6EAF9502D27024D53FC826BF7D2012148A0575435DF54B2B");
+        assert_eq!(aggregate(&[0, 0, 0]), "B436E3BAD62B8CD409969A224731C193D051162D8C5AE8B109306127DA3AA935");
+        assert_eq!(aggregate(&[0, 0, 1, 1]), "69BC22BFA5D106306E48A20679DE1D7389386124D07571D0D872686028C26A3E");
+        assert!(MusigKeyAgg::new(&[]).is_err());
+    }
+    
+    #[test]
+    fn test_musig_nonce_generation_matches_bip327_vectors() {
+        use crate::bitcoin::signature::MusigSecretNonce;
+        
+        let bytes = |value: &str| -> [u8; 32] { hex::decode(value).unwrap().try_into().unwrap() };
+        let random = bytes("0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F0F");
+        let secret_key = secp256k1::SecretKey::from_slice(&bytes("0202020202020202020202020202020202020202020202020202020202020202")).unwrap();
+        let public_key = secp256k1::PublicKey::from_slice(&hex::decode("024D4B6CD1361032CA9BD2AEB9D900AA4D45D9EAD80AC9423374C451A7254D0766").unwrap()).unwrap();
+        let aggregate_key = secp256k1::XOnlyPublicKey::from_slice(&bytes("0707070707070707070707070707070707070707070707070707070707070707")).unwrap();
+        let message = bytes("0101010101010101010101010101010101010101010101010101010101010101");
+        let extra_input = bytes("0808080808080808080808080808080808080808080808080808080808080808");
+        
+        let (secret_nonce, public_nonce) = MusigSecretNonce::derive(
+            &random, Some(&secret_key), &public_key, Some(&aggregate_key), Some(&message), &extra_input,
+        ).unwrap();
+        assert_eq!(hex::encode_upper(secret_nonce.serialize()), "B114E502BEAA4E301DD08A50264172C84E41650E6CB726B410C0694D59EFFB6495B5CAF28D045B973D63E3C99A44B807BDE375FD6CB39E46DC4A511708D0E9D2024D4B6CD1361032CA9BD2AEB9D900AA4D45D9EAD80AC9423374C451A7254D0766");
+        assert_eq!(hex::encode_upper(public_nonce.serialize()), "02F7BE7089E8376EB355272368766B17E88E7DB72047D05E56AA881EA52B3B35DF02C29C8046FDD0DED4C7E55869137200FBDBFE2EB654267B6D7013602CAED3115A");
+        
+        // Every optional input left out
+        let public_key = secp256k1::PublicKey::from_slice(&hex::decode("02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9").unwrap()).unwrap();
+        let (secret_nonce, public_nonce) = MusigSecretNonce::derive(&random, None, &public_key, None, None, &[]).unwrap();
+        assert_eq!(hex::encode_upper(secret_nonce.serialize()), "89BDD787D0284E5E4D5FC572E49E316BAB7E21E3B1830DE37DFE80156FA41A6D0B17AE8D024C53679699A6FD7944D9C4A366B514BAF43088E0708B1023DD289702F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9");
+        assert_eq!(hex::encode_upper(public_nonce.serialize()), "02C96E7CB1E8AA5DAC64D872947914198F607D90ECDE5200DE52978AD5DED63C000299EC5117C2D29EDEE8A2092587C3909BE694D5CFF0667D6C02EA4059F7CD9786");
+    }
+    
+    #[test]
+    fn test_musig_partial_signatures_match_bip327_vectors() {
+        use crate::bitcoin::signature::{musig_partial_sign, musig_partial_verify, MusigAggNonce, MusigPublicNonce, MusigSecretNonce};
+        
+        let secret_key = secp256k1::SecretKey::from_slice(&hex::decode("7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671").unwrap()).unwrap();
+        let public_keys = [
+            "03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA661",
+            "020000000000000000000000000000000000000000000000000000000000000007",
+        ];
+        let secret_nonces = [
+            "508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F703935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000003935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+        ];
+        let public_nonces = [
+            "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+            "0237C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0387BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+            "0200000000000000000000000000000000000000000000000000000000000000090287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+        ];
+        let aggregate_nonces = [
+            "028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61037496A3CC86926D452CAFCFD55D25972CA1675D549310DE296BFF42F72EEEA8C9",
+            "000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000",
+            "048465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61037496A3CC86926D452CAFCFD55D25972CA1675D549310DE296BFF42F72EEEA8C9",
+            "028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61020000000000000000000000000000000000000000000000000000000000000009",
+            "028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD6102FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFC30",
+        ];
+        let message: [u8; 32] = hex::decode("F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF").unwrap().try_into().unwrap();
+        
+        let key = |index: usize| secp256k1::PublicKey::from_slice(&hex::decode(public_keys[index]).unwrap());
+        let public_nonce = |index: usize| MusigPublicNonce::from_slice(&hex::decode(public_nonces[index]).unwrap());
+        let aggregate_nonce = |index: usize| MusigAggNonce::from_slice(&hex::decode(aggregate_nonces[index]).unwrap());
+        let key_agg = |indices: &[usize]| {
+            let keys: Vec<_> = indices.iter().map(|index| key(*index).unwrap()).collect();
+            MusigKeyAgg::new(&keys).unwrap()
+        };
+        let secret_nonce = || MusigSecretNonce::from_slice(&hex::decode(secret_nonces[0]).unwrap()).unwrap();
+        
+        // Key indices, nonce indices, aggregate nonce index, signer index and partial signature
+        let valid: [(&[usize], &[usize], usize, usize, &str); 4] = [
+            (&[0, 1, 2], &[0, 1, 2], 0, 0, "012ABBCB52B3016AC03AD82395A1A415C48B93DEF78718E62A7A90052FE224FB"),
+            (&[1, 0, 2], &[1, 0, 2], 0, 1, "9FF2F7AAA856150CC8819254218D3ADEEB0535269051897724F9DB3789513A52"),
+            (&[1, 2, 0], &[1, 2, 0], 0, 2, "FA23C359F6FAC4E7796BB93BC9F0532A95468C539BA20FF86D7C76ED92227900"),
+            (&[0, 1], &[0, 3], 1, 0, "AE386064B26105404798F75DE2EB9AF5EDA5387B064B83D049CB7C5E08879531"),
+        ];
+        for (keys, nonces, aggregate_index, signer, expected) in valid {
+            let aggregate = aggregate_nonce(aggregate_index).unwrap();
+            let nonces: Vec<_> = nonces.iter().map(|index| public_nonce(*index).unwrap()).collect();
+            assert_eq!(MusigAggNonce::new(&nonces), aggregate);
+            
+            let key_agg = key_agg(keys);
+            let partial = musig_partial_sign(secret_nonce(), &secret_key, &key_agg, &aggregate, &message).unwrap();
+            assert_eq!(hex::encode_upper(partial), expected);
+            assert!(musig_partial_verify(&partial, &nonces[signer], &key(keys[signer]).unwrap(), &key_agg, &aggregate, &message));
+        }
+        
+        // Signing refuses a key list without the signer, an invalid key,
+        // invalid aggregate nonces and a nonce zeroed after use
+        assert!(musig_partial_sign(secret_nonce(), &secret_key, &key_agg(&[1, 2]), &aggregate_nonce(0).unwrap(), &message).is_err());
+        assert!(key(3).is_err());
+        assert!((2..5).all(|index| aggregate_nonce(index).is_err()));
+        assert!(MusigSecretNonce::from_slice(&hex::decode(secret_nonces[1]).unwrap()).is_err());
+        
+        // Verification fails for a wrong signature, the wrong signer and a
+        // signature past the curve order, and an invalid nonce is refused
+        let key_agg = key_agg(&[0, 1, 2]);
+        let aggregate = aggregate_nonce(0).unwrap();
+        let invalid = [
+            ("97AC833ADCB1AFA42EBF9E0725616F3C9A0D5B614F6FE283CEAAA37A8FFAF406", 0),
+            ("68537CC5234E505BD14061F8DA9E90C220A181855FD8BDB7F127BB12403B4D3B", 1),
+            ("FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141", 0),
+        ];
+        for (partial, signer) in invalid {
+            let partial: [u8; 32] = hex::decode(partial).unwrap().try_into().unwrap();
+            assert!(!musig_partial_verify(&partial, &public_nonce(signer).unwrap(), &key(signer).unwrap(), &key_agg, &aggregate, &message));
+        }
+        assert!(public_nonce(4).is_err());
+    }
+    
+    #[test]
+    fn test_musig_tweaks_match_bip327_vectors() {
+        use crate::bitcoin::signature::{musig_partial_sign, musig_partial_verify, MusigAggNonce, MusigPublicNonce, MusigSecretNonce};
+        
+        let secret_key = secp256k1::SecretKey::from_slice(&hex::decode("7FB9E0E687ADA1EEBF7ECFE2F21E73EBDB51A7D450948DFE8D76D7F2D1007671").unwrap()).unwrap();
+        let secret_nonce = "508B81A611F100A6B2B6B29656590898AF488BCF2E1F55CF22E5CFB84421FE61FA27FD49B1D50085B481285E1CA205D55C82CC1B31FF5CD54A489829355901F703935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9";
+        let aggregate = MusigAggNonce::from_slice(&hex::decode("028465FCF0BBDBCF443AABCCE533D42B4B5A10966AC09A49655E8C42DAAB8FCD61037496A3CC86926D452CAFCFD55D25972CA1675D549310DE296BFF42F72EEEA8C9").unwrap()).unwrap();
+        let message: [u8; 32] = hex::decode("F95466D086770E689964664219266FE5ED215C92AE20BAB5C9D79ADDDDF3C0CF").unwrap().try_into().unwrap();
+        let public_keys = [
+            "03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "02F9308A019258C31049344F85F89D5229B531C845836F99B08601F113BCE036F9",
+            "02DFF1D77F2A671C5F36183726DB2341BE58FEAE1DA2DECED843240F7B502BA659",
+        ];
+        let public_nonces = [
+            "0337C87821AFD50A8644D820A8F3E02E499C931865C2360FB43D0A0D20DAFE07EA0287BF891D2A6DEAEBADC909352AA9405D1428C15F4B75F04DAE642A95C2548480",
+            "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F817980279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+            "032DE2662628C90B03F5E720284EB52FF7D71F4284F627B68A853D78C78E1FFE9303E4C5524E83FFE1493B9077CF1CA6BEB2090C93D930321071AD40B2F44E599046",
+        ];
+        let tweaks = [
+            "E8F791FF9225A2AF0102AFFF4A9A723D9612A682A25EBE79802B263CDFCD83BB",
+            "AE2EA797CC0FE72AC5B97B97F3C6957D7E4199A167A58EB08BCAFFDA70AC0455",
+            "F52ECBC565B3D8BEA2DFD5B75A4F457E54369809322E4120831626F290FA87E0",
+            "1969AD73CC177FA0B4FCED6DF1F7BF9907E665FDE9BA196A74FED0A3CF5AEF9D",
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141",
+        ];
+        
+        let keys: Vec<_> = [1, 2, 0].iter().map(|index| secp256k1::PublicKey::from_slice(&hex::decode(public_keys[*index]).unwrap()).unwrap()).collect();
+        let nonces: Vec<_> = [1, 2, 0].iter().map(|index| MusigPublicNonce::from_slice(&hex::decode(public_nonces[*index]).unwrap()).unwrap()).collect();
+        assert_eq!(MusigAggNonce::new(&nonces), aggregate);
+        let untweaked = MusigKeyAgg::new(&keys).unwrap();
+        let tweak = |index: usize| -> [u8; 32] { hex::decode(tweaks[index]).unwrap().try_into().unwrap() };
+        
+        // Tweaks in order, each with whether it is x-only, and the partial
+        // signature of the last signer
+        let valid: [(&[(usize, bool)], &str); 5] = [
+            (&[(0, true)], "E28A5C66E61E178C2BA19DB77B6CF9F7E2F0F56C17918CD13135E60CC848FE91"),
+            (&[(0, false)], "38B0767798252F21BF5702C48028B095428320F73A4B14DB1E25DE58543D2D2D"),
+            (&[(0, false), (1, true)], "408A0A21C4A0F5DACAF9646AD6EB6FECD7F7A11F03ED1F48DFFF2185BC2C2408"),
+            (&[(0, false), (1, false), (2, true), (3, true)], "45ABD206E61E3DF2EC9E264A6FEC8292141A633C28586388235541F9ADE75435"),
+            (&[(0, true), (1, false), (2, true), (3, false)], "B255FDCAC27B40C7CE7848E2D3B7BF5EA0ED756DA81565AC804CCCA3E1D5D239"),
+        ];
+        for (applied, expected) in valid {
+            let key_agg = applied.iter().fold(untweaked.clone(), |key_agg, (index, x_only)| key_agg.with_tweak(&tweak(*index), *x_only).unwrap());
+            let secret_nonce = MusigSecretNonce::from_slice(&hex::decode(secret_nonce).unwrap()).unwrap();
+            let partial = musig_partial_sign(secret_nonce, &secret_key, &key_agg, &aggregate, &message).unwrap();
+            assert_eq!(hex::encode_upper(partial), expected);
+            assert!(musig_partial_verify(&partial, &nonces[2], &keys[2], &key_agg, &aggregate, &message));
+        }
+        
+        // A tweak past the curve order
+        assert!(untweaked.with_tweak(&tweak(4), false).is_err());
+    }
+    
+    #[test]
+    fn test_musig_signature_aggregation_matches_bip327_vectors() {
+        use crate::bitcoin::signature::{musig_aggregate, MusigAggNonce};
+        
+        let public_keys = [
+            "03935F972DA013F80AE011890FA89B67A27B7BE6CCB24D3274D18B2D4067F261A9",
+            "02D2DC6F5DF7C56ACF38C7FA0AE7A759AE30E19B37359DFDE015872324C7EF6E05",
+            "03C7FB101D97FF930ACD0C6760852EF64E69083DE0B06AC6335724754BB4B0522C",
+            "02352433B21E7E05D3B452B81CAE566E06D2E003ECE16D1074AABA4289E0E3D581",
+        ];
+        let tweaks = [
+            "B511DA492182A91B0FFB9A98020D55F260AE86D7ECBD0399C7383D59A5F2AF7C",
+            "A815FE049EE3C5AAB66310477FBC8BCCCAC2F3395F59F921C364ACD78A2F48DC",
+            "75448A87274B056468B977BE06EB1E9F657577B7320B0A3376EA51FD420D18A8",
+        ];
+        let partials = [
+            "B15D2CD3C3D22B04DAE438CE653F6B4ECF042F42CFDED7C41B64AAF9B4AF53FB",
+            "6193D6AC61B354E9105BBDC8937A3454A6D705B6D57322A5A472A02CE99FCB64",
+            "9A87D3B79EC67228CB97878B76049B15DBD05B8158D17B5B9114D3C226887505",
+            "66F82EA90923689B855D36C6B7E032FB9970301481B99E01CDB4D6AC7C347A15",
+            "4F5AEE41510848A6447DCD1BBC78457EF69024944C87F40250D3EF2C25D33EFE",
+            "DDEF427BBB847CC027BEFF4EDB01038148917832253EBC355FC33F4A8E2FCCE4",
+            "97B890A26C981DA8102D3BC294159D171D72810FDF7C6A691DEF02F0F7AF3FDC",
+            "53FA9E08BA5243CBCB0D797C5EE83BC6728E539EB76C2D0BF0F971EE4E909971",
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEBAAEDCE6AF48A03BBFD25E8CD0364141",
+        ];
+        let message: [u8; 32] = hex::decode("599C67EA410D005B9DA90817CF03ED3B1C868E4DA4EDF00A5880B0082C237869").unwrap().try_into().unwrap();
+        
+        let aggregate = |keys: &[usize], applied: &[(usize, bool)], nonce: &str, signers: &[usize]| {
+            let keys: Vec<_> = keys.iter().map(|index| secp256k1::PublicKey::from_slice(&hex::decode(public_keys[*index]).unwrap()).unwrap()).collect();
+            let key_agg = applied.iter().fold(MusigKeyAgg::new(&keys).unwrap(), |key_agg, (index, x_only)| {
+                key_agg.with_tweak(&hex::decode(tweaks[*index]).unwrap().try_into().unwrap(), *x_only).unwrap()
+            });
+            let nonce = MusigAggNonce::from_slice(&hex::decode(nonce).unwrap()).unwrap();
+            let partials: Vec<[u8; 32]> = signers.iter().map(|index| hex::decode(partials[*index]).unwrap().try_into().unwrap()).collect();
+            musig_aggregate(&partials, &key_agg, &nonce, &message).map(|signature| (signature, key_agg.x_only_key()))
+        };
+        
+        // Key indices, tweaks, aggregate nonce, partial signature indices and signature
+        let valid: [(&[usize], &[(usize, bool)], &str, &[usize], &str); 4] = [
+            (&[0, 1], &[], "0341432722C5CD0268D829C702CF0D1CBCE57033EED201FD335191385227C3210C03D377F2D258B64AADC0E16F26462323D701D286046A2EA93365656AFD9875982B", &[0, 1], "041DA22223CE65C92C9A0D6C2CAC828AAF1EEE56304FEC371DDF91EBB2B9EF0912F1038025857FEDEB3FF696F8B99FA4BB2C5812F6095A2E0004EC99CE18DE1E"),
+            (&[0, 2], &[], "0224AFD36C902084058B51B5D36676BBA4DC97C775873768E58822F87FE437D792028CB15929099EEE2F5DAE404CD39357591BA32E9AF4E162B8D3E7CB5EFE31CB20", &[2, 3], "1069B67EC3D2F3C7C08291ACCB17A9C9B8F2819A52EB5DF8726E17E7D6B52E9F01800260A7E9DAC450F4BE522DE4CE12BA91AEAF2B4279219EF74BE1D286ADD9"),
+            (&[0, 2], &[(0, false)], "0208C5C438C710F4F96A61E9FF3C37758814B8C3AE12BFEA0ED2C87FF6954FF186020B1816EA104B4FCA2D304D733E0E19CEAD51303FF6420BFD222335CAA402916D", &[4, 5], "5C558E1DCADE86DA0B2F02626A512E30A22CF5255CAEA7EE32C38E9A71A0E9148BA6C0E6EC7683B64220F0298696F1B878CD47B107B81F7188812D593971E0CC"),
+            (&[0, 3], &[(0, true), (1, false), (2, true)], "02B5AD07AFCD99B6D92CB433FBD2A28FDEB98EAE2EB09B6014EF0F8197CD58403302E8616910F9293CF692C49F351DB86B25E352901F0E237BAFDA11F1C1CEF29FFD", &[6, 7], "839B08820B681DBA8DAF4CC7B104E8F2638F9388F8D7A555DC17B6E6971D7426CE07BF6AB01F1DB50E4E33719295F4094572B79868E440FB3DEFD3FAC1DB589E"),
+        ];
+        let secp = secp256k1::Secp256k1::verification_only();
+        for (keys, applied, nonce, signers, expected) in valid {
+            let (signature, aggregate_key) = aggregate(keys, applied, nonce, signers).unwrap();
+            assert_eq!(signature.to_string().to_uppercase(), expected);
+            assert!(secp.verify_schnorr(&signature, &secp256k1::Message::from_slice(&message).unwrap(), &aggregate_key).is_ok());
+        }
+        
+        // A partial signature past the curve order
+        assert!(aggregate(&[0, 3], &[(0, true), (1, false), (2, true)], "02B5AD07AFCD99B6D92CB433FBD2A28FDEB98EAE2EB09B6014EF0F8197CD58403302E8616910F9293CF692C49F351DB86B25E352901F0E237BAFDA11F1C1CEF29FFD", &[7, 8]).is_err());
+    }
+    
+    #[test]
+    fn test_musig_escrow_spends_cooperatively_and_by_recovery() {
+        use bitcoincore_rpc::bitcoin::hashes::Hash;
+        use bitcoincore_rpc::bitcoin::sighash::{Prevouts, SighashCache, TapSighashType};
+        use bitcoincore_rpc::bitcoin::Sequence;
+        
+        let secp = secp256k1::Secp256k1::new();
+        let (vault_key, depositor_key) = musig_keys();
+        let mut vault = musig_client(None);
+        let mut depositor = musig_client(None);
+        
+        // The wallet pays to a taproot output described by its own descriptor
+        let wallet = vault.get_wallet("escrow").unwrap().clone();
+        let policy = wallet.musig_policy().unwrap();
+        assert_eq!(wallet.kind, WalletKind::MusigTaproot);
+        assert!(wallet.address.starts_with("tb1p"));
+        assert!(wallet.policy().is_err());
+        assert_eq!(MusigEscrowPolicy::parse(&wallet.descriptor().unwrap()).unwrap(), policy);
+        assert_eq!(descriptor_address(&wallet.descriptor().unwrap(), Network::Testnet).unwrap().to_string(), wallet.address);
+        assert!(wallet.descriptor().unwrap().starts_with(&format!("tr(musig({},{}),and_v(v:pk(", wallet.public_keys[0], wallet.public_keys[1])));
+        let output_key = policy.spend_info().unwrap().output_key().to_inner();
+        
+        // Cooperative spend: both sides exchange nonces, then partial signatures
+        let (tx, prevouts) = musig_spending_tx(&policy, 0, Sequence::MAX);
+        let sighash = SighashCache::new(&tx)
+            .taproot_key_spend_signature_hash(0, &Prevouts::All(&prevouts), TapSighashType::Default)
+            .unwrap()
+            .to_byte_array();
+        let vault_nonce = vault.start_musig_spend("escrow", "payout-1", &sighash, &vault_key).unwrap().public_nonce;
+        let depositor_nonce = depositor.start_musig_spend("escrow", "payout-1", &sighash, &depositor_key).unwrap().public_nonce;
+        let vault_partial = vault.sign_musig_spend("payout-1", &depositor_nonce, &vault_key).unwrap();
+        let depositor_partial = depositor.sign_musig_spend("payout-1", &vault_nonce, &depositor_key).unwrap();
+        
+        // A tampered partial signature is caught before aggregating
+        let mut tampered = hex::decode(&depositor_partial).unwrap();
+        tampered[31] ^= 1;
+        assert!(vault.complete_musig_spend("payout-1", &hex::encode(tampered)).is_err());
+        
+        // Both sides end up with one signature, valid for the output key
+        let signature = vault.complete_musig_spend("payout-1", &depositor_partial).unwrap();
+        assert_eq!(depositor.complete_musig_spend("payout-1", &vault_partial).unwrap(), signature);
+        assert_eq!(vault.musig_spend("payout-1").unwrap().stage, MusigSpendStage::Complete);
+        let signature = secp256k1::schnorr::Signature::from_slice(&hex::decode(&signature).unwrap()).unwrap();
+        let message = secp256k1::Message::from_slice(&sighash).unwrap();
+        assert!(secp.verify_schnorr(&signature, &message, &output_key).is_ok());
+        assert_eq!(MusigEscrowPolicy::key_path_witness(&signature).len(), 1);
+        
+        // Recovery: the depositor alone, only with a lock time at the lock height
+        let (early, prevouts) = musig_spending_tx(&policy, 2_499_999, Sequence::ENABLE_LOCKTIME_NO_RBF);
+        assert!(matches!(policy.recovery_sighash(&early, 0, &prevouts), Err(ContractError::InvalidDescriptor(_))));
+        let (final_sequence, prevouts) = musig_spending_tx(&policy, 2_500_000, Sequence::MAX);
+        assert!(policy.recovery_sighash(&final_sequence, 0, &prevouts).is_err());
+        
+        let (recovery, prevouts) = musig_spending_tx(&policy, 2_500_000, Sequence::ENABLE_LOCKTIME_NO_RBF);
+        let sighash = policy.recovery_sighash(&recovery, 0, &prevouts).unwrap();
+        let keypair = secp256k1::KeyPair::from_secret_key(&secp, &depositor_key);
+        let signature = secp.sign_schnorr_no_aux_rand(&secp256k1::Message::from_slice(&sighash).unwrap(), &keypair);
+        let witness = policy.recovery_witness(&signature).unwrap();
+        
+        // The witness reveals the leaf, which the output commits to, signed by the depositor
+        assert_eq!(witness.len(), 3);
+        let control_block = policy.recovery_control_block().unwrap();
+        assert!(control_block.verify_taproot_commitment(&secp, output_key, &policy.recovery_script()));
+        assert!(secp.verify_schnorr(&signature, &secp256k1::Message::from_slice(&sighash).unwrap(), &keypair.x_only_public_key().0).is_ok());
+    }
+    
+    #[test]
+    fn test_musig_session_resumes_after_restart_and_never_reuses_nonce() {
+        use crate::store::{EncryptedStateStore, StateKey};
+        use crate::store::encrypted::MUSIG_SESSIONS_BLOB;
+        
+        let dir = tempfile::tempdir().unwrap();
+        let state_key = StateKey::generate();
+        let sessions = Some((dir.path(), &state_key));
+        let (vault_key, depositor_key) = musig_keys();
+        let message = [7u8; 32];
+        
+        let mut vault = musig_client(sessions);
+        let mut depositor = musig_client(None);
+        let vault_nonce = vault.start_musig_spend("escrow", "sweep-7", &message, &vault_key).unwrap().public_nonce;
+        let depositor_nonce = depositor.start_musig_spend("escrow", "sweep-7", &message, &depositor_key).unwrap().public_nonce;
+        
+        // The secret nonce is only ever on disk sealed
+        let blob = dir.path().join(format!("{}.enc", MUSIG_SESSIONS_BLOB));
+        let sealed = String::from_utf8_lossy(&std::fs::read(&blob).unwrap()).into_owned();
+        assert!(!sealed.contains("secret_nonce") && !sealed.contains("sweep-7"));
+        assert!(EncryptedStateStore::open(dir.path(), StateKey::generate()).unwrap().read_blob(MUSIG_SESSIONS_BLOB).is_err());
+        
+        // After a restart the session hands out the nonce it already sent, for the same message only
+        let mut vault = musig_client(sessions);
+        assert_eq!(vault.start_musig_spend("escrow", "sweep-7", &message, &vault_key).unwrap().public_nonce, vault_nonce);
+        assert!(vault.start_musig_spend("escrow", "sweep-7", &[8u8; 32], &vault_key).is_err());
+        let partial = vault.sign_musig_spend("sweep-7", &depositor_nonce, &vault_key).unwrap();
+        
+        // The secret nonce is gone from the store once it has signed
+        let store = EncryptedStateStore::open(dir.path(), state_key.clone()).unwrap();
+        let stored: serde_json::Value = serde_json::from_slice(store.read_blob(MUSIG_SESSIONS_BLOB).unwrap().unwrap().expose_secret()).unwrap();
+        assert!(stored["spends"]["sweep-7"]["secret_nonce"].is_null());
+        
+        // After another restart the same peer nonce gets the same signature, any other one nothing
+        let mut vault = musig_client(sessions);
+        assert_eq!(vault.sign_musig_spend("sweep-7", &depositor_nonce, &vault_key).unwrap(), partial);
+        let other_nonce = depositor.start_musig_spend("escrow", "sweep-8", &message, &depositor_key).unwrap().public_nonce;
+        assert!(matches!(
+            vault.sign_musig_spend("sweep-7", &other_nonce, &vault_key),
+            Err(ContractError::MusigNonceReused { ref spend_id }) if spend_id == "sweep-7"
+        ));
+        
+        // The session still completes with the nonce exchange it signed for
+        let depositor_partial = depositor.sign_musig_spend("sweep-7", &vault_nonce, &depositor_key).unwrap();
+        let signature = vault.complete_musig_spend("sweep-7", &depositor_partial).unwrap();
+        let vault = musig_client(sessions);
+        assert_eq!(vault.musig_spend("sweep-7").unwrap().signature.as_deref(), Some(signature.as_str()));
+        assert_eq!(vault.musig_spends().len(), 1);
+    }
+    
+    /// Payouts made by the mock: recipient and amount, in order
+    type RecordedPayouts = Arc<std::sync::Mutex<Vec<(String, u64)>>>;
+    
+    /// Create a contract with a permissive mock recording the payouts it makes
+    fn contract_recording_payouts(clock: Arc<MockClock>) -> (TimeLockedDeposit<MockTokenTransferMock>, RecordedPayouts) {
+        let payouts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut mock = MockTokenTransferMock::new();
+        mock.expect_validate_address().returning(|_| Ok(()));
+        mock.expect_supports_token_type().returning(|_| true);
+        mock.expect_get_balance().returning(|_, _| Ok(1_000_000));
+        mock.expect_transfer_to_contract().returning(|_, _, _| Ok(()));
+        let recorded = payouts.clone();
+        mock.expect_transfer_from_contract().returning(move |address, _, amount| {
+            recorded.lock().unwrap().push((address.to_string(), amount));
+            Ok(())
+        });
+        
+        let mut contract = TimeLockedDeposit::new_with_defaults("owner_address".to_string(), 10, mock).unwrap();
+        contract.set_clock(clock);
+        (contract, payouts)
+    }
+    
+    #[test]
+    fn test_refunds_of_rejected_deposits_deduct_the_network_fee_once() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, _) = contract_recording_payouts(clock.clone());
+        let store = Arc::new(RecordingStateStore::default());
+        contract.set_state_store(store.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 100_000, 30, None).unwrap();
+        let committed = store.committed_before_drain();
+        
+        // After a crash before the drain the owner lowered the limits, so the funded deposit no longer fits
+        let (fresh, payouts) = contract_recording_payouts(clock.clone());
+        let mut restarted = TimeLockedDeposit::from_state(committed, fresh.token_transfer).unwrap();
+        restarted.set_clock(clock.clone());
+        restarted.deposit_limits.max_total_deposits = Some(50_000);
+        let policy = RefundPolicy { network_fee_deduction: 1_500, ..RefundPolicy::default() };
+        assert!(matches!(
+            restarted.set_refund_policy("depositor_address".to_string(), policy.clone(), None),
+            Err(ContractError::Unauthorized)
+        ));
+        restarted.set_refund_policy("owner_address".to_string(), policy, None).unwrap();
+        let sink = Arc::new(RecordingSink::default());
+        restarted.add_event_sink(sink.clone());
+        
+        // Recovery pulls the funding, finds the deposit rejected and queues the funds for a refund
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert!(restarted.get_outbox().is_empty());
+        assert!(restarted.deposit_registry.is_empty());
+        let refunds = restarted.list_pending_refunds();
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].reason, RefundReason::DepositRejected { deposit_id: 1 });
+        assert_eq!((refunds[0].amount, refunds[0].fee_deduction, refunds[0].refund_amount()), (100_000, 1_500, 98_500));
+        restarted.check_accounting_invariants().unwrap();
+        
+        // The refund is sent once, however often the routines run
+        let events = restarted.process_refunds().unwrap();
+        assert!(matches!(&events[..], [Event::RefundSent { refund_id: 1, refunded_amount: 98_500, fee_deduction: 1_500, .. }]));
+        assert!(restarted.process_refunds().unwrap().is_empty());
+        assert!(restarted.recover_in_flight().unwrap().is_empty());
+        assert_eq!(*payouts.lock().unwrap(), vec![("depositor_address".to_string(), 98_500)]);
+        assert!(restarted.list_pending_refunds().is_empty());
+        assert_eq!(sink.names.lock().unwrap().as_slice(), ["RefundQueued", "RefundSent"]);
+        
+        // A refund the deduction consumes entirely is settled without a payout
+        let funds = crate::bitcoin::incoming::IncomingFunds {
+            txid: "cd".repeat(32),
+            vout: 0,
+            amount: 1_000,
+            sender_addresses: vec!["sender_address".to_string()],
+            confirmations: 6,
+        };
+        restarted.record_incoming_funds(&funds).unwrap();
+        let event = restarted.queue_unattributed_refund("owner_address".to_string(), &funds.outpoint(), "sender_address".to_string(), None).unwrap();
+        assert!(matches!(event, Event::RefundQueued { refund_id: 2, amount: 1_000, fee_deduction: 1_000, .. }));
+        assert!(restarted.list_unattributed_funds().is_empty());
+        assert!(matches!(
+            restarted.queue_unattributed_refund("owner_address".to_string(), &funds.outpoint(), "sender_address".to_string(), None),
+            Err(ContractError::UnattributedFundsNotFound)
+        ));
+        let events = restarted.process_refunds().unwrap();
+        assert!(matches!(&events[..], [Event::RefundSent { refund_id: 2, refunded_amount: 0, fee_deduction: 1_000, .. }]));
+        assert_eq!(payouts.lock().unwrap().len(), 1);
+        
+        // Refunded funds are not listed or refunded again after a restore
+        let (again, _) = contract_recording_payouts(clock);
+        let mut restored = TimeLockedDeposit::from_state(restarted.export_state(), again.token_transfer).unwrap();
+        assert!(restored.record_incoming_funds(&funds).is_none());
+        assert!(restored.refunded_deposits.contains(&1));
+        assert_eq!(restored.get_refund_policy().network_fee_deduction, 1_500);
+        restored.check_accounting_invariants().unwrap();
+    }
+    
+    #[test]
+    fn test_late_funding_of_expired_deposits_is_refunded_once() {
+        let clock = Arc::new(MockClock::new(chrono::Utc::now()));
+        let (mut contract, payouts) = contract_recording_payouts(clock.clone());
+        contract.set_pending_deposit_timeout("owner_address".to_string(), std::time::Duration::from_secs(3600), None).unwrap();
+        contract.set_refund_policy("owner_address".to_string(), RefundPolicy { refund_late_funding: true, ..RefundPolicy::default() }, None).unwrap();
+        
+        // On-chain funding confirming after the timeout goes back to the depositor instead of the unattributed ledger
+        let funds = crate::bitcoin::incoming::IncomingFunds {
+            txid: "ef".repeat(32),
+            vout: 0,
+            amount: 20_000,
+            sender_addresses: vec!["sender_address".to_string()],
+            confirmations: 1,
+        };
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 20_000, 1, Some(funds.outpoint().parse().unwrap()), None).unwrap();
+        clock.advance(chrono::Duration::hours(1));
+        let event = contract.record_incoming_funds(&funds);
+        assert!(matches!(&event, Some(Event::RefundQueued { amount: 20_000, reason: RefundReason::LateFunding { deposit_id: 1 }, .. })));
+        assert_eq!(event.unwrap().deposit_id(), Some(1));
+        assert_eq!(contract.deposit_registry[&1].status, DepositStatus::Expired);
+        assert!(contract.list_unattributed_funds().is_empty());
+        assert!(contract.record_incoming_funds(&funds).is_none());
+        assert!(contract.confirm_pending_deposit(1).unwrap().is_none());
+        
+        // A Lightning payment after the invoice expiry is refunded rather than rejected
+        let invoice_expiry = clock.now() + chrono::Duration::minutes(10);
+        contract.deposit_pending("depositor_address".to_string(), TokenType::Lightning, 1_000, 1, None, Some(invoice_expiry)).unwrap();
+        clock.set(invoice_expiry);
+        let event = contract.confirm_pending_deposit(2).unwrap();
+        assert!(matches!(event, Some(Event::RefundQueued { amount: 1_000, reason: RefundReason::LateFunding { deposit_id: 2 }, .. })));
+        assert!(contract.confirm_pending_deposit(2).unwrap().is_none());
+        assert_eq!(contract.list_pending_refunds().len(), 2);
+        contract.check_accounting_invariants().unwrap();
+        
+        let events = contract.process_refunds().unwrap();
+        assert!(matches!(&events[..], [Event::RefundSent { refund_id: 1, refunded_amount: 20_000, .. }, Event::RefundSent { refund_id: 2, refunded_amount: 1_000, .. }]));
+        assert!(contract.process_refunds().unwrap().is_empty());
+        assert_eq!(
+            *payouts.lock().unwrap(),
+            vec![("depositor_address".to_string(), 20_000), ("depositor_address".to_string(), 1_000)]
+        );
+        assert_eq!(contract.total_deposits[&TokenType::Bitcoin], 0);
+    }
+    
+    #[test]
+    fn test_read_only_transfer_refuses_transfers_and_forwards_queries() {
+        use crate::contract::read_only::ReadOnlyTransfer;
+        
+        let backend = ReadOnlyTransfer::new(contract_with_clock(Arc::new(MockClock::new(chrono::Utc::now()))).token_transfer);
+        
+        // Queries reach the inner backend
+        assert_eq!(backend.get_balance("depositor_address", &TokenType::Bitcoin), Ok(1_000_000));
+        assert!(backend.supports_token_type(&TokenType::Bitcoin));
+        assert!(backend.validate_address("depositor_address").is_ok());
+        
+        // Every way of sending funds is refused as read-only
+        let refused = backend.transfer_from_contract("depositor_address", &TokenType::Bitcoin, 1_000).unwrap_err();
+        assert!(matches!(ContractError::transfer_failed(TransferStage::Withdrawal, refused), ContractError::ReadOnlyMode));
+        let refused = backend.transfer_to_contract("depositor_address", &TokenType::Bitcoin, 1_000).unwrap_err();
+        assert!(matches!(ContractError::transfer_failed(TransferStage::Deposit, refused), ContractError::ReadOnlyMode));
+        let options = WithdrawOptions { coin_selection: Some(CoinControl::Exclude(Vec::new())), ..WithdrawOptions::default() };
+        assert!(matches!(
+            backend.transfer_from_contract_with_options("depositor_address", &TokenType::Bitcoin, 1_000, TransferPriority::Normal, &options),
+            Err(ContractError::ReadOnlyMode)
+        ));
+        assert!(matches!(backend.sweep_from_contract("treasury_address", &TokenType::Bitcoin, 1_000), Err(ContractError::ReadOnlyMode)));
+    }
+    
+    #[test]
+    fn test_read_only_contract_refuses_mutations_and_reads_like_a_writable_one() {
+        use crate::contract::read_only::ReadOnlyTransfer;
+        use crate::contract::query::DepositFilter;
+        use crate::contract::summary::SortKey;
+        
+        let clock = Arc::new(MockClock::new(chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap()));
+        let mut contract = contract_with_clock(clock.clone());
+        contract.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).unwrap();
+        contract.deposit("other_address".to_string(), TokenType::Bitcoin, 2_000, 60, None).unwrap();
+        contract.emergency_withdraw("other_address".to_string(), 2).unwrap();
+        contract.deposit("other_address".to_string(), TokenType::Bitcoin, 3_000, 60, None).unwrap();
+        clock.advance(chrono::Duration::days(31));
+        let state = contract.export_state();
+        
+        let backend = || {
+            let mut mock = contract_with_clock(clock.clone()).token_transfer;
+            mock.expect_get_contract_balance().returning(|_| Ok(4_000));
+            mock
+        };
+        let mut writable = TimeLockedDeposit::from_state(state.clone(), backend()).unwrap();
+        let backend = ReadOnlyTransfer::new(backend());
+        let mut replica = TimeLockedDeposit::from_state_read_only(state, backend).unwrap();
+        writable.set_clock(clock.clone());
+        replica.set_clock(clock.clone());
+        assert!(replica.is_read_only());
+        assert!(!writable.is_read_only());
+        
+        // Every path that moves funds or changes settings is refused, even for callers allowed to use it
+        let notice = ReorgNotice::Suspended { deposit_id: 3, txid: "ab".repeat(32), reason: "reorganized out".to_string() };
+        let audit = crate::contract::utxo_audit::UtxoAuditReport { generated_at: clock.now(), entries: Vec::new(), next_cursor: None };
+        let refused: Vec<Result<(), ContractError>> = vec![
+            replica.deposit("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None).map(drop),
+            replica.deposit_pending("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30, None, None).map(drop),
+            replica.try_reserve_deposit_slot("depositor_address".to_string(), TokenType::Bitcoin, 1_000, 30).map(drop),
+            replica.withdraw("depositor_address".to_string(), 1).map(drop),
+            replica.emergency_withdraw("other_address".to_string(), 3).map(drop),
+            replica.quote_emergency_withdraw("other_address".to_string(), 3).map(drop),
+            replica.transfer_deposit_ownership("other_address".to_string(), 3, "new_address".to_string()).map(drop),
+            replica.set_inheritance("other_address".to_string(), 3, "beneficiary_address".to_string(), 90).map(drop),
+            replica.heartbeat("other_address".to_string()),
+            replica.claim_referral_rewards("other_address".to_string(), TokenType::Bitcoin).map(drop),
+            replica.withdraw_all_fees("owner_address".to_string(), TokenType::Bitcoin, None).map(drop),
+            replica.transfer_ownership("owner_address".to_string(), "new_owner".to_string(), None),
+            replica.set_max_lock_days("owner_address".to_string(), 100, None),
+            replica.pause_token("owner_address".to_string(), TokenType::Bitcoin, None).map(drop),
+            replica.process_refunds().map(drop),
+            replica.recover_in_flight().map(drop),
+            replica.confirm_pending_deposit(3).map(drop),
+            replica.apply_reorg_notice(&notice).map(drop),
+            replica.suspend_spent_deposits(&audit).map(drop),
+        ];
+        for result in refused {
+            assert!(matches!(result, Err(ContractError::ReadOnlyMode)), "{:?}", result);
+        }
+        
+        // The daemon's periodic jobs do nothing on a replica
+        let funds = crate::bitcoin::incoming::IncomingFunds {
+            txid: "cd".repeat(32),
+            vout: 0,
+            amount: 50_000,
+            sender_addresses: vec!["sender_address".to_string()],
+            confirmations: 6,
+        };
+        assert!(replica.record_incoming_funds(&funds).is_none());
+        assert!(replica.expire_stale_pending_deposits(clock.now() + chrono::Duration::days(365)).is_empty());
+        assert!(replica.pause_tokens_behind_open_gates().is_empty());
+        assert!(replica.revalidate_supported_tokens().is_empty());
+        assert_eq!(replica.mark_notified(&[1, 2, 3]), 0);
+        
+        // Refused calls left the replica as restored
+        assert_eq!(
+            serde_json::to_value(replica.export_state()).unwrap(),
+            serde_json::to_value(writable.export_state()).unwrap()
+        );
+        
+        // Reads answer the same as on a writable contract restored from the same snapshot
+        assert_eq!(serde_json::to_value(replica.get_stats()).unwrap(), serde_json::to_value(writable.get_stats()).unwrap());
+        assert_eq!(serde_json::to_value(replica.reconcile(0)).unwrap(), serde_json::to_value(writable.reconcile(0)).unwrap());
+        assert_eq!(
+            serde_json::to_value(replica.list_deposit_summaries(DepositFilter::default(), SortKey::default(), 0, 10)).unwrap(),
+            serde_json::to_value(writable.list_deposit_summaries(DepositFilter::default(), SortKey::default(), 0, 10)).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(replica.get_deposit_details(&[1, 2, 3])).unwrap(),
+            serde_json::to_value(writable.get_deposit_details(&[1, 2, 3])).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_value(replica.get_user_summary("other_address")).unwrap(),
+            serde_json::to_value(writable.get_user_summary("other_address")).unwrap()
+        );
+        let statement = |contract_deposits: Vec<&crate::models::Deposit>| {
+            let period = crate::statements::StatementPeriod::new(2023, 11).unwrap();
+            serde_json::to_value(crate::statements::build_statement("other_address", period, &[], contract_deposits, clock.now())).unwrap()
+        };
+        let filter = || DepositFilter::active().with_address("other_address");
+        assert_eq!(statement(replica.deposits_filtered(filter()).collect()), statement(writable.deposits_filtered(filter()).collect()));
+        let (mut replica_csv, mut writable_csv) = (Vec::new(), Vec::new());
+        assert_eq!(replica.export_deposits_csv(DepositFilter::default(), &mut replica_csv, None).unwrap(), 3);
+        writable.export_deposits_csv(DepositFilter::default(), &mut writable_csv, None).unwrap();
+        assert_eq!(replica_csv, writable_csv);
+        
+        // The writable contract runs the same calls the replica refused
+        writable.withdraw("depositor_address".to_string(), 1).unwrap();
+        writable.withdraw_all_fees("owner_address".to_string(), TokenType::Bitcoin, None).unwrap();
+        assert!(writable.apply_reorg_notice(&notice).unwrap().is_some());
+        assert!(writable.record_incoming_funds(&funds).is_some());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,344 @@
+//! Historical series of total value locked and remaining lock duration
+//!
+//! `MetricsRecorder` is an event sink that mirrors the funded deposits from
+//! the events it receives, and after every event that changes them records a
+//! `TvlSample` of the affected token. Samples are kept in one ring of buckets
+//! per configured `SeriesTier`, e.g. per minute for a day and per hour for a
+//! month. A bucket holds the last sample taken within it, so each bucket is
+//! the state at the end of its period and downsampling a finer series gives
+//! the same values as the coarser tier. Buckets older than the tier's
+//! retention are dropped, so memory is bounded by the configuration, not by
+//! uptime. Periods without any change have no bucket; the previous value
+//! still holds for them.
+//!
+//! The average remaining lock is the plain mean over a token's funded
+//! deposits of the time until each unlocks, with already unlocked deposits
+//! counting as zero.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::events::{Event, EventSink};
+use crate::contract::recovery::WithdrawalCorrection;
+use crate::models::{Deposit, DepositStatus, TokenType};
+
+/// Resolution and retention of one series tier
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeriesTier {
+    /// Width of a bucket in seconds
+    pub resolution_secs: u64,
+    /// How far back buckets are kept, in seconds
+    pub retention_secs: u64,
+}
+
+/// Tiers the recorder keeps
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsRecorderConfig {
+    /// Tiers, finest first
+    pub tiers: Vec<SeriesTier>,
+}
+
+impl Default for MetricsRecorderConfig {
+    fn default() -> Self {
+        Self {
+            tiers: vec![
+                // Per minute for a day
+                SeriesTier { resolution_secs: 60, retention_secs: 24 * 60 * 60 },
+                // Per hour for 30 days
+                SeriesTier { resolution_secs: 60 * 60, retention_secs: 30 * 24 * 60 * 60 },
+            ],
+        }
+    }
+}
+
+impl MetricsRecorderConfig {
+    /// Check every tier has a resolution and keeps at least one bucket
+    pub fn validate(&self) -> Result<(), ContractError> {
+        if self.tiers.is_empty() {
+            return Err(ContractError::InvalidSeriesResolution("No series tiers configured".to_string()));
+        }
+        
+        for tier in &self.tiers {
+            if tier.resolution_secs == 0 || tier.retention_secs < tier.resolution_secs {
+                return Err(ContractError::InvalidSeriesResolution(format!(
+                    "Tier of {}s kept for {}s", tier.resolution_secs, tier.retention_secs
+                )));
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+/// Value locked in one token at a point in time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TvlSample {
+    /// Start of the bucket the sample stands for
+    pub timestamp: DateTime<Utc>,
+    /// Token type
+    pub token: TokenType,
+    /// Total locked in funded deposits
+    pub tvl: u64,
+    /// Number of funded deposits
+    pub active_deposits: u64,
+    /// Mean time until the funded deposits unlock, in seconds
+    pub avg_remaining_lock_secs: u64,
+}
+
+/// Serializable series of a recorder, persisted with the contract state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TvlSeries {
+    /// Tiers the series was recorded at
+    pub config: MetricsRecorderConfig,
+    /// Buckets of each tier, in the order of `config.tiers`, sorted by token then time
+    pub tiers: Vec<Vec<TvlSample>>,
+}
+
+/// Funded deposit as mirrored from the events
+#[derive(Debug, Clone)]
+struct LockedDeposit {
+    /// Token type
+    token: TokenType,
+    /// Locked amount
+    amount: u64,
+    /// When the deposit unlocks
+    unlock_timestamp: DateTime<Utc>,
+}
+
+/// State behind the recorder's lock
+#[derive(Debug, Default)]
+struct RecorderState {
+    /// Funded deposits by ID
+    deposits: HashMap<u64, LockedDeposit>,
+    /// Buckets per token of each tier, oldest first
+    tiers: Vec<HashMap<TokenType, VecDeque<TvlSample>>>,
+}
+
+impl RecorderState {
+    /// Take a sample of a token's funded deposits at a point in time
+    fn sample(&self, token: &TokenType, now: DateTime<Utc>) -> TvlSample {
+        let mut tvl: u64 = 0;
+        let mut active_deposits: u64 = 0;
+        let mut remaining_secs: u128 = 0;
+        
+        for deposit in self.deposits.values().filter(|deposit| &deposit.token == token) {
+            tvl = tvl.saturating_add(deposit.amount);
+            active_deposits += 1;
+            remaining_secs += (deposit.unlock_timestamp - now).num_seconds().max(0) as u128;
+        }
+        
+        TvlSample {
+            timestamp: now,
+            token: token.clone(),
+            tvl,
+            active_deposits,
+            avg_remaining_lock_secs: remaining_secs.checked_div(active_deposits as u128).unwrap_or(0) as u64,
+        }
+    }
+    
+    /// Put a sample into its bucket of every tier and drop buckets past retention
+    fn record(&mut self, config: &MetricsRecorderConfig, sample: TvlSample) {
+        for (tier, buckets) in config.tiers.iter().zip(self.tiers.iter_mut()) {
+            let bucket_start = bucket_start(sample.timestamp, tier.resolution_secs);
+            let buckets = buckets.entry(sample.token.clone()).or_default();
+            
+            // The last sample of a bucket stands for it; a clock stepping back stays in the latest bucket
+            match buckets.back_mut() {
+                Some(last) if last.timestamp >= bucket_start => {
+                    *last = TvlSample { timestamp: last.timestamp, ..sample.clone() };
+                },
+                _ => buckets.push_back(TvlSample { timestamp: bucket_start, ..sample.clone() }),
+            }
+            
+            let oldest_kept = bucket_start - chrono::Duration::seconds(tier.retention_secs as i64);
+            while buckets.front().is_some_and(|bucket| bucket.timestamp <= oldest_kept) {
+                buckets.pop_front();
+            }
+        }
+    }
+}
+
+/// Start of the bucket a timestamp falls into
+fn bucket_start(timestamp: DateTime<Utc>, resolution_secs: u64) -> DateTime<Utc> {
+    let resolution = resolution_secs as i64;
+    let start = timestamp.timestamp().div_euclid(resolution) * resolution;
+    DateTime::from_timestamp(start, 0).unwrap_or(timestamp)
+}
+
+/// Event sink recording TVL and lock duration series per token
+pub struct MetricsRecorder {
+    /// Tiers kept
+    config: MetricsRecorderConfig,
+    /// Mirrored deposits and recorded buckets
+    state: Mutex<RecorderState>,
+}
+
+impl fmt::Debug for MetricsRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetricsRecorder")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MetricsRecorder {
+    /// Create a recorder with no history
+    pub fn new(config: MetricsRecorderConfig) -> Result<Self, ContractError> {
+        config.validate()?;
+        
+        let tiers = vec![HashMap::new(); config.tiers.len()];
+        
+        Ok(Self {
+            config,
+            state: Mutex::new(RecorderState { deposits: HashMap::new(), tiers }),
+        })
+    }
+    
+    /// Create a recorder continuing a persisted series
+    pub fn from_series(series: TvlSeries) -> Result<Self, ContractError> {
+        let recorder = Self::new(series.config)?;
+        
+        if let Ok(mut state) = recorder.state.lock() {
+            for (buckets, samples) in state.tiers.iter_mut().zip(series.tiers) {
+                for sample in samples {
+                    buckets.entry(sample.token.clone()).or_default().push_back(sample);
+                }
+            }
+        }
+        
+        Ok(recorder)
+    }
+    
+    /// Get the tiers kept
+    pub fn config(&self) -> &MetricsRecorderConfig {
+        &self.config
+    }
+    
+    /// Start mirroring deposits funded before the recorder saw any event
+    pub fn seed<'a>(&self, deposits: impl Iterator<Item = &'a Deposit>) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        
+        for deposit in deposits.filter(|deposit| matches!(deposit.status, DepositStatus::Active | DepositStatus::Suspended | DepositStatus::WithdrawalPending)) {
+            state.deposits.insert(deposit.deposit_id, LockedDeposit {
+                token: deposit.deposited_token_type.clone(),
+                amount: deposit.deposited_amount,
+                unlock_timestamp: deposit.unlock_timestamp,
+            });
+        }
+    }
+    
+    /// Get a copy of the recorded series, for persisting
+    pub fn series(&self) -> TvlSeries {
+        let tiers = self.state.lock()
+            .map(|state| state.tiers.iter()
+                .map(|buckets| {
+                    let mut tokens: Vec<&TokenType> = buckets.keys().collect();
+                    tokens.sort_by_key(|token| format!("{:?}", token));
+                    tokens.into_iter().flat_map(|token| buckets[token].iter().cloned()).collect()
+                })
+                .collect())
+            .unwrap_or_default();
+        
+        TvlSeries {
+            config: self.config.clone(),
+            tiers,
+        }
+    }
+    
+    /// Get a token's samples from `from` (inclusive) to `to` (exclusive) at a resolution
+    ///
+    /// The series comes from the finest tier whose resolution divides the
+    /// requested one and still reaches back to `from`, or the coarsest such
+    /// tier if none does. Its buckets are regrouped at the requested
+    /// resolution keeping the last sample of each group.
+    pub fn get_tvl_series(
+        &self,
+        token: &TokenType,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        resolution_secs: u64,
+    ) -> Result<Vec<TvlSample>, ContractError> {
+        let state = self.state.lock()
+            .map_err(|_| ContractError::InitializationError("Metrics recorder lock poisoned".to_string()))?;
+        
+        // Tiers the requested resolution can be built from, finest first
+        let mut candidates: Vec<usize> = (0..self.config.tiers.len())
+            .filter(|index| {
+                let tier_resolution = self.config.tiers[*index].resolution_secs;
+                resolution_secs > 0 && resolution_secs.is_multiple_of(tier_resolution)
+            })
+            .collect();
+        candidates.sort_by_key(|index| self.config.tiers[*index].resolution_secs);
+        
+        let empty = VecDeque::new();
+        let buckets_of = |index: usize| state.tiers[index].get(token).unwrap_or(&empty);
+        let covers_from = |index: usize| buckets_of(index).front().is_some_and(|bucket| bucket.timestamp <= from);
+        
+        let tier = candidates.iter().copied().find(|index| covers_from(*index))
+            .or_else(|| candidates.last().copied())
+            .ok_or_else(|| ContractError::InvalidSeriesResolution(format!(
+                "{}s is not a multiple of any recorded resolution", resolution_secs
+            )))?;
+        
+        let mut series: Vec<TvlSample> = Vec::new();
+        for bucket in buckets_of(tier).iter().filter(|bucket| bucket.timestamp >= from && bucket.timestamp < to) {
+            let group_start = bucket_start(bucket.timestamp, resolution_secs);
+            match series.last_mut() {
+                Some(last) if last.timestamp == group_start => {
+                    *last = TvlSample { timestamp: group_start, ..bucket.clone() };
+                },
+                _ => series.push(TvlSample { timestamp: group_start, ..bucket.clone() }),
+            }
+        }
+        
+        Ok(series)
+    }
+}
+
+impl EventSink for MetricsRecorder {
+    fn publish(&self, event: &Event) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        
+        // Update the mirror, noting the token whose value changed
+        let token = match event {
+            Event::Deposited { deposit_id, token_type, deposit_amount, fee_amount, locked_amount, unlock_timestamp, .. } => {
+                // Events from before the locked amount was recorded only have the gross amount and fee
+                let amount = if *locked_amount > 0 { *locked_amount } else { deposit_amount.saturating_sub(*fee_amount) };
+                state.deposits.insert(*deposit_id, LockedDeposit {
+                    token: token_type.clone(),
+                    amount,
+                    unlock_timestamp: *unlock_timestamp,
+                });
+                Some(token_type.clone())
+            },
+            Event::DepositImported { deposit_id, token_type, amount, unlock_timestamp, withdrawn: false, .. } => {
+                state.deposits.insert(*deposit_id, LockedDeposit {
+                    token: token_type.clone(),
+                    amount: *amount,
+                    unlock_timestamp: *unlock_timestamp,
+                });
+                Some(token_type.clone())
+            },
+            Event::Withdrawn { deposit_id, .. }
+            | Event::EmergencyWithdrawn { deposit_id, .. }
+            | Event::InheritanceClaimed { deposit_id, .. }
+            | Event::WithdrawalRecovered { deposit_id, correction: WithdrawalCorrection::Completed | WithdrawalCorrection::Queued, .. } => {
+                state.deposits.remove(deposit_id).map(|deposit| deposit.token)
+            },
+            _ => None,
+        };
+        
+        if let Some(token) = token {
+            let sample = state.sample(&token, event.timestamp());
+            state.record(&self.config, sample);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Webhook notifications for contract events
+//!
+//! `WebhookSink` POSTs every event as JSON to a configured URL, signed with
+//! an HMAC-SHA256 of the body. Deliveries run on a bounded background queue
+//! so a slow or dead endpoint never blocks contract operations.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use bitcoincore_rpc::bitcoin::hashes::{hmac, sha256, Hash, HashEngine};
+use log::{debug, error, warn};
+use serde::{Serialize, Deserialize};
+
+use crate::errors::ContractError;
+use crate::events::{Event, EventSink};
+use crate::logging::CONTRACT;
+
+/// Header carrying the hex HMAC-SHA256 signature of the body
+pub const SIGNATURE_HEADER: &str = "X-Vault-Signature";
+
+/// Header carrying the event name
+pub const EVENT_HEADER: &str = "X-Vault-Event";
+
+/// Configuration for webhook delivery
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// Endpoint URL (http only; terminate TLS in a local proxy)
+    pub url: String,
+    /// Shared secret for the HMAC signature
+    pub secret: String,
+    /// Maximum delivery attempts per event
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled on every further retry
+    pub initial_backoff: Duration,
+    /// Connect/read/write timeout per attempt
+    pub timeout: Duration,
+    /// Maximum number of events waiting for delivery
+    pub queue_capacity: usize,
+}
+
+impl WebhookConfig {
+    /// Create a webhook configuration with default retry settings
+    pub fn new(url: String, secret: String) -> Self {
+        Self {
+            url,
+            secret,
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            queue_capacity: 1000,
+        }
+    }
+    
+    /// Validate the configuration
+    pub fn validate(&self) -> Result<(), String> {
+        WebhookEndpoint::parse(&self.url)?;
+        
+        if self.secret.is_empty() {
+            return Err("Webhook secret cannot be empty".to_string());
+        }
+        
+        if self.max_attempts == 0 {
+            return Err("Webhook max attempts cannot be zero".to_string());
+        }
+        
+        if self.queue_capacity == 0 {
+            return Err("Webhook queue capacity cannot be zero".to_string());
+        }
+        
+        Ok(())
+    }
+}
+
+/// Parsed webhook endpoint
+#[derive(Debug, Clone)]
+struct WebhookEndpoint {
+    /// Host name
+    host: String,
+    /// Port
+    port: u16,
+    /// Request path
+    path: String,
+}
+
+impl WebhookEndpoint {
+    /// Parse an http URL
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = match url.strip_prefix("http://") {
+            Some(rest) => rest,
+            None if url.starts_with("https://") => {
+                return Err("HTTPS webhook URLs are not supported; use a local TLS-terminating proxy".to_string())
+            },
+            None => return Err("Webhook URL must start with http://".to_string()),
+        };
+        
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().map_err(|_| "Invalid webhook port".to_string())?),
+            None => (authority, 80),
+        };
+        
+        if host.is_empty() {
+            return Err("Webhook URL has no host".to_string());
+        }
+        
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Outcome of a single delivery attempt
+#[derive(Debug)]
+enum DeliveryOutcome {
+    /// Endpoint accepted the event
+    Delivered,
+    /// Temporary failure (5xx, timeout, connection error)
+    Retryable(String),
+    /// Permanent failure (4xx)
+    Rejected(String),
+}
+
+/// Delivery counters of a webhook sink
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookDeliveryStats {
+    /// Events queued or being delivered
+    pub pending: u64,
+    /// Events accepted by the endpoint
+    pub delivered: u64,
+    /// Events given up on after exhausting retries or being rejected
+    pub failed: u64,
+    /// Events dropped because the queue was full
+    pub dropped: u64,
+}
+
+/// Shared delivery counters
+#[derive(Debug, Default)]
+struct DeliveryCounters {
+    pending: AtomicU64,
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// Queued webhook delivery
+#[derive(Debug)]
+struct WebhookDelivery {
+    /// Event name
+    event_name: &'static str,
+    /// Serialized event body
+    body: Vec<u8>,
+}
+
+/// Event sink delivering events to an HTTP endpoint
+#[derive(Debug)]
+pub struct WebhookSink {
+    /// Sender side of the delivery queue
+    sender: Mutex<SyncSender<WebhookDelivery>>,
+    /// Delivery counters
+    counters: Arc<DeliveryCounters>,
+}
+
+impl WebhookSink {
+    /// Create a webhook sink and start its delivery thread
+    pub fn new(config: WebhookConfig) -> Result<Self, ContractError> {
+        config.validate().map_err(ContractError::InitializationError)?;
+        
+        let endpoint = WebhookEndpoint::parse(&config.url).map_err(ContractError::InitializationError)?;
+        let (sender, receiver) = mpsc::sync_channel(config.queue_capacity);
+        let counters = Arc::new(DeliveryCounters::default());
+        
+        let worker_counters = counters.clone();
+        thread::spawn(move || Self::run_worker(config, endpoint, receiver, worker_counters));
+        
+        Ok(Self {
+            sender: Mutex::new(sender),
+            counters,
+        })
+    }
+    
+    /// Get the delivery counters
+    pub fn delivery_stats(&self) -> WebhookDeliveryStats {
+        WebhookDeliveryStats {
+            pending: self.counters.pending.load(Ordering::SeqCst),
+            delivered: self.counters.delivered.load(Ordering::SeqCst),
+            failed: self.counters.failed.load(Ordering::SeqCst),
+            dropped: self.counters.dropped.load(Ordering::SeqCst),
+        }
+    }
+    
+    /// Compute the hex HMAC-SHA256 signature of a body
+    pub fn sign(secret: &str, body: &[u8]) -> String {
+        let mut engine = hmac::HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+        engine.input(body);
+        hmac::Hmac::<sha256::Hash>::from_engine(engine).to_string()
+    }
+    
+    /// Deliver queued events until the sink is dropped
+    fn run_worker(
+        config: WebhookConfig,
+        endpoint: WebhookEndpoint,
+        receiver: Receiver<WebhookDelivery>,
+        counters: Arc<DeliveryCounters>,
+    ) {
+        while let Ok(delivery) = receiver.recv() {
+            let mut backoff = config.initial_backoff;
+            let mut attempt = 1;
+            
+            loop {
+                match Self::attempt_delivery(&config, &endpoint, &delivery) {
+                    DeliveryOutcome::Delivered => {
+                        debug!(target: CONTRACT, "Webhook delivered {} on attempt {}", delivery.event_name, attempt);
+                        counters.delivered.fetch_add(1, Ordering::SeqCst);
+                        break;
+                    },
+                    DeliveryOutcome::Rejected(reason) => {
+                        error!(target: CONTRACT, "Webhook rejected {}: {}", delivery.event_name, reason);
+                        counters.failed.fetch_add(1, Ordering::SeqCst);
+                        break;
+                    },
+                    DeliveryOutcome::Retryable(reason) if attempt >= config.max_attempts => {
+                        error!(target: CONTRACT, "Dropping webhook {} after {} attempts: {}", delivery.event_name, attempt, reason);
+                        counters.failed.fetch_add(1, Ordering::SeqCst);
+                        break;
+                    },
+                    DeliveryOutcome::Retryable(reason) => {
+                        warn!(target: CONTRACT, "Webhook attempt {} for {} failed: {}", attempt, delivery.event_name, reason);
+                        thread::sleep(backoff);
+                        backoff = backoff.saturating_mul(2);
+                        attempt += 1;
+                    },
+                }
+            }
+            
+            counters.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+    
+    /// Make a single POST request
+    fn attempt_delivery(config: &WebhookConfig, endpoint: &WebhookEndpoint, delivery: &WebhookDelivery) -> DeliveryOutcome {
+        let address = match (endpoint.host.as_str(), endpoint.port).to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next()) {
+            Some(address) => address,
+            None => return DeliveryOutcome::Retryable(format!("Failed to resolve {}", endpoint.host)),
+        };
+        
+        let mut stream = match TcpStream::connect_timeout(&address, config.timeout) {
+            Ok(stream) => stream,
+            Err(e) => return DeliveryOutcome::Retryable(format!("Failed to connect: {}", e)),
+        };
+        
+        if let Err(e) = stream.set_read_timeout(Some(config.timeout))
+            .and_then(|_| stream.set_write_timeout(Some(config.timeout))) {
+            return DeliveryOutcome::Retryable(format!("Failed to set timeouts: {}", e));
+        }
+        
+        let signature = Self::sign(&config.secret, &delivery.body);
+        let head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}: {}\r\n{}: sha256={}\r\nConnection: close\r\n\r\n",
+            endpoint.path,
+            endpoint.host,
+            delivery.body.len(),
+            EVENT_HEADER,
+            delivery.event_name,
+            SIGNATURE_HEADER,
+            signature,
+        );
+        
+        if let Err(e) = stream.write_all(head.as_bytes())
+            .and_then(|_| stream.write_all(&delivery.body))
+            .and_then(|_| stream.flush()) {
+            return DeliveryOutcome::Retryable(format!("Failed to send request: {}", e));
+        }
+        
+        // Only the status line matters
+        let mut status_line = String::new();
+        if let Err(e) = BufReader::new(stream).read_line(&mut status_line) {
+            return DeliveryOutcome::Retryable(format!("Failed to read response: {}", e));
+        }
+        
+        match status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()) {
+            Some(code) if (200..300).contains(&code) => DeliveryOutcome::Delivered,
+            Some(code) if code >= 500 || code == 408 || code == 429 => {
+                DeliveryOutcome::Retryable(format!("Endpoint returned {}", code))
+            },
+            Some(code) => DeliveryOutcome::Rejected(format!("Endpoint returned {}", code)),
+            None => DeliveryOutcome::Retryable("Malformed response".to_string()),
+        }
+    }
+}
+
+impl EventSink for WebhookSink {
+    fn publish(&self, event: &Event) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                error!(target: CONTRACT, "Failed to serialize {} for webhook: {}", event.name(), e);
+                return;
+            }
+        };
+        
+        let sender = match self.sender.lock() {
+            Ok(sender) => sender,
+            Err(_) => return,
+        };
+        
+        self.counters.pending.fetch_add(1, Ordering::SeqCst);
+        
+        let delivery = WebhookDelivery {
+            event_name: event.name(),
+            body,
+        };
+        
+        match sender.try_send(delivery) {
+            Ok(()) => {},
+            Err(TrySendError::Full(delivery)) => {
+                warn!(target: CONTRACT, "Webhook queue full, dropping {}", delivery.event_name);
+                self.counters.pending.fetch_sub(1, Ordering::SeqCst);
+                self.counters.dropped.fetch_add(1, Ordering::SeqCst);
+            },
+            Err(TrySendError::Disconnected(delivery)) => {
+                error!(target: CONTRACT, "Webhook worker stopped, dropping {}", delivery.event_name);
+                self.counters.pending.fetch_sub(1, Ordering::SeqCst);
+                self.counters.dropped.fetch_add(1, Ordering::SeqCst);
+            },
+        }
+    }
+}